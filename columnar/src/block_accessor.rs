@@ -10,6 +10,41 @@ pub struct ColumnBlockAccessor<T> {
     row_id_cache: Vec<RowId>,
 }
 
+impl<T> ColumnBlockAccessor<T> {
+    /// Creates an accessor caching the values and doc ids of a block in the given buffers, which
+    /// are cleared.
+    ///
+    /// This makes it possible to reuse the buffers of another accessor, see
+    /// [`ColumnBlockAccessor::into_buffers`].
+    pub fn from_buffers(
+        mut val_cache: Vec<T>,
+        mut docid_cache: Vec<DocId>,
+        mut missing_docids_cache: Vec<DocId>,
+        mut row_id_cache: Vec<RowId>,
+    ) -> Self {
+        val_cache.clear();
+        docid_cache.clear();
+        missing_docids_cache.clear();
+        row_id_cache.clear();
+        Self {
+            val_cache,
+            docid_cache,
+            missing_docids_cache,
+            row_id_cache,
+        }
+    }
+
+    /// Returns the buffers of the accessor, in the order of [`ColumnBlockAccessor::from_buffers`].
+    pub fn into_buffers(self) -> (Vec<T>, Vec<DocId>, Vec<DocId>, Vec<RowId>) {
+        (
+            self.val_cache,
+            self.docid_cache,
+            self.missing_docids_cache,
+            self.row_id_cache,
+        )
+    }
+}
+
 impl<T: PartialOrd + Copy + std::fmt::Debug + Send + Sync + 'static + Default>
     ColumnBlockAccessor<T>
 {
@@ -76,6 +111,37 @@ impl<T: PartialOrd + Copy + std::fmt::Debug + Send + Sync + 'static + Default>
                 .zip(self.val_cache.iter().cloned())
         }
     }
+
+    #[inline]
+    /// Returns an iterator over the docids and the slice of their values, with one item per doc
+    /// having at least one value.
+    /// The passed in `docs` slice needs to be the same slice that was passed to `fetch_block` or
+    /// `fetch_block_with_missing`.
+    ///
+    /// The docs filled in by `fetch_block_with_missing` have a single value, the missing value.
+    pub fn iter_docid_val_slices<'a>(
+        &'a self,
+        docs: &'a [u32],
+        accessor: &Column<T>,
+    ) -> impl Iterator<Item = (DocId, &'a [T])> + 'a + use<'a, T> {
+        let docids: &'a [DocId] = if accessor.index.get_cardinality().is_full() {
+            docs
+        } else {
+            &self.docid_cache
+        };
+        let mut start = 0;
+        std::iter::from_fn(move || {
+            let doc = *docids.get(start)?;
+            // The values of a doc are contiguous, the missing docs being appended at the end.
+            let num_vals = docids[start..]
+                .iter()
+                .take_while(|&&docid| docid == doc)
+                .count();
+            let vals = &self.val_cache[start..start + num_vals];
+            start += num_vals;
+            Some((doc, vals))
+        })
+    }
 }
 
 /// Given two sorted lists of docids `docs` and `hits`, hits is a subset of `docs`.
@@ -6,7 +6,7 @@ use std::io::Write;
 use std::ops::{Range, RangeInclusive};
 use std::sync::Arc;
 
-use common::BinarySerializable;
+use common::{BinarySerializable, OwnedBytes};
 pub use dictionary_encoded::{BytesColumn, StrColumn};
 pub use serialize::{
     open_column_bytes, open_column_str, open_column_u64, open_column_u128,
@@ -14,10 +14,16 @@ pub use serialize::{
     serialize_column_mappable_to_u128,
 };
 
-use crate::column_index::{ColumnIndex, Set};
+use crate::column_index::{
+    ColumnIndex, SerializableColumnIndex, SerializableMultivalueIndex, SerializableOptionalIndex,
+    Set, open_column_index, serialize_column_index,
+};
 use crate::column_values::monotonic_mapping::StrictlyMonotonicMappingToInternal;
-use crate::column_values::{ColumnValues, monotonic_map_column};
-use crate::{Cardinality, DocId, EmptyColumnValues, MonotonicallyMappableToU64, RowId};
+use crate::column_values::{ColumnValues, ConstantColumnValues, VecColumn, monotonic_map_column};
+use crate::{
+    Cardinality, DateTime, DocId, EmptyColumnValues, Granularity, MonotonicallyMappableToU64,
+    RowId, Version,
+};
 
 #[derive(Clone)]
 pub struct Column<T = u64> {
@@ -44,6 +50,16 @@ impl<T: PartialOrd + Default> Column<T> {
     }
 }
 
+impl<T: PartialOrd + Copy + Debug + Send + Sync + 'static> Column<T> {
+    /// Builds a column where each of the `num_docs` docs has the single value `value`.
+    pub fn build_constant_column(num_docs: u32, value: T) -> Column<T> {
+        Column {
+            index: ColumnIndex::Full,
+            values: Arc::new(ConstantColumnValues::new(value, num_docs)),
+        }
+    }
+}
+
 impl<T: MonotonicallyMappableToU64> Column<T> {
     pub fn to_u64_monotonic(self) -> Column<u64> {
         let values = Arc::new(monotonic_map_column(
@@ -57,6 +73,24 @@ impl<T: MonotonicallyMappableToU64> Column<T> {
     }
 }
 
+impl Column<DateTime> {
+    /// Returns the number of days elapsed from the first value of `row_id` to `reference`, as a
+    /// fraction, or `None` if `row_id` has no value.
+    ///
+    /// The values are read at the precision the column was written with.
+    pub fn days_between(&self, row_id: RowId, reference: DateTime) -> Option<f64> {
+        self.first(row_id)
+            .map(|date_time| date_time.days_until(reference))
+    }
+
+    /// Returns the first value of `row_id` rounded down to `granularity`, or `None` if `row_id`
+    /// has no value.
+    pub fn truncate(&self, row_id: RowId, granularity: Granularity) -> Option<DateTime> {
+        self.first(row_id)
+            .map(|date_time| date_time.truncate_to(granularity))
+    }
+}
+
 impl<T: PartialOrd + Copy + Debug + Send + Sync + 'static> Column<T> {
     #[inline]
     pub fn get_cardinality(&self) -> Cardinality {
@@ -84,6 +118,42 @@ impl<T: PartialOrd + Copy + Debug + Send + Sync + 'static> Column<T> {
         self.values.max_value()
     }
 
+    /// Returns a lower and an upper bound for the values of the docs in `doc_id_range`, or `None`
+    /// if none of these docs has a value.
+    ///
+    /// The bounds are not necessarily tight, see [`ColumnValues::value_bounds`].
+    pub fn value_bounds(&self, doc_id_range: Range<DocId>) -> Option<(T, T)> {
+        let doc_id_range = doc_id_range.start..doc_id_range.end.min(self.num_docs());
+        if doc_id_range.is_empty() {
+            return None;
+        }
+        let row_id_range = self.index.docid_range_to_rowids(doc_id_range);
+        if row_id_range.is_empty() {
+            return None;
+        }
+        Some(self.values.value_bounds(row_id_range))
+    }
+
+    /// Returns true if all of the docs in `doc_id_range` have a value.
+    ///
+    /// For multivalued columns, this is only computed for empty ranges: it returns false
+    /// otherwise.
+    pub fn has_value_for_all_docs(&self, doc_id_range: Range<DocId>) -> bool {
+        if doc_id_range.is_empty() {
+            return true;
+        }
+        match &self.index {
+            ColumnIndex::Empty { .. } | ColumnIndex::Multivalued(_) => false,
+            ColumnIndex::Full => doc_id_range.end <= self.num_docs(),
+            ColumnIndex::Optional(optional_index) => {
+                doc_id_range.end <= optional_index.num_docs()
+                    && optional_index.rank(doc_id_range.end)
+                        - optional_index.rank(doc_id_range.start)
+                        == doc_id_range.end - doc_id_range.start
+            }
+        }
+    }
+
     #[inline]
     pub fn first(&self, row_id: RowId) -> Option<T> {
         self.values_for_doc(row_id).next()
@@ -175,6 +245,60 @@ impl<T: PartialOrd + Copy + Debug + Send + Sync + 'static> Column<T> {
     }
 }
 
+impl<T: PartialOrd + Copy + Debug + Send + Sync + Default + 'static> Column<T> {
+    /// Combines columns over the same `num_docs` docs into a single column, built in memory.
+    ///
+    /// The values of a doc are those of the first column, in the order of `columns`, that has
+    /// values for it. The values of the other columns for this doc are ignored.
+    pub fn coalesce(columns: &[Column<T>], num_docs: DocId) -> Column<T> {
+        let mut values: Vec<T> = Vec::new();
+        let mut docs_with_values: Vec<DocId> = Vec::new();
+        // Start offsets of the values of the docs with values, followed by the overall number of
+        // values.
+        let mut start_offsets: Vec<RowId> = vec![0];
+        for doc in 0..num_docs {
+            let Some(column) = columns
+                .iter()
+                .find(|column| doc < column.num_docs() && column.index.has_value(doc))
+            else {
+                continue;
+            };
+            values.extend(column.values_for_doc(doc));
+            docs_with_values.push(doc);
+            start_offsets.push(values.len() as RowId);
+        }
+
+        let is_multivalued = values.len() > docs_with_values.len();
+        let serializable_column_index = if docs_with_values.is_empty() {
+            return Column::build_empty_column(num_docs);
+        } else if is_multivalued {
+            SerializableColumnIndex::Multivalued(SerializableMultivalueIndex {
+                doc_ids_with_values: SerializableOptionalIndex {
+                    non_null_row_ids: Box::new(&docs_with_values[..]),
+                    num_rows: num_docs,
+                },
+                start_offsets: Box::new(&start_offsets[..]),
+            })
+        } else if docs_with_values.len() as DocId == num_docs {
+            SerializableColumnIndex::Full
+        } else {
+            SerializableColumnIndex::Optional(SerializableOptionalIndex {
+                non_null_row_ids: Box::new(&docs_with_values[..]),
+                num_rows: num_docs,
+            })
+        };
+        let mut buffer = Vec::new();
+        serialize_column_index(serializable_column_index, &mut buffer)
+            .expect("writing to a Vec cannot fail");
+        let index = open_column_index(OwnedBytes::new(buffer), Version::V2)
+            .expect("the column index was just serialized");
+        Column {
+            index,
+            values: Arc::new(VecColumn::from(values)),
+        }
+    }
+}
+
 impl BinarySerializable for Cardinality {
     fn serialize<W: Write + ?Sized>(&self, writer: &mut W) -> std::io::Result<()> {
         self.to_code().serialize(writer)
@@ -209,6 +333,28 @@ impl<T: PartialOrd + Debug + Send + Sync + Copy + 'static> ColumnValues<T>
         self.column.values.max_value()
     }
 
+    fn value_bounds(&self, row_id_range: Range<RowId>) -> (T, T) {
+        let default_bounds = (self.default_value, self.default_value);
+        let Some((lower_bound, upper_bound)) = self.column.value_bounds(row_id_range.clone())
+        else {
+            return default_bounds;
+        };
+        if self.column.has_value_for_all_docs(row_id_range) {
+            return (lower_bound, upper_bound);
+        }
+        let lower_bound = if self.default_value < lower_bound {
+            self.default_value
+        } else {
+            lower_bound
+        };
+        let upper_bound = if self.default_value > upper_bound {
+            self.default_value
+        } else {
+            upper_bound
+        };
+        (lower_bound, upper_bound)
+    }
+
     fn num_vals(&self) -> u32 {
         match &self.column.index {
             ColumnIndex::Empty { .. } => 0u32,
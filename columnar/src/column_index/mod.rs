@@ -71,6 +71,30 @@ impl ColumnIndex {
         }
     }
 
+    /// Returns the first doc id greater than or equal to `doc_id` having at least one value, or
+    /// `None` if there is none below `num_docs`.
+    ///
+    /// Unlike calling `has_value` on every doc, this skips over the docs without values.
+    pub fn next_doc_with_value(&self, doc_id: DocId, num_docs: DocId) -> Option<DocId> {
+        if doc_id >= num_docs {
+            return None;
+        }
+        match self {
+            ColumnIndex::Empty { .. } => None,
+            ColumnIndex::Full => Some(doc_id),
+            ColumnIndex::Optional(optional_index) => {
+                next_doc_in_optional_index(optional_index, doc_id)
+            }
+            ColumnIndex::Multivalued(MultiValueIndex::MultiValueIndexV1(index)) => {
+                (doc_id..num_docs).find(|&doc_id| !index.range(doc_id).is_empty())
+            }
+            // The optional index of the v2 format only contains the docs having values.
+            ColumnIndex::Multivalued(MultiValueIndex::MultiValueIndexV2(index)) => {
+                next_doc_in_optional_index(&index.optional_index, doc_id)
+            }
+        }
+    }
+
     pub fn value_row_ids(&self, doc_id: DocId) -> Range<RowId> {
         match self {
             ColumnIndex::Empty { .. } => 0..0,
@@ -191,6 +215,15 @@ impl ColumnIndex {
     }
 }
 
+fn next_doc_in_optional_index(optional_index: &OptionalIndex, doc_id: DocId) -> Option<DocId> {
+    let rank = optional_index.rank(doc_id);
+    if rank < optional_index.num_non_nulls() {
+        Some(optional_index.select(rank))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{Cardinality, ColumnIndex};
@@ -164,6 +164,16 @@ pub trait ColumnValues<T: PartialOrd = u64>: Send + Sync + DowncastSync {
     /// ∃i < self.num_vals(), self.get_val(i) == self.max_value()
     fn max_value(&self) -> T;
 
+    /// Returns a lower and an upper bound for the values of the rows in `row_id_range`.
+    ///
+    /// The bounds are not necessarily tight. Codecs storing block-level information, like the
+    /// blockwise linear codec, return bounds specific to the blocks of the range. The default
+    /// implementation returns the bounds of the whole column.
+    fn value_bounds(&self, row_id_range: Range<RowId>) -> (T, T) {
+        let _ = row_id_range;
+        (self.min_value(), self.max_value())
+    }
+
     /// The number of values in the column.
     fn num_vals(&self) -> u32;
 
@@ -195,6 +205,40 @@ impl<T: PartialOrd + Default> ColumnValues<T> for EmptyColumnValues {
     }
 }
 
+/// Column of values where all of the rows have the same value.
+pub struct ConstantColumnValues<T> {
+    value: T,
+    num_vals: u32,
+}
+
+impl<T> ConstantColumnValues<T> {
+    /// Creates a column of `num_vals` values, all equal to `value`.
+    pub fn new(value: T, num_vals: u32) -> Self {
+        ConstantColumnValues { value, num_vals }
+    }
+}
+
+impl<T: Copy + PartialOrd + Send + Sync + Debug + 'static> ColumnValues<T>
+    for ConstantColumnValues<T>
+{
+    #[inline(always)]
+    fn get_val(&self, _idx: u32) -> T {
+        self.value
+    }
+
+    fn min_value(&self) -> T {
+        self.value
+    }
+
+    fn max_value(&self) -> T {
+        self.value
+    }
+
+    fn num_vals(&self) -> u32 {
+        self.num_vals
+    }
+}
+
 impl<T: Copy + PartialOrd + Debug + 'static> ColumnValues<T> for Arc<dyn ColumnValues<T>> {
     #[inline(always)]
     fn get_val(&self, idx: u32) -> T {
@@ -216,6 +260,11 @@ impl<T: Copy + PartialOrd + Debug + 'static> ColumnValues<T> for Arc<dyn ColumnV
         self.as_ref().max_value()
     }
 
+    #[inline(always)]
+    fn value_bounds(&self, row_id_range: Range<RowId>) -> (T, T) {
+        self.as_ref().value_bounds(row_id_range)
+    }
+
     #[inline(always)]
     fn num_vals(&self) -> u32 {
         self.as_ref().num_vals()
@@ -66,6 +66,14 @@ where
         self.monotonic_mapping.mapping(from_max_value)
     }
 
+    fn value_bounds(&self, row_id_range: Range<u32>) -> (Output, Output) {
+        let (from_min_value, from_max_value) = self.from_column.value_bounds(row_id_range);
+        (
+            self.monotonic_mapping.mapping(from_min_value),
+            self.monotonic_mapping.mapping(from_max_value),
+        )
+    }
+
     fn num_vals(&self) -> u32 {
         self.from_column.num_vals()
     }
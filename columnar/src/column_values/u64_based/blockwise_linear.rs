@@ -1,4 +1,5 @@
 use std::io::Write;
+use std::ops::Range;
 use std::sync::Arc;
 use std::{io, iter};
 
@@ -230,6 +231,44 @@ impl ColumnValues for BlockwiseLinearReader {
         self.stats.max_value
     }
 
+    fn value_bounds(&self, row_id_range: Range<u32>) -> (u64, u64) {
+        let column_bounds = (self.stats.min_value, self.stats.max_value);
+        let row_id_range = row_id_range.start..row_id_range.end.min(self.stats.num_rows);
+        if row_id_range.is_empty() {
+            return column_bounds;
+        }
+        // Bounds of the values divided by the gcd and shifted by the min value.
+        let max_normalized_value =
+            (self.stats.max_value - self.stats.min_value) / self.stats.gcd.get();
+        let mut normalized_bounds = (u64::MAX, 0u64);
+        let first_block_id = row_id_range.start / BLOCK_SIZE;
+        let last_block_id = (row_id_range.end - 1) / BLOCK_SIZE;
+        for block_id in first_block_id..=last_block_id {
+            let block_start = block_id * BLOCK_SIZE;
+            let idx_range_within_block = row_id_range.start.max(block_start) - block_start
+                ..row_id_range.end.min(block_start + BLOCK_SIZE) - block_start;
+            let block = &self.blocks[block_id as usize];
+            let Some((block_lower_bound, block_upper_bound)) = block
+                .line
+                .interpolated_bounds(idx_range_within_block, block.bit_unpacker.bit_width())
+            else {
+                return column_bounds;
+            };
+            if block_lower_bound > max_normalized_value {
+                return column_bounds;
+            }
+            normalized_bounds.0 = normalized_bounds.0.min(block_lower_bound);
+            normalized_bounds.1 = normalized_bounds
+                .1
+                .max(block_upper_bound.min(max_normalized_value));
+        }
+        let gcd = self.stats.gcd.get();
+        (
+            self.stats.min_value + gcd * normalized_bounds.0,
+            self.stats.min_value + gcd * normalized_bounds.1,
+        )
+    }
+
     #[inline(always)]
     fn num_vals(&self) -> u32 {
         self.stats.num_rows
@@ -239,6 +278,7 @@ impl ColumnValues for BlockwiseLinearReader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::column_values::u64_based::CodecType;
     use crate::column_values::u64_based::tests::create_and_validate;
 
     #[test]
@@ -270,6 +310,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_blockwise_linear_value_bounds() {
+        let vals: Vec<u64> = (0..2_000u64).map(|i| 1_000 + i * 10 + i % 3).collect();
+        let reader = crate::column_values::serialize_and_load_u64_based_column_values::<u64>(
+            &&vals[..],
+            &[CodecType::BlockwiseLinear],
+        );
+        for row_id_range in [0..2_000, 0..1, 100..200, 500..600, 1_999..2_000, 600..1_800] {
+            let (lower_bound, upper_bound) = reader.value_bounds(row_id_range.clone());
+            let range_vals = &vals[row_id_range.start as usize..row_id_range.end as usize];
+            assert!(lower_bound <= *range_vals.iter().min().unwrap());
+            assert!(upper_bound >= *range_vals.iter().max().unwrap());
+            // The bounds are those of the blocks of the range, not of the whole column.
+            assert!(upper_bound - lower_bound < 20 * 1_024);
+        }
+        assert!(reader.value_bounds(100..200).1 < 3_000);
+    }
+
     #[test]
     fn test_blockwise_linear_fast_field_rand() {
         for _ in 0..500 {
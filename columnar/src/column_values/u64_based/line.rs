@@ -1,5 +1,6 @@
 use std::io;
 use std::num::NonZeroU32;
+use std::ops::Range;
 
 use common::{BinarySerializable, VInt};
 
@@ -144,6 +145,42 @@ impl Line {
     }
 }
 
+impl Line {
+    /// Returns bounds of `self.eval(x).wrapping_add(diff)` for any `x` in the non-empty `x_range`
+    /// and any `diff` fitting in `bit_width` bits.
+    ///
+    /// Returns `None` if the values of the line over `x_range` are not monotonic, or if the bounds
+    /// wrap around the `u64` space.
+    pub fn interpolated_bounds(&self, x_range: Range<u32>, bit_width: u8) -> Option<(u64, u64)> {
+        let first_x = x_range.start;
+        let last_x = x_range.end.checked_sub(1)?;
+        // The linear part of `eval` is the i32 cast of `(x * slope) >> 32`. It is monotonic as
+        // long as that cast does not wrap.
+        let is_monotonic = if self.slope < 1 << 63 {
+            (last_x as u128 * self.slope as u128) >> 32 <= i32::MAX as u128
+        } else {
+            last_x as u128 * self.slope.wrapping_neg() as u128 <= 1 << 63
+        };
+        if !is_monotonic {
+            return None;
+        }
+        let linear_part = |x: u32| self.eval(x).wrapping_sub(self.intercept) as i64 as i128;
+        let (first_linear_part, last_linear_part) = (linear_part(first_x), linear_part(last_x));
+        let lower_bound = self.intercept as i128 + first_linear_part.min(last_linear_part);
+        let upper_bound = self.intercept as i128
+            + first_linear_part.max(last_linear_part)
+            + ((1u128 << bit_width) - 1) as i128;
+        const SPACE: i128 = 1 << 64;
+        if lower_bound.div_euclid(SPACE) != upper_bound.div_euclid(SPACE) {
+            return None;
+        }
+        Some((
+            lower_bound.rem_euclid(SPACE) as u64,
+            upper_bound.rem_euclid(SPACE) as u64,
+        ))
+    }
+}
+
 impl BinarySerializable for Line {
     fn serialize<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
         VInt(self.slope).serialize(writer)?;
@@ -190,6 +227,36 @@ mod tests {
             .max()
     }
 
+    #[test]
+    fn test_interpolated_bounds() {
+        let datasets: [Vec<u64>; 4] = [
+            (0..512).map(|x| 1_000 + x * 3 + x % 7).collect(),
+            (0..512).map(|x| 1_000_000 - x * 1_000 + x % 5).collect(),
+            (0..512).map(|x| u64::MAX - 2_000 + x * 3).collect(),
+            vec![13, 13, 12, 11, 11, 11],
+        ];
+        for ys in datasets {
+            let line = Line::train(&VecColumn::from(ys.clone()));
+            let max_diff = ys
+                .iter()
+                .enumerate()
+                .map(|(x, y)| y.wrapping_sub(line.eval(x as u32)))
+                .max()
+                .unwrap();
+            let bit_width = tantivy_bitpacker::compute_num_bits(max_diff);
+            for x_range in [0..ys.len() as u32, 1..3, 2..ys.len() as u32] {
+                let (lower_bound, upper_bound) = line
+                    .interpolated_bounds(x_range.clone(), bit_width)
+                    .unwrap();
+                for x in x_range {
+                    let y = ys[x as usize];
+                    assert!(lower_bound <= y && y <= upper_bound);
+                }
+            }
+        }
+        assert_eq!(Line::default().interpolated_bounds(0..0, 0), None);
+    }
+
     #[test]
     fn test_train() {
         test_line_interpol_with_translation(&[11, 11, 11, 12, 12, 13], Some(1));
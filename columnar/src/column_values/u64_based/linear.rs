@@ -1,4 +1,5 @@
 use std::io;
+use std::ops::Range;
 
 use common::{BinarySerializable, OwnedBytes};
 use tantivy_bitpacker::{BitPacker, BitUnpacker, compute_num_bits};
@@ -39,6 +40,25 @@ impl ColumnValues for LinearReader {
         self.stats.max_value
     }
 
+    fn value_bounds(&self, row_id_range: Range<RowId>) -> (u64, u64) {
+        let column_bounds = (self.stats.min_value, self.stats.max_value);
+        let row_id_range = row_id_range.start..row_id_range.end.min(self.stats.num_rows);
+        let Some((lower_bound, upper_bound)) = self
+            .linear_params
+            .line
+            .interpolated_bounds(row_id_range, self.linear_params.bit_unpacker.bit_width())
+        else {
+            return column_bounds;
+        };
+        if lower_bound > self.stats.max_value || upper_bound < self.stats.min_value {
+            return column_bounds;
+        }
+        (
+            lower_bound.max(self.stats.min_value),
+            upper_bound.min(self.stats.max_value),
+        )
+    }
+
     #[inline]
     fn num_vals(&self) -> u32 {
         self.stats.num_rows
@@ -44,7 +44,8 @@ pub use block_accessor::ColumnBlockAccessor;
 pub use column::{BytesColumn, Column, StrColumn};
 pub use column_index::ColumnIndex;
 pub use column_values::{
-    ColumnValues, EmptyColumnValues, MonotonicallyMappableToU64, MonotonicallyMappableToU128,
+    ColumnValues, ConstantColumnValues, EmptyColumnValues, MonotonicallyMappableToU64,
+    MonotonicallyMappableToU128,
 };
 pub use columnar::{
     CURRENT_VERSION, ColumnType, ColumnarReader, ColumnarWriter, HasAssociatedColumnType,
@@ -67,7 +68,7 @@ pub struct RowAddr {
 pub use sstable::Dictionary;
 pub type Streamer<'a> = sstable::Streamer<'a, VoidSSTable>;
 
-pub use common::DateTime;
+pub use common::{DateTime, Granularity};
 
 #[derive(Copy, Clone, Debug)]
 pub struct InvalidData;
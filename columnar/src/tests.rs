@@ -91,6 +91,45 @@ fn test_dataframe_writer_u64_multivalued() {
     assert_eq!(divisor_col.num_docs(), 7);
 }
 
+#[test]
+fn test_column_coalesce() {
+    let mut dataframe_writer = ColumnarWriter::default();
+    dataframe_writer.record_numerical(0u32, "first", 1u64);
+    dataframe_writer.record_numerical(2u32, "first", 3u64);
+    dataframe_writer.record_numerical(1u32, "second", 20u64);
+    dataframe_writer.record_numerical(2u32, "second", 30u64);
+    dataframe_writer.record_numerical(3u32, "second", 40u64);
+    dataframe_writer.record_numerical(3u32, "second", 41u64);
+    let mut buffer: Vec<u8> = Vec::new();
+    dataframe_writer.serialize(5, &mut buffer).unwrap();
+    let columnar = ColumnarReader::open(buffer).unwrap();
+    let open_i64 = |column_name: &str| -> Column<i64> {
+        let cols = columnar.read_columns(column_name).unwrap();
+        let DynamicColumn::I64(col) = cols[0].open().unwrap() else {
+            panic!();
+        };
+        col
+    };
+    let first = open_i64("first");
+    let second = open_i64("second");
+
+    let coalesced = Column::coalesce(&[first.clone(), second.clone()], 5);
+    assert_eq!(coalesced.get_cardinality(), Cardinality::Multivalued);
+    let vals: Vec<Vec<i64>> = (0..5)
+        .map(|doc| coalesced.values_for_doc(doc).collect())
+        .collect();
+    assert_eq!(vals, vec![vec![1], vec![20], vec![3], vec![40, 41], vec![]]);
+
+    let coalesced = Column::coalesce(&[first.clone()], 5);
+    assert_eq!(coalesced.get_cardinality(), Cardinality::Optional);
+    let vals: Vec<Option<i64>> = (0..5).map(|doc| coalesced.first(doc)).collect();
+    assert_eq!(vals, vec![Some(1), None, Some(3), None, None]);
+
+    let coalesced = Column::coalesce(&[], 5);
+    assert_eq!(coalesced.num_docs(), 5);
+    assert_eq!(coalesced.first(0), None::<i64>);
+}
+
 #[test]
 fn test_dataframe_writer_ip_addr() {
     let mut dataframe_writer = ColumnarWriter::default();
@@ -1,4 +1,5 @@
 use std::io::Write;
+use std::ops::Range;
 use std::{fmt, io};
 
 use ownedbytes::OwnedBytes;
@@ -239,6 +240,20 @@ impl BitSet {
         self.intersect_update_with_iter(other.iter_tinysets());
     }
 
+    /// Intersect with another `BitSet` of the same `max_value`.
+    pub fn intersect_update_with_bitset(&mut self, other: &BitSet) {
+        self.intersect_update_with_iter(other.tinysets.iter().cloned());
+    }
+
+    /// Union with another `BitSet` of the same `max_value`.
+    pub fn union_update(&mut self, other: &BitSet) {
+        self.len = 0;
+        for (left, right) in self.tinysets.iter_mut().zip(other.tinysets.iter().cloned()) {
+            *left = left.union(right);
+            self.len += left.len() as u64;
+        }
+    }
+
     /// Intersect with tinysets
     fn intersect_update_with_iter(&mut self, other: impl Iterator<Item = TinySet>) {
         self.len = 0;
@@ -263,6 +278,37 @@ impl BitSet {
         self.len += u64::from(self.tinysets[higher as usize].insert_mut(lower));
     }
 
+    /// Inserts all of the elements of `range` in the `BitSet`, a bucket at a time.
+    pub fn insert_range(&mut self, range: Range<u32>) {
+        let mut start = range.start;
+        while start < range.end {
+            let bucket = start / 64u32;
+            let bucket_end = (u64::from(bucket + 1) * 64u64).min(u64::from(range.end)) as u32;
+            let mut inserted = TinySet::range_greater_or_equal(start % 64u32);
+            let end_in_bucket = bucket_end - bucket * 64u32;
+            if end_in_bucket < 64u32 {
+                inserted = inserted.intersect(TinySet::range_lower(end_in_bucket));
+            }
+            let tinyset = &mut self.tinysets[bucket as usize];
+            let previous_len = tinyset.len();
+            *tinyset = tinyset.union(inserted);
+            self.len += u64::from(tinyset.len() - previous_len);
+            start = bucket_end;
+        }
+    }
+
+    /// Iterate over the elements of the `BitSet`, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.tinysets
+            .iter()
+            .enumerate()
+            .flat_map(|(bucket, tinyset)| {
+                tinyset
+                    .into_iter()
+                    .map(move |el| bucket as u32 * 64u32 + el)
+            })
+    }
+
     /// Inserts an element in the `BitSet`
     #[inline]
     pub fn remove(&mut self, el: u32) {
@@ -367,6 +413,22 @@ impl ReadOnlyBitSet {
             })
     }
 
+    /// Iterate over the positions in `[0, max_value)` that are not in the bitset.
+    #[inline]
+    pub fn iter_complement(&self) -> impl Iterator<Item = u32> + '_ {
+        self.iter_tinysets()
+            .enumerate()
+            .flat_map(move |(chunk_num, tinyset)| {
+                let chunk_base_val = chunk_num as u32 * 64;
+                // The padding of the last bucket is excluded by the `take_while`.
+                tinyset
+                    .complement()
+                    .into_iter()
+                    .map(move |val| val + chunk_base_val)
+                    .take_while(move |doc| *doc < self.max_value)
+            })
+    }
+
     /// Returns true iff the elements is in the `BitSet`.
     #[inline]
     pub fn contains(&self, el: u32) -> bool {
@@ -607,6 +669,31 @@ mod tests {
         test_against_hashset(&[62u32, 63u32], 64);
     }
 
+    #[test]
+    fn test_bitset_insert_range_and_union() {
+        let mut bitset = BitSet::with_max_value(300);
+        bitset.insert_range(3..3);
+        assert_eq!(bitset.len(), 0);
+        bitset.insert_range(60..200);
+        bitset.insert(10);
+        bitset.insert_range(150..256);
+        assert_eq!(bitset.len(), 197);
+        let expected: Vec<u32> = std::iter::once(10).chain(60..256).collect();
+        assert_eq!(bitset.iter().collect::<Vec<u32>>(), expected);
+
+        let mut other = BitSet::with_max_value(300);
+        other.insert_range(250..300);
+        let mut union = bitset.clone();
+        union.union_update(&other);
+        assert_eq!(union.len(), 241);
+        assert!(union.contains(299));
+        bitset.intersect_update_with_bitset(&other);
+        assert_eq!(
+            bitset.iter().collect::<Vec<u32>>(),
+            (250..256).collect::<Vec<u32>>()
+        );
+    }
+
     #[test]
     fn test_bitset_num_buckets() {
         use super::num_buckets;
@@ -25,6 +25,33 @@ pub enum DateTimePrecision {
     Nanoseconds,
 }
 
+/// Unit to which a datetime can be truncated, see [`DateTime::truncate_to`].
+///
+/// Days are 24 hours long: time zones and leap seconds are ignored.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Granularity {
+    /// Whole seconds.
+    Second,
+    /// Whole minutes.
+    Minute,
+    /// Whole hours.
+    Hour,
+    /// Whole days, in UTC.
+    Day,
+}
+
+impl Granularity {
+    /// Returns the length of the unit, in nanoseconds.
+    pub const fn nanos(self) -> i64 {
+        match self {
+            Granularity::Second => 1_000_000_000,
+            Granularity::Minute => 60 * 1_000_000_000,
+            Granularity::Hour => 3_600 * 1_000_000_000,
+            Granularity::Day => 86_400 * 1_000_000_000,
+        }
+    }
+}
+
 /// A date/time value with nanoseconds precision.
 ///
 /// This timestamp does not carry any explicit time zone information.
@@ -156,6 +183,27 @@ impl DateTime {
     }
 }
 
+impl DateTime {
+    /// Rounds the datetime down to a multiple of `granularity`.
+    ///
+    /// Unlike [`DateTime::truncate`], datetimes before the UNIX epoch are rounded down too, e.g.
+    /// one second before the epoch is truncated to the previous day.
+    pub fn truncate_to(self, granularity: Granularity) -> Self {
+        let unit_nanos = granularity.nanos();
+        Self {
+            timestamp_nanos: self.timestamp_nanos.div_euclid(unit_nanos) * unit_nanos,
+        }
+    }
+
+    /// Returns the number of days elapsed from `self` to `reference`, as a fraction.
+    ///
+    /// The result is negative if `reference` is before `self`.
+    pub fn days_until(self, reference: DateTime) -> f64 {
+        let elapsed_nanos = reference.timestamp_nanos as i128 - self.timestamp_nanos as i128;
+        elapsed_nanos as f64 / Granularity::Day.nanos() as f64
+    }
+}
+
 impl fmt::Debug for DateTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let utc_rfc3339 = self.into_utc().format(&Rfc3339).map_err(|_| fmt::Error)?;
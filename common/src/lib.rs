@@ -16,7 +16,7 @@ mod vint;
 mod writer;
 pub use bitset::*;
 pub use byte_count::ByteCount;
-pub use datetime::{DateTime, DateTimePrecision};
+pub use datetime::{DateTime, DateTimePrecision, Granularity};
 pub use group_by::GroupByIteratorExtended;
 pub use json_path_writer::JsonPathWriter;
 pub use ownedbytes::{OwnedBytes, StableDeref};
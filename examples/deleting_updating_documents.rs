@@ -117,7 +117,7 @@ fn main() -> tantivy::Result<()> {
     //
     // Also you might have noticed that we apply the delete before
     // having committed. This does not matter really...
-    index_writer.delete_term(frankenstein_isbn.clone());
+    index_writer.delete_term(frankenstein_isbn.clone())?;
 
     // We now need to reinsert our document without the typo.
     index_writer.add_document(doc!(
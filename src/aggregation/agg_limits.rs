@@ -5,7 +5,11 @@ use std::sync::Arc;
 use common::ByteCount;
 
 use super::collector::DEFAULT_MEMORY_LIMIT;
-use super::{AggregationError, DEFAULT_BUCKET_LIMIT};
+use super::spill::AggregationSpill;
+use super::DEFAULT_BUCKET_LIMIT;
+use crate::error::BudgetKind;
+use crate::reader::charge_current_search;
+use crate::TantivyError;
 
 /// An estimate for memory consumption. Non recursive
 pub trait MemoryConsumption {
@@ -24,6 +28,9 @@ impl<K, V, S> MemoryConsumption for HashMap<K, V, S> {
 ///
 /// The memory limit is also a guard, which tracks how much it allocated and releases it's memory
 /// on the shared counter. Cloning will create a new guard.
+///
+/// The memory is also charged to the [`GlobalSearchBudget`](crate::GlobalSearchBudget) of the
+/// reader of the searcher, if any.
 pub struct AggregationLimitsGuard {
     /// The counter which is shared between the aggregations for one request.
     memory_consumption: Arc<AtomicU64>,
@@ -34,6 +41,11 @@ pub struct AggregationLimitsGuard {
     bucket_limit: u32,
     /// Allocated memory with this guard.
     allocated_with_the_guard: u64,
+    /// The number of docs buffered before they are passed down the aggregation tree.
+    /// If not set, it is derived from the aggregation request.
+    block_size: Option<usize>,
+    /// Where the terms aggregations with `allow_spill` spill their buckets.
+    spill: Option<AggregationSpill>,
 }
 impl Clone for AggregationLimitsGuard {
     fn clone(&self) -> Self {
@@ -42,6 +54,8 @@ impl Clone for AggregationLimitsGuard {
             memory_limit: self.memory_limit,
             bucket_limit: self.bucket_limit,
             allocated_with_the_guard: 0,
+            block_size: self.block_size,
+            spill: self.spill.clone(),
         }
     }
 }
@@ -62,6 +76,8 @@ impl Default for AggregationLimitsGuard {
             memory_limit: DEFAULT_MEMORY_LIMIT.into(),
             bucket_limit: DEFAULT_BUCKET_LIMIT,
             allocated_with_the_guard: 0,
+            block_size: None,
+            spill: None,
         }
     }
 }
@@ -84,15 +100,44 @@ impl AggregationLimitsGuard {
             memory_limit: memory_limit.unwrap_or(DEFAULT_MEMORY_LIMIT).into(),
             bucket_limit: bucket_limit.unwrap_or(DEFAULT_BUCKET_LIMIT),
             allocated_with_the_guard: 0,
+            block_size: None,
+            spill: None,
         }
     }
 
+    /// Sets the number of documents that are buffered before they are passed down the
+    /// aggregation tree as one block.
+    ///
+    /// By default the block size is chosen per request, based on the number of aggregations and
+    /// their column types. The value is clamped between 32 and 512.
+    /// The block size only affects performance, the aggregation results are the same.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Returns the block size set via [`AggregationLimitsGuard::with_block_size`], if any.
+    pub fn block_size(&self) -> Option<usize> {
+        self.block_size
+    }
+
+    pub(crate) fn with_spill(mut self, spill: AggregationSpill) -> Self {
+        self.spill = Some(spill);
+        self
+    }
+
+    pub(crate) fn spill(&self) -> Option<&AggregationSpill> {
+        self.spill.as_ref()
+    }
+
     pub(crate) fn add_memory_consumed(&mut self, add_num_bytes: u64) -> crate::Result<()> {
         let prev_value = self
             .memory_consumption
             .fetch_add(add_num_bytes, Ordering::Relaxed);
         self.allocated_with_the_guard += add_num_bytes;
         validate_memory_consumption(prev_value + add_num_bytes, self.memory_limit)?;
+        // The memory is also charged to the global search budget of the reader, if any.
+        charge_current_search(add_num_bytes)?;
         Ok(())
     }
 
@@ -101,16 +146,67 @@ impl AggregationLimitsGuard {
     }
 }
 
+/// Memory of a result charged to the aggregation limits for as long as the result is kept, e.g.
+/// for the results of the segments merged one at a time.
+///
+/// The charge is not part of the result: a clone is not charged, and all instances compare equal.
+///
+/// Nested results embed it too, so the guard is boxed to keep them small.
+#[derive(Default)]
+pub(crate) struct ChargedMemory {
+    guard: Option<Box<AggregationLimitsGuard>>,
+}
+
+impl ChargedMemory {
+    /// Charges `limits` with the growth of the result, up to its new `memory_consumption`.
+    pub(crate) fn charge(
+        &mut self,
+        limits: &AggregationLimitsGuard,
+        memory_consumption: u64,
+    ) -> crate::Result<()> {
+        let guard = self.guard.get_or_insert_with(|| Box::new(limits.clone()));
+        let charged = guard.allocated_with_the_guard;
+        if memory_consumption > charged {
+            guard.add_memory_consumed(memory_consumption - charged)?;
+        }
+        Ok(())
+    }
+}
+
+impl Clone for ChargedMemory {
+    fn clone(&self) -> Self {
+        ChargedMemory::default()
+    }
+}
+
+impl PartialEq for ChargedMemory {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Debug for ChargedMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let charged = self
+            .guard
+            .as_ref()
+            .map_or(0, |guard| guard.allocated_with_the_guard);
+        f.debug_struct("ChargedMemory")
+            .field("charged", &charged)
+            .finish()
+    }
+}
+
 fn validate_memory_consumption(
     memory_consumption: u64,
     memory_limit: ByteCount,
-) -> Result<(), AggregationError> {
+) -> crate::Result<()> {
     // Load the estimated memory consumed by the aggregations
-    let memory_consumed: ByteCount = memory_consumption.into();
-    if memory_consumed > memory_limit {
-        return Err(AggregationError::MemoryExceeded {
-            limit: memory_limit,
-            current: memory_consumed,
+    if memory_consumption > memory_limit.get_bytes() {
+        return Err(TantivyError::BudgetExceeded {
+            kind: BudgetKind::Memory,
+            limit: memory_limit.get_bytes(),
+            used: memory_consumption,
         });
     }
     Ok(())
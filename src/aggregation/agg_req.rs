@@ -31,13 +31,17 @@ use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
 use super::bucket::{
-    DateHistogramAggregationReq, HistogramAggregation, RangeAggregation, TermsAggregation,
+    CompositeAggregation, DateHistogramAggregationReq, DateRangeAggregation, ExistsAggregation,
+    HistogramAggregation, MissingAggregation, PathTermsAggregation, PrefixTermsAggregation,
+    RangeAggregation, SignificantTermsAggregation, TermsAggregation,
 };
 use super::metric::{
-    AverageAggregation, CardinalityAggregationReq, CountAggregation, ExtendedStatsAggregation,
-    MaxAggregation, MinAggregation, PercentilesAggregationReq, StatsAggregation, SumAggregation,
-    TopHitsAggregationReq,
+    AverageAggregation, CardinalityAggregationReq, CountAggregation, DateMetricFormat,
+    ExtendedStatsAggregation, MaxAggregation, MinAggregation, PercentilesAggregationReq,
+    StatsAggregation, SumAggregation, TopHitsAggregationReq,
 };
+use super::value_transform::ValueTransform;
+use super::AggregationSampling;
 
 /// The top-level aggregation request structure, which contains [`Aggregation`] and their user
 /// defined names. It is also used in buckets aggregations to define sub-aggregations.
@@ -45,6 +49,27 @@ use super::metric::{
 /// The key is the user defined name of the aggregation.
 pub type Aggregations = HashMap<String, Aggregation>;
 
+/// An [`Aggregations`] request, with the options applying to all of its aggregations next to
+/// them.
+///
+/// ```json
+/// {
+///     "sampling": { "probability": 0.1, "seed": 42 },
+///     "avg_price": { "avg": { "field": "price" } }
+/// }
+/// ```
+///
+/// See [`AggregationCollector::from_request()`](super::AggregationCollector::from_request).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AggregationRequest {
+    /// Samples the documents before they are aggregated, see [`AggregationSampling`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<AggregationSampling>,
+    /// The aggregations, by name.
+    #[serde(flatten)]
+    pub aggs: Aggregations,
+}
+
 /// Aggregation request.
 ///
 /// An aggregation is either a bucket or a metric.
@@ -58,6 +83,40 @@ pub struct Aggregation {
     #[serde(rename = "aggs")]
     #[serde(skip_serializing_if = "Aggregations::is_empty")]
     pub sub_aggregation: Aggregations,
+    /// Fields read as a single logical field, e.g. when the field of the aggregation was renamed
+    /// and older documents still have their values in the former field.
+    ///
+    /// The `field` of the aggregation has to be one of them. The values of a document are read
+    /// from the first field of the list that has values for it: the order of the list sets the
+    /// precedence, when a document has values in several of these fields.
+    ///
+    /// ```json
+    /// {
+    ///     "stats": { "field": "price" },
+    ///     "field_union": ["price", "price_usd"]
+    /// }
+    /// ```
+    ///
+    /// In a segment, the fields need to be of the same type, or all numeric, in which case their
+    /// values are read as `f64`. Text fields cannot be combined, as their terms are stored in
+    /// different dictionaries.
+    ///
+    /// Only aggregations reading a single fast field support it: `range`, `histogram`,
+    /// `date_histogram`, `terms`, `cardinality` and the metric aggregations except `top_hits`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_union: Option<Vec<String>>,
+    /// Fails the aggregation when the values of its field have types it cannot combine, instead
+    /// of reporting the conflict in the `warnings` of the result. Applies to the
+    /// sub-aggregations too.
+    ///
+    /// This happens with JSON fields, when documents wrote values of different types to the same
+    /// path. Numeric values are read as `f64` by the metrics, whatever their type, but the values
+    /// of other types are ignored, e.g. the text values of a path aggregated by `avg`. The
+    /// `terms` aggregation returns all its keys as text when they mix numbers and text.
+    ///
+    /// See [`AggregationResults::warnings()`](super::agg_result::AggregationResults::warnings).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub strict_types: bool,
 }
 
 /// In order to display proper error message, we cannot rely on flattening
@@ -70,6 +129,10 @@ struct AggregationForDeserialization {
     #[serde(rename = "aggs")]
     #[serde(default)]
     pub sub_aggregation: Aggregations,
+    #[serde(default)]
+    pub field_union: Option<Vec<String>>,
+    #[serde(default)]
+    pub strict_types: bool,
 }
 
 impl TryFrom<AggregationForDeserialization> for Aggregation {
@@ -79,15 +142,41 @@ impl TryFrom<AggregationForDeserialization> for Aggregation {
         let AggregationForDeserialization {
             aggs_remaining_json,
             sub_aggregation,
+            field_union,
+            strict_types,
         } = value;
         let agg: AggregationVariants = serde_json::from_value(aggs_remaining_json)?;
+        validate_metric_value_source(&agg)?;
         Ok(Aggregation {
             agg,
             sub_aggregation,
+            field_union,
+            strict_types,
         })
     }
 }
 
+/// Checks that the metric aggregations which can aggregate the number of tokens of a text field
+/// read either a field or a token count.
+fn validate_metric_value_source(agg: &AggregationVariants) -> serde_json::Result<()> {
+    let field = match agg {
+        AggregationVariants::Average(avg) => &avg.field,
+        AggregationVariants::Max(max) => &max.field,
+        AggregationVariants::Min(min) => &min.field,
+        AggregationVariants::Stats(stats) => &stats.field,
+        AggregationVariants::ExtendedStats(extended_stats) => &extended_stats.field,
+        AggregationVariants::Sum(sum) => &sum.field,
+        _ => return Ok(()),
+    };
+    match (field.is_empty(), agg.token_count_field().is_some()) {
+        (true, false) => Err(serde::de::Error::missing_field("field")),
+        (false, true) => Err(serde::de::Error::custom(
+            "exactly one of `field` and `token_count_field` must be set",
+        )),
+        _ => Ok(()),
+    }
+}
+
 impl Aggregation {
     pub(crate) fn sub_aggregation(&self) -> &Aggregations {
         &self.sub_aggregation
@@ -100,8 +189,18 @@ impl Aggregation {
                 .iter()
                 .map(|s| s.to_string()),
         );
+        if let Some(field_union) = self.field_union.as_ref() {
+            fast_field_names.extend(field_union.iter().cloned());
+        }
         fast_field_names.extend(get_fast_field_names(&self.sub_aggregation));
     }
+
+    fn requires_scoring(&self) -> bool {
+        self.agg
+            .as_top_hits()
+            .is_some_and(|top_hits| top_hits.sorts_by_score())
+            || requires_scoring(&self.sub_aggregation)
+    }
 }
 
 /// Extract all fast field names used in the tree.
@@ -113,6 +212,12 @@ pub fn get_fast_field_names(aggs: &Aggregations) -> HashSet<String> {
     fast_field_names
 }
 
+/// Returns true if an aggregation of the tree needs the score of the documents, i.e. a
+/// `top_hits` aggregation sorting by `_score`.
+pub fn requires_scoring(aggs: &Aggregations) -> bool {
+    aggs.values().any(|agg| agg.requires_scoring())
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 /// All aggregation types.
 pub enum AggregationVariants {
@@ -120,6 +225,9 @@ pub enum AggregationVariants {
     /// Put data into buckets of user-defined ranges.
     #[serde(rename = "range")]
     Range(RangeAggregation),
+    /// Put data into buckets of user-defined date ranges, which may be relative to now.
+    #[serde(rename = "date_range")]
+    DateRange(DateRangeAggregation),
     /// Put data into a histogram.
     #[serde(rename = "histogram")]
     Histogram(HistogramAggregation),
@@ -129,6 +237,24 @@ pub enum AggregationVariants {
     /// Put data into buckets of terms.
     #[serde(rename = "terms")]
     Terms(TermsAggregation),
+    /// Put data into buckets of terms which are unusually frequent compared to the whole index.
+    #[serde(rename = "significant_terms")]
+    SignificantTerms(SignificantTermsAggregation),
+    /// Put data into buckets of hierarchical paths truncated to a given depth.
+    #[serde(rename = "path_terms")]
+    PathTerms(PathTermsAggregation),
+    /// Put data into buckets of the terms starting with a prefix.
+    #[serde(rename = "prefix_terms")]
+    PrefixTerms(PrefixTermsAggregation),
+    /// Put data into buckets of combined values of several sources, one page at a time.
+    #[serde(rename = "composite")]
+    Composite(CompositeAggregation),
+    /// Put the documents having a value in a field into a single bucket.
+    #[serde(rename = "exists")]
+    Exists(ExistsAggregation),
+    /// Put the documents without any value in a field into a single bucket.
+    #[serde(rename = "missing")]
+    Missing(MissingAggregation),
 
     // Metric aggregation types
     /// Computes the average of the extracted values.
@@ -169,9 +295,20 @@ pub enum AggregationVariants {
 impl AggregationVariants {
     /// Returns the name of the fields used by the aggregation.
     pub fn get_fast_field_names(&self) -> Vec<&str> {
+        // The token counts are read from the fieldnorms or from a hidden column.
+        if self.token_count_field().is_some() {
+            return Vec::new();
+        }
         match self {
             AggregationVariants::Terms(terms) => vec![terms.field.as_str()],
+            AggregationVariants::SignificantTerms(terms) => vec![terms.field.as_str()],
+            AggregationVariants::PathTerms(terms) => vec![terms.field.as_str()],
+            AggregationVariants::PrefixTerms(terms) => vec![terms.field.as_str()],
+            AggregationVariants::Composite(composite) => composite.field_names(),
+            AggregationVariants::Exists(exists) => vec![exists.field.as_str()],
+            AggregationVariants::Missing(missing) => vec![missing.field.as_str()],
             AggregationVariants::Range(range) => vec![range.field.as_str()],
+            AggregationVariants::DateRange(range) => vec![range.field.as_str()],
             AggregationVariants::Histogram(histogram) => vec![histogram.field.as_str()],
             AggregationVariants::DateHistogram(histogram) => vec![histogram.field.as_str()],
             AggregationVariants::Average(avg) => vec![avg.field_name()],
@@ -187,12 +324,65 @@ impl AggregationVariants {
         }
     }
 
-    pub(crate) fn as_range(&self) -> Option<&RangeAggregation> {
-        match &self {
-            AggregationVariants::Range(range) => Some(range),
-            _ => None,
+    /// Returns the transform applied to the values of the aggregation, if any.
+    pub(crate) fn value_transform(&self) -> Option<&ValueTransform> {
+        match self {
+            AggregationVariants::Range(range) => range.value_transform.as_ref(),
+            AggregationVariants::Histogram(histogram) => histogram.value_transform.as_ref(),
+            AggregationVariants::Average(avg) => avg.value_transform.as_ref(),
+            AggregationVariants::Max(max) => max.value_transform.as_ref(),
+            AggregationVariants::Min(min) => min.value_transform.as_ref(),
+            AggregationVariants::Stats(stats) => stats.value_transform.as_ref(),
+            AggregationVariants::ExtendedStats(extended_stats) => {
+                extended_stats.value_transform.as_ref()
+            }
+            AggregationVariants::Sum(sum) => sum.value_transform.as_ref(),
+            AggregationVariants::Percentiles(per) => per.value_transform.as_ref(),
+            AggregationVariants::Terms(_)
+            | AggregationVariants::SignificantTerms(_)
+            | AggregationVariants::PathTerms(_)
+            | AggregationVariants::PrefixTerms(_)
+            | AggregationVariants::Composite(_)
+            | AggregationVariants::Exists(_)
+            | AggregationVariants::Missing(_)
+            | AggregationVariants::DateHistogram(_)
+            | AggregationVariants::DateRange(_)
+            | AggregationVariants::Count(_)
+            | AggregationVariants::TopHits(_)
+            | AggregationVariants::Cardinality(_) => None,
+        }
+    }
+
+    /// Returns the text field whose number of tokens per document is aggregated by a metric
+    /// aggregation, if any.
+    pub(crate) fn token_count_field(&self) -> Option<&str> {
+        match self {
+            AggregationVariants::Average(avg) => avg.token_count_field.as_deref(),
+            AggregationVariants::Max(max) => max.token_count_field.as_deref(),
+            AggregationVariants::Min(min) => min.token_count_field.as_deref(),
+            AggregationVariants::Stats(stats) => stats.token_count_field.as_deref(),
+            AggregationVariants::ExtendedStats(extended_stats) => {
+                extended_stats.token_count_field.as_deref()
+            }
+            AggregationVariants::Sum(sum) => sum.token_count_field.as_deref(),
+            AggregationVariants::Terms(_)
+            | AggregationVariants::SignificantTerms(_)
+            | AggregationVariants::PathTerms(_)
+            | AggregationVariants::PrefixTerms(_)
+            | AggregationVariants::Composite(_)
+            | AggregationVariants::Exists(_)
+            | AggregationVariants::Missing(_)
+            | AggregationVariants::Range(_)
+            | AggregationVariants::DateRange(_)
+            | AggregationVariants::Histogram(_)
+            | AggregationVariants::DateHistogram(_)
+            | AggregationVariants::Count(_)
+            | AggregationVariants::Percentiles(_)
+            | AggregationVariants::TopHits(_)
+            | AggregationVariants::Cardinality(_) => None,
         }
     }
+
     pub(crate) fn as_histogram(&self) -> crate::Result<Option<HistogramAggregation>> {
         match &self {
             AggregationVariants::Histogram(histogram) => Ok(Some(histogram.clone())),
@@ -208,6 +398,18 @@ impl AggregationVariants {
             _ => None,
         }
     }
+    pub(crate) fn as_significant_terms(&self) -> Option<&SignificantTermsAggregation> {
+        match &self {
+            AggregationVariants::SignificantTerms(terms) => Some(terms),
+            _ => None,
+        }
+    }
+    pub(crate) fn as_composite(&self) -> Option<&CompositeAggregation> {
+        match &self {
+            AggregationVariants::Composite(composite) => Some(composite),
+            _ => None,
+        }
+    }
     pub(crate) fn as_top_hits(&self) -> Option<&TopHitsAggregationReq> {
         match &self {
             AggregationVariants::TopHits(top_hits) => Some(top_hits),
@@ -215,6 +417,16 @@ impl AggregationVariants {
         }
     }
 
+    /// Returns the format of the date metrics requested by a min, max or stats aggregation.
+    pub(crate) fn date_metric_format(&self) -> Option<DateMetricFormat> {
+        match &self {
+            AggregationVariants::Min(min) => min.format,
+            AggregationVariants::Max(max) => max.format,
+            AggregationVariants::Stats(stats) => stats.format,
+            _ => None,
+        }
+    }
+
     pub(crate) fn as_percentile(&self) -> Option<&PercentilesAggregationReq> {
         match &self {
             AggregationVariants::Percentiles(percentile_req) => Some(percentile_req),
@@ -2,22 +2,28 @@
 
 use std::collections::HashMap;
 use std::io;
+use std::sync::Arc;
 
-use columnar::{Column, ColumnBlockAccessor, ColumnType, DynamicColumn, StrColumn};
+use columnar::{Column, ColumnBlockAccessor, ColumnType, DynamicColumn, NumericalType, StrColumn};
 
-use super::agg_req::{Aggregation, AggregationVariants, Aggregations};
+use super::agg_req::{get_fast_field_names, Aggregation, AggregationVariants, Aggregations};
 use super::bucket::{
-    DateHistogramAggregationReq, HistogramAggregation, RangeAggregation, TermsAggregation,
+    BackgroundTermCounts, DateHistogramAggregationReq, ExistsAggregation, HistogramAggregation,
+    MissingAggregation, PathOrdMappingCache, PathTermsAggregation, PrefixTermsAggregation,
+    RangeAggregation, SignificantTermsAggregation, TermsAggregation,
 };
 use super::metric::{
-    AverageAggregation, CardinalityAggregationReq, CountAggregation, ExtendedStatsAggregation,
-    MaxAggregation, MinAggregation, StatsAggregation, SumAggregation,
+    token_count_column, AverageAggregation, CardinalityAggregationReq, CountAggregation, DocScores,
+    ExtendedStatsAggregation, MaxAggregation, MinAggregation, StatsAggregation, SumAggregation,
 };
+use super::multi_collector::SharedColumnBlock;
 use super::segment_agg_result::AggregationLimitsGuard;
+use super::value_transform::{ValueTransformFn, ValueTransformRegistry};
 use super::VecWithNames;
-use crate::aggregation::{f64_to_fastfield_u64, Key};
+use crate::aggregation::{f64_to_fastfield_u64, AggregationError, AggregationErrorKind, Key};
 use crate::index::SegmentReader;
-use crate::SegmentOrdinal;
+use crate::schema::{DateTimePrecision, FieldType};
+use crate::{DocId, SearchScratch, SegmentOrdinal, TantivyError};
 
 #[derive(Default)]
 pub(crate) struct AggregationsWithAccessor {
@@ -32,6 +38,30 @@ impl AggregationsWithAccessor {
     pub fn is_empty(&self) -> bool {
         self.aggs.is_empty()
     }
+
+    /// Shares `doc_scores` with the `top_hits` aggregations of the tree sorting by `_score`.
+    pub(crate) fn set_doc_scores(&mut self, doc_scores: &DocScores) {
+        for agg in self.aggs.values.iter_mut() {
+            if agg
+                .agg
+                .agg
+                .as_top_hits()
+                .is_some_and(|top_hits| top_hits.sorts_by_score())
+            {
+                agg.doc_scores = Some(doc_scores.clone());
+            }
+            agg.sub_aggregation.set_doc_scores(doc_scores);
+        }
+    }
+
+    /// Takes the buffers of the column block accessors of the tree from the [`SearchScratch`]
+    /// pool, with room for a block of `block_size` docs.
+    pub(crate) fn take_block_buffers(&mut self, block_size: usize) {
+        for agg in self.aggs.values.iter_mut() {
+            agg.column_block_accessor = scratch_column_block_accessor(block_size);
+            agg.sub_aggregation.take_block_buffers(block_size);
+        }
+    }
 }
 
 pub struct AggregationWithAccessor {
@@ -47,6 +77,11 @@ pub struct AggregationWithAccessor {
     pub(crate) sub_aggregation: AggregationsWithAccessor,
     pub(crate) limits: AggregationLimitsGuard,
     pub(crate) column_block_accessor: ColumnBlockAccessor<u64>,
+    /// Column values shared with the aggregations of other requests reading the same column.
+    /// Only set on top-level aggregations of a [`MultiAggregationCollector`].
+    ///
+    /// [`MultiAggregationCollector`]: super::MultiAggregationCollector
+    pub(crate) shared_column_block: Option<SharedColumnBlock>,
     /// Used for missing term aggregation, which checks all columns for existence.
     /// And also for `top_hits` aggregation, which may sort on multiple fields.
     /// By convention the missing aggregation is chosen, when this property is set
@@ -58,6 +93,20 @@ pub struct AggregationWithAccessor {
     /// Map field names to all associated column accessors.
     /// This field is used for `docvalue_fields`, which is currently only supported for `top_hits`.
     pub(crate) value_accessors: HashMap<String, Vec<DynamicColumn>>,
+    /// Term counts over all alive documents of the segment.
+    /// This field is only used by the `significant_terms` aggregation.
+    pub(crate) background_term_counts: Option<Arc<BackgroundTermCounts>>,
+    /// Cache of the term ordinal to path ordinal mappings of the segment.
+    /// This field is only used by the `path_terms` aggregation.
+    pub(crate) path_ord_mapping_cache: Option<Arc<PathOrdMappingCache>>,
+    /// Text columns of the sources, to resolve their term ordinals.
+    /// This field is only used by the `composite` aggregation.
+    pub(crate) composite_str_columns: Vec<Option<StrColumn>>,
+    /// Transform applied to the values read from `accessor`.
+    pub(crate) value_transform: Option<ValueTransformFn>,
+    /// Scores of the documents of the segment.
+    /// This field is only used by the `top_hits` aggregation, when sorting by `_score`.
+    pub(crate) doc_scores: Option<DocScores>,
     pub(crate) agg: Aggregation,
 }
 
@@ -69,6 +118,8 @@ impl AggregationWithAccessor {
         reader: &SegmentReader,
         segment_ordinal: SegmentOrdinal,
         limits: AggregationLimitsGuard,
+        value_transforms: &ValueTransformRegistry,
+        missing_column_default: Option<f64>,
     ) -> crate::Result<Vec<AggregationWithAccessor>> {
         let mut agg = agg.clone();
 
@@ -88,12 +139,20 @@ impl AggregationWithAccessor {
                     reader,
                     segment_ordinal,
                     &limits,
+                    value_transforms,
+                    missing_column_default,
                 )?,
                 agg: agg.clone(),
                 limits: limits.clone(),
                 missing_value_for_accessor: None,
                 str_dict_column: None,
                 column_block_accessor: Default::default(),
+                shared_column_block: None,
+                background_term_counts: None,
+                path_ord_mapping_cache: None,
+                composite_str_columns: Vec::new(),
+                value_transform: None,
+                doc_scores: None,
             };
             aggs.push(res);
             Ok(())
@@ -104,31 +163,70 @@ impl AggregationWithAccessor {
                                       aggs: &mut Vec<AggregationWithAccessor>,
                                       value_accessors: HashMap<String, Vec<DynamicColumn>>|
          -> crate::Result<()> {
-            let (accessor, field_type) = accessors.first().expect("at least one accessor");
+            // `top_hits` sorting by `_score` only reads no column.
+            let (accessor, field_type) = accessors.first().cloned().unwrap_or_else(|| {
+                (
+                    Column::build_empty_column(reader.max_doc()),
+                    ColumnType::U64,
+                )
+            });
             let limits = limits.clone();
             let res = AggregationWithAccessor {
                 segment_ordinal,
                 // TODO: We should do away with the `accessor` field altogether
-                accessor: accessor.clone(),
+                accessor,
                 value_accessors,
-                field_type: *field_type,
+                field_type,
                 accessors,
                 sub_aggregation: get_aggs_with_segment_accessor_and_validate(
                     sub_aggregation,
                     reader,
                     segment_ordinal,
                     &limits,
+                    value_transforms,
+                    missing_column_default,
                 )?,
                 agg: agg.clone(),
                 limits,
                 missing_value_for_accessor: None,
                 str_dict_column: None,
                 column_block_accessor: Default::default(),
+                shared_column_block: None,
+                background_term_counts: None,
+                path_ord_mapping_cache: None,
+                composite_str_columns: Vec::new(),
+                value_transform: None,
+                doc_scores: None,
             };
             aggs.push(res);
             Ok(())
         };
 
+        let field_union = agg.field_union.clone();
+        if let Some(field_union) = field_union.as_ref() {
+            validate_field_union(&agg, field_union)?;
+        }
+        // Reads the column of the field, or of the union of fields of the aggregation.
+        let get_field_ff_reader =
+            |field_name: &str, allowed_column_types: Option<&[ColumnType]>| match field_union
+                .as_ref()
+            {
+                Some(field_union) => get_union_ff_reader(
+                    reader,
+                    field_union,
+                    allowed_column_types,
+                    ColumnType::U64,
+                    missing_column_default,
+                )
+                .map(|(accessor, column_type, _)| (accessor, column_type)),
+                None => get_ff_reader(
+                    reader,
+                    field_name,
+                    allowed_column_types,
+                    missing_column_default,
+                ),
+            };
+
         let mut res: Vec<AggregationWithAccessor> = Vec::new();
         use AggregationVariants::*;
 
@@ -138,7 +236,7 @@ impl AggregationWithAccessor {
                 ..
             }) => {
                 let (accessor, column_type) =
-                    get_ff_reader(reader, field_name, Some(get_numeric_or_date_column_types()))?;
+                    get_field_ff_reader(field_name, Some(get_numeric_or_date_column_types()))?;
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
             }
             Histogram(HistogramAggregation {
@@ -146,7 +244,7 @@ impl AggregationWithAccessor {
                 ..
             }) => {
                 let (accessor, column_type) =
-                    get_ff_reader(reader, field_name, Some(get_numeric_or_date_column_types()))?;
+                    get_field_ff_reader(field_name, Some(get_numeric_or_date_column_types()))?;
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
             }
             DateHistogram(DateHistogramAggregationReq {
@@ -155,8 +253,50 @@ impl AggregationWithAccessor {
             }) => {
                 let (accessor, column_type) =
                     // Only DateTime is supported for DateHistogram
-                    get_ff_reader(reader, field_name, Some(&[ColumnType::DateTime]))?;
+                    get_field_ff_reader(field_name, Some(&[ColumnType::DateTime]))?;
+                add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
+            }
+            DateRange(ref mut date_range) => {
+                date_range.precision = Some(date_field_precision(reader, &date_range.field));
+                let (accessor, column_type) =
+                    get_field_ff_reader(&date_range.field, Some(&[ColumnType::DateTime]))?;
+                add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
+            }
+            Terms(TermsAggregation {
+                field: ref field_name,
+                ref missing,
+                ..
+            })
+            | Cardinality(CardinalityAggregationReq {
+                field: ref field_name,
+                ref missing,
+                ..
+            }) if field_union.is_some() => {
+                let field_union = field_union.as_ref().unwrap();
+                let (accessor, column_type, single_field) = get_union_ff_reader(
+                    reader,
+                    field_union,
+                    Some(&TERMS_COLUMN_TYPES),
+                    missing_fallback_type(missing),
+                    missing_column_default,
+                )?;
+                // The term ordinals can only be resolved if a single field has a column.
+                let str_dict_column = match single_field {
+                    Some(single_field) if column_type == ColumnType::Str => {
+                        reader.try_fast_fields()?.str(single_field)?
+                    }
+                    _ => None,
+                };
+                let missing_value_for_accessor = match missing.as_ref() {
+                    Some(missing) => {
+                        get_missing_val_as_u64_lenient(column_type, missing, field_name)?
+                    }
+                    None => None,
+                };
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
+                let agg_with_accessor = res.last_mut().expect("an aggregation was just added");
+                agg_with_accessor.str_dict_column = str_dict_column;
+                agg_with_accessor.missing_value_for_accessor = missing_value_for_accessor;
             }
             Terms(TermsAggregation {
                 field: ref field_name,
@@ -168,33 +308,17 @@ impl AggregationWithAccessor {
                 ref missing,
                 ..
             }) => {
-                let str_dict_column = reader.fast_fields().str(field_name)?;
-                let allowed_column_types = [
-                    ColumnType::I64,
-                    ColumnType::U64,
-                    ColumnType::F64,
-                    ColumnType::Str,
-                    ColumnType::DateTime,
-                    ColumnType::Bool,
-                    ColumnType::IpAddr,
-                    // ColumnType::Bytes Unsupported
-                ];
+                let str_dict_column = reader.try_fast_fields()?.str(field_name)?;
+                let allowed_column_types = TERMS_COLUMN_TYPES;
 
                 // In case the column is empty we want the shim column to match the missing type
-                let fallback_type = missing
-                    .as_ref()
-                    .map(|missing| match missing {
-                        Key::Str(_) => ColumnType::Str,
-                        Key::F64(_) => ColumnType::F64,
-                        Key::I64(_) => ColumnType::I64,
-                        Key::U64(_) => ColumnType::U64,
-                    })
-                    .unwrap_or(ColumnType::U64);
+                let fallback_type = missing_fallback_type(missing);
                 let column_and_types = get_all_ff_reader_or_empty(
                     reader,
                     field_name,
                     Some(&allowed_column_types),
                     fallback_type,
+                    missing_column_default,
                 )?;
                 let missing_and_more_than_one_col = column_and_types.len() > 1 && missing.is_some();
                 let text_on_non_text_col = column_and_types.len() == 1
@@ -217,8 +341,13 @@ impl AggregationWithAccessor {
                 let use_special_missing_agg =
                     missing_and_more_than_one_col || text_on_non_text_col || text_on_date_col;
                 if use_special_missing_agg {
-                    let column_and_types =
-                        get_all_ff_reader_or_empty(reader, field_name, None, fallback_type)?;
+                    let column_and_types = get_all_ff_reader_or_empty(
+                        reader,
+                        field_name,
+                        None,
+                        fallback_type,
+                        missing_column_default,
+                    )?;
 
                     let accessors = column_and_types
                         .iter()
@@ -258,15 +387,140 @@ impl AggregationWithAccessor {
                             reader,
                             segment_ordinal,
                             &limits,
+                            value_transforms,
+                            missing_column_default,
                         )?,
                         agg: agg.clone(),
                         str_dict_column: str_dict_column.clone(),
                         limits,
                         column_block_accessor: Default::default(),
+                        shared_column_block: None,
+                        background_term_counts: None,
+                        path_ord_mapping_cache: None,
+                        composite_str_columns: Vec::new(),
+                        value_transform: None,
+                        doc_scores: None,
                     };
                     res.push(agg);
                 }
             }
+            SignificantTerms(SignificantTermsAggregation {
+                field: ref field_name,
+                ..
+            }) => {
+                let str_dict_column = reader.try_fast_fields()?.str(field_name)?;
+                let (accessor, column_type) = if let Some(str_column) = str_dict_column.as_ref() {
+                    (str_column.ords().clone(), ColumnType::Str)
+                } else {
+                    // Non text columns are rejected when building the collector.
+                    reader
+                        .try_fast_fields()?
+                        .u64_lenient_for_type(None, field_name)?
+                        .unwrap_or_else(|| {
+                            (
+                                Column::build_empty_column(reader.num_docs()),
+                                ColumnType::Str,
+                            )
+                        })
+                };
+                let background_term_counts = BackgroundTermCounts::for_field(reader, field_name)?;
+                let limits = limits.clone();
+                res.push(AggregationWithAccessor {
+                    segment_ordinal,
+                    missing_value_for_accessor: None,
+                    accessor,
+                    accessors: Default::default(),
+                    value_accessors: Default::default(),
+                    field_type: column_type,
+                    sub_aggregation: get_aggs_with_segment_accessor_and_validate(
+                        sub_aggregation,
+                        reader,
+                        segment_ordinal,
+                        &limits,
+                        value_transforms,
+                        missing_column_default,
+                    )?,
+                    agg: agg.clone(),
+                    str_dict_column,
+                    limits,
+                    column_block_accessor: Default::default(),
+                    shared_column_block: None,
+                    background_term_counts: Some(background_term_counts),
+                    path_ord_mapping_cache: None,
+                    composite_str_columns: Vec::new(),
+                    value_transform: None,
+                    doc_scores: None,
+                });
+            }
+            PathTerms(PathTermsAggregation {
+                field: ref field_name,
+                ..
+            })
+            | PrefixTerms(PrefixTermsAggregation {
+                field: ref field_name,
+                ..
+            }) => {
+                let str_dict_column = reader.try_fast_fields()?.str(field_name)?;
+                let (accessor, column_type) = if let Some(str_column) = str_dict_column.as_ref() {
+                    (str_column.ords().clone(), ColumnType::Str)
+                } else {
+                    // Non text columns are rejected when building the collector.
+                    reader
+                        .try_fast_fields()?
+                        .u64_lenient_for_type(None, field_name)?
+                        .unwrap_or_else(|| {
+                            (
+                                Column::build_empty_column(reader.num_docs()),
+                                ColumnType::Str,
+                            )
+                        })
+                };
+                let limits = limits.clone();
+                res.push(AggregationWithAccessor {
+                    segment_ordinal,
+                    missing_value_for_accessor: None,
+                    accessor,
+                    accessors: Default::default(),
+                    value_accessors: Default::default(),
+                    field_type: column_type,
+                    sub_aggregation: get_aggs_with_segment_accessor_and_validate(
+                        sub_aggregation,
+                        reader,
+                        segment_ordinal,
+                        &limits,
+                        value_transforms,
+                        missing_column_default,
+                    )?,
+                    agg: agg.clone(),
+                    str_dict_column,
+                    limits,
+                    column_block_accessor: Default::default(),
+                    shared_column_block: None,
+                    background_term_counts: None,
+                    path_ord_mapping_cache: matches!(agg.agg, PathTerms(_))
+                        .then(|| reader.extension::<PathOrdMappingCache>()),
+                    composite_str_columns: Vec::new(),
+                    value_transform: None,
+                    doc_scores: None,
+                });
+            }
+            Composite(ref composite) => {
+                let mut accessors = Vec::new();
+                let mut str_columns = Vec::new();
+                for (_, source) in composite.named_sources()? {
+                    let field_name = source.field();
+                    accessors.push(get_ff_reader(
+                        reader,
+                        field_name,
+                        Some(source.column_types()),
+                        missing_column_default,
+                    )?);
+                    str_columns.push(reader.try_fast_fields()?.str(field_name)?);
+                }
+                add_agg_with_accessors(&agg, accessors, &mut res, Default::default())?;
+                let agg_with_accessor = res.last_mut().expect("an aggregation was just added");
+                agg_with_accessor.composite_str_columns = str_columns;
+            }
             Average(AverageAggregation {
                 field: ref field_name,
                 ..
@@ -291,43 +545,75 @@ impl AggregationWithAccessor {
                 field: ref field_name,
                 ..
             }) => {
-                let (accessor, column_type) =
-                    get_ff_reader(reader, field_name, Some(get_numeric_or_date_column_types()))?;
+                let token_count_field = agg.agg.token_count_field();
+                if field_name.is_empty() == token_count_field.is_none() {
+                    return Err(AggregationError::InvalidRequest(
+                        "exactly one of field and token_count_field must be set".to_string(),
+                    )
+                    .into());
+                }
+                let (accessor, column_type) = match token_count_field {
+                    Some(token_count_field) => (
+                        token_count_column(reader, token_count_field)?,
+                        ColumnType::U64,
+                    ),
+                    None => {
+                        match field_union.as_ref() {
+                            Some(field_union) => {
+                                for union_field in field_union {
+                                    validate_metric_field_type(reader, union_field)?;
+                                }
+                            }
+                            None => validate_metric_field_type(reader, field_name)?,
+                        }
+                        get_field_ff_reader(field_name, Some(get_metric_column_types()))?
+                    }
+                };
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
             }
+            Exists(ExistsAggregation {
+                field: ref field_name,
+            })
+            | Missing(MissingAggregation {
+                field: ref field_name,
+            }) => {
+                // The presence of values is checked on the columns of all types.
+                let accessors = get_all_ff_reader_or_empty(
+                    reader,
+                    field_name,
+                    None,
+                    ColumnType::U64,
+                    missing_column_default,
+                )?;
+                add_agg_with_accessors(&agg, accessors, &mut res, Default::default())?;
+            }
             Count(CountAggregation {
                 field: ref field_name,
                 ..
             }) => {
-                let allowed_column_types = [
-                    ColumnType::I64,
-                    ColumnType::U64,
-                    ColumnType::F64,
-                    ColumnType::Str,
-                    ColumnType::DateTime,
-                    ColumnType::Bool,
-                    ColumnType::IpAddr,
-                    // ColumnType::Bytes Unsupported
-                ];
                 let (accessor, column_type) =
-                    get_ff_reader(reader, field_name, Some(&allowed_column_types))?;
+                    get_field_ff_reader(field_name, Some(&COUNT_COLUMN_TYPES))?;
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
             }
             Percentiles(ref percentiles) => {
-                let (accessor, column_type) = get_ff_reader(
-                    reader,
+                let (accessor, column_type) = get_field_ff_reader(
                     percentiles.field_name(),
                     Some(get_numeric_or_date_column_types()),
                 )?;
                 add_agg_with_accessor(&agg, accessor, column_type, &mut res)?;
             }
             TopHits(ref mut top_hits) => {
-                top_hits.validate_and_resolve_field_names(reader.fast_fields().columnar())?;
+                top_hits.validate_and_resolve_field_names(reader.try_fast_fields()?.columnar())?;
                 let accessors: Vec<(Column<u64>, ColumnType)> = top_hits
                     .field_names()
                     .iter()
                     .map(|field| {
-                        get_ff_reader(reader, field, Some(get_numeric_or_date_column_types()))
+                        get_ff_reader(
+                            reader,
+                            field,
+                            Some(get_numeric_or_date_column_types()),
+                            missing_column_default,
+                        )
                     })
                     .collect::<crate::Result<_>>()?;
 
@@ -346,8 +632,81 @@ impl AggregationWithAccessor {
             }
         };
 
+        if let Some(value_transform) = agg.agg.value_transform() {
+            let value_transform = value_transform.resolve(value_transforms)?;
+            for agg_with_accessor in res.iter_mut() {
+                agg_with_accessor.value_transform = Some(value_transform.clone());
+            }
+        }
+
         Ok(res)
     }
+
+    /// Loads the values of `docs` into `column_block_accessor`.
+    #[inline]
+    pub(crate) fn fetch_block(&mut self, docs: &[DocId]) {
+        self.fetch_block_with_optional_missing(docs, None);
+    }
+
+    /// Loads the values of `docs` into `column_block_accessor`, using `missing` for the docs
+    /// without a value.
+    #[inline]
+    pub(crate) fn fetch_block_with_missing(&mut self, docs: &[DocId], missing: u64) {
+        self.fetch_block_with_optional_missing(docs, Some(missing));
+    }
+
+    #[inline]
+    fn fetch_block_with_optional_missing(&mut self, docs: &[DocId], missing: Option<u64>) {
+        if let Some(shared_column_block) = self.shared_column_block.as_ref() {
+            shared_column_block.fetch_block(
+                docs,
+                missing,
+                &self.accessor,
+                &mut self.column_block_accessor,
+            );
+            return;
+        }
+        match missing {
+            Some(missing) => {
+                self.column_block_accessor
+                    .fetch_block_with_missing(docs, &self.accessor, missing)
+            }
+            None => self.column_block_accessor.fetch_block(docs, &self.accessor),
+        }
+    }
+
+    /// Applies the `value_transform` of the aggregation, if any.
+    #[inline]
+    pub(crate) fn transform_value(&self, val: f64) -> f64 {
+        match self.value_transform.as_ref() {
+            Some(value_transform) => value_transform(val),
+            None => val,
+        }
+    }
+}
+
+impl Drop for AggregationWithAccessor {
+    fn drop(&mut self) {
+        let (vals, docs, missing_docs, row_ids) =
+            std::mem::take(&mut self.column_block_accessor).into_buffers();
+        SearchScratch::recycle(vals);
+        SearchScratch::recycle(docs);
+        SearchScratch::recycle(missing_docs);
+        SearchScratch::recycle(row_ids);
+    }
+}
+
+/// Creates a column block accessor reusing buffers of the [`SearchScratch`] pool.
+///
+/// The buffers are returned to the pool when the [`AggregationWithAccessor`] is dropped. They
+/// have room for a block of `block_size` docs, so that they are not grown while collecting.
+fn scratch_column_block_accessor(block_size: usize) -> ColumnBlockAccessor<u64> {
+    ColumnBlockAccessor::from_buffers(
+        SearchScratch::take_vec(block_size),
+        SearchScratch::take_vec(block_size),
+        SearchScratch::take_vec(block_size),
+        SearchScratch::take_vec(block_size),
+    )
 }
 
 /// Get the missing value as internal u64 representation
@@ -379,15 +738,81 @@ fn get_missing_val_as_u64_lenient(
             f64_to_fastfield_u64(*val as f64, &column_type)
         }
         _ => {
-            return Err(crate::TantivyError::InvalidArgument(format!(
-                "Missing value {missing:?} for field {field_name} is not supported for column \
-                 type {column_type:?}"
-            )));
+            return Err(AggregationError::invalid_parameter(
+                "missing",
+                format!(
+                    "Missing value {missing:?} for field {field_name} is not supported for column \
+                     type {column_type:?}"
+                ),
+            )
+            .into());
         }
     };
     Ok(missing_val)
 }
 
+/// Returns the precision of the date values of the fast field `field_name`.
+///
+/// The dates of JSON fields are stored with their full precision.
+fn date_field_precision(reader: &SegmentReader, field_name: &str) -> DateTimePrecision {
+    let schema = reader.schema();
+    match schema.find_field(field_name) {
+        Some((field, _)) => match schema.get_field_entry(field).field_type() {
+            FieldType::Date(date_options) => date_options.get_precision(),
+            _ => DateTimePrecision::Nanoseconds,
+        },
+        None => DateTimePrecision::Nanoseconds,
+    }
+}
+
+/// Column types supported by the `terms` and `cardinality` aggregations.
+const TERMS_COLUMN_TYPES: [ColumnType; 7] = [
+    ColumnType::I64,
+    ColumnType::U64,
+    ColumnType::F64,
+    ColumnType::Str,
+    ColumnType::DateTime,
+    ColumnType::Bool,
+    ColumnType::IpAddr,
+    // ColumnType::Bytes Unsupported
+];
+
+/// Column types supported by the `count` aggregation.
+const COUNT_COLUMN_TYPES: [ColumnType; 7] = TERMS_COLUMN_TYPES;
+
+/// Returns the column types read by the aggregations reading a single column of their field,
+/// the first column of the field of one of these types.
+pub(crate) fn single_column_types(agg: &AggregationVariants) -> Option<&'static [ColumnType]> {
+    use AggregationVariants::*;
+    if agg.token_count_field().is_some() {
+        return None;
+    }
+    match agg {
+        Range(_) | Histogram(_) | Percentiles(_) => Some(get_numeric_or_date_column_types()),
+        DateHistogram(_) | DateRange(_) => Some(&[ColumnType::DateTime]),
+        Average(_) | Max(_) | Min(_) | Stats(_) | ExtendedStats(_) | Sum(_) => {
+            Some(get_metric_column_types())
+        }
+        Count(_) => Some(&COUNT_COLUMN_TYPES),
+        Terms(_) | SignificantTerms(_) | PathTerms(_) | PrefixTerms(_) | Composite(_)
+        | Exists(_) | Missing(_) | TopHits(_) | Cardinality(_) => None,
+    }
+}
+
+/// Type of the shim column used when the field has no column, matching the type of the missing
+/// value.
+fn missing_fallback_type(missing: &Option<Key>) -> ColumnType {
+    missing
+        .as_ref()
+        .map(|missing| match missing {
+            Key::Str(_) => ColumnType::Str,
+            Key::F64(_) => ColumnType::F64,
+            Key::I64(_) => ColumnType::I64,
+            Key::U64(_) => ColumnType::U64,
+        })
+        .unwrap_or(ColumnType::U64)
+}
+
 fn get_numeric_or_date_column_types() -> &'static [ColumnType] {
     &[
         ColumnType::F64,
@@ -397,11 +822,108 @@ fn get_numeric_or_date_column_types() -> &'static [ColumnType] {
     ]
 }
 
+/// Numeric metrics coerce bools to 0 and 1.
+fn get_metric_column_types() -> &'static [ColumnType] {
+    &[
+        ColumnType::F64,
+        ColumnType::U64,
+        ColumnType::I64,
+        ColumnType::DateTime,
+        ColumnType::Bool,
+    ]
+}
+
+/// Rejects the text and bytes fields, which numeric metrics cannot aggregate.
+///
+/// The check is done on the schema rather than on the columns, so that it does not depend on
+/// the segment. The path of the error is filled as it bubbles up the aggregation tree.
+fn validate_metric_field_type(reader: &SegmentReader, field_name: &str) -> crate::Result<()> {
+    let schema = reader.schema();
+    let Some((field, _)) = schema.find_field(field_name) else {
+        return Ok(());
+    };
+    let field_entry = schema.get_field_entry(field);
+    let field_type = field_entry.field_type();
+    // Fields that are not fast are reported when opening their column.
+    if field_entry.is_fast() && matches!(field_type, FieldType::Str(_) | FieldType::Bytes(_)) {
+        return Err(AggregationError::UnsupportedFieldType {
+            path: String::new(),
+            field: field_name.to_string(),
+            field_type: format!("{:?}", field_type.value_type()),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Prepends `key` to the path of an [`AggregationError::UnsupportedFieldType`] or
+/// [`AggregationError::InvalidAggregation`] error.
+pub(crate) fn prefix_agg_path(err: TantivyError, key: &str) -> TantivyError {
+    let prefix = |path: String| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{key}.{path}")
+        }
+    };
+    match err {
+        TantivyError::AggregationError(AggregationError::UnsupportedFieldType {
+            path,
+            field,
+            field_type,
+        }) => AggregationError::UnsupportedFieldType {
+            path: prefix(path),
+            field,
+            field_type,
+        }
+        .into(),
+        TantivyError::AggregationError(AggregationError::InvalidAggregation { path, kind }) => {
+            AggregationError::InvalidAggregation {
+                path: prefix(path),
+                kind,
+            }
+            .into()
+        }
+        err => err,
+    }
+}
+
+/// Checks that the fields read by the aggregations are fast fields, if they are in the schema.
+///
+/// Fields which are not in the schema are read as empty columns.
+pub(crate) fn validate_fast_fields(
+    aggs: &Aggregations,
+    reader: &SegmentReader,
+) -> crate::Result<()> {
+    let schema = reader.schema();
+    for (key, agg) in aggs.iter() {
+        for field_name in agg.agg.get_fast_field_names() {
+            let Some((field, _path)) = schema.find_field(field_name) else {
+                continue;
+            };
+            if !schema.get_field_entry(field).is_fast() {
+                return Err(AggregationError::InvalidAggregation {
+                    path: key.to_string(),
+                    kind: AggregationErrorKind::FieldNotFound {
+                        field: field_name.to_string(),
+                    },
+                }
+                .into());
+            }
+        }
+        validate_fast_fields(agg.sub_aggregation(), reader)
+            .map_err(|err| prefix_agg_path(err, key))?;
+    }
+    Ok(())
+}
+
 pub(crate) fn get_aggs_with_segment_accessor_and_validate(
     aggs: &Aggregations,
     reader: &SegmentReader,
     segment_ordinal: SegmentOrdinal,
     limits: &AggregationLimitsGuard,
+    value_transforms: &ValueTransformRegistry,
+    missing_column_default: Option<f64>,
 ) -> crate::Result<AggregationsWithAccessor> {
     let mut aggss = Vec::new();
     for (key, agg) in aggs.iter() {
@@ -411,7 +933,10 @@ pub(crate) fn get_aggs_with_segment_accessor_and_validate(
             reader,
             segment_ordinal,
             limits.clone(),
-        )?;
+            value_transforms,
+            missing_column_default,
+        )
+        .map_err(|err| prefix_agg_path(err, key))?;
         for agg in aggs {
             aggss.push((key.to_string(), agg));
         }
@@ -421,29 +946,223 @@ pub(crate) fn get_aggs_with_segment_accessor_and_validate(
     ))
 }
 
+/// Returns the sorted names of the fast fields read by the aggregations, which have no column in
+/// the segment.
+pub(crate) fn missing_fast_fields(
+    aggs: &Aggregations,
+    reader: &SegmentReader,
+) -> crate::Result<Vec<String>> {
+    let mut missing_fields = Vec::new();
+    for field_name in get_fast_field_names(aggs) {
+        // The fields added to the schema after the segment was written are not missing, but
+        // have no value in the segment.
+        if reader
+            .try_fast_fields()?
+            .dynamic_column_handles(&field_name)?
+            .is_empty()
+            && !reader
+                .try_fast_fields()?
+                .is_added_after_segment(&field_name)?
+        {
+            missing_fields.push(field_name);
+        }
+    }
+    missing_fields.sort();
+    Ok(missing_fields)
+}
+
+/// Checks that the `field_union` of an aggregation includes its field, and that the aggregation
+/// reads a single field.
+fn validate_field_union(agg: &Aggregation, field_union: &[String]) -> crate::Result<()> {
+    use AggregationVariants::*;
+    let supported = match &agg.agg {
+        Range(_) | DateRange(_) | Histogram(_) | DateHistogram(_) | Terms(_) | Cardinality(_)
+        | Count(_) | Percentiles(_) => true,
+        Average(_) | Max(_) | Min(_) | Stats(_) | ExtendedStats(_) | Sum(_) => {
+            agg.agg.token_count_field().is_none()
+        }
+        SignificantTerms(_) | PathTerms(_) | PrefixTerms(_) | Composite(_) | Exists(_)
+        | Missing(_) | TopHits(_) => false,
+    };
+    if !supported {
+        return Err(AggregationError::InvalidRequest(
+            "field_union is only supported by aggregations reading a single field".to_string(),
+        )
+        .into());
+    }
+    let field_name = agg.agg.get_fast_field_names()[0];
+    if !field_union
+        .iter()
+        .any(|union_field| union_field == field_name)
+    {
+        return Err(AggregationError::InvalidRequest(format!(
+            "field_union {field_union:?} does not include the field {field_name:?} of the \
+             aggregation"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Reads the fields of a `field_union` as a single column.
+///
+/// The values of a document are those of the first field with values for it. If only one of
+/// the fields has a column in the segment, this column is returned as is, along with the name
+/// of the field. Otherwise, the columns are combined in memory.
+fn get_union_ff_reader<'a>(
+    reader: &SegmentReader,
+    field_union: &'a [String],
+    allowed_column_types: Option<&[ColumnType]>,
+    fallback_type: ColumnType,
+    missing_column_default: Option<f64>,
+) -> crate::Result<(Column<u64>, ColumnType, Option<&'a str>)> {
+    let ff_fields = reader.try_fast_fields()?;
+    let mut columns: Vec<(Column<u64>, ColumnType, &str)> = Vec::new();
+    for field_name in field_union {
+        if let Some((column, column_type)) =
+            ff_fields.u64_lenient_for_type(allowed_column_types, field_name)?
+        {
+            columns.push((column, column_type, field_name));
+        }
+    }
+    if columns.len() <= 1 {
+        return Ok(match columns.pop() {
+            Some((column, column_type, field_name)) => (column, column_type, Some(field_name)),
+            None => {
+                let (column, column_type) = missing_column(
+                    reader,
+                    allowed_column_types,
+                    fallback_type,
+                    missing_column_default,
+                );
+                (column, column_type, None)
+            }
+        });
+    }
+
+    let mut column_type = columns[0].1;
+    if columns
+        .iter()
+        .any(|(_, column_type, _)| matches!(column_type, ColumnType::Str | ColumnType::Bytes))
+    {
+        return Err(AggregationError::InvalidRequest(format!(
+            "field_union {field_union:?} combines text fields, whose terms cannot be combined"
+        ))
+        .into());
+    }
+    if columns
+        .iter()
+        .any(|(_, other_type, _)| *other_type != column_type)
+    {
+        if !columns
+            .iter()
+            .all(|(_, column_type, _)| column_type.numerical_type().is_some())
+        {
+            let column_types: Vec<ColumnType> = columns
+                .iter()
+                .map(|(_, column_type, _)| *column_type)
+                .collect();
+            return Err(AggregationError::InvalidRequest(format!(
+                "field_union {field_union:?} combines fields of incompatible types \
+                 {column_types:?}"
+            ))
+            .into());
+        }
+        // Numbers of different types are read as f64.
+        for (column, numerical_column_type, field_name) in columns.iter_mut() {
+            *column = open_as_f64(reader, field_name, *numerical_column_type)?;
+        }
+        column_type = ColumnType::F64;
+    }
+    let columns: Vec<Column<u64>> = columns.into_iter().map(|(column, _, _)| column).collect();
+    Ok((
+        Column::coalesce(&columns, reader.max_doc()),
+        column_type,
+        None,
+    ))
+}
+
+/// Opens the numerical column of type `column_type` of the field, with its values converted to
+/// f64.
+fn open_as_f64(
+    reader: &SegmentReader,
+    field_name: &str,
+    column_type: ColumnType,
+) -> crate::Result<Column<u64>> {
+    let handle = reader
+        .try_fast_fields()?
+        .dynamic_column_handles(field_name)?
+        .into_iter()
+        .find(|handle| handle.column_type() == column_type)
+        .ok_or_else(|| {
+            crate::TantivyError::InternalError(format!(
+                "column of type {column_type:?} of field {field_name} not found"
+            ))
+        })?;
+    match handle.open()?.coerce_numerical(NumericalType::F64) {
+        Some(DynamicColumn::F64(column)) => Ok(column.to_u64_monotonic()),
+        _ => Err(crate::TantivyError::InternalError(format!(
+            "column of type {column_type:?} of field {field_name} cannot be read as f64"
+        ))),
+    }
+}
+
 /// Get fast field reader or empty as default.
 fn get_ff_reader(
     reader: &SegmentReader,
     field_name: &str,
     allowed_column_types: Option<&[ColumnType]>,
+    missing_column_default: Option<f64>,
 ) -> crate::Result<(columnar::Column<u64>, ColumnType)> {
-    let ff_fields = reader.fast_fields();
+    let ff_fields = reader.try_fast_fields()?;
     let ff_field_with_type = ff_fields
         .u64_lenient_for_type(allowed_column_types, field_name)?
         .unwrap_or_else(|| {
-            (
-                Column::build_empty_column(reader.num_docs()),
+            missing_column(
+                reader,
+                allowed_column_types,
                 ColumnType::U64,
+                missing_column_default,
             )
         });
     Ok(ff_field_with_type)
 }
 
+/// Returns the column read for a field without column in the segment.
+///
+/// The column is empty, unless the aggregation collector has a
+/// [`MissingColumnPolicy::Default`](crate::collector::MissingColumnPolicy::Default) policy, in
+/// which case all of the docs have the default value. The value is stored as `f64` if the
+/// aggregation allows it, or in the first allowed numerical type otherwise.
+fn missing_column(
+    reader: &SegmentReader,
+    allowed_column_types: Option<&[ColumnType]>,
+    fallback_type: ColumnType,
+    missing_column_default: Option<f64>,
+) -> (Column<u64>, ColumnType) {
+    let default_column = missing_column_default.and_then(|default_value| {
+        let column_type = match allowed_column_types {
+            Some(column_types) if !column_types.contains(&ColumnType::F64) => {
+                *column_types.first()?
+            }
+            _ => ColumnType::F64,
+        };
+        let default_value = f64_to_fastfield_u64(default_value, &column_type)?;
+        Some((
+            Column::build_constant_column(reader.max_doc(), default_value),
+            column_type,
+        ))
+    });
+    default_column.unwrap_or_else(|| (Column::build_empty_column(reader.num_docs()), fallback_type))
+}
+
 fn get_dynamic_columns(
     reader: &SegmentReader,
     field_name: &str,
 ) -> crate::Result<Vec<columnar::DynamicColumn>> {
-    let ff_fields = reader.fast_fields().dynamic_column_handles(field_name)?;
+    let ff_fields = reader
+        .try_fast_fields()?
+        .dynamic_column_handles(field_name)?;
     let cols = ff_fields
         .iter()
         .map(|h| h.open())
@@ -460,12 +1179,18 @@ fn get_all_ff_reader_or_empty(
     field_name: &str,
     allowed_column_types: Option<&[ColumnType]>,
     fallback_type: ColumnType,
+    missing_column_default: Option<f64>,
 ) -> crate::Result<Vec<(columnar::Column<u64>, ColumnType)>> {
-    let ff_fields = reader.fast_fields();
+    let ff_fields = reader.try_fast_fields()?;
     let mut ff_field_with_type =
         ff_fields.u64_lenient_for_type_all(allowed_column_types, field_name)?;
     if ff_field_with_type.is_empty() {
-        ff_field_with_type.push((Column::build_empty_column(reader.num_docs()), fallback_type));
+        ff_field_with_type.push(missing_column(
+            reader,
+            allowed_column_types,
+            fallback_type,
+            missing_column_default,
+        ));
     }
     Ok(ff_field_with_type)
 }
@@ -12,14 +12,76 @@ use super::bucket::GetDocCount;
 use super::metric::{
     ExtendedStats, PercentilesMetricResult, SingleMetricResult, Stats, TopHitsMetricResult,
 };
+use super::sampling::SampledDocs;
 use super::{AggregationError, Key};
 use crate::TantivyError;
 
+/// Key of the warnings in the [`AggregationResults`], see [`AggregationResults::warnings()`].
+pub const WARNINGS_KEY: &str = "warnings";
+
+/// Key of the sampling report in the [`AggregationResults`], see
+/// [`AggregationResults::sampled()`].
+pub const SAMPLED_KEY: &str = "sampled";
+
 #[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 /// The final aggegation result.
 pub struct AggregationResults(pub FxHashMap<String, AggregationResult>);
 
 impl AggregationResults {
+    /// Returns the warnings of the aggregation, e.g. the values of a JSON path that were ignored
+    /// because their type differs from the type of the other values of the path.
+    ///
+    /// The warnings are returned next to the results of the aggregations, as an array under the
+    /// [`WARNINGS_KEY`] key, which is only present if there are warnings. It replaces the result of
+    /// an aggregation of the same name. See [`Aggregation::strict_types`] to fail the aggregation
+    /// instead.
+    ///
+    /// [`Aggregation::strict_types`]: super::agg_req::Aggregation::strict_types
+    pub fn warnings(&self) -> Vec<&str> {
+        match self.0.get(WARNINGS_KEY) {
+            Some(AggregationResult::CustomResult(serde_json::Value::Array(warnings))) => warnings
+                .iter()
+                .filter_map(|warning| warning.as_str())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_warnings(&mut self, mut warnings: Vec<String>) {
+        if warnings.is_empty() {
+            return;
+        }
+        warnings.sort();
+        warnings.dedup();
+        self.0.insert(
+            WARNINGS_KEY.to_string(),
+            AggregationResult::CustomResult(warnings.into()),
+        );
+    }
+
+    /// Returns the report of the sampling of the documents, if the request sampled them, see
+    /// [`AggregationSampling`](super::AggregationSampling).
+    ///
+    /// The report is returned next to the results of the aggregations, under the
+    /// [`SAMPLED_KEY`] key. Like the warnings, it replaces the result of an aggregation of the
+    /// same name.
+    pub fn sampled(&self) -> Option<SampledDocs> {
+        match self.0.get(SAMPLED_KEY) {
+            Some(AggregationResult::CustomResult(sampled)) => {
+                serde_json::from_value(sampled.clone()).ok()
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_sampled(&mut self, sampled: SampledDocs) {
+        let sampled = serde_json::to_value(sampled).expect("the report serializes to JSON");
+        self.0.insert(
+            SAMPLED_KEY.to_string(),
+            AggregationResult::CustomResult(sampled),
+        );
+    }
+
     pub(crate) fn get_bucket_count(&self) -> u64 {
         self.0
             .values()
@@ -51,13 +113,16 @@ pub enum AggregationResult {
     BucketResult(BucketResult),
     /// Metric result variant.
     MetricResult(MetricResult),
+    /// Final result of a custom intermediate aggregation result, see
+    /// [`custom_intermediate_result`](super::custom_intermediate_result).
+    CustomResult(serde_json::Value),
 }
 
 impl AggregationResult {
     pub(crate) fn get_bucket_count(&self) -> u64 {
         match self {
             AggregationResult::BucketResult(bucket) => bucket.get_bucket_count(),
-            AggregationResult::MetricResult(_) => 0,
+            AggregationResult::MetricResult(_) | AggregationResult::CustomResult(_) => 0,
         }
     }
 
@@ -73,6 +138,11 @@ impl AggregationResult {
                     .to_string(),
             )),
             AggregationResult::MetricResult(metric) => metric.get_value(agg_property),
+            AggregationResult::CustomResult(_) => Err(TantivyError::AggregationError(
+                AggregationError::InvalidRequest(
+                    "custom aggregations can't be used to order".to_string(),
+                ),
+            )),
         }
     }
 }
@@ -128,6 +198,19 @@ impl MetricResult {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum BucketResult {
+    /// This is the significant terms result.
+    ///
+    /// Listed first, as its untagged deserialization is the most restrictive.
+    SignificantTerms {
+        /// The number of documents in the foreground set.
+        doc_count: u64,
+        /// The number of documents in the background set.
+        bg_count: u64,
+        /// The buckets sorted by descending significance.
+        ///
+        /// See [`SignificantTermsAggregation`](super::bucket::SignificantTermsAggregation)
+        buckets: Vec<SignificantTermBucketEntry>,
+    },
     /// This is the range entry for a bucket, which contains a key, count, from, to, and optionally
     /// sub-aggregations.
     Range {
@@ -156,6 +239,29 @@ pub enum BucketResult {
         /// The upper bound error for the doc count of each term.
         doc_count_error_upper_bound: Option<u64>,
     },
+    /// This is the composite result, whose buckets are keyed by the values of several sources.
+    Composite {
+        /// The key of the last bucket, to pass as `after` in the request of the next page.
+        ///
+        /// Missing if there are no buckets, i.e. if all the buckets were returned already.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        after_key: Option<FxHashMap<String, Key>>,
+        /// The buckets sorted by key.
+        ///
+        /// See [`CompositeAggregation`](super::bucket::CompositeAggregation)
+        buckets: Vec<CompositeBucketEntry>,
+    },
+    /// This is the result of a single bucket aggregation, e.g. `exists` or `missing`, which
+    /// contains a count, and optionally sub-aggregations.
+    ///
+    /// Listed last, as its untagged deserialization is the least restrictive.
+    Single {
+        /// The number of documents in the bucket.
+        doc_count: u64,
+        /// The sub-aggregations of the bucket.
+        #[serde(flatten)]
+        sub_aggregation: AggregationResults,
+    },
 }
 
 impl BucketResult {
@@ -172,6 +278,15 @@ impl BucketResult {
                 sum_other_doc_count: _,
                 doc_count_error_upper_bound: _,
             } => buckets.iter().map(|bucket| bucket.get_bucket_count()).sum(),
+            BucketResult::SignificantTerms { buckets, .. } => {
+                buckets.iter().map(|bucket| bucket.get_bucket_count()).sum()
+            }
+            BucketResult::Composite { buckets, .. } => {
+                buckets.iter().map(|bucket| bucket.get_bucket_count()).sum()
+            }
+            BucketResult::Single {
+                sub_aggregation, ..
+            } => 1 + sub_aggregation.get_bucket_count(),
         }
     }
 }
@@ -251,6 +366,87 @@ impl GetDocCount for BucketEntry {
     }
 }
 
+/// This is the entry of a significant term, which contains a key, the foreground and background
+/// counts, the significance score, and optionally sub-aggregations.
+///
+/// # JSON Format
+/// ```json
+/// {
+///   ...
+///     "my_significant_terms": {
+///       "doc_count": 10,
+///       "bg_count": 100,
+///       "buckets": [
+///         {
+///           "key": "rare",
+///           "doc_count": 8,
+///           "bg_count": 10,
+///           "score": 5.6
+///         }
+///       ]
+///    }
+///    ...
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignificantTermBucketEntry {
+    /// The identifier of the bucket.
+    pub key: Key,
+    /// Number of documents of the foreground containing the term.
+    pub doc_count: u64,
+    /// Number of documents of the background containing the term.
+    pub bg_count: u64,
+    /// The significance score of the term.
+    pub score: f64,
+    #[serde(flatten)]
+    /// Sub-aggregations in this bucket.
+    pub sub_aggregation: AggregationResults,
+}
+impl SignificantTermBucketEntry {
+    pub(crate) fn get_bucket_count(&self) -> u64 {
+        1 + self.sub_aggregation.get_bucket_count()
+    }
+}
+
+/// This is the entry of a composite bucket, which contains the values of the sources, a count,
+/// and optionally sub-aggregations.
+///
+/// # JSON Format
+/// ```json
+/// {
+///   ...
+///     "my_composite": {
+///       "after_key": { "shop": "b", "price": 10.0 },
+///       "buckets": [
+///         {
+///           "key": { "shop": "a", "price": 0.0 },
+///           "doc_count": 4
+///         },
+///         {
+///           "key": { "shop": "b", "price": 10.0 },
+///           "doc_count": 2
+///         }
+///       ]
+///    }
+///    ...
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompositeBucketEntry {
+    /// The value of each source, by source name.
+    pub key: FxHashMap<String, Key>,
+    /// Number of documents in the bucket.
+    pub doc_count: u64,
+    #[serde(flatten)]
+    /// Sub-aggregations in this bucket.
+    pub sub_aggregation: AggregationResults,
+}
+impl CompositeBucketEntry {
+    pub(crate) fn get_bucket_count(&self) -> u64 {
+        1 + self.sub_aggregation.get_bucket_count()
+    }
+}
+
 /// This is the range entry for a bucket, which contains a key, count, and optionally
 /// sub-aggregations.
 ///
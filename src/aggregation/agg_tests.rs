@@ -1,16 +1,22 @@
+use itertools::Itertools;
 use serde_json::Value;
 
 use crate::aggregation::agg_req::{Aggregation, Aggregations};
 use crate::aggregation::agg_result::AggregationResults;
-use crate::aggregation::buf_collector::DOC_BLOCK_SIZE;
-use crate::aggregation::collector::AggregationCollector;
+use crate::aggregation::buf_collector::{MAX_DOC_BLOCK_SIZE, MIN_DOC_BLOCK_SIZE};
+use crate::aggregation::collector::{AggregationCollector, AggregationSegmentCollector};
 use crate::aggregation::intermediate_agg_result::IntermediateAggregationResults;
 use crate::aggregation::segment_agg_result::AggregationLimitsGuard;
-use crate::aggregation::tests::{get_test_index_2_segments, get_test_index_from_values_and_terms};
-use crate::aggregation::DistributedAggregationCollector;
+use crate::aggregation::tests::{
+    exec_request, get_test_index_2_segments, get_test_index_from_values_and_terms,
+};
+use crate::aggregation::{AggregationError, AggregationErrorKind, DistributedAggregationCollector};
+use crate::collector::MissingColumnPolicy;
+use crate::error::BudgetKind;
+use crate::indexer::NoMergePolicy;
 use crate::query::{AllQuery, TermQuery};
-use crate::schema::{IndexRecordOption, Schema, FAST};
-use crate::{Index, IndexWriter, Term};
+use crate::schema::{Field, IndexRecordOption, Schema, FAST, STRING};
+use crate::{Index, IndexWriter, TantivyError, Term};
 
 fn get_avg_req(field_name: &str) -> Aggregation {
     serde_json::from_value(json!({
@@ -38,8 +44,7 @@ fn test_aggregation_flushing(
 
     let reader = index.reader()?;
 
-    assert_eq!(DOC_BLOCK_SIZE, 64);
-    // In the tree we cache Documents of DOC_BLOCK_SIZE, before passing them down as one block.
+    // In the tree we cache Documents in blocks of 64, before passing them down as one block.
     //
     // Build a request so that on the first level we have one full cache, which is then flushed.
     // The same cache should have some residue docs at the end, which are flushed (Range 0-70)
@@ -111,6 +116,19 @@ fn test_aggregation_flushing(
             }
         }
     },
+    "significant_terms_test":{
+        "significant_terms": {
+            "field": "string_id"
+        },
+        "aggs": {
+            "bucketsL2": {
+                "histogram": {
+                    "field": "score",
+                    "interval":  70.0
+                }
+            }
+        }
+    },
     "cardinality_string_id":{
         "cardinality": {
             "field": "string_id"
@@ -127,11 +145,9 @@ fn test_aggregation_flushing(
         serde_json::from_str(&serde_json::to_string(&elasticsearch_compatible_json).unwrap())
             .unwrap();
 
+    let limits = AggregationLimitsGuard::default().with_block_size(64);
     let agg_res: AggregationResults = if use_distributed_collector {
-        let collector = DistributedAggregationCollector::from_aggs(
-            agg_req.clone(),
-            AggregationLimitsGuard::default(),
-        );
+        let collector = DistributedAggregationCollector::from_aggs(agg_req.clone(), limits);
 
         let searcher = reader.searcher();
         let intermediate_agg_result = searcher.search(&AllQuery, &collector).unwrap();
@@ -149,7 +165,7 @@ fn test_aggregation_flushing(
             .into_final_result(agg_req, Default::default())
             .unwrap()
     } else {
-        let collector = get_collector(agg_req);
+        let collector = AggregationCollector::from_aggs(agg_req, limits);
 
         let searcher = reader.searcher();
         searcher.search(&AllQuery, &collector).unwrap()
@@ -222,6 +238,12 @@ fn test_aggregation_flushing(
         )
     );
 
+    // The foreground is the whole index, so no term is significant.
+    assert_eq!(
+        res["significant_terms_test"],
+        json!({ "doc_count": 80, "bg_count": 80, "buckets": [] })
+    );
+
     assert_eq!(res["cardinality_string_id"]["value"], 2.0);
     assert_eq!(res["cardinality_score"]["value"], 80.0);
 
@@ -236,6 +258,148 @@ fn test_aggregation_flushing_variants() {
     test_aggregation_flushing(true, true).unwrap();
 }
 
+#[test]
+fn test_aggregation_block_size_sweep() -> crate::Result<()> {
+    let values_and_terms = (0..3)
+        .map(|segment| {
+            (0..700)
+                .map(|val| {
+                    let val = (val * 7 + segment * 13) as f64;
+                    (val, format!("term{}", val as u64 % 5))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let index = get_test_index_from_values_and_terms(false, &values_and_terms)?;
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+    let text_field = searcher.schema().get_field("text_id").unwrap();
+    let term_query = TermQuery::new(
+        Term::from_field_text(text_field, "term1"),
+        IndexRecordOption::Basic,
+    );
+
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "range": {
+            "range": {
+                "field": "score",
+                "ranges": [ { "to": 100.0 }, { "from": 100.0, "to": 2000.0 }, { "from": 2000.0 } ]
+            },
+            "aggs": {
+                "stats": { "stats": { "field": "fraction_f64" } },
+                "terms": { "terms": { "field": "string_id" } }
+            }
+        },
+        "histogram": {
+            "histogram": { "field": "score_f64", "interval": 333.0 },
+            "aggs": {
+                "avg": { "avg": { "field": "fraction_f64" } },
+                "percentiles": { "percentiles": { "field": "score_i64" } }
+            }
+        },
+        "terms": {
+            "terms": { "field": "string_id", "order": { "sum": "desc" } },
+            "aggs": {
+                "sum": { "sum": { "field": "fraction_f64" } },
+                "top_hits": {
+                    "top_hits": { "size": 3, "sort": [ { "score": "desc" } ] }
+                }
+            }
+        },
+        "extended_stats": { "extended_stats": { "field": "fraction_f64" } },
+        "cardinality": { "cardinality": { "field": "string_id" } }
+    }))
+    .unwrap();
+
+    let search = |block_size: Option<usize>| -> crate::Result<(String, String)> {
+        let mut limits = AggregationLimitsGuard::default();
+        if let Some(block_size) = block_size {
+            limits = limits.with_block_size(block_size);
+        }
+        let collector = AggregationCollector::from_aggs(agg_req.clone(), limits);
+        let all_res = searcher.search(&AllQuery, &collector)?;
+        let term_res = searcher.search(&term_query, &collector)?;
+        Ok((
+            serde_json::to_string(&all_res)?,
+            serde_json::to_string(&term_res)?,
+        ))
+    };
+
+    let expected = search(None)?;
+    for block_size in [1, 32, 33, 64, 100, 128, 255, 256, 512, 1000] {
+        assert_eq!(
+            search(Some(block_size))?,
+            expected,
+            "block_size {block_size}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_block_size_is_used() -> crate::Result<()> {
+    let index = get_test_index_2_segments(false)?;
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+    let segment_reader = searcher.segment_reader(0);
+
+    let block_size_for = |agg_req: serde_json::Value, limits: AggregationLimitsGuard| {
+        let agg_req: Aggregations = serde_json::from_value(agg_req).unwrap();
+        AggregationSegmentCollector::from_agg_req_and_reader(&agg_req, segment_reader, 0, &limits)
+            .unwrap()
+            .block_size()
+    };
+
+    let single_agg = json!({ "avg": { "avg": { "field": "score" } } });
+    let wide_agg = json!({
+        "terms": {
+            "terms": { "field": "string_id" },
+            "aggs": {
+                "range": {
+                    "range": { "field": "score", "ranges": [ { "to": 3.0 } ] },
+                    "aggs": {
+                        "terms": { "terms": { "field": "string_id" } },
+                        "avg": { "avg": { "field": "score_f64" } }
+                    }
+                },
+                "stats": { "stats": { "field": "score_i64" } },
+                "cardinality": { "cardinality": { "field": "string_id" } }
+            }
+        },
+        "histogram": {
+            "histogram": { "field": "score", "interval": 10.0 },
+            "aggs": {
+                "terms": { "terms": { "field": "string_id" } },
+                "max": { "max": { "field": "score" } }
+            }
+        },
+        "min": { "min": { "field": "score_f64" } }
+    });
+
+    // A single cheap aggregation uses large blocks, a wide tree small ones.
+    assert_eq!(
+        block_size_for(single_agg.clone(), Default::default()),
+        MAX_DOC_BLOCK_SIZE
+    );
+    assert_eq!(
+        block_size_for(wide_agg.clone(), Default::default()),
+        MIN_DOC_BLOCK_SIZE
+    );
+
+    // The configured block size takes precedence, but stays in bounds.
+    let limits = |block_size| AggregationLimitsGuard::default().with_block_size(block_size);
+    assert_eq!(block_size_for(single_agg.clone(), limits(100)), 100);
+    assert_eq!(block_size_for(wide_agg.clone(), limits(100)), 100);
+    assert_eq!(
+        block_size_for(single_agg.clone(), limits(1)),
+        MIN_DOC_BLOCK_SIZE
+    );
+    assert_eq!(block_size_for(wide_agg, limits(10_000)), MAX_DOC_BLOCK_SIZE);
+
+    Ok(())
+}
+
 #[test]
 fn test_aggregation_level1_simple() -> crate::Result<()> {
     let index = get_test_index_2_segments(true)?;
@@ -572,9 +736,19 @@ fn test_aggregation_invalid_requests() -> crate::Result<()> {
     };
 
     let agg_res = avg_on_field("dummy_text").unwrap_err();
+    assert!(
+        matches!(
+            &agg_res,
+            TantivyError::AggregationError(AggregationError::InvalidAggregation {
+                path,
+                kind: AggregationErrorKind::FieldNotFound { field },
+            }) if path == "average" && field == "dummy_text"
+        ),
+        "{agg_res:?}"
+    );
     assert_eq!(
-        format!("{agg_res:?}"),
-        r#"InvalidArgument("Field \"dummy_text\" is not configured as fast field")"#
+        agg_res.to_string(),
+        "An invalid argument was passed: 'Field \"dummy_text\" is not configured as fast field'"
     );
 
     let agg_req_1: Result<Aggregations, serde_json::Error> = serde_json::from_value(json!({
@@ -939,14 +1113,28 @@ fn test_aggregation_on_json_object_mixed_types() {
           },
           "termagg": {
             "buckets": [
-              { "doc_count": 1, "key": 10, "min_price": { "value": 10.0 } },
+              { "doc_count": 1, "key": "10", "min_price": { "value": 10.0 } },
               { "doc_count": 3, "key": "blue", "min_price": { "value": 5.0 } },
               { "doc_count": 2, "key": "red", "min_price": { "value": 1.0 } },
-              { "doc_count": 1, "key": -20.5, "min_price": { "value": -20.5 } },
-              { "doc_count": 2, "key": 1, "key_as_string": "true", "min_price": { "value": null } },
+              { "doc_count": 1, "key": "-20.5", "min_price": { "value": -20.5 } },
+              { "doc_count": 2, "key": "true", "min_price": { "value": null } },
             ],
             "sum_other_doc_count": 0
-          }
+          },
+          "warnings": [
+            "Aggregation \"rangeagg\" on field \"json.mixed_type\": values of type Bool are \
+             ignored",
+            "Aggregation \"rangeagg\" on field \"json.mixed_type\": values of type Str are \
+             ignored",
+            "Aggregation \"rangeagg.average_in_range\" on field \"json.mixed_type\": values of \
+             type Bool are ignored",
+            "Aggregation \"rangeagg.average_in_range\" on field \"json.mixed_type\": values of \
+             type Str are ignored",
+            "Aggregation \"termagg\" on field \"json.mixed_type\": keys mix numbers and text, \
+             they are all returned as text",
+            "Aggregation \"termagg.min_price\" on field \"json.mixed_price\": values of type \
+             Str are ignored",
+          ]
         }
         )
     );
@@ -1008,3 +1196,772 @@ fn test_aggregation_on_json_object_mixed_numerical_segments() {
         )
     );
 }
+
+fn get_test_index_with_mixed_type_segments() -> crate::Result<Index> {
+    let mut schema_builder = Schema::builder();
+    let json = schema_builder.add_json_field("json", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    // Segment with u64 values.
+    index_writer.add_document(doc!(json => json!({"price": 10, "qty": 1})))?;
+    index_writer.commit()?;
+    // Segment with f64 values.
+    index_writer.add_document(doc!(json => json!({"price": 2.5, "qty": 0.5})))?;
+    index_writer.commit()?;
+    // Segment mixing text and numbers.
+    index_writer.add_document(doc!(json => json!({"price": "free", "qty": 2})))?;
+    index_writer.add_document(doc!(json => json!({"price": 4, "qty": 2})))?;
+    index_writer.commit()?;
+    Ok(index)
+}
+
+#[test]
+fn test_aggregation_mixed_types_lenient() -> crate::Result<()> {
+    let index = get_test_index_with_mixed_type_segments()?;
+    assert_eq!(index.searchable_segment_ids()?.len(), 3);
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "avg_price": { "avg": { "field": "json.price" } },
+        "price_terms": { "terms": { "field": "json.price" } },
+        "qty_terms": { "terms": { "field": "json.qty" } },
+        "sum_qty": { "sum": { "field": "json.qty" } },
+    }))
+    .unwrap();
+    let searcher = index.reader()?.searcher();
+    let res = searcher.search(&AllQuery, &get_collector(agg_req))?;
+    assert_eq!(
+        res.warnings(),
+        vec![
+            "Aggregation \"avg_price\" on field \"json.price\": values of type Str are ignored",
+            "Aggregation \"price_terms\" on field \"json.price\": keys mix numbers and text, they \
+             are all returned as text",
+        ]
+    );
+    let res = serde_json::to_value(res).unwrap();
+    // The u64 and f64 values unify to f64, the text value is ignored.
+    assert_eq!(res["avg_price"]["value"], 5.5);
+    assert_eq!(res["sum_qty"]["value"], 5.5);
+    let sorted_keys = |res: &Value| {
+        res["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| bucket["key"].clone())
+            .sorted_by_key(|key| key.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        sorted_keys(&res["price_terms"]),
+        vec![json!("10"), json!("2.5"), json!("4"), json!("free")]
+    );
+    // Numbers of different types are not a conflict.
+    assert_eq!(
+        sorted_keys(&res["qty_terms"]),
+        vec![json!(0.5), json!(1), json!(2)]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_mixed_types_strict() -> crate::Result<()> {
+    let index = get_test_index_with_mixed_type_segments()?;
+    let exec = |agg_req: Value| -> crate::Result<AggregationResults> {
+        let agg_req: Aggregations = serde_json::from_value(agg_req).unwrap();
+        let searcher = index.reader()?.searcher();
+        searcher.search(&AllQuery, &get_collector(agg_req))
+    };
+
+    let err = exec(json!({
+        "avg_price": { "avg": { "field": "json.price" }, "strict_types": true },
+    }))
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Aggregation \"avg_price\" on field \"json.price\": values of type Str are ignored"
+    );
+    let err = exec(json!({
+        "price_terms": { "terms": { "field": "json.price" }, "strict_types": true },
+    }))
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Aggregation \"price_terms\" on field \"json.price\": keys mix numbers and text"
+    );
+    // The sub-aggregations inherit the flag.
+    let err = exec(json!({
+        "by_qty": {
+            "terms": { "field": "json.qty" },
+            "strict_types": true,
+            "aggs": { "price_terms": { "terms": { "field": "json.price" } } }
+        },
+    }))
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        TantivyError::AggregationError(AggregationError::TypeConflict { ref path, .. })
+            if path == "by_qty.price_terms"
+    ));
+
+    // Fields without conflicts are aggregated as usual.
+    let res = exec(json!({
+        "sum_qty": { "sum": { "field": "json.qty" }, "strict_types": true },
+    }))?;
+    assert!(res.warnings().is_empty());
+    assert_eq!(serde_json::to_value(res).unwrap()["sum_qty"]["value"], 5.5);
+    Ok(())
+}
+
+fn get_test_index_with_renamed_price() -> crate::Result<(Index, Field, Field)> {
+    let mut schema_builder = Schema::builder();
+    let price = schema_builder.add_u64_field("price", FAST);
+    let price_usd = schema_builder.add_f64_field("price_usd", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    // Segment with only the former field.
+    index_writer.add_document(doc!(price_usd => 10.0))?;
+    index_writer.add_document(doc!(price_usd => 20.0))?;
+    index_writer.commit()?;
+    // Segment with only the new field.
+    index_writer.add_document(doc!(price => 30u64))?;
+    index_writer.add_document(doc!(price => 10u64))?;
+    index_writer.commit()?;
+    // Segment with both fields, and a document having a value in both.
+    index_writer.add_document(doc!(price => 40u64, price_usd => 99.0))?;
+    index_writer.add_document(doc!(price_usd => 20.0))?;
+    index_writer.add_document(doc!(price => 50u64))?;
+    index_writer.add_document(doc!())?;
+    index_writer.commit()?;
+    Ok((index, price, price_usd))
+}
+
+#[test]
+fn test_aggregation_field_union() -> crate::Result<()> {
+    let (index, _, _) = get_test_index_with_renamed_price()?;
+    assert_eq!(index.searchable_segment_ids()?.len(), 3);
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "price_terms": {
+            "terms": { "field": "price" },
+            "field_union": ["price", "price_usd"]
+        },
+        "price_stats": {
+            "stats": { "field": "price" },
+            "field_union": ["price", "price_usd"]
+        },
+        "price_usd_stats": {
+            "stats": { "field": "price_usd" },
+            "field_union": ["price_usd", "price"]
+        },
+        "price_only_stats": {
+            "stats": { "field": "price" }
+        }
+    }))
+    .unwrap();
+    let res = exec_request(agg_req, &index)?;
+
+    let mut buckets: Vec<(f64, u64)> = res["price_terms"]["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| {
+            (
+                bucket["key"].as_f64().unwrap(),
+                bucket["doc_count"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    buckets.sort_by(|left, right| left.0.total_cmp(&right.0));
+    assert_eq!(
+        buckets,
+        vec![(10.0, 2), (20.0, 2), (30.0, 1), (40.0, 1), (50.0, 1)]
+    );
+
+    // The first field of the union wins.
+    assert_eq!(res["price_stats"]["count"], 7);
+    assert_eq!(res["price_stats"]["sum"], 180.0);
+    assert_eq!(res["price_stats"]["min"], 10.0);
+    assert_eq!(res["price_stats"]["max"], 50.0);
+    assert_eq!(res["price_usd_stats"]["count"], 7);
+    assert_eq!(res["price_usd_stats"]["sum"], 239.0);
+    assert_eq!(res["price_usd_stats"]["max"], 99.0);
+    // Without the union, only the documents of the new field are aggregated.
+    assert_eq!(res["price_only_stats"]["count"], 4);
+    assert_eq!(res["price_only_stats"]["sum"], 130.0);
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_field_union_invalid_requests() -> crate::Result<()> {
+    let (index, _, _) = get_test_index_with_renamed_price()?;
+    let exec = |agg_req: Value| -> String {
+        let agg_req: Aggregations = serde_json::from_value(agg_req).unwrap();
+        exec_request(agg_req, &index).unwrap_err().to_string()
+    };
+
+    let err = exec(json!({
+        "price_stats": {
+            "stats": { "field": "cost" },
+            "field_union": ["price", "price_usd"]
+        }
+    }));
+    assert!(err.contains("does not include the field"), "{err}");
+
+    let err = exec(json!({
+        "price_exists": {
+            "exists": { "field": "price" },
+            "field_union": ["price", "price_usd"]
+        }
+    }));
+    assert!(
+        err.contains("only supported by aggregations reading a single field"),
+        "{err}"
+    );
+
+    let mut schema_builder = Schema::builder();
+    let price = schema_builder.add_u64_field("price", FAST);
+    let currency = schema_builder.add_text_field("currency", STRING | FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.add_document(doc!(price => 10u64, currency => "usd"))?;
+    index_writer.commit()?;
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "price_terms": {
+            "terms": { "field": "price" },
+            "field_union": ["price", "currency"]
+        }
+    }))
+    .unwrap();
+    let err = exec_request(agg_req, &index).unwrap_err().to_string();
+    assert!(err.contains("text fields"), "{err}");
+    Ok(())
+}
+
+fn all_aggregations_req() -> Aggregations {
+    serde_json::from_value(json!({
+        "range": {
+            "range": {
+                "field": "score",
+                "ranges": [{ "to": 10.0 }, { "from": 10.0, "to": 50.0 }, { "from": 50.0 }]
+            },
+            "aggs": { "avg": { "avg": { "field": "score_f64" } } }
+        },
+        "histogram": {
+            "histogram": { "field": "score", "interval": 10.0 },
+            "aggs": { "sum": { "sum": { "field": "score_i64" } } }
+        },
+        "terms": {
+            "terms": { "field": "string_id" },
+            "aggs": { "stats": { "stats": { "field": "score" } } }
+        },
+        "significant_terms": { "significant_terms": { "field": "string_id" } },
+        "path_terms": { "path_terms": { "field": "string_id" } },
+        "composite": {
+            "composite": {
+                "size": 100,
+                "sources": [
+                    { "term": { "terms": { "field": "string_id" } } },
+                    { "bucket": { "histogram": { "field": "score", "interval": 25.0 } } }
+                ]
+            }
+        },
+        "exists": { "exists": { "field": "score" } },
+        "missing": { "missing": { "field": "score" } },
+        "avg": { "avg": { "field": "score" } },
+        "value_count": { "value_count": { "field": "score" } },
+        "max": { "max": { "field": "score" } },
+        "min": { "min": { "field": "score" } },
+        "stats": { "stats": { "field": "score_i64" } },
+        "extended_stats": { "extended_stats": { "field": "score" } },
+        "sum": { "sum": { "field": "score" } },
+        "percentiles": { "percentiles": { "field": "score" } },
+        "cardinality": { "cardinality": { "field": "string_id" } },
+        "top_hits": {
+            "top_hits": {
+                "size": 3,
+                "sort": [{ "score": "desc" }],
+                "docvalue_fields": ["score"]
+            }
+        }
+    }))
+    .unwrap()
+}
+
+fn collect_segment_fruits(
+    index: &Index,
+    collector: &DistributedAggregationCollector,
+) -> crate::Result<Vec<crate::Result<IntermediateAggregationResults>>> {
+    use crate::collector::Collector;
+    use crate::query::{EnableScoring, Query};
+
+    let searcher = index.reader()?.searcher();
+    let weight = AllQuery.weight(EnableScoring::disabled_from_searcher(&searcher))?;
+    searcher
+        .segment_readers()
+        .iter()
+        .enumerate()
+        .map(|(segment_ord, segment_reader)| {
+            collector.collect_segment(weight.as_ref(), segment_ord as u32, segment_reader)
+        })
+        .collect()
+}
+
+mod merge_order {
+    use proptest::prelude::*;
+    use serde_json::Value;
+
+    use super::{all_aggregations_req, collect_segment_fruits};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::intermediate_agg_result::merge_intermediate_tree;
+    use crate::aggregation::tests::get_test_index_from_values_and_terms;
+    use crate::aggregation::{AggregationLimitsGuard, DistributedAggregationCollector};
+    use crate::collector::Collector;
+    use crate::Executor;
+
+    fn segments_strategy() -> impl Strategy<Value = Vec<Vec<(f64, String)>>> {
+        let doc = (0u64..100, 0usize..6)
+            .prop_map(|(value, term)| (value as f64, format!("term{term}/sub{}", value % 3)));
+        proptest::collection::vec(proptest::collection::vec(doc, 1..20), 2..6)
+    }
+
+    fn merge_in_order(
+        collector: &DistributedAggregationCollector,
+        segment_fruits: &[crate::aggregation::intermediate_agg_result::IntermediateAggregationResults],
+        order: &[usize],
+    ) -> Value {
+        let mut merged = Ok(segment_fruits[order[0]].clone());
+        for &segment_ord in &order[1..] {
+            merged = collector
+                .merge_segment_fruit(merged, Ok(segment_fruits[segment_ord].clone()))
+                .unwrap();
+        }
+        let merged = collector.merge_fruits(vec![merged]).unwrap();
+        let res = merged
+            .into_final_result(all_aggregations_req(), Default::default())
+            .unwrap();
+        serde_json::to_value(res).unwrap()
+    }
+
+    /// Compares two results, allowing for floating point rounding differences, as e.g. the
+    /// variance of `extended_stats` depends on the order in which the segments are merged.
+    fn approx_eq(left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Number(left), Value::Number(right)) => {
+                let (left, right) = (left.as_f64().unwrap(), right.as_f64().unwrap());
+                (left - right).abs() <= 1e-9 * left.abs().max(right.abs()).max(1.0)
+            }
+            (Value::Array(left), Value::Array(right)) => {
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .zip(right)
+                        .all(|(left, right)| approx_eq(left, right))
+            }
+            (Value::Object(left), Value::Object(right)) => {
+                left.len() == right.len()
+                    && left.iter().all(|(key, left)| {
+                        right.get(key).is_some_and(|right| approx_eq(left, right))
+                    })
+            }
+            _ => left == right,
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+        #[test]
+        fn test_merge_order_does_not_change_results(
+            (segments, order) in segments_strategy().prop_flat_map(|segments| {
+                let order = Just((0..segments.len()).collect::<Vec<_>>()).prop_shuffle();
+                (Just(segments), order)
+            })
+        ) {
+            let index = get_test_index_from_values_and_terms(false, &segments).unwrap();
+            let collector =
+                DistributedAggregationCollector::from_aggs(all_aggregations_req(), Default::default());
+            let segment_fruits = collect_segment_fruits(&index, &collector)
+                .unwrap()
+                .into_iter()
+                .collect::<crate::Result<Vec<_>>>()
+                .unwrap();
+            let identity_order = (0..segment_fruits.len()).collect::<Vec<_>>();
+            let reversed_order = identity_order.iter().rev().copied().collect::<Vec<_>>();
+
+            let expected = merge_in_order(&collector, &segment_fruits, &identity_order);
+            let reversed = merge_in_order(&collector, &segment_fruits, &reversed_order);
+            prop_assert!(approx_eq(&reversed, &expected), "{reversed} != {expected}");
+            let shuffled = merge_in_order(&collector, &segment_fruits, &order);
+            prop_assert!(approx_eq(&shuffled, &expected), "{shuffled} != {expected}");
+
+            let batch_merged = collector.merge_fruits(segment_fruits.into_iter().map(Ok).collect()).unwrap();
+            let batch_merged = serde_json::to_value(
+                batch_merged
+                    .into_final_result(all_aggregations_req(), Default::default())
+                    .unwrap(),
+            )
+            .unwrap();
+            prop_assert!(approx_eq(&batch_merged, &expected), "{batch_merged} != {expected}");
+        }
+    }
+
+    fn tree_merge_req() -> Aggregations {
+        serde_json::from_value(serde_json::json!({
+            "terms": {
+                "terms": { "field": "string_id", "size": 100 },
+                "aggs": { "percentiles": { "percentiles": { "field": "score" } } }
+            },
+            "histogram": {
+                "histogram": { "field": "score", "interval": 10.0 },
+                "aggs": { "cardinality": { "cardinality": { "field": "string_id" } } }
+            },
+            "percentiles": { "percentiles": { "field": "score" } },
+            "cardinality": { "cardinality": { "field": "string_id" } }
+        }))
+        .unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+        #[test]
+        fn test_tree_merge_equals_sequential_merge(
+            (segments, order, fan_in) in proptest::collection::vec(
+                proptest::collection::vec(
+                    (0u64..100, 0usize..20).prop_map(|(value, term)| (value as f64, format!("term{term}"))),
+                    1..10,
+                ),
+                1..40,
+            )
+            .prop_flat_map(|segments| {
+                let order = Just((0..segments.len()).collect::<Vec<_>>()).prop_shuffle();
+                (Just(segments), order, 2usize..6)
+            })
+        ) {
+            let index = get_test_index_from_values_and_terms(false, &segments).unwrap();
+            let collector =
+                DistributedAggregationCollector::from_aggs(tree_merge_req(), Default::default());
+            let segment_fruits = collect_segment_fruits(&index, &collector)
+                .unwrap()
+                .into_iter()
+                .collect::<crate::Result<Vec<_>>>()
+                .unwrap();
+            let shard_results = order
+                .iter()
+                .map(|&segment_ord| segment_fruits[segment_ord].clone())
+                .collect::<Vec<_>>();
+
+            let mut sequential_iter = shard_results.clone().into_iter();
+            let mut sequential = sequential_iter.next().unwrap();
+            for next in sequential_iter {
+                sequential.merge_fruits(next).unwrap();
+            }
+            let expected = serde_json::to_value(
+                sequential
+                    .into_final_result(tree_merge_req(), Default::default())
+                    .unwrap(),
+            )
+            .unwrap();
+
+            let executor = Executor::multi_thread(4, "agg-merge-test-").unwrap();
+            let limits = AggregationLimitsGuard::default();
+            let tree_merged =
+                merge_intermediate_tree(&tree_merge_req(), shard_results, fan_in, &limits, &executor)
+                    .unwrap();
+            let tree_merged = serde_json::to_value(
+                tree_merged
+                    .into_final_result(tree_merge_req(), Default::default())
+                    .unwrap(),
+            )
+            .unwrap();
+            prop_assert_eq!(tree_merged, expected);
+        }
+    }
+
+    #[test]
+    fn test_tree_merge_edge_cases() {
+        let limits = AggregationLimitsGuard::default();
+        let executor = Executor::single_thread();
+        let empty =
+            merge_intermediate_tree(&tree_merge_req(), Vec::new(), 2, &limits, &executor).unwrap();
+        assert_eq!(empty.aggs_res.len(), 4);
+        assert!(matches!(
+            merge_intermediate_tree(&tree_merge_req(), Vec::new(), 1, &limits, &executor),
+            Err(crate::TantivyError::InvalidArgument(_))
+        ));
+        // The memory of the merged results counts against the memory limit.
+        let index = get_test_index_from_values_and_terms(
+            false,
+            &(0..8)
+                .map(|segment_ord| vec![(segment_ord as f64, format!("term{segment_ord}"))])
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let collector =
+            DistributedAggregationCollector::from_aggs(tree_merge_req(), Default::default());
+        let segment_fruits = collect_segment_fruits(&index, &collector)
+            .unwrap()
+            .into_iter()
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        let limits = AggregationLimitsGuard::new(Some(100), None);
+        assert!(matches!(
+            merge_intermediate_tree(&tree_merge_req(), segment_fruits, 2, &limits, &executor),
+            Err(crate::TantivyError::BudgetExceeded { .. })
+        ));
+    }
+}
+
+fn get_test_index_many_segments(num_segments: usize, num_terms: usize) -> crate::Result<Index> {
+    let segments = (0..num_segments)
+        .map(|segment_ord| {
+            (0..num_terms)
+                .map(|term_ord| {
+                    (
+                        term_ord as f64,
+                        format!("segment{segment_ord}_term{term_ord}"),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    get_test_index_from_values_and_terms(false, &segments)
+}
+
+#[test]
+fn test_aggregation_many_segments_multithread() -> crate::Result<()> {
+    let index = get_test_index_many_segments(48, 10)?;
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.segment_readers().len(), 48);
+
+    let collector = AggregationCollector::from_aggs(all_aggregations_req(), Default::default());
+    let single_thread = searcher.search(&AllQuery, &collector)?;
+    let multi_thread = searcher.search_with_executor(
+        &AllQuery,
+        &collector,
+        &crate::Executor::multi_thread(4, "agg-test-")?,
+        crate::query::EnableScoring::disabled_from_searcher(&searcher),
+    )?;
+    assert_eq!(
+        serde_json::to_value(&multi_thread)?,
+        serde_json::to_value(&single_thread)?
+    );
+    let res = serde_json::to_value(&single_thread)?;
+    assert_eq!(res["value_count"]["value"], 480.0);
+    assert_eq!(res["cardinality"]["value"], 480.0);
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_memory_limit_accounts_for_merged_results() -> crate::Result<()> {
+    let index = get_test_index_many_segments(40, 50)?;
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "terms": { "terms": { "field": "string_id", "size": 10_000 } }
+    }))
+    .unwrap();
+
+    let collector = DistributedAggregationCollector::from_aggs(agg_req.clone(), Default::default());
+    let searcher = index.reader()?.searcher();
+    let merged_memory = searcher.search(&AllQuery, &collector)?.memory_consumption() as u64;
+
+    // Each segment holds 1/40th of the terms, so a single segment fits within the limit, but the
+    // merged results of all segments do not.
+    let limits = AggregationLimitsGuard::new(Some(merged_memory / 2), None);
+    let collector = DistributedAggregationCollector::from_aggs(agg_req.clone(), limits.clone());
+    let segment_fruits = collect_segment_fruits(&index, &collector)?;
+    assert!(segment_fruits.iter().all(|fruit| fruit.is_ok()));
+
+    let collector = AggregationCollector::from_aggs(agg_req, limits);
+    let err = searcher.search(&AllQuery, &collector).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            TantivyError::BudgetExceeded {
+                kind: BudgetKind::Memory,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_merged_results_stay_charged() -> crate::Result<()> {
+    use crate::collector::Collector;
+
+    let index = get_test_index_many_segments(4, 50)?;
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "terms": { "terms": { "field": "string_id", "size": 10_000 } }
+    }))
+    .unwrap();
+    let merge_incrementally = |collector: &DistributedAggregationCollector| {
+        let mut segment_fruits = collect_segment_fruits(&index, collector)?.into_iter();
+        let merged = segment_fruits.next().unwrap();
+        segment_fruits.try_fold(merged, |merged, segment_fruit| {
+            collector.merge_segment_fruit(merged, segment_fruit)
+        })
+    };
+    let collector = DistributedAggregationCollector::from_aggs(agg_req.clone(), Default::default());
+    let merged_memory = merge_incrementally(&collector)??.memory_consumption() as u64;
+
+    let limits = AggregationLimitsGuard::new(Some(2 * merged_memory), None);
+    let collector = DistributedAggregationCollector::from_aggs(agg_req, limits.clone());
+    let merged = merge_incrementally(&collector)??;
+    // The merged results are charged for as long as they are kept.
+    assert!(limits
+        .clone()
+        .add_memory_consumed(merged_memory + 1)
+        .is_err());
+    drop(merged);
+    limits.clone().add_memory_consumed(merged_memory + 1)?;
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_missing_column_policy() -> crate::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let attributes = schema_builder.add_json_field("attributes", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    // The old segment was written before the documents had a price.
+    index_writer.add_document(doc!(attributes => json!({"color": "red"})))?;
+    index_writer.add_document(doc!(attributes => json!({"color": "blue"})))?;
+    index_writer.commit()?;
+    index_writer.add_document(doc!(attributes => json!({"price": 10.0})))?;
+    index_writer.add_document(doc!(attributes => json!({"price": 20.0})))?;
+    index_writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "price_stats": { "stats": { "field": "attributes.price" } }
+    }))
+    .unwrap();
+    let exec = |policy: Option<MissingColumnPolicy<f64>>| -> crate::Result<Value> {
+        let mut collector = AggregationCollector::from_aggs(agg_req.clone(), Default::default());
+        if let Some(policy) = policy {
+            collector = collector.with_missing_column_policy(policy);
+        }
+        let res = searcher.search(&AllQuery, &collector)?;
+        Ok(serde_json::to_value(res)?)
+    };
+
+    let old_segment_id = searcher
+        .segment_readers()
+        .iter()
+        .find(|segment_reader| {
+            segment_reader
+                .fast_fields()
+                .u64_lenient("attributes.price")
+                .unwrap()
+                .is_none()
+        })
+        .unwrap()
+        .segment_id();
+    let err = exec(Some(MissingColumnPolicy::Error)).unwrap_err();
+    assert!(matches!(
+        &err,
+        TantivyError::MissingColumn { segment_id, field }
+            if *segment_id == old_segment_id && field == "attributes.price"
+    ));
+
+    // The two documents of the old segment count with the default value.
+    let res = exec(Some(MissingColumnPolicy::Default(5.0)))?;
+    assert_eq!(res["price_stats"]["count"], 4);
+    assert_eq!(res["price_stats"]["sum"], 40.0);
+    assert_eq!(res["price_stats"]["min"], 5.0);
+
+    let res = exec(Some(MissingColumnPolicy::SkipSegment))?;
+    assert_eq!(res["price_stats"]["count"], 2);
+    assert_eq!(res["price_stats"]["sum"], 30.0);
+
+    // Without a policy, the old segment has no value for the field.
+    assert_eq!(exec(Some(MissingColumnPolicy::SkipSegment))?, exec(None)?);
+    Ok(())
+}
+
+#[test]
+fn test_aggregation_error_variants() -> crate::Result<()> {
+    let mut index = get_test_index_2_segments(false)?;
+
+    // Runs the request on a single thread, on several threads and with the distributed
+    // collector, and checks that the error is the same in all three cases.
+    let mut search_err = |agg_req: Value, limits: AggregationLimitsGuard| {
+        let agg_req: Aggregations = serde_json::from_value(agg_req).unwrap();
+        let mut errors = Vec::new();
+        for num_threads in [1, 4] {
+            index.set_multithread_executor(num_threads).unwrap();
+            let searcher = index.reader().unwrap().searcher();
+            let collector = AggregationCollector::from_aggs(agg_req.clone(), limits.clone());
+            errors.push(searcher.search(&AllQuery, &collector).unwrap_err());
+            let collector =
+                DistributedAggregationCollector::from_aggs(agg_req.clone(), limits.clone());
+            errors.push(searcher.search(&AllQuery, &collector).unwrap_err());
+        }
+        for err in &errors[1..] {
+            assert_eq!(err.to_string(), errors[0].to_string());
+            assert_eq!(err.error_code(), errors[0].error_code());
+        }
+        errors.pop().unwrap()
+    };
+
+    let err = search_err(
+        json!({
+            "histo": {
+                "histogram": { "field": "score", "interval": 10.0 },
+                "aggs": { "avg_dummy": { "avg": { "field": "dummy_text" } } }
+            }
+        }),
+        Default::default(),
+    );
+    assert!(
+        matches!(
+            &err,
+            TantivyError::AggregationError(AggregationError::InvalidAggregation {
+                path,
+                kind: AggregationErrorKind::FieldNotFound { field },
+            }) if path == "histo.avg_dummy" && field == "dummy_text"
+        ),
+        "{err:?}"
+    );
+    assert_eq!(err.error_code(), "aggregation.field_not_found");
+
+    let err = search_err(
+        json!({ "histo": { "histogram": { "field": "score", "interval": 0.0 } } }),
+        Default::default(),
+    );
+    assert!(matches!(
+        &err,
+        TantivyError::AggregationError(AggregationError::InvalidAggregation {
+            path,
+            kind: AggregationErrorKind::InvalidParameter { name, .. },
+        }) if path == "histo" && name == "interval"
+    ));
+    assert_eq!(err.error_code(), "aggregation.invalid_parameter");
+
+    let err = search_err(
+        json!({ "significant": { "significant_terms": { "field": "score" } } }),
+        Default::default(),
+    );
+    assert!(matches!(
+        &err,
+        TantivyError::AggregationError(AggregationError::InvalidAggregation {
+            path,
+            kind: AggregationErrorKind::TypeMismatch { field, found, .. },
+        }) if path == "significant" && field == "score" && found == "U64"
+    ));
+    assert_eq!(err.error_code(), "aggregation.type_mismatch");
+
+    let err = search_err(
+        json!({ "terms": { "terms": { "field": "string_id" } } }),
+        AggregationLimitsGuard::new(Some(100), None),
+    );
+    assert!(matches!(
+        err,
+        TantivyError::BudgetExceeded {
+            kind: BudgetKind::Memory,
+            limit: 100,
+            used,
+        } if used > 100
+    ));
+    assert_eq!(err.error_code(), "budget_exceeded.memory");
+    Ok(())
+}
@@ -0,0 +1,828 @@
+//! Conversion of bucket aggregation results to Apache Arrow record batches.
+//!
+//! Requires the `arrow` feature.
+//!
+//! # Flattening scheme
+//!
+//! A bucket aggregation (`terms`, `histogram`, `date_histogram`, `range`, `significant_terms`,
+//! ...) becomes one row per bucket, with the following columns:
+//! - `key`: the key of the bucket. Its type follows the type of the keys: `UInt64`, `Int64`,
+//!   `Float64` or `Utf8`. The keys of a `date_histogram` become a millisecond `Timestamp`, and the
+//!   keys of a `terms` aggregation on a bool field become `Boolean`. Keys of different types are
+//!   converted to `Float64` if they are all numbers, to `Utf8` otherwise.
+//! - `doc_count`: `UInt64`.
+//! - `from` and `to` for `range` buckets: `Float64`, or a nanosecond `Timestamp` on date fields.
+//! - `bg_count` (`UInt64`) and `score` (`Float64`) for `significant_terms` buckets.
+//! - One column per metric sub-aggregation, named after it. Single value metrics are `Float64`.
+//!   `stats` are split into `<name>.count`, `<name>.sum`, `<name>.min`, `<name>.max` and
+//!   `<name>.avg` columns.
+//!
+//! One level of nesting is supported: if the buckets have a bucket sub-aggregation, there is one
+//! row per sub-bucket, and the columns of the sub-buckets are prefixed with the name of the
+//! sub-aggregation, e.g. `<name>.key`. The columns of a parent bucket are repeated for each of its
+//! sub-buckets. A parent bucket without sub-buckets still gets a row, in which the columns of the
+//! sub-buckets are null.
+//!
+//! Deeper nesting, or several bucket sub-aggregations, return
+//! [`ArrowConversionError::UnsupportedNesting`].
+//! Single bucket aggregations (`exists`, `missing`) have no key, and composite aggregations have
+//! several keys per bucket: both return [`ArrowConversionError::UnsupportedAggregation`].
+//! The [custom results](crate::aggregation::custom_intermediate_result) of sub-aggregations are
+//! skipped.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMillisecondArray, TimestampNanosecondArray, UInt64Array,
+};
+use arrow_schema::{Field, Schema};
+
+use super::agg_result::{
+    AggregationResult, AggregationResults, BucketEntries, BucketEntry, BucketResult, MetricResult,
+    RangeBucketEntry,
+};
+use super::metric::Stats;
+use super::Key;
+
+/// Error returned when aggregation results cannot be converted to an Arrow record batch.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ArrowConversionError {
+    /// Bucket aggregations are nested more than one level deep, or a level has several bucket
+    /// sub-aggregations.
+    #[error("Unsupported nesting: {0}")]
+    UnsupportedNesting(String),
+    /// The aggregation cannot be represented as columns, e.g. a `top_hits` metric.
+    #[error("Unsupported aggregation: {0}")]
+    UnsupportedAggregation(String),
+    /// The record batch was rejected by Arrow.
+    #[error("Arrow error: {0}")]
+    Arrow(String),
+}
+
+impl AggregationResults {
+    /// Converts the results to an Arrow record batch, following the
+    /// [flattening scheme](crate::aggregation::arrow).
+    ///
+    /// The results need to contain exactly one aggregation, which needs to be a bucket
+    /// aggregation. Use [`BucketResult::to_arrow`] to convert one of several aggregations.
+    pub fn to_arrow(&self) -> Result<RecordBatch, ArrowConversionError> {
+        let mut aggregations = self.0.iter();
+        match (aggregations.next(), aggregations.next()) {
+            (Some((_, AggregationResult::BucketResult(bucket_result))), None) => {
+                bucket_result.to_arrow()
+            }
+            (Some((name, AggregationResult::MetricResult(_))), None) => {
+                Err(ArrowConversionError::UnsupportedAggregation(format!(
+                    "{name:?} is a metric aggregation, expected a bucket aggregation"
+                )))
+            }
+            (Some((name, AggregationResult::CustomResult(_))), None) => {
+                Err(ArrowConversionError::UnsupportedAggregation(format!(
+                    "{name:?} is a custom aggregation, expected a bucket aggregation"
+                )))
+            }
+            _ => Err(ArrowConversionError::UnsupportedAggregation(format!(
+                "expected exactly one aggregation, got {}",
+                self.0.len()
+            ))),
+        }
+    }
+}
+
+impl BucketResult {
+    /// Converts the buckets to an Arrow record batch, following the
+    /// [flattening scheme](crate::aggregation::arrow).
+    pub fn to_arrow(&self) -> Result<RecordBatch, ArrowConversionError> {
+        let parent_buckets = bucket_views(self)?;
+        let sub_bucket_name = sub_bucket_aggregation_name(&parent_buckets)?;
+
+        let mut rows: Vec<(&BucketView, Option<BucketView>)> = Vec::new();
+        for parent_bucket in &parent_buckets {
+            let sub_buckets =
+                match sub_bucket_name.and_then(|name| parent_bucket.sub_aggregation.0.get(name)) {
+                    Some(AggregationResult::BucketResult(sub_bucket_result)) => {
+                        bucket_views(sub_bucket_result)?
+                    }
+                    Some(AggregationResult::MetricResult(_))
+                    | Some(AggregationResult::CustomResult(_))
+                    | None => Vec::new(),
+                };
+            if let Some(nested_name) = sub_bucket_aggregation_name(&sub_buckets)? {
+                return Err(ArrowConversionError::UnsupportedNesting(format!(
+                    "{nested_name:?} is nested more than one level deep"
+                )));
+            }
+            if sub_buckets.is_empty() {
+                rows.push((parent_bucket, None));
+            } else {
+                rows.extend(
+                    sub_buckets
+                        .into_iter()
+                        .map(|sub_bucket| (parent_bucket, Some(sub_bucket))),
+                );
+            }
+        }
+
+        let parent_column_buckets: Vec<Option<&BucketView>> =
+            rows.iter().map(|(parent, _)| Some(*parent)).collect();
+        let mut columns = level_columns("", &parent_column_buckets, sub_bucket_name, false)?;
+        if let Some(sub_bucket_name) = sub_bucket_name {
+            let sub_column_buckets: Vec<Option<&BucketView>> = rows
+                .iter()
+                .map(|(_, sub_bucket)| sub_bucket.as_ref())
+                .collect();
+            let prefix = format!("{sub_bucket_name}.");
+            columns.extend(level_columns(&prefix, &sub_column_buckets, None, true)?);
+        }
+
+        let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = columns.into_iter().unzip();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+            .map_err(|err| ArrowConversionError::Arrow(err.to_string()))
+    }
+}
+
+/// A bucket of any bucket aggregation.
+struct BucketView<'a> {
+    key: &'a Key,
+    key_kind: KeyKind,
+    doc_count: u64,
+    extra: BucketExtra<'a>,
+    sub_aggregation: &'a AggregationResults,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Plain,
+    /// Key of a date histogram, in milliseconds.
+    DateMillis,
+    /// Key of a terms aggregation on a bool field.
+    Bool,
+}
+
+enum BucketExtra<'a> {
+    None,
+    Range(&'a RangeBucketEntry),
+    SignificantTerms { bg_count: u64, score: f64 },
+}
+
+fn bucket_views(bucket_result: &BucketResult) -> Result<Vec<BucketView<'_>>, ArrowConversionError> {
+    let bucket_views = match bucket_result {
+        BucketResult::Terms { buckets, .. } => buckets
+            .iter()
+            .map(|bucket| BucketView {
+                key: &bucket.key,
+                // Only set on bool fields, where the key is 0 or 1.
+                key_kind: if bucket.key_as_string.is_some() {
+                    KeyKind::Bool
+                } else {
+                    KeyKind::Plain
+                },
+                doc_count: bucket.doc_count,
+                extra: BucketExtra::None,
+                sub_aggregation: &bucket.sub_aggregation,
+            })
+            .collect(),
+        BucketResult::Histogram { buckets } => {
+            let buckets: Vec<&BucketEntry> = match buckets {
+                BucketEntries::Vec(buckets) => buckets.iter().collect(),
+                BucketEntries::HashMap(buckets) => {
+                    let mut buckets: Vec<_> = buckets.values().collect();
+                    buckets.sort_by(|left, right| {
+                        key_as_f64(&left.key).total_cmp(&key_as_f64(&right.key))
+                    });
+                    buckets
+                }
+            };
+            buckets
+                .into_iter()
+                .map(|bucket| BucketView {
+                    key: &bucket.key,
+                    // Only set on date histograms, where the key is in milliseconds.
+                    key_kind: if bucket.key_as_string.is_some() {
+                        KeyKind::DateMillis
+                    } else {
+                        KeyKind::Plain
+                    },
+                    doc_count: bucket.doc_count,
+                    extra: BucketExtra::None,
+                    sub_aggregation: &bucket.sub_aggregation,
+                })
+                .collect()
+        }
+        BucketResult::Range { buckets } => {
+            let buckets: Vec<&RangeBucketEntry> = match buckets {
+                BucketEntries::Vec(buckets) => buckets.iter().collect(),
+                BucketEntries::HashMap(buckets) => {
+                    let mut buckets: Vec<_> = buckets.values().collect();
+                    buckets.sort_by(|left, right| {
+                        let left_from = left.from.unwrap_or(f64::MIN);
+                        let right_from = right.from.unwrap_or(f64::MIN);
+                        left_from.total_cmp(&right_from)
+                    });
+                    buckets
+                }
+            };
+            buckets
+                .into_iter()
+                .map(|bucket| BucketView {
+                    key: &bucket.key,
+                    key_kind: KeyKind::Plain,
+                    doc_count: bucket.doc_count,
+                    extra: BucketExtra::Range(bucket),
+                    sub_aggregation: &bucket.sub_aggregation,
+                })
+                .collect()
+        }
+        BucketResult::SignificantTerms { buckets, .. } => buckets
+            .iter()
+            .map(|bucket| BucketView {
+                key: &bucket.key,
+                key_kind: KeyKind::Plain,
+                doc_count: bucket.doc_count,
+                extra: BucketExtra::SignificantTerms {
+                    bg_count: bucket.bg_count,
+                    score: bucket.score,
+                },
+                sub_aggregation: &bucket.sub_aggregation,
+            })
+            .collect(),
+        BucketResult::Composite { .. } => {
+            return Err(ArrowConversionError::UnsupportedAggregation(
+                "composite aggregations have one key per source".to_string(),
+            ));
+        }
+        BucketResult::Single { .. } => {
+            return Err(ArrowConversionError::UnsupportedAggregation(
+                "single bucket aggregations, e.g. `exists` or `missing`, have no key".to_string(),
+            ));
+        }
+    };
+    Ok(bucket_views)
+}
+
+/// Returns the name of the bucket sub-aggregation of the buckets, if any.
+fn sub_bucket_aggregation_name<'a>(
+    buckets: &[BucketView<'a>],
+) -> Result<Option<&'a str>, ArrowConversionError> {
+    let names: BTreeSet<&'a str> = buckets
+        .iter()
+        .flat_map(|bucket| bucket.sub_aggregation.0.iter())
+        .filter(|(_, result)| matches!(result, AggregationResult::BucketResult(_)))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let mut names_iter = names.iter();
+    match (names_iter.next(), names_iter.next()) {
+        (None, _) => Ok(None),
+        (Some(name), None) => Ok(Some(name)),
+        (Some(_), Some(_)) => Err(ArrowConversionError::UnsupportedNesting(format!(
+            "only one bucket sub-aggregation is supported per level, got {names:?}"
+        ))),
+    }
+}
+
+type Column = (Field, ArrayRef);
+
+/// Reads an optional value of a bucket or a metric.
+type Getter<T> = fn(&T) -> Option<f64>;
+
+/// Builds the columns of one level of buckets, one value per row.
+///
+/// A `None` bucket is a parent bucket without sub-buckets, and gets null values.
+fn level_columns(
+    prefix: &str,
+    buckets: &[Option<&BucketView>],
+    sub_bucket_name: Option<&str>,
+    nullable: bool,
+) -> Result<Vec<Column>, ArrowConversionError> {
+    let mut columns = vec![
+        key_column(&format!("{prefix}key"), buckets, nullable),
+        column(
+            format!("{prefix}doc_count"),
+            nullable,
+            buckets
+                .iter()
+                .map(|bucket| bucket.map(|bucket| bucket.doc_count))
+                .collect::<UInt64Array>(),
+        ),
+    ];
+    columns.extend(extra_columns(prefix, buckets, nullable));
+
+    let mut metric_names: BTreeSet<&str> = BTreeSet::new();
+    for bucket in buckets.iter().flatten() {
+        for (name, result) in &bucket.sub_aggregation.0 {
+            if let AggregationResult::MetricResult(_) = result {
+                metric_names.insert(name);
+            }
+        }
+    }
+    for metric_name in metric_names {
+        if Some(metric_name) == sub_bucket_name {
+            continue;
+        }
+        let metrics: Vec<Option<&MetricResult>> = buckets
+            .iter()
+            .map(
+                |bucket| match (*bucket)?.sub_aggregation.0.get(metric_name)? {
+                    AggregationResult::MetricResult(metric) => Some(metric),
+                    AggregationResult::BucketResult(_) | AggregationResult::CustomResult(_) => None,
+                },
+            )
+            .collect();
+        columns.extend(metric_columns(&format!("{prefix}{metric_name}"), &metrics)?);
+    }
+    Ok(columns)
+}
+
+fn column(name: String, nullable: bool, array: impl Array + 'static) -> Column {
+    let field = Field::new(name, array.data_type().clone(), nullable);
+    (field, Arc::new(array))
+}
+
+fn key_as_f64(key: &Key) -> f64 {
+    match key {
+        Key::Str(_) => f64::NAN,
+        Key::I64(val) => *val as f64,
+        Key::U64(val) => *val as f64,
+        Key::F64(val) => *val,
+    }
+}
+
+fn key_column(name: &str, buckets: &[Option<&BucketView>], nullable: bool) -> Column {
+    let name = name.to_string();
+    let keys = || buckets.iter().map(|bucket| bucket.map(|bucket| bucket.key));
+    let present_buckets = || buckets.iter().flatten();
+    let all_keys =
+        |predicate: fn(&Key) -> bool| present_buckets().all(|bucket| predicate(bucket.key));
+    let all_kind = |key_kind: KeyKind| present_buckets().all(|bucket| bucket.key_kind == key_kind);
+
+    if present_buckets().next().is_none() || present_buckets().any(|b| matches!(b.key, Key::Str(_)))
+    {
+        let array = keys()
+            .map(|key| key.map(|key| key.to_string()))
+            .collect::<StringArray>();
+        return column(name, nullable, array);
+    }
+    if all_kind(KeyKind::DateMillis) && all_keys(|key| matches!(key, Key::F64(_))) {
+        let array = keys()
+            .map(|key| key.map(|key| key_as_f64(key) as i64))
+            .collect::<TimestampMillisecondArray>()
+            .with_timezone("UTC");
+        return column(name, nullable, array);
+    }
+    if all_kind(KeyKind::Bool) && all_keys(|key| matches!(key, Key::U64(_))) {
+        let array = keys()
+            .map(|key| key.map(|key| *key == Key::U64(1)))
+            .collect::<BooleanArray>();
+        return column(name, nullable, array);
+    }
+    if all_keys(|key| matches!(key, Key::U64(_))) {
+        let array = keys()
+            .map(|key| {
+                key.map(|key| match key {
+                    Key::U64(val) => *val,
+                    _ => unreachable!(),
+                })
+            })
+            .collect::<UInt64Array>();
+        return column(name, nullable, array);
+    }
+    if all_keys(|key| matches!(key, Key::I64(_))) {
+        let array = keys()
+            .map(|key| {
+                key.map(|key| match key {
+                    Key::I64(val) => *val,
+                    _ => unreachable!(),
+                })
+            })
+            .collect::<Int64Array>();
+        return column(name, nullable, array);
+    }
+    let array = keys()
+        .map(|key| key.map(key_as_f64))
+        .collect::<Float64Array>();
+    column(name, nullable, array)
+}
+
+fn extra_columns(prefix: &str, buckets: &[Option<&BucketView>], nullable: bool) -> Vec<Column> {
+    let present_buckets = || buckets.iter().flatten();
+    let is_range = present_buckets().any(|bucket| matches!(bucket.extra, BucketExtra::Range(_)));
+    let is_significant_terms = present_buckets()
+        .any(|bucket| matches!(bucket.extra, BucketExtra::SignificantTerms { .. }));
+    let mut columns = Vec::new();
+    if is_range {
+        let ranges: Vec<Option<&RangeBucketEntry>> = buckets
+            .iter()
+            .map(|bucket| match (*bucket)?.extra {
+                BucketExtra::Range(range) => Some(range),
+                _ => None,
+            })
+            .collect();
+        // The bounds of ranges on date fields are in nanoseconds.
+        let is_date = ranges
+            .iter()
+            .flatten()
+            .any(|range| range.from_as_string.is_some() || range.to_as_string.is_some());
+        let bounds: [(&str, Getter<RangeBucketEntry>); 2] =
+            [("from", |range| range.from), ("to", |range| range.to)];
+        for (bound_name, bound) in bounds {
+            let values = ranges.iter().map(|range| range.and_then(bound));
+            let name = format!("{prefix}{bound_name}");
+            // Unbounded ranges are null.
+            if is_date {
+                let array = values
+                    .map(|value| value.map(|value| value as i64))
+                    .collect::<TimestampNanosecondArray>()
+                    .with_timezone("UTC");
+                columns.push(column(name, true, array));
+            } else {
+                let array = values.collect::<Float64Array>();
+                columns.push(column(name, true, array));
+            }
+        }
+    }
+    if is_significant_terms {
+        let significant_terms: Vec<Option<(u64, f64)>> = buckets
+            .iter()
+            .map(|bucket| match (*bucket)?.extra {
+                BucketExtra::SignificantTerms { bg_count, score } => Some((bg_count, score)),
+                _ => None,
+            })
+            .collect();
+        let bg_counts = significant_terms
+            .iter()
+            .map(|values| values.map(|(bg_count, _)| bg_count))
+            .collect::<UInt64Array>();
+        columns.push(column(format!("{prefix}bg_count"), nullable, bg_counts));
+        let scores = significant_terms
+            .iter()
+            .map(|values| values.map(|(_, score)| score))
+            .collect::<Float64Array>();
+        columns.push(column(format!("{prefix}score"), nullable, scores));
+    }
+    columns
+}
+
+/// Builds the columns of a metric sub-aggregation. Buckets missing the metric get null values.
+fn metric_columns(
+    name: &str,
+    metrics: &[Option<&MetricResult>],
+) -> Result<Vec<Column>, ArrowConversionError> {
+    let Some(first_metric) = metrics.iter().flatten().next() else {
+        return Ok(Vec::new());
+    };
+    match first_metric {
+        MetricResult::Average(_)
+        | MetricResult::Count(_)
+        | MetricResult::Max(_)
+        | MetricResult::Min(_)
+        | MetricResult::Sum(_)
+        | MetricResult::Cardinality(_) => {
+            let array = metrics
+                .iter()
+                .map(|metric| match metric {
+                    Some(
+                        MetricResult::Average(single)
+                        | MetricResult::Count(single)
+                        | MetricResult::Max(single)
+                        | MetricResult::Min(single)
+                        | MetricResult::Sum(single)
+                        | MetricResult::Cardinality(single),
+                    ) => single.value,
+                    _ => None,
+                })
+                .collect::<Float64Array>();
+            Ok(vec![column(name.to_string(), true, array)])
+        }
+        MetricResult::Stats(_) => {
+            let stats: Vec<_> = metrics
+                .iter()
+                .map(|metric| match metric {
+                    Some(MetricResult::Stats(stats)) => Some(stats),
+                    _ => None,
+                })
+                .collect();
+            let count = stats
+                .iter()
+                .map(|stats| stats.map(|stats| stats.count))
+                .collect::<UInt64Array>();
+            let mut columns = vec![column(format!("{name}.count"), true, count)];
+            let values: [(&str, Getter<Stats>); 4] = [
+                ("sum", |stats| Some(stats.sum)),
+                ("min", |stats| stats.min),
+                ("max", |stats| stats.max),
+                ("avg", |stats| stats.avg),
+            ];
+            for (value_name, value) in values {
+                let array = stats
+                    .iter()
+                    .map(|stats| stats.and_then(value))
+                    .collect::<Float64Array>();
+                columns.push(column(format!("{name}.{value_name}"), true, array));
+            }
+            Ok(columns)
+        }
+        MetricResult::ExtendedStats(_)
+        | MetricResult::Percentiles(_)
+        | MetricResult::TopHits(_) => Err(ArrowConversionError::UnsupportedAggregation(format!(
+            "the metric {name:?} cannot be represented as columns"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{
+        Float64Type, Int64Type, TimestampMillisecondType, TimestampNanosecondType, UInt64Type,
+    };
+    use arrow_schema::{DataType, TimeUnit};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::get_test_index_2_segments;
+    use crate::aggregation::{AggregationCollector, AggregationLimitsGuard};
+    use crate::query::AllQuery;
+    use crate::Index;
+
+    fn exec(index: &Index, agg_req: Value) -> (AggregationResults, Value) {
+        let agg_req: Aggregations = serde_json::from_value(agg_req).unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, AggregationLimitsGuard::default());
+        let searcher = index.reader().unwrap().searcher();
+        let agg_res = searcher.search(&AllQuery, &collector).unwrap();
+        let json = serde_json::to_value(&agg_res).unwrap();
+        (agg_res, json)
+    }
+
+    /// Returns the value of a cell as JSON, to be compared with the JSON results.
+    fn cell(batch: &RecordBatch, column_name: &str, row: usize) -> Value {
+        let array = batch
+            .column_by_name(column_name)
+            .unwrap_or_else(|| panic!("missing column {column_name:?}"));
+        if array.is_null(row) {
+            return Value::Null;
+        }
+        match array.data_type() {
+            DataType::UInt64 => json!(array.as_primitive::<UInt64Type>().value(row)),
+            DataType::Int64 => json!(array.as_primitive::<Int64Type>().value(row)),
+            DataType::Float64 => json!(array.as_primitive::<Float64Type>().value(row)),
+            DataType::Utf8 => json!(array.as_string::<i32>().value(row)),
+            DataType::Boolean => json!(array.as_boolean().value(row)),
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                json!(array.as_primitive::<TimestampMillisecondType>().value(row) as f64)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                json!(array.as_primitive::<TimestampNanosecondType>().value(row) as f64)
+            }
+            data_type => panic!("unexpected data type {data_type:?}"),
+        }
+    }
+
+    fn data_type(batch: &RecordBatch, column_name: &str) -> DataType {
+        batch
+            .schema()
+            .field_with_name(column_name)
+            .unwrap()
+            .data_type()
+            .clone()
+    }
+
+    #[test]
+    fn test_arrow_terms_with_metrics() {
+        let index = get_test_index_2_segments(false).unwrap();
+        let (agg_res, json) = exec(
+            &index,
+            json!({
+                "my_texts": {
+                    "terms": { "field": "text" },
+                    "aggs": {
+                        "avg_score": { "avg": { "field": "score" } },
+                        "score_stats": { "stats": { "field": "score_i64" } }
+                    }
+                }
+            }),
+        );
+        let batch = agg_res.to_arrow().unwrap();
+        let column_names: Vec<String> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+        assert_eq!(
+            column_names,
+            [
+                "key",
+                "doc_count",
+                "avg_score",
+                "score_stats.count",
+                "score_stats.sum",
+                "score_stats.min",
+                "score_stats.max",
+                "score_stats.avg"
+            ]
+        );
+        assert_eq!(data_type(&batch, "key"), DataType::Utf8);
+        assert_eq!(data_type(&batch, "doc_count"), DataType::UInt64);
+        assert_eq!(data_type(&batch, "score_stats.count"), DataType::UInt64);
+
+        let buckets = json["my_texts"]["buckets"].as_array().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_rows(), buckets.len());
+        for (row, bucket) in buckets.iter().enumerate() {
+            assert_eq!(cell(&batch, "key", row), bucket["key"]);
+            assert_eq!(cell(&batch, "doc_count", row), bucket["doc_count"]);
+            assert_eq!(cell(&batch, "avg_score", row), bucket["avg_score"]["value"]);
+            for stat in ["count", "sum", "min", "max", "avg"] {
+                assert_eq!(
+                    cell(&batch, &format!("score_stats.{stat}"), row),
+                    bucket["score_stats"][stat]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_arrow_histogram_and_date_histogram() {
+        let index = get_test_index_2_segments(true).unwrap();
+        let (agg_res, json) = exec(
+            &index,
+            json!({
+                "histo": {
+                    "histogram": { "field": "score", "interval": 10.0 },
+                    "aggs": { "max_score": { "max": { "field": "score_f64" } } }
+                }
+            }),
+        );
+        let batch = agg_res.to_arrow().unwrap();
+        assert_eq!(data_type(&batch, "key"), DataType::Float64);
+        let buckets = json["histo"]["buckets"].as_array().unwrap();
+        assert_eq!(batch.num_rows(), buckets.len());
+        for (row, bucket) in buckets.iter().enumerate() {
+            assert_eq!(cell(&batch, "key", row), bucket["key"]);
+            assert_eq!(cell(&batch, "doc_count", row), bucket["doc_count"]);
+            assert_eq!(cell(&batch, "max_score", row), bucket["max_score"]["value"]);
+        }
+
+        let (agg_res, json) = exec(
+            &index,
+            json!({
+                "by_day": { "date_histogram": { "field": "date", "fixed_interval": "1d" } }
+            }),
+        );
+        let batch = agg_res.to_arrow().unwrap();
+        assert_eq!(
+            data_type(&batch, "key"),
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+        );
+        let buckets = json["by_day"]["buckets"].as_array().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        for (row, bucket) in buckets.iter().enumerate() {
+            assert_eq!(cell(&batch, "key", row), bucket["key"]);
+            assert_eq!(cell(&batch, "doc_count", row), bucket["doc_count"]);
+        }
+    }
+
+    #[test]
+    fn test_arrow_range() {
+        let index = get_test_index_2_segments(false).unwrap();
+        let (agg_res, json) = exec(
+            &index,
+            json!({
+                "ranges": {
+                    "range": {
+                        "field": "score",
+                        "ranges": [{ "to": 3.0 }, { "from": 3.0, "to": 7.0 }, { "from": 7.0 }],
+                        "keyed": true
+                    },
+                    "aggs": { "score_sum": { "sum": { "field": "score" } } }
+                }
+            }),
+        );
+        let batch = agg_res.to_arrow().unwrap();
+        assert_eq!(data_type(&batch, "key"), DataType::Utf8);
+        assert_eq!(data_type(&batch, "from"), DataType::Float64);
+        // Keyed buckets are ordered by their lower bound.
+        let keys = ["*-3", "3-7", "7-*"];
+        assert_eq!(batch.num_rows(), keys.len());
+        for (row, key) in keys.iter().enumerate() {
+            let bucket = &json["ranges"]["buckets"][key];
+            assert_eq!(cell(&batch, "key", row), bucket["key"]);
+            assert_eq!(cell(&batch, "doc_count", row), bucket["doc_count"]);
+            assert_eq!(cell(&batch, "from", row), bucket["from"]);
+            assert_eq!(cell(&batch, "to", row), bucket["to"]);
+            assert_eq!(cell(&batch, "score_sum", row), bucket["score_sum"]["value"]);
+        }
+        assert_eq!(cell(&batch, "from", 0), Value::Null);
+        assert_eq!(cell(&batch, "to", 2), Value::Null);
+    }
+
+    #[test]
+    fn test_arrow_one_level_of_nesting() {
+        let index = get_test_index_2_segments(false).unwrap();
+        let (agg_res, json) = exec(
+            &index,
+            json!({
+                "my_texts": {
+                    "terms": { "field": "text" },
+                    "aggs": {
+                        "histo": {
+                            "histogram": { "field": "score_i64", "interval": 10.0 },
+                            "aggs": { "min_score": { "min": { "field": "score" } } }
+                        },
+                        "avg_score": { "avg": { "field": "score" } }
+                    }
+                }
+            }),
+        );
+        let batch = agg_res.to_arrow().unwrap();
+        let column_names: Vec<String> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+        assert_eq!(
+            column_names,
+            [
+                "key",
+                "doc_count",
+                "avg_score",
+                "histo.key",
+                "histo.doc_count",
+                "histo.min_score"
+            ]
+        );
+        let mut row = 0;
+        for parent_bucket in json["my_texts"]["buckets"].as_array().unwrap() {
+            for sub_bucket in parent_bucket["histo"]["buckets"].as_array().unwrap() {
+                assert_eq!(cell(&batch, "key", row), parent_bucket["key"]);
+                assert_eq!(cell(&batch, "doc_count", row), parent_bucket["doc_count"]);
+                assert_eq!(
+                    cell(&batch, "avg_score", row),
+                    parent_bucket["avg_score"]["value"]
+                );
+                assert_eq!(cell(&batch, "histo.key", row), sub_bucket["key"]);
+                assert_eq!(
+                    cell(&batch, "histo.doc_count", row),
+                    sub_bucket["doc_count"]
+                );
+                assert_eq!(
+                    cell(&batch, "histo.min_score", row),
+                    sub_bucket["min_score"]["value"]
+                );
+                row += 1;
+            }
+        }
+        assert_eq!(batch.num_rows(), row);
+    }
+
+    #[test]
+    fn test_arrow_unsupported() {
+        let index = get_test_index_2_segments(false).unwrap();
+        let (agg_res, _) = exec(
+            &index,
+            json!({
+                "my_texts": {
+                    "terms": { "field": "text" },
+                    "aggs": {
+                        "histo": {
+                            "histogram": { "field": "score", "interval": 10.0 },
+                            "aggs": {
+                                "histo_f64": {
+                                    "histogram": { "field": "score_f64", "interval": 5.0 }
+                                }
+                            }
+                        }
+                    }
+                }
+            }),
+        );
+        assert!(matches!(
+            agg_res.to_arrow(),
+            Err(ArrowConversionError::UnsupportedNesting(_))
+        ));
+
+        let (agg_res, _) = exec(
+            &index,
+            json!({
+                "my_texts": {
+                    "terms": { "field": "text" },
+                    "aggs": { "percentiles": { "percentiles": { "field": "score" } } }
+                }
+            }),
+        );
+        assert!(matches!(
+            agg_res.to_arrow(),
+            Err(ArrowConversionError::UnsupportedAggregation(_))
+        ));
+
+        let (agg_res, _) = exec(&index, json!({ "avg": { "avg": { "field": "score" } } }));
+        assert!(matches!(
+            agg_res.to_arrow(),
+            Err(ArrowConversionError::UnsupportedAggregation(_))
+        ));
+    }
+}
@@ -0,0 +1,896 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use columnar::{Column, ColumnType, MonotonicallyMappableToU64, StrColumn};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{get_bucket_key_from_pos, get_bucket_pos_f64, DateHistogramAggregationReq};
+use crate::aggregation::agg_limits::MemoryConsumption;
+use crate::aggregation::agg_req_with_accessor::{
+    AggregationWithAccessor, AggregationsWithAccessor,
+};
+use crate::aggregation::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+    IntermediateCompositeBucketResult, IntermediateCompositeKey, IntermediateKey,
+    IntermediateTermBucketEntry,
+};
+use crate::aggregation::segment_agg_result::{
+    build_segment_agg_collector, SegmentAggregationCollector,
+};
+use crate::aggregation::{f64_from_fastfield_u64, AggregationError, Key};
+use crate::{DocId, TantivyError};
+
+/// Puts the documents into buckets keyed by the combination of the values of several sources,
+/// and returns the buckets one page at a time.
+///
+/// Each source computes a value from a field: the term itself for a `terms` source, and the key
+/// of the bucket of the value for a `histogram` or `date_histogram` source. A document is put in
+/// one bucket per combination of the values of its sources. Documents without a value for one of
+/// the sources are ignored.
+///
+/// The buckets are sorted by key, comparing the values of the sources in the order of the
+/// sources. A page contains the `size` lowest keys following the `after` key. To get the next
+/// page, pass the `after_key` of the result as the `after` key of the request. All the buckets
+/// were returned once a page is empty.
+///
+/// Unlike the [terms aggregation](super::TermsAggregation), the doc counts are exact: every segment
+/// returns its `size` lowest keys following `after`, which include all the buckets of the page.
+///
+/// ## Sources
+/// - `terms`: `{ "field": "shop" }`, on text, numeric or bool fast fields.
+/// - `histogram`: `{ "field": "price", "interval": 10, "offset": 0 }`, on numeric fast fields.
+/// - `date_histogram`: `{ "field": "date", "fixed_interval": "1d", "offset": "-4h" }`, on date fast
+///   fields. See [`DateHistogramAggregationReq`] for the intervals. The keys are in milliseconds.
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "by_shop_and_day": {
+///         "composite": {
+///             "size": 2,
+///             "sources": [
+///                 { "shop": { "terms": { "field": "shop" } } },
+///                 { "day": { "date_histogram": { "field": "date", "fixed_interval": "1d" } } }
+///             ],
+///             "after": { "shop": "a", "day": 1546300800000.0 }
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "by_shop_and_day": {
+///             "after_key": { "shop": "b", "day": 1546300800000.0 },
+///             "buckets": [
+///                 { "key": { "shop": "a", "day": 1546387200000.0 }, "doc_count": 3 },
+///                 { "key": { "shop": "b", "day": 1546300800000.0 }, "doc_count": 1 }
+///             ]
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompositeAggregation {
+    /// The sources of the keys, each one in a map with its name as single key.
+    pub sources: Vec<HashMap<String, CompositeSource>>,
+    /// The number of buckets per page. Defaults to 10.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size: Option<u32>,
+    /// Only return the buckets following this key, with a value for each source.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub after: Option<HashMap<String, Key>>,
+}
+
+/// A source of the keys of a [`CompositeAggregation`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CompositeSource {
+    /// The values of a field.
+    #[serde(rename = "terms")]
+    Terms(CompositeTermsSource),
+    /// The histogram buckets of the values of a numeric field.
+    #[serde(rename = "histogram")]
+    Histogram(CompositeHistogramSource),
+    /// The date histogram buckets of the values of a date field.
+    #[serde(rename = "date_histogram")]
+    DateHistogram(CompositeDateHistogramSource),
+}
+
+/// A `terms` source of a [`CompositeAggregation`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompositeTermsSource {
+    /// The field to read the values from.
+    pub field: String,
+}
+
+/// A `histogram` source of a [`CompositeAggregation`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompositeHistogramSource {
+    /// The field to read the values from.
+    pub field: String,
+    /// The width of the buckets. See
+    /// [`HistogramAggregation::interval`](super::HistogramAggregation::interval).
+    pub interval: f64,
+    /// Shifts the buckets. See
+    /// [`HistogramAggregation::offset`](super::HistogramAggregation::offset).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub offset: Option<f64>,
+}
+
+/// A `date_histogram` source of a [`CompositeAggregation`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompositeDateHistogramSource {
+    /// The field to read the values from.
+    pub field: String,
+    /// The width of the buckets, e.g. `1d`. See
+    /// [`DateHistogramAggregationReq::fixed_interval`].
+    pub fixed_interval: String,
+    /// Shifts the buckets, e.g. `-4h`. See [`DateHistogramAggregationReq::offset`].
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub offset: Option<String>,
+}
+
+impl CompositeSource {
+    pub(crate) fn field(&self) -> &str {
+        match self {
+            CompositeSource::Terms(terms) => &terms.field,
+            CompositeSource::Histogram(histogram) => &histogram.field,
+            CompositeSource::DateHistogram(histogram) => &histogram.field,
+        }
+    }
+
+    /// Returns the column types the source can read.
+    pub(crate) fn column_types(&self) -> &'static [ColumnType] {
+        match self {
+            CompositeSource::Terms(_) => &[
+                ColumnType::Str,
+                ColumnType::U64,
+                ColumnType::I64,
+                ColumnType::F64,
+                ColumnType::Bool,
+            ],
+            CompositeSource::Histogram(_) => &[ColumnType::F64, ColumnType::U64, ColumnType::I64],
+            CompositeSource::DateHistogram(_) => &[ColumnType::DateTime],
+        }
+    }
+}
+
+const DEFAULT_COMPOSITE_SIZE: u32 = 10;
+
+impl CompositeAggregation {
+    pub(crate) fn size(&self) -> u32 {
+        self.size.unwrap_or(DEFAULT_COMPOSITE_SIZE)
+    }
+
+    pub(crate) fn field_names(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .flat_map(|source| source.values())
+            .map(CompositeSource::field)
+            .collect()
+    }
+
+    /// Returns the sources with their names, in order.
+    pub(crate) fn named_sources(&self) -> crate::Result<Vec<(&str, &CompositeSource)>> {
+        if self.sources.is_empty() {
+            return Err(AggregationError::invalid_parameter(
+                "sources",
+                "composite aggregation requires at least one source",
+            )
+            .into());
+        }
+        let mut names = HashSet::new();
+        let mut named_sources = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            let mut entries = source.iter();
+            let (Some((name, source)), None) = (entries.next(), entries.next()) else {
+                return Err(AggregationError::invalid_parameter(
+                    "sources",
+                    format!(
+                        "composite aggregation sources must have a single name, got {:?}",
+                        source.keys().collect::<Vec<_>>()
+                    ),
+                )
+                .into());
+            };
+            if !names.insert(name.as_str()) {
+                return Err(AggregationError::invalid_parameter(
+                    "sources",
+                    format!("composite aggregation has several sources named {name:?}"),
+                )
+                .into());
+            }
+            named_sources.push((name.as_str(), source));
+        }
+        Ok(named_sources)
+    }
+
+    pub(crate) fn source_names(&self) -> crate::Result<Vec<&str>> {
+        Ok(self
+            .named_sources()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Returns the `after` key, with the values in the order of the sources.
+    fn after_key(&self) -> crate::Result<Option<IntermediateCompositeKey>> {
+        let Some(after) = self.after.as_ref() else {
+            return Ok(None);
+        };
+        let source_names = self.source_names()?;
+        if let Some(name) = after
+            .keys()
+            .find(|name| !source_names.contains(&name.as_str()))
+        {
+            return Err(AggregationError::invalid_parameter(
+                "after",
+                format!(
+                    "composite aggregation `after` key has a value for {name:?}, which is not a \
+                     source"
+                ),
+            )
+            .into());
+        }
+        let values = source_names
+            .iter()
+            .map(|name| {
+                after
+                    .get(*name)
+                    .cloned()
+                    .map(IntermediateKey::from)
+                    .ok_or_else(|| {
+                        TantivyError::from(AggregationError::invalid_parameter(
+                            "after",
+                            format!(
+                                "composite aggregation `after` key is missing a value for source \
+                                 {name:?}"
+                            ),
+                        ))
+                    })
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(Some(IntermediateCompositeKey(values)))
+    }
+}
+
+/// How a source turns the values of its column into the values of the keys.
+#[derive(Clone, Debug)]
+enum SourceBucketing {
+    Terms,
+    /// `interval` and `offset` are in milliseconds for the dates.
+    Histogram {
+        interval: f64,
+        offset: f64,
+        is_date: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct CompositeSourceInternal {
+    bucketing: SourceBucketing,
+    column_type: ColumnType,
+}
+
+impl CompositeSourceInternal {
+    fn from_req(source: &CompositeSource, column_type: ColumnType) -> crate::Result<Self> {
+        let bucketing = match source {
+            CompositeSource::Terms(_) => SourceBucketing::Terms,
+            CompositeSource::Histogram(histogram) => {
+                if !(histogram.interval.is_finite() && histogram.interval > 0.0) {
+                    return Err(AggregationError::invalid_parameter(
+                        "interval",
+                        format!(
+                            "composite histogram source requires a positive interval, got {}",
+                            histogram.interval
+                        ),
+                    )
+                    .into());
+                }
+                SourceBucketing::Histogram {
+                    interval: histogram.interval,
+                    offset: histogram.offset.unwrap_or(0.0),
+                    is_date: false,
+                }
+            }
+            CompositeSource::DateHistogram(histogram) => {
+                let histogram = DateHistogramAggregationReq {
+                    field: histogram.field.clone(),
+                    fixed_interval: Some(histogram.fixed_interval.clone()),
+                    offset: histogram.offset.clone(),
+                    ..Default::default()
+                }
+                .to_histogram_req()?;
+                SourceBucketing::Histogram {
+                    interval: histogram.interval,
+                    offset: histogram.offset.unwrap_or(0.0),
+                    is_date: true,
+                }
+            }
+        };
+        Ok(CompositeSourceInternal {
+            bucketing,
+            column_type,
+        })
+    }
+
+    /// Maps a value of the column to the value of the key in the segment.
+    ///
+    /// The mapping preserves the order of the keys: a term ordinal for the text columns, the
+    /// column value for the other columns, and the monotonic mapping of the bucket position for
+    /// the histograms.
+    #[inline]
+    fn segment_value(&self, val: u64) -> u64 {
+        match self.bucketing {
+            SourceBucketing::Terms => val,
+            SourceBucketing::Histogram {
+                interval,
+                offset,
+                is_date,
+            } => {
+                let mut val = f64_from_fastfield_u64(val, &self.column_type);
+                if is_date {
+                    // Dates are stored in nanoseconds.
+                    val /= 1_000_000.0;
+                }
+                (get_bucket_pos_f64(val, interval, offset) as i64).to_u64()
+            }
+        }
+    }
+
+    fn key_value(
+        &self,
+        segment_value: u64,
+        str_column: Option<&StrColumn>,
+        buffer: &mut Vec<u8>,
+    ) -> crate::Result<IntermediateKey> {
+        let key = match (&self.bucketing, self.column_type) {
+            (SourceBucketing::Terms, ColumnType::Str) => {
+                let str_column = str_column.ok_or_else(|| {
+                    TantivyError::InternalError(
+                        "composite terms source is missing the dictionary of its column"
+                            .to_string(),
+                    )
+                })?;
+                buffer.clear();
+                str_column.dictionary().ord_to_term(segment_value, buffer)?;
+                IntermediateKey::Str(String::from_utf8(buffer.clone()).map_err(|utf8_err| {
+                    TantivyError::InternalError(format!("term is not valid utf8: {utf8_err}"))
+                })?)
+            }
+            (SourceBucketing::Terms, ColumnType::I64) => {
+                IntermediateKey::I64(i64::from_u64(segment_value))
+            }
+            (SourceBucketing::Terms, ColumnType::F64) => {
+                IntermediateKey::F64(f64::from_u64(segment_value))
+            }
+            (SourceBucketing::Terms, ColumnType::Bool) => {
+                IntermediateKey::Bool(bool::from_u64(segment_value))
+            }
+            (SourceBucketing::Terms, _) => IntermediateKey::U64(segment_value),
+            (
+                SourceBucketing::Histogram {
+                    interval, offset, ..
+                },
+                _,
+            ) => {
+                let bucket_pos = i64::from_u64(segment_value) as f64;
+                IntermediateKey::F64(get_bucket_key_from_pos(bucket_pos, *interval, *offset))
+            }
+        };
+        Ok(key)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SegmentCompositeBucket {
+    doc_count: u32,
+    sub_aggs: Option<Box<dyn SegmentAggregationCollector>>,
+}
+
+/// The collector maps the values of the sources to order preserving `u64`s, and puts the
+/// documents into buckets keyed by the tuples of these values.
+///
+/// The keys are only turned into values, e.g. terms, for the buckets returned by the segment.
+#[derive(Clone, Debug)]
+pub(crate) struct SegmentCompositeCollector {
+    buckets: FxHashMap<Box<[u64]>, SegmentCompositeBucket>,
+    sources: Vec<CompositeSourceInternal>,
+    /// The distinct values of each source for the current document.
+    doc_values: Vec<Vec<u64>>,
+    /// The position in `doc_values` of each source, when enumerating the combinations.
+    positions: Vec<usize>,
+    key: Vec<u64>,
+    size: u32,
+    after: Option<IntermediateCompositeKey>,
+    blueprint: Option<Box<dyn SegmentAggregationCollector>>,
+    accessor_idx: usize,
+}
+
+impl SegmentAggregationCollector for SegmentCompositeCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+
+        let bucket = self.into_intermediate_bucket_result(agg_with_accessor)?;
+        results.push(name, IntermediateAggregationResult::Bucket(bucket))?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn collect(
+        &mut self,
+        doc: crate::DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    fn collect_block(
+        &mut self,
+        docs: &[crate::DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        let bucket_agg_accessor = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+        let mem_pre = self.buckets.memory_consumption();
+
+        for &doc in docs {
+            if !self.fetch_doc_values(doc, &bucket_agg_accessor.accessors) {
+                continue;
+            }
+            // Enumerates the combinations of the values of the sources.
+            self.positions.iter_mut().for_each(|position| *position = 0);
+            loop {
+                self.key.clear();
+                self.key.extend(
+                    self.doc_values
+                        .iter()
+                        .zip(&self.positions)
+                        .map(|(values, position)| values[*position]),
+                );
+                let bucket = match self.buckets.get_mut(self.key.as_slice()) {
+                    Some(bucket) => bucket,
+                    None => self
+                        .buckets
+                        .entry(self.key.clone().into_boxed_slice())
+                        .or_insert_with(|| SegmentCompositeBucket {
+                            doc_count: 0,
+                            sub_aggs: self.blueprint.clone(),
+                        }),
+                };
+                bucket.doc_count += 1;
+                if let Some(sub_aggs) = bucket.sub_aggs.as_mut() {
+                    sub_aggs.collect(doc, &mut bucket_agg_accessor.sub_aggregation)?;
+                }
+                if !self.next_combination() {
+                    break;
+                }
+            }
+        }
+
+        let mem_delta = self.buckets.memory_consumption() - mem_pre;
+        if mem_delta > 0 {
+            bucket_agg_accessor
+                .limits
+                .add_memory_consumed(mem_delta as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        let sub_aggregation_accessor =
+            &mut agg_with_accessor.aggs.values[self.accessor_idx].sub_aggregation;
+        for bucket in self.buckets.values_mut() {
+            if let Some(sub_aggs) = bucket.sub_aggs.as_mut() {
+                sub_aggs.flush(sub_aggregation_accessor)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SegmentCompositeCollector {
+    pub(crate) fn from_req_and_validate(
+        req: &CompositeAggregation,
+        sub_aggregations: &mut AggregationsWithAccessor,
+        accessors: &[(Column<u64>, ColumnType)],
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        let named_sources = req.named_sources()?;
+        assert_eq!(
+            named_sources.len(),
+            accessors.len(),
+            "composite aggregation expects one column per source"
+        );
+        let sources = named_sources
+            .iter()
+            .zip(accessors)
+            .map(|((_, source), (_, column_type))| {
+                CompositeSourceInternal::from_req(source, *column_type)
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        if req.size() == 0 {
+            return Err(AggregationError::invalid_parameter(
+                "size",
+                "composite aggregation requires a size of at least 1",
+            )
+            .into());
+        }
+        let after = req.after_key()?;
+        let blueprint = if !sub_aggregations.is_empty() {
+            Some(build_segment_agg_collector(sub_aggregations)?)
+        } else {
+            None
+        };
+
+        Ok(SegmentCompositeCollector {
+            buckets: Default::default(),
+            doc_values: vec![Vec::new(); sources.len()],
+            positions: vec![0; sources.len()],
+            key: Vec::with_capacity(sources.len()),
+            sources,
+            size: req.size(),
+            after,
+            blueprint,
+            accessor_idx,
+        })
+    }
+
+    /// Loads the distinct values of each source for `doc`.
+    ///
+    /// Returns false if a source has no value for `doc`.
+    #[inline]
+    fn fetch_doc_values(&mut self, doc: DocId, accessors: &[(Column<u64>, ColumnType)]) -> bool {
+        for ((source, values), (column, _)) in
+            self.sources.iter().zip(&mut self.doc_values).zip(accessors)
+        {
+            values.clear();
+            values.extend(
+                column
+                    .values_for_doc(doc)
+                    .map(|val| source.segment_value(val)),
+            );
+            if values.is_empty() {
+                return false;
+            }
+            values.sort_unstable();
+            values.dedup();
+        }
+        true
+    }
+
+    /// Moves `positions` to the next combination of values. Returns false once all the
+    /// combinations were enumerated.
+    #[inline]
+    fn next_combination(&mut self) -> bool {
+        for (position, values) in self.positions.iter_mut().zip(&self.doc_values).rev() {
+            *position += 1;
+            if *position < values.len() {
+                return true;
+            }
+            *position = 0;
+        }
+        false
+    }
+
+    fn to_key(
+        &self,
+        segment_key: &[u64],
+        str_columns: &[Option<StrColumn>],
+        buffer: &mut Vec<u8>,
+    ) -> crate::Result<IntermediateCompositeKey> {
+        let values = self
+            .sources
+            .iter()
+            .zip(segment_key)
+            .zip(str_columns)
+            .map(|((source, segment_value), str_column)| {
+                source.key_value(*segment_value, str_column.as_ref(), buffer)
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(IntermediateCompositeKey(values))
+    }
+
+    pub(crate) fn into_intermediate_bucket_result(
+        mut self,
+        agg_with_accessor: &AggregationWithAccessor,
+    ) -> crate::Result<IntermediateBucketResult> {
+        let str_columns = &agg_with_accessor.composite_str_columns;
+        let mut buckets: Vec<(Box<[u64]>, SegmentCompositeBucket)> =
+            std::mem::take(&mut self.buckets).into_iter().collect();
+        // The order of the segment keys is the order of the keys.
+        buckets.sort_unstable_by(|(left, _), (right, _)| left.cmp(right));
+
+        let mut buffer = Vec::new();
+        let start = match self.after.as_ref() {
+            Some(after) => {
+                let mut error = None;
+                let start = buckets.partition_point(|(segment_key, _)| {
+                    match self.to_key(segment_key, str_columns, &mut buffer) {
+                        Ok(key) => key <= *after,
+                        Err(err) => {
+                            error.get_or_insert(err);
+                            false
+                        }
+                    }
+                });
+                if let Some(err) = error {
+                    return Err(err);
+                }
+                start
+            }
+            None => 0,
+        };
+
+        let mut entries = BTreeMap::new();
+        for (segment_key, bucket) in buckets.into_iter().skip(start).take(self.size as usize) {
+            let key = self.to_key(&segment_key, str_columns, &mut buffer)?;
+            let mut sub_aggregation = IntermediateAggregationResults::default();
+            if let Some(sub_aggs) = bucket.sub_aggs {
+                sub_aggs.add_intermediate_aggregation_result(
+                    &agg_with_accessor.sub_aggregation,
+                    &mut sub_aggregation,
+                )?;
+            }
+            entries.insert(
+                key,
+                IntermediateTermBucketEntry {
+                    doc_count: bucket.doc_count,
+                    sub_aggregation,
+                },
+            );
+        }
+
+        Ok(IntermediateBucketResult::Composite {
+            buckets: IntermediateCompositeBucketResult {
+                entries,
+                size: self.size,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::exec_request_with_query;
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{DateTime, Index, IndexWriter, TantivyDocument};
+
+    const SHOPS: [&str; 5] = ["a", "b", "c", "d", "e"];
+    const DAY_MS: i64 = 86_400_000;
+
+    /// A `(shop, bucket)` key, with its doc count and price sum.
+    type Group = ((String, i64), (u64, f64));
+
+    struct TestDoc {
+        shops: Vec<&'static str>,
+        price: Option<u64>,
+        date_ms: i64,
+    }
+
+    fn test_docs() -> Vec<TestDoc> {
+        (0..120u64)
+            .map(|i| {
+                let mut shops = vec![SHOPS[(i * 7 % 5) as usize]];
+                if i % 9 == 0 {
+                    // Multivalued, with a duplicate value.
+                    shops.push(SHOPS[(i % 5) as usize]);
+                    shops.push(SHOPS[(i % 5) as usize]);
+                }
+                TestDoc {
+                    shops,
+                    price: (i % 11 != 0).then_some(i * 13 % 70),
+                    date_ms: 1_546_300_800_000 + (i as i64 * 37 % 5) * DAY_MS + i as i64 * 1000,
+                }
+            })
+            .collect()
+    }
+
+    fn create_index(docs: &[TestDoc]) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let shop = schema_builder.add_text_field("shop", STRING | FAST);
+        let price = schema_builder.add_u64_field("price", FAST);
+        let date = schema_builder.add_date_field("date", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment_docs in docs.chunks(50) {
+            for test_doc in segment_docs {
+                let mut doc = TantivyDocument::default();
+                for shop_value in &test_doc.shops {
+                    doc.add_text(shop, shop_value);
+                }
+                if let Some(price_value) = test_doc.price {
+                    doc.add_u64(price, price_value);
+                }
+                doc.add_date(date, DateTime::from_timestamp_millis(test_doc.date_ms));
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    /// Returns the groups of each page.
+    fn collect_pages(
+        index: &Index,
+        second_source: Value,
+        size: u32,
+    ) -> crate::Result<Vec<Vec<Group>>> {
+        let mut pages = Vec::new();
+        let mut after: Option<Value> = None;
+        loop {
+            let mut composite = json!({
+                "size": size,
+                "sources": [
+                    { "shop": { "terms": { "field": "shop" } } },
+                    { "bucket": second_source.clone() }
+                ]
+            });
+            if let Some(after) = after.take() {
+                composite["after"] = after;
+            }
+            let agg_req: Aggregations = serde_json::from_value(json!({
+                "by": {
+                    "composite": composite,
+                    "aggs": { "price_sum": { "sum": { "field": "price" } } }
+                }
+            }))
+            .unwrap();
+            let res = exec_request_with_query(agg_req, index, None)?;
+            let buckets = res["by"]["buckets"].as_array().unwrap();
+            if buckets.is_empty() {
+                assert!(res["by"].get("after_key").is_none());
+                return Ok(pages);
+            }
+            assert!(buckets.len() <= size as usize);
+            let page = buckets
+                .iter()
+                .map(|bucket| {
+                    let key = (
+                        bucket["key"]["shop"].as_str().unwrap().to_string(),
+                        bucket["key"]["bucket"].as_f64().unwrap() as i64,
+                    );
+                    let doc_count = bucket["doc_count"].as_u64().unwrap();
+                    let price_sum = bucket["price_sum"]["value"].as_f64().unwrap_or(0.0);
+                    (key, (doc_count, price_sum))
+                })
+                .collect();
+            pages.push(page);
+            assert_eq!(res["by"]["after_key"], buckets.last().unwrap()["key"]);
+            after = Some(res["by"]["after_key"].clone());
+        }
+    }
+
+    /// Groups the documents by `(shop, bucket)`.
+    fn group_by(docs: &[TestDoc], bucket: impl Fn(&TestDoc) -> Option<i64>) -> Vec<Group> {
+        let mut groups: BTreeMap<(String, i64), (u64, f64)> = BTreeMap::new();
+        for doc in docs {
+            let Some(bucket) = bucket(doc) else {
+                continue;
+            };
+            let mut shops = doc.shops.clone();
+            shops.sort_unstable();
+            shops.dedup();
+            for shop in shops {
+                let group = groups.entry((shop.to_string(), bucket)).or_default();
+                group.0 += 1;
+                group.1 += doc.price.unwrap_or(0) as f64;
+            }
+        }
+        groups.into_iter().collect()
+    }
+
+    #[test]
+    fn test_composite_paging_matches_group_by() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = create_index(&docs)?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 3);
+        let expected = group_by(&docs, |doc| doc.price.map(|price| (price / 10 * 10) as i64));
+
+        for size in [1, 3, 7, 100] {
+            let pages = collect_pages(
+                &index,
+                json!({ "histogram": { "field": "price", "interval": 10 } }),
+                size,
+            )?;
+            assert_eq!(pages.len(), expected.len().div_ceil(size as usize));
+            let buckets: Vec<_> = pages.into_iter().flatten().collect();
+            assert_eq!(buckets, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_date_histogram_source() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = create_index(&docs)?;
+        let expected = group_by(&docs, |doc| Some(doc.date_ms.div_euclid(DAY_MS) * DAY_MS));
+
+        let pages = collect_pages(
+            &index,
+            json!({ "date_histogram": { "field": "date", "fixed_interval": "1d" } }),
+            4,
+        )?;
+        let buckets: Vec<_> = pages.into_iter().flatten().collect();
+        assert_eq!(buckets, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_after_key_between_buckets() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = create_index(&docs)?;
+        // The `after` key does not need to be the key of a bucket.
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "by": {
+                "composite": {
+                    "size": 2,
+                    "sources": [
+                        { "shop": { "terms": { "field": "shop" } } },
+                        { "bucket": { "histogram": { "field": "price", "interval": 10 } } }
+                    ],
+                    "after": { "shop": "b", "bucket": 65 }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        let keys: Vec<&Value> = res["by"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| &bucket["key"])
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                &json!({ "shop": "c", "bucket": 0.0 }),
+                &json!({ "shop": "c", "bucket": 10.0 })
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_invalid_requests() -> crate::Result<()> {
+        let index = create_index(&test_docs())?;
+        let terms = json!({ "terms": { "field": "shop" } });
+        let shop_source = json!({ "shop": terms });
+        let zero_interval = json!({ "histogram": { "field": "price", "interval": 0 } });
+        let month_interval =
+            json!({ "date_histogram": { "field": "date", "fixed_interval": "1M" } });
+        for composite in [
+            json!({ "sources": [] }),
+            json!({ "sources": [shop_source, shop_source] }),
+            json!({ "sources": [{ "a": terms, "b": terms }] }),
+            json!({ "sources": [shop_source], "size": 0 }),
+            json!({ "sources": [shop_source], "after": { "other": "a" } }),
+            json!({ "sources": [shop_source, { "price": zero_interval }] }),
+            json!({ "sources": [{ "day": month_interval }] }),
+        ] {
+            let agg_req: Aggregations =
+                serde_json::from_value(json!({ "by": { "composite": composite } })).unwrap();
+            assert!(
+                exec_request_with_query(agg_req, &index, None).is_err(),
+                "{composite}"
+            );
+        }
+        Ok(())
+    }
+}
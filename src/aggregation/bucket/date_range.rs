@@ -0,0 +1,502 @@
+use std::collections::HashSet;
+
+use columnar::MonotonicallyMappableToU64;
+use common::{DateTime, DateTimePrecision};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::range::{range_to_string, InternalRangeAggregationRange};
+use crate::aggregation::agg_req::{AggregationVariants, Aggregations};
+use crate::aggregation::date_math::{parse_date_math, parse_timestamp};
+use crate::aggregation::*;
+
+/// Provide user-defined date ranges to aggregate on, with bounds given as RFC3339 timestamps or
+/// date math expressions.
+///
+/// Unlike the [`RangeAggregation`], only the requested ranges are returned as buckets, and the
+/// ranges may overlap or leave gaps. Each range includes its `from` date and excludes its `to`
+/// date, a missing bound makes the range open ended.
+///
+/// A date math expression starts with `now`, or a RFC3339 timestamp followed by `||`, and
+/// continues with operations applied from left to right: `+<n><unit>` and `-<n><unit>` add and
+/// subtract `n` units, `/<unit>` rounds down to the start of the unit. The units are `y`, `M`,
+/// `w`, `d`, `h`, `m` and `s`. E.g. `now-7d/d` is midnight seven days ago. The dates are in UTC.
+///
+/// The bounds are truncated to the precision of the fast field, like the values of the documents
+/// are when they are indexed. See
+/// [`DateOptions::set_precision`](crate::schema::DateOptions::set_precision).
+///
+/// Result type is [`BucketResult`](crate::aggregation::agg_result::BucketResult) with
+/// [`RangeBucketEntry`](crate::aggregation::agg_result::RangeBucketEntry) on the
+/// `AggregationCollector`. The `from` and `to` of the buckets are in milliseconds, like the keys
+/// of the `date_histogram`, and the `from_as_string` and `to_as_string` are RFC3339 timestamps.
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "recency": {
+///         "date_range": {
+///             "field": "created_at",
+///             "ranges": [
+///                 { "key": "last_24h", "from": "now-24h" },
+///                 { "key": "last_7d", "from": "now-7d/d" },
+///                 { "key": "older", "to": "now-7d/d" }
+///             ],
+///             "keyed": true
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DateRangeAggregation {
+    /// The field to aggregate on.
+    pub field: String,
+    /// The ranges of the buckets.
+    pub ranges: Vec<DateRangeAggregationRange>,
+    /// Whether to return the buckets as a hash map
+    #[serde(default)]
+    pub keyed: bool,
+    /// The time `now` refers to in the date math expressions of the ranges, as a RFC3339
+    /// timestamp.
+    ///
+    /// Defaults to the time the aggregation collector is created, so that all the segments
+    /// resolve the ranges alike.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub now: Option<String>,
+    /// The precision of the fast field in the segment being collected.
+    #[serde(skip)]
+    pub(crate) precision: Option<DateTimePrecision>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// The range for one date range bucket.
+pub struct DateRangeAggregationRange {
+    /// Custom key for the range bucket
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub key: Option<String>,
+    /// The from date, which is inclusive in the range, as a RFC3339 timestamp or date math
+    /// expression.
+    /// `None` equals to an open ended interval.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub from: Option<String>,
+    /// The to date, which is not inclusive in the range, as a RFC3339 timestamp or date math
+    /// expression.
+    /// `None` equals to an open ended interval.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub to: Option<String>,
+}
+
+impl DateRangeAggregation {
+    /// Returns the ranges of fast field values of the buckets, sorted by their start.
+    ///
+    /// The ranges without a custom key get a key describing their dates.
+    pub(crate) fn to_internal_ranges(&self) -> crate::Result<Vec<InternalRangeAggregationRange>> {
+        let now = match self.now.as_ref() {
+            Some(now) => parse_timestamp(now).map_err(AggregationError::from)?,
+            None => DateTime::from_utc(OffsetDateTime::now_utc()),
+        };
+        let precision = self.precision.unwrap_or(DateTimePrecision::Nanoseconds);
+        let resolve_bound = |bound: Option<&String>| -> crate::Result<Option<u64>> {
+            let Some(bound) = bound else {
+                return Ok(None);
+            };
+            let date = parse_date_math(bound, now).map_err(AggregationError::from)?;
+            Ok(Some(
+                date.truncate(precision).into_timestamp_nanos().to_u64(),
+            ))
+        };
+        let mut ranges = Vec::with_capacity(self.ranges.len());
+        let mut keys = HashSet::new();
+        for range in &self.ranges {
+            let start = resolve_bound(range.from.as_ref())?.unwrap_or(u64::MIN);
+            let end = resolve_bound(range.to.as_ref())?.unwrap_or(u64::MAX);
+            if start > end {
+                return Err(AggregationError::invalid_parameter(
+                    "ranges",
+                    format!("The from date of the date range {range:?} is after its to date"),
+                )
+                .into());
+            }
+            let key = match range.key.as_ref() {
+                Some(key) => key.clone(),
+                None => range_to_string(&(start..end), &ColumnType::DateTime)?,
+            };
+            if !keys.insert(key.clone()) {
+                return Err(AggregationError::invalid_parameter(
+                    "ranges",
+                    format!("Duplicate key {key:?} in the date ranges"),
+                )
+                .into());
+            }
+            ranges.push(InternalRangeAggregationRange {
+                key: Some(key),
+                range: start..end,
+            });
+        }
+        ranges.sort_by_key(|range| (range.range.start, range.range.end));
+        Ok(ranges)
+    }
+}
+
+/// Sets the `now` of the `date_range` aggregations of the tree that do not have one to the
+/// current time, so that their ranges are the same in all the segments.
+pub(crate) fn pin_date_range_now(aggs: &mut Aggregations) {
+    let mut now = None;
+    pin_date_range_now_rec(aggs, &mut now);
+}
+
+fn pin_date_range_now_rec(aggs: &mut Aggregations, now: &mut Option<String>) {
+    for agg in aggs.values_mut() {
+        if let AggregationVariants::DateRange(date_range) = &mut agg.agg {
+            if date_range.now.is_none() {
+                let now = now.get_or_insert_with(|| {
+                    let now = DateTime::from_utc(OffsetDateTime::now_utc());
+                    format_date(now.into_timestamp_nanos()).expect("the current time is valid")
+                });
+                date_range.now = Some(now.clone());
+            }
+        }
+        pin_date_range_now_rec(&mut agg.sub_aggregation, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::aggregation::tests::exec_request;
+    use crate::aggregation::AggregationCollector;
+    use crate::query::AllQuery;
+    use crate::schema::{DateOptions, Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, TantivyError};
+
+    const NOW: &str = "2024-03-13T15:42:17Z";
+
+    /// Index with the documents `(timestamp, category)`, one segment per slice.
+    fn get_test_index(
+        segments: &[&[(&str, &str)]],
+        precision: DateTimePrecision,
+    ) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let date_field =
+            schema_builder.add_date_field("date", DateOptions::from(FAST).set_precision(precision));
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for docs in segments {
+            for (timestamp, category_value) in docs.iter() {
+                index_writer.add_document(doc!(
+                    date_field => parse_timestamp(timestamp).unwrap(),
+                    category => *category_value,
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn bucket_counts(res: &Value) -> Vec<(String, u64)> {
+        res["recency"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| {
+                (
+                    bucket["key"].as_str().unwrap().to_string(),
+                    bucket["doc_count"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn date_range_boundaries_test() -> crate::Result<()> {
+        let index = get_test_index(
+            &[
+                &[("2024-03-10T23:59:59Z", "a"), ("2024-03-11T00:00:00Z", "a")],
+                &[("2024-03-11T23:59:59Z", "b"), ("2024-03-12T00:00:00Z", "b")],
+            ],
+            DateTimePrecision::Seconds,
+        )?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "recency": {
+                "date_range": {
+                    "field": "date",
+                    "ranges": [
+                        { "to": "2024-03-11T00:00:00Z" },
+                        { "from": "2024-03-11T00:00:00Z", "to": "2024-03-12T00:00:00Z" },
+                        { "from": "2024-03-12T00:00:00Z" },
+                    ]
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(
+            bucket_counts(&res),
+            vec![
+                ("*-2024-03-11T00:00:00Z".to_string(), 1),
+                ("2024-03-11T00:00:00Z-2024-03-12T00:00:00Z".to_string(), 2),
+                ("2024-03-12T00:00:00Z-*".to_string(), 1),
+            ]
+        );
+        let bucket = &res["recency"]["buckets"][1];
+        assert_eq!(bucket["from"], 1710115200000.0);
+        assert_eq!(bucket["to"], 1710201600000.0);
+        assert_eq!(bucket["from_as_string"], "2024-03-11T00:00:00Z");
+        assert_eq!(bucket["to_as_string"], "2024-03-12T00:00:00Z");
+        assert_eq!(res["recency"]["buckets"][0].get("from"), None);
+        assert_eq!(res["recency"]["buckets"][2].get("to"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn date_range_date_math_test() -> crate::Result<()> {
+        let index = get_test_index(
+            &[
+                &[
+                    ("2024-03-13T15:00:00Z", "a"),
+                    ("2024-03-12T16:00:00Z", "a"),
+                    ("2024-03-12T15:00:00Z", "a"),
+                ],
+                &[
+                    ("2024-03-06T00:00:00Z", "b"),
+                    ("2024-03-05T23:59:59Z", "b"),
+                    ("2024-01-01T00:00:00Z", "b"),
+                ],
+            ],
+            DateTimePrecision::Seconds,
+        )?;
+        // The ranges overlap, and the documents of the last 24 hours are in both of the first
+        // two buckets.
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "recency": {
+                "date_range": {
+                    "field": "date",
+                    "now": NOW,
+                    "ranges": [
+                        { "key": "last_24h", "from": "now-24h" },
+                        { "key": "last_7d", "from": "now-7d/d" },
+                        { "key": "older", "to": "now-7d/d" },
+                        { "key": "this_year", "from": "now/y", "to": "now/y+1y" },
+                    ]
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(
+            bucket_counts(&res),
+            vec![
+                ("older".to_string(), 2),
+                ("this_year".to_string(), 6),
+                ("last_7d".to_string(), 4),
+                ("last_24h".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            res["recency"]["buckets"][2]["from_as_string"],
+            "2024-03-06T00:00:00Z"
+        );
+        assert_eq!(
+            res["recency"]["buckets"][3]["from_as_string"],
+            "2024-03-12T15:42:17Z"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn date_range_now_is_pinned_test() -> crate::Result<()> {
+        let mut agg_req: Aggregations = serde_json::from_value(json!({
+            "recency": {
+                "date_range": {
+                    "field": "date",
+                    "ranges": [{ "from": "now-1d" }]
+                },
+                "aggs": {
+                    "sub_recency": {
+                        "date_range": {
+                            "field": "date",
+                            "ranges": [{ "from": "now-1h" }]
+                        }
+                    },
+                    "fixed": {
+                        "date_range": {
+                            "field": "date",
+                            "now": NOW,
+                            "ranges": [{ "from": "now-1h" }]
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        pin_date_range_now(&mut agg_req);
+        let now_of = |agg: &crate::aggregation::agg_req::Aggregation| match &agg.agg {
+            AggregationVariants::DateRange(date_range) => date_range.now.clone().unwrap(),
+            _ => panic!("expected a date range aggregation"),
+        };
+        let now = now_of(&agg_req["recency"]);
+        assert!(parse_timestamp(&now).is_ok());
+        let sub_aggs = &agg_req["recency"].sub_aggregation;
+        assert_eq!(now_of(&sub_aggs["sub_recency"]), now);
+        assert_eq!(now_of(&sub_aggs["fixed"]), NOW);
+        Ok(())
+    }
+
+    #[test]
+    fn date_range_keyed_test() -> crate::Result<()> {
+        let index = get_test_index(
+            &[&[
+                ("2024-03-13T15:00:00Z", "a"),
+                ("2024-03-13T14:00:00Z", "b"),
+                ("2024-03-01T00:00:00Z", "a"),
+            ]],
+            DateTimePrecision::Seconds,
+        )?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "recency": {
+                "date_range": {
+                    "field": "date",
+                    "now": NOW,
+                    "keyed": true,
+                    "ranges": [
+                        { "key": "last_24h", "from": "now-24h" },
+                        { "from": "2024-02-01T00:00:00Z", "to": "now-7d" },
+                    ]
+                },
+                "aggs": {
+                    "categories": { "terms": { "field": "category" } }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(
+            res,
+            json!({
+                "recency": {
+                    "buckets": {
+                        "2024-02-01T00:00:00Z-2024-03-06T15:42:17Z": {
+                            "key": "2024-02-01T00:00:00Z-2024-03-06T15:42:17Z",
+                            "doc_count": 1,
+                            "from": 1706745600000.0,
+                            "from_as_string": "2024-02-01T00:00:00Z",
+                            "to": 1709739737000.0,
+                            "to_as_string": "2024-03-06T15:42:17Z",
+                            "categories": {
+                                "buckets": [{ "key": "a", "doc_count": 1 }],
+                                "doc_count_error_upper_bound": 0,
+                                "sum_other_doc_count": 0
+                            }
+                        },
+                        "last_24h": {
+                            "key": "last_24h",
+                            "doc_count": 2,
+                            "from": 1710258137000.0,
+                            "from_as_string": "2024-03-12T15:42:17Z",
+                            "categories": {
+                                "buckets": [
+                                    { "key": "a", "doc_count": 1 },
+                                    { "key": "b", "doc_count": 1 }
+                                ],
+                                "doc_count_error_upper_bound": 0,
+                                "sum_other_doc_count": 0
+                            }
+                        }
+                    }
+                }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn date_range_field_precision_test() -> crate::Result<()> {
+        // The values are truncated to the second, and so are the bounds.
+        let index = get_test_index(
+            &[&[
+                ("2024-03-13T15:42:16.900Z", "a"),
+                ("2024-03-13T15:42:17.100Z", "a"),
+            ]],
+            DateTimePrecision::Seconds,
+        )?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "recency": {
+                "date_range": {
+                    "field": "date",
+                    "now": "2024-03-13T15:42:17.500Z",
+                    "ranges": [{ "key": "recent", "from": "now" }]
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req.clone(), &index)?;
+        assert_eq!(res["recency"]["buckets"][0]["doc_count"], 1);
+        assert_eq!(
+            res["recency"]["buckets"][0]["from_as_string"],
+            "2024-03-13T15:42:17Z"
+        );
+
+        let index = get_test_index(
+            &[&[
+                ("2024-03-13T15:42:17.400Z", "a"),
+                ("2024-03-13T15:42:17.600Z", "a"),
+            ]],
+            DateTimePrecision::Milliseconds,
+        )?;
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(res["recency"]["buckets"][0]["doc_count"], 1);
+        assert_eq!(res["recency"]["buckets"][0]["from"], 1710344537500.0);
+        assert_eq!(
+            res["recency"]["buckets"][0]["from_as_string"],
+            "2024-03-13T15:42:17.5Z"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn date_range_invalid_request_test() -> crate::Result<()> {
+        let index = get_test_index(&[&[(NOW, "a")]], DateTimePrecision::Seconds)?;
+        let searcher = index.reader()?.searcher();
+        let search = |date_range: Value| {
+            let agg_req: Aggregations =
+                serde_json::from_value(json!({ "recency": { "date_range": date_range } })).unwrap();
+            let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+            searcher.search(&AllQuery, &collector).map(|_| ())
+        };
+
+        let err = search(json!({ "field": "date", "ranges": [{ "from": "now-1x" }] })).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Date math parse error: expected `+<n><unit>`, `-<n><unit>` or `/<unit>` operations \
+             in \"now-1x\""
+        );
+        let err = search(json!({
+            "field": "date",
+            "ranges": [{ "from": "now", "to": "now-1d" }],
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::AggregationError(AggregationError::InvalidAggregation {
+                kind: AggregationErrorKind::InvalidParameter { ref name, .. },
+                ..
+            }) if name == "ranges"
+        ));
+        let err = search(json!({
+            "field": "date",
+            "ranges": [{ "key": "recent", "from": "now-1d" }, { "key": "recent", "to": "now" }],
+        }))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "An invalid argument was passed: 'Duplicate key \"recent\" in the date ranges'"
+        );
+        let err = search(json!({ "field": "date", "now": "today", "ranges": [{ "from": "now" }] }))
+            .unwrap_err();
+        assert!(matches!(err, TantivyError::AggregationError(_)));
+        Ok(())
+    }
+}
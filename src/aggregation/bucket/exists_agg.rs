@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aggregation::agg_req_with_accessor::AggregationsWithAccessor;
+use crate::aggregation::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+};
+use crate::aggregation::segment_agg_result::{
+    build_segment_agg_collector, SegmentAggregationCollector,
+};
+use crate::DocId;
+
+/// Creates a single bucket of the documents having at least one value in a field.
+///
+/// Multi-valued fields count a document once, whatever its number of values. On a JSON path
+/// indexed with several types, e.g. numbers and text, a value of any type counts.
+///
+/// ## Prerequisite
+/// Exists aggregations work only on [fast fields](`crate::fastfield`), of any type. The
+/// presence of a value is read from the column indexes, without reading the values.
+///
+/// See [`MissingAggregation`] for the documents without a value.
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "with_rating": {
+///         "exists": { "field": "rating" },
+///         "aggs": { "avg_rating": { "avg": { "field": "rating" } } }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "with_rating": {
+///             "doc_count": 7,
+///             "avg_rating": { "value": 3.5 }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExistsAggregation {
+    /// The field to check for values.
+    pub field: String,
+}
+
+/// Creates a single bucket of the documents without any value in a field.
+///
+/// This is the complement of [`ExistsAggregation`]: on a field absent from a segment, all of the
+/// documents of the segment are counted.
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "without_rating": {
+///         "missing": { "field": "rating" }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "without_rating": { "doc_count": 3 }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MissingAggregation {
+    /// The field to check for values.
+    pub field: String,
+}
+
+/// Segment collector of the [`ExistsAggregation`] and [`MissingAggregation`].
+#[derive(Clone, Debug)]
+pub(crate) struct SegmentExistsCollector {
+    /// If true, collects the docs having a value, the docs without a value otherwise.
+    exists: bool,
+    doc_count: u64,
+    accessor_idx: usize,
+    sub_agg: Option<Box<dyn SegmentAggregationCollector>>,
+    /// Buffer of the docs of a block matching the bucket.
+    matching_docs: Vec<DocId>,
+}
+
+impl SegmentExistsCollector {
+    pub(crate) fn new(
+        exists: bool,
+        sub_aggregations: &mut AggregationsWithAccessor,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        let sub_agg = if sub_aggregations.is_empty() {
+            None
+        } else {
+            Some(build_segment_agg_collector(sub_aggregations)?)
+        };
+        Ok(SegmentExistsCollector {
+            exists,
+            doc_count: 0,
+            accessor_idx,
+            sub_agg,
+            matching_docs: Vec::new(),
+        })
+    }
+}
+
+impl SegmentAggregationCollector for SegmentExistsCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let mut sub_aggregation = IntermediateAggregationResults::default();
+        if let Some(sub_agg) = self.sub_agg {
+            sub_agg.add_intermediate_aggregation_result(
+                &agg_with_accessor.sub_aggregation,
+                &mut sub_aggregation,
+            )?;
+        }
+        let bucket = IntermediateBucketResult::Single {
+            doc_count: self.doc_count,
+            sub_aggregation,
+        };
+        results.push(name, IntermediateAggregationResult::Bucket(bucket))?;
+        Ok(())
+    }
+
+    fn collect(
+        &mut self,
+        doc: DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    fn collect_block(
+        &mut self,
+        docs: &[DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        let agg = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+        self.matching_docs.clear();
+        self.matching_docs
+            .extend(docs.iter().copied().filter(|&doc| {
+                let has_value = agg
+                    .accessors
+                    .iter()
+                    .any(|(accessor, _)| accessor.index.has_value(doc));
+                has_value == self.exists
+            }));
+        self.doc_count += self.matching_docs.len() as u64;
+        if let Some(sub_agg) = self.sub_agg.as_mut() {
+            if !self.matching_docs.is_empty() {
+                sub_agg.collect_block(&self.matching_docs, &mut agg.sub_aggregation)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        if let Some(sub_agg) = self.sub_agg.as_mut() {
+            let agg = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+            sub_agg.flush(&mut agg.sub_aggregation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::{exec_request, exec_request_with_query};
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    fn exists_and_missing(index: &Index, field: &str) -> crate::Result<Value> {
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "exists": {
+                "exists": { "field": field },
+                "aggs": { "sum_score": { "sum": { "field": "score" } } }
+            },
+            "missing": {
+                "missing": { "field": field },
+                "aggs": { "sum_score": { "sum": { "field": "score" } } }
+            }
+        }))
+        .unwrap();
+        exec_request(agg_req, index)
+    }
+
+    #[test]
+    fn exists_aggregation_multi_valued_and_json() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let tags = schema_builder.add_text_field("tags", STRING | FAST);
+        let json = schema_builder.add_json_field("json", FAST);
+        let score = schema_builder.add_f64_field("score", FAST);
+        let _never = schema_builder.add_u64_field("never", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(score => 1.0, tags => "a", tags => "b"))?;
+        index_writer.add_document(doc!(score => 2.0, json => json!({"color": "red"})))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(score => 4.0, json => json!({"color": 3, "size": 1})))?;
+        index_writer.add_document(doc!(score => 8.0, tags => "c"))?;
+        index_writer.add_document(doc!(score => 16.0, json => json!({"size": 2})))?;
+        index_writer.commit()?;
+
+        let res = exists_and_missing(&index, "tags")?;
+        assert_eq!(
+            res,
+            json!({
+                "exists": { "doc_count": 2, "sum_score": { "value": 9.0 } },
+                "missing": { "doc_count": 3, "sum_score": { "value": 22.0 } },
+            })
+        );
+        // Mixed types on the same JSON path.
+        let res = exists_and_missing(&index, "json.color")?;
+        assert_eq!(res["exists"]["doc_count"], 2);
+        assert_eq!(res["exists"]["sum_score"]["value"], 6.0);
+        assert_eq!(res["missing"]["doc_count"], 3);
+        // The path only exists in the second segment.
+        let res = exists_and_missing(&index, "json.size")?;
+        assert_eq!(res["exists"]["doc_count"], 2);
+        assert_eq!(res["exists"]["sum_score"]["value"], 20.0);
+        assert_eq!(res["missing"]["doc_count"], 3);
+        // The column is absent from all of the segments.
+        for field in ["never", "json.absent"] {
+            let res = exists_and_missing(&index, field)?;
+            assert_eq!(res["exists"]["doc_count"], 0);
+            assert_eq!(res["missing"]["doc_count"], 5);
+            assert_eq!(res["missing"]["sum_score"]["value"], 31.0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn exists_aggregation_with_deletes_and_nested() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", FAST | crate::schema::INDEXED);
+        let rating = schema_builder.add_u64_field("rating", FAST);
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..20u64 {
+            let category_val = if i % 2 == 0 { "even" } else { "odd" };
+            if i % 3 == 0 {
+                index_writer.add_document(doc!(id => i, category => category_val))?;
+            } else {
+                index_writer.add_document(doc!(id => i, rating => i, category => category_val))?;
+            }
+        }
+        index_writer.commit()?;
+        // Deletes a doc with a rating and a doc without.
+        index_writer.delete_term(Term::from_field_u64(id, 1))?;
+        index_writer.delete_term(Term::from_field_u64(id, 3))?;
+        index_writer.commit()?;
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "categories": {
+                "terms": { "field": "category", "order": { "_key": "asc" } },
+                "aggs": {
+                    "rated": { "exists": { "field": "rating" } },
+                    "unrated": { "missing": { "field": "rating" } }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        let buckets = &res["categories"]["buckets"];
+        // even: 0..20 step 2, without a rating for 0, 6, 12, 18.
+        assert_eq!(buckets[0]["key"], "even");
+        assert_eq!(buckets[0]["rated"]["doc_count"], 6);
+        assert_eq!(buckets[0]["unrated"]["doc_count"], 4);
+        // odd: 1 and 3 are deleted, 9 and 15 have no rating.
+        assert_eq!(buckets[1]["key"], "odd");
+        assert_eq!(buckets[1]["rated"]["doc_count"], 6);
+        assert_eq!(buckets[1]["unrated"]["doc_count"], 2);
+        Ok(())
+    }
+}
@@ -133,32 +133,45 @@ impl DateHistogramAggregationReq {
             extended_bounds: self.extended_bounds,
             keyed: self.keyed,
             is_normalized_to_ns: false,
+            value_transform: None,
         })
     }
 
     fn validate(&self) -> crate::Result<()> {
         if let Some(interval) = self.interval.as_ref() {
-            return Err(crate::TantivyError::InvalidArgument(format!(
-                "`interval` parameter {interval:?} in date histogram is unsupported, only \
-                 `fixed_interval` is supported"
-            )));
+            return Err(AggregationError::invalid_parameter(
+                "interval",
+                format!(
+                    "`interval` parameter {interval:?} in date histogram is unsupported, only \
+                     `fixed_interval` is supported"
+                ),
+            )
+            .into());
         }
         if let Some(interval) = self.calendar_interval.as_ref() {
-            return Err(crate::TantivyError::InvalidArgument(format!(
-                "`calendar_interval` parameter {interval:?} in date histogram is unsupported, \
-                 only `fixed_interval` is supported"
-            )));
+            return Err(AggregationError::invalid_parameter(
+                "calendar_interval",
+                format!(
+                    "`calendar_interval` parameter {interval:?} in date histogram is unsupported, \
+                     only `fixed_interval` is supported"
+                ),
+            )
+            .into());
         }
         if self.format.is_some() {
-            return Err(crate::TantivyError::InvalidArgument(
-                "format parameter on date_histogram is unsupported".to_string(),
-            ));
+            return Err(AggregationError::invalid_parameter(
+                "format",
+                "format parameter on date_histogram is unsupported",
+            )
+            .into());
         }
 
         if self.fixed_interval.is_none() {
-            return Err(crate::TantivyError::InvalidArgument(
-                "fixed_interval in date histogram is missing".to_string(),
-            ));
+            return Err(AggregationError::invalid_parameter(
+                "fixed_interval",
+                "fixed_interval in date histogram is missing",
+            )
+            .into());
         }
 
         parse_into_milliseconds(self.fixed_interval.as_ref().unwrap())?;
@@ -17,8 +17,8 @@ use crate::aggregation::intermediate_agg_result::{
 use crate::aggregation::segment_agg_result::{
     build_segment_agg_collector, SegmentAggregationCollector,
 };
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
-use crate::TantivyError;
 
 /// Histogram is a bucket aggregation, where buckets are created dynamically for given `interval`.
 /// Each document value is rounded down to its bucket.
@@ -124,6 +124,10 @@ pub struct HistogramAggregation {
     /// Whether the values are normalized to ns for date time values. Defaults to false.
     #[serde(default)]
     pub is_normalized_to_ns: bool,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
 }
 
 impl HistogramAggregation {
@@ -146,24 +150,32 @@ impl HistogramAggregation {
 
     fn validate(&self) -> crate::Result<()> {
         if self.interval <= 0.0f64 {
-            return Err(TantivyError::InvalidArgument(
-                "interval must be a positive value".to_string(),
-            ));
+            return Err(AggregationError::invalid_parameter(
+                "interval",
+                "interval must be a positive value",
+            )
+            .into());
         }
 
         if self.min_doc_count.unwrap_or(0) > 0 && self.extended_bounds.is_some() {
-            return Err(TantivyError::InvalidArgument(
-                "Cannot set min_doc_count and extended_bounds at the same time".to_string(),
-            ));
+            return Err(AggregationError::invalid_parameter(
+                "extended_bounds",
+                "Cannot set min_doc_count and extended_bounds at the same time",
+            )
+            .into());
         }
 
         if let (Some(hard_bounds), Some(extended_bounds)) = (self.hard_bounds, self.extended_bounds)
         {
             if extended_bounds.min < hard_bounds.min || extended_bounds.max > hard_bounds.max {
-                return Err(TantivyError::InvalidArgument(format!(
-                    "extended_bounds have to be inside hard_bounds, extended_bounds: \
-                     {extended_bounds}, hard_bounds {hard_bounds}"
-                )));
+                return Err(AggregationError::invalid_parameter(
+                    "extended_bounds",
+                    format!(
+                        "extended_bounds have to be inside hard_bounds, extended_bounds: \
+                         {extended_bounds}, hard_bounds {hard_bounds}"
+                    ),
+                )
+                .into());
             }
         }
 
@@ -303,15 +315,13 @@ impl SegmentAggregationCollector for SegmentHistogramCollector {
         let offset = self.offset;
         let get_bucket_pos = |val| (get_bucket_pos_f64(val, interval, offset) as i64);
 
-        bucket_agg_accessor
-            .column_block_accessor
-            .fetch_block(docs, &bucket_agg_accessor.accessor);
+        bucket_agg_accessor.fetch_block(docs);
 
         for (doc, val) in bucket_agg_accessor
             .column_block_accessor
             .iter_docid_vals(docs, &bucket_agg_accessor.accessor)
         {
-            let val = self.f64_from_fastfield_u64(val);
+            let val = bucket_agg_accessor.transform_value(self.f64_from_fastfield_u64(val));
 
             let bucket_pos = get_bucket_pos(val);
 
@@ -424,12 +434,12 @@ impl SegmentHistogramCollector {
 }
 
 #[inline]
-fn get_bucket_pos_f64(val: f64, interval: f64, offset: f64) -> f64 {
+pub(crate) fn get_bucket_pos_f64(val: f64, interval: f64, offset: f64) -> f64 {
     ((val - offset) / interval).floor()
 }
 
 #[inline]
-fn get_bucket_key_from_pos(bucket_pos: f64, interval: f64, offset: f64) -> f64 {
+pub(crate) fn get_bucket_key_from_pos(bucket_pos: f64, interval: f64, offset: f64) -> f64 {
     bucket_pos * interval + offset
 }
 
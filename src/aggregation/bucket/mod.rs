@@ -20,19 +20,43 @@
 //! - [Histogram](HistogramAggregation)
 //! - [DateHistogram](DateHistogramAggregationReq)
 //! - [Range](RangeAggregation)
+//! - [DateRange](DateRangeAggregation)
 //! - [Terms](TermsAggregation)
-
+//! - [SignificantTerms](SignificantTermsAggregation)
+//! - [PathTerms](PathTermsAggregation)
+//! - [PrefixTerms](PrefixTermsAggregation)
+//! - [Composite](CompositeAggregation)
+//! - [Exists](ExistsAggregation) and [Missing](MissingAggregation)
+
+mod composite_agg;
+mod date_range;
+mod exists_agg;
 mod histogram;
+mod path_terms_agg;
+mod prefix_terms_agg;
 mod range;
+mod significant_terms_agg;
 mod term_agg;
 mod term_missing_agg;
 
 use std::collections::HashMap;
 use std::fmt;
 
+pub(crate) use composite_agg::SegmentCompositeCollector;
+pub use composite_agg::{
+    CompositeAggregation, CompositeDateHistogramSource, CompositeHistogramSource, CompositeSource,
+    CompositeTermsSource,
+};
+pub(crate) use date_range::pin_date_range_now;
+pub use date_range::{DateRangeAggregation, DateRangeAggregationRange};
+pub(crate) use exists_agg::SegmentExistsCollector;
+pub use exists_agg::{ExistsAggregation, MissingAggregation};
 pub use histogram::*;
+pub use path_terms_agg::*;
+pub use prefix_terms_agg::*;
 pub use range::*;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+pub use significant_terms_agg::*;
 pub use term_agg::*;
 pub use term_missing_agg::*;
 
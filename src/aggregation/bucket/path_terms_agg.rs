@@ -0,0 +1,912 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use columnar::{ColumnType, Dictionary};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cut_off_buckets, get_agg_name_and_property, CustomOrder, Order, OrderTarget, TermsAggregation,
+};
+use crate::aggregation::agg_limits::MemoryConsumption;
+use crate::aggregation::agg_req_with_accessor::{
+    AggregationWithAccessor, AggregationsWithAccessor,
+};
+use crate::aggregation::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+    IntermediateKey, IntermediateTermBucketEntry, IntermediateTermBucketResult,
+};
+use crate::aggregation::segment_agg_result::{
+    build_segment_agg_collector, SegmentAggregationCollector,
+};
+use crate::aggregation::AggregationError;
+use crate::{DocId, TantivyError};
+
+/// Counts the documents per hierarchical path, with the paths truncated to a given depth.
+///
+/// The values of the text fast field are interpreted as paths, whose components are delimited by
+/// `separator`, e.g. `electronics/phones/android`. Every value is truncated to its first `depth`
+/// components, and each document is counted once per distinct truncated path. Values with fewer
+/// than `depth` components are ignored.
+///
+/// With a `prefix`, only the paths below the prefix are counted. This allows drilling down in the
+/// hierarchy: by default, `depth` is one level below the prefix, so that the buckets are the
+/// immediate children of the prefix.
+///
+/// This provides facet counts on a plain text field, without the
+/// [facet field type](crate::schema::Facet).
+///
+/// ## Prerequisite
+/// Path terms aggregations work only on [fast fields](`crate::fastfield`) of type text. The field
+/// should not be tokenized, e.g. by using the `raw` tokenizer.
+///
+/// ## Performance
+/// Each segment maps the term ordinals of the field to the ordinals of the truncated paths. This
+/// table is built when a segment first collects a document, by going through the whole term
+/// dictionary. It is cached on the [`SegmentReader`](crate::SegmentReader), so that it is shared
+/// by all the requests on the same searcher generation. The least recently used tables are
+/// evicted beyond 16 tables or 64MB per segment.
+///
+/// ## Document count error
+/// Like for the [terms aggregation](super::TermsAggregation), each segment returns up to
+/// `segment_size` buckets. The result is a
+/// [`BucketResult::Terms`](crate::aggregation::agg_result::BucketResult::Terms).
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "categories": {
+///         "path_terms": { "field": "category", "prefix": "electronics" }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "categories": {
+///             "doc_count_error_upper_bound": 0,
+///             "sum_other_doc_count": 0,
+///             "buckets": [
+///                 { "key": "electronics/phones", "doc_count": 6 },
+///                 { "key": "electronics/laptops", "doc_count": 2 }
+///             ]
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PathTermsAggregation {
+    /// The field to aggregate on.
+    pub field: String,
+    /// The delimiter of the path components. Defaults to `/`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub separator: Option<String>,
+    /// Only count the paths below this path.
+    ///
+    /// A path is below the prefix if its first components are the components of the prefix.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub prefix: Option<String>,
+    /// The number of path components of the buckets.
+    ///
+    /// Defaults to the number of components of `prefix` plus one, i.e. to 1 without prefix.
+    ///
+    /// The values with fewer components are not counted in any bucket, e.g. with a depth of 2,
+    /// a document whose only value is `electronics` is not counted.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub depth: Option<u32>,
+    /// By default, the top 10 paths with the most documents are returned.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size: Option<u32>,
+    /// To get more accurate results, we fetch more than `size` from each segment.
+    ///
+    /// Defaults to 10 * size.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[serde(alias = "shard_size")]
+    #[serde(alias = "split_size")]
+    pub segment_size: Option<u32>,
+    /// Filter all paths that are lower than `min_doc_count`. Defaults to 1.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min_doc_count: Option<u64>,
+    /// Set the order. See [`TermsAggregation::order`].
+    ///
+    /// Defaults to `{"_count": "desc"}`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub order: Option<CustomOrder>,
+}
+
+impl PathTermsAggregation {
+    /// Returns the terms aggregation used to turn the merged buckets into the final result.
+    pub(crate) fn to_terms_req(&self) -> TermsAggregation {
+        TermsAggregation {
+            field: self.field.clone(),
+            size: self.size,
+            segment_size: self.segment_size,
+            min_doc_count: self.min_doc_count,
+            order: self.order.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Same as PathTermsAggregation, but with populated defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PathTermsAggregationInternal {
+    pub path: PathTruncation,
+    /// The number of paths returned per segment. Defaults to 10 * size.
+    pub segment_size: u32,
+    pub order: CustomOrder,
+}
+
+impl PathTermsAggregationInternal {
+    pub(crate) fn from_req(req: &PathTermsAggregation) -> crate::Result<Self> {
+        let size = req.size.unwrap_or(10);
+        let segment_size = req.segment_size.unwrap_or(size * 10).max(size);
+        Ok(PathTermsAggregationInternal {
+            path: PathTruncation::from_req(req)?,
+            segment_size,
+            order: req.order.clone().unwrap_or_default(),
+        })
+    }
+}
+
+/// Describes how the values of the field are turned into the keys of the buckets.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PathTruncation {
+    field: String,
+    separator: String,
+    prefix: Option<String>,
+    depth: usize,
+}
+
+impl PathTruncation {
+    fn from_req(req: &PathTermsAggregation) -> crate::Result<Self> {
+        let separator = req.separator.clone().unwrap_or_else(|| "/".to_string());
+        if separator.is_empty() {
+            return Err(AggregationError::invalid_parameter(
+                "separator",
+                "path_terms aggregation requires a non empty separator",
+            )
+            .into());
+        }
+        let prefix = req
+            .prefix
+            .as_deref()
+            .map(|prefix| prefix.strip_suffix(separator.as_str()).unwrap_or(prefix))
+            .filter(|prefix| !prefix.is_empty())
+            .map(str::to_string);
+        let prefix_depth = prefix
+            .as_deref()
+            .map(|prefix| prefix.split(separator.as_str()).count())
+            .unwrap_or(0);
+        let depth = req
+            .depth
+            .map(|depth| depth as usize)
+            .unwrap_or(prefix_depth + 1);
+        if depth == 0 {
+            return Err(AggregationError::invalid_parameter(
+                "depth",
+                "path_terms aggregation requires a depth of at least 1",
+            )
+            .into());
+        }
+        Ok(PathTruncation {
+            field: req.field.clone(),
+            separator,
+            prefix,
+            depth,
+        })
+    }
+
+    /// Returns the first `depth` components of `path`, if `path` has enough components and is
+    /// below the prefix.
+    ///
+    /// The paths with fewer than `depth` components are dropped rather than kept whole, so that
+    /// the prefix itself is not returned as one of its children when drilling down.
+    fn truncate<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let truncated = match path
+            .match_indices(self.separator.as_str())
+            .nth(self.depth - 1)
+        {
+            Some((end, _)) => &path[..end],
+            None if path.split(self.separator.as_str()).count() == self.depth => path,
+            None => return None,
+        };
+        let Some(prefix) = self.prefix.as_deref() else {
+            return Some(truncated);
+        };
+        let is_below_prefix = truncated
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(self.separator.as_str()));
+        is_below_prefix.then_some(truncated)
+    }
+}
+
+/// Maps the term ordinals of a segment to the ordinals of their truncated paths.
+///
+/// Path ordinals follow the lexicographic order of the truncated paths.
+#[derive(Debug)]
+pub(crate) struct PathOrdMapping {
+    term_ord_to_path_ord: Vec<u32>,
+    paths: Vec<String>,
+}
+
+impl PathOrdMapping {
+    /// Placeholder for the terms which are not counted.
+    const NO_PATH: u32 = u32::MAX;
+
+    fn build(dictionary: &Dictionary, path: &PathTruncation) -> io::Result<Self> {
+        let mut path_ids: FxHashMap<String, u32> = Default::default();
+        let mut term_ord_to_path_ord = Vec::with_capacity(dictionary.num_terms());
+        let mut stream = dictionary.stream()?;
+        while let Some((term, _)) = stream.next() {
+            let truncated = std::str::from_utf8(term)
+                .ok()
+                .and_then(|term| path.truncate(term));
+            let path_id = match truncated {
+                Some(truncated) => match path_ids.get(truncated) {
+                    Some(path_id) => *path_id,
+                    None => {
+                        let path_id = path_ids.len() as u32;
+                        path_ids.insert(truncated.to_string(), path_id);
+                        path_id
+                    }
+                },
+                None => Self::NO_PATH,
+            };
+            term_ord_to_path_ord.push(path_id);
+        }
+
+        // Renumber the paths in lexicographic order.
+        let mut paths: Vec<(String, u32)> = path_ids.into_iter().collect();
+        paths.sort_unstable();
+        let mut path_id_to_path_ord = vec![0u32; paths.len()];
+        for (path_ord, (_, path_id)) in paths.iter().enumerate() {
+            path_id_to_path_ord[*path_id as usize] = path_ord as u32;
+        }
+        for path_ord in &mut term_ord_to_path_ord {
+            if *path_ord != Self::NO_PATH {
+                *path_ord = path_id_to_path_ord[*path_ord as usize];
+            }
+        }
+        Ok(PathOrdMapping {
+            term_ord_to_path_ord,
+            paths: paths.into_iter().map(|(path, _)| path).collect(),
+        })
+    }
+
+    #[inline]
+    fn path_ord(&self, term_ord: u64) -> Option<u32> {
+        self.term_ord_to_path_ord
+            .get(term_ord as usize)
+            .copied()
+            .filter(|path_ord| *path_ord != Self::NO_PATH)
+    }
+
+    fn num_paths(&self) -> usize {
+        self.paths.len()
+    }
+
+    fn num_bytes(&self) -> usize {
+        let paths_num_bytes: usize = self
+            .paths
+            .iter()
+            .map(|path| path.len() + std::mem::size_of::<String>())
+            .sum();
+        self.term_ord_to_path_ord.len() * std::mem::size_of::<u32>() + paths_num_bytes
+    }
+}
+
+struct CachedPathOrdMapping {
+    mapping: Arc<PathOrdMapping>,
+    num_bytes: usize,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct PathOrdMappingCacheState {
+    mappings: HashMap<PathTruncation, CachedPathOrdMapping>,
+    num_bytes: usize,
+    clock: u64,
+}
+
+/// Cache of the [`PathOrdMapping`]s of a segment, attached to its
+/// [`SegmentReader`](crate::SegmentReader) as an extension.
+///
+/// The least recently used mappings are evicted beyond [`Self::MAX_NUM_MAPPINGS`] mappings or
+/// [`Self::MAX_NUM_BYTES`] bytes.
+#[derive(Default)]
+pub(crate) struct PathOrdMappingCache {
+    state: Mutex<PathOrdMappingCacheState>,
+}
+
+impl PathOrdMappingCache {
+    const MAX_NUM_MAPPINGS: usize = 16;
+    const MAX_NUM_BYTES: usize = 64_000_000;
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, PathOrdMappingCacheState> {
+        self.state
+            .lock()
+            .expect("Lock poisoned. This should never happen")
+    }
+
+    fn get_or_build(
+        &self,
+        path: &PathTruncation,
+        dictionary: &Dictionary,
+    ) -> io::Result<Arc<PathOrdMapping>> {
+        {
+            let mut state = self.lock_state();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(cached) = state.mappings.get_mut(path) {
+                cached.last_access = clock;
+                return Ok(Arc::clone(&cached.mapping));
+            }
+        }
+        // The lock is released while building, so a mapping may be built twice. This is fine.
+        let mapping = Arc::new(PathOrdMapping::build(dictionary, path)?);
+        self.insert(path, Arc::clone(&mapping));
+        Ok(mapping)
+    }
+
+    /// Inserts a mapping, evicting the least recently used ones beyond the limits.
+    fn insert(&self, path: &PathTruncation, mapping: Arc<PathOrdMapping>) {
+        let num_bytes = mapping.num_bytes();
+        if num_bytes > Self::MAX_NUM_BYTES {
+            return;
+        }
+        let mut state = self.lock_state();
+        state.clock += 1;
+        let cached = CachedPathOrdMapping {
+            mapping,
+            num_bytes,
+            last_access: state.clock,
+        };
+        if let Some(previous) = state.mappings.insert(path.clone(), cached) {
+            state.num_bytes -= previous.num_bytes;
+        }
+        state.num_bytes += num_bytes;
+        while state.mappings.len() > Self::MAX_NUM_MAPPINGS || state.num_bytes > Self::MAX_NUM_BYTES
+        {
+            let Some(evicted_path) = state
+                .mappings
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_access)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = state.mappings.remove(&evicted_path) {
+                state.num_bytes -= evicted.num_bytes;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn num_mappings(&self) -> usize {
+        self.lock_state().mappings.len()
+    }
+}
+
+/// The collector maps the term ordinals of the text fast field to truncated path ordinals, and
+/// puts them into buckets.
+#[derive(Clone, Debug)]
+pub struct SegmentPathTermsCollector {
+    /// Number of documents per path ordinal.
+    doc_counts: Vec<u32>,
+    sub_aggs: FxHashMap<u32, Box<dyn SegmentAggregationCollector>>,
+    /// The distinct `(doc, path_ord)` pairs of the current block.
+    doc_paths: Vec<(DocId, u32)>,
+    mapping: Option<Arc<PathOrdMapping>>,
+    req: PathTermsAggregationInternal,
+    blueprint: Option<Box<dyn SegmentAggregationCollector>>,
+    accessor_idx: usize,
+}
+
+impl SegmentAggregationCollector for SegmentPathTermsCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+
+        let bucket = self.into_intermediate_bucket_result(agg_with_accessor)?;
+        results.push(name, IntermediateAggregationResult::Bucket(bucket))?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn collect(
+        &mut self,
+        doc: crate::DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    #[inline]
+    fn collect_block(
+        &mut self,
+        docs: &[crate::DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        let bucket_agg_accessor = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+        let Some(str_dict_column) = bucket_agg_accessor.str_dict_column.as_ref() else {
+            // The field does not exist in this segment.
+            return Ok(());
+        };
+        let mapping = match self.mapping.as_ref() {
+            Some(mapping) => mapping,
+            None => {
+                let cache = bucket_agg_accessor
+                    .path_ord_mapping_cache
+                    .as_ref()
+                    .expect("path_terms aggregation requires a path ordinal cache");
+                let mapping = cache.get_or_build(&self.req.path, str_dict_column.dictionary())?;
+                self.doc_counts = vec![0; mapping.num_paths()];
+                self.mapping.insert(mapping)
+            }
+        };
+
+        let mem_pre = self.sub_aggs.memory_consumption();
+
+        // A document is counted once per path, even if several of its values share the path.
+        self.doc_paths.clear();
+        let mut doc_start = 0;
+        bucket_agg_accessor.fetch_block(docs);
+        for (doc, term_ord) in bucket_agg_accessor
+            .column_block_accessor
+            .iter_docid_vals(docs, &bucket_agg_accessor.accessor)
+        {
+            let Some(path_ord) = mapping.path_ord(term_ord) else {
+                continue;
+            };
+            if self
+                .doc_paths
+                .get(doc_start)
+                .is_some_and(|(start_doc, _)| *start_doc != doc)
+            {
+                doc_start = self.doc_paths.len();
+            }
+            if self.doc_paths[doc_start..]
+                .iter()
+                .all(|(_, doc_path_ord)| *doc_path_ord != path_ord)
+            {
+                self.doc_paths.push((doc, path_ord));
+            }
+        }
+
+        for &(doc, path_ord) in &self.doc_paths {
+            self.doc_counts[path_ord as usize] += 1;
+            if let Some(blueprint) = self.blueprint.as_ref() {
+                self.sub_aggs
+                    .entry(path_ord)
+                    .or_insert_with(|| blueprint.clone())
+                    .collect(doc, &mut bucket_agg_accessor.sub_aggregation)?;
+            }
+        }
+
+        let mem_delta = self.sub_aggs.memory_consumption() - mem_pre;
+        if mem_delta > 0 {
+            bucket_agg_accessor
+                .limits
+                .add_memory_consumed(mem_delta as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        let sub_aggregation_accessor =
+            &mut agg_with_accessor.aggs.values[self.accessor_idx].sub_aggregation;
+        for sub_aggregations in self.sub_aggs.values_mut() {
+            sub_aggregations.flush(sub_aggregation_accessor)?;
+        }
+        Ok(())
+    }
+}
+
+impl SegmentPathTermsCollector {
+    pub(crate) fn from_req_and_validate(
+        req: &PathTermsAggregation,
+        sub_aggregations: &mut AggregationsWithAccessor,
+        field_type: ColumnType,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        if field_type != ColumnType::Str {
+            return Err(AggregationError::type_mismatch(
+                &req.field,
+                "a text fast field",
+                format!("{field_type:?}"),
+            )
+            .into());
+        }
+        let req = PathTermsAggregationInternal::from_req(req)?;
+        if let OrderTarget::SubAggregation(sub_agg_name) = &req.order.target {
+            let (agg_name, _agg_property) = get_agg_name_and_property(sub_agg_name);
+            sub_aggregations.aggs.get(agg_name).ok_or_else(|| {
+                TantivyError::from(AggregationError::invalid_parameter(
+                    "order",
+                    format!(
+                        "could not find aggregation with name {agg_name} in metric \
+                         sub_aggregations"
+                    ),
+                ))
+            })?;
+        }
+        let blueprint = if !sub_aggregations.is_empty() {
+            Some(build_segment_agg_collector(sub_aggregations)?)
+        } else {
+            None
+        };
+
+        Ok(SegmentPathTermsCollector {
+            doc_counts: Vec::new(),
+            sub_aggs: Default::default(),
+            doc_paths: Vec::new(),
+            mapping: None,
+            req,
+            blueprint,
+            accessor_idx,
+        })
+    }
+
+    pub(crate) fn into_intermediate_bucket_result(
+        mut self,
+        agg_with_accessor: &AggregationWithAccessor,
+    ) -> crate::Result<IntermediateBucketResult> {
+        let Some(mapping) = self.mapping.take() else {
+            return Ok(IntermediateBucketResult::Terms {
+                buckets: Default::default(),
+            });
+        };
+        let mut entries: Vec<(u64, u32)> = self
+            .doc_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, doc_count)| **doc_count > 0)
+            .map(|(path_ord, doc_count)| (path_ord as u64, *doc_count))
+            .collect();
+
+        // Path ordinals follow the order of the paths.
+        let order = self.req.order.order;
+        let (term_doc_count_before_cutoff, sum_other_doc_count) = match self.req.order.target {
+            OrderTarget::Key | OrderTarget::Count => {
+                if self.req.order.target == OrderTarget::Key {
+                    entries.sort_unstable_by_key(|(path_ord, _)| *path_ord);
+                } else {
+                    entries.sort_unstable_by_key(|(_, doc_count)| *doc_count);
+                }
+                if order == Order::Desc {
+                    entries.reverse();
+                }
+                cut_off_buckets(&mut entries, self.req.segment_size as usize)
+            }
+            OrderTarget::SubAggregation(_) => (0, 0),
+        };
+
+        let mut dict: FxHashMap<IntermediateKey, IntermediateTermBucketEntry> = Default::default();
+        dict.reserve(entries.len());
+        for (path_ord, doc_count) in entries {
+            let mut sub_aggregation = IntermediateAggregationResults::default();
+            if let Some(sub_agg) = self.sub_aggs.remove(&(path_ord as u32)) {
+                sub_agg.add_intermediate_aggregation_result(
+                    &agg_with_accessor.sub_aggregation,
+                    &mut sub_aggregation,
+                )?;
+            }
+            dict.insert(
+                IntermediateKey::Str(mapping.paths[path_ord as usize].clone()),
+                IntermediateTermBucketEntry {
+                    doc_count,
+                    sub_aggregation,
+                },
+            );
+        }
+
+        Ok(IntermediateBucketResult::Terms {
+            buckets: IntermediateTermBucketResult {
+                entries: dict,
+                sum_other_doc_count,
+                doc_count_error_upper_bound: term_doc_count_before_cutoff,
+                spilled: Default::default(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathOrdMappingCache, PathTermsAggregation, PathTruncation};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::exec_request_with_query;
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{Index, IndexWriter};
+
+    fn truncation(prefix: Option<&str>, depth: Option<u32>) -> PathTruncation {
+        PathTruncation::from_req(&PathTermsAggregation {
+            field: "category".to_string(),
+            prefix: prefix.map(str::to_string),
+            depth,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_path_truncation() {
+        let top_level = truncation(None, None);
+        assert_eq!(top_level.truncate("a/b/c"), Some("a"));
+        assert_eq!(top_level.truncate("a"), Some("a"));
+
+        let second_level = truncation(None, Some(2));
+        assert_eq!(second_level.truncate("a/b/c"), Some("a/b"));
+        assert_eq!(second_level.truncate("a/b"), Some("a/b"));
+        assert_eq!(second_level.truncate("a"), None);
+
+        let children = truncation(Some("a/"), None);
+        assert_eq!(children.truncate("a/b/c"), Some("a/b"));
+        assert_eq!(children.truncate("a"), None);
+        assert_eq!(children.truncate("ab/c"), None);
+        assert_eq!(children.truncate("b/c"), None);
+    }
+
+    fn get_path_test_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let segments: [&[&[&str]]; 2] = [
+            &[
+                &["electronics/phones/android"],
+                &["electronics/phones/ios"],
+                &["electronics/laptops"],
+                &["books/fiction"],
+            ],
+            &[
+                &["electronics/phones/android"],
+                // Both paths are below `electronics/phones`.
+                &["electronics/phones/android", "electronics/phones/ios"],
+                &["electronics/laptops", "books/science"],
+                &["electronics"],
+                &["electronicsstore/phones"],
+            ],
+        ];
+        for docs in segments {
+            for paths in docs {
+                let mut doc = crate::TantivyDocument::default();
+                for path in paths.iter() {
+                    doc.add_text(category, path);
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn path_terms_counts(index: &Index, path_terms: serde_json::Value) -> Vec<(String, u64)> {
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "categories": {
+                "path_terms": path_terms
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, index, None).unwrap();
+        res["categories"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| {
+                (
+                    bucket["key"].as_str().unwrap().to_string(),
+                    bucket["doc_count"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_path_terms_multi_level() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+
+        assert_eq!(
+            path_terms_counts(&index, json!({ "field": "category" })),
+            vec![
+                ("electronics".to_string(), 7),
+                ("books".to_string(), 2),
+                ("electronicsstore".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            path_terms_counts(
+                &index,
+                json!({ "field": "category", "depth": 2, "order": { "_key": "asc" } })
+            ),
+            vec![
+                ("books/fiction".to_string(), 1),
+                ("books/science".to_string(), 1),
+                ("electronics/laptops".to_string(), 2),
+                ("electronics/phones".to_string(), 4),
+                ("electronicsstore/phones".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            path_terms_counts(
+                &index,
+                json!({ "field": "category", "depth": 2, "size": 1 })
+            ),
+            vec![("electronics/phones".to_string(), 4)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_prefix_drill_down() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        assert_eq!(
+            path_terms_counts(
+                &index,
+                json!({ "field": "category", "prefix": "electronics" })
+            ),
+            vec![
+                ("electronics/phones".to_string(), 4),
+                ("electronics/laptops".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            path_terms_counts(
+                &index,
+                json!({ "field": "category", "prefix": "electronics/phones/" })
+            ),
+            vec![
+                ("electronics/phones/android".to_string(), 3),
+                ("electronics/phones/ios".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            path_terms_counts(&index, json!({ "field": "category", "prefix": "toys" })),
+            vec![]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_ignores_shallower_paths() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        // `electronics/laptops`, `electronics`, `books/fiction`, ... have fewer than 3
+        // components.
+        assert_eq!(
+            path_terms_counts(&index, json!({ "field": "category", "depth": 3 })),
+            vec![
+                ("electronics/phones/android".to_string(), 3),
+                ("electronics/phones/ios".to_string(), 2),
+            ]
+        );
+        // The document whose only value is `electronics` is not a child of `electronics`.
+        assert_eq!(
+            path_terms_counts(
+                &index,
+                json!({ "field": "category", "prefix": "electronics", "order": { "_key": "asc" } })
+            ),
+            vec![
+                ("electronics/laptops".to_string(), 2),
+                ("electronics/phones".to_string(), 4),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_multiple_paths_per_doc() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "categories": {
+                "path_terms": { "field": "category", "depth": 2, "order": { "_key": "asc" } },
+                "aggs": {
+                    "leaves": {
+                        "terms": { "field": "category", "order": { "_key": "asc" } }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        let phones = &res["categories"]["buckets"][3];
+        assert_eq!(phones["key"], "electronics/phones");
+        // The document with both `android` and `ios` is counted once.
+        assert_eq!(phones["doc_count"], 4);
+        assert_eq!(
+            phones["leaves"]["buckets"],
+            json!([
+                { "key": "electronics/phones/android", "doc_count": 3 },
+                { "key": "electronics/phones/ios", "doc_count": 2 },
+            ])
+        );
+        // A document with paths in several subtrees is counted in each of them.
+        let books_science = &res["categories"]["buckets"][1];
+        assert_eq!(books_science["key"], "books/science");
+        assert_eq!(
+            books_science["leaves"]["buckets"],
+            json!([
+                { "key": "books/science", "doc_count": 1 },
+                { "key": "electronics/laptops", "doc_count": 1 },
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_mapping_is_cached() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        let searcher = index.reader()?.searcher();
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "categories": { "path_terms": { "field": "category" } }
+        }))
+        .unwrap();
+        for _ in 0..2 {
+            let collector = crate::aggregation::AggregationCollector::from_aggs(
+                agg_req.clone(),
+                Default::default(),
+            );
+            searcher.search(&crate::query::AllQuery, &collector)?;
+        }
+        for segment_reader in searcher.segment_readers() {
+            let cache = segment_reader.extension::<PathOrdMappingCache>();
+            assert_eq!(cache.num_mappings(), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_mapping_cache_is_bounded() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+        let str_column = segment_reader
+            .fast_fields()
+            .str("category")?
+            .expect("category is a text fast field");
+        let cache = PathOrdMappingCache::default();
+        let max_num_mappings = PathOrdMappingCache::MAX_NUM_MAPPINGS as u32;
+        for depth in 1..=max_num_mappings + 2 {
+            cache.get_or_build(&truncation(None, Some(depth)), str_column.dictionary())?;
+            // Keeps the first mapping recently used.
+            cache.get_or_build(&truncation(None, Some(1)), str_column.dictionary())?;
+        }
+        assert_eq!(cache.num_mappings(), PathOrdMappingCache::MAX_NUM_MAPPINGS);
+        let state = cache.lock_state();
+        assert!(state.mappings.contains_key(&truncation(None, Some(1))));
+        assert!(!state.mappings.contains_key(&truncation(None, Some(2))));
+        assert!(state
+            .mappings
+            .contains_key(&truncation(None, Some(max_num_mappings + 2))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_terms_invalid_requests() -> crate::Result<()> {
+        let index = get_path_test_index()?;
+        for path_terms in [
+            json!({ "field": "category", "depth": 0 }),
+            json!({ "field": "category", "separator": "" }),
+        ] {
+            let agg_req: Aggregations = serde_json::from_value(json!({
+                "categories": { "path_terms": path_terms }
+            }))
+            .unwrap();
+            assert!(exec_request_with_query(agg_req, &index, None).is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+use std::io;
+use std::ops::{Bound, Range};
+
+use columnar::{ColumnType, Dictionary};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{cut_off_buckets, TermsAggregation};
+use crate::aggregation::agg_limits::MemoryConsumption;
+use crate::aggregation::agg_req_with_accessor::{
+    AggregationWithAccessor, AggregationsWithAccessor,
+};
+use crate::aggregation::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+    IntermediateKey, IntermediateTermBucketEntry, IntermediateTermBucketResult,
+};
+use crate::aggregation::segment_agg_result::{
+    build_segment_agg_collector, SegmentAggregationCollector,
+};
+use crate::aggregation::AggregationError;
+use crate::query::prefix_end;
+
+/// Counts the documents per term, for the terms starting with a prefix.
+///
+/// This is typically used to display the completions of a prefix typed by a user, with the number
+/// of documents matching the current query for each of them. The top `size` terms with the most
+/// documents are returned.
+///
+/// Unlike a [terms aggregation](super::TermsAggregation) whose buckets are filtered afterwards,
+/// only the terms starting with the prefix are counted: they form a contiguous range of term
+/// ordinals, which is looked up once per segment in the term dictionary.
+///
+/// ## Prerequisite
+/// Prefix terms aggregations work only on [fast fields](`crate::fastfield`) of type text.
+///
+/// ## Case sensitivity
+/// The prefix is compared to the indexed terms byte by byte. To match the prefix regardless of
+/// its case, the field has to be normalized at indexing time, e.g. with a tokenizer lowercasing
+/// the terms, and the prefix has to be normalized the same way.
+///
+/// ## Document count error
+/// Like for the terms aggregation, each segment returns up to `segment_size` buckets. The result
+/// is a [`BucketResult::Terms`](crate::aggregation::agg_result::BucketResult::Terms).
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "completions": {
+///         "prefix_terms": { "field": "brand", "prefix": "sam", "size": 5 }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "completions": {
+///             "doc_count_error_upper_bound": 0,
+///             "sum_other_doc_count": 0,
+///             "buckets": [
+///                 { "key": "samsung", "doc_count": 12 },
+///                 { "key": "samsonite", "doc_count": 3 }
+///             ]
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrefixTermsAggregation {
+    /// The field to aggregate on.
+    pub field: String,
+    /// Only the terms starting with this prefix are counted.
+    ///
+    /// An empty prefix counts all of the terms, like a terms aggregation.
+    pub prefix: String,
+    /// By default, the top 10 terms with the most documents are returned.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size: Option<u32>,
+    /// To get more accurate results, we fetch more than `size` from each segment.
+    ///
+    /// Defaults to 10 * size.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[serde(alias = "shard_size")]
+    #[serde(alias = "split_size")]
+    pub segment_size: Option<u32>,
+    /// Filter all terms that are lower than `min_doc_count`. Defaults to 1.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min_doc_count: Option<u64>,
+}
+
+impl PrefixTermsAggregation {
+    /// Returns the terms aggregation used to turn the merged buckets into the final result.
+    pub(crate) fn to_terms_req(&self) -> TermsAggregation {
+        TermsAggregation {
+            field: self.field.clone(),
+            size: self.size,
+            segment_size: self.segment_size,
+            min_doc_count: self.min_doc_count,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of terms returned per segment.
+    fn segment_size(&self) -> u32 {
+        let size = self.size.unwrap_or(10);
+        self.segment_size.unwrap_or(size * 10).max(size)
+    }
+}
+
+/// Returns the range of the ordinals of the terms of `dictionary` starting with `prefix`.
+fn prefix_ord_range(dictionary: &Dictionary, prefix: &str) -> io::Result<Range<u64>> {
+    let num_terms = dictionary.num_terms() as u64;
+    let upper_bound = prefix_end(prefix.as_bytes());
+    let (lower_bound, upper_bound) = dictionary.term_bounds_to_ord(
+        Bound::Included(prefix.as_bytes()),
+        upper_bound
+            .as_deref()
+            .map(Bound::Excluded)
+            .unwrap_or(Bound::Unbounded),
+    )?;
+    let start = match lower_bound {
+        Bound::Included(ord) => ord,
+        Bound::Excluded(ord) => ord.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match upper_bound {
+        Bound::Included(ord) => ord.saturating_add(1),
+        Bound::Excluded(ord) => ord,
+        Bound::Unbounded => num_terms,
+    };
+    // The dictionary returns `u64::MAX` for the bounds after its last term.
+    Ok(start.min(num_terms)..end.min(num_terms))
+}
+
+/// The collector counts the term ordinals of the text fast field which are in the ordinal range
+/// of the prefix.
+#[derive(Clone, Debug)]
+pub struct SegmentPrefixTermsCollector {
+    /// Number of documents per term ordinal, relative to the start of `ord_range`.
+    doc_counts: Vec<u32>,
+    sub_aggs: FxHashMap<u32, Box<dyn SegmentAggregationCollector>>,
+    /// The ordinal range of the terms starting with the prefix, looked up on the first block.
+    ord_range: Option<Range<u64>>,
+    req: PrefixTermsAggregation,
+    blueprint: Option<Box<dyn SegmentAggregationCollector>>,
+    accessor_idx: usize,
+}
+
+impl SegmentAggregationCollector for SegmentPrefixTermsCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+
+        let bucket = self.into_intermediate_bucket_result(agg_with_accessor)?;
+        results.push(name, IntermediateAggregationResult::Bucket(bucket))?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn collect(
+        &mut self,
+        doc: crate::DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    #[inline]
+    fn collect_block(
+        &mut self,
+        docs: &[crate::DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        let bucket_agg_accessor = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+        let Some(str_dict_column) = bucket_agg_accessor.str_dict_column.as_ref() else {
+            // The field does not exist in this segment.
+            return Ok(());
+        };
+        let ord_range = match self.ord_range.as_ref() {
+            Some(ord_range) => ord_range,
+            None => {
+                let ord_range = prefix_ord_range(str_dict_column.dictionary(), &self.req.prefix)?;
+                self.doc_counts = vec![0; (ord_range.end - ord_range.start) as usize];
+                self.ord_range.insert(ord_range)
+            }
+        };
+        if ord_range.is_empty() {
+            return Ok(());
+        }
+
+        let mem_pre = self.sub_aggs.memory_consumption();
+
+        bucket_agg_accessor.fetch_block(docs);
+        if let Some(blueprint) = self.blueprint.as_ref() {
+            for (doc, term_ord) in bucket_agg_accessor
+                .column_block_accessor
+                .iter_docid_vals(docs, &bucket_agg_accessor.accessor)
+            {
+                if !ord_range.contains(&term_ord) {
+                    continue;
+                }
+                let idx = (term_ord - ord_range.start) as u32;
+                self.doc_counts[idx as usize] += 1;
+                self.sub_aggs
+                    .entry(idx)
+                    .or_insert_with(|| blueprint.clone())
+                    .collect(doc, &mut bucket_agg_accessor.sub_aggregation)?;
+            }
+        } else {
+            for term_ord in bucket_agg_accessor.column_block_accessor.iter_vals() {
+                // Ordinals out of the range wrap around to large values.
+                let idx = term_ord.wrapping_sub(ord_range.start);
+                if let Some(doc_count) = self.doc_counts.get_mut(idx as usize) {
+                    *doc_count += 1;
+                }
+            }
+        }
+
+        let mem_delta = self.sub_aggs.memory_consumption() - mem_pre;
+        if mem_delta > 0 {
+            bucket_agg_accessor
+                .limits
+                .add_memory_consumed(mem_delta as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        let sub_aggregation_accessor =
+            &mut agg_with_accessor.aggs.values[self.accessor_idx].sub_aggregation;
+        for sub_aggregations in self.sub_aggs.values_mut() {
+            sub_aggregations.flush(sub_aggregation_accessor)?;
+        }
+        Ok(())
+    }
+}
+
+impl SegmentPrefixTermsCollector {
+    pub(crate) fn from_req_and_validate(
+        req: &PrefixTermsAggregation,
+        sub_aggregations: &mut AggregationsWithAccessor,
+        field_type: ColumnType,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        if field_type != ColumnType::Str {
+            return Err(AggregationError::type_mismatch(
+                &req.field,
+                "a text fast field",
+                format!("{field_type:?}"),
+            )
+            .into());
+        }
+        let blueprint = if !sub_aggregations.is_empty() {
+            Some(build_segment_agg_collector(sub_aggregations)?)
+        } else {
+            None
+        };
+
+        Ok(SegmentPrefixTermsCollector {
+            doc_counts: Vec::new(),
+            sub_aggs: Default::default(),
+            ord_range: None,
+            req: req.clone(),
+            blueprint,
+            accessor_idx,
+        })
+    }
+
+    pub(crate) fn into_intermediate_bucket_result(
+        mut self,
+        agg_with_accessor: &AggregationWithAccessor,
+    ) -> crate::Result<IntermediateBucketResult> {
+        let (Some(ord_range), Some(str_dict_column)) = (
+            self.ord_range.take(),
+            agg_with_accessor.str_dict_column.as_ref(),
+        ) else {
+            return Ok(IntermediateBucketResult::Terms {
+                buckets: Default::default(),
+            });
+        };
+        let mut entries: Vec<(u64, u32)> = self
+            .doc_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, doc_count)| **doc_count > 0)
+            .map(|(idx, doc_count)| (idx as u64, *doc_count))
+            .collect();
+        entries.sort_unstable_by_key(|(_, doc_count)| std::cmp::Reverse(*doc_count));
+        let (term_doc_count_before_cutoff, sum_other_doc_count) =
+            cut_off_buckets(&mut entries, self.req.segment_size() as usize);
+
+        // The terms are resolved in the order of their ordinals.
+        entries.sort_unstable_by_key(|(idx, _)| *idx);
+        let mut dict: FxHashMap<IntermediateKey, IntermediateTermBucketEntry> = Default::default();
+        dict.reserve(entries.len());
+        let mut entries_iter = entries.iter();
+        str_dict_column.dictionary().sorted_ords_to_term_cb(
+            entries.iter().map(|(idx, _)| ord_range.start + idx),
+            |term| {
+                let (idx, doc_count) = entries_iter
+                    .next()
+                    .expect("one term is returned per ordinal");
+                let mut sub_aggregation = IntermediateAggregationResults::default();
+                if let Some(sub_agg) = self.sub_aggs.remove(&(*idx as u32)) {
+                    sub_agg
+                        .add_intermediate_aggregation_result(
+                            &agg_with_accessor.sub_aggregation,
+                            &mut sub_aggregation,
+                        )
+                        .map_err(io::Error::other)?;
+                }
+                dict.insert(
+                    IntermediateKey::Str(
+                        String::from_utf8(term.to_vec()).expect("could not convert to String"),
+                    ),
+                    IntermediateTermBucketEntry {
+                        doc_count: *doc_count,
+                        sub_aggregation,
+                    },
+                );
+                Ok(())
+            },
+        )?;
+
+        Ok(IntermediateBucketResult::Terms {
+            buckets: IntermediateTermBucketResult {
+                entries: dict,
+                sum_other_doc_count,
+                doc_count_error_upper_bound: term_doc_count_before_cutoff,
+                spilled: Default::default(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use columnar::Dictionary;
+
+    use super::prefix_ord_range;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::exec_request_with_query;
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{Index, IndexWriter};
+
+    #[test]
+    fn test_prefix_ord_range() {
+        let dictionary = Dictionary::build_for_tests(&["apple", "samba", "samsung", "sony"]);
+        assert_eq!(prefix_ord_range(&dictionary, "sam").unwrap(), 1..3);
+        assert_eq!(prefix_ord_range(&dictionary, "samsung").unwrap(), 2..3);
+        assert_eq!(prefix_ord_range(&dictionary, "s").unwrap(), 1..4);
+        assert_eq!(prefix_ord_range(&dictionary, "").unwrap(), 0..4);
+        assert!(prefix_ord_range(&dictionary, "b").unwrap().is_empty());
+        assert!(prefix_ord_range(&dictionary, "zz").unwrap().is_empty());
+    }
+
+    fn get_brand_test_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let brand = schema_builder.add_text_field("brand", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let segments: [&[&[&str]]; 2] = [
+            &[
+                &["samsung"],
+                &["samsung"],
+                &["samsonite"],
+                &["sony"],
+                &["apple", "samsung"],
+            ],
+            &[
+                &["samsung"],
+                &["samba", "sam"],
+                &["sam"],
+                &["sony", "samsonite"],
+                &[],
+                &["Samsung"],
+            ],
+        ];
+        for docs in segments {
+            for brands in docs {
+                let mut doc = crate::TantivyDocument::default();
+                for brand_value in brands.iter() {
+                    doc.add_text(brand, brand_value);
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn bucket_counts(res: &serde_json::Value) -> Vec<(String, u64)> {
+        res["brands"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| {
+                (
+                    bucket["key"].as_str().unwrap().to_string(),
+                    bucket["doc_count"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn prefix_terms_counts(index: &Index, prefix_terms: serde_json::Value) -> Vec<(String, u64)> {
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "brands": { "prefix_terms": prefix_terms }
+        }))
+        .unwrap();
+        bucket_counts(&exec_request_with_query(agg_req, index, None).unwrap())
+    }
+
+    fn filtered_terms_counts(index: &Index, prefix: &str) -> Vec<(String, u64)> {
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "brands": { "terms": { "field": "brand", "size": 100 } }
+        }))
+        .unwrap();
+        let mut counts = bucket_counts(&exec_request_with_query(agg_req, index, None).unwrap());
+        counts.retain(|(term, _)| term.starts_with(prefix));
+        counts
+    }
+
+    #[test]
+    fn test_prefix_terms_matches_filtered_terms() -> crate::Result<()> {
+        let index = get_brand_test_index()?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+        for prefix in ["sam", "sams", "samsung", "s", "S", "a"] {
+            assert_eq!(
+                prefix_terms_counts(&index, json!({ "field": "brand", "prefix": prefix })),
+                filtered_terms_counts(&index, prefix),
+                "prefix {prefix:?}"
+            );
+        }
+        assert_eq!(
+            prefix_terms_counts(&index, json!({ "field": "brand", "prefix": "sam" })),
+            vec![
+                ("samsung".to_string(), 4),
+                ("sam".to_string(), 2),
+                ("samsonite".to_string(), 2),
+                ("samba".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            prefix_terms_counts(
+                &index,
+                json!({ "field": "brand", "prefix": "sam", "size": 1 })
+            ),
+            vec![("samsung".to_string(), 4)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_terms_empty_prefix() -> crate::Result<()> {
+        let index = get_brand_test_index()?;
+        let counts = prefix_terms_counts(&index, json!({ "field": "brand", "prefix": "" }));
+        assert_eq!(counts, filtered_terms_counts(&index, ""));
+        assert_eq!(counts.len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_terms_no_matching_terms() -> crate::Result<()> {
+        let index = get_brand_test_index()?;
+        for prefix in ["b", "zzz", "samsungs"] {
+            assert_eq!(
+                prefix_terms_counts(&index, json!({ "field": "brand", "prefix": prefix })),
+                vec![]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_terms_sub_aggregation() -> crate::Result<()> {
+        let index = get_brand_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "brands": {
+                "prefix_terms": { "field": "brand", "prefix": "sams" },
+                "aggs": {
+                    "co_brands": { "terms": { "field": "brand", "order": { "_key": "asc" } } }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        assert_eq!(
+            res["brands"]["buckets"][1]["co_brands"]["buckets"],
+            json!([
+                { "key": "samsonite", "doc_count": 2 },
+                { "key": "sony", "doc_count": 1 },
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_terms_requires_text_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("brand", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(index.schema().get_field("brand")? => 1u64))?;
+        index_writer.commit()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "brands": { "prefix_terms": { "field": "brand", "prefix": "a" } }
+        }))
+        .unwrap();
+        assert!(exec_request_with_query(agg_req, &index, None).is_err());
+        Ok(())
+    }
+}
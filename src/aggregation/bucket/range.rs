@@ -4,7 +4,9 @@ use std::ops::Range;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
+use super::DateRangeAggregation;
 use crate::aggregation::agg_req_with_accessor::AggregationsWithAccessor;
+use crate::aggregation::date::nanos_to_millis;
 use crate::aggregation::intermediate_agg_result::{
     IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
     IntermediateRangeBucketEntry, IntermediateRangeBucketResult,
@@ -12,6 +14,7 @@ use crate::aggregation::intermediate_agg_result::{
 use crate::aggregation::segment_agg_result::{
     build_segment_agg_collector, SegmentAggregationCollector,
 };
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
 use crate::TantivyError;
 
@@ -59,6 +62,10 @@ pub struct RangeAggregation {
     /// Whether to return the buckets as a hash map
     #[serde(default)]
     pub keyed: bool,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -109,9 +116,9 @@ impl From<Range<f64>> for RangeAggregationRange {
 /// Internally used u64 range for one range bucket.
 pub(crate) struct InternalRangeAggregationRange {
     /// Custom key for the range bucket
-    key: Option<String>,
+    pub(crate) key: Option<String>,
     /// `u64` range value
-    range: Range<u64>,
+    pub(crate) range: Range<u64>,
 }
 
 impl From<Range<u64>> for InternalRangeAggregationRange {
@@ -132,6 +139,9 @@ pub(crate) struct SegmentRangeAndBucketEntry {
 pub struct SegmentRangeCollector {
     /// The buckets containing the aggregation data.
     buckets: Vec<SegmentRangeAndBucketEntry>,
+    /// Whether the buckets are the requested ranges only, which can overlap and leave gaps,
+    /// instead of ranges covering all the values.
+    exact_ranges: bool,
     column_type: ColumnType,
     pub(crate) accessor_idx: usize,
 }
@@ -188,6 +198,7 @@ impl SegmentAggregationCollector for SegmentRangeCollector {
         results: &mut IntermediateAggregationResults,
     ) -> crate::Result<()> {
         let field_type = self.column_type;
+        let exact_ranges = self.exact_ranges;
         let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
         let sub_agg = &agg_with_accessor.aggs.values[self.accessor_idx].sub_aggregation;
 
@@ -195,8 +206,14 @@ impl SegmentAggregationCollector for SegmentRangeCollector {
             .buckets
             .into_iter()
             .map(move |range_bucket| {
+                // The keys of exact ranges are unique, unlike their ranges.
+                let serialized_key = if exact_ranges {
+                    range_bucket.bucket.key.to_string()
+                } else {
+                    range_to_string(&range_bucket.range, &field_type)?
+                };
                 Ok((
-                    range_to_string(&range_bucket.range, &field_type)?,
+                    serialized_key,
                     range_bucket
                         .bucket
                         .into_intermediate_bucket_entry(sub_agg)?,
@@ -231,14 +248,38 @@ impl SegmentAggregationCollector for SegmentRangeCollector {
     ) -> crate::Result<()> {
         let bucket_agg_accessor = &mut agg_with_accessor.aggs.values[self.accessor_idx];
 
-        bucket_agg_accessor
-            .column_block_accessor
-            .fetch_block(docs, &bucket_agg_accessor.accessor);
+        bucket_agg_accessor.fetch_block(docs);
 
         for (doc, val) in bucket_agg_accessor
             .column_block_accessor
             .iter_docid_vals(docs, &bucket_agg_accessor.accessor)
         {
+            let val = if bucket_agg_accessor.value_transform.is_some() {
+                // The transformed value is mapped back into the u64 space of the ranges.
+                let val = f64_from_fastfield_u64(val, &self.column_type);
+                let Some(val) = f64_to_fastfield_u64(
+                    bucket_agg_accessor.transform_value(val),
+                    &self.column_type,
+                ) else {
+                    continue;
+                };
+                val
+            } else {
+                val
+            };
+            if self.exact_ranges {
+                for bucket in self
+                    .buckets
+                    .iter_mut()
+                    .filter(|bucket| bucket.range.contains(&val))
+                {
+                    bucket.bucket.doc_count += 1;
+                    if let Some(sub_aggregation) = &mut bucket.bucket.sub_aggregation {
+                        sub_aggregation.collect(doc, &mut bucket_agg_accessor.sub_aggregation)?;
+                    }
+                }
+                continue;
+            }
             let bucket_pos = self.get_bucket_pos(val);
 
             let bucket = &mut self.buckets[bucket_pos];
@@ -277,7 +318,55 @@ impl SegmentRangeCollector {
         // The range input on the request is f64.
         // We need to convert to u64 ranges, because we read the values as u64.
         // The mapping from the conversion is monotonic so ordering is preserved.
-        let buckets: Vec<_> = extend_validate_ranges(&req.ranges, &field_type)?
+        let ranges = req
+            .ranges
+            .iter()
+            .map(|range| to_u64_range(range, &field_type))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Self::from_ranges(
+            extend_validate_ranges(ranges)?,
+            false,
+            |val| f64_from_fastfield_u64(val, &field_type),
+            sub_aggregation,
+            limits,
+            field_type,
+            accessor_idx,
+        )
+    }
+
+    pub(crate) fn from_date_range_req_and_validate(
+        req: &DateRangeAggregation,
+        sub_aggregation: &mut AggregationsWithAccessor,
+        limits: &mut AggregationLimitsGuard,
+        field_type: ColumnType,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        // The bounds of the buckets are returned in milliseconds, like the keys of the
+        // `date_histogram`.
+        Self::from_ranges(
+            req.to_internal_ranges()?,
+            true,
+            |val| nanos_to_millis(i64::from_u64(val)),
+            sub_aggregation,
+            limits,
+            field_type,
+            accessor_idx,
+        )
+    }
+
+    /// Creates the collector of the buckets of `ranges`, sorted by their start.
+    ///
+    /// `bound_to_f64` converts the bounds of the ranges to the `from` and `to` of the buckets.
+    fn from_ranges(
+        ranges: Vec<InternalRangeAggregationRange>,
+        exact_ranges: bool,
+        bound_to_f64: impl Fn(u64) -> f64,
+        sub_aggregation: &mut AggregationsWithAccessor,
+        limits: &mut AggregationLimitsGuard,
+        field_type: ColumnType,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        let buckets: Vec<_> = ranges
             .iter()
             .map(|range| {
                 let key = range
@@ -288,12 +377,12 @@ impl SegmentRangeCollector {
                 let to = if range.range.end == u64::MAX {
                     None
                 } else {
-                    Some(f64_from_fastfield_u64(range.range.end, &field_type))
+                    Some(bound_to_f64(range.range.end))
                 };
                 let from = if range.range.start == u64::MIN {
                     None
                 } else {
-                    Some(f64_from_fastfield_u64(range.range.start, &field_type))
+                    Some(bound_to_f64(range.range.start))
                 };
                 let sub_aggregation = if sub_aggregation.is_empty() {
                     None
@@ -320,6 +409,7 @@ impl SegmentRangeCollector {
 
         Ok(SegmentRangeCollector {
             buckets,
+            exact_ranges,
             column_type: field_type,
             accessor_idx,
         })
@@ -375,14 +465,8 @@ fn to_u64_range(
 /// Extends the provided buckets to contain the whole value range, by inserting buckets at the
 /// beginning and end and filling gaps.
 fn extend_validate_ranges(
-    buckets: &[RangeAggregationRange],
-    field_type: &ColumnType,
+    mut converted_buckets: Vec<InternalRangeAggregationRange>,
 ) -> crate::Result<Vec<InternalRangeAggregationRange>> {
-    let mut converted_buckets = buckets
-        .iter()
-        .map(|range| to_u64_range(range, field_type))
-        .collect::<crate::Result<Vec<_>>>()?;
-
     converted_buckets.sort_by_key(|bucket| bucket.range.start);
     if converted_buckets[0].range.start != u64::MIN {
         converted_buckets.insert(0, (u64::MIN..converted_buckets[0].range.start).into());
@@ -397,9 +481,12 @@ fn extend_validate_ranges(
     let find_hole = |converted_buckets: &[InternalRangeAggregationRange]| {
         for (pos, ranges) in converted_buckets.windows(2).enumerate() {
             if ranges[0].range.end > ranges[1].range.start {
-                return Err(TantivyError::InvalidArgument(format!(
-                    "Overlapping ranges not supported range {:?}, range+1 {:?}",
-                    ranges[0], ranges[1]
+                return Err(TantivyError::from(AggregationError::invalid_parameter(
+                    "ranges",
+                    format!(
+                        "Overlapping ranges not supported range {:?}, range+1 {:?}",
+                        ranges[0], ranges[1]
+                    ),
                 )));
             }
             if ranges[0].range.end != ranges[1].range.start {
@@ -0,0 +1,916 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use columnar::{Column, ColumnType, StrColumn};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::aggregation::agg_limits::MemoryConsumption;
+use crate::aggregation::agg_req::{AggregationVariants, Aggregations};
+use crate::aggregation::agg_req_with_accessor::{
+    AggregationWithAccessor, AggregationsWithAccessor,
+};
+use crate::aggregation::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+    IntermediateKey, IntermediateSignificantTermBucketEntry,
+    IntermediateSignificantTermsBucketResult,
+};
+use crate::aggregation::segment_agg_result::{
+    build_segment_agg_collector, SegmentAggregationCollector,
+};
+use crate::aggregation::AggregationError;
+use crate::{DocId, Searcher, SegmentReader};
+
+/// Finds terms that are unusually frequent in the documents of the bucket (the *foreground*),
+/// compared to their frequency in the whole index (the *background*).
+///
+/// For every candidate term, the foreground count is collected from the matching documents,
+/// while the background count is taken from all of the segments of the searcher. Both counts,
+/// together with the foreground and background set sizes, are kept in the intermediate result,
+/// so that the significance score is recomputed on the summed counts after merging shards.
+///
+/// ## Prerequisite
+/// Significant terms aggregations work only on [fast fields](`crate::fastfield`) of type text.
+///
+/// ## Scoring
+/// The significance heuristic can be selected in the request. By default
+/// [JLH](SignificantTermsAggregation::jlh) is used, which is the product of the absolute and the
+/// relative change of a term's probability between background and foreground.
+/// [Chi-square](SignificantTermsAggregation::chi_square) is available as an alternative.
+///
+/// Only terms that are more frequent in the foreground than in the background get a positive
+/// score. Terms with a score of zero are never returned.
+///
+/// ## Limitations/Compatibility
+///
+/// Like for the [terms aggregation](super::TermsAggregation), counts are term occurrences,
+/// which equal document counts for untokenized single value fields.
+///
+/// Each segment selects its candidate terms on its own background counts. The background counts
+/// of the final candidates are then looked up in all of the segments when the segment results
+/// are merged, so a term cut off in some segments still gets its full background count. The
+/// background is the alive documents of the searcher, also when nested under another bucket
+/// aggregation.
+///
+/// The background counts of a segment are computed once per searcher generation, on the first
+/// request on the field.
+///
+/// # Request JSON Format
+/// ```json
+/// {
+///     "significant_genres": {
+///         "significant_terms": { "field": "genre" }
+///     }
+/// }
+/// ```
+///
+/// # Response JSON Format
+/// ```json
+/// {
+///     ...
+///     "aggregations": {
+///         "significant_genres": {
+///             "doc_count": 12,
+///             "bg_count": 1000,
+///             "buckets": [
+///                 { "key": "drumnbass", "doc_count": 6, "bg_count": 10, "score": 2.9 }
+///             ]
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SignificantTermsAggregation {
+    /// The field to aggregate on.
+    pub field: String,
+    /// By default, the top 10 significant terms are returned.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size: Option<u32>,
+
+    /// To get more accurate results, we fetch more than `size` from each segment.
+    ///
+    /// Defaults to 10 * size.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[serde(alias = "shard_size")]
+    #[serde(alias = "split_size")]
+    pub segment_size: Option<u32>,
+
+    /// Filter all terms that occur less than `min_doc_count` times in the foreground.
+    /// Defaults to 3.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min_doc_count: Option<u64>,
+
+    /// Use the JLH score as significance heuristic. This is the default.
+    ///
+    /// JSON format: `"jlh": {}`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub jlh: Option<JlhHeuristic>,
+
+    /// Use the chi-square statistic as significance heuristic.
+    ///
+    /// JSON format: `"chi_square": {}`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub chi_square: Option<ChiSquareHeuristic>,
+}
+
+/// Parameters of the JLH significance heuristic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JlhHeuristic {}
+
+/// Parameters of the chi-square significance heuristic.
+///
+/// The background is always considered to be a superset of the foreground.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChiSquareHeuristic {}
+
+/// The heuristic used to score the significance of a term.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum SignificanceHeuristic {
+    #[default]
+    Jlh,
+    ChiSquare,
+}
+
+impl SignificanceHeuristic {
+    /// Computes the significance score of a term.
+    ///
+    /// `subset_freq` and `subset_size` are the foreground term count and foreground size,
+    /// `superset_freq` and `superset_size` the background term count and background size. The
+    /// background includes the foreground.
+    pub(crate) fn score(
+        self,
+        subset_freq: u64,
+        subset_size: u64,
+        superset_freq: u64,
+        superset_size: u64,
+    ) -> f64 {
+        if subset_size == 0 || superset_size == 0 || superset_freq == 0 {
+            return 0.0;
+        }
+        match self {
+            SignificanceHeuristic::Jlh => {
+                let subset_probability = subset_freq as f64 / subset_size as f64;
+                let superset_probability = superset_freq as f64 / superset_size as f64;
+                if subset_probability <= superset_probability {
+                    return 0.0;
+                }
+                let absolute_change = subset_probability - superset_probability;
+                let relative_change = subset_probability / superset_probability;
+                absolute_change * relative_change
+            }
+            SignificanceHeuristic::ChiSquare => {
+                // Contingency table: first index is "contains term", second "is in subset".
+                let n_1 = subset_size as f64;
+                let n_0 = superset_size.saturating_sub(subset_size) as f64;
+                let n11 = subset_freq as f64;
+                let n10 = superset_freq.saturating_sub(subset_freq) as f64;
+                let n01 = n_1 - n11;
+                let n00 = n_0 - n10;
+                let n1_ = n11 + n10;
+                let n0_ = n01 + n00;
+                let n = n_1 + n_0;
+                if n_0 == 0.0 || n1_ == 0.0 || n0_ == 0.0 || n11 / n_1 <= n10 / n_0 {
+                    return 0.0;
+                }
+                let diff = n11 * n00 - n10 * n01;
+                n * diff * diff / (n1_ * n_1 * n0_ * n_0)
+            }
+        }
+    }
+}
+
+/// Same as SignificantTermsAggregation, but with populated defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SignificantTermsAggregationInternal {
+    /// The number of terms returned. Defaults to 10.
+    pub size: u32,
+    /// The number of terms returned per segment. Defaults to 10 * size.
+    pub segment_size: u32,
+    /// Filter all terms that are lower than `min_doc_count`. Defaults to 3.
+    pub min_doc_count: u64,
+    pub heuristic: SignificanceHeuristic,
+}
+
+impl SignificantTermsAggregationInternal {
+    pub(crate) fn from_req(req: &SignificantTermsAggregation) -> Self {
+        let size = req.size.unwrap_or(10);
+        let segment_size = req.segment_size.unwrap_or(size * 10).max(size);
+        let heuristic = if req.chi_square.is_some() {
+            SignificanceHeuristic::ChiSquare
+        } else {
+            SignificanceHeuristic::Jlh
+        };
+        SignificantTermsAggregationInternal {
+            size,
+            segment_size,
+            min_doc_count: req.min_doc_count.unwrap_or(3),
+            heuristic,
+        }
+    }
+}
+
+/// Number of occurrences of each term ordinal of a field over all alive documents of a segment.
+#[derive(Debug)]
+pub(crate) struct BackgroundTermCounts {
+    str_column: Option<StrColumn>,
+    term_counts: Vec<u32>,
+    num_docs: u64,
+}
+
+impl BackgroundTermCounts {
+    /// Returns the counts of `field_name` in the segment of `reader`, computing them on the
+    /// first request for the field in the searcher generation.
+    pub(crate) fn for_field(reader: &SegmentReader, field_name: &str) -> crate::Result<Arc<Self>> {
+        reader
+            .extension::<BackgroundTermCountsCache>()
+            .get_or_compute(reader, field_name)
+    }
+
+    fn compute(reader: &SegmentReader, field_name: &str) -> crate::Result<Self> {
+        let str_column = reader.try_fast_fields()?.str(field_name)?;
+        // Documents without a value for the field are still part of the background.
+        let (term_ords, num_terms) = match str_column.as_ref() {
+            Some(str_column) => (str_column.ords().clone(), str_column.num_terms()),
+            None => (Column::build_empty_column(reader.max_doc()), 0),
+        };
+        let mut term_counts = vec![0u32; num_terms];
+        let mut num_docs = 0u64;
+        let mut count_doc = |doc: DocId| {
+            num_docs += 1;
+            for term_ord in term_ords.values_for_doc(doc) {
+                term_counts[term_ord as usize] += 1;
+            }
+        };
+        if let Some(alive_bitset) = reader.alive_bitset() {
+            alive_bitset.iter_alive().for_each(&mut count_doc);
+        } else {
+            (0..reader.max_doc()).for_each(&mut count_doc);
+        }
+        Ok(BackgroundTermCounts {
+            str_column,
+            term_counts,
+            num_docs,
+        })
+    }
+
+    fn term_count(&self, term_ord: u64) -> u64 {
+        self.term_counts
+            .get(term_ord as usize)
+            .copied()
+            .unwrap_or(0) as u64
+    }
+
+    /// Returns the number of occurrences of the term `key`.
+    fn key_count(&self, key: &str) -> io::Result<u64> {
+        let Some(str_column) = self.str_column.as_ref() else {
+            return Ok(0);
+        };
+        let term_ord = str_column.dictionary().term_ord(key)?;
+        Ok(term_ord
+            .map(|term_ord| self.term_count(term_ord))
+            .unwrap_or(0))
+    }
+}
+
+#[derive(Default)]
+struct BackgroundTermCountsCacheState {
+    /// The counts and the time of their last access, by field.
+    counts: FxHashMap<String, (Arc<BackgroundTermCounts>, u64)>,
+    clock: u64,
+}
+
+/// Cache of the [`BackgroundTermCounts`] of a segment, attached to its
+/// [`SegmentReader`] as an extension.
+///
+/// The readers are opened for each searcher generation, so the counts are computed once per
+/// segment and generation. The counts of the least recently used fields are evicted beyond
+/// [`Self::MAX_NUM_FIELDS`] fields.
+#[derive(Default)]
+pub(crate) struct BackgroundTermCountsCache {
+    state: Mutex<BackgroundTermCountsCacheState>,
+}
+
+impl BackgroundTermCountsCache {
+    const MAX_NUM_FIELDS: usize = 16;
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, BackgroundTermCountsCacheState> {
+        self.state
+            .lock()
+            .expect("Lock poisoned. This should never happen")
+    }
+
+    fn get_or_compute(
+        &self,
+        reader: &SegmentReader,
+        field_name: &str,
+    ) -> crate::Result<Arc<BackgroundTermCounts>> {
+        {
+            let mut state = self.lock_state();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some((counts, last_access)) = state.counts.get_mut(field_name) {
+                *last_access = clock;
+                return Ok(Arc::clone(counts));
+            }
+        }
+        // The lock is released while computing, so counts may be computed twice. This is fine.
+        let counts = Arc::new(BackgroundTermCounts::compute(reader, field_name)?);
+        let mut state = self.lock_state();
+        state.clock += 1;
+        let clock = state.clock;
+        state
+            .counts
+            .insert(field_name.to_string(), (Arc::clone(&counts), clock));
+        if state.counts.len() > Self::MAX_NUM_FIELDS {
+            let evicted_field = state
+                .counts
+                .iter()
+                .min_by_key(|(_, (_, last_access))| *last_access)
+                .map(|(field_name, _)| field_name.clone());
+            if let Some(evicted_field) = evicted_field {
+                state.counts.remove(&evicted_field);
+            }
+        }
+        Ok(counts)
+    }
+
+    #[cfg(test)]
+    fn num_fields(&self) -> usize {
+        self.lock_state().counts.len()
+    }
+}
+
+/// Background counts of the segments of a searcher, by field and term.
+struct SearcherBackground<'a> {
+    searcher: &'a Searcher,
+    segment_counts: FxHashMap<String, Vec<Arc<BackgroundTermCounts>>>,
+    key_counts: FxHashMap<(String, String), u64>,
+}
+
+impl SearcherBackground<'_> {
+    fn segment_counts(&mut self, field_name: &str) -> crate::Result<&[Arc<BackgroundTermCounts>]> {
+        if !self.segment_counts.contains_key(field_name) {
+            let segment_counts = self
+                .searcher
+                .segment_readers()
+                .iter()
+                .map(|reader| BackgroundTermCounts::for_field(reader, field_name))
+                .collect::<crate::Result<_>>()?;
+            self.segment_counts
+                .insert(field_name.to_string(), segment_counts);
+        }
+        Ok(&self.segment_counts[field_name])
+    }
+
+    fn num_docs(&mut self, field_name: &str) -> crate::Result<u64> {
+        Ok(self
+            .segment_counts(field_name)?
+            .iter()
+            .map(|counts| counts.num_docs)
+            .sum())
+    }
+
+    fn key_count(&mut self, field_name: &str, key: &str) -> crate::Result<u64> {
+        let cache_key = (field_name.to_string(), key.to_string());
+        if let Some(key_count) = self.key_counts.get(&cache_key) {
+            return Ok(*key_count);
+        }
+        let mut key_count = 0;
+        for counts in self.segment_counts(field_name)? {
+            key_count += counts.key_count(key)?;
+        }
+        self.key_counts.insert(cache_key, key_count);
+        Ok(key_count)
+    }
+}
+
+/// Sets the background counts of the `significant_terms` results of the tree to their counts
+/// over all of the segments of `searcher`.
+///
+/// The segments only report the background counts of the terms they selected, so the merged
+/// counts miss the segments in which a term was cut off, while the merged background size
+/// counts all of them.
+pub(crate) fn resolve_background_counts(
+    results: &mut IntermediateAggregationResults,
+    req: &Aggregations,
+    searcher: &Searcher,
+) -> crate::Result<()> {
+    let mut background = SearcherBackground {
+        searcher,
+        segment_counts: Default::default(),
+        key_counts: Default::default(),
+    };
+    resolve_background_counts_rec(results, req, &mut background)
+}
+
+fn resolve_background_counts_rec(
+    results: &mut IntermediateAggregationResults,
+    req: &Aggregations,
+    background: &mut SearcherBackground,
+) -> crate::Result<()> {
+    for (name, agg_res) in results.aggs_res.iter_mut() {
+        // Custom results are not part of the request.
+        let Some(agg) = req.get(name) else {
+            continue;
+        };
+        let IntermediateAggregationResult::Bucket(bucket_res) = agg_res else {
+            continue;
+        };
+        if let (
+            AggregationVariants::SignificantTerms(significant_terms_req),
+            IntermediateBucketResult::SignificantTerms { buckets },
+        ) = (&agg.agg, &mut *bucket_res)
+        {
+            let field_name = significant_terms_req.field.as_str();
+            buckets.superset_size = background.num_docs(field_name)?;
+            for (key, entry) in buckets.entries.iter_mut() {
+                if let IntermediateKey::Str(key) = key {
+                    entry.bg_count = background.key_count(field_name, key)?;
+                }
+            }
+        }
+        if agg.sub_aggregation.is_empty() {
+            continue;
+        }
+        for sub_results in bucket_res.sub_aggregations_mut() {
+            resolve_background_counts_rec(sub_results, &agg.sub_aggregation, background)?;
+        }
+    }
+    Ok(())
+}
+
+/// The collector puts the term ordinals of the text fast field into buckets and keeps track of
+/// the number of documents collected.
+#[derive(Clone, Debug)]
+pub struct SegmentSignificantTermsCollector {
+    entries: FxHashMap<u64, u32>,
+    sub_aggs: FxHashMap<u64, Box<dyn SegmentAggregationCollector>>,
+    subset_size: u64,
+    req: SignificantTermsAggregationInternal,
+    blueprint: Option<Box<dyn SegmentAggregationCollector>>,
+    accessor_idx: usize,
+}
+
+impl SegmentAggregationCollector for SegmentSignificantTermsCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let name = agg_with_accessor.aggs.keys[self.accessor_idx].to_string();
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+
+        let bucket = self.into_intermediate_bucket_result(agg_with_accessor)?;
+        results.push(name, IntermediateAggregationResult::Bucket(bucket))?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn collect(
+        &mut self,
+        doc: crate::DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    #[inline]
+    fn collect_block(
+        &mut self,
+        docs: &[crate::DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        let bucket_agg_accessor = &mut agg_with_accessor.aggs.values[self.accessor_idx];
+
+        let mem_pre = self.get_memory_consumption();
+
+        self.subset_size += docs.len() as u64;
+        bucket_agg_accessor.fetch_block(docs);
+        for term_id in bucket_agg_accessor.column_block_accessor.iter_vals() {
+            *self.entries.entry(term_id).or_default() += 1;
+        }
+        if let Some(blueprint) = self.blueprint.as_ref() {
+            for (doc, term_id) in bucket_agg_accessor
+                .column_block_accessor
+                .iter_docid_vals(docs, &bucket_agg_accessor.accessor)
+            {
+                let sub_aggregations = self
+                    .sub_aggs
+                    .entry(term_id)
+                    .or_insert_with(|| blueprint.clone());
+                sub_aggregations.collect(doc, &mut bucket_agg_accessor.sub_aggregation)?;
+            }
+        }
+
+        let mem_delta = self.get_memory_consumption() - mem_pre;
+        if mem_delta > 0 {
+            bucket_agg_accessor
+                .limits
+                .add_memory_consumed(mem_delta as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        let sub_aggregation_accessor =
+            &mut agg_with_accessor.aggs.values[self.accessor_idx].sub_aggregation;
+        for sub_aggregations in self.sub_aggs.values_mut() {
+            sub_aggregations.flush(sub_aggregation_accessor)?;
+        }
+        Ok(())
+    }
+}
+
+impl SegmentSignificantTermsCollector {
+    fn get_memory_consumption(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.entries.memory_consumption()
+            + self.sub_aggs.memory_consumption()
+    }
+
+    pub(crate) fn from_req_and_validate(
+        req: &SignificantTermsAggregation,
+        sub_aggregations: &mut AggregationsWithAccessor,
+        field_type: ColumnType,
+        accessor_idx: usize,
+    ) -> crate::Result<Self> {
+        if field_type != ColumnType::Str {
+            return Err(AggregationError::type_mismatch(
+                &req.field,
+                "a text fast field",
+                format!("{field_type:?}"),
+            )
+            .into());
+        }
+        if req.jlh.is_some() && req.chi_square.is_some() {
+            return Err(AggregationError::invalid_parameter(
+                "heuristic",
+                "significant_terms aggregation accepts only one significance heuristic",
+            )
+            .into());
+        }
+        let blueprint = if !sub_aggregations.is_empty() {
+            Some(build_segment_agg_collector(sub_aggregations)?)
+        } else {
+            None
+        };
+
+        Ok(SegmentSignificantTermsCollector {
+            entries: Default::default(),
+            sub_aggs: Default::default(),
+            subset_size: 0,
+            req: SignificantTermsAggregationInternal::from_req(req),
+            blueprint,
+            accessor_idx,
+        })
+    }
+
+    pub(crate) fn into_intermediate_bucket_result(
+        mut self,
+        agg_with_accessor: &AggregationWithAccessor,
+    ) -> crate::Result<IntermediateBucketResult> {
+        let background = agg_with_accessor
+            .background_term_counts
+            .as_ref()
+            .expect("significant_terms aggregation requires background term counts");
+
+        // Cut off on the segment local significance.
+        let mut entries: Vec<(u64, u32, f64)> = self
+            .entries
+            .into_iter()
+            .map(|(term_ord, doc_count)| {
+                let score = self.req.heuristic.score(
+                    doc_count as u64,
+                    self.subset_size,
+                    background.term_count(term_ord),
+                    background.num_docs,
+                );
+                (term_ord, doc_count, score)
+            })
+            .filter(|(_, _, score)| *score > 0.0)
+            .collect();
+        entries.sort_unstable_by(|left, right| right.2.total_cmp(&left.2));
+        entries.truncate(self.req.segment_size as usize);
+
+        // Sort by term ord to stream the dictionary.
+        entries.sort_unstable_by_key(|(term_ord, _, _)| *term_ord);
+
+        let mut dict: FxHashMap<IntermediateKey, IntermediateSignificantTermBucketEntry> =
+            Default::default();
+        dict.reserve(entries.len());
+        if let Some(str_dict_column) = agg_with_accessor.str_dict_column.as_ref() {
+            let mut idx = 0;
+            str_dict_column.dictionary().sorted_ords_to_term_cb(
+                entries.iter().map(|(term_ord, _, _)| *term_ord),
+                |term| {
+                    let (term_ord, doc_count, _score) = entries[idx];
+                    idx += 1;
+                    let mut sub_aggregation = IntermediateAggregationResults::default();
+                    if let Some(sub_agg) = self.sub_aggs.remove(&term_ord) {
+                        sub_agg
+                            .add_intermediate_aggregation_result(
+                                &agg_with_accessor.sub_aggregation,
+                                &mut sub_aggregation,
+                            )
+                            .map_err(io::Error::other)?;
+                    }
+                    let key = String::from_utf8(term.to_vec()).map_err(io::Error::other)?;
+                    dict.insert(
+                        IntermediateKey::Str(key),
+                        IntermediateSignificantTermBucketEntry {
+                            doc_count: doc_count as u64,
+                            bg_count: background.term_count(term_ord),
+                            sub_aggregation,
+                        },
+                    );
+                    Ok(())
+                },
+            )?;
+        }
+
+        Ok(IntermediateBucketResult::SignificantTerms {
+            buckets: IntermediateSignificantTermsBucketResult {
+                entries: dict,
+                subset_size: self.subset_size,
+                superset_size: background.num_docs,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{BackgroundTermCounts, BackgroundTermCountsCache, SignificanceHeuristic};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::tests::exec_request_with_query;
+    use crate::aggregation::{AggregationError, AggregationErrorKind};
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, TantivyError};
+
+    /// 100 documents, 10 of them in category "a". Every document is tagged with "common", but
+    /// "rare" is concentrated in category "a" (8 out of 10 occurrences).
+    fn get_significant_terms_test_index(merge_segments: bool) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let tag = schema_builder.add_text_field("tag", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.set_merge_policy(Box::new(NoMergePolicy));
+            for doc_id in 0..100 {
+                let category_val = if doc_id % 10 == 0 { "a" } else { "b" };
+                let mut doc = doc!(category => category_val, tag => "common");
+                if (category_val == "a" && doc_id < 80) || doc_id == 1 || doc_id == 2 {
+                    doc.add_text(tag, "rare");
+                }
+                index_writer.add_document(doc)?;
+                if doc_id == 49 {
+                    index_writer.commit()?;
+                }
+            }
+            index_writer.commit()?;
+        }
+        if merge_segments {
+            let segment_ids = index.searchable_segment_ids()?;
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.merge(&segment_ids).wait()?;
+            index_writer.wait_merging_threads()?;
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn significant_terms_heuristics_test() {
+        let jlh = SignificanceHeuristic::Jlh;
+        assert_eq!(jlh.score(10, 10, 100, 100), 0.0);
+        assert!((jlh.score(8, 10, 10, 100) - 0.7 * 8.0).abs() < 1e-9);
+        assert_eq!(jlh.score(2, 90, 10, 100), 0.0);
+        assert_eq!(jlh.score(0, 0, 0, 0), 0.0);
+
+        let chi_square = SignificanceHeuristic::ChiSquare;
+        let expected = 100.0 * 700.0 * 700.0 / (10.0 * 10.0 * 90.0 * 90.0);
+        assert!((chi_square.score(8, 10, 10, 100) - expected).abs() < 1e-9);
+        assert_eq!(chi_square.score(2, 90, 10, 100), 0.0);
+        assert_eq!(chi_square.score(10, 10, 100, 100), 0.0);
+    }
+
+    #[test]
+    fn significant_terms_test_single_segment() -> crate::Result<()> {
+        significant_terms_test_merge_segment(true)
+    }
+
+    #[test]
+    fn significant_terms_test() -> crate::Result<()> {
+        significant_terms_test_merge_segment(false)
+    }
+
+    fn significant_terms_test_merge_segment(merge_segments: bool) -> crate::Result<()> {
+        let index = get_significant_terms_test_index(merge_segments)?;
+
+        // The most frequent term in the foreground is "common".
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "tags": { "terms": { "field": "tag" } },
+            "significant_tags": { "significant_terms": { "field": "tag" } },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, Some(("category", "a")))?;
+        assert_eq!(res["tags"]["buckets"][0]["key"], "common");
+        assert_eq!(res["tags"]["buckets"][0]["doc_count"], 10);
+        assert_eq!(res["tags"]["buckets"][1]["key"], "rare");
+        assert_eq!(res["tags"]["buckets"][1]["doc_count"], 8);
+
+        // But the significant one is "rare".
+        let significant_tags = &res["significant_tags"];
+        assert_eq!(significant_tags["doc_count"], 10);
+        assert_eq!(significant_tags["bg_count"], 100);
+        assert_eq!(significant_tags["buckets"].as_array().unwrap().len(), 1);
+        assert_eq!(significant_tags["buckets"][0]["key"], "rare");
+        assert_eq!(significant_tags["buckets"][0]["doc_count"], 8);
+        assert_eq!(significant_tags["buckets"][0]["bg_count"], 10);
+        let score = significant_tags["buckets"][0]["score"].as_f64().unwrap();
+        assert!((score - 0.7 * 8.0).abs() < 1e-9);
+
+        // chi_square
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "significant_tags": {
+                "significant_terms": { "field": "tag", "chi_square": {} }
+            },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, Some(("category", "a")))?;
+        let significant_tags = &res["significant_tags"];
+        assert_eq!(significant_tags["buckets"][0]["key"], "rare");
+        let score = significant_tags["buckets"][0]["score"].as_f64().unwrap();
+        let expected = 100.0 * 700.0 * 700.0 / (10.0 * 10.0 * 90.0 * 90.0);
+        assert!((score - expected).abs() < 1e-9);
+
+        // min_doc_count filters the foreground count
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "significant_tags": {
+                "significant_terms": { "field": "tag", "min_doc_count": 9 }
+            },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, Some(("category", "a")))?;
+        assert_eq!(res["significant_tags"]["buckets"], json!([]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn significant_terms_bg_count_of_segments_cutting_off_the_term() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let tag = schema_builder.add_text_field("tag", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.set_merge_policy(Box::new(NoMergePolicy));
+            // "x" is significant in the first segment.
+            for doc_id in 0..10 {
+                if doc_id < 5 {
+                    index_writer.add_document(doc!(category => "a", tag => "x"))?;
+                } else {
+                    index_writer.add_document(doc!(category => "b"))?;
+                }
+            }
+            index_writer.commit()?;
+            // "x" is cut off in the second segment, where it is frequent in the background.
+            for doc_id in 0..10 {
+                let mut doc = if doc_id < 3 {
+                    doc!(category => "a", tag => "y")
+                } else {
+                    doc!(category => "b", tag => "x")
+                };
+                if doc_id == 0 {
+                    doc.add_text(tag, "x");
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "significant_tags": {
+                "significant_terms": { "field": "tag", "segment_size": 1 }
+            },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, Some(("category", "a")))?;
+        let significant_tags = &res["significant_tags"];
+        assert_eq!(significant_tags["doc_count"], 8);
+        assert_eq!(significant_tags["bg_count"], 20);
+        // With the background count of the first segment only, "x" would score 0.9375.
+        assert_eq!(significant_tags["buckets"].as_array().unwrap().len(), 1);
+        assert_eq!(significant_tags["buckets"][0]["key"], "y");
+        assert_eq!(significant_tags["buckets"][0]["bg_count"], 3);
+        let score = significant_tags["buckets"][0]["score"].as_f64().unwrap();
+        assert!((score - (3.0 / 8.0 - 3.0 / 20.0) * (3.0 / 8.0) / (3.0 / 20.0)).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn significant_terms_background_counts_are_cached() -> crate::Result<()> {
+        let index = get_significant_terms_test_index(false)?;
+        let searcher = index.reader()?.searcher();
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "significant_tags": { "significant_terms": { "field": "tag" } },
+        }))
+        .unwrap();
+        let search = || -> crate::Result<()> {
+            let collector = crate::aggregation::AggregationCollector::from_aggs(
+                agg_req.clone(),
+                Default::default(),
+            );
+            searcher.search(&crate::query::AllQuery, &collector)?;
+            Ok(())
+        };
+        search()?;
+        let cached_counts: Vec<Arc<BackgroundTermCounts>> = searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| {
+                assert_eq!(
+                    reader.extension::<BackgroundTermCountsCache>().num_fields(),
+                    1
+                );
+                BackgroundTermCounts::for_field(reader, "tag")
+            })
+            .collect::<crate::Result<_>>()?;
+        search()?;
+        for (reader, cached_counts) in searcher.segment_readers().iter().zip(&cached_counts) {
+            let counts = BackgroundTermCounts::for_field(reader, "tag")?;
+            assert!(Arc::ptr_eq(&counts, cached_counts));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn significant_terms_nested_test() -> crate::Result<()> {
+        let index = get_significant_terms_test_index(true)?;
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "categories": {
+                "terms": { "field": "category", "order": { "_key": "asc" } },
+                "aggs": {
+                    "significant_tags": {
+                        "significant_terms": { "field": "tag" },
+                        "aggs": {
+                            "tag_categories": { "terms": { "field": "category" } }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+
+        let bucket_a = &res["categories"]["buckets"][0];
+        assert_eq!(bucket_a["key"], "a");
+        assert_eq!(bucket_a["significant_tags"]["doc_count"], 10);
+        assert_eq!(bucket_a["significant_tags"]["bg_count"], 100);
+        assert_eq!(bucket_a["significant_tags"]["buckets"][0]["key"], "rare");
+        assert_eq!(
+            bucket_a["significant_tags"]["buckets"][0]["tag_categories"]["buckets"][0]["doc_count"],
+            8
+        );
+
+        let bucket_b = &res["categories"]["buckets"][1];
+        assert_eq!(bucket_b["key"], "b");
+        assert_eq!(bucket_b["significant_tags"]["doc_count"], 90);
+        assert_eq!(bucket_b["significant_tags"]["buckets"], json!([]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn significant_terms_invalid_field_type_test() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let score = schema_builder.add_u64_field("score", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.add_document(doc!(score => 1u64))?;
+            index_writer.commit()?;
+        }
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "significant_scores": { "significant_terms": { "field": "score" } },
+        }))
+        .unwrap();
+        let err = exec_request_with_query(agg_req, &index, None).unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::AggregationError(AggregationError::InvalidAggregation {
+                kind: AggregationErrorKind::TypeMismatch { .. },
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
@@ -21,7 +21,8 @@ use crate::aggregation::intermediate_agg_result::{
 use crate::aggregation::segment_agg_result::{
     build_segment_agg_collector, SegmentAggregationCollector,
 };
-use crate::aggregation::{format_date, Key};
+use crate::aggregation::spill::{AggregationSpill, SpilledTermBuckets};
+use crate::aggregation::{format_date, AggregationError, Key};
 use crate::error::DataCorruption;
 use crate::TantivyError;
 
@@ -164,6 +165,41 @@ pub struct TermsAggregation {
     /// add text.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub missing: Option<Key>,
+
+    /// Merges the buckets whose text keys are within a Levenshtein distance of each other, e.g.
+    /// to group keys with typos. Example in JSON format:
+    /// { "fuzzy_key_merge": { "distance": 1, "transposition_cost_one": true } }
+    ///
+    /// The buckets are merged after the results of the segments are merged, from the bucket with
+    /// the highest count down: each bucket not merged yet absorbs the remaining buckets within
+    /// the distance of its key, and keeps its key. `min_doc_count`, `order` and `size` then apply
+    /// to the merged buckets.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fuzzy_key_merge: Option<FuzzyKeyMerge>,
+
+    /// Returns exact results for fields with a very large number of distinct terms, by
+    /// spilling the buckets to disk instead of holding all of them in memory. Example in JSON
+    /// format: { "allow_spill": true }
+    ///
+    /// The buckets of the segments are not cut off at `segment_size`, so the counts are exact.
+    /// The buckets are spilled to the [`AggregationSpill`] set with
+    /// [`AggregationCollector::with_spill`](crate::aggregation::AggregationCollector::with_spill)
+    /// once they exceed its memory budget, and do not count towards the memory limit of the
+    /// aggregation. Without a spill, all of the buckets are kept in memory. Cannot be combined
+    /// with `fuzzy_key_merge`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub allow_spill: Option<bool>,
+}
+
+/// Merges the buckets of a [`TermsAggregation`] whose keys are within a Levenshtein distance.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FuzzyKeyMerge {
+    /// The maximum Levenshtein distance between the key of a bucket and the key of the bucket it
+    /// is merged into. At most 2.
+    pub distance: u8,
+    /// Counts the transposition of two adjacent characters as a single edit, instead of two.
+    #[serde(default)]
+    pub transposition_cost_one: bool,
 }
 
 /// Same as TermsAggregation, but with populated defaults.
@@ -195,13 +231,20 @@ pub(crate) struct TermsAggregationInternal {
 
     pub order: CustomOrder,
     pub missing: Option<Key>,
+    pub allow_spill: bool,
 }
 
 impl TermsAggregationInternal {
     pub(crate) fn from_req(req: &TermsAggregation) -> Self {
         let size = req.size.unwrap_or(10);
 
-        let mut segment_size = req.segment_size.unwrap_or(size * 10);
+        let allow_spill = req.allow_spill.unwrap_or(false);
+        // The results are exact when spilling, the segments are not cut off.
+        let mut segment_size = if allow_spill {
+            u32::MAX
+        } else {
+            req.segment_size.unwrap_or(size * 10)
+        };
 
         let order = req.order.clone().unwrap_or_default();
         segment_size = segment_size.max(size);
@@ -215,6 +258,7 @@ impl TermsAggregationInternal {
             min_doc_count: req.min_doc_count.unwrap_or(1),
             order,
             missing: req.missing.clone(),
+            allow_spill,
         }
     }
 }
@@ -254,6 +298,8 @@ pub struct SegmentTermCollector {
     blueprint: Option<Box<dyn SegmentAggregationCollector>>,
     column_type: ColumnType,
     accessor_idx: usize,
+    /// The buckets spilled to disk, if the aggregation allows it.
+    spilled: SpilledTermBuckets,
 }
 
 pub(crate) fn get_agg_name_and_property(name: &str) -> (&str, &str) {
@@ -296,13 +342,9 @@ impl SegmentAggregationCollector for SegmentTermCollector {
         let mem_pre = self.get_memory_consumption();
 
         if let Some(missing) = bucket_agg_accessor.missing_value_for_accessor {
-            bucket_agg_accessor
-                .column_block_accessor
-                .fetch_block_with_missing(docs, &bucket_agg_accessor.accessor, missing);
+            bucket_agg_accessor.fetch_block_with_missing(docs, missing);
         } else {
-            bucket_agg_accessor
-                .column_block_accessor
-                .fetch_block(docs, &bucket_agg_accessor.accessor);
+            bucket_agg_accessor.fetch_block(docs);
         }
 
         for term_id in bucket_agg_accessor.column_block_accessor.iter_vals() {
@@ -324,11 +366,20 @@ impl SegmentAggregationCollector for SegmentTermCollector {
             }
         }
 
-        let mem_delta = self.get_memory_consumption() - mem_pre;
-        if mem_delta > 0 {
+        let mem = self.get_memory_consumption();
+        if let Some(spill) = bucket_agg_accessor
+            .limits
+            .spill()
+            .filter(|_| self.req.allow_spill)
+        {
+            // The spilled buckets bound the memory, which is not charged to the limits.
+            if mem > spill.memory_budget() {
+                self.spill_term_buckets(bucket_agg_accessor)?;
+            }
+        } else if mem > mem_pre {
             bucket_agg_accessor
                 .limits
-                .add_memory_consumed(mem_delta as u64)?;
+                .add_memory_consumed((mem - mem_pre) as u64)?;
         }
 
         Ok(())
@@ -355,11 +406,34 @@ impl SegmentTermCollector {
         sub_aggregations: &mut AggregationsWithAccessor,
         field_type: ColumnType,
         accessor_idx: usize,
+        spill: Option<&AggregationSpill>,
     ) -> crate::Result<Self> {
         if field_type == ColumnType::Bytes {
-            return Err(TantivyError::InvalidArgument(format!(
-                "terms aggregation is not supported for column type {field_type:?}"
-            )));
+            return Err(AggregationError::type_mismatch(
+                &req.field,
+                "a fast field of any type but bytes",
+                format!("{field_type:?}"),
+            )
+            .into());
+        }
+        if let Some(fuzzy_key_merge) = req.fuzzy_key_merge.as_ref() {
+            if req.allow_spill == Some(true) {
+                return Err(AggregationError::invalid_parameter(
+                    "allow_spill",
+                    "cannot be combined with fuzzy_key_merge",
+                )
+                .into());
+            }
+            if fuzzy_key_merge.distance > 2 {
+                return Err(AggregationError::invalid_parameter(
+                    "fuzzy_key_merge",
+                    format!(
+                        "the distance must be at most 2, got {}",
+                        fuzzy_key_merge.distance
+                    ),
+                )
+                .into());
+            }
         }
         let term_buckets = TermBuckets::default();
 
@@ -369,9 +443,12 @@ impl SegmentTermCollector {
                 let (agg_name, _agg_property) = get_agg_name_and_property(sub_agg_name);
 
                 sub_aggregations.aggs.get(agg_name).ok_or_else(|| {
-                    TantivyError::InvalidArgument(format!(
-                        "could not find aggregation with name {agg_name} in metric \
-                         sub_aggregations"
+                    TantivyError::from(AggregationError::invalid_parameter(
+                        "order",
+                        format!(
+                            "could not find aggregation with name {agg_name} in metric \
+                             sub_aggregations"
+                        ),
                     ))
                 })?;
             }
@@ -385,26 +462,52 @@ impl SegmentTermCollector {
             None
         };
 
+        let spilled = match spill {
+            Some(spill) if req.allow_spill == Some(true) => SpilledTermBuckets::new(
+                spill.clone(),
+                req.missing.clone().map(IntermediateKey::from),
+            ),
+            _ => SpilledTermBuckets::default(),
+        };
+
         Ok(SegmentTermCollector {
             req: TermsAggregationInternal::from_req(req),
             term_buckets,
             blueprint,
             column_type: field_type,
             accessor_idx,
+            spilled,
         })
     }
 
+    /// Spills the buckets collected so far to disk, and clears them.
+    fn spill_term_buckets(
+        &mut self,
+        agg_with_accessor: &mut AggregationWithAccessor,
+    ) -> crate::Result<()> {
+        self.term_buckets
+            .force_flush(&mut agg_with_accessor.sub_aggregation)?;
+        let entries: Vec<(u64, u32)> = std::mem::take(&mut self.term_buckets.entries)
+            .into_iter()
+            .collect();
+        let buckets = self.take_intermediate_entries(entries, agg_with_accessor, false)?;
+        self.term_buckets = TermBuckets::default();
+        self.spilled.spill_buckets(buckets.into_iter().collect())
+    }
+
     #[inline]
     pub(crate) fn into_intermediate_bucket_result(
         mut self,
         agg_with_accessor: &AggregationWithAccessor,
     ) -> crate::Result<IntermediateBucketResult> {
-        let mut entries: Vec<(u64, u32)> = self.term_buckets.entries.into_iter().collect();
+        let mut entries: Vec<(u64, u32)> = std::mem::take(&mut self.term_buckets.entries)
+            .into_iter()
+            .collect();
 
         let order_by_sub_aggregation =
             matches!(self.req.order.target, OrderTarget::SubAggregation(_));
 
-        match self.req.order.target {
+        match &self.req.order.target {
             OrderTarget::Key => {
                 // We rely on the fact, that term ordinals match the order of the strings
                 // TODO: We could have a special collector, that keeps only TOP n results at any
@@ -435,6 +538,28 @@ impl SegmentTermCollector {
             cut_off_buckets(&mut entries, self.req.segment_size as usize)
         };
 
+        let entries = self.take_intermediate_entries(entries, agg_with_accessor, true)?;
+        let mut buckets = IntermediateTermBucketResult {
+            entries,
+            sum_other_doc_count,
+            doc_count_error_upper_bound: term_doc_count_before_cutoff,
+            spilled: std::mem::take(&mut self.spilled),
+        };
+        buckets.spill_if_over_budget()?;
+        Ok(IntermediateBucketResult::Terms { buckets })
+    }
+
+    /// Converts the term ids or values of `entries` to the keys of the buckets, taking their
+    /// sub aggregations out of the term buckets.
+    ///
+    /// With `fill_zero_counts`, the buckets of the terms of the dictionary without documents are
+    /// added if the request has `min_doc_count: 0`.
+    fn take_intermediate_entries(
+        &mut self,
+        mut entries: Vec<(u64, u32)>,
+        agg_with_accessor: &AggregationWithAccessor,
+        fill_zero_counts: bool,
+    ) -> crate::Result<FxHashMap<IntermediateKey, IntermediateTermBucketEntry>> {
         let mut dict: FxHashMap<IntermediateKey, IntermediateTermBucketEntry> = Default::default();
         dict.reserve(entries.len());
 
@@ -530,7 +655,7 @@ impl SegmentTermCollector {
                 },
             )?;
 
-            if self.req.min_doc_count == 0 {
+            if fill_zero_counts && self.req.min_doc_count == 0 {
                 // TODO: Handle rev streaming for descending sorting by keys
                 let mut stream = term_dict.stream()?;
                 let empty_sub_aggregation = IntermediateAggregationResults::empty_from_req(
@@ -614,13 +739,7 @@ impl SegmentTermCollector {
             }
         };
 
-        Ok(IntermediateBucketResult::Terms {
-            buckets: IntermediateTermBucketResult {
-                entries: dict,
-                sum_other_doc_count,
-                doc_count_error_upper_bound: term_doc_count_before_cutoff,
-            },
-        })
+        Ok(dict)
     }
 }
 
@@ -669,10 +788,10 @@ mod tests {
         exec_request, exec_request_with_query, exec_request_with_query_and_memory_limit,
         get_test_index_from_terms, get_test_index_from_values_and_terms,
     };
-    use crate::aggregation::AggregationLimitsGuard;
+    use crate::aggregation::{AggregationError, AggregationErrorKind, AggregationLimitsGuard};
     use crate::indexer::NoMergePolicy;
     use crate::schema::{IntoIpv6Addr, Schema, FAST, STRING};
-    use crate::{Index, IndexWriter};
+    use crate::{Index, IndexWriter, TantivyError};
 
     #[test]
     fn terms_aggregation_test_single_segment() -> crate::Result<()> {
@@ -758,6 +877,92 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn terms_aggregation_fuzzy_key_merge() -> crate::Result<()> {
+        let terms = [
+            ("iphone", 6),
+            ("iphnoe", 2),
+            ("iphone1", 1),
+            ("ipone", 1),
+            ("galaxy", 4),
+            ("galaxi", 3),
+            ("galax", 1),
+            ("pixel", 2),
+            ("pixle", 1),
+        ];
+        let segment_and_terms: Vec<Vec<&str>> = terms
+            .iter()
+            .flat_map(|(term, count)| std::iter::repeat_n(vec![*term], *count))
+            .collect();
+        for merge_segments in [false, true] {
+            let index = get_test_index_from_terms(merge_segments, &segment_and_terms)?;
+            let agg_req: Aggregations = serde_json::from_value(json!({
+                "my_texts": {
+                    "terms": {
+                        "field": "string_id",
+                        "min_doc_count": 3,
+                        "fuzzy_key_merge": { "distance": 1, "transposition_cost_one": true },
+                    },
+                }
+            }))
+            .unwrap();
+            let res = exec_request(agg_req, &index)?;
+            // The merged buckets keep the key with the highest count.
+            assert_eq!(
+                res["my_texts"]["buckets"],
+                json!([
+                    { "key": "iphone", "doc_count": 10 },
+                    { "key": "galaxy", "doc_count": 8 },
+                    { "key": "pixel", "doc_count": 3 },
+                ])
+            );
+
+            // Without transpositions costing one, "iphnoe" and "pixle" are not merged.
+            let agg_req: Aggregations = serde_json::from_value(json!({
+                "my_texts": {
+                    "terms": {
+                        "field": "string_id",
+                        "size": 2,
+                        "fuzzy_key_merge": { "distance": 1 },
+                    },
+                }
+            }))
+            .unwrap();
+            let res = exec_request(agg_req, &index)?;
+            assert_eq!(
+                res["my_texts"]["buckets"],
+                json!([
+                    { "key": "galaxy", "doc_count": 8 },
+                    { "key": "iphone", "doc_count": 8 },
+                ])
+            );
+            assert_eq!(res["my_texts"]["sum_other_doc_count"], 5);
+        }
+
+        let index = get_test_index_from_terms(false, &segment_and_terms)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "my_texts": {
+                "terms": {
+                    "field": "string_id",
+                    "fuzzy_key_merge": { "distance": 3 },
+                },
+            }
+        }))
+        .unwrap();
+        let err = exec_request(agg_req, &index).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                TantivyError::AggregationError(AggregationError::InvalidAggregation {
+                    kind: AggregationErrorKind::InvalidParameter { ref name, .. },
+                    ..
+                }) if name == "fuzzy_key_merge"
+            ),
+            "{err:?}"
+        );
+        Ok(())
+    }
+
     #[test]
     fn terms_aggregation_test_order_count_single_segment() -> crate::Result<()> {
         terms_aggregation_test_order_count_merge_segment(true)
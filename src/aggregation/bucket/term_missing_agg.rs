@@ -77,6 +77,7 @@ impl SegmentAggregationCollector for TermMissingAgg {
                 entries,
                 sum_other_doc_count: 0,
                 doc_count_error_upper_bound: 0,
+                spilled: Default::default(),
             },
         };
 
@@ -375,7 +376,7 @@ mod tests {
         // text field
         assert_eq!(res["replace_null"]["buckets"][0]["key"], "NULL");
         assert_eq!(res["replace_null"]["buckets"][0]["doc_count"], 3);
-        assert_eq!(res["replace_num"]["buckets"][0]["key"], 1337.0);
+        assert_eq!(res["replace_num"]["buckets"][0]["key"], "1337");
         assert_eq!(res["replace_num"]["buckets"][0]["doc_count"], 3);
         assert_eq!(res["replace_null"]["sum_other_doc_count"], 0);
         assert_eq!(res["replace_null"]["doc_count_error_upper_bound"], 0);
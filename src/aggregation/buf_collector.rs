@@ -1,16 +1,64 @@
+use columnar::ColumnType;
+
 use super::agg_req_with_accessor::AggregationsWithAccessor;
 use super::intermediate_agg_result::IntermediateAggregationResults;
 use super::segment_agg_result::SegmentAggregationCollector;
-use crate::DocId;
+use crate::{DocId, ScratchVec, SearchScratch};
+
+/// The smallest number of documents buffered before calling `collect_block()`.
+pub(crate) const MIN_DOC_BLOCK_SIZE: usize = 32;
+/// The largest number of documents buffered before calling `collect_block()`.
+pub(crate) const MAX_DOC_BLOCK_SIZE: usize = 512;
+/// The number of column values we want to touch per block, summed over all aggregation nodes.
+const DOC_BLOCK_VALUE_BUDGET: usize = 512;
 
-pub(crate) const DOC_BLOCK_SIZE: usize = 64;
-pub(crate) type DocBlock = [DocId; DOC_BLOCK_SIZE];
+/// Chooses the block size for an aggregation tree.
+///
+/// Every block walks the whole aggregation tree, so a single cheap aggregation amortizes better
+/// with large blocks, while wide trees are better off with small blocks, which keep the fetched
+/// column values in cache.
+pub(crate) fn compute_block_size(aggs: &AggregationsWithAccessor) -> usize {
+    let cost = aggregation_cost(aggs).max(1);
+    let block_size = (DOC_BLOCK_VALUE_BUDGET / cost).max(1);
+    // Round down to a power of two.
+    let block_size = 1 << block_size.ilog2();
+    clamp_block_size(block_size)
+}
+
+/// Clamps a block size into `MIN_DOC_BLOCK_SIZE..=MAX_DOC_BLOCK_SIZE`.
+pub(crate) fn clamp_block_size(block_size: usize) -> usize {
+    block_size.clamp(MIN_DOC_BLOCK_SIZE, MAX_DOC_BLOCK_SIZE)
+}
+
+/// Rough estimation of the work done per document, summed over all nodes of the tree.
+fn aggregation_cost(aggs: &AggregationsWithAccessor) -> usize {
+    aggs.aggs
+        .iter()
+        .map(|(_, agg)| {
+            let column_cost = |column_type: ColumnType| match column_type {
+                // Term ordinals end up in hash maps and dictionary lookups.
+                ColumnType::Str | ColumnType::Bytes => 2,
+                _ => 1,
+            };
+            let node_cost = if agg.accessors.is_empty() {
+                column_cost(agg.field_type)
+            } else {
+                agg.accessors
+                    .iter()
+                    .map(|(_, column_type)| column_cost(*column_type))
+                    .sum()
+            };
+            node_cost + aggregation_cost(&agg.sub_aggregation)
+        })
+        .sum()
+}
 
 /// BufAggregationCollector buffers documents before calling collect_block().
 #[derive(Clone)]
 pub(crate) struct BufAggregationCollector {
     pub(crate) collector: Box<dyn SegmentAggregationCollector>,
-    staged_docs: DocBlock,
+    /// Buffer of `block_size` docs, taken from the scratch pool.
+    staged_docs: ScratchVec<DocId>,
     num_staged_docs: usize,
 }
 
@@ -24,13 +72,24 @@ impl std::fmt::Debug for BufAggregationCollector {
 }
 
 impl BufAggregationCollector {
-    pub fn new(collector: Box<dyn SegmentAggregationCollector>) -> Self {
+    /// Creates a new buffer of `block_size` docs, clamped to
+    /// `MIN_DOC_BLOCK_SIZE..=MAX_DOC_BLOCK_SIZE`.
+    pub fn new(collector: Box<dyn SegmentAggregationCollector>, block_size: usize) -> Self {
+        let block_size = clamp_block_size(block_size);
+        let mut staged_docs = SearchScratch::get(block_size);
+        staged_docs.resize(block_size, 0);
         Self {
             collector,
             num_staged_docs: 0,
-            staged_docs: [0; DOC_BLOCK_SIZE],
+            staged_docs,
         }
     }
+
+    /// The number of docs buffered before they are passed down as one block.
+    #[cfg(test)]
+    pub fn block_size(&self) -> usize {
+        self.staged_docs.len()
+    }
 }
 
 impl SegmentAggregationCollector for BufAggregationCollector {
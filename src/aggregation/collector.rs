@@ -1,15 +1,37 @@
-use super::agg_req::Aggregations;
+use itertools::Itertools;
+
+use super::agg_req::{requires_scoring, AggregationRequest, AggregationVariants, Aggregations};
 use super::agg_req_with_accessor::AggregationsWithAccessor;
 use super::agg_result::AggregationResults;
-use super::buf_collector::BufAggregationCollector;
-use super::intermediate_agg_result::IntermediateAggregationResults;
+use super::bucket::{pin_date_range_now, resolve_background_counts};
+use super::buf_collector::{clamp_block_size, compute_block_size, BufAggregationCollector};
+use super::intermediate_agg_result::{
+    merge_intermediate_tree_with_cancellation, IntermediateAggregationResult,
+    IntermediateAggregationResults, IntermediateMetricResult,
+};
+use super::metric::{
+    DocScores, IntermediateAverage, IntermediateCount, IntermediateMax, IntermediateMin,
+    IntermediateSum,
+};
+use super::sampling::{AggregationSampling, DocSampler};
 use super::segment_agg_result::{
-    build_segment_agg_collector, AggregationLimitsGuard, SegmentAggregationCollector,
+    build_segment_agg_collector, AggregationLimitsGuard, GenericSegmentAggregationResultsCollector,
+    ProfiledSegmentAggregationCollector, SegmentAggregationCollector,
 };
-use crate::aggregation::agg_req_with_accessor::get_aggs_with_segment_accessor_and_validate;
-use crate::collector::{Collector, SegmentCollector};
+use super::spill::AggregationSpill;
+use super::type_conflicts::check_segment_column_types;
+use super::value_transform::ValueTransformRegistry;
+use crate::aggregation::agg_req_with_accessor::{
+    get_aggs_with_segment_accessor_and_validate, missing_fast_fields, validate_fast_fields,
+};
+use crate::collector::{
+    collect_alive_docs, missing_column_error, Collector, MissingColumnPolicy, ProfileRecorder,
+    SegmentCollector,
+};
+use crate::fastfield::ColumnStats;
 use crate::index::SegmentReader;
-use crate::{DocId, SegmentOrdinal, TantivyError};
+use crate::query::Weight;
+use crate::{CancellationToken, DocId, Executor, Searcher, SegmentOrdinal, TantivyError};
 
 /// The default max bucket count, before the aggregation fails.
 pub const DEFAULT_BUCKET_LIMIT: u32 = 65000;
@@ -17,12 +39,20 @@ pub const DEFAULT_BUCKET_LIMIT: u32 = 65000;
 /// The default memory limit in bytes before the aggregation fails. 500MB
 pub const DEFAULT_MEMORY_LIMIT: u64 = 500_000_000;
 
+/// The fan-in of the tree merge of the segment results, when there are more segment results
+/// than this.
+const MERGE_TREE_FAN_IN: usize = 16;
+
 /// Collector for aggregations.
 ///
 /// The collector collects all aggregations by the underlying aggregation request.
 pub struct AggregationCollector {
     agg: Aggregations,
     limits: AggregationLimitsGuard,
+    value_transforms: ValueTransformRegistry,
+    missing_column_policy: Option<MissingColumnPolicy<f64>>,
+    cancellation: Option<CancellationToken>,
+    sampling: Option<AggregationSampling>,
 }
 
 impl AggregationCollector {
@@ -30,8 +60,90 @@ impl AggregationCollector {
     ///
     /// Aggregation fails when the limits in `AggregationLimits` is exceeded. (memory limit and
     /// bucket limit)
-    pub fn from_aggs(agg: Aggregations, limits: AggregationLimitsGuard) -> Self {
-        Self { agg, limits }
+    pub fn from_aggs(mut agg: Aggregations, limits: AggregationLimitsGuard) -> Self {
+        pin_date_range_now(&mut agg);
+        Self {
+            agg,
+            limits,
+            value_transforms: ValueTransformRegistry::default(),
+            missing_column_policy: None,
+            cancellation: None,
+            sampling: None,
+        }
+    }
+
+    /// Create collector from an aggregation request, with the options of the request.
+    ///
+    /// Aggregation fails when the limits in `AggregationLimits` is exceeded. (memory limit and
+    /// bucket limit)
+    pub fn from_request(request: AggregationRequest, limits: AggregationLimitsGuard) -> Self {
+        let mut collector = Self::from_aggs(request.aggs, limits);
+        collector.sampling = request.sampling;
+        collector
+    }
+
+    /// Samples the documents before they are aggregated, see [`AggregationSampling`].
+    pub fn with_sampling(mut self, sampling: AggregationSampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Sets the registry used to resolve the custom `value_transform`s of the request.
+    pub fn with_value_transforms(mut self, value_transforms: ValueTransformRegistry) -> Self {
+        self.value_transforms = value_transforms;
+        self
+    }
+
+    /// Sets the behavior for the segments without a column for a fast field read by the
+    /// aggregations.
+    ///
+    /// By default, such a segment is aggregated as if none of its documents had a value for the
+    /// field. With [`MissingColumnPolicy::Default`], all of its documents have the given value.
+    pub fn with_missing_column_policy(
+        mut self,
+        missing_column_policy: MissingColumnPolicy<f64>,
+    ) -> Self {
+        self.missing_column_policy = Some(missing_column_policy);
+        self
+    }
+
+    /// Sets the token cancelling the merge of the intermediate results of the segments.
+    ///
+    /// The merge fails with [`TantivyError::Cancelled`] once the token is cancelled. Pass the
+    /// same token to [`Searcher::search_cancellable()`](crate::Searcher::search_cancellable) to
+    /// cancel the collection of the segments as well.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Lets the terms aggregations with `allow_spill` spill their buckets to `spill` once they
+    /// exceed its memory budget, see [`AggregationSpill`].
+    ///
+    /// Without a spill, these aggregations keep all of their buckets in memory.
+    pub fn with_spill(mut self, spill: AggregationSpill) -> Self {
+        self.limits = self.limits.with_spill(spill);
+        self
+    }
+
+    /// Merges the segment fruits into the final result, resolving the background counts of the
+    /// `significant_terms` aggregations on `searcher` if it is known.
+    fn merge_fruits_and_finalize(
+        &self,
+        segment_fruits: Vec<crate::Result<IntermediateAggregationResults>>,
+        searcher: Option<&Searcher>,
+    ) -> crate::Result<AggregationResults> {
+        let mut res = merge_fruits(
+            &self.agg,
+            segment_fruits,
+            &self.limits,
+            self.cancellation.as_ref(),
+        )?;
+        check_cancellation(self.cancellation.as_ref())?;
+        if let Some(searcher) = searcher {
+            resolve_background_counts(&mut res, &self.agg, searcher)?;
+        }
+        res.into_final_result(self.agg.clone(), self.limits.clone())
     }
 }
 
@@ -46,6 +158,10 @@ impl AggregationCollector {
 pub struct DistributedAggregationCollector {
     agg: Aggregations,
     limits: AggregationLimitsGuard,
+    value_transforms: ValueTransformRegistry,
+    missing_column_policy: Option<MissingColumnPolicy<f64>>,
+    cancellation: Option<CancellationToken>,
+    sampling: Option<AggregationSampling>,
 }
 
 impl DistributedAggregationCollector {
@@ -53,8 +169,61 @@ impl DistributedAggregationCollector {
     ///
     /// Aggregation fails when the limits in `AggregationLimits` is exceeded. (memory limit and
     /// bucket limit)
-    pub fn from_aggs(agg: Aggregations, limits: AggregationLimitsGuard) -> Self {
-        Self { agg, limits }
+    pub fn from_aggs(mut agg: Aggregations, limits: AggregationLimitsGuard) -> Self {
+        pin_date_range_now(&mut agg);
+        Self {
+            agg,
+            limits,
+            value_transforms: ValueTransformRegistry::default(),
+            missing_column_policy: None,
+            cancellation: None,
+            sampling: None,
+        }
+    }
+
+    /// Create collector from an aggregation request, with the options of the request.
+    ///
+    /// Aggregation fails when the limits in `AggregationLimits` is exceeded. (memory limit and
+    /// bucket limit)
+    pub fn from_request(request: AggregationRequest, limits: AggregationLimitsGuard) -> Self {
+        let mut collector = Self::from_aggs(request.aggs, limits);
+        collector.sampling = request.sampling;
+        collector
+    }
+
+    /// Samples the documents before they are aggregated, see [`AggregationSampling`].
+    pub fn with_sampling(mut self, sampling: AggregationSampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Sets the registry used to resolve the custom `value_transform`s of the request.
+    pub fn with_value_transforms(mut self, value_transforms: ValueTransformRegistry) -> Self {
+        self.value_transforms = value_transforms;
+        self
+    }
+
+    /// Sets the behavior for the segments without a column for a fast field read by the
+    /// aggregations.
+    ///
+    /// By default, such a segment is aggregated as if none of its documents had a value for the
+    /// field. With [`MissingColumnPolicy::Default`], all of its documents have the given value.
+    pub fn with_missing_column_policy(
+        mut self,
+        missing_column_policy: MissingColumnPolicy<f64>,
+    ) -> Self {
+        self.missing_column_policy = Some(missing_column_policy);
+        self
+    }
+
+    /// Sets the token cancelling the merge of the intermediate results of the segments.
+    ///
+    /// The merge fails with [`TantivyError::Cancelled`] once the token is cancelled. Pass the
+    /// same token to [`Searcher::search_cancellable()`](crate::Searcher::search_cancellable) to
+    /// cancel the collection of the segments as well.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
     }
 }
 
@@ -68,23 +237,74 @@ impl Collector for DistributedAggregationCollector {
         segment_local_id: crate::SegmentOrdinal,
         reader: &crate::SegmentReader,
     ) -> crate::Result<Self::Child> {
-        AggregationSegmentCollector::from_agg_req_and_reader(
+        AggregationSegmentCollector::from_agg_req_and_reader_with_options(
             &self.agg,
             reader,
             segment_local_id,
             &self.limits,
-        )
+            &self.value_transforms,
+            self.missing_column_policy.as_ref(),
+        )?
+        .with_sampling(self.sampling.as_ref(), reader)
     }
 
     fn requires_scoring(&self) -> bool {
-        false
+        requires_scoring(&self.agg)
     }
 
     fn merge_fruits(
         &self,
         segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
     ) -> crate::Result<Self::Fruit> {
-        merge_fruits(segment_fruits)
+        merge_fruits(
+            &self.agg,
+            segment_fruits,
+            &self.limits,
+            self.cancellation.as_ref(),
+        )
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        let mut res = self.merge_fruits(segment_fruits)?;
+        resolve_background_counts(&mut res, &self.agg, searcher)?;
+        Ok(res)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        collect_segment(
+            self,
+            &self.agg,
+            self.missing_column_policy.as_ref(),
+            weight,
+            segment_ord,
+            reader,
+        )
+    }
+
+    fn merges_incrementally(&self) -> bool {
+        true
+    }
+
+    fn merge_segment_fruit(
+        &self,
+        merged_fruit: crate::Result<IntermediateAggregationResults>,
+        segment_fruit: crate::Result<IntermediateAggregationResults>,
+    ) -> crate::Result<crate::Result<IntermediateAggregationResults>> {
+        check_cancellation(self.cancellation.as_ref())?;
+        Ok(merge_segment_fruit(
+            merged_fruit,
+            segment_fruit,
+            &self.limits,
+        ))
     }
 }
 
@@ -98,33 +318,228 @@ impl Collector for AggregationCollector {
         segment_local_id: crate::SegmentOrdinal,
         reader: &crate::SegmentReader,
     ) -> crate::Result<Self::Child> {
-        AggregationSegmentCollector::from_agg_req_and_reader(
+        AggregationSegmentCollector::from_agg_req_and_reader_with_options(
             &self.agg,
             reader,
             segment_local_id,
             &self.limits,
-        )
+            &self.value_transforms,
+            self.missing_column_policy.as_ref(),
+        )?
+        .with_sampling(self.sampling.as_ref(), reader)
     }
 
     fn requires_scoring(&self) -> bool {
-        false
+        requires_scoring(&self.agg)
     }
 
     fn merge_fruits(
         &self,
         segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
     ) -> crate::Result<Self::Fruit> {
-        let res = merge_fruits(segment_fruits)?;
-        res.into_final_result(self.agg.clone(), self.limits.clone())
+        self.merge_fruits_and_finalize(segment_fruits, None)
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        self.merge_fruits_and_finalize(segment_fruits, Some(searcher))
     }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        collect_segment(
+            self,
+            &self.agg,
+            self.missing_column_policy.as_ref(),
+            weight,
+            segment_ord,
+            reader,
+        )
+    }
+
+    fn merges_incrementally(&self) -> bool {
+        true
+    }
+
+    fn merge_segment_fruit(
+        &self,
+        merged_fruit: crate::Result<IntermediateAggregationResults>,
+        segment_fruit: crate::Result<IntermediateAggregationResults>,
+    ) -> crate::Result<crate::Result<IntermediateAggregationResults>> {
+        check_cancellation(self.cancellation.as_ref())?;
+        Ok(merge_segment_fruit(
+            merged_fruit,
+            segment_fruit,
+            &self.limits,
+        ))
+    }
+}
+
+/// Collects a segment, answering the aggregations from the column statistics of the segment if
+/// they allow it, see [`intermediate_results_from_column_stats`].
+fn collect_segment<C: Collector<Child = AggregationSegmentCollector>>(
+    collector: &C,
+    agg: &Aggregations,
+    missing_column_policy: Option<&MissingColumnPolicy<f64>>,
+    weight: &dyn Weight,
+    segment_ord: u32,
+    reader: &SegmentReader,
+) -> crate::Result<crate::Result<IntermediateAggregationResults>> {
+    // The segment collector is created anyway, to validate the request against the segment.
+    let mut segment_collector = collector.for_segment(segment_ord, reader)?;
+    if weight.matches_all_docs()
+        && missing_column_policy.is_none()
+        && segment_collector.sampler.is_none()
+    {
+        if let Some(mut results) = intermediate_results_from_column_stats(agg, reader) {
+            results.warnings = segment_collector.warnings;
+            return Ok(Ok(results));
+        }
+    }
+    collect_alive_docs(
+        weight,
+        reader,
+        collector.requires_scoring(),
+        &mut segment_collector,
+    )?;
+    Ok(segment_collector.harvest())
+}
+
+/// Computes the intermediate results of a segment whose documents all match from the
+/// [`ColumnStats`] of its fields, without reading the columns.
+///
+/// Returns `None` unless the segment has no deletes, and all of the aggregations are
+/// `avg`, `value_count`, `max`, `min`, `stats`, `sum` or `percentiles` (with the default
+/// compression) aggregations over a field with column statistics, without any option changing
+/// the values read.
+fn intermediate_results_from_column_stats(
+    agg: &Aggregations,
+    reader: &SegmentReader,
+) -> Option<IntermediateAggregationResults> {
+    if reader.alive_bitset().is_some() {
+        return None;
+    }
+    let column_stats = |field_name: &str| -> Option<&ColumnStats> {
+        let field = reader.schema().get_field(field_name).ok()?;
+        reader.column_stats(field)
+    };
+    let mut results = IntermediateAggregationResults::default();
+    // The results of the segments are merged in the iteration order of their maps, so they are
+    // inserted in the sorted order of the names, as the segment collectors do.
+    for (name, aggregation) in agg.iter().sorted_by_key(|(name, _)| *name) {
+        if aggregation.field_union.is_some() || aggregation.agg.token_count_field().is_some() {
+            return None;
+        }
+        let metric_result = match &aggregation.agg {
+            AggregationVariants::Average(req)
+                if req.missing.is_none() && req.value_transform.is_none() && req.mode.is_none() =>
+            {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Average(IntermediateAverage::from_stats(stats))
+            }
+            AggregationVariants::Count(req) if req.missing.is_none() && req.mode.is_none() => {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Count(IntermediateCount::from_stats(stats))
+            }
+            AggregationVariants::Max(req)
+                if req.missing.is_none() && req.value_transform.is_none() && req.mode.is_none() =>
+            {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Max(IntermediateMax::from_stats(stats))
+            }
+            AggregationVariants::Min(req)
+                if req.missing.is_none() && req.value_transform.is_none() && req.mode.is_none() =>
+            {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Min(IntermediateMin::from_stats(stats))
+            }
+            AggregationVariants::Stats(req)
+                if req.missing.is_none() && req.value_transform.is_none() && req.mode.is_none() =>
+            {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Stats(stats)
+            }
+            AggregationVariants::Sum(req)
+                if req.missing.is_none() && req.value_transform.is_none() && req.mode.is_none() =>
+            {
+                let stats = column_stats(&req.field)?.intermediate_stats().clone();
+                IntermediateMetricResult::Sum(IntermediateSum::from_stats(stats))
+            }
+            AggregationVariants::Percentiles(req)
+                if req.missing.is_none()
+                    && req.value_transform.is_none()
+                    && req.mode.is_none()
+                    && req.has_default_compression() =>
+            {
+                let percentiles = column_stats(&req.field)?.percentiles().clone();
+                IntermediateMetricResult::Percentiles(percentiles)
+            }
+            _ => return None,
+        };
+        results
+            .push(
+                name.to_string(),
+                IntermediateAggregationResult::Metric(metric_result),
+            )
+            .ok()?;
+    }
+    Some(results)
 }
 
+/// Merges the intermediate results of a segment into the merged results of other segments.
+///
+/// The merged results are checked against the memory limit, on top of the memory used by the
+/// segments being collected. Their memory stays charged until they are dropped.
+fn merge_segment_fruit(
+    merged_fruit: crate::Result<IntermediateAggregationResults>,
+    segment_fruit: crate::Result<IntermediateAggregationResults>,
+    limits: &AggregationLimitsGuard,
+) -> crate::Result<IntermediateAggregationResults> {
+    let mut merged_fruit = merged_fruit?;
+    merged_fruit.merge_fruits(segment_fruit?)?;
+    let memory_consumption = merged_fruit.memory_consumption() as u64;
+    merged_fruit
+        .charged_memory
+        .charge(limits, memory_consumption)?;
+    Ok(merged_fruit)
+}
+
+fn check_cancellation(cancellation: Option<&CancellationToken>) -> crate::Result<()> {
+    cancellation.map_or(Ok(()), CancellationToken::check)
+}
+
+/// Merges the results of the segments. Many results are merged as a tree, so that the memory of
+/// each merge is accounted for.
 fn merge_fruits(
+    agg: &Aggregations,
     mut segment_fruits: Vec<crate::Result<IntermediateAggregationResults>>,
+    limits: &AggregationLimitsGuard,
+    cancellation: Option<&CancellationToken>,
 ) -> crate::Result<IntermediateAggregationResults> {
+    if segment_fruits.len() > MERGE_TREE_FAN_IN {
+        let segment_fruits = segment_fruits
+            .into_iter()
+            .collect::<crate::Result<Vec<_>>>()?;
+        return merge_intermediate_tree_with_cancellation(
+            agg,
+            segment_fruits,
+            MERGE_TREE_FAN_IN,
+            limits,
+            &Executor::single_thread(),
+            cancellation,
+        );
+    }
     if let Some(fruit) = segment_fruits.pop() {
         let mut fruit = fruit?;
         for next_fruit in segment_fruits {
+            check_cancellation(cancellation)?;
             fruit.merge_fruits(next_fruit?)?;
         }
         Ok(fruit)
@@ -133,11 +548,34 @@ fn merge_fruits(
     }
 }
 
+/// Creates the scores of the documents of the segment, if an aggregation of `agg` sorts by
+/// `_score`.
+pub(crate) fn doc_scores_for_aggs(
+    agg: &Aggregations,
+    aggs_with_accessor: &mut AggregationsWithAccessor,
+    limits: &AggregationLimitsGuard,
+) -> Option<DocScores> {
+    if !requires_scoring(agg) {
+        return None;
+    }
+    let doc_scores = DocScores::new(limits);
+    aggs_with_accessor.set_doc_scores(&doc_scores);
+    Some(doc_scores)
+}
+
 /// `AggregationSegmentCollector` does the aggregation collection on a segment.
 pub struct AggregationSegmentCollector {
     aggs_with_accessor: AggregationsWithAccessor,
     agg_collector: BufAggregationCollector,
+    doc_scores: Option<DocScores>,
+    warnings: Vec<String>,
     error: Option<TantivyError>,
+    /// Set if the segment is skipped, with [`MissingColumnPolicy::SkipSegment`].
+    skip_segment: bool,
+    /// Set if the documents are sampled, see [`AggregationSampling`].
+    sampler: Option<DocSampler>,
+    /// Buffer of the sampled documents of a block.
+    sampled_block: Vec<DocId>,
 }
 
 impl AggregationSegmentCollector {
@@ -149,26 +587,127 @@ impl AggregationSegmentCollector {
         segment_ordinal: SegmentOrdinal,
         limits: &AggregationLimitsGuard,
     ) -> crate::Result<Self> {
-        let mut aggs_with_accessor =
-            get_aggs_with_segment_accessor_and_validate(agg, reader, segment_ordinal, limits)?;
-        let result =
-            BufAggregationCollector::new(build_segment_agg_collector(&mut aggs_with_accessor)?);
+        Self::from_agg_req_and_reader_with_value_transforms(
+            agg,
+            reader,
+            segment_ordinal,
+            limits,
+            &ValueTransformRegistry::default(),
+        )
+    }
+
+    /// Same as [`AggregationSegmentCollector::from_agg_req_and_reader`], resolving custom
+    /// `value_transform`s with the given registry.
+    pub fn from_agg_req_and_reader_with_value_transforms(
+        agg: &Aggregations,
+        reader: &SegmentReader,
+        segment_ordinal: SegmentOrdinal,
+        limits: &AggregationLimitsGuard,
+        value_transforms: &ValueTransformRegistry,
+    ) -> crate::Result<Self> {
+        Self::from_agg_req_and_reader_with_options(
+            agg,
+            reader,
+            segment_ordinal,
+            limits,
+            value_transforms,
+            None,
+        )
+    }
+
+    /// Same as [`AggregationSegmentCollector::from_agg_req_and_reader_with_value_transforms`],
+    /// applying `missing_column_policy` if the segment has no column for one of the fast fields
+    /// of the request.
+    pub(crate) fn from_agg_req_and_reader_with_options(
+        agg: &Aggregations,
+        reader: &SegmentReader,
+        segment_ordinal: SegmentOrdinal,
+        limits: &AggregationLimitsGuard,
+        value_transforms: &ValueTransformRegistry,
+        missing_column_policy: Option<&MissingColumnPolicy<f64>>,
+    ) -> crate::Result<Self> {
+        validate_fast_fields(agg, reader)?;
+        let mut missing_column_default = None;
+        let mut skip_segment = false;
+        if let Some(missing_column_policy) = missing_column_policy {
+            if let Some(field_name) = missing_fast_fields(agg, reader)?.first() {
+                match missing_column_policy {
+                    MissingColumnPolicy::Error => {
+                        return Err(missing_column_error(reader, field_name));
+                    }
+                    MissingColumnPolicy::Default(value) => missing_column_default = Some(*value),
+                    MissingColumnPolicy::SkipSegment => skip_segment = true,
+                }
+            }
+        }
+        let warnings = check_segment_column_types(agg, reader)?;
+        let mut aggs_with_accessor = get_aggs_with_segment_accessor_and_validate(
+            agg,
+            reader,
+            segment_ordinal,
+            limits,
+            value_transforms,
+            missing_column_default,
+        )?;
+        let doc_scores = doc_scores_for_aggs(agg, &mut aggs_with_accessor, limits);
+        let block_size = limits
+            .block_size()
+            .unwrap_or_else(|| compute_block_size(&aggs_with_accessor));
+        aggs_with_accessor.take_block_buffers(clamp_block_size(block_size));
+        let result = BufAggregationCollector::new(
+            build_segment_agg_collector(&mut aggs_with_accessor)?,
+            block_size,
+        );
         Ok(AggregationSegmentCollector {
             aggs_with_accessor,
             agg_collector: result,
+            doc_scores,
+            warnings,
             error: None,
+            skip_segment,
+            sampler: None,
+            sampled_block: Vec::new(),
         })
     }
+
+    /// Samples the documents of the segment before they are aggregated, if `sampling` is set.
+    pub(crate) fn with_sampling(
+        mut self,
+        sampling: Option<&AggregationSampling>,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self> {
+        if let Some(sampling) = sampling {
+            sampling.validate()?;
+            self.sampler = Some(DocSampler::new(sampling, reader.segment_id()));
+        }
+        Ok(self)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn block_size(&self) -> usize {
+        self.agg_collector.block_size()
+    }
 }
 
 impl SegmentCollector for AggregationSegmentCollector {
     type Fruit = crate::Result<IntermediateAggregationResults>;
 
     #[inline]
-    fn collect(&mut self, doc: DocId, _score: crate::Score) {
-        if self.error.is_some() {
+    fn collect(&mut self, doc: DocId, score: crate::Score) {
+        if self.error.is_some() || self.skip_segment {
             return;
         }
+        if let Some(sampler) = &mut self.sampler {
+            if !sampler.sample(doc) {
+                return;
+            }
+        }
+        if let Some(doc_scores) = &self.doc_scores {
+            if let Err(err) = doc_scores.record(doc, score) {
+                self.error = Some(err);
+                return;
+            }
+        }
         if let Err(err) = self
             .agg_collector
             .collect(doc, &mut self.aggs_with_accessor)
@@ -181,9 +720,15 @@ impl SegmentCollector for AggregationSegmentCollector {
     ///
     /// Only valid for Collectors that ignore docs
     fn collect_block(&mut self, docs: &[DocId]) {
-        if self.error.is_some() {
+        if self.error.is_some() || self.skip_segment {
             return;
         }
+        let docs = if let Some(sampler) = &mut self.sampler {
+            sampler.sample_block(docs, &mut self.sampled_block);
+            &self.sampled_block
+        } else {
+            docs
+        };
         if let Err(err) = self
             .agg_collector
             .collect_block(docs, &mut self.aggs_with_accessor)
@@ -192,6 +737,21 @@ impl SegmentCollector for AggregationSegmentCollector {
         }
     }
 
+    /// Replaces the collector tree by one measuring the time spent in each top-level
+    /// aggregation.
+    fn enable_profiling(&mut self, recorder: ProfileRecorder) {
+        let collector = std::mem::replace(
+            &mut self.agg_collector.collector,
+            Box::new(GenericSegmentAggregationResultsCollector::default()),
+        );
+        self.agg_collector.collector =
+            Box::new(ProfiledSegmentAggregationCollector::from_collector(
+                collector,
+                &self.aggs_with_accessor,
+                recorder,
+            ));
+    }
+
     fn harvest(mut self) -> Self::Fruit {
         if let Some(err) = self.error {
             return Err(err);
@@ -203,7 +763,141 @@ impl SegmentCollector for AggregationSegmentCollector {
             &self.aggs_with_accessor,
             &mut sub_aggregation_res,
         )?;
+        sub_aggregation_res.warnings = self.warnings;
+        sub_aggregation_res.sampled = self.sampler.map(|sampler| sampler.sampled_docs());
 
         Ok(sub_aggregation_res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::intermediate_results_from_column_stats;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, Query, RangeQuery};
+    use crate::schema::{Schema, FAST, INDEXED};
+    use crate::{DateTime, Index, IndexSettings, IndexWriter, Term};
+
+    fn create_index(column_stats: bool) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let value = schema_builder.add_f64_field("value", FAST | INDEXED);
+        let date = schema_builder.add_date_field("date", FAST);
+        let settings = IndexSettings {
+            column_stats_fields: if column_stats {
+                vec!["value".to_string(), "date".to_string()]
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        };
+        let index = Index::builder()
+            .schema(schema_builder.build())
+            .settings(settings)
+            .create_in_ram()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment in 0..3 {
+            for doc in 0..50 {
+                let value_f64 = (segment * 50 + doc) as f64 * 1.5 - 20.0;
+                let timestamp = DateTime::from_timestamp_secs(1_700_000_000 + doc * 60);
+                if doc % 10 == 0 {
+                    // Multivalued documents, and documents without a date.
+                    index_writer.add_document(doc!(value => value_f64, value => -value_f64))?;
+                } else {
+                    index_writer.add_document(doc!(value => value_f64, date => timestamp))?;
+                }
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn aggregations() -> Aggregations {
+        serde_json::from_value(json!({
+            "avg": { "avg": { "field": "value" } },
+            "count": { "value_count": { "field": "value" } },
+            "min": { "min": { "field": "value" } },
+            "max": { "max": { "field": "value" } },
+            "stats": { "stats": { "field": "value" } },
+            "sum": { "sum": { "field": "value" } },
+            "percentiles": { "percentiles": { "field": "value" } },
+            "date_stats": { "stats": { "field": "date", "format": "rfc3339" } }
+        }))
+        .unwrap()
+    }
+
+    fn aggregate(index: &Index, query: &dyn Query) -> crate::Result<Value> {
+        let collector = AggregationCollector::from_aggs(aggregations(), Default::default());
+        let results = index.reader()?.searcher().search(query, &collector)?;
+        Ok(serde_json::to_value(results)?)
+    }
+
+    fn num_segments_answered_from_column_stats(index: &Index) -> crate::Result<usize> {
+        let searcher = index.reader()?.searcher();
+        Ok(searcher
+            .segment_readers()
+            .iter()
+            .filter(|segment_reader| {
+                intermediate_results_from_column_stats(&aggregations(), segment_reader).is_some()
+            })
+            .count())
+    }
+
+    #[test]
+    fn test_aggregation_from_column_stats() -> crate::Result<()> {
+        let index_with_stats = create_index(true)?;
+        let index_without_stats = create_index(false)?;
+        assert_eq!(
+            num_segments_answered_from_column_stats(&index_with_stats)?,
+            3
+        );
+        assert_eq!(
+            num_segments_answered_from_column_stats(&index_without_stats)?,
+            0
+        );
+        let results = aggregate(&index_with_stats, &AllQuery)?;
+        assert_eq!(results, aggregate(&index_without_stats, &AllQuery)?);
+        assert_eq!(results["count"]["value"], 165.0);
+
+        // Requests changing the values read are collected.
+        let aggs: Aggregations = serde_json::from_value(json!({
+            "avg": { "avg": { "field": "value", "missing": 0.0 } },
+        }))
+        .unwrap();
+        let searcher = index_with_stats.reader()?.searcher();
+        assert!(
+            intermediate_results_from_column_stats(&aggs, searcher.segment_reader(0)).is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregation_from_column_stats_with_deletes() -> crate::Result<()> {
+        let mut results = Vec::new();
+        for column_stats in [true, false] {
+            let index = create_index(column_stats)?;
+            let value = index.schema().get_field("value")?;
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.delete_term(Term::from_field_f64(value, -20.0))?;
+            index_writer.commit()?;
+            // The segment with deletes is collected.
+            let num_segments = num_segments_answered_from_column_stats(&index)?;
+            assert_eq!(num_segments, if column_stats { 2 } else { 0 });
+            results.push(aggregate(&index, &AllQuery)?);
+            // So are the queries that do not match all of the documents.
+            let range_query = RangeQuery::new(
+                std::ops::Bound::Included(Term::from_field_f64(value, -1000.0)),
+                std::ops::Bound::Included(Term::from_field_f64(value, 1000.0)),
+            );
+            results.push(aggregate(&index, &range_query)?);
+        }
+        assert_eq!(results[0], results[2]);
+        assert_eq!(results[1], results[3]);
+        assert_eq!(results[0]["count"]["value"], 163.0);
+        Ok(())
+    }
+}
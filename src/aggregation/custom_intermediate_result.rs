@@ -0,0 +1,342 @@
+//! Custom intermediate aggregation results, merged and serialized alongside the built-in ones.
+//!
+//! Aggregations implemented outside of tantivy can push their intermediate results into
+//! [`IntermediateAggregationResults`](super::intermediate_agg_result::IntermediateAggregationResults)
+//! as [`IntermediateAggregationResult::Custom`](super::intermediate_agg_result::IntermediateAggregationResult::Custom).
+//! They are then merged with [`CustomIntermediateAggregation::merge`] and converted into the final
+//! result with [`CustomIntermediateAggregation::finalize`], like the results of the built-in
+//! aggregations.
+//!
+//! A custom result is serialized as its type tag and the payload returned by
+//! [`CustomIntermediateAggregation::serialize`]. Deserializing it requires a deserializer to be
+//! registered for its type tag with [`register_custom_intermediate_aggregation`], on every node
+//! deserializing intermediate results.
+//!
+//! Custom results are not part of the aggregation request: they are finalized whatever the
+//! request passed to
+//! [`IntermediateAggregationResults::into_final_result`](super::intermediate_agg_result::IntermediateAggregationResults::into_final_result).
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::AggregationError;
+use crate::TantivyError;
+
+/// Deserializes the payload of a custom intermediate result.
+pub type CustomIntermediateAggregationDeserializer =
+    Arc<dyn Fn(&[u8]) -> crate::Result<Box<dyn CustomIntermediateAggregation>> + Send + Sync>;
+
+static DESERIALIZERS: Lazy<RwLock<HashMap<String, CustomIntermediateAggregationDeserializer>>> =
+    Lazy::new(Default::default);
+
+/// Registers the deserializer of the custom intermediate results of type `type_tag`.
+///
+/// If a deserializer was already registered for this type tag, it is replaced.
+pub fn register_custom_intermediate_aggregation(
+    type_tag: &str,
+    deserializer: CustomIntermediateAggregationDeserializer,
+) {
+    DESERIALIZERS
+        .write()
+        .unwrap()
+        .insert(type_tag.to_string(), deserializer);
+}
+
+fn get_deserializer(type_tag: &str) -> Option<CustomIntermediateAggregationDeserializer> {
+    DESERIALIZERS.read().unwrap().get(type_tag).cloned()
+}
+
+/// Intermediate result of an aggregation implemented outside of tantivy.
+///
+/// See the [module documentation](self).
+pub trait CustomIntermediateAggregation:
+    CustomIntermediateAggregationClone + fmt::Debug + Send + Sync + 'static
+{
+    /// The type tag of the result, for which a deserializer is registered with
+    /// [`register_custom_intermediate_aggregation`].
+    fn type_tag(&self) -> &str;
+
+    /// Serializes the result into a payload, which the registered deserializer reads back.
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Merges another result of the same aggregation into this result.
+    ///
+    /// `other` has the same type tag as `self`, and can be downcast via
+    /// [`CustomIntermediateAggregation::as_any`].
+    fn merge(&mut self, other: &dyn CustomIntermediateAggregation) -> crate::Result<()>;
+
+    /// Converts the result into the final result of the aggregation.
+    fn finalize(&self) -> serde_json::Value;
+
+    /// Returns the result as `Any`, so that it can be downcast to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Used to clone a boxed [`CustomIntermediateAggregation`].
+pub trait CustomIntermediateAggregationClone {
+    /// Returns a boxed clone of `self`.
+    fn box_clone(&self) -> Box<dyn CustomIntermediateAggregation>;
+}
+
+impl<T> CustomIntermediateAggregationClone for T
+where T: CustomIntermediateAggregation + Clone
+{
+    fn box_clone(&self) -> Box<dyn CustomIntermediateAggregation> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn CustomIntermediateAggregation> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+impl PartialEq for dyn CustomIntermediateAggregation {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_tag() == other.type_tag() && self.serialize() == other.serialize()
+    }
+}
+
+/// Merges `right` into `left`, checking that they have the same type.
+pub(crate) fn merge_custom(
+    left: &mut dyn CustomIntermediateAggregation,
+    right: &dyn CustomIntermediateAggregation,
+) -> crate::Result<()> {
+    if left.type_tag() != right.type_tag() {
+        return Err(TantivyError::AggregationError(
+            AggregationError::InternalError(format!(
+                "Cannot merge custom intermediate aggregation results of type {:?} and {:?}",
+                left.type_tag(),
+                right.type_tag()
+            )),
+        ));
+    }
+    left.merge(right)
+}
+
+#[derive(Serialize)]
+struct SerializedCustomRef<'a> {
+    type_tag: &'a str,
+    payload: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct SerializedCustom {
+    type_tag: String,
+    payload: Vec<u8>,
+}
+
+// The signature is the one expected by `serialize_with`.
+#[allow(clippy::borrowed_box)]
+pub(crate) fn serialize<S: Serializer>(
+    custom: &Box<dyn CustomIntermediateAggregation>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    SerializedCustomRef {
+        type_tag: custom.type_tag(),
+        payload: custom.serialize(),
+    }
+    .serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Box<dyn CustomIntermediateAggregation>, D::Error> {
+    let SerializedCustom { type_tag, payload } = SerializedCustom::deserialize(deserializer)?;
+    let custom_deserializer = get_deserializer(&type_tag).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "Unknown custom intermediate aggregation type tag {type_tag:?}: its deserializer \
+             needs to be registered with `register_custom_intermediate_aggregation`"
+        ))
+    })?;
+    custom_deserializer(&payload).map_err(|err| {
+        serde::de::Error::custom(format!(
+            "Failed to deserialize custom intermediate aggregation of type tag {type_tag:?}: {err}"
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::{register_custom_intermediate_aggregation, CustomIntermediateAggregation};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::intermediate_agg_result::{
+        IntermediateAggregationResult, IntermediateAggregationResults,
+    };
+    use crate::aggregation::{AggregationLimitsGuard, DistributedAggregationCollector};
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST};
+    use crate::{Index, IndexWriter, TantivyError};
+
+    /// Toy aggregation collecting the distinct values of a field.
+    #[derive(Clone, Debug, Default)]
+    struct DistinctValues(BTreeSet<u64>);
+
+    impl CustomIntermediateAggregation for DistinctValues {
+        fn type_tag(&self) -> &str {
+            "distinct_values"
+        }
+
+        fn serialize(&self) -> Vec<u8> {
+            self.0.iter().flat_map(|val| val.to_le_bytes()).collect()
+        }
+
+        fn merge(&mut self, other: &dyn CustomIntermediateAggregation) -> crate::Result<()> {
+            let other = other.as_any().downcast_ref::<DistinctValues>().unwrap();
+            self.0.extend(other.0.iter().copied());
+            Ok(())
+        }
+
+        fn finalize(&self) -> serde_json::Value {
+            json!({ "count": self.0.len(), "values": self.0 })
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    fn deserialize_distinct_values(
+        payload: &[u8],
+    ) -> crate::Result<Box<dyn CustomIntermediateAggregation>> {
+        if payload.len() % 8 != 0 {
+            return Err(TantivyError::InvalidArgument(
+                "Invalid distinct values payload".to_string(),
+            ));
+        }
+        let values = payload
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok(Box::new(DistinctValues(values)))
+    }
+
+    /// Runs the built-in `stats` aggregation on a shard, and adds the distinct values of the shard
+    /// as a custom result, serializing the intermediate results as a shard would.
+    fn shard_results(values: &[u64], agg_req: &Aggregations) -> crate::Result<Vec<u8>> {
+        let mut schema_builder = Schema::builder();
+        let value_field = schema_builder.add_u64_field("value", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for &value in values {
+            index_writer.add_document(doc!(value_field => value))?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let collector = DistributedAggregationCollector::from_aggs(
+            agg_req.clone(),
+            AggregationLimitsGuard::default(),
+        );
+        let mut intermediate_results = searcher.search(&AllQuery, &collector)?;
+        let distinct_values = DistinctValues(values.iter().copied().collect());
+        intermediate_results.push(
+            "distinct_values".to_string(),
+            IntermediateAggregationResult::Custom(Box::new(distinct_values)),
+        )?;
+        Ok(postcard::to_allocvec(&intermediate_results).unwrap())
+    }
+
+    #[test]
+    fn test_custom_intermediate_aggregation_distributed_merge() -> crate::Result<()> {
+        register_custom_intermediate_aggregation(
+            "distinct_values",
+            Arc::new(deserialize_distinct_values),
+        );
+        let agg_req: Aggregations =
+            serde_json::from_value(json!({ "stats": { "stats": { "field": "value" } } })).unwrap();
+        let shards = [vec![1, 2, 3], vec![3, 4], vec![4, 5, 5, 10]];
+        let mut merged_results: Option<IntermediateAggregationResults> = None;
+        for shard_values in &shards {
+            let shard_bytes = shard_results(shard_values, &agg_req)?;
+            let intermediate_results: IntermediateAggregationResults =
+                postcard::from_bytes(&shard_bytes).unwrap();
+            match merged_results.as_mut() {
+                Some(merged_results) => merged_results.merge_fruits(intermediate_results)?,
+                None => merged_results = Some(intermediate_results),
+            }
+        }
+        let final_results = merged_results
+            .unwrap()
+            .into_final_result(agg_req, AggregationLimitsGuard::default())?;
+        let final_results = serde_json::to_value(final_results)?;
+        assert_eq!(
+            final_results["distinct_values"],
+            json!({ "count": 6, "values": [1, 2, 3, 4, 5, 10] })
+        );
+        assert_eq!(final_results["stats"]["count"], 9);
+        assert_eq!(final_results["stats"]["sum"], 37.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_intermediate_aggregation_unknown_tag() {
+        #[derive(Clone, Debug)]
+        struct Unregistered;
+
+        impl CustomIntermediateAggregation for Unregistered {
+            fn type_tag(&self) -> &str {
+                "unregistered_custom_aggregation"
+            }
+
+            fn serialize(&self) -> Vec<u8> {
+                Vec::new()
+            }
+
+            fn merge(&mut self, _other: &dyn CustomIntermediateAggregation) -> crate::Result<()> {
+                Ok(())
+            }
+
+            fn finalize(&self) -> serde_json::Value {
+                serde_json::Value::Null
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        let mut intermediate_results = IntermediateAggregationResults::default();
+        intermediate_results
+            .push(
+                "custom".to_string(),
+                IntermediateAggregationResult::Custom(Box::new(Unregistered)),
+            )
+            .unwrap();
+        let bytes = postcard::to_allocvec(&intermediate_results).unwrap();
+        let err = serde_json::from_value::<IntermediateAggregationResults>(
+            serde_json::to_value(&intermediate_results).unwrap(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(r#"Unknown custom intermediate aggregation type tag "unregistered_custom_aggregation""#),
+            "{err}"
+        );
+        assert!(postcard::from_bytes::<IntermediateAggregationResults>(&bytes).is_err());
+        // A custom result cannot be merged with a result of another type.
+        let mut unregistered = intermediate_results.clone();
+        let mut other = IntermediateAggregationResults::default();
+        other
+            .push(
+                "custom".to_string(),
+                IntermediateAggregationResult::Custom(Box::new(DistinctValues::default())),
+            )
+            .unwrap();
+        assert!(matches!(
+            unregistered.merge_fruits(other),
+            Err(TantivyError::AggregationError(_))
+        ));
+    }
+}
@@ -14,3 +14,16 @@ pub(crate) fn format_date(val: i64) -> crate::Result<String> {
         .map_err(|_err| TantivyError::InvalidArgument("Could not serialize date".to_string()))?;
     Ok(key_as_string)
 }
+
+/// Converts a timestamp in nanoseconds to milliseconds, without the rounding error of converting
+/// the nanoseconds to `f64` first.
+pub(crate) fn nanos_to_millis(val: i64) -> f64 {
+    val.div_euclid(1_000_000) as f64 + val.rem_euclid(1_000_000) as f64 / 1_000_000.0
+}
+
+/// Formats a timestamp in milliseconds, to the microsecond.
+pub(crate) fn format_date_millis(val: f64) -> crate::Result<String> {
+    let millis = val.floor();
+    let micros = ((val - millis) * 1_000.0).round() as i64;
+    format_date(millis as i64 * 1_000_000 + micros * 1_000)
+}
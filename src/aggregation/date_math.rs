@@ -0,0 +1,291 @@
+//! Date math expressions, e.g. `now-7d/d`, which resolve to a date relative to a reference time.
+//!
+//! An expression starts with an anchor, `now` or a RFC3339 timestamp followed by `||`, and
+//! continues with any number of operations, applied from left to right:
+//! - `+<n><unit>` and `-<n><unit>` add and subtract `n` units,
+//! - `/<unit>` rounds down to the start of the unit.
+//!
+//! The units are `y` (years), `M` (months), `w` (weeks), `d` (days), `h` and `H` (hours), `m`
+//! (minutes) and `s` (seconds). Adding months or years keeps the day of the month, clamped to the
+//! last day of the resulting month. The dates are in UTC: days are 24 hours long, and weeks start
+//! on monday.
+//!
+//! A RFC3339 timestamp without `||` is also an expression, e.g. `2024-03-10T12:30:00Z`.
+
+use common::DateTime;
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::{Date, Month, OffsetDateTime, Time, UtcOffset};
+
+/// Error of a date math expression, see the [`DateRangeAggregation`] for the syntax.
+///
+/// [`DateRangeAggregation`]: super::bucket::DateRangeAggregation
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DateMathParseError {
+    /// The expression does not start with `now` or a RFC3339 timestamp
+    #[error("expected `now` or a RFC3339 timestamp at the start of {0:?}")]
+    InvalidAnchor(String),
+    /// An operation of the expression is not `+<n><unit>`, `-<n><unit>` or `/<unit>`
+    #[error("expected `+<n><unit>`, `-<n><unit>` or `/<unit>` operations in {0:?}")]
+    InvalidOperation(String),
+    /// The resolved date is out of the supported range of dates
+    #[error("the date of {0:?} is out of range")]
+    OutOfRange(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DateMathUnit {
+    Year,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl DateMathUnit {
+    fn parse(input: &str) -> Option<(DateMathUnit, &str)> {
+        let unit = match input.as_bytes().first()? {
+            b'y' => DateMathUnit::Year,
+            b'M' => DateMathUnit::Month,
+            b'w' => DateMathUnit::Week,
+            b'd' => DateMathUnit::Day,
+            b'h' | b'H' => DateMathUnit::Hour,
+            b'm' => DateMathUnit::Minute,
+            b's' => DateMathUnit::Second,
+            _ => return None,
+        };
+        Some((unit, &input[1..]))
+    }
+
+    /// Length of the unit in seconds, `None` for the units of variable length.
+    fn seconds(self) -> Option<i64> {
+        match self {
+            DateMathUnit::Year | DateMathUnit::Month => None,
+            DateMathUnit::Week => Some(7 * 86_400),
+            DateMathUnit::Day => Some(86_400),
+            DateMathUnit::Hour => Some(3_600),
+            DateMathUnit::Minute => Some(60),
+            DateMathUnit::Second => Some(1),
+        }
+    }
+}
+
+/// Parses a RFC3339 timestamp.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Result<DateTime, DateMathParseError> {
+    OffsetDateTime::parse(timestamp, &Rfc3339)
+        .map(DateTime::from_utc)
+        .map_err(|_| DateMathParseError::InvalidAnchor(timestamp.to_string()))
+}
+
+/// Resolves the date math `expression`, with `now` as the time its `now` anchor refers to.
+pub(crate) fn parse_date_math(
+    expression: &str,
+    now: DateTime,
+) -> Result<DateTime, DateMathParseError> {
+    let (anchor, mut operations) = if let Some(operations) = expression.strip_prefix("now") {
+        (now, operations)
+    } else if let Some((timestamp, operations)) = expression.split_once("||") {
+        (parse_timestamp(timestamp)?, operations)
+    } else {
+        (parse_timestamp(expression)?, "")
+    };
+    let invalid_operation = || DateMathParseError::InvalidOperation(expression.to_string());
+    let out_of_range = || DateMathParseError::OutOfRange(expression.to_string());
+
+    let mut date = anchor.into_utc();
+    while let Some(operator) = operations.as_bytes().first() {
+        let operands = &operations[1..];
+        match operator {
+            b'+' | b'-' => {
+                let num_digits = operands.bytes().take_while(u8::is_ascii_digit).count();
+                let (number, rest) = operands.split_at(num_digits);
+                let number: i64 = number.parse().map_err(|_| invalid_operation())?;
+                let number = if *operator == b'-' { -number } else { number };
+                let (unit, rest) = DateMathUnit::parse(rest).ok_or_else(invalid_operation)?;
+                date = add_units(date, number, unit).ok_or_else(out_of_range)?;
+                operations = rest;
+            }
+            b'/' => {
+                let (unit, rest) = DateMathUnit::parse(operands).ok_or_else(invalid_operation)?;
+                date = round_down(date, unit).ok_or_else(out_of_range)?;
+                operations = rest;
+            }
+            _ => return Err(invalid_operation()),
+        }
+    }
+    // `DateTime` holds the nanoseconds since the epoch in an `i64`.
+    i64::try_from(date.unix_timestamp_nanos())
+        .map(DateTime::from_timestamp_nanos)
+        .map_err(|_| out_of_range())
+}
+
+fn add_units(date: OffsetDateTime, number: i64, unit: DateMathUnit) -> Option<OffsetDateTime> {
+    match unit {
+        DateMathUnit::Year => add_months(date, number.checked_mul(12)?),
+        DateMathUnit::Month => add_months(date, number),
+        _ => {
+            let seconds = number.checked_mul(unit.seconds()?)?;
+            date.checked_add(time::Duration::seconds(seconds))
+        }
+    }
+}
+
+fn add_months(date: OffsetDateTime, months: i64) -> Option<OffsetDateTime> {
+    let month_index = (date.year() as i64 * 12 + date.month() as i64 - 1).checked_add(months)?;
+    let year = i32::try_from(month_index.div_euclid(12)).ok()?;
+    let month = Month::try_from(month_index.rem_euclid(12) as u8 + 1).ok()?;
+    let day = date.day().min(month.length(year));
+    let new_date = Date::from_calendar_date(year, month, day).ok()?;
+    Some(date.replace_date(new_date))
+}
+
+fn round_down(date: OffsetDateTime, unit: DateMathUnit) -> Option<OffsetDateTime> {
+    let date = date.to_offset(UtcOffset::UTC);
+    let rounded = match unit {
+        DateMathUnit::Year => date
+            .replace_date(Date::from_calendar_date(date.year(), Month::January, 1).ok()?)
+            .replace_time(Time::MIDNIGHT),
+        DateMathUnit::Month => date.replace_day(1).ok()?.replace_time(Time::MIDNIGHT),
+        DateMathUnit::Week => {
+            let days_since_monday = date.weekday().number_days_from_monday() as i64;
+            date.checked_sub(time::Duration::days(days_since_monday))?
+                .replace_time(Time::MIDNIGHT)
+        }
+        DateMathUnit::Day => date.replace_time(Time::MIDNIGHT),
+        DateMathUnit::Hour => date.replace_time(Time::from_hms(date.hour(), 0, 0).ok()?),
+        DateMathUnit::Minute => {
+            date.replace_time(Time::from_hms(date.hour(), date.minute(), 0).ok()?)
+        }
+        DateMathUnit::Second => date.replace_nanosecond(0).ok()?,
+    };
+    Some(rounded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(timestamp: &str) -> DateTime {
+        parse_timestamp(timestamp).unwrap()
+    }
+
+    fn resolve(expression: &str) -> Result<DateTime, DateMathParseError> {
+        // A wednesday.
+        parse_date_math(expression, date("2024-03-13T15:42:17.250Z"))
+    }
+
+    #[test]
+    fn test_date_math_anchors() {
+        assert_eq!(resolve("now"), Ok(date("2024-03-13T15:42:17.250Z")));
+        assert_eq!(
+            resolve("2019-01-01T00:00:00Z"),
+            Ok(date("2019-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            resolve("2019-01-01T02:00:00+02:00"),
+            Ok(date("2019-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            resolve("2019-01-01T00:00:00Z||+1d"),
+            Ok(date("2019-01-02T00:00:00Z"))
+        );
+        assert_eq!(
+            resolve("2019-01-01T00:00:00Z||"),
+            Ok(date("2019-01-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_date_math_add_and_subtract() {
+        assert_eq!(resolve("now-24h"), Ok(date("2024-03-12T15:42:17.250Z")));
+        assert_eq!(resolve("now+1H"), Ok(date("2024-03-13T16:42:17.250Z")));
+        assert_eq!(resolve("now-7d"), Ok(date("2024-03-06T15:42:17.250Z")));
+        assert_eq!(resolve("now-2w"), Ok(date("2024-02-28T15:42:17.250Z")));
+        assert_eq!(resolve("now+90m"), Ok(date("2024-03-13T17:12:17.250Z")));
+        assert_eq!(resolve("now-30s"), Ok(date("2024-03-13T15:41:47.250Z")));
+        assert_eq!(resolve("now-1M"), Ok(date("2024-02-13T15:42:17.250Z")));
+        assert_eq!(resolve("now+10M"), Ok(date("2025-01-13T15:42:17.250Z")));
+        assert_eq!(resolve("now-1y"), Ok(date("2023-03-13T15:42:17.250Z")));
+        assert_eq!(resolve("now-1d+2h"), Ok(date("2024-03-12T17:42:17.250Z")));
+    }
+
+    #[test]
+    fn test_date_math_months_are_clamped() {
+        assert_eq!(
+            resolve("2024-01-31T10:00:00Z||+1M"),
+            Ok(date("2024-02-29T10:00:00Z"))
+        );
+        assert_eq!(
+            resolve("2024-02-29T10:00:00Z||+1y"),
+            Ok(date("2025-02-28T10:00:00Z"))
+        );
+        assert_eq!(
+            resolve("2024-03-31T10:00:00Z||-13M"),
+            Ok(date("2023-02-28T10:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_date_math_rounding() {
+        assert_eq!(resolve("now/y"), Ok(date("2024-01-01T00:00:00Z")));
+        assert_eq!(resolve("now/M"), Ok(date("2024-03-01T00:00:00Z")));
+        assert_eq!(resolve("now/w"), Ok(date("2024-03-11T00:00:00Z")));
+        assert_eq!(resolve("now/d"), Ok(date("2024-03-13T00:00:00Z")));
+        assert_eq!(resolve("now/h"), Ok(date("2024-03-13T15:00:00Z")));
+        assert_eq!(resolve("now/m"), Ok(date("2024-03-13T15:42:00Z")));
+        assert_eq!(resolve("now/s"), Ok(date("2024-03-13T15:42:17Z")));
+        assert_eq!(resolve("now-7d/d"), Ok(date("2024-03-06T00:00:00Z")));
+        assert_eq!(resolve("now/d+1d"), Ok(date("2024-03-14T00:00:00Z")));
+        // A sunday rounds down to the monday before it.
+        assert_eq!(
+            resolve("2024-03-17T23:00:00Z||/w"),
+            Ok(date("2024-03-11T00:00:00Z"))
+        );
+        // The rounding is in UTC.
+        assert_eq!(
+            resolve("2024-03-13T01:00:00+02:00||/d"),
+            Ok(date("2024-03-12T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_date_math_errors() {
+        for expression in ["", "yesterday", "2019-01-01", "||+1d"] {
+            assert!(
+                matches!(
+                    resolve(expression),
+                    Err(DateMathParseError::InvalidAnchor(_))
+                ),
+                "{expression}"
+            );
+        }
+        for expression in [
+            "now-d",
+            "now-1",
+            "now-1x",
+            "now/",
+            "now/7d",
+            "now*2d",
+            "now 1d",
+            "2019-01-01T00:00:00Z||now",
+            "now-99999999999999999999y",
+        ] {
+            assert_eq!(
+                resolve(expression),
+                Err(DateMathParseError::InvalidOperation(expression.to_string()))
+            );
+        }
+        assert_eq!(
+            resolve("now+1000y"),
+            Err(DateMathParseError::OutOfRange("now+1000y".to_string()))
+        );
+        assert_eq!(
+            resolve("now-99999999999999999y"),
+            Err(DateMathParseError::OutOfRange(
+                "now-99999999999999999y".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,1096 @@
+//! Typed builders for aggregation requests.
+//!
+//! The builders produce the same [`Aggregations`] as the JSON format, but the options are checked
+//! at compile time. Metric aggregations cannot have sub-aggregations: only the bucket builders have
+//! a `sub` method.
+//!
+//! [`AggregationsDsl`] provides [`Aggregations::builder()`](AggregationsDsl::builder), as well as
+//! the conversion from and to JSON, so that requests can be migrated gradually.
+//!
+//! # Example
+//!
+//! ```
+//! use serde_json::json;
+//! use tantivy::aggregation::agg_req::Aggregations;
+//! use tantivy::aggregation::dsl::{AggregationsDsl, StatsAgg, TermsAgg};
+//!
+//! let agg_req = Aggregations::builder()
+//!     .terms(
+//!         "by_cat",
+//!         TermsAgg::field("category")
+//!             .size(20)
+//!             .sub(StatsAgg::field("price"), "price_stats"),
+//!     )
+//!     .build();
+//!
+//! let json_agg_req = Aggregations::from_json(json!({
+//!     "by_cat": {
+//!         "terms": { "field": "category", "size": 20 },
+//!         "aggs": {
+//!             "price_stats": { "stats": { "field": "price" } }
+//!         }
+//!     }
+//! }))
+//! .unwrap();
+//! assert_eq!(agg_req, json_agg_req);
+//! ```
+
+use std::collections::HashMap;
+
+use super::agg_req::{Aggregation, AggregationVariants, Aggregations};
+use super::bucket::{
+    ChiSquareHeuristic, CompositeAggregation, CompositeSource, CustomOrder,
+    DateHistogramAggregationReq, DateRangeAggregation, DateRangeAggregationRange,
+    ExistsAggregation, FuzzyKeyMerge, HistogramAggregation, HistogramBounds, JlhHeuristic,
+    MissingAggregation, Order, OrderTarget, PathTermsAggregation, PrefixTermsAggregation,
+    RangeAggregation, RangeAggregationRange, SignificantTermsAggregation, TermsAggregation,
+};
+use super::metric::{
+    AverageAggregation, CardinalityAggregationReq, CountAggregation, DateMetricFormat,
+    ExtendedStatsAggregation, KeyOrder, MaxAggregation, MinAggregation, PercentilesAggregationReq,
+    StatsAggregation, SumAggregation, TopHitsAggregationReq,
+};
+use super::value_transform::ValueTransform;
+use super::{AggregationError, Key};
+use crate::fastfield::MultiValueMode;
+use crate::TantivyError;
+
+/// Builds and converts [`Aggregations`].
+pub trait AggregationsDsl: Sized {
+    /// Returns a builder of aggregation requests.
+    fn builder() -> AggregationsBuilder;
+
+    /// Serializes the request to its JSON format.
+    fn to_json(&self) -> serde_json::Value;
+
+    /// Parses a request from its JSON format.
+    fn from_json(json: serde_json::Value) -> crate::Result<Self>;
+}
+
+impl AggregationsDsl for Aggregations {
+    fn builder() -> AggregationsBuilder {
+        AggregationsBuilder::default()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("aggregation requests always serialize to JSON")
+    }
+
+    fn from_json(json: serde_json::Value) -> crate::Result<Self> {
+        serde_json::from_value(json).map_err(|err| {
+            TantivyError::AggregationError(AggregationError::InvalidRequest(err.to_string()))
+        })
+    }
+}
+
+/// An aggregation built with a typed builder, which can be added to [`Aggregations`].
+pub trait IntoAggregation {
+    /// Converts the builder into the aggregation request.
+    fn into_aggregation(self) -> Aggregation;
+}
+
+impl IntoAggregation for Aggregation {
+    fn into_aggregation(self) -> Aggregation {
+        self
+    }
+}
+
+/// Builder of [`Aggregations`], keyed by the user defined names of the aggregations.
+///
+/// Adding an aggregation under a name already used replaces the previous aggregation.
+#[derive(Clone, Debug, Default)]
+pub struct AggregationsBuilder {
+    aggs: Aggregations,
+}
+
+impl AggregationsBuilder {
+    /// Adds an aggregation under the given name.
+    pub fn agg(mut self, name: impl Into<String>, agg: impl IntoAggregation) -> Self {
+        self.aggs.insert(name.into(), agg.into_aggregation());
+        self
+    }
+
+    /// Returns the aggregation requests.
+    pub fn build(self) -> Aggregations {
+        self.aggs
+    }
+}
+
+macro_rules! aggregation_builder {
+    ($(#[$attr:meta])* $builder:ident($req:ty) => $variant:ident, $method:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $builder {
+            req: $req,
+            sub_aggregation: Aggregations,
+            field_union: Option<Vec<String>>,
+            strict_types: bool,
+        }
+
+        impl From<$req> for $builder {
+            fn from(req: $req) -> Self {
+                $builder {
+                    req,
+                    sub_aggregation: Aggregations::default(),
+                    field_union: None,
+                    strict_types: false,
+                }
+            }
+        }
+
+        impl $builder {
+            /// Reads the values of several fields as a single logical field.
+            /// See [`Aggregation::field_union`].
+            pub fn field_union(
+                mut self,
+                fields: impl IntoIterator<Item = impl Into<String>>,
+            ) -> Self {
+                self.field_union = Some(fields.into_iter().map(Into::into).collect());
+                self
+            }
+
+            /// Fails the aggregation on values of conflicting types.
+            /// See [`Aggregation::strict_types`].
+            pub fn strict_types(mut self, strict_types: bool) -> Self {
+                self.strict_types = strict_types;
+                self
+            }
+        }
+
+        impl IntoAggregation for $builder {
+            fn into_aggregation(self) -> Aggregation {
+                Aggregation {
+                    agg: AggregationVariants::$variant(self.req),
+                    sub_aggregation: self.sub_aggregation,
+                    field_union: self.field_union,
+                    strict_types: self.strict_types,
+                }
+            }
+        }
+
+        impl AggregationsBuilder {
+            #[doc = concat!("Adds a [`", stringify!($builder), "`] under the given name.")]
+            pub fn $method(self, name: impl Into<String>, agg: $builder) -> Self {
+                self.agg(name, agg)
+            }
+        }
+    };
+}
+
+macro_rules! bucket_aggregation_builder {
+    ($(#[$attr:meta])* $builder:ident($req:ty) => $variant:ident, $method:ident) => {
+        aggregation_builder!($(#[$attr])* $builder($req) => $variant, $method);
+
+        impl $builder {
+            /// Adds a sub-aggregation, computed on the documents of each bucket.
+            pub fn sub(mut self, agg: impl IntoAggregation, name: impl Into<String>) -> Self {
+                self.sub_aggregation
+                    .insert(name.into(), agg.into_aggregation());
+                self
+            }
+        }
+    };
+}
+
+macro_rules! setter {
+    ($(#[$attr:meta])* $name:ident: $ty:ty) => {
+        $(#[$attr])*
+        pub fn $name(mut self, $name: $ty) -> Self {
+            self.req.$name = Some($name);
+            self
+        }
+    };
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`RangeAggregation`].
+    RangeAgg(RangeAggregation) => Range, range
+);
+
+impl RangeAgg {
+    /// Creates a range aggregation on `field`, without ranges.
+    pub fn field(field: impl Into<String>) -> Self {
+        RangeAggregation {
+            field: field.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Adds a range, e.g. `3.0..7.0`.
+    pub fn range(mut self, range: impl Into<RangeAggregationRange>) -> Self {
+        self.req.ranges.push(range.into());
+        self
+    }
+
+    /// Returns the buckets as a map keyed by the range keys.
+    pub fn keyed(mut self, keyed: bool) -> Self {
+        self.req.keyed = keyed;
+        self
+    }
+
+    setter!(
+        /// Sets the transform applied to the values. See [`ValueTransform`].
+        value_transform: ValueTransform
+    );
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`DateRangeAggregation`].
+    DateRangeAgg(DateRangeAggregation) => DateRange, date_range
+);
+
+impl DateRangeAgg {
+    /// Creates a date range aggregation on `field`, without ranges.
+    pub fn field(field: impl Into<String>) -> Self {
+        DateRangeAggregation {
+            field: field.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Adds a range, whose bounds are dates or date math expressions.
+    pub fn range(mut self, range: DateRangeAggregationRange) -> Self {
+        self.req.ranges.push(range);
+        self
+    }
+
+    /// Returns the buckets as a map keyed by the range keys.
+    pub fn keyed(mut self, keyed: bool) -> Self {
+        self.req.keyed = keyed;
+        self
+    }
+
+    /// Sets the date `now` resolves to in the date math expressions.
+    pub fn now(mut self, now: impl Into<String>) -> Self {
+        self.req.now = Some(now.into());
+        self
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`HistogramAggregation`].
+    HistogramAgg(HistogramAggregation) => Histogram, histogram
+);
+
+impl HistogramAgg {
+    /// Creates a histogram aggregation on `field`, with buckets of width `interval`.
+    pub fn field(field: impl Into<String>, interval: f64) -> Self {
+        HistogramAggregation {
+            field: field.into(),
+            interval,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    setter!(
+        /// Shifts the bucket boundaries by `offset`.
+        offset: f64
+    );
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+
+    /// Limits the buckets to the values in `[min, max]`.
+    pub fn hard_bounds(mut self, min: f64, max: f64) -> Self {
+        self.req.hard_bounds = Some(HistogramBounds { min, max });
+        self
+    }
+
+    /// Returns empty buckets to cover `[min, max]`.
+    pub fn extended_bounds(mut self, min: f64, max: f64) -> Self {
+        self.req.extended_bounds = Some(HistogramBounds { min, max });
+        self
+    }
+
+    /// Returns the buckets as a map keyed by the bucket keys.
+    pub fn keyed(mut self, keyed: bool) -> Self {
+        self.req.keyed = keyed;
+        self
+    }
+
+    /// Sets whether the values of date fields are normalized to nanoseconds.
+    pub fn is_normalized_to_ns(mut self, is_normalized_to_ns: bool) -> Self {
+        self.req.is_normalized_to_ns = is_normalized_to_ns;
+        self
+    }
+
+    setter!(
+        /// Sets the transform applied to the values. See [`ValueTransform`].
+        value_transform: ValueTransform
+    );
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`DateHistogramAggregationReq`].
+    DateHistogramAgg(DateHistogramAggregationReq) => DateHistogram, date_histogram
+);
+
+impl DateHistogramAgg {
+    /// Creates a date histogram aggregation on `field`, with buckets of width `fixed_interval`,
+    /// e.g. `30d`.
+    pub fn field(field: impl Into<String>, fixed_interval: impl Into<String>) -> Self {
+        DateHistogramAggregationReq {
+            field: field.into(),
+            fixed_interval: Some(fixed_interval.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Sets the format of the `key_as_string` of the buckets.
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.req.format = Some(format.into());
+        self
+    }
+
+    /// Shifts the bucket boundaries by `offset`, e.g. `-2d`.
+    pub fn offset(mut self, offset: impl Into<String>) -> Self {
+        self.req.offset = Some(offset.into());
+        self
+    }
+
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+
+    /// Limits the buckets to the values in `[min, max]`, in milliseconds since the epoch.
+    pub fn hard_bounds(mut self, min: f64, max: f64) -> Self {
+        self.req.hard_bounds = Some(HistogramBounds { min, max });
+        self
+    }
+
+    /// Returns empty buckets to cover `[min, max]`, in milliseconds since the epoch.
+    pub fn extended_bounds(mut self, min: f64, max: f64) -> Self {
+        self.req.extended_bounds = Some(HistogramBounds { min, max });
+        self
+    }
+
+    /// Returns the buckets as a map keyed by the bucket keys.
+    pub fn keyed(mut self, keyed: bool) -> Self {
+        self.req.keyed = keyed;
+        self
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`TermsAggregation`].
+    TermsAgg(TermsAggregation) => Terms, terms
+);
+
+impl TermsAgg {
+    /// Creates a terms aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        TermsAggregation {
+            field: field.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    setter!(
+        /// Sets the number of returned buckets.
+        size: u32
+    );
+    setter!(
+        /// Sets the number of buckets collected per segment.
+        segment_size: u32
+    );
+    setter!(
+        /// Returns the upper bound of the doc count error of each term.
+        show_term_doc_count_error: bool
+    );
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+
+    /// Orders the buckets by `target`.
+    pub fn order(mut self, target: OrderTarget, order: Order) -> Self {
+        self.req.order = Some(CustomOrder { target, order });
+        self
+    }
+
+    setter!(
+        /// Sets the key of the bucket of the documents without a value.
+        missing: Key
+    );
+    setter!(
+        /// Merges the buckets whose keys are within a Levenshtein distance of each other.
+        fuzzy_key_merge: FuzzyKeyMerge
+    );
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`SignificantTermsAggregation`].
+    SignificantTermsAgg(SignificantTermsAggregation) => SignificantTerms, significant_terms
+);
+
+impl SignificantTermsAgg {
+    /// Creates a significant terms aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        SignificantTermsAggregation {
+            field: field.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    setter!(
+        /// Sets the number of returned buckets.
+        size: u32
+    );
+    setter!(
+        /// Sets the number of buckets collected per segment.
+        segment_size: u32
+    );
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+
+    /// Scores the terms with the JLH heuristic, which is the default.
+    pub fn jlh(mut self) -> Self {
+        self.req.jlh = Some(JlhHeuristic::default());
+        self
+    }
+
+    /// Scores the terms with the chi square heuristic.
+    pub fn chi_square(mut self) -> Self {
+        self.req.chi_square = Some(ChiSquareHeuristic::default());
+        self
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`PathTermsAggregation`].
+    PathTermsAgg(PathTermsAggregation) => PathTerms, path_terms
+);
+
+impl PathTermsAgg {
+    /// Creates a path terms aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        PathTermsAggregation {
+            field: field.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Sets the separator of the path segments.
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.req.separator = Some(separator.into());
+        self
+    }
+
+    /// Only aggregates the paths below `prefix`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.req.prefix = Some(prefix.into());
+        self
+    }
+
+    setter!(
+        /// Sets the depth the paths are truncated to.
+        depth: u32
+    );
+    setter!(
+        /// Sets the number of returned buckets.
+        size: u32
+    );
+    setter!(
+        /// Sets the number of buckets collected per segment.
+        segment_size: u32
+    );
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+
+    /// Orders the buckets by `target`.
+    pub fn order(mut self, target: OrderTarget, order: Order) -> Self {
+        self.req.order = Some(CustomOrder { target, order });
+        self
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`PrefixTermsAggregation`].
+    PrefixTermsAgg(PrefixTermsAggregation) => PrefixTerms, prefix_terms
+);
+
+impl PrefixTermsAgg {
+    /// Creates an aggregation of the terms of `field` starting with `prefix`.
+    pub fn field(field: impl Into<String>, prefix: impl Into<String>) -> Self {
+        PrefixTermsAggregation {
+            field: field.into(),
+            prefix: prefix.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    setter!(
+        /// Sets the number of returned buckets.
+        size: u32
+    );
+    setter!(
+        /// Sets the number of buckets collected per segment.
+        segment_size: u32
+    );
+    setter!(
+        /// Sets the minimum number of documents of the returned buckets.
+        min_doc_count: u64
+    );
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`CompositeAggregation`].
+    CompositeAgg(CompositeAggregation) => Composite, composite
+);
+
+impl CompositeAgg {
+    /// Creates a composite aggregation, without sources.
+    pub fn new() -> Self {
+        CompositeAggregation::default().into()
+    }
+
+    /// Adds a source, whose values are combined with the values of the previous sources.
+    pub fn source(mut self, name: impl Into<String>, source: CompositeSource) -> Self {
+        self.req
+            .sources
+            .push(HashMap::from([(name.into(), source)]));
+        self
+    }
+
+    setter!(
+        /// Sets the number of returned buckets.
+        size: u32
+    );
+    setter!(
+        /// Returns the buckets after the given key, i.e. the next page.
+        after: HashMap<String, Key>
+    );
+}
+
+impl Default for CompositeAgg {
+    fn default() -> Self {
+        CompositeAgg::new()
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of an [`ExistsAggregation`].
+    ExistsAgg(ExistsAggregation) => Exists, exists
+);
+
+impl ExistsAgg {
+    /// Creates an exists aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        ExistsAggregation {
+            field: field.into(),
+        }
+        .into()
+    }
+}
+
+bucket_aggregation_builder!(
+    /// Builder of a [`MissingAggregation`].
+    MissingAgg(MissingAggregation) => Missing, missing
+);
+
+impl MissingAgg {
+    /// Creates a missing aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        MissingAggregation {
+            field: field.into(),
+        }
+        .into()
+    }
+}
+
+/// Setters shared by the metric aggregations on numeric values.
+macro_rules! numeric_metric_setters {
+    ($builder:ident) => {
+        impl $builder {
+            setter!(
+                /// Sets the value of the documents without a value.
+                missing: f64
+            );
+            setter!(
+                /// Sets the transform applied to the values. See [`ValueTransform`].
+                value_transform: ValueTransform
+            );
+            setter!(
+                /// Sets how the values of a document are combined. See [`MultiValueMode`].
+                mode: MultiValueMode
+            );
+        }
+    };
+}
+
+/// Constructor of the metric aggregations on the number of tokens of a text field.
+macro_rules! token_count_constructor {
+    ($builder:ident($req:ident)) => {
+        impl $builder {
+            /// Creates the aggregation on the number of tokens per document of the text field
+            /// `field`. See [token counts](crate::aggregation::metric#token-counts).
+            pub fn token_count_field(field: impl Into<String>) -> Self {
+                $req {
+                    token_count_field: Some(field.into()),
+                    ..$req::from_field_name(String::new())
+                }
+                .into()
+            }
+        }
+    };
+}
+
+aggregation_builder!(
+    /// Builder of an [`AverageAggregation`].
+    AvgAgg(AverageAggregation) => Average, avg
+);
+numeric_metric_setters!(AvgAgg);
+token_count_constructor!(AvgAgg(AverageAggregation));
+
+impl AvgAgg {
+    /// Creates an average aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        AverageAggregation::from_field_name(field.into()).into()
+    }
+}
+
+aggregation_builder!(
+    /// Builder of a [`CountAggregation`].
+    CountAgg(CountAggregation) => Count, value_count
+);
+
+impl CountAgg {
+    /// Creates a value count aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        CountAggregation::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the value of the documents without a value.
+        missing: f64
+    );
+    setter!(
+        /// Sets how the values of a document are combined. See [`MultiValueMode`].
+        mode: MultiValueMode
+    );
+}
+
+aggregation_builder!(
+    /// Builder of a [`MaxAggregation`].
+    MaxAgg(MaxAggregation) => Max, max
+);
+numeric_metric_setters!(MaxAgg);
+token_count_constructor!(MaxAgg(MaxAggregation));
+
+impl MaxAgg {
+    /// Creates a max aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        MaxAggregation::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the format of the maximum of a date field. See [`DateMetricFormat`].
+        format: DateMetricFormat
+    );
+}
+
+aggregation_builder!(
+    /// Builder of a [`MinAggregation`].
+    MinAgg(MinAggregation) => Min, min
+);
+numeric_metric_setters!(MinAgg);
+token_count_constructor!(MinAgg(MinAggregation));
+
+impl MinAgg {
+    /// Creates a min aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        MinAggregation::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the format of the minimum of a date field. See [`DateMetricFormat`].
+        format: DateMetricFormat
+    );
+}
+
+aggregation_builder!(
+    /// Builder of a [`StatsAggregation`].
+    StatsAgg(StatsAggregation) => Stats, stats
+);
+numeric_metric_setters!(StatsAgg);
+token_count_constructor!(StatsAgg(StatsAggregation));
+
+impl StatsAgg {
+    /// Creates a stats aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        StatsAggregation::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the format of the minimum and maximum of a date field. See [`DateMetricFormat`].
+        format: DateMetricFormat
+    );
+}
+
+aggregation_builder!(
+    /// Builder of an [`ExtendedStatsAggregation`].
+    ExtendedStatsAgg(ExtendedStatsAggregation) => ExtendedStats, extended_stats
+);
+numeric_metric_setters!(ExtendedStatsAgg);
+token_count_constructor!(ExtendedStatsAgg(ExtendedStatsAggregation));
+
+impl ExtendedStatsAgg {
+    /// Creates an extended stats aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        ExtendedStatsAggregation::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the number of standard deviations of the `std_deviation_bounds`.
+        sigma: f64
+    );
+}
+
+aggregation_builder!(
+    /// Builder of a [`SumAggregation`].
+    SumAgg(SumAggregation) => Sum, sum
+);
+numeric_metric_setters!(SumAgg);
+token_count_constructor!(SumAgg(SumAggregation));
+
+impl SumAgg {
+    /// Creates a sum aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        SumAggregation::from_field_name(field.into()).into()
+    }
+}
+
+aggregation_builder!(
+    /// Builder of a [`PercentilesAggregationReq`].
+    PercentilesAgg(PercentilesAggregationReq) => Percentiles, percentiles
+);
+numeric_metric_setters!(PercentilesAgg);
+
+impl PercentilesAgg {
+    /// Creates a percentiles aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        PercentilesAggregationReq::from_field_name(field.into()).into()
+    }
+
+    /// Sets the percentiles to compute.
+    pub fn percents(mut self, percents: impl IntoIterator<Item = f64>) -> Self {
+        self.req.percents = Some(percents.into_iter().collect());
+        self
+    }
+
+    /// Returns the percentiles as a map, which is the default.
+    pub fn keyed(mut self, keyed: bool) -> Self {
+        self.req.keyed = keyed;
+        self
+    }
+
+    setter!(
+        /// Trades memory for accuracy. See [`PercentilesAggregationReq::compression`].
+        compression: f64
+    );
+}
+
+aggregation_builder!(
+    /// Builder of a [`TopHitsAggregationReq`].
+    TopHitsAgg(TopHitsAggregationReq) => TopHits, top_hits
+);
+
+impl TopHitsAgg {
+    /// Creates a top hits aggregation, returning `size` documents.
+    pub fn size(size: usize) -> Self {
+        let mut req = TopHitsAggregationReq::default();
+        req.size = size;
+        req.into()
+    }
+
+    /// Sorts the documents by `field`, or by their score with `_score`. The first sort keys take
+    /// precedence.
+    pub fn sort(mut self, field: impl Into<String>, order: Order) -> Self {
+        self.req.sort.push(KeyOrder {
+            field: field.into(),
+            order,
+        });
+        self
+    }
+
+    setter!(
+        /// Skips the first `from` documents.
+        from: usize
+    );
+
+    /// Returns the values of `field` for each document. `field` may contain `*` wildcards.
+    pub fn doc_value_field(mut self, field: impl Into<String>) -> Self {
+        self.req.doc_value_fields.push(field.into());
+        self
+    }
+}
+
+aggregation_builder!(
+    /// Builder of a [`CardinalityAggregationReq`].
+    CardinalityAgg(CardinalityAggregationReq) => Cardinality, cardinality
+);
+
+impl CardinalityAgg {
+    /// Creates a cardinality aggregation on `field`.
+    pub fn field(field: impl Into<String>) -> Self {
+        CardinalityAggregationReq::from_field_name(field.into()).into()
+    }
+
+    setter!(
+        /// Sets the value of the documents without a value.
+        missing: Key
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::aggregation::bucket::CompositeTermsSource;
+    use crate::aggregation::value_transform::ValueTransform;
+
+    fn assert_builds_json(aggs: Aggregations, json: serde_json::Value) {
+        let parsed = Aggregations::from_json(json).unwrap();
+        assert_eq!(aggs, parsed);
+        assert_eq!(Aggregations::from_json(aggs.to_json()).unwrap(), aggs);
+    }
+
+    #[test]
+    fn test_dsl_bucket_aggregations() {
+        let aggs = Aggregations::builder()
+            .range(
+                "prices",
+                RangeAgg::field("price")
+                    .range(0.0..10.0)
+                    .range(10.0..20.0)
+                    .keyed(true),
+            )
+            .date_range(
+                "recent",
+                DateRangeAgg::field("date")
+                    .range(DateRangeAggregationRange {
+                        key: Some("last_week".to_string()),
+                        from: Some("now-7d".to_string()),
+                        to: None,
+                    })
+                    .now("2024-01-08T00:00:00Z"),
+            )
+            .histogram(
+                "histo",
+                HistogramAgg::field("price", 5.0)
+                    .offset(1.0)
+                    .min_doc_count(1)
+                    .hard_bounds(0.0, 100.0)
+                    .extended_bounds(0.0, 50.0),
+            )
+            .date_histogram(
+                "per_day",
+                DateHistogramAgg::field("date", "1d")
+                    .format("yyyy-MM-dd")
+                    .keyed(true)
+                    .sub(AvgAgg::field("price"), "avg_price"),
+            )
+            .terms(
+                "by_cat",
+                TermsAgg::field("category")
+                    .size(20)
+                    .segment_size(100)
+                    .min_doc_count(2)
+                    .order(OrderTarget::Key, Order::Asc)
+                    .missing(Key::Str("none".to_string()))
+                    .sub(PathTermsAgg::field("path").separator("/").depth(2), "paths"),
+            )
+            .significant_terms(
+                "significant",
+                SignificantTermsAgg::field("category").size(5).chi_square(),
+            )
+            .composite(
+                "pages",
+                CompositeAgg::new()
+                    .source(
+                        "cat",
+                        CompositeSource::Terms(CompositeTermsSource {
+                            field: "category".to_string(),
+                        }),
+                    )
+                    .size(10),
+            )
+            .exists(
+                "has_price",
+                ExistsAgg::field("price").sub(SumAgg::field("price"), "sum"),
+            )
+            .missing("no_price", MissingAgg::field("price"))
+            .build();
+        assert_builds_json(
+            aggs,
+            json!({
+                "prices": {
+                    "range": {
+                        "field": "price",
+                        "ranges": [{ "from": 0.0, "to": 10.0 }, { "from": 10.0, "to": 20.0 }],
+                        "keyed": true
+                    }
+                },
+                "recent": {
+                    "date_range": {
+                        "field": "date",
+                        "ranges": [{ "key": "last_week", "from": "now-7d" }],
+                        "now": "2024-01-08T00:00:00Z"
+                    }
+                },
+                "histo": {
+                    "histogram": {
+                        "field": "price",
+                        "interval": 5.0,
+                        "offset": 1.0,
+                        "min_doc_count": 1,
+                        "hard_bounds": { "min": 0.0, "max": 100.0 },
+                        "extended_bounds": { "min": 0.0, "max": 50.0 }
+                    }
+                },
+                "per_day": {
+                    "date_histogram": {
+                        "field": "date",
+                        "fixed_interval": "1d",
+                        "format": "yyyy-MM-dd",
+                        "keyed": true
+                    },
+                    "aggs": { "avg_price": { "avg": { "field": "price" } } }
+                },
+                "by_cat": {
+                    "terms": {
+                        "field": "category",
+                        "size": 20,
+                        "segment_size": 100,
+                        "min_doc_count": 2,
+                        "order": { "_key": "asc" },
+                        "missing": "none"
+                    },
+                    "aggs": {
+                        "paths": {
+                            "path_terms": { "field": "path", "separator": "/", "depth": 2 }
+                        }
+                    }
+                },
+                "significant": {
+                    "significant_terms": { "field": "category", "size": 5, "chi_square": {} }
+                },
+                "pages": {
+                    "composite": {
+                        "sources": [{ "cat": { "terms": { "field": "category" } } }],
+                        "size": 10
+                    }
+                },
+                "has_price": {
+                    "exists": { "field": "price" },
+                    "aggs": { "sum": { "sum": { "field": "price" } } }
+                },
+                "no_price": { "missing": { "field": "price" } }
+            }),
+        );
+    }
+
+    #[test]
+    fn test_dsl_metric_aggregations() {
+        let aggs = Aggregations::builder()
+            .avg(
+                "avg",
+                AvgAgg::field("price")
+                    .missing(0.0)
+                    .mode(MultiValueMode::Max),
+            )
+            .value_count("count", CountAgg::field("price").mode(MultiValueMode::Min))
+            .max(
+                "max",
+                MaxAgg::field("date").format(DateMetricFormat::Rfc3339),
+            )
+            .min(
+                "min",
+                MinAgg::field("price").value_transform(ValueTransform::Log10),
+            )
+            .stats(
+                "stats",
+                StatsAgg::field("price").field_union(["price", "price_usd"]),
+            )
+            .extended_stats("extended", ExtendedStatsAgg::field("price").sigma(3.0))
+            .sum("sum", SumAgg::field("price").strict_types(true))
+            .avg("title_len", AvgAgg::token_count_field("title"))
+            .percentiles(
+                "percentiles",
+                PercentilesAgg::field("price")
+                    .percents([50.0, 99.0])
+                    .keyed(false)
+                    .compression(200.0),
+            )
+            .top_hits(
+                "top",
+                TopHitsAgg::size(3)
+                    .sort("date", Order::Desc)
+                    .from(1)
+                    .doc_value_field("title"),
+            )
+            .cardinality(
+                "cardinality",
+                CardinalityAgg::field("category").missing(Key::Str("none".to_string())),
+            )
+            .build();
+        assert_builds_json(
+            aggs,
+            json!({
+                "avg": { "avg": { "field": "price", "missing": 0.0, "mode": "max" } },
+                "count": { "value_count": { "field": "price", "mode": "min" } },
+                "max": { "max": { "field": "date", "format": "rfc3339" } },
+                "min": { "min": { "field": "price", "value_transform": "log10" } },
+                "stats": {
+                    "stats": { "field": "price" },
+                    "field_union": ["price", "price_usd"]
+                },
+                "extended": { "extended_stats": { "field": "price", "sigma": 3.0 } },
+                "sum": { "sum": { "field": "price" }, "strict_types": true },
+                "title_len": { "avg": { "token_count_field": "title" } },
+                "percentiles": {
+                    "percentiles": {
+                        "field": "price",
+                        "percents": [50.0, 99.0],
+                        "keyed": false,
+                        "compression": 200.0
+                    }
+                },
+                "top": {
+                    "top_hits": {
+                        "size": 3,
+                        "sort": [{ "date": "desc" }],
+                        "from": 1,
+                        "docvalue_fields": ["title"]
+                    }
+                },
+                "cardinality": { "cardinality": { "field": "category", "missing": "none" } }
+            }),
+        );
+    }
+
+    #[test]
+    fn test_dsl_from_json_invalid() {
+        let err = Aggregations::from_json(json!({ "avg": { "avgg": { "field": "price" } } }))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::AggregationError(AggregationError::InvalidRequest(_))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use common::ByteCount;
 
 use super::bucket::DateHistogramParseError;
+use super::DateMathParseError;
 
 /// Error that may occur when opening a directory
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
@@ -11,10 +12,50 @@ pub enum AggregationError {
     /// Invalid Aggregation Request
     #[error("InvalidRequest: {0:?}")]
     InvalidRequest(String),
+    /// The type of a field is not supported by the aggregation
+    #[error(
+        "Aggregation {path:?}: field {field:?} of type {field_type} is not supported, expected a \
+         numeric, date or bool field"
+    )]
+    UnsupportedFieldType {
+        /// Path of the aggregation in the request, e.g. `by_category.avg_price`
+        path: String,
+        /// Name of the field
+        field: String,
+        /// Type of the field
+        field_type: String,
+    },
+    /// The values of the field of an aggregation have types it cannot combine, e.g. numbers and
+    /// text written to the same JSON path. Only returned with `strict_types`, the conflict is
+    /// otherwise reported as a warning of the result.
+    #[error("Aggregation {path:?} on field {field:?}: {reason}")]
+    TypeConflict {
+        /// Path of the aggregation in the request, e.g. `by_category.avg_price`
+        path: String,
+        /// Name of the field
+        field: String,
+        /// Description of the conflict
+        reason: String,
+    },
+    /// An aggregation of the request is invalid, e.g. it reads a field which is not a fast field,
+    /// or one of its parameters has an invalid value.
+    #[error("{kind}")]
+    InvalidAggregation {
+        /// Path of the aggregation in the request, e.g. `by_category.avg_price`
+        path: String,
+        /// What is invalid in the aggregation
+        kind: AggregationErrorKind,
+    },
     /// Date histogram parse error
     #[error("Date histogram parse error: {0:?}")]
     DateHistogramParseError(#[from] DateHistogramParseError),
+    /// Date math parse error
+    #[error("Date math parse error: {0}")]
+    DateMathParseError(#[from] DateMathParseError),
     /// Memory limit exceeded
+    ///
+    /// The memory limit of the aggregations is now reported as a
+    /// [`TantivyError::BudgetExceeded`](crate::TantivyError::BudgetExceeded) error.
     #[error(
         "Aborting aggregation because memory limit was exceeded. Limit: {limit:?}, Current: \
          {current:?}"
@@ -37,3 +78,95 @@ pub enum AggregationError {
         current: u32,
     },
 }
+
+impl AggregationError {
+    /// Returns a stable code identifying the kind of the error, see
+    /// [`TantivyError::error_code`](crate::TantivyError::error_code).
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            AggregationError::InternalError(_) => "aggregation.internal",
+            AggregationError::InvalidRequest(_) => "aggregation.invalid_request",
+            AggregationError::UnsupportedFieldType { .. } => "aggregation.type_mismatch",
+            AggregationError::TypeConflict { .. } => "aggregation.type_conflict",
+            AggregationError::InvalidAggregation { kind, .. } => kind.error_code(),
+            AggregationError::DateHistogramParseError(_)
+            | AggregationError::DateMathParseError(_) => "aggregation.invalid_parameter",
+            AggregationError::MemoryExceeded { .. } => "budget_exceeded.memory",
+            AggregationError::BucketLimitExceeded { .. } => "aggregation.bucket_limit_exceeded",
+        }
+    }
+
+    /// Returns an [`AggregationError::InvalidAggregation`] error of kind
+    /// [`AggregationErrorKind::InvalidParameter`].
+    ///
+    /// The path of the aggregation is filled as the error bubbles up the aggregation tree.
+    pub(crate) fn invalid_parameter(name: &str, reason: impl ToString) -> AggregationError {
+        AggregationError::InvalidAggregation {
+            path: String::new(),
+            kind: AggregationErrorKind::InvalidParameter {
+                name: name.to_string(),
+                reason: reason.to_string(),
+            },
+        }
+    }
+
+    /// Returns an [`AggregationError::InvalidAggregation`] error of kind
+    /// [`AggregationErrorKind::TypeMismatch`].
+    pub(crate) fn type_mismatch(
+        field: &str,
+        expected: impl ToString,
+        found: impl ToString,
+    ) -> AggregationError {
+        AggregationError::InvalidAggregation {
+            path: String::new(),
+            kind: AggregationErrorKind::TypeMismatch {
+                field: field.to_string(),
+                expected: expected.to_string(),
+                found: found.to_string(),
+            },
+        }
+    }
+}
+
+/// What is invalid in an aggregation, see [`AggregationError::InvalidAggregation`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AggregationErrorKind {
+    /// The aggregation reads a field which is not a fast field of the schema.
+    #[error("An invalid argument was passed: 'Field {field:?} is not configured as fast field'")]
+    FieldNotFound {
+        /// Name of the field
+        field: String,
+    },
+    /// The type of the field, or of a value of the request, is not the one expected by the
+    /// aggregation.
+    #[error(
+        "An invalid argument was passed: 'Field {field:?} is of type {found}, expected {expected}'"
+    )]
+    TypeMismatch {
+        /// Name of the field
+        field: String,
+        /// Type expected by the aggregation
+        expected: String,
+        /// Type found
+        found: String,
+    },
+    /// A parameter of the aggregation has an invalid value.
+    #[error("An invalid argument was passed: '{reason}'")]
+    InvalidParameter {
+        /// Name of the parameter, e.g. `interval`
+        name: String,
+        /// Why the value is invalid
+        reason: String,
+    },
+}
+
+impl AggregationErrorKind {
+    /// Returns a stable code identifying the kind of the error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            AggregationErrorKind::FieldNotFound { .. } => "aggregation.field_not_found",
+            AggregationErrorKind::TypeMismatch { .. } => "aggregation.type_mismatch",
+            AggregationErrorKind::InvalidParameter { .. } => "aggregation.invalid_parameter",
+        }
+    }
+}
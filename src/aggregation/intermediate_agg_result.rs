@@ -4,30 +4,43 @@
 
 use std::cmp::Ordering;
 use std::collections::hash_map::Entry;
+use std::collections::{btree_map, BTreeMap};
 use std::hash::Hash;
 use std::net::Ipv6Addr;
 
 use columnar::ColumnType;
 use itertools::Itertools;
+use levenshtein_automata::Distance;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
+use super::agg_limits::{ChargedMemory, MemoryConsumption};
 use super::agg_req::{Aggregation, AggregationVariants, Aggregations};
 use super::agg_result::{AggregationResult, BucketResult, MetricResult, RangeBucketEntry};
 use super::bucket::{
     cut_off_buckets, get_agg_name_and_property, intermediate_histogram_buckets_to_final_buckets,
-    GetDocCount, Order, OrderTarget, RangeAggregation, TermsAggregation,
+    CompositeAggregation, CustomOrder, FuzzyKeyMerge, GetDocCount, Order, OrderTarget,
+    SignificantTermsAggregation, SignificantTermsAggregationInternal, TermsAggregation,
 };
+use super::custom_intermediate_result::{self, CustomIntermediateAggregation};
+use super::date::format_date_millis;
 use super::metric::{
     IntermediateAverage, IntermediateCount, IntermediateExtendedStats, IntermediateMax,
     IntermediateMin, IntermediateStats, IntermediateSum, PercentilesCollector, TopHitsTopNComputer,
 };
+use super::sampling::{scale_results, SampledDocs};
 use super::segment_agg_result::AggregationLimitsGuard;
+use super::spill::SpilledTermBuckets;
+use super::type_conflicts::resolve_terms_key_conflicts;
 use super::{format_date, AggregationError, Key, SerializedKey};
-use crate::aggregation::agg_result::{AggregationResults, BucketEntries, BucketEntry};
+use crate::aggregation::agg_result::{
+    AggregationResults, BucketEntries, BucketEntry, CompositeBucketEntry,
+    SignificantTermBucketEntry,
+};
 use crate::aggregation::bucket::TermsAggregationInternal;
 use crate::aggregation::metric::CardinalityCollector;
-use crate::TantivyError;
+use crate::query::automaton_builder;
+use crate::{CancellationToken, Executor, TantivyError};
 
 /// Contains the intermediate aggregation result, which is optimized to be merged with other
 /// intermediate results.
@@ -36,6 +49,19 @@ use crate::TantivyError;
 #[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct IntermediateAggregationResults {
     pub(crate) aggs_res: FxHashMap<String, IntermediateAggregationResult>,
+    /// Type conflicts found in the collected segments, see
+    /// [`AggregationResults::warnings()`]. Only set on the top-level results.
+    #[serde(default)]
+    pub(crate) warnings: Vec<String>,
+    /// Documents sampled in the collected segments, see [`AggregationSampling`]. Only set on
+    /// the top-level results, if the request sampled the documents.
+    ///
+    /// [`AggregationSampling`]: super::AggregationSampling
+    #[serde(default)]
+    pub(crate) sampled: Option<SampledDocs>,
+    /// Memory of the results charged by the incremental merge of the fruits of the segments.
+    #[serde(skip)]
+    pub(crate) charged_memory: ChargedMemory,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialOrd, PartialEq)]
@@ -102,6 +128,68 @@ impl std::hash::Hash for IntermediateKey {
     }
 }
 
+/// The key of a composite bucket, with one value per source of the
+/// [composite aggregation](super::bucket::CompositeAggregation).
+///
+/// Keys are compared value by value, in the order of the sources. Numbers come before strings,
+/// and are compared by value whatever their type, so that an `after` key parsed from JSON
+/// compares as expected with the keys read from the columns.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IntermediateCompositeKey(pub(crate) Vec<IntermediateKey>);
+
+impl Ord for IntermediateCompositeKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(left, right)| cmp_composite_key_values(left, right))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+impl PartialOrd for IntermediateCompositeKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IntermediateCompositeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for IntermediateCompositeKey {}
+
+fn cmp_composite_key_values(left: &IntermediateKey, right: &IntermediateKey) -> Ordering {
+    enum Number {
+        Int(i128),
+        Float(f64),
+    }
+    let as_number = |key: &IntermediateKey| match *key {
+        IntermediateKey::Bool(val) => Some(Number::Int(val as i128)),
+        IntermediateKey::U64(val) => Some(Number::Int(val as i128)),
+        IntermediateKey::I64(val) => Some(Number::Int(val as i128)),
+        IntermediateKey::F64(val) => Some(Number::Float(val)),
+        IntermediateKey::IpAddr(_) | IntermediateKey::Str(_) => None,
+    };
+    match (as_number(left), as_number(right)) {
+        (Some(Number::Int(left)), Some(Number::Int(right))) => left.cmp(&right),
+        (Some(Number::Float(left)), Some(Number::Float(right))) => left.total_cmp(&right),
+        (Some(Number::Int(left)), Some(Number::Float(right))) => (left as f64).total_cmp(&right),
+        (Some(Number::Float(left)), Some(Number::Int(right))) => left.total_cmp(&(right as f64)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => match (left, right) {
+            (IntermediateKey::IpAddr(left), IntermediateKey::IpAddr(right)) => left.cmp(right),
+            (IntermediateKey::Str(left), IntermediateKey::Str(right)) => left.cmp(right),
+            (IntermediateKey::IpAddr(_), _) => Ordering::Less,
+            _ => Ordering::Greater,
+        },
+    }
+}
+
 impl IntermediateAggregationResults {
     /// Add a result
     pub fn push(&mut self, key: String, value: IntermediateAggregationResult) -> crate::Result<()> {
@@ -120,11 +208,17 @@ impl IntermediateAggregationResults {
 
     /// Convert intermediate result and its aggregation request to the final result.
     pub fn into_final_result(
-        self,
+        mut self,
         req: Aggregations,
         mut limits: AggregationLimitsGuard,
     ) -> crate::Result<AggregationResults> {
-        let res = self.into_final_result_internal(&req, &mut limits)?;
+        let mut warnings = std::mem::take(&mut self.warnings);
+        let sampled = self.sampled.take();
+        resolve_terms_key_conflicts(&mut self, &req, &mut warnings)?;
+        let mut res = self.into_final_result_internal(&req, &mut limits)?;
+        if let Some(sampled) = sampled {
+            scale_results(&mut res, 1.0 / sampled.probability);
+        }
         let bucket_count = res.get_bucket_count() as u32;
         if bucket_count > limits.get_bucket_limit() {
             return Err(TantivyError::AggregationError(
@@ -134,6 +228,10 @@ impl IntermediateAggregationResults {
                 },
             ));
         }
+        res.set_warnings(warnings);
+        if let Some(sampled) = sampled {
+            res.set_sampled(sampled);
+        }
         Ok(res)
     }
 
@@ -145,6 +243,11 @@ impl IntermediateAggregationResults {
     ) -> crate::Result<AggregationResults> {
         let mut results: FxHashMap<String, AggregationResult> = FxHashMap::default();
         for (key, agg_res) in self.aggs_res.into_iter() {
+            if let IntermediateAggregationResult::Custom(custom) = &agg_res {
+                // Custom aggregations are not part of the request.
+                results.insert(key, AggregationResult::CustomResult(custom.finalize()));
+                continue;
+            }
             let req = req.get(key.as_str()).unwrap_or_else(|| {
                 panic!(
                     "Could not find key {:?} in request keys {:?}. This probably means that \
@@ -155,13 +258,11 @@ impl IntermediateAggregationResults {
             });
             results.insert(key, agg_res.into_final_result(req, limits)?);
         }
-        // Handle empty results
-        if results.len() != req.len() {
-            for (key, req) in req.iter() {
-                if !results.contains_key(key) {
-                    let empty_res = empty_from_req(req);
-                    results.insert(key.to_string(), empty_res.into_final_result(req, limits)?);
-                }
+        // Handle empty results. The custom results do not count, as they are not in the request.
+        for (key, req) in req.iter() {
+            if !results.contains_key(key) {
+                let empty_res = empty_from_req(req);
+                results.insert(key.to_string(), empty_res.into_final_result(req, limits)?);
             }
         }
 
@@ -175,7 +276,25 @@ impl IntermediateAggregationResults {
             aggs_res.insert(key.to_string(), empty_res);
         }
 
-        Self { aggs_res }
+        Self {
+            aggs_res,
+            warnings: Vec::new(),
+            sampled: None,
+            charged_memory: Default::default(),
+        }
+    }
+
+    /// Returns an estimate of the memory used by the results, in bytes.
+    ///
+    /// The estimate covers the bucket maps and the sub-aggregations of every bucket. Metrics are
+    /// counted with the size of their type only.
+    pub(crate) fn memory_consumption(&self) -> usize {
+        self.aggs_res.memory_consumption()
+            + self
+                .aggs_res
+                .values()
+                .map(IntermediateAggregationResult::memory_consumption)
+                .sum::<usize>()
     }
 
     /// Merge another intermediate aggregation result into this result.
@@ -186,19 +305,138 @@ impl IntermediateAggregationResults {
         for (left, right) in self.aggs_res.values_mut().zip(other.aggs_res.into_values()) {
             left.merge_fruits(right)?;
         }
+        if !other.warnings.is_empty() {
+            self.warnings.extend(other.warnings);
+            self.warnings.sort();
+            self.warnings.dedup();
+        }
+        match (&mut self.sampled, other.sampled) {
+            (Some(sampled), Some(other_sampled)) => sampled.merge(&other_sampled),
+            (sampled @ None, other_sampled) => *sampled = other_sampled,
+            (Some(_), None) => {}
+        }
         Ok(())
     }
 }
 
+/// Merges intermediate aggregation results as a tree: the results are merged by groups of
+/// `fan_in` in parallel on `executor`, then the merged results by groups of `fan_in`, and so on
+/// until one result is left.
+///
+/// The merged result is the same as merging the results one after the other. Each merged result
+/// is checked against the memory limit of `limits`, as long as it is not merged into the next
+/// level.
+///
+/// Returns the empty results of `request` if there are no results to merge.
+pub fn merge_intermediate_tree(
+    request: &Aggregations,
+    results: Vec<IntermediateAggregationResults>,
+    fan_in: usize,
+    limits: &AggregationLimitsGuard,
+    executor: &Executor,
+) -> crate::Result<IntermediateAggregationResults> {
+    merge_intermediate_tree_with_cancellation(request, results, fan_in, limits, executor, None)
+}
+
+/// Same as [`merge_intermediate_tree`], but checks `cancellation` before each merge.
+pub(crate) fn merge_intermediate_tree_with_cancellation(
+    request: &Aggregations,
+    results: Vec<IntermediateAggregationResults>,
+    fan_in: usize,
+    limits: &AggregationLimitsGuard,
+    executor: &Executor,
+    cancellation: Option<&CancellationToken>,
+) -> crate::Result<IntermediateAggregationResults> {
+    if fan_in < 2 {
+        return Err(TantivyError::InvalidArgument(format!(
+            "The fan-in of the merge needs to be at least 2, got {fan_in}."
+        )));
+    }
+    // The memory of the merged results is released when they are merged into the next level.
+    let mut level: Vec<(
+        IntermediateAggregationResults,
+        Option<AggregationLimitsGuard>,
+    )> = results.into_iter().map(|result| (result, None)).collect();
+    while level.len() > 1 {
+        let mut groups = Vec::with_capacity(level.len().div_ceil(fan_in));
+        let mut level_iter = level.into_iter();
+        loop {
+            let group: Vec<_> = level_iter.by_ref().take(fan_in).collect();
+            if group.is_empty() {
+                break;
+            }
+            groups.push(group);
+        }
+        level = executor.map(
+            |group| merge_group(group, limits, cancellation),
+            groups.into_iter(),
+        )?;
+    }
+    match level.pop() {
+        Some((merged, _guard)) => Ok(merged),
+        None => Ok(IntermediateAggregationResults::empty_from_req(request)),
+    }
+}
+
+/// Merges a group of results in order, accounting the memory of the merged result.
+fn merge_group(
+    group: Vec<(
+        IntermediateAggregationResults,
+        Option<AggregationLimitsGuard>,
+    )>,
+    limits: &AggregationLimitsGuard,
+    cancellation: Option<&CancellationToken>,
+) -> crate::Result<(
+    IntermediateAggregationResults,
+    Option<AggregationLimitsGuard>,
+)> {
+    let mut group_iter = group.into_iter();
+    let Some((mut merged, guard)) = group_iter.next() else {
+        return Err(TantivyError::InternalError(
+            "Empty group of intermediate aggregation results".to_string(),
+        ));
+    };
+    if group_iter.len() == 0 {
+        return Ok((merged, guard));
+    }
+    for (next, _guard) in group_iter {
+        if let Some(cancellation) = cancellation {
+            cancellation.check()?;
+        }
+        merged.merge_fruits(next)?;
+    }
+    let mut guard = limits.clone();
+    guard.add_memory_consumed(merged.memory_consumption() as u64)?;
+    Ok((merged, Some(guard)))
+}
+
 pub(crate) fn empty_from_req(req: &Aggregation) -> IntermediateAggregationResult {
     use AggregationVariants::*;
     match req.agg {
-        Terms(_) => IntermediateAggregationResult::Bucket(IntermediateBucketResult::Terms {
-            buckets: Default::default(),
-        }),
-        Range(_) => IntermediateAggregationResult::Bucket(IntermediateBucketResult::Range(
-            Default::default(),
-        )),
+        Terms(_) | PathTerms(_) | PrefixTerms(_) => {
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Terms {
+                buckets: Default::default(),
+            })
+        }
+        SignificantTerms(_) => {
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::SignificantTerms {
+                buckets: Default::default(),
+            })
+        }
+        Composite(ref req) => {
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Composite {
+                buckets: IntermediateCompositeBucketResult::new(req.size()),
+            })
+        }
+        Range(_) | DateRange(_) => IntermediateAggregationResult::Bucket(
+            IntermediateBucketResult::Range(Default::default()),
+        ),
+        Exists(_) | Missing(_) => {
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Single {
+                doc_count: 0,
+                sub_aggregation: Default::default(),
+            })
+        }
         Histogram(_) => {
             IntermediateAggregationResult::Bucket(IntermediateBucketResult::Histogram {
                 buckets: Vec::new(),
@@ -232,8 +470,8 @@ pub(crate) fn empty_from_req(req: &Aggregation) -> IntermediateAggregationResult
         Sum(_) => IntermediateAggregationResult::Metric(IntermediateMetricResult::Sum(
             IntermediateSum::default(),
         )),
-        Percentiles(_) => IntermediateAggregationResult::Metric(
-            IntermediateMetricResult::Percentiles(PercentilesCollector::default()),
+        Percentiles(ref req) => IntermediateAggregationResult::Metric(
+            IntermediateMetricResult::Percentiles(PercentilesCollector::from_req(req)),
         ),
         TopHits(ref req) => IntermediateAggregationResult::Metric(
             IntermediateMetricResult::TopHits(TopHitsTopNComputer::new(req)),
@@ -251,6 +489,16 @@ pub enum IntermediateAggregationResult {
     Bucket(IntermediateBucketResult),
     /// Metric variant
     Metric(IntermediateMetricResult),
+    /// Result of an aggregation implemented outside of tantivy.
+    ///
+    /// See [`custom_intermediate_result`](super::custom_intermediate_result).
+    Custom(
+        #[serde(
+            serialize_with = "custom_intermediate_result::serialize",
+            deserialize_with = "custom_intermediate_result::deserialize"
+        )]
+        Box<dyn CustomIntermediateAggregation>,
+    ),
 }
 
 impl IntermediateAggregationResult {
@@ -266,9 +514,57 @@ impl IntermediateAggregationResult {
             IntermediateAggregationResult::Metric(metric) => {
                 AggregationResult::MetricResult(metric.into_final_metric_result(req))
             }
+            IntermediateAggregationResult::Custom(custom) => {
+                AggregationResult::CustomResult(custom.finalize())
+            }
         };
         Ok(res)
     }
+    fn memory_consumption(&self) -> usize {
+        match self {
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Range(range_res)) => {
+                range_res.buckets.memory_consumption()
+                    + sub_aggregations_memory(
+                        range_res.buckets.values().map(|b| &b.sub_aggregation),
+                    )
+            }
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Histogram {
+                buckets,
+                ..
+            }) => {
+                buckets.capacity() * std::mem::size_of::<IntermediateHistogramBucketEntry>()
+                    + sub_aggregations_memory(buckets.iter().map(|b| &b.sub_aggregation))
+            }
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Terms { buckets }) => {
+                terms_entries_memory(&buckets.entries)
+            }
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::SignificantTerms {
+                buckets,
+            }) => {
+                buckets.entries.memory_consumption()
+                    + sub_aggregations_memory(buckets.entries.values().map(|b| &b.sub_aggregation))
+            }
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Composite {
+                buckets,
+            }) => {
+                buckets.entries.len()
+                    * (std::mem::size_of::<IntermediateCompositeKey>()
+                        + std::mem::size_of::<IntermediateTermBucketEntry>())
+                    + sub_aggregations_memory(buckets.entries.values().map(|b| &b.sub_aggregation))
+            }
+            IntermediateAggregationResult::Bucket(IntermediateBucketResult::Single {
+                sub_aggregation,
+                ..
+            }) => sub_aggregation.memory_consumption(),
+            IntermediateAggregationResult::Metric(_) => {
+                std::mem::size_of::<IntermediateMetricResult>()
+            }
+            IntermediateAggregationResult::Custom(_) => {
+                std::mem::size_of::<Box<dyn CustomIntermediateAggregation>>()
+            }
+        }
+    }
+
     fn merge_fruits(&mut self, other: IntermediateAggregationResult) -> crate::Result<()> {
         match (self, other) {
             (
@@ -279,11 +575,38 @@ impl IntermediateAggregationResult {
                 IntermediateAggregationResult::Metric(m1),
                 IntermediateAggregationResult::Metric(m2),
             ) => m1.merge_fruits(m2),
+            (
+                IntermediateAggregationResult::Custom(c1),
+                IntermediateAggregationResult::Custom(c2),
+            ) => custom_intermediate_result::merge_custom(c1.as_mut(), c2.as_ref()),
+            (IntermediateAggregationResult::Custom(_), _)
+            | (_, IntermediateAggregationResult::Custom(_)) => Err(TantivyError::AggregationError(
+                AggregationError::InternalError(
+                    "Cannot merge a custom intermediate aggregation result with a built-in one"
+                        .to_string(),
+                ),
+            )),
             _ => panic!("aggregation result type mismatch (mixed metric and buckets)"),
         }
     }
 }
 
+fn sub_aggregations_memory<'a>(
+    sub_aggregations: impl Iterator<Item = &'a IntermediateAggregationResults>,
+) -> usize {
+    sub_aggregations
+        .map(IntermediateAggregationResults::memory_consumption)
+        .sum()
+}
+
+/// Returns an estimate of the memory used by the buckets of a terms aggregation, in bytes.
+pub(crate) fn terms_entries_memory(
+    entries: &FxHashMap<IntermediateKey, IntermediateTermBucketEntry>,
+) -> usize {
+    entries.memory_consumption()
+        + sub_aggregations_memory(entries.values().map(|entry| &entry.sub_aggregation))
+}
+
 /// Holds the intermediate data for metric results
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum IntermediateMetricResult {
@@ -318,15 +641,15 @@ impl IntermediateMetricResult {
             IntermediateMetricResult::Count(intermediate_count) => {
                 MetricResult::Count(intermediate_count.finalize().into())
             }
-            IntermediateMetricResult::Max(intermediate_max) => {
-                MetricResult::Max(intermediate_max.finalize().into())
-            }
-            IntermediateMetricResult::Min(intermediate_min) => {
-                MetricResult::Min(intermediate_min.finalize().into())
-            }
-            IntermediateMetricResult::Stats(intermediate_stats) => {
-                MetricResult::Stats(intermediate_stats.finalize())
-            }
+            IntermediateMetricResult::Max(intermediate_max) => MetricResult::Max(
+                intermediate_max.finalize_with_format(req.agg.date_metric_format()),
+            ),
+            IntermediateMetricResult::Min(intermediate_min) => MetricResult::Min(
+                intermediate_min.finalize_with_format(req.agg.date_metric_format()),
+            ),
+            IntermediateMetricResult::Stats(intermediate_stats) => MetricResult::Stats(
+                intermediate_stats.finalize_with_format(req.agg.date_metric_format()),
+            ),
             IntermediateMetricResult::ExtendedStats(intermediate_stats) => {
                 MetricResult::ExtendedStats(intermediate_stats.finalize())
             }
@@ -426,9 +749,59 @@ pub enum IntermediateBucketResult {
         /// The term buckets
         buckets: IntermediateTermBucketResult,
     },
+    /// Significant terms aggregation
+    SignificantTerms {
+        /// The significant term buckets
+        buckets: IntermediateSignificantTermsBucketResult,
+    },
+    /// Composite aggregation
+    Composite {
+        /// The composite buckets
+        buckets: IntermediateCompositeBucketResult,
+    },
+    /// Single bucket aggregation, e.g. `exists` or `missing`
+    Single {
+        /// The number of documents in the bucket.
+        doc_count: u64,
+        /// The sub_aggregation in the bucket.
+        sub_aggregation: IntermediateAggregationResults,
+    },
 }
 
 impl IntermediateBucketResult {
+    /// Returns the sub-aggregation results of the buckets.
+    pub(crate) fn sub_aggregations_mut(&mut self) -> Vec<&mut IntermediateAggregationResults> {
+        match self {
+            IntermediateBucketResult::Range(range_res) => range_res
+                .buckets
+                .values_mut()
+                .map(|bucket| &mut bucket.sub_aggregation)
+                .collect(),
+            IntermediateBucketResult::Histogram { buckets, .. } => buckets
+                .iter_mut()
+                .map(|bucket| &mut bucket.sub_aggregation)
+                .collect(),
+            IntermediateBucketResult::Terms { buckets } => buckets
+                .entries
+                .values_mut()
+                .map(|bucket| &mut bucket.sub_aggregation)
+                .collect(),
+            IntermediateBucketResult::SignificantTerms { buckets } => buckets
+                .entries
+                .values_mut()
+                .map(|bucket| &mut bucket.sub_aggregation)
+                .collect(),
+            IntermediateBucketResult::Composite { buckets } => buckets
+                .entries
+                .values_mut()
+                .map(|bucket| &mut bucket.sub_aggregation)
+                .collect(),
+            IntermediateBucketResult::Single {
+                sub_aggregation, ..
+            } => vec![sub_aggregation],
+        }
+    }
+
     pub(crate) fn into_final_bucket_result(
         self,
         req: &Aggregation,
@@ -436,32 +809,37 @@ impl IntermediateBucketResult {
     ) -> crate::Result<BucketResult> {
         match self {
             IntermediateBucketResult::Range(range_res) => {
+                // The bounds of the date ranges are in milliseconds.
+                let (is_keyed, bounds_in_millis) = match &req.agg {
+                    AggregationVariants::Range(range_req) => (range_req.keyed, false),
+                    AggregationVariants::DateRange(date_range_req) => (date_range_req.keyed, true),
+                    _ => panic!("unexpected aggregation, expected range aggregation"),
+                };
                 let mut buckets: Vec<RangeBucketEntry> = range_res
                     .buckets
                     .into_values()
                     .map(|bucket| {
                         bucket.into_final_bucket_entry(
                             req.sub_aggregation(),
-                            req.agg
-                                .as_range()
-                                .expect("unexpected aggregation, expected histogram aggregation"),
+                            bounds_in_millis,
                             range_res.column_type,
                             limits,
                         )
                     })
                     .collect::<crate::Result<Vec<_>>>()?;
 
+                // Overlapping date ranges can start at the same date.
                 buckets.sort_by(|left, right| {
                     left.from
                         .unwrap_or(f64::MIN)
                         .total_cmp(&right.from.unwrap_or(f64::MIN))
+                        .then_with(|| {
+                            left.to
+                                .unwrap_or(f64::MAX)
+                                .total_cmp(&right.to.unwrap_or(f64::MAX))
+                        })
                 });
 
-                let is_keyed = req
-                    .agg
-                    .as_range()
-                    .expect("unexpected aggregation, expected range aggregation")
-                    .keyed;
                 let buckets = if is_keyed {
                     let mut bucket_map =
                         FxHashMap::with_capacity_and_hasher(buckets.len(), Default::default());
@@ -502,13 +880,45 @@ impl IntermediateBucketResult {
                 };
                 Ok(BucketResult::Histogram { buckets })
             }
-            IntermediateBucketResult::Terms { buckets: terms } => terms.into_final_result(
+            IntermediateBucketResult::Terms { buckets: terms } => {
+                let bucket_terms_req;
+                let terms_req = match &req.agg {
+                    AggregationVariants::PathTerms(path_terms) => {
+                        bucket_terms_req = path_terms.to_terms_req();
+                        &bucket_terms_req
+                    }
+                    AggregationVariants::PrefixTerms(prefix_terms) => {
+                        bucket_terms_req = prefix_terms.to_terms_req();
+                        &bucket_terms_req
+                    }
+                    agg => agg
+                        .as_term()
+                        .expect("unexpected aggregation, expected term aggregation"),
+                };
+                terms.into_final_result(terms_req, req.sub_aggregation(), limits)
+            }
+            IntermediateBucketResult::SignificantTerms { buckets } => buckets.into_final_result(
+                req.agg
+                    .as_significant_terms()
+                    .expect("unexpected aggregation, expected significant terms aggregation"),
+                req.sub_aggregation(),
+                limits,
+            ),
+            IntermediateBucketResult::Composite { buckets } => buckets.into_final_result(
                 req.agg
-                    .as_term()
-                    .expect("unexpected aggregation, expected term aggregation"),
+                    .as_composite()
+                    .expect("unexpected aggregation, expected composite aggregation"),
                 req.sub_aggregation(),
                 limits,
             ),
+            IntermediateBucketResult::Single {
+                doc_count,
+                sub_aggregation,
+            } => Ok(BucketResult::Single {
+                doc_count,
+                sub_aggregation: sub_aggregation
+                    .into_final_result_internal(req.sub_aggregation(), limits)?,
+            }),
         }
     }
 
@@ -526,14 +936,50 @@ impl IntermediateBucketResult {
                 term_res_left.sum_other_doc_count += term_res_right.sum_other_doc_count;
                 term_res_left.doc_count_error_upper_bound +=
                     term_res_right.doc_count_error_upper_bound;
+                term_res_left.spilled.append(term_res_right.spilled);
+                term_res_left.spill_if_over_budget()?;
+            }
+            (
+                IntermediateBucketResult::SignificantTerms {
+                    buckets: term_res_left,
+                },
+                IntermediateBucketResult::SignificantTerms {
+                    buckets: term_res_right,
+                },
+            ) => {
+                merge_maps(&mut term_res_left.entries, term_res_right.entries)?;
+                term_res_left.subset_size += term_res_right.subset_size;
+                term_res_left.superset_size += term_res_right.superset_size;
+            }
+            (
+                IntermediateBucketResult::Composite {
+                    buckets: composite_res_left,
+                },
+                IntermediateBucketResult::Composite {
+                    buckets: composite_res_right,
+                },
+            ) => {
+                composite_res_left.merge_fruits(composite_res_right)?;
             }
-
             (
                 IntermediateBucketResult::Range(range_res_left),
                 IntermediateBucketResult::Range(range_res_right),
             ) => {
                 merge_maps(&mut range_res_left.buckets, range_res_right.buckets)?;
             }
+            (
+                IntermediateBucketResult::Single {
+                    doc_count: doc_count_left,
+                    sub_aggregation: sub_aggregation_left,
+                },
+                IntermediateBucketResult::Single {
+                    doc_count: doc_count_right,
+                    sub_aggregation: sub_aggregation_right,
+                },
+            ) => {
+                *doc_count_left += doc_count_right;
+                sub_aggregation_left.merge_fruits(sub_aggregation_right)?;
+            }
             (
                 IntermediateBucketResult::Histogram {
                     buckets: buckets_left,
@@ -571,6 +1017,15 @@ impl IntermediateBucketResult {
             (IntermediateBucketResult::Terms { .. }, _) => {
                 panic!("try merge on different types")
             }
+            (IntermediateBucketResult::SignificantTerms { .. }, _) => {
+                panic!("try merge on different types")
+            }
+            (IntermediateBucketResult::Composite { .. }, _) => {
+                panic!("try merge on different types")
+            }
+            (IntermediateBucketResult::Single { .. }, _) => {
+                panic!("try merge on different types")
+            }
         }
         Ok(())
     }
@@ -589,88 +1044,172 @@ pub struct IntermediateTermBucketResult {
     pub(crate) entries: FxHashMap<IntermediateKey, IntermediateTermBucketEntry>,
     pub(crate) sum_other_doc_count: u64,
     pub(crate) doc_count_error_upper_bound: u64,
+    /// The buckets spilled to disk, see
+    /// [`TermsAggregation::allow_spill`](super::bucket::TermsAggregation::allow_spill). They are
+    /// only valid in the process which spilled them, and are not serialized.
+    #[serde(skip)]
+    pub(crate) spilled: SpilledTermBuckets,
 }
 
 impl IntermediateTermBucketResult {
+    /// Spills the buckets to disk if the aggregation allows it and they exceed the memory budget
+    /// of the spill.
+    pub(crate) fn spill_if_over_budget(&mut self) -> crate::Result<()> {
+        self.spilled.spill_if_over_budget(&mut self.entries)
+    }
+
+    /// Returns true if the keys mix numbers and text, the `missing` key aside.
+    pub(crate) fn has_mixed_keys(&self, missing: Option<&Key>) -> bool {
+        let missing = missing.cloned().map(IntermediateKey::from);
+        let (mut has_number, mut has_text) =
+            (self.spilled.has_number_keys(), self.spilled.has_text_keys());
+        for key in self.entries.keys() {
+            match key {
+                _ if Some(key) == missing.as_ref() => {}
+                IntermediateKey::Str(_) | IntermediateKey::IpAddr(_) => has_text = true,
+                IntermediateKey::Bool(_)
+                | IntermediateKey::F64(_)
+                | IntermediateKey::I64(_)
+                | IntermediateKey::U64(_) => has_number = true,
+            }
+        }
+        has_number && has_text
+    }
+
+    /// Converts the keys to text, merging the buckets whose keys have the same text.
+    pub(crate) fn convert_keys_to_text(&mut self) -> crate::Result<()> {
+        fn key_to_text(key: IntermediateKey) -> IntermediateKey {
+            let text = match key {
+                IntermediateKey::Bool(val) => val.to_string(),
+                key => Key::from(key).to_string(),
+            };
+            IntermediateKey::Str(text)
+        }
+        self.spilled.convert_keys(key_to_text)?;
+        for (key, entry) in std::mem::take(&mut self.entries) {
+            match self.entries.entry(key_to_text(key)) {
+                Entry::Occupied(mut existing_entry) => {
+                    existing_entry.get_mut().merge_fruits(entry)?
+                }
+                Entry::Vacant(vacant_entry) => {
+                    vacant_entry.insert(entry);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges the buckets whose text keys are within the distance of `fuzzy_key_merge`.
+    ///
+    /// The buckets are visited by decreasing count: a bucket not merged yet absorbs all of the
+    /// following buckets within the distance of its key.
+    pub(crate) fn merge_fuzzy_keys(
+        &mut self,
+        fuzzy_key_merge: &FuzzyKeyMerge,
+    ) -> crate::Result<()> {
+        let automaton_builder = automaton_builder(
+            fuzzy_key_merge.distance,
+            fuzzy_key_merge.transposition_cost_one,
+        )?;
+        let mut text_entries: Vec<Option<(String, IntermediateTermBucketEntry)>> = Vec::new();
+        for (key, entry) in std::mem::take(&mut self.entries) {
+            match key {
+                IntermediateKey::Str(text) => text_entries.push(Some((text, entry))),
+                key => {
+                    self.entries.insert(key, entry);
+                }
+            }
+        }
+        // Ties are broken by key, so the representative keys do not depend on the order in which
+        // the segment results were merged.
+        text_entries.sort_by(|left, right| {
+            let (left_key, left_entry) = left.as_ref().unwrap();
+            let (right_key, right_entry) = right.as_ref().unwrap();
+            right_entry
+                .doc_count
+                .cmp(&left_entry.doc_count)
+                .then_with(|| left_key.cmp(right_key))
+        });
+        for idx in 0..text_entries.len() {
+            let Some((key, mut entry)) = text_entries[idx].take() else {
+                continue;
+            };
+            let dfa = automaton_builder.build_dfa(&key);
+            for candidate in &mut text_entries[idx + 1..] {
+                let is_within_distance = candidate.as_ref().is_some_and(|(candidate_key, _)| {
+                    matches!(dfa.eval(candidate_key), Distance::Exact(_))
+                });
+                if is_within_distance {
+                    let (_, candidate_entry) = candidate.take().unwrap();
+                    entry.merge_fruits(candidate_entry)?;
+                }
+            }
+            self.entries.insert(IntermediateKey::Str(key), entry);
+        }
+        Ok(())
+    }
+
     pub(crate) fn into_final_result(
-        self,
+        mut self,
         req: &TermsAggregation,
         sub_aggregation_req: &Aggregations,
         limits: &mut AggregationLimitsGuard,
     ) -> crate::Result<BucketResult> {
+        if let Some(fuzzy_key_merge) = req.fuzzy_key_merge.as_ref() {
+            self.merge_fuzzy_keys(fuzzy_key_merge)?;
+        }
         let req = TermsAggregationInternal::from_req(req);
-        let mut buckets: Vec<BucketEntry> = self
-            .entries
-            .into_iter()
-            .filter(|bucket| bucket.1.doc_count as u64 >= req.min_doc_count)
-            .map(|(key, entry)| {
-                let key_as_string = match key {
-                    IntermediateKey::Bool(key) => {
-                        let val = if key { "true" } else { "false" };
-                        Some(val.to_string())
-                    }
-                    _ => None,
-                };
-                Ok(BucketEntry {
-                    key_as_string,
-                    key: key.into(),
-                    doc_count: entry.doc_count as u64,
-                    sub_aggregation: entry
-                        .sub_aggregation
-                        .into_final_result_internal(sub_aggregation_req, limits)?,
-                })
+        let into_bucket_entry = |key: IntermediateKey,
+                                 entry: IntermediateTermBucketEntry,
+                                 limits: &mut AggregationLimitsGuard|
+         -> crate::Result<BucketEntry> {
+            let key_as_string = match key {
+                IntermediateKey::Bool(key) => {
+                    let val = if key { "true" } else { "false" };
+                    Some(val.to_string())
+                }
+                _ => None,
+            };
+            Ok(BucketEntry {
+                key_as_string,
+                key: key.into(),
+                doc_count: entry.doc_count as u64,
+                sub_aggregation: entry
+                    .sub_aggregation
+                    .into_final_result_internal(sub_aggregation_req, limits)?,
             })
-            .collect::<crate::Result<_>>()?;
-
-        let order = req.order.order;
-        match req.order.target {
-            OrderTarget::Key => {
-                buckets.sort_by(|left, right| {
-                    if req.order.order == Order::Asc {
-                        left.key.partial_cmp(&right.key)
-                    } else {
-                        right.key.partial_cmp(&left.key)
-                    }
-                    .expect("expected type string, which is always sortable")
-                });
-            }
-            OrderTarget::Count => {
-                if req.order.order == Order::Desc {
-                    buckets.sort_unstable_by_key(|bucket| std::cmp::Reverse(bucket.doc_count()));
-                } else {
-                    buckets.sort_unstable_by_key(|bucket| bucket.doc_count());
+        };
+        let mut sum_other_doc_count = self.sum_other_doc_count;
+        let mut buckets: Vec<BucketEntry> = Vec::new();
+        if self.spilled.is_empty() {
+            for (key, entry) in self.entries {
+                if entry.doc_count as u64 >= req.min_doc_count {
+                    buckets.push(into_bucket_entry(key, entry, limits)?);
                 }
             }
-            OrderTarget::SubAggregation(name) => {
-                let (agg_name, agg_property) = get_agg_name_and_property(&name);
-                let mut buckets_with_val = buckets
-                    .into_iter()
-                    .map(|bucket| {
-                        let val = bucket
-                            .sub_aggregation
-                            .get_value_from_aggregation(agg_name, agg_property)?
-                            .unwrap_or(f64::MIN);
-                        Ok((bucket, val))
-                    })
-                    .collect::<crate::Result<Vec<_>>>()?;
-
-                buckets_with_val.sort_by(|(_, val1), (_, val2)| match &order {
-                    Order::Desc => val2.total_cmp(val1),
-                    Order::Asc => val1.total_cmp(val2),
-                });
-                buckets = buckets_with_val
-                    .into_iter()
-                    .map(|(bucket, _val)| bucket)
-                    .collect_vec();
+        } else {
+            // The merged buckets are streamed, keeping at most twice the top buckets in memory.
+            let max_buckets = (req.size as usize).max(1) * 2;
+            for bucket in self.spilled.merge(self.entries)? {
+                let (key, entry) = bucket?;
+                if (entry.doc_count as u64) < req.min_doc_count {
+                    continue;
+                }
+                buckets.push(into_bucket_entry(key, entry, limits)?);
+                if buckets.len() >= max_buckets {
+                    buckets = sort_term_buckets(buckets, &req.order)?;
+                    sum_other_doc_count += cut_off_buckets(&mut buckets, req.size as usize).1;
+                }
             }
         }
+        let mut buckets = sort_term_buckets(buckets, &req.order)?;
 
         // We ignore _term_doc_count_before_cutoff here, because it increases the upperbound error
         // only for terms that didn't make it into the top N.
         //
         // This can be interesting, as a value of quality of the results, but not good to check the
         // actual error count for the returned terms.
-        let (_term_doc_count_before_cutoff, sum_other_doc_count) =
+        let (_term_doc_count_before_cutoff, cut_off_doc_count) =
             cut_off_buckets(&mut buckets, req.size as usize);
 
         let doc_count_error_upper_bound = if req.show_term_doc_count_error {
@@ -681,13 +1220,208 @@ impl IntermediateTermBucketResult {
 
         Ok(BucketResult::Terms {
             buckets,
-            sum_other_doc_count: self.sum_other_doc_count + sum_other_doc_count,
+            sum_other_doc_count: sum_other_doc_count + cut_off_doc_count,
             doc_count_error_upper_bound,
         })
     }
 }
 
-trait MergeFruits {
+/// Sorts the buckets of a terms aggregation in the order of the request.
+fn sort_term_buckets(
+    mut buckets: Vec<BucketEntry>,
+    order: &CustomOrder,
+) -> crate::Result<Vec<BucketEntry>> {
+    match &order.target {
+        OrderTarget::Key => {
+            buckets.sort_by(|left, right| {
+                if order.order == Order::Asc {
+                    left.key.partial_cmp(&right.key)
+                } else {
+                    right.key.partial_cmp(&left.key)
+                }
+                .expect("expected type string, which is always sortable")
+            });
+        }
+        OrderTarget::Count => {
+            // Ties are broken by key, so the order does not depend on the order in which
+            // the segment results were merged.
+            buckets.sort_by(|left, right| {
+                let count_order = if order.order == Order::Desc {
+                    right.doc_count().cmp(&left.doc_count())
+                } else {
+                    left.doc_count().cmp(&right.doc_count())
+                };
+                count_order.then_with(|| cmp_keys(&left.key, &right.key))
+            });
+        }
+        OrderTarget::SubAggregation(name) => {
+            let (agg_name, agg_property) = get_agg_name_and_property(name);
+            let mut buckets_with_val = buckets
+                .into_iter()
+                .map(|bucket| {
+                    let val = bucket
+                        .sub_aggregation
+                        .get_value_from_aggregation(agg_name, agg_property)?
+                        .unwrap_or(f64::MIN);
+                    Ok((bucket, val))
+                })
+                .collect::<crate::Result<Vec<_>>>()?;
+
+            buckets_with_val.sort_by(|(left, val1), (right, val2)| {
+                match &order.order {
+                    Order::Desc => val2.total_cmp(val1),
+                    Order::Asc => val1.total_cmp(val2),
+                }
+                .then_with(|| cmp_keys(&left.key, &right.key))
+            });
+            buckets = buckets_with_val
+                .into_iter()
+                .map(|(bucket, _val)| bucket)
+                .collect_vec();
+        }
+    }
+    Ok(buckets)
+}
+
+/// Total order on bucket keys, used to break ties between buckets.
+fn cmp_keys(left: &Key, right: &Key) -> Ordering {
+    left.partial_cmp(right).unwrap_or(Ordering::Equal)
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Significant terms aggregation including the foreground and background sizes
+pub struct IntermediateSignificantTermsBucketResult {
+    pub(crate) entries: FxHashMap<IntermediateKey, IntermediateSignificantTermBucketEntry>,
+    /// The number of documents in the foreground set.
+    pub(crate) subset_size: u64,
+    /// The number of documents in the background set.
+    pub(crate) superset_size: u64,
+}
+
+impl IntermediateSignificantTermsBucketResult {
+    pub(crate) fn into_final_result(
+        self,
+        req: &SignificantTermsAggregation,
+        sub_aggregation_req: &Aggregations,
+        limits: &mut AggregationLimitsGuard,
+    ) -> crate::Result<BucketResult> {
+        let req = SignificantTermsAggregationInternal::from_req(req);
+        // The significance is recomputed on the merged counts.
+        let mut buckets: Vec<(IntermediateKey, IntermediateSignificantTermBucketEntry, f64)> = self
+            .entries
+            .into_iter()
+            .filter(|(_, entry)| entry.doc_count >= req.min_doc_count)
+            .map(|(key, entry)| {
+                let score = req.heuristic.score(
+                    entry.doc_count,
+                    self.subset_size,
+                    entry.bg_count,
+                    self.superset_size,
+                );
+                (key, entry, score)
+            })
+            .filter(|(_, _, score)| *score > 0.0)
+            .collect();
+        buckets.sort_by(|(left_key, _, left), (right_key, _, right)| {
+            right
+                .total_cmp(left)
+                .then_with(|| cmp_composite_key_values(left_key, right_key))
+        });
+        buckets.truncate(req.size as usize);
+
+        let buckets = buckets
+            .into_iter()
+            .map(|(key, entry, score)| {
+                Ok(SignificantTermBucketEntry {
+                    key: key.into(),
+                    doc_count: entry.doc_count,
+                    bg_count: entry.bg_count,
+                    score,
+                    sub_aggregation: entry
+                        .sub_aggregation
+                        .into_final_result_internal(sub_aggregation_req, limits)?,
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+
+        Ok(BucketResult::SignificantTerms {
+            doc_count: self.subset_size,
+            bg_count: self.superset_size,
+            buckets,
+        })
+    }
+}
+
+/// Composite aggregation, restricted to the lowest `size` keys.
+///
+/// The keys are the lowest ones following the `after` key of the request, which is applied when
+/// collecting the segments.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntermediateCompositeBucketResult {
+    pub(crate) entries: BTreeMap<IntermediateCompositeKey, IntermediateTermBucketEntry>,
+    /// The maximum number of buckets.
+    pub(crate) size: u32,
+}
+
+impl IntermediateCompositeBucketResult {
+    pub(crate) fn new(size: u32) -> Self {
+        IntermediateCompositeBucketResult {
+            entries: BTreeMap::new(),
+            size,
+        }
+    }
+
+    /// Merges the buckets of `other`, and drops the buckets beyond `size`.
+    ///
+    /// A bucket among the lowest `size` keys of the whole index is among the lowest `size` keys
+    /// of every segment it appears in, so that the doc counts of the kept buckets are exact.
+    fn merge_fruits(&mut self, other: IntermediateCompositeBucketResult) -> crate::Result<()> {
+        for (key, entry) in other.entries {
+            match self.entries.entry(key) {
+                btree_map::Entry::Occupied(mut occupied) => {
+                    occupied.get_mut().merge_fruits(entry)?;
+                }
+                btree_map::Entry::Vacant(vacant) => {
+                    vacant.insert(entry);
+                }
+            }
+        }
+        while self.entries.len() > self.size as usize {
+            self.entries.pop_last();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_final_result(
+        self,
+        req: &CompositeAggregation,
+        sub_aggregation_req: &Aggregations,
+        limits: &mut AggregationLimitsGuard,
+    ) -> crate::Result<BucketResult> {
+        let source_names = req.source_names()?;
+        let buckets: Vec<CompositeBucketEntry> = self
+            .entries
+            .into_iter()
+            .map(|(key, entry)| {
+                Ok(CompositeBucketEntry {
+                    key: source_names
+                        .iter()
+                        .map(|name| name.to_string())
+                        .zip(key.0.into_iter().map(Key::from))
+                        .collect(),
+                    doc_count: entry.doc_count as u64,
+                    sub_aggregation: entry
+                        .sub_aggregation
+                        .into_final_result_internal(sub_aggregation_req, limits)?,
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+        let after_key = buckets.last().map(|bucket| bucket.key.clone());
+        Ok(BucketResult::Composite { after_key, buckets })
+    }
+}
+
+pub(crate) trait MergeFruits {
     fn merge_fruits(&mut self, other: Self) -> crate::Result<()>;
 }
 
@@ -756,7 +1490,7 @@ impl IntermediateRangeBucketEntry {
     pub(crate) fn into_final_bucket_entry(
         self,
         req: &Aggregations,
-        _range_req: &RangeAggregation,
+        bounds_in_millis: bool,
         column_type: Option<ColumnType>,
         limits: &mut AggregationLimitsGuard,
     ) -> crate::Result<RangeBucketEntry> {
@@ -775,13 +1509,18 @@ impl IntermediateRangeBucketEntry {
         // If we have a date type on the histogram buckets, we add the `key_as_string` field as
         // rfc339
         if column_type == Some(ColumnType::DateTime) {
+            let format_bound = |val: f64| {
+                if bounds_in_millis {
+                    format_date_millis(val)
+                } else {
+                    format_date(val as i64)
+                }
+            };
             if let Some(val) = range_bucket_entry.to {
-                let key_as_string = format_date(val as i64)?;
-                range_bucket_entry.to_as_string = Some(key_as_string);
+                range_bucket_entry.to_as_string = Some(format_bound(val)?);
             }
             if let Some(val) = range_bucket_entry.from {
-                let key_as_string = format_date(val as i64)?;
-                range_bucket_entry.from_as_string = Some(key_as_string);
+                range_bucket_entry.from_as_string = Some(format_bound(val)?);
             }
         }
 
@@ -807,6 +1546,27 @@ impl MergeFruits for IntermediateTermBucketEntry {
     }
 }
 
+/// This is the significant term entry for a bucket, which contains the foreground and background
+/// counts, and optionally sub_aggregations.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntermediateSignificantTermBucketEntry {
+    /// The number of documents of the foreground containing the term.
+    pub doc_count: u64,
+    /// The number of documents of the background containing the term.
+    pub bg_count: u64,
+    /// The sub_aggregation in this bucket.
+    pub sub_aggregation: IntermediateAggregationResults,
+}
+
+impl MergeFruits for IntermediateSignificantTermBucketEntry {
+    fn merge_fruits(&mut self, other: IntermediateSignificantTermBucketEntry) -> crate::Result<()> {
+        self.doc_count += other.doc_count;
+        self.bg_count += other.bg_count;
+        self.sub_aggregation.merge_fruits(other.sub_aggregation)?;
+        Ok(())
+    }
+}
+
 impl MergeFruits for IntermediateRangeBucketEntry {
     fn merge_fruits(&mut self, other: IntermediateRangeBucketEntry) -> crate::Result<()> {
         self.doc_count += other.doc_count;
@@ -857,6 +1617,9 @@ mod tests {
         );
         IntermediateAggregationResults {
             aggs_res: map.into_iter().collect(),
+            warnings: Vec::new(),
+            sampled: None,
+            charged_memory: Default::default(),
         }
     }
 
@@ -891,6 +1654,9 @@ mod tests {
         );
         IntermediateAggregationResults {
             aggs_res: map.into_iter().collect(),
+            warnings: Vec::new(),
+            sampled: None,
+            charged_memory: Default::default(),
         }
     }
 
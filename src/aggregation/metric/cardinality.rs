@@ -137,15 +137,9 @@ impl SegmentCardinalityCollector {
         agg_accessor: &mut AggregationWithAccessor,
     ) {
         if let Some(missing) = agg_accessor.missing_value_for_accessor {
-            agg_accessor.column_block_accessor.fetch_block_with_missing(
-                docs,
-                &agg_accessor.accessor,
-                missing,
-            );
+            agg_accessor.fetch_block_with_missing(docs, missing);
         } else {
-            agg_accessor
-                .column_block_accessor
-                .fetch_block(docs, &agg_accessor.accessor);
+            agg_accessor.fetch_block(docs);
         }
     }
 
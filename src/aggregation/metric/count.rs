@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use super::*;
 use crate::aggregation::*;
+use crate::fastfield::MultiValueMode;
 
 /// A single-value metric aggregation that counts the number of values that are
 /// extracted from the aggregated documents.
@@ -27,6 +28,11 @@ pub struct CountAggregation {
     /// { "field": "my_numbers", "missing": "10.0" }
     #[serde(default, deserialize_with = "deserialize_option_f64")]
     pub missing: Option<f64>,
+    /// How the values of a document are combined on a multivalued field. With any mode but
+    /// `all`, which is the default, each document with a value is counted once.
+    /// See [`MultiValueMode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MultiValueMode>,
 }
 
 impl CountAggregation {
@@ -35,6 +41,7 @@ impl CountAggregation {
         Self {
             field: field_name,
             missing: None,
+            mode: None,
         }
     }
     /// Returns the field name the aggregation is computed on.
@@ -57,6 +64,11 @@ impl IntermediateCount {
             stats: collector.stats,
         }
     }
+
+    /// Creates a new [`IntermediateCount`] instance from precomputed stats.
+    pub(crate) fn from_stats(stats: IntermediateStats) -> Self {
+        Self { stats }
+    }
     /// Merges the other intermediate result into self.
     pub fn merge_fruits(&mut self, other: IntermediateCount) {
         self.stats.merge_fruits(other.stats);
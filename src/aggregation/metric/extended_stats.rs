@@ -11,7 +11,9 @@ use crate::aggregation::intermediate_agg_result::{
     IntermediateAggregationResult, IntermediateAggregationResults, IntermediateMetricResult,
 };
 use crate::aggregation::segment_agg_result::SegmentAggregationCollector;
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
+use crate::fastfield::MultiValueMode;
 use crate::{DocId, TantivyError};
 
 /// A multi-value metric aggregation that computes a collection of extended statistics
@@ -31,7 +33,18 @@ use crate::{DocId, TantivyError};
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ExtendedStatsAggregation {
     /// The field name to compute the stats on.
+    ///
+    /// Either `field` or `token_count_field` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub field: String,
+    /// The text field whose number of tokens per document is aggregated, instead of the values
+    /// of a fast field. Example in JSON format:
+    /// { "token_count_field": "title" }
+    ///
+    /// The number of tokens is approximate for long texts, see
+    /// [token counts](crate::aggregation::metric#token-counts).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count_field: Option<String>,
     /// The missing parameter defines how documents that are missing a value should be treated.
     /// By default they will be ignored but it is also possible to treat them as if they had a
     /// value. Examples in JSON format:
@@ -44,6 +57,14 @@ pub struct ExtendedStatsAggregation {
     /// { "field": "my_numbers", "sigma": "3.0" }
     #[serde(default)]
     pub sigma: Option<f64>,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
+    /// How the values of a document are combined on a multivalued field. By default, every
+    /// value is aggregated on its own. See [`MultiValueMode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MultiValueMode>,
 }
 
 impl ExtendedStatsAggregation {
@@ -51,8 +72,11 @@ impl ExtendedStatsAggregation {
     pub fn from_field_name(field_name: String) -> Self {
         ExtendedStatsAggregation {
             field: field_name,
+            token_count_field: None,
             missing: None,
             sigma: None,
+            value_transform: None,
+            mode: None,
         }
     }
     /// Returns the field name the aggregation is computed on.
@@ -322,6 +346,7 @@ impl IntermediateExtendedStats {
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct SegmentExtendedStatsCollector {
     missing: Option<u64>,
+    mode: MultiValueMode,
     field_type: ColumnType,
     pub(crate) extended_stats: IntermediateExtendedStats,
     pub(crate) accessor_idx: usize,
@@ -334,6 +359,7 @@ impl SegmentExtendedStatsCollector {
         sigma: Option<f64>,
         accessor_idx: usize,
         missing: Option<f64>,
+        mode: Option<MultiValueMode>,
     ) -> Self {
         let missing = missing.and_then(|val| f64_to_fastfield_u64(val, &field_type));
         Self {
@@ -341,6 +367,7 @@ impl SegmentExtendedStatsCollector {
             extended_stats: IntermediateExtendedStats::with_sigma(sigma),
             accessor_idx,
             missing,
+            mode: mode.unwrap_or_default(),
             val_cache: Default::default(),
         }
     }
@@ -351,20 +378,15 @@ impl SegmentExtendedStatsCollector {
         agg_accessor: &mut AggregationWithAccessor,
     ) {
         if let Some(missing) = self.missing.as_ref() {
-            agg_accessor.column_block_accessor.fetch_block_with_missing(
-                docs,
-                &agg_accessor.accessor,
-                *missing,
-            );
+            agg_accessor.fetch_block_with_missing(docs, *missing);
         } else {
-            agg_accessor
-                .column_block_accessor
-                .fetch_block(docs, &agg_accessor.accessor);
-        }
-        for val in agg_accessor.column_block_accessor.iter_vals() {
-            let val1 = f64_from_fastfield_u64(val, &self.field_type);
-            self.extended_stats.collect(val1);
+            agg_accessor.fetch_block(docs);
         }
+        let field_type = self.field_type;
+        let to_f64 = move |val| f64_from_fastfield_u64(val, &field_type);
+        collect_block_values(docs, agg_accessor, self.mode, to_f64, |val| {
+            self.extended_stats.collect(val)
+        });
     }
 }
 
@@ -392,24 +414,12 @@ impl SegmentAggregationCollector for SegmentExtendedStatsCollector {
         doc: crate::DocId,
         agg_with_accessor: &mut AggregationsWithAccessor,
     ) -> crate::Result<()> {
-        let field = &agg_with_accessor.aggs.values[self.accessor_idx].accessor;
-        if let Some(missing) = self.missing {
-            let mut has_val = false;
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.extended_stats.collect(val1);
-                has_val = true;
-            }
-            if !has_val {
-                self.extended_stats
-                    .collect(f64_from_fastfield_u64(missing, &self.field_type));
-            }
-        } else {
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.extended_stats.collect(val1);
-            }
-        }
+        let agg_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let field_type = self.field_type;
+        let to_f64 = move |val| f64_from_fastfield_u64(val, &field_type);
+        collect_doc_values(doc, agg_accessor, self.missing, self.mode, to_f64, |val| {
+            self.extended_stats.collect(val)
+        });
 
         Ok(())
     }
@@ -15,6 +15,24 @@
 //! - [Sum](SumAggregation)
 //! - [Count](CountAggregation)
 //! - [Percentiles](PercentilesAggregationReq)
+//!
+//! ## Token counts
+//!
+//! Instead of the values of a fast field, the `avg`, `max`, `min`, `stats`, `extended_stats` and
+//! `sum` aggregations can aggregate the number of tokens of a text field per document, with
+//! `token_count_field`: `{"avg": {"token_count_field": "title"}}`. The field does not need to be
+//! a fast field.
+//!
+//! By default, the number of tokens is read from the [fieldnorms](crate::fieldnorm) of the field,
+//! so the field is required to have fieldnorms. Fieldnorms are encoded on a single byte: the
+//! number of tokens is exact up to 40 tokens, and is rounded down above, to a value at most
+//! 11.2% lower than the actual number of tokens.
+//!
+//! The exact number of tokens is read instead if the field is indexed with
+//! [`TextFieldIndexing::set_exact_token_count()`](crate::schema::TextFieldIndexing::set_exact_token_count),
+//! which writes it to a hidden fast field column.
+//!
+//! In both cases, the documents without a value for the field count as 0 tokens.
 
 mod average;
 mod cardinality;
@@ -25,6 +43,7 @@ mod min;
 mod percentiles;
 mod stats;
 mod sum;
+mod token_count;
 mod top_hits;
 
 use std::collections::HashMap;
@@ -40,9 +59,13 @@ use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 pub use stats::*;
 pub use sum::*;
+pub(crate) use token_count::token_count_column;
 pub use top_hits::*;
 
+use super::agg_req_with_accessor::AggregationWithAccessor;
+use crate::fastfield::MultiValueMode;
 use crate::schema::OwnedValue;
+use crate::DocId;
 
 /// Single-metric aggregations use this common result structure.
 ///
@@ -51,20 +74,54 @@ use crate::schema::OwnedValue;
 pub struct SingleMetricResult {
     /// The value of the single value metric.
     pub value: Option<f64>,
+    /// The value formatted as requested by [`DateMetricFormat::Rfc3339`], if the metric is the
+    /// minimum or maximum of a date field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_as_string: Option<String>,
 }
 
 impl From<f64> for SingleMetricResult {
     fn from(value: f64) -> Self {
-        Self { value: Some(value) }
+        Self {
+            value: Some(value),
+            value_as_string: None,
+        }
     }
 }
 
 impl From<Option<f64>> for SingleMetricResult {
     fn from(value: Option<f64>) -> Self {
-        Self { value }
+        Self {
+            value,
+            value_as_string: None,
+        }
     }
 }
 
+/// Format of the minimum and maximum computed on a date field.
+///
+/// The metrics of date fields are returned in milliseconds since the epoch. With `rfc3339`,
+/// the minimum and maximum are also returned as RFC 3339 strings, e.g. in `value_as_string`.
+///
+/// # JSON Format
+/// ```json
+/// {
+///     "max": {
+///         "field": "timestamp",
+///         "format": "rfc3339"
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateMetricFormat {
+    /// Milliseconds since the epoch.
+    #[default]
+    EpochMillis,
+    /// Milliseconds since the epoch, along with an RFC 3339 string.
+    Rfc3339,
+}
+
 /// This is the wrapper of percentile entries, which can be vector or hashmap
 /// depending on if it's keyed or not.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -114,6 +171,66 @@ pub struct TopHitsMetricResult {
     pub hits: Vec<TopHitsVecEntry>,
 }
 
+/// Calls `collect` with the transformed values of `docs`, which need to be fetched in
+/// `agg_accessor` beforehand.
+///
+/// The values of each doc are first reduced to a single value by `mode`, unless it is
+/// [`MultiValueMode::All`]. `to_f64` maps the raw fast field values to the reduced domain.
+#[inline]
+pub(crate) fn collect_block_values(
+    docs: &[DocId],
+    agg_accessor: &AggregationWithAccessor,
+    mode: MultiValueMode,
+    to_f64: impl Fn(u64) -> f64,
+    mut collect: impl FnMut(f64),
+) {
+    if !mode.is_reducer() {
+        for val in agg_accessor.column_block_accessor.iter_vals() {
+            collect(agg_accessor.transform_value(to_f64(val)));
+        }
+        return;
+    }
+    for (_doc, vals) in agg_accessor
+        .column_block_accessor
+        .iter_docid_val_slices(docs, &agg_accessor.accessor)
+    {
+        if let Some(reduced) = mode.reduce(vals.iter().map(|&val| to_f64(val))) {
+            collect(agg_accessor.transform_value(reduced));
+        }
+    }
+}
+
+/// Calls `collect` with the transformed values of `doc`, or with `missing` if the doc has no
+/// value.
+///
+/// See [`collect_block_values`] for `mode` and `to_f64`.
+#[inline]
+pub(crate) fn collect_doc_values(
+    doc: DocId,
+    agg_accessor: &AggregationWithAccessor,
+    missing: Option<u64>,
+    mode: MultiValueMode,
+    to_f64: impl Fn(u64) -> f64,
+    mut collect: impl FnMut(f64),
+) {
+    let mut has_val = false;
+    if mode.is_reducer() {
+        let vals = agg_accessor.accessor.values_for_doc(doc).map(&to_f64);
+        if let Some(reduced) = mode.reduce(vals) {
+            collect(agg_accessor.transform_value(reduced));
+            has_val = true;
+        }
+    } else {
+        for val in agg_accessor.accessor.values_for_doc(doc) {
+            collect(agg_accessor.transform_value(to_f64(val)));
+            has_val = true;
+        }
+    }
+    if let (false, Some(missing)) = (has_val, missing) {
+        collect(agg_accessor.transform_value(to_f64(missing)));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::aggregation::agg_req::Aggregations;
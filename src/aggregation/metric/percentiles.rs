@@ -10,7 +10,9 @@ use crate::aggregation::intermediate_agg_result::{
     IntermediateAggregationResult, IntermediateAggregationResults, IntermediateMetricResult,
 };
 use crate::aggregation::segment_agg_result::SegmentAggregationCollector;
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
+use crate::fastfield::MultiValueMode;
 use crate::{DocId, TantivyError};
 
 /// # Percentiles
@@ -69,6 +71,28 @@ use crate::{DocId, TantivyError};
 /// calculating exact percentiles for large data sets can be computationally
 /// expensive and time-consuming. As a result, many percentile aggregation
 /// algorithms use approximation techniques to provide faster results.
+///
+/// The percentiles are estimated with a [DDSketch](https://arxiv.org/abs/1908.10693), whose
+/// error is relative to the value: the estimate of a percentile is within `1 / compression`
+/// of the value of the data point at its rank, i.e. within 1% with the default compression
+/// of 100. The rank itself is exact, and the 0th and 100th percentiles are the exact minimum
+/// and maximum. The error does not depend on the number of values nor on how the values are
+/// split across segments, since merging sketches is lossless.
+///
+/// The `compression` parameter trades memory for accuracy: a sketch holds at most
+/// `20.48 * compression` bins of 8 bytes for the positive values, and as many for the negative
+/// values, i.e. 16KB each for the default compression. Past that number of bins, the smallest
+/// values are merged together and lose accuracy, which only happens if the values span more
+/// than about 18 orders of magnitude.
+///
+/// ```JSON
+/// {
+///     "percentiles": {
+///         "field": "load_time",
+///         "compression": 1000
+///     }
+/// }
+/// ```
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PercentilesAggregationReq {
     /// The field name to compute the percentiles on.
@@ -89,7 +113,24 @@ pub struct PercentilesAggregationReq {
         deserialize_with = "deserialize_option_f64"
     )]
     pub missing: Option<f64>,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
+    /// How the values of a document are combined on a multivalued field. By default, every
+    /// value is aggregated on its own. See [`MultiValueMode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MultiValueMode>,
+    /// Trades memory for accuracy, the percentiles being estimated within a relative error of
+    /// `1 / compression`. Has to be greater than 1 and at most 10000.
+    /// Defaults to 100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<f64>,
 }
+
+const DEFAULT_COMPRESSION: f64 = 100.0;
+const MAX_COMPRESSION: f64 = 10_000.0;
+
 fn default_percentiles() -> &'static [f64] {
     &[1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0]
 }
@@ -105,6 +146,9 @@ impl PercentilesAggregationReq {
             percents: None,
             keyed: default_as_true(),
             missing: None,
+            value_transform: None,
+            mode: None,
+            compression: None,
         }
     }
     /// Returns the field name the aggregation is computed on.
@@ -112,6 +156,12 @@ impl PercentilesAggregationReq {
         &self.field
     }
 
+    /// Returns true if the percentiles are estimated with the default compression.
+    pub(crate) fn has_default_compression(&self) -> bool {
+        self.compression
+            .is_none_or(|compression| compression == DEFAULT_COMPRESSION)
+    }
+
     fn validate(&self) -> crate::Result<()> {
         if let Some(percents) = self.percents.as_ref() {
             let all_in_range = percents
@@ -126,6 +176,16 @@ impl PercentilesAggregationReq {
                 ));
             }
         }
+        if let Some(compression) = self.compression {
+            if !(compression > 1.0 && compression <= MAX_COMPRESSION) {
+                return Err(TantivyError::AggregationError(
+                    AggregationError::InvalidRequest(format!(
+                        "The compression of percentiles has to be greater than 1 and at most \
+                         {MAX_COMPRESSION}, got {compression}"
+                    )),
+                ));
+            }
+        }
 
         Ok(())
     }
@@ -137,6 +197,7 @@ pub(crate) struct SegmentPercentilesCollector {
     pub(crate) percentiles: PercentilesCollector,
     pub(crate) accessor_idx: usize,
     missing: Option<u64>,
+    mode: MultiValueMode,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -211,14 +272,35 @@ impl PercentilesCollector {
     }
 
     fn new() -> Self {
-        let ddsketch_config = sketches_ddsketch::Config::defaults();
+        Self::with_compression(DEFAULT_COMPRESSION)
+    }
+
+    /// Creates an empty collector for the given request. Sketches only merge if they were
+    /// created with the same compression.
+    pub(crate) fn from_req(req: &PercentilesAggregationReq) -> Self {
+        Self::with_compression(req.compression.unwrap_or(DEFAULT_COMPRESSION))
+    }
+
+    fn with_compression(compression: f64) -> Self {
+        // With the default compression, this is the default configuration of the sketch.
+        let ddsketch_config = sketches_ddsketch::Config::new(
+            1.0 / compression,
+            (compression * 20.48).ceil() as u32,
+            1.0e-9,
+        );
         let sketch = sketches_ddsketch::DDSketch::new(ddsketch_config);
         Self { sketch }
     }
-    fn collect(&mut self, val: f64) {
+    pub(crate) fn collect(&mut self, val: f64) {
         self.sketch.add(val);
     }
 
+    /// Returns the estimate of the quantile `q`, between 0 and 1, or `None` if the sketch is
+    /// empty or `q` is out of range.
+    pub(crate) fn quantile(&self, q: f64) -> Option<f64> {
+        self.sketch.quantile(q).ok().flatten()
+    }
+
     pub(crate) fn merge_fruits(&mut self, right: PercentilesCollector) -> crate::Result<()> {
         self.sketch.merge(&right.sketch).map_err(|err| {
             TantivyError::AggregationError(AggregationError::InternalError(format!(
@@ -243,9 +325,10 @@ impl SegmentPercentilesCollector {
 
         Ok(Self {
             field_type,
-            percentiles: PercentilesCollector::new(),
+            percentiles: PercentilesCollector::from_req(req),
             accessor_idx,
             missing,
+            mode: req.mode.unwrap_or_default(),
         })
     }
     #[inline]
@@ -255,21 +338,16 @@ impl SegmentPercentilesCollector {
         agg_accessor: &mut AggregationWithAccessor,
     ) {
         if let Some(missing) = self.missing.as_ref() {
-            agg_accessor.column_block_accessor.fetch_block_with_missing(
-                docs,
-                &agg_accessor.accessor,
-                *missing,
-            );
+            agg_accessor.fetch_block_with_missing(docs, *missing);
         } else {
-            agg_accessor
-                .column_block_accessor
-                .fetch_block(docs, &agg_accessor.accessor);
+            agg_accessor.fetch_block(docs);
         }
 
-        for val in agg_accessor.column_block_accessor.iter_vals() {
-            let val1 = f64_from_fastfield_u64(val, &self.field_type);
-            self.percentiles.collect(val1);
-        }
+        let field_type = self.field_type;
+        let to_f64 = move |val| f64_from_fastfield_u64(val, &field_type);
+        collect_block_values(docs, agg_accessor, self.mode, to_f64, |val| {
+            self.percentiles.collect(val)
+        });
     }
 }
 
@@ -297,25 +375,12 @@ impl SegmentAggregationCollector for SegmentPercentilesCollector {
         doc: crate::DocId,
         agg_with_accessor: &mut AggregationsWithAccessor,
     ) -> crate::Result<()> {
-        let field = &agg_with_accessor.aggs.values[self.accessor_idx].accessor;
-
-        if let Some(missing) = self.missing {
-            let mut has_val = false;
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.percentiles.collect(val1);
-                has_val = true;
-            }
-            if !has_val {
-                self.percentiles
-                    .collect(f64_from_fastfield_u64(missing, &self.field_type));
-            }
-        } else {
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.percentiles.collect(val1);
-            }
-        }
+        let agg_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let field_type = self.field_type;
+        let to_f64 = move |val| f64_from_fastfield_u64(val, &field_type);
+        collect_doc_values(doc, agg_accessor, self.missing, self.mode, to_f64, |val| {
+            self.percentiles.collect(val)
+        });
 
         Ok(())
     }
@@ -341,6 +406,7 @@ mod tests {
     use rand::SeedableRng;
     use serde_json::Value;
 
+    use super::{PercentileValues, PercentilesAggregationReq, PercentilesCollector};
     use crate::aggregation::agg_req::Aggregations;
     use crate::aggregation::agg_result::AggregationResults;
     use crate::aggregation::tests::{
@@ -351,6 +417,152 @@ mod tests {
     use crate::schema::{Schema, FAST};
     use crate::Index;
 
+    /// Value at the rank of percentile `percent` of the sorted values, as defined by the sketch.
+    fn exact_percentile(percent: f64, sorted_values: &[f64]) -> f64 {
+        let rank = (percent / 100.0 * (sorted_values.len() as f64 - 1.0)) as usize;
+        sorted_values[rank]
+    }
+
+    #[test]
+    fn test_percentiles_accuracy_bounds() {
+        use rand_distr::Distribution;
+        let lg_norm = rand_distr::LogNormal::new(2.996f64, 0.979f64).unwrap();
+        let mut rng = StdRng::from_seed([2u8; 32]);
+        let mut values: Vec<f64> = (0..1_000_000).map(|_| lg_norm.sample(&mut rng)).collect();
+        let percents = vec![0.0, 1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0, 99.9, 100.0];
+
+        for compression in [100.0, 1000.0] {
+            let mut req = PercentilesAggregationReq::from_field_name("score".to_string());
+            req.percents = Some(percents.clone());
+            req.compression = Some(compression);
+            // One sketch per shard, serialized and merged like the intermediate results of a
+            // distributed search.
+            let mut merged = PercentilesCollector::from_req(&req);
+            for shard_values in values.chunks(300_000) {
+                let mut collector = PercentilesCollector::from_req(&req);
+                for &value in shard_values {
+                    collector.collect(value);
+                }
+                let serialized = postcard::to_allocvec(&collector).unwrap();
+                merged
+                    .merge_fruits(postcard::from_bytes(&serialized).unwrap())
+                    .unwrap();
+            }
+            let PercentileValues::HashMap(estimates) = merged.into_final_result(&req).values else {
+                panic!("expected keyed percentiles");
+            };
+
+            values.sort_unstable_by(f64::total_cmp);
+            for percent in &percents {
+                let exact = exact_percentile(*percent, &values);
+                let estimate = estimates[&super::format_percentile(*percent)];
+                assert_le!(
+                    (estimate - exact).abs(),
+                    exact / compression,
+                    "percent {percent}, compression {compression}"
+                );
+            }
+            assert_eq!(estimates["0.0"], values[0]);
+            assert_eq!(estimates["100.0"], values[values.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn test_percentiles_tiny_dataset() -> crate::Result<()> {
+        let values = vec![16.0, 1.0, 4.0, 2.0, 8.0];
+        let index = get_test_index_from_values(false, &values)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "percentiles": {
+                "percentiles": {
+                    "field": "score",
+                    "percents": [0, 10, 25, 50, 60, 75, 99, 100],
+                    "compression": 10000
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+
+        let mut sorted_values = values.clone();
+        sorted_values.sort_unstable_by(f64::total_cmp);
+        for percent in [0.0, 10.0, 25.0, 50.0, 60.0, 75.0, 99.0, 100.0] {
+            let estimate = res["percentiles"]["values"][super::format_percentile(percent)]
+                .as_f64()
+                .unwrap();
+            let exact = exact_percentile(percent, &sorted_values);
+            assert_le!(
+                (estimate - exact).abs(),
+                exact / 10000.0,
+                "percent {percent}"
+            );
+        }
+        assert_eq!(res["percentiles"]["values"]["0.0"], 1.0);
+        assert_eq!(res["percentiles"]["values"]["100.0"], 16.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_percentiles_nested_in_terms() -> crate::Result<()> {
+        let segment_and_values = vec![
+            (1..=100)
+                .map(|value| (value as f64, "fast".to_string()))
+                .collect_vec(),
+            (1..=100)
+                .map(|value| (value as f64 * 100.0, "slow".to_string()))
+                .collect_vec(),
+        ];
+        let index = get_test_index_from_values_and_terms(false, &segment_and_values)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "by_term": {
+                "terms": { "field": "string_id", "order": { "_key": "asc" } },
+                "aggs": {
+                    "latency": {
+                        "percentiles": {
+                            "field": "score_f64",
+                            "percents": [50, 99],
+                            "compression": 1000
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+
+        let buckets = &res["by_term"]["buckets"];
+        assert_eq!(buckets[0]["key"], "fast");
+        assert_eq!(buckets[1]["key"], "slow");
+        let check = |bucket: &Value, percent: &str, exact: f64| {
+            let estimate = bucket["latency"]["values"][percent].as_f64().unwrap();
+            assert_le!((estimate - exact).abs(), exact / 1000.0);
+        };
+        check(&buckets[0], "50.0", 50.0);
+        check(&buckets[0], "99.0", 99.0);
+        check(&buckets[1], "50.0", 5000.0);
+        check(&buckets[1], "99.0", 9900.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_percentiles_invalid_compression() -> crate::Result<()> {
+        let index = get_test_index_from_values(false, &[1.0])?;
+        for compression in [0.5, 1.0, 20_000.0] {
+            let agg_req: Aggregations = serde_json::from_value(json!({
+                "percentiles": {
+                    "percentiles": { "field": "score", "compression": compression }
+                }
+            }))
+            .unwrap();
+            let err = exec_request_with_query(agg_req, &index, None).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("The compression of percentiles has to be greater than 1"),
+                "{err}"
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_aggregation_percentiles_empty_index() -> crate::Result<()> {
         // test index without segments
@@ -6,11 +6,14 @@ use super::*;
 use crate::aggregation::agg_req_with_accessor::{
     AggregationWithAccessor, AggregationsWithAccessor,
 };
+use crate::aggregation::date::format_date;
 use crate::aggregation::intermediate_agg_result::{
     IntermediateAggregationResult, IntermediateAggregationResults, IntermediateMetricResult,
 };
 use crate::aggregation::segment_agg_result::SegmentAggregationCollector;
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
+use crate::fastfield::MultiValueMode;
 use crate::{DocId, TantivyError};
 
 /// A multi-value metric aggregation that computes a collection of statistics on numeric values that
@@ -29,13 +32,35 @@ use crate::{DocId, TantivyError};
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct StatsAggregation {
     /// The field name to compute the stats on.
+    ///
+    /// Either `field` or `token_count_field` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub field: String,
+    /// The text field whose number of tokens per document is aggregated, instead of the values
+    /// of a fast field. Example in JSON format:
+    /// { "token_count_field": "title" }
+    ///
+    /// The number of tokens is approximate for long texts, see
+    /// [token counts](crate::aggregation::metric#token-counts).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count_field: Option<String>,
     /// The missing parameter defines how documents that are missing a value should be treated.
     /// By default they will be ignored but it is also possible to treat them as if they had a
     /// value. Examples in JSON format:
     /// { "field": "my_numbers", "missing": "10.0" }
     #[serde(default, deserialize_with = "deserialize_option_f64")]
     pub missing: Option<f64>,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
+    /// How the values of a document are combined on a multivalued field. By default, every
+    /// value is aggregated on its own. See [`MultiValueMode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MultiValueMode>,
+    /// Format of the minimum and maximum on a date field. See [`DateMetricFormat`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<DateMetricFormat>,
 }
 
 impl StatsAggregation {
@@ -43,7 +68,11 @@ impl StatsAggregation {
     pub fn from_field_name(field_name: String) -> Self {
         StatsAggregation {
             field: field_name,
+            token_count_field: None,
             missing: None,
+            value_transform: None,
+            mode: None,
+            format: None,
         }
     }
     /// Returns the field name the aggregation is computed on.
@@ -65,6 +94,12 @@ pub struct Stats {
     pub max: Option<f64>,
     /// The average of the fast field values. `None` if count equals zero.
     pub avg: Option<f64>,
+    /// The min value formatted as requested by [`DateMetricFormat::Rfc3339`], for date fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_as_string: Option<String>,
+    /// The max value formatted as requested by [`DateMetricFormat::Rfc3339`], for date fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_as_string: Option<String>,
 }
 
 impl Stats {
@@ -96,6 +131,10 @@ pub struct IntermediateStats {
     pub(crate) min: f64,
     /// The max value.
     pub(crate) max: f64,
+    /// Whether the values are dates, in nanoseconds. They are converted to milliseconds on
+    /// finalization.
+    #[serde(default)]
+    pub(crate) is_date: bool,
 }
 
 impl Default for IntermediateStats {
@@ -106,6 +145,7 @@ impl Default for IntermediateStats {
             delta: 0.0,
             min: f64::MAX,
             max: f64::MIN,
+            is_date: false,
         }
     }
 }
@@ -123,36 +163,73 @@ impl IntermediateStats {
 
         self.min = self.min.min(other.min);
         self.max = self.max.max(other.max);
+        // Segments without a value for the field have no column type, and are not dates.
+        self.is_date |= other.is_date;
     }
 
     /// Computes the final stats value.
+    ///
+    /// Dates are returned in milliseconds.
     pub fn finalize(&self) -> Stats {
+        let unit = if self.is_date { 1_000_000.0 } else { 1.0 };
         let min = if self.count == 0 {
             None
         } else {
-            Some(self.min)
+            Some(self.min / unit)
         };
         let max = if self.count == 0 {
             None
         } else {
-            Some(self.max)
+            Some(self.max / unit)
         };
         let avg = if self.count == 0 {
             None
         } else {
-            Some(self.sum / (self.count as f64))
+            Some(self.sum / (self.count as f64) / unit)
         };
         Stats {
             count: self.count,
-            sum: self.sum,
+            sum: self.sum / unit,
             min,
             max,
             avg,
+            min_as_string: None,
+            max_as_string: None,
         }
     }
 
+    /// Computes the final stats value, formatting dates as requested by `format`.
+    pub(crate) fn finalize_with_format(&self, format: Option<DateMetricFormat>) -> Stats {
+        Stats {
+            min_as_string: self.min_as_string(format),
+            max_as_string: self.max_as_string(format),
+            ..self.finalize()
+        }
+    }
+
+    pub(in crate::aggregation::metric) fn min_as_string(
+        &self,
+        format: Option<DateMetricFormat>,
+    ) -> Option<String> {
+        self.format_date(self.min, format)
+    }
+
+    pub(in crate::aggregation::metric) fn max_as_string(
+        &self,
+        format: Option<DateMetricFormat>,
+    ) -> Option<String> {
+        self.format_date(self.max, format)
+    }
+
+    fn format_date(&self, value: f64, format: Option<DateMetricFormat>) -> Option<String> {
+        if !self.is_date || self.count == 0 || format != Some(DateMetricFormat::Rfc3339) {
+            return None;
+        }
+        format_date(value as i64).ok()
+    }
+
     #[inline]
-    pub(in crate::aggregation::metric) fn collect(&mut self, value: f64) {
+    pub(crate) fn collect(&mut self, value: f64) {
         self.count += 1;
 
         // kahan algorithm for sum
@@ -179,6 +256,7 @@ pub(crate) enum SegmentStatsType {
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct SegmentStatsCollector {
     missing: Option<u64>,
+    mode: MultiValueMode,
     field_type: ColumnType,
     pub(crate) collecting_for: SegmentStatsType,
     pub(crate) stats: IntermediateStats,
@@ -192,52 +270,61 @@ impl SegmentStatsCollector {
         collecting_for: SegmentStatsType,
         accessor_idx: usize,
         missing: Option<f64>,
+        mode: Option<MultiValueMode>,
     ) -> Self {
         let missing = missing.and_then(|val| f64_to_fastfield_u64(val, &field_type));
+        let stats = IntermediateStats {
+            is_date: field_type == ColumnType::DateTime,
+            ..Default::default()
+        };
         Self {
             field_type,
             collecting_for,
-            stats: IntermediateStats::default(),
+            stats,
             accessor_idx,
             missing,
+            mode: mode.unwrap_or_default(),
             val_cache: Default::default(),
         }
     }
+
+    /// Maps a fast field value to `f64`. The values of non-numeric columns map to `0.0`, only
+    /// their count being relevant.
     #[inline]
-    pub(crate) fn collect_block_with_field(
-        &mut self,
-        docs: &[DocId],
-        agg_accessor: &mut AggregationWithAccessor,
-    ) {
-        if let Some(missing) = self.missing.as_ref() {
-            agg_accessor.column_block_accessor.fetch_block_with_missing(
-                docs,
-                &agg_accessor.accessor,
-                *missing,
-            );
-        } else {
-            agg_accessor
-                .column_block_accessor
-                .fetch_block(docs, &agg_accessor.accessor);
-        }
-        if [
+    fn to_f64(&self) -> impl Fn(u64) -> f64 + use<> {
+        let field_type = self.field_type;
+        let is_numeric = [
             ColumnType::I64,
             ColumnType::U64,
             ColumnType::F64,
             ColumnType::DateTime,
+            ColumnType::Bool,
         ]
-        .contains(&self.field_type)
-        {
-            for val in agg_accessor.column_block_accessor.iter_vals() {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.stats.collect(val1);
+        .contains(&field_type);
+        move |val| {
+            if is_numeric {
+                f64_from_fastfield_u64(val, &field_type)
+            } else {
+                0.0
             }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn collect_block_with_field(
+        &mut self,
+        docs: &[DocId],
+        agg_accessor: &mut AggregationWithAccessor,
+    ) {
+        if let Some(missing) = self.missing.as_ref() {
+            agg_accessor.fetch_block_with_missing(docs, *missing);
         } else {
-            for _val in agg_accessor.column_block_accessor.iter_vals() {
-                // we ignore the value and simply record that we got something
-                self.stats.collect(0.0);
-            }
+            agg_accessor.fetch_block(docs);
         }
+        let to_f64 = self.to_f64();
+        collect_block_values(docs, agg_accessor, self.mode, to_f64, |val| {
+            self.stats.collect(val)
+        });
     }
 }
 
@@ -283,24 +370,11 @@ impl SegmentAggregationCollector for SegmentStatsCollector {
         doc: crate::DocId,
         agg_with_accessor: &mut AggregationsWithAccessor,
     ) -> crate::Result<()> {
-        let field = &agg_with_accessor.aggs.values[self.accessor_idx].accessor;
-        if let Some(missing) = self.missing {
-            let mut has_val = false;
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.stats.collect(val1);
-                has_val = true;
-            }
-            if !has_val {
-                self.stats
-                    .collect(f64_from_fastfield_u64(missing, &self.field_type));
-            }
-        } else {
-            for val in field.values_for_doc(doc) {
-                let val1 = f64_from_fastfield_u64(val, &self.field_type);
-                self.stats.collect(val1);
-            }
-        }
+        let agg_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let to_f64 = self.to_f64();
+        collect_doc_values(doc, agg_accessor, self.missing, self.mode, to_f64, |val| {
+            self.stats.collect(val)
+        });
 
         Ok(())
     }
@@ -327,9 +401,10 @@ mod tests {
         exec_request_with_query, get_test_index_2_segments, get_test_index_from_values,
     };
     use crate::aggregation::AggregationCollector;
+    use crate::fastfield::MultiValueMode;
     use crate::query::{AllQuery, TermQuery};
     use crate::schema::{IndexRecordOption, Schema, FAST};
-    use crate::{Index, IndexWriter, Term};
+    use crate::{DateTime, Index, IndexWriter, TantivyDocument, Term};
 
     #[test]
     fn test_aggregation_stats_empty_index() -> crate::Result<()> {
@@ -707,4 +782,195 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_stats_bool_fraction() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let flag = schema_builder.add_bool_field("flag", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(flag => true))?;
+        index_writer.add_document(doc!(flag => false))?;
+        index_writer.add_document(doc!(flag => true))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(flag => true))?;
+        index_writer.add_document(doc!())?;
+        index_writer.commit()?;
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "flag_stats": { "stats": { "field": "flag" } },
+            "flag_fraction": { "avg": { "field": "flag" } },
+            "flag_count": { "value_count": { "field": "flag" } },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        assert_eq!(
+            res["flag_stats"],
+            json!({
+                "avg": 0.75,
+                "count": 4,
+                "max": 1.0,
+                "min": 0.0,
+                "sum": 3.0
+            })
+        );
+        assert_eq!(res["flag_fraction"]["value"], 0.75);
+        assert_eq!(res["flag_count"]["value"], 4.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_date_format() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let date = schema_builder.add_date_field("date", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        // 2015-01-01T00:00:00Z and 2015-01-02T12:00:00Z
+        index_writer.add_document(doc!(date => DateTime::from_timestamp_secs(1_420_070_400)))?;
+        index_writer.add_document(doc!(date => DateTime::from_timestamp_secs(1_420_200_000)))?;
+        index_writer.commit()?;
+        // A segment without dates.
+        index_writer.add_document(doc!())?;
+        index_writer.commit()?;
+        // 2015-01-03T00:00:00Z
+        index_writer.add_document(doc!(date => DateTime::from_timestamp_secs(1_420_243_200)))?;
+        index_writer.commit()?;
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "date_stats": { "stats": { "field": "date", "format": "rfc3339" } },
+            "date_min": { "min": { "field": "date", "format": "rfc3339" } },
+            "date_max": { "max": { "field": "date" } },
+            "date_avg": { "avg": { "field": "date" } },
+        }))
+        .unwrap();
+        let res = exec_request_with_query(agg_req, &index, None)?;
+        assert_eq!(
+            res["date_stats"],
+            json!({
+                "avg": 1420171200000.0,
+                "count": 3,
+                "max": 1420243200000.0,
+                "max_as_string": "2015-01-03T00:00:00Z",
+                "min": 1420070400000.0,
+                "min_as_string": "2015-01-01T00:00:00Z",
+                "sum": 4260513600000.0
+            })
+        );
+        assert_eq!(
+            res["date_min"],
+            json!({
+                "value": 1420070400000.0,
+                "value_as_string": "2015-01-01T00:00:00Z"
+            })
+        );
+        assert_eq!(res["date_max"], json!({ "value": 1420243200000.0 }));
+        assert_eq!(res["date_avg"], json!({ "value": 1420171200000.0 }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_text_field_error_has_path() -> crate::Result<()> {
+        let index = get_test_index_2_segments(false)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "by_score": {
+                "histogram": { "field": "score", "interval": 10.0 },
+                "aggs": {
+                    "text_stats": { "stats": { "field": "text" } }
+                }
+            }
+        }))
+        .unwrap();
+        let err = exec_request_with_query(agg_req, &index, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Aggregation \"by_score.text_stats\": field \"text\" of type Str is not supported, \
+             expected a numeric, date or bool field"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_multi_value_modes_match_brute_force() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let vals_field = schema_builder.add_i64_field("vals", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        // Docs with no value, a single value and many values, over two segments.
+        let segments: Vec<Vec<Vec<i64>>> = vec![
+            vec![vec![], vec![3], vec![5, -2, 8], vec![1, 1]],
+            vec![vec![-7], vec![], vec![4, 9, 2, 2], vec![]],
+        ];
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in &segments {
+            for doc_vals in segment {
+                let mut doc = TantivyDocument::default();
+                for &val in doc_vals {
+                    doc.add_i64(vals_field, val);
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        let modes = [
+            MultiValueMode::Min,
+            MultiValueMode::Max,
+            MultiValueMode::Sum,
+            MultiValueMode::Avg,
+            MultiValueMode::All,
+        ];
+        for mode in modes {
+            for missing in [None, Some(100.0)] {
+                let mut expected_vals: Vec<f64> = Vec::new();
+                for doc_vals in segments.iter().flatten() {
+                    let doc_vals: Vec<f64> = doc_vals.iter().map(|&val| val as f64).collect();
+                    match (doc_vals.is_empty(), missing) {
+                        (true, Some(missing)) => expected_vals.push(missing),
+                        (true, None) => {}
+                        (false, _) if mode == MultiValueMode::All => expected_vals.extend(doc_vals),
+                        (false, _) => {
+                            let sum: f64 = doc_vals.iter().sum();
+                            expected_vals.push(match mode {
+                                MultiValueMode::Min => {
+                                    doc_vals.iter().copied().fold(f64::MAX, f64::min)
+                                }
+                                MultiValueMode::Max => {
+                                    doc_vals.iter().copied().fold(f64::MIN, f64::max)
+                                }
+                                MultiValueMode::Sum => sum,
+                                _ => sum / doc_vals.len() as f64,
+                            });
+                        }
+                    }
+                }
+                let agg_req: Aggregations = serde_json::from_value(json!({
+                    "vals_stats": {
+                        "stats": { "field": "vals", "mode": mode, "missing": missing }
+                    },
+                    "vals_count": {
+                        "value_count": { "field": "vals", "mode": mode, "missing": missing }
+                    },
+                }))
+                .unwrap();
+                let res = exec_request_with_query(agg_req, &index, None)?;
+                let count = expected_vals.len();
+                let sum: f64 = expected_vals.iter().sum();
+                let min = expected_vals.iter().copied().fold(f64::MAX, f64::min);
+                let max = expected_vals.iter().copied().fold(f64::MIN, f64::max);
+                let stats = &res["vals_stats"];
+                assert_eq!(stats["count"], count, "{mode:?} {missing:?}");
+                assert_eq!(
+                    res["vals_count"]["value"], count as f64,
+                    "{mode:?} {missing:?}"
+                );
+                assert_eq!(stats["sum"].as_f64().unwrap(), sum, "{mode:?} {missing:?}");
+                assert_eq!(stats["min"].as_f64().unwrap(), min, "{mode:?} {missing:?}");
+                assert_eq!(stats["max"].as_f64().unwrap(), max, "{mode:?} {missing:?}");
+                let avg = stats["avg"].as_f64().unwrap();
+                assert!(
+                    (avg - sum / count as f64).abs() < 1e-9,
+                    "{mode:?} {missing:?}"
+                );
+            }
+        }
+        Ok(())
+    }
 }
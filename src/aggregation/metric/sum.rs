@@ -3,7 +3,9 @@ use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
 
 use super::*;
+use crate::aggregation::value_transform::ValueTransform;
 use crate::aggregation::*;
+use crate::fastfield::MultiValueMode;
 
 /// A single-value metric aggregation that sums up numeric values that are
 /// extracted from the aggregated documents.
@@ -20,13 +22,32 @@ use crate::aggregation::*;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SumAggregation {
     /// The field name to compute the minimum on.
+    ///
+    /// Either `field` or `token_count_field` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub field: String,
+    /// The text field whose number of tokens per document is aggregated, instead of the values
+    /// of a fast field. Example in JSON format:
+    /// { "token_count_field": "title" }
+    ///
+    /// The number of tokens is approximate for long texts, see
+    /// [token counts](crate::aggregation::metric#token-counts).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count_field: Option<String>,
     /// The missing parameter defines how documents that are missing a value should be treated.
     /// By default they will be ignored but it is also possible to treat them as if they had a
     /// value. Examples in JSON format:
     /// { "field": "my_numbers", "missing": "10.0" }
     #[serde(default, deserialize_with = "deserialize_option_f64")]
     pub missing: Option<f64>,
+    /// Transform applied to the values before they are aggregated.
+    /// See [`ValueTransform`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<ValueTransform>,
+    /// How the values of a document are combined on a multivalued field. By default, every
+    /// value is aggregated on its own. See [`MultiValueMode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<MultiValueMode>,
 }
 
 impl SumAggregation {
@@ -34,7 +55,10 @@ impl SumAggregation {
     pub fn from_field_name(field_name: String) -> Self {
         Self {
             field: field_name,
+            token_count_field: None,
             missing: None,
+            value_transform: None,
+            mode: None,
         }
     }
     /// Returns the field name the aggregation is computed on.
@@ -57,6 +81,11 @@ impl IntermediateSum {
             stats: collector.stats,
         }
     }
+
+    /// Creates a new [`IntermediateSum`] instance from precomputed stats.
+    pub(crate) fn from_stats(stats: IntermediateStats) -> Self {
+        Self { stats }
+    }
     /// Merges the other intermediate result into self.
     pub fn merge_fruits(&mut self, other: IntermediateSum) {
         self.stats.merge_fruits(other.stats);
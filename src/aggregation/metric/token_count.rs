@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use columnar::{Column, ColumnIndex, ColumnType, ColumnValues};
+
+use crate::aggregation::{AggregationError, AggregationErrorKind};
+use crate::fastfield::token_count_column_name;
+use crate::fieldnorm::FieldNormReader;
+use crate::index::SegmentReader;
+use crate::schema::FieldType;
+use crate::DocId;
+
+/// Opens the number of tokens of the text field `field_name` per document, as a column with a
+/// value for every document of the segment.
+///
+/// The exact number of tokens is read if the field keeps it, the approximate number of tokens
+/// of its fieldnorms otherwise. See [token counts](super#token-counts).
+pub(crate) fn token_count_column(
+    reader: &SegmentReader,
+    field_name: &str,
+) -> crate::Result<Column<u64>> {
+    let schema = reader.schema();
+    let invalid_field = |reason: String| {
+        AggregationError::InvalidAggregation {
+            path: String::new(),
+            kind: AggregationErrorKind::InvalidParameter {
+                name: "token_count_field".to_string(),
+                reason,
+            },
+        }
+        .into()
+    };
+    let field = schema
+        .get_field(field_name)
+        .map_err(|_| invalid_field(format!("field {field_name:?} is not in the schema")))?;
+    let field_entry = schema.get_field_entry(field);
+    let keeps_exact_token_count = match field_entry.field_type() {
+        FieldType::Str(text_options) => text_options
+            .get_indexing_options()
+            .is_some_and(|indexing| indexing.exact_token_count()),
+        _ => false,
+    };
+    if keeps_exact_token_count {
+        let column_name = token_count_column_name(field_name);
+        let column_handle = reader
+            .try_fast_fields()?
+            .columnar()
+            .read_columns(&column_name)?
+            .into_iter()
+            .find(|handle| handle.column_type() == ColumnType::U64);
+        if let Some(column) = column_handle
+            .map(|handle| handle.open_u64_lenient())
+            .transpose()?
+            .flatten()
+        {
+            return Ok(Column {
+                index: ColumnIndex::Full,
+                values: column.first_or_default_col(0),
+            });
+        }
+    }
+    if !field_entry.has_fieldnorms() {
+        return Err(invalid_field(format!(
+            "field {field_name:?} has no fieldnorms to read its number of tokens from"
+        )));
+    }
+    let fieldnorm_reader = reader.get_fieldnorms_reader(field)?;
+    Ok(Column {
+        index: ColumnIndex::Full,
+        values: Arc::new(FieldNormColumnValues {
+            num_docs: reader.max_doc(),
+            fieldnorm_reader,
+        }),
+    })
+}
+
+/// The approximate number of tokens per document decoded from the fieldnorms of a field.
+struct FieldNormColumnValues {
+    fieldnorm_reader: FieldNormReader,
+    num_docs: DocId,
+}
+
+impl ColumnValues<u64> for FieldNormColumnValues {
+    #[inline]
+    fn get_val(&self, doc: DocId) -> u64 {
+        self.fieldnorm_reader.fieldnorm(doc) as u64
+    }
+
+    fn min_value(&self) -> u64 {
+        0
+    }
+
+    fn max_value(&self) -> u64 {
+        FieldNormReader::id_to_fieldnorm(u8::MAX) as u64
+    }
+
+    fn num_vals(&self) -> u32 {
+        self.num_docs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::agg_result::AggregationResults;
+    use crate::aggregation::dsl::{AggregationsDsl, AvgAgg};
+    use crate::aggregation::AggregationCollector;
+    use crate::fieldnorm::FieldNormReader;
+    use crate::query::AllQuery;
+    use crate::schema::{
+        IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, FAST, STORED, STRING,
+        TEXT,
+    };
+    use crate::tokenizer::TokenStream;
+    use crate::{Index, IndexWriter, TantivyDocument};
+
+    fn text_of_len(num_tokens: usize) -> String {
+        (0..num_tokens)
+            .map(|i| format!("w{i}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let exact_indexing = TextFieldIndexing::default()
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions)
+            .set_exact_token_count(true);
+        let body = schema_builder.add_text_field(
+            "body",
+            TextOptions::default()
+                .set_indexing_options(exact_indexing)
+                .set_stored(),
+        );
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let no_fieldnorms = TextFieldIndexing::default().set_fieldnorms(false);
+        schema_builder.add_text_field(
+            "no_fieldnorms",
+            TextOptions::default().set_indexing_options(no_fieldnorms),
+        );
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let lengths = [3, 17, 40, 41, 55, 120, 300, 1000];
+        for (i, len) in lengths.iter().enumerate() {
+            let category_value = if i % 2 == 0 { "even" } else { "odd" };
+            index_writer.add_document(doc!(
+                title => text_of_len(*len),
+                body => text_of_len(*len),
+                category => category_value,
+            ))?;
+            if i == 3 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn exec(index: &Index, aggs: serde_json::Value) -> crate::Result<serde_json::Value> {
+        let aggs: Aggregations = serde_json::from_value(aggs).unwrap();
+        let collector = AggregationCollector::from_aggs(aggs, Default::default());
+        let agg_res: AggregationResults =
+            index.reader()?.searcher().search(&AllQuery, &collector)?;
+        Ok(serde_json::to_value(agg_res)?)
+    }
+
+    /// Returns the number of tokens of the stored values of `field_name`.
+    fn stored_token_counts(index: &Index, field_name: &str) -> crate::Result<Vec<u64>> {
+        let searcher = index.reader()?.searcher();
+        let field = index.schema().get_field(field_name)?;
+        let mut tokenizer = index.tokenizers().get("default").unwrap();
+        let mut token_counts = Vec::new();
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(0)?;
+            for doc in segment_reader.doc_ids_alive() {
+                let doc: TantivyDocument = store_reader.get(doc)?;
+                let text = doc.get_first(field).and_then(|value| value.as_str());
+                let mut num_tokens = 0;
+                if let Some(text) = text {
+                    let mut token_stream = tokenizer.token_stream(text);
+                    while token_stream.advance() {
+                        num_tokens += 1;
+                    }
+                }
+                token_counts.push(num_tokens);
+            }
+        }
+        Ok(token_counts)
+    }
+
+    #[test]
+    fn test_token_count_approximate_within_bounds() -> crate::Result<()> {
+        let index = create_index()?;
+        let token_counts = stored_token_counts(&index, "title")?;
+        let res = exec(
+            &index,
+            json!({
+                "title_len": { "stats": { "token_count_field": "title" } },
+            }),
+        )?;
+        let actual_sum: u64 = token_counts.iter().sum();
+        let actual_max = *token_counts.iter().max().unwrap() as f64;
+        assert_eq!(res["title_len"]["count"], 8);
+        assert_eq!(res["title_len"]["min"], 3.0);
+        let sum = res["title_len"]["sum"].as_f64().unwrap();
+        let max = res["title_len"]["max"].as_f64().unwrap();
+        // The fieldnorms round the number of tokens down, by at most 11.2%.
+        assert!(sum <= actual_sum as f64 && sum >= actual_sum as f64 * 0.888);
+        assert!(sum < actual_sum as f64);
+        assert!(max <= actual_max && max >= actual_max * 0.888);
+        // The number of tokens is exact up to 40 tokens.
+        let res = exec(
+            &index,
+            json!({
+                "by_category": {
+                    "terms": { "field": "category", "order": { "_key": "asc" } },
+                    "aggs": { "title_len": { "min": { "token_count_field": "title" } } },
+                }
+            }),
+        )?;
+        assert_eq!(res["by_category"]["buckets"][1]["key"], "odd");
+        assert_eq!(res["by_category"]["buckets"][1]["title_len"]["value"], 17.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_count_exact() -> crate::Result<()> {
+        let index = create_index()?;
+        let token_counts = stored_token_counts(&index, "body")?;
+        let res = exec(
+            &index,
+            json!({
+                "body_len": { "stats": { "token_count_field": "body" } },
+                "body_avg": { "avg": { "token_count_field": "body" } },
+            }),
+        )?;
+        let actual_sum: u64 = token_counts.iter().sum();
+        assert_eq!(res["body_len"]["sum"], actual_sum as f64);
+        assert_eq!(res["body_len"]["max"], 1000.0);
+        assert_eq!(res["body_len"]["min"], 3.0);
+        assert_eq!(res["body_avg"]["value"], actual_sum as f64 / 8.0);
+
+        // The exact token counts are kept across merges.
+        let segment_ids = index.searchable_segment_ids()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        let res = exec(
+            &index,
+            json!({ "body_len": { "sum": { "token_count_field": "body" } } }),
+        )?;
+        assert_eq!(res["body_len"]["value"], actual_sum as f64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_count_under_terms() -> crate::Result<()> {
+        let index = create_index()?;
+        let res = exec(
+            &index,
+            json!({
+                "by_category": {
+                    "terms": { "field": "category" },
+                    "aggs": {
+                        "body_len": { "sum": { "token_count_field": "body" } },
+                        "title_len": { "max": { "token_count_field": "title" } },
+                    }
+                }
+            }),
+        )?;
+        let bucket = |key: &str| {
+            res["by_category"]["buckets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|bucket| bucket["key"] == key)
+                .unwrap()
+                .clone()
+        };
+        let approximate = |num_tokens: u32| {
+            FieldNormReader::id_to_fieldnorm(FieldNormReader::fieldnorm_to_id(num_tokens)) as f64
+        };
+        let even = bucket("even");
+        assert_eq!(even["body_len"]["value"], (3 + 40 + 55 + 300) as f64);
+        assert_eq!(even["title_len"]["value"], approximate(300));
+        let odd = bucket("odd");
+        assert_eq!(odd["body_len"]["value"], (17 + 41 + 120 + 1000) as f64);
+        assert_eq!(odd["title_len"]["value"], approximate(1000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_count_invalid_requests() -> crate::Result<()> {
+        let index = create_index()?;
+        let err = exec(
+            &index,
+            json!({ "len": { "avg": { "token_count_field": "no_fieldnorms" } } }),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("has no fieldnorms"), "{err}");
+        let err = exec(
+            &index,
+            json!({ "len": { "avg": { "token_count_field": "unknown" } } }),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not in the schema"), "{err}");
+        let agg_req: Result<Aggregations, _> = serde_json::from_value(json!({
+            "len": { "avg": { "field": "category", "token_count_field": "title" } }
+        }));
+        let err = agg_req.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "exactly one of `field` and `token_count_field` must be set"
+        );
+        // The requests built in code are checked when collecting.
+        let aggs = Aggregations::builder()
+            .avg("len", AvgAgg::field(""))
+            .build();
+        let collector = AggregationCollector::from_aggs(aggs, Default::default());
+        let searcher = index.reader()?.searcher();
+        let err = searcher.search(&AllQuery, &collector).unwrap_err();
+        assert!(err.to_string().contains("exactly one"), "{err}");
+        Ok(())
+    }
+}
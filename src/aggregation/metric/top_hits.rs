@@ -1,23 +1,30 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::net::Ipv6Addr;
+use std::rc::Rc;
 
 use columnar::{Column, ColumnType, ColumnarReader, DynamicColumn};
 use common::json_path_writer::JSON_PATH_SEGMENT_SEP_STR;
-use common::DateTime;
+use common::{f64_to_u64, DateTime};
 use regex::Regex;
+use rustc_hash::FxHashMap;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::{TopHitsMetricResult, TopHitsVecEntry};
+use crate::aggregation::agg_limits::MemoryConsumption;
 use crate::aggregation::bucket::Order;
 use crate::aggregation::intermediate_agg_result::{
     IntermediateAggregationResult, IntermediateMetricResult,
 };
 use crate::aggregation::segment_agg_result::SegmentAggregationCollector;
-use crate::aggregation::AggregationError;
+use crate::aggregation::{AggregationError, AggregationLimitsGuard};
 use crate::collector::TopNComputer;
 use crate::schema::OwnedValue;
-use crate::{DocAddress, DocId, SegmentOrdinal};
+use crate::{DocAddress, DocId, Score, SegmentOrdinal};
+
+/// Sort key ranking the documents by their score.
+pub const SCORE_SORT_KEY: &str = "_score";
 
 /// # Top Hits
 ///
@@ -65,7 +72,14 @@ use crate::{DocAddress, DocId, SegmentOrdinal};
 ///
 /// This request will return an object containing the top two documents, sorted
 /// by the `date` field in descending order. You can also sort by multiple fields, which
-/// helps to resolve ties. The aggregation object for each bucket will look like:
+/// helps to resolve ties.
+///
+/// The `_score` sort key ranks the documents by the score of the query, e.g.
+/// `"sort": [{ "_score": "desc" }, { "date": "desc" }]`. The collector then asks the searcher
+/// to compute the scores. Its sort value is the `u64` representation of the score, as for `f64`
+/// fast fields.
+///
+/// The aggregation object for each bucket will look like:
 /// ```JSON
 /// {
 ///     "hits": [
@@ -90,13 +104,13 @@ use crate::{DocAddress, DocId, SegmentOrdinal};
 /// ```
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct TopHitsAggregationReq {
-    sort: Vec<KeyOrder>,
-    size: usize,
-    from: Option<usize>,
+    pub(crate) sort: Vec<KeyOrder>,
+    pub(crate) size: usize,
+    pub(crate) from: Option<usize>,
 
     #[serde(rename = "docvalue_fields")]
     #[serde(default)]
-    doc_value_fields: Vec<String>,
+    pub(crate) doc_value_fields: Vec<String>,
 
     // Not supported
     _source: Option<serde_json::Value>,
@@ -108,9 +122,9 @@ pub struct TopHitsAggregationReq {
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
-struct KeyOrder {
-    field: String,
-    order: Order,
+pub(crate) struct KeyOrder {
+    pub(crate) field: String,
+    pub(crate) order: Order,
 }
 
 impl Serialize for KeyOrder {
@@ -225,13 +239,23 @@ impl TopHitsAggregationReq {
     }
 
     /// Return fields accessed by the aggregator, in order.
+    ///
+    /// The `_score` sort key is not a field, and is skipped.
     pub fn field_names(&self) -> Vec<&str> {
         self.sort
             .iter()
             .map(|KeyOrder { field, .. }| field.as_str())
+            .filter(|field| *field != SCORE_SORT_KEY)
             .collect()
     }
 
+    /// Returns true if the documents are sorted by their score.
+    pub fn sorts_by_score(&self) -> bool {
+        self.sort
+            .iter()
+            .any(|KeyOrder { field, .. }| field == SCORE_SORT_KEY)
+    }
+
     /// Return fields accessed by the aggregator's value retrieval.
     pub fn value_field_names(&self) -> Vec<&str> {
         self.doc_value_fields.iter().map(|s| s.as_str()).collect()
@@ -487,6 +511,55 @@ impl TopHitsTopNComputer {
     }
 }
 
+/// Scores of the documents of a segment, for the `top_hits` aggregations sorting by `_score`.
+///
+/// Bucket aggregations buffer the documents passed to their sub-aggregations, so the scores are
+/// kept for the whole segment rather than for the current document only. Only the scores of the
+/// collected documents are kept, and their memory is charged to the aggregation limits until the
+/// segment is collected.
+#[derive(Clone)]
+pub(crate) struct DocScores(Rc<RefCell<DocScoresInner>>);
+
+struct DocScoresInner {
+    scores: FxHashMap<DocId, Score>,
+    memory_guard: AggregationLimitsGuard,
+}
+
+impl DocScores {
+    pub fn new(limits: &AggregationLimitsGuard) -> Self {
+        DocScores(Rc::new(RefCell::new(DocScoresInner {
+            scores: FxHashMap::default(),
+            memory_guard: limits.clone(),
+        })))
+    }
+
+    #[inline]
+    pub fn record(&self, doc: DocId, score: Score) -> crate::Result<()> {
+        let DocScoresInner {
+            scores,
+            memory_guard,
+        } = &mut *self.0.borrow_mut();
+        let prev_memory_consumption = scores.memory_consumption();
+        scores.insert(doc, score);
+        let memory_consumption = scores.memory_consumption();
+        if memory_consumption > prev_memory_consumption {
+            memory_guard
+                .add_memory_consumed((memory_consumption - prev_memory_consumption) as u64)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn get(&self, doc: DocId) -> Score {
+        self.0
+            .borrow()
+            .scores
+            .get(&doc)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct TopHitsSegmentCollector {
     segment_ordinal: SegmentOrdinal,
@@ -534,19 +607,24 @@ impl TopHitsSegmentCollector {
         doc_id: crate::DocId,
         req: &TopHitsAggregationReq,
         accessors: &[(Column<u64>, ColumnType)],
+        doc_scores: Option<&DocScores>,
     ) -> crate::Result<()> {
+        let mut accessors = accessors.iter();
         let sorts: Vec<DocValueAndOrder> = req
             .sort
             .iter()
-            .enumerate()
-            .map(|(idx, KeyOrder { order, .. })| {
+            .map(|KeyOrder { field, order }| {
                 let order = *order;
-                let value = accessors
-                    .get(idx)
-                    .expect("could not find field in accessors")
-                    .0
-                    .values_for_doc(doc_id)
-                    .next();
+                let value = if field == SCORE_SORT_KEY {
+                    doc_scores.map(|doc_scores| f64_to_u64(doc_scores.get(doc_id) as f64))
+                } else {
+                    accessors
+                        .next()
+                        .expect("could not find field in accessors")
+                        .0
+                        .values_for_doc(doc_id)
+                        .next()
+                };
                 DocValueAndOrder { value, order }
             })
             .collect();
@@ -592,13 +670,18 @@ impl SegmentAggregationCollector for TopHitsSegmentCollector {
         doc_id: crate::DocId,
         agg_with_accessor: &mut crate::aggregation::agg_req_with_accessor::AggregationsWithAccessor,
     ) -> crate::Result<()> {
-        let tophits_req = &agg_with_accessor.aggs.values[self.accessor_idx]
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let tophits_req = agg_with_accessor
             .agg
             .agg
             .as_top_hits()
             .expect("aggregation request must be of type top hits");
-        let accessors = &agg_with_accessor.aggs.values[self.accessor_idx].accessors;
-        self.collect_with(doc_id, tophits_req, accessors)?;
+        self.collect_with(
+            doc_id,
+            tophits_req,
+            &agg_with_accessor.accessors,
+            agg_with_accessor.doc_scores.as_ref(),
+        )?;
         Ok(())
     }
 
@@ -607,15 +690,20 @@ impl SegmentAggregationCollector for TopHitsSegmentCollector {
         docs: &[crate::DocId],
         agg_with_accessor: &mut crate::aggregation::agg_req_with_accessor::AggregationsWithAccessor,
     ) -> crate::Result<()> {
-        let tophits_req = &agg_with_accessor.aggs.values[self.accessor_idx]
+        let agg_with_accessor = &agg_with_accessor.aggs.values[self.accessor_idx];
+        let tophits_req = agg_with_accessor
             .agg
             .agg
             .as_top_hits()
             .expect("aggregation request must be of type top hits");
-        let accessors = &agg_with_accessor.aggs.values[self.accessor_idx].accessors;
         // TODO: Consider getting fields with the column block accessor.
         for doc in docs {
-            self.collect_with(*doc, tophits_req, accessors)?;
+            self.collect_with(
+                *doc,
+                tophits_req,
+                &agg_with_accessor.accessors,
+                agg_with_accessor.doc_scores.as_ref(),
+            )?;
         }
         Ok(())
     }
@@ -628,15 +716,16 @@ mod tests {
     use serde_json::Value;
     use time::macros::datetime;
 
-    use super::{DocSortValuesAndFields, DocValueAndOrder, Order};
+    use super::{DocScores, DocSortValuesAndFields, DocValueAndOrder, Order};
     use crate::aggregation::agg_req::Aggregations;
     use crate::aggregation::agg_result::AggregationResults;
     use crate::aggregation::bucket::tests::get_test_index_from_docs;
     use crate::aggregation::tests::get_test_index_from_values;
-    use crate::aggregation::AggregationCollector;
-    use crate::collector::ComparableDoc;
-    use crate::query::AllQuery;
-    use crate::schema::OwnedValue;
+    use crate::aggregation::{AggregationCollector, AggregationLimitsGuard};
+    use crate::collector::{Collector, ComparableDoc, TopDocs};
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, OwnedValue, Schema, FAST, STRING, TEXT};
+    use crate::{Index, IndexWriter, TantivyError, Term};
 
     fn invert_order(cmp_feature: DocValueAndOrder) -> DocValueAndOrder {
         let DocValueAndOrder { value, order } = cmp_feature;
@@ -913,4 +1002,208 @@ mod tests {
     fn test_aggregation_top_hits_multi_segment() -> crate::Result<()> {
         test_aggregation_top_hits(false)
     }
+
+    #[test]
+    fn test_aggregation_top_hits_ascending_after_truncation() -> crate::Result<()> {
+        // The best documents come last, after the buffer of the top hits was truncated.
+        let values: Vec<f64> = (0..20).rev().map(|value| value as f64).collect();
+        let index = get_test_index_from_values(true, &values)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "top": {
+                "top_hits": {
+                    "size": 2,
+                    "sort": [{ "score_f64": "asc" }],
+                    "docvalue_fields": ["score_f64"]
+                }
+            }
+        }))?;
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+        let searcher = index.reader()?.searcher();
+        let agg_res = serde_json::to_value(searcher.search(&AllQuery, &collector)?)?;
+        let hit_values: Vec<f64> = agg_res["top"]["hits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["docvalue_fields"]["score_f64"][0].as_f64().unwrap())
+            .collect();
+        assert_eq!(hit_values, vec![0.0, 1.0]);
+        Ok(())
+    }
+
+    fn nested_top_hit_ids(agg_res: &Value) -> Vec<(String, String, Vec<u64>)> {
+        let mut res = Vec::new();
+        for category_bucket in agg_res["category"]["buckets"].as_array().unwrap() {
+            for color_bucket in category_bucket["color"]["buckets"].as_array().unwrap() {
+                let ids = color_bucket["top"]["hits"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|hit| hit["docvalue_fields"]["id"][0].as_u64().unwrap())
+                    .collect();
+                res.push((
+                    category_bucket["key"].as_str().unwrap().to_string(),
+                    color_bucket["key"].as_str().unwrap().to_string(),
+                    ids,
+                ));
+            }
+        }
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn test_aggregation_top_hits_nested_sort_by_score() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let color = schema_builder.add_text_field("color", STRING | FAST);
+        let id = schema_builder.add_u64_field("id", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let categories = ["book", "movie"];
+        let colors = ["red", "green", "blue"];
+        for i in 0..60u64 {
+            let num_hellos = i % 4 + 1;
+            let num_fillers = i % 7;
+            let body = format!(
+                "{}{}",
+                "hello ".repeat(num_hellos as usize),
+                "filler ".repeat(num_fillers as usize)
+            );
+            index_writer.add_document(doc!(
+                text => body,
+                category => categories[(i % 2) as usize],
+                color => colors[(i % 3) as usize],
+                id => i,
+            ))?;
+            if i == 29 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 2);
+        let query = TermQuery::new(
+            Term::from_field_text(text, "hello"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let aggs = |sort: Value| -> Aggregations {
+            serde_json::from_value(json!({
+                "category": {
+                    "terms": { "field": "category" },
+                    "aggs": {
+                        "color": {
+                            "terms": { "field": "color" },
+                            "aggs": {
+                                "top": {
+                                    "top_hits": {
+                                        "size": 3,
+                                        "sort": sort,
+                                        "docvalue_fields": ["id"]
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }))
+            .unwrap()
+        };
+
+        // Brute force: scores of all the matching docs, grouped by bucket.
+        let mut scored_docs: Vec<(String, String, f32, u64)> = Vec::new();
+        for (score, doc_address) in searcher.search(&query, &TopDocs::with_limit(100))? {
+            let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+            let fast_fields = segment_reader.fast_fields();
+            let mut category = String::new();
+            let category_column = fast_fields.str("category")?.unwrap();
+            let ord = category_column
+                .term_ords(doc_address.doc_id)
+                .next()
+                .unwrap();
+            category_column.ord_to_str(ord, &mut category)?;
+            let mut color = String::new();
+            let color_column = fast_fields.str("color")?.unwrap();
+            let ord = color_column.term_ords(doc_address.doc_id).next().unwrap();
+            color_column.ord_to_str(ord, &mut color)?;
+            let id = fast_fields.u64("id")?.first(doc_address.doc_id).unwrap();
+            scored_docs.push((category, color, score, id));
+        }
+        assert_eq!(scored_docs.len(), 60);
+        let expected_top_hits = |by_score: bool| -> Vec<(String, String, Vec<u64>)> {
+            let mut scored_docs = scored_docs.clone();
+            scored_docs.sort_by(|left, right| {
+                (&left.0, &left.1).cmp(&(&right.0, &right.1)).then_with(|| {
+                    if by_score {
+                        right
+                            .2
+                            .total_cmp(&left.2)
+                            .then_with(|| left.3.cmp(&right.3))
+                    } else {
+                        right.3.cmp(&left.3)
+                    }
+                })
+            });
+            scored_docs
+                .chunk_by(|left, right| (&left.0, &left.1) == (&right.0, &right.1))
+                .map(|bucket| {
+                    let ids = bucket.iter().take(3).map(|doc| doc.3).collect();
+                    (bucket[0].0.clone(), bucket[0].1.clone(), ids)
+                })
+                .collect()
+        };
+
+        let collector = AggregationCollector::from_aggs(
+            aggs(json!([{ "_score": "desc" }, { "id": "asc" }])),
+            Default::default(),
+        );
+        assert!(collector.requires_scoring());
+        let agg_res = serde_json::to_value(searcher.search(&query, &collector)?)?;
+        let top_hits = nested_top_hit_ids(&agg_res);
+        assert_eq!(top_hits.len(), 6);
+        assert_eq!(top_hits, expected_top_hits(true));
+        // The scores are not all equal, so the order differs from the order by id.
+        assert_ne!(top_hits, expected_top_hits(false));
+
+        // Sorting by score only reads no fast field.
+        let collector = AggregationCollector::from_aggs(
+            aggs(json!([{ "_score": "desc" }])),
+            Default::default(),
+        );
+        let agg_res = serde_json::to_value(searcher.search(&query, &collector)?)?;
+        let top_hits = nested_top_hit_ids(&agg_res);
+        assert_eq!(top_hits.len(), 6);
+        assert!(top_hits.iter().all(|(_, _, ids)| ids.len() == 3));
+
+        // Sorting by a fast field does not require the scores.
+        let collector =
+            AggregationCollector::from_aggs(aggs(json!([{ "id": "desc" }])), Default::default());
+        assert!(!collector.requires_scoring());
+        let agg_res = serde_json::to_value(searcher.search(&query, &collector)?)?;
+        assert_eq!(nested_top_hit_ids(&agg_res), expected_top_hits(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_doc_scores_are_sparse_and_charged() {
+        let limits = AggregationLimitsGuard::new(Some(2_000), None);
+        let doc_scores = DocScores::new(&limits);
+        // Only the recorded documents use memory, whatever their ids.
+        for doc in 0..10 {
+            doc_scores.record(doc * 1_000_000, doc as f32).unwrap();
+        }
+        assert_eq!(doc_scores.get(3_000_000), 3.0);
+        assert_eq!(doc_scores.get(5), 0.0);
+
+        // The memory of the scores stays charged while they are kept.
+        let err = (10..1_000)
+            .map(|doc| doc_scores.record(doc, 1.0))
+            .find_map(Result::err)
+            .unwrap();
+        assert!(matches!(err, TantivyError::BudgetExceeded { .. }));
+        drop(doc_scores);
+        limits.clone().add_memory_consumed(1_000).unwrap();
+    }
 }
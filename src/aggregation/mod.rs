@@ -21,6 +21,9 @@
 //! [`Searcher::search()`](crate::Searcher::search).
 //!
 //!
+//! Requests can also be built with the typed builders of the [`dsl`] module, which produce the
+//! same [`Aggregations`](agg_req::Aggregations) as the JSON format.
+//!
 //! ## JSON Format
 //! Aggregations request and result structures de/serialize into elasticsearch compatible JSON.
 //!
@@ -40,7 +43,14 @@
 //!     - [Histogram](bucket::HistogramAggregation)
 //!     - [DateHistogram](bucket::DateHistogramAggregationReq)
 //!     - [Range](bucket::RangeAggregation)
+//!     - [DateRange](bucket::DateRangeAggregation)
 //!     - [Terms](bucket::TermsAggregation)
+//!     - [SignificantTerms](bucket::SignificantTermsAggregation)
+//!     - [PathTerms](bucket::PathTermsAggregation)
+//!     - [PrefixTerms](bucket::PrefixTermsAggregation)
+//!     - [Composite](bucket::CompositeAggregation)
+//!     - [Exists](bucket::ExistsAggregation)
+//!     - [Missing](bucket::MissingAggregation)
 //! - [Metric](metric)
 //!     - [Average](metric::AverageAggregation)
 //!     - [Stats](metric::StatsAggregation)
@@ -131,15 +141,28 @@ mod agg_limits;
 pub mod agg_req;
 mod agg_req_with_accessor;
 pub mod agg_result;
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod bucket;
 mod buf_collector;
 mod collector;
+pub mod custom_intermediate_result;
 mod date;
+mod date_math;
+pub mod dsl;
 mod error;
 pub mod intermediate_agg_result;
 pub mod metric;
+mod multi_collector;
+mod page_collector;
 
+mod sampling;
 mod segment_agg_result;
+mod session_cache;
+mod spill;
+mod type_conflicts;
+pub mod value_transform;
+
 use std::collections::HashMap;
 use std::fmt::Display;
 
@@ -155,10 +178,16 @@ pub use collector::{
 };
 use columnar::{ColumnType, MonotonicallyMappableToU64};
 pub(crate) use date::format_date;
-pub use error::AggregationError;
+pub use date_math::DateMathParseError;
+pub use error::{AggregationError, AggregationErrorKind};
 use itertools::Itertools;
+pub use multi_collector::{MultiAggregationCollector, MultiAggregationSegmentCollector};
+pub use page_collector::{PageAggregationsSegmentCollector, TopDocsWithPageAggregations};
+pub use sampling::{AggregationSampling, SampledDocs};
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
+pub use session_cache::AggregationSessionCache;
+pub use spill::AggregationSpill;
 
 fn parse_str_into_f64<E: de::Error>(value: &str) -> Result<f64, E> {
     let parsed = value
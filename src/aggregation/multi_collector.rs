@@ -0,0 +1,632 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use columnar::{Column, ColumnBlockAccessor, ColumnType};
+
+use super::agg_req::{requires_scoring, Aggregations};
+use super::agg_req_with_accessor::{
+    get_aggs_with_segment_accessor_and_validate, validate_fast_fields, AggregationsWithAccessor,
+};
+use super::agg_result::AggregationResults;
+use super::bucket::{pin_date_range_now, resolve_background_counts};
+use super::buf_collector::{clamp_block_size, compute_block_size};
+use super::collector::doc_scores_for_aggs;
+use super::intermediate_agg_result::IntermediateAggregationResults;
+use super::metric::DocScores;
+use super::segment_agg_result::{
+    build_segment_agg_collector, AggregationLimitsGuard, SegmentAggregationCollector,
+};
+use super::type_conflicts::check_segment_column_types;
+use super::value_transform::ValueTransformRegistry;
+use crate::collector::{Collector, SegmentCollector};
+use crate::index::SegmentReader;
+use crate::{DocId, Searcher, SegmentOrdinal, TantivyError};
+
+/// Collector computing several independent aggregation requests in a single pass.
+///
+/// Compared to running one [`AggregationCollector`](super::AggregationCollector) per request,
+/// the matching documents are buffered once for all the requests, and a column read by the
+/// top-level aggregations of several requests is only decoded once per block of documents.
+///
+/// Each request has its own [`AggregationLimitsGuard`]. The fruit maps the name of each request
+/// to its [`AggregationResults`].
+///
+/// ```rust
+/// use tantivy::aggregation::agg_req::Aggregations;
+/// use tantivy::aggregation::MultiAggregationCollector;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST};
+/// use tantivy::{doc, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let price = schema_builder.add_f64_field("price", FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// for val in [1.0, 2.0, 6.0] {
+///     index_writer.add_document(doc!(price => val))?;
+/// }
+/// index_writer.commit()?;
+///
+/// let avg: Aggregations =
+///     serde_json::from_str(r#"{ "avg_price": { "avg": { "field": "price" } } }"#).unwrap();
+/// let max: Aggregations =
+///     serde_json::from_str(r#"{ "max_price": { "max": { "field": "price" } } }"#).unwrap();
+/// let collector =
+///     MultiAggregationCollector::new(vec![("avg".to_string(), avg), ("max".to_string(), max)]);
+///
+/// let searcher = index.reader()?.searcher();
+/// let results = searcher.search(&AllQuery, &collector)?;
+/// let avg = serde_json::to_value(&results["avg"]).unwrap();
+/// assert_eq!(avg["avg_price"]["value"], 3.0);
+/// let max = serde_json::to_value(&results["max"]).unwrap();
+/// assert_eq!(max["max_price"]["value"], 6.0);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MultiAggregationCollector {
+    requests: Vec<(String, Aggregations, AggregationLimitsGuard)>,
+    value_transforms: ValueTransformRegistry,
+}
+
+impl MultiAggregationCollector {
+    /// Creates a collector from named aggregation requests, each with the default limits.
+    pub fn new(requests: Vec<(String, Aggregations)>) -> Self {
+        Self::from_requests_with_limits(
+            requests
+                .into_iter()
+                .map(|(name, aggs)| (name, aggs, AggregationLimitsGuard::default()))
+                .collect(),
+        )
+    }
+
+    /// Creates a collector from named aggregation requests, each with its own limits.
+    pub fn from_requests_with_limits(
+        mut requests: Vec<(String, Aggregations, AggregationLimitsGuard)>,
+    ) -> Self {
+        for (_, aggs, _) in requests.iter_mut() {
+            pin_date_range_now(aggs);
+        }
+        Self {
+            requests,
+            value_transforms: ValueTransformRegistry::default(),
+        }
+    }
+
+    /// Sets the registry used to resolve the custom `value_transform`s of the requests.
+    pub fn with_value_transforms(mut self, value_transforms: ValueTransformRegistry) -> Self {
+        self.value_transforms = value_transforms;
+        self
+    }
+}
+
+impl Collector for MultiAggregationCollector {
+    type Fruit = HashMap<String, AggregationResults>;
+
+    type Child = MultiAggregationSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        MultiAggregationSegmentCollector::from_requests_and_reader(
+            &self.requests,
+            reader,
+            segment_local_id,
+            &self.value_transforms,
+        )
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.requests
+            .iter()
+            .any(|(_, aggs, _)| requires_scoring(aggs))
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<crate::Result<Vec<IntermediateAggregationResults>>>,
+    ) -> crate::Result<Self::Fruit> {
+        self.merge_fruits_and_finalize(segment_fruits, None)
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<crate::Result<Vec<IntermediateAggregationResults>>>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        self.merge_fruits_and_finalize(segment_fruits, Some(searcher))
+    }
+}
+
+impl MultiAggregationCollector {
+    /// Merges the segment fruits into the final results, resolving the background counts of the
+    /// `significant_terms` aggregations on `searcher` if it is known.
+    fn merge_fruits_and_finalize(
+        &self,
+        segment_fruits: Vec<crate::Result<Vec<IntermediateAggregationResults>>>,
+        searcher: Option<&Searcher>,
+    ) -> crate::Result<HashMap<String, AggregationResults>> {
+        let mut merged: Vec<Option<IntermediateAggregationResults>> =
+            vec![None; self.requests.len()];
+        for segment_fruit in segment_fruits {
+            for (merged, fruit) in merged.iter_mut().zip(segment_fruit?) {
+                match merged {
+                    Some(merged) => merged.merge_fruits(fruit)?,
+                    None => *merged = Some(fruit),
+                }
+            }
+        }
+        self.requests
+            .iter()
+            .zip(merged)
+            .map(|((name, aggs, limits), res)| {
+                let mut res = res.unwrap_or_default();
+                if let Some(searcher) = searcher {
+                    resolve_background_counts(&mut res, aggs, searcher)?;
+                }
+                let res = res.into_final_result(aggs.clone(), limits.clone())?;
+                Ok((name.clone(), res))
+            })
+            .collect()
+    }
+}
+
+/// Segment collector of [`MultiAggregationCollector`].
+pub struct MultiAggregationSegmentCollector {
+    requests: Vec<(
+        AggregationsWithAccessor,
+        Box<dyn SegmentAggregationCollector>,
+    )>,
+    staged_docs: Box<[DocId]>,
+    num_staged_docs: usize,
+    block_ord: Rc<Cell<u64>>,
+    doc_scores: Vec<DocScores>,
+    /// Warnings of the type conflicts of each request.
+    warnings: Vec<Vec<String>>,
+    error: Option<TantivyError>,
+}
+
+impl MultiAggregationSegmentCollector {
+    fn from_requests_and_reader(
+        requests: &[(String, Aggregations, AggregationLimitsGuard)],
+        reader: &SegmentReader,
+        segment_ordinal: SegmentOrdinal,
+        value_transforms: &ValueTransformRegistry,
+    ) -> crate::Result<Self> {
+        let mut block_size = usize::MAX;
+        let mut aggs_with_accessors = Vec::with_capacity(requests.len());
+        let mut doc_scores = Vec::new();
+        let mut warnings = Vec::with_capacity(requests.len());
+        for (i, (name, aggs, limits)) in requests.iter().enumerate() {
+            if requests[..i]
+                .iter()
+                .any(|(other_name, ..)| other_name == name)
+            {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "Duplicate aggregation request name `{name}`"
+                )));
+            }
+            validate_fast_fields(aggs, reader)?;
+            warnings.push(check_segment_column_types(aggs, reader)?);
+            let mut aggs_with_accessor = get_aggs_with_segment_accessor_and_validate(
+                aggs,
+                reader,
+                segment_ordinal,
+                limits,
+                value_transforms,
+                None,
+            )?;
+            doc_scores.extend(doc_scores_for_aggs(aggs, &mut aggs_with_accessor, limits));
+            block_size = block_size.min(
+                limits
+                    .block_size()
+                    .unwrap_or_else(|| compute_block_size(&aggs_with_accessor)),
+            );
+            aggs_with_accessors.push(aggs_with_accessor);
+        }
+
+        // Only the columns read by several top-level aggregations are shared, the others are
+        // fetched directly into the block accessor of their aggregation.
+        let mut num_readers_per_column: HashMap<(String, ColumnType), usize> = HashMap::new();
+        for aggs_with_accessor in &aggs_with_accessors {
+            for agg in &aggs_with_accessor.aggs.values {
+                if let Some(key) = shared_column_key(agg) {
+                    *num_readers_per_column.entry(key).or_default() += 1;
+                }
+            }
+        }
+        let block_ord = Rc::new(Cell::new(0));
+        let mut shared_column_blocks: HashMap<(String, ColumnType), SharedColumnBlock> =
+            HashMap::new();
+        for aggs_with_accessor in aggs_with_accessors.iter_mut() {
+            for agg in aggs_with_accessor.aggs.values.iter_mut() {
+                let Some(key) = shared_column_key(agg) else {
+                    continue;
+                };
+                if num_readers_per_column[&key] < 2 {
+                    continue;
+                }
+                let shared_column_block = shared_column_blocks
+                    .entry(key)
+                    .or_insert_with(|| SharedColumnBlock::new(block_ord.clone()));
+                agg.shared_column_block = Some(shared_column_block.clone());
+            }
+        }
+
+        let requests = aggs_with_accessors
+            .into_iter()
+            .map(|mut aggs_with_accessor| {
+                aggs_with_accessor.take_block_buffers(clamp_block_size(block_size));
+                let collector = build_segment_agg_collector(&mut aggs_with_accessor)?;
+                Ok((aggs_with_accessor, collector))
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(Self {
+            requests,
+            staged_docs: vec![0; clamp_block_size(block_size)].into_boxed_slice(),
+            num_staged_docs: 0,
+            block_ord,
+            doc_scores,
+            warnings,
+            error: None,
+        })
+    }
+
+    /// Passes a block of docs to the collectors of all the requests.
+    fn collect_docs(
+        requests: &mut [(
+            AggregationsWithAccessor,
+            Box<dyn SegmentAggregationCollector>,
+        )],
+        block_ord: &Cell<u64>,
+        docs: &[DocId],
+    ) -> crate::Result<()> {
+        block_ord.set(block_ord.get() + 1);
+        for (aggs_with_accessor, collector) in requests.iter_mut() {
+            collector.collect_block(docs, aggs_with_accessor)?;
+        }
+        Ok(())
+    }
+
+    /// Number of blocks decoded from the shared column `field` of type `column_type`.
+    #[cfg(test)]
+    pub(crate) fn num_shared_blocks_decoded(&self, field: &str, column_type: ColumnType) -> u64 {
+        let key = (field.to_string(), column_type);
+        self.requests
+            .iter()
+            .flat_map(|(aggs_with_accessor, _)| aggs_with_accessor.aggs.values.iter())
+            .filter(|agg| shared_column_key(agg).as_ref() == Some(&key))
+            .find_map(|agg| agg.shared_column_block.as_ref())
+            .map(|shared_column_block| shared_column_block.blocks.borrow().num_decoded_blocks)
+            .unwrap_or(0)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn block_size(&self) -> usize {
+        self.staged_docs.len()
+    }
+}
+
+impl SegmentCollector for MultiAggregationSegmentCollector {
+    type Fruit = crate::Result<Vec<IntermediateAggregationResults>>;
+
+    #[inline]
+    fn collect(&mut self, doc: DocId, score: crate::Score) {
+        if self.error.is_some() {
+            return;
+        }
+        for doc_scores in &self.doc_scores {
+            if let Err(err) = doc_scores.record(doc, score) {
+                self.error = Some(err);
+                return;
+            }
+        }
+        self.staged_docs[self.num_staged_docs] = doc;
+        self.num_staged_docs += 1;
+        if self.num_staged_docs == self.staged_docs.len() {
+            self.num_staged_docs = 0;
+            if let Err(err) =
+                Self::collect_docs(&mut self.requests, &self.block_ord, &self.staged_docs)
+            {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = Self::collect_docs(&mut self.requests, &self.block_ord, docs) {
+            self.error = Some(err);
+        }
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        Self::collect_docs(
+            &mut self.requests,
+            &self.block_ord,
+            &self.staged_docs[..self.num_staged_docs],
+        )?;
+        self.requests
+            .into_iter()
+            .zip(self.warnings)
+            .map(|((mut aggs_with_accessor, mut collector), warnings)| {
+                collector.flush(&mut aggs_with_accessor)?;
+                let mut res = IntermediateAggregationResults::default();
+                collector.add_intermediate_aggregation_result(&aggs_with_accessor, &mut res)?;
+                res.warnings = warnings;
+                Ok(res)
+            })
+            .collect()
+    }
+}
+
+/// Identifies the column read by a top-level aggregation, if it can be shared.
+fn shared_column_key(
+    agg: &super::agg_req_with_accessor::AggregationWithAccessor,
+) -> Option<(String, ColumnType)> {
+    // Aggregations reading several columns don't go through the block accessor.
+    if !agg.accessors.is_empty() {
+        return None;
+    }
+    let field_names = agg.agg.agg.get_fast_field_names();
+    let [field_name] = field_names.as_slice() else {
+        return None;
+    };
+    Some((field_name.to_string(), agg.field_type))
+}
+
+/// Column values of the current block, shared by the top-level aggregations of several requests
+/// reading the same column.
+#[derive(Clone)]
+pub(crate) struct SharedColumnBlock {
+    blocks: Rc<RefCell<SharedBlocks>>,
+    block_ord: Rc<Cell<u64>>,
+}
+
+#[derive(Default)]
+struct SharedBlocks {
+    /// One block per distinct missing value used by the aggregations.
+    blocks: Vec<SharedBlock>,
+    num_decoded_blocks: u64,
+}
+
+struct SharedBlock {
+    missing: Option<u64>,
+    docs: SharedBlockDocs,
+    column_block_accessor: ColumnBlockAccessor<u64>,
+}
+
+/// The docs of a block are identified by the ordinal of the block and the slice they are passed
+/// in: the buffer holding them is not modified while the block is collected.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SharedBlockDocs {
+    block_ord: u64,
+    docs_ptr: *const DocId,
+    num_docs: usize,
+}
+
+impl SharedColumnBlock {
+    fn new(block_ord: Rc<Cell<u64>>) -> Self {
+        Self {
+            blocks: Default::default(),
+            block_ord,
+        }
+    }
+
+    /// Loads the values of `docs` into `column_block_accessor`, decoding them from `accessor`
+    /// only if no other aggregation did for the current block.
+    pub(crate) fn fetch_block(
+        &self,
+        docs: &[DocId],
+        missing: Option<u64>,
+        accessor: &Column<u64>,
+        column_block_accessor: &mut ColumnBlockAccessor<u64>,
+    ) {
+        let block_docs = SharedBlockDocs {
+            block_ord: self.block_ord.get(),
+            docs_ptr: docs.as_ptr(),
+            num_docs: docs.len(),
+        };
+        let mut shared_blocks = self.blocks.borrow_mut();
+        let SharedBlocks {
+            blocks,
+            num_decoded_blocks,
+        } = &mut *shared_blocks;
+        let block_idx = match blocks.iter().position(|block| block.missing == missing) {
+            Some(block_idx) => block_idx,
+            None => {
+                blocks.push(SharedBlock {
+                    missing,
+                    docs: SharedBlockDocs {
+                        block_ord: u64::MAX,
+                        docs_ptr: std::ptr::null(),
+                        num_docs: 0,
+                    },
+                    column_block_accessor: ColumnBlockAccessor::default(),
+                });
+                blocks.len() - 1
+            }
+        };
+        let block = &mut blocks[block_idx];
+        if block.docs != block_docs {
+            match missing {
+                Some(missing) => block
+                    .column_block_accessor
+                    .fetch_block_with_missing(docs, accessor, missing),
+                None => block.column_block_accessor.fetch_block(docs, accessor),
+            }
+            block.docs = block_docs;
+            *num_decoded_blocks += 1;
+        }
+        column_block_accessor.clone_from(&block.column_block_accessor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use columnar::ColumnType;
+    use serde_json::json;
+
+    use super::MultiAggregationCollector;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::agg_result::AggregationResults;
+    use crate::aggregation::{AggregationCollector, AggregationLimitsGuard};
+    use crate::collector::{Collector, SegmentCollector};
+    use crate::query::{AllQuery, Query, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let color = schema_builder.add_text_field("color", STRING | FAST);
+        let score = schema_builder.add_f64_field("score", FAST);
+        let views = schema_builder.add_u64_field("views", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let colors = ["red", "green", "blue"];
+        for segment in 0..2 {
+            for i in 0..(300 + segment * 17) {
+                let mut doc = doc!(color => colors[(i * 7 + segment) % 3]);
+                // Some docs have no score.
+                if i % 5 != 0 {
+                    doc.add_f64(score, (i % 41) as f64 * 1.5);
+                }
+                doc.add_u64(views, (i * 13 % 100) as u64);
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn requests() -> Vec<(String, Aggregations)> {
+        let requests = [
+            (
+                "stats",
+                json!({
+                    "score_stats": { "stats": { "field": "score" } },
+                    "avg_views": { "avg": { "field": "views" } },
+                }),
+            ),
+            (
+                "buckets",
+                json!({
+                    "score_ranges": {
+                        "range": {
+                            "field": "score",
+                            "ranges": [{ "to": 10.0 }, { "from": 10.0, "to": 30.0 }, { "from": 30.0 }]
+                        },
+                        "aggs": { "max_views": { "max": { "field": "views" } } }
+                    },
+                    "colors": { "terms": { "field": "color" } },
+                }),
+            ),
+            (
+                "missing",
+                json!({
+                    "score_sum": { "sum": { "field": "score", "missing": 100.0 } },
+                    "score_percentiles": { "percentiles": { "field": "score" } },
+                    "color_cardinality": { "cardinality": { "field": "color" } },
+                }),
+            ),
+        ];
+        requests
+            .into_iter()
+            .map(|(name, req)| (name.to_string(), serde_json::from_value(req).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_multi_aggregation_equals_separate_requests() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let color = index.schema().get_field("color")?;
+        let red_query = TermQuery::new(
+            Term::from_field_text(color, "red"),
+            IndexRecordOption::Basic,
+        );
+        let queries: [&dyn Query; 2] = [&AllQuery, &red_query];
+        for query in queries {
+            let collector = MultiAggregationCollector::new(requests());
+            let results = searcher.search(query, &collector)?;
+            assert_eq!(results.len(), 3);
+            for (name, aggs) in requests() {
+                let expected: AggregationResults = searcher.search(
+                    query,
+                    &AggregationCollector::from_aggs(aggs, Default::default()),
+                )?;
+                assert_eq!(
+                    serde_json::to_value(&results[&name])?,
+                    serde_json::to_value(&expected)?,
+                    "request {name}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_aggregation_decodes_shared_column_once_per_block() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let collector = MultiAggregationCollector::new(requests());
+        let reader = searcher.segment_reader(0);
+        let mut segment_collector = collector.for_segment(0, reader)?;
+        for doc in 0..reader.max_doc() {
+            segment_collector.collect(doc, 0.0);
+        }
+        let num_full_blocks = reader.max_doc() as usize / segment_collector.block_size();
+        // `score` is read by four top-level aggregations, one of which with a missing value.
+        // The last block is only collected on harvest.
+        assert_eq!(
+            segment_collector.num_shared_blocks_decoded("score", ColumnType::F64),
+            2 * num_full_blocks as u64
+        );
+        // `views` is only read by a single top-level aggregation.
+        assert_eq!(
+            segment_collector.num_shared_blocks_decoded("views", ColumnType::U64),
+            0
+        );
+        assert_eq!(
+            segment_collector.num_shared_blocks_decoded("color", ColumnType::Str),
+            num_full_blocks as u64
+        );
+        let fruit = segment_collector.harvest()?;
+        assert_eq!(fruit.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_aggregation_limits_per_request() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let mut requests = requests().into_iter();
+        let (stats_name, stats) = requests.next().unwrap();
+        let (buckets_name, buckets) = requests.next().unwrap();
+        let collector = MultiAggregationCollector::from_requests_with_limits(vec![
+            (stats_name.clone(), stats.clone(), Default::default()),
+            (
+                buckets_name,
+                buckets,
+                AggregationLimitsGuard::new(None, Some(2)),
+            ),
+        ]);
+        let err = searcher.search(&AllQuery, &collector).unwrap_err();
+        assert!(err.to_string().contains("bucket"), "{err}");
+
+        let collector = MultiAggregationCollector::new(vec![
+            (stats_name.clone(), stats.clone()),
+            (stats_name, stats),
+        ]);
+        assert!(searcher.search(&AllQuery, &collector).is_err());
+        Ok(())
+    }
+}
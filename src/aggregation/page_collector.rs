@@ -0,0 +1,386 @@
+use super::agg_req::Aggregations;
+use super::agg_result::AggregationResults;
+use super::bucket::pin_date_range_now;
+use super::collector::AggregationSegmentCollector;
+use super::intermediate_agg_result::IntermediateAggregationResults;
+use super::segment_agg_result::AggregationLimitsGuard;
+use super::value_transform::ValueTransformRegistry;
+use crate::collector::{Collector, SegmentCollector};
+use crate::index::SegmentReader;
+use crate::query::Weight;
+use crate::{DocAddress, DocId, Score, SegmentOrdinal};
+
+/// Collector computing the top documents of a query, together with aggregations over these top
+/// documents only.
+///
+/// A regular `(TopDocs, AggregationCollector)` computes the aggregations over all the documents
+/// matching the query. `TopDocsWithPageAggregations` instead runs in two phases:
+/// - the top documents are collected with the wrapped collector, e.g.
+///   [`TopDocs`](crate::collector::TopDocs) or one of its `custom_score`/`tweak_score` variants.
+/// - once the top documents are known, the aggregations are collected over the documents of the
+///   page, reusing the segment readers opened by the searcher for the first phase.
+///
+/// The fruit is the pair of the top documents and the [`AggregationResults`] of the page.
+///
+/// ```rust
+/// use tantivy::aggregation::agg_req::Aggregations;
+/// use tantivy::aggregation::TopDocsWithPageAggregations;
+/// use tantivy::collector::TopDocs;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST};
+/// use tantivy::{doc, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let price = schema_builder.add_u64_field("price", FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// for val in [10u64, 20, 30, 40] {
+///     index_writer.add_document(doc!(price => val))?;
+/// }
+/// index_writer.commit()?;
+///
+/// let aggs: Aggregations =
+///     serde_json::from_str(r#"{ "avg_price": { "avg": { "field": "price" } } }"#).unwrap();
+/// let collector = TopDocsWithPageAggregations::new(
+///     TopDocs::with_limit(2).order_by_u64_field("price", tantivy::Order::Desc),
+///     aggs,
+/// );
+/// let searcher = index.reader()?.searcher();
+/// let (top_docs, page_aggs) = searcher.search(&AllQuery, &collector)?;
+/// assert_eq!(top_docs.len(), 2);
+/// let page_aggs = serde_json::to_value(page_aggs).unwrap();
+/// assert_eq!(page_aggs["avg_price"]["value"], 35.0);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TopDocsWithPageAggregations<TCollector> {
+    top_docs: TCollector,
+    agg: Aggregations,
+    limits: AggregationLimitsGuard,
+    value_transforms: ValueTransformRegistry,
+}
+
+impl<TCollector> TopDocsWithPageAggregations<TCollector> {
+    /// Creates the collector from a top documents collector and an aggregation request, using
+    /// the default [`AggregationLimitsGuard`].
+    pub fn new(top_docs: TCollector, mut agg: Aggregations) -> Self {
+        pin_date_range_now(&mut agg);
+        TopDocsWithPageAggregations {
+            top_docs,
+            agg,
+            limits: AggregationLimitsGuard::default(),
+            value_transforms: ValueTransformRegistry::default(),
+        }
+    }
+
+    /// Sets the limits of the page aggregations.
+    pub fn with_limits(mut self, limits: AggregationLimitsGuard) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the registry used to resolve the custom `value_transform`s of the request.
+    pub fn with_value_transforms(mut self, value_transforms: ValueTransformRegistry) -> Self {
+        self.value_transforms = value_transforms;
+        self
+    }
+
+    /// Collects the aggregations over the given documents of the segment, in doc id order.
+    fn collect_page_segment(
+        &self,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+        docs: &[DocId],
+    ) -> crate::Result<IntermediateAggregationResults> {
+        let mut agg_collector =
+            AggregationSegmentCollector::from_agg_req_and_reader_with_value_transforms(
+                &self.agg,
+                reader,
+                segment_ord,
+                &self.limits,
+                &self.value_transforms,
+            )?;
+        agg_collector.collect_block(docs);
+        agg_collector.harvest()
+    }
+}
+
+impl<TCollector, TScore> Collector for TopDocsWithPageAggregations<TCollector>
+where
+    TCollector: Collector<Fruit = Vec<(TScore, DocAddress)>>,
+    TScore: Send + 'static,
+{
+    type Fruit = (Vec<(TScore, DocAddress)>, AggregationResults);
+
+    type Child = PageAggregationsSegmentCollector<TCollector::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        Ok(PageAggregationsSegmentCollector {
+            top_docs: self.top_docs.for_segment(segment_local_id, reader)?,
+            segment_ord: segment_local_id,
+            reader: reader.clone(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.top_docs.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> crate::Result<Self::Fruit> {
+        let mut readers = Vec::with_capacity(segment_fruits.len());
+        let mut top_docs_fruits = Vec::with_capacity(segment_fruits.len());
+        for (top_docs_fruit, segment_ord, reader) in segment_fruits {
+            top_docs_fruits.push(top_docs_fruit);
+            readers.push((segment_ord, reader));
+        }
+        let top_docs = self.top_docs.merge_fruits(top_docs_fruits)?;
+
+        let mut page_aggs: Option<IntermediateAggregationResults> = None;
+        for (segment_ord, reader) in &readers {
+            let mut docs: Vec<DocId> = top_docs
+                .iter()
+                .filter(|(_, doc_address)| doc_address.segment_ord == *segment_ord)
+                .map(|(_, doc_address)| doc_address.doc_id)
+                .collect();
+            if docs.is_empty() {
+                continue;
+            }
+            docs.sort_unstable();
+            let segment_aggs = self.collect_page_segment(*segment_ord, reader, &docs)?;
+            match page_aggs.as_mut() {
+                Some(page_aggs) => page_aggs.merge_fruits(segment_aggs)?,
+                None => page_aggs = Some(segment_aggs),
+            }
+        }
+        let page_aggs = page_aggs
+            .unwrap_or_default()
+            .into_final_result(self.agg.clone(), self.limits.clone())?;
+        Ok((top_docs, page_aggs))
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        // Keeps the optimized collection of the wrapped collector, if any.
+        let top_docs_fruit = self.top_docs.collect_segment(weight, segment_ord, reader)?;
+        Ok((top_docs_fruit, segment_ord, reader.clone()))
+    }
+}
+
+/// Segment collector of [`TopDocsWithPageAggregations`].
+///
+/// Its fruit keeps the segment reader, so that the page aggregations can be collected once the
+/// top documents of all segments are merged.
+pub struct PageAggregationsSegmentCollector<TSegmentCollector> {
+    top_docs: TSegmentCollector,
+    segment_ord: SegmentOrdinal,
+    reader: SegmentReader,
+}
+
+impl<TSegmentCollector: SegmentCollector> SegmentCollector
+    for PageAggregationsSegmentCollector<TSegmentCollector>
+{
+    type Fruit = (TSegmentCollector::Fruit, SegmentOrdinal, SegmentReader);
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.top_docs.collect(doc, score);
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        self.top_docs.collect_block(docs);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        (self.top_docs.harvest(), self.segment_ord, self.reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use common::BitSet;
+    use serde_json::{json, Value};
+
+    use super::TopDocsWithPageAggregations;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Collector, TopDocs};
+    use crate::index::SegmentId;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, STRING, TEXT};
+    use crate::{
+        DocAddress, Index, IndexWriter, Order, Searcher, SegmentDocFilter, SegmentReader, Term,
+    };
+
+    struct PageFilter(HashMap<SegmentId, Arc<BitSet>>);
+
+    impl SegmentDocFilter for PageFilter {
+        fn alive(&self, segment_id: SegmentId) -> Option<Arc<BitSet>> {
+            self.0.get(&segment_id).cloned()
+        }
+    }
+
+    /// Aggregates the given documents with a regular `AggregationCollector`, hiding all the
+    /// other documents.
+    fn aggregate_docs(searcher: &Searcher, docs: &[DocAddress], aggs: &Aggregations) -> Value {
+        let filter = searcher
+            .segment_readers()
+            .iter()
+            .enumerate()
+            .map(|(segment_ord, reader): (usize, &SegmentReader)| {
+                let mut bitset = BitSet::with_max_value(reader.max_doc());
+                for doc in docs {
+                    if doc.segment_ord as usize == segment_ord {
+                        bitset.insert(doc.doc_id);
+                    }
+                }
+                (reader.segment_id(), Arc::new(bitset))
+            })
+            .collect();
+        let filtered_searcher = searcher
+            .with_doc_filter(Arc::new(PageFilter(filter)))
+            .unwrap();
+        let collector = AggregationCollector::from_aggs(aggs.clone(), Default::default());
+        let agg_res = filtered_searcher.search(&AllQuery, &collector).unwrap();
+        serde_json::to_value(agg_res).unwrap()
+    }
+
+    fn test_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let tag = schema_builder.add_text_field("tag", STRING | FAST);
+        let price = schema_builder.add_u64_field("price", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..3u64 {
+            for i in 0..40u64 {
+                let val = segment * 40 + i;
+                let body = if val % 3 == 0 {
+                    "hello hello world"
+                } else {
+                    "hello"
+                };
+                index_writer.add_document(doc!(
+                    text => body,
+                    tag => format!("tag{}", val % 4),
+                    price => val,
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn aggs_req() -> Aggregations {
+        serde_json::from_value(json!({
+            "tags": {
+                "terms": { "field": "tag" },
+                "aggs": { "max_price": { "max": { "field": "price" } } }
+            },
+            "avg_price": { "avg": { "field": "price" } },
+            "price_histogram": { "histogram": { "field": "price", "interval": 50.0 } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_page_aggregations_order_by_fast_field() -> crate::Result<()> {
+        let index = test_index()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 3);
+        let aggs = aggs_req();
+
+        let collector = TopDocsWithPageAggregations::new(
+            TopDocs::with_limit(10)
+                .and_offset(5)
+                .order_by_u64_field("price", Order::Desc),
+            aggs.clone(),
+        );
+        let (top_docs, page_aggs) = searcher.search(&AllQuery, &collector)?;
+        let prices: Vec<u64> = top_docs.iter().map(|(price, _)| *price).collect();
+        assert_eq!(prices, (105..=114).rev().collect::<Vec<u64>>());
+
+        let docs: Vec<DocAddress> = top_docs.iter().map(|(_, doc)| *doc).collect();
+        let page_aggs = serde_json::to_value(page_aggs).unwrap();
+        assert_eq!(page_aggs, aggregate_docs(&searcher, &docs, &aggs));
+        assert_eq!(page_aggs["avg_price"]["value"], 109.5);
+        assert_eq!(page_aggs["price_histogram"]["buckets"][0]["key"], 100.0);
+        assert_eq!(page_aggs["price_histogram"]["buckets"][0]["doc_count"], 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_aggregations_scored() -> crate::Result<()> {
+        let index = test_index()?;
+        let text = index.schema().get_field("text").unwrap();
+        let searcher = index.reader()?.searcher();
+        let aggs = aggs_req();
+
+        let query = TermQuery::new(
+            Term::from_field_text(text, "hello"),
+            IndexRecordOption::WithFreqs,
+        );
+        let collector = TopDocsWithPageAggregations::new(TopDocs::with_limit(15), aggs.clone());
+        assert!(collector.requires_scoring());
+        let (top_docs, page_aggs) = searcher.search(&query, &collector)?;
+        assert_eq!(top_docs, searcher.search(&query, &TopDocs::with_limit(15))?);
+        let docs: Vec<DocAddress> = top_docs.iter().map(|(_, doc)| *doc).collect();
+        assert_eq!(
+            serde_json::to_value(page_aggs).unwrap(),
+            aggregate_docs(&searcher, &docs, &aggs)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_aggregations_custom_score_and_empty_page() -> crate::Result<()> {
+        let index = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let aggs = aggs_req();
+
+        // Ranks the documents by the remainder of their price by 7.
+        let top_docs = TopDocs::with_limit(12).custom_score(|reader: &SegmentReader| {
+            let price = reader
+                .fast_fields()
+                .u64("price")
+                .unwrap()
+                .first_or_default_col(0);
+            move |doc| price.get_val(doc) % 7
+        });
+        let collector = TopDocsWithPageAggregations::new(top_docs, aggs.clone());
+        let (top_docs, page_aggs) = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(top_docs.len(), 12);
+        assert!(top_docs.iter().all(|(score, _)| *score == 6));
+        let docs: Vec<DocAddress> = top_docs.iter().map(|(_, doc)| *doc).collect();
+        assert_eq!(
+            serde_json::to_value(page_aggs).unwrap(),
+            aggregate_docs(&searcher, &docs, &aggs)
+        );
+
+        let query = TermQuery::new(
+            Term::from_field_text(index.schema().get_field("text").unwrap(), "missing"),
+            IndexRecordOption::Basic,
+        );
+        let collector = TopDocsWithPageAggregations::new(TopDocs::with_limit(5), aggs.clone());
+        let (top_docs, page_aggs) = searcher.search(&query, &collector)?;
+        assert!(top_docs.is_empty());
+        assert_eq!(
+            serde_json::to_value(page_aggs).unwrap(),
+            aggregate_docs(&searcher, &[], &aggs)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+//! Sampling of the documents fed to the aggregations, trading exactness for speed.
+//!
+//! See [`AggregationSampling`].
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use super::agg_result::{
+    AggregationResult, AggregationResults, BucketEntries, BucketResult, MetricResult,
+};
+use super::metric::SingleMetricResult;
+use super::AggregationError;
+use crate::index::SegmentId;
+use crate::{DocId, TantivyError};
+
+/// Samples the documents matching the query before they are aggregated.
+///
+/// Each document is kept with the given `probability`, the choice only depending on the `seed`,
+/// the segment and the document id: the results are deterministic for a fixed seed and segment
+/// layout.
+///
+/// The counts are then scaled by `1 / probability`, to estimate the counts of the whole set of
+/// documents:
+/// - the `doc_count` of the buckets, and the `sum_other_doc_count` and
+///   `doc_count_error_upper_bound` of the `terms` aggregations,
+/// - the `sum` and `value_count` metrics, and the `count`, `sum` and `sum_of_squares` of `stats`
+///   and `extended_stats`.
+///
+/// The other metrics, e.g. `avg`, `min`, `max` and `percentiles`, are computed on the sample
+/// directly. The `cardinality` is not scaled either: the number of distinct values does not grow
+/// linearly with the number of documents, so it is the number of distinct values of the sample.
+/// The options applying to the counts of the buckets, e.g. `min_doc_count`, apply to the counts
+/// of the sample, before they are scaled.
+///
+/// The results report the sampling in their [`SampledDocs`], see
+/// [`AggregationResults::sampled()`].
+///
+/// ```json
+/// {
+///     "sampling": { "probability": 0.1, "seed": 42 },
+///     "avg_price": { "avg": { "field": "price" } }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AggregationSampling {
+    /// The probability for a document to be aggregated, in `(0, 1]`.
+    pub probability: f64,
+    /// The seed of the sampling.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl AggregationSampling {
+    /// Creates a sampling keeping each document with `probability`.
+    pub fn new(probability: f64, seed: u64) -> AggregationSampling {
+        AggregationSampling { probability, seed }
+    }
+
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        if !(self.probability > 0.0 && self.probability <= 1.0) {
+            return Err(TantivyError::AggregationError(
+                AggregationError::InvalidRequest(format!(
+                    "the sampling probability must be in (0, 1], got {}",
+                    self.probability
+                )),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Report of the sampling of the documents of an aggregation, see [`AggregationSampling`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampledDocs {
+    /// The probability for a document to be aggregated.
+    pub probability: f64,
+    /// The number of documents aggregated.
+    pub docs_sampled: u64,
+    /// The number of documents matching the query.
+    pub docs_total: u64,
+}
+
+impl SampledDocs {
+    pub(crate) fn merge(&mut self, other: &SampledDocs) {
+        self.probability = other.probability;
+        self.docs_sampled += other.docs_sampled;
+        self.docs_total += other.docs_total;
+    }
+}
+
+/// Decides which documents of a segment are aggregated.
+pub(crate) struct DocSampler {
+    probability: f64,
+    /// The documents whose hash is below the threshold are sampled.
+    threshold: u64,
+    segment_seed: u64,
+    docs_sampled: u64,
+    docs_total: u64,
+}
+
+impl DocSampler {
+    pub fn new(sampling: &AggregationSampling, segment_id: SegmentId) -> DocSampler {
+        let mut hasher = DefaultHasher::new();
+        sampling.seed.hash(&mut hasher);
+        segment_id.hash(&mut hasher);
+        let threshold = if sampling.probability >= 1.0 {
+            u64::MAX
+        } else {
+            (sampling.probability * u64::MAX as f64) as u64
+        };
+        DocSampler {
+            probability: sampling.probability,
+            threshold,
+            segment_seed: hasher.finish(),
+            docs_sampled: 0,
+            docs_total: 0,
+        }
+    }
+
+    #[inline]
+    pub fn sample(&mut self, doc: DocId) -> bool {
+        self.docs_total += 1;
+        let sampled = self.threshold == u64::MAX
+            || splitmix64(self.segment_seed ^ doc as u64) < self.threshold;
+        self.docs_sampled += sampled as u64;
+        sampled
+    }
+
+    /// Copies the sampled documents of `docs` to `output`.
+    pub fn sample_block(&mut self, docs: &[DocId], output: &mut Vec<DocId>) {
+        output.clear();
+        output.extend(docs.iter().copied().filter(|doc| self.sample(*doc)));
+    }
+
+    pub fn sampled_docs(&self) -> SampledDocs {
+        SampledDocs {
+            probability: self.probability,
+            docs_sampled: self.docs_sampled,
+            docs_total: self.docs_total,
+        }
+    }
+}
+
+/// Mixes the bits of `val`, see the SplitMix64 generator.
+#[inline]
+fn splitmix64(val: u64) -> u64 {
+    let mut z = val.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Scales the counts of the results of sampled documents by `factor`, see
+/// [`AggregationSampling`].
+pub(crate) fn scale_results(results: &mut AggregationResults, factor: f64) {
+    for result in results.0.values_mut() {
+        match result {
+            AggregationResult::BucketResult(bucket_result) => {
+                scale_bucket_result(bucket_result, factor)
+            }
+            AggregationResult::MetricResult(metric_result) => {
+                scale_metric_result(metric_result, factor)
+            }
+            AggregationResult::CustomResult(_) => {}
+        }
+    }
+}
+
+fn scale_count(count: &mut u64, factor: f64) {
+    *count = (*count as f64 * factor).round() as u64;
+}
+
+fn scale_bucket_result(bucket_result: &mut BucketResult, factor: f64) {
+    match bucket_result {
+        BucketResult::SignificantTerms {
+            doc_count, buckets, ..
+        } => {
+            // The background set is not sampled.
+            scale_count(doc_count, factor);
+            for bucket in buckets {
+                scale_count(&mut bucket.doc_count, factor);
+                scale_results(&mut bucket.sub_aggregation, factor);
+            }
+        }
+        BucketResult::Range { buckets } => {
+            let buckets: Box<dyn Iterator<Item = _>> = match buckets {
+                BucketEntries::Vec(buckets) => Box::new(buckets.iter_mut()),
+                BucketEntries::HashMap(buckets) => Box::new(buckets.values_mut()),
+            };
+            for bucket in buckets {
+                scale_count(&mut bucket.doc_count, factor);
+                scale_results(&mut bucket.sub_aggregation, factor);
+            }
+        }
+        BucketResult::Histogram { buckets } => {
+            let buckets: Box<dyn Iterator<Item = _>> = match buckets {
+                BucketEntries::Vec(buckets) => Box::new(buckets.iter_mut()),
+                BucketEntries::HashMap(buckets) => Box::new(buckets.values_mut()),
+            };
+            for bucket in buckets {
+                scale_count(&mut bucket.doc_count, factor);
+                scale_results(&mut bucket.sub_aggregation, factor);
+            }
+        }
+        BucketResult::Terms {
+            buckets,
+            sum_other_doc_count,
+            doc_count_error_upper_bound,
+        } => {
+            scale_count(sum_other_doc_count, factor);
+            if let Some(doc_count_error_upper_bound) = doc_count_error_upper_bound {
+                scale_count(doc_count_error_upper_bound, factor);
+            }
+            for bucket in buckets {
+                scale_count(&mut bucket.doc_count, factor);
+                scale_results(&mut bucket.sub_aggregation, factor);
+            }
+        }
+        BucketResult::Composite { buckets, .. } => {
+            for bucket in buckets {
+                scale_count(&mut bucket.doc_count, factor);
+                scale_results(&mut bucket.sub_aggregation, factor);
+            }
+        }
+        BucketResult::Single {
+            doc_count,
+            sub_aggregation,
+        } => {
+            scale_count(doc_count, factor);
+            scale_results(sub_aggregation, factor);
+        }
+    }
+}
+
+fn scale_metric_result(metric_result: &mut MetricResult, factor: f64) {
+    let scale_value = |metric: &mut SingleMetricResult| {
+        if let Some(value) = metric.value.as_mut() {
+            *value *= factor;
+        }
+    };
+    match metric_result {
+        MetricResult::Count(count) => {
+            if let Some(value) = count.value.as_mut() {
+                *value = (*value * factor).round();
+            }
+        }
+        MetricResult::Sum(sum) => scale_value(sum),
+        MetricResult::Stats(stats) => {
+            scale_count(&mut stats.count, factor);
+            stats.sum *= factor;
+        }
+        MetricResult::ExtendedStats(extended_stats) => {
+            scale_count(&mut extended_stats.count, factor);
+            extended_stats.sum *= factor;
+            if let Some(sum_of_squares) = extended_stats.sum_of_squares.as_mut() {
+                *sum_of_squares *= factor;
+            }
+        }
+        MetricResult::Average(_)
+        | MetricResult::Max(_)
+        | MetricResult::Min(_)
+        | MetricResult::Percentiles(_)
+        | MetricResult::TopHits(_)
+        | MetricResult::Cardinality(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{AggregationSampling, SampledDocs};
+    use crate::aggregation::agg_req::{AggregationRequest, Aggregations};
+    use crate::aggregation::agg_result::{AggregationResults, SAMPLED_KEY};
+    use crate::aggregation::AggregationCollector;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, TantivyError};
+
+    const NUM_DOCS_PER_SEGMENT: u64 = 20_000;
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let category = schema_builder.add_text_field("category", STRING | FAST);
+        let value = schema_builder.add_f64_field("value", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..3 {
+            for i in 0..NUM_DOCS_PER_SEGMENT {
+                // Categories of decreasing sizes: 40%, 30%, 20% and 10% of the documents.
+                let category_id = match i % 10 {
+                    0..=3 => 0,
+                    4..=6 => 1,
+                    7..=8 => 2,
+                    _ => 3,
+                };
+                let value_f64 = ((i * 7 + segment) % 100) as f64;
+                index_writer.add_document(doc!(
+                    category => format!("category-{category_id}"),
+                    value => value_f64,
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn aggs() -> Aggregations {
+        serde_json::from_value(json!({
+            "categories": {
+                "terms": { "field": "category" },
+                "aggs": { "sum": { "sum": { "field": "value" } } }
+            },
+            "values": { "histogram": { "field": "value", "interval": 25.0 } },
+            "count": { "value_count": { "field": "value" } },
+            "stats": { "stats": { "field": "value" } },
+            "avg": { "avg": { "field": "value" } },
+            "cardinality": { "cardinality": { "field": "category" } }
+        }))
+        .unwrap()
+    }
+
+    fn aggregate(index: &Index, sampling: Option<AggregationSampling>) -> crate::Result<Value> {
+        let mut collector = AggregationCollector::from_aggs(aggs(), Default::default());
+        if let Some(sampling) = sampling {
+            collector = collector.with_sampling(sampling);
+        }
+        let searcher = index.reader()?.searcher();
+        let results: AggregationResults = searcher.search(&AllQuery, &collector)?;
+        Ok(serde_json::to_value(results)?)
+    }
+
+    /// Checks that the estimate of a count of the sampled documents is within 5 standard
+    /// deviations of the exact count.
+    fn assert_count_within_tolerance(estimate: &Value, exact: &Value, probability: f64) {
+        let estimate = estimate.as_f64().unwrap();
+        let exact = exact.as_f64().unwrap();
+        let std_dev = (exact * (1.0 - probability) / probability).sqrt();
+        assert!(
+            (estimate - exact).abs() <= 5.0 * std_dev,
+            "estimate {estimate}, exact {exact}"
+        );
+    }
+
+    #[test]
+    fn test_sampled_aggregations_estimate_exact_results() -> crate::Result<()> {
+        let index = create_index()?;
+        let exact = aggregate(&index, None)?;
+        let probability = 0.1;
+        let sampled = aggregate(&index, Some(AggregationSampling::new(probability, 42)))?;
+
+        let report: SampledDocs = serde_json::from_value(sampled[SAMPLED_KEY].clone()).unwrap();
+        assert_eq!(report.probability, probability);
+        assert_eq!(report.docs_total, 3 * NUM_DOCS_PER_SEGMENT);
+        assert_count_within_tolerance(
+            &json!(report.docs_sampled as f64 / probability),
+            &json!(report.docs_total),
+            probability,
+        );
+
+        for (bucket, exact_bucket) in sampled["categories"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .zip(exact["categories"]["buckets"].as_array().unwrap())
+        {
+            // The order of the categories is preserved, they differ by more than the error.
+            assert_eq!(bucket["key"], exact_bucket["key"]);
+            assert_count_within_tolerance(
+                &bucket["doc_count"],
+                &exact_bucket["doc_count"],
+                probability,
+            );
+            // The sum of the values, in [0, 100), is estimated like a count scaled by 100.
+            let sum = bucket["sum"]["value"].as_f64().unwrap() / 100.0;
+            let exact_sum = exact_bucket["sum"]["value"].as_f64().unwrap() / 100.0;
+            assert_count_within_tolerance(&json!(sum), &json!(exact_sum), probability);
+        }
+        for (bucket, exact_bucket) in sampled["values"]["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .zip(exact["values"]["buckets"].as_array().unwrap())
+        {
+            assert_count_within_tolerance(
+                &bucket["doc_count"],
+                &exact_bucket["doc_count"],
+                probability,
+            );
+        }
+        assert_count_within_tolerance(
+            &sampled["count"]["value"],
+            &exact["count"]["value"],
+            probability,
+        );
+        assert_count_within_tolerance(
+            &sampled["stats"]["count"],
+            &exact["stats"]["count"],
+            probability,
+        );
+        // The average and the cardinality are not scaled.
+        let avg = sampled["avg"]["value"].as_f64().unwrap();
+        let exact_avg = exact["avg"]["value"].as_f64().unwrap();
+        assert!((avg - exact_avg).abs() < 2.0, "{avg} {exact_avg}");
+        assert_eq!(sampled["stats"]["avg"], sampled["avg"]["value"]);
+        assert_eq!(sampled["cardinality"]["value"], 4.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampling_with_probability_one_is_exact() -> crate::Result<()> {
+        let index = create_index()?;
+        let exact = aggregate(&index, None)?;
+        let mut sampled = aggregate(&index, Some(AggregationSampling::new(1.0, 7)))?;
+        let report = sampled
+            .as_object_mut()
+            .unwrap()
+            .remove(SAMPLED_KEY)
+            .unwrap();
+        assert_eq!(
+            report,
+            json!({ "probability": 1.0, "docs_sampled": 60_000, "docs_total": 60_000 })
+        );
+        assert_eq!(sampled, exact);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_for_a_seed() -> crate::Result<()> {
+        let index = create_index()?;
+        let sampling = AggregationSampling::new(0.05, 3);
+        let first = aggregate(&index, Some(sampling))?;
+        assert_eq!(aggregate(&index, Some(sampling))?, first);
+        let other_seed = aggregate(&index, Some(AggregationSampling::new(0.05, 4)))?;
+        assert_ne!(other_seed, first);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampling_in_request() -> crate::Result<()> {
+        let index = create_index()?;
+        let request: AggregationRequest = serde_json::from_value(json!({
+            "sampling": { "probability": 0.5, "seed": 42 },
+            "count": { "value_count": { "field": "value" } }
+        }))
+        .unwrap();
+        assert_eq!(request.sampling, Some(AggregationSampling::new(0.5, 42)));
+        assert_eq!(request.aggs.len(), 1);
+        let collector = AggregationCollector::from_request(request, Default::default());
+        let searcher = index.reader()?.searcher();
+        let results = searcher.search(&AllQuery, &collector)?;
+        let report = results.sampled().unwrap();
+        assert_eq!(report.docs_total, 60_000);
+        assert!(report.docs_sampled > 25_000 && report.docs_sampled < 35_000);
+
+        let request: AggregationRequest = serde_json::from_value(json!({
+            "sampling": { "probability": 0.0 },
+            "count": { "value_count": { "field": "value" } }
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_request(request, Default::default());
+        let err = searcher.search(&AllQuery, &collector).unwrap_err();
+        assert!(matches!(err, TantivyError::AggregationError(_)), "{err:?}");
+        Ok(())
+    }
+}
@@ -4,24 +4,36 @@
 //! merging.
 
 use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+use downcast_rs::impl_downcast;
 
 pub(crate) use super::agg_limits::AggregationLimitsGuard;
 use super::agg_req::AggregationVariants;
-use super::agg_req_with_accessor::{AggregationWithAccessor, AggregationsWithAccessor};
-use super::bucket::{SegmentHistogramCollector, SegmentRangeCollector, SegmentTermCollector};
+use super::agg_req_with_accessor::{
+    prefix_agg_path, AggregationWithAccessor, AggregationsWithAccessor,
+};
+use super::bucket::{
+    SegmentCompositeCollector, SegmentHistogramCollector, SegmentPathTermsCollector,
+    SegmentPrefixTermsCollector, SegmentRangeCollector, SegmentSignificantTermsCollector,
+    SegmentTermCollector,
+};
 use super::intermediate_agg_result::IntermediateAggregationResults;
 use super::metric::{
     AverageAggregation, CountAggregation, ExtendedStatsAggregation, MaxAggregation, MinAggregation,
     SegmentPercentilesCollector, SegmentStatsCollector, SegmentStatsType, StatsAggregation,
     SumAggregation,
 };
-use crate::aggregation::bucket::TermMissingAgg;
+use crate::aggregation::bucket::{SegmentExistsCollector, TermMissingAgg};
 use crate::aggregation::metric::{
     CardinalityAggregationReq, SegmentCardinalityCollector, SegmentExtendedStatsCollector,
     TopHitsSegmentCollector,
 };
+use crate::collector::ProfileRecorder;
 
-pub(crate) trait SegmentAggregationCollector: CollectorClone + Debug {
+pub(crate) trait SegmentAggregationCollector:
+    CollectorClone + Debug + downcast_rs::Downcast
+{
     fn add_intermediate_aggregation_result(
         self: Box<Self>,
         agg_with_accessor: &AggregationsWithAccessor,
@@ -47,6 +59,8 @@ pub(crate) trait SegmentAggregationCollector: CollectorClone + Debug {
     }
 }
 
+impl_downcast!(SegmentAggregationCollector);
+
 pub(crate) trait CollectorClone {
     fn clone_box(&self) -> Box<dyn SegmentAggregationCollector>;
 }
@@ -70,9 +84,11 @@ pub(crate) fn build_segment_agg_collector(
 ) -> crate::Result<Box<dyn SegmentAggregationCollector>> {
     // Single collector special case
     if req.aggs.len() == 1 {
+        let key = req.aggs.keys[0].clone();
         let req = &mut req.aggs.values[0];
         let accessor_idx = 0;
-        return build_single_agg_segment_collector(req, accessor_idx);
+        return build_single_agg_segment_collector(req, accessor_idx)
+            .map_err(|err| prefix_agg_path(err, &key));
     }
 
     let agg = GenericSegmentAggregationResultsCollector::from_req_and_validate(req)?;
@@ -92,6 +108,7 @@ pub(crate) fn build_single_agg_segment_collector(
                     &mut req.sub_aggregation,
                     req.field_type,
                     accessor_idx,
+                    req.limits.spill(),
                 )?))
             } else {
                 Ok(Box::new(TermMissingAgg::new(
@@ -100,6 +117,46 @@ pub(crate) fn build_single_agg_segment_collector(
                 )?))
             }
         }
+        SignificantTerms(significant_terms_req) => Ok(Box::new(
+            SegmentSignificantTermsCollector::from_req_and_validate(
+                significant_terms_req,
+                &mut req.sub_aggregation,
+                req.field_type,
+                accessor_idx,
+            )?,
+        )),
+        PathTerms(path_terms_req) => {
+            Ok(Box::new(SegmentPathTermsCollector::from_req_and_validate(
+                path_terms_req,
+                &mut req.sub_aggregation,
+                req.field_type,
+                accessor_idx,
+            )?))
+        }
+        PrefixTerms(prefix_terms_req) => Ok(Box::new(
+            SegmentPrefixTermsCollector::from_req_and_validate(
+                prefix_terms_req,
+                &mut req.sub_aggregation,
+                req.field_type,
+                accessor_idx,
+            )?,
+        )),
+        Composite(composite_req) => Ok(Box::new(SegmentCompositeCollector::from_req_and_validate(
+            composite_req,
+            &mut req.sub_aggregation,
+            &req.accessors,
+            accessor_idx,
+        )?)),
+        Exists(_) => Ok(Box::new(SegmentExistsCollector::new(
+            true,
+            &mut req.sub_aggregation,
+            accessor_idx,
+        )?)),
+        Missing(_) => Ok(Box::new(SegmentExistsCollector::new(
+            false,
+            &mut req.sub_aggregation,
+            accessor_idx,
+        )?)),
         Range(range_req) => Ok(Box::new(SegmentRangeCollector::from_req_and_validate(
             range_req,
             &mut req.sub_aggregation,
@@ -107,6 +164,15 @@ pub(crate) fn build_single_agg_segment_collector(
             req.field_type,
             accessor_idx,
         )?)),
+        DateRange(date_range_req) => Ok(Box::new(
+            SegmentRangeCollector::from_date_range_req_and_validate(
+                date_range_req,
+                &mut req.sub_aggregation,
+                &mut req.limits,
+                req.field_type,
+                accessor_idx,
+            )?,
+        )),
         Histogram(histogram) => Ok(Box::new(SegmentHistogramCollector::from_req_and_validate(
             histogram.clone(),
             &mut req.sub_aggregation,
@@ -119,46 +185,65 @@ pub(crate) fn build_single_agg_segment_collector(
             req.field_type,
             accessor_idx,
         )?)),
-        Average(AverageAggregation { missing, .. }) => {
+        Average(AverageAggregation { missing, mode, .. }) => {
             Ok(Box::new(SegmentStatsCollector::from_req(
                 req.field_type,
                 SegmentStatsType::Average,
                 accessor_idx,
                 *missing,
+                *mode,
             )))
         }
-        Count(CountAggregation { missing, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
-            req.field_type,
-            SegmentStatsType::Count,
-            accessor_idx,
-            *missing,
-        ))),
-        Max(MaxAggregation { missing, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
+        Count(CountAggregation { missing, mode, .. }) => {
+            Ok(Box::new(SegmentStatsCollector::from_req(
+                req.field_type,
+                SegmentStatsType::Count,
+                accessor_idx,
+                *missing,
+                *mode,
+            )))
+        }
+        Max(MaxAggregation { missing, mode, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
             req.field_type,
             SegmentStatsType::Max,
             accessor_idx,
             *missing,
+            *mode,
         ))),
-        Min(MinAggregation { missing, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
+        Min(MinAggregation { missing, mode, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
             req.field_type,
             SegmentStatsType::Min,
             accessor_idx,
             *missing,
+            *mode,
         ))),
-        Stats(StatsAggregation { missing, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
+        Stats(StatsAggregation { missing, mode, .. }) => {
+            Ok(Box::new(SegmentStatsCollector::from_req(
+                req.field_type,
+                SegmentStatsType::Stats,
+                accessor_idx,
+                *missing,
+                *mode,
+            )))
+        }
+        ExtendedStats(ExtendedStatsAggregation {
+            missing,
+            sigma,
+            mode,
+            ..
+        }) => Ok(Box::new(SegmentExtendedStatsCollector::from_req(
             req.field_type,
-            SegmentStatsType::Stats,
+            *sigma,
             accessor_idx,
             *missing,
+            *mode,
         ))),
-        ExtendedStats(ExtendedStatsAggregation { missing, sigma, .. }) => Ok(Box::new(
-            SegmentExtendedStatsCollector::from_req(req.field_type, *sigma, accessor_idx, *missing),
-        )),
-        Sum(SumAggregation { missing, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
+        Sum(SumAggregation { missing, mode, .. }) => Ok(Box::new(SegmentStatsCollector::from_req(
             req.field_type,
             SegmentStatsType::Sum,
             accessor_idx,
             *missing,
+            *mode,
         ))),
         Percentiles(percentiles_req) => Ok(Box::new(
             SegmentPercentilesCollector::from_req_and_validate(
@@ -239,13 +324,116 @@ impl SegmentAggregationCollector for GenericSegmentAggregationResultsCollector {
 
 impl GenericSegmentAggregationResultsCollector {
     pub(crate) fn from_req_and_validate(req: &mut AggregationsWithAccessor) -> crate::Result<Self> {
+        let keys = req.aggs.keys.clone();
         let aggs = req
             .aggs
             .values_mut()
+            .zip(&keys)
             .enumerate()
-            .map(|(accessor_idx, req)| build_single_agg_segment_collector(req, accessor_idx))
+            .map(|(accessor_idx, (req, key))| {
+                build_single_agg_segment_collector(req, accessor_idx)
+                    .map_err(|err| prefix_agg_path(err, key))
+            })
             .collect::<crate::Result<Vec<Box<dyn SegmentAggregationCollector>>>>()?;
 
         Ok(GenericSegmentAggregationResultsCollector { aggs })
     }
 }
+
+/// Same as [`GenericSegmentAggregationResultsCollector`], but measures the time spent in each
+/// top-level aggregation, including its sub-aggregations.
+///
+/// The time is measured per block of documents, and reported to the recorder when the
+/// intermediate results are created.
+#[derive(Clone)]
+pub(crate) struct ProfiledSegmentAggregationCollector {
+    aggs: Vec<Box<dyn SegmentAggregationCollector>>,
+    names: Vec<String>,
+    durations: Vec<Duration>,
+    recorder: ProfileRecorder,
+}
+
+impl Debug for ProfiledSegmentAggregationCollector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProfiledSegmentAggregationCollector")
+            .field("aggs", &self.aggs)
+            .field("names", &self.names)
+            .field("durations", &self.durations)
+            .finish()
+    }
+}
+
+impl SegmentAggregationCollector for ProfiledSegmentAggregationCollector {
+    fn add_intermediate_aggregation_result(
+        self: Box<Self>,
+        agg_with_accessor: &AggregationsWithAccessor,
+        results: &mut IntermediateAggregationResults,
+    ) -> crate::Result<()> {
+        let ProfiledSegmentAggregationCollector {
+            aggs,
+            names,
+            durations,
+            recorder,
+        } = *self;
+        for ((agg, name), duration) in aggs.into_iter().zip(&names).zip(durations) {
+            let start = Instant::now();
+            agg.add_intermediate_aggregation_result(agg_with_accessor, results)?;
+            recorder.record(name, duration + start.elapsed());
+        }
+        Ok(())
+    }
+
+    fn collect(
+        &mut self,
+        doc: crate::DocId,
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        self.collect_block(&[doc], agg_with_accessor)
+    }
+
+    fn collect_block(
+        &mut self,
+        docs: &[crate::DocId],
+        agg_with_accessor: &mut AggregationsWithAccessor,
+    ) -> crate::Result<()> {
+        for (collector, duration) in self.aggs.iter_mut().zip(&mut self.durations) {
+            let start = Instant::now();
+            collector.collect_block(docs, agg_with_accessor)?;
+            *duration += start.elapsed();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, agg_with_accessor: &mut AggregationsWithAccessor) -> crate::Result<()> {
+        for (collector, duration) in self.aggs.iter_mut().zip(&mut self.durations) {
+            let start = Instant::now();
+            collector.flush(agg_with_accessor)?;
+            *duration += start.elapsed();
+        }
+        Ok(())
+    }
+}
+
+impl ProfiledSegmentAggregationCollector {
+    /// Wraps the collector tree built by [`build_segment_agg_collector`].
+    pub(crate) fn from_collector(
+        collector: Box<dyn SegmentAggregationCollector>,
+        req: &AggregationsWithAccessor,
+        recorder: ProfileRecorder,
+    ) -> Self {
+        // Several aggregations are collected by a `GenericSegmentAggregationResultsCollector`,
+        // a single one by its own collector.
+        let aggs = match collector.downcast::<GenericSegmentAggregationResultsCollector>() {
+            Ok(generic_collector) => generic_collector.aggs,
+            Err(collector) => vec![collector],
+        };
+        let names: Vec<String> = req.aggs.keys().map(str::to_string).collect();
+        let durations = vec![Duration::ZERO; aggs.len()];
+        ProfiledSegmentAggregationCollector {
+            aggs,
+            names,
+            durations,
+            recorder,
+        }
+    }
+}
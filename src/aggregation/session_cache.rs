@@ -0,0 +1,337 @@
+use std::hash::Hasher;
+use std::sync::Mutex;
+
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+
+use super::agg_req::Aggregations;
+use super::agg_result::AggregationResults;
+use super::bucket::pin_date_range_now;
+use super::collector::{AggregationSegmentCollector, DistributedAggregationCollector};
+use super::intermediate_agg_result::IntermediateAggregationResults;
+use super::segment_agg_result::AggregationLimitsGuard;
+use crate::collector::{Collector, SegmentCollector};
+use crate::index::SegmentId;
+use crate::query::{CacheableQuery, Weight};
+use crate::store::CacheStats;
+use crate::{DocId, Opstamp, Searcher, SegmentOrdinal, SegmentReader};
+
+/// Cache key of the result of an aggregation request on a segment: query fingerprint,
+/// aggregation fingerprint and segment.
+type SessionKey = (u64, u64, SegmentId);
+
+/// Identifies the set of alive documents of a segment: its delete opstamp, and its number of
+/// alive documents, which changes with soft deletes.
+type DeleteGeneration = (Option<Opstamp>, DocId);
+
+/// Cache of the intermediate results of aggregation requests, per segment.
+///
+/// Running the same aggregation request again on a searcher that shares segments with a previous
+/// run, e.g. when a dashboard is refreshed after a commit added a new segment, only collects the
+/// new segments and the segments that gained deletes since. The intermediate results of the
+/// other segments are loaded from the cache, and merged with the collected ones.
+///
+/// The entry of a segment is replaced when the segment gains deletes, and dropped once the
+/// segment is no longer searched, e.g. after a merge.
+///
+/// Requests with a `top_hits` aggregation are not cached, as their results refer to the
+/// documents by segment ordinal, which is not stable across searchers. The searchers of a
+/// principal, from
+/// [`IndexReader::searcher_for_principal()`](crate::IndexReader::searcher_for_principal), bypass
+/// the cache too, since the results of a segment depend on the documents the principal is
+/// allowed to see. Neither should the cache be used with searchers hiding documents with
+/// [`Searcher::with_doc_filter`](crate::Searcher::with_doc_filter).
+#[derive(Default)]
+pub struct AggregationSessionCache {
+    inner: Mutex<AggregationSessionCacheInner>,
+}
+
+#[derive(Default)]
+struct AggregationSessionCacheInner {
+    results: FxHashMap<SessionKey, (DeleteGeneration, IntermediateAggregationResults)>,
+    cache_hits: usize,
+    cache_misses: usize,
+}
+
+impl AggregationSessionCache {
+    /// Creates an empty cache.
+    pub fn new() -> AggregationSessionCache {
+        AggregationSessionCache::default()
+    }
+
+    /// Returns the number of cached segment results, the number of segments loaded from the
+    /// cache (hits) and the number of segments collected (misses).
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            num_entries: inner.results.len(),
+            cache_hits: inner.cache_hits,
+            cache_misses: inner.cache_misses,
+        }
+    }
+
+    /// Runs the aggregation request `aggs` on the documents matching `query`, only collecting the
+    /// segments whose result is not cached, and updates the cache.
+    pub fn search<Q: CacheableQuery>(
+        &self,
+        searcher: &Searcher,
+        query: &Q,
+        mut aggs: Aggregations,
+        limits: AggregationLimitsGuard,
+    ) -> crate::Result<AggregationResults> {
+        // The results of the ranges relative to now are only reused for the same now.
+        pin_date_range_now(&mut aggs);
+        let query_fingerprint = query.fingerprint();
+        let aggregation_fingerprint = aggregation_fingerprint(&aggs)?;
+        let collector = SessionCachedAggregationCollector {
+            cache: self,
+            collector: DistributedAggregationCollector::from_aggs(aggs.clone(), limits.clone()),
+            fingerprints: (query_fingerprint, aggregation_fingerprint),
+            is_cacheable: !contains_top_hits(&aggs) && !searcher.is_access_restricted(),
+        };
+        let intermediate_results = searcher.search(query, &collector)?;
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .results
+            .retain(|&(entry_query, entry_aggregation, segment_id), _| {
+                (entry_query, entry_aggregation) != (query_fingerprint, aggregation_fingerprint)
+                    || searcher
+                        .segment_readers()
+                        .iter()
+                        .any(|segment_reader| segment_reader.segment_id() == segment_id)
+            });
+        drop(inner);
+        intermediate_results.into_final_result(aggs, limits)
+    }
+}
+
+/// Returns a hash of the aggregation request that does not depend on the order of the
+/// aggregations in their maps.
+fn aggregation_fingerprint(aggs: &Aggregations) -> crate::Result<u64> {
+    fn hash_json(value: &Value, hasher: &mut fnv::FnvHasher) {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                hasher.write(b"{");
+                for (key, value) in entries {
+                    hasher.write(key.as_bytes());
+                    hasher.write(b":");
+                    hash_json(value, hasher);
+                }
+                hasher.write(b"}");
+            }
+            Value::Array(values) => {
+                hasher.write(b"[");
+                for value in values {
+                    hash_json(value, hasher);
+                }
+                hasher.write(b"]");
+            }
+            _ => hasher.write(value.to_string().as_bytes()),
+        }
+    }
+    let aggs_json = serde_json::to_value(aggs).map_err(|err| {
+        crate::TantivyError::InvalidArgument(format!("Failed to serialize aggregations: {err}"))
+    })?;
+    let mut hasher = fnv::FnvHasher::default();
+    hash_json(&aggs_json, &mut hasher);
+    Ok(hasher.finish())
+}
+
+fn contains_top_hits(aggs: &Aggregations) -> bool {
+    aggs.values()
+        .any(|agg| agg.agg.as_top_hits().is_some() || contains_top_hits(&agg.sub_aggregation))
+}
+
+/// Collector loading the results of the cached segments, and collecting the other ones.
+struct SessionCachedAggregationCollector<'a> {
+    cache: &'a AggregationSessionCache,
+    collector: DistributedAggregationCollector,
+    /// Query and aggregation fingerprints.
+    fingerprints: (u64, u64),
+    is_cacheable: bool,
+}
+
+impl Collector for SessionCachedAggregationCollector<'_> {
+    type Fruit = IntermediateAggregationResults;
+
+    type Child = AggregationSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        self.collector.for_segment(segment_local_id, reader)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> crate::Result<Self::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        if !self.is_cacheable {
+            return self.collector.collect_segment(weight, segment_ord, reader);
+        }
+        let key = (
+            self.fingerprints.0,
+            self.fingerprints.1,
+            reader.segment_id(),
+        );
+        let delete_generation = (reader.delete_opstamp(), reader.num_docs());
+        {
+            let mut inner = self.cache.inner.lock().unwrap();
+            let cached_results = inner
+                .results
+                .get(&key)
+                .filter(|(entry_delete_generation, _)| {
+                    *entry_delete_generation == delete_generation
+                })
+                .map(|(_, results)| results.clone());
+            if let Some(results) = cached_results {
+                inner.cache_hits += 1;
+                return Ok(Ok(results));
+            }
+            inner.cache_misses += 1;
+        }
+        let results = self
+            .collector
+            .collect_segment(weight, segment_ord, reader)??;
+        self.cache
+            .inner
+            .lock()
+            .unwrap()
+            .results
+            .insert(key, (delete_generation, results.clone()));
+        Ok(Ok(results))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::aggregation::AggregationCollector;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    #[test]
+    fn test_aggregation_session_cache() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let kind = schema_builder.add_text_field("kind", STRING);
+        let id = schema_builder.add_u64_field("id", FAST | INDEXED);
+        let price = schema_builder.add_f64_field("price", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let mut add_segment = |ids: std::ops::Range<u64>| -> crate::Result<()> {
+            for doc_id in ids {
+                index_writer.add_document(doc!(
+                    kind => if doc_id % 4 == 0 { "rare" } else { "common" },
+                    id => doc_id,
+                    price => (doc_id % 17) as f64,
+                ))?;
+            }
+            index_writer.commit()?;
+            Ok(())
+        };
+        add_segment(0..100)?;
+        add_segment(100..200)?;
+
+        let reader = index.reader()?;
+        let query = TermQuery::new(
+            Term::from_field_text(kind, "common"),
+            IndexRecordOption::Basic,
+        );
+        let aggs: Aggregations = serde_json::from_value(json!({
+            "prices": {
+                "histogram": { "field": "price", "interval": 5.0 },
+                "aggs": { "max_id": { "max": { "field": "id" } } }
+            },
+            "avg_price": { "avg": { "field": "price" } }
+        }))
+        .unwrap();
+        let cache = AggregationSessionCache::new();
+        let run = |searcher: &Searcher| -> crate::Result<(Value, Value)> {
+            let cached = cache.search(searcher, &query, aggs.clone(), Default::default())?;
+            let collector = AggregationCollector::from_aggs(aggs.clone(), Default::default());
+            let from_scratch = searcher.search(&query, &collector)?;
+            Ok((
+                serde_json::to_value(cached).unwrap(),
+                serde_json::to_value(from_scratch).unwrap(),
+            ))
+        };
+        let stats = |cache: &AggregationSessionCache| {
+            let stats = cache.stats();
+            (stats.num_entries, stats.cache_hits, stats.cache_misses)
+        };
+
+        let (cached, from_scratch) = run(&reader.searcher())?;
+        assert_eq!(cached, from_scratch);
+        assert_eq!(stats(&cache), (2, 0, 2));
+
+        // Only the new segment is collected.
+        add_segment(200..250)?;
+        reader.reload()?;
+        let (cached, from_scratch) = run(&reader.searcher())?;
+        assert_eq!(cached, from_scratch);
+        assert_eq!(stats(&cache), (3, 2, 3));
+
+        // The segment that gained deletes is collected again.
+        for doc_id in 100..110 {
+            index_writer.delete_term(Term::from_field_u64(id, doc_id))?;
+        }
+        index_writer.commit()?;
+        reader.reload()?;
+        let (cached, from_scratch) = run(&reader.searcher())?;
+        assert_eq!(cached, from_scratch);
+        assert_eq!(stats(&cache), (3, 4, 4));
+
+        // A different aggregation request is not served from the cache.
+        let other_aggs: Aggregations =
+            serde_json::from_value(json!({ "avg_price": { "avg": { "field": "price" } } }))
+                .unwrap();
+        cache.search(&reader.searcher(), &query, other_aggs, Default::default())?;
+        assert_eq!(stats(&cache), (6, 4, 7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregation_fingerprint_ignores_order() -> crate::Result<()> {
+        let aggs_1: Aggregations = serde_json::from_str(
+            r#"{ "a": { "avg": { "field": "x" } }, "b": { "max": { "field": "y" } } }"#,
+        )
+        .unwrap();
+        let aggs_2: Aggregations = serde_json::from_str(
+            r#"{ "b": { "max": { "field": "y" } }, "a": { "avg": { "field": "x" } } }"#,
+        )
+        .unwrap();
+        let aggs_3: Aggregations =
+            serde_json::from_str(r#"{ "a": { "avg": { "field": "y" } } }"#).unwrap();
+        assert_eq!(
+            aggregation_fingerprint(&aggs_1)?,
+            aggregation_fingerprint(&aggs_2)?
+        );
+        assert_ne!(
+            aggregation_fingerprint(&aggs_1)?,
+            aggregation_fingerprint(&aggs_3)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,594 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use common::file_slice::FileSlice;
+use common::TerminatingWrite;
+use rustc_hash::FxHashMap;
+
+use super::intermediate_agg_result::{
+    terms_entries_memory, IntermediateKey, IntermediateTermBucketEntry, MergeFruits,
+};
+use crate::directory::Directory;
+use crate::error::DataCorruption;
+
+/// A bucket of a terms aggregation, as written to the spill files.
+type TermBucket = (IntermediateKey, IntermediateTermBucketEntry);
+
+/// Temporary storage for the buckets of the [terms aggregations](super::bucket::TermsAggregation)
+/// with `allow_spill`, set with
+/// [`AggregationCollector::with_spill`](super::AggregationCollector::with_spill).
+///
+/// Once the buckets of such an aggregation exceed `memory_budget` bytes, on a segment or while
+/// merging the results of the segments, they are written to a temporary file of `directory` as
+/// a run sorted by key, and removed from memory. The runs are merged with a k-way merge when the
+/// final result is computed, keeping only the top buckets in memory.
+///
+/// The temporary files are deleted once the results they belong to are dropped, whether the
+/// aggregation succeeds, fails or is cancelled.
+#[derive(Clone)]
+pub struct AggregationSpill {
+    inner: Arc<SpillInner>,
+}
+
+struct SpillInner {
+    directory: Box<dyn Directory>,
+    memory_budget: usize,
+    /// Makes the names of the files unique among the spills sharing a directory.
+    file_prefix: String,
+    num_spilled_runs: AtomicU64,
+    num_spilled_buckets: AtomicU64,
+}
+
+impl AggregationSpill {
+    /// Creates a spill writing its temporary files to `directory`, once the buckets of an
+    /// aggregation exceed `memory_budget` bytes.
+    pub fn new(directory: Box<dyn Directory>, memory_budget: usize) -> AggregationSpill {
+        AggregationSpill {
+            inner: Arc::new(SpillInner {
+                directory,
+                memory_budget,
+                file_prefix: format!("agg-spill-{}", uuid::Uuid::new_v4().simple()),
+                num_spilled_runs: AtomicU64::new(0),
+                num_spilled_buckets: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the number of runs written to the directory so far.
+    pub fn num_spilled_runs(&self) -> u64 {
+        self.inner.num_spilled_runs.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Returns the number of buckets written to the directory so far.
+    pub fn num_spilled_buckets(&self) -> u64 {
+        self.inner.num_spilled_buckets.load(AtomicOrdering::Relaxed)
+    }
+
+    pub(crate) fn memory_budget(&self) -> usize {
+        self.inner.memory_budget
+    }
+
+    /// Writes the buckets to a new run, sorted by key.
+    fn write_run(&self, mut buckets: Vec<TermBucket>) -> crate::Result<SpilledRun> {
+        buckets.sort_unstable_by(|left, right| cmp_intermediate_keys(&left.0, &right.0));
+        let run_id = self
+            .inner
+            .num_spilled_runs
+            .fetch_add(1, AtomicOrdering::Relaxed);
+        let run = SpilledRun {
+            spill: self.clone(),
+            path: PathBuf::from(format!("{}-{run_id}.run", self.inner.file_prefix)),
+        };
+        // The run deletes its file when dropped, including when the write fails midway.
+        let mut writer = self.inner.directory.open_write(&run.path)?;
+        for bucket in &buckets {
+            let bytes = postcard::to_allocvec(bucket).map_err(io::Error::other)?;
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+        writer.terminate()?;
+        self.inner
+            .num_spilled_buckets
+            .fetch_add(buckets.len() as u64, AtomicOrdering::Relaxed);
+        Ok(run)
+    }
+}
+
+impl fmt::Debug for AggregationSpill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AggregationSpill")
+            .field("memory_budget", &self.inner.memory_budget)
+            .field("num_spilled_runs", &self.num_spilled_runs())
+            .finish()
+    }
+}
+
+/// A temporary file holding buckets sorted by key, deleted when dropped.
+pub(crate) struct SpilledRun {
+    spill: AggregationSpill,
+    path: PathBuf,
+}
+
+impl SpilledRun {
+    fn open(self: &Arc<Self>) -> crate::Result<SpilledRunReader> {
+        let file = self.spill.inner.directory.open_read(&self.path)?;
+        Ok(SpilledRunReader {
+            _run: self.clone(),
+            file,
+            offset: 0,
+        })
+    }
+}
+
+impl Drop for SpilledRun {
+    fn drop(&mut self) {
+        if let Err(err) = self.spill.inner.directory.delete(&self.path) {
+            warn!(
+                "Failed to delete aggregation spill file {:?}: {err:?}",
+                self.path
+            );
+        }
+    }
+}
+
+struct SpilledRunReader {
+    // Keeps the file alive while it is read.
+    _run: Arc<SpilledRun>,
+    file: FileSlice,
+    offset: usize,
+}
+
+impl SpilledRunReader {
+    fn next_bucket(&mut self) -> crate::Result<Option<TermBucket>> {
+        if self.offset >= self.file.num_bytes().get_bytes() as usize {
+            return Ok(None);
+        }
+        let len_bytes = self.file.read_bytes_slice(self.offset..self.offset + 4)?;
+        let len = u32::from_le_bytes(len_bytes.as_slice().try_into().unwrap()) as usize;
+        let start = self.offset + 4;
+        let bytes = self.file.read_bytes_slice(start..start + len)?;
+        self.offset = start + len;
+        let bucket = postcard::from_bytes(bytes.as_slice()).map_err(|err| {
+            DataCorruption::comment_only(format!("Invalid aggregation spill file: {err}"))
+        })?;
+        Ok(Some(bucket))
+    }
+}
+
+/// Total order of the keys of the spilled runs: by type, then by value.
+fn cmp_intermediate_keys(left: &IntermediateKey, right: &IntermediateKey) -> Ordering {
+    fn rank(key: &IntermediateKey) -> u8 {
+        match key {
+            IntermediateKey::IpAddr(_) => 0,
+            IntermediateKey::Bool(_) => 1,
+            IntermediateKey::Str(_) => 2,
+            IntermediateKey::F64(_) => 3,
+            IntermediateKey::I64(_) => 4,
+            IntermediateKey::U64(_) => 5,
+        }
+    }
+    match (left, right) {
+        (IntermediateKey::F64(left), IntermediateKey::F64(right)) => left.total_cmp(right),
+        _ => rank(left)
+            .cmp(&rank(right))
+            .then_with(|| left.partial_cmp(right).unwrap_or(Ordering::Equal)),
+    }
+}
+
+/// The spilled runs of the buckets of a terms aggregation.
+#[derive(Clone, Default)]
+pub(crate) struct SpilledTermBuckets {
+    /// Set if the buckets of the aggregation may be spilled.
+    spill: Option<AggregationSpill>,
+    /// The `missing` key of the request, which does not count as a number or text key.
+    missing: Option<IntermediateKey>,
+    runs: Vec<Arc<SpilledRun>>,
+    has_number_keys: bool,
+    has_text_keys: bool,
+}
+
+impl SpilledTermBuckets {
+    pub(crate) fn new(spill: AggregationSpill, missing: Option<IntermediateKey>) -> Self {
+        SpilledTermBuckets {
+            spill: Some(spill),
+            missing,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub(crate) fn has_number_keys(&self) -> bool {
+        self.has_number_keys
+    }
+
+    pub(crate) fn has_text_keys(&self) -> bool {
+        self.has_text_keys
+    }
+
+    /// Moves the runs of `other` to these runs.
+    pub(crate) fn append(&mut self, mut other: SpilledTermBuckets) {
+        if self.spill.is_none() {
+            self.spill = other.spill.take();
+            self.missing = other.missing.take();
+        }
+        self.runs.append(&mut other.runs);
+        self.has_number_keys |= other.has_number_keys;
+        self.has_text_keys |= other.has_text_keys;
+    }
+
+    /// Spills `entries` to a new run if they exceed the memory budget of the spill.
+    pub(crate) fn spill_if_over_budget(
+        &mut self,
+        entries: &mut FxHashMap<IntermediateKey, IntermediateTermBucketEntry>,
+    ) -> crate::Result<()> {
+        let Some(spill) = self.spill.as_ref() else {
+            return Ok(());
+        };
+        if entries.is_empty() || terms_entries_memory(entries) <= spill.memory_budget() {
+            return Ok(());
+        }
+        self.spill_buckets(std::mem::take(entries).into_iter().collect())
+    }
+
+    /// Spills `buckets` to a new run.
+    ///
+    /// Panics if the buckets of the aggregation may not be spilled.
+    pub(crate) fn spill_buckets(&mut self, buckets: Vec<TermBucket>) -> crate::Result<()> {
+        let spill = self
+            .spill
+            .as_ref()
+            .expect("the buckets of the aggregation may not be spilled");
+        for (key, _) in &buckets {
+            match key {
+                _ if Some(key) == self.missing.as_ref() => {}
+                IntermediateKey::Str(_) | IntermediateKey::IpAddr(_) => self.has_text_keys = true,
+                IntermediateKey::Bool(_)
+                | IntermediateKey::F64(_)
+                | IntermediateKey::I64(_)
+                | IntermediateKey::U64(_) => self.has_number_keys = true,
+            }
+        }
+        let run = spill.write_run(buckets)?;
+        self.runs.push(Arc::new(run));
+        Ok(())
+    }
+
+    /// Converts the keys of the spilled buckets with `convert_key`, sorting the runs again.
+    pub(crate) fn convert_keys(
+        &mut self,
+        convert_key: impl Fn(IntermediateKey) -> IntermediateKey,
+    ) -> crate::Result<()> {
+        if self.runs.is_empty() {
+            return Ok(());
+        }
+        let memory_budget = self
+            .spill
+            .as_ref()
+            .map_or(0, AggregationSpill::memory_budget);
+        let runs = std::mem::take(&mut self.runs);
+        self.has_number_keys = false;
+        self.has_text_keys = false;
+        let mut chunk: FxHashMap<IntermediateKey, IntermediateTermBucketEntry> =
+            FxHashMap::default();
+        for bucket in SpilledBucketsMerge::new(runs, FxHashMap::default())? {
+            let (key, entry) = bucket?;
+            match chunk.entry(convert_key(key)) {
+                std::collections::hash_map::Entry::Occupied(mut existing_entry) => {
+                    existing_entry.get_mut().merge_fruits(entry)?
+                }
+                std::collections::hash_map::Entry::Vacant(vacant_entry) => {
+                    vacant_entry.insert(entry);
+                }
+            }
+            if terms_entries_memory(&chunk) > memory_budget {
+                self.spill_buckets(std::mem::take(&mut chunk).into_iter().collect())?;
+            }
+        }
+        if !chunk.is_empty() {
+            self.spill_buckets(chunk.into_iter().collect())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the buckets of the runs and of `entries`, by key, the buckets with the same key
+    /// being merged.
+    pub(crate) fn merge(
+        self,
+        entries: FxHashMap<IntermediateKey, IntermediateTermBucketEntry>,
+    ) -> crate::Result<SpilledBucketsMerge> {
+        SpilledBucketsMerge::new(self.runs, entries)
+    }
+}
+
+impl PartialEq for SpilledTermBuckets {
+    fn eq(&self, other: &Self) -> bool {
+        self.runs.len() == other.runs.len()
+            && self
+                .runs
+                .iter()
+                .zip(&other.runs)
+                .all(|(left, right)| left.path == right.path)
+    }
+}
+
+impl fmt::Debug for SpilledTermBuckets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpilledTermBuckets")
+            .field("num_runs", &self.runs.len())
+            .finish()
+    }
+}
+
+enum BucketSource {
+    Run(SpilledRunReader),
+    Memory(std::vec::IntoIter<TermBucket>),
+}
+
+impl BucketSource {
+    fn next_bucket(&mut self) -> crate::Result<Option<TermBucket>> {
+        match self {
+            BucketSource::Run(reader) => reader.next_bucket(),
+            BucketSource::Memory(buckets) => Ok(buckets.next()),
+        }
+    }
+}
+
+struct HeapEntry {
+    bucket: TermBucket,
+    source_ord: usize,
+}
+
+impl Ord for HeapEntry {
+    // Reversed, so that the binary heap pops the smallest key first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_intermediate_keys(&other.bucket.0, &self.bucket.0)
+            .then_with(|| other.source_ord.cmp(&self.source_ord))
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+/// K-way merge of sorted runs of buckets.
+pub(crate) struct SpilledBucketsMerge {
+    sources: Vec<BucketSource>,
+    heap: BinaryHeap<HeapEntry>,
+}
+
+impl SpilledBucketsMerge {
+    fn new(
+        runs: Vec<Arc<SpilledRun>>,
+        entries: FxHashMap<IntermediateKey, IntermediateTermBucketEntry>,
+    ) -> crate::Result<Self> {
+        let mut entries: Vec<TermBucket> = entries.into_iter().collect();
+        entries.sort_unstable_by(|left, right| cmp_intermediate_keys(&left.0, &right.0));
+        let mut sources = vec![BucketSource::Memory(entries.into_iter())];
+        for run in &runs {
+            sources.push(BucketSource::Run(run.open()?));
+        }
+        let mut merge = SpilledBucketsMerge {
+            sources,
+            heap: BinaryHeap::new(),
+        };
+        for source_ord in 0..merge.sources.len() {
+            merge.advance(source_ord)?;
+        }
+        Ok(merge)
+    }
+
+    fn advance(&mut self, source_ord: usize) -> crate::Result<()> {
+        if let Some(bucket) = self.sources[source_ord].next_bucket()? {
+            self.heap.push(HeapEntry { bucket, source_ord });
+        }
+        Ok(())
+    }
+
+    fn next_bucket(&mut self) -> crate::Result<Option<TermBucket>> {
+        let Some(HeapEntry {
+            bucket: (key, mut entry),
+            source_ord,
+        }) = self.heap.pop()
+        else {
+            return Ok(None);
+        };
+        self.advance(source_ord)?;
+        while self
+            .heap
+            .peek()
+            .is_some_and(|head| cmp_intermediate_keys(&head.bucket.0, &key) == Ordering::Equal)
+        {
+            let head = self.heap.pop().unwrap();
+            entry.merge_fruits(head.bucket.1)?;
+            self.advance(head.source_ord)?;
+        }
+        Ok(Some((key, entry)))
+    }
+}
+
+impl Iterator for SpilledBucketsMerge {
+    type Item = crate::Result<TermBucket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_bucket().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::AggregationSpill;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::{AggregationCollector, AggregationLimitsGuard};
+    use crate::directory::RamDirectory;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{CancellationToken, Index, IndexWriter, TantivyError};
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let term = schema_builder.add_text_field("term", STRING | FAST);
+        let num = schema_builder.add_u64_field("num", FAST);
+        let score = schema_builder.add_f64_field("score", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..3u64 {
+            for i in 0..400u64 {
+                let val = (segment * 131 + i * 7) % 450;
+                index_writer.add_document(doc!(
+                    term => format!("term{val:03}"),
+                    num => val % 97,
+                    score => (i % 13) as f64,
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn terms_aggs(allow_spill: bool) -> Aggregations {
+        let terms = |field: &str, extra: Value| {
+            let mut terms = json!({ "field": field, "allow_spill": allow_spill });
+            if !allow_spill {
+                // Exact reference results, without cut off on the segments.
+                terms["segment_size"] = json!(100_000);
+            }
+            terms
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            terms
+        };
+        serde_json::from_value(json!({
+            "by_count": {
+                "terms": terms("term", json!({ "size": 15 })),
+                "aggs": { "avg_score": { "avg": { "field": "score" } } }
+            },
+            "by_key": {
+                "terms": terms("term", json!({ "size": 7, "order": { "_key": "desc" } })),
+            },
+            "by_sub_agg": {
+                "terms": terms("term", json!({ "size": 5, "order": { "max_score": "asc" } })),
+                "aggs": { "max_score": { "max": { "field": "score" } } }
+            },
+            "nums": {
+                "terms": terms("num", json!({ "size": 10, "min_doc_count": 13 })),
+                "aggs": { "terms": { "terms": { "field": "term", "size": 2 } } }
+            },
+        }))
+        .unwrap()
+    }
+
+    fn aggregate(index: &Index, collector: &AggregationCollector) -> crate::Result<Value> {
+        let searcher = index.reader()?.searcher();
+        let agg_res = searcher.search(&AllQuery, collector)?;
+        Ok(serde_json::to_value(agg_res).unwrap())
+    }
+
+    #[test]
+    fn test_spilled_terms_equal_in_memory_terms() -> crate::Result<()> {
+        let index = create_index()?;
+        let expected = aggregate(
+            &index,
+            &AggregationCollector::from_aggs(terms_aggs(false), Default::default()),
+        )?;
+        assert_eq!(
+            expected["by_count"]["buckets"].as_array().unwrap().len(),
+            15
+        );
+        assert_eq!(expected["by_count"]["doc_count_error_upper_bound"], 0);
+
+        // Without a spill, or with a large budget, the buckets stay in memory.
+        let without_spill = aggregate(
+            &index,
+            &AggregationCollector::from_aggs(terms_aggs(true), Default::default()),
+        )?;
+        assert_eq!(without_spill, expected);
+        let directory = RamDirectory::create();
+        let spill = AggregationSpill::new(Box::new(directory.clone()), 100_000_000);
+        let collector = AggregationCollector::from_aggs(terms_aggs(true), Default::default())
+            .with_spill(spill.clone());
+        assert_eq!(aggregate(&index, &collector)?, expected);
+        assert_eq!(spill.num_spilled_runs(), 0);
+
+        // With a tiny budget, every block of buckets is spilled.
+        let spill = AggregationSpill::new(Box::new(directory.clone()), 1);
+        let collector = AggregationCollector::from_aggs(terms_aggs(true), Default::default())
+            .with_spill(spill.clone());
+        assert_eq!(aggregate(&index, &collector)?, expected);
+        assert!(spill.num_spilled_runs() > 12, "{spill:?}");
+        // All of the 450 terms of the 3 segments are spilled at least once.
+        assert!(spill.num_spilled_buckets() >= 450);
+        assert_eq!(directory.total_mem_usage(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spilled_terms_with_fuzzy_key_merge() -> crate::Result<()> {
+        let index = create_index()?;
+        let aggs: Aggregations = serde_json::from_value(json!({
+            "terms": {
+                "terms": {
+                    "field": "term",
+                    "allow_spill": true,
+                    "fuzzy_key_merge": { "distance": 1 }
+                }
+            }
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_aggs(aggs, Default::default());
+        let err = aggregate(&index, &collector).unwrap_err();
+        assert!(matches!(err, TantivyError::AggregationError(_)), "{err:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_spill_files_deleted_on_error_and_cancellation() -> crate::Result<()> {
+        let index = create_index()?;
+        let directory = RamDirectory::create();
+
+        // The bucket limit is checked once the spilled runs are merged.
+        let spill = AggregationSpill::new(Box::new(directory.clone()), 1);
+        let collector = AggregationCollector::from_aggs(
+            terms_aggs(true),
+            AggregationLimitsGuard::new(None, Some(10)),
+        )
+        .with_spill(spill.clone());
+        let err = aggregate(&index, &collector).unwrap_err();
+        assert!(matches!(err, TantivyError::AggregationError(_)), "{err:?}");
+        assert!(spill.num_spilled_runs() > 0);
+        assert_eq!(directory.total_mem_usage(), 0);
+
+        // The cancellation is checked when the results of the segments are merged.
+        let spill = AggregationSpill::new(Box::new(directory.clone()), 1);
+        let token = CancellationToken::new();
+        token.cancel();
+        let collector = AggregationCollector::from_aggs(terms_aggs(true), Default::default())
+            .with_spill(spill.clone())
+            .with_cancellation(token);
+        let err = aggregate(&index, &collector).unwrap_err();
+        assert!(matches!(err, TantivyError::Cancelled), "{err:?}");
+        assert!(spill.num_spilled_runs() > 0);
+        assert_eq!(directory.total_mem_usage(), 0);
+        Ok(())
+    }
+}
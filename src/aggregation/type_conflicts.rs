@@ -0,0 +1,182 @@
+//! Detection of the fields whose values have types an aggregation cannot combine.
+//!
+//! The documents can write values of different types to the same path of a JSON field. In a
+//! segment, the numeric values of a path are stored in a single column, and the values of each
+//! other type (text, bool, date, ip, bytes) in a column of their own. The coercion rules are:
+//! - The aggregations reading numbers (the metrics, `range`, `histogram`, ...) read the numeric
+//!   values as `f64`, whatever their type in each segment. This is not a conflict.
+//! - These aggregations read a single column per segment: the values of the other columns of the
+//!   path are ignored, which is a conflict. E.g. the text values of a path aggregated by `avg`.
+//! - The `terms` aggregation reads the columns of all types. Its keys mixing numbers and text is a
+//!   conflict, in which case all the keys are returned as text.
+//!
+//! The conflicts are reported in the warnings of the result, or fail the aggregation if it, or
+//! one of its parents, sets [`strict_types`](super::agg_req::Aggregation::strict_types).
+
+use std::collections::HashSet;
+
+use super::agg_req::{AggregationVariants, Aggregations};
+use super::agg_req_with_accessor::single_column_types;
+use super::bucket::TermsAggregation;
+use super::intermediate_agg_result::{
+    IntermediateAggregationResult, IntermediateAggregationResults, IntermediateBucketResult,
+    IntermediateTermBucketResult,
+};
+use super::AggregationError;
+use crate::SegmentReader;
+
+/// Returns the warnings of the type conflicts of the fields of `aggs` in the segment.
+pub(crate) fn check_segment_column_types(
+    aggs: &Aggregations,
+    reader: &SegmentReader,
+) -> crate::Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    check_segment_column_types_rec(aggs, reader, "", false, &mut warnings)?;
+    Ok(warnings)
+}
+
+fn check_segment_column_types_rec(
+    aggs: &Aggregations,
+    reader: &SegmentReader,
+    parent_path: &str,
+    strict: bool,
+    warnings: &mut Vec<String>,
+) -> crate::Result<()> {
+    for (name, agg) in aggs {
+        let path = agg_path(parent_path, name);
+        let strict = strict || agg.strict_types;
+        // The fields of a `field_union` are checked when reading their columns.
+        let read_column_types = single_column_types(&agg.agg);
+        if let Some(read_column_types) = read_column_types.filter(|_| agg.field_union.is_none()) {
+            let field = agg.agg.get_fast_field_names()[0];
+            let column_types: Vec<_> = reader
+                .try_fast_fields()?
+                .dynamic_column_handles(field)?
+                .iter()
+                .map(|handle| handle.column_type())
+                .collect();
+            let read_column_type = column_types
+                .iter()
+                .find(|column_type| read_column_types.contains(column_type));
+            for column_type in &column_types {
+                if Some(column_type) != read_column_type {
+                    let conflict = AggregationError::TypeConflict {
+                        path: path.clone(),
+                        field: field.to_string(),
+                        reason: format!("values of type {column_type:?} are ignored"),
+                    };
+                    report_conflict(conflict, strict, warnings)?;
+                }
+            }
+        }
+        check_segment_column_types_rec(&agg.sub_aggregation, reader, &path, strict, warnings)?;
+    }
+    Ok(())
+}
+
+/// Returns the keys of the `terms` aggregations of `results` as text, if they mix numbers and
+/// text, and adds the warnings of these conflicts to `warnings`.
+///
+/// The keys of an aggregation are converted in all the buckets of its parents, as soon as they
+/// mix numbers and text in one of them.
+pub(crate) fn resolve_terms_key_conflicts(
+    results: &mut IntermediateAggregationResults,
+    req: &Aggregations,
+    warnings: &mut Vec<String>,
+) -> crate::Result<()> {
+    let mut conflicting_paths: HashSet<String> = HashSet::new();
+    visit_terms_results(
+        results,
+        req,
+        "",
+        false,
+        &mut |path, terms_req, strict, buckets| {
+            if conflicting_paths.contains(path)
+                || !buckets.has_mixed_keys(terms_req.missing.as_ref())
+            {
+                return Ok(());
+            }
+            let reason = if strict {
+                "keys mix numbers and text"
+            } else {
+                "keys mix numbers and text, they are all returned as text"
+            };
+            let conflict = AggregationError::TypeConflict {
+                path: path.to_string(),
+                field: terms_req.field.clone(),
+                reason: reason.to_string(),
+            };
+            report_conflict(conflict, strict, warnings)?;
+            conflicting_paths.insert(path.to_string());
+            Ok(())
+        },
+    )?;
+    if conflicting_paths.is_empty() {
+        return Ok(());
+    }
+    visit_terms_results(results, req, "", false, &mut |path, _, _, buckets| {
+        if conflicting_paths.contains(path) {
+            buckets.convert_keys_to_text()?;
+        }
+        Ok(())
+    })
+}
+
+/// Calls `visitor` with the path, the request, the strictness and the buckets of each result of
+/// a `terms` aggregation of the tree.
+fn visit_terms_results<F>(
+    results: &mut IntermediateAggregationResults,
+    req: &Aggregations,
+    parent_path: &str,
+    strict: bool,
+    visitor: &mut F,
+) -> crate::Result<()>
+where
+    F: FnMut(&str, &TermsAggregation, bool, &mut IntermediateTermBucketResult) -> crate::Result<()>,
+{
+    for (name, agg_res) in results.aggs_res.iter_mut() {
+        // Custom results are not part of the request.
+        let Some(agg) = req.get(name) else {
+            continue;
+        };
+        let IntermediateAggregationResult::Bucket(bucket_res) = agg_res else {
+            continue;
+        };
+        let path = agg_path(parent_path, name);
+        let strict = strict || agg.strict_types;
+        if let (
+            AggregationVariants::Terms(terms_req),
+            IntermediateBucketResult::Terms { buckets },
+        ) = (&agg.agg, &mut *bucket_res)
+        {
+            visitor(&path, terms_req, strict, buckets)?;
+        }
+        if agg.sub_aggregation.is_empty() {
+            continue;
+        }
+        for sub_results in bucket_res.sub_aggregations_mut() {
+            visit_terms_results(sub_results, &agg.sub_aggregation, &path, strict, visitor)?;
+        }
+    }
+    Ok(())
+}
+
+fn report_conflict(
+    conflict: AggregationError,
+    strict: bool,
+    warnings: &mut Vec<String>,
+) -> crate::Result<()> {
+    if strict {
+        return Err(conflict.into());
+    }
+    warnings.push(conflict.to_string());
+    Ok(())
+}
+
+fn agg_path(parent_path: &str, name: &str) -> String {
+    if parent_path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{parent_path}.{name}")
+    }
+}
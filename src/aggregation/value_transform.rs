@@ -0,0 +1,449 @@
+//! Transforms applied to fast field values before they are aggregated.
+//!
+//! Metric aggregations and the `histogram` and `range` bucket aggregations accept an optional
+//! `value_transform` parameter. The transform is applied to every value read from the column
+//! (including the `missing` value), before the value is bucketed or collected.
+//! Bucket aggregations therefore assign documents to buckets based on the transformed value.
+//!
+//! Custom transforms can be registered on a [`ValueTransformRegistry`], which is passed to the
+//! collector via e.g.
+//! [`AggregationCollector::with_value_transforms`](super::AggregationCollector::with_value_transforms).
+//!
+//! # JSON Format
+//! ```json
+//! {
+//!     "prices": {
+//!         "histogram": {
+//!             "field": "price_in_cents",
+//!             "interval": 10,
+//!             "value_transform": { "scale": 0.01 }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::AggregationError;
+use crate::TantivyError;
+
+/// A function transforming a single value.
+pub(crate) type ValueTransformFn = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+
+/// The transform applied to the values of an aggregation.
+///
+/// Examples in JSON format:
+/// - `{ "scale": 2.0 }`
+/// - `{ "offset": -10.0 }`
+/// - `"log10"`
+/// - `{ "clamp": { "min": 0.0, "max": 100.0 } }`
+/// - `{ "time_truncate": "hour" }`
+/// - `{ "custom": "my_transform" }`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueTransform {
+    /// Multiplies the value by the given factor.
+    Scale(f64),
+    /// Adds the given value.
+    Offset(f64),
+    /// Computes the base 10 logarithm of the value.
+    ///
+    /// Like [`f64::log10`], zero is mapped to negative infinity and negative values to NaN.
+    Log10,
+    /// Restricts the value to the `[min, max]` interval.
+    Clamp {
+        /// The lower bound.
+        min: f64,
+        /// The upper bound.
+        max: f64,
+    },
+    /// Rounds a timestamp down to the start of its time unit.
+    ///
+    /// The value is interpreted as nanoseconds since the UNIX epoch, which is how date fields are
+    /// stored.
+    TimeTruncate(TimeUnit),
+    /// A transform registered in the [`ValueTransformRegistry`] under this name.
+    Custom(String),
+}
+
+/// Time unit used by [`ValueTransform::TimeTruncate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeUnit {
+    /// One second.
+    Second,
+    /// One minute.
+    Minute,
+    /// One hour.
+    Hour,
+    /// One day.
+    Day,
+}
+
+impl TimeUnit {
+    fn as_nanos(self) -> f64 {
+        const NANOS_PER_SECOND: f64 = 1_000_000_000.0;
+        match self {
+            TimeUnit::Second => NANOS_PER_SECOND,
+            TimeUnit::Minute => 60.0 * NANOS_PER_SECOND,
+            TimeUnit::Hour => 3_600.0 * NANOS_PER_SECOND,
+            TimeUnit::Day => 86_400.0 * NANOS_PER_SECOND,
+        }
+    }
+}
+
+impl ValueTransform {
+    /// Resolves the transform into a function, looking up custom transforms in `registry`.
+    pub(crate) fn resolve(
+        &self,
+        registry: &ValueTransformRegistry,
+    ) -> crate::Result<ValueTransformFn> {
+        let transform: ValueTransformFn = match *self {
+            ValueTransform::Scale(factor) => Arc::new(move |val| val * factor),
+            ValueTransform::Offset(offset) => Arc::new(move |val| val + offset),
+            ValueTransform::Log10 => Arc::new(f64::log10),
+            ValueTransform::Clamp { min, max } => {
+                // `f64::clamp` panics on invalid bounds, so we validate them upfront.
+                if min.is_nan() || max.is_nan() || min > max {
+                    return Err(invalid_request(format!(
+                        "Invalid clamp value_transform: min {min} must be smaller or equal to max \
+                         {max}"
+                    )));
+                }
+                Arc::new(move |val: f64| val.clamp(min, max))
+            }
+            ValueTransform::TimeTruncate(unit) => {
+                let unit_nanos = unit.as_nanos();
+                Arc::new(move |val: f64| (val / unit_nanos).floor() * unit_nanos)
+            }
+            ValueTransform::Custom(ref name) => registry
+                .get(name)
+                .cloned()
+                .ok_or_else(|| invalid_request(format!("Unknown value_transform {name:?}")))?,
+        };
+        Ok(transform)
+    }
+}
+
+fn invalid_request(msg: String) -> TantivyError {
+    TantivyError::AggregationError(AggregationError::InvalidRequest(msg))
+}
+
+/// Registry of named transforms, which can be referenced in an aggregation request via
+/// [`ValueTransform::Custom`].
+#[derive(Clone, Default)]
+pub struct ValueTransformRegistry {
+    transforms: HashMap<String, ValueTransformFn>,
+}
+
+impl fmt::Debug for ValueTransformRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.transforms.keys()).finish()
+    }
+}
+
+impl ValueTransformRegistry {
+    /// Registers a transform under the given name.
+    ///
+    /// If a transform was already registered with this name, it is replaced.
+    pub fn register(&mut self, name: &str, transform: Box<dyn Fn(f64) -> f64 + Send + Sync>) {
+        self.transforms
+            .insert(name.to_string(), Arc::from(transform));
+    }
+
+    fn get(&self, name: &str) -> Option<&ValueTransformFn> {
+        self.transforms.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::bucket::tests::get_test_index_from_docs;
+    use crate::aggregation::tests::{exec_request, get_test_index_from_values_and_terms};
+    use crate::aggregation::AggregationCollector;
+    use crate::query::AllQuery;
+    use crate::Index;
+
+    fn apply(transform: &ValueTransform, val: f64) -> f64 {
+        transform
+            .resolve(&ValueTransformRegistry::default())
+            .unwrap()(val)
+    }
+
+    #[test]
+    fn test_value_transform_deserialization() {
+        let transforms: Vec<ValueTransform> = serde_json::from_str(
+            r#"[
+                { "scale": 2.0 },
+                { "offset": -1.5 },
+                "log10",
+                { "clamp": { "min": 0.0, "max": 10.0 } },
+                { "time_truncate": "hour" },
+                { "custom": "my_transform" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            transforms,
+            vec![
+                ValueTransform::Scale(2.0),
+                ValueTransform::Offset(-1.5),
+                ValueTransform::Log10,
+                ValueTransform::Clamp {
+                    min: 0.0,
+                    max: 10.0
+                },
+                ValueTransform::TimeTruncate(TimeUnit::Hour),
+                ValueTransform::Custom("my_transform".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builtin_value_transforms() {
+        assert_eq!(apply(&ValueTransform::Scale(2.5), 4.0), 10.0);
+        assert_eq!(apply(&ValueTransform::Offset(-1.5), 4.0), 2.5);
+        assert_eq!(apply(&ValueTransform::Log10, 1000.0), 3.0);
+        let clamp = ValueTransform::Clamp {
+            min: 0.0,
+            max: 10.0,
+        };
+        assert_eq!(apply(&clamp, -3.0), 0.0);
+        assert_eq!(apply(&clamp, 3.0), 3.0);
+        assert_eq!(apply(&clamp, 13.0), 10.0);
+        let hour_nanos = 3_600.0 * 1_000_000_000.0;
+        let truncate = ValueTransform::TimeTruncate(TimeUnit::Hour);
+        assert_eq!(apply(&truncate, 2.5 * hour_nanos), 2.0 * hour_nanos);
+        assert_eq!(apply(&truncate, -0.5 * hour_nanos), -hour_nanos);
+        assert_eq!(
+            apply(
+                &ValueTransform::TimeTruncate(TimeUnit::Day),
+                2.5 * hour_nanos
+            ),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_invalid_value_transforms() {
+        let registry = ValueTransformRegistry::default();
+        let err = ValueTransform::Custom("unknown".to_string())
+            .resolve(&registry)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            TantivyError::AggregationError(AggregationError::InvalidRequest(msg))
+                if msg == r#"Unknown value_transform "unknown""#
+        ));
+        let clamp = ValueTransform::Clamp { min: 1.0, max: 0.0 };
+        assert!(clamp.resolve(&registry).is_err());
+    }
+
+    #[test]
+    fn test_custom_value_transform() {
+        let mut registry = ValueTransformRegistry::default();
+        registry.register("square", Box::new(|val| val * val));
+        let transform = ValueTransform::Custom("square".to_string())
+            .resolve(&registry)
+            .unwrap();
+        assert_eq!(transform(3.0), 9.0);
+    }
+
+    fn get_test_index() -> crate::Result<Index> {
+        let values_and_terms = vec![
+            vec![(1.0, "a".to_string()), (10.0, "a".to_string())],
+            vec![(100.0, "b".to_string()), (1000.0, "a".to_string())],
+        ];
+        get_test_index_from_values_and_terms(false, &values_and_terms)
+    }
+
+    fn exec_request_with_registry(
+        agg_req: Aggregations,
+        index: &Index,
+        registry: ValueTransformRegistry,
+    ) -> crate::Result<Value> {
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default())
+            .with_value_transforms(registry);
+        let searcher = index.reader()?.searcher();
+        let agg_res = searcher.search(&AllQuery, &collector)?;
+        Ok(serde_json::to_value(agg_res)?)
+    }
+
+    #[test]
+    fn test_value_transform_scale_and_offset_metrics() -> crate::Result<()> {
+        let index = get_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "avg_scaled": { "avg": { "field": "score", "value_transform": { "scale": 0.5 } } },
+            "sum_offset": { "sum": { "field": "score_f64", "value_transform": { "offset": 1.0 } } },
+            "min_offset": { "min": { "field": "score_i64", "value_transform": { "offset": -1.0 } } },
+            "stats_scaled": { "stats": { "field": "score", "value_transform": { "scale": 2.0 } } },
+            "extended_stats_scaled": {
+                "extended_stats": { "field": "score", "value_transform": { "scale": 2.0 } }
+            },
+            "percentiles_scaled": {
+                "percentiles": {
+                    "field": "score",
+                    "percents": [100.0],
+                    "value_transform": { "scale": 2.0 }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(res["avg_scaled"]["value"], 1111.0 / 4.0 * 0.5);
+        assert_eq!(res["sum_offset"]["value"], 1115.0);
+        assert_eq!(res["min_offset"]["value"], 0.0);
+        assert_eq!(res["stats_scaled"]["max"], 2000.0);
+        assert_eq!(res["stats_scaled"]["sum"], 2222.0);
+        assert_eq!(res["extended_stats_scaled"]["sum"], 2222.0);
+        let max_percentile = res["percentiles_scaled"]["values"]["100.0"]
+            .as_f64()
+            .unwrap();
+        assert!((max_percentile - 2000.0).abs() < 20.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_transform_log10_and_clamp() -> crate::Result<()> {
+        let index = get_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "stats_log10": { "stats": { "field": "score", "value_transform": "log10" } },
+            "max_clamped": {
+                "max": { "field": "score", "value_transform": { "clamp": { "min": 0.0, "max": 50.0 } } }
+            },
+            "histogram_log10": {
+                "histogram": { "field": "score", "interval": 2.0, "value_transform": "log10" }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(res["stats_log10"]["min"], 0.0);
+        assert_eq!(res["stats_log10"]["max"], 3.0);
+        assert_eq!(res["stats_log10"]["sum"], 6.0);
+        assert_eq!(res["max_clamped"]["value"], 50.0);
+        assert_eq!(
+            res["histogram_log10"]["buckets"],
+            json!([{ "key": 0.0, "doc_count": 2 }, { "key": 2.0, "doc_count": 2 }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_transform_bucket_aggregations() -> crate::Result<()> {
+        let index = get_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "range_scaled": {
+                "range": {
+                    "field": "score",
+                    "ranges": [ { "to": 10.0 }, { "from": 10.0 } ],
+                    "value_transform": { "scale": 0.1 }
+                },
+                "aggs": { "sum": { "sum": { "field": "score" } } }
+            },
+            "histogram_offset": {
+                "histogram": {
+                    "field": "score_f64",
+                    "interval": 100.0,
+                    "min_doc_count": 1,
+                    "value_transform": { "offset": 95.0 }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        // Documents are bucketed on the transformed value, sub aggregations see the raw values.
+        assert_eq!(res["range_scaled"]["buckets"][0]["key"], "*-10");
+        assert_eq!(res["range_scaled"]["buckets"][0]["doc_count"], 2);
+        assert_eq!(res["range_scaled"]["buckets"][0]["sum"]["value"], 11.0);
+        assert_eq!(res["range_scaled"]["buckets"][1]["doc_count"], 2);
+        assert_eq!(res["range_scaled"]["buckets"][1]["sum"]["value"], 1100.0);
+        // 1, 10, 100 and 1000 are shifted to 96, 105, 195 and 1095.
+        assert_eq!(
+            res["histogram_offset"]["buckets"],
+            json!([
+                { "key": 0.0, "doc_count": 1 },
+                { "key": 100.0, "doc_count": 2 },
+                { "key": 1000.0, "doc_count": 1 }
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_transform_time_truncate() -> crate::Result<()> {
+        let docs = vec![
+            vec![r#"{ "date": "2015-01-01T12:10:30Z" }"#],
+            vec![r#"{ "date": "2015-01-01T23:00:00Z" }"#],
+            vec![r#"{ "date": "2015-01-02T05:00:00Z" }"#],
+        ];
+        let index = get_test_index_from_docs(false, &docs)?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "per_day": {
+                "histogram": {
+                    "field": "date",
+                    "interval": 3_600_000.0,
+                    "min_doc_count": 1,
+                    "value_transform": { "time_truncate": "day" }
+                }
+            }
+        }))
+        .unwrap();
+        let res = exec_request(agg_req, &index)?;
+        assert_eq!(
+            res["per_day"]["buckets"][0]["key_as_string"],
+            "2015-01-01T00:00:00Z"
+        );
+        assert_eq!(res["per_day"]["buckets"][0]["doc_count"], 2);
+        assert_eq!(
+            res["per_day"]["buckets"][1]["key_as_string"],
+            "2015-01-02T00:00:00Z"
+        );
+        assert_eq!(res["per_day"]["buckets"][1]["doc_count"], 1);
+        assert_eq!(res["per_day"]["buckets"].as_array().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_transform_custom_nested_in_terms() -> crate::Result<()> {
+        let index = get_test_index()?;
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "terms": {
+                "terms": { "field": "string_id" },
+                "aggs": {
+                    "sum_squares": {
+                        "sum": { "field": "score", "value_transform": { "custom": "square" } }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let err = exec_request(agg_req.clone(), &index).unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::AggregationError(AggregationError::InvalidRequest(msg))
+                if msg == r#"Unknown value_transform "square""#
+        ));
+
+        let mut registry = ValueTransformRegistry::default();
+        registry.register("square", Box::new(|val| val * val));
+        let res = exec_request_with_registry(agg_req, &index, registry)?;
+        assert_eq!(res["terms"]["buckets"][0]["key"], "a");
+        assert_eq!(
+            res["terms"]["buckets"][0]["sum_squares"]["value"],
+            1.0 + 100.0 + 1_000_000.0
+        );
+        assert_eq!(res["terms"]["buckets"][1]["key"], "b");
+        assert_eq!(res["terms"]["buckets"][1]["sum_squares"]["value"], 10_000.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use std::io;
+
+use common::BitSet;
+
+use super::{Collector, SegmentCollector};
+use crate::index::SegmentId;
+use crate::{
+    DocAddress, DocId, Score, Searcher, SearcherGeneration, SegmentOrdinal, SegmentReader,
+    TantivyError,
+};
+
+/// Collector returning the documents matching the query as one bitset per segment, for set
+/// operations between the results of several queries, e.g. to join them with other data systems.
+///
+/// The fruit is keyed by the [`SearcherGeneration`] it was collected from, so that the results
+/// of queries executed on different versions of the index are not combined by mistake: see
+/// [`DocBitSets::union()`] and [`DocBitSets::intersect()`]. The bitsets of the segments can be
+/// serialized with [`SegmentBitSet::serialize()`].
+///
+/// Blocks of contiguous documents, e.g. matched by an
+/// [`AllQuery`](crate::query::AllQuery) or a range over a sorted fast field, are inserted a
+/// bitset bucket at a time.
+///
+/// The fruits are merged with the searcher (see [`Collector::merge_fruits_with_searcher`]), so
+/// the collector has to be passed to a [`Searcher`].
+///
+/// ```rust
+/// use tantivy::collector::BitSetCollector;
+/// use tantivy::query::TermQuery;
+/// use tantivy::schema::{IndexRecordOption, Schema, STRING};
+/// use tantivy::{doc, Index, IndexWriter, Term};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let color = schema_builder.add_text_field("color", STRING);
+/// let size = schema_builder.add_text_field("size", STRING);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(15_000_000)?;
+/// index_writer.add_document(doc!(color => "red", size => "small"))?;
+/// index_writer.add_document(doc!(color => "red", size => "large"))?;
+/// index_writer.add_document(doc!(color => "blue", size => "large"))?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let term_query = |field, text| {
+///     TermQuery::new(Term::from_field_text(field, text), IndexRecordOption::Basic)
+/// };
+/// let red = searcher.search(&term_query(color, "red"), &BitSetCollector)?;
+/// let large = searcher.search(&term_query(size, "large"), &BitSetCollector)?;
+/// assert_eq!(red.intersect(&large)?.len(), 1);
+/// assert_eq!(red.union(&large)?.len(), 3);
+/// # Ok(())
+/// # }
+/// ```
+pub struct BitSetCollector;
+
+/// Documents matching a query in a segment, as collected by [`BitSetCollector`].
+#[derive(Clone)]
+pub struct SegmentBitSet {
+    segment_ord: SegmentOrdinal,
+    segment_id: SegmentId,
+    docs: BitSet,
+}
+
+impl SegmentBitSet {
+    /// Returns the ordinal of the segment in the searcher.
+    pub fn segment_ord(&self) -> SegmentOrdinal {
+        self.segment_ord
+    }
+
+    /// Returns the id of the segment.
+    pub fn segment_id(&self) -> SegmentId {
+        self.segment_id
+    }
+
+    /// Returns the bitset of the matching documents. Its `max_value` is the `max_doc` of the
+    /// segment.
+    pub fn docs(&self) -> &BitSet {
+        &self.docs
+    }
+
+    /// Returns the number of matching documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Returns true if no document of the segment matches.
+    pub fn is_empty(&self) -> bool {
+        self.docs.len() == 0
+    }
+
+    /// Returns true if `doc` matches.
+    pub fn contains(&self, doc: DocId) -> bool {
+        self.docs.contains(doc)
+    }
+
+    /// Serializes the bitset of the matching documents, in the format read by
+    /// [`ReadOnlyBitSet::open()`](common::ReadOnlyBitSet::open).
+    pub fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.docs.serialize(writer)
+    }
+}
+
+/// Documents matching a query, as collected by [`BitSetCollector`], with one bitset per segment
+/// of the searcher.
+#[derive(Clone)]
+pub struct DocBitSets {
+    generation: SearcherGeneration,
+    segments: Vec<SegmentBitSet>,
+}
+
+impl DocBitSets {
+    /// Returns the generation of the searcher the documents were collected with.
+    pub fn generation(&self) -> &SearcherGeneration {
+        &self.generation
+    }
+
+    /// Returns the bitsets of the segments, ordered by segment ordinal.
+    pub fn segments(&self) -> &[SegmentBitSet] {
+        &self.segments
+    }
+
+    /// Returns the number of matching documents.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(SegmentBitSet::len).sum()
+    }
+
+    /// Returns true if no document matches.
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(SegmentBitSet::is_empty)
+    }
+
+    /// Returns true if the document at `doc_address` matches.
+    pub fn contains(&self, doc_address: DocAddress) -> bool {
+        self.segments
+            .get(doc_address.segment_ord as usize)
+            .is_some_and(|segment| segment.contains(doc_address.doc_id))
+    }
+
+    /// Iterates over the addresses of the matching documents, ordered by segment and doc id.
+    pub fn doc_addresses(&self) -> impl Iterator<Item = DocAddress> + '_ {
+        self.segments.iter().flat_map(|segment| {
+            segment
+                .docs
+                .iter()
+                .map(move |doc_id| DocAddress::new(segment.segment_ord, doc_id))
+        })
+    }
+
+    /// Returns the documents matching either `self` or `other`.
+    ///
+    /// Returns [`TantivyError::InvalidArgument`] if the documents were collected from different
+    /// searcher generations.
+    pub fn union(&self, other: &DocBitSets) -> crate::Result<DocBitSets> {
+        self.combine(other, BitSet::union_update)
+    }
+
+    /// Returns the documents matching both `self` and `other`.
+    ///
+    /// Returns [`TantivyError::InvalidArgument`] if the documents were collected from different
+    /// searcher generations.
+    pub fn intersect(&self, other: &DocBitSets) -> crate::Result<DocBitSets> {
+        self.combine(other, BitSet::intersect_update_with_bitset)
+    }
+
+    fn combine(
+        &self,
+        other: &DocBitSets,
+        combine_segment: impl Fn(&mut BitSet, &BitSet),
+    ) -> crate::Result<DocBitSets> {
+        if self.generation != other.generation {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Cannot combine documents collected from different searcher generations ({} and \
+                 {})",
+                self.generation.generation_id(),
+                other.generation.generation_id()
+            )));
+        }
+        let segments = self
+            .segments
+            .iter()
+            .zip(&other.segments)
+            .map(|(segment, other_segment)| {
+                let mut segment = segment.clone();
+                combine_segment(&mut segment.docs, &other_segment.docs);
+                segment
+            })
+            .collect();
+        Ok(DocBitSets {
+            generation: self.generation.clone(),
+            segments,
+        })
+    }
+}
+
+impl Collector for BitSetCollector {
+    type Fruit = DocBitSets;
+    type Child = BitSetSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        Ok(BitSetSegmentCollector {
+            segment: SegmentBitSet {
+                segment_ord: segment_local_id,
+                segment_id: segment.segment_id(),
+                docs: BitSet::with_max_value(segment.max_doc()),
+            },
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, _segment_fruits: Vec<SegmentBitSet>) -> crate::Result<Self::Fruit> {
+        Err(TantivyError::InternalError(
+            "The bitsets are keyed by the generation of the searcher, see \
+             `Collector::merge_fruits_with_searcher`"
+                .to_string(),
+        ))
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        mut segment_fruits: Vec<SegmentBitSet>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        segment_fruits.sort_by_key(SegmentBitSet::segment_ord);
+        Ok(DocBitSets {
+            generation: searcher.generation().clone(),
+            segments: segment_fruits,
+        })
+    }
+}
+
+/// Segment collector of [`BitSetCollector`].
+pub struct BitSetSegmentCollector {
+    segment: SegmentBitSet,
+}
+
+impl SegmentCollector for BitSetSegmentCollector {
+    type Fruit = SegmentBitSet;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        self.segment.docs.insert(doc);
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        // Runs of contiguous documents are inserted as ranges.
+        let mut remaining_docs = docs;
+        while let Some((&start, tail)) = remaining_docs.split_first() {
+            let run_len = tail
+                .iter()
+                .zip(start + 1..)
+                .take_while(|(&doc, expected_doc)| doc == *expected_doc)
+                .count();
+            self.segment
+                .docs
+                .insert_range(start..start + 1 + run_len as DocId);
+            remaining_docs = &tail[run_len..];
+        }
+    }
+
+    fn harvest(self) -> SegmentBitSet {
+        self.segment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use common::{OwnedBytes, ReadOnlyBitSet};
+
+    use super::BitSetCollector;
+    use crate::collector::DocSetCollector;
+    use crate::query::{AllQuery, BooleanQuery, Occur, Query, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, STRING};
+    use crate::{DocAddress, Index, IndexWriter, TantivyError, Term};
+
+    #[test]
+    fn test_bitset_collector_set_operations() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let color = schema_builder.add_text_field("color", STRING);
+        let size = schema_builder.add_text_field("size", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..3 {
+            for i in 0..200 {
+                let doc_color = if (i + segment) % 3 == 0 {
+                    "red"
+                } else {
+                    "blue"
+                };
+                let doc_size = if i % 5 < 2 { "small" } else { "large" };
+                index_writer.add_document(doc!(color => doc_color, size => doc_size))?;
+            }
+            index_writer.commit()?;
+        }
+        index_writer.delete_term(Term::from_field_text(size, "small"))?;
+        index_writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let term_query = |field, text| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, text),
+                IndexRecordOption::Basic,
+            ))
+        };
+        let red = searcher.search(&term_query(color, "red"), &BitSetCollector)?;
+        let large = searcher.search(&term_query(size, "large"), &BitSetCollector)?;
+        let union = red.union(&large)?;
+        let intersection = red.intersect(&large)?;
+
+        let expected_union = searcher.search(
+            &BooleanQuery::new(vec![
+                (Occur::Should, term_query(color, "red")),
+                (Occur::Should, term_query(size, "large")),
+            ]),
+            &DocSetCollector,
+        )?;
+        let expected_intersection = searcher.search(
+            &BooleanQuery::new(vec![
+                (Occur::Must, term_query(color, "red")),
+                (Occur::Must, term_query(size, "large")),
+            ]),
+            &DocSetCollector,
+        )?;
+        assert_eq!(
+            union.doc_addresses().collect::<HashSet<DocAddress>>(),
+            expected_union
+        );
+        assert_eq!(union.len(), expected_union.len());
+        assert_eq!(
+            intersection
+                .doc_addresses()
+                .collect::<HashSet<DocAddress>>(),
+            expected_intersection
+        );
+        assert_eq!(intersection.len(), expected_intersection.len());
+
+        // Contiguous documents are collected as ranges, the deleted ones being skipped.
+        let all = searcher.search(&AllQuery, &BitSetCollector)?;
+        assert_eq!(all.len() as u64, searcher.num_docs());
+        assert_eq!(all.union(&large)?.len(), all.len());
+        let segment = &all.segments()[0];
+        let mut serialized = Vec::new();
+        segment.serialize(&mut serialized)?;
+        let read_only = ReadOnlyBitSet::open(OwnedBytes::new(serialized));
+        assert!(read_only.iter().eq(segment.docs().iter()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bitset_collector_generation_mismatch() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let color = schema_builder.add_text_field("color", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(color => "red"))?;
+        index_writer.commit()?;
+        let reader = index.reader()?;
+        let before_reload = reader.searcher().search(&AllQuery, &BitSetCollector)?;
+
+        index_writer.add_document(doc!(color => "blue"))?;
+        index_writer.commit()?;
+        reader.reload()?;
+        let after_reload = reader.searcher().search(&AllQuery, &BitSetCollector)?;
+        assert!(matches!(
+            before_reload.union(&after_reload),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            after_reload.intersect(&before_reload),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert_eq!(after_reload.intersect(&after_reload)?.len(), 2);
+        Ok(())
+    }
+}
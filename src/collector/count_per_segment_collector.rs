@@ -0,0 +1,251 @@
+use super::Collector;
+use crate::collector::SegmentCollector;
+use crate::index::SegmentId;
+use crate::query::Weight;
+use crate::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// Number of documents matching a query in a segment, as computed by
+/// [`CountPerSegmentCollector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentCountEntry {
+    /// Id of the segment.
+    pub segment_id: SegmentId,
+    /// Number of alive documents matching the query.
+    pub alive_matches: u32,
+    /// Number of deleted documents matching the query.
+    ///
+    /// These documents would be removed by a merge of the segment.
+    pub deleted_matches: u32,
+    /// Number of documents in the segment, including the deleted ones.
+    pub max_doc: u32,
+}
+
+/// `CountPerSegmentCollector` counts the documents matching the query in each segment,
+/// separating the alive documents from the deleted ones.
+///
+/// Segments without deletes are counted with [`Weight::count`], which may avoid visiting the
+/// matching documents. In segments with deletes, each matching document is checked against the
+/// alive bitset.
+///
+/// The deleted documents can only be counted when `CountPerSegmentCollector` is the collector
+/// passed to the [`Searcher`](crate::Searcher). When nested in another collector, e.g. in a
+/// tuple, the deleted documents are filtered out before reaching it, and `deleted_matches` is
+/// always 0.
+///
+/// ```rust
+/// use tantivy::collector::CountPerSegmentCollector;
+/// use tantivy::query::TermQuery;
+/// use tantivy::schema::{IndexRecordOption, Schema, STRING};
+/// use tantivy::{doc, Index, IndexWriter, Term};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let color = schema_builder.add_text_field("color", STRING);
+/// let index = Index::create_in_ram(schema_builder.build());
+///
+/// let mut index_writer: IndexWriter = index.writer(15_000_000)?;
+/// index_writer.add_document(doc!(color => "red"))?;
+/// index_writer.add_document(doc!(color => "blue"))?;
+/// index_writer.add_document(doc!(color => "red"))?;
+/// index_writer.commit()?;
+/// index_writer.delete_term(Term::from_field_text(color, "blue"))?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let query = TermQuery::new(
+///     Term::from_field_text(color, "blue"),
+///     IndexRecordOption::Basic,
+/// );
+/// let counts = searcher.search(&query, &CountPerSegmentCollector)?;
+/// assert_eq!(counts.len(), 1);
+/// assert_eq!(counts[0].alive_matches, 0);
+/// assert_eq!(counts[0].deleted_matches, 1);
+/// assert_eq!(counts[0].max_doc, 3);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CountPerSegmentCollector;
+
+impl Collector for CountPerSegmentCollector {
+    type Fruit = Vec<SegmentCountEntry>;
+
+    type Child = SegmentCountPerSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<SegmentCountPerSegmentCollector> {
+        Ok(SegmentCountPerSegmentCollector {
+            entry: SegmentCountEntry {
+                segment_id: reader.segment_id(),
+                alive_matches: 0,
+                deleted_matches: 0,
+                max_doc: reader.max_doc(),
+            },
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_entries: Vec<SegmentCountEntry>,
+    ) -> crate::Result<Vec<SegmentCountEntry>> {
+        Ok(segment_entries)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<SegmentCountEntry> {
+        let mut segment_collector = self.for_segment(segment_ord, reader)?;
+        let entry = &mut segment_collector.entry;
+        match reader.alive_bitset() {
+            None => {
+                entry.alive_matches = weight.count(reader)?;
+            }
+            Some(alive_bitset) => {
+                weight.for_each_no_score(reader, &mut |docs| {
+                    let num_alive = docs
+                        .iter()
+                        .filter(|&&doc| alive_bitset.is_alive(doc))
+                        .count() as u32;
+                    entry.alive_matches += num_alive;
+                    entry.deleted_matches += docs.len() as u32 - num_alive;
+                })?;
+            }
+        }
+        Ok(segment_collector.harvest())
+    }
+}
+
+/// Segment collector of [`CountPerSegmentCollector`].
+pub struct SegmentCountPerSegmentCollector {
+    entry: SegmentCountEntry,
+}
+
+impl SegmentCollector for SegmentCountPerSegmentCollector {
+    type Fruit = SegmentCountEntry;
+
+    fn collect(&mut self, _: DocId, _: Score) {
+        self.entry.alive_matches += 1;
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        self.entry.alive_matches += docs.len() as u32;
+    }
+
+    fn harvest(self) -> SegmentCountEntry {
+        self.entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountPerSegmentCollector, SegmentCountEntry};
+    use crate::collector::Count;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, Query, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, Searcher, Term};
+
+    /// Counts the matching docs by checking every document of every segment.
+    fn brute_force_counts(
+        searcher: &Searcher,
+        is_match: impl Fn(&str) -> bool,
+    ) -> Vec<SegmentCountEntry> {
+        searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| {
+                let colors = reader.fast_fields().str("color").unwrap().unwrap();
+                let mut entry = SegmentCountEntry {
+                    segment_id: reader.segment_id(),
+                    alive_matches: 0,
+                    deleted_matches: 0,
+                    max_doc: reader.max_doc(),
+                };
+                let mut color = String::new();
+                for doc in 0..reader.max_doc() {
+                    let ord = colors.term_ords(doc).next().unwrap();
+                    color.clear();
+                    colors.ord_to_str(ord, &mut color).unwrap();
+                    if !is_match(&color) {
+                        continue;
+                    }
+                    if reader.is_deleted(doc) {
+                        entry.deleted_matches += 1;
+                    } else {
+                        entry.alive_matches += 1;
+                    }
+                }
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_count_per_segment_with_deletes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let color = schema_builder.add_text_field("color", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let colors = ["red", "green", "blue"];
+        for segment in 0..3 {
+            for i in 0..(50 + segment * 20) {
+                index_writer.add_document(doc!(color => colors[(i * 7 + segment) % 3]))?;
+            }
+            index_writer.commit()?;
+        }
+        // Deletes within some of the segments only.
+        index_writer.delete_term(Term::from_field_text(color, "green"))?;
+        for i in 0..10 {
+            index_writer.add_document(doc!(color => colors[i % 3]))?;
+        }
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 4);
+        assert!(searcher
+            .segment_readers()
+            .iter()
+            .any(|reader| reader.has_deletes()));
+        assert!(searcher
+            .segment_readers()
+            .iter()
+            .any(|reader| !reader.has_deletes()));
+
+        let check = |query: &dyn Query, is_match: &dyn Fn(&str) -> bool| -> crate::Result<()> {
+            let counts = searcher.search(query, &CountPerSegmentCollector)?;
+            assert_eq!(counts, brute_force_counts(&searcher, is_match));
+            let alive_matches: u32 = counts.iter().map(|entry| entry.alive_matches).sum();
+            assert_eq!(alive_matches as usize, searcher.search(query, &Count)?);
+            Ok(())
+        };
+        for color_name in colors {
+            let query = TermQuery::new(
+                Term::from_field_text(color, color_name),
+                IndexRecordOption::Basic,
+            );
+            check(&query, &|color| color == color_name)?;
+        }
+        check(&AllQuery, &|_| true)?;
+
+        let green_query = TermQuery::new(
+            Term::from_field_text(color, "green"),
+            IndexRecordOption::Basic,
+        );
+        let counts = searcher.search(&green_query, &CountPerSegmentCollector)?;
+        assert!(counts
+            .iter()
+            .all(|entry| entry.alive_matches == if entry.max_doc == 10 { 3 } else { 0 }));
+        assert!(counts.iter().any(|entry| entry.deleted_matches > 0));
+        Ok(())
+    }
+}
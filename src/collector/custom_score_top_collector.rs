@@ -1,14 +1,24 @@
-use crate::collector::top_collector::{TopCollector, TopSegmentCollector};
-use crate::collector::{Collector, SegmentCollector};
-use crate::{DocAddress, DocId, Score, SegmentReader};
+use std::ops::Range;
 
-pub(crate) struct CustomScoreTopCollector<TCustomScorer, TScore = Score> {
+use crate::collector::rescore_top_collector::RescoreTopCollector;
+use crate::collector::top_collector::{SearchAfterKey, TopCollector, TopSegmentCollector};
+use crate::collector::{filter_alive_docs, Collector, SegmentCollector};
+use crate::fastfield::AliveBitSet;
+use crate::query::Weight;
+use crate::{
+    DocAddress, DocId, Score, ScratchVec, SearchScratch, SegmentReader, COLLECT_BLOCK_BUFFER_LEN,
+};
+
+/// Collector keeping track of the top `K` documents sorted by a custom score.
+///
+/// It is built via [`TopDocs::custom_score`](crate::collector::TopDocs::custom_score).
+pub struct CustomScoreTopCollector<TCustomScorer, TScore = Score> {
     custom_scorer: TCustomScorer,
     collector: TopCollector<TScore>,
 }
 
 impl<TCustomScorer, TScore> CustomScoreTopCollector<TCustomScorer, TScore>
-where TScore: Clone + PartialOrd
+where TScore: Clone + PartialOrd + 'static
 {
     pub(crate) fn new(
         custom_scorer: TCustomScorer,
@@ -19,6 +29,49 @@ where TScore: Clone + PartialOrd
             collector,
         }
     }
+
+    /// Only collect the documents sorting after the given key.
+    ///
+    /// See [`TopDocs::with_search_after`](crate::collector::TopDocs::with_search_after).
+    #[must_use]
+    pub fn with_search_after(
+        self,
+        key: SearchAfterKey<TScore>,
+    ) -> CustomScoreTopCollector<TCustomScorer, TScore> {
+        CustomScoreTopCollector {
+            custom_scorer: self.custom_scorer,
+            collector: self.collector.with_search_after(key),
+        }
+    }
+
+    /// Re-scores the top `window` documents by the custom score with the expensive `rescorer`,
+    /// see [`TopDocs::with_rescoring`](crate::collector::TopDocs::with_rescoring).
+    ///
+    /// Returns an error if `window` is smaller than the limit plus the offset, or if the
+    /// collector only collects the documents after a search-after key.
+    pub fn with_rescoring<TRescore, TRescorer>(
+        self,
+        rescorer: TRescorer,
+        window: usize,
+    ) -> crate::Result<
+        RescoreTopCollector<CustomScoreTopCollector<TCustomScorer, TScore>, TRescorer, TRescore>,
+    >
+    where
+        TRescorer: CustomScorer<TRescore>,
+    {
+        if self.collector.search_after.is_some() {
+            return Err(crate::TantivyError::InvalidArgument(
+                "Rescoring does not support search-after keys.".to_string(),
+            ));
+        }
+        RescoreTopCollector::new(
+            CustomScoreTopCollector::new(self.custom_scorer, TopCollector::with_limit(window)),
+            rescorer,
+            window,
+            self.collector.limit,
+            self.collector.offset,
+        )
+    }
 }
 
 /// A custom segment scorer makes it possible to define any kind of score
@@ -28,6 +81,36 @@ where TScore: Clone + PartialOrd
 pub trait CustomSegmentScorer<TScore>: 'static {
     /// Computes the score of a specific `doc`.
     fn score(&mut self, doc: DocId) -> TScore;
+
+    /// Computes the scores of a block of sorted `docs`, pushing them into `scores` in the same
+    /// order. `scores` is empty when the method is called.
+    ///
+    /// The default implementation calls [`CustomSegmentScorer::score`] for each document.
+    fn score_block(&mut self, docs: &[DocId], scores: &mut Vec<TScore>) {
+        scores.extend(docs.iter().map(|&doc| self.score(doc)));
+    }
+
+    /// Returns the scorer as a [`BlockBoundedScorer`], if it can bound the scores of a range of
+    /// docs.
+    ///
+    /// The default implementation returns `None`.
+    fn as_block_bounded(&self) -> Option<&dyn BlockBoundedScorer<TScore>> {
+        None
+    }
+}
+
+/// Extension of a [`CustomSegmentScorer`] that can bound the scores of a range of docs.
+///
+/// This is typically implemented by scorers that are a monotonic function of a single fast field,
+/// using the bounds of the values of the column over the range
+/// (see [`Column::value_bounds`](columnar::Column::value_bounds)).
+///
+/// The collector skips scoring the ranges of docs whose best score can't enter the top docs. The
+/// scorer opts in by returning itself in [`CustomSegmentScorer::as_block_bounded`].
+pub trait BlockBoundedScorer<TScore>: CustomSegmentScorer<TScore> {
+    /// Returns a lower and an upper bound for the scores of the docs in `doc_range`, or `None` if
+    /// they can't be bounded.
+    fn block_bounds(&self, doc_range: Range<DocId>) -> Option<(TScore, TScore)>;
 }
 
 /// `CustomScorer` makes it possible to define any kind of score.
@@ -41,6 +124,19 @@ pub trait CustomScorer<TScore>: Sync {
     /// Builds a child scorer for a specific segment. The child scorer is associated with
     /// a specific segment.
     fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child>;
+
+    /// Builds a child scorer for a specific segment, or returns `None` if the segment should not
+    /// contribute any hit, e.g. because it has no column for a fast field read by the scorer (see
+    /// [`MissingColumnPolicy::SkipSegment`](crate::collector::MissingColumnPolicy::SkipSegment)).
+    ///
+    /// This is the method called by the collector. The default implementation calls
+    /// [`CustomScorer::segment_scorer`].
+    fn segment_scorer_opt(
+        &self,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Option<Self::Child>> {
+        self.segment_scorer(segment_reader).map(Some)
+    }
 }
 
 impl<TCustomScorer, TScore> Collector for CustomScoreTopCollector<TCustomScorer, TScore>
@@ -58,10 +154,12 @@ where
         segment_reader: &SegmentReader,
     ) -> crate::Result<Self::Child> {
         let segment_collector = self.collector.for_segment(segment_local_id, segment_reader);
-        let segment_scorer = self.custom_scorer.segment_scorer(segment_reader)?;
+        let segment_scorer = self.custom_scorer.segment_scorer_opt(segment_reader)?;
         Ok(CustomScoreTopSegmentCollector {
             segment_collector,
             segment_scorer,
+            scores: SearchScratch::get(COLLECT_BLOCK_BUFFER_LEN),
+            num_skipped_docs: 0,
         })
     }
 
@@ -69,6 +167,34 @@ where
         false
     }
 
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Fruit> {
+        let mut segment_collector = self.for_segment(segment_ord, reader)?;
+        if segment_collector.segment_scorer.is_none() {
+            return Ok(segment_collector.harvest());
+        }
+        if weight.matches_all_docs() {
+            // The docs are enumerated without advancing the weight, so that whole blocks of docs
+            // can be skipped.
+            segment_collector.collect_all_docs(reader.max_doc(), reader.alive_bitset());
+        } else if let Some(alive_bitset) = reader.alive_bitset() {
+            let mut alive_docs = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+            weight.for_each_no_score(reader, &mut |docs| {
+                let num_alive_docs = filter_alive_docs(docs, alive_bitset, &mut alive_docs);
+                segment_collector.collect_block(&alive_docs[..num_alive_docs]);
+            })?;
+        } else {
+            weight.for_each_no_score(reader, &mut |docs| {
+                segment_collector.collect_block(docs);
+            })?;
+        }
+        Ok(segment_collector.harvest())
+    }
+
     fn merge_fruits(&self, segment_fruits: Vec<Self::Fruit>) -> crate::Result<Self::Fruit> {
         self.collector.merge_fruits(segment_fruits)
     }
@@ -80,7 +206,73 @@ where
     T: CustomSegmentScorer<TScore>,
 {
     segment_collector: TopSegmentCollector<TScore>,
-    segment_scorer: T,
+    /// `None` if the segment is skipped.
+    segment_scorer: Option<T>,
+    /// Buffer receiving the scores of a block of docs, taken from the scratch pool.
+    scores: ScratchVec<TScore>,
+    /// Number of docs skipped because their scores could not enter the top docs.
+    num_skipped_docs: u32,
+}
+
+impl<T, TScore> CustomScoreTopSegmentCollector<T, TScore>
+where
+    TScore: 'static + PartialOrd + Clone + Send + Sync,
+    T: 'static + CustomSegmentScorer<TScore>,
+{
+    /// Returns true if none of the docs of `doc_range` can enter the top docs, according to the
+    /// bounds of their scores.
+    fn can_skip(&self, doc_range: Range<DocId>) -> bool {
+        let Some(block_bounded_scorer) = self
+            .segment_scorer
+            .as_ref()
+            .and_then(|segment_scorer| segment_scorer.as_block_bounded())
+        else {
+            return false;
+        };
+        let Some(threshold) = self.segment_collector.threshold() else {
+            return false;
+        };
+        matches!(
+            block_bounded_scorer.block_bounds(doc_range),
+            Some((_, max_score)) if max_score < *threshold
+        )
+    }
+
+    fn score_and_collect_block(&mut self, docs: &[DocId]) {
+        let Some(segment_scorer) = self.segment_scorer.as_mut() else {
+            return;
+        };
+        self.scores.clear();
+        segment_scorer.score_block(docs, &mut self.scores);
+        for (&doc, score) in docs.iter().zip(self.scores.drain(..)) {
+            self.segment_collector.collect(doc, score);
+        }
+    }
+
+    /// Collects all of the alive docs of a segment with `max_doc` docs.
+    pub(crate) fn collect_all_docs(&mut self, max_doc: DocId, alive_bitset: Option<&AliveBitSet>) {
+        let mut docs = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+        for block_start in (0..max_doc).step_by(COLLECT_BLOCK_BUFFER_LEN) {
+            let block_end = (block_start + COLLECT_BLOCK_BUFFER_LEN as u32).min(max_doc);
+            if self.can_skip(block_start..block_end) {
+                self.num_skipped_docs += block_end - block_start;
+                continue;
+            }
+            let mut num_docs = 0;
+            for doc in block_start..block_end {
+                docs[num_docs] = doc;
+                num_docs +=
+                    alive_bitset.is_none_or(|alive_bitset| alive_bitset.is_alive(doc)) as usize;
+            }
+            self.score_and_collect_block(&docs[..num_docs]);
+        }
+    }
+
+    /// Returns the number of docs skipped so far because their scores could not enter the top
+    /// docs. See [`BlockBoundedScorer`].
+    pub fn num_skipped_docs(&self) -> u32 {
+        self.num_skipped_docs
+    }
 }
 
 impl<T, TScore> SegmentCollector for CustomScoreTopSegmentCollector<T, TScore>
@@ -91,10 +283,24 @@ where
     type Fruit = Vec<(TScore, DocAddress)>;
 
     fn collect(&mut self, doc: DocId, _score: Score) {
-        let score = self.segment_scorer.score(doc);
+        let Some(segment_scorer) = self.segment_scorer.as_mut() else {
+            return;
+        };
+        let score = segment_scorer.score(doc);
         self.segment_collector.collect(doc, score);
     }
 
+    fn collect_block(&mut self, docs: &[DocId]) {
+        // The docs are sorted, so that the range of the block goes from its first to its last doc.
+        if let (Some(&first_doc), Some(&last_doc)) = (docs.first(), docs.last()) {
+            if self.can_skip(first_doc..last_doc + 1) {
+                self.num_skipped_docs += docs.len() as u32;
+                return;
+            }
+        }
+        self.score_and_collect_block(docs);
+    }
+
     fn harvest(self) -> Vec<(TScore, DocAddress)> {
         self.segment_collector.harvest()
     }
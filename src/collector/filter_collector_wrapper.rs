@@ -12,7 +12,7 @@ use std::marker::PhantomData;
 use columnar::{BytesColumn, Column, DynamicColumn, HasAssociatedColumnType};
 
 use crate::collector::{Collector, SegmentCollector};
-use crate::{DocId, Score, SegmentReader};
+use crate::{DocId, Score, Searcher, SegmentReader};
 
 /// The `FilterCollector` filters docs using a fast field value and a predicate.
 ///
@@ -109,7 +109,7 @@ where
         segment_local_id: u32,
         segment_reader: &SegmentReader,
     ) -> crate::Result<Self::Child> {
-        let column_opt = segment_reader.fast_fields().column_opt(&self.field)?;
+        let column_opt = segment_reader.try_fast_fields()?.column_opt(&self.field)?;
 
         let segment_collector = self
             .collector
@@ -133,6 +133,15 @@ where
     ) -> crate::Result<TCollector::Fruit> {
         self.collector.merge_fruits(segment_fruits)
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<TCollector::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<TCollector::Fruit> {
+        self.collector
+            .merge_fruits_with_searcher(segment_fruits, searcher)
+    }
 }
 
 pub struct FilterSegmentCollector<TSegmentCollector, TPredicate, TPredicateValue> {
@@ -263,7 +272,7 @@ where
         segment_local_id: u32,
         segment_reader: &SegmentReader,
     ) -> crate::Result<Self::Child> {
-        let column_opt = segment_reader.fast_fields().bytes(&self.field)?;
+        let column_opt = segment_reader.try_fast_fields()?.bytes(&self.field)?;
 
         let segment_collector = self
             .collector
@@ -287,6 +296,15 @@ where
     ) -> crate::Result<TCollector::Fruit> {
         self.collector.merge_fruits(segment_fruits)
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<TCollector::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<TCollector::Fruit> {
+        self.collector
+            .merge_fruits_with_searcher(segment_fruits, searcher)
+    }
 }
 
 pub struct BytesFilterSegmentCollector<TSegmentCollector, TPredicate>
@@ -0,0 +1,644 @@
+use columnar::{Column, ColumnType};
+
+use crate::aggregation::f64_from_fastfield_u64;
+use crate::collector::{CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker};
+use crate::schema::Type;
+use crate::{DocId, Score, SegmentReader, TantivyError};
+
+/// Column types that can be read by a [`LinearScore`] component.
+const NUMERICAL_COLUMN_TYPES: [ColumnType; 5] = [
+    ColumnType::U64,
+    ColumnType::I64,
+    ColumnType::F64,
+    ColumnType::DateTime,
+    ColumnType::Bool,
+];
+
+/// Transform applied to the value of a field by a [`LinearScore`] component.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// The value is used as is.
+    Identity,
+    /// `log10(1 + value)`. Negative values are treated as zero, so that the result is never
+    /// negative.
+    Log10,
+    /// Square root of the value. Negative values are treated as zero.
+    Sqrt,
+}
+
+impl Transform {
+    #[inline]
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Transform::Identity => value,
+            Transform::Log10 => (1.0 + value.max(0.0)).log10(),
+            Transform::Sqrt => value.max(0.0).sqrt(),
+        }
+    }
+}
+
+/// Decay function applied to the value of a field by a [`LinearScore`] component.
+///
+/// The decay is `1.0` when the value is equal to `origin`, and `0.5` when the value is at a
+/// distance of `scale` from `origin`, in either direction. `origin` and `scale` are expressed in
+/// the unit of the field. For date fields, this is nanoseconds since the UNIX epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decay {
+    /// `0.5 ^ (distance / scale)`
+    Exponential {
+        /// Value at which the decay is `1.0`.
+        origin: f64,
+        /// Distance from `origin` at which the decay is `0.5`.
+        scale: f64,
+    },
+    /// `0.5 ^ ((distance / scale) ^ 2)`
+    Gauss {
+        /// Value at which the decay is `1.0`.
+        origin: f64,
+        /// Distance from `origin` at which the decay is `0.5`.
+        scale: f64,
+    },
+    /// `max(0, 1 - 0.5 * distance / scale)`
+    Linear {
+        /// Value at which the decay is `1.0`.
+        origin: f64,
+        /// Distance from `origin` at which the decay is `0.5`.
+        scale: f64,
+    },
+}
+
+impl Decay {
+    #[inline]
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Decay::Exponential { origin, scale } => 0.5f64.powf((value - origin).abs() / scale),
+            Decay::Gauss { origin, scale } => {
+                let normalized_distance = (value - origin) / scale;
+                0.5f64.powf(normalized_distance * normalized_distance)
+            }
+            Decay::Linear { origin, scale } => {
+                (1.0 - 0.5 * (value - origin).abs() / scale).max(0.0)
+            }
+        }
+    }
+
+    fn scale(self) -> f64 {
+        match self {
+            Decay::Exponential { scale, .. }
+            | Decay::Gauss { scale, .. }
+            | Decay::Linear { scale, .. } => scale,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ComponentFn {
+    Transform(Transform),
+    Decay(Decay),
+}
+
+impl ComponentFn {
+    /// Adds `weight * f(value)` to the scores.
+    ///
+    /// The function is resolved outside of the loop, so that each loop can be vectorized.
+    fn accumulate(self, weight: f64, values: &[f64], scores: &mut [f64]) {
+        match self {
+            ComponentFn::Transform(transform) => {
+                for (score, &value) in scores.iter_mut().zip(values) {
+                    *score += weight * transform.apply(value);
+                }
+            }
+            ComponentFn::Decay(decay) => {
+                for (score, &value) in scores.iter_mut().zip(values) {
+                    *score += weight * decay.apply(value);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            ComponentFn::Transform(transform) => transform.apply(value),
+            ComponentFn::Decay(decay) => decay.apply(value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FieldComponent {
+    field: String,
+    weight: f64,
+    func: ComponentFn,
+    missing: f64,
+}
+
+/// Declarative custom score, computing a weighted sum of components.
+///
+/// Each component reads the first value of a numerical fast field, applies a [`Transform`] or a
+/// [`Decay`] to it, and multiplies the result by its weight. Optionally, the query score (e.g.
+/// BM25) is added with its own weight.
+///
+/// ```rust
+/// use tantivy::collector::{Decay, LinearScore, TopDocs, Transform};
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST};
+/// use tantivy::{doc, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let popularity = schema_builder.add_u64_field("popularity", FAST);
+/// let timestamp = schema_builder.add_i64_field("timestamp", FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// index_writer.add_document(doc!(popularity => 99u64, timestamp => 0i64))?;
+/// index_writer.add_document(doc!(popularity => 9u64, timestamp => 1_000i64))?;
+/// index_writer.commit()?;
+///
+/// let linear_score = LinearScore::new()
+///     .field("popularity", 0.3, Transform::Log10)
+///     .field_decay(
+///         "timestamp",
+///         0.6,
+///         Decay::Exponential {
+///             origin: 1_000.0,
+///             scale: 100.0,
+///         },
+///     );
+/// let searcher = index.reader()?.searcher();
+/// let top_docs = searcher.search(&AllQuery, &TopDocs::with_limit(2).custom_score(linear_score))?;
+/// // 0.3 * log10(1 + 9) + 0.6 * 1.0
+/// assert!((top_docs[0].0 - 0.9).abs() < 1e-9);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// When used with [`TopDocs::custom_score`](crate::collector::TopDocs::custom_score), the
+/// scores are computed one block of documents at a time. The [`LinearScore::bm25`] component
+/// requires the query score, so it is only available with
+/// [`TopDocs::tweak_score`](crate::collector::TopDocs::tweak_score).
+///
+/// The fields are validated when the score is computed on a segment: an unknown field, a field
+/// that is not fast, or a field that is not numerical results in an error.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinearScore {
+    bm25_weight: Option<f64>,
+    components: Vec<FieldComponent>,
+}
+
+impl LinearScore {
+    /// Creates a score without any component, i.e. always equal to zero.
+    pub fn new() -> LinearScore {
+        LinearScore::default()
+    }
+
+    /// Adds the query score, multiplied by `weight`.
+    pub fn bm25(mut self, weight: f64) -> LinearScore {
+        self.bm25_weight = Some(weight);
+        self
+    }
+
+    /// Adds `weight * transform(value)`, `value` being the first value of the fast field `field`.
+    pub fn field(self, field: impl ToString, weight: f64, transform: Transform) -> LinearScore {
+        self.push_component(field.to_string(), weight, ComponentFn::Transform(transform))
+    }
+
+    /// Adds `weight * decay(value)`, `value` being the first value of the fast field `field`.
+    pub fn field_decay(self, field: impl ToString, weight: f64, decay: Decay) -> LinearScore {
+        self.push_component(field.to_string(), weight, ComponentFn::Decay(decay))
+    }
+
+    /// Sets the value used for documents without a value, for the last added field component.
+    /// It defaults to `0.0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no field component was added yet.
+    pub fn missing(mut self, value: f64) -> LinearScore {
+        self.components
+            .last_mut()
+            .expect("`missing` must be called after adding a field component")
+            .missing = value;
+        self
+    }
+
+    fn push_component(mut self, field: String, weight: f64, func: ComponentFn) -> LinearScore {
+        self.components.push(FieldComponent {
+            field,
+            weight,
+            func,
+            missing: 0.0,
+        });
+        self
+    }
+
+    fn linear_segment_scorer(
+        &self,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<LinearSegmentScorer> {
+        let components = self
+            .components
+            .iter()
+            .map(|component| open_component(component, segment_reader))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(LinearSegmentScorer {
+            bm25_weight: self.bm25_weight.unwrap_or(0.0),
+            components,
+            first_vals: Vec::new(),
+            values: Vec::new(),
+        })
+    }
+}
+
+fn component_error(component: &FieldComponent, msg: &str) -> TantivyError {
+    TantivyError::InvalidArgument(format!(
+        "Linear score component `{}`: {msg}",
+        component.field
+    ))
+}
+
+fn open_component(
+    component: &FieldComponent,
+    segment_reader: &SegmentReader,
+) -> crate::Result<SegmentComponent> {
+    let schema = segment_reader.schema();
+    let (field, _path) = schema
+        .find_field(&component.field)
+        .ok_or_else(|| component_error(component, "unknown field"))?;
+    let field_entry = schema.get_field_entry(field);
+    if !field_entry.is_fast() {
+        return Err(component_error(component, "field is not a fast field"));
+    }
+    if !matches!(
+        field_entry.field_type().value_type(),
+        Type::U64 | Type::I64 | Type::F64 | Type::Date | Type::Bool | Type::Json
+    ) {
+        return Err(component_error(component, "field is not numerical"));
+    }
+    if let ComponentFn::Decay(decay) = component.func {
+        let scale = decay.scale();
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(component_error(
+                component,
+                &format!("decay scale must be positive, got {scale}"),
+            ));
+        }
+    }
+    let column = segment_reader
+        .try_fast_fields()?
+        .u64_lenient_for_type(Some(&NUMERICAL_COLUMN_TYPES), &component.field)?;
+    Ok(SegmentComponent {
+        column,
+        weight: component.weight,
+        func: component.func,
+        missing: component.missing,
+    })
+}
+
+struct SegmentComponent {
+    /// `None` if no document of the segment has a value.
+    column: Option<(Column<u64>, ColumnType)>,
+    weight: f64,
+    func: ComponentFn,
+    missing: f64,
+}
+
+impl SegmentComponent {
+    #[inline]
+    fn value(&self, doc: DocId) -> f64 {
+        self.column
+            .as_ref()
+            .and_then(|(column, column_type)| {
+                column
+                    .first(doc)
+                    .map(|val| f64_from_fastfield_u64(val, column_type))
+            })
+            .unwrap_or(self.missing)
+    }
+}
+
+/// Segment scorer of a [`LinearScore`].
+pub struct LinearSegmentScorer {
+    bm25_weight: f64,
+    components: Vec<SegmentComponent>,
+    /// Scratch buffers used to read a column for a block of docs.
+    first_vals: Vec<Option<u64>>,
+    values: Vec<f64>,
+}
+
+impl LinearSegmentScorer {
+    fn fields_score(&self, doc: DocId) -> f64 {
+        self.components
+            .iter()
+            .map(|component| component.weight * component.func.apply(component.value(doc)))
+            .sum()
+    }
+}
+
+impl CustomSegmentScorer<f64> for LinearSegmentScorer {
+    fn score(&mut self, doc: DocId) -> f64 {
+        self.fields_score(doc)
+    }
+
+    fn score_block(&mut self, docs: &[DocId], scores: &mut Vec<f64>) {
+        scores.resize(docs.len(), 0.0);
+        self.first_vals.resize(docs.len(), None);
+        self.values.resize(docs.len(), 0.0);
+        for component in &self.components {
+            match &component.column {
+                Some((column, column_type)) => {
+                    column.first_vals(docs, &mut self.first_vals);
+                    for (value, first_val) in self.values.iter_mut().zip(&self.first_vals) {
+                        *value = first_val
+                            .map(|val| f64_from_fastfield_u64(val, column_type))
+                            .unwrap_or(component.missing);
+                    }
+                }
+                None => self.values.fill(component.missing),
+            }
+            component
+                .func
+                .accumulate(component.weight, &self.values, scores);
+        }
+    }
+}
+
+impl ScoreSegmentTweaker<f64> for LinearSegmentScorer {
+    fn score(&mut self, doc: DocId, score: Score) -> f64 {
+        self.bm25_weight * score as f64 + self.fields_score(doc)
+    }
+}
+
+impl CustomScorer<f64> for LinearScore {
+    type Child = LinearSegmentScorer;
+
+    fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        if self.bm25_weight.is_some() {
+            return Err(TantivyError::InvalidArgument(
+                "Linear score component `bm25`: the query score is not available with \
+                 `TopDocs::custom_score`, use `TopDocs::tweak_score` instead"
+                    .to_string(),
+            ));
+        }
+        self.linear_segment_scorer(segment_reader)
+    }
+}
+
+impl ScoreTweaker<f64> for LinearScore {
+    type Child = LinearSegmentScorer;
+
+    fn segment_tweaker(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        self.linear_segment_scorer(segment_reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Decay, LinearScore, Transform};
+    use crate::collector::TopDocs;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STRING, TEXT};
+    use crate::{DateTime, Index, IndexWriter, TantivyDocument, TantivyError, Term};
+
+    const DAY_NANOS: f64 = 86_400.0 * 1_000_000_000.0;
+
+    /// Values of a test document, `None` meaning missing.
+    struct TestDoc {
+        popularity: Option<u64>,
+        rating: Option<f64>,
+        day: Option<i64>,
+    }
+
+    fn test_docs() -> Vec<TestDoc> {
+        (0..300u64)
+            .map(|i| TestDoc {
+                popularity: (i % 5 != 0).then_some(i * 37 % 1000),
+                rating: (i % 7 != 0).then_some((i % 11) as f64 - 3.5),
+                day: (i % 3 != 0).then_some((i * 13 % 60) as i64),
+            })
+            .collect()
+    }
+
+    fn test_index(docs: &[TestDoc]) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", FAST);
+        let text = schema_builder.add_text_field("text", TEXT);
+        let tag = schema_builder.add_text_field("tag", STRING | FAST);
+        let popularity = schema_builder.add_u64_field("popularity", FAST);
+        let rating = schema_builder.add_f64_field("rating", FAST | INDEXED);
+        let timestamp = schema_builder.add_date_field("timestamp", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for (i, test_doc) in docs.iter().enumerate() {
+            let mut doc = TantivyDocument::default();
+            doc.add_u64(id, i as u64);
+            doc.add_text(text, if i % 4 == 0 { "hello hello" } else { "hello" });
+            doc.add_text(tag, "tag");
+            if let Some(val) = test_doc.popularity {
+                doc.add_u64(popularity, val);
+            }
+            if let Some(val) = test_doc.rating {
+                doc.add_f64(rating, val);
+            }
+            if let Some(day) = test_doc.day {
+                doc.add_date(
+                    timestamp,
+                    DateTime::from_timestamp_nanos(day * DAY_NANOS as i64),
+                );
+            }
+            index_writer.add_document(doc)?;
+            // Every segment misses some of the values.
+            if i % 100 == 99 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn decay() -> Decay {
+        Decay::Exponential {
+            origin: 30.0 * DAY_NANOS,
+            scale: 7.0 * DAY_NANOS,
+        }
+    }
+
+    fn linear_score() -> LinearScore {
+        LinearScore::new()
+            .field("popularity", 0.3, Transform::Log10)
+            .missing(1.0)
+            .field("rating", 0.2, Transform::Identity)
+            .missing(-10.0)
+            .field_decay("timestamp", 0.6, decay())
+    }
+
+    /// Straightforward computation of the score of `linear_score()`.
+    fn reference_score(doc: &TestDoc) -> f64 {
+        let popularity = doc.popularity.map(|val| val as f64).unwrap_or(1.0);
+        let rating = doc.rating.unwrap_or(-10.0);
+        let timestamp = doc.day.map(|day| day as f64 * DAY_NANOS).unwrap_or(0.0);
+        let distance = (timestamp - 30.0 * DAY_NANOS).abs();
+        0.3 * (1.0 + popularity).log10()
+            + 0.2 * rating
+            + 0.6 * 0.5f64.powf(distance / (7.0 * DAY_NANOS))
+    }
+
+    /// Returns the score of each doc, by id.
+    fn scores_by_id(index: &Index, top_docs: &[(f64, crate::DocAddress)]) -> HashMap<u64, f64> {
+        let searcher = index.reader().unwrap().searcher();
+        top_docs
+            .iter()
+            .map(|(score, doc_address)| {
+                let id_column = searcher
+                    .segment_reader(doc_address.segment_ord)
+                    .fast_fields()
+                    .u64("id")
+                    .unwrap();
+                (id_column.first(doc_address.doc_id).unwrap(), *score)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_linear_score_custom_score() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = test_index(&docs)?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 3);
+
+        // `AllQuery` does not require scoring, so the docs are scored by block.
+        let top_docs = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(docs.len()).custom_score(linear_score()),
+        )?;
+        assert_eq!(top_docs.len(), docs.len());
+        assert!(top_docs.windows(2).all(|w| w[0].0 >= w[1].0));
+        for (id, score) in scores_by_id(&index, &top_docs) {
+            let expected = reference_score(&docs[id as usize]);
+            assert!(
+                (score - expected).abs() < 1e-9,
+                "doc {id}: {score} != {expected}"
+            );
+        }
+
+        // Docs pushed one at a time.
+        let tag = index.schema().get_field("tag").unwrap();
+        let query = TermQuery::new(Term::from_field_text(tag, "tag"), IndexRecordOption::Basic);
+        let top_docs_per_doc = searcher.search(
+            &query,
+            &(
+                TopDocs::with_limit(docs.len()).custom_score(linear_score()),
+                TopDocs::with_limit(1),
+            ),
+        )?;
+        assert_eq!(
+            scores_by_id(&index, &top_docs_per_doc.0),
+            scores_by_id(&index, &top_docs)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_linear_score_tweak_score_bm25() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = test_index(&docs)?;
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text").unwrap();
+        let query = TermQuery::new(
+            Term::from_field_text(text, "hello"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let bm25_scores: HashMap<u64, f64> = {
+            let top_docs = searcher.search(&query, &TopDocs::with_limit(docs.len()))?;
+            let top_docs: Vec<(f64, crate::DocAddress)> = top_docs
+                .into_iter()
+                .map(|(score, doc_address)| (score as f64, doc_address))
+                .collect();
+            scores_by_id(&index, &top_docs)
+        };
+        let top_docs = searcher.search(
+            &query,
+            &TopDocs::with_limit(docs.len()).tweak_score(linear_score().bm25(0.1)),
+        )?;
+        assert_eq!(top_docs.len(), docs.len());
+        for (id, score) in scores_by_id(&index, &top_docs) {
+            let expected = 0.1 * bm25_scores[&id] + reference_score(&docs[id as usize]);
+            assert!(
+                (score - expected).abs() < 1e-6,
+                "doc {id}: {score} != {expected}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_linear_score_decays() {
+        let origin = 10.0;
+        let scale = 2.0;
+        for decay in [
+            Decay::Exponential { origin, scale },
+            Decay::Gauss { origin, scale },
+            Decay::Linear { origin, scale },
+        ] {
+            assert_eq!(decay.apply(10.0), 1.0);
+            assert!((decay.apply(12.0) - 0.5).abs() < 1e-12);
+            assert!((decay.apply(8.0) - 0.5).abs() < 1e-12);
+            assert!(decay.apply(14.0) < 0.5);
+        }
+        assert_eq!(Decay::Linear { origin, scale }.apply(20.0), 0.0);
+        assert_eq!(Transform::Log10.apply(99.0), 2.0);
+        assert_eq!(Transform::Log10.apply(-5.0), 0.0);
+        assert_eq!(Transform::Sqrt.apply(16.0), 4.0);
+    }
+
+    #[test]
+    fn test_linear_score_validation_errors() -> crate::Result<()> {
+        let docs = test_docs();
+        let index = test_index(&docs[..10])?;
+        let searcher = index.reader()?.searcher();
+        let check_error = |linear_score: LinearScore, expected: &str| {
+            let err = searcher
+                .search(
+                    &AllQuery,
+                    &TopDocs::with_limit(10).custom_score(linear_score),
+                )
+                .unwrap_err();
+            let TantivyError::InvalidArgument(msg) = err else {
+                panic!("unexpected error {err:?}");
+            };
+            assert_eq!(msg, expected);
+        };
+        check_error(
+            linear_score().field("unknown", 1.0, Transform::Identity),
+            "Linear score component `unknown`: unknown field",
+        );
+        check_error(
+            linear_score().field("text", 1.0, Transform::Identity),
+            "Linear score component `text`: field is not a fast field",
+        );
+        check_error(
+            linear_score().field("tag", 1.0, Transform::Identity),
+            "Linear score component `tag`: field is not numerical",
+        );
+        check_error(
+            LinearScore::new().field_decay(
+                "rating",
+                1.0,
+                Decay::Gauss {
+                    origin: 0.0,
+                    scale: 0.0,
+                },
+            ),
+            "Linear score component `rating`: decay scale must be positive, got 0",
+        );
+        check_error(
+            linear_score().bm25(0.1),
+            "Linear score component `bm25`: the query score is not available with \
+             `TopDocs::custom_score`, use `TopDocs::tweak_score` instead",
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use columnar::{Column, DynamicColumn, HasAssociatedColumnType};
+
+use crate::{SegmentReader, TantivyError};
+
+/// Behavior of a collector reading a fast field, when a segment has no column for it.
+///
+/// A segment has no column for a fast field if none of its documents has a value for the field,
+/// typically because the segment was written before the field was added to the documents.
+///
+/// The policy is given to the collector, e.g. with
+/// [`TopDocs::order_by_fast_field_with_policy`](crate::collector::TopDocs::order_by_fast_field_with_policy)
+/// or [`AggregationCollector::with_missing_column_policy`](crate::aggregation::AggregationCollector::with_missing_column_policy).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum MissingColumnPolicy<T> {
+    /// The search fails with a [`TantivyError::MissingColumn`] error, naming the segment and the
+    /// field.
+    #[default]
+    Error,
+    /// All of the documents of the segment have the given value.
+    Default(T),
+    /// The segment contributes no hits.
+    SkipSegment,
+}
+
+impl<T> MissingColumnPolicy<T> {
+    /// Converts the default value of the policy.
+    pub fn map<U>(self, map_fn: impl FnOnce(T) -> U) -> MissingColumnPolicy<U> {
+        match self {
+            MissingColumnPolicy::Error => MissingColumnPolicy::Error,
+            MissingColumnPolicy::Default(value) => MissingColumnPolicy::Default(map_fn(value)),
+            MissingColumnPolicy::SkipSegment => MissingColumnPolicy::SkipSegment,
+        }
+    }
+
+    /// Opens the column of type `T` of the fast field `field_name`, applying the policy if the
+    /// segment has no such column.
+    ///
+    /// Returns `None` if the segment should be skipped. This is meant to be used by
+    /// [`CustomScorer::segment_scorer_opt`](crate::collector::CustomScorer::segment_scorer_opt).
+    pub fn column(
+        &self,
+        segment_reader: &SegmentReader,
+        field_name: &str,
+    ) -> crate::Result<Option<Column<T>>>
+    where
+        T: HasAssociatedColumnType + Copy + Send + Sync + 'static,
+        DynamicColumn: Into<Option<Column<T>>>,
+    {
+        if let Some(column) = segment_reader.try_fast_fields()?.column_opt(field_name)? {
+            return Ok(Some(column));
+        }
+        match self {
+            MissingColumnPolicy::Error => Err(missing_column_error(segment_reader, field_name)),
+            MissingColumnPolicy::Default(value) => Ok(Some(Column::build_constant_column(
+                segment_reader.max_doc(),
+                *value,
+            ))),
+            MissingColumnPolicy::SkipSegment => Ok(None),
+        }
+    }
+}
+
+/// Returns the error reported with [`MissingColumnPolicy::Error`].
+pub(crate) fn missing_column_error(
+    segment_reader: &SegmentReader,
+    field_name: &str,
+) -> TantivyError {
+    TantivyError::MissingColumn {
+        segment_id: segment_reader.segment_id(),
+        field: field_name.to_string(),
+    }
+}
@@ -83,25 +83,66 @@
 
 use downcast_rs::impl_downcast;
 
-use crate::{DocId, Score, SegmentOrdinal, SegmentReader};
+use crate::fastfield::AliveBitSet;
+use crate::{DocId, Score, Searcher, SegmentOrdinal, SegmentReader, COLLECT_BLOCK_BUFFER_LEN};
 
 mod count_collector;
 pub use self::count_collector::Count;
 
+mod count_per_segment_collector;
+pub use self::count_per_segment_collector::{
+    CountPerSegmentCollector, SegmentCountEntry, SegmentCountPerSegmentCollector,
+};
+
 mod histogram_collector;
 pub use histogram_collector::HistogramCollector;
 
+mod missing_column_policy;
+pub(crate) use self::missing_column_policy::missing_column_error;
+pub use self::missing_column_policy::MissingColumnPolicy;
+
 mod multi_collector;
 pub use self::multi_collector::{FruitHandle, MultiCollector, MultiFruit};
 
 mod top_collector;
 
 mod top_score_collector;
-pub use self::top_collector::ComparableDoc;
-pub use self::top_score_collector::{TopDocs, TopNComputer};
+pub use self::top_collector::{ComparableDoc, SearchAfterKey};
+pub use self::top_score_collector::{NormalizedTopDocs, TopDocs, TopNComputer};
+
+mod score_normalization;
+pub use self::score_normalization::Normalization;
+
+mod term_match_collector;
+pub use self::term_match_collector::{
+    TermMatchHits, TermMatchSegmentCollector, TopDocsWithTermMatches,
+};
+
+mod top_string_collector;
+pub use self::top_string_collector::{
+    StringFastFieldTopCollector, StringFastFieldTopSegmentCollector,
+};
 
 mod custom_score_top_collector;
-pub use self::custom_score_top_collector::{CustomScorer, CustomSegmentScorer};
+pub use self::custom_score_top_collector::{
+    BlockBoundedScorer, CustomScoreTopCollector, CustomScorer, CustomSegmentScorer,
+};
+
+mod rescore_top_collector;
+pub use self::rescore_top_collector::{
+    RescoreSegmentFruit, RescoreTopCollector, RescoreTopSegmentCollector,
+};
+
+mod linear_score;
+pub use self::linear_score::{Decay, LinearScore, LinearSegmentScorer, Transform};
+
+mod proximity_score;
+pub use self::proximity_score::{ProximityScorer, ProximitySegmentScorer};
+
+mod recency_score;
+pub use self::recency_score::{
+    RecencyBoost, RecencyScorer, RecencySegmentBoost, RecencySegmentScorer,
+};
 
 mod tweak_score_top_collector;
 pub use self::tweak_score_top_collector::{ScoreSegmentTweaker, ScoreTweaker};
@@ -112,6 +153,17 @@ use crate::query::Weight;
 mod docset_collector;
 pub use self::docset_collector::DocSetCollector;
 
+mod bitset_collector;
+pub use self::bitset_collector::{
+    BitSetCollector, BitSetSegmentCollector, DocBitSets, SegmentBitSet,
+};
+
+mod profile;
+use self::profile::{collect_segment_profiled, ProfiledSegmentCollector};
+pub use self::profile::{
+    CollectorProfile, NodeProfile, ProfileRecorder, SearchProfile, SegmentProfile,
+};
+
 mod filter_collector_wrapper;
 pub use self::filter_collector_wrapper::{BytesFilterCollector, FilterCollector};
 
@@ -163,6 +215,53 @@ pub trait Collector: Sync + Send {
         segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
     ) -> crate::Result<Self::Fruit>;
 
+    /// Same as [`merge_fruits`](Collector::merge_fruits), but with the searcher the segment
+    /// fruits were collected from.
+    ///
+    /// The segment ordinals the fruits were collected with are the ordinals of the segments of
+    /// `searcher`, so collectors can access the [`SegmentReader`]s when merging, e.g. to resolve
+    /// the term ordinals collected by their segment collectors instead of storing the readers in
+    /// their fruits. The [`Searcher`](crate::Searcher) merges the fruits with itself, so the
+    /// searcher generation is the one of the collection, even if the reader was reloaded
+    /// meanwhile.
+    ///
+    /// Defaults to [`merge_fruits`](Collector::merge_fruits). Collectors wrapping other
+    /// collectors should forward the searcher to them.
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        _searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        self.merge_fruits(segment_fruits)
+    }
+
+    /// Returns true if the fruits of the segments can be merged one at a time with
+    /// [`merge_segment_fruit`](Collector::merge_segment_fruit), in any order.
+    ///
+    /// The search then merges the fruit of each segment as soon as it is collected, instead of
+    /// keeping all of them until the end, which bounds the memory used by large fruits, e.g.
+    /// aggregation results.
+    fn merges_incrementally(&self) -> bool {
+        false
+    }
+
+    /// Merges the fruit of a segment into the merged fruit of other segments.
+    ///
+    /// The merge has to be commutative and associative. The result is passed to
+    /// [`merge_fruits`](Collector::merge_fruits) as a single fruit, once all of the segments are
+    /// merged.
+    ///
+    /// Only called if [`merges_incrementally`](Collector::merges_incrementally) returns true.
+    fn merge_segment_fruit(
+        &self,
+        _merged_fruit: <Self::Child as SegmentCollector>::Fruit,
+        _segment_fruit: <Self::Child as SegmentCollector>::Fruit,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        Err(crate::TantivyError::InternalError(
+            "The collector does not merge its fruits incrementally".to_string(),
+        ))
+    }
+
     /// Created a segment collector and
     fn collect_segment(
         &self,
@@ -171,38 +270,90 @@ pub trait Collector: Sync + Send {
         reader: &SegmentReader,
     ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
         let mut segment_collector = self.for_segment(segment_ord, reader)?;
+        collect_alive_docs(
+            weight,
+            reader,
+            self.requires_scoring(),
+            &mut segment_collector,
+        )?;
+        Ok(segment_collector.harvest())
+    }
+
+    /// Same as [`collect_segment`](Collector::collect_segment), but records where the time goes
+    /// into `profile`.
+    ///
+    /// This is used by [`Searcher::search_with_profiling`](crate::Searcher::search_with_profiling).
+    fn collect_segment_profiled(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+        profile: &mut SegmentProfile,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut segment_collector =
+            ProfiledSegmentCollector::new(self.for_segment(segment_ord, reader)?);
+        collect_segment_profiled(
+            weight,
+            reader,
+            self.requires_scoring(),
+            profile,
+            &mut [&mut segment_collector],
+        )?;
+        let (fruit, collector_profile) = segment_collector.harvest();
+        profile.collectors.push(collector_profile);
+        Ok(fruit)
+    }
+}
 
-        match (reader.alive_bitset(), self.requires_scoring()) {
-            (Some(alive_bitset), true) => {
-                weight.for_each(reader, &mut |doc, score| {
-                    if alive_bitset.is_alive(doc) {
-                        segment_collector.collect(doc, score);
-                    }
-                })?;
-            }
-            (Some(alive_bitset), false) => {
-                weight.for_each_no_score(reader, &mut |docs| {
-                    for doc in docs.iter().cloned() {
-                        if alive_bitset.is_alive(doc) {
-                            segment_collector.collect(doc, 0.0);
-                        }
-                    }
-                })?;
-            }
-            (None, true) => {
-                weight.for_each(reader, &mut |doc, score| {
+/// Pushes the alive documents matching `weight` to `segment_collector`, with their scores if
+/// `requires_scoring` is true.
+pub(crate) fn collect_alive_docs(
+    weight: &dyn Weight,
+    reader: &SegmentReader,
+    requires_scoring: bool,
+    segment_collector: &mut impl SegmentCollector,
+) -> crate::Result<()> {
+    match (reader.alive_bitset(), requires_scoring) {
+        (Some(alive_bitset), true) => {
+            weight.for_each(reader, &mut |doc, score| {
+                if alive_bitset.is_alive(doc) {
                     segment_collector.collect(doc, score);
-                })?;
-            }
-            (None, false) => {
-                weight.for_each_no_score(reader, &mut |docs| {
-                    segment_collector.collect_block(docs);
-                })?;
-            }
+                }
+            })?;
+        }
+        (Some(alive_bitset), false) => {
+            let mut alive_docs = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+            weight.for_each_no_score(reader, &mut |docs| {
+                let num_alive_docs = filter_alive_docs(docs, alive_bitset, &mut alive_docs);
+                segment_collector.collect_block(&alive_docs[..num_alive_docs]);
+            })?;
+        }
+        (None, true) => {
+            weight.for_each(reader, &mut |doc, score| {
+                segment_collector.collect(doc, score);
+            })?;
+        }
+        (None, false) => {
+            weight.for_each_no_score(reader, &mut |docs| {
+                segment_collector.collect_block(docs);
+            })?;
         }
+    }
+    Ok(())
+}
 
-        Ok(segment_collector.harvest())
+/// Copies the alive documents of `docs` into `alive_docs`, and returns their number.
+fn filter_alive_docs(
+    docs: &[DocId],
+    alive_bitset: &AliveBitSet,
+    alive_docs: &mut [DocId; COLLECT_BLOCK_BUFFER_LEN],
+) -> usize {
+    let mut num_alive_docs = 0;
+    for &doc in docs {
+        alive_docs[num_alive_docs] = doc;
+        num_alive_docs += alive_bitset.is_alive(doc) as usize;
     }
+    num_alive_docs
 }
 
 impl<TSegmentCollector: SegmentCollector> SegmentCollector for Option<TSegmentCollector> {
@@ -217,6 +368,12 @@ impl<TSegmentCollector: SegmentCollector> SegmentCollector for Option<TSegmentCo
     fn harvest(self) -> Self::Fruit {
         self.map(|segment_collector| segment_collector.harvest())
     }
+
+    fn enable_profiling(&mut self, recorder: ProfileRecorder) {
+        if let Some(segment_collector) = self {
+            segment_collector.enable_profiling(recorder);
+        }
+    }
 }
 
 impl<TCollector: Collector> Collector for Option<TCollector> {
@@ -258,6 +415,23 @@ impl<TCollector: Collector> Collector for Option<TCollector> {
             Ok(None)
         }
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        if let Some(inner) = self.as_ref() {
+            let inner_segment_fruits: Vec<_> = segment_fruits
+                .into_iter()
+                .flat_map(|fruit_opt| fruit_opt.into_iter())
+                .collect();
+            let fruit = inner.merge_fruits_with_searcher(inner_segment_fruits, searcher)?;
+            Ok(Some(fruit))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 /// The `SegmentCollector` is the trait in charge of defining the
@@ -286,6 +460,12 @@ pub trait SegmentCollector: 'static {
 
     /// Extract the fruit of the collection from the `SegmentCollector`.
     fn harvest(self) -> Self::Fruit;
+
+    /// Called before any document is collected, if the search is profiled.
+    ///
+    /// Segment collectors can report a finer grained breakdown of their collection time through
+    /// `recorder`. The default implementation does nothing.
+    fn enable_profiling(&mut self, _recorder: ProfileRecorder) {}
 }
 
 // -----------------------------------------------
@@ -328,6 +508,40 @@ where
             self.1.merge_fruits(right_fruits)?,
         ))
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<(Left::Fruit, Right::Fruit)> {
+        let (left_fruits, right_fruits) = segment_fruits.into_iter().unzip();
+        Ok((
+            self.0.merge_fruits_with_searcher(left_fruits, searcher)?,
+            self.1.merge_fruits_with_searcher(right_fruits, searcher)?,
+        ))
+    }
+
+    fn collect_segment_profiled(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+        profile: &mut SegmentProfile,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut left = ProfiledSegmentCollector::new(self.0.for_segment(segment_ord, reader)?);
+        let mut right = ProfiledSegmentCollector::new(self.1.for_segment(segment_ord, reader)?);
+        collect_segment_profiled(
+            weight,
+            reader,
+            self.requires_scoring(),
+            profile,
+            &mut [&mut left, &mut right],
+        )?;
+        let (left_fruit, left_profile) = left.harvest();
+        let (right_fruit, right_profile) = right.harvest();
+        profile.collectors.extend([left_profile, right_profile]);
+        Ok((left_fruit, right_fruit))
+    }
 }
 
 impl<Left, Right> SegmentCollector for (Left, Right)
@@ -345,6 +559,11 @@ where
     fn harvest(self) -> <Self as SegmentCollector>::Fruit {
         (self.0.harvest(), self.1.harvest())
     }
+
+    fn enable_profiling(&mut self, recorder: ProfileRecorder) {
+        self.0.enable_profiling(recorder.clone());
+        self.1.enable_profiling(recorder);
+    }
 }
 
 // 3-Tuple
@@ -391,6 +610,52 @@ where
             self.2.merge_fruits(three_fruits)?,
         ))
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        children: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        let mut one_fruits = vec![];
+        let mut two_fruits = vec![];
+        let mut three_fruits = vec![];
+        for (one_fruit, two_fruit, three_fruit) in children {
+            one_fruits.push(one_fruit);
+            two_fruits.push(two_fruit);
+            three_fruits.push(three_fruit);
+        }
+        Ok((
+            self.0.merge_fruits_with_searcher(one_fruits, searcher)?,
+            self.1.merge_fruits_with_searcher(two_fruits, searcher)?,
+            self.2.merge_fruits_with_searcher(three_fruits, searcher)?,
+        ))
+    }
+
+    fn collect_segment_profiled(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+        profile: &mut SegmentProfile,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut one = ProfiledSegmentCollector::new(self.0.for_segment(segment_ord, reader)?);
+        let mut two = ProfiledSegmentCollector::new(self.1.for_segment(segment_ord, reader)?);
+        let mut three = ProfiledSegmentCollector::new(self.2.for_segment(segment_ord, reader)?);
+        collect_segment_profiled(
+            weight,
+            reader,
+            self.requires_scoring(),
+            profile,
+            &mut [&mut one, &mut two, &mut three],
+        )?;
+        let (one_fruit, one_profile) = one.harvest();
+        let (two_fruit, two_profile) = two.harvest();
+        let (three_fruit, three_profile) = three.harvest();
+        profile
+            .collectors
+            .extend([one_profile, two_profile, three_profile]);
+        Ok((one_fruit, two_fruit, three_fruit))
+    }
 }
 
 impl<One, Two, Three> SegmentCollector for (One, Two, Three)
@@ -410,6 +675,12 @@ where
     fn harvest(self) -> <Self as SegmentCollector>::Fruit {
         (self.0.harvest(), self.1.harvest(), self.2.harvest())
     }
+
+    fn enable_profiling(&mut self, recorder: ProfileRecorder) {
+        self.0.enable_profiling(recorder.clone());
+        self.1.enable_profiling(recorder.clone());
+        self.2.enable_profiling(recorder);
+    }
 }
 
 // 4-Tuple
@@ -464,6 +735,57 @@ where
             self.3.merge_fruits(four_fruits)?,
         ))
     }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        children: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        let mut one_fruits = vec![];
+        let mut two_fruits = vec![];
+        let mut three_fruits = vec![];
+        let mut four_fruits = vec![];
+        for (one_fruit, two_fruit, three_fruit, four_fruit) in children {
+            one_fruits.push(one_fruit);
+            two_fruits.push(two_fruit);
+            three_fruits.push(three_fruit);
+            four_fruits.push(four_fruit);
+        }
+        Ok((
+            self.0.merge_fruits_with_searcher(one_fruits, searcher)?,
+            self.1.merge_fruits_with_searcher(two_fruits, searcher)?,
+            self.2.merge_fruits_with_searcher(three_fruits, searcher)?,
+            self.3.merge_fruits_with_searcher(four_fruits, searcher)?,
+        ))
+    }
+
+    fn collect_segment_profiled(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+        profile: &mut SegmentProfile,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut one = ProfiledSegmentCollector::new(self.0.for_segment(segment_ord, reader)?);
+        let mut two = ProfiledSegmentCollector::new(self.1.for_segment(segment_ord, reader)?);
+        let mut three = ProfiledSegmentCollector::new(self.2.for_segment(segment_ord, reader)?);
+        let mut four = ProfiledSegmentCollector::new(self.3.for_segment(segment_ord, reader)?);
+        collect_segment_profiled(
+            weight,
+            reader,
+            self.requires_scoring(),
+            profile,
+            &mut [&mut one, &mut two, &mut three, &mut four],
+        )?;
+        let (one_fruit, one_profile) = one.harvest();
+        let (two_fruit, two_profile) = two.harvest();
+        let (three_fruit, three_profile) = three.harvest();
+        let (four_fruit, four_profile) = four.harvest();
+        profile
+            .collectors
+            .extend([one_profile, two_profile, three_profile, four_profile]);
+        Ok((one_fruit, two_fruit, three_fruit, four_fruit))
+    }
 }
 
 impl<One, Two, Three, Four> SegmentCollector for (One, Two, Three, Four)
@@ -490,6 +812,13 @@ where
             self.3.harvest(),
         )
     }
+
+    fn enable_profiling(&mut self, recorder: ProfileRecorder) {
+        self.0.enable_profiling(recorder.clone());
+        self.1.enable_profiling(recorder.clone());
+        self.2.enable_profiling(recorder.clone());
+        self.3.enable_profiling(recorder);
+    }
 }
 
 impl_downcast!(Fruit);
@@ -3,7 +3,7 @@ use std::ops::Deref;
 
 use super::{Collector, SegmentCollector};
 use crate::collector::Fruit;
-use crate::{DocId, Score, SegmentOrdinal, SegmentReader, TantivyError};
+use crate::{DocId, Score, Searcher, SegmentOrdinal, SegmentReader, TantivyError};
 
 /// MultiFruit keeps Fruits from every nested Collector
 pub struct MultiFruit {
@@ -33,22 +33,40 @@ impl<TCollector: Collector> Collector for CollectorWrapper<TCollector> {
         &self,
         children: Vec<<Self::Child as SegmentCollector>::Fruit>,
     ) -> crate::Result<Box<dyn Fruit>> {
-        let typed_fruit: Vec<<TCollector::Child as SegmentCollector>::Fruit> = children
-            .into_iter()
-            .map(|untyped_fruit| {
-                untyped_fruit
-                    .downcast::<<TCollector::Child as SegmentCollector>::Fruit>()
-                    .map(|boxed_but_typed| *boxed_but_typed)
-                    .map_err(|_| {
-                        TantivyError::InvalidArgument("Failed to cast child fruit.".to_string())
-                    })
-            })
-            .collect::<crate::Result<_>>()?;
-        let merged_fruit = self.0.merge_fruits(typed_fruit)?;
+        let merged_fruit = self
+            .0
+            .merge_fruits(downcast_fruits::<TCollector>(children)?)?;
+        Ok(Box::new(merged_fruit))
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        children: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Box<dyn Fruit>> {
+        let merged_fruit = self
+            .0
+            .merge_fruits_with_searcher(downcast_fruits::<TCollector>(children)?, searcher)?;
         Ok(Box::new(merged_fruit))
     }
 }
 
+fn downcast_fruits<TCollector: Collector>(
+    children: Vec<Box<dyn Fruit>>,
+) -> crate::Result<Vec<<TCollector::Child as SegmentCollector>::Fruit>> {
+    children
+        .into_iter()
+        .map(|untyped_fruit| {
+            untyped_fruit
+                .downcast::<<TCollector::Child as SegmentCollector>::Fruit>()
+                .map(|boxed_but_typed| *boxed_but_typed)
+                .map_err(|_| {
+                    TantivyError::InvalidArgument("Failed to cast child fruit.".to_string())
+                })
+        })
+        .collect()
+}
+
 impl SegmentCollector for Box<dyn BoxableSegmentCollector> {
     type Fruit = Box<dyn Fruit>;
 
@@ -215,6 +233,39 @@ impl Collector for MultiCollector<'_> {
     }
 
     fn merge_fruits(&self, segments_multifruits: Vec<MultiFruit>) -> crate::Result<MultiFruit> {
+        let sub_fruits = self
+            .collector_wrappers
+            .iter()
+            .zip(self.split_fruits(segments_multifruits))
+            .map(|(child_collector, segment_fruits)| {
+                Ok(Some(child_collector.merge_fruits(segment_fruits)?))
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(MultiFruit { sub_fruits })
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segments_multifruits: Vec<MultiFruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<MultiFruit> {
+        let sub_fruits = self
+            .collector_wrappers
+            .iter()
+            .zip(self.split_fruits(segments_multifruits))
+            .map(|(child_collector, segment_fruits)| {
+                Ok(Some(
+                    child_collector.merge_fruits_with_searcher(segment_fruits, searcher)?,
+                ))
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(MultiFruit { sub_fruits })
+    }
+}
+
+impl MultiCollector<'_> {
+    /// Groups the fruits of the segments by child collector.
+    fn split_fruits(&self, segments_multifruits: Vec<MultiFruit>) -> Vec<Vec<Box<dyn Fruit>>> {
         let mut segment_fruits_list: Vec<Vec<Box<dyn Fruit>>> = (0..self.collector_wrappers.len())
             .map(|_| Vec::with_capacity(segments_multifruits.len()))
             .collect::<Vec<_>>();
@@ -225,15 +276,7 @@ impl Collector for MultiCollector<'_> {
                 }
             }
         }
-        let sub_fruits = self
-            .collector_wrappers
-            .iter()
-            .zip(segment_fruits_list)
-            .map(|(child_collector, segment_fruits)| {
-                Ok(Some(child_collector.merge_fruits(segment_fruits)?))
-            })
-            .collect::<crate::Result<_>>()?;
-        Ok(MultiFruit { sub_fruits })
+        segment_fruits_list
     }
 }
 
@@ -0,0 +1,378 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{filter_alive_docs, SegmentCollector};
+use crate::docset::{DocSet, COLLECT_BLOCK_BUFFER_LEN, TERMINATED};
+use crate::query::Weight;
+use crate::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// Breakdown of where the time of a search went.
+///
+/// It is returned by [`Searcher::search_with_profiling`](crate::Searcher::search_with_profiling).
+#[derive(Clone, Debug, Default)]
+pub struct SearchProfile {
+    /// Time spent creating the [`Weight`] of the query.
+    pub weight: Duration,
+    /// Profile of each segment, in segment ordinal order.
+    pub segments: Vec<SegmentProfile>,
+    /// Time spent merging the segment fruits.
+    pub merge: Duration,
+}
+
+impl SearchProfile {
+    /// Total number of documents pushed to the collector, over all segments.
+    pub fn num_docs_collected(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.num_docs_collected)
+            .sum()
+    }
+
+    /// Returns true if at least one segment was collected with
+    /// [`SegmentCollector::collect_block`].
+    pub fn block_path_used(&self) -> bool {
+        self.segments.iter().any(|segment| segment.block_path)
+    }
+
+    /// Sum of all the recorded durations.
+    ///
+    /// Segments may be collected in parallel, so this can be larger than the wall time of the
+    /// search.
+    pub fn total_duration(&self) -> Duration {
+        self.weight
+            + self.merge
+            + self
+                .segments
+                .iter()
+                .map(|segment| {
+                    segment.scorer_construction
+                        + segment.collection
+                        + segment
+                            .collectors
+                            .iter()
+                            .map(|collector| collector.harvest)
+                            .sum::<Duration>()
+                })
+                .sum::<Duration>()
+    }
+}
+
+/// Breakdown of where the time of a search went, within a segment.
+#[derive(Clone, Debug, Default)]
+pub struct SegmentProfile {
+    /// The ordinal of the segment.
+    pub segment_ord: SegmentOrdinal,
+    /// Time spent creating the scorer of the segment.
+    pub scorer_construction: Duration,
+    /// Time spent iterating over the matching documents and pushing them to the collectors.
+    pub collection: Duration,
+    /// Number of documents pushed to the collector.
+    pub num_docs_collected: u64,
+    /// True if the documents were pushed with [`SegmentCollector::collect_block`], which happens
+    /// if the collector does not require scoring.
+    pub block_path: bool,
+    /// One profile per collector. Tuple collectors have one profile per element, any other
+    /// collector has a single profile.
+    pub collectors: Vec<CollectorProfile>,
+}
+
+/// Breakdown of the time spent in a segment collector.
+#[derive(Clone, Debug, Default)]
+pub struct CollectorProfile {
+    /// Time spent in the `collect` calls of the segment collector.
+    pub collection: Duration,
+    /// Time spent in [`SegmentCollector::harvest`].
+    pub harvest: Duration,
+    /// Finer grained timings reported by the collector through its [`ProfileRecorder`], e.g. one
+    /// entry per aggregation.
+    pub nodes: Vec<NodeProfile>,
+}
+
+/// Time spent in a part of a collector, as reported through a [`ProfileRecorder`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeProfile {
+    /// Name of the node, e.g. the name of an aggregation.
+    pub name: String,
+    /// Time spent in the node.
+    pub duration: Duration,
+}
+
+/// Handle given to a [`SegmentCollector`] in
+/// [`SegmentCollector::enable_profiling`], to report the time spent in its parts.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileRecorder {
+    nodes: Arc<Mutex<Vec<NodeProfile>>>,
+}
+
+impl ProfileRecorder {
+    /// Adds `duration` to the time spent in the node `name`.
+    pub fn record(&self, name: &str, duration: Duration) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(node) = nodes.iter_mut().find(|node| node.name == name) {
+            node.duration += duration;
+        } else {
+            nodes.push(NodeProfile {
+                name: name.to_string(),
+                duration,
+            });
+        }
+    }
+
+    fn take(&self) -> Vec<NodeProfile> {
+        std::mem::take(&mut *self.nodes.lock().unwrap())
+    }
+}
+
+/// Object safe view on a profiled segment collector, so that the elements of a tuple collector
+/// can be driven by the same loop.
+pub(crate) trait ProfiledBlockCollector {
+    fn collect_profiled(&mut self, docs: &[DocId], scores: &[Score], block_path: bool);
+}
+
+/// Wraps a segment collector and measures the time spent in it.
+pub(crate) struct ProfiledSegmentCollector<TSegmentCollector> {
+    segment_collector: TSegmentCollector,
+    recorder: ProfileRecorder,
+    collection: Duration,
+}
+
+impl<TSegmentCollector: SegmentCollector> ProfiledSegmentCollector<TSegmentCollector> {
+    pub fn new(mut segment_collector: TSegmentCollector) -> Self {
+        let recorder = ProfileRecorder::default();
+        segment_collector.enable_profiling(recorder.clone());
+        ProfiledSegmentCollector {
+            segment_collector,
+            recorder,
+            collection: Duration::ZERO,
+        }
+    }
+
+    pub fn harvest(self) -> (TSegmentCollector::Fruit, CollectorProfile) {
+        let start = Instant::now();
+        let fruit = self.segment_collector.harvest();
+        let harvest = start.elapsed();
+        let profile = CollectorProfile {
+            collection: self.collection,
+            harvest,
+            nodes: self.recorder.take(),
+        };
+        (fruit, profile)
+    }
+}
+
+impl<TSegmentCollector: SegmentCollector> ProfiledBlockCollector
+    for ProfiledSegmentCollector<TSegmentCollector>
+{
+    fn collect_profiled(&mut self, docs: &[DocId], scores: &[Score], block_path: bool) {
+        let start = Instant::now();
+        if block_path {
+            self.segment_collector.collect_block(docs);
+        } else {
+            for (&doc, &score) in docs.iter().zip(scores) {
+                self.segment_collector.collect(doc, score);
+            }
+        }
+        self.collection += start.elapsed();
+    }
+}
+
+/// Pushes the documents matching `weight` in `reader` to `segment_collectors`, recording the
+/// timings into `profile`.
+///
+/// Documents are pushed in blocks of [`COLLECT_BLOCK_BUFFER_LEN`], so that the time spent in
+/// each collector can be measured without timing every single document.
+pub(crate) fn collect_segment_profiled(
+    weight: &dyn Weight,
+    reader: &SegmentReader,
+    requires_scoring: bool,
+    profile: &mut SegmentProfile,
+    segment_collectors: &mut [&mut dyn ProfiledBlockCollector],
+) -> crate::Result<()> {
+    let start = Instant::now();
+    let mut scorer = weight.scorer(reader, 1.0)?;
+    profile.scorer_construction = start.elapsed();
+
+    let start = Instant::now();
+    let alive_bitset = reader.alive_bitset();
+    profile.block_path = !requires_scoring;
+    let mut docs = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+    let mut scores = [0.0; COLLECT_BLOCK_BUFFER_LEN];
+    let mut block_buffer = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+    while scorer.doc() != TERMINATED {
+        let num_docs = if profile.block_path {
+            let num_docs = scorer.fill_buffer(&mut block_buffer);
+            if let Some(alive_bitset) = alive_bitset {
+                filter_alive_docs(&block_buffer[..num_docs], alive_bitset, &mut docs)
+            } else {
+                docs[..num_docs].copy_from_slice(&block_buffer[..num_docs]);
+                num_docs
+            }
+        } else {
+            let mut num_docs = 0;
+            let mut doc = scorer.doc();
+            while doc != TERMINATED && num_docs < COLLECT_BLOCK_BUFFER_LEN {
+                if alive_bitset.is_none_or(|alive_bitset| alive_bitset.is_alive(doc)) {
+                    docs[num_docs] = doc;
+                    scores[num_docs] = scorer.score();
+                    num_docs += 1;
+                }
+                doc = scorer.advance();
+            }
+            num_docs
+        };
+        for segment_collector in segment_collectors.iter_mut() {
+            segment_collector.collect_profiled(
+                &docs[..num_docs],
+                &scores[..num_docs],
+                profile.block_path,
+            );
+        }
+        profile.num_docs_collected += num_docs as u64;
+    }
+    profile.collection = start.elapsed();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, TopDocs};
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let tag = schema_builder.add_text_field("tag", STRING | FAST);
+        let value = schema_builder.add_u64_field("value", INDEXED | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..3u64 {
+            for i in 0..500u64 {
+                let tag_value = if i % 2 == 0 { "even" } else { "odd" };
+                index_writer.add_document(doc!(tag => tag_value, value => segment * 1000 + i))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn test_search_with_profiling_tuple_collector() -> crate::Result<()> {
+        let index = create_index()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let value = index.schema().get_field("value").unwrap();
+        index_writer.delete_term(Term::from_field_u64(value, 2))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let tag = index.schema().get_field("tag").unwrap();
+        let query = TermQuery::new(Term::from_field_text(tag, "even"), IndexRecordOption::Basic);
+
+        let collector = (Count, TopDocs::with_limit(10));
+        let ((count, top_docs), profile) = searcher.search_with_profiling(&query, &collector)?;
+        assert_eq!(
+            (count, top_docs.clone()),
+            searcher.search(&query, &collector)?
+        );
+        assert_eq!(count, 749);
+        assert_eq!(profile.num_docs_collected(), count as u64);
+        assert!(!profile.block_path_used());
+        assert!(profile.weight > Duration::ZERO);
+        assert!(profile.merge > Duration::ZERO);
+
+        assert_eq!(profile.segments.len(), 3);
+        for (segment_ord, segment) in profile.segments.iter().enumerate() {
+            assert_eq!(segment.segment_ord, segment_ord as u32);
+            assert!(segment.scorer_construction > Duration::ZERO);
+            assert!(segment.collection > Duration::ZERO);
+            assert_eq!(segment.collectors.len(), 2);
+            let collectors_duration: Duration = segment
+                .collectors
+                .iter()
+                .map(|collector| collector.collection)
+                .sum();
+            assert!(collectors_duration > Duration::ZERO);
+            assert!(collectors_duration <= segment.collection);
+        }
+        let segments_duration: Duration = profile
+            .segments
+            .iter()
+            .map(|segment| segment.scorer_construction + segment.collection)
+            .sum();
+        assert!(profile.total_duration() >= profile.weight + profile.merge + segments_duration);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_with_profiling_block_path() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let (count, profile) = searcher.search_with_profiling(&AllQuery, &Count)?;
+        assert_eq!(count, 1500);
+        assert_eq!(profile.num_docs_collected(), 1500);
+        assert!(profile.block_path_used());
+        for segment in &profile.segments {
+            assert!(segment.block_path);
+            assert_eq!(segment.num_docs_collected, 500);
+            assert_eq!(segment.collectors.len(), 1);
+            assert!(segment.collectors[0].nodes.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_with_profiling_aggregation_nodes() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "tags": {
+                "terms": { "field": "tag" },
+                "aggs": { "avg_value": { "avg": { "field": "value" } } }
+            },
+            "max_value": { "max": { "field": "value" } },
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req.clone(), Default::default());
+        let (agg_res, profile) = searcher.search_with_profiling(&AllQuery, &collector)?;
+        assert_eq!(
+            serde_json::to_value(&agg_res)?,
+            serde_json::to_value(searcher.search(&AllQuery, &collector)?)?
+        );
+        assert_eq!(profile.num_docs_collected(), 1500);
+        for segment in &profile.segments {
+            let collector_profile = &segment.collectors[0];
+            let mut names: Vec<&str> = collector_profile
+                .nodes
+                .iter()
+                .map(|node| node.name.as_str())
+                .collect();
+            names.sort();
+            assert_eq!(names, ["max_value", "tags"]);
+            let nodes_duration: Duration = collector_profile
+                .nodes
+                .iter()
+                .map(|node| node.duration)
+                .sum();
+            assert!(collector_profile.nodes[0].duration > Duration::ZERO);
+            assert!(collector_profile.nodes[1].duration > Duration::ZERO);
+            assert!(nodes_duration <= collector_profile.collection + collector_profile.harvest);
+        }
+
+        // A single aggregation is reported as well.
+        let agg_req: Aggregations =
+            serde_json::from_value(json!({ "max_value": { "max": { "field": "value" } } }))
+                .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+        let (_, profile) = searcher.search_with_profiling(&AllQuery, &collector)?;
+        for segment in &profile.segments {
+            assert_eq!(segment.collectors[0].nodes.len(), 1);
+            assert_eq!(segment.collectors[0].nodes[0].name, "max_value");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::collector::{CustomScorer, CustomSegmentScorer};
+use crate::docset::DocSet;
+use crate::postings::PositionsPostings;
+use crate::schema::Field;
+use crate::{DocId, SegmentReader};
+
+/// Custom score measuring the proximity of two terms in a text field.
+///
+/// The score is the minimum distance, in positions, between an occurrence of `term_a` and an
+/// occurrence of `term_b` within the same value of the field, or `u32::MAX` if the document does
+/// not contain both terms in a same value. The closest documents have the lowest scores.
+///
+/// The terms are looked up as is, they are not tokenized. The field must be a text field indexed
+/// with positions. It must also be stored to tell apart the values of multi-valued fields, see
+/// [`PositionsPostings`].
+///
+/// ```rust
+/// use tantivy::collector::{ProximityScorer, TopDocs};
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, STORED, TEXT};
+/// use tantivy::{doc, DocAddress, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let body = schema_builder.add_text_field("body", TEXT | STORED);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// index_writer.add_document(doc!(body => "a quick brown fox"))?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let top_docs_by_proximity =
+///     TopDocs::with_limit(1).custom_score(ProximityScorer::new(body, "quick", "fox"));
+/// let top_docs = searcher.search(&AllQuery, &top_docs_by_proximity)?;
+/// assert_eq!(top_docs, vec![(2, DocAddress::new(0, 0))]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProximityScorer {
+    field: Field,
+    term_a: String,
+    term_b: String,
+}
+
+impl ProximityScorer {
+    /// Creates a score measuring the distance between `term_a` and `term_b` in `field`.
+    pub fn new(field: Field, term_a: impl ToString, term_b: impl ToString) -> Self {
+        ProximityScorer {
+            field,
+            term_a: term_a.to_string(),
+            term_b: term_b.to_string(),
+        }
+    }
+}
+
+impl CustomScorer<u32> for ProximityScorer {
+    type Child = ProximitySegmentScorer;
+
+    fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        Ok(ProximitySegmentScorer {
+            postings_a: segment_reader.positions_reader(self.field, &self.term_a)?,
+            postings_b: segment_reader.positions_reader(self.field, &self.term_b)?,
+        })
+    }
+}
+
+/// Segment scorer of a [`ProximityScorer`].
+///
+/// The documents must be scored in increasing order, as collectors do.
+pub struct ProximitySegmentScorer {
+    /// `None` if the term does not appear in the segment.
+    postings_a: Option<PositionsPostings>,
+    postings_b: Option<PositionsPostings>,
+}
+
+impl CustomSegmentScorer<u32> for ProximitySegmentScorer {
+    fn score(&mut self, doc: DocId) -> u32 {
+        let (Some(postings_a), Some(postings_b)) = (&mut self.postings_a, &mut self.postings_b)
+        else {
+            return u32::MAX;
+        };
+        if postings_a.doc() < doc {
+            postings_a.seek(doc);
+        }
+        if postings_b.doc() < doc {
+            postings_b.seek(doc);
+        }
+        if postings_a.doc() != doc || postings_b.doc() != doc {
+            return u32::MAX;
+        }
+        let (positions_a, positions_b) =
+            match (postings_a.doc_positions(), postings_b.doc_positions()) {
+                (Ok(positions_a), Ok(positions_b)) => (positions_a, positions_b),
+                (Err(err), _) | (_, Err(err)) => {
+                    error!("Failed to read the positions of doc {doc}: {err:?}");
+                    return u32::MAX;
+                }
+            };
+        positions_a
+            .values()
+            .zip(positions_b.values())
+            .map(|(value_positions_a, value_positions_b)| {
+                min_distance(value_positions_a, value_positions_b)
+            })
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+/// Returns the minimum distance between a position of `left` and a position of `right`, or
+/// `u32::MAX` if either is empty. Both slices must be sorted.
+fn min_distance(left: &[u32], right: &[u32]) -> u32 {
+    let mut min_distance = u32::MAX;
+    let (mut left_idx, mut right_idx) = (0, 0);
+    while left_idx < left.len() && right_idx < right.len() {
+        let (left_pos, right_pos) = (left[left_idx], right[right_idx]);
+        min_distance = min_distance.min(left_pos.abs_diff(right_pos));
+        if left_pos < right_pos {
+            left_idx += 1;
+        } else {
+            right_idx += 1;
+        }
+    }
+    min_distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::TopDocs;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, Value, STORED, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, TantivyDocument};
+
+    /// Computes the proximity of `term_a` and `term_b` from the stored values, splitting them
+    /// on whitespaces.
+    fn brute_force_proximity(values: &[&str], term_a: &str, term_b: &str) -> u32 {
+        let mut min_distance = u32::MAX;
+        for value in values {
+            let words: Vec<&str> = value.split_whitespace().collect();
+            for (pos_a, word_a) in words.iter().enumerate() {
+                for (pos_b, word_b) in words.iter().enumerate() {
+                    if *word_a == term_a && *word_b == term_b {
+                        min_distance = min_distance.min(pos_a.abs_diff(pos_b) as u32);
+                    }
+                }
+            }
+        }
+        min_distance
+    }
+
+    #[test]
+    fn test_min_distance() {
+        assert_eq!(min_distance(&[], &[1]), u32::MAX);
+        assert_eq!(min_distance(&[1, 10], &[5, 12]), 2);
+        assert_eq!(min_distance(&[7], &[1, 3, 20]), 4);
+        assert_eq!(min_distance(&[3], &[3]), 0);
+    }
+
+    #[test]
+    fn test_proximity_no_cross_value_proximity() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let body = schema_builder.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        // Without the value boundaries, `fox` and `quick` would be two positions apart.
+        index_writer.add_document(doc!(body => "the lazy fox", body => "quick"))?;
+        index_writer.add_document(doc!(body => "the lazy fox", body => "quick", body => "fox"))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let top_docs = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(2).custom_score(ProximityScorer::new(body, "quick", "fox")),
+        )?;
+        assert_eq!(
+            top_docs,
+            vec![
+                (u32::MAX, DocAddress::new(0, 0)),
+                (u32::MAX, DocAddress::new(0, 1)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_proximity_agrees_with_brute_force() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let body = schema_builder.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let words = ["red", "green", "blue", "cyan", "pink"];
+        for doc_id in 0..200usize {
+            let mut doc = TantivyDocument::default();
+            for value_id in 0..(doc_id % 4) {
+                let num_words = (doc_id * 7 + value_id * 3) % 9;
+                let value: Vec<&str> = (0..num_words)
+                    .map(|word_id| words[(doc_id * 31 + value_id * 17 + word_id * 13) % 5])
+                    .collect();
+                doc.add_text(body, value.join(" "));
+            }
+            index_writer.add_document(doc)?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        for (term_a, term_b) in [("red", "blue"), ("green", "green"), ("cyan", "pink")] {
+            let top_docs = searcher.search(
+                &AllQuery,
+                &TopDocs::with_limit(200).custom_score(ProximityScorer::new(body, term_a, term_b)),
+            )?;
+            assert_eq!(top_docs.len(), 200);
+            for (proximity, doc_address) in top_docs {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                let values: Vec<&str> = doc
+                    .get_all(body)
+                    .map(|value| value.as_str().unwrap())
+                    .collect();
+                assert_eq!(proximity, brute_force_proximity(&values, term_a, term_b));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,620 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use columnar::Column;
+
+use crate::collector::{
+    BlockBoundedScorer, CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker,
+};
+use crate::schema::Type;
+use crate::time::OffsetDateTime;
+use crate::{DateTime, DocId, Score, SegmentReader, TantivyError};
+
+/// Custom score decaying exponentially with the age of a document.
+///
+/// The age is the time elapsed from the first value of a date fast field to a reference time,
+/// usually now. The score is `0.5 ^ (age / half_life)`: `1.0` for a document dated at the
+/// reference time, `0.5` for a document one half-life older, and so on. Documents dated after the
+/// reference time get a score of `1.0`.
+///
+/// The dates are read at the precision the field was indexed with, see
+/// [`DateOptions::set_precision`](crate::schema::DateOptions::set_precision).
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use tantivy::collector::{RecencyScorer, TopDocs};
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST};
+/// use tantivy::{doc, DateTime, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// const DAY_SECS: i64 = 86_400;
+/// let mut schema_builder = Schema::builder();
+/// let published = schema_builder.add_date_field("published", FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// index_writer.add_document(doc!(published => DateTime::from_timestamp_secs(0)))?;
+/// index_writer.add_document(doc!(published => DateTime::from_timestamp_secs(9 * DAY_SECS)))?;
+/// index_writer.commit()?;
+///
+/// let recency_scorer = RecencyScorer::new(
+///     "published",
+///     DateTime::from_timestamp_secs(10 * DAY_SECS),
+///     Duration::from_secs(DAY_SECS as u64),
+/// );
+/// let searcher = index.reader()?.searcher();
+/// let top_docs = searcher.search(&AllQuery, &TopDocs::with_limit(2).custom_score(recency_scorer))?;
+/// // Published one day, i.e. one half-life, before the reference time.
+/// assert!((top_docs[0].0 - 0.5).abs() < 1e-9);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// When used with [`TopDocs::custom_score`](crate::collector::TopDocs::custom_score), the
+/// scores are computed one block of documents at a time.
+///
+/// The field is validated when the score is computed on a segment: an unknown field, a field
+/// that is not fast, or a field that is not a date results in an error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecencyScorer {
+    field: String,
+    reference_time: DateTime,
+    half_life: Duration,
+    missing: f64,
+}
+
+impl RecencyScorer {
+    /// Creates a score decaying by half every `half_life` before `reference_time`, based on the
+    /// date fast field `field`.
+    pub fn new(field: impl ToString, reference_time: DateTime, half_life: Duration) -> Self {
+        RecencyScorer {
+            field: field.to_string(),
+            reference_time,
+            half_life,
+            missing: 0.0,
+        }
+    }
+
+    /// Sets the score of the documents without a value. It defaults to `0.0`.
+    pub fn missing(mut self, score: f64) -> RecencyScorer {
+        self.missing = score;
+        self
+    }
+
+    fn error(&self, msg: &str) -> TantivyError {
+        TantivyError::InvalidArgument(format!("Recency score field `{}`: {msg}", self.field))
+    }
+}
+
+impl CustomScorer<f64> for RecencyScorer {
+    type Child = RecencySegmentScorer;
+
+    fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        if self.half_life.is_zero() {
+            return Err(self.error("half-life must be positive"));
+        }
+        let schema = segment_reader.schema();
+        let (field, _path) = schema
+            .find_field(&self.field)
+            .ok_or_else(|| self.error("unknown field"))?;
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            return Err(self.error("field is not a fast field"));
+        }
+        if !matches!(
+            field_entry.field_type().value_type(),
+            Type::Date | Type::Json
+        ) {
+            return Err(self.error("field is not a date field"));
+        }
+        let column = segment_reader
+            .try_fast_fields()?
+            .column_opt::<DateTime>(&self.field)?;
+        let half_life_days = self.half_life.as_secs_f64() / 86_400.0;
+        Ok(RecencySegmentScorer {
+            column,
+            reference_time: self.reference_time,
+            half_life_days,
+            missing: self.missing,
+            first_vals: Vec::new(),
+        })
+    }
+}
+
+/// Segment scorer of a [`RecencyScorer`].
+pub struct RecencySegmentScorer {
+    /// `None` if no document of the segment has a value.
+    column: Option<Column<DateTime>>,
+    reference_time: DateTime,
+    half_life_days: f64,
+    missing: f64,
+    /// Scratch buffer used to read the column for a block of docs.
+    first_vals: Vec<Option<DateTime>>,
+}
+
+impl RecencySegmentScorer {
+    #[inline]
+    fn decay(&self, date_time: Option<DateTime>) -> f64 {
+        match date_time {
+            Some(date_time) => {
+                let age_days = date_time.days_until(self.reference_time).max(0.0);
+                0.5f64.powf(age_days / self.half_life_days)
+            }
+            None => self.missing,
+        }
+    }
+}
+
+impl CustomSegmentScorer<f64> for RecencySegmentScorer {
+    fn score(&mut self, doc: DocId) -> f64 {
+        let date_time = self.column.as_ref().and_then(|column| column.first(doc));
+        self.decay(date_time)
+    }
+
+    fn score_block(&mut self, docs: &[DocId], scores: &mut Vec<f64>) {
+        let Some(column) = &self.column else {
+            scores.resize(docs.len(), self.missing);
+            return;
+        };
+        self.first_vals.clear();
+        self.first_vals.resize(docs.len(), None);
+        column.first_vals(docs, &mut self.first_vals);
+        scores.extend(
+            self.first_vals
+                .iter()
+                .map(|&date_time| self.decay(date_time)),
+        );
+    }
+
+    fn as_block_bounded(&self) -> Option<&dyn BlockBoundedScorer<f64>> {
+        Some(self)
+    }
+}
+
+impl BlockBoundedScorer<f64> for RecencySegmentScorer {
+    fn block_bounds(&self, doc_range: Range<DocId>) -> Option<(f64, f64)> {
+        let missing_bounds = (self.missing, self.missing);
+        let Some(column) = &self.column else {
+            return Some(missing_bounds);
+        };
+        let Some((min_date_time, max_date_time)) = column.value_bounds(doc_range.clone()) else {
+            return Some(missing_bounds);
+        };
+        // The decay increases with the date.
+        let (min_score, max_score) = (
+            self.decay(Some(min_date_time)),
+            self.decay(Some(max_date_time)),
+        );
+        if column.has_value_for_all_docs(doc_range) {
+            Some((min_score, max_score))
+        } else {
+            Some((min_score.min(self.missing), max_score.max(self.missing)))
+        }
+    }
+}
+
+/// Score tweaker multiplying the score of the documents by a boost decaying exponentially with
+/// their age, for [`TopDocs::tweak_score`](crate::collector::TopDocs::tweak_score).
+///
+/// The boost is `1 + (max_boost - 1) * 0.5 ^ (age / half_life)`, where the age is computed as
+/// for a [`RecencyScorer`], from the reference time, by default the time the boost is created.
+/// It goes from `max_boost` for a document dated at the reference time down to `1.0`, which is
+/// also the boost of the documents without a date.
+///
+/// The boosted score is always finite: the boost is clamped to `[1.0, max_boost]`, and a
+/// non-finite score, which a scorer should never return, is replaced by `0.0`.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use tantivy::collector::{RecencyBoost, TopDocs};
+/// use tantivy::query::QueryParser;
+/// use tantivy::schema::{Schema, FAST, TEXT};
+/// use tantivy::{doc, DateTime, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// const DAY_SECS: i64 = 86_400;
+/// let mut schema_builder = Schema::builder();
+/// let title = schema_builder.add_text_field("title", TEXT);
+/// let published = schema_builder.add_date_field("published", FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// index_writer.add_document(doc!(
+///     title => "release notes",
+///     published => DateTime::from_timestamp_secs(0),
+/// ))?;
+/// index_writer.add_document(doc!(
+///     title => "release notes",
+///     published => DateTime::from_timestamp_secs(9 * DAY_SECS),
+/// ))?;
+/// index_writer.commit()?;
+///
+/// let recency_boost = RecencyBoost::new("published", Duration::from_secs(DAY_SECS as u64), 2.0)
+///     .reference_time(DateTime::from_timestamp_secs(10 * DAY_SECS));
+/// let query = QueryParser::for_index(&index, vec![title]).parse_query("release")?;
+/// let searcher = index.reader()?.searcher();
+/// let top_docs = searcher.search(&query, &TopDocs::with_limit(2).tweak_score(recency_boost))?;
+/// // The most recent document comes first, with a 1.5x boost.
+/// assert_eq!(top_docs[0].1.doc_id, 1);
+/// assert!((top_docs[0].0 / top_docs[1].0 - 1.5 / (1.0 + 0.5f32.powi(10))).abs() < 1e-5);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecencyBoost {
+    field: String,
+    reference_time: DateTime,
+    half_life: Duration,
+    max_boost: f64,
+}
+
+impl RecencyBoost {
+    /// Creates a boost of at most `max_boost`, decaying by half every `half_life` before now,
+    /// based on the date fast field `field`.
+    pub fn new(field: impl ToString, half_life: Duration, max_boost: f64) -> Self {
+        RecencyBoost {
+            field: field.to_string(),
+            reference_time: DateTime::from_utc(OffsetDateTime::now_utc()),
+            half_life,
+            max_boost,
+        }
+    }
+
+    /// Sets the time from which the age of the documents is computed. It defaults to the time
+    /// the boost was created.
+    pub fn reference_time(mut self, reference_time: DateTime) -> RecencyBoost {
+        self.reference_time = reference_time;
+        self
+    }
+}
+
+impl ScoreTweaker<Score> for RecencyBoost {
+    type Child = RecencySegmentBoost;
+
+    fn segment_tweaker(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        if !(self.max_boost >= 1.0 && (self.max_boost as Score).is_finite()) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Recency boost field `{}`: max boost must be a finite number greater than or \
+                 equal to 1, got {}",
+                self.field, self.max_boost
+            )));
+        }
+        // The documents without a date get a decay of 0, i.e. a boost of 1.
+        let recency_scorer = RecencyScorer::new(&self.field, self.reference_time, self.half_life)
+            .segment_scorer(segment_reader)?;
+        Ok(RecencySegmentBoost {
+            recency_scorer,
+            max_boost: self.max_boost,
+        })
+    }
+}
+
+/// Segment tweaker of a [`RecencyBoost`].
+pub struct RecencySegmentBoost {
+    recency_scorer: RecencySegmentScorer,
+    max_boost: f64,
+}
+
+impl RecencySegmentBoost {
+    #[inline]
+    fn boost(&self, decay: f64) -> f64 {
+        let boost = 1.0 + (self.max_boost - 1.0) * decay;
+        // Written so that `NaN` falls back to no boost.
+        if boost >= 1.0 {
+            boost.min(self.max_boost)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl ScoreSegmentTweaker<Score> for RecencySegmentBoost {
+    fn score(&mut self, doc: DocId, score: Score) -> Score {
+        let decay = self.recency_scorer.score(doc);
+        let boosted_score = score * self.boost(decay) as Score;
+        debug_assert!(
+            boosted_score.is_finite(),
+            "recency boost of score {score} is not finite"
+        );
+        if boosted_score.is_finite() {
+            boosted_score
+        } else if boosted_score.is_nan() {
+            0.0
+        } else {
+            boosted_score.clamp(Score::MIN, Score::MAX)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RecencyBoost, RecencyScorer};
+    use crate::collector::custom_score_top_collector::CustomScoreTopCollector;
+    use crate::collector::top_collector::TopCollector;
+    use crate::collector::{
+        Collector, Count, CustomScorer, CustomSegmentScorer, SegmentCollector, TopDocs,
+    };
+    use crate::fastfield::Granularity;
+    use crate::query::{AllQuery, QueryParser};
+    use crate::schema::{DateOptions, DateTimePrecision, Schema, FAST, STORED, TEXT};
+    use crate::{
+        DateTime, DocId, Index, IndexWriter, Score, SegmentReader, TantivyDocument, TantivyError,
+    };
+
+    const DAY_SECS: i64 = 86_400;
+    const REFERENCE_SECS: i64 = 1_000 * DAY_SECS;
+
+    /// Creates an index of 100 docs, the doc `i` being published `i` days and 1.5s before the
+    /// reference time, except every fourth doc which has no publication date.
+    fn test_index(precision: DateTimePrecision) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let published = schema_builder.add_date_field(
+            "published",
+            DateOptions::from(FAST).set_precision(precision),
+        );
+        let id = schema_builder.add_u64_field("id", FAST | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..100u64 {
+            let mut doc = TantivyDocument::new();
+            doc.add_u64(id, i);
+            if i % 4 != 3 {
+                let published_nanos =
+                    (REFERENCE_SECS - i as i64 * DAY_SECS) * 1_000_000_000 - 1_500_000_000;
+                doc.add_date(published, DateTime::from_timestamp_nanos(published_nanos));
+            }
+            index_writer.add_document(doc)?;
+            if i == 49 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_recency_scorer_precisions() -> crate::Result<()> {
+        let half_life = Duration::from_secs(10 * DAY_SECS as u64);
+        let reference_time = DateTime::from_timestamp_secs(REFERENCE_SECS);
+        for (precision, age_offset_secs) in [
+            (DateTimePrecision::Seconds, 2.0),
+            (DateTimePrecision::Milliseconds, 1.5),
+            (DateTimePrecision::Microseconds, 1.5),
+        ] {
+            let index = test_index(precision)?;
+            let searcher = index.reader()?.searcher();
+            let recency_scorer =
+                RecencyScorer::new("published", reference_time, half_life).missing(-1.0);
+            let top_docs = searcher.search(
+                &AllQuery,
+                &TopDocs::with_limit(100).custom_score(recency_scorer.clone()),
+            )?;
+            assert_eq!(top_docs.len(), 100);
+            for (score, doc_address) in top_docs {
+                let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+                let id = segment_reader
+                    .fast_fields()
+                    .u64("id")?
+                    .first(doc_address.doc_id)
+                    .unwrap();
+                let expected_score = if id % 4 == 3 {
+                    -1.0
+                } else {
+                    let age_days = id as f64 + age_offset_secs / DAY_SECS as f64;
+                    0.5f64.powf(age_days / 10.0)
+                };
+                assert!(
+                    (score - expected_score).abs() < 1e-12,
+                    "{precision:?}, doc {id}: {score} != {expected_score}"
+                );
+                // Scoring one doc at a time gives the same score as scoring by blocks.
+                let mut segment_scorer = recency_scorer.segment_scorer(segment_reader)?;
+                assert_eq!(segment_scorer.score(doc_address.doc_id), score);
+
+                let column = segment_reader.fast_fields().date("published")?;
+                let days = column.days_between(doc_address.doc_id, reference_time);
+                let truncated = column.truncate(doc_address.doc_id, Granularity::Day);
+                if id % 4 == 3 {
+                    assert_eq!(days, None);
+                    assert_eq!(truncated, None);
+                } else {
+                    let days = days.unwrap();
+                    assert!((days - (id as f64 + age_offset_secs / DAY_SECS as f64)).abs() < 1e-9);
+                    // Published 1.5s before midnight: truncated to the previous day.
+                    assert_eq!(
+                        truncated,
+                        Some(DateTime::from_timestamp_secs(
+                            REFERENCE_SECS - (id as i64 + 1) * DAY_SECS
+                        ))
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_recency_scorer_future_and_missing_column() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let published = schema_builder.add_date_field("published", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(TantivyDocument::new())?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(published => DateTime::from_timestamp_secs(DAY_SECS)))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let recency_scorer = RecencyScorer::new(
+            "published",
+            DateTime::from_timestamp_secs(0),
+            Duration::from_secs(3_600),
+        )
+        .missing(0.25);
+        let top_docs = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(10).custom_score(recency_scorer),
+        )?;
+        let scores: Vec<f64> = top_docs.into_iter().map(|(score, _)| score).collect();
+        // The document published after the reference time is not boosted above 1.0.
+        assert_eq!(scores, [1.0, 0.25]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recency_scorer_block_bounds() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let published = schema_builder.add_date_field(
+            "published",
+            DateOptions::from(FAST).set_precision(DateTimePrecision::Nanoseconds),
+        );
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..2_000i64 {
+            if i % 7 == 0 {
+                index_writer.add_document(TantivyDocument::new())?;
+            } else {
+                // Published every millisecond, so that the column is encoded by blocks.
+                let published_nanos = REFERENCE_SECS * 1_000_000_000 - i * 1_000_000;
+                index_writer.add_document(
+                    doc!(published => DateTime::from_timestamp_nanos(published_nanos)),
+                )?;
+            }
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+        let recency_scorer = RecencyScorer::new(
+            "published",
+            DateTime::from_timestamp_secs(REFERENCE_SECS),
+            Duration::from_secs(1),
+        )
+        .missing(0.5);
+
+        let mut segment_scorer = recency_scorer.segment_scorer(segment_reader)?;
+        for doc_range in [0..2_000, 0..1, 5..64, 700..1_400] {
+            let (min_score, max_score) = segment_scorer
+                .as_block_bounded()
+                .unwrap()
+                .block_bounds(doc_range.clone())
+                .unwrap();
+            for doc in doc_range {
+                let score = segment_scorer.score(doc);
+                assert!(min_score <= score && score <= max_score);
+            }
+        }
+
+        // Scoring one doc at a time does not skip any doc.
+        let exhaustive_scorer = recency_scorer.clone();
+        let exhaustive_top_docs = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(10).custom_score(move |segment_reader: &SegmentReader| {
+                let mut segment_scorer = exhaustive_scorer.segment_scorer(segment_reader).unwrap();
+                move |doc: DocId| segment_scorer.score(doc)
+            }),
+        )?;
+        let collector = CustomScoreTopCollector::new(recency_scorer, TopCollector::with_limit(10));
+        let mut segment_collector = collector.for_segment(0, segment_reader)?;
+        segment_collector.collect_all_docs(segment_reader.max_doc(), None);
+        assert!(segment_collector.num_skipped_docs() > 0);
+        assert_eq!(segment_collector.harvest(), exhaustive_top_docs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recency_boost_missing_and_extreme_dates() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let published = schema_builder.add_date_field(
+            "published",
+            DateOptions::from(FAST).set_precision(DateTimePrecision::Nanoseconds),
+        );
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let reference_time = DateTime::from_timestamp_secs(REFERENCE_SECS);
+        // Missing, oldest, newest, at the reference time, and one half-life old.
+        let dates = [
+            None,
+            Some(DateTime::from_timestamp_nanos(i64::MIN)),
+            Some(DateTime::from_timestamp_nanos(i64::MAX)),
+            Some(reference_time),
+            Some(DateTime::from_timestamp_secs(REFERENCE_SECS - DAY_SECS)),
+        ];
+        for date in dates {
+            let mut doc = doc!(text => "hello");
+            if let Some(date) = date {
+                doc.add_date(published, date);
+            }
+            index_writer.add_document(doc)?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let query = QueryParser::for_index(&index, vec![text]).parse_query("hello")?;
+        let unboosted_score = searcher.search(&query, &TopDocs::with_limit(1))?[0].0;
+
+        let boosted_scores = |half_life: Duration| -> crate::Result<Vec<Score>> {
+            let recency_boost =
+                RecencyBoost::new("published", half_life, 3.0).reference_time(reference_time);
+            let mut top_docs =
+                searcher.search(&query, &TopDocs::with_limit(10).tweak_score(recency_boost))?;
+            top_docs.sort_by_key(|(_, doc_address)| *doc_address);
+            Ok(top_docs.into_iter().map(|(score, _)| score).collect())
+        };
+        let scores = boosted_scores(Duration::from_secs(DAY_SECS as u64))?;
+        let boosts: Vec<Score> = scores.iter().map(|score| score / unboosted_score).collect();
+        let expected_boosts = [1.0, 1.0, 3.0, 3.0, 2.0];
+        for (boost, expected_boost) in boosts.iter().zip(expected_boosts) {
+            assert!((boost - expected_boost).abs() < 1e-5, "{boosts:?}");
+        }
+        for half_life in [Duration::from_nanos(1), Duration::MAX] {
+            for score in boosted_scores(half_life)? {
+                assert!(score.is_finite());
+                assert!(score >= unboosted_score && score <= 3.0 * unboosted_score);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_recency_boost_invalid() -> crate::Result<()> {
+        let index = test_index(DateTimePrecision::Seconds)?;
+        let searcher = index.reader()?.searcher();
+        for max_boost in [0.5, f64::NAN, f64::INFINITY, 1e300] {
+            let recency_boost = RecencyBoost::new("published", Duration::from_secs(1), max_boost);
+            let search_res = searcher.search(
+                &AllQuery,
+                &TopDocs::with_limit(1).tweak_score(recency_boost),
+            );
+            assert!(matches!(search_res, Err(TantivyError::InvalidArgument(_))));
+        }
+        let recency_boost = RecencyBoost::new("id", Duration::from_secs(1), 2.0);
+        let search_res = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(1).tweak_score(recency_boost),
+        );
+        assert!(matches!(search_res, Err(TantivyError::InvalidArgument(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_recency_scorer_invalid() -> crate::Result<()> {
+        let index = test_index(DateTimePrecision::Seconds)?;
+        let searcher = index.reader()?.searcher();
+        let reference_time = DateTime::from_timestamp_secs(REFERENCE_SECS);
+        for recency_scorer in [
+            RecencyScorer::new("unknown", reference_time, Duration::from_secs(1)),
+            RecencyScorer::new("id", reference_time, Duration::from_secs(1)),
+            RecencyScorer::new("published", reference_time, Duration::ZERO),
+        ] {
+            let search_res = searcher.search(
+                &AllQuery,
+                &TopDocs::with_limit(1).custom_score(recency_scorer),
+            );
+            assert!(matches!(search_res, Err(TantivyError::InvalidArgument(_))));
+        }
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 100);
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use crate::collector::custom_score_top_collector::{CustomScorer, CustomSegmentScorer};
+use crate::collector::{Collector, SegmentCollector};
+use crate::query::Weight;
+use crate::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader, COLLECT_BLOCK_BUFFER_LEN};
+
+/// Collector re-scoring the top documents of a first, cheaper, collector with an expensive
+/// [`CustomScorer`].
+///
+/// The first phase keeps the top `window` documents of each segment according to the cheap
+/// score, and merges them into the global top `window` documents. Only these candidates are then
+/// re-scored, segment by segment and by blocks of sorted docs, and sorted by their new score
+/// before the final `offset` and `limit` are applied.
+///
+/// Its fruit carries, for each document, its re-scored score, its first phase score and its
+/// address.
+///
+/// It is built via [`TopDocs::with_rescoring`](crate::collector::TopDocs::with_rescoring) or
+/// [`CustomScoreTopCollector::with_rescoring`](crate::collector::CustomScoreTopCollector::with_rescoring).
+pub struct RescoreTopCollector<TCollector, TRescorer, TRescore = Score> {
+    first_phase: TCollector,
+    rescorer: TRescorer,
+    limit: usize,
+    offset: usize,
+    _marker: PhantomData<TRescore>,
+}
+
+impl<TCollector, TRescorer, TRescore> RescoreTopCollector<TCollector, TRescorer, TRescore> {
+    /// Creates a collector re-scoring the documents collected by `first_phase`, which keeps the
+    /// top `window` documents.
+    pub(crate) fn new(
+        first_phase: TCollector,
+        rescorer: TRescorer,
+        window: usize,
+        limit: usize,
+        offset: usize,
+    ) -> crate::Result<RescoreTopCollector<TCollector, TRescorer, TRescore>> {
+        if window < limit + offset {
+            return Err(crate::TantivyError::InvalidArgument(format!(
+                "The rescore window ({window}) must not be smaller than the limit ({limit}) plus \
+                 the offset ({offset})."
+            )));
+        }
+        Ok(RescoreTopCollector {
+            first_phase,
+            rescorer,
+            limit,
+            offset,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Segment collector of a [`RescoreTopCollector`], running the first phase on a segment.
+pub struct RescoreTopSegmentCollector<TSegmentCollector> {
+    segment_collector: TSegmentCollector,
+    segment_ord: SegmentOrdinal,
+    segment_reader: SegmentReader,
+}
+
+/// Candidates of a segment of a [`RescoreTopCollector`], with the reader of the segment to
+/// re-score them once the global top candidates are known.
+pub struct RescoreSegmentFruit<TFruit> {
+    fruit: TFruit,
+    segment_ord: SegmentOrdinal,
+    segment_reader: SegmentReader,
+}
+
+impl<TSegmentCollector> SegmentCollector for RescoreTopSegmentCollector<TSegmentCollector>
+where TSegmentCollector: SegmentCollector
+{
+    type Fruit = RescoreSegmentFruit<TSegmentCollector::Fruit>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.segment_collector.collect(doc, score);
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        self.segment_collector.collect_block(docs);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        RescoreSegmentFruit {
+            fruit: self.segment_collector.harvest(),
+            segment_ord: self.segment_ord,
+            segment_reader: self.segment_reader,
+        }
+    }
+}
+
+impl<TCollector, TRescorer, TScore, TRescore> Collector
+    for RescoreTopCollector<TCollector, TRescorer, TRescore>
+where
+    TCollector: Collector<Fruit = Vec<(TScore, DocAddress)>>,
+    <TCollector::Child as SegmentCollector>::Fruit: Send,
+    TRescorer: CustomScorer<TRescore> + Send + Sync,
+    TScore: 'static + Send + Sync + Clone + PartialOrd,
+    TRescore: 'static + Send + Sync + Clone + PartialOrd,
+{
+    type Fruit = Vec<(TRescore, TScore, DocAddress)>;
+
+    type Child = RescoreTopSegmentCollector<TCollector::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        Ok(RescoreTopSegmentCollector {
+            segment_collector: self
+                .first_phase
+                .for_segment(segment_local_id, segment_reader)?,
+            segment_ord: segment_local_id,
+            segment_reader: segment_reader.clone(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.first_phase.requires_scoring()
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        Ok(RescoreSegmentFruit {
+            fruit: self
+                .first_phase
+                .collect_segment(weight, segment_ord, reader)?,
+            segment_ord,
+            segment_reader: reader.clone(),
+        })
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> crate::Result<Self::Fruit> {
+        let mut segment_readers = BTreeMap::new();
+        let mut first_phase_fruits = Vec::with_capacity(segment_fruits.len());
+        for segment_fruit in segment_fruits {
+            segment_readers.insert(segment_fruit.segment_ord, segment_fruit.segment_reader);
+            first_phase_fruits.push(segment_fruit.fruit);
+        }
+        let candidates = self.first_phase.merge_fruits(first_phase_fruits)?;
+
+        // The candidates are re-scored segment by segment, in the order of their doc ids.
+        let mut candidates_per_segment: BTreeMap<SegmentOrdinal, Vec<(DocId, TScore)>> =
+            BTreeMap::new();
+        for (score, doc_address) in candidates {
+            candidates_per_segment
+                .entry(doc_address.segment_ord)
+                .or_default()
+                .push((doc_address.doc_id, score));
+        }
+        let mut hits = Vec::new();
+        let mut rescores = Vec::with_capacity(COLLECT_BLOCK_BUFFER_LEN);
+        for (segment_ord, mut candidates) in candidates_per_segment {
+            let segment_reader = segment_readers.get(&segment_ord).ok_or_else(|| {
+                crate::TantivyError::InternalError(format!(
+                    "No reader for the segment {segment_ord} of a candidate to re-score."
+                ))
+            })?;
+            let Some(mut segment_scorer) = self.rescorer.segment_scorer_opt(segment_reader)? else {
+                continue;
+            };
+            candidates.sort_unstable_by_key(|(doc, _)| *doc);
+            let mut docs = Vec::with_capacity(COLLECT_BLOCK_BUFFER_LEN);
+            for block in candidates.chunks(COLLECT_BLOCK_BUFFER_LEN) {
+                docs.clear();
+                docs.extend(block.iter().map(|(doc, _)| *doc));
+                rescores.clear();
+                segment_scorer.score_block(&docs, &mut rescores);
+                for ((doc_id, score), rescore) in block.iter().zip(rescores.drain(..)) {
+                    hits.push((
+                        rescore,
+                        score.clone(),
+                        DocAddress::new(segment_ord, *doc_id),
+                    ));
+                }
+            }
+        }
+        // Ties are broken by the doc address, like for the other top collectors.
+        hits.sort_by(
+            |(left_rescore, _, left_doc), (right_rescore, _, right_doc)| {
+                right_rescore
+                    .partial_cmp(left_rescore)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| left_doc.cmp(right_doc))
+            },
+        );
+        Ok(hits
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::collector::TopDocs;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, QueryParser};
+    use crate::schema::{Schema, FAST, TEXT};
+    use crate::{DocId, Index, IndexWriter, SegmentReader, TantivyError};
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let embedding = schema_builder.add_u64_field("embedding", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        // Segments of uneven sizes, so that they contribute unevenly to the candidates.
+        let mut i = 0u64;
+        for num_docs in [300u64, 20, 120] {
+            for _ in 0..num_docs {
+                let body = match i % 5 {
+                    0 => "apple apple apple banana",
+                    1 => "apple banana",
+                    2 => "apple banana banana cherry cherry",
+                    3 => "apple",
+                    _ => "banana cherry",
+                };
+                index_writer.add_document(doc!(text => body, embedding => (i * 37) % 101))?;
+                i += 1;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    /// Expensive scorer, counting the documents it scores.
+    fn expensive_scorer(
+        num_scored: Arc<AtomicUsize>,
+    ) -> impl Fn(&SegmentReader) -> Box<dyn FnMut(DocId) -> u64> + Send + Sync {
+        move |segment_reader: &SegmentReader| {
+            let embedding = segment_reader
+                .fast_fields()
+                .u64("embedding")
+                .unwrap()
+                .first_or_default_col(0);
+            let num_scored = num_scored.clone();
+            Box::new(move |doc: DocId| {
+                num_scored.fetch_add(1, Ordering::Relaxed);
+                embedding.get_val(doc)
+            })
+        }
+    }
+
+    #[test]
+    fn test_rescoring_with_large_window_equals_exhaustive_scoring() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let num_scored = Arc::new(AtomicUsize::new(0));
+
+        let exhaustive = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(20)
+                .and_offset(5)
+                .custom_score(expensive_scorer(num_scored.clone())),
+        )?;
+        let collector = TopDocs::with_limit(20)
+            .and_offset(5)
+            .with_rescoring(expensive_scorer(num_scored.clone()), 1_000)?;
+        let rescored = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(
+            rescored
+                .iter()
+                .map(|(rescore, _, doc_address)| (*rescore, *doc_address))
+                .collect::<Vec<_>>(),
+            exhaustive
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescoring_scores_at_most_window_docs() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text")?;
+        let query = QueryParser::for_index(&index, vec![text]).parse_query("apple")?;
+        let num_scored = Arc::new(AtomicUsize::new(0));
+        let window = 30;
+        let collector =
+            TopDocs::with_limit(10).with_rescoring(expensive_scorer(num_scored.clone()), window)?;
+        let rescored = searcher.search(&query, &collector)?;
+        assert_eq!(num_scored.load(Ordering::Relaxed), window);
+        assert_eq!(rescored.len(), 10);
+
+        // The candidates are the top `window` documents by BM25 score.
+        let first_phase = searcher.search(&query, &TopDocs::with_limit(window))?;
+        let min_bm25 = first_phase.last().unwrap().0;
+        for (rescore, bm25, doc_address) in &rescored {
+            assert!(*bm25 >= min_bm25);
+            assert!(first_phase.contains(&(*bm25, *doc_address)));
+            assert!(*rescore >= rescored.last().unwrap().0);
+        }
+        let mut expected: Vec<_> = first_phase
+            .iter()
+            .map(|(_, doc_address)| {
+                let embedding = searcher
+                    .segment_reader(doc_address.segment_ord)
+                    .fast_fields()
+                    .u64("embedding")
+                    .unwrap()
+                    .first(doc_address.doc_id)
+                    .unwrap();
+                (embedding, *doc_address)
+            })
+            .collect();
+        expected.sort_by(|left, right| right.0.cmp(&left.0).then(left.1.cmp(&right.1)));
+        expected.truncate(10);
+        assert_eq!(
+            rescored
+                .iter()
+                .map(|(rescore, _, doc_address)| (*rescore, *doc_address))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescoring_custom_score_first_phase() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let num_scored = Arc::new(AtomicUsize::new(0));
+        // The cheap score favors the last docs of each segment.
+        let collector = TopDocs::with_limit(5)
+            .custom_score(|_: &SegmentReader| |doc: DocId| doc)
+            .with_rescoring(expensive_scorer(num_scored.clone()), 50)?;
+        let rescored = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(rescored.len(), 5);
+        assert_eq!(num_scored.load(Ordering::Relaxed), 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescoring_window_smaller_than_limit() {
+        let num_scored = Arc::new(AtomicUsize::new(0));
+        let res = TopDocs::with_limit(10)
+            .and_offset(5)
+            .with_rescoring(expensive_scorer(num_scored), 12);
+        assert!(matches!(res, Err(TantivyError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,123 @@
+/// Normalization applied to the scores of the top documents once the results of all segments
+/// have been merged.
+///
+/// Normalized scores make it possible to combine the results of several queries whose raw
+/// scores are not on the same scale, e.g. a BM25 query and a fast field based ranking.
+///
+/// The normalization is computed over the globally merged candidate list, i.e. the top
+/// `limit + offset` documents, before the offset is applied. Paging through the results with
+/// an offset therefore yields consistent normalized scores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// `(score - min) / (max - min)`, where `min` and `max` are the lowest and highest scores of
+    /// the candidates.
+    ///
+    /// If all the candidates have the same score, their normalized score is `1.0`.
+    MinMax,
+    /// `(score - mean) / std_dev`, where `mean` and `std_dev` are the mean and the population
+    /// standard deviation of the scores of the candidates.
+    ///
+    /// If all the candidates have the same score, their normalized score is `0.0`.
+    ZScore,
+    /// Reciprocal rank fusion: `1 / (k + rank)`, where `rank` is the 1-based position of the
+    /// document in the merged candidate list.
+    ///
+    /// The raw score is only used to rank the candidates.
+    Rrf {
+        /// Smoothing constant. `60` is the value suggested by the original paper.
+        k: u32,
+    },
+}
+
+impl Normalization {
+    /// Normalizes the given scores, sorted by decreasing relevance.
+    ///
+    /// `NaN` scores are ignored when computing the statistics of the candidates and are
+    /// normalized to `NaN` by [`Normalization::MinMax`] and [`Normalization::ZScore`].
+    pub(crate) fn normalize(self, scores: &[f64]) -> Vec<f32> {
+        match self {
+            Normalization::MinMax => {
+                let (min, max) = scores
+                    .iter()
+                    .filter(|score| !score.is_nan())
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &score| {
+                        (min.min(score), max.max(score))
+                    });
+                let range = max - min;
+                scores
+                    .iter()
+                    .map(|&score| {
+                        if score.is_nan() {
+                            f32::NAN
+                        } else if range > 0.0 {
+                            ((score - min) / range) as f32
+                        } else {
+                            1.0
+                        }
+                    })
+                    .collect()
+            }
+            Normalization::ZScore => {
+                let (count, sum) = scores
+                    .iter()
+                    .filter(|score| !score.is_nan())
+                    .fold((0usize, 0.0f64), |(count, sum), &score| {
+                        (count + 1, sum + score)
+                    });
+                let mean = if count == 0 { 0.0 } else { sum / count as f64 };
+                let variance = scores
+                    .iter()
+                    .filter(|score| !score.is_nan())
+                    .map(|&score| (score - mean) * (score - mean))
+                    .sum::<f64>()
+                    / count.max(1) as f64;
+                let std_dev = variance.sqrt();
+                scores
+                    .iter()
+                    .map(|&score| {
+                        if score.is_nan() {
+                            f32::NAN
+                        } else if std_dev > 0.0 {
+                            ((score - mean) / std_dev) as f32
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            }
+            Normalization::Rrf { k } => (1..=scores.len())
+                .map(|rank| (1.0 / (k as f64 + rank as f64)) as f32)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Normalization;
+
+    #[test]
+    fn test_min_max_normalization() {
+        let normalized = Normalization::MinMax.normalize(&[5.0, 4.0, 2.0, 1.0]);
+        assert_eq!(normalized, vec![1.0, 0.75, 0.25, 0.0]);
+        assert_eq!(Normalization::MinMax.normalize(&[3.0, 3.0]), vec![1.0, 1.0]);
+        let normalized = Normalization::MinMax.normalize(&[3.0, 1.0, f64::NAN]);
+        assert_eq!(&normalized[..2], &[1.0, 0.0]);
+        assert!(normalized[2].is_nan());
+    }
+
+    #[test]
+    fn test_z_score_normalization() {
+        // mean = 5, population std dev = 2
+        let normalized = Normalization::ZScore.normalize(&[9.0, 7.0, 5.0, 5.0, 4.0, 4.0, 4.0, 2.0]);
+        assert_eq!(normalized, vec![2.0, 1.0, 0.0, 0.0, -0.5, -0.5, -0.5, -1.5]);
+        assert_eq!(Normalization::ZScore.normalize(&[3.0, 3.0]), vec![0.0, 0.0]);
+        assert!(Normalization::ZScore.normalize(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_rrf_normalization() {
+        let normalized = Normalization::Rrf { k: 60 }.normalize(&[10.0, 10.0, 0.5]);
+        assert_eq!(normalized, vec![1.0 / 61.0, 1.0 / 62.0, 1.0 / 63.0]);
+    }
+}
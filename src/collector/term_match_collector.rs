@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::top_score_collector::TopScoreSegmentCollector;
+use super::{Collector, SegmentCollector, TopDocs};
+use crate::query::term_matches::merge_term_matches;
+use crate::query::{TermMatch, Weight};
+use crate::schema::Field;
+use crate::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// The `TopDocsWithTermMatches` collector keeps track of the top `K` documents sorted by their
+/// score, and captures the occurrences of the terms of the query in these documents, e.g. to
+/// highlight them.
+///
+/// The positions are only read for the documents surviving the top `K` of their segment, by
+/// seeking the positions of the terms once the segment has been scanned.
+///
+/// Term, phrase and boolean queries support capturing their matches. The documents matched by
+/// other queries get empty matches, and [`TermMatchHits::unsupported_query`] is set.
+///
+/// See [`TopDocs::capture_term_matches`].
+pub struct TopDocsWithTermMatches {
+    top_docs: TopDocs,
+    fields: Vec<Field>,
+}
+
+impl TopDocsWithTermMatches {
+    pub(crate) fn new(top_docs: TopDocs, fields: Vec<Field>) -> TopDocsWithTermMatches {
+        TopDocsWithTermMatches { top_docs, fields }
+    }
+}
+
+impl fmt::Debug for TopDocsWithTermMatches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TopDocsWithTermMatches({:?}, fields={:?})",
+            self.top_docs, self.fields
+        )
+    }
+}
+
+/// Fruit of the [`TopDocsWithTermMatches`] collector.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TermMatchHits {
+    /// The top documents, sorted by decreasing score, with the matches of the query terms in
+    /// each of them, sorted by term.
+    pub hits: Vec<(Score, DocAddress, Vec<TermMatch>)>,
+    /// `true` if a part of the query does not support capturing its matches, in which case the
+    /// matches of the hits may be incomplete.
+    pub unsupported_query: bool,
+}
+
+impl Collector for TopDocsWithTermMatches {
+    type Fruit = TermMatchHits;
+
+    type Child = TermMatchSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        let top_docs = self.top_docs.for_segment(segment_local_id, reader)?;
+        Ok(TermMatchSegmentCollector { top_docs })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, child_fruits: Vec<TermMatchHits>) -> crate::Result<TermMatchHits> {
+        let mut unsupported_query = false;
+        let mut term_matches: HashMap<DocAddress, Vec<TermMatch>> = HashMap::new();
+        let mut scored_docs = Vec::with_capacity(child_fruits.len());
+        for child_fruit in child_fruits {
+            unsupported_query |= child_fruit.unsupported_query;
+            let mut child_scored_docs = Vec::with_capacity(child_fruit.hits.len());
+            for (score, doc_address, doc_term_matches) in child_fruit.hits {
+                child_scored_docs.push((score, doc_address));
+                term_matches.insert(doc_address, doc_term_matches);
+            }
+            scored_docs.push(child_scored_docs);
+        }
+        let hits = self
+            .top_docs
+            .merge_fruits(scored_docs)?
+            .into_iter()
+            .map(|(score, doc_address)| {
+                let doc_term_matches = term_matches.remove(&doc_address).unwrap_or_default();
+                (score, doc_address, doc_term_matches)
+            })
+            .collect();
+        Ok(TermMatchHits {
+            hits,
+            unsupported_query,
+        })
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<TermMatchHits> {
+        let scored_docs = self.top_docs.collect_segment(weight, segment_ord, reader)?;
+        let mut unsupported_query = false;
+        let mut hits = Vec::with_capacity(scored_docs.len());
+        for (score, doc_address) in scored_docs {
+            let mut term_matches = Vec::new();
+            unsupported_query |= !weight.term_matches(
+                reader,
+                doc_address.doc_id,
+                &self.fields,
+                &mut term_matches,
+            )?;
+            merge_term_matches(&mut term_matches);
+            hits.push((score, doc_address, term_matches));
+        }
+        Ok(TermMatchHits {
+            hits,
+            unsupported_query,
+        })
+    }
+}
+
+/// Segment Collector associated with `TopDocsWithTermMatches`.
+///
+/// The matches are captured by [`TopDocsWithTermMatches::collect_segment`], which has access to
+/// the weight of the query. Harvesting this segment collector directly, e.g. as part of a
+/// [`MultiCollector`](super::MultiCollector), yields empty matches and flags the query as
+/// unsupported.
+pub struct TermMatchSegmentCollector {
+    top_docs: TopScoreSegmentCollector,
+}
+
+impl SegmentCollector for TermMatchSegmentCollector {
+    type Fruit = TermMatchHits;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.top_docs.collect(doc, score);
+    }
+
+    fn harvest(self) -> TermMatchHits {
+        let hits = self
+            .top_docs
+            .harvest()
+            .into_iter()
+            .map(|(score, doc_address)| (score, doc_address, Vec::new()))
+            .collect();
+        TermMatchHits {
+            hits,
+            unsupported_query: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ops::Bound;
+
+    use super::TermMatchHits;
+    use crate::collector::{MultiCollector, TopDocs};
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{BooleanQuery, Occur, Query, QueryParser, RangeQuery, TermQuery};
+    use crate::schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, Searcher, TantivyDocument, Term};
+
+    fn create_index() -> crate::Result<(Index, Field, Field)> {
+        let mut schema_builder = Schema::builder();
+        let body = schema_builder.add_text_field("body", TEXT | STORED);
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_u64_field("year", INDEXED | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(
+            title => "The quick fox",
+            body => "The quick brown fox jumps over the lazy dog",
+        ))?;
+        index_writer.add_document(doc!(
+            title => "Brown",
+            body => "a brown quick fox, then a quick brown dog and a quick  brown cat",
+        ))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(
+            title => "Lazy",
+            body => "lazy dog sleeping while the fox runs",
+        ))?;
+        index_writer.add_document(doc!(
+            title => "Quick brown",
+            body => "nothing to see here",
+        ))?;
+        index_writer.commit()?;
+        Ok((index, body, title))
+    }
+
+    /// Title of a hit, with the positions captured for each of the matched terms.
+    type HitSpans = (String, Vec<(String, Vec<u32>)>);
+
+    /// Returns the tokens of the stored value of `field`, by position.
+    fn stored_tokens(
+        index: &Index,
+        searcher: &Searcher,
+        doc_address: DocAddress,
+        field: Field,
+    ) -> crate::Result<HashMap<u32, String>> {
+        let doc: TantivyDocument = searcher.doc(doc_address)?;
+        let text = doc
+            .get_first(field)
+            .and_then(|value| value.as_str())
+            .unwrap();
+        let mut tokenizer = index.tokenizer_for_field(field)?;
+        let mut token_stream = tokenizer.token_stream(text);
+        let mut tokens = HashMap::new();
+        token_stream.process(&mut |token| {
+            tokens.insert(token.position as u32, token.text.clone());
+        });
+        Ok(tokens)
+    }
+
+    /// Checks that each captured position holds the matched term in the stored text, and returns
+    /// the captured positions by term, for each hit identified by its title.
+    fn check_spans(
+        index: &Index,
+        searcher: &Searcher,
+        top_docs: &TermMatchHits,
+    ) -> crate::Result<Vec<HitSpans>> {
+        let title = index.schema().get_field("title")?;
+        let mut spans = Vec::new();
+        for (_score, doc_address, term_matches) in &top_docs.hits {
+            let mut doc_spans = Vec::new();
+            for term_match in term_matches {
+                let tokens = stored_tokens(index, searcher, *doc_address, term_match.field())?;
+                let text = term_match.term.value().as_str().unwrap().to_string();
+                let mut positions = Vec::new();
+                for range in &term_match.positions {
+                    for position in range.clone() {
+                        assert_eq!(tokens.get(&position), Some(&text));
+                        positions.push(position);
+                    }
+                }
+                doc_spans.push((text, positions));
+            }
+            let doc: TantivyDocument = searcher.doc(*doc_address)?;
+            let title = doc
+                .get_first(title)
+                .and_then(|value| value.as_str())
+                .unwrap();
+            spans.push((title.to_string(), doc_spans));
+        }
+        Ok(spans)
+    }
+
+    fn spans_of(title: &str, matches: &[(&str, &[u32])]) -> HitSpans {
+        let matches = matches
+            .iter()
+            .map(|(text, positions)| (text.to_string(), positions.to_vec()))
+            .collect();
+        (title.to_string(), matches)
+    }
+
+    #[test]
+    fn test_capture_phrase_matches() -> crate::Result<()> {
+        let (index, body, title) = create_index()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 2);
+        let query =
+            QueryParser::for_index(&index, vec![body, title]).parse_query("\"quick brown\"")?;
+        let collector = TopDocs::with_limit(10).capture_term_matches(vec![body, title]);
+        let top_docs = searcher.search(&query, &collector)?;
+        assert!(!top_docs.unsupported_query);
+
+        let mut spans = check_spans(&index, &searcher, &top_docs)?;
+        spans.sort();
+        assert_eq!(
+            spans,
+            vec![
+                spans_of("Brown", &[("brown", &[7, 12]), ("quick", &[6, 11])]),
+                spans_of("Quick brown", &[("brown", &[1]), ("quick", &[0])]),
+                spans_of("The quick fox", &[("brown", &[2]), ("quick", &[1])]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_boolean_matches() -> crate::Result<()> {
+        let (index, body, title) = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let query = QueryParser::for_index(&index, vec![body]).parse_query("+fox lazy -cat")?;
+        let collector = TopDocs::with_limit(2).capture_term_matches(vec![body, title]);
+        let top_docs = searcher.search(&query, &collector)?;
+        assert!(!top_docs.unsupported_query);
+        assert_eq!(top_docs.hits.len(), 2);
+
+        let mut spans = check_spans(&index, &searcher, &top_docs)?;
+        spans.sort();
+        assert_eq!(
+            spans,
+            vec![
+                spans_of("Lazy", &[("fox", &[5]), ("lazy", &[0])]),
+                spans_of("The quick fox", &[("fox", &[3]), ("lazy", &[7])]),
+            ]
+        );
+
+        // Only the terms of the requested fields are captured.
+        let collector = TopDocs::with_limit(2).capture_term_matches(vec![title]);
+        let top_docs = searcher.search(&query, &collector)?;
+        assert!(top_docs
+            .hits
+            .iter()
+            .all(|(_, _, term_matches)| term_matches.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_matches_same_hits_as_top_docs() -> crate::Result<()> {
+        let (index, body, _title) = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let query = QueryParser::for_index(&index, vec![body]).parse_query("quick OR dog")?;
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(2).and_offset(1))?;
+        let with_matches = searcher.search(
+            &query,
+            &TopDocs::with_limit(2)
+                .and_offset(1)
+                .capture_term_matches(vec![body]),
+        )?;
+        let hits: Vec<_> = with_matches
+            .hits
+            .iter()
+            .map(|(score, doc_address, _)| (*score, *doc_address))
+            .collect();
+        assert_eq!(hits, top_docs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_matches_unsupported_query() -> crate::Result<()> {
+        let (index, body, _title) = create_index()?;
+        let schema = index.schema();
+        let year = schema.get_field("year")?;
+        let searcher = index.reader()?.searcher();
+        let collector = TopDocs::with_limit(10).capture_term_matches(vec![body]);
+
+        let range_query = RangeQuery::new(
+            Bound::Included(Term::from_field_u64(year, 0)),
+            Bound::Unbounded,
+        );
+        let query = BooleanQuery::new(vec![
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(body, "lazy"),
+                    IndexRecordOption::WithFreqsAndPositions,
+                )) as Box<dyn Query>,
+            ),
+            (Occur::Should, Box::new(range_query.clone())),
+        ]);
+        let top_docs = searcher.search(&query, &collector)?;
+        assert!(top_docs.unsupported_query);
+        assert_eq!(top_docs.hits.len(), 2);
+        for (_, _, term_matches) in &top_docs.hits {
+            assert_eq!(term_matches.len(), 1);
+        }
+        check_spans(&index, &searcher, &top_docs)?;
+
+        let phrase_query =
+            QueryParser::for_index(&index, vec![body]).parse_query("\"quick fox\"~1")?;
+        let top_docs = searcher.search(&phrase_query, &collector)?;
+        assert!(top_docs.unsupported_query);
+        assert!(!top_docs.hits.is_empty());
+        assert!(top_docs
+            .hits
+            .iter()
+            .all(|(_, _, term_matches)| term_matches.is_empty()));
+
+        // The segment collectors do not have access to the weight of the query.
+        let mut multi_collector = MultiCollector::new();
+        let handle = multi_collector.add_collector(collector);
+        let mut multi_fruit = searcher.search(&query, &multi_collector)?;
+        let top_docs = handle.extract(&mut multi_fruit);
+        assert!(top_docs.unsupported_query);
+        assert_eq!(top_docs.hits.len(), 2);
+        Ok(())
+    }
+}
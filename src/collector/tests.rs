@@ -304,3 +304,131 @@ fn test_option_collector_none() -> crate::Result<()> {
     assert_eq!(counts, None);
     Ok(())
 }
+
+/// Collects the term ordinals of a string fast field, and resolves them when merging.
+struct TermOrdinalCollector {
+    field: String,
+    /// Called when the first segment is collected.
+    on_collect: std::sync::Once,
+    reload: Box<dyn Fn() + Send + Sync>,
+    merge_generation_id: std::sync::Mutex<Option<u64>>,
+}
+
+impl Collector for TermOrdinalCollector {
+    type Fruit = Vec<String>;
+    type Child = TermOrdinalSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> crate::Result<TermOrdinalSegmentCollector> {
+        self.on_collect.call_once(|| (self.reload)());
+        Ok(TermOrdinalSegmentCollector {
+            segment_ord: segment_local_id,
+            segment_id: segment.segment_id(),
+            column: segment.fast_fields().str(&self.field)?.unwrap(),
+            term_ords: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, _segment_fruits: Vec<TermOrdinalFruit>) -> crate::Result<Vec<String>> {
+        panic!("The searcher should merge the fruits with merge_fruits_with_searcher");
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<TermOrdinalFruit>,
+        searcher: &Searcher,
+    ) -> crate::Result<Vec<String>> {
+        *self.merge_generation_id.lock().unwrap() = Some(searcher.generation().generation_id());
+        let mut values = Vec::new();
+        for (segment_ord, segment_id, term_ords) in segment_fruits {
+            let segment_reader = searcher.segment_reader(segment_ord);
+            assert_eq!(segment_reader.segment_id(), segment_id);
+            let column = segment_reader.fast_fields().str(&self.field)?.unwrap();
+            for term_ord in term_ords {
+                let mut value = String::new();
+                assert!(column.ord_to_str(term_ord, &mut value)?);
+                values.push(value);
+            }
+        }
+        values.sort();
+        Ok(values)
+    }
+}
+
+type TermOrdinalFruit = (SegmentOrdinal, crate::index::SegmentId, Vec<u64>);
+
+struct TermOrdinalSegmentCollector {
+    segment_ord: SegmentOrdinal,
+    segment_id: crate::index::SegmentId,
+    column: columnar::StrColumn,
+    term_ords: Vec<u64>,
+}
+
+impl SegmentCollector for TermOrdinalSegmentCollector {
+    type Fruit = TermOrdinalFruit;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        self.term_ords.extend(self.column.term_ords(doc));
+    }
+
+    fn harvest(self) -> TermOrdinalFruit {
+        (self.segment_ord, self.segment_id, self.term_ords)
+    }
+}
+
+#[test]
+fn test_merge_fruits_with_searcher() -> crate::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let city = schema_builder.add_text_field("city", crate::schema::STRING | FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(crate::indexer::NoMergePolicy));
+    for segment_cities in [&["paris", "berlin"][..], &["rome", "berlin"], &["athens"]] {
+        for value in segment_cities {
+            index_writer.add_document(doc!(city => *value))?;
+        }
+        index_writer.commit()?;
+    }
+    let reader = index
+        .reader_builder()
+        .reload_policy(crate::ReloadPolicy::Manual)
+        .try_into()?;
+    let searcher = reader.searcher();
+
+    // The reader is reloaded with a new segment while the segments are collected.
+    let index_writer = std::sync::Mutex::new(index_writer);
+    let reload_reader = reader.clone();
+    let collector = TermOrdinalCollector {
+        field: "city".to_string(),
+        on_collect: std::sync::Once::new(),
+        reload: Box::new(move || {
+            let mut index_writer = index_writer.lock().unwrap();
+            index_writer.add_document(doc!(city => "madrid")).unwrap();
+            index_writer.commit().unwrap();
+            reload_reader.reload().unwrap();
+        }),
+        merge_generation_id: Default::default(),
+    };
+    let values = searcher.search(&AllQuery, &collector)?;
+    assert_eq!(values, vec!["athens", "berlin", "berlin", "paris", "rome"]);
+    let merge_generation_id = collector.merge_generation_id.lock().unwrap().unwrap();
+    assert_eq!(merge_generation_id, searcher.generation().generation_id());
+    assert_ne!(
+        merge_generation_id,
+        reader.searcher().generation().generation_id()
+    );
+    assert_eq!(reader.searcher().segment_readers().len(), 4);
+
+    // The searcher is forwarded through the collector combinators.
+    let (count, values) = searcher.search(&AllQuery, &(Count, Some(collector)))?;
+    assert_eq!(count, 5);
+    assert_eq!(values.unwrap().len(), 5);
+    Ok(())
+}
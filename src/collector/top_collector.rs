@@ -4,8 +4,9 @@ use std::marker::PhantomData;
 use serde::{Deserialize, Serialize};
 
 use super::top_score_collector::TopNComputer;
+use super::Normalization;
 use crate::index::SegmentReader;
-use crate::{DocAddress, DocId, SegmentOrdinal};
+use crate::{DocAddress, DocId, Score, SearchScratch, SegmentOrdinal};
 
 /// Contains a feature (field, score, etc.) of a document along with the document address.
 ///
@@ -36,6 +37,67 @@ impl<T: std::fmt::Debug, D: std::fmt::Debug, const R: bool> std::fmt::Debug
     }
 }
 
+/// Compares two features, the features that are not comparable with themselves, i.e. `NaN`,
+/// being lower than all of the others.
+///
+/// `PartialOrd` gives no order to `NaN`, which would break the total order the top-k selection
+/// relies on, and let a single `NaN` score corrupt the results.
+///
+/// Two features that are comparable with themselves but not with each other are a bug in the
+/// `PartialOrd` implementation of the feature type. They are detected by
+/// [`check_feature_order`] in debug builds, and otherwise considered equal, so that the tie is
+/// broken by the document address and the results stay deterministic.
+#[inline]
+pub(crate) fn cmp_nan_lowest<T: PartialOrd>(left: &T, right: &T) -> Ordering {
+    if let Some(ord) = left.partial_cmp(right) {
+        return ord;
+    }
+    is_nan(right).cmp(&is_nan(left))
+}
+
+#[inline]
+fn is_nan<T: PartialOrd>(feature: &T) -> bool {
+    feature.partial_cmp(feature).is_none()
+}
+
+/// Spot-checks that the `PartialOrd` implementation of a feature type is consistent, on a pair
+/// of features met during the top-k selection.
+///
+/// A feature type violating the `PartialOrd` contract silently corrupts the selection, and
+/// yields missing or duplicated hits. The check panics, naming the feature type, if:
+/// - the two features are not comparable while both are comparable with themselves,
+/// - comparing them in both directions does not give opposite orderings,
+/// - `partial_cmp` and `==` disagree on their equality.
+///
+/// It is only active in debug builds, or with the `paranoid-collectors` feature.
+#[inline]
+pub(crate) fn check_feature_order<T: PartialOrd>(left: &T, right: &T) {
+    if !cfg!(any(debug_assertions, feature = "paranoid-collectors")) {
+        return;
+    }
+    let type_name = std::any::type_name::<T>();
+    let forward = left.partial_cmp(right);
+    let backward = right.partial_cmp(left);
+    if forward.is_none() && !is_nan(left) && !is_nan(right) {
+        panic!(
+            "Inconsistent `PartialOrd` for score type `{type_name}`: two scores comparable with \
+             themselves are not comparable with each other."
+        );
+    }
+    if forward.map(Ordering::reverse) != backward {
+        panic!(
+            "Inconsistent `PartialOrd` for score type `{type_name}`: comparison is not \
+             antisymmetric ({forward:?} vs reversed {backward:?})."
+        );
+    }
+    if (forward == Some(Ordering::Equal)) != (left == right) {
+        panic!(
+            "Inconsistent `PartialOrd` for score type `{type_name}`: `partial_cmp` and `==` \
+             disagree on equality."
+        );
+    }
+}
+
 impl<T: PartialOrd, D: PartialOrd, const R: bool> PartialOrd for ComparableDoc<T, D, R> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -45,11 +107,8 @@ impl<T: PartialOrd, D: PartialOrd, const R: bool> PartialOrd for ComparableDoc<T
 impl<T: PartialOrd, D: PartialOrd, const R: bool> Ord for ComparableDoc<T, D, R> {
     #[inline]
     fn cmp(&self, other: &Self) -> Ordering {
-        let by_feature = self
-            .feature
-            .partial_cmp(&other.feature)
-            .map(|ord| if R { ord.reverse() } else { ord })
-            .unwrap_or(Ordering::Equal);
+        let by_feature = cmp_nan_lowest(&self.feature, &other.feature);
+        let by_feature = if R { by_feature.reverse() } else { by_feature };
 
         let lazy_by_doc_address = || self.doc.partial_cmp(&other.doc).unwrap_or(Ordering::Equal);
 
@@ -68,14 +127,58 @@ impl<T: PartialOrd, D: PartialOrd, const R: bool> PartialEq for ComparableDoc<T,
 
 impl<T: PartialOrd, D: PartialOrd, const R: bool> Eq for ComparableDoc<T, D, R> {}
 
+/// Identifies the position of a hit in the ordering of a top-k collector, so that a following
+/// search can resume right after it (`search_after` pagination).
+///
+/// Hits are sorted by descending score, `NaN` scores coming last. Ties are broken by ascending
+/// [`DocAddress`].
+/// A collector configured with a `SearchAfterKey` skips every document that sorts at or
+/// before the key, which makes deep pagination independent of the number of skipped hits.
+///
+/// The key embeds a `DocAddress`, which is only meaningful for the [`Searcher`](crate::Searcher)
+/// that produced it. Keys must not be reused across searcher generations, as segments may have
+/// been merged in between.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SearchAfterKey<TScore = Score> {
+    /// The score of the hit.
+    pub score: TScore,
+    /// The address of the hit, used to break ties on the score.
+    pub doc_address: DocAddress,
+}
+
+impl<TScore: PartialOrd + Clone> SearchAfterKey<TScore> {
+    /// Creates a new key for the hit with the given score and address.
+    pub fn new(score: TScore, doc_address: DocAddress) -> SearchAfterKey<TScore> {
+        SearchAfterKey { score, doc_address }
+    }
+
+    /// Returns the key of the last hit of a page of results, if any.
+    pub fn from_last_hit(hits: &[(TScore, DocAddress)]) -> Option<SearchAfterKey<TScore>> {
+        hits.last()
+            .map(|(score, doc_address)| SearchAfterKey::new(score.clone(), *doc_address))
+    }
+
+    /// Returns true if a hit with the given score and address comes strictly after the key in
+    /// the results, i.e. belongs to a later page.
+    #[inline]
+    pub(crate) fn is_after_in_results(&self, score: &TScore, doc_address: DocAddress) -> bool {
+        match cmp_nan_lowest(score, &self.score) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => doc_address > self.doc_address,
+        }
+    }
+}
+
 pub(crate) struct TopCollector<T> {
     pub limit: usize,
     pub offset: usize,
+    pub search_after: Option<SearchAfterKey<T>>,
     _marker: PhantomData<T>,
 }
 
 impl<T> TopCollector<T>
-where T: PartialOrd + Clone
+where T: PartialOrd + Clone + 'static
 {
     /// Creates a top collector, with a number of documents equal to "limit".
     ///
@@ -86,6 +189,7 @@ where T: PartialOrd + Clone
         Self {
             limit,
             offset: 0,
+            search_after: None,
             _marker: PhantomData,
         }
     }
@@ -99,38 +203,84 @@ where T: PartialOrd + Clone
         self
     }
 
+    /// Only collect the documents sorting after the given key.
+    pub fn with_search_after(mut self, key: SearchAfterKey<T>) -> TopCollector<T> {
+        self.search_after = Some(key);
+        self
+    }
+
     pub fn merge_fruits(
         &self,
         children: Vec<Vec<(T, DocAddress)>>,
     ) -> crate::Result<Vec<(T, DocAddress)>> {
+        Ok(self
+            .merge_candidates(children)
+            .into_iter()
+            .skip(self.offset)
+            .map(|cdoc| (cdoc.feature, cdoc.doc))
+            .collect())
+    }
+
+    /// Merges the fruits of the segments, and normalizes the scores of the merged candidates
+    /// before the offset is applied.
+    ///
+    /// Each document carries both its raw and its normalized score.
+    pub fn merge_fruits_normalized(
+        &self,
+        children: Vec<Vec<(T, DocAddress)>>,
+        normalization: Normalization,
+    ) -> crate::Result<Vec<(T, f32, DocAddress)>>
+    where
+        T: Into<f64>,
+    {
+        let candidates = self.merge_candidates(children);
+        let raw_scores: Vec<f64> = candidates
+            .iter()
+            .map(|cdoc| cdoc.feature.clone().into())
+            .collect();
+        let normalized_scores = normalization.normalize(&raw_scores);
+        Ok(candidates
+            .into_iter()
+            .zip(normalized_scores)
+            .skip(self.offset)
+            .map(|(cdoc, normalized_score)| (cdoc.feature, normalized_score, cdoc.doc))
+            .collect())
+    }
+
+    /// Returns the top `limit + offset` documents of all segments, sorted by decreasing score.
+    fn merge_candidates(
+        &self,
+        children: Vec<Vec<(T, DocAddress)>>,
+    ) -> Vec<ComparableDoc<T, DocAddress, true>> {
         if self.limit == 0 {
-            return Ok(Vec::new());
+            return Vec::new();
         }
         let mut top_collector: TopNComputer<_, _> = TopNComputer::new(self.limit + self.offset);
         for child_fruit in children {
+            for pair in child_fruit.windows(2) {
+                check_feature_order(&pair[0].0, &pair[1].0);
+            }
             for (feature, doc) in child_fruit {
                 top_collector.push(feature, doc);
             }
         }
-
-        Ok(top_collector
-            .into_sorted_vec()
-            .into_iter()
-            .skip(self.offset)
-            .map(|cdoc| (cdoc.feature, cdoc.doc))
-            .collect())
+        top_collector.into_sorted_vec()
     }
 
-    pub(crate) fn for_segment<F: PartialOrd + Clone>(
+    pub(crate) fn for_segment(
         &self,
         segment_id: SegmentOrdinal,
         _: &SegmentReader,
-    ) -> TopSegmentCollector<F> {
-        TopSegmentCollector::new(segment_id, self.limit + self.offset)
+    ) -> TopSegmentCollector<T> {
+        let mut segment_collector = TopSegmentCollector::new(segment_id, self.limit + self.offset);
+        segment_collector.search_after = self.search_after.clone();
+        segment_collector
     }
 
     /// Create a new TopCollector with the same limit and offset.
     ///
+    /// The search after key is not carried over, since it is bound to the score type.
+    ///
     /// Ideally we would use Into but the blanket implementation seems to cause the Scorer traits
     /// to fail.
     #[doc(hidden)]
@@ -138,6 +288,7 @@ where T: PartialOrd + Clone
         TopCollector {
             limit: self.limit,
             offset: self.offset,
+            search_after: None,
             _marker: PhantomData,
         }
     }
@@ -154,23 +305,27 @@ pub(crate) struct TopSegmentCollector<T> {
     /// have top-semantics instead of bottom semantics.
     topn_computer: TopNComputer<T, DocId>,
     segment_ord: u32,
+    search_after: Option<SearchAfterKey<T>>,
 }
 
-impl<T: PartialOrd + Clone> TopSegmentCollector<T> {
+impl<T: PartialOrd + Clone + 'static> TopSegmentCollector<T> {
     fn new(segment_ord: SegmentOrdinal, limit: usize) -> TopSegmentCollector<T> {
+        // The buffer of the computer is taken from the scratch pool, and returned on harvest.
+        let buffer = SearchScratch::take_vec(limit.max(1) * 2);
         TopSegmentCollector {
-            topn_computer: TopNComputer::new(limit),
+            topn_computer: TopNComputer::with_buffer(limit, buffer),
             segment_ord,
+            search_after: None,
         }
     }
 }
 
-impl<T: PartialOrd + Clone> TopSegmentCollector<T> {
+impl<T: PartialOrd + Clone + 'static> TopSegmentCollector<T> {
     pub fn harvest(self) -> Vec<(T, DocAddress)> {
         let segment_ord = self.segment_ord;
-        self.topn_computer
-            .into_sorted_vec()
-            .into_iter()
+        let mut buffer = self.topn_computer.into_sorted_vec();
+        let fruit = buffer
+            .drain(..)
             .map(|comparable_doc| {
                 (
                     comparable_doc.feature,
@@ -180,7 +335,15 @@ impl<T: PartialOrd + Clone> TopSegmentCollector<T> {
                     },
                 )
             })
-            .collect()
+            .collect();
+        SearchScratch::recycle(buffer);
+        fruit
+    }
+
+    /// Returns the feature below which documents are ignored, once the top docs are full.
+    #[inline]
+    pub fn threshold(&self) -> Option<&T> {
+        self.topn_computer.threshold.as_ref()
     }
 
     /// Collects a document scored by the given feature
@@ -189,13 +352,19 @@ impl<T: PartialOrd + Clone> TopSegmentCollector<T> {
     /// will compare the lowest scoring item with the given one and keep whichever is greater.
     #[inline]
     pub fn collect(&mut self, doc: DocId, feature: T) {
+        if let Some(search_after) = &self.search_after {
+            let doc_address = DocAddress::new(self.segment_ord, doc);
+            if !search_after.is_after_in_results(&feature, doc_address) {
+                return;
+            }
+        }
         self.topn_computer.push(feature, doc);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{TopCollector, TopSegmentCollector};
+    use super::{SearchAfterKey, TopCollector, TopSegmentCollector};
     use crate::DocAddress;
 
     #[test]
@@ -257,6 +426,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_top_segment_collector_search_after() {
+        let key = SearchAfterKey::new(0.5, DocAddress::new(1, 4));
+        let mut top_collector = TopSegmentCollector::new(1, 10);
+        top_collector.search_after = Some(key);
+        top_collector.collect(1, 0.8);
+        top_collector.collect(2, 0.5);
+        top_collector.collect(4, 0.5);
+        top_collector.collect(5, 0.5);
+        top_collector.collect(6, 0.2);
+        assert_eq!(
+            top_collector.harvest(),
+            vec![(0.5, DocAddress::new(1, 5)), (0.2, DocAddress::new(1, 6))]
+        );
+    }
+
     #[test]
     fn test_top_collector_with_limit_and_offset() {
         let collector = TopCollector::with_limit(2).and_offset(1);
@@ -304,6 +489,81 @@ mod tests {
 
         assert_eq!(results, vec![]);
     }
+
+    /// A score type whose distinct values are never comparable, while being comparable with
+    /// themselves.
+    #[derive(Clone, Debug, PartialEq)]
+    struct IncomparableScore(u32);
+
+    impl PartialOrd for IncomparableScore {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            (self.0 == other.0).then_some(std::cmp::Ordering::Equal)
+        }
+    }
+
+    /// A score type ordered by its score only, while `==` also compares the tag.
+    #[derive(Clone, Debug, PartialEq)]
+    struct ScoreOnlyOrd {
+        score: u32,
+        tag: u32,
+    }
+
+    impl PartialOrd for ScoreOnlyOrd {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            self.score.partial_cmp(&other.score)
+        }
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "paranoid-collectors"))]
+    #[should_panic(expected = "IncomparableScore`: two scores comparable with themselves")]
+    fn test_top_segment_collector_detects_incomparable_scores() {
+        let mut top_collector = TopSegmentCollector::new(0, 2);
+        for doc in 0..10 {
+            top_collector.collect(doc, IncomparableScore(doc));
+        }
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "paranoid-collectors"))]
+    #[should_panic(expected = "`partial_cmp` and `==` disagree on equality")]
+    fn test_top_collector_merge_fruits_detects_inconsistent_equality() {
+        let collector = TopCollector::with_limit(2);
+        let _ = collector.merge_fruits(vec![vec![
+            (ScoreOnlyOrd { score: 1, tag: 0 }, DocAddress::new(0, 1)),
+            (ScoreOnlyOrd { score: 1, tag: 1 }, DocAddress::new(0, 2)),
+        ]]);
+    }
+
+    #[test]
+    #[cfg(not(any(debug_assertions, feature = "paranoid-collectors")))]
+    fn test_top_collector_incomparable_scores_are_deterministic() {
+        // Incomparable scores are treated as equal, so the documents are sorted by address.
+        let mut top_collector = TopSegmentCollector::new(0, 3);
+        for doc in (0..10).rev() {
+            top_collector.collect(doc, IncomparableScore(doc));
+        }
+        let fruit = top_collector.harvest();
+        let docs: Vec<u32> = fruit.iter().map(|(_, doc)| doc.doc_id).collect();
+        assert_eq!(docs, vec![0, 1, 2]);
+
+        let collector = TopCollector::with_limit(3);
+        let merged = collector
+            .merge_fruits(vec![
+                fruit,
+                vec![(IncomparableScore(0), DocAddress::new(1, 0))],
+            ])
+            .unwrap();
+        let addresses: Vec<DocAddress> = merged.into_iter().map(|(_, doc)| doc).collect();
+        assert_eq!(
+            addresses,
+            vec![
+                DocAddress::new(0, 0),
+                DocAddress::new(0, 1),
+                DocAddress::new(0, 2)
+            ]
+        );
+    }
 }
 
 #[cfg(all(test, feature = "unstable"))]
@@ -1,19 +1,32 @@
+use std::cmp::Ordering;
 use std::fmt;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::sync::Arc;
 
-use columnar::ColumnValues;
+use columnar::{
+    Column, ColumnType, ColumnValues, ConstantColumnValues, MonotonicallyMappableToU64,
+};
 use serde::{Deserialize, Serialize};
 
 use super::Collector;
+use crate::aggregation::f64_from_fastfield_u64;
 use crate::collector::custom_score_top_collector::CustomScoreTopCollector;
-use crate::collector::top_collector::{ComparableDoc, TopCollector, TopSegmentCollector};
+use crate::collector::rescore_top_collector::RescoreTopCollector;
+use crate::collector::top_collector::{
+    check_feature_order, cmp_nan_lowest, ComparableDoc, SearchAfterKey, TopCollector,
+    TopSegmentCollector,
+};
+use crate::collector::top_string_collector::StringFastFieldTopCollector;
 use crate::collector::tweak_score_top_collector::TweakedScoreTopCollector;
 use crate::collector::{
-    CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector,
+    missing_column_error, BlockBoundedScorer, CustomScorer, CustomSegmentScorer,
+    MissingColumnPolicy, Normalization, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector,
+    TopDocsWithTermMatches,
 };
-use crate::fastfield::{FastFieldNotAvailableError, FastValue};
+use crate::fastfield::{FastFieldNotAvailableError, FastValue, MultiValueMode};
 use crate::query::Weight;
+use crate::schema::Field;
 use crate::{DocAddress, DocId, Order, Score, SegmentOrdinal, SegmentReader, TantivyError};
 
 struct FastFieldConvertCollector<
@@ -83,6 +96,54 @@ where
     }
 }
 
+struct ReducedFastFieldConvertCollector<
+    TCollector: Collector<Fruit = Vec<(Option<u64>, DocAddress)>>,
+> {
+    collector: TCollector,
+    order: Order,
+}
+
+impl<TCollector> Collector for ReducedFastFieldConvertCollector<TCollector>
+where TCollector: Collector<Fruit = Vec<(Option<u64>, DocAddress)>>
+{
+    type Fruit = Vec<(Option<f64>, DocAddress)>;
+
+    type Child = TCollector::Child;
+
+    fn for_segment(
+        &self,
+        segment_local_id: crate::SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        self.collector.for_segment(segment_local_id, segment)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> crate::Result<Self::Fruit> {
+        let raw_result = self.collector.merge_fruits(segment_fruits)?;
+        let transformed_result = raw_result
+            .into_iter()
+            .map(|(score, doc_address)| {
+                let value = score.map(|score| {
+                    if self.order.is_desc() {
+                        f64::from_u64(score)
+                    } else {
+                        f64::from_u64(u64::MAX - score)
+                    }
+                });
+                (value, doc_address)
+            })
+            .collect::<Vec<_>>();
+        Ok(transformed_result)
+    }
+}
+
 /// The `TopDocs` collector keeps track of the top `K` documents
 /// sorted by their score.
 ///
@@ -153,33 +214,135 @@ impl CustomSegmentScorer<u64> for ScorerByFastFieldReader {
             u64::MAX - value
         }
     }
+
+    fn as_block_bounded(&self) -> Option<&dyn BlockBoundedScorer<u64>> {
+        Some(self)
+    }
+}
+
+impl BlockBoundedScorer<u64> for ScorerByFastFieldReader {
+    fn block_bounds(&self, doc_range: Range<DocId>) -> Option<(u64, u64)> {
+        let (min_value, max_value) = self.sort_column.value_bounds(doc_range);
+        if self.order.is_desc() {
+            Some((min_value, max_value))
+        } else {
+            Some((u64::MAX - max_value, u64::MAX - min_value))
+        }
+    }
 }
 
 struct ScorerByField {
     field: String,
     order: Order,
+    /// The default value of the policy is the u64 representation of the value.
+    missing_column_policy: MissingColumnPolicy<u64>,
 }
 
 impl CustomScorer<u64> for ScorerByField {
     type Child = ScorerByFastFieldReader;
 
     fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        self.segment_scorer_opt(segment_reader)?
+            .ok_or_else(|| missing_column_error(segment_reader, &self.field))
+    }
+
+    fn segment_scorer_opt(
+        &self,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Option<Self::Child>> {
         // We interpret this field as u64, regardless of its type, that way,
         // we avoid needless conversion. Regardless of the fast field type, the
         // mapping is monotonic, so it is sufficient to compute our top-K docs.
         //
         // The conversion will then happen only on the top-K docs.
-        let sort_column_opt = segment_reader.fast_fields().u64_lenient(&self.field)?;
-        let (sort_column, _sort_column_type) =
+        let sort_column_opt = segment_reader.try_fast_fields()?.u64_lenient(&self.field)?;
+        let sort_column: Arc<dyn ColumnValues<u64>> = match sort_column_opt {
+            Some((sort_column, _sort_column_type)) => {
+                let mut default_value = 0u64;
+                if self.order.is_asc() {
+                    default_value = u64::MAX;
+                }
+                sort_column.first_or_default_col(default_value)
+            }
+            None => match self.missing_column_policy {
+                MissingColumnPolicy::Error => {
+                    return Err(missing_column_error(segment_reader, &self.field));
+                }
+                MissingColumnPolicy::Default(value) => {
+                    Arc::new(ConstantColumnValues::new(value, segment_reader.max_doc()))
+                }
+                MissingColumnPolicy::SkipSegment => return Ok(None),
+            },
+        };
+        Ok(Some(ScorerByFastFieldReader {
+            sort_column,
+            order: self.order.clone(),
+        }))
+    }
+}
+
+/// Scores the documents by the reduced value of their fast field values.
+///
+/// The reduced `f64` value is mapped to `u64` to be compared, while the documents without a value
+/// have the lowest score, `None`, so that they sort last in both orders.
+struct ScorerByReducedFastFieldReader {
+    sort_column: Column<u64>,
+    column_type: ColumnType,
+    mode: MultiValueMode,
+    order: Order,
+}
+
+impl CustomSegmentScorer<Option<u64>> for ScorerByReducedFastFieldReader {
+    fn score(&mut self, doc: DocId) -> Option<u64> {
+        let column_type = self.column_type;
+        let vals = self
+            .sort_column
+            .values_for_doc(doc)
+            .map(|val| f64_from_fastfield_u64(val, &column_type));
+        let value = self.mode.reduce(vals)?.to_u64();
+        if self.order.is_desc() {
+            Some(value)
+        } else {
+            Some(u64::MAX - value)
+        }
+    }
+}
+
+struct ScorerByReducedField {
+    field: String,
+    mode: MultiValueMode,
+    order: Order,
+}
+
+impl CustomScorer<Option<u64>> for ScorerByReducedField {
+    type Child = ScorerByReducedFastFieldReader;
+
+    fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+        if !self.mode.is_reducer() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Cannot order by field {:?} with multi value mode {:?}, which does not reduce the \
+                 values of a document to a single value.",
+                self.field, self.mode
+            )));
+        }
+        let sort_column_opt = segment_reader.try_fast_fields()?.u64_lenient_for_type(
+            Some(&[
+                ColumnType::U64,
+                ColumnType::I64,
+                ColumnType::F64,
+                ColumnType::Bool,
+                ColumnType::DateTime,
+            ]),
+            &self.field,
+        )?;
+        let (sort_column, column_type) =
             sort_column_opt.ok_or_else(|| FastFieldNotAvailableError {
                 field_name: self.field.clone(),
             })?;
-        let mut default_value = 0u64;
-        if self.order.is_asc() {
-            default_value = u64::MAX;
-        }
-        Ok(ScorerByFastFieldReader {
-            sort_column: sort_column.first_or_default_col(default_value),
+        Ok(ScorerByReducedFastFieldReader {
+            sort_column,
+            column_type,
+            mode: self.mode,
             order: self.order.clone(),
         })
     }
@@ -314,11 +477,64 @@ impl TopDocs {
         self,
         field: impl ToString,
         order: Order,
+    ) -> impl Collector<Fruit = Vec<(u64, DocAddress)>> {
+        self.order_by_u64_field_with_policy(field, order, MissingColumnPolicy::Error)
+    }
+
+    /// Same as [`TopDocs::order_by_u64_field`], with the given behavior for the segments
+    /// without a column for the field.
+    ///
+    /// With [`MissingColumnPolicy::Error`], the search fails on such segments, like with
+    /// [`TopDocs::order_by_u64_field`].
+    ///
+    /// The segments written before the documents had a value for a path of a JSON field have no
+    /// column for it. Their documents can for instance be ordered as if they had a default value,
+    /// given in its `u64` representation:
+    ///
+    /// ```rust
+    /// # use tantivy::schema::{Schema, FAST};
+    /// # use tantivy::{doc, Index, Order};
+    /// # use tantivy::query::AllQuery;
+    /// use tantivy::collector::{MissingColumnPolicy, TopDocs};
+    /// use tantivy::columnar::MonotonicallyMappableToU64;
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// #   let mut schema_builder = Schema::builder();
+    /// #   let attributes = schema_builder.add_json_field("attributes", FAST);
+    /// #   let index = Index::create_in_ram(schema_builder.build());
+    /// #   let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// // This segment was written before the documents had a rating.
+    /// index_writer.add_document(doc!(attributes => serde_json::json!({"color": "red"})))?;
+    /// index_writer.commit()?;
+    /// // JSON integers are stored as `i64`.
+    /// index_writer.add_document(doc!(attributes => serde_json::json!({"rating": -2})))?;
+    /// index_writer.commit()?;
+    /// #   let searcher = index.reader()?.searcher();
+    /// let top_by_rating = TopDocs::with_limit(2).order_by_u64_field_with_policy(
+    ///     "attributes.rating",
+    ///     Order::Desc,
+    ///     MissingColumnPolicy::Default(0i64.to_u64()),
+    /// );
+    /// let ratings: Vec<i64> = searcher
+    ///     .search(&AllQuery, &top_by_rating)?
+    ///     .into_iter()
+    ///     .map(|(rating, _)| i64::from_u64(rating))
+    ///     .collect();
+    /// assert_eq!(ratings, vec![0, -2]);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn order_by_u64_field_with_policy(
+        self,
+        field: impl ToString,
+        order: Order,
+        missing_column_policy: MissingColumnPolicy<u64>,
     ) -> impl Collector<Fruit = Vec<(u64, DocAddress)>> {
         CustomScoreTopCollector::new(
             ScorerByField {
                 field: field.to_string(),
                 order,
+                missing_column_policy,
             },
             self.0.into_tscore(),
         )
@@ -401,7 +617,27 @@ impl TopDocs {
     where
         TFastValue: FastValue,
     {
-        let u64_collector = self.order_by_u64_field(fast_field.to_string(), order.clone());
+        self.order_by_fast_field_with_policy(fast_field, order, MissingColumnPolicy::Error)
+    }
+
+    /// Same as [`TopDocs::order_by_fast_field`], with the given behavior for the segments
+    /// without a column for the field.
+    ///
+    /// See [`TopDocs::order_by_u64_field_with_policy`].
+    pub fn order_by_fast_field_with_policy<TFastValue>(
+        self,
+        fast_field: impl ToString,
+        order: Order,
+        missing_column_policy: MissingColumnPolicy<TFastValue>,
+    ) -> impl Collector<Fruit = Vec<(TFastValue, DocAddress)>>
+    where
+        TFastValue: FastValue,
+    {
+        let u64_collector = self.order_by_u64_field_with_policy(
+            fast_field.to_string(),
+            order.clone(),
+            missing_column_policy.map(|value| value.to_u64()),
+        );
         FastFieldConvertCollector {
             collector: u64_collector,
             field: fast_field.to_string(),
@@ -410,6 +646,88 @@ impl TopDocs {
         }
     }
 
+    /// Set top-K to rank documents by the values of a multivalued numeric fast field, reduced
+    /// to a single value per document by `mode`.
+    ///
+    /// [`TopDocs::order_by_fast_field`] uses the first value of each document instead.
+    ///
+    /// The values are reduced as `f64`, including on integer columns, and returned along with the
+    /// documents. Date values are their timestamp in nanoseconds. The documents without a value
+    /// have no reduced value and sort last, in both orders.
+    ///
+    /// [`MultiValueMode::All`] does not reduce the values, and is not a valid mode to order by:
+    /// an error is returned at the moment of collection, as well as if the field is not a
+    /// numeric or date fast field.
+    ///
+    /// ```rust
+    /// # use tantivy::schema::{Schema, FAST};
+    /// # use tantivy::{doc, DocAddress, Index, Order};
+    /// # use tantivy::query::AllQuery;
+    /// use tantivy::collector::TopDocs;
+    /// use tantivy::fastfield::MultiValueMode;
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// #   let mut schema_builder = Schema::builder();
+    /// #   let ratings = schema_builder.add_u64_field("ratings", FAST);
+    /// #   let index = Index::create_in_ram(schema_builder.build());
+    /// #   let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// #   index_writer.add_document(doc!(ratings => 4u64, ratings => 1u64))?;
+    /// #   index_writer.add_document(doc!(ratings => 3u64))?;
+    /// #   index_writer.commit()?;
+    /// #   let searcher = index.reader()?.searcher();
+    /// let top_by_avg_rating = TopDocs::with_limit(2).order_by_fast_field_with_mode(
+    ///     "ratings",
+    ///     Order::Desc,
+    ///     MultiValueMode::Avg,
+    /// );
+    /// let top_docs = searcher.search(&AllQuery, &top_by_avg_rating)?;
+    /// assert_eq!(
+    ///     top_docs,
+    ///     vec![
+    ///         (Some(3.0), DocAddress::new(0, 1)),
+    ///         (Some(2.5), DocAddress::new(0, 0)),
+    ///     ]
+    /// );
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn order_by_fast_field_with_mode(
+        self,
+        fast_field: impl ToString,
+        order: Order,
+        mode: MultiValueMode,
+    ) -> impl Collector<Fruit = Vec<(Option<f64>, DocAddress)>> {
+        let reduced_collector = CustomScoreTopCollector::new(
+            ScorerByReducedField {
+                field: fast_field.to_string(),
+                mode,
+                order: order.clone(),
+            },
+            self.0.into_tscore(),
+        );
+        ReducedFastFieldConvertCollector {
+            collector: reduced_collector,
+            order,
+        }
+    }
+
+    /// Set top-K to rank documents by a given string fast field, e.g. a `STRING | FAST` text
+    /// field.
+    ///
+    /// The documents are compared by term ordinal within a segment, and only the strings of the
+    /// top-K documents of each segment are loaded to merge the segments. The documents without
+    /// a value sort last, see [`StringFastFieldTopCollector`] for the details.
+    ///
+    /// If the field is not a fast field, this method does not panic, but an explicit error will
+    /// be returned at the moment of collection.
+    pub fn order_by_string_fast_field(
+        self,
+        field: impl ToString,
+        order: Order,
+    ) -> StringFastFieldTopCollector {
+        StringFastFieldTopCollector::new(field.to_string(), order, self.0.limit, self.0.offset)
+    }
+
     /// Ranks the documents using a custom score.
     ///
     /// This method offers a convenient way to tweak or replace
@@ -622,7 +940,7 @@ impl TopDocs {
     pub fn custom_score<TScore, TCustomSegmentScorer, TCustomScorer>(
         self,
         custom_score: TCustomScorer,
-    ) -> impl Collector<Fruit = Vec<(TScore, DocAddress)>>
+    ) -> CustomScoreTopCollector<TCustomScorer, TScore>
     where
         TScore: 'static + Send + Sync + Clone + PartialOrd,
         TCustomSegmentScorer: CustomSegmentScorer<TScore> + 'static,
@@ -630,6 +948,273 @@ impl TopDocs {
     {
         CustomScoreTopCollector::new(custom_score, self.0.into_tscore())
     }
+
+    /// Only collect the documents sorting after the given key.
+    ///
+    /// This makes it possible to paginate through results without the `O(offset)` cost of
+    /// [`and_offset`](TopDocs::and_offset): the key of the last hit of a page, obtained via
+    /// [`SearchAfterKey::from_last_hit`], is passed to the search of the next page.
+    ///
+    /// The key is bound to the searcher generation it was computed on.
+    ///
+    /// This only applies to the score based ordering of `TopDocs`. For custom scores, use
+    /// [`CustomScoreTopCollector::with_search_after`].
+    ///
+    /// ```rust
+    /// use tantivy::collector::{SearchAfterKey, TopDocs};
+    /// use tantivy::query::QueryParser;
+    /// use tantivy::schema::{Schema, TEXT};
+    /// use tantivy::{doc, Index};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let title = schema_builder.add_text_field("title", TEXT);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    ///
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(title => "The Diary of Muadib"))?;
+    /// index_writer.add_document(doc!(title => "The Diary of a Young Girl"))?;
+    /// index_writer.add_document(doc!(title => "Diary of a Wimpy Kid"))?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let query = QueryParser::for_index(&index, vec![title]).parse_query("diary")?;
+    ///
+    /// let first_page = searcher.search(&query, &TopDocs::with_limit(2))?;
+    /// let key = SearchAfterKey::from_last_hit(&first_page).unwrap();
+    /// let second_page = searcher.search(&query, &TopDocs::with_limit(2).with_search_after(key))?;
+    ///
+    /// let all = searcher.search(&query, &TopDocs::with_limit(3))?;
+    /// assert_eq!(second_page, all[2..].to_vec());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_search_after(self, key: SearchAfterKey<Score>) -> TopDocs {
+        TopDocs(self.0.with_search_after(key))
+    }
+
+    /// Normalizes the scores of the top documents once the results of all segments have been
+    /// merged.
+    ///
+    /// The resulting collector returns both the raw and the normalized score of each document.
+    /// Documents are still ranked by their raw score.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tantivy::collector::{Normalization, TopDocs};
+    /// use tantivy::query::QueryParser;
+    /// use tantivy::schema::{Schema, TEXT};
+    /// use tantivy::{doc, Index};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let title = schema_builder.add_text_field("title", TEXT);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    ///
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(title => "The Diary of Muadib"))?;
+    /// index_writer.add_document(doc!(title => "The Diary of a Young Girl"))?;
+    /// index_writer.add_document(doc!(title => "Diary, diary, diary"))?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let query = QueryParser::for_index(&index, vec![title]).parse_query("diary")?;
+    /// let collector = TopDocs::with_limit(3).with_normalization(Normalization::MinMax);
+    /// let top_docs = searcher.search(&query, &collector)?;
+    ///
+    /// assert_eq!(top_docs[0].1, 1.0);
+    /// assert_eq!(top_docs[2].1, 0.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_normalization(self, normalization: Normalization) -> NormalizedTopDocs {
+        NormalizedTopDocs {
+            top_docs: self,
+            normalization,
+        }
+    }
+
+    /// Re-scores the top `window` documents by score with the expensive `rescorer`.
+    ///
+    /// The documents are first ranked by their score, e.g. BM25, keeping the top `window`
+    /// documents of the index as candidates. Only these candidates are scored by `rescorer`,
+    /// and the top documents by rescored score are returned. Scorers that are too expensive to
+    /// run on every matching document, e.g. reading a stored embedding, only change the ranking
+    /// of the documents that are already competitive.
+    ///
+    /// The collector returns, for each document, its rescored score, its original score and its
+    /// address. With a `window` larger than the number of matching documents, the results are
+    /// those of [`TopDocs::custom_score`] with `rescorer`.
+    ///
+    /// Returns an error if `window` is smaller than the limit plus the offset, or if the
+    /// collector only collects the documents after a search-after key. To use a cheap custom
+    /// score in the first phase, see [`CustomScoreTopCollector::with_rescoring`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tantivy::collector::TopDocs;
+    /// use tantivy::query::QueryParser;
+    /// use tantivy::schema::{Schema, FAST, TEXT};
+    /// use tantivy::{doc, DocId, Index, SegmentReader};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let title = schema_builder.add_text_field("title", TEXT);
+    /// let popularity = schema_builder.add_u64_field("popularity", FAST);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    ///
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(title => "The Diary of Muadib", popularity => 1u64))?;
+    /// index_writer.add_document(doc!(title => "Diary, diary, diary", popularity => 5u64))?;
+    /// index_writer.add_document(doc!(title => "A Dairy Cow", popularity => 10u64))?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let query = QueryParser::for_index(&index, vec![title]).parse_query("diary")?;
+    /// let rescorer = |segment_reader: &SegmentReader| {
+    ///     let popularity = segment_reader.fast_fields().u64("popularity").unwrap();
+    ///     move |doc: DocId| popularity.first(doc).unwrap_or(0)
+    /// };
+    /// let collector = TopDocs::with_limit(1).with_rescoring(rescorer, 10)?;
+    /// let top_docs = searcher.search(&query, &collector)?;
+    ///
+    /// let (popularity, _bm25, _doc_address) = top_docs[0];
+    /// assert_eq!(popularity, 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_rescoring<TRescore, TRescorer>(
+        self,
+        rescorer: TRescorer,
+        window: usize,
+    ) -> crate::Result<RescoreTopCollector<TopDocs, TRescorer, TRescore>>
+    where
+        TRescorer: CustomScorer<TRescore>,
+    {
+        if self.0.search_after.is_some() {
+            return Err(TantivyError::InvalidArgument(
+                "Rescoring does not support search-after keys.".to_string(),
+            ));
+        }
+        RescoreTopCollector::new(
+            TopDocs::with_limit(window),
+            rescorer,
+            window,
+            self.0.limit,
+            self.0.offset,
+        )
+    }
+
+    /// Captures the occurrences of the terms of the query in the top documents, e.g. to
+    /// highlight them.
+    ///
+    /// Only the terms of the given `fields` are captured, and these fields need to be indexed
+    /// with positions. The positions are read once the top documents of a segment are known,
+    /// so the cost of the capture does not depend on the number of matching documents.
+    ///
+    /// Term, phrase (without slop) and boolean queries made of those support capturing their
+    /// matches. See [`TopDocsWithTermMatches`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tantivy::collector::TopDocs;
+    /// use tantivy::query::QueryParser;
+    /// use tantivy::schema::{Schema, TEXT};
+    /// use tantivy::{doc, Index};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let title = schema_builder.add_text_field("title", TEXT);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    ///
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(title => "The Diary of Muadib"))?;
+    /// index_writer.add_document(doc!(title => "A Dairy Cow"))?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let query = QueryParser::for_index(&index, vec![title]).parse_query("\"of muadib\"")?;
+    /// let collector = TopDocs::with_limit(10).capture_term_matches(vec![title]);
+    /// let top_docs = searcher.search(&query, &collector)?;
+    ///
+    /// let (_score, _doc_address, term_matches) = &top_docs.hits[0];
+    /// assert_eq!(term_matches.len(), 2);
+    /// assert_eq!(term_matches[0].term.value().as_str(), Some("muadib"));
+    /// assert_eq!(term_matches[0].positions, vec![3..4]);
+    /// assert_eq!(term_matches[1].term.value().as_str(), Some("of"));
+    /// assert_eq!(term_matches[1].positions, vec![2..3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn capture_term_matches(
+        self,
+        fields: impl IntoIterator<Item = Field>,
+    ) -> TopDocsWithTermMatches {
+        TopDocsWithTermMatches::new(self, fields.into_iter().collect())
+    }
+}
+
+/// The `NormalizedTopDocs` collector keeps track of the top `K` documents sorted by their score,
+/// and normalizes their scores once the results of all segments have been merged.
+///
+/// Its fruit carries, for each document, its raw score, its normalized score and its address.
+///
+/// See [`TopDocs::with_normalization`].
+pub struct NormalizedTopDocs {
+    top_docs: TopDocs,
+    normalization: Normalization,
+}
+
+impl fmt::Debug for NormalizedTopDocs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NormalizedTopDocs(limit={}, offset={}, normalization={:?})",
+            self.top_docs.0.limit, self.top_docs.0.offset, self.normalization
+        )
+    }
+}
+
+impl Collector for NormalizedTopDocs {
+    type Fruit = Vec<(Score, f32, DocAddress)>;
+
+    type Child = TopScoreSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        self.top_docs.for_segment(segment_local_id, reader)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(
+        &self,
+        child_fruits: Vec<Vec<(Score, DocAddress)>>,
+    ) -> crate::Result<Self::Fruit> {
+        self.top_docs
+            .0
+            .merge_fruits_normalized(child_fruits, self.normalization)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> crate::Result<<Self::Child as SegmentCollector>::Fruit> {
+        self.top_docs.collect_segment(weight, segment_ord, reader)
+    }
 }
 
 impl Collector for TopDocs {
@@ -666,7 +1251,22 @@ impl Collector for TopDocs {
         let heap_len = self.0.limit + self.0.offset;
         let mut top_n: TopNComputer<_, _> = TopNComputer::new(heap_len);
 
-        if let Some(alive_bitset) = reader.alive_bitset() {
+        if let Some(search_after) = self.0.search_after.as_ref() {
+            let alive_bitset = reader.alive_bitset();
+            let mut threshold = Score::MIN;
+            top_n.threshold = Some(threshold);
+            weight.for_each_pruning(Score::MIN, reader, &mut |doc, score| {
+                let is_deleted = alive_bitset.is_some_and(|bitset| bitset.is_deleted(doc));
+                if is_deleted
+                    || !search_after.is_after_in_results(&score, DocAddress::new(segment_ord, doc))
+                {
+                    return threshold;
+                }
+                top_n.push(score, doc);
+                threshold = top_n.threshold.unwrap_or(Score::MIN);
+                threshold
+            })?;
+        } else if let Some(alive_bitset) = reader.alive_bitset() {
             let mut threshold = Score::MIN;
             top_n.threshold = Some(threshold);
             weight.for_each_pruning(Score::MIN, reader, &mut |doc, score| {
@@ -802,12 +1402,26 @@ where
         }
     }
 
+    /// Create a new `TopNComputer` reusing `buffer`, which is cleared.
+    pub(crate) fn with_buffer(top_n: usize, mut buffer: Vec<ComparableDoc<Score, D, R>>) -> Self {
+        buffer.clear();
+        buffer.reserve_exact(top_n.max(1) * 2);
+        TopNComputer {
+            buffer,
+            top_n,
+            threshold: None,
+        }
+    }
+
     /// Push a new document to the top n.
     /// If the document is below the current threshold, it will be ignored.
     #[inline]
     pub fn push(&mut self, feature: Score, doc: D) {
-        if let Some(last_median) = self.threshold.clone() {
-            if feature < last_median {
+        if let Some(last_median) = &self.threshold {
+            check_feature_order(&feature, last_median);
+            // Without `REVERSE_ORDER`, the lowest features are kept.
+            let rejected = if R { Ordering::Less } else { Ordering::Greater };
+            if cmp_nan_lowest(&feature, last_median) == rejected {
                 return;
             }
         }
@@ -832,7 +1446,10 @@ where
     #[inline(never)]
     fn truncate_top_n(&mut self) -> Score {
         // Use select_nth_unstable to find the top nth score
-        let (_, median_el, _) = self.buffer.select_nth_unstable(self.top_n);
+        let (top, median_el, _) = self.buffer.select_nth_unstable(self.top_n);
+        if let Some(first) = top.first() {
+            check_feature_order(&first.feature, &median_el.feature);
+        }
 
         let median_score = median_el.feature.clone();
         // Remove all elements below the top_n
@@ -863,16 +1480,24 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{TopDocs, TopNComputer};
-    use crate::collector::top_collector::ComparableDoc;
-    use crate::collector::Collector;
+    use columnar::MonotonicallyMappableToU64;
+    use serde_json::json;
+
+    use super::{ScorerByField, TopDocs, TopNComputer};
+    use crate::collector::score_normalization::Normalization;
+    use crate::collector::top_collector::{ComparableDoc, SearchAfterKey};
+    use crate::collector::{
+        missing_column_error, Collector, CustomScorer, MissingColumnPolicy, SegmentCollector,
+    };
+    use crate::fastfield::MultiValueMode;
+    use crate::indexer::NoMergePolicy;
     use crate::query::{AllQuery, Query, QueryParser};
-    use crate::schema::{Field, Schema, FAST, STORED, TEXT};
+    use crate::schema::{Field, Schema, FAST, INDEXED, STORED, TEXT};
     use crate::time::format_description::well_known::Rfc3339;
     use crate::time::OffsetDateTime;
     use crate::{
         assert_nearly_equals, DateTime, DocAddress, DocId, Index, IndexWriter, Order, Score,
-        SegmentReader,
+        Searcher, SegmentReader, TantivyDocument, TantivyError, Term,
     };
 
     fn make_index() -> crate::Result<Index> {
@@ -948,6 +1573,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_topn_computer_nan_lowest() {
+        let mut computer: TopNComputer<f32, u32> = TopNComputer::new(3);
+        for doc in 0..20u32 {
+            let feature = if doc % 3 == 0 { f32::NAN } else { doc as f32 };
+            computer.push(feature, doc);
+        }
+        let docs: Vec<u32> = computer
+            .into_sorted_vec()
+            .into_iter()
+            .map(|comparable_doc| comparable_doc.doc)
+            .collect();
+        assert_eq!(docs, [19, 17, 16]);
+
+        let mut computer: TopNComputer<f32, u32> = TopNComputer::new(4);
+        for (feature, doc) in [(f32::NAN, 0), (1.0, 1), (f32::NAN, 2), (2.0, 3), (0.5, 4)] {
+            computer.push(feature, doc);
+        }
+        let sorted_vec = computer.into_sorted_vec();
+        let docs: Vec<u32> = sorted_vec
+            .iter()
+            .map(|comparable_doc| comparable_doc.doc)
+            .collect();
+        assert_eq!(docs, [3, 1, 4, 0]);
+        assert!(sorted_vec[3].feature.is_nan());
+    }
+
+    #[test]
+    fn test_topn_computer_ascending_threshold() {
+        let mut computer: TopNComputer<u32, u32, false> = TopNComputer::new(2);
+        for doc in [5u32, 9, 1, 7, 8, 3, 6, 0, 4, 2] {
+            computer.push(doc, doc);
+        }
+        let docs: Vec<u32> = computer
+            .into_sorted_vec()
+            .into_iter()
+            .map(|comparable_doc| comparable_doc.doc)
+            .collect();
+        assert_eq!(docs, [0, 1]);
+    }
+
     #[test]
     fn test_topn_computer_no_panic() {
         for top_n in 0..10 {
@@ -1074,6 +1740,7 @@ mod tests {
 
     const TITLE: &str = "title";
     const SIZE: &str = "size";
+    const RATING: &str = "attributes.rating";
 
     #[test]
     fn test_top_field_collector_not_at_capacity() -> crate::Result<()> {
@@ -1291,6 +1958,61 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_tweak_score_nan_does_not_corrupt_ordering() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for doc in 0..300 {
+            index_writer.add_document(doc!(text => "hello"))?;
+            if doc % 100 == 99 {
+                index_writer.commit()?;
+            }
+        }
+        let searcher = index.reader()?.searcher();
+        let query = QueryParser::for_index(&index, vec![text]).parse_query("hello")?;
+        // A hostile tweaker, returning NaN for a third of the documents.
+        let hostile_tweaker = |_segment_reader: &SegmentReader| {
+            |doc: DocId, _original_score: Score| {
+                if doc % 3 == 0 {
+                    Score::NAN
+                } else {
+                    doc as Score
+                }
+            }
+        };
+        let top_docs = searcher.search(
+            &query,
+            &TopDocs::with_limit(10).tweak_score(hostile_tweaker),
+        )?;
+        assert_eq!(top_docs.len(), 10);
+        let scores: Vec<Score> = top_docs.iter().map(|(score, _)| *score).collect();
+        // The three segments have the same docs, so the best scores come in threes.
+        assert_eq!(
+            scores,
+            [98.0, 98.0, 98.0, 97.0, 97.0, 97.0, 95.0, 95.0, 95.0, 94.0]
+        );
+        for window in top_docs.windows(2) {
+            if window[0].0 == window[1].0 {
+                assert!(window[0].1 < window[1].1);
+            }
+        }
+
+        let all_docs = searcher.search(
+            &query,
+            &TopDocs::with_limit(300).tweak_score(hostile_tweaker),
+        )?;
+        assert_eq!(all_docs.len(), 300);
+        assert!(all_docs[..198].iter().all(|(score, _)| !score.is_nan()));
+        assert!(all_docs[198..].iter().all(|(score, _)| score.is_nan()));
+        for window in all_docs[..198].windows(2) {
+            assert!(window[0].0 >= window[1].0);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_custom_score_top_collector_with_offset() {
         let index = make_index().unwrap();
@@ -1313,6 +2035,93 @@ mod tests {
         );
     }
 
+    fn make_index_for_search_after() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let rank_field = schema_builder.add_u64_field("rank", FAST | INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for i in 0..30u64 {
+            // Few distinct scores, so that there are many ties across segments.
+            let text = if i % 2 == 0 { "beer" } else { "beer beer" };
+            index_writer.add_document(doc!(text_field => text, rank_field => i % 3))?;
+            if i % 10 == 9 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.delete_term(Term::from_field_u64(rank_field, 2))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_top_docs_search_after() -> crate::Result<()> {
+        let index = make_index_for_search_after()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 3);
+        let field = index.schema().get_field("text").unwrap();
+        let query = QueryParser::for_index(&index, vec![field]).parse_query("beer")?;
+
+        let expected = searcher.search(&query, &TopDocs::with_limit(100))?;
+        assert_eq!(expected.len(), 20);
+
+        for page_size in [1, 3, 7] {
+            let mut pages = Vec::new();
+            let mut collector = TopDocs::with_limit(page_size);
+            loop {
+                let page = searcher.search(&query, &collector)?;
+                let Some(key) = SearchAfterKey::from_last_hit(&page) else {
+                    break;
+                };
+                let offset_page = searcher.search(
+                    &query,
+                    &TopDocs::with_limit(page_size).and_offset(pages.len()),
+                )?;
+                assert_eq!(page, offset_page);
+                pages.extend(page);
+                collector = TopDocs::with_limit(page_size).with_search_after(key);
+            }
+            assert_eq!(pages, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_score_top_collector_search_after() -> crate::Result<()> {
+        let index = make_index_for_search_after()?;
+        let searcher = index.reader()?.searcher();
+        let rank_scorer = |segment_reader: &SegmentReader| {
+            let rank_reader = segment_reader
+                .fast_fields()
+                .u64("rank")
+                .unwrap()
+                .first_or_default_col(0);
+            move |doc: DocId| rank_reader.get_val(doc)
+        };
+
+        let expected = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(100).custom_score(rank_scorer),
+        )?;
+        assert_eq!(expected.len(), 20);
+
+        let mut pages = Vec::new();
+        let mut collector = TopDocs::with_limit(3).custom_score(rank_scorer);
+        loop {
+            let page: Vec<(u64, DocAddress)> = searcher.search(&AllQuery, &collector)?;
+            let Some(key) = SearchAfterKey::from_last_hit(&page) else {
+                break;
+            };
+            pages.extend(page);
+            collector = TopDocs::with_limit(3)
+                .custom_score(rank_scorer)
+                .with_search_after(key);
+        }
+        assert_eq!(pages, expected);
+        Ok(())
+    }
+
     fn index(
         query: &str,
         query_field: Field,
@@ -1373,4 +2182,421 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_order_by_fast_field_skips_blocks() -> crate::Result<()> {
+        use crate::collector::custom_score_top_collector::CustomScoreTopCollector;
+        use crate::collector::top_collector::TopCollector;
+        use crate::query::{EnableScoring, TermQuery};
+        use crate::schema::IndexRecordOption;
+
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", INDEXED);
+        let text = schema_builder.add_text_field("text", TEXT);
+        let value = schema_builder.add_u64_field("value", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 20_000_000)?;
+        for i in 0..5_000u64 {
+            let parity = if i % 2 == 0 { "even" } else { "odd" };
+            if i % 11 == 0 {
+                index_writer.add_document(doc!(id => i, text => parity))?;
+            } else {
+                // A sawtooth, so that both orders find better blocks late in the segment.
+                let val = (i % 1_000) * 7 + i % 5;
+                index_writer.add_document(doc!(id => i, text => parity, value => val))?;
+            }
+        }
+        index_writer.commit()?;
+        for deleted_id in [997, 998, 1_001, 4_999] {
+            index_writer.delete_term(Term::from_field_u64(id, deleted_id))?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        let segment_reader = searcher.segment_reader(0);
+
+        let term_query = TermQuery::new(
+            Term::from_field_text(text, "even"),
+            IndexRecordOption::Basic,
+        );
+        for order in [Order::Desc, Order::Asc] {
+            // Custom scores computing the same scores, without bounds.
+            let is_desc = order.is_desc();
+            let exhaustive_collector =
+                TopDocs::with_limit(10).custom_score(move |segment_reader: &SegmentReader| {
+                    let default_value = if is_desc { 0 } else { u64::MAX };
+                    let column = segment_reader
+                        .fast_fields()
+                        .u64("value")
+                        .unwrap()
+                        .first_or_default_col(default_value);
+                    move |doc: DocId| {
+                        let val = column.get_val(doc);
+                        if is_desc {
+                            val
+                        } else {
+                            u64::MAX - val
+                        }
+                    }
+                });
+            let collector = TopDocs::with_limit(10).order_by_u64_field("value", order.clone());
+            for query in [&AllQuery as &dyn Query, &term_query] {
+                let expected = searcher.search(query, &exhaustive_collector)?;
+                assert_eq!(searcher.search(query, &collector)?, expected);
+            }
+
+            let collector = CustomScoreTopCollector::new(
+                ScorerByField {
+                    field: "value".to_string(),
+                    order: order.clone(),
+                    missing_column_policy: MissingColumnPolicy::Error,
+                },
+                TopCollector::with_limit(10),
+            );
+            // Match-all queries enumerate the docs, skipping whole blocks.
+            let mut segment_collector = collector.for_segment(0, segment_reader)?;
+            segment_collector
+                .collect_all_docs(segment_reader.max_doc(), segment_reader.alive_bitset());
+            assert!(segment_collector.num_skipped_docs() > 0);
+            assert_eq!(
+                segment_collector.harvest(),
+                searcher.search(&AllQuery, &exhaustive_collector)?
+            );
+            // Other queries skip scoring the blocks of docs they match.
+            let weight = term_query.weight(EnableScoring::disabled_from_searcher(&searcher))?;
+            let mut segment_collector = collector.for_segment(0, segment_reader)?;
+            weight.for_each_no_score(segment_reader, &mut |docs| {
+                segment_collector.collect_block(docs);
+            })?;
+            assert!(segment_collector.num_skipped_docs() > 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_docs_normalization_merge_segments() -> crate::Result<()> {
+        let segment_fruits = || {
+            vec![
+                vec![(5.0, DocAddress::new(0, 1)), (2.0, DocAddress::new(0, 0))],
+                vec![(4.0, DocAddress::new(1, 3)), (1.0, DocAddress::new(1, 0))],
+                vec![(3.0, DocAddress::new(2, 2))],
+            ]
+        };
+        // The candidates are the top 4 documents: 5, 4, 3 and 2. The first one is then skipped.
+        let top_docs = || TopDocs::with_limit(3).and_offset(1);
+        let expected_docs = [
+            DocAddress::new(1, 3),
+            DocAddress::new(2, 2),
+            DocAddress::new(0, 0),
+        ];
+        let check = |normalization: Normalization, expected: [f32; 3]| -> crate::Result<()> {
+            let collector = top_docs().with_normalization(normalization);
+            let fruit = collector.merge_fruits(segment_fruits())?;
+            assert_eq!(fruit.len(), 3);
+            for ((raw_score, _, doc), (expected_raw_score, expected_doc)) in fruit
+                .iter()
+                .zip([4.0, 3.0, 2.0].iter().zip(expected_docs.iter()))
+            {
+                assert_eq!(raw_score, expected_raw_score);
+                assert_eq!(doc, expected_doc);
+            }
+            for ((_, normalized_score, _), expected) in fruit.iter().zip(expected.iter()) {
+                assert_nearly_equals!(*normalized_score, *expected);
+            }
+            Ok(())
+        };
+        check(Normalization::MinMax, [2.0 / 3.0, 1.0 / 3.0, 0.0])?;
+        // mean = 3.5, population std dev = sqrt(1.25)
+        let std_dev = 1.25f32.sqrt();
+        check(
+            Normalization::ZScore,
+            [0.5 / std_dev, -0.5 / std_dev, -1.5 / std_dev],
+        )?;
+        check(
+            Normalization::Rrf { k: 60 },
+            [1.0 / 62.0, 1.0 / 63.0, 1.0 / 64.0],
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_docs_normalization_rrf_ties() -> crate::Result<()> {
+        // Ties are ranked by doc address, so that ranks are deterministic.
+        let collector = TopDocs::with_limit(3).with_normalization(Normalization::Rrf { k: 1 });
+        let fruit = collector.merge_fruits(vec![
+            vec![(1.0, DocAddress::new(0, 4))],
+            vec![(1.0, DocAddress::new(1, 2))],
+            vec![(2.0, DocAddress::new(2, 7))],
+        ])?;
+        assert_eq!(
+            fruit,
+            vec![
+                (2.0, 0.5, DocAddress::new(2, 7)),
+                (1.0, 1.0 / 3.0, DocAddress::new(0, 4)),
+                (1.0, 0.25, DocAddress::new(1, 2)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_docs_normalization_search() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(text_field=>"happy tax payer"))?;
+        index_writer.add_document(doc!(text_field=>"happy happy happy"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text_field=>"happy droopy"))?;
+        index_writer.add_document(doc!(text_field=>"a happy day for a happy droopy"))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 2);
+        let query = QueryParser::for_index(&index, vec![text_field]).parse_query("happy")?;
+
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(4))?;
+        let normalized = searcher.search(
+            &query,
+            &TopDocs::with_limit(4).with_normalization(Normalization::MinMax),
+        )?;
+        assert_eq!(normalized.len(), 4);
+        let max = top_docs[0].0;
+        let min = top_docs[3].0;
+        assert!(max > min);
+        for ((raw_score, normalized_score, doc), (expected_score, expected_doc)) in
+            normalized.iter().zip(top_docs.iter())
+        {
+            assert_eq!(raw_score, expected_score);
+            assert_eq!(doc, expected_doc);
+            assert_nearly_equals!(*normalized_score, (raw_score - min) / (max - min));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_docs_order_by_fast_field_with_mode_brute_force() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let vals_field = schema_builder.add_i64_field("vals", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        // Docs with no value, a single value and many values, over two segments.
+        let segments: Vec<Vec<Vec<i64>>> = vec![
+            vec![vec![], vec![3], vec![5, -2, 8], vec![1, 2], vec![]],
+            vec![vec![-7], vec![4, 9, 2, 2], vec![], vec![6, -6], vec![10]],
+        ];
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment in &segments {
+            for doc_vals in segment {
+                let mut doc = TantivyDocument::default();
+                for &val in doc_vals {
+                    doc.add_i64(vals_field, val);
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        let searcher = index.reader()?.searcher();
+        let modes = [
+            MultiValueMode::Min,
+            MultiValueMode::Max,
+            MultiValueMode::Sum,
+            MultiValueMode::Avg,
+        ];
+        for mode in modes {
+            for order in [Order::Desc, Order::Asc] {
+                let mut expected: Vec<(Option<f64>, DocAddress)> = Vec::new();
+                for (segment_ord, segment) in searcher.segment_readers().iter().enumerate() {
+                    let column = segment.fast_fields().i64("vals")?;
+                    for doc in 0..segment.max_doc() {
+                        let doc_vals: Vec<f64> =
+                            column.values_for_doc(doc).map(|val| val as f64).collect();
+                        let sum: f64 = doc_vals.iter().sum();
+                        let value = (!doc_vals.is_empty()).then(|| match mode {
+                            MultiValueMode::Min => {
+                                doc_vals.iter().copied().fold(f64::MAX, f64::min)
+                            }
+                            MultiValueMode::Max => {
+                                doc_vals.iter().copied().fold(f64::MIN, f64::max)
+                            }
+                            MultiValueMode::Sum => sum,
+                            _ => sum / doc_vals.len() as f64,
+                        });
+                        expected.push((value, DocAddress::new(segment_ord as u32, doc)));
+                    }
+                }
+                let num_docs = expected.len();
+                // The docs without a value sort last.
+                expected.sort_by(|(left, _), (right, _)| match (left, right) {
+                    (Some(left), Some(right)) if order.is_desc() => right.total_cmp(left),
+                    (Some(left), Some(right)) => left.total_cmp(right),
+                    (left, right) => right.is_some().cmp(&left.is_some()),
+                });
+                for limit in [3, num_docs] {
+                    let collector = TopDocs::with_limit(limit).order_by_fast_field_with_mode(
+                        "vals",
+                        order.clone(),
+                        mode,
+                    );
+                    let top_docs = searcher.search(&AllQuery, &collector)?;
+                    let values: Vec<Option<f64>> = top_docs.iter().map(|(val, _)| *val).collect();
+                    let expected_values: Vec<Option<f64>> =
+                        expected[..limit].iter().map(|(val, _)| *val).collect();
+                    assert_eq!(values, expected_values, "{mode:?} {order:?}");
+                    for (value, doc_address) in &top_docs {
+                        assert!(expected.contains(&(*value, *doc_address)));
+                    }
+                }
+            }
+        }
+        let collector = TopDocs::with_limit(3).order_by_fast_field_with_mode(
+            "vals",
+            Order::Desc,
+            MultiValueMode::All,
+        );
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    fn make_index_with_old_segment() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let attributes = schema_builder.add_json_field("attributes", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        // The old segment was written before the documents had a rating.
+        index_writer.add_document(doc!(attributes => json!({"color": "red"})))?;
+        index_writer.add_document(doc!(attributes => json!({"color": "blue"})))?;
+        index_writer.commit()?;
+        // JSON integers are stored as `i64`.
+        index_writer.add_document(doc!(attributes => json!({"rating": -2})))?;
+        index_writer.add_document(doc!(attributes => json!({"rating": 5})))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn old_segment_ord(searcher: &Searcher) -> u32 {
+        searcher
+            .segment_readers()
+            .iter()
+            .position(|segment_reader| {
+                segment_reader
+                    .fast_fields()
+                    .u64_lenient(RATING)
+                    .unwrap()
+                    .is_none()
+            })
+            .unwrap() as u32
+    }
+
+    #[test]
+    fn test_order_by_u64_field_missing_column_policy() -> crate::Result<()> {
+        let index = make_index_with_old_segment()?;
+        let searcher = index.reader()?.searcher();
+        let old_segment_ord = old_segment_ord(&searcher);
+        let new_segment_ord = 1 - old_segment_ord;
+        let ratings = |top_docs: Vec<(u64, DocAddress)>| -> Vec<(i64, DocAddress)> {
+            top_docs
+                .into_iter()
+                .map(|(rating, doc_address)| (i64::from_u64(rating), doc_address))
+                .collect()
+        };
+
+        let collector = TopDocs::with_limit(4).order_by_u64_field_with_policy(
+            RATING,
+            Order::Desc,
+            MissingColumnPolicy::Error,
+        );
+        let err = searcher.search(&AllQuery, &collector).unwrap_err();
+        let old_segment_id = searcher.segment_reader(old_segment_ord).segment_id();
+        assert!(matches!(
+            &err,
+            TantivyError::MissingColumn { segment_id, field }
+                if *segment_id == old_segment_id && field == RATING
+        ));
+        assert!(err.to_string().contains(RATING));
+        // The default policy of `order_by_u64_field` is `Error`.
+        let collector = TopDocs::with_limit(4).order_by_u64_field(RATING, Order::Desc);
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(TantivyError::MissingColumn { .. })
+        ));
+
+        let collector = TopDocs::with_limit(4).order_by_u64_field_with_policy(
+            RATING,
+            Order::Desc,
+            MissingColumnPolicy::Default(3i64.to_u64()),
+        );
+        assert_eq!(
+            ratings(searcher.search(&AllQuery, &collector)?),
+            vec![
+                (5, DocAddress::new(new_segment_ord, 1)),
+                (3, DocAddress::new(old_segment_ord, 0)),
+                (3, DocAddress::new(old_segment_ord, 1)),
+                (-2, DocAddress::new(new_segment_ord, 0)),
+            ]
+        );
+
+        let collector = TopDocs::with_limit(4).order_by_u64_field_with_policy(
+            RATING,
+            Order::Desc,
+            MissingColumnPolicy::SkipSegment,
+        );
+        assert_eq!(
+            ratings(searcher.search(&AllQuery, &collector)?),
+            vec![
+                (5, DocAddress::new(new_segment_ord, 1)),
+                (-2, DocAddress::new(new_segment_ord, 0)),
+            ]
+        );
+        Ok(())
+    }
+
+    struct RatingScorer(MissingColumnPolicy<i64>);
+
+    impl CustomScorer<i64> for RatingScorer {
+        type Child = Box<dyn Fn(DocId) -> i64 + Send>;
+
+        fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+            self.segment_scorer_opt(segment_reader)?
+                .ok_or_else(|| missing_column_error(segment_reader, RATING))
+        }
+
+        fn segment_scorer_opt(
+            &self,
+            segment_reader: &SegmentReader,
+        ) -> crate::Result<Option<Self::Child>> {
+            let Some(column) = self.0.column(segment_reader, RATING)? else {
+                return Ok(None);
+            };
+            Ok(Some(Box::new(move |doc: DocId| {
+                column.first(doc).unwrap_or_default()
+            })))
+        }
+    }
+
+    #[test]
+    fn test_custom_score_missing_column_policy() -> crate::Result<()> {
+        let index = make_index_with_old_segment()?;
+        let searcher = index.reader()?.searcher();
+        let scores = |policy: MissingColumnPolicy<i64>| -> crate::Result<Vec<i64>> {
+            let collector = TopDocs::with_limit(4).custom_score(RatingScorer(policy));
+            Ok(searcher
+                .search(&AllQuery, &collector)?
+                .into_iter()
+                .map(|(score, _)| score)
+                .collect())
+        };
+        assert!(matches!(
+            scores(MissingColumnPolicy::Error),
+            Err(TantivyError::MissingColumn { .. })
+        ));
+        assert_eq!(scores(MissingColumnPolicy::Default(1))?, vec![5, 1, 1, -2]);
+        assert_eq!(scores(MissingColumnPolicy::SkipSegment)?, vec![5, -2]);
+        Ok(())
+    }
 }
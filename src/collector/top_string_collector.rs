@@ -0,0 +1,438 @@
+use std::cmp::Ordering;
+
+use columnar::StrColumn;
+
+use super::top_score_collector::TopNComputer;
+use crate::collector::{Collector, SegmentCollector};
+use crate::termdict::TermOrdinal;
+use crate::{
+    DocAddress, DocId, Order, Score, Searcher, SegmentOrdinal, SegmentReader, TantivyError,
+};
+
+/// Collector ranking the documents by the value of a string fast field.
+///
+/// Within a segment, the documents are compared by the term ordinal of their value, which follows
+/// the order of the terms. The segment collectors only keep the ordinals of their top documents.
+/// They are resolved to their string when the segments are merged, with the segment readers of
+/// the searcher (see [`Collector::merge_fruits_with_searcher`]), and the strings are compared to
+/// merge the segments, as ordinals of different segments are not comparable. The collector
+/// therefore requires a [`Searcher`](crate::Searcher) to merge its fruits.
+///
+/// The documents without a value for the field sort last, whatever the order, unless
+/// [`StringFastFieldTopCollector::missing_first()`] is set. Their value is `None` in the results.
+/// Multi-valued documents are ranked by their first value. Ties are broken by ascending
+/// [`DocAddress`].
+///
+/// Created with
+/// [`TopDocs::order_by_string_fast_field()`](crate::collector::TopDocs::order_by_string_fast_field).
+///
+/// ```rust
+/// use tantivy::collector::TopDocs;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST, STRING};
+/// use tantivy::{doc, DocAddress, Index, IndexWriter, Order};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let city = schema_builder.add_text_field("city", STRING | FAST);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// index_writer.add_document(doc!(city => "Paris"))?;
+/// index_writer.add_document(doc!())?;
+/// index_writer.add_document(doc!(city => "Berlin"))?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let top_docs = searcher.search(
+///     &AllQuery,
+///     &TopDocs::with_limit(3).order_by_string_fast_field("city", Order::Asc),
+/// )?;
+/// assert_eq!(
+///     top_docs,
+///     vec![
+///         (Some("Berlin".to_string()), DocAddress::new(0, 2)),
+///         (Some("Paris".to_string()), DocAddress::new(0, 0)),
+///         (None, DocAddress::new(0, 1)),
+///     ]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct StringFastFieldTopCollector {
+    field: String,
+    order: Order,
+    missing_first: bool,
+    limit: usize,
+    offset: usize,
+}
+
+impl StringFastFieldTopCollector {
+    pub(crate) fn new(
+        field: String,
+        order: Order,
+        limit: usize,
+        offset: usize,
+    ) -> StringFastFieldTopCollector {
+        StringFastFieldTopCollector {
+            field,
+            order,
+            missing_first: false,
+            limit,
+            offset,
+        }
+    }
+
+    /// Sets whether the documents without a value for the field sort first instead of last.
+    #[must_use]
+    pub fn missing_first(mut self, missing_first: bool) -> StringFastFieldTopCollector {
+        self.missing_first = missing_first;
+        self
+    }
+
+    fn cmp_values(&self, left: &Option<String>, right: &Option<String>) -> Ordering {
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                if self.order.is_desc() {
+                    right.cmp(left)
+                } else {
+                    left.cmp(right)
+                }
+            }
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) if self.missing_first => Ordering::Less,
+            (Some(_), None) if self.missing_first => Ordering::Greater,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+        }
+    }
+}
+
+impl Collector for StringFastFieldTopCollector {
+    type Fruit = Vec<(Option<String>, DocAddress)>;
+
+    type Child = StringFastFieldTopSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        let schema = segment.schema();
+        let (field, _) = schema
+            .find_field(&self.field)
+            .ok_or_else(|| TantivyError::FieldNotFound(self.field.clone()))?;
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a fast field.",
+                field_entry.name()
+            )));
+        }
+        let str_column = segment.try_fast_fields()?.str(&self.field)?;
+        let num_terms = str_column
+            .as_ref()
+            .map(|str_column| str_column.num_terms() as u64)
+            .unwrap_or(0);
+        Ok(StringFastFieldTopSegmentCollector {
+            top_n: TopNComputer::new(self.limit + self.offset),
+            str_column,
+            num_terms,
+            order: self.order.clone(),
+            missing_first: self.missing_first,
+            segment_ord: segment_local_id,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        _segment_fruits: Vec<Vec<(Option<TermOrdinal>, DocAddress)>>,
+    ) -> crate::Result<Self::Fruit> {
+        Err(TantivyError::InternalError(
+            "Ordering by a string fast field requires the searcher to resolve the term ordinals, \
+             see `Collector::merge_fruits_with_searcher`"
+                .to_string(),
+        ))
+    }
+
+    fn merge_fruits_with_searcher(
+        &self,
+        segment_fruits: Vec<Vec<(Option<TermOrdinal>, DocAddress)>>,
+        searcher: &Searcher,
+    ) -> crate::Result<Self::Fruit> {
+        let mut top_docs = Vec::new();
+        for segment_fruit in segment_fruits {
+            let Some(&(_, first_doc_address)) = segment_fruit.first() else {
+                continue;
+            };
+            let segment_reader = searcher.segment_reader(first_doc_address.segment_ord);
+            let str_column = segment_reader.try_fast_fields()?.str(&self.field)?;
+            for (term_ord_opt, doc_address) in segment_fruit {
+                let (Some(term_ord), Some(str_column)) = (term_ord_opt, &str_column) else {
+                    top_docs.push((None, doc_address));
+                    continue;
+                };
+                let mut value = String::new();
+                if !str_column.ord_to_str(term_ord, &mut value)? {
+                    return Err(TantivyError::InternalError(format!(
+                        "Term ordinal {term_ord} not found in the dictionary"
+                    )));
+                }
+                top_docs.push((Some(value), doc_address));
+            }
+        }
+        top_docs.sort_by(|(left_value, left_doc), (right_value, right_doc)| {
+            self.cmp_values(left_value, right_value)
+                .then_with(|| left_doc.cmp(right_doc))
+        });
+        Ok(top_docs
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect())
+    }
+}
+
+/// Segment collector of a [`StringFastFieldTopCollector`].
+pub struct StringFastFieldTopSegmentCollector {
+    /// The documents are ranked by a key derived from their term ordinal, the highest first.
+    top_n: TopNComputer<u64, DocId>,
+    /// `None` if no document of the segment has a value for the field.
+    str_column: Option<StrColumn>,
+    num_terms: u64,
+    order: Order,
+    missing_first: bool,
+    segment_ord: SegmentOrdinal,
+}
+
+impl StringFastFieldTopSegmentCollector {
+    /// Key of the documents without a value. The keys of the values are in `1..=num_terms`.
+    fn missing_key(&self) -> u64 {
+        if self.missing_first {
+            u64::MAX
+        } else {
+            0
+        }
+    }
+
+    fn sort_key(&self, doc: DocId) -> u64 {
+        let term_ord_opt = self
+            .str_column
+            .as_ref()
+            .and_then(|str_column| str_column.ords().first(doc));
+        match term_ord_opt {
+            Some(term_ord) if self.order.is_desc() => term_ord + 1,
+            Some(term_ord) => self.num_terms - term_ord,
+            None => self.missing_key(),
+        }
+    }
+
+    fn term_ord(&self, sort_key: u64) -> Option<u64> {
+        if sort_key == self.missing_key() {
+            None
+        } else if self.order.is_desc() {
+            Some(sort_key - 1)
+        } else {
+            Some(self.num_terms - sort_key)
+        }
+    }
+}
+
+impl SegmentCollector for StringFastFieldTopSegmentCollector {
+    /// The term ordinals of the top documents, resolved when the segments are merged.
+    type Fruit = Vec<(Option<TermOrdinal>, DocAddress)>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let sort_key = self.sort_key(doc);
+        self.top_n.push(sort_key, doc);
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        let top_n = std::mem::replace(&mut self.top_n, TopNComputer::new(0));
+        top_n
+            .into_sorted_vec()
+            .into_iter()
+            .map(|comparable_doc| {
+                (
+                    self.term_ord(comparable_doc.feature),
+                    DocAddress::new(self.segment_ord, comparable_doc.doc),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::TopDocs;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST, STRING, TEXT};
+    use crate::{Index, IndexWriter, TantivyDocument};
+
+    /// Creates an index with one segment per slice of values, `None` being a document without
+    /// a value.
+    fn create_index(segments: &[&[Option<&str>]]) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let city = schema_builder.add_text_field("city", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment_values in segments {
+            for value in *segment_values {
+                let mut doc = TantivyDocument::default();
+                if let Some(value) = value {
+                    doc.add_text(city, value);
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn top_docs(
+        index: &Index,
+        collector: StringFastFieldTopCollector,
+    ) -> crate::Result<Vec<(Option<String>, DocAddress)>> {
+        index.reader()?.searcher().search(&AllQuery, &collector)
+    }
+
+    /// Sorts all the documents of the index, the way the collector should.
+    fn brute_force(
+        index: &Index,
+        order: Order,
+        missing_first: bool,
+    ) -> crate::Result<Vec<(Option<String>, DocAddress)>> {
+        let searcher = index.reader()?.searcher();
+        let mut all_docs = Vec::new();
+        for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+            let str_column = segment_reader.fast_fields().str("city")?.unwrap();
+            for doc in 0..segment_reader.max_doc() {
+                let value = str_column.term_ords(doc).next().map(|term_ord| {
+                    let mut value = String::new();
+                    str_column.ord_to_str(term_ord, &mut value).unwrap();
+                    value
+                });
+                all_docs.push((value, DocAddress::new(segment_ord as u32, doc)));
+            }
+        }
+        let collector = TopDocs::with_limit(1)
+            .order_by_string_fast_field("city", order)
+            .missing_first(missing_first);
+        all_docs.sort_by(|(left_value, left_doc), (right_value, right_doc)| {
+            collector
+                .cmp_values(left_value, right_value)
+                .then_with(|| left_doc.cmp(right_doc))
+        });
+        Ok(all_docs)
+    }
+
+    #[test]
+    fn test_order_by_string_fast_field_across_segments() -> crate::Result<()> {
+        let index = create_index(&[
+            &[
+                Some("lyon"),
+                Some("paris"),
+                None,
+                Some("berlin"),
+                Some("paris"),
+            ],
+            // Overlaps with the first segment.
+            &[Some("paris"), Some("amsterdam"), None, Some("lyon")],
+            // Disjoint from the other segments.
+            &[Some("zurich"), Some("oslo"), Some("cairo")],
+        ])?;
+        let top_docs_asc = top_docs(
+            &index,
+            TopDocs::with_limit(4).order_by_string_fast_field("city", Order::Asc),
+        )?;
+        let values: Vec<Option<&str>> = top_docs_asc
+            .iter()
+            .map(|(value, _)| value.as_deref())
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                Some("amsterdam"),
+                Some("berlin"),
+                Some("cairo"),
+                Some("lyon")
+            ]
+        );
+        for order in [Order::Asc, Order::Desc] {
+            for missing_first in [false, true] {
+                let expected = brute_force(&index, order.clone(), missing_first)?;
+                for (limit, offset) in [(1, 0), (3, 2), (12, 0), (20, 5)] {
+                    let collector = TopDocs::with_limit(limit)
+                        .and_offset(offset)
+                        .order_by_string_fast_field("city", order.clone())
+                        .missing_first(missing_first);
+                    let expected_page: Vec<_> =
+                        expected.iter().skip(offset).take(limit).cloned().collect();
+                    assert_eq!(top_docs(&index, collector)?, expected_page);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_string_fast_field_ties_and_missing() -> crate::Result<()> {
+        let index = create_index(&[&[None, Some("b"), Some("a")], &[Some("b"), None]])?;
+        let collector = TopDocs::with_limit(5).order_by_string_fast_field("city", Order::Desc);
+        assert_eq!(
+            top_docs(&index, collector)?,
+            vec![
+                (Some("b".to_string()), DocAddress::new(0, 1)),
+                (Some("b".to_string()), DocAddress::new(1, 0)),
+                (Some("a".to_string()), DocAddress::new(0, 2)),
+                (None, DocAddress::new(0, 0)),
+                (None, DocAddress::new(1, 1)),
+            ]
+        );
+        let collector = TopDocs::with_limit(3)
+            .order_by_string_fast_field("city", Order::Asc)
+            .missing_first(true);
+        assert_eq!(
+            top_docs(&index, collector)?,
+            vec![
+                (None, DocAddress::new(0, 0)),
+                (None, DocAddress::new(1, 1)),
+                (Some("a".to_string()), DocAddress::new(0, 2)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_string_fast_field_segment_without_values() -> crate::Result<()> {
+        let index = create_index(&[&[None, None], &[Some("a")]])?;
+        let collector = TopDocs::with_limit(3).order_by_string_fast_field("city", Order::Asc);
+        let top_docs = top_docs(&index, collector)?;
+        let values: Vec<Option<&str>> =
+            top_docs.iter().map(|(value, _)| value.as_deref()).collect();
+        assert_eq!(values, vec![Some("a"), None, None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_string_fast_field_not_fast() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(TantivyDocument::default())?;
+        index_writer.commit()?;
+        let collector = TopDocs::with_limit(3).order_by_string_fast_field("title", Order::Asc);
+        assert!(matches!(
+            top_docs(&index, collector),
+            Err(TantivyError::SchemaError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Debug;
+
+use columnar::Column;
+
+use crate::index::SegmentReader;
+use crate::{DocAddress, DocId, SegmentOrdinal};
+
+/// Maximum number of documents of a [`ColumnBlock`].
+const COLUMN_BLOCK_LEN: usize = 1_024;
+
+/// Returns the first value of each alive document of the segments, in [`DocAddress`] order.
+///
+/// `columns` has one entry per segment, `None` if the segment does not have the column.
+pub(crate) fn column_iter<'a, T>(
+    segment_readers: &'a [SegmentReader],
+    columns: Vec<Option<Column<T>>>,
+) -> impl Iterator<Item = (DocAddress, Option<T>)> + 'a
+where
+    T: PartialOrd + Copy + Debug + Send + Sync + 'static,
+{
+    segment_readers.iter().zip(columns).enumerate().flat_map(
+        |(segment_ord, (segment_reader, column_opt))| {
+            segment_reader.doc_ids_alive().map(move |doc_id| {
+                let doc_address = DocAddress::new(segment_ord as SegmentOrdinal, doc_id);
+                let value = column_opt.as_ref().and_then(|column| column.first(doc_id));
+                (doc_address, value)
+            })
+        },
+    )
+}
+
+/// Blocks of the first values of the alive documents of the segments of a
+/// [`Searcher`](crate::Searcher), in [`DocAddress`] order.
+///
+/// Created by [`Searcher::column_blocks()`](crate::Searcher::column_blocks). The blocks borrow
+/// buffers that are reused from one block to the next, which is why `ColumnBlocks` is not an
+/// [`Iterator`]: the blocks are visited with [`ColumnBlocks::next_block()`].
+pub struct ColumnBlocks<'a, T> {
+    segment_readers: &'a [SegmentReader],
+    columns: Vec<Option<Column<T>>>,
+    segment_ord: usize,
+    next_doc: DocId,
+    docs: Vec<DocId>,
+    values: Vec<Option<T>>,
+}
+
+/// The first values of a block of alive documents of a segment, see [`ColumnBlocks`].
+#[derive(Debug)]
+pub struct ColumnBlock<'b, T> {
+    segment_ord: SegmentOrdinal,
+    docs: &'b [DocId],
+    values: &'b [Option<T>],
+}
+
+impl<'b, T: Copy> ColumnBlock<'b, T> {
+    /// Returns the ordinal of the segment of the documents.
+    pub fn segment_ord(&self) -> SegmentOrdinal {
+        self.segment_ord
+    }
+
+    /// Returns the ids of the documents within their segment, in increasing order.
+    pub fn docs(&self) -> &'b [DocId] {
+        self.docs
+    }
+
+    /// Returns the first value of each of the [`ColumnBlock::docs()`], `None` if the document has
+    /// no value.
+    pub fn values(&self) -> &'b [Option<T>] {
+        self.values
+    }
+
+    /// Returns the addresses of the documents along with their first value.
+    pub fn iter(&self) -> impl Iterator<Item = (DocAddress, Option<T>)> + 'b {
+        let segment_ord = self.segment_ord;
+        self.docs
+            .iter()
+            .zip(self.values)
+            .map(move |(&doc_id, &value)| (DocAddress::new(segment_ord, doc_id), value))
+    }
+}
+
+impl<'a, T> ColumnBlocks<'a, T>
+where T: PartialOrd + Copy + Debug + Send + Sync + 'static
+{
+    pub(crate) fn new(
+        segment_readers: &'a [SegmentReader],
+        columns: Vec<Option<Column<T>>>,
+    ) -> Self {
+        ColumnBlocks {
+            segment_readers,
+            columns,
+            segment_ord: 0,
+            next_doc: 0,
+            docs: Vec::with_capacity(COLUMN_BLOCK_LEN),
+            values: Vec::with_capacity(COLUMN_BLOCK_LEN),
+        }
+    }
+
+    /// Returns the next block, or `None` once all of the segments have been visited.
+    ///
+    /// A block holds up to 1024 documents of a single segment. The segments without alive
+    /// documents are skipped.
+    pub fn next_block(&mut self) -> Option<ColumnBlock<'_, T>> {
+        loop {
+            let segment_reader = self.segment_readers.get(self.segment_ord)?;
+            let max_doc = segment_reader.max_doc();
+            let alive_bitset_opt = segment_reader.alive_bitset();
+            self.docs.clear();
+            while self.next_doc < max_doc && self.docs.len() < COLUMN_BLOCK_LEN {
+                let doc_id = self.next_doc;
+                self.next_doc += 1;
+                if alive_bitset_opt.is_none_or(|alive_bitset| alive_bitset.is_alive(doc_id)) {
+                    self.docs.push(doc_id);
+                }
+            }
+            if self.docs.is_empty() {
+                self.segment_ord += 1;
+                self.next_doc = 0;
+                continue;
+            }
+            self.values.clear();
+            self.values.resize(self.docs.len(), None);
+            if let Some(column) = &self.columns[self.segment_ord] {
+                column.first_vals(&self.docs, &mut self.values);
+            }
+            return Some(ColumnBlock {
+                segment_ord: self.segment_ord as SegmentOrdinal,
+                docs: &self.docs,
+                values: &self.values,
+            });
+        }
+    }
+}
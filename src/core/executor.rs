@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "quickwit")]
 use futures_util::{future::Either, FutureExt};
@@ -94,6 +94,74 @@ impl Executor {
         }
     }
 
+    /// Perform a map in the thread pool, reducing the results with `reduce` as soon as they are
+    /// available.
+    ///
+    /// Unlike [`Executor::map`], the results are not all kept until the end: each one is merged
+    /// into a single accumulator as soon as its task completes, so that at most one result per
+    /// thread, plus the accumulator, are alive at any time. The results are reduced in the order
+    /// their tasks complete, so `reduce` needs to be commutative and associative for the outcome
+    /// to be deterministic.
+    ///
+    /// Returns `None` if there are no arguments. As for [`Executor::map`], panics in the task
+    /// propagate to the caller.
+    pub fn map_reduce<A, R, F, G>(
+        &self,
+        f: F,
+        reduce: G,
+        args: impl Iterator<Item = A>,
+    ) -> crate::Result<Option<R>>
+    where
+        A: Send,
+        R: Send,
+        F: Sized + Sync + Fn(A) -> crate::Result<R>,
+        G: Sized + Sync + Fn(R, R) -> crate::Result<R>,
+    {
+        match self {
+            Executor::SingleThread => {
+                let mut accumulator: Option<R> = None;
+                for arg in args {
+                    let result = f(arg)?;
+                    accumulator = Some(match accumulator.take() {
+                        Some(merged) => reduce(merged, result)?,
+                        None => result,
+                    });
+                }
+                Ok(accumulator)
+            }
+            Executor::ThreadPool(pool) => {
+                let args: Vec<A> = args.collect();
+                let accumulator: Mutex<crate::Result<Option<R>>> = Mutex::new(Ok(None));
+                pool.scope(|scope| {
+                    for arg in args {
+                        let f_ref = &f;
+                        let reduce_ref = &reduce;
+                        let accumulator_ref = &accumulator;
+                        scope.spawn(move |_| {
+                            if accumulator_ref.lock().unwrap().is_err() {
+                                return;
+                            }
+                            let result = f_ref(arg);
+                            let mut accumulator_guard = accumulator_ref.lock().unwrap();
+                            let merged = match (
+                                std::mem::replace(&mut *accumulator_guard, Ok(None)),
+                                result,
+                            ) {
+                                (Err(err), _) | (_, Err(err)) => Err(err),
+                                (Ok(None), Ok(result)) => Ok(Some(result)),
+                                (Ok(Some(merged)), Ok(result)) => {
+                                    reduce_ref(merged, result).map(Some)
+                                }
+                            };
+                            *accumulator_guard = merged;
+                        });
+                    }
+                });
+                accumulator.into_inner().unwrap()
+            }
+        }
+    }
+
     /// Spawn a task on the pool, returning a future completing on task success.
     ///
     /// If the task panics, returns `Err(())`.
@@ -123,6 +191,9 @@ impl Executor {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
     use super::Executor;
 
     #[test]
@@ -175,6 +246,91 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_map_reduce_singlethread() {
+        let result = Executor::single_thread()
+            .map_reduce(|i| Ok(i * 2), |left, right| Ok(left + right), 0..1_000)
+            .unwrap();
+        assert_eq!(result, Some(999 * 1_000));
+        let empty: Option<usize> = Executor::single_thread()
+            .map_reduce(Ok, |left, right| Ok(left + right), 0..0)
+            .unwrap();
+        assert_eq!(empty, None);
+    }
+
+    #[test]
+    fn test_map_reduce_multithread_error() {
+        let result: crate::Result<Option<usize>> = Executor::multi_thread(3, "search-test")
+            .unwrap()
+            .map_reduce(
+                |i| {
+                    if i == 5 {
+                        Err(crate::TantivyError::InvalidArgument("fail".to_string()))
+                    } else {
+                        Ok(i)
+                    }
+                },
+                |left, right| Ok(left + right),
+                0..10,
+            );
+        assert!(matches!(
+            result,
+            Err(crate::TantivyError::InvalidArgument(_))
+        ));
+    }
+
+    /// A result counting the number of results alive.
+    struct TrackedResult {
+        value: usize,
+        num_alive: Arc<AtomicUsize>,
+        max_num_alive: Arc<AtomicUsize>,
+    }
+
+    impl TrackedResult {
+        fn new(value: usize, num_alive: &Arc<AtomicUsize>, max: &Arc<AtomicUsize>) -> Self {
+            let alive = num_alive.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(alive, Ordering::SeqCst);
+            TrackedResult {
+                value,
+                num_alive: num_alive.clone(),
+                max_num_alive: max.clone(),
+            }
+        }
+    }
+
+    impl Drop for TrackedResult {
+        fn drop(&mut self) {
+            self.num_alive.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_map_reduce_multithread_bounds_alive_results() {
+        let num_threads = 3;
+        let num_alive: Arc<AtomicUsize> = Default::default();
+        let max_num_alive: Arc<AtomicUsize> = Default::default();
+        let result = Executor::multi_thread(num_threads, "search-test")
+            .unwrap()
+            .map_reduce(
+                |i| Ok(TrackedResult::new(i, &num_alive, &max_num_alive)),
+                |left, right| {
+                    let merged = TrackedResult::new(
+                        left.value + right.value,
+                        &left.num_alive,
+                        &left.max_num_alive,
+                    );
+                    Ok(merged)
+                },
+                0..1_000,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.value, 999 * 1_000 / 2);
+        // One result per thread, the accumulator, and the result of a merge before the merged
+        // results are dropped.
+        assert!(max_num_alive.load(Ordering::SeqCst) <= num_threads + 2);
+    }
+
     #[cfg(feature = "quickwit")]
     #[test]
     fn test_cancel_cpu_intensive_tasks() {
@@ -61,6 +61,19 @@ impl IndexingPositionsPerPath {
     pub fn clear(&mut self) {
         self.positions_per_path.clear();
     }
+
+    /// Returns the number of dropped tokens and of tokenizer errors of all of the paths.
+    pub fn num_dropped_tokens_and_errors(&self) -> (u32, u32) {
+        self.positions_per_path.values().fold(
+            (0, 0),
+            |(num_dropped_tokens, num_errors), position| {
+                (
+                    num_dropped_tokens + position.num_dropped_tokens,
+                    num_errors + position.num_tokenizer_errors,
+                )
+            },
+        )
+    }
 }
 
 /// Convert JSON_PATH_SEGMENT_SEP to a dot.
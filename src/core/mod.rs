@@ -1,14 +1,29 @@
+mod column_iter;
 mod executor;
 #[doc(hidden)]
 pub mod json_utils;
+mod multi_searcher;
+mod search_cancellation;
+mod search_progress;
+mod search_scratch;
 pub mod searcher;
+mod searcher_statistics;
+mod segment_candidates;
 
 use std::path::Path;
 
 use once_cell::sync::Lazy;
 
+pub use self::column_iter::{ColumnBlock, ColumnBlocks};
 pub use self::executor::Executor;
-pub use self::searcher::{Searcher, SearcherGeneration};
+pub use self::multi_searcher::{MultiDocAddress, MultiSearcher};
+pub(crate) use self::search_cancellation::CancellableWeight;
+pub use self::search_cancellation::CancellationToken;
+pub use self::search_progress::{SearchProgress, SearchProgressCallback};
+pub use self::search_scratch::{ScratchVec, SearchScratch};
+pub use self::searcher::{Searcher, SearcherGeneration, SegmentDocFilter};
+pub use self::searcher_statistics::SearcherStatistics;
+pub use self::segment_candidates::SegmentCandidates;
 
 /// The meta file contains all the information about the list of segments and the schema
 /// of the index.
@@ -21,5 +36,16 @@ pub static META_FILEPATH: Lazy<&'static Path> = Lazy::new(|| Path::new("meta.jso
 /// are currently in the directory
 pub static MANAGED_FILEPATH: Lazy<&'static Path> = Lazy::new(|| Path::new(".managed.json"));
 
+/// The commit payloads file contains the payloads of the last commits, so that they remain
+/// available once `meta.json` has been overwritten by later commits.
+pub static COMMIT_PAYLOADS_FILEPATH: Lazy<&'static Path> =
+    Lazy::new(|| Path::new("commit_payloads.json"));
+
+/// The commit points file contains the metas of the last commits, so that the files they use
+/// are kept and they can still be searched once `meta.json` has been overwritten by later
+/// commits.
+pub static COMMIT_POINTS_FILEPATH: Lazy<&'static Path> =
+    Lazy::new(|| Path::new("commit_points.json"));
+
 #[cfg(test)]
 mod tests;
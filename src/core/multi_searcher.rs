@@ -0,0 +1,419 @@
+use crate::collector::Collector;
+use crate::index::SegmentReader;
+use crate::query::{Bm25StatisticsProvider, EnableScoring, Query, Weight};
+use crate::schema::document::DocumentDeserialize;
+use crate::schema::{Field, Schema, Term};
+use crate::{DocAddress, Searcher, SegmentOrdinal, TantivyError};
+
+/// Address of a document in one of the indexes of a [`MultiSearcher`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MultiDocAddress {
+    /// Position of the searcher of the document, in the searchers of the [`MultiSearcher`].
+    pub index_ord: u32,
+    /// Address of the document, relative to the searcher of its index.
+    pub doc_address: DocAddress,
+}
+
+/// Searches several indexes sharing the same schema as if they were a single index.
+///
+/// The segments of all the searchers are numbered one after the other, so the collectors see
+/// the segments of all of the indexes, and the [`DocAddress`]es of the fruits are unique across
+/// indexes. They can be resolved into the index they belong to with
+/// [`MultiSearcher::multi_doc_address`], and the documents can be fetched with
+/// [`MultiSearcher::doc`].
+///
+/// The BM25 statistics (number of documents, number of tokens and document frequencies) are
+/// computed over all of the indexes, so scores are the same as if all of the documents were in a
+/// single index.
+///
+/// Since collectors merge the fruits of all of the segments together, all collectors work
+/// across indexes, including top docs, counts and aggregations.
+#[derive(Clone)]
+pub struct MultiSearcher {
+    searchers: Vec<Searcher>,
+    /// `segment_ord_offsets[i]` is the ordinal of the first segment of the i-th searcher.
+    segment_ord_offsets: Vec<SegmentOrdinal>,
+}
+
+impl MultiSearcher {
+    /// Creates a `MultiSearcher` over the given searchers.
+    ///
+    /// Returns an error if there is no searcher, or if the searchers do not share the same
+    /// schema.
+    pub fn new(searchers: Vec<Searcher>) -> crate::Result<MultiSearcher> {
+        let Some(first_searcher) = searchers.first() else {
+            return Err(TantivyError::InvalidArgument(
+                "A MultiSearcher requires at least one searcher".to_string(),
+            ));
+        };
+        if let Some(index_ord) = searchers
+            .iter()
+            .position(|searcher| searcher.schema() != first_searcher.schema())
+        {
+            return Err(TantivyError::SchemaError(format!(
+                "The schema of searcher {index_ord} differs from the schema of searcher 0"
+            )));
+        }
+        let mut segment_ord_offsets = Vec::with_capacity(searchers.len());
+        let mut num_segments = 0;
+        for searcher in &searchers {
+            segment_ord_offsets.push(num_segments);
+            num_segments += searcher.segment_readers().len() as SegmentOrdinal;
+        }
+        Ok(MultiSearcher {
+            searchers,
+            segment_ord_offsets,
+        })
+    }
+
+    /// Returns the searchers of the `MultiSearcher`.
+    pub fn searchers(&self) -> &[Searcher] {
+        &self.searchers
+    }
+
+    /// Returns the schema shared by the searchers.
+    pub fn schema(&self) -> &Schema {
+        self.searchers[0].schema()
+    }
+
+    /// Returns the overall number of documents in the indexes.
+    pub fn num_docs(&self) -> u64 {
+        self.searchers.iter().map(Searcher::num_docs).sum()
+    }
+
+    /// Returns the overall number of documents containing the given term, in all of the indexes.
+    pub fn doc_freq(&self, term: &Term) -> crate::Result<u64> {
+        let mut total_doc_freq = 0;
+        for searcher in &self.searchers {
+            total_doc_freq += searcher.doc_freq(term)?;
+        }
+        Ok(total_doc_freq)
+    }
+
+    /// Resolves a [`DocAddress`] returned by a search on the `MultiSearcher` into the index of
+    /// the document and its address in that index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the segment ordinal of the address is out of bounds.
+    pub fn multi_doc_address(&self, doc_address: DocAddress) -> MultiDocAddress {
+        let index_ord = self
+            .segment_ord_offsets
+            .partition_point(|&offset| offset <= doc_address.segment_ord)
+            - 1;
+        let segment_ord = doc_address.segment_ord - self.segment_ord_offsets[index_ord];
+        assert!(
+            (segment_ord as usize) < self.searchers[index_ord].segment_readers().len(),
+            "Segment ordinal {} is out of bounds",
+            doc_address.segment_ord
+        );
+        MultiDocAddress {
+            index_ord: index_ord as u32,
+            doc_address: DocAddress::new(segment_ord, doc_address.doc_id),
+        }
+    }
+
+    /// Returns the segment reader associated with a segment ordinal of the `MultiSearcher`.
+    pub fn segment_reader(&self, segment_ord: SegmentOrdinal) -> &SegmentReader {
+        let multi_doc_address = self.multi_doc_address(DocAddress::new(segment_ord, 0));
+        self.searchers[multi_doc_address.index_ord as usize]
+            .segment_reader(multi_doc_address.doc_address.segment_ord)
+    }
+
+    /// Fetches a document from the index it belongs to.
+    ///
+    /// `doc_address` is an address returned by a search on the `MultiSearcher`.
+    pub fn doc<D: DocumentDeserialize>(&self, doc_address: DocAddress) -> crate::Result<D> {
+        let multi_doc_address = self.multi_doc_address(doc_address);
+        self.searchers[multi_doc_address.index_ord as usize].doc(multi_doc_address.doc_address)
+    }
+
+    /// Runs a query on all of the indexes, and merges the results with the collector.
+    ///
+    /// Segments are collected with the [`Executor`](crate::Executor) of the index of the first
+    /// searcher.
+    ///
+    /// The fruits are merged with [`Collector::merge_fruits`], as they do not come from a single
+    /// [`Searcher`]: the collectors requiring
+    /// [`Collector::merge_fruits_with_searcher`], such as
+    /// [`TopDocs::order_by_string_fast_field`](crate::collector::TopDocs::order_by_string_fast_field),
+    /// are not supported.
+    pub fn search<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+    ) -> crate::Result<C::Fruit> {
+        self.search_with_statistics_provider(query, collector, self)
+    }
+
+    /// Same as [`MultiSearcher::search`], but allows specifying the [`Bm25StatisticsProvider`].
+    pub fn search_with_statistics_provider<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+        statistics_provider: &dyn Bm25StatisticsProvider,
+    ) -> crate::Result<C::Fruit> {
+        // The weights can depend on the searcher, but they all share the same statistics.
+        let weights = self
+            .searchers
+            .iter()
+            .map(|searcher| {
+                let enable_scoring = if collector.requires_scoring() {
+                    EnableScoring::enabled_from_statistics_provider(statistics_provider, searcher)
+                } else {
+                    EnableScoring::disabled_from_searcher(searcher)
+                };
+                query.weight(enable_scoring)
+            })
+            .collect::<crate::Result<Vec<Box<dyn Weight>>>>()?;
+        let segments = self
+            .searchers
+            .iter()
+            .zip(&weights)
+            .zip(&self.segment_ord_offsets)
+            .flat_map(|((searcher, weight), &segment_ord_offset)| {
+                searcher.segment_readers().iter().enumerate().map(
+                    move |(segment_ord, segment_reader)| {
+                        (
+                            weight.as_ref(),
+                            segment_ord_offset + segment_ord as SegmentOrdinal,
+                            segment_reader,
+                        )
+                    },
+                )
+            });
+        let executor = self.searchers[0].index().search_executor();
+        let fruits = executor.map(
+            |(weight, segment_ord, segment_reader)| {
+                collector.collect_segment(weight, segment_ord, segment_reader)
+            },
+            segments,
+        )?;
+        collector.merge_fruits(fruits)
+    }
+}
+
+impl Bm25StatisticsProvider for MultiSearcher {
+    fn total_num_tokens(&self, field: Field) -> crate::Result<u64> {
+        let mut total_num_tokens = 0u64;
+        for searcher in &self.searchers {
+            total_num_tokens += searcher.total_num_tokens(field)?;
+        }
+        Ok(total_num_tokens)
+    }
+
+    fn total_num_docs(&self) -> crate::Result<u64> {
+        let mut total_num_docs = 0u64;
+        for searcher in &self.searchers {
+            total_num_docs += searcher.total_num_docs()?;
+        }
+        Ok(total_num_docs)
+    }
+
+    fn doc_freq(&self, term: &Term) -> crate::Result<u64> {
+        self.doc_freq(term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{MultiDocAddress, MultiSearcher};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, TopDocs};
+    use crate::query::QueryParser;
+    use crate::schema::{Schema, Value, FAST, STORED, STRING, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, Score, Searcher, TantivyDocument};
+
+    const WORDS: [&str; 6] = ["apple", "banana", "cherry", "date", "elder", "fig"];
+
+    fn schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("id", STORED | FAST);
+        schema_builder.add_text_field("body", TEXT);
+        schema_builder.add_text_field("month", STRING | FAST);
+        schema_builder.build()
+    }
+
+    /// Creates the document with the given id, with a body of varying length and word
+    /// frequencies.
+    fn test_doc(schema: &Schema, id: u64) -> TantivyDocument {
+        let id_field = schema.get_field("id").unwrap();
+        let body = schema.get_field("body").unwrap();
+        let month = schema.get_field("month").unwrap();
+        let text: Vec<&str> = (0..(id % 7 + 1))
+            .map(|i| WORDS[((id * 5 + i * i) % WORDS.len() as u64) as usize])
+            .collect();
+        doc!(
+            id_field => id,
+            body => text.join(" "),
+            month => format!("month-{}", id % 3),
+        )
+    }
+
+    /// Indexes the ids in an index, committing every `commit_every` documents.
+    fn create_index(ids: impl Iterator<Item = u64>, commit_every: u64) -> crate::Result<Index> {
+        let schema = schema();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for id in ids {
+            index_writer.add_document(test_doc(&schema, id))?;
+            if id % commit_every == 0 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn monthly_searchers() -> crate::Result<Vec<Searcher>> {
+        [0..40u64, 40..100, 100..130]
+            .into_iter()
+            .map(|ids| Ok(create_index(ids, 17)?.reader()?.searcher()))
+            .collect()
+    }
+
+    fn id_of(doc: &TantivyDocument, schema: &Schema) -> u64 {
+        doc.get_first(schema.get_field("id").unwrap())
+            .unwrap()
+            .as_u64()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_multi_searcher_bm25_matches_single_index() -> crate::Result<()> {
+        let schema = schema();
+        let body = schema.get_field("body").unwrap();
+        let multi_searcher = MultiSearcher::new(monthly_searchers()?)?;
+        let single_index = create_index(0..130, 29)?;
+        let single_searcher = single_index.reader()?.searcher();
+        assert_eq!(multi_searcher.num_docs(), single_searcher.num_docs());
+
+        let query_parser = QueryParser::for_index(&single_index, vec![body]);
+        for query_str in ["apple", "banana cherry", "fig OR date", "+elder apple"] {
+            let query = query_parser.parse_query(query_str)?;
+            let multi_top_docs = multi_searcher.search(&query, &TopDocs::with_limit(200))?;
+            let single_top_docs = single_searcher.search(&query, &TopDocs::with_limit(200))?;
+            assert!(!multi_top_docs.is_empty());
+            assert_eq!(multi_top_docs.len(), single_top_docs.len());
+
+            let single_scores: HashMap<u64, Score> = single_top_docs
+                .iter()
+                .map(|(score, doc_address)| {
+                    let doc: TantivyDocument = single_searcher.doc(*doc_address).unwrap();
+                    (id_of(&doc, &schema), *score)
+                })
+                .collect();
+            for (score, doc_address) in &multi_top_docs {
+                let doc: TantivyDocument = multi_searcher.doc(*doc_address)?;
+                let expected = single_scores[&id_of(&doc, &schema)];
+                assert!(
+                    (score - expected).abs() < 1e-5,
+                    "{query_str}: {score} != {expected}"
+                );
+            }
+            assert_eq!(
+                multi_searcher.search(&query, &Count)?,
+                single_searcher.search(&query, &Count)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_searcher_aggregations() -> crate::Result<()> {
+        let schema = schema();
+        let body = schema.get_field("body").unwrap();
+        let multi_searcher = MultiSearcher::new(monthly_searchers()?)?;
+        let single_index = create_index(0..130, 29)?;
+        let single_searcher = single_index.reader()?.searcher();
+
+        let aggs: Aggregations = serde_json::from_value(json!({
+            "months": {
+                "terms": { "field": "month" },
+                "aggs": { "max_id": { "max": { "field": "id" } } }
+            },
+            "ids": { "histogram": { "field": "id", "interval": 25.0 } }
+        }))
+        .unwrap();
+        let query = QueryParser::for_index(&single_index, vec![body]).parse_query("banana")?;
+        let collector = AggregationCollector::from_aggs(aggs, Default::default());
+        assert_eq!(
+            serde_json::to_value(multi_searcher.search(&query, &collector)?)?,
+            serde_json::to_value(single_searcher.search(&query, &collector)?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_doc_address() -> crate::Result<()> {
+        let schema = schema();
+        let searchers = monthly_searchers()?;
+        let num_segments: Vec<usize> = searchers
+            .iter()
+            .map(|searcher| searcher.segment_readers().len())
+            .collect();
+        let multi_searcher = MultiSearcher::new(searchers)?;
+
+        let last_segment_ord = (num_segments.iter().sum::<usize>() - 1) as u32;
+        assert_eq!(
+            multi_searcher.multi_doc_address(DocAddress::new(last_segment_ord, 3)),
+            MultiDocAddress {
+                index_ord: 2,
+                doc_address: DocAddress::new(num_segments[2] as u32 - 1, 3),
+            }
+        );
+        assert_eq!(
+            multi_searcher.multi_doc_address(DocAddress::new(num_segments[0] as u32, 0)),
+            MultiDocAddress {
+                index_ord: 1,
+                doc_address: DocAddress::new(0, 0),
+            }
+        );
+
+        // Every document of the monthly indexes is found exactly once.
+        let all_docs = multi_searcher.search(&crate::query::AllQuery, &TopDocs::with_limit(200))?;
+        let mut ids: Vec<u64> = all_docs
+            .iter()
+            .map(|(_, doc_address)| {
+                let multi_doc_address = multi_searcher.multi_doc_address(*doc_address);
+                let doc: TantivyDocument = multi_searcher.searchers()
+                    [multi_doc_address.index_ord as usize]
+                    .doc(multi_doc_address.doc_address)
+                    .unwrap();
+                let id = id_of(&doc, &schema);
+                let expected_index_ord = if id < 40 {
+                    0
+                } else if id < 100 {
+                    1
+                } else {
+                    2
+                };
+                assert_eq!(multi_doc_address.index_ord, expected_index_ord);
+                id
+            })
+            .collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (0..130).collect::<Vec<u64>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_searcher_errors() -> crate::Result<()> {
+        assert!(MultiSearcher::new(Vec::new()).is_err());
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("body", TEXT);
+        let other_index = Index::create_in_ram(schema_builder.build());
+        let mut searchers = monthly_searchers()?;
+        searchers.push(other_index.reader()?.searcher());
+        assert!(matches!(
+            MultiSearcher::new(searchers),
+            Err(crate::TantivyError::SchemaError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::query::{Explanation, Scorer, Weight};
+use crate::{
+    DocId, DocSet, Score, SegmentReader, TantivyError, COLLECT_BLOCK_BUFFER_LEN, TERMINATED,
+};
+
+/// Token cancelling the searches it is passed to, from any thread.
+///
+/// Cancellation is cooperative: the searches check the token between the blocks of documents they
+/// collect, between the segments, and before merging the fruits of the segments, and fail with
+/// [`TantivyError::Cancelled`] once the token is cancelled. See
+/// [`Searcher::search_cancellable()`](crate::Searcher::search_cancellable).
+///
+/// Cloning the token is cheap, and all of the clones share the same cancellation state.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the searches using this token, or any of its clones.
+    ///
+    /// Cancelling a token is final, and the searches started later with this token fail right
+    /// away.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns [`TantivyError::Cancelled`] if the token was cancelled.
+    pub fn check(&self) -> crate::Result<()> {
+        if self.is_cancelled() {
+            return Err(TantivyError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Wraps the weight of a search, to stop its collection loops once the token is cancelled.
+///
+/// The token is checked every [`COLLECT_BLOCK_BUFFER_LEN`] documents. The scored and unscored
+/// loops drive the scorer of the weight themselves, while the pruning loop of the weight is kept,
+/// so that the pruning optimizations of the scorers (e.g. BlockWAND) still apply.
+pub(crate) struct CancellableWeight {
+    weight: Box<dyn Weight>,
+    token: CancellationToken,
+}
+
+impl CancellableWeight {
+    pub(crate) fn new(weight: Box<dyn Weight>, token: CancellationToken) -> CancellableWeight {
+        CancellableWeight { weight, token }
+    }
+}
+
+impl Weight for CancellableWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        self.weight.scorer(reader, boost)
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        self.weight.explain(reader, doc)
+    }
+
+    fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
+        self.token.check()?;
+        self.weight.count(reader)
+    }
+
+    fn for_each(
+        &self,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(DocId, Score),
+    ) -> crate::Result<()> {
+        let mut scorer = self.weight.scorer(reader, 1.0)?;
+        let mut num_docs = 0;
+        let mut doc = scorer.doc();
+        while doc != TERMINATED {
+            if num_docs % COLLECT_BLOCK_BUFFER_LEN == 0 {
+                self.token.check()?;
+            }
+            num_docs += 1;
+            callback(doc, scorer.score());
+            doc = scorer.advance();
+        }
+        Ok(())
+    }
+
+    fn for_each_no_score(
+        &self,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(&[DocId]),
+    ) -> crate::Result<()> {
+        let mut docset = self.weight.scorer(reader, 1.0)?;
+        let mut buffer = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+        loop {
+            self.token.check()?;
+            let num_items = docset.fill_buffer(&mut buffer);
+            callback(&buffer[..num_items]);
+            if num_items != buffer.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn for_each_pruning(
+        &self,
+        threshold: Score,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(DocId, Score) -> Score,
+    ) -> crate::Result<()> {
+        self.token.check()?;
+        let mut num_docs = 0;
+        let mut is_cancelled = false;
+        self.weight
+            .for_each_pruning(threshold, reader, &mut |doc, score| {
+                if !is_cancelled && num_docs % COLLECT_BLOCK_BUFFER_LEN == 0 {
+                    is_cancelled = self.token.is_cancelled();
+                }
+                num_docs += 1;
+                if is_cancelled {
+                    // The highest threshold lets the scorer skip all of the remaining documents.
+                    return Score::MAX;
+                }
+                callback(doc, score)
+            })?;
+        self.token.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::CancellationToken;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, TopDocs};
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST};
+    use crate::{Index, IndexWriter, TantivyError};
+
+    const NUM_SEGMENTS: u64 = 4;
+    const NUM_DOCS_PER_SEGMENT: u64 = 100_000;
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let value = schema_builder.add_u64_field("value", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 50_000_000)?;
+        for segment in 0..NUM_SEGMENTS {
+            for doc in 0..NUM_DOCS_PER_SEGMENT {
+                index_writer.add_document(doc!(value => segment * NUM_DOCS_PER_SEGMENT + doc))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn aggregation_collector(token: &CancellationToken) -> AggregationCollector {
+        let aggs: Aggregations = serde_json::from_value(serde_json::json!({
+            "values": {
+                "terms": { "field": "value", "size": 10 },
+                "aggs": { "avg_value": { "avg": { "field": "value" } } }
+            }
+        }))
+        .unwrap();
+        AggregationCollector::from_aggs(aggs, Default::default()).with_cancellation(token.clone())
+    }
+
+    #[test]
+    fn test_search_cancellable() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+
+        let token = CancellationToken::new();
+        let start = Instant::now();
+        searcher.search_cancellable(&AllQuery, &aggregation_collector(&token), &token)?;
+        let uncancelled_elapsed = start.elapsed();
+
+        let token = CancellationToken::new();
+        let cancel_thread = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(uncancelled_elapsed / 20);
+                token.cancel();
+            })
+        };
+        let start = Instant::now();
+        let err = searcher
+            .search_cancellable(&AllQuery, &aggregation_collector(&token), &token)
+            .unwrap_err();
+        let cancelled_elapsed = start.elapsed();
+        cancel_thread.join().unwrap();
+        assert!(matches!(err, TantivyError::Cancelled));
+        assert_eq!(err.error_code(), "cancelled");
+        assert!(
+            cancelled_elapsed < uncancelled_elapsed / 2,
+            "{cancelled_elapsed:?} vs {uncancelled_elapsed:?}"
+        );
+
+        // A cancelled token fails the searches right away.
+        assert!(matches!(
+            searcher.search_cancellable(&AllQuery, &Count, &token),
+            Err(TantivyError::Cancelled)
+        ));
+        assert!(matches!(
+            searcher.search_cancellable(&AllQuery, &TopDocs::with_limit(3), &token),
+            Err(TantivyError::Cancelled)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_cancellable_not_cancelled() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let token = CancellationToken::new();
+        let count = searcher.search_cancellable(&AllQuery, &Count, &token)?;
+        assert_eq!(count as u64, NUM_SEGMENTS * NUM_DOCS_PER_SEGMENT);
+        let top_docs = searcher.search_cancellable(&AllQuery, &TopDocs::with_limit(3), &token)?;
+        assert_eq!(top_docs.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregation_merge_cancelled() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let token = CancellationToken::new();
+        let collector = aggregation_collector(&token);
+        token.cancel();
+        // The plain search collects the segments, but the merge of their fruits is cancelled.
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(TantivyError::Cancelled)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancellation_token_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert!(token.check().is_ok());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(matches!(token.check(), Err(TantivyError::Cancelled)));
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::query::{Explanation, Scorer, Weight};
+use crate::{DocId, Score, SegmentReader, COLLECT_BLOCK_BUFFER_LEN};
+
+/// Number of blocks of documents collected between two progress reports within a segment.
+const PROGRESS_REPORT_NUM_BLOCKS: u64 = 64;
+
+/// Progress of a search, reported by
+/// [`Searcher::search_with_progress()`](crate::Searcher::search_with_progress).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchProgress {
+    /// Number of segments searched.
+    pub segments_total: usize,
+    /// Number of segments whose collection is over.
+    pub segments_completed: usize,
+    /// Number of alive documents pushed to the collectors so far.
+    ///
+    /// Once the search is complete, this is the number of documents matching the query, unless
+    /// the collection skips the documents that cannot make it to the results, as
+    /// [`TopDocs`](crate::collector::TopDocs) does.
+    pub docs_collected: u64,
+}
+
+impl SearchProgress {
+    /// Returns true if all of the segments were collected. The fruits of the segments may not be
+    /// merged yet.
+    pub fn is_complete(&self) -> bool {
+        self.segments_completed == self.segments_total
+    }
+}
+
+/// Callback receiving the progress of a search.
+pub type SearchProgressCallback = Box<dyn Fn(SearchProgress) + Send + Sync>;
+
+/// Counters shared by the segments of a search.
+///
+/// The counters are updated with relaxed atomics. Reports are serialized by a mutex that the
+/// collecting threads only try to lock, so that they never wait for each other.
+pub(crate) struct ProgressTracker {
+    segments_total: usize,
+    segments_completed: AtomicUsize,
+    docs_collected: AtomicU64,
+    /// Last reported progress, so that the reports never go backward nor repeat.
+    last_report: Mutex<Option<SearchProgress>>,
+    callback: SearchProgressCallback,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(segments_total: usize, callback: SearchProgressCallback) -> ProgressTracker {
+        ProgressTracker {
+            segments_total,
+            segments_completed: AtomicUsize::new(0),
+            docs_collected: AtomicU64::new(0),
+            last_report: Mutex::new(None),
+            callback,
+        }
+    }
+
+    fn add_docs_collected(&self, num_docs: u64) {
+        if num_docs > 0 {
+            self.docs_collected.fetch_add(num_docs, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn complete_segment(&self) {
+        self.segments_completed.fetch_add(1, Ordering::Relaxed);
+        self.try_report();
+    }
+
+    /// Reports the progress, unless another thread is reporting it.
+    fn try_report(&self) {
+        if let Ok(mut last_report) = self.last_report.try_lock() {
+            self.report_locked(&mut last_report);
+        }
+    }
+
+    /// Reports the progress, waiting for the other threads to be done reporting. This is called
+    /// once the collection is over, so that the last report is always complete.
+    pub(crate) fn report_final(&self) {
+        let mut last_report = self.last_report.lock().unwrap();
+        self.report_locked(&mut last_report);
+    }
+
+    fn report_locked(&self, last_report: &mut Option<SearchProgress>) {
+        let progress = SearchProgress {
+            segments_total: self.segments_total,
+            segments_completed: self.segments_completed.load(Ordering::Relaxed),
+            docs_collected: self.docs_collected.load(Ordering::Relaxed),
+        };
+        if *last_report == Some(progress) {
+            return;
+        }
+        *last_report = Some(progress);
+        (self.callback)(progress);
+    }
+}
+
+/// Counts the documents of a segment, and reports the progress every
+/// [`PROGRESS_REPORT_NUM_BLOCKS`] blocks.
+struct SegmentProgress<'a> {
+    tracker: &'a ProgressTracker,
+    num_docs: u64,
+    num_blocks: u64,
+}
+
+impl SegmentProgress<'_> {
+    fn record_block(&mut self, num_docs: usize) {
+        self.num_docs += num_docs as u64;
+        self.num_blocks += 1;
+        if self.num_blocks % PROGRESS_REPORT_NUM_BLOCKS == 0 {
+            self.flush();
+            self.tracker.try_report();
+        }
+    }
+
+    fn record_doc(&mut self) {
+        self.num_docs += 1;
+        if self.num_docs == PROGRESS_REPORT_NUM_BLOCKS * COLLECT_BLOCK_BUFFER_LEN as u64 {
+            self.flush();
+            self.tracker.try_report();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.tracker.add_docs_collected(self.num_docs);
+        self.num_docs = 0;
+    }
+}
+
+impl Drop for SegmentProgress<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Wraps the weight of a search, to count the documents its collection loops push to the
+/// collectors.
+///
+/// Since the loops of the weight are kept, the collectors keep their optimized collection, with
+/// the exception of the collectors enumerating all of the documents themselves, see
+/// [`Weight::matches_all_docs()`].
+pub(crate) struct ProgressWeight {
+    weight: Box<dyn Weight>,
+    tracker: Arc<ProgressTracker>,
+}
+
+impl ProgressWeight {
+    pub(crate) fn new(weight: Box<dyn Weight>, tracker: Arc<ProgressTracker>) -> ProgressWeight {
+        ProgressWeight { weight, tracker }
+    }
+
+    fn segment_progress(&self) -> SegmentProgress<'_> {
+        SegmentProgress {
+            tracker: &self.tracker,
+            num_docs: 0,
+            num_blocks: 0,
+        }
+    }
+}
+
+impl Weight for ProgressWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        self.weight.scorer(reader, boost)
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        self.weight.explain(reader, doc)
+    }
+
+    fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
+        let count = self.weight.count(reader)?;
+        self.tracker.add_docs_collected(count as u64);
+        Ok(count)
+    }
+
+    fn for_each(
+        &self,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(DocId, Score),
+    ) -> crate::Result<()> {
+        let alive_bitset = reader.alive_bitset();
+        let mut segment_progress = self.segment_progress();
+        self.weight.for_each(reader, &mut |doc, score| {
+            if alive_bitset.is_none_or(|alive_bitset| alive_bitset.is_alive(doc)) {
+                segment_progress.record_doc();
+            }
+            callback(doc, score);
+        })
+    }
+
+    fn for_each_no_score(
+        &self,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(&[DocId]),
+    ) -> crate::Result<()> {
+        let alive_bitset = reader.alive_bitset();
+        let mut segment_progress = self.segment_progress();
+        self.weight.for_each_no_score(reader, &mut |docs| {
+            let num_alive_docs = match alive_bitset {
+                Some(alive_bitset) => docs
+                    .iter()
+                    .filter(|&&doc| alive_bitset.is_alive(doc))
+                    .count(),
+                None => docs.len(),
+            };
+            segment_progress.record_block(num_alive_docs);
+            callback(docs);
+        })
+    }
+
+    fn for_each_pruning(
+        &self,
+        threshold: Score,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(DocId, Score) -> Score,
+    ) -> crate::Result<()> {
+        let alive_bitset = reader.alive_bitset();
+        let mut segment_progress = self.segment_progress();
+        self.weight
+            .for_each_pruning(threshold, reader, &mut |doc, score| {
+                if alive_bitset.is_none_or(|alive_bitset| alive_bitset.is_alive(doc)) {
+                    segment_progress.record_doc();
+                }
+                callback(doc, score)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::SearchProgress;
+    use crate::collector::{Count, TopDocs};
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, STRING};
+    use crate::{Index, IndexWriter, Term, COLLECT_BLOCK_BUFFER_LEN};
+
+    const NUM_SEGMENTS: usize = 4;
+    const NUM_DOCS_PER_SEGMENT: usize = 200 * COLLECT_BLOCK_BUFFER_LEN;
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", STRING);
+        let mut index = Index::create_in_ram(schema_builder.build());
+        index.set_multithread_executor(2)?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for _ in 0..NUM_SEGMENTS {
+            for doc in 0..NUM_DOCS_PER_SEGMENT {
+                let value = if doc % 2 == 0 { "even" } else { "odd" };
+                index_writer.add_document(doc!(text => value))?;
+            }
+            index_writer.commit()?;
+        }
+        index_writer.delete_term(Term::from_field_text(text, "odd"))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn check_progress(progress: &[SearchProgress]) {
+        assert!(!progress.is_empty());
+        for window in progress.windows(2) {
+            assert!(window[0].segments_completed <= window[1].segments_completed);
+            assert!(window[0].docs_collected <= window[1].docs_collected);
+            assert_ne!(window[0], window[1]);
+        }
+        assert!(progress
+            .iter()
+            .all(|progress| progress.segments_total == NUM_SEGMENTS));
+        let last = progress.last().unwrap();
+        assert!(last.is_complete());
+        assert_eq!(
+            progress
+                .iter()
+                .filter(|progress| progress.is_complete())
+                .count(),
+            1
+        );
+    }
+
+    fn search_with_progress<C: crate::collector::Collector>(
+        index: &Index,
+        query: &dyn crate::query::Query,
+        collector: &C,
+    ) -> crate::Result<(C::Fruit, Vec<SearchProgress>)> {
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), NUM_SEGMENTS);
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_clone = progress.clone();
+        let fruit = searcher.search_with_progress(
+            query,
+            collector,
+            Box::new(move |progress| progress_clone.lock().unwrap().push(progress)),
+        )?;
+        let progress = progress.lock().unwrap().clone();
+        Ok((fruit, progress))
+    }
+
+    #[test]
+    fn test_search_with_progress() -> crate::Result<()> {
+        let index = create_index()?;
+        let num_alive_docs = (NUM_SEGMENTS * NUM_DOCS_PER_SEGMENT / 2) as u64;
+
+        let (count, progress) = search_with_progress(&index, &AllQuery, &Count)?;
+        assert_eq!(count as u64, num_alive_docs);
+        check_progress(&progress);
+        assert_eq!(progress.last().unwrap().docs_collected, num_alive_docs);
+        // The progress is also reported within the segments.
+        assert!(progress[0].docs_collected > 0);
+        assert!(progress[0].segments_completed < NUM_SEGMENTS);
+
+        // Pruned documents are not collected.
+        let (top_docs, progress) =
+            search_with_progress(&index, &AllQuery, &TopDocs::with_limit(3))?;
+        assert_eq!(top_docs.len(), 3);
+        check_progress(&progress);
+        let docs_collected = progress.last().unwrap().docs_collected;
+        assert!(docs_collected >= 3 && docs_collected < num_alive_docs);
+
+        let text = index.schema().get_field("text")?;
+        let even_query = TermQuery::new(
+            Term::from_field_text(text, "even"),
+            IndexRecordOption::Basic,
+        );
+        let (count, progress) = search_with_progress(&index, &even_query, &Count)?;
+        assert_eq!(count as u64, num_alive_docs);
+        check_progress(&progress);
+        assert_eq!(progress.last().unwrap().docs_collected, num_alive_docs);
+        Ok(())
+    }
+}
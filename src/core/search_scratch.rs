@@ -0,0 +1,312 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Default cap of the bytes pooled per thread, see [`SearchScratch::set_max_pooled_bytes`].
+const DEFAULT_MAX_POOLED_BYTES: usize = 4 << 20;
+
+/// Maximum number of buffers pooled per thread for a given element type and capacity class.
+const MAX_POOLED_BUFFERS_PER_CLASS: usize = 64;
+
+static MAX_POOLED_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_POOLED_BYTES);
+
+thread_local! {
+    static SCRATCH_POOL: RefCell<ScratchPool> = RefCell::new(ScratchPool::default());
+}
+
+/// Pool of the temporary buffers allocated by searches.
+///
+/// Collectors allocate the same buffers for every segment of every search: the heaps of the top
+/// docs collectors, the staging buffers of the aggregations, the column values of a block of
+/// docs... `SearchScratch` keeps those buffers around once a search is done, so that the next
+/// search on the same thread reuses them instead of going through the allocator.
+///
+/// The pool is per thread, i.e. per thread of the [`Executor`](crate::Executor) running the
+/// search. A buffer requested while the pool is empty is simply allocated, and a buffer released
+/// while the pool is full is simply freed: the pool never holds more than
+/// [`SearchScratch::max_pooled_bytes`] per thread.
+///
+/// The buffers are allocated with a power of two capacity, and pooled by capacity class. A buffer
+/// taken from the pool always has room for the requested elements and is never resized, so that
+/// the same searches end up reusing the same buffers.
+///
+/// ```rust
+/// use tantivy::SearchScratch;
+///
+/// let mut doc_ids = SearchScratch::get::<u32>(128);
+/// doc_ids.extend(0..128);
+/// assert_eq!(doc_ids.len(), 128);
+/// // Dropping the buffer returns it to the pool, cleared.
+/// drop(doc_ids);
+/// let doc_ids = SearchScratch::get::<u32>(128);
+/// assert!(doc_ids.is_empty());
+/// assert!(doc_ids.capacity() >= 128);
+/// ```
+pub struct SearchScratch;
+
+impl SearchScratch {
+    /// Returns an empty buffer with room for at least `capacity` elements, taken from the pool of
+    /// the current thread if possible.
+    ///
+    /// The buffer is returned to the pool when dropped.
+    pub fn get<T: 'static>(capacity: usize) -> ScratchVec<T> {
+        ScratchVec {
+            buffer: SearchScratch::take_vec(capacity),
+        }
+    }
+
+    /// Returns an empty `Vec` with room for at least `capacity` elements, taken from the pool of
+    /// the current thread if possible.
+    ///
+    /// Unlike [`SearchScratch::get`], the `Vec` is not returned to the pool when dropped. It can
+    /// be returned with [`SearchScratch::recycle`].
+    pub fn take_vec<T: 'static>(capacity: usize) -> Vec<T> {
+        let Some(class_capacity) = capacity.checked_next_power_of_two() else {
+            return Vec::with_capacity(capacity);
+        };
+        let pooled_buffer = SCRATCH_POOL
+            .try_with(|pool| {
+                pool.try_borrow_mut()
+                    .ok()?
+                    .pop::<T>(capacity_class(class_capacity))
+            })
+            .ok()
+            .flatten();
+        match pooled_buffer {
+            Some(buffer) => buffer,
+            None if capacity == 0 => Vec::new(),
+            // Allocated with the capacity of its class, so that it is pooled in that class.
+            None => Vec::with_capacity(class_capacity),
+        }
+    }
+
+    /// Returns a buffer to the pool of the current thread. Its elements are dropped.
+    ///
+    /// The buffer is freed if the pool is full.
+    pub fn recycle<T: 'static>(mut buffer: Vec<T>) {
+        if buffer.capacity() == 0 || std::mem::size_of::<T>() == 0 {
+            return;
+        }
+        // Dropping the elements may recycle other buffers.
+        buffer.clear();
+        let _ = SCRATCH_POOL.try_with(|pool| {
+            if let Ok(mut pool) = pool.try_borrow_mut() {
+                pool.push(buffer);
+            }
+        });
+    }
+
+    /// Returns the maximum number of bytes pooled per thread.
+    pub fn max_pooled_bytes() -> usize {
+        MAX_POOLED_BYTES.load(Ordering::Relaxed)
+    }
+
+    /// Sets the maximum number of bytes pooled per thread. It defaults to 4MB.
+    ///
+    /// Lowering the cap does not free the buffers already pooled, but no buffer is pooled until
+    /// the pool fits the new cap. Setting it to 0 disables the pool.
+    pub fn set_max_pooled_bytes(max_pooled_bytes: usize) {
+        MAX_POOLED_BYTES.store(max_pooled_bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the number of bytes pooled by the current thread.
+    pub fn pooled_bytes() -> usize {
+        SCRATCH_POOL
+            .try_with(|pool| pool.borrow().num_pooled_bytes)
+            .unwrap_or(0)
+    }
+
+    /// Frees the buffers pooled by the current thread.
+    pub fn clear() {
+        let free_buffers = SCRATCH_POOL
+            .try_with(|pool| {
+                let mut pool = pool.borrow_mut();
+                pool.num_pooled_bytes = 0;
+                std::mem::take(&mut pool.free_buffers)
+            })
+            .unwrap_or_default();
+        drop(free_buffers);
+    }
+}
+
+#[derive(Default)]
+struct ScratchPool {
+    /// Free buffers, by element type. The value of the type `T` is a `FreeBuffers<T>`.
+    free_buffers: HashMap<TypeId, Box<dyn Any>>,
+    num_pooled_bytes: usize,
+}
+
+/// Free buffers of an element type, by capacity class.
+///
+/// The buffers of the class `i` have a capacity in `[2^i, 2^(i+1))`.
+struct FreeBuffers<T> {
+    classes: Vec<Vec<Vec<T>>>,
+}
+
+impl<T> Default for FreeBuffers<T> {
+    fn default() -> Self {
+        FreeBuffers {
+            classes: (0..usize::BITS).map(|_| Vec::new()).collect(),
+        }
+    }
+}
+
+fn num_bytes<T>(buffer: &Vec<T>) -> usize {
+    buffer.capacity() * std::mem::size_of::<T>()
+}
+
+/// Returns the capacity class of a non-zero capacity, i.e. its base 2 logarithm rounded down.
+fn capacity_class(capacity: usize) -> usize {
+    capacity.ilog2() as usize
+}
+
+impl ScratchPool {
+    /// Pops a buffer of the smallest non-empty capacity class starting from `class`.
+    fn pop<T: 'static>(&mut self, class: usize) -> Option<Vec<T>> {
+        let free_buffers = self
+            .free_buffers
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut::<FreeBuffers<T>>()?;
+        let buffer = free_buffers.classes[class..]
+            .iter_mut()
+            .find_map(|class_buffers| class_buffers.pop())?;
+        self.num_pooled_bytes -= num_bytes(&buffer);
+        Some(buffer)
+    }
+
+    fn push<T: 'static>(&mut self, buffer: Vec<T>) {
+        let num_bytes = num_bytes(&buffer);
+        if self.num_pooled_bytes + num_bytes > SearchScratch::max_pooled_bytes() {
+            return;
+        }
+        let class_buffers = &mut self
+            .free_buffers
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(FreeBuffers::<T>::default()))
+            .downcast_mut::<FreeBuffers<T>>()
+            .expect("The free buffers are keyed by their element type.")
+            .classes[capacity_class(buffer.capacity())];
+        if class_buffers.capacity() == 0 {
+            // Reserved upfront, so that pooling a buffer does not allocate afterwards.
+            class_buffers.reserve_exact(MAX_POOLED_BUFFERS_PER_CLASS);
+        }
+        if class_buffers.len() < MAX_POOLED_BUFFERS_PER_CLASS {
+            class_buffers.push(buffer);
+            self.num_pooled_bytes += num_bytes;
+        }
+    }
+}
+
+/// Buffer taken from the [`SearchScratch`] pool, returned to the pool when dropped.
+///
+/// It dereferences to a `Vec`.
+pub struct ScratchVec<T: 'static> {
+    buffer: Vec<T>,
+}
+
+impl<T: 'static> ScratchVec<T> {
+    /// Detaches the buffer from the pool.
+    pub fn into_vec(mut self) -> Vec<T> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+impl<T: 'static> Deref for ScratchVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.buffer
+    }
+}
+
+impl<T: 'static> DerefMut for ScratchVec<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.buffer
+    }
+}
+
+impl<T: 'static> Drop for ScratchVec<T> {
+    fn drop(&mut self) {
+        SearchScratch::recycle(std::mem::take(&mut self.buffer));
+    }
+}
+
+impl<T: Clone + 'static> Clone for ScratchVec<T> {
+    fn clone(&self) -> Self {
+        let mut scratch_vec = SearchScratch::get(self.buffer.len());
+        scratch_vec.extend_from_slice(&self.buffer);
+        scratch_vec
+    }
+}
+
+impl<T: fmt::Debug + 'static> fmt::Debug for ScratchVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.buffer.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchScratch, MAX_POOLED_BUFFERS_PER_CLASS};
+
+    #[test]
+    fn test_search_scratch_reuses_buffers() {
+        SearchScratch::clear();
+        let mut buffer = SearchScratch::get::<u64>(100);
+        buffer.extend(0..100);
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+        // The capacity is rounded up to a power of two.
+        assert_eq!(SearchScratch::pooled_bytes(), 1024);
+        let buffer = SearchScratch::get::<u64>(10);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(SearchScratch::pooled_bytes(), 0);
+        // Buffers of another element type are pooled separately.
+        let other_buffer = SearchScratch::get::<u32>(10);
+        assert_ne!(other_buffer.as_ptr() as *const u64, ptr);
+        // A detached buffer is not returned to the pool.
+        drop(buffer.into_vec());
+        assert_eq!(SearchScratch::pooled_bytes(), 0);
+        drop(other_buffer);
+        assert_eq!(SearchScratch::pooled_bytes(), 64);
+        SearchScratch::clear();
+        assert_eq!(SearchScratch::pooled_bytes(), 0);
+    }
+
+    #[test]
+    fn test_search_scratch_does_not_grow_pooled_buffers() {
+        SearchScratch::clear();
+        let small_buffer = SearchScratch::get::<u64>(100);
+        let small_ptr = small_buffer.as_ptr();
+        drop(small_buffer);
+        // The pooled buffer is too small, it stays in the pool as is.
+        let large_buffer = SearchScratch::get::<u64>(200);
+        assert_eq!(large_buffer.capacity(), 256);
+        assert_eq!(SearchScratch::pooled_bytes(), 1024);
+        drop(large_buffer);
+        assert_eq!(SearchScratch::pooled_bytes(), 3072);
+        assert_eq!(SearchScratch::get::<u64>(100).as_ptr(), small_ptr);
+        SearchScratch::clear();
+    }
+
+    #[test]
+    fn test_search_scratch_is_bounded() {
+        SearchScratch::clear();
+        let buffers: Vec<_> = (0..MAX_POOLED_BUFFERS_PER_CLASS + 1)
+            .map(|_| SearchScratch::get::<u8>(1))
+            .collect();
+        drop(buffers);
+        assert_eq!(SearchScratch::pooled_bytes(), MAX_POOLED_BUFFERS_PER_CLASS);
+        SearchScratch::clear();
+
+        // A buffer larger than the cap is freed.
+        let max_pooled_bytes = SearchScratch::max_pooled_bytes();
+        let large_buffer = SearchScratch::get::<u8>(max_pooled_bytes + 1);
+        drop(large_buffer);
+        assert_eq!(SearchScratch::pooled_bytes(), 0);
+    }
+}
@@ -1,16 +1,48 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, io};
 
-use crate::collector::Collector;
-use crate::core::Executor;
-use crate::index::{SegmentId, SegmentReader};
-use crate::query::{Bm25StatisticsProvider, EnableScoring, Query};
+use columnar::{Column, DynamicColumn, HasAssociatedColumnType};
+use common::BitSet;
+use once_cell::sync::OnceCell;
+
+use crate::collector::{
+    Collector, CustomScorer, SearchProfile, SegmentCollector, SegmentProfile, TopDocs,
+};
+use crate::core::column_iter::{self, ColumnBlocks};
+use crate::core::search_progress::{ProgressTracker, ProgressWeight};
+use crate::core::{
+    CancellableWeight, CancellationToken, Executor, SearchProgressCallback, SearcherStatistics,
+    SegmentCandidates,
+};
+use crate::fastfield::{AliveBitSet, ColumnStats};
+use crate::index::{ComponentSet, Segment, SegmentId, SegmentReader};
+use crate::query::synonym_query::AnalyzedSynonyms;
+use crate::query::{Bm25StatisticsProvider, EnableScoring, Query, QueryCostEstimate, SynonymSet};
+use crate::reader::{SearchBudgetPool, SearchPermit};
 use crate::schema::document::DocumentDeserialize;
-use crate::schema::{Schema, Term};
+use crate::schema::{Field, FieldEntry, IndexRecordOption, Schema, Term, Type};
 use crate::space_usage::SearcherSpaceUsage;
 use crate::store::{CacheStats, StoreReader};
-use crate::{DocAddress, Index, Opstamp, TrackedObject};
+use crate::termdict::MergedTermDictionary;
+use crate::{
+    DocAddress, DocId, DocSet, Index, Inventory, Opstamp, SegmentOrdinal, TantivyError,
+    TrackedObject, TERMINATED,
+};
+
+/// Filters the documents seen by a [`Searcher`], without modifying the index.
+///
+/// See [`Searcher::with_doc_filter`].
+pub trait SegmentDocFilter: Send + Sync {
+    /// Returns the set of documents of the segment that should be searched, or `None` if all of
+    /// them should be searched.
+    ///
+    /// The bitset needs to have a max value equal to the segment's
+    /// [`max_doc`](SegmentReader::max_doc). Documents deleted from the index stay deleted,
+    /// whether they are in the bitset or not.
+    fn alive(&self, segment_id: SegmentId) -> Option<Arc<BitSet>>;
+}
 
 /// Identifies the searcher generation accessed by a [`Searcher`].
 ///
@@ -68,9 +100,67 @@ impl SearcherGeneration {
 #[derive(Clone)]
 pub struct Searcher {
     inner: Arc<SearcherInner>,
+    /// Budget of the reader the searcher was obtained from, if any.
+    search_budget: Option<Arc<SearchBudgetPool>>,
+    /// Synonyms of the reader the searcher was obtained from, if any.
+    query_synonyms: Option<Arc<AnalyzedSynonyms>>,
 }
 
 impl Searcher {
+    /// Creates a searcher over a single segment that is not committed yet, e.g. to lower a
+    /// query to a weight reflecting the state of the segment the deletes are applied to.
+    pub(crate) fn for_segment(
+        segment: &Segment,
+        segment_reader: SegmentReader,
+    ) -> crate::Result<Searcher> {
+        let generation = Inventory::default().track(SearcherGeneration::from_segment_readers(
+            std::slice::from_ref(&segment_reader),
+            0,
+        ));
+        let index = segment.index();
+        let inner = SearcherInner::new(
+            index.schema(),
+            index.clone(),
+            vec![segment_reader],
+            generation,
+            segment.meta().delete_opstamp().unwrap_or_default(),
+            0,
+        )?;
+        Ok(Arc::new(inner).into())
+    }
+
+    /// Makes the searches wait to be admitted by `search_budget`.
+    pub(crate) fn with_search_budget(
+        mut self,
+        search_budget: Option<Arc<SearchBudgetPool>>,
+    ) -> Searcher {
+        self.search_budget = search_budget;
+        self
+    }
+
+    /// Makes the [`SynonymQueryWrapper`](crate::query::SynonymQueryWrapper)s expand their query
+    /// with `query_synonyms`.
+    pub(crate) fn with_query_synonyms(
+        mut self,
+        query_synonyms: Option<Arc<AnalyzedSynonyms>>,
+    ) -> Searcher {
+        self.query_synonyms = query_synonyms;
+        self
+    }
+
+    /// Returns the synonyms the [`SynonymQueryWrapper`](crate::query::SynonymQueryWrapper)s
+    /// expand their query with, set with
+    /// [`IndexReader::set_query_synonyms`](crate::IndexReader::set_query_synonyms).
+    pub fn query_synonyms(&self) -> Option<&Arc<SynonymSet>> {
+        self.query_synonyms
+            .as_ref()
+            .map(|query_synonyms| query_synonyms.synonym_set())
+    }
+
+    pub(crate) fn analyzed_synonyms(&self) -> Option<&AnalyzedSynonyms> {
+        self.query_synonyms.as_deref()
+    }
+
     /// Returns the `Index` associated with the `Searcher`
     pub fn index(&self) -> &Index {
         &self.inner.index
@@ -85,11 +175,61 @@ impl Searcher {
     ///
     /// The searcher uses the segment ordinal to route the
     /// request to the right `Segment`.
+    ///
+    /// If the searcher is restricted by an [`AccessFilter`](crate::AccessFilter), fetching a
+    /// document the principal is not allowed to see returns a [`TantivyError::AccessDenied`]
+    /// error.
     pub fn doc<D: DocumentDeserialize>(&self, doc_address: DocAddress) -> crate::Result<D> {
-        let store_reader = &self.inner.store_readers[doc_address.segment_ord as usize];
+        self.inner.check_access(doc_address)?;
+        let store_reader = self.inner.store_reader(doc_address.segment_ord as usize)?;
         store_reader.get(doc_address.doc_id)
     }
 
+    /// Fetches the values of `fields` of a document from tantivy's store.
+    ///
+    /// The returned document only contains the values of `fields`. Fetching a few small fields
+    /// of documents with large stored fields is cheaper than [`Searcher::doc()`], as the values
+    /// of the other fields are skipped without being deserialized. The segments written by
+    /// older versions of tantivy are supported, but their values still have to be parsed to be
+    /// skipped.
+    pub fn doc_fields<D: DocumentDeserialize>(
+        &self,
+        doc_address: DocAddress,
+        fields: &[Field],
+    ) -> crate::Result<D> {
+        self.inner.check_access(doc_address)?;
+        let store_reader = self.inner.store_reader(doc_address.segment_ord as usize)?;
+        store_reader.get_fields(doc_address.doc_id, fields)
+    }
+
+    /// Fetches several documents from tantivy's store, in the order of `doc_addresses`.
+    ///
+    /// See [`Searcher::doc()`].
+    pub fn docs_batch<D: DocumentDeserialize>(
+        &self,
+        doc_addresses: &[DocAddress],
+    ) -> crate::Result<Vec<D>> {
+        doc_addresses
+            .iter()
+            .map(|&doc_address| self.doc(doc_address))
+            .collect()
+    }
+
+    /// Fetches the values of `fields` of several documents from tantivy's store, in the order
+    /// of `doc_addresses`.
+    ///
+    /// See [`Searcher::doc_fields()`].
+    pub fn docs_batch_fields<D: DocumentDeserialize>(
+        &self,
+        doc_addresses: &[DocAddress],
+        fields: &[Field],
+    ) -> crate::Result<Vec<D>> {
+        doc_addresses
+            .iter()
+            .map(|&doc_address| self.doc_fields(doc_address, fields))
+            .collect()
+    }
+
     /// The cache stats for the underlying store reader.
     ///
     /// Aggregates the sum for each segment store reader.
@@ -98,6 +238,7 @@ impl Searcher {
             .inner
             .store_readers
             .iter()
+            .filter_map(OnceCell::get)
             .map(|reader| reader.cache_stats())
             .sum();
         cache_stats
@@ -109,11 +250,255 @@ impl Searcher {
         &self,
         doc_address: DocAddress,
     ) -> crate::Result<D> {
+        self.inner.check_access(doc_address)?;
         let executor = self.inner.index.search_executor();
-        let store_reader = &self.inner.store_readers[doc_address.segment_ord as usize];
+        let store_reader = self.inner.store_reader(doc_address.segment_ord as usize)?;
         store_reader.get_async(doc_address.doc_id, executor).await
     }
 
+    /// Returns the address of the alive document whose `id_field` has the value `id_value`.
+    ///
+    /// A [`DocAddress`] is only valid for the searcher it comes from: merges renumber the
+    /// documents. Documents that need to be identified across searchers should instead carry an
+    /// id in a `u64` field, and be looked up with this method.
+    ///
+    /// The field has to be indexed or fast. Indexed fields are looked up in the term dictionary,
+    /// while fast fields have their column scanned.
+    ///
+    /// The ids are expected to be unique among the alive documents, e.g. by deleting the id
+    /// before adding a document again. If several alive documents have the same id, the one with
+    /// the lowest [`DocAddress`] is returned, see [`Searcher::resolve_all_docs()`] to get all of
+    /// them. Deleted documents are never returned.
+    pub fn resolve_doc(&self, id_field: Field, id_value: u64) -> crate::Result<Option<DocAddress>> {
+        Ok(self.resolve_docs(id_field, &[id_value])?[0])
+    }
+
+    /// Returns the addresses of all of the alive documents whose `id_field` has the value
+    /// `id_value`, in increasing order.
+    ///
+    /// See [`Searcher::resolve_doc()`].
+    pub fn resolve_all_docs(
+        &self,
+        id_field: Field,
+        id_value: u64,
+    ) -> crate::Result<Vec<DocAddress>> {
+        let id_lookup = IdLookup::new(&self.inner.schema, id_field)?;
+        let mut doc_addresses = Vec::new();
+        for (segment_ord, segment_reader) in self.inner.segment_readers.iter().enumerate() {
+            id_lookup.lookup_segment(segment_reader, &[id_value], &mut |_, doc_id| {
+                doc_addresses.push(DocAddress::new(segment_ord as SegmentOrdinal, doc_id));
+            })?;
+        }
+        doc_addresses.sort_unstable();
+        doc_addresses.dedup();
+        Ok(doc_addresses)
+    }
+
+    /// Bulk version of [`Searcher::resolve_doc()`], returning the address of the document of each
+    /// of the `id_values`, in the same order.
+    ///
+    /// The ids are sorted, and each segment is looked up once for all of them: its term
+    /// dictionary is searched with the sorted ids, or its column is scanned once.
+    pub fn resolve_docs(
+        &self,
+        id_field: Field,
+        id_values: &[u64],
+    ) -> crate::Result<Vec<Option<DocAddress>>> {
+        let id_lookup = IdLookup::new(&self.inner.schema, id_field)?;
+        let mut sorted_ids = id_values.to_vec();
+        sorted_ids.sort_unstable();
+        sorted_ids.dedup();
+        let mut sorted_doc_addresses: Vec<Option<DocAddress>> = vec![None; sorted_ids.len()];
+        for (segment_ord, segment_reader) in self.inner.segment_readers.iter().enumerate() {
+            id_lookup.lookup_segment(segment_reader, &sorted_ids, &mut |id_ord, doc_id| {
+                let doc_address = DocAddress::new(segment_ord as SegmentOrdinal, doc_id);
+                let resolved = &mut sorted_doc_addresses[id_ord];
+                if resolved.is_none_or(|resolved| doc_address < resolved) {
+                    *resolved = Some(doc_address);
+                }
+            })?;
+        }
+        Ok(id_values
+            .iter()
+            .map(|id_value| {
+                let id_ord = sorted_ids.binary_search(id_value).unwrap();
+                sorted_doc_addresses[id_ord]
+            })
+            .collect())
+    }
+
+    /// Returns the value of `id_field` for the document at `doc_address`, i.e. the reverse of
+    /// [`Searcher::resolve_doc()`].
+    ///
+    /// The field has to be a fast field. `None` is returned if the document has no value.
+    pub fn doc_id_value(
+        &self,
+        doc_address: DocAddress,
+        id_field: Field,
+    ) -> crate::Result<Option<u64>> {
+        self.inner.check_access(doc_address)?;
+        let field_entry = IdLookup::new(&self.inner.schema, id_field)?.field_entry;
+        if !field_entry.is_fast() {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a fast field.",
+                field_entry.name()
+            )));
+        }
+        let column = self
+            .segment_reader(doc_address.segment_ord)
+            .try_fast_fields()?
+            .u64(field_entry.name())?;
+        Ok(column.first(doc_address.doc_id))
+    }
+
+    /// Returns the first value of `field` for each alive document, in [`DocAddress`] order.
+    ///
+    /// The segments are visited in the order of their ordinal, and their deleted documents are
+    /// skipped. `None` is returned for the documents without a value, including all of the
+    /// documents of the segments without the column. On multivalued fields, only the first value
+    /// of each document is returned.
+    ///
+    /// `T` is the type of the column, e.g. `u64`, `i64`, `f64`, `bool` or
+    /// [`DateTime`](crate::DateTime). The values of other types are not read, as if the
+    /// documents had no value. See [`Searcher::column_iter_str_ords()`] for text fields.
+    ///
+    /// The field has to be a fast field. [`Searcher::column_blocks()`] reads the values a block
+    /// of documents at a time, which is faster.
+    ///
+    /// ```rust
+    /// use tantivy::schema::{Schema, FAST};
+    /// use tantivy::{doc, DocAddress, Index, IndexWriter};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let popularity = schema_builder.add_u64_field("popularity", FAST);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    /// let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(popularity => 10u64))?;
+    /// index_writer.add_document(doc!())?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let values: Vec<(DocAddress, Option<u64>)> =
+    ///     searcher.column_iter::<u64>("popularity")?.collect();
+    /// assert_eq!(
+    ///     values,
+    ///     vec![(DocAddress::new(0, 0), Some(10)), (DocAddress::new(0, 1), None)]
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column_iter<T>(
+        &self,
+        field: &str,
+    ) -> crate::Result<impl Iterator<Item = (DocAddress, Option<T>)> + '_>
+    where
+        T: HasAssociatedColumnType + PartialOrd + Copy + fmt::Debug + Send + Sync + 'static,
+        DynamicColumn: Into<Option<Column<T>>>,
+    {
+        let columns = self.open_columns(field)?;
+        Ok(column_iter::column_iter(
+            &self.inner.segment_readers,
+            columns,
+        ))
+    }
+
+    /// Block-wise version of [`Searcher::column_iter()`], returning the documents and their
+    /// value a block of documents of a segment at a time.
+    ///
+    /// ```rust
+    /// use tantivy::schema::{Schema, FAST};
+    /// use tantivy::{doc, Index, IndexWriter};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let price = schema_builder.add_f64_field("price", FAST);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    /// let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(price => 2.5f64))?;
+    /// index_writer.add_document(doc!(price => 4.0f64))?;
+    /// index_writer.commit()?;
+    ///
+    /// let searcher = index.reader()?.searcher();
+    /// let mut total_price = 0.0;
+    /// let mut blocks = searcher.column_blocks::<f64>("price")?;
+    /// while let Some(block) = blocks.next_block() {
+    ///     total_price += block.values().iter().flatten().sum::<f64>();
+    /// }
+    /// assert_eq!(total_price, 6.5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column_blocks<T>(&self, field: &str) -> crate::Result<ColumnBlocks<'_, T>>
+    where
+        T: HasAssociatedColumnType + PartialOrd + Copy + fmt::Debug + Send + Sync + 'static,
+        DynamicColumn: Into<Option<Column<T>>>,
+    {
+        let columns = self.open_columns(field)?;
+        Ok(ColumnBlocks::new(&self.inner.segment_readers, columns))
+    }
+
+    /// Returns the term ordinal of the first value of the text `field` for each alive document,
+    /// in [`DocAddress`] order.
+    ///
+    /// The term ordinals are local to each segment: the same ordinal may stand for different
+    /// terms in different segments. See [`Searcher::column_iter()`].
+    pub fn column_iter_str_ords(
+        &self,
+        field: &str,
+    ) -> crate::Result<impl Iterator<Item = (DocAddress, Option<u64>)> + '_> {
+        let columns = self.open_str_ord_columns(field)?;
+        Ok(column_iter::column_iter(
+            &self.inner.segment_readers,
+            columns,
+        ))
+    }
+
+    /// Block-wise version of [`Searcher::column_iter_str_ords()`].
+    pub fn column_blocks_str_ords(&self, field: &str) -> crate::Result<ColumnBlocks<'_, u64>> {
+        let columns = self.open_str_ord_columns(field)?;
+        Ok(ColumnBlocks::new(&self.inner.segment_readers, columns))
+    }
+
+    fn check_fast_field(&self, field: &str) -> crate::Result<()> {
+        let Some((schema_field, _)) = self.inner.schema.find_field(field) else {
+            return Err(TantivyError::FieldNotFound(field.to_string()));
+        };
+        let field_entry = self.inner.schema.get_field_entry(schema_field);
+        if !field_entry.is_fast() {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a fast field.",
+                field_entry.name()
+            )));
+        }
+        Ok(())
+    }
+
+    fn open_columns<T>(&self, field: &str) -> crate::Result<Vec<Option<Column<T>>>>
+    where
+        T: HasAssociatedColumnType,
+        DynamicColumn: Into<Option<Column<T>>>,
+    {
+        self.check_fast_field(field)?;
+        self.inner
+            .segment_readers
+            .iter()
+            .map(|segment_reader| segment_reader.try_fast_fields()?.column_opt(field))
+            .collect()
+    }
+
+    fn open_str_ord_columns(&self, field: &str) -> crate::Result<Vec<Option<Column<u64>>>> {
+        self.check_fast_field(field)?;
+        self.inner
+            .segment_readers
+            .iter()
+            .map(|segment_reader| {
+                let str_column_opt = segment_reader.try_fast_fields()?.str(field)?;
+                Ok(str_column_opt.map(|str_column| str_column.ords().clone()))
+            })
+            .collect()
+    }
+
     /// Access the schema associated with the index of this searcher.
     pub fn schema(&self) -> &Schema {
         &self.inner.schema
@@ -140,6 +525,59 @@ impl Searcher {
         Ok(total_doc_freq)
     }
 
+    /// Estimates the cost of running `query`, without running it.
+    ///
+    /// The estimate is made of upper bounds of the number of documents matching the query and of
+    /// the number of documents examined to find them, per segment and summed over the segments.
+    /// It is derived from cheap information, such as the document frequencies of the term
+    /// dictionaries or the bounds of the fast fields, so that obviously expensive queries can be
+    /// rejected before running them:
+    /// - term queries read the document frequency of their term,
+    /// - boolean queries combine the bounds of their clauses,
+    /// - range queries on fast fields check the range against the min and max values of the column,
+    ///   and scan its values,
+    /// - the queries expanding to the terms matching an automaton or a range, such as regex and
+    ///   fuzzy queries, walk the matching terms of the dictionary. Beyond 10,000 terms, the walk
+    ///   stops and the query is reported as [unbounded](crate::query::CostBounds::unbounded).
+    ///
+    /// The cost of the queries which do not support the estimate is
+    /// [`CostEstimate::Unknown`](crate::query::CostEstimate::Unknown). See
+    /// [`Weight::estimate_cost()`](crate::query::Weight::estimate_cost).
+    pub fn estimate_cost(&self, query: &dyn Query) -> crate::Result<QueryCostEstimate> {
+        let weight = query.weight(EnableScoring::disabled_from_searcher(self))?;
+        let segments = self
+            .inner
+            .segment_readers
+            .iter()
+            .map(|segment_reader| weight.estimate_cost(segment_reader))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(QueryCostEstimate::from_segments(segments))
+    }
+
+    /// Returns a handle on the index-wide statistics of the searcher: number of documents, number
+    /// of tokens per field and document frequencies, cached as they are computed.
+    ///
+    /// The handle is cheap to clone, and can be given to a
+    /// [`CustomScorer`](crate::collector::CustomScorer) to compute IDF-style weights in its
+    /// segment scorers.
+    pub fn statistics_provider(&self) -> SearcherStatistics {
+        SearcherStatistics::new(self.clone())
+    }
+
+    /// Returns the term dictionaries of `field` over all of the segments.
+    ///
+    /// Its streams merge the terms of the segments, with their document frequencies summed, so
+    /// that they can be filtered index-wide.
+    pub fn merged_term_dictionary(&self, field: Field) -> crate::Result<MergedTermDictionary> {
+        let inverted_indexes = self
+            .inner
+            .segment_readers
+            .iter()
+            .map(|segment_reader| segment_reader.inverted_index(field))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(MergedTermDictionary::new(inverted_indexes))
+    }
+
     /// Return the overall number of documents containing
     /// the given term in an asynchronous manner.
     #[cfg(feature = "quickwit")]
@@ -163,6 +601,124 @@ impl Searcher {
         &self.inner.segment_readers[segment_ord as usize]
     }
 
+    /// Returns a searcher over the same segments, that only sees the documents accepted by
+    /// `filter`.
+    ///
+    /// The documents rejected by the filter are handled like deleted documents: they are never
+    /// pushed to the collectors, and they are not counted in [`Searcher::num_docs`]. Document
+    /// frequencies and BM25 statistics are unaffected.
+    ///
+    /// The index is not modified, which makes it possible to hide a different set of documents
+    /// on every request.
+    pub fn with_doc_filter(&self, filter: Arc<dyn SegmentDocFilter>) -> crate::Result<Searcher> {
+        // The bitset of a segment is converted once, for both the readers with and without the
+        // soft-deleted documents.
+        let filter_bitsets: Vec<Option<AliveBitSet>> = self
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| {
+                let Some(alive_bitset) = filter.alive(segment_reader.segment_id()) else {
+                    return Ok(None);
+                };
+                if alive_bitset.max_value() != segment_reader.max_doc() {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "The doc filter bitset of segment {} has a max value of {}, but the \
+                         segment has {} docs.",
+                        segment_reader.segment_id().short_uuid_string(),
+                        alive_bitset.max_value(),
+                        segment_reader.max_doc()
+                    )));
+                }
+                Ok(Some(AliveBitSet::from(&*alive_bitset)))
+            })
+            .collect::<crate::Result<_>>()?;
+        let apply_filter = |segment_readers: &[SegmentReader]| -> Vec<SegmentReader> {
+            segment_readers
+                .iter()
+                .zip(&filter_bitsets)
+                .map(|(segment_reader, filter_bitset)| match filter_bitset {
+                    Some(filter_bitset) => segment_reader.with_alive_bitset(filter_bitset.clone()),
+                    None => segment_reader.clone(),
+                })
+                .collect()
+        };
+        let inner = SearcherInner {
+            segment_readers: apply_filter(&self.inner.segment_readers),
+            segment_readers_with_soft_deleted: apply_filter(
+                &self.inner.segment_readers_with_soft_deleted,
+            ),
+            ..self.inner.shallow_clone()
+        };
+        Ok(Searcher {
+            inner: Arc::new(inner),
+            search_budget: self.search_budget.clone(),
+            query_synonyms: self.query_synonyms.clone(),
+        })
+    }
+
+    /// Returns a searcher over the same segments, that only sees the documents of
+    /// `access_bitsets`, and refuses to fetch the others.
+    pub(crate) fn with_access_bitsets(
+        &self,
+        access_bitsets: Arc<Vec<Option<AliveBitSet>>>,
+    ) -> Searcher {
+        let restrict = |segment_readers: &[SegmentReader]| -> Vec<SegmentReader> {
+            segment_readers
+                .iter()
+                .zip(access_bitsets.iter())
+                .map(|(segment_reader, access_bitset)| match access_bitset {
+                    Some(access_bitset) => segment_reader.with_alive_bitset(access_bitset.clone()),
+                    None => segment_reader.clone(),
+                })
+                .collect()
+        };
+        let inner = SearcherInner {
+            segment_readers: restrict(&self.inner.segment_readers),
+            segment_readers_with_soft_deleted: restrict(
+                &self.inner.segment_readers_with_soft_deleted,
+            ),
+            access_bitsets: Some(access_bitsets.clone()),
+            ..self.inner.shallow_clone()
+        };
+        Searcher {
+            inner: Arc::new(inner),
+            search_budget: self.search_budget.clone(),
+            query_synonyms: self.query_synonyms.clone(),
+        }
+    }
+
+    /// Returns true if the searcher only sees the documents a principal is allowed to see.
+    pub(crate) fn is_access_restricted(&self) -> bool {
+        self.inner.access_bitsets.is_some()
+    }
+
+    /// Returns a searcher over the same segments, in which the soft-deleted documents are
+    /// visible or not.
+    ///
+    /// By default, soft-deleted documents are handled like deleted documents: they are never
+    /// pushed to the collectors, and they are not counted in [`Searcher::num_docs`]. See
+    /// [`IndexWriter::soft_delete_term`](crate::IndexWriter::soft_delete_term).
+    pub fn include_soft_deleted(&self, include_soft_deleted: bool) -> Searcher {
+        let segment_readers = if include_soft_deleted {
+            self.inner.segment_readers_with_soft_deleted.clone()
+        } else {
+            self.inner
+                .segment_readers_with_soft_deleted
+                .iter()
+                .map(SegmentReader::without_soft_deleted)
+                .collect()
+        };
+        let inner = SearcherInner {
+            segment_readers,
+            ..self.inner.shallow_clone()
+        };
+        Searcher {
+            inner: Arc::new(inner),
+            search_budget: self.search_budget.clone(),
+            query_synonyms: self.query_synonyms.clone(),
+        }
+    }
+
     /// Runs a query on the segment readers wrapped by the searcher.
     ///
     /// Search works as follows :
@@ -177,6 +733,11 @@ impl Searcher {
     ///
     ///  Finally, the Collector merges each of the child collectors into itself for result usability
     ///  by the caller.
+    ///
+    /// If the searcher was obtained from an [`IndexReader`](crate::IndexReader) with a
+    /// [`GlobalSearchBudget`](crate::GlobalSearchBudget), the search first waits to be admitted
+    /// by the budget, reserving its
+    /// [`default_search_bytes`](crate::GlobalSearchBudget::default_search_bytes).
     pub fn search<C: Collector>(
         &self,
         query: &dyn Query,
@@ -185,6 +746,43 @@ impl Searcher {
         self.search_with_statistics_provider(query, collector, self)
     }
 
+    /// Same as [`search(...)`](Searcher::search), but reserves `estimated_bytes` in the
+    /// [`GlobalSearchBudget`](crate::GlobalSearchBudget) of the reader, instead of its default.
+    ///
+    /// The search fails if the memory charged by its aggregations exceeds its estimate by more
+    /// than the bytes left in the budget.
+    pub fn search_with_estimate<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+        estimated_bytes: u64,
+    ) -> crate::Result<C::Fruit> {
+        let enabled_scoring = if collector.requires_scoring() {
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let executor = self.inner.index.search_executor();
+        self.search_with_executor_and_estimate(
+            query,
+            collector,
+            executor,
+            enabled_scoring,
+            Some(estimated_bytes),
+        )
+    }
+
+    /// Waits for the search to be admitted by the budget of the reader, if any.
+    fn acquire_search_permit(
+        &self,
+        estimated_bytes: Option<u64>,
+    ) -> crate::Result<Option<SearchPermit>> {
+        self.search_budget
+            .as_ref()
+            .map(|search_budget| search_budget.acquire(estimated_bytes))
+            .transpose()
+    }
+
     /// Same as [`search(...)`](Searcher::search) but allows specifying
     /// a [Bm25StatisticsProvider].
     ///
@@ -224,30 +822,360 @@ impl Searcher {
         executor: &Executor,
         enabled_scoring: EnableScoring,
     ) -> crate::Result<C::Fruit> {
+        self.search_with_executor_and_estimate(query, collector, executor, enabled_scoring, None)
+    }
+
+    fn search_with_executor_and_estimate<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+        executor: &Executor,
+        enabled_scoring: EnableScoring,
+        estimated_bytes: Option<u64>,
+    ) -> crate::Result<C::Fruit> {
+        let permit = self.acquire_search_permit(estimated_bytes)?;
+        let enter_permit = || permit.as_ref().map(SearchPermit::enter);
         let weight = query.weight(enabled_scoring)?;
         let segment_readers = self.segment_readers();
+        let collect_segment = |(segment_ord, segment_reader): (usize, &SegmentReader)| {
+            let _permit_scope = enter_permit();
+            collector.collect_segment(weight.as_ref(), segment_ord as u32, segment_reader)
+        };
+        if collector.merges_incrementally() {
+            let merged_fruit = executor.map_reduce(
+                collect_segment,
+                |merged_fruit, segment_fruit| {
+                    let _permit_scope = enter_permit();
+                    collector.merge_segment_fruit(merged_fruit, segment_fruit)
+                },
+                segment_readers.iter().enumerate(),
+            )?;
+            let _permit_scope = enter_permit();
+            return collector.merge_fruits_with_searcher(merged_fruit.into_iter().collect(), self);
+        }
+        let fruits = executor.map(collect_segment, segment_readers.iter().enumerate())?;
+        let _permit_scope = enter_permit();
+        collector.merge_fruits_with_searcher(fruits, self)
+    }
+
+    /// Same as [`search(...)`](Searcher::search), but reports the progress of the search to
+    /// `progress_callback`.
+    ///
+    /// The callback is called each time a segment is collected, and every few blocks of
+    /// documents within a segment. The reported values never decrease, and the last report is
+    /// [complete](crate::SearchProgress::is_complete), even if the segments are collected in
+    /// parallel by the search executor. The callback is never called concurrently, but it is
+    /// called from the threads of the executor, so it should return quickly.
+    ///
+    /// The documents are counted as they are pushed to the collectors, so the collectors which
+    /// enumerate the documents of a segment themselves instead of going through the query use
+    /// their generic collection loop.
+    pub fn search_with_progress<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+        progress_callback: SearchProgressCallback,
+    ) -> crate::Result<C::Fruit> {
+        let enabled_scoring = if collector.requires_scoring() {
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let executor = self.inner.index.search_executor();
+        let permit = self.acquire_search_permit(None)?;
+        let enter_permit = || permit.as_ref().map(SearchPermit::enter);
+        let segment_readers = self.segment_readers();
+        let tracker = Arc::new(ProgressTracker::new(
+            segment_readers.len(),
+            progress_callback,
+        ));
+        let weight = ProgressWeight::new(query.weight(enabled_scoring)?, tracker.clone());
         let fruits = executor.map(
             |(segment_ord, segment_reader)| {
-                collector.collect_segment(weight.as_ref(), segment_ord as u32, segment_reader)
+                let _permit_scope = enter_permit();
+                let fruit =
+                    collector.collect_segment(&weight, segment_ord as u32, segment_reader)?;
+                tracker.complete_segment();
+                Ok(fruit)
             },
             segment_readers.iter().enumerate(),
         )?;
-        collector.merge_fruits(fruits)
+        tracker.report_final();
+        let _permit_scope = enter_permit();
+        collector.merge_fruits_with_searcher(fruits, self)
+    }
+
+    /// Same as [`search(...)`](Searcher::search), but fails with [`TantivyError::Cancelled`]
+    /// once `token` is cancelled, typically from another thread.
+    ///
+    /// The token is checked before collecting each segment, every block of documents within a
+    /// segment, and before each merge of the fruits of the segments. The merge of the fruits of
+    /// an [`AggregationCollector`](crate::aggregation::AggregationCollector) can be cancelled as
+    /// well, by passing it the same token with `with_cancellation`.
+    ///
+    /// Like [`search_with_progress(...)`](Searcher::search_with_progress), the collectors which
+    /// enumerate the documents of a segment themselves use their generic collection loop.
+    pub fn search_cancellable<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+        token: &CancellationToken,
+    ) -> crate::Result<C::Fruit> {
+        token.check()?;
+        let enabled_scoring = if collector.requires_scoring() {
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let executor = self.inner.index.search_executor();
+        let permit = self.acquire_search_permit(None)?;
+        let enter_permit = || permit.as_ref().map(SearchPermit::enter);
+        let weight = CancellableWeight::new(query.weight(enabled_scoring)?, token.clone());
+        let segment_readers = self.segment_readers();
+        let collect_segment = |(segment_ord, segment_reader): (usize, &SegmentReader)| {
+            token.check()?;
+            let _permit_scope = enter_permit();
+            collector.collect_segment(&weight, segment_ord as u32, segment_reader)
+        };
+        if collector.merges_incrementally() {
+            let merged_fruit = executor.map_reduce(
+                collect_segment,
+                |merged_fruit, segment_fruit| {
+                    token.check()?;
+                    let _permit_scope = enter_permit();
+                    collector.merge_segment_fruit(merged_fruit, segment_fruit)
+                },
+                segment_readers.iter().enumerate(),
+            )?;
+            token.check()?;
+            let _permit_scope = enter_permit();
+            return collector.merge_fruits_with_searcher(merged_fruit.into_iter().collect(), self);
+        }
+        let fruits = executor.map(collect_segment, segment_readers.iter().enumerate())?;
+        token.check()?;
+        let _permit_scope = enter_permit();
+        collector.merge_fruits_with_searcher(fruits, self)
+    }
+
+    /// Scores the given candidate documents with `custom_scorer`, and returns the `limit` best
+    /// ones.
+    ///
+    /// This is the equivalent of searching with
+    /// [`TopDocs::custom_score`](crate::collector::TopDocs::custom_score) and a query matching
+    /// exactly the candidates, without building any query: the candidates are directly pushed
+    /// to the segment collectors. This is typically useful to rerank a candidate set computed
+    /// outside of tantivy.
+    ///
+    /// Deleted candidates are skipped. An error is returned if a candidate does not belong to
+    /// any segment of this searcher.
+    pub fn rerank<TScore, TCustomScorer>(
+        &self,
+        candidates: &SegmentCandidates,
+        custom_scorer: TCustomScorer,
+        limit: usize,
+    ) -> crate::Result<Vec<(TScore, DocAddress)>>
+    where
+        TScore: 'static + PartialOrd + Clone + Send + Sync,
+        TCustomScorer: CustomScorer<TScore> + Send + Sync,
+    {
+        let num_segments = self.segment_readers().len();
+        if let Some(segment_ord) = candidates
+            .segment_ords()
+            .find(|&segment_ord| segment_ord as usize >= num_segments)
+        {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The candidates refer to segment {segment_ord}, but the searcher only has \
+                 {num_segments} segments."
+            )));
+        }
+        let collector = TopDocs::with_limit(limit).custom_score(custom_scorer);
+        let executor = self.inner.index.search_executor();
+        let fruits = executor.map(
+            |segment_ord| {
+                let segment_reader = self.segment_reader(segment_ord);
+                let mut segment_collector = collector.for_segment(segment_ord, segment_reader)?;
+                candidates.for_each_alive_block(segment_ord, segment_reader, &mut |docs| {
+                    segment_collector.collect_block(docs);
+                })?;
+                Ok(segment_collector.harvest())
+            },
+            candidates.segment_ords(),
+        )?;
+        collector.merge_fruits_with_searcher(fruits, self)
+    }
+
+    /// Same as [`search(...)`](Searcher::search), but also returns a [`SearchProfile`]
+    /// breaking down where the time of the search went.
+    ///
+    /// The profile contains the time spent creating the weight, the time spent creating the
+    /// scorer and collecting each segment, and the time spent merging the segment fruits.
+    /// Tuple collectors report the collection time of each of their elements, and collectors
+    /// may report finer grained timings, e.g. the
+    /// [`AggregationCollector`](crate::aggregation::AggregationCollector) reports the time
+    /// spent in each aggregation.
+    ///
+    /// Documents are pushed to the collector in blocks in order to limit the overhead of
+    /// measuring the time, so the profiled search does not use the specialized collection loop
+    /// of some queries and collectors. A search that is not profiled is not affected.
+    pub fn search_with_profiling<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+    ) -> crate::Result<(C::Fruit, SearchProfile)> {
+        let enabled_scoring = if collector.requires_scoring() {
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let executor = self.inner.index.search_executor();
+        let permit = self.acquire_search_permit(None)?;
+        let enter_permit = || permit.as_ref().map(SearchPermit::enter);
+        let mut profile = SearchProfile::default();
+        let start = Instant::now();
+        let weight = query.weight(enabled_scoring)?;
+        profile.weight = start.elapsed();
+        let segment_readers = self.segment_readers();
+        let fruits_and_profiles = executor.map(
+            |(segment_ord, segment_reader)| {
+                let _permit_scope = enter_permit();
+                let mut segment_profile = SegmentProfile {
+                    segment_ord: segment_ord as u32,
+                    ..Default::default()
+                };
+                let fruit = collector.collect_segment_profiled(
+                    weight.as_ref(),
+                    segment_ord as u32,
+                    segment_reader,
+                    &mut segment_profile,
+                )?;
+                Ok((fruit, segment_profile))
+            },
+            segment_readers.iter().enumerate(),
+        )?;
+        let (fruits, segment_profiles) = fruits_and_profiles.into_iter().unzip();
+        profile.segments = segment_profiles;
+        let start = Instant::now();
+        let _permit_scope = enter_permit();
+        let fruit = collector.merge_fruits_with_searcher(fruits, self)?;
+        profile.merge = start.elapsed();
+        Ok((fruit, profile))
     }
 
     /// Summarize total space usage of this searcher.
     pub fn space_usage(&self) -> io::Result<SearcherSpaceUsage> {
         let mut space_usage = SearcherSpaceUsage::new();
         for segment_reader in self.segment_readers() {
-            space_usage.add_segment(segment_reader.space_usage()?);
+            space_usage.add_segment(segment_reader.space_usage()?, self.schema());
         }
         Ok(space_usage)
     }
+
+    /// Returns the statistics of the values of a fast field over all of the segments, merged from
+    /// the statistics computed when the segments were written.
+    ///
+    /// Returns `None` if a segment has no statistics for the field, see
+    /// [`SegmentReader::column_stats()`], or if a segment has deletes, since the statistics
+    /// would then include the values of the deleted documents.
+    pub fn column_stats(&self, field: Field) -> crate::Result<Option<ColumnStats>> {
+        let mut merged_column_stats = ColumnStats::default();
+        for segment_reader in self.segment_readers() {
+            if segment_reader.alive_bitset().is_some() {
+                return Ok(None);
+            }
+            let Some(column_stats) = segment_reader.column_stats(field) else {
+                return Ok(None);
+            };
+            merged_column_stats.merge(column_stats.clone())?;
+        }
+        Ok(Some(merged_column_stats))
+    }
+}
+
+/// Looks up the documents of a segment by the value of a `u64` id field.
+struct IdLookup<'a> {
+    field: Field,
+    field_entry: &'a FieldEntry,
+}
+
+impl<'a> IdLookup<'a> {
+    fn new(schema: &'a Schema, field: Field) -> crate::Result<IdLookup<'a>> {
+        let field_entry = schema.get_field_entry(field);
+        if field_entry.field_type().value_type() != Type::U64 {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a u64 field.",
+                field_entry.name()
+            )));
+        }
+        if !field_entry.is_indexed() && !field_entry.is_fast() {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is neither indexed nor a fast field.",
+                field_entry.name()
+            )));
+        }
+        Ok(IdLookup { field, field_entry })
+    }
+
+    /// Calls `callback` with the ordinal of the id and the doc id of each of the alive documents
+    /// of the segment having one of the `sorted_ids`.
+    fn lookup_segment(
+        &self,
+        segment_reader: &SegmentReader,
+        sorted_ids: &[u64],
+        callback: &mut dyn FnMut(usize, DocId),
+    ) -> crate::Result<()> {
+        let (Some(&min_id), Some(&max_id)) = (sorted_ids.first(), sorted_ids.last()) else {
+            return Ok(());
+        };
+        if self.field_entry.is_indexed() {
+            let inverted_index = segment_reader.inverted_index(self.field)?;
+            for (id_ord, &id) in sorted_ids.iter().enumerate() {
+                let term = Term::from_field_u64(self.field, id);
+                let Some(mut postings) =
+                    inverted_index.read_postings(&term, IndexRecordOption::Basic)?
+                else {
+                    continue;
+                };
+                let mut doc_id = postings.doc();
+                while doc_id != TERMINATED {
+                    if !segment_reader.is_deleted(doc_id) {
+                        callback(id_ord, doc_id);
+                    }
+                    doc_id = postings.advance();
+                }
+            }
+        } else {
+            let column = segment_reader
+                .try_fast_fields()?
+                .u64(self.field_entry.name())?;
+            let mut doc_ids = Vec::new();
+            column.get_docids_for_value_range(
+                min_id..=max_id,
+                0..segment_reader.max_doc(),
+                &mut doc_ids,
+            );
+            doc_ids.dedup();
+            for doc_id in doc_ids {
+                if segment_reader.is_deleted(doc_id) {
+                    continue;
+                }
+                for value in column.values_for_doc(doc_id) {
+                    if let Ok(id_ord) = sorted_ids.binary_search(&value) {
+                        callback(id_ord, doc_id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl From<Arc<SearcherInner>> for Searcher {
     fn from(inner: Arc<SearcherInner>) -> Self {
-        Searcher { inner }
+        Searcher {
+            inner,
+            search_budget: None,
+            query_synonyms: None,
+        }
     }
 }
 
@@ -259,8 +1187,17 @@ pub(crate) struct SearcherInner {
     schema: Schema,
     index: Index,
     segment_readers: Vec<SegmentReader>,
-    store_readers: Vec<StoreReader>,
+    /// The segment readers, in which the soft-deleted documents are visible.
+    segment_readers_with_soft_deleted: Vec<SegmentReader>,
+    /// The store readers of the segments, opened on first access if the doc store was not loaded
+    /// with the segment readers.
+    store_readers: Arc<Vec<OnceCell<StoreReader>>>,
+    doc_store_cache_num_blocks: usize,
     generation: TrackedObject<SearcherGeneration>,
+    commit_opstamp: Opstamp,
+    /// The documents of each segment the principal of the searcher is allowed to see, if the
+    /// searcher is restricted by an access filter. `None` if all of them are.
+    access_bitsets: Option<Arc<Vec<Option<AliveBitSet>>>>,
 }
 
 impl SearcherInner {
@@ -270,6 +1207,7 @@ impl SearcherInner {
         index: Index,
         segment_readers: Vec<SegmentReader>,
         generation: TrackedObject<SearcherGeneration>,
+        commit_opstamp: Opstamp,
         doc_store_cache_num_blocks: usize,
     ) -> io::Result<SearcherInner> {
         assert_eq!(
@@ -280,19 +1218,75 @@ impl SearcherInner {
             generation.segments(),
             "Set of segments referenced by this Searcher and its SearcherGeneration must match"
         );
-        let store_readers: Vec<StoreReader> = segment_readers
+        let store_readers: Vec<OnceCell<StoreReader>> = segment_readers
             .iter()
-            .map(|segment_reader| segment_reader.get_store_reader(doc_store_cache_num_blocks))
+            .map(|segment_reader| {
+                if !segment_reader.is_loaded(ComponentSet::STORE) {
+                    return Ok(OnceCell::new());
+                }
+                let store_reader = segment_reader.get_store_reader(doc_store_cache_num_blocks)?;
+                Ok(OnceCell::with_value(store_reader))
+            })
             .collect::<io::Result<Vec<_>>>()?;
 
         Ok(SearcherInner {
             schema,
             index,
-            segment_readers,
-            store_readers,
+            segment_readers: segment_readers
+                .iter()
+                .map(SegmentReader::without_soft_deleted)
+                .collect(),
+            segment_readers_with_soft_deleted: segment_readers,
+            store_readers: Arc::new(store_readers),
+            doc_store_cache_num_blocks,
             generation,
+            commit_opstamp,
+            access_bitsets: None,
         })
     }
+
+    /// Returns a copy of this `SearcherInner`, sharing its segments.
+    fn shallow_clone(&self) -> SearcherInner {
+        SearcherInner {
+            schema: self.schema.clone(),
+            index: self.index.clone(),
+            segment_readers: self.segment_readers.clone(),
+            segment_readers_with_soft_deleted: self.segment_readers_with_soft_deleted.clone(),
+            store_readers: self.store_readers.clone(),
+            doc_store_cache_num_blocks: self.doc_store_cache_num_blocks,
+            generation: self.generation.clone(),
+            commit_opstamp: self.commit_opstamp,
+            access_bitsets: self.access_bitsets.clone(),
+        }
+    }
+
+    /// Returns an error if the principal of the searcher is not allowed to see the document.
+    fn check_access(&self, doc_address: DocAddress) -> crate::Result<()> {
+        let Some(access_bitsets) = self.access_bitsets.as_ref() else {
+            return Ok(());
+        };
+        let is_denied = access_bitsets
+            .get(doc_address.segment_ord as usize)
+            .and_then(Option::as_ref)
+            .is_some_and(|access_bitset| access_bitset.is_deleted(doc_address.doc_id));
+        if is_denied {
+            return Err(TantivyError::AccessDenied { doc_address });
+        }
+        Ok(())
+    }
+
+    /// Returns the store reader of the segment `segment_ord`, opening it if needed.
+    fn store_reader(&self, segment_ord: usize) -> crate::Result<&StoreReader> {
+        self.store_readers[segment_ord].get_or_try_init(|| {
+            self.segment_readers_with_soft_deleted[segment_ord]
+                .open_store_reader(self.doc_store_cache_num_blocks)
+        })
+    }
+
+    /// Returns the opstamp of the commit this searcher was loaded from.
+    pub(crate) fn commit_opstamp(&self) -> Opstamp {
+        self.commit_opstamp
+    }
 }
 
 impl fmt::Debug for Searcher {
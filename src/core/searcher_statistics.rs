@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::query::Bm25StatisticsProvider;
+use crate::schema::{Field, Term};
+use crate::Searcher;
+
+/// Index-wide statistics of a [`Searcher`], for scorers computing IDF-style weights.
+///
+/// The statistics sum the statistics of all of the segments of the searcher, like the ones used
+/// by the built-in BM25 scoring. They are computed lazily and cached, so that the
+/// [`CustomScorer`](crate::collector::CustomScorer) of a search can look them up in each of its
+/// segment scorers.
+///
+/// Cloning the statistics is cheap, and the clones share their cache.
+///
+/// ```rust
+/// use tantivy::schema::{Schema, TEXT};
+/// use tantivy::{doc, Index, IndexWriter, Term};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let body = schema_builder.add_text_field("body", TEXT);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(15_000_000)?;
+/// index_writer.add_document(doc!(body => "the old man and the sea"))?;
+/// index_writer.add_document(doc!(body => "the sea wolf"))?;
+/// index_writer.commit()?;
+///
+/// let statistics = index.reader()?.searcher().statistics_provider();
+/// assert_eq!(statistics.total_num_docs(), 2);
+/// assert_eq!(statistics.field_total_tokens(body)?, 9);
+/// assert_eq!(statistics.doc_freq(&Term::from_field_text(body, "sea"))?, 2);
+/// assert_eq!(statistics.doc_freq(&Term::from_field_text(body, "wolf"))?, 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SearcherStatistics {
+    searcher: Searcher,
+    cache: Arc<Mutex<StatisticsCache>>,
+}
+
+#[derive(Default)]
+struct StatisticsCache {
+    doc_freqs: HashMap<Term, u64>,
+    field_total_tokens: HashMap<Field, u64>,
+}
+
+impl fmt::Debug for SearcherStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cache = self.cache.lock().unwrap();
+        f.debug_struct("SearcherStatistics")
+            .field("num_cached_doc_freqs", &cache.doc_freqs.len())
+            .field("num_cached_fields", &cache.field_total_tokens.len())
+            .finish()
+    }
+}
+
+impl SearcherStatistics {
+    pub(crate) fn new(searcher: Searcher) -> SearcherStatistics {
+        SearcherStatistics {
+            searcher,
+            cache: Default::default(),
+        }
+    }
+
+    /// Returns the number of documents of the searcher, deleted documents included.
+    pub fn total_num_docs(&self) -> u64 {
+        self.searcher
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| u64::from(segment_reader.max_doc()))
+            .sum()
+    }
+
+    /// Returns the number of tokens of `field` in all of the documents of the searcher.
+    pub fn field_total_tokens(&self, field: Field) -> crate::Result<u64> {
+        if let Some(total_tokens) = self.cache.lock().unwrap().field_total_tokens.get(&field) {
+            return Ok(*total_tokens);
+        }
+        let mut total_tokens = 0u64;
+        for segment_reader in self.searcher.segment_readers() {
+            total_tokens += segment_reader.inverted_index(field)?.total_num_tokens();
+        }
+        self.cache
+            .lock()
+            .unwrap()
+            .field_total_tokens
+            .insert(field, total_tokens);
+        Ok(total_tokens)
+    }
+
+    /// Returns the number of documents of the searcher containing `term`.
+    pub fn doc_freq(&self, term: &Term) -> crate::Result<u64> {
+        Ok(self.doc_freqs(std::slice::from_ref(term))?[0])
+    }
+
+    /// Returns the number of documents of the searcher containing each of `terms`.
+    ///
+    /// The terms which are not cached yet are looked up together: the term dictionary of each
+    /// of their fields is opened once per segment, and the terms are looked up in sorted order.
+    pub fn doc_freqs(&self, terms: &[Term]) -> crate::Result<Vec<u64>> {
+        let mut missing_terms: Vec<&Term> = {
+            let cache = self.cache.lock().unwrap();
+            terms
+                .iter()
+                .filter(|term| !cache.doc_freqs.contains_key(*term))
+                .collect()
+        };
+        if !missing_terms.is_empty() {
+            missing_terms.sort();
+            missing_terms.dedup();
+            let mut doc_freqs = vec![0u64; missing_terms.len()];
+            for segment_reader in self.searcher.segment_readers() {
+                // The terms are sorted by field first, so the terms of a field are contiguous.
+                let mut start = 0;
+                while start < missing_terms.len() {
+                    let field = missing_terms[start].field();
+                    let end = start
+                        + missing_terms[start..]
+                            .iter()
+                            .take_while(|term| term.field() == field)
+                            .count();
+                    let inverted_index = segment_reader.inverted_index(field)?;
+                    for (term, doc_freq) in missing_terms[start..end]
+                        .iter()
+                        .zip(&mut doc_freqs[start..end])
+                    {
+                        *doc_freq += u64::from(inverted_index.doc_freq(term)?);
+                    }
+                    start = end;
+                }
+            }
+            let mut cache = self.cache.lock().unwrap();
+            for (term, doc_freq) in missing_terms.into_iter().zip(doc_freqs) {
+                cache.doc_freqs.insert(term.clone(), doc_freq);
+            }
+        }
+        let cache = self.cache.lock().unwrap();
+        Ok(terms.iter().map(|term| cache.doc_freqs[term]).collect())
+    }
+}
+
+impl Bm25StatisticsProvider for SearcherStatistics {
+    fn total_num_tokens(&self, field: Field) -> crate::Result<u64> {
+        self.field_total_tokens(field)
+    }
+
+    fn total_num_docs(&self) -> crate::Result<u64> {
+        Ok(SearcherStatistics::total_num_docs(self))
+    }
+
+    fn doc_freq(&self, term: &Term) -> crate::Result<u64> {
+        SearcherStatistics::doc_freq(self, term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collector::{CustomScorer, CustomSegmentScorer, TopDocs};
+    use crate::fieldnorm::FieldNormReader;
+    use crate::postings::{Postings, SegmentPostings};
+    use crate::query::{BooleanQuery, Occur, Query, TermQuery};
+    use crate::schema::{Field, IndexRecordOption, Schema, TEXT};
+    use crate::{
+        DocAddress, DocId, DocSet, Index, IndexWriter, Score, SearcherStatistics, SegmentReader,
+        Term,
+    };
+
+    /// BM25, computed from the statistics of the searcher.
+    struct HandRolledBm25 {
+        statistics: SearcherStatistics,
+        terms: Vec<Term>,
+    }
+
+    struct HandRolledBm25SegmentScorer {
+        /// Postings, fieldnorms, idf and average fieldnorm of each term.
+        terms: Vec<(SegmentPostings, FieldNormReader, Score, Score)>,
+    }
+
+    impl CustomScorer<Score> for HandRolledBm25 {
+        type Child = HandRolledBm25SegmentScorer;
+
+        fn segment_scorer(&self, segment_reader: &SegmentReader) -> crate::Result<Self::Child> {
+            let total_num_docs = self.statistics.total_num_docs();
+            let doc_freqs = self.statistics.doc_freqs(&self.terms)?;
+            let mut terms = Vec::new();
+            for (term, doc_freq) in self.terms.iter().zip(doc_freqs) {
+                let field = term.field();
+                let avg_fieldnorm =
+                    self.statistics.field_total_tokens(field)? as Score / total_num_docs as Score;
+                let idf = (1.0
+                    + (total_num_docs as Score - doc_freq as Score + 0.5)
+                        / (doc_freq as Score + 0.5))
+                    .ln();
+                let Some(postings) = segment_reader
+                    .inverted_index(field)?
+                    .read_postings(term, IndexRecordOption::WithFreqs)?
+                else {
+                    continue;
+                };
+                let fieldnorm_reader = segment_reader.get_fieldnorms_reader(field)?;
+                terms.push((postings, fieldnorm_reader, idf, avg_fieldnorm));
+            }
+            Ok(HandRolledBm25SegmentScorer { terms })
+        }
+    }
+
+    impl CustomSegmentScorer<Score> for HandRolledBm25SegmentScorer {
+        fn score(&mut self, doc: DocId) -> Score {
+            let (k1, b) = (1.2, 0.75);
+            let mut score = 0.0;
+            for (postings, fieldnorm_reader, idf, avg_fieldnorm) in &mut self.terms {
+                if postings.doc() < doc {
+                    postings.seek(doc);
+                }
+                if postings.doc() != doc {
+                    continue;
+                }
+                let term_freq = postings.term_freq() as Score;
+                let fieldnorm =
+                    FieldNormReader::id_to_fieldnorm(fieldnorm_reader.fieldnorm_id(doc)) as Score;
+                let norm = k1 * (1.0 - b + b * fieldnorm / *avg_fieldnorm);
+                score += *idf * term_freq * (k1 + 1.0) / (term_freq + norm);
+            }
+            score
+        }
+    }
+
+    fn create_index() -> crate::Result<(Index, Field, Field)> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT);
+        let body = schema_builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let docs = [
+            (
+                "The Old Man and the Sea",
+                "an old fisherman and a big fish at sea",
+            ),
+            ("Of Mice and Men", "two men and a dream"),
+            ("The Sea Wolf", "a captain at sea, a wolf of the sea"),
+            ("Sea Shells", "a guide to the shells of the sea shore"),
+            ("The Old Sea Road", "old roads by the old sea"),
+            ("Men at Sea", "men, ships and the sea"),
+        ];
+        // Several segments, so that the statistics have to be summed.
+        for (doc_id, (title_text, body_text)) in docs.into_iter().enumerate() {
+            index_writer.add_document(doc!(title => title_text, body => body_text))?;
+            if doc_id % 2 == 1 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok((index, title, body))
+    }
+
+    #[test]
+    fn test_searcher_statistics() -> crate::Result<()> {
+        let (index, title, body) = create_index()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 3);
+        let statistics = searcher.statistics_provider();
+        assert_eq!(statistics.total_num_docs(), 6);
+        assert_eq!(statistics.field_total_tokens(title)?, 22);
+        let sea = Term::from_field_text(body, "sea");
+        let terms = [
+            sea.clone(),
+            Term::from_field_text(title, "old"),
+            Term::from_field_text(body, "absent"),
+            sea.clone(),
+        ];
+        assert_eq!(statistics.doc_freqs(&terms)?, vec![5, 2, 0, 5]);
+        for term in &terms {
+            assert_eq!(statistics.doc_freq(term)?, searcher.doc_freq(term)?);
+        }
+        // Clones share the cache.
+        let statistics_clone = statistics.clone();
+        assert_eq!(statistics_clone.doc_freq(&sea)?, 5);
+        assert_eq!(
+            format!("{statistics_clone:?}"),
+            "SearcherStatistics { num_cached_doc_freqs: 3, num_cached_fields: 1 }"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hand_rolled_bm25_matches_builtin_scores() -> crate::Result<()> {
+        let (index, title, body) = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let terms = vec![
+            Term::from_field_text(title, "sea"),
+            Term::from_field_text(body, "sea"),
+            Term::from_field_text(body, "old"),
+            Term::from_field_text(title, "men"),
+        ];
+        let query = BooleanQuery::new(
+            terms
+                .iter()
+                .map(|term| {
+                    let query: Box<dyn Query> =
+                        Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs));
+                    (Occur::Should, query)
+                })
+                .collect(),
+        );
+
+        let mut builtin: Vec<(Score, DocAddress)> =
+            searcher.search(&query, &TopDocs::with_limit(10))?;
+        let mut hand_rolled: Vec<(Score, DocAddress)> = searcher.search(
+            &query,
+            &TopDocs::with_limit(10).custom_score(HandRolledBm25 {
+                statistics: searcher.statistics_provider(),
+                terms,
+            }),
+        )?;
+        assert_eq!(builtin.len(), 6);
+        builtin.sort_by_key(|(_, doc_address)| *doc_address);
+        hand_rolled.sort_by_key(|(_, doc_address)| *doc_address);
+        for ((builtin_score, builtin_doc), (score, doc)) in builtin.iter().zip(&hand_rolled) {
+            assert_eq!(builtin_doc, doc);
+            assert!(
+                (builtin_score - score).abs() < 1e-4,
+                "{builtin_doc:?}: {builtin_score} != {score}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use common::BitSet;
+
+use crate::{
+    DocAddress, DocId, SegmentOrdinal, SegmentReader, TantivyError, COLLECT_BLOCK_BUFFER_LEN,
+};
+
+/// Candidate documents of a segment.
+#[derive(Clone)]
+enum CandidateDocs {
+    /// Sorted and deduplicated doc ids.
+    Sorted(Vec<DocId>),
+    BitSet(BitSet),
+}
+
+/// Set of candidate documents, per segment, to be scored by
+/// [`Searcher::rerank`](crate::Searcher::rerank).
+///
+/// The candidates of a segment are either given as a list of doc ids, or as a bitset.
+#[derive(Clone, Default)]
+pub struct SegmentCandidates {
+    per_segment: BTreeMap<SegmentOrdinal, CandidateDocs>,
+}
+
+impl SegmentCandidates {
+    /// Creates an empty set of candidates.
+    pub fn new() -> SegmentCandidates {
+        SegmentCandidates::default()
+    }
+
+    /// Creates a set of candidates from their doc addresses.
+    pub fn from_doc_addresses(
+        doc_addresses: impl IntoIterator<Item = DocAddress>,
+    ) -> SegmentCandidates {
+        let mut docs_per_segment: BTreeMap<SegmentOrdinal, Vec<DocId>> = BTreeMap::new();
+        for doc_address in doc_addresses {
+            docs_per_segment
+                .entry(doc_address.segment_ord)
+                .or_default()
+                .push(doc_address.doc_id);
+        }
+        let mut candidates = SegmentCandidates::new();
+        for (segment_ord, docs) in docs_per_segment {
+            candidates.set_docs(segment_ord, docs);
+        }
+        candidates
+    }
+
+    /// Sets the candidates of the segment `segment_ord`, replacing its previous candidates.
+    ///
+    /// The doc ids do not need to be sorted.
+    pub fn set_docs(&mut self, segment_ord: SegmentOrdinal, mut docs: Vec<DocId>) {
+        docs.sort_unstable();
+        docs.dedup();
+        self.per_segment
+            .insert(segment_ord, CandidateDocs::Sorted(docs));
+    }
+
+    /// Sets the candidates of the segment `segment_ord` as a bitset, replacing its previous
+    /// candidates.
+    ///
+    /// The max value of the bitset cannot exceed the segment's
+    /// [`max_doc`](SegmentReader::max_doc).
+    pub fn set_bitset(&mut self, segment_ord: SegmentOrdinal, bitset: BitSet) {
+        self.per_segment
+            .insert(segment_ord, CandidateDocs::BitSet(bitset));
+    }
+
+    /// Returns the ordinals of the segments having candidates, in increasing order.
+    pub(crate) fn segment_ords(&self) -> impl Iterator<Item = SegmentOrdinal> + '_ {
+        self.per_segment.keys().copied()
+    }
+
+    /// Pushes the alive candidates of the segment `segment_ord` to `callback`, by blocks of
+    /// sorted doc ids.
+    pub(crate) fn for_each_alive_block(
+        &self,
+        segment_ord: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+        callback: &mut dyn FnMut(&[DocId]),
+    ) -> crate::Result<()> {
+        let Some(candidate_docs) = self.per_segment.get(&segment_ord) else {
+            return Ok(());
+        };
+        let max_doc = segment_reader.max_doc();
+        match candidate_docs {
+            CandidateDocs::Sorted(docs) => {
+                if let Some(&last_doc) = docs.last() {
+                    if last_doc >= max_doc {
+                        return Err(TantivyError::InvalidArgument(format!(
+                            "The candidate doc {last_doc} of segment {segment_ord} is out of \
+                             bounds, as the segment has {max_doc} docs."
+                        )));
+                    }
+                }
+                push_alive_blocks(docs.iter().copied(), segment_reader, callback);
+            }
+            CandidateDocs::BitSet(bitset) => {
+                if bitset.max_value() > max_doc {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "The candidate bitset of segment {segment_ord} has a max value of {}, but \
+                         the segment has {max_doc} docs.",
+                        bitset.max_value()
+                    )));
+                }
+                let num_buckets = bitset.max_value().div_ceil(64);
+                let docs = (0..num_buckets).flat_map(|bucket| {
+                    bitset
+                        .tinyset(bucket)
+                        .into_iter()
+                        .map(move |low_bits| bucket * 64 + low_bits)
+                });
+                push_alive_blocks(docs, segment_reader, callback);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_alive_blocks(
+    docs: impl Iterator<Item = DocId>,
+    segment_reader: &SegmentReader,
+    callback: &mut dyn FnMut(&[DocId]),
+) {
+    let alive_bitset_opt = segment_reader.alive_bitset();
+    let mut block = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+    let mut block_len = 0;
+    for doc in docs {
+        if let Some(alive_bitset) = alive_bitset_opt {
+            if alive_bitset.is_deleted(doc) {
+                continue;
+            }
+        }
+        block[block_len] = doc;
+        block_len += 1;
+        if block_len == COLLECT_BLOCK_BUFFER_LEN {
+            callback(&block);
+            block_len = 0;
+        }
+    }
+    if block_len > 0 {
+        callback(&block[..block_len]);
+    }
+}
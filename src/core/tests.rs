@@ -1,14 +1,22 @@
-use crate::collector::Count;
+use std::sync::Arc;
+
+use common::BitSet;
+use serde_json::json;
+
+use crate::aggregation::agg_req::Aggregations;
+use crate::aggregation::AggregationCollector;
+use crate::collector::{Count, TopDocs};
 use crate::directory::{RamDirectory, WatchCallback};
 use crate::index::SegmentId;
 use crate::indexer::{LogMergePolicy, NoMergePolicy};
 use crate::postings::Postings;
-use crate::query::TermQuery;
-use crate::schema::{Field, IndexRecordOption, Schema, INDEXED, STRING, TEXT};
+use crate::query::{AllQuery, TermQuery};
+use crate::schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
 use crate::tokenizer::TokenizerManager;
 use crate::{
-    Directory, DocSet, Index, IndexBuilder, IndexReader, IndexSettings, IndexWriter, ReloadPolicy,
-    TantivyDocument, Term,
+    Directory, DocAddress, DocId, DocSet, Index, IndexBuilder, IndexReader, IndexSettings,
+    IndexWriter, ReloadPolicy, Searcher, SegmentCandidates, SegmentDocFilter, SegmentReader,
+    TantivyDocument, TantivyError, Term,
 };
 
 #[test]
@@ -197,6 +205,27 @@ mod mmap_specific {
         test_index_on_commit_reload_policy_aux(field, &write_index, &reader)
     }
 }
+#[test]
+fn test_index_manual_policy_reload_if_newer() -> crate::Result<()> {
+    let schema = throw_away_schema();
+    let field = schema.get_field("num_likes").unwrap();
+    let index = Index::create_in_ram(schema);
+    let mut writer: IndexWriter = index.writer_for_tests()?;
+    writer.commit()?;
+    let reader = index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()?;
+    assert!(!reader.reload_if_newer()?);
+    writer.add_document(doc!(field=>1u64))?;
+    writer.commit()?;
+    assert_eq!(reader.searcher().num_docs(), 0);
+    assert!(reader.reload_if_newer()?);
+    assert_eq!(reader.searcher().num_docs(), 1);
+    assert!(!reader.reload_if_newer()?);
+    Ok(())
+}
+
 fn test_index_on_commit_reload_policy_aux(
     field: Field,
     index: &Index,
@@ -325,7 +354,9 @@ fn test_merging_segment_update_docfreq() {
     }
     writer.add_document(TantivyDocument::default()).unwrap();
     writer.add_document(TantivyDocument::default()).unwrap();
-    writer.delete_term(Term::from_field_text(id_field, "TO_BE_DELETED"));
+    writer
+        .delete_term(Term::from_field_text(id_field, "TO_BE_DELETED"))
+        .unwrap();
     writer.commit().unwrap();
 
     let segment_ids: Vec<SegmentId> = index
@@ -466,3 +497,488 @@ fn test_non_text_json_term_freq_bitpacked() {
         assert_eq!(postings.term_freq(), 1u32);
     }
 }
+
+struct HideDocsFilter {
+    segment_id: SegmentId,
+    alive_bitset: Arc<BitSet>,
+}
+
+impl SegmentDocFilter for HideDocsFilter {
+    fn alive(&self, segment_id: SegmentId) -> Option<Arc<BitSet>> {
+        (segment_id == self.segment_id).then(|| self.alive_bitset.clone())
+    }
+}
+
+#[test]
+fn test_searcher_with_doc_filter() -> crate::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let tag = schema_builder.add_text_field("tag", STRING | FAST);
+    let num = schema_builder.add_u64_field("num", INDEXED | FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    for segment in 0..2u64 {
+        for i in 0..100u64 {
+            let tag_value = if i < 30 { "a" } else { "b" };
+            index_writer.add_document(doc!(tag => tag_value, num => segment * 100 + i))?;
+        }
+        index_writer.commit()?;
+    }
+    // Deletes are still applied on top of the filter.
+    index_writer.delete_term(Term::from_field_u64(num, 1))?;
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    let segment_reader = searcher
+        .segment_readers()
+        .iter()
+        .find(|segment_reader| segment_reader.alive_bitset().is_some())
+        .unwrap();
+
+    // Hide the even docs of the segment with a delete.
+    let mut alive_bitset = BitSet::with_max_value(segment_reader.max_doc());
+    for doc in (1..segment_reader.max_doc()).step_by(2) {
+        alive_bitset.insert(doc);
+    }
+    let filtered_segment_id = segment_reader.segment_id();
+    let filter = HideDocsFilter {
+        segment_id: filtered_segment_id,
+        alive_bitset: Arc::new(alive_bitset),
+    };
+    let filtered_searcher = searcher.with_doc_filter(Arc::new(filter))?;
+
+    // The original searcher is unaffected.
+    assert_eq!(searcher.num_docs(), 199);
+    assert_eq!(searcher.search(&AllQuery, &Count)?, 199);
+    // 50 odd docs in the filtered segment, minus the deleted one.
+    assert_eq!(filtered_searcher.num_docs(), 149);
+    assert_eq!(filtered_searcher.search(&AllQuery, &Count)?, 149);
+    let (count, profile) = filtered_searcher.search_with_profiling(&AllQuery, &Count)?;
+    assert_eq!(count, 149);
+    assert_eq!(profile.num_docs_collected(), 149);
+
+    let query = TermQuery::new(Term::from_field_text(tag, "a"), IndexRecordOption::Basic);
+    assert_eq!(filtered_searcher.search(&query, &Count)?, 44);
+    let top_docs = filtered_searcher.search(&query, &TopDocs::with_limit(100))?;
+    assert_eq!(top_docs.len(), 44);
+    for (_, doc_address) in &top_docs {
+        let segment_reader = filtered_searcher.segment_reader(doc_address.segment_ord);
+        assert!(!segment_reader.is_deleted(doc_address.doc_id));
+        if segment_reader.segment_id() == filtered_segment_id {
+            assert_eq!(doc_address.doc_id % 2, 1);
+        }
+    }
+    // The scores are computed with the same statistics.
+    let unfiltered_top_docs = searcher.search(&query, &TopDocs::with_limit(1))?;
+    assert_eq!(top_docs[0].0, unfiltered_top_docs[0].0);
+
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "tags": { "terms": { "field": "tag" } }
+    }))
+    .unwrap();
+    let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+    let agg_res = serde_json::to_value(filtered_searcher.search(&AllQuery, &collector)?)?;
+    assert_eq!(
+        agg_res["tags"]["buckets"],
+        json!([
+            { "key": "b", "doc_count": 105 },
+            { "key": "a", "doc_count": 44 },
+        ])
+    );
+    let agg_res = serde_json::to_value(filtered_searcher.search(&query, &collector)?)?;
+    assert_eq!(
+        agg_res["tags"]["buckets"],
+        json!([{ "key": "a", "doc_count": 44 }])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_searcher_with_doc_filter_invalid_bitset() -> crate::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let tag = schema_builder.add_text_field("tag", STRING);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.add_document(doc!(tag => "a"))?;
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    let filter = HideDocsFilter {
+        segment_id: searcher.segment_reader(0).segment_id(),
+        alive_bitset: Arc::new(BitSet::with_max_value(10)),
+    };
+    assert!(matches!(
+        searcher.with_doc_filter(Arc::new(filter)),
+        Err(TantivyError::InvalidArgument(_))
+    ));
+    Ok(())
+}
+
+fn rerank_test_index() -> crate::Result<Index> {
+    let mut schema_builder = Schema::builder();
+    let num = schema_builder.add_u64_field("num", INDEXED | FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    for segment in 0..2u64 {
+        for i in 0..100u64 {
+            index_writer.add_document(doc!(num => segment * 100 + i))?;
+        }
+        index_writer.commit()?;
+    }
+    // The doc 6 of both segments is a candidate of `test_searcher_rerank`.
+    index_writer.delete_term(Term::from_field_u64(num, 6))?;
+    index_writer.delete_term(Term::from_field_u64(num, 106))?;
+    index_writer.commit()?;
+    Ok(index)
+}
+
+/// Scores are distinct for all of the `num` values of the test index.
+fn rerank_score(segment_reader: &SegmentReader) -> impl FnMut(DocId) -> u64 {
+    let num = segment_reader
+        .fast_fields()
+        .u64("num")
+        .unwrap()
+        .first_or_default_col(0);
+    move |doc| num.get_val(doc) * 7919 % 1009
+}
+
+#[test]
+fn test_searcher_rerank() -> crate::Result<()> {
+    let index = rerank_test_index()?;
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.segment_readers().len(), 2);
+
+    let mut candidates = SegmentCandidates::new();
+    // Unsorted, with a duplicate.
+    let mut segment_0_docs: Vec<DocId> = (0..100).step_by(3).rev().collect();
+    segment_0_docs.push(6);
+    candidates.set_docs(0, segment_0_docs);
+    // More candidates than a collection block.
+    let mut segment_1_bitset = BitSet::with_max_value(100);
+    for doc in (0..100).filter(|doc| doc % 5 != 0) {
+        segment_1_bitset.insert(doc);
+    }
+    candidates.set_bitset(1, segment_1_bitset);
+
+    let mut expected: Vec<(u64, DocAddress)> = Vec::new();
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        let mut score = rerank_score(segment_reader);
+        for doc in 0..segment_reader.max_doc() {
+            let is_candidate = if segment_ord == 0 {
+                doc % 3 == 0
+            } else {
+                doc % 5 != 0
+            };
+            if is_candidate && !segment_reader.is_deleted(doc) {
+                expected.push((score(doc), DocAddress::new(segment_ord as u32, doc)));
+            }
+        }
+    }
+    expected.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    // 34 + 80 candidates, minus one deleted candidate in each segment.
+    assert_eq!(expected.len(), 112);
+
+    for limit in [1, 10, 200] {
+        let top_docs = searcher.rerank(&candidates, rerank_score, limit)?;
+        assert_eq!(&top_docs[..], &expected[..limit.min(expected.len())]);
+    }
+
+    let candidates = SegmentCandidates::from_doc_addresses(
+        expected[..5]
+            .iter()
+            .rev()
+            .map(|(_, doc_address)| *doc_address),
+    );
+    assert_eq!(
+        searcher.rerank(&candidates, rerank_score, 10)?,
+        expected[..5].to_vec()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_searcher_rerank_invalid_candidates() -> crate::Result<()> {
+    let index = rerank_test_index()?;
+    let searcher = index.reader()?.searcher();
+
+    let mut candidates = SegmentCandidates::new();
+    candidates.set_docs(2, vec![0]);
+    assert!(matches!(
+        searcher.rerank(&candidates, rerank_score, 10),
+        Err(TantivyError::InvalidArgument(_))
+    ));
+
+    let mut candidates = SegmentCandidates::new();
+    candidates.set_docs(0, vec![0, 100]);
+    assert!(matches!(
+        searcher.rerank(&candidates, rerank_score, 10),
+        Err(TantivyError::InvalidArgument(_))
+    ));
+
+    let mut candidates = SegmentCandidates::new();
+    candidates.set_bitset(1, BitSet::with_max_value(101));
+    assert!(matches!(
+        searcher.rerank(&candidates, rerank_score, 10),
+        Err(TantivyError::InvalidArgument(_))
+    ));
+    Ok(())
+}
+
+fn resolve_test_index() -> crate::Result<(Index, IndexWriter)> {
+    let mut schema_builder = Schema::builder();
+    schema_builder.add_u64_field("id", INDEXED | FAST);
+    schema_builder.add_u64_field("fast_id", FAST);
+    schema_builder.add_u64_field("indexed_id", INDEXED);
+    schema_builder.add_text_field("text", STRING | STORED);
+    let index = Index::create_in_ram(schema_builder.build());
+    let index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    Ok((index, index_writer))
+}
+
+fn add_id_doc(index_writer: &IndexWriter, id: u64) -> crate::Result<()> {
+    let schema = index_writer.index().schema();
+    let mut doc = TantivyDocument::new();
+    doc.add_u64(schema.get_field("id")?, id);
+    doc.add_u64(schema.get_field("fast_id")?, id);
+    doc.add_u64(schema.get_field("indexed_id")?, id);
+    doc.add_text(schema.get_field("text")?, format!("doc{id}"));
+    index_writer.add_document(doc)?;
+    Ok(())
+}
+
+#[test]
+fn test_searcher_resolve_doc_across_merges() -> crate::Result<()> {
+    let (index, mut index_writer) = resolve_test_index()?;
+    let schema = index.schema();
+    let id_fields = [
+        schema.get_field("id")?,
+        schema.get_field("fast_id")?,
+        schema.get_field("indexed_id")?,
+    ];
+    let text = schema.get_field("text")?;
+    for segment in 0..3 {
+        for id in (0..30).filter(|id| id % 3 == segment) {
+            add_id_doc(&index_writer, id)?;
+        }
+        index_writer.commit()?;
+    }
+    index_writer.delete_term(Term::from_field_text(text, "doc5"))?;
+    index_writer.commit()?;
+
+    let check_ids = |searcher: &Searcher| -> crate::Result<()> {
+        for id in 0..30 {
+            for id_field in id_fields {
+                let doc_address = searcher.resolve_doc(id_field, id)?;
+                if id == 5 {
+                    assert_eq!(doc_address, None);
+                    continue;
+                }
+                let doc: TantivyDocument = searcher.doc(doc_address.unwrap())?;
+                assert_eq!(
+                    doc.get_first(text).and_then(|value| value.as_str()),
+                    Some(format!("doc{id}").as_str())
+                );
+            }
+            if let Some(doc_address) = searcher.resolve_doc(id_fields[0], id)? {
+                assert_eq!(searcher.doc_id_value(doc_address, id_fields[0])?, Some(id));
+                assert_eq!(searcher.doc_id_value(doc_address, id_fields[1])?, Some(id));
+            }
+        }
+        assert_eq!(searcher.resolve_doc(id_fields[0], 30)?, None);
+        Ok(())
+    };
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.segment_readers().len(), 3);
+    check_ids(&searcher)?;
+
+    let segment_ids = index.searchable_segment_ids()?;
+    index_writer.merge(&segment_ids).wait()?;
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.segment_readers().len(), 1);
+    check_ids(&searcher)?;
+    Ok(())
+}
+
+#[test]
+fn test_searcher_resolve_duplicate_ids() -> crate::Result<()> {
+    let (index, mut index_writer) = resolve_test_index()?;
+    let schema = index.schema();
+    let text = schema.get_field("text")?;
+    add_id_doc(&index_writer, 1)?;
+    add_id_doc(&index_writer, 2)?;
+    add_id_doc(&index_writer, 2)?;
+    index_writer.commit()?;
+    add_id_doc(&index_writer, 1)?;
+    index_writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    for id_field in ["id", "fast_id", "indexed_id"] {
+        let id_field = schema.get_field(id_field)?;
+        let all_docs = searcher.resolve_all_docs(id_field, 1)?;
+        assert_eq!(all_docs.len(), 2);
+        assert!(all_docs[0] < all_docs[1]);
+        // The lowest address wins.
+        assert_eq!(searcher.resolve_doc(id_field, 1)?, Some(all_docs[0]));
+        let all_docs = searcher.resolve_all_docs(id_field, 2)?;
+        assert_eq!(all_docs.len(), 2);
+        assert_eq!(all_docs[0].segment_ord, all_docs[1].segment_ord);
+        assert_eq!(searcher.resolve_doc(id_field, 2)?, Some(all_docs[0]));
+    }
+
+    // Deleted documents are not resolved.
+    index_writer.delete_term(Term::from_field_text(text, "doc2"))?;
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    let id_field = schema.get_field("fast_id")?;
+    assert!(searcher.resolve_all_docs(id_field, 2)?.is_empty());
+    assert_eq!(searcher.resolve_all_docs(id_field, 1)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_searcher_resolve_docs_bulk() -> crate::Result<()> {
+    let (index, mut index_writer) = resolve_test_index()?;
+    let schema = index.schema();
+    for segment in 0..4u64 {
+        for id in (0..1_000u64).filter(|id| id % 4 == segment) {
+            add_id_doc(&index_writer, id * 7)?;
+        }
+        index_writer.commit()?;
+    }
+    let searcher = index.reader()?.searcher();
+    // Unsorted, with duplicates and missing ids.
+    let id_values: Vec<u64> = (0..3_000u64).map(|i| (i * 7_919) % 7_500).collect();
+    for id_field in ["id", "fast_id", "indexed_id"] {
+        let id_field = schema.get_field(id_field)?;
+        let doc_addresses = searcher.resolve_docs(id_field, &id_values)?;
+        assert_eq!(doc_addresses.len(), id_values.len());
+        for (&id_value, doc_address) in id_values.iter().zip(&doc_addresses) {
+            assert_eq!(doc_address.is_some(), id_value % 7 == 0 && id_value < 7_000);
+            assert_eq!(*doc_address, searcher.resolve_doc(id_field, id_value)?);
+        }
+    }
+    assert!(searcher
+        .resolve_docs(schema.get_field("id")?, &[])?
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_searcher_resolve_doc_invalid_field() -> crate::Result<()> {
+    let (index, mut index_writer) = resolve_test_index()?;
+    let schema = index.schema();
+    add_id_doc(&index_writer, 1)?;
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    assert!(matches!(
+        searcher.resolve_doc(schema.get_field("text")?, 1),
+        Err(TantivyError::SchemaError(_))
+    ));
+    let doc_address = searcher
+        .resolve_doc(schema.get_field("indexed_id")?, 1)?
+        .unwrap();
+    assert!(matches!(
+        searcher.doc_id_value(doc_address, schema.get_field("indexed_id")?),
+        Err(TantivyError::SchemaError(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_searcher_column_iter() -> crate::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let id = schema_builder.add_u64_field("id", INDEXED);
+    let score = schema_builder.add_i64_field("score", FAST | STORED);
+    let tag = schema_builder.add_text_field("tag", STRING | FAST | STORED);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    index_writer.set_merge_policy(Box::new(NoMergePolicy));
+    // More than a block of documents, some of them without value or with several values.
+    for doc_id in 0..2_500u64 {
+        let mut doc = TantivyDocument::default();
+        doc.add_u64(id, doc_id);
+        if doc_id % 3 != 0 {
+            doc.add_i64(score, doc_id as i64 - 1_000);
+        }
+        if doc_id % 7 == 0 {
+            doc.add_i64(score, -1);
+            doc.add_text(tag, format!("tag{}", doc_id % 5));
+        }
+        index_writer.add_document(doc)?;
+    }
+    index_writer.commit()?;
+    // A segment without the columns.
+    for doc_id in 2_500..2_510u64 {
+        index_writer.add_document(doc!(id => doc_id))?;
+    }
+    index_writer.commit()?;
+    for doc_id in 2_510..2_520u64 {
+        index_writer.add_document(doc!(id => doc_id, score => 5i64, tag => "last"))?;
+    }
+    index_writer.commit()?;
+    for doc_id in (0..2_520u64).filter(|doc_id| doc_id % 11 == 0) {
+        index_writer.delete_term(Term::from_field_u64(id, doc_id))?;
+    }
+    index_writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.segment_readers().len(), 3);
+    let mut expected_scores = Vec::new();
+    let mut expected_tags = Vec::new();
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        let tag_column = segment_reader.fast_fields().str("tag")?;
+        for doc_id in segment_reader.doc_ids_alive() {
+            let doc_address = DocAddress::new(segment_ord as u32, doc_id);
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let score_value = doc.get_first(score).and_then(|value| value.as_i64());
+            expected_scores.push((doc_address, score_value));
+            let tag_value = doc.get_first(tag).and_then(|value| value.as_str());
+            let tag_ord = tag_value.map(|tag_value| {
+                let tag_column = tag_column.as_ref().unwrap();
+                tag_column
+                    .dictionary()
+                    .term_ord(tag_value)
+                    .unwrap()
+                    .unwrap()
+            });
+            expected_tags.push((doc_address, tag_ord));
+        }
+    }
+    assert_eq!(expected_scores.len() as u64, searcher.num_docs());
+
+    let scores: Vec<(DocAddress, Option<i64>)> = searcher.column_iter::<i64>("score")?.collect();
+    assert_eq!(scores, expected_scores);
+    let tags: Vec<(DocAddress, Option<u64>)> = searcher.column_iter_str_ords("tag")?.collect();
+    assert_eq!(tags, expected_tags);
+
+    let mut block_scores = Vec::new();
+    let mut num_blocks = 0;
+    let mut blocks = searcher.column_blocks::<i64>("score")?;
+    while let Some(block) = blocks.next_block() {
+        assert!(block.docs().len() <= 1_024);
+        assert_eq!(block.docs().len(), block.values().len());
+        block_scores.extend(block.iter());
+        num_blocks += 1;
+    }
+    assert_eq!(num_blocks, 5);
+    assert_eq!(block_scores, expected_scores);
+    let mut block_tags = Vec::new();
+    let mut blocks = searcher.column_blocks_str_ords("tag")?;
+    while let Some(block) = blocks.next_block() {
+        block_tags.extend(block.iter());
+    }
+    assert_eq!(block_tags, expected_tags);
+
+    // The values of another type are not read.
+    assert!(searcher
+        .column_iter::<u64>("score")?
+        .all(|(_, value)| value.is_none()));
+    assert!(matches!(
+        searcher.column_iter::<u64>("id").err(),
+        Some(TantivyError::SchemaError(_))
+    ));
+    assert!(matches!(
+        searcher.column_blocks::<u64>("unknown").err(),
+        Some(TantivyError::FieldNotFound(_))
+    ));
+    Ok(())
+}
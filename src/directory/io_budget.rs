@@ -0,0 +1,207 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::{AntiCallToken, TerminatingWrite};
+
+use crate::directory::WritePtr;
+
+/// Amount of time worth of budget that can be consumed in a burst, after the budget has been left
+/// unused.
+const IO_BUDGET_BURST: Duration = Duration::from_millis(100);
+
+/// Budget of bytes per second, shared by all the writers it throttles.
+///
+/// The budget is enforced by a token bucket: writing blocks the calling thread until the bytes
+/// written fit in the budget. Cloning an `IoBudget` gives a handle to the same budget, which can be
+/// used to adjust it while writers are running.
+///
+/// The [`IndexWriter`](crate::IndexWriter) uses an `IoBudget` to throttle the files written by
+/// merges, see
+/// [`IndexWriterOptions::merge_io_budget_bytes_per_sec`](crate::indexer::IndexWriterOptions).
+#[derive(Clone)]
+pub struct IoBudget {
+    token_bucket: Arc<Mutex<TokenBucket>>,
+}
+
+struct TokenBucket {
+    bytes_per_sec: Option<u64>,
+    /// Bytes that can be written without waiting. Negative when writers are in debt.
+    available_bytes: f64,
+    last_refill: Instant,
+}
+
+impl IoBudget {
+    /// Creates a budget of `bytes_per_sec`, or an unlimited budget if `None`.
+    ///
+    /// # Panics
+    /// If `bytes_per_sec` is `Some(0)`.
+    pub fn new(bytes_per_sec: Option<u64>) -> IoBudget {
+        assert_ne!(
+            bytes_per_sec,
+            Some(0),
+            "The IO budget needs to be positive."
+        );
+        IoBudget {
+            token_bucket: Arc::new(Mutex::new(TokenBucket {
+                bytes_per_sec,
+                available_bytes: 0.0,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Creates an unlimited budget.
+    pub fn unlimited() -> IoBudget {
+        IoBudget::new(None)
+    }
+
+    /// Returns the budget in bytes per second, or `None` if unlimited.
+    pub fn bytes_per_sec(&self) -> Option<u64> {
+        self.token_bucket.lock().unwrap().bytes_per_sec
+    }
+
+    /// Sets the budget in bytes per second, or makes it unlimited if `None`.
+    ///
+    /// The new budget applies to the bytes written from now on. Writers already waiting for
+    /// budget are not woken up.
+    ///
+    /// # Panics
+    /// If `bytes_per_sec` is `Some(0)`.
+    pub fn set_bytes_per_sec(&self, bytes_per_sec: Option<u64>) {
+        assert_ne!(
+            bytes_per_sec,
+            Some(0),
+            "The IO budget needs to be positive."
+        );
+        let mut token_bucket = self.token_bucket.lock().unwrap();
+        token_bucket.refill(Instant::now());
+        token_bucket.bytes_per_sec = bytes_per_sec;
+        if bytes_per_sec.is_none() {
+            token_bucket.available_bytes = 0.0;
+        }
+    }
+
+    /// Blocks until `num_bytes` fit in the budget.
+    pub(crate) fn consume(&self, num_bytes: usize) {
+        let wait = {
+            let mut token_bucket = self.token_bucket.lock().unwrap();
+            let Some(bytes_per_sec) = token_bucket.bytes_per_sec else {
+                return;
+            };
+            token_bucket.refill(Instant::now());
+            // The bytes are consumed right away, possibly putting the bucket in debt: the
+            // following writers wait for the debt to be paid back.
+            token_bucket.available_bytes -= num_bytes as f64;
+            if token_bucket.available_bytes >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-token_bucket.available_bytes / bytes_per_sec as f64)
+        };
+        std::thread::sleep(wait);
+    }
+
+    /// Wraps `write`, so that the bytes written to it consume this budget.
+    pub(crate) fn throttle(&self, write: WritePtr) -> WritePtr {
+        WritePtr::new(Box::new(ThrottledWrite {
+            write,
+            io_budget: self.clone(),
+        }))
+    }
+}
+
+impl std::fmt::Debug for IoBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IoBudget")
+            .field("bytes_per_sec", &self.bytes_per_sec())
+            .finish()
+    }
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        let Some(bytes_per_sec) = self.bytes_per_sec else {
+            return;
+        };
+        let max_available_bytes = bytes_per_sec as f64 * IO_BUDGET_BURST.as_secs_f64();
+        self.available_bytes = (self.available_bytes
+            + elapsed.as_secs_f64() * bytes_per_sec as f64)
+            .min(max_available_bytes);
+    }
+}
+
+/// Writer consuming an [`IoBudget`] for the bytes written through it.
+struct ThrottledWrite {
+    write: WritePtr,
+    io_budget: IoBudget,
+}
+
+impl Write for ThrottledWrite {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.write.write(buf)?;
+        self.io_budget.consume(num_bytes);
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write.flush()
+    }
+}
+
+impl TerminatingWrite for ThrottledWrite {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.write.terminate_ref(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use common::HasLen;
+
+    use super::IoBudget;
+    use crate::directory::{Directory, RamDirectory, TerminatingWrite};
+
+    #[test]
+    fn test_io_budget_unlimited() {
+        let io_budget = IoBudget::unlimited();
+        let start = Instant::now();
+        io_budget.consume(1_000_000_000);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_io_budget_throttles_writes() {
+        let io_budget = IoBudget::new(Some(100_000));
+        let directory = RamDirectory::create();
+        let mut write = io_budget.throttle(directory.open_write(Path::new("file")).unwrap());
+        let start = Instant::now();
+        for _ in 0..50 {
+            write.write_all(&[0u8; 1_000]).unwrap();
+        }
+        write.terminate().unwrap();
+        // 50KB at 100KB/s, all of which is in debt as the budget starts empty.
+        assert!(start.elapsed() >= Duration::from_millis(450));
+        assert_eq!(
+            directory.open_read(Path::new("file")).unwrap().len(),
+            50_000
+        );
+    }
+
+    #[test]
+    fn test_io_budget_live_adjustment() {
+        let io_budget = IoBudget::new(Some(1_000));
+        assert_eq!(io_budget.bytes_per_sec(), Some(1_000));
+        let handle = io_budget.clone();
+        handle.set_bytes_per_sec(None);
+        assert_eq!(io_budget.bytes_per_sec(), None);
+        let start = Instant::now();
+        io_budget.consume(1_000_000);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -1,8 +1,8 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, RwLockWriteGuard};
-use std::{io, result};
+use std::{fmt, io, result};
 
 use crc32fast::Hasher;
 
@@ -45,6 +45,46 @@ pub struct ManagedDirectory {
 #[derive(Debug, Default)]
 struct MetaInformation {
     managed_paths: HashSet<PathBuf>,
+    protected_files: HashMap<PathBuf, usize>,
+}
+
+/// A `FileProtection` prevents the garbage collection of a file.
+///
+/// The file stays protected until all of the `FileProtection`s
+/// created for it are dropped.
+///
+/// See [`ManagedDirectory::protect_file_from_delete`].
+pub struct FileProtection {
+    meta_informations: Arc<RwLock<MetaInformation>>,
+    path: PathBuf,
+}
+
+impl FileProtection {
+    /// Path of the protected file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Debug for FileProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FileProtection({:?})", self.path)
+    }
+}
+
+impl Drop for FileProtection {
+    fn drop(&mut self) {
+        let mut meta_wlock = self
+            .meta_informations
+            .write()
+            .expect("Managed file lock poisoned on unprotect");
+        if let Some(count) = meta_wlock.protected_files.get_mut(&self.path) {
+            *count -= 1;
+            if *count == 0 {
+                meta_wlock.protected_files.remove(&self.path);
+            }
+        }
+    }
 }
 
 /// Saves the file containing the list of existing files
@@ -76,6 +116,7 @@ impl ManagedDirectory {
                     directory,
                     meta_informations: Arc::new(RwLock::new(MetaInformation {
                         managed_paths: managed_files,
+                        protected_files: HashMap::new(),
                     })),
                 })
             }
@@ -102,6 +143,9 @@ impl ManagedDirectory {
     /// The use a callback ensures that the list of living_files is computed
     /// while we hold the lock on meta.
     ///
+    /// Files protected by a [`FileProtection`] are never deleted, even if
+    /// they are not part of the living files.
+    ///
     /// This method does not panick nor returns errors.
     /// If a file cannot be deleted (for permission reasons for instance)
     /// an error is simply logged, and the file remains in the list of managed
@@ -139,7 +183,11 @@ impl ManagedDirectory {
                 Ok(_meta_lock) => {
                     let living_files = get_living_files();
                     for managed_path in &meta_informations_rlock.managed_paths {
-                        if !living_files.contains(managed_path) {
+                        if !living_files.contains(managed_path)
+                            && !meta_informations_rlock
+                                .protected_files
+                                .contains_key(managed_path)
+                        {
                             files_to_delete.push(managed_path.clone());
                         }
                     }
@@ -199,6 +247,25 @@ impl ManagedDirectory {
         })
     }
 
+    /// Protects a file from being deleted by [`ManagedDirectory::garbage_collect`].
+    ///
+    /// The protection is lifted when the returned [`FileProtection`] is dropped.
+    /// Protections only apply to the garbage collection run by this process.
+    pub fn protect_file_from_delete(&self, path: &Path) -> FileProtection {
+        let mut meta_wlock = self
+            .meta_informations
+            .write()
+            .expect("Managed file lock poisoned on protect");
+        *meta_wlock
+            .protected_files
+            .entry(path.to_owned())
+            .or_insert(0) += 1;
+        FileProtection {
+            meta_informations: self.meta_informations.clone(),
+            path: path.to_owned(),
+        }
+    }
+
     /// Registers a file as managed
     ///
     /// This method must be called before the file is
@@ -210,7 +277,7 @@ impl ManagedDirectory {
     /// File starting by "." are reserved to locks.
     /// They are not managed and cannot be subjected
     /// to garbage collection.
-    fn register_file_as_managed(&self, filepath: &Path) -> io::Result<()> {
+    pub(crate) fn register_file_as_managed(&self, filepath: &Path) -> io::Result<()> {
         // Files starting by "." (e.g. lock files) are not managed.
         if !is_managed(filepath) {
             return Ok(());
@@ -239,6 +306,11 @@ impl ManagedDirectory {
         Ok(())
     }
 
+    /// Returns the wrapped directory, which reads files along with their footer.
+    pub(crate) fn underlying_directory(&self) -> &dyn Directory {
+        self.directory.as_ref()
+    }
+
     /// Verify checksum of a managed file
     pub fn validate_checksum(&self, path: &Path) -> result::Result<bool, OpenReadError> {
         let reader = self.directory.open_read(path)?;
@@ -410,4 +482,28 @@ mod tests_mmap_specific {
         }
         assert!(!managed_directory.exists(test_path1).unwrap());
     }
+
+    #[test]
+    fn test_managed_directory_protected_files() {
+        let test_path1: &'static Path = Path::new("some_path_for_test");
+
+        let tempdir = TempDir::new().unwrap();
+        let mmap_directory = MmapDirectory::open(tempdir.path()).unwrap();
+        let mut managed_directory = ManagedDirectory::wrap(Box::new(mmap_directory)).unwrap();
+        managed_directory.atomic_write(test_path1, &[0u8]).unwrap();
+
+        let protection1 = managed_directory.protect_file_from_delete(test_path1);
+        let protection2 = managed_directory.protect_file_from_delete(test_path1);
+        let gc_result = managed_directory.garbage_collect(HashSet::new).unwrap();
+        assert!(gc_result.deleted_files.is_empty());
+        assert!(managed_directory.exists(test_path1).unwrap());
+
+        drop(protection1);
+        managed_directory.garbage_collect(HashSet::new).unwrap();
+        assert!(managed_directory.exists(test_path1).unwrap());
+
+        drop(protection2);
+        managed_directory.garbage_collect(HashSet::new).unwrap();
+        assert!(!managed_directory.exists(test_path1).unwrap());
+    }
 }
@@ -7,6 +7,7 @@ mod directory;
 mod directory_lock;
 mod file_watcher;
 pub mod footer;
+mod io_budget;
 mod managed_directory;
 mod ram_directory;
 mod watch_event_router;
@@ -25,6 +26,7 @@ pub use common::{AntiCallToken, OwnedBytes, TerminatingWrite};
 pub(crate) use self::composite_file::{CompositeFile, CompositeWrite};
 pub use self::directory::{Directory, DirectoryClone, DirectoryLock};
 pub use self::directory_lock::{Lock, INDEX_WRITER_LOCK, META_LOCK};
+pub use self::io_budget::IoBudget;
 pub use self::ram_directory::RamDirectory;
 pub use self::watch_event_router::{WatchCallback, WatchCallbackList, WatchHandle};
 
@@ -45,7 +47,7 @@ pub struct GarbageCollectionResult {
 #[cfg(all(feature = "mmap", unix))]
 pub use memmap2::Advice;
 
-pub use self::managed_directory::ManagedDirectory;
+pub use self::managed_directory::{FileProtection, ManagedDirectory};
 #[cfg(feature = "mmap")]
 pub use self::mmap_directory::MmapDirectory;
 
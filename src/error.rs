@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use std::sync::{Arc, PoisonError};
 use std::{fmt, io};
 
+use common::ByteCount;
 use thiserror::Error;
 
 use crate::aggregation::AggregationError;
@@ -11,8 +12,10 @@ use crate::directory::error::{
     Incompatibility, LockError, OpenDirectoryError, OpenReadError, OpenWriteError,
 };
 use crate::fastfield::FastFieldNotAvailableError;
+use crate::index::{ComponentSet, SegmentId};
+use crate::indexer::TokenizationError;
 use crate::schema::document::DeserializeError;
-use crate::{query, schema};
+use crate::{query, schema, DocAddress, DocId};
 
 /// Represents a `DataCorruption` error.
 ///
@@ -110,6 +113,278 @@ pub enum TantivyError {
     #[error("Deserialize error: {0}")]
     /// An error occurred while attempting to deserialize a document.
     DeserializeError(DeserializeError),
+    /// The payload of a commit exceeds the size allowed by the
+    /// [`IndexWriterOptions`](crate::indexer::IndexWriterOptions).
+    #[error(
+        "The commit payload is {num_bytes} bytes, exceeding the limit of {max_num_bytes} bytes"
+    )]
+    CommitPayloadTooLarge {
+        /// Size of the serialized payload.
+        num_bytes: usize,
+        /// Maximum size of a payload.
+        max_num_bytes: usize,
+    },
+    /// A query expanded to more terms than allowed in a segment, e.g. a regex matching too many
+    /// terms of a delete query.
+    ///
+    /// See [`Query::set_max_expanded_terms()`](crate::query::Query::set_max_expanded_terms).
+    #[error("The query expanded to more than {max_expanded_terms} terms of field {field:?}")]
+    TooManyExpandedTerms {
+        /// Name of the field whose terms were expanded.
+        field: String,
+        /// Maximum number of terms a query may expand to.
+        max_expanded_terms: usize,
+    },
+    /// A component of a segment was accessed, but was not loaded by an index reader opened with
+    /// [`IndexReaderBuilder::strict_component_loading`](crate::IndexReaderBuilder::strict_component_loading).
+    #[error(
+        "The {component:?} component of segment {segment_id:?} was not loaded by the index reader"
+    )]
+    ComponentNotLoaded {
+        /// Segment whose component was accessed.
+        segment_id: SegmentId,
+        /// Component accessed.
+        component: ComponentSet,
+    },
+    /// A segment has no column for a fast field read by a collector, with the
+    /// [`MissingColumnPolicy::Error`](crate::collector::MissingColumnPolicy::Error) policy.
+    #[error("The segment {segment_id:?} has no column for the fast field {field:?}")]
+    MissingColumn {
+        /// Segment without a column for the field.
+        segment_id: SegmentId,
+        /// Name of the fast field.
+        field: String,
+    },
+    /// A search was not admitted by the [`GlobalSearchBudget`](crate::GlobalSearchBudget) of its
+    /// reader, because the maximum number of searches are running, with the
+    /// [`BudgetExhaustedPolicy::FailFast`](crate::BudgetExhaustedPolicy::FailFast) policy.
+    #[error("The maximum of {max_concurrent} concurrent searches is reached")]
+    TooManyConcurrentSearches {
+        /// Maximum number of searches running concurrently.
+        max_concurrent: usize,
+    },
+    /// A search requested more memory than left in the
+    /// [`GlobalSearchBudget`](crate::GlobalSearchBudget) of its reader.
+    #[error(
+        "The search requested {requested_bytes} bytes, but only {available_bytes} bytes are left \
+         in the search budget"
+    )]
+    SearchMemoryBudgetExceeded {
+        /// Bytes requested by the search.
+        requested_bytes: u64,
+        /// Bytes left in the budget.
+        available_bytes: u64,
+    },
+    /// A search exceeded one of its limits, e.g. the memory limit of its aggregations.
+    #[error("{}", budget_exceeded_message(*.kind, *.limit, *.used))]
+    BudgetExceeded {
+        /// Limited resource.
+        kind: BudgetKind,
+        /// Limit, in the unit of the resource.
+        limit: u64,
+        /// Usage which exceeded the limit, in the unit of the resource.
+        used: u64,
+    },
+    /// A component of a segment could not be read, because its files are corrupted.
+    #[error("The {component:?} component of segment {segment_id:?} is corrupted: {reason}")]
+    SegmentCorruption {
+        /// Corrupted segment.
+        segment_id: SegmentId,
+        /// Corrupted component.
+        component: ComponentSet,
+        /// Description of the corruption.
+        reason: String,
+    },
+    /// A merge was cancelled before the merged segment was written, see
+    /// [`MergeHandle`](crate::indexer::MergeHandle).
+    #[error("The merge was cancelled")]
+    MergeCancelled,
+    /// A search was cancelled through its
+    /// [`CancellationToken`](crate::CancellationToken), see
+    /// [`Searcher::search_cancellable()`](crate::Searcher::search_cancellable).
+    #[error("The search was cancelled")]
+    Cancelled,
+    /// A document upserted with the
+    /// [`UpsertConflictPolicy::Error`](crate::indexer::UpsertConflictPolicy::Error) policy has
+    /// the same unique key as an existing document.
+    #[error("A document with the same value for the unique field {field:?} already exists")]
+    UniqueKeyConflict {
+        /// Name of the unique field.
+        field: String,
+    },
+    /// The schema passed to [`Index::update_schema()`](crate::Index::update_schema) does not
+    /// only add fields to the schema of the index.
+    #[error("The field {field:?} of the schema cannot be {kind}")]
+    IncompatibleSchemaChange {
+        /// Name of the field of the schema of the index.
+        field: String,
+        /// How the field was changed.
+        kind: SchemaChangeKind,
+    },
+    /// A document was fetched by a searcher whose principal is not allowed to see it, see
+    /// [`SearcherContext::with_access_filter()`](crate::SearcherContext::with_access_filter).
+    #[error("Access to the document {doc_address:?} is denied")]
+    AccessDenied {
+        /// Address of the document.
+        doc_address: DocAddress,
+    },
+    /// A commit was rejected by one of the
+    /// [`CommitValidator`](crate::indexer::CommitValidator)s of the index writer.
+    #[error(
+        "The commit was rejected by a validator of segment {segment_id:?} (document {doc:?}): \
+         {reason}"
+    )]
+    CommitViolation {
+        /// Segment of the commit which violates the invariant.
+        segment_id: SegmentId,
+        /// Document which violates the invariant, if the violation is about a document.
+        doc: Option<DocId>,
+        /// Description of the violation.
+        reason: String,
+    },
+    /// A segment could not be replaced by
+    /// [`IndexWriter::replace_segment()`](crate::IndexWriter::replace_segment), because it is
+    /// being merged or already being replaced.
+    #[error("The segment {segment_id:?} is being merged or replaced")]
+    SegmentBusy {
+        /// Segment which could not be replaced.
+        segment_id: SegmentId,
+    },
+    /// A query cannot be converted to or from a
+    /// [`SerializableQuery`](crate::query::SerializableQuery), e.g. a custom query type, or a
+    /// query type unknown to this version of tantivy.
+    #[error("Unsupported query: {0}")]
+    UnsupportedQuery(String),
+    /// A document updated with
+    /// [`IndexWriter::update_document_versioned()`](crate::IndexWriter::update_document_versioned)
+    /// was rejected, because the live document with the same key has a newer version.
+    #[error(
+        "The live version {live_version} is newer than the expected version {expected_version:?} \
+         or the version {version} of the update"
+    )]
+    VersionConflict {
+        /// Version of the rejected document.
+        version: u64,
+        /// Version the update expected the live document to have, if any.
+        expected_version: Option<u64>,
+        /// Version of the live document.
+        live_version: u64,
+    },
+    /// A document was rejected because its text could not be tokenized entirely, see
+    /// [`TokenizationErrorPolicy`](crate::indexer::TokenizationErrorPolicy).
+    #[error("Tokenization error: {0}")]
+    Tokenization(#[from] TokenizationError),
+}
+
+/// Change of a field rejected by a [`TantivyError::IncompatibleSchemaChange`] error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchemaChangeKind {
+    /// The field is missing from the new schema.
+    Removed,
+    /// The field is at another position in the new schema. The new fields must be added after
+    /// the existing ones.
+    Moved,
+    /// The type or the options of the field are different in the new schema.
+    Modified,
+}
+
+impl fmt::Display for SchemaChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChangeKind::Removed => write!(f, "removed"),
+            SchemaChangeKind::Moved => write!(f, "moved"),
+            SchemaChangeKind::Modified => write!(f, "modified"),
+        }
+    }
+}
+
+/// Resource limited by a [`TantivyError::BudgetExceeded`] error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetKind {
+    /// Memory, in bytes.
+    Memory,
+    /// Documents, in number of documents.
+    Docs,
+    /// Time, in milliseconds.
+    Time,
+}
+
+impl BudgetKind {
+    /// Returns the error code of the [`TantivyError::BudgetExceeded`] errors of this kind.
+    pub fn error_code(self) -> &'static str {
+        match self {
+            BudgetKind::Memory => "budget_exceeded.memory",
+            BudgetKind::Docs => "budget_exceeded.docs",
+            BudgetKind::Time => "budget_exceeded.time",
+        }
+    }
+}
+
+fn budget_exceeded_message(kind: BudgetKind, limit: u64, used: u64) -> String {
+    match kind {
+        BudgetKind::Memory => format!(
+            "Aborting aggregation because memory limit was exceeded. Limit: {:?}, Current: {:?}",
+            ByteCount::from(limit),
+            ByteCount::from(used)
+        ),
+        BudgetKind::Docs => format!(
+            "Aborting search because the document limit was exceeded. Limit: {limit}, Current: \
+             {used}"
+        ),
+        BudgetKind::Time => format!(
+            "Aborting search because the time limit was exceeded. Limit: {limit}ms, Current: \
+             {used}ms"
+        ),
+    }
+}
+
+impl TantivyError {
+    /// Returns a stable code identifying the kind of the error.
+    ///
+    /// Unlike the error messages, the codes are not meant to change, so that they can be matched
+    /// by the applications, e.g. to tell the errors of the user (`aggregation.invalid_parameter`,
+    /// `invalid_argument`...) from the capacity errors (`budget_exceeded.*`) and the corruptions
+    /// of the index (`data_corruption`, `segment_corruption`).
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            TantivyError::AggregationError(err) => err.error_code(),
+            TantivyError::OpenDirectoryError(_) => "open_directory",
+            TantivyError::OpenReadError(_) => "open_read",
+            TantivyError::OpenWriteError(_) => "open_write",
+            TantivyError::IndexAlreadyExists => "index_already_exists",
+            TantivyError::LockFailure(..) => "lock_failure",
+            TantivyError::IoError(_) => "io",
+            TantivyError::DataCorruption(_) => "data_corruption",
+            TantivyError::Poisoned => "poisoned",
+            TantivyError::FieldNotFound(_) => "field_not_found",
+            TantivyError::InvalidArgument(_) => "invalid_argument",
+            TantivyError::ErrorInThread(_) => "error_in_thread",
+            TantivyError::IndexBuilderMissingArgument(_) => "index_builder_missing_argument",
+            TantivyError::SchemaError(_) => "schema",
+            TantivyError::SystemError(_) => "system",
+            TantivyError::IncompatibleIndex(_) => "incompatible_index",
+            TantivyError::InternalError(_) => "internal",
+            TantivyError::DeserializeError(_) => "deserialize",
+            TantivyError::CommitPayloadTooLarge { .. } => "commit_payload_too_large",
+            TantivyError::TooManyExpandedTerms { .. } => "too_many_expanded_terms",
+            TantivyError::ComponentNotLoaded { .. } => "component_not_loaded",
+            TantivyError::MissingColumn { .. } => "missing_column",
+            TantivyError::TooManyConcurrentSearches { .. } => "budget_exceeded.concurrent_searches",
+            TantivyError::SearchMemoryBudgetExceeded { .. } => "budget_exceeded.search_memory",
+            TantivyError::BudgetExceeded { kind, .. } => kind.error_code(),
+            TantivyError::SegmentCorruption { .. } => "segment_corruption",
+            TantivyError::MergeCancelled => "merge_cancelled",
+            TantivyError::Cancelled => "cancelled",
+            TantivyError::UniqueKeyConflict { .. } => "unique_key_conflict",
+            TantivyError::IncompatibleSchemaChange { .. } => "incompatible_schema_change",
+            TantivyError::AccessDenied { .. } => "access_denied",
+            TantivyError::CommitViolation { .. } => "commit_violation",
+            TantivyError::SegmentBusy { .. } => "segment_busy",
+            TantivyError::UnsupportedQuery(_) => "unsupported_query",
+            TantivyError::VersionConflict { .. } => "version_conflict",
+            TantivyError::Tokenization(_) => "tokenization_error",
+        }
+    }
 }
 
 impl From<io::Error> for TantivyError {
@@ -72,6 +72,12 @@ impl AliveBitSet {
         self.bitset.iter()
     }
 
+    /// Iterate over the deleted doc_ids.
+    #[inline]
+    pub fn iter_deleted(&self) -> impl Iterator<Item = DocId> + '_ {
+        self.bitset.iter_complement()
+    }
+
     /// Get underlying bitset.
     #[inline]
     pub fn bitset(&self) -> &ReadOnlyBitSet {
@@ -99,9 +105,20 @@ impl From<ReadOnlyBitSet> for AliveBitSet {
     }
 }
 
+impl From<&BitSet> for AliveBitSet {
+    fn from(bitset: &BitSet) -> AliveBitSet {
+        AliveBitSet {
+            num_alive_docs: bitset.len(),
+            bitset: ReadOnlyBitSet::from(bitset),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
+    use common::BitSet;
+
     use super::AliveBitSet;
 
     #[test]
@@ -134,6 +151,35 @@ mod tests {
         assert_eq!(alive_bitset.num_alive_docs(), 8);
     }
 
+    #[test]
+    fn test_alive_bitset_from_bitset() {
+        let mut bitset = BitSet::with_max_value(70);
+        for doc in [0, 5, 64, 69] {
+            bitset.insert(doc);
+        }
+        let alive_bitset = AliveBitSet::from(&bitset);
+        assert_eq!(alive_bitset.num_alive_docs(), 4);
+        assert_eq!(
+            alive_bitset.iter_alive().collect::<Vec<_>>(),
+            vec![0, 5, 64, 69]
+        );
+        assert_eq!(alive_bitset.bitset().max_value(), 70);
+    }
+
+    #[test]
+    fn test_alive_bitset_iter_deleted() {
+        let alive_bitset = AliveBitSet::for_test_from_deleted_docs(&[1, 9], 10);
+        assert_eq!(alive_bitset.iter_deleted().collect::<Vec<_>>(), vec![1, 9]);
+        // The padding of the last bucket is not reported as deleted.
+        let alive_bitset = AliveBitSet::for_test_from_deleted_docs(&[0, 63, 64, 68], 70);
+        assert_eq!(
+            alive_bitset.iter_deleted().collect::<Vec<_>>(),
+            vec![0, 63, 64, 68]
+        );
+        let alive_bitset = AliveBitSet::for_test_from_deleted_docs(&[], 70);
+        assert_eq!(alive_bitset.iter_deleted().next(), None);
+    }
+
     #[test]
     fn test_alive_bitset_iter_minimal() {
         let alive_bitset = AliveBitSet::for_test_from_deleted_docs(&[7], 8);
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use columnar::ColumnType;
+use common::TerminatingWrite;
+use serde::{Deserialize, Serialize};
+
+use crate::aggregation::f64_from_fastfield_u64;
+use crate::aggregation::metric::{IntermediateStats, PercentilesCollector};
+use crate::directory::error::OpenReadError;
+use crate::fastfield::FastFieldReaders;
+use crate::index::{Segment, SegmentComponent};
+
+/// Column types the statistics are computed for.
+const NUMERICAL_COLUMN_TYPES: [ColumnType; 4] = [
+    ColumnType::U64,
+    ColumnType::I64,
+    ColumnType::F64,
+    ColumnType::DateTime,
+];
+
+/// Statistics over the values of a fast field in a segment, computed when the segment is written.
+///
+/// They are computed for the fields listed in
+/// [`IndexSettings::column_stats_fields`](crate::IndexSettings::column_stats_fields), over all of
+/// the documents of the segment, deleted documents included. See
+/// [`SegmentReader::column_stats()`](crate::SegmentReader::column_stats) and
+/// [`Searcher::column_stats()`](crate::Searcher::column_stats).
+///
+/// The values are read as `f64`. The values of a date field are timestamps in nanoseconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ColumnStats {
+    stats: IntermediateStats,
+    percentiles: PercentilesCollector,
+}
+
+impl ColumnStats {
+    /// Computes the statistics of the values of `field_name`.
+    ///
+    /// The values are collected in the order of the documents, as the aggregations do, so that
+    /// the statistics are exactly the ones a match-all aggregation computes on the segment.
+    fn compute(fast_fields: &FastFieldReaders, field_name: &str) -> crate::Result<ColumnStats> {
+        let mut column_stats = ColumnStats::default();
+        let Some((column, column_type)) =
+            fast_fields.u64_lenient_for_type(Some(&NUMERICAL_COLUMN_TYPES), field_name)?
+        else {
+            return Ok(column_stats);
+        };
+        column_stats.stats.is_date = column_type == ColumnType::DateTime;
+        for doc in 0..column.num_docs() {
+            for value in column.values_for_doc(doc) {
+                let value = f64_from_fastfield_u64(value, &column_type);
+                column_stats.stats.collect(value);
+                column_stats.percentiles.collect(value);
+            }
+        }
+        Ok(column_stats)
+    }
+
+    /// Returns the number of values.
+    pub fn count(&self) -> u64 {
+        self.stats.count
+    }
+
+    /// Returns the sum of the values.
+    pub fn sum(&self) -> f64 {
+        self.stats.sum
+    }
+
+    /// Returns the smallest value, or `None` if there are no values.
+    pub fn min(&self) -> Option<f64> {
+        (self.count() > 0).then_some(self.stats.min)
+    }
+
+    /// Returns the largest value, or `None` if there are no values.
+    pub fn max(&self) -> Option<f64> {
+        (self.count() > 0).then_some(self.stats.max)
+    }
+
+    /// Returns the average of the values, or `None` if there are no values.
+    pub fn avg(&self) -> Option<f64> {
+        (self.count() > 0).then(|| self.sum() / self.count() as f64)
+    }
+
+    /// Returns an estimate of the `percent` percentile of the values, within a relative error of
+    /// 1%, or `None` if there are no values or `percent` is not between 0 and 100.
+    ///
+    /// The estimate is the one of the
+    /// [`percentiles`](crate::aggregation::metric::PercentilesAggregationReq) aggregation with
+    /// the default compression.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        self.percentiles.quantile(percent / 100.0)
+    }
+
+    /// Merges the statistics of another segment into these ones.
+    pub fn merge(&mut self, other: ColumnStats) -> crate::Result<()> {
+        self.stats.merge_fruits(other.stats);
+        self.percentiles.merge_fruits(other.percentiles)
+    }
+
+    pub(crate) fn intermediate_stats(&self) -> &IntermediateStats {
+        &self.stats
+    }
+
+    pub(crate) fn percentiles(&self) -> &PercentilesCollector {
+        &self.percentiles
+    }
+}
+
+/// Column statistics of the fields of a segment, stored in the
+/// [`SegmentComponent::ColumnStats`] file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SegmentColumnStats {
+    fields: BTreeMap<String, ColumnStats>,
+}
+
+impl SegmentColumnStats {
+    /// Computes the statistics of the `field_names` from the fast fields written in `segment`,
+    /// and writes them to the column statistics file of the segment.
+    pub(crate) fn write(segment: &mut Segment, field_names: &[String]) -> crate::Result<()> {
+        let fast_fields_data = segment.open_read(SegmentComponent::FastFields)?;
+        let fast_fields = FastFieldReaders::open(fast_fields_data, segment.schema())?;
+        let mut segment_column_stats = SegmentColumnStats::default();
+        for field_name in field_names {
+            let column_stats = ColumnStats::compute(&fast_fields, field_name)?;
+            segment_column_stats
+                .fields
+                .insert(field_name.clone(), column_stats);
+        }
+        let mut write = segment.open_write(SegmentComponent::ColumnStats)?;
+        serde_json::to_writer(&mut write, &segment_column_stats)?;
+        write.flush()?;
+        write.terminate()?;
+        Ok(())
+    }
+
+    /// Reads the column statistics of `segment`, or returns `None` if they were not computed
+    /// when the segment was written.
+    pub(crate) fn open(segment: &Segment) -> crate::Result<Option<SegmentColumnStats>> {
+        let file = match segment.open_read(SegmentComponent::ColumnStats) {
+            Ok(file) => file,
+            Err(OpenReadError::FileDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let bytes = file.read_bytes()?;
+        let segment_column_stats = serde_json::from_slice(bytes.as_slice())?;
+        Ok(Some(segment_column_stats))
+    }
+
+    pub(crate) fn get(&self, field_name: &str) -> Option<&ColumnStats> {
+        self.fields.get(field_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, FAST, INDEXED};
+    use crate::{Index, IndexSettings, IndexWriter, Term};
+
+    fn create_index(column_stats_fields: &[&str]) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let value = schema_builder.add_i64_field("value", FAST | INDEXED);
+        let other = schema_builder.add_u64_field("other", FAST);
+        let settings = IndexSettings {
+            column_stats_fields: column_stats_fields
+                .iter()
+                .map(|field_name| field_name.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        let index = Index::builder()
+            .schema(schema_builder.build())
+            .settings(settings)
+            .create_in_ram()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment in 0..3i64 {
+            for doc in 0..100i64 {
+                let value_i64 = segment * 100 + doc - 50;
+                index_writer.add_document(doc!(value => value_i64, other => 1u64))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn test_column_stats() -> crate::Result<()> {
+        let index = create_index(&["value"])?;
+        let value = index.schema().get_field("value")?;
+        let other = index.schema().get_field("other")?;
+        let searcher = index.reader()?.searcher();
+        for segment_reader in searcher.segment_readers() {
+            let segment_stats = segment_reader.column_stats(value).unwrap();
+            assert_eq!(segment_stats.count(), 100);
+            assert_eq!(
+                segment_stats.max().unwrap() - segment_stats.min().unwrap(),
+                99.0
+            );
+            assert!(segment_reader.column_stats(other).is_none());
+        }
+
+        let column_stats = searcher.column_stats(value)?.unwrap();
+        assert_eq!(column_stats.count(), 300);
+        assert_eq!(column_stats.min(), Some(-50.0));
+        assert_eq!(column_stats.max(), Some(249.0));
+        assert_eq!(column_stats.sum(), (-50..250).sum::<i64>() as f64);
+        assert_eq!(column_stats.avg(), Some(99.5));
+        let median = column_stats.percentile(50.0).unwrap();
+        assert!((median - 99.0).abs() <= 1.0, "{median}");
+        assert!(searcher.column_stats(other)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_stats_disabled() -> crate::Result<()> {
+        let index = create_index(&[])?;
+        let value = index.schema().get_field("value")?;
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers()[0].column_stats(value).is_none());
+        assert!(searcher.column_stats(value)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_stats_with_deletes_and_merge() -> crate::Result<()> {
+        let index = create_index(&["value"])?;
+        let value = index.schema().get_field("value")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.delete_term(Term::from_field_i64(value, 249))?;
+        index_writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        // The segment stats still include the deleted value, so they cannot be merged.
+        assert!(searcher.column_stats(value)?.is_none());
+        let segment_with_deletes = searcher
+            .segment_readers()
+            .iter()
+            .find(|segment_reader| segment_reader.has_deletes())
+            .unwrap();
+        assert_eq!(
+            segment_with_deletes.column_stats(value).unwrap().max(),
+            Some(249.0)
+        );
+
+        // The merged segment has its stats computed again, without the deleted document.
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        let column_stats = searcher.column_stats(value)?.unwrap();
+        assert_eq!(column_stats.count(), 299);
+        assert_eq!(column_stats.min(), Some(-50.0));
+        assert_eq!(column_stats.max(), Some(248.0));
+        Ok(())
+    }
+}
@@ -19,20 +19,26 @@
 //!
 //! Read access performance is comparable to that of an array lookup.
 
-pub use columnar::Column;
 use columnar::MonotonicallyMappableToU64;
+pub use columnar::{Column, Granularity};
 
 pub use self::alive_bitset::{intersect_alive_bitsets, write_alive_bitset, AliveBitSet};
+pub use self::column_stats::ColumnStats;
+pub(crate) use self::column_stats::SegmentColumnStats;
 pub use self::error::{FastFieldNotAvailableError, Result};
 pub use self::facet_reader::FacetReader;
+pub use self::multi_value_mode::MultiValueMode;
 pub use self::readers::FastFieldReaders;
+pub(crate) use self::writer::token_count_column_name;
 pub use self::writer::FastFieldsWriter;
 use crate::schema::Type;
 use crate::DateTime;
 
 mod alive_bitset;
+mod column_stats;
 mod error;
 mod facet_reader;
+mod multi_value_mode;
 mod readers;
 mod writer;
 
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+/// Defines how the values of a document are combined on a multivalued fast field.
+///
+/// Every mode but [`MultiValueMode::All`] reduces the values of a document to a single value.
+/// A document without values has no reduced value.
+///
+/// # JSON Format
+/// ```json
+/// "min" | "max" | "sum" | "avg" | "all"
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiValueMode {
+    /// The smallest value of the document.
+    Min,
+    /// The largest value of the document.
+    Max,
+    /// The sum of the values of the document.
+    Sum,
+    /// The average of the values of the document.
+    ///
+    /// The average is computed on `f64`, including on integer columns.
+    Avg,
+    /// Every value of the document is used on its own, without reduction.
+    ///
+    /// This is the behavior of aggregations when no mode is set. It is not a valid mode to order
+    /// documents by.
+    #[default]
+    All,
+}
+
+impl MultiValueMode {
+    /// Returns true if the mode reduces the values of a document to a single value.
+    pub fn is_reducer(self) -> bool {
+        self != MultiValueMode::All
+    }
+
+    /// Reduces the values of a document, returning `None` if the document has no value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called on [`MultiValueMode::All`], which does not reduce values.
+    pub(crate) fn reduce(self, values: impl Iterator<Item = f64>) -> Option<f64> {
+        let mut num_vals = 0u64;
+        let mut reduced: Option<f64> = None;
+        for val in values {
+            num_vals += 1;
+            reduced = Some(match (self, reduced) {
+                (MultiValueMode::All, _) => panic!("MultiValueMode::All does not reduce values"),
+                (_, None) => val,
+                (MultiValueMode::Min, Some(acc)) => acc.min(val),
+                (MultiValueMode::Max, Some(acc)) => acc.max(val),
+                (MultiValueMode::Sum | MultiValueMode::Avg, Some(acc)) => acc + val,
+            });
+        }
+        if self == MultiValueMode::Avg {
+            reduced.map(|sum| sum / num_vals as f64)
+        } else {
+            reduced
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiValueMode;
+
+    #[test]
+    fn test_multi_value_mode_reduce() {
+        let vals = [3.0, -1.0, 4.0, 2.0];
+        let reduce = |mode: MultiValueMode| mode.reduce(vals.iter().copied());
+        assert_eq!(reduce(MultiValueMode::Min), Some(-1.0));
+        assert_eq!(reduce(MultiValueMode::Max), Some(4.0));
+        assert_eq!(reduce(MultiValueMode::Sum), Some(8.0));
+        assert_eq!(reduce(MultiValueMode::Avg), Some(2.0));
+        assert_eq!(MultiValueMode::Avg.reduce(std::iter::empty()), None);
+        assert_eq!(MultiValueMode::Min.reduce(std::iter::once(7.0)), Some(7.0));
+    }
+
+    #[test]
+    fn test_multi_value_mode_serde() {
+        let mode: MultiValueMode = serde_json::from_str("\"avg\"").unwrap();
+        assert_eq!(mode, MultiValueMode::Avg);
+        assert_eq!(
+            serde_json::to_string(&MultiValueMode::All).unwrap(),
+            "\"all\""
+        );
+        assert!(serde_json::from_str::<MultiValueMode>("\"median\"").is_err());
+    }
+}
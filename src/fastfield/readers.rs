@@ -3,16 +3,17 @@ use std::net::Ipv6Addr;
 use std::sync::Arc;
 
 use columnar::{
-    BytesColumn, Column, ColumnType, ColumnValues, ColumnarReader, DynamicColumn,
-    DynamicColumnHandle, HasAssociatedColumnType, StrColumn,
+    BytesColumn, Column, ColumnIndex, ColumnType, ColumnValues, ColumnarReader,
+    ConstantColumnValues, DynamicColumn, DynamicColumnHandle, HasAssociatedColumnType, StrColumn,
 };
 use common::ByteCount;
 
 use crate::core::json_utils::encode_column_name;
 use crate::directory::FileSlice;
-use crate::schema::{Field, FieldEntry, FieldType, Schema};
+use crate::error::DataCorruption;
+use crate::schema::{value_type_to_column_type, Field, FieldEntry, FieldType, Schema};
 use crate::space_usage::{FieldUsage, PerFieldSpaceUsage};
-use crate::TantivyError;
+use crate::{DocId, TantivyError};
 
 /// Provides access to all of the BitpackedFastFieldReader.
 ///
@@ -22,15 +23,40 @@ use crate::TantivyError;
 pub struct FastFieldReaders {
     columnar: Arc<ColumnarReader>,
     schema: Schema,
+    /// The `max_doc` of the segment, if known.
+    max_doc: Option<DocId>,
 }
 
 impl FastFieldReaders {
     pub(crate) fn open(fast_field_file: FileSlice, schema: Schema) -> io::Result<FastFieldReaders> {
         let columnar = Arc::new(ColumnarReader::open(fast_field_file)?);
-        Ok(FastFieldReaders { columnar, schema })
+        Ok(FastFieldReaders {
+            columnar,
+            schema,
+            max_doc: None,
+        })
+    }
+
+    /// Makes the accessors return an error, instead of columns that would panic when accessed,
+    /// if the columns have fewer docs than the segment.
+    pub(crate) fn with_max_doc(mut self, max_doc: DocId) -> FastFieldReaders {
+        self.max_doc = Some(max_doc);
+        self
+    }
+
+    fn check_num_docs(&self) -> crate::Result<()> {
+        let num_docs = self.columnar.num_docs();
+        match self.max_doc {
+            Some(max_doc) if num_docs < max_doc => Err(DataCorruption::comment_only(format!(
+                "the fast fields have {num_docs} docs, but the segment has {max_doc} docs"
+            ))
+            .into()),
+            _ => Ok(()),
+        }
     }
 
     fn resolve_field(&self, column_name: &str) -> crate::Result<Option<String>> {
+        self.check_num_docs()?;
         let default_field_opt: Option<Field> = if cfg!(feature = "quickwit") {
             self.schema.get_field("_dynamic").ok()
         } else {
@@ -42,7 +68,10 @@ impl FastFieldReaders {
     pub(crate) fn space_usage(&self, schema: &Schema) -> io::Result<PerFieldSpaceUsage> {
         let mut per_field_usages: Vec<FieldUsage> = Default::default();
         for (field, field_entry) in schema.fields() {
-            let column_handles = self.columnar.read_columns(field_entry.name())?;
+            let mut column_handles = self.columnar.read_columns(field_entry.name())?;
+            if field_entry.field_type().is_json() {
+                column_handles.extend(self.columnar.read_subpath_columns(field_entry.name())?);
+            }
             let num_bytes: ByteCount = column_handles
                 .iter()
                 .map(|column_handle| column_handle.num_bytes())
@@ -51,7 +80,6 @@ impl FastFieldReaders {
             field_usage.add_field_idx(0, num_bytes);
             per_field_usages.push(field_usage);
         }
-        // TODO fix space usage for JSON fields.
         Ok(PerFieldSpaceUsage::new(per_field_usages))
     }
 
@@ -59,6 +87,51 @@ impl FastFieldReaders {
         self.columnar.as_ref()
     }
 
+    /// Returns the column type of `resolved_field_name`, if it is a fast field of the schema
+    /// without any column in the segment.
+    ///
+    /// The segments always have a column for the fast fields of the schema they were written
+    /// with, so these fields were added to the schema after the segment was written, see
+    /// [`Index::update_schema()`](crate::Index::update_schema). They are read as columns without
+    /// values.
+    fn added_column_type(&self, resolved_field_name: &str) -> crate::Result<Option<ColumnType>> {
+        let Ok(field) = self.schema.get_field(resolved_field_name) else {
+            return Ok(None);
+        };
+        let field_entry = self.schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            return Ok(None);
+        }
+        let Some(column_type) = value_type_to_column_type(field_entry.field_type().value_type())
+        else {
+            return Ok(None);
+        };
+        if !self.columnar.read_columns(resolved_field_name)?.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(column_type))
+    }
+
+    /// Returns a column without values, with a document per document of the segment.
+    fn empty_column<T>(&self, default_value: T) -> Column<T>
+    where T: Copy + PartialOrd + Send + Sync + std::fmt::Debug + 'static {
+        Column {
+            index: ColumnIndex::Empty {
+                num_docs: self.columnar.num_docs(),
+            },
+            values: Arc::new(ConstantColumnValues::new(default_value, 0)),
+        }
+    }
+
+    /// Returns true if `field_name` is a fast field added to the schema after the segment was
+    /// written, which has no value in the segment.
+    pub(crate) fn is_added_after_segment(&self, field_name: &str) -> crate::Result<bool> {
+        let Some(resolved_field_name) = self.resolve_field(field_name)? else {
+            return Ok(false);
+        };
+        Ok(self.added_column_type(&resolved_field_name)?.is_some())
+    }
+
     /// Transforms a user-supplied fast field name into a column name.
     ///
     /// A user-supplied fast field name is not necessarily a schema field name
@@ -123,6 +196,12 @@ impl FastFieldReaders {
         let Some(dynamic_column_handle) =
             self.dynamic_column_handle(field_name, T::column_type())?
         else {
+            let Some(resolved_field_name) = self.resolve_field(field_name)? else {
+                return Ok(None);
+            };
+            if self.added_column_type(&resolved_field_name)? == Some(T::column_type()) {
+                return Ok(Some(self.empty_column(T::default_value())));
+            }
             return Ok(None);
         };
         let dynamic_column = dynamic_column_handle.open()?;
@@ -319,7 +398,7 @@ impl FastFieldReaders {
                 return Ok(Some((col_u64, col.column_type())));
             }
         }
-        Ok(None)
+        self.added_u64_lenient_column(type_white_list_opt, &resolved_field_name)
     }
 
     /// Returns the all `u64` column used to represent any `u64`-mapped typed (String/Bytes term
@@ -347,9 +426,41 @@ impl FastFieldReaders {
                 columns_and_types.push((col_u64, col.column_type()));
             }
         }
+        columns_and_types
+            .extend(self.added_u64_lenient_column(type_white_list_opt, &resolved_field_name)?);
         Ok(columns_and_types)
     }
 
+    /// Returns the `u64` column without values of a field added to the schema after the segment
+    /// was written, if its type is numerical and in the white list.
+    fn added_u64_lenient_column(
+        &self,
+        type_white_list_opt: Option<&[ColumnType]>,
+        resolved_field_name: &str,
+    ) -> crate::Result<Option<(Column<u64>, ColumnType)>> {
+        let Some(column_type) = self.added_column_type(resolved_field_name)? else {
+            return Ok(None);
+        };
+        if let Some(type_white_list) = type_white_list_opt {
+            if !type_white_list.contains(&column_type) {
+                return Ok(None);
+            }
+        }
+        // The term ordinals of the `str` and `bytes` columns and the compact values of the
+        // `ip` columns cannot be read without their column.
+        if !matches!(
+            column_type,
+            ColumnType::U64
+                | ColumnType::I64
+                | ColumnType::F64
+                | ColumnType::Bool
+                | ColumnType::DateTime
+        ) {
+            return Ok(None);
+        }
+        Ok(Some((self.empty_column(0u64), column_type)))
+    }
+
     /// Returns the `u64` column used to represent any `u64`-mapped typed (i64, u64, f64, DateTime).
     ///
     /// Returns Ok(None) for empty columns
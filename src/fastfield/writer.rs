@@ -1,11 +1,11 @@
 use std::io;
 
-use columnar::{ColumnarWriter, NumericalValue};
+use columnar::{ColumnType, ColumnarWriter, NumericalValue};
 use common::{DateTimePrecision, JsonPathWriter};
 use tokenizer_api::Token;
 
 use crate::schema::document::{Document, ReferenceValue, ReferenceValueLeaf, Value};
-use crate::schema::{value_type_to_column_type, Field, FieldType, Schema, Type};
+use crate::schema::{value_type_to_column_type, Field, FieldType, GeoPoint, Schema, Type};
 use crate::tokenizer::{TextAnalyzer, TokenizerManager};
 use crate::{DocId, TantivyError};
 
@@ -13,6 +13,15 @@ use crate::{DocId, TantivyError};
 /// This is mostly to guard us from a stack overflow triggered by malicious input.
 const JSON_DEPTH_LIMIT: usize = 20;
 
+/// Returns the name of the hidden column holding the exact number of tokens of the documents for
+/// the text field `field_name`, if the field is indexed with
+/// [`TextFieldIndexing::set_exact_token_count()`](crate::schema::TextFieldIndexing::set_exact_token_count).
+///
+/// Field names cannot start with `-`, so that the column does not clash with a fast field.
+pub(crate) fn token_count_column_name(field_name: &str) -> String {
+    format!("-token_count.{field_name}")
+}
+
 /// The `FastFieldsWriter` groups all of the fast field writers.
 pub struct FastFieldsWriter {
     columnar_writer: ColumnarWriter,
@@ -20,6 +29,9 @@ pub struct FastFieldsWriter {
     per_field_tokenizer: Vec<Option<TextAnalyzer>>,
     date_precisions: Vec<DateTimePrecision>,
     expand_dots: Vec<bool>,
+    // Geo points are recorded as their code, instead of as objects.
+    geo_point_fields: Vec<bool>,
+    token_count_column_names: Vec<Option<String>>,
     num_docs: DocId,
     // Buffer that we recycle to avoid allocation.
     json_path_buffer: JsonPathWriter,
@@ -45,9 +57,21 @@ impl FastFieldsWriter {
                 .take(schema.num_fields())
                 .collect();
         let mut expand_dots = vec![false; schema.num_fields()];
+        let mut geo_point_fields = vec![false; schema.num_fields()];
         let mut per_field_tokenizer: Vec<Option<TextAnalyzer>> = vec![None; schema.num_fields()];
+        let mut token_count_column_names: Vec<Option<String>> = vec![None; schema.num_fields()];
         // TODO see other types
         for (field_id, field_entry) in schema.fields() {
+            if let FieldType::Str(text_options) = field_entry.field_type() {
+                if text_options
+                    .get_indexing_options()
+                    .is_some_and(|indexing| indexing.exact_token_count())
+                {
+                    let column_name = token_count_column_name(field_entry.name());
+                    columnar_writer.record_column_type(&column_name, ColumnType::U64, false);
+                    token_count_column_names[field_id.field_id() as usize] = Some(column_name);
+                }
+            }
             if !field_entry.field_type().is_fast() {
                 continue;
             }
@@ -56,6 +80,8 @@ impl FastFieldsWriter {
             if let FieldType::Date(date_options) = field_entry.field_type() {
                 date_precisions[field_id.field_id() as usize] = date_options.get_precision();
             }
+            geo_point_fields[field_id.field_id() as usize] =
+                field_entry.field_type().is_geo_point();
             if let FieldType::JsonObject(json_object_options) = field_entry.field_type() {
                 if let Some(tokenizer_name) = json_object_options.get_fast_field_tokenizer_name() {
                     let text_analyzer = tokenizer_manager.get(tokenizer_name).ok_or_else(|| {
@@ -96,6 +122,8 @@ impl FastFieldsWriter {
             num_docs: 0u32,
             date_precisions,
             expand_dots,
+            geo_point_fields,
+            token_count_column_names,
             json_path_buffer: JsonPathWriter::default(),
         })
     }
@@ -128,6 +156,26 @@ impl FastFieldsWriter {
             Some(name) => name,
         };
 
+        if self.geo_point_fields[field.field_id() as usize] {
+            if let Some(values) = value.as_array() {
+                for value in values {
+                    self.add_doc_value(doc_id, field, value)?;
+                }
+                return Ok(());
+            }
+            let geo_point = GeoPoint::from_value(value).ok_or_else(|| {
+                TantivyError::SchemaError(format!(
+                    "Expected a geo point object with a valid lat and lon for field {field_name:?}"
+                ))
+            })?;
+            self.columnar_writer.record_numerical(
+                doc_id,
+                field_name,
+                NumericalValue::from(geo_point.to_u64()),
+            );
+            return Ok(());
+        }
+
         match value.as_value() {
             ReferenceValue::Leaf(leaf) => match leaf {
                 ReferenceValueLeaf::Null => {}
@@ -220,6 +268,18 @@ impl FastFieldsWriter {
         Ok(())
     }
 
+    /// Records the number of tokens of the text field `field` of a document, if the field keeps
+    /// the exact token count of its documents.
+    pub(crate) fn record_token_count(&mut self, doc_id: DocId, field: Field, num_tokens: u32) {
+        if let Some(column_name) = &self.token_count_column_names[field.field_id() as usize] {
+            self.columnar_writer.record_numerical(
+                doc_id,
+                column_name,
+                NumericalValue::from(num_tokens as u64),
+            );
+        }
+    }
+
     /// Serializes all of the `FastFieldWriter`s by pushing them in
     /// order to the fast field serializer.
     pub fn serialize(mut self, wrt: &mut dyn io::Write) -> io::Result<()> {
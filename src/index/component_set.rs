@@ -0,0 +1,318 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+/// Set of the components of a segment loaded when a [`SegmentReader`](crate::SegmentReader) is
+/// opened.
+///
+/// See [`IndexReaderBuilder::load_components`](crate::IndexReaderBuilder::load_components).
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComponentSet(u8);
+
+impl ComponentSet {
+    /// No component.
+    pub const NONE: ComponentSet = ComponentSet(0);
+    /// The term dictionaries, postings and positions, required by queries on the inverted index.
+    pub const POSTINGS: ComponentSet = ComponentSet(1);
+    /// The fast fields, required by aggregations, sorting and fast field queries.
+    pub const FAST_FIELDS: ComponentSet = ComponentSet(1 << 1);
+    /// The field norms, required by scoring.
+    pub const FIELD_NORMS: ComponentSet = ComponentSet(1 << 2);
+    /// The doc store, required to retrieve stored documents.
+    pub const STORE: ComponentSet = ComponentSet(1 << 3);
+    /// All of the components.
+    pub const ALL: ComponentSet = ComponentSet(0b1111);
+
+    /// Returns true if all of the components of `other` are in this set.
+    pub const fn contains(self, other: ComponentSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the components of either set.
+    pub const fn union(self, other: ComponentSet) -> ComponentSet {
+        ComponentSet(self.0 | other.0)
+    }
+
+    /// Returns true if the set has no component.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Default for ComponentSet {
+    fn default() -> ComponentSet {
+        ComponentSet::ALL
+    }
+}
+
+impl BitOr for ComponentSet {
+    type Output = ComponentSet;
+
+    fn bitor(self, other: ComponentSet) -> ComponentSet {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for ComponentSet {
+    fn bitor_assign(&mut self, other: ComponentSet) {
+        *self = self.union(other);
+    }
+}
+
+impl fmt::Debug for ComponentSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (ComponentSet::POSTINGS, "POSTINGS"),
+            (ComponentSet::FAST_FIELDS, "FAST_FIELDS"),
+            (ComponentSet::FIELD_NORMS, "FIELD_NORMS"),
+            (ComponentSet::STORE, "STORE"),
+        ];
+        f.debug_set()
+            .entries(
+                names
+                    .iter()
+                    .filter(|(component, _)| self.contains(*component))
+                    .map(|(_, name)| name),
+            )
+            .finish()
+    }
+}
+
+/// Defines how the components of a segment are loaded by a
+/// [`SegmentReader`](crate::SegmentReader).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ComponentLoading {
+    /// Components loaded when the reader is opened.
+    pub components: ComponentSet,
+    /// If true, accessing a component that was not loaded when the reader was opened returns an
+    /// error. Otherwise, the component is loaded on first access.
+    pub strict: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::ComponentSet;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, TopDocs};
+    use crate::directory::error::{DeleteError, OpenReadError, OpenWriteError};
+    use crate::directory::{
+        Directory, FileHandle, RamDirectory, WatchCallback, WatchHandle, WritePtr,
+    };
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, STORED, TEXT};
+    use crate::{
+        Index, IndexReader, IndexSettings, IndexWriter, Order, ReloadPolicy, TantivyDocument,
+        TantivyError, Term,
+    };
+
+    /// Directory recording the paths of the files opened for read.
+    #[derive(Clone, Debug, Default)]
+    struct RecordingDirectory {
+        directory: RamDirectory,
+        opened_paths: Arc<Mutex<Vec<PathBuf>>>,
+    }
+
+    impl RecordingDirectory {
+        fn take_opened_extensions(&self) -> Vec<String> {
+            let opened_paths = std::mem::take(&mut *self.opened_paths.lock().unwrap());
+            opened_paths
+                .iter()
+                .filter_map(|path| path.extension())
+                .map(|extension| extension.to_string_lossy().to_string())
+                .collect()
+        }
+    }
+
+    impl Directory for RecordingDirectory {
+        fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+            self.opened_paths.lock().unwrap().push(path.to_path_buf());
+            self.directory.get_file_handle(path)
+        }
+
+        fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+            self.directory.delete(path)
+        }
+
+        fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+            self.directory.exists(path)
+        }
+
+        fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+            self.directory.open_write(path)
+        }
+
+        fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+            self.directory.atomic_read(path)
+        }
+
+        fn atomic_write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+            self.directory.atomic_write(path, data)
+        }
+
+        fn sync_directory(&self) -> std::io::Result<()> {
+            self.directory.sync_directory()
+        }
+
+        fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+            self.directory.watch(watch_callback)
+        }
+    }
+
+    fn test_index() -> crate::Result<(Index, RecordingDirectory)> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT | STORED);
+        let value = schema_builder.add_u64_field("value", FAST);
+        let directory = RecordingDirectory::default();
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            IndexSettings::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for i in 0..10u64 {
+            index_writer.add_document(doc!(text => format!("hello doc{i}"), value => i))?;
+            if i == 4 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        index_writer.wait_merging_threads()?;
+        Ok((index, directory))
+    }
+
+    fn open_reader(
+        index: &Index,
+        components: ComponentSet,
+        strict: bool,
+    ) -> crate::Result<IndexReader> {
+        index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .load_components(components)
+            .strict_component_loading(strict)
+            .try_into()
+    }
+
+    fn max_value(reader: &IndexReader) -> crate::Result<serde_json::Value> {
+        let agg_req: Aggregations =
+            serde_json::from_value(json!({ "max_value": { "max": { "field": "value" } } }))
+                .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+        let agg_res = reader.searcher().search(&AllQuery, &collector)?;
+        Ok(serde_json::to_value(agg_res)?)
+    }
+
+    #[test]
+    fn test_component_set() {
+        let components = ComponentSet::FAST_FIELDS | ComponentSet::STORE;
+        assert!(components.contains(ComponentSet::FAST_FIELDS));
+        assert!(!components.contains(ComponentSet::POSTINGS));
+        assert!(ComponentSet::ALL.contains(components));
+        assert!(ComponentSet::NONE.is_empty());
+        assert_eq!(ComponentSet::default(), ComponentSet::ALL);
+        assert_eq!(format!("{components:?}"), r#"{"FAST_FIELDS", "STORE"}"#);
+    }
+
+    #[test]
+    fn test_load_fast_fields_only() -> crate::Result<()> {
+        let (index, directory) = test_index()?;
+        directory.take_opened_extensions();
+        let reader = open_reader(&index, ComponentSet::FAST_FIELDS, false)?;
+        assert_eq!(
+            max_value(&reader)?,
+            json!({ "max_value": { "value": 9.0 } })
+        );
+        let opened_extensions = directory.take_opened_extensions();
+        assert!(opened_extensions
+            .iter()
+            .all(|extension| extension == "fast"));
+        assert_eq!(opened_extensions.len(), 2);
+
+        // The other components are loaded on first access.
+        let searcher = reader.searcher();
+        let term = Term::from_field_text(index.schema().get_field("text")?, "doc3");
+        let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(1))?;
+        assert_eq!(top_docs.len(), 1);
+        let doc: TantivyDocument = searcher.doc(top_docs[0].1)?;
+        assert_eq!(
+            doc.get_first(index.schema().get_field("text")?)
+                .and_then(|value| crate::schema::Value::as_str(&value)),
+            Some("hello doc3")
+        );
+        let opened_extensions = directory.take_opened_extensions();
+        for extension in ["idx", "term", "fieldnorm", "store"] {
+            assert!(opened_extensions.iter().any(|opened| opened == extension));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_component_loading() -> crate::Result<()> {
+        let (index, _directory) = test_index()?;
+        let reader = open_reader(&index, ComponentSet::FAST_FIELDS, true)?;
+        assert_eq!(
+            max_value(&reader)?,
+            json!({ "max_value": { "value": 9.0 } })
+        );
+        let searcher = reader.searcher();
+        let term = Term::from_field_text(index.schema().get_field("text")?, "hello");
+        let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+        assert!(matches!(
+            searcher.search(&query, &Count),
+            Err(TantivyError::ComponentNotLoaded { component, .. })
+                if component == ComponentSet::POSTINGS
+        ));
+        let doc_res = searcher.doc::<TantivyDocument>(crate::DocAddress::new(0, 0));
+        assert!(matches!(
+            doc_res,
+            Err(TantivyError::ComponentNotLoaded { component, .. })
+                if component == ComponentSet::STORE
+        ));
+
+        // Loading the components needed by a scoring query makes it work in strict mode.
+        let reader = open_reader(
+            &index,
+            ComponentSet::POSTINGS | ComponentSet::FIELD_NORMS,
+            true,
+        )?;
+        let term = Term::from_field_text(index.schema().get_field("text")?, "hello");
+        let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+        let searcher = reader.searcher();
+        assert_eq!(searcher.search(&query, &TopDocs::with_limit(20))?.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_component_loading_returns_errors() -> crate::Result<()> {
+        let (index, _directory) = test_index()?;
+        let reader = open_reader(&index, ComponentSet::POSTINGS, true)?;
+        assert!(matches!(
+            max_value(&reader),
+            Err(TantivyError::ComponentNotLoaded { component, .. })
+                if component == ComponentSet::FAST_FIELDS
+        ));
+        let searcher = reader.searcher();
+        let collector = TopDocs::with_limit(3).order_by_fast_field::<u64>("value", Order::Desc);
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(TantivyError::ComponentNotLoaded { component, .. })
+                if component == ComponentSet::FAST_FIELDS
+        ));
+        let term = Term::from_field_text(index.schema().get_field("text")?, "hello");
+        let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+        assert!(matches!(
+            searcher.search(&query, &TopDocs::with_limit(20)),
+            Err(TantivyError::ComponentNotLoaded { component, .. })
+                if component == ComponentSet::FIELD_NORMS
+        ));
+        Ok(())
+    }
+}
@@ -5,9 +5,13 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::thread::available_parallelism;
 
+use common::ByteCount;
+
 use super::segment::Segment;
 use super::segment_reader::merge_field_meta_data;
-use super::{FieldMetadata, IndexSettings};
+use super::segment_verify::{self, IndexVerifyReport, SegmentRepair, SegmentVerifyOptions};
+use super::snapshot::{self, Snapshot, SnapshotListing};
+use super::{schema_evolution, FieldMetadata, IndexSettings};
 use crate::core::{Executor, META_FILEPATH};
 use crate::directory::error::OpenReadError;
 #[cfg(feature = "mmap")]
@@ -15,16 +19,19 @@ use crate::directory::MmapDirectory;
 use crate::directory::{Directory, ManagedDirectory, RamDirectory, INDEX_WRITER_LOCK};
 use crate::error::{DataCorruption, TantivyError};
 use crate::index::{IndexMeta, SegmentId, SegmentMeta, SegmentMetaInventory};
+use crate::indexer::commit_payload::{load_commit_payloads, parse_commit_payload};
+use crate::indexer::commit_points::load_commit_points;
 use crate::indexer::index_writer::{
     IndexWriterOptions, MAX_NUM_THREAD, MEMORY_BUDGET_NUM_BYTES_MIN,
 };
 use crate::indexer::segment_updater::save_metas;
-use crate::indexer::{IndexWriter, SingleSegmentIndexWriter};
-use crate::reader::{IndexReader, IndexReaderBuilder};
+use crate::indexer::{CommitPayload, IndexWriter, SingleSegmentIndexWriter};
+use crate::reader::{IndexReader, IndexReaderBuilder, ReloadPolicy};
 use crate::schema::document::Document;
 use crate::schema::{Field, FieldType, Schema};
+use crate::space_usage::{IndexSpaceUsage, SearcherSpaceUsage};
 use crate::tokenizer::{TextAnalyzer, TokenizerManager};
-use crate::SegmentReader;
+use crate::{Opstamp, SegmentReader};
 
 fn load_metas(
     directory: &dyn Directory,
@@ -466,6 +473,11 @@ impl Index {
         Index::open(mmap_directory)
     }
 
+    /// Returns the inventory of the segment metas tracked by the index.
+    pub(crate) fn inventory(&self) -> &SegmentMetaInventory {
+        &self.inventory
+    }
+
     /// Returns the list of the segment metas tracked by the index.
     ///
     /// Such segments can of course be part of the index,
@@ -516,11 +528,89 @@ impl Index {
         Ok(index)
     }
 
+    /// Restores an index from a copy of the files of a [`Snapshot`].
+    ///
+    /// `directory` is expected to contain the files of `listing`, but no `meta.json`. Their
+    /// presence, size and checksum are checked before the `meta.json` of the snapshot is written,
+    /// so that the directory only contains an index once the copy is complete.
+    pub fn restore_from_listing<T: Into<Box<dyn Directory>>>(
+        directory: T,
+        listing: &SnapshotListing,
+    ) -> crate::Result<Index> {
+        let directory = snapshot::restore_from_listing(directory.into(), listing)?;
+        let inventory = SegmentMetaInventory::default();
+        let metas = load_metas(&directory, &inventory)?;
+        Ok(Index::open_from_metas(directory, &metas, inventory))
+    }
+
     /// Reads the index meta file from the directory.
     pub fn load_metas(&self) -> crate::Result<IndexMeta> {
         load_metas(self.directory(), &self.inventory)
     }
 
+    /// Returns the payload of the last commit, if any.
+    ///
+    /// Payloads added with [`IndexWriter::commit_with_payload()`] are returned as they were
+    /// serialized. Payloads added with
+    /// [`PreparedCommit::set_payload()`](crate::indexer::PreparedCommit::set_payload) that are
+    /// not valid JSON are returned as a JSON string.
+    pub fn commit_payload(&self) -> crate::Result<Option<serde_json::Value>> {
+        Ok(self
+            .load_metas()?
+            .payload
+            .as_deref()
+            .map(parse_commit_payload))
+    }
+
+    /// Returns the payloads of the last commits, oldest first.
+    ///
+    /// Only commits with a payload are listed. The number of commits retained is set by the
+    /// [`IndexWriterOptions`](crate::indexer::IndexWriterOptions) of the writers.
+    pub fn list_commit_payloads(&self) -> crate::Result<Vec<CommitPayload>> {
+        load_commit_payloads(self.directory())
+    }
+
+    /// Returns the opstamps of the retained commit points, oldest first.
+    ///
+    /// The number of commits retained is set by the
+    /// [`IndexWriterOptions`](crate::indexer::IndexWriterOptions) of the writers. See
+    /// [`Index::open_commit()`].
+    pub fn list_commit_points(&self) -> crate::Result<Vec<Opstamp>> {
+        Ok(load_commit_points(self.directory(), &self.inventory)?
+            .iter()
+            .map(|index_meta| index_meta.opstamp)
+            .collect())
+    }
+
+    /// Opens a reader on the commit with the given opstamp, which is either the last commit or a
+    /// retained commit point.
+    ///
+    /// The reader sees the index as it was at this commit, including the documents deleted
+    /// since then. It is never reloaded to a later commit, and the files of the commit are kept
+    /// by the garbage collection of this process as long as the reader is alive, even once the
+    /// commit point is released.
+    ///
+    /// Returns [`TantivyError::InvalidArgument`] if the commit is not retained.
+    pub fn open_commit(&self, opstamp: Opstamp) -> crate::Result<IndexReader> {
+        let last_commit = self.load_metas()?;
+        let commit = if last_commit.opstamp == opstamp {
+            last_commit
+        } else {
+            load_commit_points(self.directory(), &self.inventory)?
+                .into_iter()
+                .find(|commit_point| commit_point.opstamp == opstamp)
+                .ok_or_else(|| {
+                    TantivyError::InvalidArgument(format!(
+                        "The commit {opstamp} is not a retained commit point."
+                    ))
+                })?
+        };
+        self.reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .commit(commit)
+            .try_into()
+    }
+
     /// Open a new index writer with the given options. Attempts to acquire a lockfile.
     ///
     /// The lockfile should be deleted on drop, but it is possible
@@ -639,6 +729,26 @@ impl Index {
         self.schema.clone()
     }
 
+    /// Adds fields to the schema of the index, without reindexing its documents.
+    ///
+    /// `new_schema` must contain the fields of the schema of the index, at the same position and
+    /// with the same type and options, followed by the new fields. Otherwise a
+    /// [`TantivyError::IncompatibleSchemaChange`] error is returned and the schema is unchanged.
+    ///
+    /// The new schema is recorded in the meta file of the index, and the segments written from
+    /// then on have the new fields. The segments written before have no value for the new fields:
+    /// they do not match the queries on them, have no value in their fast fields, and their
+    /// stored documents do not have them. Merging old and new segments is supported.
+    ///
+    /// The schema can only be updated while no [`IndexWriter`] is running. The readers and the
+    /// writers created from clones of this `Index` made before the update keep the previous
+    /// schema.
+    pub fn update_schema(&mut self, new_schema: Schema) -> crate::Result<()> {
+        schema_evolution::update_schema(self, &new_schema)?;
+        self.schema = new_schema;
+        Ok(())
+    }
+
     /// Returns the list of segments that are searchable
     pub fn searchable_segments(&self) -> crate::Result<Vec<Segment>> {
         Ok(self
@@ -661,6 +771,70 @@ impl Index {
         self.segment(segment_meta)
     }
 
+    /// Creates the segment of the documents flushed by an indexing thread, whose first document
+    /// has the opstamp `first_opstamp`.
+    pub(crate) fn new_segment_for_flush(&self, first_opstamp: Opstamp) -> Segment {
+        let mut seed = b"flush".to_vec();
+        seed.extend_from_slice(&first_opstamp.to_le_bytes());
+        self.new_segment_from_seed(seed)
+    }
+
+    /// Creates the segment merging the segments of `segment_metas`, with their deletes applied up
+    /// to `target_opstamp`.
+    pub(crate) fn new_segment_for_merge(
+        &self,
+        segment_metas: &[SegmentMeta],
+        target_opstamp: Opstamp,
+    ) -> Segment {
+        let mut merged_segments: Vec<(String, Opstamp)> = segment_metas
+            .iter()
+            .map(|segment_meta| {
+                (
+                    segment_meta.id().uuid_string(),
+                    segment_meta.delete_opstamp().unwrap_or(0),
+                )
+            })
+            .collect();
+        merged_segments.sort();
+        let mut seed = b"merge".to_vec();
+        for (segment_id, delete_opstamp) in merged_segments {
+            seed.extend_from_slice(segment_id.as_bytes());
+            seed.extend_from_slice(&delete_opstamp.to_le_bytes());
+        }
+        seed.extend_from_slice(&target_opstamp.to_le_bytes());
+        self.new_segment_from_seed(seed)
+    }
+
+    /// Creates a new segment whose id is derived from `seed` if the index has
+    /// [`IndexSettings::deterministic_segment_ids`], and random otherwise.
+    fn new_segment_from_seed(&self, mut seed: Vec<u8>) -> Segment {
+        if !self.settings.deterministic_segment_ids {
+            return self.new_segment();
+        }
+        // The segments of a rolled back or failed operation may still be alive or have files,
+        // while their origin is replayed. Their ids are skipped.
+        let used_segment_ids: HashSet<SegmentId> =
+            self.inventory.all().iter().map(SegmentMeta::id).collect();
+        let used_files = self.directory.list_managed_files();
+        let is_used = |segment_id: &SegmentId| {
+            let uuid_string = segment_id.uuid_string();
+            used_segment_ids.contains(segment_id)
+                || used_files
+                    .iter()
+                    .any(|path| path.to_string_lossy().starts_with(&uuid_string))
+        };
+        let seed_len = seed.len();
+        let segment_id = (0u64..)
+            .map(|attempt| {
+                seed.truncate(seed_len);
+                seed.extend_from_slice(&attempt.to_le_bytes());
+                SegmentId::derive_from(&seed)
+            })
+            .find(|segment_id| !is_used(segment_id))
+            .expect("There is always a segment id which is not used");
+        self.segment(self.inventory.new_segment_meta(segment_id, 0))
+    }
+
     /// Return a reference to the index directory.
     pub fn directory(&self) -> &ManagedDirectory {
         &self.directory
@@ -686,6 +860,64 @@ impl Index {
             .collect())
     }
 
+    /// Summarizes the space usage of the index: the size of the files of each component of
+    /// each searchable segment, and the space usage attributed to each field.
+    ///
+    /// See [`Searcher::space_usage()`](crate::Searcher::space_usage) for the space usage of the
+    /// segments of an open searcher.
+    pub fn space_usage(&self) -> crate::Result<IndexSpaceUsage> {
+        let schema = self.schema();
+        let mut segments_space_usage = SearcherSpaceUsage::new();
+        for segment in self.searchable_segments()? {
+            let segment_reader = SegmentReader::open(&segment)?;
+            segments_space_usage.add_segment(segment_reader.space_usage()?, &schema);
+        }
+        let meta = ByteCount::from(self.directory.atomic_read(&META_FILEPATH)?.len());
+        Ok(IndexSpaceUsage::new(segments_space_usage, meta))
+    }
+
+    /// Takes a snapshot of the last commit, for instance to back up the index.
+    ///
+    /// The files of the commit are protected from garbage collection until the
+    /// [`Snapshot`] is released, so that they can be copied while the index keeps on
+    /// committing and merging. As for [`IndexReader::pin_searcher()`], a writer running in
+    /// another process may still delete them.
+    pub fn create_snapshot(&self) -> crate::Result<Snapshot> {
+        snapshot::create_snapshot(&self.directory)
+    }
+
+    /// Checks the consistency of all of the searchable segments of the index.
+    ///
+    /// See [`Segment::verify()`].
+    pub fn verify_all(&self) -> crate::Result<IndexVerifyReport> {
+        self.verify_all_with_options(&SegmentVerifyOptions::default())
+    }
+
+    /// Checks the consistency of all of the searchable segments of the index, as configured by
+    /// `options`.
+    pub fn verify_all_with_options(
+        &self,
+        options: &SegmentVerifyOptions,
+    ) -> crate::Result<IndexVerifyReport> {
+        let segments = self
+            .searchable_segments()?
+            .iter()
+            .map(|segment| segment.verify_with_options(options))
+            .collect();
+        Ok(IndexVerifyReport { segments })
+    }
+
+    /// Applies the repairs of `report` that are safe, and returns them.
+    ///
+    /// Only derived metadata is repaired, e.g. the number of deleted documents recorded in a
+    /// segment meta is recomputed from its alive bitset. The data of the segments is never
+    /// modified. The issues of a segment whose alive bitset is damaged are not repaired.
+    ///
+    /// The index must not have a running [`IndexWriter`](crate::IndexWriter).
+    pub fn repair(&self, report: &IndexVerifyReport) -> crate::Result<Vec<SegmentRepair>> {
+        segment_verify::repair_index(self, report)
+    }
+
     /// Returns the set of corrupted files
     pub fn validate_checksum(&self) -> crate::Result<HashSet<PathBuf>> {
         let managed_files = self.directory.list_managed_files();
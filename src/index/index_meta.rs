@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
@@ -18,6 +18,14 @@ struct DeleteMeta {
     opstamp: Opstamp,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SoftDeleteMeta {
+    num_soft_deleted_docs: u32,
+    opstamp: Opstamp,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstone_fields: Vec<String>,
+}
+
 #[derive(Clone, Default)]
 pub(crate) struct SegmentMetaInventory {
     inventory: Inventory<InnerSegmentMeta>,
@@ -39,6 +47,8 @@ impl SegmentMetaInventory {
             max_doc,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
             deletes: None,
+            soft_deletes: None,
+            store_compressor: None,
         };
         SegmentMeta::from(self.inventory.track(inner))
     }
@@ -142,6 +152,10 @@ impl SegmentMeta {
             SegmentComponent::FastFields => ".fast".to_string(),
             SegmentComponent::FieldNorms => ".fieldnorm".to_string(),
             SegmentComponent::Delete => format!(".{}.del", self.delete_opstamp().unwrap_or(0)),
+            SegmentComponent::SoftDelete => {
+                format!(".{}.softdel", self.soft_delete_opstamp().unwrap_or(0))
+            }
+            SegmentComponent::ColumnStats => ".colstats".to_string(),
         });
         PathBuf::from(path)
     }
@@ -175,6 +189,41 @@ impl SegmentMeta {
         self.num_deleted_docs() > 0
     }
 
+    /// Returns the number of soft-deleted documents.
+    ///
+    /// Soft-deleted documents are not counted as deleted: they are still part of
+    /// [`SegmentMeta::num_docs`].
+    pub fn num_soft_deleted_docs(&self) -> u32 {
+        self.tracked
+            .soft_deletes
+            .as_ref()
+            .map(|soft_delete_meta| soft_delete_meta.num_soft_deleted_docs)
+            .unwrap_or(0u32)
+    }
+
+    /// Returns true iff some documents of the segment are soft-deleted.
+    pub fn has_soft_deletes(&self) -> bool {
+        self.num_soft_deleted_docs() > 0
+    }
+
+    /// Returns the `Opstamp` of the last soft delete operation
+    /// taken in account in this segment.
+    pub fn soft_delete_opstamp(&self) -> Option<Opstamp> {
+        self.tracked
+            .soft_deletes
+            .as_ref()
+            .map(|soft_delete_meta| soft_delete_meta.opstamp)
+    }
+
+    /// Returns the tombstone fields of the soft deletes applied to this segment.
+    pub fn soft_delete_tombstone_fields(&self) -> &[String] {
+        self.tracked
+            .soft_deletes
+            .as_ref()
+            .map(|soft_delete_meta| &soft_delete_meta.tombstone_fields[..])
+            .unwrap_or(&[])
+    }
+
     /// Updates the max_doc value from the `SegmentMeta`.
     pub fn with_max_doc(self, max_doc: u32) -> SegmentMeta {
         assert_eq!(self.tracked.max_doc, 0);
@@ -183,7 +232,9 @@ impl SegmentMeta {
             segment_id: inner_meta.segment_id,
             max_doc,
             deletes: None,
+            soft_deletes: None,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
+            store_compressor: inner_meta.store_compressor,
         });
         SegmentMeta { tracked }
     }
@@ -204,6 +255,59 @@ impl SegmentMeta {
             max_doc: inner_meta.max_doc,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
             deletes: Some(delete_meta),
+            soft_deletes: inner_meta.soft_deletes.clone(),
+            store_compressor: inner_meta.store_compressor,
+        });
+        SegmentMeta { tracked }
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn with_soft_delete_meta(
+        self,
+        num_soft_deleted_docs: u32,
+        opstamp: Opstamp,
+        tombstone_fields: Vec<String>,
+    ) -> SegmentMeta {
+        assert!(
+            num_soft_deleted_docs <= self.num_docs(),
+            "There cannot be more soft-deleted docs than there are alive docs."
+        );
+        let soft_delete_meta = SoftDeleteMeta {
+            num_soft_deleted_docs,
+            opstamp,
+            tombstone_fields,
+        };
+        let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
+            segment_id: inner_meta.segment_id,
+            max_doc: inner_meta.max_doc,
+            include_temp_doc_store: Arc::new(AtomicBool::new(true)),
+            deletes: inner_meta.deletes.clone(),
+            soft_deletes: Some(soft_delete_meta),
+            store_compressor: inner_meta.store_compressor,
+        });
+        SegmentMeta { tracked }
+    }
+
+    /// Returns the `Compressor` of the doc store of the segment.
+    ///
+    /// Segments written before the compressor was recorded return `None`. The doc store reader
+    /// does not depend on this information, as the compressor is also recorded in the footer of
+    /// the doc store.
+    pub fn store_compressor(&self) -> Option<Compressor> {
+        self.tracked.store_compressor
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn with_store_compressor(self, store_compressor: Compressor) -> SegmentMeta {
+        let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
+            segment_id: inner_meta.segment_id,
+            max_doc: inner_meta.max_doc,
+            include_temp_doc_store: Arc::new(AtomicBool::new(true)),
+            deletes: inner_meta.deletes.clone(),
+            soft_deletes: inner_meta.soft_deletes.clone(),
+            store_compressor: Some(store_compressor),
         });
         SegmentMeta { tracked }
     }
@@ -214,6 +318,10 @@ struct InnerSegmentMeta {
     segment_id: SegmentId,
     max_doc: u32,
     deletes: Option<DeleteMeta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    soft_deletes: Option<SoftDeleteMeta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    store_compressor: Option<Compressor>,
     /// If you want to avoid the SegmentComponent::TempStore file to be covered by
     /// garbage collection and deleted, set this to true. This is used during merge.
     #[serde(skip)]
@@ -240,6 +348,10 @@ fn is_true(val: &bool) -> bool {
     *val
 }
 
+fn is_false(val: &bool) -> bool {
+    !*val
+}
+
 /// Search Index Settings.
 ///
 /// Contains settings which are applied on the whole
@@ -247,8 +359,26 @@ fn is_true(val: &bool) -> bool {
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct IndexSettings {
     /// The `Compressor` used to compress the doc store.
+    ///
+    /// It can be overridden for the segments created by flushing new documents, and for the
+    /// segments created by merges, with `store_codec_on_flush` and `store_codec_on_merge`.
     #[serde(default)]
     pub docstore_compression: Compressor,
+    /// The `Compressor` used to compress the doc store of the segments created by flushing new
+    /// documents. Defaults to `docstore_compression`.
+    ///
+    /// A cheap compressor keeps indexing fast, while the merges recompress the documents of
+    /// the long-lived segments with `store_codec_on_merge`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_codec_on_flush: Option<Compressor>,
+    /// The `Compressor` used to compress the doc store of the segments created by merges.
+    /// Defaults to `docstore_compression`.
+    ///
+    /// The doc store blocks of the merged segments are recompressed if they were compressed
+    /// with another compressor. Indexes whose segments use different compressors can be read
+    /// seamlessly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_codec_on_merge: Option<Compressor>,
     /// If set to true, docstore compression will happen on a dedicated thread.
     /// (defaults: true)
     #[doc(hidden)]
@@ -258,6 +388,33 @@ pub struct IndexSettings {
     #[serde(default = "default_docstore_blocksize")]
     /// The size of each block that will be compressed and written to disk
     pub docstore_blocksize: usize,
+    /// The fast fields whose [`ColumnStats`](crate::fastfield::ColumnStats) are computed when a
+    /// segment is written, by flushing new documents or by a merge.
+    ///
+    /// The match-all metric aggregations over these fields are then answered from the
+    /// statistics, on the segments without deletes. Only the numerical and date fields have
+    /// statistics.
+    ///
+    /// The value distribution is kept as the DDSketch of the
+    /// [`percentiles`](crate::aggregation::metric::PercentilesAggregationReq) aggregation rather
+    /// than as an equi-width histogram or a t-digest: its relative error bound holds whatever the
+    /// range of the values, and the sketches of the segments merge into exactly the one the
+    /// aggregation would compute. The statistics of all of the fields of a segment are stored as
+    /// a single JSON file, which is small since a sketch only grows with the logarithm of the
+    /// range of the values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub column_stats_fields: Vec<String>,
+    /// If set to true, the ids of the segments created by flushing new documents and by merges
+    /// are derived from how the segments were created, instead of being random.
+    ///
+    /// The id of a flushed segment is derived from the opstamp of its first document, and the id
+    /// of a merged segment from the ids and delete opstamps of the merged segments and from the
+    /// opstamp the deletes are applied up to. Rebuilding an index by replaying the same
+    /// operations with a single indexing thread then yields the same segment ids.
+    ///
+    /// An id which is already used by a segment or by a file of the index is never reused.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub deterministic_segment_ids: bool,
 }
 
 /// Must be a function to be compatible with serde defaults
@@ -269,12 +426,31 @@ impl Default for IndexSettings {
     fn default() -> Self {
         Self {
             docstore_compression: Compressor::default(),
+            store_codec_on_flush: None,
+            store_codec_on_merge: None,
             docstore_blocksize: default_docstore_blocksize(),
             docstore_compress_dedicated_thread: true,
+            column_stats_fields: Vec::new(),
+            deterministic_segment_ids: false,
         }
     }
 }
 
+impl IndexSettings {
+    /// Returns the `Compressor` of the doc store of the segments created by flushing new
+    /// documents.
+    pub fn store_compressor_on_flush(&self) -> Compressor {
+        self.store_codec_on_flush
+            .unwrap_or(self.docstore_compression)
+    }
+
+    /// Returns the `Compressor` of the doc store of the segments created by merges.
+    pub fn store_compressor_on_merge(&self) -> Compressor {
+        self.store_codec_on_merge
+            .unwrap_or(self.docstore_compression)
+    }
+}
+
 /// The order to sort by
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum Order {
@@ -371,6 +547,135 @@ impl IndexMeta {
         let untracked_meta_json: UntrackedIndexMeta = serde_json::from_str(meta_json)?;
         Ok(untracked_meta_json.track(inventory))
     }
+
+    /// Deserializes a JSON array of index metas, tracking their segments in `inventory`.
+    pub(crate) fn deserialize_list(
+        metas_json: &[u8],
+        inventory: &SegmentMetaInventory,
+    ) -> serde_json::Result<Vec<IndexMeta>> {
+        let untracked_metas: Vec<UntrackedIndexMeta> = serde_json::from_slice(metas_json)?;
+        Ok(untracked_metas
+            .into_iter()
+            .map(|untracked_meta| untracked_meta.track(inventory))
+            .collect())
+    }
+
+    /// Returns the changes of the segments from `self` to `other`.
+    ///
+    /// This allows tools syncing a copy of an index directory to only transfer the files that
+    /// changed between two commits, e.g. with [`MetaDiff::files_to_copy`].
+    pub fn diff(&self, other: &IndexMeta) -> MetaDiff {
+        let segments: HashMap<SegmentId, &SegmentMeta> = self
+            .segments
+            .iter()
+            .map(|segment_meta| (segment_meta.id(), segment_meta))
+            .collect();
+        let other_segment_ids: HashSet<SegmentId> =
+            other.segments.iter().map(SegmentMeta::id).collect();
+        let mut diff = MetaDiff::default();
+        for other_segment_meta in &other.segments {
+            let Some(segment_meta) = segments.get(&other_segment_meta.id()) else {
+                diff.added_segments.push(other_segment_meta.clone());
+                continue;
+            };
+            let deletes = |segment_meta: &SegmentMeta| {
+                (
+                    segment_meta.delete_opstamp(),
+                    segment_meta.num_deleted_docs(),
+                    segment_meta.soft_delete_opstamp(),
+                    segment_meta.num_soft_deleted_docs(),
+                )
+            };
+            if deletes(segment_meta) != deletes(other_segment_meta) {
+                diff.changed_deletes.push(SegmentDeletesChange {
+                    before: (*segment_meta).clone(),
+                    after: other_segment_meta.clone(),
+                });
+            }
+        }
+        diff.removed_segments = self
+            .segments
+            .iter()
+            .filter(|segment_meta| !other_segment_ids.contains(&segment_meta.id()))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+/// The changes of the segments between two [`IndexMeta`], returned by [`IndexMeta::diff`].
+#[derive(Clone, Debug, Default)]
+pub struct MetaDiff {
+    /// The segments which were added, e.g. by a commit or a merge.
+    pub added_segments: Vec<SegmentMeta>,
+    /// The segments which were removed, e.g. because they were merged.
+    pub removed_segments: Vec<SegmentMeta>,
+    /// The segments whose deletes or soft deletes changed.
+    pub changed_deletes: Vec<SegmentDeletesChange>,
+}
+
+/// A segment whose deletes or soft deletes changed, in a [`MetaDiff`].
+#[derive(Clone, Debug)]
+pub struct SegmentDeletesChange {
+    /// The meta of the segment before the change.
+    pub before: SegmentMeta,
+    /// The meta of the segment after the change.
+    pub after: SegmentMeta,
+}
+
+impl MetaDiff {
+    /// Returns true if the segments did not change.
+    pub fn is_empty(&self) -> bool {
+        self.added_segments.is_empty()
+            && self.removed_segments.is_empty()
+            && self.changed_deletes.is_empty()
+    }
+
+    /// Returns the files of the segments which are new after the change: the files of the added
+    /// segments, and the new delete files of the segments whose deletes changed.
+    ///
+    /// Like for [`SegmentMeta::list_files`], some of the returned files may not exist, e.g. the
+    /// positions file of a segment without positions.
+    pub fn files_to_copy(&self) -> HashSet<PathBuf> {
+        let mut files: HashSet<PathBuf> = self
+            .added_segments
+            .iter()
+            .flat_map(SegmentMeta::list_files)
+            .collect();
+        for change in &self.changed_deletes {
+            let before_files = change.before.list_files();
+            files.extend(
+                change
+                    .after
+                    .list_files()
+                    .into_iter()
+                    .filter(|path| !before_files.contains(path)),
+            );
+        }
+        files
+    }
+
+    /// Returns the files of the segments which are not used anymore after the change: the files
+    /// of the removed segments, and the former delete files of the segments whose deletes
+    /// changed.
+    pub fn files_to_remove(&self) -> HashSet<PathBuf> {
+        let mut files: HashSet<PathBuf> = self
+            .removed_segments
+            .iter()
+            .flat_map(SegmentMeta::list_files)
+            .collect();
+        for change in &self.changed_deletes {
+            let after_files = change.after.list_files();
+            files.extend(
+                change
+                    .before
+                    .list_files()
+                    .into_iter()
+                    .filter(|path| !after_files.contains(path)),
+            );
+        }
+        files
+    }
 }
 
 impl fmt::Debug for IndexMeta {
@@ -387,13 +692,17 @@ impl fmt::Debug for IndexMeta {
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashSet;
+
     use super::IndexMeta;
     use crate::index::index_meta::UntrackedIndexMeta;
-    use crate::schema::{Schema, TEXT};
+    use crate::index::{SegmentComponent, SegmentId, SegmentMeta};
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, STRING, TEXT};
     use crate::store::Compressor;
     #[cfg(feature = "zstd-compression")]
     use crate::store::ZstdCompressor;
-    use crate::IndexSettings;
+    use crate::{Index, IndexSettings, IndexWriter, Term};
 
     #[test]
     fn test_serialize_metas() {
@@ -436,6 +745,7 @@ mod tests {
                 }),
                 docstore_blocksize: 1_000_000,
                 docstore_compress_dedicated_thread: true,
+                ..Default::default()
             },
             segments: Vec::new(),
             schema,
@@ -499,7 +809,11 @@ mod tests {
             IndexSettings {
                 docstore_compression: Compressor::default(),
                 docstore_compress_dedicated_thread: true,
-                docstore_blocksize: 16_384
+                docstore_blocksize: 16_384,
+                store_codec_on_flush: None,
+                store_codec_on_merge: None,
+                column_stats_fields: Vec::new(),
+                deterministic_segment_ids: false,
             }
         );
         {
@@ -531,4 +845,152 @@ mod tests {
             assert_eq!(index_settings_deser, index_settings);
         }
     }
+
+    fn deterministic_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("text", STRING);
+        Index::builder()
+            .schema(schema_builder.build())
+            .settings(IndexSettings {
+                deterministic_segment_ids: true,
+                ..Default::default()
+            })
+            .create_in_ram()
+    }
+
+    /// Builds an index with two commits, a delete and a merge, and returns the ids of its
+    /// segments after each of these steps.
+    fn build_deterministic_index() -> crate::Result<Vec<Vec<SegmentId>>> {
+        let index = deterministic_index()?;
+        let text = index.schema().get_field("text")?;
+        let segment_ids = || -> crate::Result<Vec<SegmentId>> {
+            let mut segment_ids = index.searchable_segment_ids()?;
+            segment_ids.sort();
+            Ok(segment_ids)
+        };
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let mut steps = Vec::new();
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.add_document(doc!(text => "b"))?;
+        index_writer.commit()?;
+        steps.push(segment_ids()?);
+        index_writer.add_document(doc!(text => "c"))?;
+        index_writer.commit()?;
+        steps.push(segment_ids()?);
+        index_writer.delete_term(Term::from_field_text(text, "a"))?;
+        index_writer.commit()?;
+        steps.push(segment_ids()?);
+        index_writer.merge(&segment_ids()?).wait()?;
+        index_writer.wait_merging_threads()?;
+        steps.push(segment_ids()?);
+        Ok(steps)
+    }
+
+    #[test]
+    fn test_deterministic_segment_ids() -> crate::Result<()> {
+        let steps = build_deterministic_index()?;
+        assert_eq!(steps[0].len(), 1);
+        assert_eq!(steps[1].len(), 2);
+        assert_eq!(steps[2], steps[1]);
+        assert_eq!(steps[3].len(), 1);
+        assert!(!steps[1].contains(&steps[3][0]));
+        // Rebuilding the index with the same operations gives the same segments.
+        assert_eq!(build_deterministic_index()?, steps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_segment_ids_after_rollback() -> crate::Result<()> {
+        let index = deterministic_index()?;
+        let text = index.schema().get_field("text")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.prepare_commit()?.abort()?;
+        let rolled_back_files = index.directory().list_managed_files();
+        // The flushed segment replays the opstamp of the rolled back segment, whose files are
+        // still in the directory.
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 1);
+        for path in segment_metas[0].list_files() {
+            assert!(!rolled_back_files.contains(&path));
+        }
+        assert_eq!(index.reader()?.searcher().num_docs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_meta_diff() -> crate::Result<()> {
+        let index = deterministic_index()?;
+        let text = index.schema().get_field("text")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.add_document(doc!(text => "d"))?;
+        index_writer.commit()?;
+        let first_meta = index.load_metas()?;
+        assert!(first_meta.diff(&first_meta).is_empty());
+
+        // A commit adds a segment.
+        index_writer.add_document(doc!(text => "b"))?;
+        index_writer.commit()?;
+        let second_meta = index.load_metas()?;
+        let diff = first_meta.diff(&second_meta);
+        let first_segment = first_meta.segments[0].id();
+        let second_segment = second_meta
+            .segments
+            .iter()
+            .map(SegmentMeta::id)
+            .find(|segment_id| *segment_id != first_segment)
+            .unwrap();
+        assert_eq!(diff.added_segments.len(), 1);
+        assert_eq!(diff.added_segments[0].id(), second_segment);
+        assert!(diff.removed_segments.is_empty());
+        assert!(diff.changed_deletes.is_empty());
+        assert_eq!(diff.files_to_copy(), diff.added_segments[0].list_files());
+        assert!(diff.files_to_remove().is_empty());
+
+        // A delete changes the delete file of a segment.
+        index_writer.delete_term(Term::from_field_text(text, "a"))?;
+        index_writer.commit()?;
+        let third_meta = index.load_metas()?;
+        let diff = second_meta.diff(&third_meta);
+        assert!(diff.added_segments.is_empty());
+        assert!(diff.removed_segments.is_empty());
+        assert_eq!(diff.changed_deletes.len(), 1);
+        let change = &diff.changed_deletes[0];
+        assert_eq!(change.after.id(), first_segment);
+        assert_eq!(change.before.delete_opstamp(), None);
+        assert!(change.after.delete_opstamp().is_some());
+        let delete_file = change.after.relative_path(SegmentComponent::Delete);
+        assert_eq!(diff.files_to_copy(), HashSet::from([delete_file]));
+
+        // A merge replaces the segments by the merged segment.
+        index_writer
+            .merge(&[first_segment, second_segment])
+            .wait()?;
+        index_writer.wait_merging_threads()?;
+        let fourth_meta = index.load_metas()?;
+        let diff = third_meta.diff(&fourth_meta);
+        assert_eq!(diff.added_segments.len(), 1);
+        assert_eq!(diff.added_segments[0].id(), fourth_meta.segments[0].id());
+        let mut removed_segments: Vec<SegmentId> =
+            diff.removed_segments.iter().map(SegmentMeta::id).collect();
+        removed_segments.sort();
+        let mut merged_segments = vec![first_segment, second_segment];
+        merged_segments.sort();
+        assert_eq!(removed_segments, merged_segments);
+        assert!(diff.changed_deletes.is_empty());
+        assert!(diff
+            .files_to_remove()
+            .contains(&third_meta.segments[0].relative_path(SegmentComponent::Store)));
+
+        // The diff in the other direction is the reverse change.
+        let reverse_diff = fourth_meta.diff(&third_meta);
+        assert_eq!(reverse_diff.files_to_copy(), diff.files_to_remove());
+        assert_eq!(reverse_diff.files_to_remove(), diff.files_to_copy());
+        Ok(())
+    }
 }
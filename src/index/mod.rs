@@ -2,19 +2,34 @@
 //!
 //! It contains `Index` and `Segment`, where a `Index` consists of one or more `Segment`s.
 
+mod component_set;
 mod index;
 mod index_meta;
 mod inverted_index_reader;
+mod schema_evolution;
 mod segment;
 mod segment_component;
 mod segment_id;
 mod segment_reader;
+mod segment_reader_extensions;
+mod segment_verify;
+mod snapshot;
 
+pub(crate) use self::component_set::ComponentLoading;
+pub use self::component_set::ComponentSet;
 pub use self::index::{Index, IndexBuilder};
 pub(crate) use self::index_meta::SegmentMetaInventory;
-pub use self::index_meta::{IndexMeta, IndexSettings, Order, SegmentMeta};
+pub use self::index_meta::{
+    IndexMeta, IndexSettings, MetaDiff, Order, SegmentDeletesChange, SegmentMeta,
+};
 pub use self::inverted_index_reader::InvertedIndexReader;
 pub use self::segment::Segment;
 pub use self::segment_component::SegmentComponent;
 pub use self::segment_id::SegmentId;
 pub use self::segment_reader::{FieldMetadata, SegmentReader};
+pub(crate) use self::segment_reader_extensions::SegmentReaderExtensions;
+pub use self::segment_verify::{
+    IndexVerifyReport, PostingsCheck, SegmentRepair, SegmentVerifyIssue, SegmentVerifyOptions,
+    SegmentVerifyReport,
+};
+pub use self::snapshot::{Snapshot, SnapshotFile, SnapshotListing};
@@ -0,0 +1,265 @@
+use crate::directory::{Directory, INDEX_WRITER_LOCK};
+use crate::error::SchemaChangeKind;
+use crate::index::Index;
+use crate::indexer::segment_updater::save_metas;
+use crate::schema::Schema;
+use crate::TantivyError;
+
+/// Checks that `new_schema` only adds fields to `schema`, after its existing fields.
+///
+/// The fields are identified by their position in the schema, so the existing fields must keep
+/// their position, their type and their options.
+pub(crate) fn check_additive_schema_change(
+    schema: &Schema,
+    new_schema: &Schema,
+) -> crate::Result<()> {
+    for (field, field_entry) in schema.fields() {
+        let incompatible_change = |kind: SchemaChangeKind| TantivyError::IncompatibleSchemaChange {
+            field: field_entry.name().to_string(),
+            kind,
+        };
+        let Ok(new_field) = new_schema.get_field(field_entry.name()) else {
+            return Err(incompatible_change(SchemaChangeKind::Removed));
+        };
+        if new_field != field {
+            return Err(incompatible_change(SchemaChangeKind::Moved));
+        }
+        if new_schema.get_field_entry(new_field) != field_entry {
+            return Err(incompatible_change(SchemaChangeKind::Modified));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the schema recorded in the meta file of the index by `new_schema`, if it only adds
+/// fields to it.
+pub(crate) fn update_schema(index: &Index, new_schema: &Schema) -> crate::Result<()> {
+    let _directory_lock = index
+        .directory()
+        .acquire_lock(&INDEX_WRITER_LOCK)
+        .map_err(|err| {
+            TantivyError::LockFailure(
+                err,
+                Some(
+                    "The schema of an index can only be updated while no `IndexWriter` is running."
+                        .to_string(),
+                ),
+            )
+        })?;
+    let mut index_meta = index.load_metas()?;
+    check_additive_schema_change(&index_meta.schema, new_schema)?;
+    if index_meta.schema == *new_schema {
+        return Ok(());
+    }
+    index_meta.schema = new_schema.clone();
+    save_metas(&index_meta, index.directory())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, TopDocs};
+    use crate::directory::RamDirectory;
+    use crate::error::SchemaChangeKind;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, QueryParser};
+    use crate::schema::{Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
+    use crate::{Index, IndexWriter, Order, Searcher, TantivyDocument, TantivyError};
+
+    fn initial_schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_u64_field("id", INDEXED | FAST | STORED);
+        schema_builder.build()
+    }
+
+    fn evolved_schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_u64_field("id", INDEXED | FAST | STORED);
+        schema_builder.add_i64_field("rating", FAST | STORED);
+        schema_builder.add_text_field("body", TEXT | STORED);
+        schema_builder.add_text_field("tag", STRING | FAST);
+        schema_builder.build()
+    }
+
+    fn create_evolved_index(directory: RamDirectory) -> crate::Result<Index> {
+        let mut index = Index::create(directory, initial_schema(), Default::default())?;
+        let schema = index.schema();
+        let title = schema.get_field("title")?;
+        let id = schema.get_field("id")?;
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.set_merge_policy(Box::new(NoMergePolicy));
+            index_writer.add_document(doc!(title => "old hello", id => 0u64))?;
+            index_writer.add_document(doc!(title => "old world", id => 1u64))?;
+            index_writer.commit()?;
+        }
+        index.update_schema(evolved_schema())?;
+        let schema = index.schema();
+        let rating = schema.get_field("rating")?;
+        let body = schema.get_field("body")?;
+        let tag = schema.get_field("tag")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(
+            title => "new hello",
+            id => 2u64,
+            rating => -3i64,
+            body => "hello body",
+            tag => "a",
+        ))?;
+        index_writer.add_document(doc!(
+            title => "new world",
+            id => 3u64,
+            rating => 5i64,
+            body => "world body",
+            tag => "b",
+        ))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn ids_by_rating(searcher: &Searcher) -> crate::Result<Vec<(i64, u64)>> {
+        let id = searcher.schema().get_field("id")?;
+        let collector = TopDocs::with_limit(4).order_by_fast_field::<i64>("rating", Order::Desc);
+        searcher
+            .search(&AllQuery, &collector)?
+            .into_iter()
+            .map(|(rating, doc_address)| {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                Ok((rating, doc.get_first(id).unwrap().as_u64().unwrap()))
+            })
+            .collect()
+    }
+
+    fn check_evolved_index(index: &Index) -> crate::Result<()> {
+        let searcher = index.reader()?.searcher();
+        let schema = searcher.schema().clone();
+        let query_parser = QueryParser::for_index(index, vec![schema.get_field("title")?]);
+        let count = |query: &str| -> crate::Result<usize> {
+            searcher.search(&query_parser.parse_query(query)?, &Count)
+        };
+        assert_eq!(count("hello")?, 2);
+        assert_eq!(count("body:body")?, 2);
+        assert_eq!(count("body:hello OR tag:b")?, 2);
+        assert_eq!(count("body:\"hello body\"")?, 1);
+
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "rating_stats": { "stats": { "field": "rating" } },
+            "tags": { "terms": { "field": "tag" } },
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+        let agg_res = serde_json::to_value(searcher.search(&AllQuery, &collector)?)?;
+        assert_eq!(agg_res["rating_stats"]["count"], 2);
+        assert_eq!(agg_res["rating_stats"]["min"], -3.0);
+        assert_eq!(agg_res["tags"]["buckets"].as_array().unwrap().len(), 2);
+
+        // The documents of the old segment have no rating, and sort last.
+        let ids_by_rating = ids_by_rating(&searcher)?;
+        assert_eq!(&ids_by_rating[..2], &[(5, 3), (-3, 2)]);
+
+        let body = schema.get_field("body")?;
+        let id = schema.get_field("id")?;
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(0)?;
+            for doc_id in segment_reader.doc_ids_alive() {
+                let doc: TantivyDocument = store_reader.get(doc_id)?;
+                let is_old_doc = doc.get_first(id).unwrap().as_u64().unwrap() < 2;
+                assert_eq!(doc.get_first(body).is_none(), is_old_doc);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_schema() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_evolved_index(directory.clone())?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+        assert_eq!(index.load_metas()?.schema, evolved_schema());
+        check_evolved_index(&index)?;
+        // The index opened again reads the updated schema.
+        let reopened_index = Index::open(directory)?;
+        assert_eq!(reopened_index.schema(), evolved_schema());
+        check_evolved_index(&reopened_index)
+    }
+
+    #[test]
+    fn test_update_schema_merge_old_and_new_segments() -> crate::Result<()> {
+        let index = create_evolved_index(RamDirectory::create())?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 1);
+        check_evolved_index(&index)?;
+
+        // The documents of the old segment have no rating in the merged segment.
+        let searcher = index.reader()?.searcher();
+        let rating_column = searcher.segment_reader(0).fast_fields().i64("rating")?;
+        let num_ratings = (0..4)
+            .filter(|&doc| rating_column.first(doc).is_some())
+            .count();
+        assert_eq!(num_ratings, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_schema_rejects_non_additive_changes() -> crate::Result<()> {
+        let mut index = Index::create_in_ram(initial_schema());
+        let schema_change_kind = |result: crate::Result<()>| match result {
+            Err(TantivyError::IncompatibleSchemaChange { kind, .. }) => Some(kind),
+            _ => None,
+        };
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        let err = index.update_schema(schema_builder.build()).unwrap_err();
+        assert_eq!(err.error_code(), "incompatible_schema_change");
+        assert_eq!(
+            err.to_string(),
+            "The field \"id\" of the schema cannot be removed"
+        );
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("id", INDEXED | FAST | STORED);
+        schema_builder.add_text_field("title", TEXT | STORED);
+        assert_eq!(
+            schema_change_kind(index.update_schema(schema_builder.build())),
+            Some(SchemaChangeKind::Moved)
+        );
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_i64_field("id", INDEXED | FAST | STORED);
+        assert_eq!(
+            schema_change_kind(index.update_schema(schema_builder.build())),
+            Some(SchemaChangeKind::Modified)
+        );
+
+        // The rejected schemas are not persisted.
+        assert_eq!(index.schema(), initial_schema());
+        assert_eq!(index.load_metas()?.schema, initial_schema());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_schema_while_writing() -> crate::Result<()> {
+        let mut index = Index::create_in_ram(initial_schema());
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        assert!(matches!(
+            index.update_schema(evolved_schema()),
+            Err(TantivyError::LockFailure(..))
+        ));
+        drop(index_writer);
+        index.update_schema(evolved_schema())?;
+        assert_eq!(index.schema(), evolved_schema());
+        Ok(())
+    }
+}
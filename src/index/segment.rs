@@ -1,11 +1,13 @@
 use std::fmt;
 use std::path::PathBuf;
 
+use super::segment_verify::{self, SegmentVerifyOptions, SegmentVerifyReport};
 use super::SegmentComponent;
 use crate::directory::error::{OpenReadError, OpenWriteError};
-use crate::directory::{Directory, FileSlice, WritePtr};
+use crate::directory::{Directory, FileSlice, IoBudget, WritePtr};
 use crate::index::{Index, SegmentId, SegmentMeta};
 use crate::schema::Schema;
+use crate::store::Compressor;
 use crate::Opstamp;
 
 /// A segment is a piece of the index.
@@ -13,6 +15,8 @@ use crate::Opstamp;
 pub struct Segment {
     index: Index,
     meta: SegmentMeta,
+    /// Budget throttling the files written for this segment, e.g. by a merge.
+    io_budget: Option<IoBudget>,
 }
 
 impl fmt::Debug for Segment {
@@ -24,7 +28,19 @@ impl fmt::Debug for Segment {
 impl Segment {
     /// Creates a new segment given an `Index` and a `SegmentId`
     pub(crate) fn for_index(index: Index, meta: SegmentMeta) -> Segment {
-        Segment { index, meta }
+        Segment {
+            index,
+            meta,
+            io_budget: None,
+        }
+    }
+
+    /// Makes the files written for this segment consume `io_budget`.
+    pub(crate) fn with_io_budget(self, io_budget: IoBudget) -> Segment {
+        Segment {
+            io_budget: Some(io_budget),
+            ..self
+        }
     }
 
     /// Returns the index the segment belongs to.
@@ -48,8 +64,16 @@ impl Segment {
     /// as we finalize a fresh new segment.
     pub(crate) fn with_max_doc(self, max_doc: u32) -> Segment {
         Segment {
-            index: self.index,
             meta: self.meta.with_max_doc(max_doc),
+            ..self
+        }
+    }
+
+    /// Records the `Compressor` of the doc store in the `SegmentMeta`.
+    pub(crate) fn with_store_compressor(self, store_compressor: Compressor) -> Segment {
+        Segment {
+            meta: self.meta.with_store_compressor(store_compressor),
+            ..self
         }
     }
 
@@ -57,8 +81,24 @@ impl Segment {
     #[must_use]
     pub fn with_delete_meta(self, num_deleted_docs: u32, opstamp: Opstamp) -> Segment {
         Segment {
-            index: self.index,
             meta: self.meta.with_delete_meta(num_deleted_docs, opstamp),
+            ..self
+        }
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn with_soft_delete_meta(
+        self,
+        num_soft_deleted_docs: u32,
+        opstamp: Opstamp,
+        tombstone_fields: Vec<String>,
+    ) -> Segment {
+        Segment {
+            meta: self
+                .meta
+                .with_soft_delete_meta(num_soft_deleted_docs, opstamp, tombstone_fields),
+            ..self
         }
     }
 
@@ -75,6 +115,20 @@ impl Segment {
         self.meta.relative_path(component)
     }
 
+    /// Checks the consistency of the components of the segment with each other, and the footer
+    /// checksums of its files.
+    ///
+    /// This reads all of the segment. The postings lists are fully checked, see
+    /// [`Segment::verify_with_options()`] to sample them instead.
+    pub fn verify(&self) -> SegmentVerifyReport {
+        self.verify_with_options(&SegmentVerifyOptions::default())
+    }
+
+    /// Checks the consistency of the components of the segment, as configured by `options`.
+    pub fn verify_with_options(&self, options: &SegmentVerifyOptions) -> SegmentVerifyReport {
+        segment_verify::verify_segment(self, options)
+    }
+
     /// Open one of the component file for a *regular* read.
     pub fn open_read(&self, component: SegmentComponent) -> Result<FileSlice, OpenReadError> {
         let path = self.relative_path(component);
@@ -85,6 +139,9 @@ impl Segment {
     pub fn open_write(&mut self, component: SegmentComponent) -> Result<WritePtr, OpenWriteError> {
         let path = self.relative_path(component);
         let write = self.index.directory_mut().open_write(&path)?;
-        Ok(write)
+        match &self.io_budget {
+            Some(io_budget) => Ok(io_budget.throttle(write)),
+            None => Ok(write),
+        }
     }
 }
@@ -4,8 +4,8 @@ use std::slice;
 ///
 /// Each component is stored in its own file,
 /// using the pattern `segment_uuid`.`component_extension`,
-/// except the delete component that takes an `segment_uuid`.`delete_opstamp`.`component_extension`
-#[derive(Copy, Clone, Eq, PartialEq)]
+/// except the delete components that take an `segment_uuid`.`delete_opstamp`.`component_extension`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SegmentComponent {
     /// Postings (or inverted list). Sorted lists of document ids, associated with terms
     Postings,
@@ -28,12 +28,18 @@ pub enum SegmentComponent {
     /// Bitset describing which document of the segment is alive.
     /// (It was representing deleted docs but changed to represent alive docs from v0.17)
     Delete,
+    /// Bitset describing which document of the segment is not soft-deleted.
+    SoftDelete,
+    /// Statistics over the values of the fast fields listed in
+    /// [`IndexSettings::column_stats_fields`](crate::IndexSettings::column_stats_fields).
+    /// Only written if the list is not empty.
+    ColumnStats,
 }
 
 impl SegmentComponent {
     /// Iterates through the components.
     pub fn iterator() -> slice::Iter<'static, SegmentComponent> {
-        static SEGMENT_COMPONENTS: [SegmentComponent; 8] = [
+        static SEGMENT_COMPONENTS: [SegmentComponent; 10] = [
             SegmentComponent::Postings,
             SegmentComponent::Positions,
             SegmentComponent::FastFields,
@@ -42,6 +48,8 @@ impl SegmentComponent {
             SegmentComponent::Store,
             SegmentComponent::TempStore,
             SegmentComponent::Delete,
+            SegmentComponent::SoftDelete,
+            SegmentComponent::ColumnStats,
         ];
         SEGMENT_COMPONENTS.iter()
     }
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt;
+use std::hash::Hasher;
 use std::str::FromStr;
 #[cfg(test)]
 use std::sync::atomic;
@@ -49,6 +50,19 @@ impl SegmentId {
         SegmentId(create_uuid())
     }
 
+    /// Derives a `SegmentId` from `seed`, by hashing it.
+    ///
+    /// The same seed always gives the same id.
+    pub(crate) fn derive_from(seed: &[u8]) -> SegmentId {
+        let hash = |salt: u8| {
+            let mut hasher = fnv::FnvHasher::default();
+            hasher.write_u8(salt);
+            hasher.write(seed);
+            hasher.finish()
+        };
+        SegmentId(Uuid::from_u64_pair(hash(0), hash(1)))
+    }
+
     /// Returns a shorter identifier of the segment.
     ///
     /// We are using UUID4, so only 6 bits are fixed,
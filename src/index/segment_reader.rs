@@ -1,22 +1,37 @@
+use std::any::Any;
 use std::collections::HashMap;
 use std::ops::BitOrAssign;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::{fmt, io};
 
+use common::ByteCount;
 use fnv::FnvHashMap;
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 
-use crate::directory::{CompositeFile, FileSlice};
+use crate::directory::error::OpenReadError;
+use crate::directory::{CompositeFile, Directory, FileProtection, FileSlice, ManagedDirectory};
 use crate::error::DataCorruption;
-use crate::fastfield::{intersect_alive_bitsets, AliveBitSet, FacetReader, FastFieldReaders};
+use crate::fastfield::{
+    intersect_alive_bitsets, AliveBitSet, ColumnStats, FacetReader, FastFieldReaders,
+    SegmentColumnStats,
+};
 use crate::fieldnorm::{FieldNormReader, FieldNormReaders};
-use crate::index::{InvertedIndexReader, Segment, SegmentComponent, SegmentId};
+use crate::index::component_set::ComponentLoading;
+use crate::index::{
+    ComponentSet, InvertedIndexReader, Segment, SegmentComponent, SegmentId,
+    SegmentReaderExtensions,
+};
 use crate::json_utils::json_path_sep_to_dot;
+use crate::postings::PositionsPostings;
+use crate::query::{PrefixExpansionBudget, PrefixExpansionCache};
 use crate::schema::{Field, IndexRecordOption, Schema, Type};
-use crate::space_usage::SegmentSpaceUsage;
+use crate::space_usage::{ComponentsSpaceUsage, SegmentSpaceUsage};
 use crate::store::StoreReader;
 use crate::termdict::TermDictionary;
-use crate::{DocId, Opstamp};
+use crate::tokenizer::TokenizerManager;
+use crate::{DocId, Opstamp, TantivyError};
 
 /// Entry point to access all of the datastructures of the `Segment`
 ///
@@ -31,22 +46,28 @@ use crate::{DocId, Opstamp};
 #[derive(Clone)]
 pub struct SegmentReader {
     inv_idx_reader_cache: Arc<RwLock<HashMap<Field, Arc<InvertedIndexReader>>>>,
+    extensions: SegmentReaderExtensions,
+    prefix_expansion_cache: PrefixExpansionCache,
 
     segment_id: SegmentId,
+    // The segment meta is not kept, as tracked segment metas prevent the garbage collection of
+    // their files.
+    segment_files: Arc<[PathBuf]>,
     delete_opstamp: Option<Opstamp>,
 
     max_doc: DocId,
     num_docs: DocId,
 
-    termdict_composite: CompositeFile,
-    postings_composite: CompositeFile,
-    positions_composite: CompositeFile,
-    fast_fields_readers: FastFieldReaders,
-    fieldnorm_readers: FieldNormReaders,
+    components: Arc<SegmentComponents>,
+    column_stats: Option<Arc<SegmentColumnStats>>,
 
-    store_file: FileSlice,
     alive_bitset_opt: Option<AliveBitSet>,
+    /// Documents that are not soft-deleted.
+    not_soft_deleted_bitset_opt: Option<AliveBitSet>,
+    num_soft_deleted_docs: DocId,
+    soft_delete_tombstone_fields: Arc<[String]>,
     schema: Schema,
+    tokenizers: TokenizerManager,
 }
 
 impl SegmentReader {
@@ -67,6 +88,11 @@ impl SegmentReader {
         &self.schema
     }
 
+    /// Returns the tokenizers of the index this segment belongs to.
+    pub(crate) fn tokenizers(&self) -> &TokenizerManager {
+        &self.tokenizers
+    }
+
     /// Return the number of documents that have been
     /// deleted in the segment.
     pub fn num_deleted_docs(&self) -> DocId {
@@ -87,9 +113,18 @@ impl SegmentReader {
     /// declared as a fast field in the schema.
     ///
     /// # Panics
-    /// May panic if the index is corrupted.
+    /// May panic if the index is corrupted, or if the fast fields were not loaded with the reader
+    /// and the component loading is strict. See [`SegmentReader::try_fast_fields`].
     pub fn fast_fields(&self) -> &FastFieldReaders {
-        &self.fast_fields_readers
+        self.try_fast_fields()
+            .unwrap_or_else(|err| panic!("Failed to open the fast fields: {err}"))
+    }
+
+    /// Accessor to a segment's fast field readers, returning an error if they cannot be opened.
+    ///
+    /// See [`IndexReaderBuilder::load_components`](crate::IndexReaderBuilder::load_components).
+    pub fn try_fast_fields(&self) -> crate::Result<&FastFieldReaders> {
+        self.components.fast_fields()
     }
 
     /// Accessor to the `FacetReader` associated with a given `Field`.
@@ -102,7 +137,7 @@ impl SegmentReader {
                 "`{field_name}` is not a facet field.`"
             )));
         }
-        let Some(facet_column) = self.fast_fields().str(field_name)? else {
+        let Some(facet_column) = self.try_fast_fields()?.str(field_name)? else {
             panic!("Facet Field `{field_name}` is missing. This should not happen");
         };
         Ok(FacetReader::new(facet_column))
@@ -116,19 +151,29 @@ impl SegmentReader {
     /// They are simply stored as a fast field, serialized in
     /// the `.fieldnorm` file of the segment.
     pub fn get_fieldnorms_reader(&self, field: Field) -> crate::Result<FieldNormReader> {
-        self.fieldnorm_readers.get_field(field)?.ok_or_else(|| {
-            let field_name = self.schema.get_field_name(field);
-            let err_msg = format!(
-                "Field norm not found for field {field_name:?}. Was the field set to record norm \
-                 during indexing?"
-            );
-            crate::TantivyError::SchemaError(err_msg)
-        })
+        self.components
+            .fieldnorms()?
+            .get_field(field)?
+            .ok_or_else(|| {
+                let field_name = self.schema.get_field_name(field);
+                let err_msg = format!(
+                    "Field norm not found for field {field_name:?}. Was the field set to record \
+                     norm during indexing?"
+                );
+                crate::TantivyError::SchemaError(err_msg)
+            })
     }
 
     #[doc(hidden)]
     pub fn fieldnorms_readers(&self) -> &FieldNormReaders {
-        &self.fieldnorm_readers
+        self.try_fieldnorms_readers()
+            .unwrap_or_else(|err| panic!("Failed to open the field norms: {err}"))
+    }
+
+    /// Accessor to the segment's field norm readers, returning an error if they cannot be opened.
+    #[doc(hidden)]
+    pub fn try_fieldnorms_readers(&self) -> crate::Result<&FieldNormReaders> {
+        self.components.fieldnorms()
     }
 
     /// Accessor to the segment's [`StoreReader`](crate::store::StoreReader).
@@ -136,7 +181,20 @@ impl SegmentReader {
     /// `cache_num_blocks` sets the number of decompressed blocks to be cached in an LRU.
     /// The size of blocks is configurable, this should be reflexted in the
     pub fn get_store_reader(&self, cache_num_blocks: usize) -> io::Result<StoreReader> {
-        StoreReader::open(self.store_file.clone(), cache_num_blocks)
+        let store_file = self.components.store_file().map_err(io::Error::other)?;
+        StoreReader::open(store_file.clone(), cache_num_blocks)
+    }
+
+    /// Same as [`SegmentReader::get_store_reader`], without converting the errors to io errors.
+    pub(crate) fn open_store_reader(&self, cache_num_blocks: usize) -> crate::Result<StoreReader> {
+        let store_file = self.components.store_file()?;
+        Ok(StoreReader::open(store_file.clone(), cache_num_blocks)?)
+    }
+
+    /// Returns true if the `components` are loaded, either because they were loaded when the
+    /// reader was opened, or because they were already accessed.
+    pub(crate) fn is_loaded(&self, components: ComponentSet) -> bool {
+        self.components.loaded().contains(components)
     }
 
     /// Open a new segment for reading.
@@ -149,31 +207,50 @@ impl SegmentReader {
         segment: &Segment,
         custom_bitset: Option<AliveBitSet>,
     ) -> crate::Result<SegmentReader> {
-        let termdict_file = segment.open_read(SegmentComponent::Terms)?;
-        let termdict_composite = CompositeFile::open(&termdict_file)?;
+        Self::open_with_component_loading(segment, custom_bitset, ComponentLoading::default())
+    }
 
-        let store_file = segment.open_read(SegmentComponent::Store)?;
+    /// Opens a new segment for reading, loading the components defined by `component_loading`.
+    pub(crate) fn open_with_component_loading(
+        segment: &Segment,
+        custom_bitset: Option<AliveBitSet>,
+        component_loading: ComponentLoading,
+    ) -> crate::Result<SegmentReader> {
+        let schema = segment.schema();
+        let components = SegmentComponents::new(segment);
+        if component_loading
+            .components
+            .contains(ComponentSet::POSTINGS)
+        {
+            components.inverted_index_files()?;
+        }
+        if component_loading.components.contains(ComponentSet::STORE) {
+            components.store_file()?;
+        }
 
         crate::fail_point!("SegmentReader::open#middle");
 
-        let postings_file = segment.open_read(SegmentComponent::Postings)?;
-        let postings_composite = CompositeFile::open(&postings_file)?;
-
-        let positions_composite = {
-            if let Ok(positions_file) = segment.open_read(SegmentComponent::Positions) {
-                CompositeFile::open(&positions_file)?
-            } else {
-                CompositeFile::empty()
-            }
+        if component_loading
+            .components
+            .contains(ComponentSet::FAST_FIELDS)
+        {
+            components.fast_fields()?;
+        }
+        if component_loading
+            .components
+            .contains(ComponentSet::FIELD_NORMS)
+        {
+            components.fieldnorms()?;
+        }
+        let components = components.finish_opening(component_loading.strict);
+        // The column statistics are only written for the indexes listing fields to compute them
+        // for, so the other indexes do not pay for looking up their file.
+        let column_stats = if segment.index().settings().column_stats_fields.is_empty() {
+            None
+        } else {
+            SegmentColumnStats::open(segment)?.map(Arc::new)
         };
 
-        let schema = segment.schema();
-
-        let fast_fields_data = segment.open_read(SegmentComponent::FastFields)?;
-        let fast_fields_readers = FastFieldReaders::open(fast_fields_data, schema.clone())?;
-        let fieldnorm_data = segment.open_read(SegmentComponent::FieldNorms)?;
-        let fieldnorm_readers = FieldNormReaders::open(fieldnorm_data)?;
-
         let original_bitset = if segment.meta().has_deletes() {
             let alive_doc_file_slice = segment.open_read(SegmentComponent::Delete)?;
             let alive_doc_data = alive_doc_file_slice.read_bytes()?;
@@ -184,6 +261,14 @@ impl SegmentReader {
 
         let alive_bitset_opt = intersect_alive_bitset(original_bitset, custom_bitset);
 
+        let not_soft_deleted_bitset_opt = if segment.meta().has_soft_deletes() {
+            let soft_delete_file_slice = segment.open_read(SegmentComponent::SoftDelete)?;
+            let soft_delete_data = soft_delete_file_slice.read_bytes()?;
+            Some(AliveBitSet::open(soft_delete_data))
+        } else {
+            None
+        };
+
         let max_doc = segment.meta().max_doc();
         let num_docs = alive_bitset_opt
             .as_ref()
@@ -192,21 +277,90 @@ impl SegmentReader {
 
         Ok(SegmentReader {
             inv_idx_reader_cache: Default::default(),
+            extensions: Default::default(),
+            prefix_expansion_cache: Default::default(),
             num_docs,
             max_doc,
-            termdict_composite,
-            postings_composite,
-            fast_fields_readers,
-            fieldnorm_readers,
+            components: Arc::new(components),
+            column_stats,
             segment_id: segment.id(),
+            segment_files: segment.meta().list_files().into_iter().collect(),
             delete_opstamp: segment.meta().delete_opstamp(),
-            store_file,
             alive_bitset_opt,
-            positions_composite,
+            not_soft_deleted_bitset_opt,
+            num_soft_deleted_docs: segment.meta().num_soft_deleted_docs(),
+            soft_delete_tombstone_fields: segment.meta().soft_delete_tombstone_fields().into(),
             schema,
+            tokenizers: segment.index().tokenizers().clone(),
         })
     }
 
+    /// Returns a copy of this reader, in which the documents missing from `alive_bitset` are
+    /// considered deleted as well.
+    pub(crate) fn with_alive_bitset(&self, alive_bitset: AliveBitSet) -> SegmentReader {
+        let alive_bitset_opt =
+            intersect_alive_bitset(self.alive_bitset_opt.clone(), Some(alive_bitset));
+        let num_docs = alive_bitset_opt
+            .as_ref()
+            .map(|alive_bitset| alive_bitset.num_alive_docs() as u32)
+            .unwrap_or(self.max_doc);
+        SegmentReader {
+            num_docs,
+            alive_bitset_opt,
+            // The extensions may depend on the alive documents.
+            extensions: Default::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this reader, in which the soft-deleted documents are considered deleted
+    /// as well.
+    pub(crate) fn without_soft_deleted(&self) -> SegmentReader {
+        match &self.not_soft_deleted_bitset_opt {
+            Some(not_soft_deleted_bitset) => {
+                self.with_alive_bitset(not_soft_deleted_bitset.clone())
+            }
+            None => self.clone(),
+        }
+    }
+
+    /// Returns the statistics of the values of a fast field, computed when the segment was
+    /// written.
+    ///
+    /// Returns `None` if the field was not listed in
+    /// [`IndexSettings::column_stats_fields`](crate::IndexSettings::column_stats_fields) when the
+    /// segment was written. The statistics cover all of the documents of the segment, including
+    /// the ones deleted since.
+    pub fn column_stats(&self, field: Field) -> Option<&ColumnStats> {
+        let field_name = self.schema.get_field_name(field);
+        self.column_stats.as_ref()?.get(field_name)
+    }
+
+    /// Returns the files of the segment this reader was opened from.
+    pub(crate) fn segment_files(&self) -> &[PathBuf] {
+        &self.segment_files
+    }
+
+    /// Returns the extension of type `T` of the segment, e.g. a cache of an aggregation, creating
+    /// it on first access. See [`SegmentReaderExtensions`].
+    pub(crate) fn extension<T: Any + Default + Send + Sync>(&self) -> Arc<T> {
+        self.extensions.get_or_default::<T>()
+    }
+
+    /// Returns the cache of the prefix expansions used by the prefix queries.
+    pub(crate) fn prefix_expansion_cache(&self) -> &PrefixExpansionCache {
+        &self.prefix_expansion_cache
+    }
+
+    /// Returns a copy of this reader whose prefix expansion cache is bounded by `budget`.
+    pub(crate) fn with_prefix_expansion_budget(
+        mut self,
+        budget: PrefixExpansionBudget,
+    ) -> SegmentReader {
+        self.prefix_expansion_cache = PrefixExpansionCache::with_budget(budget);
+        self
+    }
+
     /// Returns a field reader associated with the field given in argument.
     /// If the field was not present in the index during indexing time,
     /// the InvertedIndexReader is empty.
@@ -236,7 +390,8 @@ impl SegmentReader {
             warn!("Field {:?} does not seem indexed.", field_entry.name());
         }
 
-        let postings_file_opt = self.postings_composite.open_read(field);
+        let inverted_index_files = self.components.inverted_index_files()?;
+        let postings_file_opt = inverted_index_files.postings_composite.open_read(field);
 
         if postings_file_opt.is_none() || record_option_opt.is_none() {
             // no documents in the segment contained this field.
@@ -250,8 +405,10 @@ impl SegmentReader {
         let record_option = record_option_opt.unwrap();
         let postings_file = postings_file_opt.unwrap();
 
-        let termdict_file: FileSlice =
-            self.termdict_composite.open_read(field).ok_or_else(|| {
+        let termdict_file: FileSlice = inverted_index_files
+            .termdict_composite
+            .open_read(field)
+            .ok_or_else(|| {
                 DataCorruption::comment_only(format!(
                     "Failed to open field {:?}'s term dictionary in the composite file. Has the \
                      schema been modified?",
@@ -259,14 +416,17 @@ impl SegmentReader {
                 ))
             })?;
 
-        let positions_file = self.positions_composite.open_read(field).ok_or_else(|| {
-            let error_msg = format!(
-                "Failed to open field {:?}'s positions in the composite file. Has the schema been \
-                 modified?",
-                field_entry.name()
-            );
-            DataCorruption::comment_only(error_msg)
-        })?;
+        let positions_file = inverted_index_files
+            .positions_composite
+            .open_read(field)
+            .ok_or_else(|| {
+                let error_msg = format!(
+                    "Failed to open field {:?}'s positions in the composite file. Has the schema \
+                     been modified?",
+                    field_entry.name()
+                );
+                DataCorruption::comment_only(error_msg)
+            })?;
 
         let inv_idx_reader = Arc::new(InvertedIndexReader::new(
             TermDictionary::open(termdict_file)?,
@@ -285,6 +445,21 @@ impl SegmentReader {
         Ok(inv_idx_reader)
     }
 
+    /// Returns the postings of the text `term` in the text field `field`, with the positions of
+    /// the term in each value of the field, or `None` if the term does not appear in the segment.
+    ///
+    /// `term` is looked up as is, it is not tokenized. See [`PositionsPostings`] for the
+    /// requirements on the field to tell the values apart.
+    ///
+    /// Returns an error if the field is not a text field indexed with positions.
+    pub fn positions_reader(
+        &self,
+        field: Field,
+        term: &str,
+    ) -> crate::Result<Option<PositionsPostings>> {
+        PositionsPostings::open(self, field, term)
+    }
+
     /// Returns the list of fields that have been indexed in the segment.
     /// The field list includes the field defined in the schema as well as the fields
     /// that have been indexed as a part of a JSON field.
@@ -352,7 +527,7 @@ impl SegmentReader {
             }
         }
         let mut fast_fields: Vec<FieldMetadata> = self
-            .fast_fields()
+            .try_fast_fields()?
             .columnar()
             .iter_columns()?
             .map(|(mut field_name, handle)| {
@@ -405,6 +580,31 @@ impl SegmentReader {
             .unwrap_or(false)
     }
 
+    /// Returns the number of soft-deleted documents of the segment.
+    ///
+    /// See [`IndexWriter::soft_delete_term`](crate::IndexWriter::soft_delete_term).
+    pub fn num_soft_deleted_docs(&self) -> DocId {
+        self.num_soft_deleted_docs
+    }
+
+    /// Returns true if the `doc` is marked as soft-deleted.
+    pub fn is_soft_deleted(&self, doc: DocId) -> bool {
+        self.not_soft_deleted_bitset_opt
+            .as_ref()
+            .map(|not_soft_deleted_bitset| not_soft_deleted_bitset.is_deleted(doc))
+            .unwrap_or(false)
+    }
+
+    /// Returns the documents that are not soft-deleted, if some documents are soft-deleted.
+    pub(crate) fn not_soft_deleted_bitset(&self) -> Option<&AliveBitSet> {
+        self.not_soft_deleted_bitset_opt.as_ref()
+    }
+
+    /// Returns the tombstone fields of the soft deletes applied to the segment.
+    pub fn soft_delete_tombstone_fields(&self) -> &[String] {
+        &self.soft_delete_tombstone_fields
+    }
+
     /// Returns an iterator that will iterate over the alive document ids
     pub fn doc_ids_alive(&self) -> Box<dyn Iterator<Item = DocId> + Send + '_> {
         if let Some(alive_bitset) = &self.alive_bitset_opt {
@@ -416,22 +616,256 @@ impl SegmentReader {
 
     /// Summarize total space usage of this segment.
     pub fn space_usage(&self) -> io::Result<SegmentSpaceUsage> {
+        let inverted_index_files = self
+            .components
+            .inverted_index_files()
+            .map_err(io::Error::other)?;
+        let fast_fields = self.try_fast_fields().map_err(io::Error::other)?;
+        let fieldnorms = self.components.fieldnorms().map_err(io::Error::other)?;
         Ok(SegmentSpaceUsage::new(
             self.num_docs(),
-            self.termdict_composite.space_usage(),
-            self.postings_composite.space_usage(),
-            self.positions_composite.space_usage(),
-            self.fast_fields_readers.space_usage(self.schema())?,
-            self.fieldnorm_readers.space_usage(),
+            inverted_index_files.termdict_composite.space_usage(),
+            inverted_index_files.postings_composite.space_usage(),
+            inverted_index_files.positions_composite.space_usage(),
+            fast_fields.space_usage(self.schema())?,
+            fieldnorms.space_usage(),
             self.get_store_reader(0)?.space_usage(),
             self.alive_bitset_opt
                 .as_ref()
                 .map(AliveBitSet::space_usage)
-                .unwrap_or_default(),
+                .unwrap_or_default()
+                + self
+                    .not_soft_deleted_bitset_opt
+                    .as_ref()
+                    .map(AliveBitSet::space_usage)
+                    .unwrap_or_default(),
+            self.components.files_space_usage(&self.segment_files),
         ))
     }
 }
 
+/// Composite files of the inverted index of a segment.
+struct InvertedIndexFiles {
+    termdict_composite: CompositeFile,
+    postings_composite: CompositeFile,
+    positions_composite: CompositeFile,
+}
+
+/// Data structures of a segment, opened either when the [`SegmentReader`] is opened, or on first
+/// access.
+struct SegmentComponents {
+    directory: ManagedDirectory,
+    segment_id: SegmentId,
+    max_doc: DocId,
+    schema: Schema,
+    termdict_path: PathBuf,
+    postings_path: PathBuf,
+    positions_path: PathBuf,
+    fast_fields_path: PathBuf,
+    fieldnorms_path: PathBuf,
+    store_path: PathBuf,
+    column_stats_path: PathBuf,
+    /// If true, the components not loaded when the reader was opened cannot be accessed.
+    strict: bool,
+    inverted_index_files: OnceCell<InvertedIndexFiles>,
+    fast_fields: OnceCell<FastFieldReaders>,
+    fieldnorms: OnceCell<FieldNormReaders>,
+    store_file: OnceCell<FileSlice>,
+    /// Protects the files of the components that were not loaded when the reader was opened
+    /// from garbage collection, so that they can still be loaded later on.
+    _file_protections: Vec<FileProtection>,
+}
+
+impl SegmentComponents {
+    /// Creates the components of `segment`, none of them being loaded yet.
+    fn new(segment: &Segment) -> SegmentComponents {
+        SegmentComponents {
+            directory: segment.index().directory().clone(),
+            segment_id: segment.id(),
+            max_doc: segment.meta().max_doc(),
+            schema: segment.schema(),
+            termdict_path: segment.relative_path(SegmentComponent::Terms),
+            postings_path: segment.relative_path(SegmentComponent::Postings),
+            positions_path: segment.relative_path(SegmentComponent::Positions),
+            fast_fields_path: segment.relative_path(SegmentComponent::FastFields),
+            fieldnorms_path: segment.relative_path(SegmentComponent::FieldNorms),
+            store_path: segment.relative_path(SegmentComponent::Store),
+            column_stats_path: segment.relative_path(SegmentComponent::ColumnStats),
+            strict: false,
+            inverted_index_files: OnceCell::new(),
+            fast_fields: OnceCell::new(),
+            fieldnorms: OnceCell::new(),
+            store_file: OnceCell::new(),
+            _file_protections: Vec::new(),
+        }
+    }
+
+    /// Ends the loading of the components done when the reader is opened.
+    ///
+    /// In lazy mode, the files of the components not loaded yet are protected from garbage
+    /// collection. This is called while the reader is being opened, when the segment files are
+    /// still alive.
+    fn finish_opening(mut self, strict: bool) -> SegmentComponents {
+        self.strict = strict;
+        if strict {
+            return self;
+        }
+        let loaded = self.loaded();
+        let component_paths = [
+            (ComponentSet::POSTINGS, &self.termdict_path),
+            (ComponentSet::POSTINGS, &self.postings_path),
+            (ComponentSet::POSTINGS, &self.positions_path),
+            (ComponentSet::FAST_FIELDS, &self.fast_fields_path),
+            (ComponentSet::FIELD_NORMS, &self.fieldnorms_path),
+            (ComponentSet::STORE, &self.store_path),
+        ];
+        let file_protections = component_paths
+            .into_iter()
+            .filter(|(component, _)| !loaded.contains(*component))
+            .map(|(_, path)| self.directory.protect_file_from_delete(path))
+            .collect();
+        self._file_protections = file_protections;
+        self
+    }
+
+    /// Returns the size of the files of each component, given all the files of the segment.
+    fn files_space_usage(&self, segment_files: &[PathBuf]) -> ComponentsSpaceUsage {
+        // The underlying directory is read so that the footers are included. The positions and
+        // column statistics files are optional.
+        let file_num_bytes = |path: &Path| -> ByteCount {
+            self.directory
+                .underlying_directory()
+                .open_read(path)
+                .map(|file| file.num_bytes())
+                .unwrap_or_default()
+        };
+        let component_paths = [
+            &self.termdict_path,
+            &self.postings_path,
+            &self.positions_path,
+            &self.fast_fields_path,
+            &self.fieldnorms_path,
+            &self.store_path,
+            &self.column_stats_path,
+        ];
+        // The remaining files of the segment are its delete files.
+        let deletes = segment_files
+            .iter()
+            .filter(|path| !component_paths.contains(path))
+            .map(|path| file_num_bytes(path))
+            .sum();
+        ComponentsSpaceUsage {
+            termdict: file_num_bytes(&self.termdict_path),
+            postings: file_num_bytes(&self.postings_path),
+            positions: file_num_bytes(&self.positions_path),
+            fast_fields: file_num_bytes(&self.fast_fields_path),
+            fieldnorms: file_num_bytes(&self.fieldnorms_path),
+            store: file_num_bytes(&self.store_path),
+            deletes,
+            column_stats: file_num_bytes(&self.column_stats_path),
+        }
+    }
+
+    fn loaded(&self) -> ComponentSet {
+        let mut loaded = ComponentSet::NONE;
+        if self.inverted_index_files.get().is_some() {
+            loaded |= ComponentSet::POSTINGS;
+        }
+        if self.fast_fields.get().is_some() {
+            loaded |= ComponentSet::FAST_FIELDS;
+        }
+        if self.fieldnorms.get().is_some() {
+            loaded |= ComponentSet::FIELD_NORMS;
+        }
+        if self.store_file.get().is_some() {
+            loaded |= ComponentSet::STORE;
+        }
+        loaded
+    }
+
+    fn check_loadable(&self, component: ComponentSet) -> crate::Result<()> {
+        if self.strict {
+            return Err(TantivyError::ComponentNotLoaded {
+                segment_id: self.segment_id,
+                component,
+            });
+        }
+        Ok(())
+    }
+
+    /// Opens a file of `component`, reporting a file whose footer is invalid as corrupted.
+    fn open_component(&self, component: ComponentSet, path: &Path) -> crate::Result<FileSlice> {
+        match self.directory.open_read(path) {
+            Ok(file) => Ok(file),
+            Err(OpenReadError::IoError { io_error, .. })
+                if io_error.kind() == io::ErrorKind::InvalidData =>
+            {
+                Err(self.corruption(component, io_error))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Reports that the files of `component` could be opened, but not parsed.
+    fn corruption(&self, component: ComponentSet, err: impl ToString) -> TantivyError {
+        TantivyError::SegmentCorruption {
+            segment_id: self.segment_id,
+            component,
+            reason: err.to_string(),
+        }
+    }
+
+    fn inverted_index_files(&self) -> crate::Result<&InvertedIndexFiles> {
+        self.inverted_index_files.get_or_try_init(|| {
+            self.check_loadable(ComponentSet::POSTINGS)?;
+            let termdict_file = self.open_component(ComponentSet::POSTINGS, &self.termdict_path)?;
+            let postings_file = self.open_component(ComponentSet::POSTINGS, &self.postings_path)?;
+            let positions_composite = match self.directory.open_read(&self.positions_path) {
+                Ok(positions_file) => CompositeFile::open(&positions_file)
+                    .map_err(|err| self.corruption(ComponentSet::POSTINGS, err))?,
+                Err(_) => CompositeFile::empty(),
+            };
+            Ok(InvertedIndexFiles {
+                termdict_composite: CompositeFile::open(&termdict_file)
+                    .map_err(|err| self.corruption(ComponentSet::POSTINGS, err))?,
+                postings_composite: CompositeFile::open(&postings_file)
+                    .map_err(|err| self.corruption(ComponentSet::POSTINGS, err))?,
+                positions_composite,
+            })
+        })
+    }
+
+    fn fast_fields(&self) -> crate::Result<&FastFieldReaders> {
+        self.fast_fields.get_or_try_init(|| {
+            self.check_loadable(ComponentSet::FAST_FIELDS)?;
+            let fast_fields_data =
+                self.open_component(ComponentSet::FAST_FIELDS, &self.fast_fields_path)?;
+            Ok(
+                FastFieldReaders::open(fast_fields_data, self.schema.clone())
+                    .map_err(|err| self.corruption(ComponentSet::FAST_FIELDS, err))?
+                    .with_max_doc(self.max_doc),
+            )
+        })
+    }
+
+    fn fieldnorms(&self) -> crate::Result<&FieldNormReaders> {
+        self.fieldnorms.get_or_try_init(|| {
+            self.check_loadable(ComponentSet::FIELD_NORMS)?;
+            let fieldnorm_data =
+                self.open_component(ComponentSet::FIELD_NORMS, &self.fieldnorms_path)?;
+            FieldNormReaders::open(fieldnorm_data)
+                .map_err(|err| self.corruption(ComponentSet::FIELD_NORMS, err))
+        })
+    }
+
+    fn store_file(&self) -> crate::Result<&FileSlice> {
+        self.store_file.get_or_try_init(|| {
+            self.check_loadable(ComponentSet::STORE)?;
+            self.open_component(ComponentSet::STORE, &self.store_path)
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 /// FieldMetadata
 pub struct FieldMetadata {
@@ -516,7 +950,7 @@ impl fmt::Debug for SegmentReader {
 mod test {
     use super::*;
     use crate::index::Index;
-    use crate::schema::{SchemaBuilder, Term, STORED, TEXT};
+    use crate::schema::{SchemaBuilder, Term, FAST, STORED, TEXT};
     use crate::IndexWriter;
 
     #[test]
@@ -636,6 +1070,38 @@ mod test {
         assert_eq!(res2, field_metadata_expected);
     }
 
+    #[test]
+    fn test_open_corrupted_segment() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let name = schema_builder.add_text_field("name", TEXT | STORED);
+        let num = schema_builder.add_u64_field("num", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(name => "tantivy", num => 1u64))?;
+        index_writer.commit()?;
+
+        let segment = index.searchable_segments()?.into_iter().next().unwrap();
+        let fast_fields_path = segment.relative_path(SegmentComponent::FastFields);
+        index
+            .directory()
+            .atomic_write(&fast_fields_path, b"this is not a columnar")?;
+
+        let err = SegmentReader::open(&segment).err().unwrap();
+        assert!(
+            matches!(
+                err,
+                TantivyError::SegmentCorruption {
+                    segment_id,
+                    component: ComponentSet::FAST_FIELDS,
+                    ..
+                } if segment_id == segment.id()
+            ),
+            "{err:?}"
+        );
+        assert_eq!(err.error_code(), "segment_corruption");
+        Ok(())
+    }
+
     #[test]
     fn test_num_alive() -> crate::Result<()> {
         let mut schema_builder = Schema::builder();
@@ -651,8 +1117,8 @@ mod test {
             index_writer.add_document(doc!(name => "jockey"))?;
             index_writer.add_document(doc!(name => "cap"))?;
             // we should now have one segment with two docs
-            index_writer.delete_term(Term::from_field_text(name, "horse"));
-            index_writer.delete_term(Term::from_field_text(name, "cap"));
+            index_writer.delete_term(Term::from_field_text(name, "horse"))?;
+            index_writer.delete_term(Term::from_field_text(name, "cap"))?;
 
             // ok, now we should have a deleted doc
             index_writer.commit()?;
@@ -682,8 +1148,8 @@ mod test {
 
         {
             let mut index_writer2: IndexWriter = index.writer(50_000_000)?;
-            index_writer2.delete_term(Term::from_field_text(name, "horse"));
-            index_writer2.delete_term(Term::from_field_text(name, "cap"));
+            index_writer2.delete_term(Term::from_field_text(name, "horse"))?;
+            index_writer2.delete_term(Term::from_field_text(name, "cap"))?;
 
             // ok, now we should have a deleted doc
             index_writer2.commit()?;
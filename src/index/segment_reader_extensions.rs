@@ -0,0 +1,65 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Per-segment state attached to a [`SegmentReader`](crate::SegmentReader) by the other modules,
+/// e.g. caches derived from the segment data.
+///
+/// The extensions are keyed by their type, and shared by the clones of the reader, which live as
+/// long as the searcher generation. The readers seeing other alive documents, see
+/// [`SegmentReader::with_alive_bitset()`](crate::SegmentReader::with_alive_bitset), get their own
+/// extensions, as the extensions may depend on the alive documents.
+#[derive(Clone, Default)]
+pub(crate) struct SegmentReaderExtensions {
+    extensions: Arc<RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl SegmentReaderExtensions {
+    /// Returns the extension of type `T`, creating it with its default value on first access.
+    pub fn get_or_default<T: Any + Default + Send + Sync>(&self) -> Arc<T> {
+        let type_id = TypeId::of::<T>();
+        let extension = self
+            .extensions
+            .read()
+            .expect("Lock poisoned. This should never happen")
+            .get(&type_id)
+            .cloned();
+        let extension = match extension {
+            Some(extension) => extension,
+            None => self
+                .extensions
+                .write()
+                .expect("Lock poisoned. This should never happen")
+                .entry(type_id)
+                .or_insert_with(|| Arc::new(T::default()))
+                .clone(),
+        };
+        extension
+            .downcast::<T>()
+            .expect("The extensions are keyed by their type.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::SegmentReaderExtensions;
+
+    #[test]
+    fn test_segment_reader_extensions_are_shared_by_type() {
+        let extensions = SegmentReaderExtensions::default();
+        extensions
+            .get_or_default::<AtomicUsize>()
+            .fetch_add(1, Ordering::Relaxed);
+        let extensions_clone = extensions.clone();
+        let counter = extensions_clone.get_or_default::<AtomicUsize>();
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+        assert!(Arc::ptr_eq(
+            &counter,
+            &extensions.get_or_default::<AtomicUsize>()
+        ));
+        assert!(extensions.get_or_default::<Vec<u32>>().is_empty());
+    }
+}
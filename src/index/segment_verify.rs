@@ -0,0 +1,711 @@
+use std::path::PathBuf;
+
+use columnar::ColumnarReader;
+
+use crate::directory::error::OpenReadError;
+use crate::directory::{Directory, ManagedDirectory, INDEX_WRITER_LOCK};
+use crate::fastfield::AliveBitSet;
+use crate::fieldnorm::FieldNormReaders;
+use crate::index::{
+    ComponentLoading, ComponentSet, Index, Segment, SegmentComponent, SegmentId, SegmentReader,
+};
+use crate::indexer::segment_updater::save_metas;
+use crate::json_utils::json_path_sep_to_dot;
+use crate::schema::IndexRecordOption;
+use crate::store::StoreReader;
+use crate::{DocId, TantivyError};
+
+/// How thoroughly [`Segment::verify_with_options()`] checks the postings lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostingsCheck {
+    /// The postings lists are not checked.
+    Skip,
+    /// The postings list of every term is checked.
+    Full,
+    /// Only the postings list of one term out of `every_nth_term` is checked, in each field.
+    Sampled {
+        /// The sampling interval, in terms.
+        every_nth_term: u64,
+    },
+}
+
+/// Options of [`Segment::verify_with_options()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentVerifyOptions {
+    /// How the doc ids of the postings lists are checked against `max_doc`.
+    pub postings: PostingsCheck,
+}
+
+impl Default for SegmentVerifyOptions {
+    fn default() -> Self {
+        SegmentVerifyOptions {
+            postings: PostingsCheck::Full,
+        }
+    }
+}
+
+/// An inconsistency found by [`Segment::verify()`].
+///
+/// Unless stated otherwise, document counts are compared to the `max_doc` of the segment meta.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentVerifyIssue {
+    /// The footer checksum of a file of the segment does not match its content.
+    ChecksumMismatch {
+        /// Path of the file.
+        path: PathBuf,
+    },
+    /// The checksum of a file of the segment could not be computed.
+    FileUnreadable {
+        /// Path of the file.
+        path: PathBuf,
+        /// The error encountered.
+        error: String,
+    },
+    /// A component of the segment could not be opened or read.
+    ComponentUnreadable {
+        /// The component.
+        component: SegmentComponent,
+        /// The error encountered.
+        error: String,
+    },
+    /// The doc store does not have one document per doc id.
+    StoreNumDocs {
+        /// The number of documents in the doc store.
+        num_docs: u32,
+    },
+    /// The field norms of a field do not have one entry per doc id.
+    FieldNormNumDocs {
+        /// Name of the field.
+        field: String,
+        /// The number of entries of the field norms.
+        num_docs: u32,
+    },
+    /// The fast fields do not have one row per doc id.
+    FastFieldNumDocs {
+        /// The number of rows of the fast fields.
+        num_docs: u32,
+    },
+    /// A fast field column does not have one row per doc id.
+    ColumnNumDocs {
+        /// Name of the column.
+        column: String,
+        /// The number of rows of the column.
+        num_docs: u32,
+    },
+    /// A delete bitset does not have one bit per doc id.
+    BitsetLength {
+        /// Either [`SegmentComponent::Delete`] or [`SegmentComponent::SoftDelete`].
+        component: SegmentComponent,
+        /// The number of bits of the bitset.
+        num_bits: u32,
+    },
+    /// The number of deleted documents recorded in the segment meta does not match the alive
+    /// bitset.
+    ///
+    /// This can be fixed by [`Index::repair()`].
+    NumDeletedDocs {
+        /// The number of deleted documents recorded in the segment meta.
+        in_meta: u32,
+        /// The number of deleted documents in the alive bitset.
+        in_alive_bitset: u32,
+    },
+    /// A postings list contains a doc id greater or equal to `max_doc`.
+    PostingsDocOutOfRange {
+        /// Name of the field.
+        field: String,
+        /// The first out of range doc id found in the field.
+        doc: DocId,
+    },
+}
+
+/// Result of the consistency checks of a segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentVerifyReport {
+    /// Id of the segment.
+    pub segment_id: SegmentId,
+    /// `max_doc` of the segment, as recorded in its meta.
+    pub max_doc: u32,
+    /// The inconsistencies found. Empty if the segment is consistent.
+    pub issues: Vec<SegmentVerifyIssue>,
+}
+
+impl SegmentVerifyReport {
+    /// Returns true if no inconsistency was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns the number of deleted documents to record in the segment meta, if it can be
+    /// safely recomputed from the alive bitset.
+    fn num_deleted_docs_repair(&self) -> Option<(u32, u32)> {
+        let alive_bitset_is_damaged = self.issues.iter().any(|issue| match issue {
+            SegmentVerifyIssue::ChecksumMismatch { path }
+            | SegmentVerifyIssue::FileUnreadable { path, .. } => {
+                path.extension().is_some_and(|extension| extension == "del")
+            }
+            SegmentVerifyIssue::ComponentUnreadable { component, .. }
+            | SegmentVerifyIssue::BitsetLength { component, .. } => {
+                *component == SegmentComponent::Delete
+            }
+            _ => false,
+        });
+        if alive_bitset_is_damaged {
+            return None;
+        }
+        self.issues.iter().find_map(|issue| match issue {
+            SegmentVerifyIssue::NumDeletedDocs {
+                in_meta,
+                in_alive_bitset,
+            } => Some((*in_meta, *in_alive_bitset)),
+            _ => None,
+        })
+    }
+}
+
+/// Result of the consistency checks of all of the searchable segments of an index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexVerifyReport {
+    /// The report of each segment.
+    pub segments: Vec<SegmentVerifyReport>,
+}
+
+impl IndexVerifyReport {
+    /// Returns true if no inconsistency was found in any segment.
+    pub fn is_ok(&self) -> bool {
+        self.segments.iter().all(SegmentVerifyReport::is_ok)
+    }
+}
+
+/// A repair applied by [`Index::repair()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentRepair {
+    /// The number of deleted documents of the segment meta was recomputed from the alive bitset.
+    NumDeletedDocs {
+        /// Id of the segment.
+        segment_id: SegmentId,
+        /// The number of deleted documents previously recorded in the segment meta.
+        previous: u32,
+        /// The number of deleted documents now recorded in the segment meta.
+        repaired: u32,
+    },
+}
+
+pub(crate) fn verify_segment(
+    segment: &Segment,
+    options: &SegmentVerifyOptions,
+) -> SegmentVerifyReport {
+    let max_doc = segment.meta().max_doc();
+    let mut issues = Vec::new();
+    verify_checksums(segment, &mut issues);
+    let store_res = verify_store(segment, max_doc, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::Store, store_res);
+    let fieldnorms_res = verify_fieldnorms(segment, max_doc, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::FieldNorms, fieldnorms_res);
+    let fast_fields_res = verify_fast_fields(segment, max_doc, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::FastFields, fast_fields_res);
+    let alive_bitset_res = verify_alive_bitset(segment, max_doc, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::Delete, alive_bitset_res);
+    let soft_delete_res = verify_soft_delete_bitset(segment, max_doc, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::SoftDelete, soft_delete_res);
+    let postings_res = verify_postings(segment, max_doc, options.postings, &mut issues);
+    report_unreadable(&mut issues, SegmentComponent::Postings, postings_res);
+    SegmentVerifyReport {
+        segment_id: segment.id(),
+        max_doc,
+        issues,
+    }
+}
+
+fn report_unreadable(
+    issues: &mut Vec<SegmentVerifyIssue>,
+    component: SegmentComponent,
+    check_res: crate::Result<()>,
+) {
+    if let Err(err) = check_res {
+        issues.push(SegmentVerifyIssue::ComponentUnreadable {
+            component,
+            error: err.to_string(),
+        });
+    }
+}
+
+fn verify_checksums(segment: &Segment, issues: &mut Vec<SegmentVerifyIssue>) {
+    let directory: &ManagedDirectory = segment.index().directory();
+    let mut paths: Vec<PathBuf> = segment.meta().list_files().into_iter().collect();
+    paths.sort();
+    for path in paths {
+        match directory.validate_checksum(&path) {
+            Ok(true) => {}
+            Ok(false) => issues.push(SegmentVerifyIssue::ChecksumMismatch { path }),
+            // Not all of the components exist, e.g. if no field has positions.
+            Err(OpenReadError::FileDoesNotExist(_)) => {}
+            Err(err) => issues.push(SegmentVerifyIssue::FileUnreadable {
+                path,
+                error: err.to_string(),
+            }),
+        }
+    }
+}
+
+fn verify_store(
+    segment: &Segment,
+    max_doc: u32,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    let store_reader = StoreReader::open(segment.open_read(SegmentComponent::Store)?, 0)?;
+    let num_docs = store_reader
+        .block_checkpoints()
+        .last()
+        .map(|checkpoint| checkpoint.doc_range.end)
+        .unwrap_or(0);
+    if num_docs != max_doc {
+        issues.push(SegmentVerifyIssue::StoreNumDocs { num_docs });
+    }
+    Ok(())
+}
+
+fn verify_fieldnorms(
+    segment: &Segment,
+    max_doc: u32,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    let fieldnorm_readers =
+        FieldNormReaders::open(segment.open_read(SegmentComponent::FieldNorms)?)?;
+    let schema = segment.schema();
+    for (field, field_entry) in schema.fields() {
+        let Some(fieldnorm_reader) = fieldnorm_readers.get_field(field)? else {
+            continue;
+        };
+        let num_docs = fieldnorm_reader.num_docs();
+        if num_docs != max_doc {
+            issues.push(SegmentVerifyIssue::FieldNormNumDocs {
+                field: field_entry.name().to_string(),
+                num_docs,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn verify_fast_fields(
+    segment: &Segment,
+    max_doc: u32,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    let columnar = ColumnarReader::open(segment.open_read(SegmentComponent::FastFields)?)?;
+    if columnar.num_docs() != max_doc {
+        issues.push(SegmentVerifyIssue::FastFieldNumDocs {
+            num_docs: columnar.num_docs(),
+        });
+    }
+    for (mut column_name, column_handle) in columnar.list_columns()? {
+        let Some(column) = column_handle.open_u64_lenient()? else {
+            continue;
+        };
+        let num_docs = column.num_docs();
+        if num_docs != max_doc {
+            json_path_sep_to_dot(&mut column_name);
+            issues.push(SegmentVerifyIssue::ColumnNumDocs {
+                column: column_name,
+                num_docs,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn verify_alive_bitset(
+    segment: &Segment,
+    max_doc: u32,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    let meta = segment.meta();
+    if meta.delete_opstamp().is_some() {
+        let alive_bitset =
+            AliveBitSet::open(segment.open_read(SegmentComponent::Delete)?.read_bytes()?);
+        let num_bits = alive_bitset.bitset().max_value();
+        if num_bits != max_doc {
+            issues.push(SegmentVerifyIssue::BitsetLength {
+                component: SegmentComponent::Delete,
+                num_bits,
+            });
+        }
+        let in_alive_bitset = num_bits.saturating_sub(alive_bitset.num_alive_docs() as u32);
+        if in_alive_bitset != meta.num_deleted_docs() {
+            issues.push(SegmentVerifyIssue::NumDeletedDocs {
+                in_meta: meta.num_deleted_docs(),
+                in_alive_bitset,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn verify_soft_delete_bitset(
+    segment: &Segment,
+    max_doc: u32,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    if segment.meta().has_soft_deletes() {
+        let soft_delete_bitset = AliveBitSet::open(
+            segment
+                .open_read(SegmentComponent::SoftDelete)?
+                .read_bytes()?,
+        );
+        let num_bits = soft_delete_bitset.bitset().max_value();
+        if num_bits != max_doc {
+            issues.push(SegmentVerifyIssue::BitsetLength {
+                component: SegmentComponent::SoftDelete,
+                num_bits,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn verify_postings(
+    segment: &Segment,
+    max_doc: u32,
+    postings_check: PostingsCheck,
+    issues: &mut Vec<SegmentVerifyIssue>,
+) -> crate::Result<()> {
+    let every_nth_term = match postings_check {
+        PostingsCheck::Skip => return Ok(()),
+        PostingsCheck::Full => 1,
+        PostingsCheck::Sampled { every_nth_term } => every_nth_term.max(1),
+    };
+    // Only the postings are loaded, so that they can be checked even if other components are
+    // damaged.
+    let component_loading = ComponentLoading {
+        components: ComponentSet::POSTINGS,
+        strict: true,
+    };
+    let segment_reader =
+        SegmentReader::open_with_component_loading(segment, None, component_loading)?;
+    let schema = segment.schema();
+    for (field, field_entry) in schema.fields() {
+        if !field_entry.is_indexed() {
+            continue;
+        }
+        let inverted_index = segment_reader.inverted_index(field)?;
+        let mut term_stream = inverted_index.terms().stream()?;
+        'terms: while term_stream.advance() {
+            if term_stream.term_ord() % every_nth_term != 0 {
+                continue;
+            }
+            let mut block_postings = inverted_index
+                .read_block_postings_from_terminfo(term_stream.value(), IndexRecordOption::Basic)?;
+            while let Some(&last_doc) = block_postings.docs().last() {
+                // Doc ids are sorted within a block.
+                if last_doc >= max_doc {
+                    let doc = block_postings
+                        .docs()
+                        .iter()
+                        .copied()
+                        .find(|&doc| doc >= max_doc)
+                        .unwrap_or(last_doc);
+                    issues.push(SegmentVerifyIssue::PostingsDocOutOfRange {
+                        field: field_entry.name().to_string(),
+                        doc,
+                    });
+                    break 'terms;
+                }
+                block_postings.advance();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies the repairs of `report` that only recompute derived metadata, and returns them.
+///
+/// See [`Index::repair()`].
+pub(crate) fn repair_index(
+    index: &Index,
+    report: &IndexVerifyReport,
+) -> crate::Result<Vec<SegmentRepair>> {
+    let _directory_lock = index
+        .directory()
+        .acquire_lock(&INDEX_WRITER_LOCK)
+        .map_err(|err| {
+            TantivyError::LockFailure(
+                err,
+                Some(
+                    "An index can only be repaired while no `IndexWriter` is running.".to_string(),
+                ),
+            )
+        })?;
+    let mut index_meta = index.load_metas()?;
+    let mut repairs = Vec::new();
+    for segment_report in &report.segments {
+        let Some((previous, repaired)) = segment_report.num_deleted_docs_repair() else {
+            continue;
+        };
+        let Some(segment_meta) = index_meta
+            .segments
+            .iter_mut()
+            .find(|segment_meta| segment_meta.id() == segment_report.segment_id)
+        else {
+            continue;
+        };
+        let Some(delete_opstamp) = segment_meta.delete_opstamp() else {
+            continue;
+        };
+        // The report may be outdated.
+        if segment_meta.num_deleted_docs() != previous {
+            continue;
+        }
+        *segment_meta = segment_meta
+            .clone()
+            .with_delete_meta(repaired, delete_opstamp);
+        repairs.push(SegmentRepair::NumDeletedDocs {
+            segment_id: segment_report.segment_id,
+            previous,
+            repaired,
+        });
+    }
+    if !repairs.is_empty() {
+        save_metas(&index_meta, index.directory())?;
+    }
+    Ok(repairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PostingsCheck, SegmentRepair, SegmentVerifyIssue, SegmentVerifyOptions};
+    use crate::collector::TopDocs;
+    use crate::directory::{Directory, RamDirectory};
+    use crate::index::{Segment, SegmentComponent};
+    use crate::indexer::segment_updater::save_metas;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST, INDEXED, STORED, TEXT};
+    use crate::{Index, IndexSettings, IndexWriter, Order, TantivyError, Term};
+
+    /// Creates an index with a segment of 10 docs and a segment of 3 docs, in that order.
+    fn create_index(directory: &RamDirectory) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT | STORED);
+        let num = schema_builder.add_u64_field("num", FAST | INDEXED);
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            IndexSettings::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for num_docs in [10u64, 3u64] {
+            for i in 0..num_docs {
+                index_writer.add_document(doc!(text => "hello", num => i))?;
+            }
+            index_writer.commit()?;
+        }
+        index_writer.wait_merging_threads()?;
+        Ok(index)
+    }
+
+    /// Returns the large and the small segment of the index.
+    fn segments(index: &Index) -> crate::Result<(Segment, Segment)> {
+        let mut segments = index.searchable_segments()?;
+        segments.sort_by_key(|segment| std::cmp::Reverse(segment.meta().max_doc()));
+        let small = segments.pop().unwrap();
+        let large = segments.pop().unwrap();
+        Ok((large, small))
+    }
+
+    /// Replaces the content of a component of `target` by the one of `source`, footer included.
+    fn copy_component(
+        directory: &RamDirectory,
+        source: &Segment,
+        target: &Segment,
+        component: SegmentComponent,
+    ) -> crate::Result<()> {
+        let bytes = directory
+            .open_read(&source.relative_path(component))?
+            .read_bytes()?;
+        directory.atomic_write(&target.relative_path(component), bytes.as_slice())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_consistent_index() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.delete_term(Term::from_field_u64(index.schema().get_field("num")?, 1))?;
+        index_writer.commit()?;
+        drop(index_writer);
+        let report = index.verify_all()?;
+        assert_eq!(report.segments.len(), 2);
+        assert!(report.is_ok(), "{report:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_fast_fields_shorter_than_max_doc() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let (large, small) = segments(&index)?;
+        copy_component(&directory, &small, &large, SegmentComponent::FastFields)?;
+
+        let report = large.verify();
+        assert_eq!(
+            report.issues,
+            vec![
+                SegmentVerifyIssue::FastFieldNumDocs { num_docs: 3 },
+                SegmentVerifyIssue::ColumnNumDocs {
+                    column: "num".to_string(),
+                    num_docs: 3,
+                },
+            ]
+        );
+        assert!(small.verify().is_ok());
+
+        // Collectors return an error instead of panicking.
+        let searcher = index.reader()?.searcher();
+        let collector = TopDocs::with_limit(20).order_by_fast_field::<u64>("num", Order::Desc);
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(TantivyError::DataCorruption(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_checksum_mismatch() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let (large, _small) = segments(&index)?;
+        let path = large.relative_path(SegmentComponent::Store);
+        let mut bytes = directory
+            .open_read(&path)?
+            .read_bytes()?
+            .as_slice()
+            .to_vec();
+        bytes[0] ^= 0xFF;
+        directory.atomic_write(&path, &bytes)?;
+
+        let report = index.verify_all()?;
+        assert!(!report.is_ok());
+        let large_report = report
+            .segments
+            .iter()
+            .find(|segment_report| segment_report.segment_id == large.id())
+            .unwrap();
+        assert!(large_report
+            .issues
+            .contains(&SegmentVerifyIssue::ChecksumMismatch { path }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_postings_out_of_range() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let (large, small) = segments(&index)?;
+        for component in [
+            SegmentComponent::Terms,
+            SegmentComponent::Postings,
+            SegmentComponent::Positions,
+        ] {
+            copy_component(&directory, &large, &small, component)?;
+        }
+        let out_of_range = |options: &SegmentVerifyOptions| {
+            small
+                .verify_with_options(options)
+                .issues
+                .into_iter()
+                .filter(|issue| matches!(issue, SegmentVerifyIssue::PostingsDocOutOfRange { .. }))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            SegmentVerifyIssue::PostingsDocOutOfRange {
+                field: "text".to_string(),
+                doc: 3,
+            },
+            SegmentVerifyIssue::PostingsDocOutOfRange {
+                field: "num".to_string(),
+                doc: 3,
+            },
+        ];
+        assert_eq!(out_of_range(&SegmentVerifyOptions::default()), expected);
+        // The term `0` of the `num` field only matches the doc 0, but `num` has 10 terms.
+        let sampled = SegmentVerifyOptions {
+            postings: PostingsCheck::Sampled { every_nth_term: 3 },
+        };
+        assert_eq!(out_of_range(&sampled), expected);
+        let skipped = SegmentVerifyOptions {
+            postings: PostingsCheck::Skip,
+        };
+        assert!(out_of_range(&skipped).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_num_deleted_docs() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let num = index.schema().get_field("num")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in [1, 2, 5] {
+            index_writer.delete_term(Term::from_field_u64(num, i))?;
+        }
+        index_writer.commit()?;
+
+        // Damage the delete count of the metas, as an interrupted write could have.
+        let mut index_meta = index.load_metas()?;
+        let (large, _small) = segments(&index)?;
+        for segment_meta in &mut index_meta.segments {
+            if segment_meta.id() == large.id() {
+                let delete_opstamp = segment_meta.delete_opstamp().unwrap();
+                *segment_meta = segment_meta.clone().with_delete_meta(1, delete_opstamp);
+            }
+        }
+        save_metas(&index_meta, index.directory())?;
+
+        let report = index.verify_all()?;
+        assert!(!report.is_ok());
+        // Only derived metadata is repaired, and not while a writer is running.
+        assert!(matches!(
+            index.repair(&report),
+            Err(TantivyError::LockFailure(..))
+        ));
+        drop(index_writer);
+        let repairs = index.repair(&report)?;
+        assert_eq!(
+            repairs,
+            vec![SegmentRepair::NumDeletedDocs {
+                segment_id: large.id(),
+                previous: 1,
+                repaired: 3,
+            }]
+        );
+        assert!(index.verify_all()?.is_ok());
+        assert_eq!(index.reader()?.searcher().num_docs(), 8);
+        // Repairing with an outdated report is a no-op.
+        assert!(index.repair(&report)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_missing_component() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index(&directory)?;
+        let (large, _small) = segments(&index)?;
+        directory
+            .delete(&large.relative_path(SegmentComponent::FieldNorms))
+            .unwrap();
+        let report = large.verify();
+        assert!(
+            matches!(
+                report.issues.as_slice(),
+                [SegmentVerifyIssue::ComponentUnreadable {
+                    component: SegmentComponent::FieldNorms,
+                    ..
+                }]
+            ),
+            "{report:?}"
+        );
+        Ok(())
+    }
+}
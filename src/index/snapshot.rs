@@ -0,0 +1,331 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crc32fast::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::core::META_FILEPATH;
+use crate::directory::error::OpenReadError;
+use crate::directory::{Directory, FileProtection, ManagedDirectory};
+use crate::error::{DataCorruption, TantivyError};
+use crate::index::{IndexMeta, SegmentComponent, SegmentMeta, SegmentMetaInventory};
+use crate::Opstamp;
+
+/// A commit point of an index, whose files are protected from garbage collection.
+///
+/// Returned by [`Index::create_snapshot()`](crate::Index::create_snapshot). While the snapshot
+/// is alive, the files listed in [`Snapshot::listing()`] can be copied safely, even if the index
+/// keeps on committing and merging. A copy can then be opened with
+/// [`Index::restore_from_listing()`](crate::Index::restore_from_listing).
+///
+/// The protection is lifted by [`Snapshot::release()`], or when the snapshot is dropped.
+/// Like [`PinnedSearcher`](crate::PinnedSearcher), it only applies to the garbage collection
+/// run by this process.
+pub struct Snapshot {
+    listing: SnapshotListing,
+    // Released on drop.
+    _file_protections: Vec<FileProtection>,
+}
+
+impl Snapshot {
+    /// Returns the files of the commit point, along with its `meta.json`.
+    pub fn listing(&self) -> &SnapshotListing {
+        &self.listing
+    }
+
+    /// Returns the opstamp of the commit point.
+    pub fn opstamp(&self) -> Opstamp {
+        self.listing.opstamp
+    }
+
+    /// Releases the snapshot, allowing the next garbage collection to remove its files if they
+    /// are not used anymore.
+    pub fn release(self) {}
+}
+
+impl std::fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("listing", &self.listing)
+            .finish()
+    }
+}
+
+/// Everything needed to copy a commit point of an index and check the copy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotListing {
+    /// Opstamp of the commit point.
+    pub opstamp: Opstamp,
+    /// Content of the `meta.json` file of the commit point.
+    ///
+    /// `meta.json` is overwritten by every commit, so it is not part of [`Self::files`]. It is
+    /// written by [`Index::restore_from_listing()`](crate::Index::restore_from_listing) once all
+    /// of the files are checked.
+    pub meta_json: String,
+    /// The segment files of the commit point.
+    pub files: Vec<SnapshotFile>,
+}
+
+/// A file of a [`SnapshotListing`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    /// Path of the file, relative to the index directory.
+    pub path: PathBuf,
+    /// Size of the file, in bytes.
+    pub num_bytes: u64,
+    /// CRC32 of the whole file.
+    pub checksum: u32,
+}
+
+/// Returns the size and the CRC32 of the whole file at `path`.
+fn file_size_and_checksum(directory: &dyn Directory, path: &Path) -> crate::Result<(u64, u32)> {
+    let bytes = directory
+        .open_read(path)?
+        .read_bytes()
+        .map_err(|io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf()))?;
+    let mut hasher = Hasher::new();
+    hasher.update(bytes.as_slice());
+    Ok((bytes.len() as u64, hasher.finalize()))
+}
+
+/// Returns the files of the segment that necessarily exist.
+///
+/// The delete files only exist if the segment has deletes, the temporary store is removed once
+/// the segment is finalized, and the column statistics are only written if they are enabled.
+fn required_segment_files(segment_meta: &SegmentMeta) -> impl Iterator<Item = PathBuf> + '_ {
+    SegmentComponent::iterator()
+        .filter(move |&&component| match component {
+            SegmentComponent::TempStore | SegmentComponent::ColumnStats => false,
+            SegmentComponent::Delete => segment_meta.delete_opstamp().is_some(),
+            SegmentComponent::SoftDelete => segment_meta.soft_delete_opstamp().is_some(),
+            _ => true,
+        })
+        .map(|&component| segment_meta.relative_path(component))
+}
+
+fn parse_meta_json(meta_json: &str) -> crate::Result<IndexMeta> {
+    IndexMeta::deserialize(meta_json, &SegmentMetaInventory::default()).map_err(|err| {
+        DataCorruption::new(
+            META_FILEPATH.to_path_buf(),
+            format!("Meta file cannot be deserialized. {err:?}."),
+        )
+        .into()
+    })
+}
+
+/// Protects the files of the last commit of `directory` and lists them.
+pub(crate) fn create_snapshot(directory: &ManagedDirectory) -> crate::Result<Snapshot> {
+    loop {
+        let meta_data = directory.atomic_read(&META_FILEPATH)?;
+        let meta_json = String::from_utf8(meta_data).map_err(|_| {
+            DataCorruption::new(
+                META_FILEPATH.to_path_buf(),
+                "Meta file does not contain valid utf8 file.".to_string(),
+            )
+        })?;
+        let index_meta = parse_meta_json(&meta_json)?;
+        let file_protections: Vec<FileProtection> = index_meta
+            .segments
+            .iter()
+            .flat_map(SegmentMeta::list_files)
+            .map(|path| directory.protect_file_from_delete(&path))
+            .collect();
+        // A commit followed by a garbage collection may have removed some of the files before
+        // they got protected. If the commit point is still the last one, its files are alive.
+        if directory.atomic_read(&META_FILEPATH)? != meta_json.as_bytes() {
+            continue;
+        }
+        let mut files = Vec::new();
+        for segment_meta in &index_meta.segments {
+            let column_stats_path = segment_meta.relative_path(SegmentComponent::ColumnStats);
+            let optional_paths = directory
+                .exists(&column_stats_path)?
+                .then_some(column_stats_path);
+            for path in required_segment_files(segment_meta).chain(optional_paths) {
+                let (num_bytes, checksum) =
+                    file_size_and_checksum(directory.underlying_directory(), &path)?;
+                files.push(SnapshotFile {
+                    path,
+                    num_bytes,
+                    checksum,
+                });
+            }
+        }
+        return Ok(Snapshot {
+            listing: SnapshotListing {
+                opstamp: index_meta.opstamp,
+                meta_json,
+                files,
+            },
+            _file_protections: file_protections,
+        });
+    }
+}
+
+/// Checks that `directory` contains the files of `listing` and writes its `meta.json`.
+pub(crate) fn restore_from_listing(
+    directory: Box<dyn Directory>,
+    listing: &SnapshotListing,
+) -> crate::Result<ManagedDirectory> {
+    if directory.exists(&META_FILEPATH)? {
+        return Err(TantivyError::IndexAlreadyExists);
+    }
+    let index_meta = parse_meta_json(&listing.meta_json)?;
+    let listed_paths: HashSet<&Path> = listing
+        .files
+        .iter()
+        .map(|file| file.path.as_path())
+        .collect();
+    for segment_meta in &index_meta.segments {
+        for path in required_segment_files(segment_meta) {
+            if !listed_paths.contains(path.as_path()) {
+                return Err(DataCorruption::new(
+                    path,
+                    "File of the snapshot is missing from the listing.".to_string(),
+                )
+                .into());
+            }
+        }
+    }
+    for file in &listing.files {
+        let (num_bytes, checksum) = file_size_and_checksum(directory.as_ref(), &file.path)?;
+        if num_bytes != file.num_bytes {
+            return Err(DataCorruption::new(
+                file.path.clone(),
+                format!(
+                    "File has {num_bytes} bytes, the snapshot expects {}.",
+                    file.num_bytes
+                ),
+            )
+            .into());
+        }
+        if checksum != file.checksum {
+            return Err(DataCorruption::new(
+                file.path.clone(),
+                "File does not match the checksum of the snapshot.".to_string(),
+            )
+            .into());
+        }
+    }
+    let managed_directory = ManagedDirectory::wrap(directory)?;
+    for file in &listing.files {
+        managed_directory.register_file_as_managed(&file.path)?;
+    }
+    managed_directory.atomic_write(&META_FILEPATH, listing.meta_json.as_bytes())?;
+    Ok(managed_directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::Count;
+    use crate::directory::RamDirectory;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    fn copy_files(index: &Index, listing: &SnapshotListing) -> RamDirectory {
+        let target = RamDirectory::create();
+        for file in &listing.files {
+            let data = index.directory().atomic_read(&file.path).unwrap();
+            target.atomic_write(&file.path, &data).unwrap();
+        }
+        target
+    }
+
+    #[test]
+    fn test_snapshot_survives_commits_and_merges() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.add_document(doc!(text => "b"))?;
+        index_writer.commit()?;
+
+        let snapshot = index.create_snapshot()?;
+        assert_eq!(snapshot.opstamp(), index.load_metas()?.opstamp);
+        assert!(!snapshot.listing().files.is_empty());
+
+        // Delete and merge, so that none of the files of the snapshot are used anymore.
+        index_writer.delete_term(Term::from_field_text(text, "a"))?;
+        index_writer.add_document(doc!(text => "c"))?;
+        index_writer.commit()?;
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.garbage_collect_files().wait()?;
+        for file in &snapshot.listing().files {
+            assert!(index.directory().exists(&file.path)?, "{:?}", file.path);
+        }
+
+        let target = copy_files(&index, snapshot.listing());
+        let restored = Index::restore_from_listing(target, snapshot.listing())?;
+        let searcher = restored.reader()?.searcher();
+        let count_term = |term: &str| {
+            let query = TermQuery::new(Term::from_field_text(text, term), IndexRecordOption::Basic);
+            searcher.search(&query, &Count).unwrap()
+        };
+        assert_eq!(searcher.num_docs(), 3);
+        assert_eq!(count_term("a"), 2);
+        assert_eq!(count_term("c"), 0);
+        assert_eq!(restored.load_metas()?.opstamp, snapshot.opstamp());
+        assert_eq!(index.reader()?.searcher().num_docs(), 2);
+
+        // Once released, the files of the snapshot are garbage collected.
+        let listing = snapshot.listing().clone();
+        snapshot.release();
+        index_writer.garbage_collect_files().wait()?;
+        for file in &listing.files {
+            assert!(!index.directory().exists(&file.path)?, "{:?}", file.path);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_from_listing_validates_the_files() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+        let snapshot = index.create_snapshot()?;
+        let listing = snapshot.listing();
+
+        let target = copy_files(&index, listing);
+        let missing_file = &listing.files[0].path;
+        target.delete(missing_file).unwrap();
+        assert!(matches!(
+            Index::restore_from_listing(target.clone(), listing),
+            Err(TantivyError::OpenReadError(OpenReadError::FileDoesNotExist(path)))
+                if &path == missing_file
+        ));
+        assert!(!Index::exists(&target)?);
+
+        let target = copy_files(&index, listing);
+        target.atomic_write(missing_file, b"corrupted")?;
+        assert!(matches!(
+            Index::restore_from_listing(target.clone(), listing),
+            Err(TantivyError::DataCorruption(_))
+        ));
+
+        let mut incomplete_listing = listing.clone();
+        incomplete_listing.files.pop();
+        assert!(matches!(
+            Index::restore_from_listing(copy_files(&index, listing), &incomplete_listing),
+            Err(TantivyError::DataCorruption(_))
+        ));
+
+        let target = copy_files(&index, listing);
+        Index::restore_from_listing(target.clone(), listing)?;
+        assert!(matches!(
+            Index::restore_from_listing(target, listing),
+            Err(TantivyError::IndexAlreadyExists)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Orders the sends of the batches of added documents to the indexing workers.
+///
+/// The batches are stamped under the enqueue lock of the [`IndexWriter`](crate::IndexWriter),
+/// which also hands out a [`AddBatchTicket`] for each of them. They are then sent once the lock
+/// is released, in the order of their tickets, so that a sender blocked on a full pipeline does
+/// not keep the other threads from stamping operations.
+#[derive(Default)]
+pub(crate) struct AddBatchSequencer {
+    state: Mutex<SequencerState>,
+    turn_changed: Condvar,
+}
+
+#[derive(Default)]
+struct SequencerState {
+    /// Ticket handed out to the next batch.
+    next_ticket: u64,
+    /// Ticket of the next batch to be sent.
+    next_turn: u64,
+}
+
+impl AddBatchSequencer {
+    fn lock_state(&self) -> MutexGuard<'_, SequencerState> {
+        self.state
+            .lock()
+            .expect("Lock poisoned. This should never happen")
+    }
+
+    /// Hands out the ticket of the next batch. Has to be called under the enqueue lock.
+    pub fn ticket(&self) -> AddBatchTicket<'_> {
+        let mut state = self.lock_state();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        AddBatchTicket {
+            sequencer: self,
+            ticket,
+        }
+    }
+
+    /// Waits until all of the tickets handed out are done, for at most `timeout`.
+    ///
+    /// Returns `false` if some tickets are still pending after `timeout`.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock_state();
+        while state.next_turn != state.next_ticket {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .turn_changed
+                .wait_timeout(state, deadline - now)
+                .expect("Lock poisoned. This should never happen")
+                .0;
+        }
+        true
+    }
+}
+
+/// Turn of a batch to be sent to the indexing workers, see [`AddBatchSequencer`].
+///
+/// The turn passes to the next ticket when the ticket is dropped, after its own turn came.
+pub(crate) struct AddBatchTicket<'a> {
+    sequencer: &'a AddBatchSequencer,
+    ticket: u64,
+}
+
+impl AddBatchTicket<'_> {
+    /// Waits for the batches of the previous tickets to be sent.
+    pub fn wait_turn(&self) {
+        let mut state = self.sequencer.lock_state();
+        while state.next_turn != self.ticket {
+            state = self
+                .sequencer
+                .turn_changed
+                .wait(state)
+                .expect("Lock poisoned. This should never happen");
+        }
+    }
+}
+
+impl Drop for AddBatchTicket<'_> {
+    fn drop(&mut self) {
+        // The turn is passed on even if the batch was not sent, e.g. because of an error, so
+        // that the next tickets are not stuck.
+        self.wait_turn();
+        self.sequencer.lock_state().next_turn += 1;
+        self.sequencer.turn_changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::AddBatchSequencer;
+
+    #[test]
+    fn test_add_batch_sequencer_orders_turns() {
+        let sequencer = Arc::new(AddBatchSequencer::default());
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let tickets: Vec<_> = (0..4).map(|_| sequencer.ticket().ticket).collect();
+        assert_eq!(tickets, vec![0, 1, 2, 3]);
+        // The tickets were dropped: their turns passed.
+        assert!(sequencer.wait_idle(Duration::ZERO));
+
+        let first = sequencer.ticket();
+        let second = sequencer.ticket();
+        assert!(!sequencer.wait_idle(Duration::from_millis(10)));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                second.wait_turn();
+                sent.lock().unwrap().push(2);
+                drop(second);
+            });
+            thread::sleep(Duration::from_millis(50));
+            sent.lock().unwrap().push(1);
+            drop(first);
+        });
+        assert_eq!(*sent.lock().unwrap(), vec![1, 2]);
+        assert!(sequencer.wait_idle(Duration::ZERO));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::reader::IndexReader;
+use crate::{FutureResult, Opstamp};
+
+/// Handle on a commit that has been persisted.
+///
+/// It is returned by [`IndexWriter::commit_async()`](crate::IndexWriter::commit_async) once the
+/// commit is durable on disk, and makes it possible to wait for the commit to become visible to
+/// an [`IndexReader`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitHandle {
+    opstamp: Opstamp,
+}
+
+impl CommitHandle {
+    pub(crate) fn new(opstamp: Opstamp) -> Self {
+        Self { opstamp }
+    }
+
+    /// Returns the opstamp of the commit.
+    pub fn opstamp(&self) -> Opstamp {
+        self.opstamp
+    }
+
+    /// Returns a future that resolves once `reader` serves a searcher that includes this commit.
+    ///
+    /// Unlike polling the reader after the commit, this is not affected by other commits: the
+    /// future resolves as soon as the loaded commit is this one or a later one.
+    ///
+    /// The reader still needs to be reloaded, either by its
+    /// [`ReloadPolicy`](crate::ReloadPolicy) or by calling [`IndexReader::reload()`].
+    pub fn searchable(&self, reader: &IndexReader) -> FutureResult<()> {
+        reader.wait_searchable(self.opstamp)
+    }
+}
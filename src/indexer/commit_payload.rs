@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::core::COMMIT_PAYLOADS_FILEPATH;
+use crate::directory::error::OpenReadError;
+use crate::directory::Directory;
+use crate::{Opstamp, TantivyError};
+
+/// Default maximum size of a commit payload, in bytes.
+pub const DEFAULT_MAX_COMMIT_PAYLOAD_NUM_BYTES: usize = 64 * 1024;
+
+/// Default number of commit payloads retained in the index.
+pub const DEFAULT_NUM_RETAINED_COMMIT_PAYLOADS: usize = 10;
+
+/// Payload attached to a commit.
+///
+/// See [`Index::list_commit_payloads()`](crate::Index::list_commit_payloads).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommitPayload {
+    /// Opstamp of the commit.
+    pub opstamp: Opstamp,
+    /// The payload, as JSON.
+    pub payload: Value,
+}
+
+/// Serializes a payload into JSON, checking its size.
+pub(crate) fn serialize_commit_payload(
+    payload: &impl Serialize,
+    max_num_bytes: usize,
+) -> crate::Result<String> {
+    let payload = serde_json::to_string(payload).map_err(|err| {
+        TantivyError::InvalidArgument(format!("Failed to serialize the commit payload: {err}"))
+    })?;
+    if payload.len() > max_num_bytes {
+        return Err(TantivyError::CommitPayloadTooLarge {
+            num_bytes: payload.len(),
+            max_num_bytes,
+        });
+    }
+    Ok(payload)
+}
+
+/// Parses a payload stored in the index meta.
+///
+/// Payloads set with [`PreparedCommit::set_payload()`](crate::indexer::PreparedCommit) are
+/// arbitrary strings: the ones that are not valid JSON are returned as a JSON string.
+pub(crate) fn parse_commit_payload(payload: &str) -> Value {
+    serde_json::from_str(payload).unwrap_or_else(|_| Value::String(payload.to_string()))
+}
+
+/// Reads the payloads of the retained commits, oldest first.
+pub(crate) fn load_commit_payloads(directory: &dyn Directory) -> crate::Result<Vec<CommitPayload>> {
+    let data = match directory.atomic_read(&COMMIT_PAYLOADS_FILEPATH) {
+        Ok(data) => data,
+        Err(OpenReadError::FileDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    serde_json::from_slice(&data).map_err(|err| {
+        TantivyError::DataCorruption(crate::error::DataCorruption::new(
+            COMMIT_PAYLOADS_FILEPATH.to_path_buf(),
+            format!("Commit payloads file cannot be deserialized: {err:?}"),
+        ))
+    })
+}
+
+/// Appends the payload of a commit to the retained ones, keeping only the last
+/// `num_retained` of them.
+///
+/// This is called once `meta.json` has been written, so the retained payloads may lag behind it
+/// after a crash, but never get ahead of it.
+pub(crate) fn save_commit_payload(
+    directory: &dyn Directory,
+    opstamp: Opstamp,
+    payload: &str,
+    num_retained: usize,
+) -> crate::Result<()> {
+    if num_retained == 0 {
+        return Ok(());
+    }
+    let mut commit_payloads = load_commit_payloads(directory)?;
+    commit_payloads.push(CommitPayload {
+        opstamp,
+        payload: parse_commit_payload(payload),
+    });
+    let num_dropped = commit_payloads.len().saturating_sub(num_retained);
+    commit_payloads.drain(..num_dropped);
+    let data = serde_json::to_vec(&commit_payloads)?;
+    directory.atomic_write(&COMMIT_PAYLOADS_FILEPATH, &data)?;
+    Ok(())
+}
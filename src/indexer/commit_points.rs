@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::core::COMMIT_POINTS_FILEPATH;
+use crate::directory::error::OpenReadError;
+use crate::directory::Directory;
+use crate::index::{IndexMeta, SegmentMetaInventory};
+use crate::{Opstamp, TantivyError};
+
+/// Default number of commit points retained in the index, the last commit included.
+pub const DEFAULT_NUM_RETAINED_COMMIT_POINTS: usize = 0;
+
+/// Reads the metas of the retained commit points, oldest first.
+///
+/// The segment metas are tracked in `inventory`, so that their files are kept by the garbage
+/// collection as long as the returned metas are alive.
+pub(crate) fn load_commit_points(
+    directory: &dyn Directory,
+    inventory: &SegmentMetaInventory,
+) -> crate::Result<Vec<IndexMeta>> {
+    let data = match directory.atomic_read(&COMMIT_POINTS_FILEPATH) {
+        Ok(data) => data,
+        Err(OpenReadError::FileDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    IndexMeta::deserialize_list(&data, inventory).map_err(|err| {
+        TantivyError::DataCorruption(crate::error::DataCorruption::new(
+            COMMIT_POINTS_FILEPATH.to_path_buf(),
+            format!("Commit points file cannot be deserialized: {err:?}"),
+        ))
+    })
+}
+
+/// Returns the files of the segments of the retained commit points.
+pub(crate) fn list_commit_point_files(
+    directory: &dyn Directory,
+    inventory: &SegmentMetaInventory,
+) -> crate::Result<HashSet<PathBuf>> {
+    Ok(load_commit_points(directory, inventory)?
+        .iter()
+        .flat_map(|index_meta| &index_meta.segments)
+        .flat_map(|segment_meta| segment_meta.list_files())
+        .collect())
+}
+
+fn save_commit_points(directory: &dyn Directory, commit_points: &[IndexMeta]) -> crate::Result<()> {
+    let data = serde_json::to_vec(commit_points)?;
+    directory.atomic_write(&COMMIT_POINTS_FILEPATH, &data)?;
+    Ok(())
+}
+
+/// Appends a commit to the retained commit points, keeping only the last `num_retained` of
+/// them.
+///
+/// This is called once `meta.json` has been written, so the retained commit points may lag
+/// behind it after a crash, but never get ahead of it.
+pub(crate) fn save_commit_point(
+    directory: &dyn Directory,
+    inventory: &SegmentMetaInventory,
+    index_meta: &IndexMeta,
+    num_retained: usize,
+) -> crate::Result<()> {
+    let mut commit_points = load_commit_points(directory, inventory)?;
+    if num_retained == 0 && commit_points.is_empty() {
+        return Ok(());
+    }
+    commit_points.retain(|commit_point| commit_point.opstamp != index_meta.opstamp);
+    commit_points.push(index_meta.clone());
+    let num_dropped = commit_points.len().saturating_sub(num_retained);
+    commit_points.drain(..num_dropped);
+    save_commit_points(directory, &commit_points)
+}
+
+/// Removes a commit from the retained commit points, so that the garbage collection can delete
+/// the files only it uses.
+///
+/// Returns false if the commit was not retained.
+pub(crate) fn release_commit_point(
+    directory: &dyn Directory,
+    inventory: &SegmentMetaInventory,
+    opstamp: Opstamp,
+) -> crate::Result<bool> {
+    let mut commit_points = load_commit_points(directory, inventory)?;
+    let num_commit_points = commit_points.len();
+    commit_points.retain(|commit_point| commit_point.opstamp != opstamp);
+    if commit_points.len() == num_commit_points {
+        return Ok(false);
+    }
+    save_commit_points(directory, &commit_points)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    use crate::collector::Count;
+    use crate::indexer::{IndexWriterOptions, NoMergePolicy};
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, STRING};
+    use crate::{Index, IndexWriter, Opstamp, TantivyError, Term};
+
+    fn delete_files(index: &Index) -> HashSet<PathBuf> {
+        index
+            .directory()
+            .list_managed_files()
+            .into_iter()
+            .filter(|path| path.to_string_lossy().ends_with(".del"))
+            .collect()
+    }
+
+    #[test]
+    fn test_open_commit_keeps_point_in_time_deletes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .num_retained_commit_points(2)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for key in ["a", "b", "c"] {
+            index_writer.add_document(doc!(id => key))?;
+        }
+        index_writer.commit()?;
+        index_writer.delete_term(Term::from_field_text(id, "c"))?;
+        let old_commit: Opstamp = index_writer.commit()?;
+        let old_delete_files = delete_files(&index);
+        assert_eq!(old_delete_files.len(), 1);
+
+        index_writer.delete_term(Term::from_field_text(id, "a"))?;
+        let new_commit = index_writer.commit()?;
+        index_writer.garbage_collect_files().wait()?;
+        // Both delete generations are kept for the retained commits.
+        let delete_files_with_old_commit = delete_files(&index);
+        assert_eq!(delete_files_with_old_commit.len(), 2);
+        assert!(delete_files_with_old_commit.is_superset(&old_delete_files));
+        assert_eq!(
+            index.list_commit_points()?.last().copied(),
+            Some(new_commit)
+        );
+
+        let count_key = |reader: &crate::IndexReader, key: &str| {
+            let query = TermQuery::new(Term::from_field_text(id, key), IndexRecordOption::Basic);
+            reader.searcher().search(&query, &Count)
+        };
+        let old_reader = index.open_commit(old_commit)?;
+        assert_eq!(old_reader.searcher().num_docs(), 2);
+        assert_eq!(count_key(&old_reader, "a")?, 1);
+        assert_eq!(count_key(&old_reader, "c")?, 0);
+        let new_reader = index.reader()?;
+        assert_eq!(new_reader.searcher().num_docs(), 1);
+        assert_eq!(count_key(&new_reader, "a")?, 0);
+        // Reloading a reader of a commit point does not move it to the last commit.
+        old_reader.reload()?;
+        assert_eq!(count_key(&old_reader, "a")?, 1);
+
+        // The files of the released commit stay until its readers are dropped.
+        assert!(index_writer.release_commit_point(old_commit)?);
+        assert!(!index_writer.release_commit_point(old_commit)?);
+        index_writer.garbage_collect_files().wait()?;
+        assert_eq!(delete_files(&index).len(), 2);
+        drop(old_reader);
+        index_writer.garbage_collect_files().wait()?;
+        assert_eq!(
+            delete_files(&index),
+            delete_files_with_old_commit
+                .difference(&old_delete_files)
+                .cloned()
+                .collect()
+        );
+        assert!(matches!(
+            index.open_commit(old_commit),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        // The last commit can always be opened.
+        assert_eq!(index.open_commit(new_commit)?.searcher().num_docs(), 1);
+        Ok(())
+    }
+}
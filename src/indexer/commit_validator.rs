@@ -0,0 +1,287 @@
+use std::fmt::{self, Debug};
+use std::marker;
+use std::ops::RangeInclusive;
+
+use columnar::{Column, DynamicColumn, HasAssociatedColumnType};
+
+use crate::index::SegmentReader;
+use crate::{DocId, TantivyError};
+
+/// A `CommitValidator` checks the invariants of the documents of a commit before it is
+/// published.
+///
+/// The validators of an [`IndexWriter`](crate::IndexWriter) are called on each segment the
+/// commit creates or updates, before the commit point is written. If one of them returns an
+/// error, the commit fails and none of its documents become visible: they stay uncommitted, so
+/// that the writer can be rolled back with
+/// [`IndexWriter::rollback()`](crate::IndexWriter::rollback).
+///
+/// See [`IndexWriter::add_commit_validator()`](crate::IndexWriter::add_commit_validator).
+pub trait CommitValidator: marker::Send + marker::Sync + Debug {
+    /// Checks the alive documents of a segment of the commit.
+    ///
+    /// This call happens on the search executor of the index, in parallel for the segments of
+    /// the commit. A violated invariant is reported with a
+    /// [`TantivyError::CommitViolation`] error, see [`commit_violation()`].
+    fn validate(&self, segment_reader: &SegmentReader) -> crate::Result<()>;
+}
+
+/// Returns the error reporting that the document `doc` of the segment violates an invariant
+/// of a [`CommitValidator`].
+pub fn commit_violation(
+    segment_reader: &SegmentReader,
+    doc: Option<DocId>,
+    reason: impl ToString,
+) -> TantivyError {
+    TantivyError::CommitViolation {
+        segment_id: segment_reader.segment_id(),
+        doc,
+        reason: reason.to_string(),
+    }
+}
+
+/// Checks that all of the documents have at least one value for a fast field.
+#[derive(Debug, Clone)]
+pub struct FieldPresentValidator {
+    field: String,
+}
+
+impl FieldPresentValidator {
+    /// Creates a validator checking that all of the documents have a value for the fast field
+    /// `field`, of any type.
+    pub fn new(field: impl ToString) -> FieldPresentValidator {
+        FieldPresentValidator {
+            field: field.to_string(),
+        }
+    }
+}
+
+impl CommitValidator for FieldPresentValidator {
+    fn validate(&self, segment_reader: &SegmentReader) -> crate::Result<()> {
+        let columns: Vec<DynamicColumn> = segment_reader
+            .try_fast_fields()?
+            .dynamic_column_handles(&self.field)?
+            .iter()
+            .map(|handle| handle.open())
+            .collect::<std::io::Result<_>>()?;
+        let missing_doc = segment_reader.doc_ids_alive().find(|&doc| {
+            !columns
+                .iter()
+                .any(|column| column.column_index().has_value(doc))
+        });
+        match missing_doc {
+            Some(doc) => Err(commit_violation(
+                segment_reader,
+                Some(doc),
+                format!("the field {:?} has no value", self.field),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks that all of the values of a numerical fast field are within a range.
+///
+/// The documents without a value for the field are accepted, see [`FieldPresentValidator`] to
+/// reject them.
+#[derive(Clone)]
+pub struct NumericRangeValidator<T> {
+    field: String,
+    range: RangeInclusive<T>,
+}
+
+impl<T> NumericRangeValidator<T> {
+    /// Creates a validator checking that the values of type `T` of the fast field `field` are
+    /// within `range`.
+    ///
+    /// `T` is one of `u64`, `i64`, `f64` or [`DateTime`](crate::DateTime).
+    pub fn new(field: impl ToString, range: RangeInclusive<T>) -> NumericRangeValidator<T> {
+        NumericRangeValidator {
+            field: field.to_string(),
+            range,
+        }
+    }
+}
+
+impl<T: Debug> Debug for NumericRangeValidator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NumericRangeValidator")
+            .field("field", &self.field)
+            .field("range", &self.range)
+            .finish()
+    }
+}
+
+impl<T> CommitValidator for NumericRangeValidator<T>
+where
+    T: HasAssociatedColumnType + PartialOrd + Debug,
+    DynamicColumn: Into<Option<Column<T>>>,
+{
+    fn validate(&self, segment_reader: &SegmentReader) -> crate::Result<()> {
+        let Some(column) = segment_reader
+            .try_fast_fields()?
+            .column_opt::<T>(&self.field)?
+        else {
+            return Ok(());
+        };
+        for doc in segment_reader.doc_ids_alive() {
+            if let Some(value) = column
+                .values_for_doc(doc)
+                .find(|value| !self.range.contains(value))
+            {
+                return Err(commit_violation(
+                    segment_reader,
+                    Some(doc),
+                    format!(
+                        "the value {value:?} of the field {:?} is not within {:?}",
+                        self.field, self.range
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommitValidator, FieldPresentValidator, NumericRangeValidator};
+    use crate::collector::Count;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST, STRING};
+    use crate::{DateTime, Index, IndexWriter, SegmentReader, TantivyError, Term};
+
+    const YEAR_SECS: i64 = 365 * 24 * 3600;
+
+    fn create_index() -> Index {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("tenant_id", STRING | FAST);
+        schema_builder.add_date_field("timestamp", FAST);
+        schema_builder.add_i64_field("rank", FAST);
+        Index::create_in_ram(schema_builder.build())
+    }
+
+    fn writer_with_validators(index: &Index) -> crate::Result<IndexWriter> {
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let now = DateTime::from_timestamp_secs(100 * YEAR_SECS);
+        let ten_years_ago = DateTime::from_timestamp_secs(90 * YEAR_SECS);
+        index_writer.add_commit_validator(Box::new(FieldPresentValidator::new("tenant_id")));
+        index_writer.add_commit_validator(Box::new(NumericRangeValidator::new(
+            "timestamp",
+            ten_years_ago..=now,
+        )));
+        Ok(index_writer)
+    }
+
+    fn num_visible_docs(index: &Index) -> crate::Result<usize> {
+        index.reader()?.searcher().search(&AllQuery, &Count)
+    }
+
+    #[test]
+    fn test_commit_validators_accept_valid_batch() -> crate::Result<()> {
+        let index = create_index();
+        let schema = index.schema();
+        let tenant_id = schema.get_field("tenant_id")?;
+        let timestamp = schema.get_field("timestamp")?;
+        let mut index_writer = writer_with_validators(&index)?;
+        index_writer.add_document(doc!(
+            tenant_id => "a",
+            timestamp => DateTime::from_timestamp_secs(95 * YEAR_SECS),
+        ))?;
+        index_writer.add_document(doc!(
+            tenant_id => "b",
+            timestamp => DateTime::from_timestamp_secs(99 * YEAR_SECS),
+        ))?;
+        // The range validator accepts the documents without a value.
+        index_writer.add_document(doc!(tenant_id => "c"))?;
+        index_writer.commit()?;
+        assert_eq!(num_visible_docs(&index)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_validators_reject_violating_batch() -> crate::Result<()> {
+        let index = create_index();
+        let schema = index.schema();
+        let tenant_id = schema.get_field("tenant_id")?;
+        let timestamp = schema.get_field("timestamp")?;
+        let rank = schema.get_field("rank")?;
+        let mut index_writer = writer_with_validators(&index)?;
+        index_writer.add_document(doc!(tenant_id => "a"))?;
+        index_writer.commit()?;
+
+        index_writer.add_document(doc!(tenant_id => "a"))?;
+        index_writer.add_document(doc!(rank => 3i64))?;
+        let err = index_writer.commit().unwrap_err();
+        let TantivyError::CommitViolation {
+            segment_id,
+            doc,
+            reason,
+        } = &err
+        else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(!index.searchable_segment_ids()?.contains(segment_id));
+        assert_eq!(*doc, Some(1));
+        assert_eq!(reason, "the field \"tenant_id\" has no value");
+        assert_eq!(err.error_code(), "commit_violation");
+        assert_eq!(num_visible_docs(&index)?, 1);
+
+        // The documents of the rejected commit stay uncommitted until the writer is rolled back.
+        index_writer.add_document(doc!(tenant_id => "b"))?;
+        assert!(index_writer.commit().is_err());
+        index_writer.rollback()?;
+        assert_eq!(num_visible_docs(&index)?, 1);
+
+        // The validators are kept by the rollback.
+        index_writer.add_document(doc!(
+            tenant_id => "b",
+            timestamp => DateTime::from_timestamp_secs(50 * YEAR_SECS),
+        ))?;
+        let err = index_writer.commit().unwrap_err();
+        assert!(err.to_string().contains("is not within"), "{err}");
+        index_writer.rollback()?;
+        index_writer.add_document(doc!(tenant_id => "b"))?;
+        index_writer.commit()?;
+        assert_eq!(num_visible_docs(&index)?, 2);
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct NoDeletedDocs;
+
+    impl CommitValidator for NoDeletedDocs {
+        fn validate(&self, segment_reader: &SegmentReader) -> crate::Result<()> {
+            if segment_reader.has_deletes() {
+                return Err(super::commit_violation(
+                    segment_reader,
+                    None,
+                    "the segment has deletes",
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_commit_validators_check_updated_segments() -> crate::Result<()> {
+        let index = create_index();
+        let tenant_id = index.schema().get_field("tenant_id")?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(tenant_id => "a"))?;
+        index_writer.add_document(doc!(tenant_id => "b"))?;
+        index_writer.commit()?;
+        index_writer.add_commit_validator(Box::new(NoDeletedDocs));
+        index_writer.delete_term(Term::from_field_text(tenant_id, "a"))?;
+        let err = index_writer.commit().unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::CommitViolation { doc: None, .. }
+        ));
+        assert_eq!(num_visible_docs(&index)?, 2);
+        Ok(())
+    }
+}
@@ -53,6 +53,8 @@ impl DeleteQueue {
         let block = Arc::new(Block {
             operations: Arc::new([]),
             next: NextBlock::from(self.clone()),
+            #[cfg(feature = "debug-delete-queue")]
+            checksum: BlockChecksum::default(),
         });
         wlock.last_block = Arc::downgrade(&block);
         block
@@ -68,6 +70,8 @@ impl DeleteQueue {
         DeleteCursor {
             block: last_block,
             pos: operations_len,
+            #[cfg(feature = "debug-delete-queue")]
+            last_opstamp: None,
         }
     }
 
@@ -105,9 +109,13 @@ impl DeleteQueue {
 
         let delete_operations = std::mem::take(&mut self_wlock.writer);
 
+        #[cfg(feature = "debug-delete-queue")]
+        let checksum = BlockChecksum::compute(&delete_operations);
         let new_block = Arc::new(Block {
             operations: Arc::from(delete_operations.into_boxed_slice()),
             next: NextBlock::from(self.clone()),
+            #[cfg(feature = "debug-delete-queue")]
+            checksum,
         });
 
         self_wlock.last_block = Arc::downgrade(&new_block);
@@ -167,12 +175,83 @@ impl NextBlock {
 struct Block {
     operations: Arc<[DeleteOperation]>,
     next: NextBlock,
+    #[cfg(feature = "debug-delete-queue")]
+    checksum: BlockChecksum,
+}
+
+/// Summary of the operations of a block, recorded when the block is flushed, and checked by the
+/// cursors reading the block.
+#[cfg(feature = "debug-delete-queue")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct BlockChecksum {
+    first_opstamp: Option<Opstamp>,
+    last_opstamp: Option<Opstamp>,
+    len: usize,
+}
+
+#[cfg(feature = "debug-delete-queue")]
+impl BlockChecksum {
+    fn compute(operations: &[DeleteOperation]) -> BlockChecksum {
+        for window in operations.windows(2) {
+            if window[0].opstamp >= window[1].opstamp {
+                report_violation(format!(
+                    "Delete queue block has out of order opstamps: {} is followed by {}",
+                    window[0].opstamp, window[1].opstamp
+                ));
+            }
+        }
+        BlockChecksum {
+            first_opstamp: operations.first().map(|operation| operation.opstamp),
+            last_opstamp: operations.last().map(|operation| operation.opstamp),
+            len: operations.len(),
+        }
+    }
+}
+
+/// Panics in debug builds, logs an error in release builds.
+#[cfg(feature = "debug-delete-queue")]
+fn report_violation(msg: String) {
+    if cfg!(debug_assertions) {
+        panic!("{msg}");
+    } else {
+        error!("{msg}");
+    }
+}
+
+/// Checks that `block` matches the checksum recorded when it was flushed, and that its opstamps
+/// come after the ones of `previous_block`.
+#[cfg(feature = "debug-delete-queue")]
+fn check_next_block(previous_block: &Block, block: &Block) {
+    let checksum = BlockChecksum {
+        first_opstamp: block.operations.first().map(|operation| operation.opstamp),
+        last_opstamp: block.operations.last().map(|operation| operation.opstamp),
+        len: block.operations.len(),
+    };
+    if checksum != block.checksum {
+        report_violation(format!(
+            "Delete queue block does not match its checksum: {checksum:?} != {:?}",
+            block.checksum
+        ));
+    }
+    if let (Some(previous_last), Some(first)) =
+        (previous_block.checksum.last_opstamp, checksum.first_opstamp)
+    {
+        if previous_last >= first {
+            report_violation(format!(
+                "Delete queue block starts with opstamp {first}, but the previous block ends with \
+                 opstamp {previous_last}"
+            ));
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DeleteCursor {
     block: Arc<Block>,
     pos: usize,
+    /// The opstamp of the last operation the cursor advanced past.
+    #[cfg(feature = "debug-delete-queue")]
+    last_opstamp: Option<Opstamp>,
 }
 
 impl DeleteCursor {
@@ -185,6 +264,42 @@ impl DeleteCursor {
         while self.is_behind_opstamp(target_opstamp) {
             self.advance();
         }
+        #[cfg(feature = "debug-delete-queue")]
+        self.check_skip_to(target_opstamp);
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    fn check_skip_to(&mut self, target_opstamp: Opstamp) {
+        if let Some(last_opstamp) = self.last_opstamp {
+            if last_opstamp >= target_opstamp {
+                report_violation(format!(
+                    "Delete cursor skipped to {target_opstamp}, but already consumed opstamp \
+                     {last_opstamp}"
+                ));
+            }
+        }
+        if let Some(operation) = self.get() {
+            if operation.opstamp < target_opstamp {
+                report_violation(format!(
+                    "Delete cursor skipped to {target_opstamp}, but is positioned on opstamp {}",
+                    operation.opstamp
+                ));
+            }
+        }
+    }
+
+    /// Checks that the cursor observes strictly increasing opstamps, and in particular never
+    /// observes the same opstamp twice.
+    #[cfg(feature = "debug-delete-queue")]
+    fn check_advance(&mut self, opstamp: Opstamp) {
+        if let Some(last_opstamp) = self.last_opstamp {
+            if last_opstamp >= opstamp {
+                report_violation(format!(
+                    "Delete cursor observed opstamp {opstamp} after opstamp {last_opstamp}"
+                ));
+            }
+        }
+        self.last_opstamp = Some(opstamp);
     }
 
     fn is_behind_opstamp(&mut self, target_opstamp: Opstamp) -> bool {
@@ -207,6 +322,8 @@ impl DeleteCursor {
             // self.go_next_block();
             match self.block.next.next_block() {
                 Some(block) => {
+                    #[cfg(feature = "debug-delete-queue")]
+                    check_next_block(&self.block, &block);
                     self.block = block;
                     self.pos = 0;
                     true
@@ -222,6 +339,8 @@ impl DeleteCursor {
     /// Returns true if and only if there is such an operation.
     pub fn advance(&mut self) -> bool {
         if self.load_block_if_required() {
+            #[cfg(feature = "debug-delete-queue")]
+            self.check_advance(self.block.operations[self.pos].opstamp);
             self.pos += 1;
             true
         } else {
@@ -245,6 +364,7 @@ mod tests {
 
     use super::{DeleteOperation, DeleteQueue};
     use crate::index::SegmentReader;
+    use crate::indexer::operation::DeleteTarget;
     use crate::query::{Explanation, Scorer, Weight};
     use crate::{DocId, Score};
 
@@ -265,7 +385,8 @@ mod tests {
 
         let make_op = |i: usize| DeleteOperation {
             opstamp: i as u64,
-            target: Box::new(DummyWeight),
+            target: DeleteTarget::Weight(Box::new(DummyWeight)),
+            tombstone_field: None,
         };
 
         delete_queue.push(make_op(1));
@@ -302,4 +423,90 @@ mod tests {
             assert!(operations_it.get().is_none());
         }
     }
+
+    #[cfg(feature = "debug-delete-queue")]
+    fn make_op(opstamp: u64) -> DeleteOperation {
+        DeleteOperation {
+            opstamp,
+            target: DeleteTarget::Weight(Box::new(DummyWeight)),
+            tombstone_field: None,
+        }
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    #[test]
+    #[should_panic(expected = "out of order opstamps: 2 is followed by 1")]
+    fn test_deletequeue_detects_out_of_order_block() {
+        let delete_queue = DeleteQueue::new();
+        let mut cursor = delete_queue.cursor();
+        delete_queue.push(make_op(2));
+        delete_queue.push(make_op(1));
+        cursor.get();
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    #[test]
+    #[should_panic(expected = "starts with opstamp 3, but the previous block ends with opstamp 5")]
+    fn test_deletequeue_detects_out_of_order_blocks() {
+        let delete_queue = DeleteQueue::new();
+        let mut cursor = delete_queue.cursor();
+        delete_queue.push(make_op(5));
+        assert_eq!(cursor.get().unwrap().opstamp, 5);
+        cursor.advance();
+        delete_queue.push(make_op(3));
+        cursor.get();
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    #[test]
+    #[should_panic(expected = "starts with opstamp 1, but the previous block ends with opstamp 1")]
+    fn test_deletequeue_detects_duplicate_opstamp() {
+        let delete_queue = DeleteQueue::new();
+        let mut cursor = delete_queue.cursor();
+        delete_queue.push(make_op(1));
+        cursor.advance();
+        delete_queue.push(make_op(1));
+        cursor.advance();
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    #[test]
+    #[should_panic(expected = "skipped to 2, but already consumed opstamp 3")]
+    fn test_deletequeue_detects_skip_to_backward() {
+        let delete_queue = DeleteQueue::new();
+        let mut cursor = delete_queue.cursor();
+        delete_queue.push(make_op(1));
+        delete_queue.push(make_op(3));
+        delete_queue.push(make_op(4));
+        cursor.skip_to(4);
+        assert_eq!(cursor.get().unwrap().opstamp, 4);
+        cursor.skip_to(2);
+    }
+
+    #[cfg(feature = "debug-delete-queue")]
+    #[test]
+    fn test_deletequeue_checks_accept_valid_queue() {
+        let delete_queue = DeleteQueue::new();
+        let mut cursor = delete_queue.cursor();
+        for opstamp in 1..10 {
+            delete_queue.push(make_op(opstamp));
+            if opstamp % 3 == 0 {
+                // Flushes a block.
+                cursor.get();
+            }
+        }
+        let mut cloned_cursor = cursor.clone();
+        cursor.skip_to(5);
+        assert_eq!(cursor.get().unwrap().opstamp, 5);
+        cursor.skip_to(5);
+        assert_eq!(cursor.get().unwrap().opstamp, 5);
+        cursor.skip_to(100);
+        assert!(cursor.get().is_none());
+        let mut opstamps = Vec::new();
+        while let Some(operation) = cloned_cursor.get() {
+            opstamps.push(operation.opstamp);
+            cloned_cursor.advance();
+        }
+        assert_eq!(opstamps, (1..10).collect::<Vec<_>>());
+    }
 }
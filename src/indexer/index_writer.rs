@@ -1,28 +1,52 @@
+use std::any::Any;
+use std::future::Future;
 use std::ops::Range;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use common::BitSet;
-use smallvec::smallvec;
-
+use crossbeam_channel::{SendTimeoutError, TrySendError};
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::add_batch_sequencer::{AddBatchSequencer, AddBatchTicket};
+use super::commit_handle::CommitHandle;
+use super::commit_payload::{
+    serialize_commit_payload, DEFAULT_MAX_COMMIT_PAYLOAD_NUM_BYTES,
+    DEFAULT_NUM_RETAINED_COMMIT_PAYLOADS,
+};
+use super::commit_points::DEFAULT_NUM_RETAINED_COMMIT_POINTS;
+use super::indexing_report::TokenizationChecker;
+use super::memory_usage::{
+    AdaptiveFlushThreshold, MemoryUsageReport, MemoryUsageTracker, ThreadMemoryUsage,
+};
 use super::operation::{AddOperation, UserOperation};
+use super::reclaim_report::{ReclaimReport, DEFAULT_EXPUNGE_DELETES_MIN_DELETED_RATIO};
 use super::segment_updater::SegmentUpdater;
 use super::{AddBatch, AddBatchReceiver, AddBatchSender, PreparedCommit};
-use crate::directory::{DirectoryLock, GarbageCollectionResult, TerminatingWrite};
+use crate::directory::{DirectoryLock, GarbageCollectionResult, IoBudget, TerminatingWrite};
 use crate::error::TantivyError;
-use crate::fastfield::write_alive_bitset;
+use crate::fastfield::{write_alive_bitset, AliveBitSet};
 use crate::index::{Index, Segment, SegmentComponent, SegmentId, SegmentMeta, SegmentReader};
 use crate::indexer::delete_queue::{DeleteCursor, DeleteQueue};
 use crate::indexer::doc_opstamp_mapping::DocToOpstampMapping;
 use crate::indexer::index_writer_status::IndexWriterStatus;
-use crate::indexer::operation::DeleteOperation;
+use crate::indexer::operation::{DeleteOperation, DeleteTarget, DeletedSegment};
 use crate::indexer::stamper::Stamper;
-use crate::indexer::{MergePolicy, SegmentEntry, SegmentWriter};
-use crate::query::{EnableScoring, Query, TermQuery};
+use crate::indexer::upsert::{unique_key_term, UpsertAction, UpsertState};
+use crate::indexer::versioned_update::{document_version, VersionState};
+use crate::indexer::{
+    CommitValidator, IndexingReport, MergeHandle, MergeMonitor, MergePolicy, MergeProgressCallback,
+    OperationLog, OperationLogBatch, SegmentEntry, SegmentWriter, SoftDeleteRetentionPolicy,
+    TokenizationErrorPolicy, TryAddError, UpsertConflictPolicy, UpsertStats,
+    DEFAULT_MERGE_POLICY_TIMEOUT,
+};
+use crate::query::{EnableScoring, FuzzyTermQuery, Query, TermQuery};
 use crate::schema::document::Document;
-use crate::schema::{IndexRecordOption, TantivyDocument, Term};
-use crate::{FutureResult, Opstamp};
+use crate::schema::{Field, IndexRecordOption, Schema, TantivyDocument, Term, TermRef};
+use crate::{DocId, FutureResult, Opstamp};
 
 // Size of the margin for the `memory_arena`. A segment is closed when the remaining memory
 // in the `memory_arena` goes below MARGIN_IN_BYTES.
@@ -35,10 +59,68 @@ pub const MEMORY_BUDGET_NUM_BYTES_MAX: usize = u32::MAX as usize - MARGIN_IN_BYT
 // We impose the number of index writer threads to be at most this.
 pub const MAX_NUM_THREAD: usize = 8;
 
+/// Default maximum number of terms a delete query may expand to in a segment.
+pub const DEFAULT_DELETE_MAX_EXPANDED_TERMS: usize = 100_000;
+
 // Add document will block if the number of docs waiting in the queue to be indexed
 // reaches `PIPELINE_MAX_SIZE_IN_DOCS`
 const PIPELINE_MAX_SIZE_IN_DOCS: usize = 10_000;
 
+/// Number of batches of documents the indexing pipeline holds, so that it holds about
+/// `PIPELINE_MAX_SIZE_IN_DOCS` documents when they are sent in batches of `add_batch_size`.
+fn pipeline_capacity(add_batch_size: usize) -> usize {
+    (PIPELINE_MAX_SIZE_IN_DOCS / add_batch_size).max(1)
+}
+
+/// Checks that a delete term can match documents: the field exists, is indexed and the term has
+/// the type of the field.
+pub(crate) fn validate_delete_term<B: AsRef<[u8]>>(
+    schema: &Schema,
+    term: &Term<B>,
+) -> crate::Result<()> {
+    let field = term.field();
+    if field.field_id() as usize >= schema.num_fields() {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {field:?} does not exist in the schema."
+        )));
+    }
+    let field_entry = schema.get_field_entry(field);
+    if !field_entry.is_indexed() {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {:?} is not indexed.",
+            field_entry.name()
+        )));
+    }
+    let field_type = field_entry.field_type().value_type();
+    let term_type = term.typ();
+    if term_type != field_type {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {:?} is of type {field_type:?}, but the term is of type {term_type:?}.",
+            field_entry.name()
+        )));
+    }
+    Ok(())
+}
+
+/// Rebuilds the operations of a batch from its adds and deletes, in the order of their
+/// opstamps.
+fn merge_user_operations<D: Document>(
+    adds: Vec<AddOperation<D>>,
+    deletes: Vec<DeleteOperation>,
+) -> Vec<UserOperation<D>> {
+    adds.into_iter()
+        .map(|add| (add.opstamp, UserOperation::Add(add.document)))
+        .merge_by(
+            deletes.into_iter().map(|delete| match delete.target {
+                DeleteTarget::Term(term) => (delete.opstamp, UserOperation::Delete(term)),
+                _ => unreachable!("the deletes of a batch target terms"),
+            }),
+            |(left_opstamp, _), (right_opstamp, _)| left_opstamp < right_opstamp,
+        )
+        .map(|(_, user_op)| user_op)
+        .collect()
+}
+
 fn error_in_index_worker_thread(context: &str) -> TantivyError {
     TantivyError::ErrorInThread(format!(
         "{context}. A worker thread encountered an error (io::Error most likely) or panicked."
@@ -57,9 +139,74 @@ pub struct IndexWriterOptions {
     #[builder(default = 1)]
     /// The number of indexer worker threads to use.
     num_worker_threads: usize,
+    #[builder(default = 1)]
+    /// The number of documents added with [`IndexWriter::add_document()`] that are sent to the
+    /// indexing workers at once.
+    ///
+    /// Sending a document to the workers can cost more than indexing it, for small documents.
+    /// With larger batches, the writer holds the added documents until the batch is full, and
+    /// sends them before the next delete and on commit, so that the operations are still applied
+    /// in the order of their opstamps. The documents held by the writer are discarded by a
+    /// rollback, like the other uncommitted documents.
+    add_batch_size: usize,
     #[builder(default = 4)]
     /// Defines the number of merger threads to use.
     num_merge_threads: usize,
+    /// Makes the indexer threads adapt the memory usage at which they flush their segment to
+    /// the size of the documents.
+    ///
+    /// See [`AdaptiveFlushThreshold`].
+    adaptive_flush_threshold: Option<AdaptiveFlushThreshold>,
+    #[builder(default = DEFAULT_MAX_COMMIT_PAYLOAD_NUM_BYTES)]
+    /// The maximum size in bytes of a structured commit payload, once serialized as JSON.
+    ///
+    /// See [`IndexWriter::commit_with_payload()`].
+    max_commit_payload_num_bytes: usize,
+    #[builder(default = DEFAULT_NUM_RETAINED_COMMIT_PAYLOADS)]
+    /// The number of commit payloads kept in the index.
+    ///
+    /// See [`Index::list_commit_payloads()`](crate::Index::list_commit_payloads).
+    num_retained_commit_payloads: usize,
+    #[builder(default = DEFAULT_NUM_RETAINED_COMMIT_POINTS)]
+    /// The number of commits, the last one included, whose files are kept in the index so that
+    /// they can still be searched. Older commits are released on commit.
+    ///
+    /// See [`Index::open_commit()`](crate::Index::open_commit).
+    num_retained_commit_points: usize,
+    /// Caps the bytes written per second by all of the merges of the writer, so that merges do
+    /// not starve searches of IO. Unlimited by default.
+    ///
+    /// Indexing and search IO are not throttled. The budget can be adjusted while the writer is
+    /// running, through [`IndexWriter::merge_io_budget()`].
+    merge_io_budget_bytes_per_sec: Option<u64>,
+    #[builder(default = DEFAULT_EXPUNGE_DELETES_MIN_DELETED_RATIO)]
+    /// The ratio of deleted documents above which [`IndexWriter::expunge_deletes()`] merges a
+    /// segment.
+    expunge_deletes_min_deleted_ratio: f64,
+    #[builder(default = DEFAULT_DELETE_MAX_EXPANDED_TERMS)]
+    /// The maximum number of terms a query given to [`IndexWriter::delete_query()`] may expand
+    /// to in a segment, e.g. for a regex or a prefix query.
+    ///
+    /// See [`Query::set_max_expanded_terms()`].
+    delete_max_expanded_terms: usize,
+    #[builder(default = DEFAULT_MERGE_POLICY_TIMEOUT)]
+    /// The time the segment updater waits for
+    /// [`MergePolicy::compute_merge_candidates_ctx()`] to resolve before giving up on the merges
+    /// it suggests.
+    merge_policy_timeout: Duration,
+    /// Records the operations of the writer, e.g. to replay them into a hot-standby index.
+    ///
+    /// The operations that were not committed by the index are discarded from the log when the
+    /// writer is created. See [`OperationLog`].
+    operation_log: Option<OperationLog>,
+    /// The `u64` fast field holding the versions of the documents, compared by
+    /// [`IndexWriter::update_document_versioned()`].
+    version_field: Option<Field>,
+    #[builder(default)]
+    /// What to do with the documents whose text cannot be tokenized entirely.
+    ///
+    /// See [`TokenizationErrorPolicy`].
+    tokenization_error_policy: TokenizationErrorPolicy,
 }
 
 /// `IndexWriter` is the user entry-point to add document to an index.
@@ -90,33 +237,184 @@ pub struct IndexWriter<D: Document = TantivyDocument> {
 
     stamper: Stamper,
     committed_opstamp: Opstamp,
+
+    /// Held while stamping and enqueuing operations, so that the delete queue and the indexing
+    /// pipeline receive the operations in opstamp order. The indexing workers rely on this order
+    /// to apply each delete to all of the documents stamped before it, and to those only.
+    ///
+    /// Holds the added documents that are not sent to the indexing workers yet, see
+    /// `add_batch_size` in [`IndexWriterOptions`]. They are shared by all of the threads adding
+    /// documents, as per-thread batches would reach the workers out of opstamp order.
+    ///
+    /// The lock is not held while blocking on a full pipeline: the batches taken under it get a
+    /// ticket from `add_batch_sequencer`, and are sent in the order of their tickets once it is
+    /// released.
+    enqueue_lock: Mutex<AddBatch<D>>,
+
+    /// Orders the sends of the batches taken under `enqueue_lock`.
+    add_batch_sequencer: AddBatchSequencer,
+
+    /// Serialized term and opstamp of the last delete enqueued by
+    /// [`IndexWriter::delete_term_ref`].
+    last_delete_term: Mutex<Option<(Vec<u8>, Opstamp)>>,
+
+    /// Held for reading by [`IndexWriter::try_run`] and [`IndexWriter::run_timeout`] from the
+    /// moment the adds of a batch are enqueued until its deletes are pushed. Indexing workers
+    /// wait on it before applying the deletes to their segment, so that they do not miss the
+    /// deletes of a batch they indexed.
+    pending_batch_deletes: Arc<RwLock<()>>,
+
+    /// Keys upserted by [`IndexWriter::upsert_document_with_policy`]. Held while an upsert
+    /// decides whether the key exists and enqueues its operations, so that concurrent upserts of
+    /// the same key do not both see it as fresh.
+    upsert_state: Mutex<UpsertState>,
+
+    /// Versions of the keys updated by [`IndexWriter::update_document_versioned`]. Held while
+    /// an update checks the live version of its key and enqueues its operations, so that
+    /// concurrent updates of the same key are applied in the order of their checks.
+    version_state: Mutex<VersionState>,
+
+    /// Tokenizes the added documents, unless the `tokenization_error_policy` is
+    /// [`TokenizationErrorPolicy::Skip`].
+    tokenization_checker: Option<TokenizationChecker>,
+
+    /// Tokenization problems of the documents added since the last commit, filled by the
+    /// indexing workers and the checks of the added documents.
+    indexing_report: Arc<Mutex<IndexingReport>>,
+
+    /// Test hook: while held for writing, the indexing workers stop consuming documents.
+    #[cfg(test)]
+    paused_workers: Arc<RwLock<()>>,
+
+    /// Test hook: number of batches of documents sent to the indexing workers.
+    #[cfg(test)]
+    num_sent_add_batches: std::sync::atomic::AtomicUsize,
+
+    memory_usage: Arc<MemoryUsageTracker>,
+}
+
+/// Documents soft-deleted in a segment.
+#[derive(Clone)]
+pub(crate) struct SoftDeletedDocs {
+    /// Documents that are not soft-deleted.
+    not_soft_deleted: BitSet,
+    /// Tombstone fields of the soft deletes, sorted.
+    tombstone_fields: Vec<String>,
+}
+
+impl SoftDeletedDocs {
+    fn new(max_doc: u32) -> SoftDeletedDocs {
+        SoftDeletedDocs {
+            not_soft_deleted: BitSet::with_max_value_and_full(max_doc),
+            tombstone_fields: Vec::new(),
+        }
+    }
+
+    /// Loads the soft-deleted documents persisted in the segment, if any.
+    fn open(segment_reader: &SegmentReader) -> Option<SoftDeletedDocs> {
+        let not_soft_deleted_bitset = segment_reader.not_soft_deleted_bitset()?;
+        let mut soft_deleted_docs = SoftDeletedDocs::new(segment_reader.max_doc());
+        soft_deleted_docs.merge_persisted(segment_reader, not_soft_deleted_bitset);
+        Some(soft_deleted_docs)
+    }
+
+    /// Adds the soft-deleted documents persisted in the segment.
+    fn merge_persisted(
+        &mut self,
+        segment_reader: &SegmentReader,
+        not_soft_deleted_bitset: &AliveBitSet,
+    ) {
+        self.not_soft_deleted
+            .intersect_update(not_soft_deleted_bitset.bitset());
+        for tombstone_field in segment_reader.soft_delete_tombstone_fields() {
+            self.add_tombstone_field(tombstone_field);
+        }
+    }
+
+    fn soft_delete(&mut self, doc: DocId) {
+        self.not_soft_deleted.remove(doc);
+    }
+
+    fn add_tombstone_field(&mut self, tombstone_field: &str) {
+        if let Err(pos) = self
+            .tombstone_fields
+            .binary_search_by(|field| field.as_str().cmp(tombstone_field))
+        {
+            self.tombstone_fields
+                .insert(pos, tombstone_field.to_string());
+        }
+    }
+
+    /// Forgets the soft deletes of the documents that are not alive anymore, and returns the
+    /// number of soft-deleted documents.
+    fn retain_alive(&mut self, alive_bitset: &BitSet) -> u32 {
+        let mut num_soft_deleted_docs = 0;
+        for doc in 0..self.not_soft_deleted.max_value() {
+            if self.not_soft_deleted.contains(doc) {
+                continue;
+            }
+            if alive_bitset.contains(doc) {
+                num_soft_deleted_docs += 1;
+            } else {
+                self.not_soft_deleted.insert(doc);
+            }
+        }
+        num_soft_deleted_docs
+    }
 }
 
 fn compute_deleted_bitset(
     alive_bitset: &mut BitSet,
+    soft_deleted_docs: &mut Option<SoftDeletedDocs>,
+    segment: &Segment,
     segment_reader: &SegmentReader,
     delete_cursor: &mut DeleteCursor,
     doc_opstamps: &DocToOpstampMapping,
     target_opstamp: Opstamp,
 ) -> crate::Result<bool> {
+    let deleted_segment = DeletedSegment::new(segment, segment_reader);
     let mut might_have_changed = false;
     while let Some(delete_op) = delete_cursor.get() {
         if delete_op.opstamp > target_opstamp {
             break;
         }
 
+        if let Some(tombstone_field) = delete_op.tombstone_field {
+            let soft_deleted_docs = soft_deleted_docs
+                .get_or_insert_with(|| SoftDeletedDocs::new(segment_reader.max_doc()));
+            let mut has_matches = false;
+            delete_op.target.for_each_no_score(
+                &deleted_segment,
+                &mut |docs_matching_delete_query| {
+                    for doc_matching_delete_query in docs_matching_delete_query.iter().cloned() {
+                        if doc_opstamps.is_deleted(doc_matching_delete_query, delete_op.opstamp) {
+                            soft_deleted_docs.soft_delete(doc_matching_delete_query);
+                            has_matches = true;
+                        }
+                    }
+                },
+            )?;
+            if has_matches {
+                let tombstone_field_name = segment_reader.schema().get_field_name(tombstone_field);
+                soft_deleted_docs.add_tombstone_field(tombstone_field_name);
+            }
+            delete_cursor.advance();
+            continue;
+        }
+
         // A delete operation should only affect
         // document that were inserted before it.
-        delete_op
-            .target
-            .for_each_no_score(segment_reader, &mut |docs_matching_delete_query| {
+        delete_op.target.for_each_no_score(
+            &deleted_segment,
+            &mut |docs_matching_delete_query| {
                 for doc_matching_delete_query in docs_matching_delete_query.iter().cloned() {
                     if doc_opstamps.is_deleted(doc_matching_delete_query, delete_op.opstamp) {
                         alive_bitset.remove(doc_matching_delete_query);
                         might_have_changed = true;
                     }
                 }
-            })?;
+            },
+        )?;
         delete_cursor.advance();
     }
     Ok(might_have_changed)
@@ -138,7 +436,10 @@ pub(crate) fn advance_deletes(
         return Ok(());
     }
 
-    if segment_entry.alive_bitset().is_none() && segment_entry.delete_cursor().get().is_none() {
+    if segment_entry.alive_bitset().is_none()
+        && segment_entry.soft_deleted_docs().is_none()
+        && segment_entry.delete_cursor().get().is_none()
+    {
         // There has been no `DeleteOperation` between the segment status and `target_opstamp`.
         return Ok(());
     }
@@ -150,11 +451,23 @@ pub(crate) fn advance_deletes(
         Some(previous_alive_bitset) => (*previous_alive_bitset).clone(),
         None => BitSet::with_max_value_and_full(max_doc),
     };
+    let mut soft_deleted_docs: Option<SoftDeletedDocs> = match segment_entry.soft_deleted_docs() {
+        Some(previous_soft_deleted_docs) => {
+            let mut soft_deleted_docs = previous_soft_deleted_docs.clone();
+            if let Some(not_soft_deleted_bitset) = segment_reader.not_soft_deleted_bitset() {
+                soft_deleted_docs.merge_persisted(&segment_reader, not_soft_deleted_bitset);
+            }
+            Some(soft_deleted_docs)
+        }
+        None => SoftDeletedDocs::open(&segment_reader),
+    };
 
     let num_deleted_docs_before = segment.meta().num_deleted_docs();
 
     compute_deleted_bitset(
         &mut alive_bitset,
+        &mut soft_deleted_docs,
+        &segment,
         &segment_reader,
         segment_entry.delete_cursor(),
         &DocToOpstampMapping::None,
@@ -175,24 +488,47 @@ pub(crate) fn advance_deletes(
         alive_doc_file.terminate()?;
     }
 
+    if let Some(mut soft_deleted_docs) = soft_deleted_docs {
+        let num_soft_deleted_docs = soft_deleted_docs.retain_alive(&alive_bitset);
+        if num_soft_deleted_docs != segment.meta().num_soft_deleted_docs()
+            || soft_deleted_docs.tombstone_fields != segment.meta().soft_delete_tombstone_fields()
+        {
+            // The soft deletes changed. We need to write a new soft delete file.
+            segment = segment.with_soft_delete_meta(
+                num_soft_deleted_docs,
+                target_opstamp,
+                soft_deleted_docs.tombstone_fields.clone(),
+            );
+            let mut soft_delete_file = segment.open_write(SegmentComponent::SoftDelete)?;
+            write_alive_bitset(&soft_deleted_docs.not_soft_deleted, &mut soft_delete_file)?;
+            soft_delete_file.terminate()?;
+        }
+    }
+
     segment_entry.set_meta(segment.meta().clone());
     Ok(())
 }
 
+#[expect(clippy::too_many_arguments)]
 fn index_documents<D: Document>(
     memory_budget: usize,
     segment: Segment,
     grouped_document_iterator: &mut dyn Iterator<Item = AddBatch<D>>,
     segment_updater: &SegmentUpdater,
     mut delete_cursor: DeleteCursor,
+    pending_batch_deletes: &RwLock<()>,
+    thread_memory_usage: &mut ThreadMemoryUsage,
+    indexing_report: &Mutex<IndexingReport>,
 ) -> crate::Result<()> {
     let mut segment_writer = SegmentWriter::for_segment(memory_budget, segment.clone())?;
+    thread_memory_usage.start_segment(segment_writer.mem_usage());
     for document_group in grouped_document_iterator {
         for doc in document_group {
             segment_writer.add_document(doc)?;
         }
         let mem_usage = segment_writer.mem_usage();
-        if mem_usage >= memory_budget - MARGIN_IN_BYTES {
+        let flush_threshold = thread_memory_usage.update(mem_usage, segment_writer.max_doc());
+        if mem_usage >= flush_threshold {
             info!(
                 "Buffer limit reached, flushing segment with maxdoc={}.",
                 segment_writer.max_doc()
@@ -211,17 +547,34 @@ fn index_documents<D: Document>(
     // the worker thread.
     assert!(max_doc > 0);
 
+    indexing_report
+        .lock()
+        .expect("Lock poisoned. This should never happen")
+        .merge(segment_writer.take_indexing_report());
+    let mem_usage = segment_writer.mem_usage();
+    let store_compressor = segment_writer.store_compressor();
     let doc_opstamps: Vec<Opstamp> = segment_writer.finalize()?;
 
-    let segment_with_max_doc = segment.with_max_doc(max_doc);
+    let segment_with_max_doc = segment
+        .with_max_doc(max_doc)
+        .with_store_compressor(store_compressor);
 
-    let alive_bitset_opt = apply_deletes(&segment_with_max_doc, &mut delete_cursor, &doc_opstamps)?;
+    // Waits for the batches being enqueued to push their deletes.
+    drop(
+        pending_batch_deletes
+            .write()
+            .expect("Lock poisoned. This should never happen"),
+    );
+    let (alive_bitset_opt, soft_deleted_docs_opt) =
+        apply_deletes(&segment_with_max_doc, &mut delete_cursor, &doc_opstamps)?;
 
     let meta = segment_with_max_doc.meta().clone();
     meta.untrack_temp_docstore();
     // update segment_updater inventory to remove tempstore
-    let segment_entry = SegmentEntry::new(meta, delete_cursor, alive_bitset_opt);
+    let segment_entry = SegmentEntry::new(meta, delete_cursor, alive_bitset_opt)
+        .with_soft_deleted_docs(soft_deleted_docs_opt);
     segment_updater.schedule_add_segment(segment_entry).wait()?;
+    thread_memory_usage.record_flush(mem_usage, max_doc);
     Ok(())
 }
 
@@ -230,11 +583,11 @@ fn apply_deletes(
     segment: &Segment,
     delete_cursor: &mut DeleteCursor,
     doc_opstamps: &[Opstamp],
-) -> crate::Result<Option<BitSet>> {
+) -> crate::Result<(Option<BitSet>, Option<SoftDeletedDocs>)> {
     if delete_cursor.get().is_none() {
         // if there are no delete operation in the queue, no need
         // to even open the segment.
-        return Ok(None);
+        return Ok((None, None));
     }
 
     let max_doc_opstamp: Opstamp = doc_opstamps
@@ -248,18 +601,22 @@ fn apply_deletes(
 
     let max_doc = segment.meta().max_doc();
     let mut deleted_bitset = BitSet::with_max_value_and_full(max_doc);
+    let mut soft_deleted_docs = None;
     let may_have_deletes = compute_deleted_bitset(
         &mut deleted_bitset,
+        &mut soft_deleted_docs,
+        segment,
         &segment_reader,
         delete_cursor,
         &doc_to_opstamps,
         max_doc_opstamp,
     )?;
-    Ok(if may_have_deletes {
+    let alive_bitset_opt = if may_have_deletes {
         Some(deleted_bitset)
     } else {
         None
-    })
+    };
+    Ok((alive_bitset_opt, soft_deleted_docs))
 }
 
 impl<D: Document> IndexWriter<D> {
@@ -295,18 +652,48 @@ impl<D: Document> IndexWriter<D> {
             );
             return Err(TantivyError::InvalidArgument(err_msg));
         }
+        if let Some(adaptive_flush_threshold) = options.adaptive_flush_threshold {
+            let min_flush_threshold = adaptive_flush_threshold.min_flush_threshold();
+            let max_flush_threshold = adaptive_flush_threshold.max_flush_threshold();
+            if min_flush_threshold > max_flush_threshold {
+                let err_msg = format!(
+                    "The min flush threshold {min_flush_threshold} cannot exceed the max flush \
+                     threshold {max_flush_threshold}."
+                );
+                return Err(TantivyError::InvalidArgument(err_msg));
+            }
+            if max_flush_threshold > options.memory_budget_per_thread - MARGIN_IN_BYTES {
+                let err_msg = format!(
+                    "The max flush threshold {max_flush_threshold} cannot exceed the memory \
+                     budget per thread minus {MARGIN_IN_BYTES}."
+                );
+                return Err(TantivyError::InvalidArgument(err_msg));
+            }
+        }
         if options.num_worker_threads == 0 {
             let err_msg = "At least one worker thread is required, got 0".to_string();
             return Err(TantivyError::InvalidArgument(err_msg));
         }
+        if options.add_batch_size == 0 {
+            let err_msg = "The add batch size needs to be positive, got 0".to_string();
+            return Err(TantivyError::InvalidArgument(err_msg));
+        }
+        if options.merge_io_budget_bytes_per_sec == Some(0) {
+            let err_msg = "The merge IO budget needs to be positive, got 0".to_string();
+            return Err(TantivyError::InvalidArgument(err_msg));
+        }
 
         let (document_sender, document_receiver) =
-            crossbeam_channel::bounded(PIPELINE_MAX_SIZE_IN_DOCS);
+            crossbeam_channel::bounded(pipeline_capacity(options.add_batch_size));
 
         let delete_queue = DeleteQueue::new();
 
         let current_opstamp = index.load_metas()?.opstamp;
 
+        if let Some(operation_log) = &options.operation_log {
+            operation_log.attach(current_opstamp)?;
+        }
+
         let stamper = Stamper::new(current_opstamp);
 
         let segment_updater = SegmentUpdater::create(
@@ -314,8 +701,17 @@ impl<D: Document> IndexWriter<D> {
             stamper.clone(),
             &delete_queue.cursor(),
             options.num_merge_threads,
+            IoBudget::new(options.merge_io_budget_bytes_per_sec),
+            options.merge_policy_timeout,
         )?;
 
+        let tokenization_checker = match options.tokenization_error_policy {
+            TokenizationErrorPolicy::Skip => None,
+            TokenizationErrorPolicy::FailDocument | TokenizationErrorPolicy::FailBatch => {
+                Some(TokenizationChecker::new(index)?)
+            }
+        };
+
         let mut index_writer = Self {
             _directory_lock: Some(directory_lock),
 
@@ -332,8 +728,25 @@ impl<D: Document> IndexWriter<D> {
 
             committed_opstamp: current_opstamp,
             stamper,
+            enqueue_lock: Mutex::default(),
+            add_batch_sequencer: AddBatchSequencer::default(),
+            last_delete_term: Mutex::default(),
+            pending_batch_deletes: Default::default(),
+            upsert_state: Mutex::default(),
+            version_state: Mutex::default(),
+            tokenization_checker,
+            indexing_report: Arc::default(),
+            #[cfg(test)]
+            paused_workers: Default::default(),
+            #[cfg(test)]
+            num_sent_add_batches: Default::default(),
 
             worker_id: 0,
+
+            memory_usage: Arc::new(MemoryUsageTracker::new(
+                options.memory_budget_per_thread,
+                options.adaptive_flush_threshold,
+            )),
         };
         index_writer.start_workers()?;
         Ok(index_writer)
@@ -349,6 +762,14 @@ impl<D: Document> IndexWriter<D> {
         &self.index
     }
 
+    /// Returns the budget throttling the files written by merges.
+    ///
+    /// The budget is shared by all of the merge threads, and can be adjusted while merges are
+    /// running, e.g. `index_writer.merge_io_budget().set_bytes_per_sec(Some(50_000_000))`.
+    pub fn merge_io_budget(&self) -> &IoBudget {
+        self.segment_updater.merge_io_budget()
+    }
+
     /// If there are some merging threads, blocks until they all finish their work and
     /// then drop the `IndexWriter`.
     pub fn wait_merging_threads(mut self) -> crate::Result<()> {
@@ -397,6 +818,34 @@ impl<D: Document> IndexWriter<D> {
         self.index.new_segment()
     }
 
+    /// Replaces the segment `replaced_segment_id` with a copy of it, e.g. rewritten offline
+    /// with a better compressed doc store, without merging it.
+    ///
+    /// The files of the copy are written beforehand in a [new segment](Self::new_segment), and
+    /// `segment_meta` is its meta. The copy must contain the same documents as the replaced
+    /// segment, in the same order, written with the schema of the index. Its deletes are
+    /// either the same as those of the replaced segment, or absent, in which case those of the
+    /// replaced segment are carried over. The deletes of the replaced segment which are not
+    /// committed yet are applied to the copy.
+    ///
+    /// The replacement becomes visible at the next commit, which garbage collects the files of
+    /// the replaced segment like those of a merged segment. Until then, the replaced segment
+    /// cannot be merged.
+    ///
+    /// Returns [`TantivyError::SegmentBusy`](crate::TantivyError::SegmentBusy) if the replaced
+    /// segment is being merged or replaced, and
+    /// [`TantivyError::InvalidArgument`](crate::TantivyError::InvalidArgument) if the copy
+    /// differs from the replaced segment.
+    pub fn replace_segment(
+        &self,
+        replaced_segment_id: SegmentId,
+        segment_meta: SegmentMeta,
+    ) -> crate::Result<()> {
+        self.segment_updater
+            .schedule_replace_segment(replaced_segment_id, segment_meta)
+            .wait()
+    }
+
     fn operation_receiver(&self) -> crate::Result<AddBatchReceiver<D>> {
         self.index_writer_status
             .operation_receiver()
@@ -420,7 +869,12 @@ impl<D: Document> IndexWriter<D> {
         let mut delete_cursor = self.delete_queue.cursor();
 
         let mem_budget = self.options.memory_budget_per_thread;
+        let mut thread_memory_usage = self.memory_usage.register_thread(self.worker_id);
         let index = self.index.clone();
+        let pending_batch_deletes = self.pending_batch_deletes.clone();
+        let indexing_report = self.indexing_report.clone();
+        #[cfg(test)]
+        let paused_workers = self.paused_workers.clone();
         let join_handle: JoinHandle<crate::Result<()>> = thread::Builder::new()
             .name(format!("thrd-tantivy-index{}", self.worker_id))
             .spawn(move || {
@@ -428,6 +882,10 @@ impl<D: Document> IndexWriter<D> {
                     let mut document_iterator = document_receiver_clone
                         .clone()
                         .into_iter()
+                        .inspect(|_| {
+                            #[cfg(test)]
+                            drop(paused_workers.read().unwrap());
+                        })
                         .filter(|batch| !batch.is_empty())
                         .peekable();
 
@@ -436,23 +894,27 @@ impl<D: Document> IndexWriter<D> {
                     //
                     // This is a valid guarantee as the peeked document now belongs to
                     // our local iterator.
-                    if let Some(batch) = document_iterator.peek() {
+                    let first_opstamp = if let Some(batch) = document_iterator.peek() {
                         assert!(!batch.is_empty());
                         delete_cursor.skip_to(batch[0].opstamp);
+                        batch[0].opstamp
                     } else {
                         // No more documents.
                         // It happens when there is a commit, or if the `IndexWriter`
                         // was dropped.
                         index_writer_bomb.defuse();
                         return Ok(());
-                    }
+                    };
 
                     index_documents(
                         mem_budget,
-                        index.new_segment(),
+                        index.new_segment_for_flush(first_opstamp),
                         &mut document_iterator,
                         &segment_updater,
                         delete_cursor.clone(),
+                        &pending_batch_deletes,
+                        &mut thread_memory_usage,
+                        &indexing_report,
                     )?;
                 }
             })?;
@@ -461,6 +923,32 @@ impl<D: Document> IndexWriter<D> {
         Ok(())
     }
 
+    /// Returns the memory usage of the indexing threads, with the memory usage at which they
+    /// will flush their segment.
+    pub fn memory_usage_report(&self) -> MemoryUsageReport {
+        self.memory_usage.report()
+    }
+
+    /// Returns the on-disk size of each segment, with its ratio of deleted documents and an
+    /// estimate of the disk space that merges expunging these deletes would give back.
+    ///
+    /// Deletes that are not committed yet are included.
+    pub fn reclaimable_space_report(&self) -> crate::Result<ReclaimReport> {
+        self.segment_updater.reclaimable_space_report()
+    }
+
+    /// Schedules a merge for each segment whose ratio of deleted documents is at least
+    /// [`IndexWriterOptions`]'s `expunge_deletes_min_deleted_ratio`.
+    ///
+    /// Each of these segments is merged on its own, which rewrites it without its deleted
+    /// documents. Segments that are already being merged are skipped.
+    ///
+    /// Returns one future per scheduled merge, resolving to the meta of the merged segment.
+    pub fn expunge_deletes(&self) -> Vec<FutureResult<Option<SegmentMeta>>> {
+        self.segment_updater
+            .expunge_deletes(self.options.expunge_deletes_min_deleted_ratio)
+    }
+
     /// Accessor to the merge policy.
     pub fn get_merge_policy(&self) -> Arc<dyn MergePolicy> {
         self.segment_updater.get_merge_policy()
@@ -471,6 +959,44 @@ impl<D: Document> IndexWriter<D> {
         self.segment_updater.set_merge_policy(merge_policy);
     }
 
+    /// Sets the context given to the merge policy, e.g. the current search load of the
+    /// application.
+    ///
+    /// The merge policy can read it with
+    /// [`MergeContext::user_context()`](crate::merge_policy::MergeContext::user_context).
+    pub fn set_merge_policy_context(&self, context: Arc<dyn Any + Send + Sync>) {
+        self.segment_updater.set_merge_policy_context(context);
+    }
+
+    /// Accessor to the soft delete retention policy.
+    pub fn get_soft_delete_retention_policy(&self) -> Arc<dyn SoftDeleteRetentionPolicy> {
+        self.segment_updater.get_soft_delete_retention_policy()
+    }
+
+    /// Setter for the soft delete retention policy.
+    ///
+    /// The policy decides which soft-deleted documents are hard-deleted when their segment is
+    /// merged.
+    pub fn set_soft_delete_retention_policy(
+        &self,
+        soft_delete_retention_policy: Box<dyn SoftDeleteRetentionPolicy>,
+    ) {
+        self.segment_updater
+            .set_soft_delete_retention_policy(soft_delete_retention_policy);
+    }
+
+    /// Adds a validator checking the segments of each commit before it is published.
+    ///
+    /// The validators are called on the segments created or updated by the commit, before the
+    /// commit point is written. If one of them fails, the commit returns its error and none of
+    /// the documents of the commit become visible. They stay uncommitted, so that a later commit
+    /// fails too, until the writer is rolled back with [`IndexWriter::rollback()`]. The
+    /// validators are kept by the rollback.
+    pub fn add_commit_validator(&self, commit_validator: Box<dyn CommitValidator>) {
+        self.segment_updater
+            .add_commit_validator(Arc::from(commit_validator));
+    }
+
     fn start_workers(&mut self) -> crate::Result<()> {
         for _ in 0..self.options.num_worker_threads {
             self.add_indexing_worker()?;
@@ -483,6 +1009,18 @@ impl<D: Document> IndexWriter<D> {
         self.segment_updater.schedule_garbage_collect()
     }
 
+    /// Releases a retained commit point, so that the garbage collection can delete the files
+    /// that only this commit uses, e.g. its delete bitsets. Returns false if the commit was not
+    /// retained.
+    ///
+    /// The files stay in the index as long as readers opened on the commit with
+    /// [`Index::open_commit()`](crate::Index::open_commit) are alive.
+    pub fn release_commit_point(&self, opstamp: Opstamp) -> crate::Result<bool> {
+        self.segment_updater
+            .schedule_release_commit_point(opstamp)
+            .wait()
+    }
+
     /// Deletes all documents from the index
     ///
     /// Requires `commit`ing
@@ -520,8 +1058,18 @@ impl<D: Document> IndexWriter<D> {
     /// }
     /// ```
     pub fn delete_all_documents(&self) -> crate::Result<Opstamp> {
+        // The documents that were not sent to the workers yet are deleted too.
+        self.lock_enqueue().clear();
+        self.version_state.lock().unwrap().clear_uncommitted();
+        *self.indexing_report.lock().unwrap() = IndexingReport::default();
         // Delete segments
         self.segment_updater.remove_all_segments();
+        // The opstamps of the deletes get reused.
+        let mut last_delete_term = self.last_delete_term.lock().unwrap();
+        *last_delete_term = None;
+        if let Some(operation_log) = &self.options.operation_log {
+            operation_log.append_delete_all(self.committed_opstamp);
+        }
         // Return new stamp - reverted stamp
         self.stamper.revert(self.committed_opstamp);
         Ok(self.committed_opstamp)
@@ -538,6 +1086,41 @@ impl<D: Document> IndexWriter<D> {
         segment_updater.start_merge(merge_operation)
     }
 
+    /// Merges a given list of segments, reporting the progress of the merge to `on_progress`.
+    ///
+    /// The callback is called from the merging thread, see
+    /// [`MergeProgress`](crate::indexer::MergeProgress) for how often.
+    /// The returned [`MergeHandle`] resolves to the meta of the merged segment like
+    /// [`IndexWriter::merge()`], but dropping it cancels the merge.
+    ///
+    /// `segment_ids` is required to be non-empty.
+    pub fn merge_with_progress(
+        &mut self,
+        segment_ids: &[SegmentId],
+        on_progress: MergeProgressCallback,
+    ) -> MergeHandle {
+        let merge_operation = self.segment_updater.make_merge_operation(segment_ids);
+        let monitor = MergeMonitor::new(on_progress);
+        let is_cancelled = monitor.cancellation_flag();
+        let merge_result = self
+            .segment_updater
+            .start_merge_with_monitor(merge_operation, monitor);
+        MergeHandle::new(merge_result, is_cancelled)
+    }
+
+    /// Merges a given list of segments, reporting the progress of the merge to `on_progress`,
+    /// and blocks until the merge is done.
+    ///
+    /// Returns the meta of the merged segment, or `None` if all the merged documents were
+    /// deleted. See [`IndexWriter::merge_with_progress()`].
+    pub fn merge_segments_and_wait(
+        &mut self,
+        segment_ids: &[SegmentId],
+        on_progress: MergeProgressCallback,
+    ) -> crate::Result<Option<SegmentMeta>> {
+        self.merge_with_progress(segment_ids, on_progress).wait()
+    }
+
     /// Closes the current document channel send.
     /// and replace all the channels by new ones.
     ///
@@ -548,7 +1131,7 @@ impl<D: Document> IndexWriter<D> {
     /// Returns the former segment_ready channel.
     fn recreate_document_channel(&mut self) {
         let (document_sender, document_receiver) =
-            crossbeam_channel::bounded(PIPELINE_MAX_SIZE_IN_DOCS);
+            crossbeam_channel::bounded(pipeline_capacity(self.options.add_batch_size));
         self.operation_sender = document_sender;
         self.index_writer_status = IndexWriterStatus::from(document_receiver);
     }
@@ -575,6 +1158,11 @@ impl<D: Document> IndexWriter<D> {
             .expect("The IndexWriter does not have any lock. This is a bug, please report.");
 
         let new_index_writer = IndexWriter::new(&self.index, self.options.clone(), directory_lock)?;
+        for commit_validator in self.segment_updater.commit_validators() {
+            new_index_writer
+                .segment_updater
+                .add_commit_validator(commit_validator);
+        }
 
         // the current `self` is dropped right away because of this call.
         //
@@ -628,6 +1216,15 @@ impl<D: Document> IndexWriter<D> {
         // committed segments.
         info!("Preparing commit");
 
+        let pending_adds = std::mem::take(
+            self.enqueue_lock
+                .get_mut()
+                .expect("Lock poisoned. This should never happen"),
+        );
+        if !pending_adds.is_empty() {
+            self.send_add_documents_batch(pending_adds)?;
+        }
+
         // this will drop the current document channel
         // and recreate a new one.
         self.recreate_document_channel();
@@ -642,8 +1239,24 @@ impl<D: Document> IndexWriter<D> {
             self.add_indexing_worker()?;
         }
 
+        self.upsert_state
+            .get_mut()
+            .expect("Lock poisoned. This should never happen")
+            .invalidate_committed();
+        self.version_state
+            .get_mut()
+            .expect("Lock poisoned. This should never happen")
+            .invalidate_committed();
+
+        let indexing_report = std::mem::take(
+            &mut *self
+                .indexing_report
+                .lock()
+                .expect("Lock poisoned. This should never happen"),
+        );
+
         let commit_opstamp = self.stamper.stamp();
-        let prepared_commit = PreparedCommit::new(self, commit_opstamp);
+        let prepared_commit = PreparedCommit::new(self, commit_opstamp, indexing_report);
         info!("Prepared commit {}", commit_opstamp);
         Ok(prepared_commit)
     }
@@ -665,10 +1278,111 @@ impl<D: Document> IndexWriter<D> {
         self.prepare_commit()?.commit()
     }
 
+    /// Commits all of the pending changes, without waiting for the commit to be persisted.
+    ///
+    /// Preparing the commit still happens synchronously, see [`IndexWriter::prepare_commit()`].
+    /// The returned future resolves once the commit is durable on disk. The resulting
+    /// [`CommitHandle`] can then be used to wait until an [`IndexReader`](crate::IndexReader)
+    /// serves a searcher including this commit.
+    ///
+    /// The future does not need to be polled for the commit to progress, and does not depend on
+    /// any specific executor.
+    pub fn commit_async(&mut self) -> impl Future<Output = crate::Result<CommitHandle>> {
+        let commit_future = match self.prepare_commit() {
+            Ok(prepared_commit) => prepared_commit.commit_future(),
+            Err(err) => FutureResult::from(err),
+        };
+        async move {
+            let opstamp = commit_future.await?;
+            Ok(CommitHandle::new(opstamp))
+        }
+    }
+
+    /// Commits all of the pending changes, with a structured payload.
+    ///
+    /// The payload is serialized as JSON and stored with the commit, see
+    /// [`Index::commit_payload()`](crate::Index::commit_payload). If the serialized payload
+    /// exceeds the maximum size set in the [`IndexWriterOptions`],
+    /// [`TantivyError::CommitPayloadTooLarge`] is returned and nothing is committed.
+    ///
+    /// To update the payload of the last commit, see [`PreparedCommit::modify_payload()`].
+    pub fn commit_with_payload(&mut self, payload: impl Serialize) -> crate::Result<Opstamp> {
+        let payload =
+            serialize_commit_payload(&payload, self.options.max_commit_payload_num_bytes)?;
+        let mut prepared_commit = self.prepare_commit()?;
+        prepared_commit.set_payload(&payload);
+        prepared_commit.commit()
+    }
+
+    /// Commits all of the pending changes, returning the tokenization problems of the documents
+    /// of the commit.
+    ///
+    /// See [`TokenizationErrorPolicy`] and [`PreparedCommit::indexing_report()`].
+    pub fn commit_with_report(&mut self) -> crate::Result<(Opstamp, IndexingReport)> {
+        let prepared_commit = self.prepare_commit()?;
+        let indexing_report = prepared_commit.indexing_report().clone();
+        let opstamp = prepared_commit.commit()?;
+        Ok((opstamp, indexing_report))
+    }
+
     pub(crate) fn segment_updater(&self) -> &SegmentUpdater {
         &self.segment_updater
     }
 
+    /// Returns the [`OperationLog`] of the writer, if any.
+    pub fn operation_log(&self) -> Option<&OperationLog> {
+        self.options.operation_log.as_ref()
+    }
+
+    /// Serializes the operations of a batch for the operation log, if the writer has one.
+    fn log_user_operations(
+        &self,
+        user_operations: &[UserOperation<D>],
+        stamps: Range<Opstamp>,
+    ) -> crate::Result<Option<OperationLogBatch>> {
+        if self.options.operation_log.is_none() {
+            return Ok(None);
+        }
+        let schema = self.index.schema();
+        let mut batch = OperationLogBatch::default();
+        for (user_op, opstamp) in user_operations.iter().zip(stamps) {
+            match user_op {
+                UserOperation::Delete(term) => batch.delete(opstamp, term),
+                UserOperation::Add(document) => batch.add(opstamp, document, &schema)?,
+            }
+        }
+        Ok(Some(batch))
+    }
+
+    /// Serializes `document` for the operation log, if the writer has one.
+    fn log_adds(
+        &self,
+        stamps: Range<Opstamp>,
+        documents: &[D],
+    ) -> crate::Result<Option<OperationLogBatch>> {
+        if self.options.operation_log.is_none() {
+            return Ok(None);
+        }
+        let schema = self.index.schema();
+        let mut batch = OperationLogBatch::default();
+        for (opstamp, document) in stamps.zip(documents) {
+            batch.add(opstamp, document, &schema)?;
+        }
+        Ok(Some(batch))
+    }
+
+    pub(crate) fn max_commit_payload_num_bytes(&self) -> usize {
+        self.options.max_commit_payload_num_bytes
+    }
+
+    pub(crate) fn num_retained_commit_payloads(&self) -> usize {
+        self.options.num_retained_commit_payloads
+    }
+
+    pub(crate) fn num_retained_commit_points(&self) -> usize {
+        self.options.num_retained_commit_points
+    }
+
     /// Delete all documents containing a given term.
     ///
     /// Delete operation only affects documents that
@@ -677,7 +1391,65 @@ impl<D: Document> IndexWriter<D> {
     ///
     /// Like adds, the deletion itself will be visible
     /// only after calling `commit()`.
-    pub fn delete_term(&self, term: Term) -> Opstamp {
+    ///
+    /// Returns a `SchemaError` if the term's field does not exist, is not indexed, or if the
+    /// term's type does not match the field type, since such a delete could never match any
+    /// document.
+    pub fn delete_term(&self, term: Term) -> crate::Result<Opstamp> {
+        validate_delete_term(&self.index.schema(), &term)?;
+        Ok(self.delete_term_unchecked(term))
+    }
+
+    /// Delete all documents containing a given term, borrowed e.g. from a
+    /// [`TermBuffer`](crate::schema::TermBuffer).
+    ///
+    /// Behaves like [`IndexWriter::delete_term`], but the term is only copied if the delete is
+    /// enqueued. The delete is not enqueued if the term is invalid, or if the previous delete of
+    /// the writer was for the same term and no other operation happened in between: that
+    /// previous delete then already removes all the matching documents.
+    pub fn delete_term_ref(&self, term: &TermRef) -> crate::Result<Opstamp> {
+        validate_delete_term(&self.index.schema(), term)?;
+        self.enqueue_after_pending_adds(|| self.enqueue_delete_term(term.serialized_term()))
+    }
+
+    /// Stamps and enqueues the delete of `serialized_term`, under the enqueue lock.
+    fn enqueue_delete_term(&self, serialized_term: &[u8]) -> Opstamp {
+        let mut last_delete_term = self.last_delete_term.lock().unwrap();
+        if let Some((last_term, last_opstamp)) = last_delete_term.as_mut() {
+            if last_term.as_slice() == serialized_term {
+                if let Some(opstamp) = self.stamper.stamp_if_next(*last_opstamp + 1) {
+                    *last_opstamp = opstamp;
+                    if let Some(operation_log) = &self.options.operation_log {
+                        operation_log.append_delete(opstamp, serialized_term);
+                    }
+                    return opstamp;
+                }
+            }
+        }
+        let opstamp = self.stamper.stamp();
+        self.delete_queue.push(DeleteOperation {
+            opstamp,
+            target: DeleteTarget::Term(Term::wrap(serialized_term.to_vec())),
+            tombstone_field: None,
+        });
+        if let Some(operation_log) = &self.options.operation_log {
+            operation_log.append_delete(opstamp, serialized_term);
+        }
+        let (last_term, last_opstamp) =
+            last_delete_term.get_or_insert_with(|| (Vec::new(), opstamp));
+        last_term.clear();
+        last_term.extend_from_slice(serialized_term);
+        *last_opstamp = opstamp;
+        opstamp
+    }
+
+    /// Delete all documents containing a given term, without validating the term against the
+    /// schema.
+    ///
+    /// This is an escape hatch for the cases where the validation of
+    /// [`IndexWriter::delete_term`] is too strict. If the term is invalid for the index, nothing
+    /// is deleted but an `Opstamp` is returned.
+    pub fn delete_term_unchecked(&self, term: Term) -> Opstamp {
         let query = TermQuery::new(term, IndexRecordOption::Basic);
         // For backward compatibility, if Term is invalid for the index, do nothing but return an
         // Opstamp
@@ -685,6 +1457,68 @@ impl<D: Document> IndexWriter<D> {
             .unwrap_or_else(|_| self.stamper.stamp())
     }
 
+    /// Delete all documents containing a term within the Levenshtein `distance` of `term`.
+    ///
+    /// The terms are expanded from the term dictionary of each segment when the delete is
+    /// applied, as for a [`FuzzyTermQuery`](crate::query::FuzzyTermQuery). The number of terms
+    /// expanded in a segment is capped by the `delete_max_expanded_terms` of the
+    /// [`IndexWriterOptions`], see [`IndexWriter::delete_query`].
+    ///
+    /// Returns a `SchemaError` if the term is not a valid delete term, and an `InvalidArgument`
+    /// error if the term is not a string or if the distance is greater than 2.
+    pub fn delete_fuzzy_term(
+        &self,
+        term: Term,
+        distance: u8,
+        transposition_cost_one: bool,
+    ) -> crate::Result<Opstamp> {
+        validate_delete_term(&self.index.schema(), &term)?;
+        let query = FuzzyTermQuery::new(term, distance, transposition_cost_one);
+        self.delete_query(Box::new(query))
+    }
+
+    /// Soft-delete all documents containing a given term.
+    ///
+    /// Unlike [`IndexWriter::delete_term`], the documents are not removed from the index: they
+    /// are marked with a tombstone, and stay searchable through
+    /// [`Searcher::include_soft_deleted`](crate::Searcher::include_soft_deleted). Regular
+    /// searches skip them.
+    ///
+    /// `tombstone_field` is recorded with the soft deletes of each segment, see
+    /// [`SegmentReader::soft_delete_tombstone_fields`]. It is typically a fast field the
+    /// [`SoftDeleteRetentionPolicy`] reads to decide when the soft-deleted documents become
+    /// hard-deleted.
+    ///
+    /// Like for regular deletes, the soft delete only affects documents that were added
+    /// before it, and is visible only after calling `commit()`.
+    ///
+    /// Returns a `SchemaError` if the term is not a valid delete term, or if the tombstone field
+    /// does not exist.
+    pub fn soft_delete_term(&self, term: Term, tombstone_field: Field) -> crate::Result<Opstamp> {
+        let schema = self.index.schema();
+        validate_delete_term(&schema, &term)?;
+        if tombstone_field.field_id() as usize >= schema.num_fields() {
+            return Err(TantivyError::SchemaError(format!(
+                "Tombstone field {tombstone_field:?} does not exist in the schema."
+            )));
+        }
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        let weight = query.weight(EnableScoring::disabled_from_schema(&schema))?;
+        self.enqueue_after_pending_adds(|| {
+            let opstamp = self.stamper.stamp();
+            let delete_operation = DeleteOperation {
+                opstamp,
+                target: DeleteTarget::Weight(weight),
+                tombstone_field: Some(tombstone_field),
+            };
+            self.delete_queue.push(delete_operation);
+            if let Some(operation_log) = &self.options.operation_log {
+                operation_log.append_unsupported(opstamp, &format!("soft delete of {query:?}"));
+            }
+            opstamp
+        })
+    }
+
     /// Delete all documents matching a given query.
     /// Returns an `Err` if the query can't be executed.
     ///
@@ -694,16 +1528,43 @@ impl<D: Document> IndexWriter<D> {
     ///
     /// Like adds, the deletion itself will be visible
     /// only after calling `commit()`.
+    ///
+    /// The query is lowered to a weight for each segment when the deletes are applied, with a
+    /// searcher over that segment only, so that it also matches the documents of the segments
+    /// created after this call. Queries expanding terms, such as a
+    /// [`RegexQuery`](crate::query::RegexQuery) or a
+    /// [`PhrasePrefixQuery`](crate::query::PhrasePrefixQuery) on a single prefix, are expanded
+    /// against the term dictionary of each segment at that time.
+    ///
+    /// The number of terms they expand to in a segment is capped by the
+    /// `delete_max_expanded_terms` of the [`IndexWriterOptions`]. Beyond it, applying the delete
+    /// fails with [`TantivyError::TooManyExpandedTerms`], and so does the commit.
     #[doc(hidden)]
-    pub fn delete_query(&self, query: Box<dyn Query>) -> crate::Result<Opstamp> {
-        let weight = query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))?;
-        let opstamp = self.stamper.stamp();
-        let delete_operation = DeleteOperation {
-            opstamp,
-            target: weight,
-        };
-        self.delete_queue.push(delete_operation);
-        Ok(opstamp)
+    pub fn delete_query(&self, mut query: Box<dyn Query>) -> crate::Result<Opstamp> {
+        query.set_max_expanded_terms(self.options.delete_max_expanded_terms);
+        // Only checks that the query can be executed, the weights are built per segment.
+        query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))?;
+        self.enqueue_after_pending_adds(|| {
+            let opstamp = self.stamper.stamp();
+            if let Some(operation_log) = &self.options.operation_log {
+                // Only term deletes can be replayed.
+                match query.downcast_ref::<TermQuery>() {
+                    Some(term_query) => {
+                        operation_log.append_delete(opstamp, term_query.term().serialized_term())
+                    }
+                    None => {
+                        operation_log.append_unsupported(opstamp, &format!("delete of {query:?}"))
+                    }
+                }
+            }
+            let delete_operation = DeleteOperation {
+                opstamp,
+                target: DeleteTarget::Query(query),
+                tombstone_field: None,
+            };
+            self.delete_queue.push(delete_operation);
+            opstamp
+        })
     }
 
     /// Returns the opstamp of the last successful commit.
@@ -717,85 +1578,627 @@ impl<D: Document> IndexWriter<D> {
         self.committed_opstamp
     }
 
+    pub(crate) fn set_committed_opstamp(&mut self, opstamp: Opstamp) {
+        self.committed_opstamp = opstamp;
+    }
+
     /// Adds a document.
     ///
-    /// If the indexing pipeline is full, this call may block.
+    /// If the indexing pipeline is full, this call may block. See
+    /// [`IndexWriter::try_add_document`] for a non-blocking alternative.
     ///
     /// The opstamp is an increasing `u64` that can
     /// be used by the client to align commits with its own
     /// document queue.
     pub fn add_document(&self, document: D) -> crate::Result<Opstamp> {
+        self.check_tokenization(&document)?;
+        self.add_tokenized_document(document)
+    }
+
+    /// Adds a document already checked by [`IndexWriter::check_tokenization`].
+    fn add_tokenized_document(&self, document: D) -> crate::Result<Opstamp> {
+        let mut pending_adds = self.lock_enqueue();
         let opstamp = self.stamper.stamp();
-        self.send_add_documents_batch(smallvec![AddOperation { opstamp, document }])?;
+        let log_batch = self.log_adds(opstamp..opstamp + 1, std::slice::from_ref(&document))?;
+        pending_adds.push(AddOperation { opstamp, document });
+        if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch) {
+            operation_log.append_batch(log_batch);
+        }
+        let full_batch = (pending_adds.len() >= self.options.add_batch_size)
+            .then(|| self.sequence_add_batch(std::mem::take(&mut *pending_adds)));
+        drop(pending_adds);
+        if let Some(full_batch) = full_batch {
+            self.send_sequenced_add_batch(full_batch)?;
+        }
         Ok(opstamp)
     }
 
-    /// Gets a range of stamps from the stamper and "pops" the last stamp
-    /// from the range returning a tuple of the last optstamp and the popped
-    /// range.
+    /// Adds a document, replacing the documents with the same value for `unique_field`.
     ///
-    /// The total number of stamps generated by this method is `count + 1`;
-    /// each operation gets a stamp from the `stamps` iterator and `last_opstamp`
-    /// is for the batch itself.
-    fn get_batch_opstamps(&self, count: Opstamp) -> (Opstamp, Range<Opstamp>) {
-        let Range { start, end } = self.stamper.stamps(count + 1u64);
-        let last_opstamp = end - 1;
-        (last_opstamp, start..last_opstamp)
+    /// Behaves like [`IndexWriter::delete_term`] on the unique key followed by
+    /// [`IndexWriter::add_document`], except that the delete is only enqueued if a document with
+    /// the key may exist. See [`IndexWriter::upsert_document_with_policy`].
+    pub fn upsert_document(&self, unique_field: Field, document: D) -> crate::Result<Opstamp> {
+        let opstamp = self.upsert_document_with_policy(
+            unique_field,
+            document,
+            UpsertConflictPolicy::Replace,
+        )?;
+        Ok(opstamp.expect("the replace policy always adds the document"))
     }
 
-    /// Runs a group of document operations ensuring that the operations are
-    /// assigned contiguous u64 opstamps and that add operations of the same
-    /// group are flushed into the same segment.
+    /// Adds a document keyed by the value of `unique_field`, resolving a conflict with an
+    /// existing document with the same key according to `policy`.
     ///
-    /// If the indexing pipeline is full, this call may block.
+    /// The unique field has to be indexed, and of type text with the `raw` tokenizer, `u64` or
+    /// `i64`. The document needs to have exactly one value for it.
     ///
-    /// Each operation of the given `user_operations` will receive an in-order,
-    /// contiguous u64 opstamp. The entire batch itself is also given an
-    /// opstamp that is 1 greater than the last given operation. This
-    /// `batch_opstamp` is the return value of `run`. An empty group of
-    /// `user_operations`, an empty `Vec<UserOperation>`, still receives
-    /// a valid opstamp even though no changes were _actually_ made to the index.
+    /// With [`UpsertConflictPolicy::Replace`], the key is deleted before the document is added,
+    /// unless it is known not to exist: the writer keeps an approximate filter of the keys it
+    /// upserted since it was opened, and looks the other keys up in the term dictionaries of the
+    /// last commit. The filter may give false positives, which only cost a useless delete.
     ///
-    /// Like adds and deletes (see `IndexWriter.add_document` and
-    /// `IndexWriter.delete_term`), the changes made by calling `run` will be
-    /// visible to readers only after calling `commit()`.
-    pub fn run<I>(&self, user_operations: I) -> crate::Result<Opstamp>
-    where
-        I: IntoIterator<Item = UserOperation<D>>,
-        I::IntoIter: ExactSizeIterator,
-    {
-        let user_operations_it = user_operations.into_iter();
-        let count = user_operations_it.len() as u64;
-        if count == 0 {
-            return Ok(self.stamper.stamp());
+    /// With [`UpsertConflictPolicy::Skip`] and [`UpsertConflictPolicy::Error`], the existence of
+    /// the key is checked exactly against the alive documents of the last commit, and the keys
+    /// upserted since then. Returns `None` if the document is skipped.
+    ///
+    /// Uniqueness is only guaranteed among the documents added by upserts, and the documents
+    /// committed before: the documents added with [`IndexWriter::add_document`] by this writer
+    /// and not committed yet are not checked. Similarly, the deletes not committed yet are not
+    /// taken into account by the `Skip` and `Error` policies.
+    pub fn upsert_document_with_policy(
+        &self,
+        unique_field: Field,
+        document: D,
+        policy: UpsertConflictPolicy,
+    ) -> crate::Result<Option<Opstamp>> {
+        let term = unique_key_term(&self.index.schema(), unique_field, &document)?;
+        self.check_tokenization(&document)?;
+        let mut upsert_state = self.upsert_state.lock().unwrap();
+        match upsert_state.resolve(&self.index, &term, policy)? {
+            UpsertAction::Skip => return Ok(None),
+            UpsertAction::DeleteAndAdd => {
+                self.delete_term_ref(&Term::wrap(term.serialized_term()))?;
+            }
+            UpsertAction::Add => {}
         }
-        let (batch_opstamp, stamps) = self.get_batch_opstamps(count);
+        let opstamp = self.add_tokenized_document(document)?;
+        upsert_state.record(&term, opstamp);
+        Ok(Some(opstamp))
+    }
 
-        let mut adds = AddBatch::default();
+    /// Replaces the documents containing `unique_term` with `document`, unless the live document
+    /// has a newer version.
+    ///
+    /// The version of a document is its value for the `u64` fast field set as `version_field`
+    /// in the [`IndexWriterOptions`]. The live version of the key is the version of the last
+    /// versioned update of this writer that was not committed yet, or else the highest version
+    /// of the alive documents with the key in the last commit.
+    ///
+    /// The update is rejected with [`TantivyError::VersionConflict`] if the live version is not
+    /// older than the version of `document`, or if it is newer than `expected_version`. The
+    /// check and the enqueueing of the delete and the add are atomic with respect to the other
+    /// versioned updates of the writer: of two concurrent updates of the same key, the one
+    /// with the older version is rejected, whatever their order.
+    ///
+    /// Regular deletes and adds of the key are not versioned: they are only taken into account
+    /// once committed.
+    pub fn update_document_versioned(
+        &self,
+        unique_term: Term,
+        document: D,
+        expected_version: Option<u64>,
+    ) -> crate::Result<Opstamp> {
+        let schema = self.index.schema();
+        let version_field = self.options.version_field.ok_or_else(|| {
+            TantivyError::InvalidArgument(
+                "Versioned updates need the version field to be set in the IndexWriterOptions."
+                    .to_string(),
+            )
+        })?;
+        let version = document_version(&schema, version_field, &document)?;
+        validate_delete_term(&schema, &unique_term)?;
+        self.check_tokenization(&document)?;
+        let version_field_name = schema.get_field_name(version_field);
+        let mut version_state = self.version_state.lock().unwrap();
+        version_state.check(
+            &self.index,
+            &unique_term,
+            version_field_name,
+            version,
+            expected_version,
+        )?;
+        self.delete_term_ref(&Term::wrap(unique_term.serialized_term()))?;
+        let opstamp = self.add_tokenized_document(document)?;
+        version_state.record(&unique_term, version, opstamp);
+        Ok(opstamp)
+    }
 
-        for (user_op, opstamp) in user_operations_it.zip(stamps) {
-            match user_op {
-                UserOperation::Delete(term) => {
-                    let query = TermQuery::new(term, IndexRecordOption::Basic);
-                    let weight =
-                        query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))?;
-                    let delete_operation = DeleteOperation {
-                        opstamp,
-                        target: weight,
-                    };
-                    self.delete_queue.push(delete_operation);
+    /// Returns the counters of the upserts of the writer.
+    pub fn upsert_stats(&self) -> UpsertStats {
+        self.upsert_state.lock().unwrap().stats()
+    }
+
+    /// Adds documents, giving them contiguous opstamps.
+    ///
+    /// Behaves like calling [`IndexWriter::add_document`] for each document, the documents being
+    /// sent to the indexing workers in batches of `add_batch_size`, see [`IndexWriterOptions`].
+    ///
+    /// Returns the range of the opstamps of the documents.
+    pub fn add_documents(&self, documents: Vec<D>) -> crate::Result<Range<Opstamp>> {
+        let documents = self.reject_untokenizable(documents, |document: &D| Some(document))?;
+        let mut pending_adds = self.lock_enqueue();
+        let stamps = self.stamper.stamps(documents.len() as u64);
+        let log_batch = self.log_adds(stamps.clone(), &documents)?;
+        let mut full_batches = Vec::new();
+        for (opstamp, document) in stamps.clone().zip(documents) {
+            pending_adds.push(AddOperation { opstamp, document });
+            if pending_adds.len() >= self.options.add_batch_size {
+                full_batches.push(self.sequence_add_batch(std::mem::take(&mut *pending_adds)));
+            }
+        }
+        if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch) {
+            operation_log.append_batch(log_batch);
+        }
+        drop(pending_adds);
+        for full_batch in full_batches {
+            self.send_sequenced_add_batch(full_batch)?;
+        }
+        Ok(stamps)
+    }
+
+    /// Adds a block of documents, which are guaranteed to be stored contiguously, in the given
+    /// order, in the same segment.
+    ///
+    /// This is used to index document families, a parent document preceded by its children,
+    /// which can then be joined with [`BlockJoinQuery`](crate::query::BlockJoinQuery). The block
+    /// is handed to a single indexing worker, which never flushes its segment in the middle of
+    /// a block, and merges preserve the order of the documents.
+    ///
+    /// Returns the range of the opstamps of the documents, or an error if the block is empty.
+    pub fn add_document_block(&self, documents: Vec<D>) -> crate::Result<Range<Opstamp>> {
+        if documents.is_empty() {
+            return Err(TantivyError::InvalidArgument(
+                "A document block cannot be empty.".to_string(),
+            ));
+        }
+        for document in &documents {
+            self.check_tokenization(document)?;
+        }
+        let block = self.enqueue_after_pending_adds(|| {
+            let stamps = self.stamper.stamps(documents.len() as u64);
+            let log_batch = self.log_adds(stamps.clone(), &documents)?;
+            let block: AddBatch<D> = stamps
+                .clone()
+                .zip(documents)
+                .map(|(opstamp, document)| AddOperation { opstamp, document })
+                .collect();
+            if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch)
+            {
+                operation_log.append_batch(log_batch);
+            }
+            crate::Result::Ok((stamps, self.sequence_add_batch(block)))
+        })?;
+        let (stamps, block) = block?;
+        self.send_sequenced_add_batch(block)?;
+        Ok(stamps)
+    }
+
+    /// Adds a document, unless the indexing pipeline is full.
+    ///
+    /// Unlike [`IndexWriter::add_document`], this call never blocks. If the indexing pipeline is
+    /// full, the document is given back in [`TryAddError::Full`], so that it can be retried later.
+    /// So it is if documents added concurrently are still being sent to the pipeline, as they
+    /// have to reach it first.
+    ///
+    /// The opstamp is given back as well if the document is rejected, unless another operation
+    /// got an opstamp in the meantime. Opstamps may therefore have gaps, which do not affect the
+    /// ordering of the operations.
+    ///
+    /// Deletes do not go through the indexing pipeline, and a concurrent
+    /// [`IndexWriter::add_document`] blocked on a full pipeline does not hold them back. They
+    /// only block to send the documents added before them that the writer still holds, see
+    /// `add_batch_size` in [`IndexWriterOptions`].
+    pub fn try_add_document(&self, document: D) -> Result<Opstamp, TryAddError<D>> {
+        self.add_document_with_timeout(document, None)
+    }
+
+    /// Adds a document, waiting at most `timeout` for the indexing pipeline to have room.
+    ///
+    /// Behaves like [`IndexWriter::try_add_document`], except that a full pipeline is only
+    /// reported after `timeout`.
+    pub fn add_document_timeout(
+        &self,
+        document: D,
+        timeout: Duration,
+    ) -> Result<Opstamp, TryAddError<D>> {
+        self.add_document_with_timeout(document, Some(timeout))
+    }
+
+    fn add_document_with_timeout(
+        &self,
+        document: D,
+        timeout: Option<Duration>,
+    ) -> Result<Opstamp, TryAddError<D>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.check_tokenization(&document)?;
+        let mut pending_adds = self.lock_enqueue();
+        let opstamp = self.stamper.stamp();
+        let log_batch = match self.log_adds(opstamp..opstamp + 1, std::slice::from_ref(&document)) {
+            Ok(log_batch) => log_batch,
+            Err(err) => {
+                self.stamper.unstamp(opstamp..opstamp + 1);
+                return Err(err.into());
+            }
+        };
+        pending_adds.push(AddOperation { opstamp, document });
+        if pending_adds.len() >= self.options.add_batch_size {
+            let add_ops = std::mem::take(&mut *pending_adds);
+            if let Err(err) = self.try_send_add_documents_batch(add_ops, deadline) {
+                self.stamper.unstamp(opstamp..opstamp + 1);
+                // The documents added before this one stay pending.
+                return Err(err.map_rejected(|mut add_ops| {
+                    let add_op = add_ops.pop().expect("the batch contains the document");
+                    *pending_adds = *add_ops;
+                    add_op.document
+                }));
+            }
+        }
+        if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch) {
+            operation_log.append_batch(log_batch);
+        }
+        Ok(opstamp)
+    }
+
+    /// Rejects a document that cannot be tokenized entirely, unless the
+    /// `tokenization_error_policy` of the writer is [`TokenizationErrorPolicy::Skip`].
+    fn check_tokenization(&self, document: &D) -> crate::Result<()> {
+        let Some(tokenization_checker) = &self.tokenization_checker else {
+            return Ok(());
+        };
+        tokenization_checker.check(document).map_err(|error| {
+            self.indexing_report
+                .lock()
+                .unwrap()
+                .record_rejected(error.clone(), false);
+            error.into()
+        })
+    }
+
+    /// Rejects the documents of a batch that cannot be tokenized entirely, according to the
+    /// `tokenization_error_policy` of the writer.
+    ///
+    /// With [`TokenizationErrorPolicy::FailDocument`], the rejected documents are removed from
+    /// the batch and listed in the indexing report. With
+    /// [`TokenizationErrorPolicy::FailBatch`], the first of them fails the whole batch.
+    fn reject_untokenizable<T>(
+        &self,
+        items: Vec<T>,
+        document: impl Fn(&T) -> Option<&D>,
+    ) -> crate::Result<Vec<T>> {
+        let Some(tokenization_checker) = &self.tokenization_checker else {
+            return Ok(items);
+        };
+        let fail_batch =
+            self.options.tokenization_error_policy == TokenizationErrorPolicy::FailBatch;
+        let mut accepted_items = Vec::with_capacity(items.len());
+        for item in items {
+            if let Some(Err(error)) = document(&item).map(|doc| tokenization_checker.check(doc)) {
+                self.indexing_report
+                    .lock()
+                    .unwrap()
+                    .record_rejected(error.clone(), !fail_batch);
+                if fail_batch {
+                    return Err(error.into());
                 }
-                UserOperation::Add(document) => {
-                    let add_operation = AddOperation { opstamp, document };
-                    adds.push(add_operation);
+                continue;
+            }
+            accepted_items.push(item);
+        }
+        Ok(accepted_items)
+    }
+
+    /// Locks the enqueuing of operations. See `enqueue_lock`.
+    fn lock_enqueue(&self) -> MutexGuard<'_, AddBatch<D>> {
+        self.enqueue_lock
+            .lock()
+            .expect("Lock poisoned. This should never happen")
+    }
+
+    /// Runs `enqueue` under the enqueue lock, after taking the pending adds. They are sent to the
+    /// indexing workers once the lock is released, before the batches taken by `enqueue`.
+    fn enqueue_after_pending_adds<T>(&self, enqueue: impl FnOnce() -> T) -> crate::Result<T> {
+        let mut pending_adds = self.lock_enqueue();
+        let pending_batch = (!pending_adds.is_empty())
+            .then(|| self.sequence_add_batch(std::mem::take(&mut *pending_adds)));
+        let enqueued = enqueue();
+        drop(pending_adds);
+        if let Some(pending_batch) = pending_batch {
+            self.send_sequenced_add_batch(pending_batch)?;
+        }
+        Ok(enqueued)
+    }
+
+    /// Gives a batch of added documents its turn to be sent to the indexing workers. Has to be
+    /// called under the enqueue lock.
+    fn sequence_add_batch(&self, add_ops: AddBatch<D>) -> SequencedAddBatch<'_, D> {
+        SequencedAddBatch {
+            add_ops,
+            ticket: self.add_batch_sequencer.ticket(),
+        }
+    }
+
+    /// Sends a batch once the batches sequenced before it are sent. Must not be called under the
+    /// enqueue lock, since the send may block on a full pipeline.
+    fn send_sequenced_add_batch(&self, batch: SequencedAddBatch<'_, D>) -> crate::Result<()> {
+        batch.ticket.wait_turn();
+        self.send_add_documents_batch(batch.add_ops)
+    }
+
+    /// Gets a range of stamps from the stamper and "pops" the last stamp
+    /// from the range returning a tuple of the last optstamp and the popped
+    /// range.
+    ///
+    /// The total number of stamps generated by this method is `count + 1`;
+    /// each operation gets a stamp from the `stamps` iterator and `last_opstamp`
+    /// is for the batch itself.
+    fn get_batch_opstamps(&self, count: Opstamp) -> (Opstamp, Range<Opstamp>) {
+        let Range { start, end } = self.stamper.stamps(count + 1u64);
+        let last_opstamp = end - 1;
+        (last_opstamp, start..last_opstamp)
+    }
+
+    /// Runs a group of document operations ensuring that the operations are
+    /// assigned contiguous u64 opstamps and that add operations of the same
+    /// group are flushed into the same segment.
+    ///
+    /// If the indexing pipeline is full, this call may block.
+    ///
+    /// Each operation of the given `user_operations` will receive an in-order,
+    /// contiguous u64 opstamp. The entire batch itself is also given an
+    /// opstamp that is 1 greater than the last given operation. This
+    /// `batch_opstamp` is the return value of `run`. An empty group of
+    /// `user_operations`, an empty `Vec<UserOperation>`, still receives
+    /// a valid opstamp even though no changes were _actually_ made to the index.
+    ///
+    /// Like adds and deletes (see `IndexWriter.add_document` and
+    /// `IndexWriter.delete_term`), the changes made by calling `run` will be
+    /// visible to readers only after calling `commit()`.
+    ///
+    /// Delete terms are validated like in `IndexWriter.delete_term`. If one of
+    /// them is invalid, a `SchemaError` is returned and none of the operations
+    /// are applied.
+    ///
+    /// The added documents are checked according to the
+    /// [`TokenizationErrorPolicy`] of the writer: with `FailBatch`, a document
+    /// that cannot be tokenized entirely fails the whole batch, and with
+    /// `FailDocument`, it is left out of the batch.
+    ///
+    /// Batches are atomic and serialized by opstamp range: the operations of
+    /// concurrent calls to `run`, and of any other operation of the writer, are
+    /// never interleaved. A delete applies to all of the documents with a lower
+    /// opstamp, and to those only. For instance, if several threads concurrently
+    /// run `[Delete(key), Add(doc)]` for the same key, exactly one document is
+    /// left for the key: the one of the batch with the highest opstamp.
+    pub fn run<I>(&self, user_operations: I) -> crate::Result<Opstamp>
+    where
+        I: IntoIterator<Item = UserOperation<D>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let user_operations = self.reject_untokenizable(
+            user_operations.into_iter().collect(),
+            UserOperation::document,
+        )?;
+        let count = user_operations.len() as u64;
+        if count == 0 {
+            return Ok(self.stamper.stamp());
+        }
+        // Validate the whole batch first, so that an invalid delete does not leave the batch
+        // half applied.
+        let schema = self.index.schema();
+        for user_op in &user_operations {
+            if let UserOperation::Delete(term) = user_op {
+                validate_delete_term(&schema, term)?;
+            }
+        }
+        let enqueued = self.enqueue_after_pending_adds(|| {
+            let (batch_opstamp, stamps) = self.get_batch_opstamps(count);
+            let log_batch = self.log_user_operations(&user_operations, stamps.clone())?;
+
+            let mut adds = AddBatch::default();
+
+            for (user_op, opstamp) in user_operations.into_iter().zip(stamps) {
+                match user_op {
+                    UserOperation::Delete(term) => {
+                        let delete_operation = DeleteOperation {
+                            opstamp,
+                            target: DeleteTarget::Term(term),
+                            tombstone_field: None,
+                        };
+                        self.delete_queue.push(delete_operation);
+                    }
+                    UserOperation::Add(document) => {
+                        let add_operation = AddOperation { opstamp, document };
+                        adds.push(add_operation);
+                    }
                 }
             }
+            if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch)
+            {
+                operation_log.append_batch(log_batch);
+            }
+            crate::Result::Ok((batch_opstamp, self.sequence_add_batch(adds)))
+        })?;
+        let (batch_opstamp, adds) = enqueued?;
+        self.send_sequenced_add_batch(adds)?;
+        Ok(batch_opstamp)
+    }
+
+    /// Runs a group of document operations, unless the indexing pipeline is full.
+    ///
+    /// Behaves like [`IndexWriter::run`], but never blocks. If the indexing pipeline is full,
+    /// none of the operations are applied, and they are given back in [`TryAddError::Full`].
+    /// Likewise, they are given back in [`TryAddError::Invalid`] if one of the delete terms is
+    /// invalid.
+    /// Like for [`IndexWriter::try_add_document`], the opstamps of a rejected batch are given
+    /// back unless another operation got an opstamp in the meantime.
+    pub fn try_run<I>(
+        &self,
+        user_operations: I,
+    ) -> Result<Opstamp, TryAddError<Vec<UserOperation<D>>>>
+    where
+        I: IntoIterator<Item = UserOperation<D>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.run_with_timeout(user_operations.into_iter().collect(), None)
+    }
+
+    /// Runs a group of document operations, waiting at most `timeout` for the indexing pipeline
+    /// to have room.
+    ///
+    /// Behaves like [`IndexWriter::try_run`], except that a full pipeline is only reported after
+    /// `timeout`.
+    pub fn run_timeout<I>(
+        &self,
+        user_operations: I,
+        timeout: Duration,
+    ) -> Result<Opstamp, TryAddError<Vec<UserOperation<D>>>>
+    where
+        I: IntoIterator<Item = UserOperation<D>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.run_with_timeout(user_operations.into_iter().collect(), Some(timeout))
+    }
+
+    fn run_with_timeout(
+        &self,
+        user_operations: Vec<UserOperation<D>>,
+        timeout: Option<Duration>,
+    ) -> Result<Opstamp, TryAddError<Vec<UserOperation<D>>>> {
+        // The pending adds and the batch share the timeout.
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // The operations are validated before anything is consumed, so that they can be given
+        // back to the caller.
+        let schema = self.index.schema();
+        let invalid_delete = user_operations.iter().find_map(|user_op| match user_op {
+            UserOperation::Delete(term) => validate_delete_term(&schema, term).err(),
+            UserOperation::Add(_) => None,
+        });
+        if let Some(err) = invalid_delete {
+            return Err(TryAddError::Invalid(user_operations, err));
+        }
+        let user_operations =
+            self.reject_untokenizable(user_operations, UserOperation::document)?;
+        let count = user_operations.len() as u64;
+        if count == 0 {
+            return Ok(self.stamper.stamp());
+        }
+        let mut pending_adds = self.lock_enqueue();
+        if !pending_adds.is_empty() {
+            let add_ops = std::mem::take(&mut *pending_adds);
+            if let Err(err) = self.try_send_add_documents_batch(add_ops, deadline) {
+                return Err(err.map_rejected(|add_ops| {
+                    *pending_adds = *add_ops;
+                    user_operations
+                }));
+            }
+        }
+        let (batch_opstamp, stamps) = self.get_batch_opstamps(count);
+        let log_batch = match self.log_user_operations(&user_operations, stamps.clone()) {
+            Ok(log_batch) => log_batch,
+            Err(err) => {
+                self.stamper.unstamp(stamps.start..batch_opstamp + 1);
+                return Err(err.into());
+            }
+        };
+
+        let mut adds = AddBatch::default();
+        let mut deletes = Vec::new();
+        for (user_op, opstamp) in user_operations.into_iter().zip(stamps.clone()) {
+            match user_op {
+                UserOperation::Delete(term) => deletes.push(DeleteOperation {
+                    opstamp,
+                    target: DeleteTarget::Term(term),
+                    tombstone_field: None,
+                }),
+                UserOperation::Add(document) => adds.push(AddOperation { opstamp, document }),
+            }
+        }
+
+        // Deletes cannot be taken back from the delete queue, so they are only pushed once the
+        // adds are enqueued. Until then, the workers wait before applying deletes to a segment.
+        let pending_batch_deletes_guard = (!deletes.is_empty()).then(|| {
+            self.pending_batch_deletes
+                .read()
+                .expect("Lock poisoned. This should never happen")
+        });
+        if !adds.is_empty() {
+            if let Err(err) = self.try_send_add_documents_batch(adds, deadline) {
+                drop(pending_batch_deletes_guard);
+                self.stamper.unstamp(stamps.start..batch_opstamp + 1);
+                return Err(err.map_rejected(|adds| {
+                    merge_user_operations(adds.into_iter().collect(), deletes)
+                }));
+            }
+        }
+        for delete_operation in deletes {
+            self.delete_queue.push(delete_operation);
+        }
+        drop(pending_batch_deletes_guard);
+        if let (Some(operation_log), Some(log_batch)) = (&self.options.operation_log, log_batch) {
+            operation_log.append_batch(log_batch);
         }
-        self.send_add_documents_batch(adds)?;
         Ok(batch_opstamp)
     }
 
+    /// Sends a batch of added documents, waiting until `deadline` at most for the indexing
+    /// pipeline to have room, or not at all without a deadline.
+    ///
+    /// The batch is boxed if it is given back, to keep the error small.
+    fn try_send_add_documents_batch(
+        &self,
+        add_ops: AddBatch<D>,
+        deadline: Option<Instant>,
+    ) -> Result<(), TryAddError<Box<AddBatch<D>>>> {
+        #[cfg(test)]
+        self.num_sent_add_batches
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let killed =
+            || TryAddError::Error(error_in_index_worker_thread("An index writer was killed."));
+        if !self.index_writer_status.is_alive() {
+            return Err(killed());
+        }
+        let remaining_time =
+            || deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        // Sent under the enqueue lock, so the batches sequenced before have to be sent first.
+        if !self
+            .add_batch_sequencer
+            .wait_idle(remaining_time().unwrap_or_default())
+        {
+            return Err(TryAddError::Full(Box::new(add_ops)));
+        }
+        match remaining_time() {
+            None => self
+                .operation_sender
+                .try_send(add_ops)
+                .map_err(|err| match err {
+                    TrySendError::Full(add_ops) => TryAddError::Full(Box::new(add_ops)),
+                    TrySendError::Disconnected(_) => killed(),
+                }),
+            Some(timeout) => self
+                .operation_sender
+                .send_timeout(add_ops, timeout)
+                .map_err(|err| match err {
+                    SendTimeoutError::Timeout(add_ops) => TryAddError::Full(Box::new(add_ops)),
+                    SendTimeoutError::Disconnected(_) => killed(),
+                }),
+        }
+    }
+
     fn send_add_documents_batch(&self, add_ops: AddBatch<D>) -> crate::Result<()> {
+        #[cfg(test)]
+        self.num_sent_add_batches
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         if self.index_writer_status.is_alive() && self.operation_sender.send(add_ops).is_ok() {
             Ok(())
         } else {
@@ -804,6 +2207,12 @@ impl<D: Document> IndexWriter<D> {
     }
 }
 
+/// Batch of added documents taken under the enqueue lock, with its turn to be sent.
+struct SequencedAddBatch<'a, D: Document> {
+    add_ops: AddBatch<D>,
+    ticket: AddBatchTicket<'a>,
+}
+
 impl<D: Document> Drop for IndexWriter<D> {
     fn drop(&mut self) {
         self.segment_updater.kill();
@@ -817,28 +2226,47 @@ impl<D: Document> Drop for IndexWriter<D> {
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, HashSet};
+    use std::io::Write;
     use std::net::Ipv6Addr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     use columnar::{Column, MonotonicallyMappableToU128};
+    use common::json_path_writer::{JSON_END_OF_PATH, JSON_PATH_SEGMENT_SEP};
+    use common::{BitSet, HasLen};
     use itertools::Itertools;
     use proptest::prop_oneof;
+    use serde_json::json;
 
     use super::super::operation::UserOperation;
     use crate::collector::{Count, TopDocs};
     use crate::directory::error::LockError;
+    use crate::directory::{Directory, RamDirectory, TerminatingWrite};
     use crate::error::*;
-    use crate::indexer::index_writer::MEMORY_BUDGET_NUM_BYTES_MIN;
-    use crate::indexer::{IndexWriterOptions, NoMergePolicy};
-    use crate::query::{QueryParser, TermQuery};
+    use crate::fastfield::write_alive_bitset;
+    use crate::index::{SegmentComponent, SegmentId, SegmentMeta};
+    use crate::indexer::index_writer::{
+        MARGIN_IN_BYTES, MEMORY_BUDGET_NUM_BYTES_MIN, PIPELINE_MAX_SIZE_IN_DOCS,
+    };
+    use crate::indexer::{
+        AdaptiveFlushThreshold, CommitPayload, ExpireSoftDeleted, IndexWriterOptions, MergePhase,
+        MergeProgress, NoMergePolicy, TryAddError,
+    };
+    use crate::query::{
+        AllQuery, EnableScoring, PhrasePrefixQuery, Query, QueryParser, RegexQuery, TermQuery,
+        TermSetQuery, Weight,
+    };
     use crate::schema::{
-        self, Facet, FacetOptions, IndexRecordOption, IpAddrOptions, JsonObjectOptions,
-        NumericOptions, Schema, TextFieldIndexing, TextOptions, Value, FAST, INDEXED, STORED,
-        STRING, TEXT,
+        self, Facet, FacetOptions, Field, IndexRecordOption, IpAddrOptions, JsonObjectOptions,
+        NumericOptions, Schema, TermBuffer, TextFieldIndexing, TextOptions, Value, FAST, INDEXED,
+        STORED, STRING, TEXT,
     };
     use crate::store::DOCSTORE_CACHE_CAPACITY;
     use crate::{
-        DateTime, DocAddress, Index, IndexSettings, IndexWriter, ReloadPolicy, TantivyDocument,
-        Term,
+        DateTime, DocAddress, Document, Index, IndexSettings, IndexWriter, Order, ReloadPolicy,
+        Searcher, TantivyDocument, Term,
     };
 
     const LOREM: &str = "Doc Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
@@ -850,62 +2278,1267 @@ mod tests {
                          mollit anim id est laborum.";
 
     #[test]
-    fn test_operations_group() {
-        // an operations group with 2 items should cause 3 opstamps 0, 1, and 2.
+    fn test_operations_group() {
+        // an operations group with 2 items should cause 3 opstamps 0, 1, and 2.
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer = index.writer_for_tests().unwrap();
+        let operations = vec![
+            UserOperation::Add(doc!(text_field=>"a")),
+            UserOperation::Add(doc!(text_field=>"b")),
+        ];
+        let batch_opstamp1 = index_writer.run(operations).unwrap();
+        assert_eq!(batch_opstamp1, 2u64);
+    }
+
+    #[test]
+    fn test_no_need_to_rewrite_delete_file_if_no_new_deletes() {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+
+        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        index_writer
+            .add_document(doc!(text_field => "hello1"))
+            .unwrap();
+        index_writer
+            .add_document(doc!(text_field => "hello2"))
+            .unwrap();
+        assert!(index_writer.commit().is_ok());
+
+        let reader = index.reader().unwrap();
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert_eq!(searcher.segment_reader(0u32).num_docs(), 2);
+
+        index_writer
+            .delete_term(Term::from_field_text(text_field, "hello1"))
+            .unwrap();
+        assert!(index_writer.commit().is_ok());
+
+        assert!(reader.reload().is_ok());
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert_eq!(searcher.segment_reader(0u32).num_docs(), 1);
+
+        let previous_delete_opstamp = index.load_metas().unwrap().segments[0].delete_opstamp();
+
+        // All docs containing hello1 have been already removed.
+        // We should not update the delete meta.
+        index_writer
+            .delete_term(Term::from_field_text(text_field, "hello1"))
+            .unwrap();
+        assert!(index_writer.commit().is_ok());
+
+        assert!(reader.reload().is_ok());
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert_eq!(searcher.segment_reader(0u32).num_docs(), 1);
+
+        let after_delete_opstamp = index.load_metas().unwrap().segments[0].delete_opstamp();
+        assert_eq!(after_delete_opstamp, previous_delete_opstamp);
+    }
+
+    #[test]
+    fn test_delete_term_wrong_type_rejected() {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        index_writer.add_document(doc!(text_field=>"a")).unwrap();
+
+        let err = index_writer
+            .delete_term(Term::from_field_u64(text_field, 1))
+            .unwrap_err();
+        assert!(
+            matches!(err, TantivyError::SchemaError(ref msg) if msg == "Field \"text\" is of type Str, but the term is of type U64."),
+            "{err:?}"
+        );
+
+        // The whole batch is rejected.
+        let operations = vec![
+            UserOperation::Add(doc!(text_field=>"b")),
+            UserOperation::Delete(Term::from_field_u64(text_field, 1)),
+        ];
+        let err = index_writer.run(operations).unwrap_err();
+        assert!(matches!(err, TantivyError::SchemaError(_)));
+
+        index_writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(searcher.num_docs(), 1);
+    }
+
+    #[test]
+    fn test_delete_term_non_indexed_field_rejected() {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        let err = index_writer
+            .delete_term(Term::from_field_u64(id_field, 1))
+            .unwrap_err();
+        assert!(
+            matches!(err, TantivyError::SchemaError(ref msg) if msg == "Field \"id\" is not indexed."),
+            "{err:?}"
+        );
+        let err = index_writer
+            .run(vec![UserOperation::Delete(Term::from_field_u64(
+                id_field, 1,
+            ))])
+            .unwrap_err();
+        assert!(matches!(err, TantivyError::SchemaError(_)));
+    }
+
+    #[test]
+    fn test_delete_term_unchecked_json_path() {
+        let mut schema_builder = schema::Schema::builder();
+        let json_field = schema_builder.add_json_field("json", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        index_writer
+            .add_document(doc!(json_field=>json!({"attr": "a"})))
+            .unwrap();
+        index_writer
+            .add_document(doc!(json_field=>json!({"attr": "b"})))
+            .unwrap();
+        index_writer.commit().unwrap();
+
+        let mut term = Term::from_field_json_path(json_field, "attr", false);
+        term.append_type_and_str("a");
+        index_writer.delete_term_unchecked(term);
+        index_writer.commit().unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let mut term = Term::from_field_json_path(json_field, "attr", false);
+        term.append_type_and_str("b");
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        assert_eq!(searcher.search(&query, &Count).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_delete_term_ref_repeated() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text_field => "a"))?;
+        let mut term_buffer = TermBuffer::new(text_field);
+        term_buffer.set_text("a");
+        let opstamp = index_writer.delete_term_ref(&term_buffer.term_ref())?;
+        // Nothing happened since the previous delete of the term.
+        assert_eq!(
+            index_writer.delete_term_ref(&term_buffer.term_ref())?,
+            opstamp + 1
+        );
+        // A document was added since the previous delete of the term: the delete is enqueued.
+        index_writer.add_document(doc!(text_field => "a"))?;
+        index_writer.add_document(doc!(text_field => "b"))?;
+        index_writer.delete_term_ref(&term_buffer.term_ref())?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_batch_size() -> crate::Result<()> {
+        const NUM_DOCS: u64 = 5_000;
+        const ADD_BATCH_SIZE: usize = 64;
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_u64_field("id", INDEXED | FAST);
+        let parity = schema_builder.add_text_field("parity", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .num_worker_threads(2)
+            .add_batch_size(ADD_BATCH_SIZE)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        let parity_doc = |i: u64| doc!(id => i, parity => if i % 2 == 0 { "even" } else { "odd" });
+
+        for i in 0..NUM_DOCS {
+            assert_eq!(index_writer.add_document(parity_doc(i))?, i);
+        }
+        let num_sends = index_writer.num_sent_add_batches.load(Ordering::Relaxed);
+        assert_eq!(num_sends, NUM_DOCS as usize / ADD_BATCH_SIZE);
+        // The pending documents are deleted like the other documents.
+        index_writer.delete_term(Term::from_field_u64(id, 1))?;
+        assert_eq!(
+            index_writer.num_sent_add_batches.load(Ordering::Relaxed),
+            NUM_DOCS.div_ceil(ADD_BATCH_SIZE as u64) as usize
+        );
+        let commit_opstamp = index_writer.commit()?;
+
+        let docs: Vec<_> = (NUM_DOCS..2 * NUM_DOCS).map(parity_doc).collect();
+        let stamps = index_writer.add_documents(docs)?;
+        assert!(stamps.start > commit_opstamp);
+        assert_eq!(stamps.end - stamps.start, NUM_DOCS);
+        index_writer.commit()?;
+        assert_eq!(
+            index_writer.num_sent_add_batches.load(Ordering::Relaxed),
+            2 * NUM_DOCS.div_ceil(ADD_BATCH_SIZE as u64) as usize
+        );
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 2 * NUM_DOCS - 1);
+        let count_term =
+            |term: Term| searcher.search(&TermQuery::new(term, IndexRecordOption::Basic), &Count);
+        assert_eq!(
+            count_term(Term::from_field_text(parity, "even"))?,
+            NUM_DOCS as usize
+        );
+        assert_eq!(
+            count_term(Term::from_field_text(parity, "odd"))?,
+            NUM_DOCS as usize - 1
+        );
+        assert_eq!(count_term(Term::from_field_u64(id, 1))?, 0);
+        assert_eq!(count_term(Term::from_field_u64(id, NUM_DOCS + 1))?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_batch_size_rollback() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder().add_batch_size(10).build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.add_document(doc!(id => 0u64))?;
+        index_writer.commit()?;
+        for i in 1..5u64 {
+            index_writer.add_document(doc!(id => i))?;
+        }
+        index_writer.rollback()?;
+        index_writer.add_document(doc!(id => 5u64))?;
+        index_writer.commit()?;
+        assert_eq!(index.reader()?.searcher().num_docs(), 2);
+        drop(index_writer);
+
+        let options = IndexWriterOptions::builder().add_batch_size(0).build();
+        assert!(matches!(
+            index.writer_with_options::<TantivyDocument>(options),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    /// Fills the indexing pipeline of a writer with paused workers, and returns the number of
+    /// documents added.
+    fn fill_pipeline(index_writer: &IndexWriter, id: Field) -> u64 {
+        let mut num_docs = 0;
+        let mut last_opstamp = None;
+        while let Ok(opstamp) = index_writer.try_add_document(doc!(id => "filler")) {
+            if let Some(last_opstamp) = last_opstamp {
+                assert_eq!(opstamp, last_opstamp + 1);
+            }
+            last_opstamp = Some(opstamp);
+            num_docs += 1;
+        }
+        // The paused worker holds at most one batch.
+        assert!((PIPELINE_MAX_SIZE_IN_DOCS..=PIPELINE_MAX_SIZE_IN_DOCS + 1)
+            .contains(&(num_docs as usize)));
+        num_docs
+    }
+
+    #[test]
+    fn test_try_add_document_full_pipeline() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter =
+            index.writer_with_num_threads(1, MEMORY_BUDGET_NUM_BYTES_MIN)?;
+        let paused_workers = index_writer.paused_workers.clone();
+        let pause_guard = paused_workers.write().unwrap();
+        let num_docs = fill_pipeline(&index_writer, id);
+
+        let start = Instant::now();
+        let Err(TryAddError::Full(rejected)) =
+            index_writer.try_add_document(doc!(id => "rejected"))
+        else {
+            panic!("expected a full pipeline");
+        };
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(rejected, doc!(id => "rejected"));
+
+        let start = Instant::now();
+        let timeout = Duration::from_millis(20);
+        let Err(TryAddError::Full(rejected)) = index_writer.add_document_timeout(rejected, timeout)
+        else {
+            panic!("expected a full pipeline");
+        };
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(rejected, doc!(id => "rejected"));
+
+        drop(pause_guard);
+        // The rejected attempts did not consume opstamps.
+        let opstamp = index_writer
+            .add_document_timeout(rejected, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(opstamp, num_docs);
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), num_docs + 1);
+        let rejected_query = TermQuery::new(
+            Term::from_field_text(id, "rejected"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&rejected_query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_run_full_pipeline() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter =
+            index.writer_with_num_threads(1, MEMORY_BUDGET_NUM_BYTES_MIN)?;
+        index_writer.add_document(doc!(id => "a"))?;
+        index_writer.commit()?;
+
+        let paused_workers = index_writer.paused_workers.clone();
+        let pause_guard = paused_workers.write().unwrap();
+        fill_pipeline(&index_writer, id);
+
+        // Replaces the document "a".
+        let upsert = || {
+            vec![
+                UserOperation::Delete(Term::from_field_text(id, "a")),
+                UserOperation::Add(doc!(id => "a")),
+            ]
+        };
+        let Err(TryAddError::Full(rejected)) = index_writer.try_run(upsert()) else {
+            panic!("expected a full pipeline");
+        };
+        assert_eq!(rejected, upsert());
+        let Err(TryAddError::Full(rejected)) =
+            index_writer.run_timeout(rejected, Duration::from_millis(20))
+        else {
+            panic!("expected a full pipeline");
+        };
+        assert_eq!(rejected, upsert());
+        // Deletes alone never wait on the pipeline.
+        let delete_only = vec![UserOperation::Delete(Term::from_field_text(id, "filler"))];
+        index_writer.try_run(delete_only).unwrap();
+
+        drop(pause_guard);
+        index_writer
+            .run_timeout(rejected, Duration::from_secs(60))
+            .unwrap();
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let query = TermQuery::new(Term::from_field_text(id, "a"), IndexRecordOption::Basic);
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocked_add_does_not_hold_back_other_operations() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter =
+            index.writer_with_num_threads(1, MEMORY_BUDGET_NUM_BYTES_MIN)?;
+        let paused_workers = index_writer.paused_workers.clone();
+        let (blocked_opstamp, delete_opstamp) = thread::scope(|scope| {
+            let pause_guard = paused_workers.write().unwrap();
+            fill_pipeline(&index_writer, id);
+            let num_sends = index_writer.num_sent_add_batches.load(Ordering::Relaxed);
+            let blocked_add = scope.spawn(|| index_writer.add_document(doc!(id => "blocked")));
+            while index_writer.num_sent_add_batches.load(Ordering::Relaxed) == num_sends {
+                thread::sleep(Duration::from_millis(1));
+            }
+            // The add is blocked on the full pipeline, but the other threads can still stamp
+            // operations.
+            let delete = scope.spawn(|| {
+                let rejected = index_writer.try_add_document(doc!(id => "rejected"));
+                assert!(matches!(rejected, Err(TryAddError::Full(_))));
+                index_writer.delete_term(Term::from_field_text(id, "filler"))
+            });
+            let start = Instant::now();
+            while !delete.is_finished() && start.elapsed() < Duration::from_secs(10) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert!(delete.is_finished());
+            assert!(!blocked_add.is_finished());
+            drop(pause_guard);
+            (
+                blocked_add.join().unwrap().unwrap(),
+                delete.join().unwrap().unwrap(),
+            )
+        });
+        assert!(blocked_opstamp < delete_opstamp);
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let query = TermQuery::new(
+            Term::from_field_text(id, "blocked"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_run_invalid_delete_gives_operations_back() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING);
+        let stored = schema_builder.add_text_field("stored", STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let operations = || {
+            vec![
+                UserOperation::Add(doc!(id => "a")),
+                UserOperation::Delete(Term::from_field_text(stored, "a")),
+            ]
+        };
+        let Err(TryAddError::Invalid(rejected, TantivyError::SchemaError(_))) =
+            index_writer.try_run(operations())
+        else {
+            panic!("expected an invalid delete");
+        };
+        assert_eq!(rejected, operations());
+        let Err(TryAddError::Invalid(rejected, _)) =
+            index_writer.run_timeout(rejected, Duration::from_millis(20))
+        else {
+            panic!("expected an invalid delete");
+        };
+        assert_eq!(rejected, operations());
+        index_writer.commit()?;
+        assert_eq!(index.reader()?.searcher().num_docs(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_run_deletes_apply_to_batch_adds() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for _ in 0..10 {
+            index_writer
+                .try_run([
+                    UserOperation::Add(doc!(id => "a")),
+                    UserOperation::Delete(Term::from_field_text(id, "a")),
+                    UserOperation::Add(doc!(id => "a")),
+                ])
+                .unwrap();
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_io_budget_throttles_merges_only() -> crate::Result<()> {
+        const MERGE_BUDGET_BYTES_PER_SEC: u64 = 200_000;
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT | STORED);
+        let id_field = schema_builder.add_u64_field("id", FAST | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        // Indexing ~100KB at such a budget would take minutes if flushes were throttled.
+        let options = IndexWriterOptions::builder()
+            .merge_io_budget_bytes_per_sec(1_000)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let start = Instant::now();
+        for segment in 0..2u64 {
+            for i in 0..2_000u64 {
+                let id = segment * 2_000 + i;
+                index_writer.add_document(doc!(
+                    text_field => format!("{LOREM} {id} {}", id * 7919),
+                    id_field => id,
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        index_writer
+            .merge_io_budget()
+            .set_bytes_per_sec(Some(MERGE_BUDGET_BYTES_PER_SEC));
+        let segment_ids = index.searchable_segment_ids()?;
+        let start = Instant::now();
+        let merged_segment_meta = index_writer.merge(&segment_ids).wait()?.unwrap();
+        let elapsed = start.elapsed();
+
+        let merged_num_bytes: usize = merged_segment_meta
+            .list_files()
+            .iter()
+            .filter_map(|path| index.directory().open_read(path).ok())
+            .map(|file| file.len())
+            .sum();
+        assert!(merged_num_bytes > MERGE_BUDGET_BYTES_PER_SEC as usize);
+        // The budget allows a burst of 100ms worth of bytes, and the footers of the files are
+        // not throttled: leave a 10% tolerance.
+        let min_elapsed_secs = (merged_num_bytes as f64 * 0.9
+            - MERGE_BUDGET_BYTES_PER_SEC as f64 * 0.1)
+            / MERGE_BUDGET_BYTES_PER_SEC as f64;
+        assert!(
+            elapsed.as_secs_f64() >= min_elapsed_secs,
+            "merged {merged_num_bytes} bytes in {elapsed:?}"
+        );
+        Ok(())
+    }
+
+    /// Creates an index with three segments of `num_docs_per_segment` documents, and deletes
+    /// every tenth document.
+    fn index_for_merge_progress(num_docs_per_segment: u64) -> crate::Result<(Index, IndexWriter)> {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT | STORED);
+        let id_field = schema_builder.add_u64_field("id", FAST | INDEXED | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment in 0..3 {
+            for i in 0..num_docs_per_segment {
+                let id = segment * num_docs_per_segment + i;
+                index_writer
+                    .add_document(doc!(text_field => format!("{LOREM} {id}"), id_field => id))?;
+            }
+            index_writer.commit()?;
+        }
+        for id in (0..3 * num_docs_per_segment).step_by(10) {
+            index_writer.delete_term(Term::from_field_u64(id_field, id))?;
+        }
+        index_writer.commit()?;
+        Ok((index, index_writer))
+    }
+
+    /// Returns the files of the index that exist.
+    fn existing_files(index: &Index) -> HashSet<std::path::PathBuf> {
+        index
+            .directory()
+            .list_managed_files()
+            .into_iter()
+            .filter(|path| index.directory().exists(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_with_progress() -> crate::Result<()> {
+        let (index, mut index_writer) = index_for_merge_progress(1_500)?;
+        let id_field = index.schema().get_field("id").unwrap();
+        let segment_ids = index.searchable_segment_ids()?;
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let progress_clone = progress.clone();
+        let (started_sender, started_receiver) = crossbeam_channel::bounded(1);
+        let (resume_sender, resume_receiver) = crossbeam_channel::bounded::<()>(1);
+        let merge_handle = index_writer.merge_with_progress(
+            &segment_ids,
+            Box::new(move |merge_progress: MergeProgress| {
+                progress_clone.lock().unwrap().push(merge_progress);
+                if merge_progress.phase == MergePhase::Store
+                    && merge_progress.num_docs_processed == 0
+                {
+                    started_sender.send(()).unwrap();
+                    resume_receiver.recv().unwrap();
+                }
+            }),
+        );
+        // Deletes committed while merging are applied to the merged segment.
+        started_receiver.recv().unwrap();
+        index_writer.delete_term(Term::from_field_u64(id_field, 1))?;
+        index_writer.commit()?;
+        resume_sender.send(()).unwrap();
+        let merged_segment_meta = merge_handle.wait()?.unwrap();
+
+        assert_eq!(merged_segment_meta.max_doc(), 4_050);
+        assert_eq!(merged_segment_meta.num_deleted_docs(), 1);
+        assert_eq!(
+            index.searchable_segment_ids()?,
+            vec![merged_segment_meta.id()]
+        );
+        let progress = progress.lock().unwrap();
+        assert!(progress
+            .iter()
+            .all(|merge_progress| merge_progress.num_docs_total == 4_050));
+        for (previous, next) in progress.iter().zip(progress.iter().skip(1)) {
+            assert!(
+                (previous.phase, previous.num_docs_processed)
+                    <= (next.phase, next.num_docs_processed)
+            );
+        }
+        for phase in [
+            MergePhase::FieldNorms,
+            MergePhase::Postings,
+            MergePhase::Store,
+            MergePhase::FastFields,
+        ] {
+            let phase_progress: Vec<u32> = progress
+                .iter()
+                .filter(|merge_progress| merge_progress.phase == phase)
+                .map(|merge_progress| merge_progress.num_docs_processed)
+                .collect();
+            assert_eq!(phase_progress.first(), Some(&0));
+            assert_eq!(phase_progress.last(), Some(&4_050));
+        }
+        // The doc store is reported every few documents.
+        assert!(
+            progress
+                .iter()
+                .filter(|merge_progress| merge_progress.phase == MergePhase::Store)
+                .count()
+                > 3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_with_progress_cancelled() -> crate::Result<()> {
+        let (index, mut index_writer) = index_for_merge_progress(100)?;
+        let segment_ids = index.searchable_segment_ids()?;
+        let files_before_merge = existing_files(&index);
+        let pause_merge_in_postings = || {
+            let (started_sender, started_receiver) = crossbeam_channel::bounded(1);
+            let (resume_sender, resume_receiver) = crossbeam_channel::bounded::<()>(1);
+            let on_progress = Box::new(move |merge_progress: MergeProgress| {
+                if merge_progress.phase == MergePhase::Postings
+                    && merge_progress.num_docs_processed == 0
+                {
+                    started_sender.send(()).unwrap();
+                    resume_receiver.recv().unwrap();
+                }
+            });
+            (on_progress, started_receiver, resume_sender)
+        };
+
+        // Explicit cancellation.
+        let (on_progress, started_receiver, resume_sender) = pause_merge_in_postings();
+        let merge_handle = index_writer.merge_with_progress(&segment_ids, on_progress);
+        started_receiver.recv().unwrap();
+        assert!(existing_files(&index).len() > files_before_merge.len());
+        merge_handle.cancel();
+        resume_sender.send(()).unwrap();
+        assert!(matches!(
+            merge_handle.wait(),
+            Err(TantivyError::MergeCancelled)
+        ));
+        assert_eq!(existing_files(&index), files_before_merge);
+        assert_eq!(index.searchable_segment_ids()?, segment_ids);
+
+        // Cancellation by dropping the handle.
+        let (on_progress, started_receiver, resume_sender) = pause_merge_in_postings();
+        let merge_handle = index_writer.merge_with_progress(&segment_ids, on_progress);
+        started_receiver.recv().unwrap();
+        drop(merge_handle);
+        resume_sender.send(()).unwrap();
+        index_writer.segment_updater.wait_merging_thread()?;
+        assert_eq!(existing_files(&index), files_before_merge);
+        assert_eq!(index.searchable_segment_ids()?, segment_ids);
+
+        // The segments can be merged again.
+        let merged_segment_meta = index_writer
+            .merge_segments_and_wait(&segment_ids, Box::new(|_| {}))?
+            .unwrap();
+        assert_eq!(merged_segment_meta.num_docs(), 270);
+        Ok(())
+    }
+
+    /// Copies the files of a segment in a new segment, but its delete files.
+    fn copy_segment(index: &Index, segment_meta: &SegmentMeta) -> crate::Result<SegmentMeta> {
+        let segment = index.segment(segment_meta.clone());
+        let mut copy = index.new_segment();
+        for &component in SegmentComponent::iterator() {
+            if matches!(
+                component,
+                SegmentComponent::Delete | SegmentComponent::SoftDelete
+            ) || !index
+                .directory()
+                .exists(&segment.relative_path(component))?
+            {
+                continue;
+            }
+            let bytes = segment.open_read(component)?.read_bytes()?;
+            let mut write = copy.open_write(component)?;
+            write.write_all(bytes.as_slice())?;
+            write.terminate()?;
+        }
+        Ok(copy.meta().clone().with_max_doc(segment_meta.max_doc()))
+    }
+
+    #[test]
+    fn test_replace_segment() -> crate::Result<()> {
+        let (index, mut index_writer) = index_for_merge_progress(100)?;
+        let schema = index.schema();
+        let id_field = schema.get_field("id").unwrap();
+        let text_field = schema.get_field("text").unwrap();
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let old_searcher = reader.searcher();
+        let replaced_segment_meta = index.searchable_segment_metas()?[0].clone();
+        let replaced_segment_id = replaced_segment_meta.id();
+        let replaced_store_path = replaced_segment_meta.relative_path(SegmentComponent::Store);
+        let segment_meta = copy_segment(&index, &replaced_segment_meta)?;
+        drop(replaced_segment_meta);
+        assert!(!segment_meta.has_deletes());
+        let segment_id = segment_meta.id();
+        index_writer.replace_segment(replaced_segment_id, segment_meta)?;
+
+        // The replacement is only visible after the next commit.
+        assert!(index
+            .searchable_segment_ids()?
+            .contains(&replaced_segment_id));
+        index_writer.commit()?;
+        let segment_ids = index.searchable_segment_ids()?;
+        assert_eq!(segment_ids.len(), 3);
+        assert!(segment_ids.contains(&segment_id));
+        assert!(!segment_ids.contains(&replaced_segment_id));
+        // The files of the replaced segment are garbage collected, but remain readable by the
+        // searchers opened before the commit.
+        assert!(!index.directory().exists(&replaced_store_path)?);
+
+        // The replacement has the same documents, and the deletes of the replaced segment.
+        reader.reload()?;
+        let searcher = reader.searcher();
+        let find_segment_reader = |searcher: &Searcher, segment_id: SegmentId| {
+            searcher
+                .segment_readers()
+                .iter()
+                .position(|segment_reader| segment_reader.segment_id() == segment_id)
+                .unwrap() as u32
+        };
+        let old_ord = find_segment_reader(&old_searcher, replaced_segment_id);
+        let new_ord = find_segment_reader(&searcher, segment_id);
+        let old_segment_reader = old_searcher.segment_reader(old_ord);
+        let new_segment_reader = searcher.segment_reader(new_ord);
+        assert_eq!(new_segment_reader.num_deleted_docs(), 10);
+        for doc in 0..new_segment_reader.max_doc() {
+            assert_eq!(
+                new_segment_reader.is_deleted(doc),
+                old_segment_reader.is_deleted(doc)
+            );
+            let old_doc: TantivyDocument = old_searcher.doc(DocAddress::new(old_ord, doc))?;
+            let new_doc: TantivyDocument = searcher.doc(DocAddress::new(new_ord, doc))?;
+            assert_eq!(new_doc.to_json(&schema), old_doc.to_json(&schema));
+        }
+        for query_str in [
+            "text:lorem",
+            "text:ipsum AND id:42",
+            "id:[5 TO 150]",
+            "id:10",
+        ] {
+            let query = QueryParser::for_index(&index, vec![text_field, id_field])
+                .parse_query(query_str)?;
+            let top_docs = TopDocs::with_limit(300).order_by_u64_field("id", Order::Asc);
+            let ids = |searcher: &Searcher| -> crate::Result<Vec<u64>> {
+                Ok(searcher
+                    .search(&query, &top_docs)?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect())
+            };
+            assert_eq!(ids(&searcher)?, ids(&old_searcher)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_segment_pending_deletes() -> crate::Result<()> {
+        let (index, mut index_writer) = index_for_merge_progress(100)?;
+        let id_field = index.schema().get_field("id").unwrap();
+        for segment_meta in index.searchable_segment_metas()? {
+            let copy = copy_segment(&index, &segment_meta)?;
+            index_writer.replace_segment(segment_meta.id(), copy)?;
+        }
+        // The deletes which are not committed are applied to the replacements.
+        index_writer.delete_term(Term::from_field_u64(id_field, 1))?;
+        index_writer.delete_term(Term::from_field_u64(id_field, 201))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 268);
+        let id_query =
+            |id: u64| TermQuery::new(Term::from_field_u64(id_field, id), IndexRecordOption::Basic);
+        assert_eq!(searcher.search(&id_query(1), &Count)?, 0);
+        assert_eq!(searcher.search(&id_query(2), &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_segment_invalid() -> crate::Result<()> {
+        let (index, index_writer) = index_for_merge_progress(100)?;
+        let segment_meta = index.searchable_segment_metas()?[0].clone();
+        let is_invalid =
+            |res: crate::Result<()>| matches!(res, Err(TantivyError::InvalidArgument(_)));
+
+        assert!(is_invalid(index_writer.replace_segment(
+            SegmentId::generate_random(),
+            segment_meta.clone()
+        )));
+        assert!(is_invalid(
+            index_writer.replace_segment(segment_meta.id(), segment_meta.clone())
+        ));
+        let shorter = index_writer.new_segment().meta().clone().with_max_doc(99);
+        assert!(is_invalid(
+            index_writer.replace_segment(segment_meta.id(), shorter)
+        ));
+
+        // The replacement has other deletes than the replaced segment.
+        let copy = copy_segment(&index, &segment_meta)?;
+        let mut copy_segment = index.segment(copy).with_delete_meta(1, 1);
+        let mut alive_bitset = BitSet::with_max_value_and_full(100);
+        alive_bitset.remove(5);
+        let mut delete_file = copy_segment.open_write(SegmentComponent::Delete)?;
+        write_alive_bitset(&alive_bitset, &mut delete_file)?;
+        delete_file.terminate()?;
+        assert!(is_invalid(index_writer.replace_segment(
+            segment_meta.id(),
+            copy_segment.meta().clone()
+        )));
+        assert_eq!(index.searchable_segment_ids()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_segment_busy() -> crate::Result<()> {
+        let (index, mut index_writer) = index_for_merge_progress(100)?;
+        let segment_metas = index.searchable_segment_metas()?;
+        let (started_sender, started_receiver) = crossbeam_channel::bounded(1);
+        let (resume_sender, resume_receiver) = crossbeam_channel::bounded::<()>(1);
+        let merge_handle = index_writer.merge_with_progress(
+            &[segment_metas[0].id(), segment_metas[1].id()],
+            Box::new(move |merge_progress: MergeProgress| {
+                if merge_progress.phase == MergePhase::Postings
+                    && merge_progress.num_docs_processed == 0
+                {
+                    started_sender.send(()).unwrap();
+                    resume_receiver.recv().unwrap();
+                }
+            }),
+        );
+        started_receiver.recv().unwrap();
+
+        // The segments being merged cannot be replaced.
+        let copy = copy_segment(&index, &segment_metas[0])?;
+        let err = index_writer
+            .replace_segment(segment_metas[0].id(), copy)
+            .unwrap_err();
+        assert!(
+            matches!(err, TantivyError::SegmentBusy { segment_id } if segment_id == segment_metas[0].id())
+        );
+        assert_eq!(err.error_code(), "segment_busy");
+        resume_sender.send(()).unwrap();
+        merge_handle.wait()?;
+
+        // The segments being replaced can neither be merged nor replaced again.
+        let copy = copy_segment(&index, &segment_metas[2])?;
+        index_writer.replace_segment(segment_metas[2].id(), copy)?;
+        let copy = copy_segment(&index, &segment_metas[2])?;
+        assert!(matches!(
+            index_writer.replace_segment(segment_metas[2].id(), copy),
+            Err(TantivyError::SegmentBusy { .. })
+        ));
+        assert!(matches!(
+            index_writer.merge(&[segment_metas[2].id()]).wait(),
+            Err(TantivyError::SegmentBusy { .. })
+        ));
+        index_writer.commit()?;
+        let segment_ids = index.searchable_segment_ids()?;
+        assert_eq!(segment_ids.len(), 2);
+        assert!(!segment_ids.contains(&segment_metas[2].id()));
+        assert_eq!(index.reader()?.searcher().num_docs(), 270);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_io_budget_needs_to_be_positive() {
+        let index = Index::create_in_ram(Schema::builder().build());
+        let options = IndexWriterOptions::builder()
+            .merge_io_budget_bytes_per_sec(0)
+            .build();
+        let writer_res = index.writer_with_options::<TantivyDocument>(options);
+        assert!(matches!(writer_res, Err(TantivyError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_concurrent_run_upserts_keep_one_doc_per_key() -> crate::Result<()> {
+        const NUM_THREADS: usize = 8;
+        const NUM_KEYS: usize = 4;
+        const NUM_UPSERTS_PER_THREAD: usize = 50;
+        let mut schema_builder = schema::Schema::builder();
+        let key = schema_builder.add_text_field("key", STRING);
+        let version = schema_builder.add_u64_field("version", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter =
+            index.writer_with_num_threads(4, 4 * MEMORY_BUDGET_NUM_BYTES_MIN)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let mut version_value = 0u64;
+        for _ in 0..20 {
+            // (key, batch opstamp, version) of every upsert.
+            let upserts: Vec<(usize, u64, u64)> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..NUM_THREADS)
+                    .map(|thread_id| {
+                        let index_writer = &index_writer;
+                        let first_version =
+                            version_value + (thread_id * NUM_UPSERTS_PER_THREAD) as u64;
+                        scope.spawn(move || {
+                            (0..NUM_UPSERTS_PER_THREAD)
+                                .map(|i| {
+                                    let key_id = (thread_id + i) % NUM_KEYS;
+                                    let key_value = format!("k{key_id}");
+                                    let version_value = first_version + i as u64;
+                                    let opstamp = index_writer
+                                        .run([
+                                            UserOperation::Delete(Term::from_field_text(
+                                                key, &key_value,
+                                            )),
+                                            UserOperation::Add(doc!(
+                                                key => key_value,
+                                                version => version_value,
+                                            )),
+                                        ])
+                                        .unwrap();
+                                    (key_id, opstamp, version_value)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+            version_value += (NUM_THREADS * NUM_UPSERTS_PER_THREAD) as u64;
+            index_writer.commit()?;
+            reader.reload()?;
+            let searcher = reader.searcher();
+            assert_eq!(searcher.num_docs(), NUM_KEYS as u64);
+            for key_id in 0..NUM_KEYS {
+                let term = Term::from_field_text(key, &format!("k{key_id}"));
+                let query = TermQuery::new(term, IndexRecordOption::Basic);
+                let top_docs = searcher.search(&query, &TopDocs::with_limit(2))?;
+                assert_eq!(top_docs.len(), 1);
+                let doc_address = top_docs[0].1;
+                let version_column = searcher
+                    .segment_reader(doc_address.segment_ord)
+                    .fast_fields()
+                    .u64("version")?;
+                let last_upsert_version = upserts
+                    .iter()
+                    .filter(|(upsert_key_id, _, _)| *upsert_key_id == key_id)
+                    .max_by_key(|(_, opstamp, _)| *opstamp)
+                    .map(|(_, _, version)| *version);
+                assert_eq!(
+                    version_column.first(doc_address.doc_id),
+                    last_upsert_version
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Matches the documents with `value` at any path of the JSON `field` ending with `leaf`. The
+    /// paths are looked up in the segments of the searcher when the weight is built.
+    #[derive(Clone, Debug)]
+    struct JsonLeafQuery {
+        field: Field,
+        leaf: &'static str,
+        value: &'static str,
+    }
+
+    impl Query for JsonLeafQuery {
+        fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+            let mut terms = Vec::new();
+            for segment_reader in enable_scoring
+                .searcher()
+                .map_or(&[][..], |searcher| searcher.segment_readers())
+            {
+                let inverted_index = segment_reader.inverted_index(self.field)?;
+                let mut term_stream = inverted_index.terms().stream()?;
+                while term_stream.advance() {
+                    let key = term_stream.key();
+                    let end_of_path = key.iter().position(|&byte| byte == JSON_END_OF_PATH);
+                    let path = String::from_utf8_lossy(&key[..end_of_path.unwrap()])
+                        .replace(JSON_PATH_SEGMENT_SEP as char, ".");
+                    if path.rsplit('.').next() == Some(self.leaf) {
+                        let mut term = Term::from_field_json_path(self.field, &path, false);
+                        term.append_type_and_str(self.value);
+                        terms.push(term);
+                    }
+                }
+            }
+            TermSetQuery::new(terms).weight(enable_scoring)
+        }
+    }
+
+    #[test]
+    fn test_delete_query_json_path_in_later_segment() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let json_field = schema_builder.add_json_field("json", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(json_field=>json!({"attr": "a", "other": "a"})))?;
+        index_writer.commit()?;
+
+        // The path is not present in any segment when the delete is enqueued. The segment of the
+        // document is only created on commit.
+        index_writer.add_document(doc!(json_field=>json!({"new": {"attr": "a"}})))?;
+        let query = JsonLeafQuery {
+            field: json_field,
+            leaf: "attr",
+            value: "a",
+        };
+        index_writer.delete_query(Box::new(query.clone()))?;
+        // Documents added after the delete are not affected by it.
+        index_writer.add_document(doc!(json_field=>json!({"new": {"attr": "a", "b": "b"}})))?;
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let mut term = Term::from_field_json_path(json_field, "new.b", false);
+        term.append_type_and_str("b");
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        Ok(())
+    }
+
+    fn paths(index: &Index, path_field: Field) -> crate::Result<Vec<String>> {
+        let searcher = index.reader()?.searcher();
+        let top_docs = searcher.search(&AllQuery, &TopDocs::with_limit(100))?;
+        let mut paths: Vec<String> = top_docs
+            .into_iter()
+            .map(|(_, doc_address)| {
+                let doc: TantivyDocument = searcher.doc(doc_address).unwrap();
+                doc.get_first(path_field)
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_delete_query_prefix_across_segments() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let path = schema_builder.add_text_field("path", STRING | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment_paths in [
+            &["/tenant42/a", "/tenant42/b", "/tenant1/a"][..],
+            &["/tenant42/c", "/tenant420/a", "/tenant7/a"][..],
+            &["/tenant1/b", "/tenant7/b"][..],
+        ] {
+            for &segment_path in segment_paths {
+                index_writer.add_document(doc!(path => segment_path))?;
+            }
+            index_writer.commit()?;
+        }
+
+        let regex_query = RegexQuery::from_pattern("/tenant42/.*", path)?;
+        index_writer.delete_query(Box::new(regex_query))?;
+        let prefix_query = PhrasePrefixQuery::new(vec![Term::from_field_text(path, "/tenant7/")]);
+        index_writer.delete_query(Box::new(prefix_query))?;
+        index_writer.commit()?;
+
+        assert_eq!(
+            paths(&index, path)?,
+            vec!["/tenant1/a", "/tenant1/b", "/tenant420/a"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_query_max_expanded_terms() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let path = schema_builder.add_text_field("path", STRING | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .num_worker_threads(1)
+            .delete_max_expanded_terms(2)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        for segment_path in ["/tenant42/a", "/tenant42/b", "/tenant42/c", "/tenant1/a"] {
+            index_writer.add_document(doc!(path => segment_path))?;
+        }
+        index_writer.commit()?;
+
+        // Two terms are within the limit.
+        let query = RegexQuery::from_pattern("/tenant42/[ab]", path)?;
+        index_writer.delete_query(Box::new(query))?;
+        index_writer.commit()?;
+        assert_eq!(paths(&index, path)?, vec!["/tenant1/a", "/tenant42/c"]);
+
+        // The deleted terms are still in the term dictionary of the segment.
+        let query = RegexQuery::from_pattern("/tenant42/.*", path)?;
+        index_writer.delete_query(Box::new(query))?;
+        let err = index_writer.commit().unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::TooManyExpandedTerms {
+                ref field,
+                max_expanded_terms: 2,
+            } if field == "path"
+        ));
+        index_writer.rollback()?;
+        assert_eq!(paths(&index, path)?, vec!["/tenant1/a", "/tenant42/c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_fuzzy_term() -> crate::Result<()> {
         let mut schema_builder = schema::Schema::builder();
-        let text_field = schema_builder.add_text_field("text", TEXT);
+        let sku = schema_builder.add_text_field("sku", STRING | STORED);
         let index = Index::create_in_ram(schema_builder.build());
-        let index_writer = index.writer_for_tests().unwrap();
-        let operations = vec![
-            UserOperation::Add(doc!(text_field=>"a")),
-            UserOperation::Add(doc!(text_field=>"b")),
-        ];
-        let batch_opstamp1 = index_writer.run(operations).unwrap();
-        assert_eq!(batch_opstamp1, 2u64);
+        let options = IndexWriterOptions::builder()
+            .num_worker_threads(1)
+            .delete_max_expanded_terms(3)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        for value in ["ab-123", "ab-124", "ab-132", "xy-123", "cd-999"] {
+            index_writer.add_document(doc!(sku => value))?;
+        }
+        index_writer.commit()?;
+        index_writer.add_document(doc!(sku => "ab-12"))?;
+
+        // The fuzzy delete also applies to the documents of the segments created after it.
+        index_writer.delete_fuzzy_term(Term::from_field_text(sku, "ab-123"), 1, true)?;
+        index_writer.add_document(doc!(sku => "ab-125"))?;
+        index_writer.commit()?;
+        assert_eq!(paths(&index, sku)?, vec!["ab-125", "cd-999", "xy-123"]);
+
+        // Transpositions cost 2.
+        index_writer.delete_fuzzy_term(Term::from_field_text(sku, "ab-215"), 1, false)?;
+        index_writer.commit()?;
+        assert_eq!(paths(&index, sku)?, vec!["ab-125", "cd-999", "xy-123"]);
+
+        // The deleted terms are still in the term dictionary of the first segment, so the
+        // expansion exceeds the safeguard.
+        index_writer.delete_fuzzy_term(Term::from_field_text(sku, "ab-123"), 2, true)?;
+        let err = index_writer.commit().unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::TooManyExpandedTerms {
+                max_expanded_terms: 3,
+                ..
+            }
+        ));
+        index_writer.rollback()?;
+        assert_eq!(paths(&index, sku)?, vec!["ab-125", "cd-999", "xy-123"]);
+
+        assert!(matches!(
+            index_writer.delete_fuzzy_term(Term::from_field_text(sku, "ab-125"), 3, true),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
     }
 
     #[test]
-    fn test_no_need_to_rewrite_delete_file_if_no_new_deletes() {
+    fn test_delete_query_regex_opstamp_order() -> crate::Result<()> {
         let mut schema_builder = schema::Schema::builder();
-        let text_field = schema_builder.add_text_field("text", TEXT);
+        let path = schema_builder.add_text_field("path", STRING | STORED);
         let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(path => "/tenant42/committed"))?;
+        index_writer.commit()?;
 
-        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
-        index_writer
-            .add_document(doc!(text_field => "hello1"))
-            .unwrap();
-        index_writer
-            .add_document(doc!(text_field => "hello2"))
-            .unwrap();
-        assert!(index_writer.commit().is_ok());
+        index_writer.add_document(doc!(path => "/tenant42/before"))?;
+        let query = RegexQuery::from_pattern("/tenant42/.*", path)?;
+        let delete_opstamp = index_writer.delete_query(Box::new(query))?;
+        let add_opstamp = index_writer.add_document(doc!(path => "/tenant42/after"))?;
+        assert!(delete_opstamp < add_opstamp);
+        index_writer.commit()?;
 
-        let reader = index.reader().unwrap();
-        let searcher = reader.searcher();
-        assert_eq!(searcher.segment_readers().len(), 1);
-        assert_eq!(searcher.segment_reader(0u32).num_docs(), 2);
+        // Only the document added after the delete survives it.
+        assert_eq!(paths(&index, path)?, vec!["/tenant42/after"]);
+        Ok(())
+    }
 
-        index_writer.delete_term(Term::from_field_text(text_field, "hello1"));
-        assert!(index_writer.commit().is_ok());
+    #[test]
+    fn test_commit_async_searchable() {
+        use futures::FutureExt;
 
-        assert!(reader.reload().is_ok());
-        let searcher = reader.searcher();
-        assert_eq!(searcher.segment_readers().len(), 1);
-        assert_eq!(searcher.segment_reader(0u32).num_docs(), 1);
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .unwrap();
+        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        index_writer.add_document(doc!(text_field=>"a")).unwrap();
+        let commit_handle = futures::executor::block_on(index_writer.commit_async()).unwrap();
 
-        let previous_delete_opstamp = index.load_metas().unwrap().segments[0].delete_opstamp();
+        // The commit is durable, but the reader was not reloaded yet.
+        let searchable = commit_handle.searchable(&reader);
+        assert!(commit_handle.searchable(&reader).now_or_never().is_none());
+        reader.reload().unwrap();
+        searchable.wait().unwrap();
+        assert_eq!(reader.searcher().num_docs(), 1);
+
+        // Already searchable.
+        assert!(matches!(
+            commit_handle.searchable(&reader).now_or_never(),
+            Some(Ok(()))
+        ));
+    }
 
-        // All docs containing hello1 have been already removed.
-        // We should not update the delete meta.
-        index_writer.delete_term(Term::from_field_text(text_field, "hello1"));
-        assert!(index_writer.commit().is_ok());
+    #[test]
+    fn test_commit_async_race_with_reloading_reader() {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .unwrap();
+        let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
 
-        assert!(reader.reload().is_ok());
-        let searcher = reader.searcher();
-        assert_eq!(searcher.segment_readers().len(), 1);
-        assert_eq!(searcher.segment_reader(0u32).num_docs(), 1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let reload_thread = {
+            let reader = reader.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    reader.reload().unwrap();
+                }
+            })
+        };
 
-        let after_delete_opstamp = index.load_metas().unwrap().segments[0].delete_opstamp();
-        assert_eq!(after_delete_opstamp, previous_delete_opstamp);
+        index_writer.add_document(doc!(text_field=>"a")).unwrap();
+        let commit1 = index_writer.commit_async();
+        index_writer.add_document(doc!(text_field=>"b")).unwrap();
+        index_writer.add_document(doc!(text_field=>"c")).unwrap();
+        let commit2 = index_writer.commit_async();
+        let (commit_handle1, commit_handle2) =
+            futures::executor::block_on(futures::future::join(commit1, commit2));
+        let commit_handle1 = commit_handle1.unwrap();
+        let commit_handle2 = commit_handle2.unwrap();
+        assert!(commit_handle1.opstamp() < commit_handle2.opstamp());
+
+        let (searchable1, searchable2) = futures::executor::block_on(futures::future::join(
+            async {
+                commit_handle1.searchable(&reader).await.unwrap();
+                reader.searcher().num_docs()
+            },
+            async {
+                commit_handle2.searchable(&reader).await.unwrap();
+                reader.searcher().num_docs()
+            },
+        ));
+        assert!(searchable1 >= 1);
+        assert_eq!(searchable2, 3);
+
+        stop.store(true, Ordering::Relaxed);
+        reload_thread.join().unwrap();
     }
 
     #[test]
@@ -1085,7 +3718,7 @@ mod tests {
         reader.reload().unwrap();
         assert_eq!(num_docs_containing("a"), 1);
 
-        index_writer.delete_term(Term::from_field_text(text_field, "a"));
+        index_writer.delete_term(Term::from_field_text(text_field, "a"))?;
         index_writer.commit()?;
 
         reader.reload().unwrap();
@@ -1131,7 +3764,7 @@ mod tests {
         reader.reload().unwrap();
         assert_eq!(num_docs_containing("a"), 4);
 
-        index_writer.delete_term(Term::from_field_text(text_field, "a"));
+        index_writer.delete_term(Term::from_field_text(text_field, "a"))?;
         index_writer.commit()?;
 
         reader.reload().unwrap();
@@ -1208,6 +3841,277 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_commit_with_payload_round_trip() -> crate::Result<()> {
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct ReplicationState {
+            position: u64,
+            shards: Vec<String>,
+        }
+
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        assert_eq!(index.commit_payload()?, None);
+
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text_field => "a"))?;
+        let state = ReplicationState {
+            position: 42,
+            shards: vec!["shard-1".to_string(), "shard-2".to_string()],
+        };
+        let opstamp = index_writer.commit_with_payload(&state)?;
+        let payload = index.commit_payload()?.unwrap();
+        assert_eq!(serde_json::from_value::<ReplicationState>(payload)?, state);
+
+        // The payload is persisted with the commit.
+        let reopened_index = Index::open(index.directory().clone())?;
+        let payload = reopened_index.commit_payload()?.unwrap();
+        assert_eq!(serde_json::from_value::<ReplicationState>(payload)?, state);
+        assert_eq!(
+            reopened_index.list_commit_payloads()?,
+            vec![CommitPayload {
+                opstamp,
+                payload: serde_json::to_value(&state)?,
+            }]
+        );
+
+        // Plain string payloads are returned as JSON strings.
+        let mut prepared_commit = index_writer.prepare_commit()?;
+        prepared_commit.set_payload("not json");
+        prepared_commit.commit()?;
+        assert_eq!(index.commit_payload()?, Some(json!("not json")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_payload_too_large() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .max_commit_payload_num_bytes(100)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.add_document(doc!(text_field => "a"))?;
+
+        let err = index_writer
+            .commit_with_payload(json!({ "data": "a".repeat(100) }))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::CommitPayloadTooLarge {
+                num_bytes: 111,
+                max_num_bytes: 100
+            }
+        ));
+        // Nothing was committed.
+        let reader = index.reader()?;
+        assert_eq!(reader.searcher().num_docs(), 0);
+        assert_eq!(index.commit_payload()?, None);
+
+        let mut prepared_commit = index_writer.prepare_commit()?;
+        assert!(matches!(
+            prepared_commit.modify_payload(|_| json!("a".repeat(200))),
+            Err(TantivyError::CommitPayloadTooLarge { .. })
+        ));
+        prepared_commit.set_json_payload(&json!({ "data": "a".repeat(80) }))?;
+        prepared_commit.commit()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().num_docs(), 1);
+        assert_eq!(
+            index.commit_payload()?,
+            Some(json!({ "data": "a".repeat(80) }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_payload_history() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .num_retained_commit_payloads(3)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        let mut opstamps = Vec::new();
+        for i in 0..5 {
+            index_writer.add_document(doc!(text_field => "a"))?;
+            opstamps.push(index_writer.commit_with_payload(json!({ "position": i }))?);
+        }
+        // Commits without a payload are not listed.
+        index_writer.commit()?;
+        assert_eq!(index.commit_payload()?, None);
+        index_writer.garbage_collect_files().wait()?;
+
+        let commit_payloads = index.list_commit_payloads()?;
+        assert_eq!(
+            commit_payloads,
+            (2..5)
+                .map(|i| CommitPayload {
+                    opstamp: opstamps[i],
+                    payload: json!({ "position": i }),
+                })
+                .collect::<Vec<_>>()
+        );
+
+        // Read-modify-write of the payload of the last commit.
+        index_writer.commit_with_payload(json!({ "position": 10 }))?;
+        let mut prepared_commit = index_writer.prepare_commit()?;
+        prepared_commit.modify_payload(|payload| {
+            let position = payload.unwrap()["position"].as_u64().unwrap();
+            json!({ "position": position + 1 })
+        })?;
+        // Composes with a payload already added to the prepared commit.
+        prepared_commit.modify_payload(|payload| {
+            let mut payload = payload.unwrap();
+            payload["checkpoint"] = json!(true);
+            payload
+        })?;
+        prepared_commit.commit()?;
+        assert_eq!(
+            index.commit_payload()?,
+            Some(json!({ "position": 11, "checkpoint": true }))
+        );
+        let positions: Vec<serde_json::Value> = index
+            .list_commit_payloads()?
+            .into_iter()
+            .map(|commit_payload| commit_payload.payload["position"].clone())
+            .collect();
+        assert_eq!(positions, vec![json!(4), json!(10), json!(11)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_soft_delete() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED | FAST);
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let retain_until_field = schema_builder.add_u64_field("retain_until", FAST);
+        let directory = RamDirectory::create();
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            IndexSettings::default(),
+        )?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        let term_a = Term::from_field_text(text_field, "a");
+        let query_a = TermQuery::new(term_a.clone(), IndexRecordOption::Basic);
+        let counts = |searcher: &crate::Searcher| -> crate::Result<(usize, usize)> {
+            Ok((
+                searcher.search(&AllQuery, &Count)?,
+                searcher.search(&query_a, &Count)?,
+            ))
+        };
+
+        // Two segments, with docs 0, 3, 6, 9 matching `a`.
+        for id in 0u64..10 {
+            let text = if id % 3 == 0 { "a" } else { "b" };
+            index_writer.add_document(doc!(id_field => id, text_field => text))?;
+            if id == 4 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        index_writer.soft_delete_term(term_a.clone(), retain_until_field)?;
+        // Added after the soft delete, so it is not affected.
+        index_writer.add_document(doc!(id_field => 10u64, text_field => "a"))?;
+        // Soft-deleted while still in the indexing buffer.
+        index_writer.add_document(doc!(id_field => 11u64, text_field => "c"))?;
+        index_writer
+            .soft_delete_term(Term::from_field_text(text_field, "c"), retain_until_field)?;
+        index_writer.commit()?;
+        reader.reload()?;
+
+        let searcher = reader.searcher();
+        assert_eq!(counts(&searcher)?, (7, 1));
+        assert_eq!(searcher.num_docs(), 7);
+        let searcher_with_soft_deleted = searcher.include_soft_deleted(true);
+        assert_eq!(counts(&searcher_with_soft_deleted)?, (12, 5));
+        assert_eq!(searcher_with_soft_deleted.num_docs(), 12);
+        assert_eq!(
+            counts(&searcher_with_soft_deleted.include_soft_deleted(false))?,
+            (7, 1)
+        );
+        let num_soft_deleted_docs: u32 = searcher
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| segment_reader.num_soft_deleted_docs())
+            .sum();
+        assert_eq!(num_soft_deleted_docs, 5);
+        for segment_reader in searcher.segment_readers() {
+            if segment_reader.num_soft_deleted_docs() > 0 {
+                assert_eq!(
+                    segment_reader.soft_delete_tombstone_fields(),
+                    ["retain_until"]
+                );
+            }
+        }
+
+        // A hard delete removes the document, soft-deleted or not.
+        index_writer.delete_term(Term::from_field_u64(id_field, 0))?;
+        index_writer.commit()?;
+        reader.reload()?;
+        assert_eq!(counts(&reader.searcher())?, (7, 1));
+        assert_eq!(
+            counts(&reader.searcher().include_soft_deleted(true))?,
+            (11, 4)
+        );
+
+        // Merges carry the soft deletes over.
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert_eq!(searcher.segment_reader(0).num_soft_deleted_docs(), 4);
+        assert_eq!(counts(&searcher)?, (7, 1));
+        assert_eq!(counts(&searcher.include_soft_deleted(true))?, (11, 4));
+        let soft_deleted_ids: HashSet<u64> = {
+            let segment_reader = searcher
+                .include_soft_deleted(true)
+                .segment_reader(0)
+                .clone();
+            let ids = segment_reader.fast_fields().u64("id")?;
+            segment_reader
+                .doc_ids_alive()
+                .filter(|&doc| segment_reader.is_soft_deleted(doc))
+                .map(|doc| ids.first(doc).unwrap())
+                .collect()
+        };
+        assert_eq!(soft_deleted_ids, HashSet::from([3, 6, 9, 11]));
+
+        // The soft deletes are persisted.
+        let reopened_index = Index::open(directory)?;
+        let reopened_searcher = reopened_index.reader()?.searcher();
+        assert_eq!(counts(&reopened_searcher)?, (7, 1));
+        assert_eq!(
+            counts(&reopened_searcher.include_soft_deleted(true))?,
+            (11, 4)
+        );
+
+        // Expired soft-deleted documents are reclaimed by merges.
+        index_writer.set_soft_delete_retention_policy(Box::new(ExpireSoftDeleted));
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_reader(0).max_doc(), 7);
+        assert_eq!(searcher.segment_reader(0).num_soft_deleted_docs(), 0);
+        assert_eq!(counts(&searcher)?, (7, 1));
+        assert_eq!(counts(&searcher.include_soft_deleted(true))?, (7, 1));
+        Ok(())
+    }
+
     #[test]
     fn test_prepare_but_rollback() -> crate::Result<()> {
         let mut schema_builder = schema::Schema::builder();
@@ -1299,7 +4203,7 @@ mod tests {
 
         // delete_all_documents the index
         let clear_tstamp = index_writer.delete_all_documents().unwrap();
-        assert_eq!(clear_tstamp, add_tstamp);
+        assert_eq!(clear_tstamp, first_commit_tstamp);
 
         // commit the clear command - now documents aren't available
         let second_commit = index_writer.commit();
@@ -1377,8 +4281,8 @@ mod tests {
 
         // clear but don't commit!
         let clear_tstamp = index_writer.delete_all_documents().unwrap();
-        // clear_tstamp should reset to before the last commit
-        assert!(clear_tstamp < commit_tstamp);
+        // clear_tstamp should reset to the last commit
+        assert_eq!(clear_tstamp, commit_tstamp);
 
         // rollback
         let _rollback_tstamp = index_writer.rollback().unwrap();
@@ -1458,7 +4362,9 @@ mod tests {
         assert!(!text_fast_field.ord_to_str(3, &mut buffer).unwrap());
 
         assert_eq!(segment_reader.max_doc(), 3);
-        index_writer.delete_term(Term::from_field_text(text_field, "three"));
+        index_writer
+            .delete_term(Term::from_field_text(text_field, "three"))
+            .unwrap();
         index_writer.commit().unwrap();
         index_writer
             .merge(&[segment_reader.segment_id()])
@@ -1741,7 +4647,7 @@ mod tests {
                     add_docs(&mut index_writer, id, value, 1)?;
                 }
                 IndexingOp::DeleteDoc { id } => {
-                    index_writer.delete_term(Term::from_field_u64(id_field, id));
+                    index_writer.delete_term(Term::from_field_u64(id_field, id))?;
                 }
                 IndexingOp::DeleteDocQuery { id } => {
                     let term = Term::from_field_u64(id_field, id);
@@ -2430,7 +5336,7 @@ mod tests {
         index_writer.add_document(doc!(
             id_field=>deleted_id,
         ))?;
-        index_writer.delete_term(Term::from_field_u64(id_field, deleted_id));
+        index_writer.delete_term(Term::from_field_u64(id_field, deleted_id))?;
         index_writer.commit()?;
 
         // Merge
@@ -2550,12 +5456,224 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_memory_usage_report_adaptive_flush_threshold() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let memory_budget = 50_000_000;
+        let min_flush_threshold = 25_000_000;
+        let max_flush_threshold = memory_budget - MARGIN_IN_BYTES;
+        let options = IndexWriterOptions::builder()
+            .memory_budget_per_thread(memory_budget)
+            .num_worker_threads(1)
+            .adaptive_flush_threshold(AdaptiveFlushThreshold::new(
+                min_flush_threshold,
+                max_flush_threshold,
+            ))
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        let report = index_writer.memory_usage_report();
+        assert_eq!(report.num_flushes, 0);
+        assert_eq!(report.bytes_per_doc_estimate, None);
+        assert_eq!(report.threads.len(), 1);
+        assert_eq!(report.threads[0].flush_threshold, max_flush_threshold);
+
+        // Small documents only flush on commit, with the max threshold.
+        for i in 0..5_000 {
+            index_writer.add_document(doc!(text => format!("small doc {i}")))?;
+        }
+        index_writer.commit()?;
+        let report = index_writer.memory_usage_report();
+        assert_eq!(report.num_flushes, 1);
+        assert_eq!(report.num_docs_flushed, 5_000);
+        let small_bytes_per_doc = report.bytes_per_doc_estimate.unwrap();
+        assert!(small_bytes_per_doc < 1_000);
+        assert_eq!(report.threads.len(), 1);
+        assert_eq!(report.threads[0].mem_usage, 0);
+        assert_eq!(report.threads[0].num_docs_buffered, 0);
+        assert_eq!(report.threads[0].flush_threshold, max_flush_threshold);
+
+        // Large documents lower the threshold, and get flushed before the commit.
+        for i in 0..150 {
+            let large_text = (0..2_000).map(|j| format!("w{i}x{j}")).join(" ");
+            index_writer.add_document(doc!(text => large_text))?;
+        }
+        index_writer.commit()?;
+        let report = index_writer.memory_usage_report();
+        assert!(report.num_flushes >= 3);
+        assert_eq!(report.num_docs_flushed, 5_150);
+        assert!(report.bytes_per_doc_estimate.unwrap() > small_bytes_per_doc);
+        assert_eq!(report.threads[0].flush_threshold, min_flush_threshold);
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len() as u64, report.num_flushes);
+        assert_eq!(searcher.num_docs(), 5_150);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reclaimable_space_report_and_expunge_deletes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", INDEXED | STORED | FAST);
+        let text = schema_builder.add_text_field("text", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .num_worker_threads(1)
+            .expunge_deletes_min_deleted_ratio(0.3)
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        // Three segments of 20 docs each.
+        for segment_ord in 0..3u64 {
+            for doc in 0..20u64 {
+                index_writer.add_document(doc!(id => segment_ord * 100 + doc, text => LOREM))?;
+            }
+            index_writer.commit()?;
+        }
+        // Deletes half of the first segment, and a tenth of the second one.
+        for doc in 0..10u64 {
+            index_writer.delete_term(Term::from_field_u64(id, doc))?;
+        }
+        for doc in 0..2u64 {
+            index_writer.delete_term(Term::from_field_u64(id, 100 + doc))?;
+        }
+        index_writer.commit()?;
+
+        let report = index_writer.reclaimable_space_report()?;
+        assert_eq!(report.segments.len(), 3);
+        let mut deleted_ratios: Vec<f64> = report
+            .segments
+            .iter()
+            .map(|segment| segment.deleted_ratio())
+            .collect();
+        deleted_ratios.sort_by(f64::total_cmp);
+        assert_eq!(deleted_ratios, vec![0.0, 0.1, 0.5]);
+        for segment in &report.segments {
+            assert!(segment.committed);
+            assert_eq!(segment.max_doc, 20);
+            let components: Vec<SegmentComponent> = segment
+                .num_bytes_by_component
+                .iter()
+                .map(|(component, _)| *component)
+                .collect();
+            assert!(components.contains(&SegmentComponent::Postings));
+            assert!(components.contains(&SegmentComponent::Store));
+            assert!(components.contains(&SegmentComponent::FastFields));
+            assert_eq!(
+                components.contains(&SegmentComponent::Delete),
+                segment.num_deleted_docs > 0
+            );
+            assert_eq!(
+                segment.num_bytes,
+                segment
+                    .num_bytes_by_component
+                    .iter()
+                    .map(|(_, num_bytes)| num_bytes)
+                    .sum::<u64>()
+            );
+            assert_eq!(
+                segment.estimated_reclaimable_bytes,
+                (segment.deleted_ratio() * segment.num_bytes as f64) as u64
+            );
+        }
+        assert_eq!(
+            report.total_num_bytes,
+            report
+                .segments
+                .iter()
+                .map(|segment| segment.num_bytes)
+                .sum::<u64>()
+        );
+        assert_eq!(
+            report.total_estimated_reclaimable_bytes,
+            report
+                .segments
+                .iter()
+                .map(|segment| segment.estimated_reclaimable_bytes)
+                .sum::<u64>()
+        );
+        let half_deleted_segment_id = report
+            .segments
+            .iter()
+            .find(|segment| segment.num_deleted_docs == 10)
+            .unwrap()
+            .segment_id;
+
+        // Only the half deleted segment is above the threshold.
+        let merges = index_writer.expunge_deletes();
+        assert_eq!(merges.len(), 1);
+        for merge in merges {
+            let merged_segment_meta = merge.wait()?.unwrap();
+            assert_eq!(merged_segment_meta.max_doc(), 10);
+            assert_eq!(merged_segment_meta.num_deleted_docs(), 0);
+        }
+        index_writer.wait_merging_threads()?;
+
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 3);
+        assert!(segment_metas
+            .iter()
+            .all(|segment_meta| segment_meta.id() != half_deleted_segment_id));
+        let mut num_deleted_docs: Vec<u32> = segment_metas
+            .iter()
+            .map(|segment_meta| segment_meta.num_deleted_docs())
+            .collect();
+        num_deleted_docs.sort();
+        assert_eq!(num_deleted_docs, vec![0, 0, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_usage_report_static_flush_threshold() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let report = index_writer.memory_usage_report();
+        assert_eq!(report.threads.len(), 1);
+        assert_eq!(
+            report.threads[0].flush_threshold,
+            MEMORY_BUDGET_NUM_BYTES_MIN - MARGIN_IN_BYTES
+        );
+        for i in 0..100 {
+            index_writer.add_document(doc!(text => format!("doc {i}")))?;
+        }
+        index_writer.commit()?;
+        let report = index_writer.memory_usage_report();
+        assert_eq!(report.num_flushes, 1);
+        assert_eq!(report.num_docs_flushed, 100);
+        assert_eq!(
+            report.threads[0].flush_threshold,
+            MEMORY_BUDGET_NUM_BYTES_MIN - MARGIN_IN_BYTES
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_writer_options_validation() {
         let mut schema_builder = Schema::builder();
         let _field = schema_builder.add_bool_field("example", STORED);
         let index = Index::create_in_ram(schema_builder.build());
 
+        let opt_with_invalid_thresholds = IndexWriterOptions::builder()
+            .adaptive_flush_threshold(AdaptiveFlushThreshold::new(10_000_000, 5_000_000))
+            .build();
+        let result = index.writer_with_options::<TantivyDocument>(opt_with_invalid_thresholds);
+        assert!(matches!(result, Err(TantivyError::InvalidArgument(_))));
+
+        let opt_with_too_high_threshold = IndexWriterOptions::builder()
+            .adaptive_flush_threshold(AdaptiveFlushThreshold::new(
+                5_000_000,
+                MEMORY_BUDGET_NUM_BYTES_MIN,
+            ))
+            .build();
+        let result = index.writer_with_options::<TantivyDocument>(opt_with_too_high_threshold);
+        assert!(matches!(result, Err(TantivyError::InvalidArgument(_))));
+
         let opt_wo_threads = IndexWriterOptions::builder().num_worker_threads(0).build();
         let result = index.writer_with_options::<TantivyDocument>(opt_wo_threads);
         assert!(result.is_err(), "Writer should reject 0 thread count");
@@ -0,0 +1,500 @@
+use std::collections::BTreeMap;
+
+use crate::index::Index;
+use crate::schema::document::{Document, ReferenceValue, ReferenceValueLeaf, Value};
+use crate::schema::{FieldType, Schema};
+use crate::tokenizer::{PreTokenizedStream, TextAnalyzer, TokenStream, MAX_TOKEN_LEN};
+use crate::{Opstamp, TantivyError};
+
+/// Maximum number of opstamps of affected documents kept per field in an [`IndexingReport`].
+pub const MAX_EXAMPLE_OPSTAMPS: usize = 10;
+
+/// What the [`IndexWriter`](crate::IndexWriter) does with a document whose text cannot be
+/// tokenized entirely, because a token exceeds [`MAX_TOKEN_LEN`] or because the tokenizer
+/// failed.
+///
+/// Set in the [`IndexWriterOptions`](crate::indexer::IndexWriterOptions).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenizationErrorPolicy {
+    /// Indexes the tokens produced before the error, dropping the others. The dropped tokens
+    /// are counted in the [`IndexingReport`] of the commit.
+    #[default]
+    Skip,
+    /// Rejects the document with a [`TantivyError::Tokenization`] error.
+    ///
+    /// The documents of [`IndexWriter::run()`](crate::IndexWriter::run) and
+    /// [`IndexWriter::add_documents()`](crate::IndexWriter::add_documents) are rejected one by
+    /// one: the other documents are still added, and the rejected ones are listed in the
+    /// [`IndexingReport`] of the commit. A block of
+    /// [`IndexWriter::add_document_block()`](crate::IndexWriter::add_document_block) is
+    /// rejected as a whole.
+    FailDocument,
+    /// Rejects the whole call with a [`TantivyError::Tokenization`] error, e.g. all of the
+    /// operations of [`IndexWriter::run()`](crate::IndexWriter::run).
+    FailBatch,
+}
+
+/// Why the text of a field could not be tokenized entirely.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum TokenizationError {
+    /// A token exceeds [`MAX_TOKEN_LEN`].
+    #[error("A token of the field {field:?} exceeds MAX_TOKEN_LEN ({len}>{MAX_TOKEN_LEN}).")]
+    TokenTooLong {
+        /// Name of the field.
+        field: String,
+        /// Length of the token in bytes.
+        len: usize,
+    },
+    /// The tokenizer ended its stream with an error, see
+    /// [`TokenStream::error()`](crate::tokenizer::TokenStream::error).
+    #[error("The tokenizer of the field {field:?} failed: {reason}")]
+    TokenizerFailed {
+        /// Name of the field.
+        field: String,
+        /// Error reported by the tokenizer.
+        reason: String,
+    },
+}
+
+impl TokenizationError {
+    /// Returns the name of the field that could not be tokenized.
+    pub fn field(&self) -> &str {
+        match self {
+            TokenizationError::TokenTooLong { field, .. }
+            | TokenizationError::TokenizerFailed { field, .. } => field,
+        }
+    }
+}
+
+/// Tokenization problems of the documents of a field, see [`IndexingReport`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldIndexingReport {
+    /// Number of indexed documents with dropped tokens in the field.
+    pub docs_affected: u64,
+    /// Number of tokens dropped for exceeding [`MAX_TOKEN_LEN`].
+    pub tokens_dropped: u64,
+    /// Number of texts whose tokenization was ended early by a tokenizer error.
+    pub tokenizer_errors: u64,
+    /// Opstamps of some of the affected documents, the lowest ones up to
+    /// [`MAX_EXAMPLE_OPSTAMPS`].
+    pub example_opstamps: Vec<Opstamp>,
+    /// Number of documents rejected because of the field, see
+    /// [`TokenizationErrorPolicy`].
+    pub docs_rejected: u64,
+}
+
+/// Tokenization problems of the documents of a commit, per field.
+///
+/// Returned by [`IndexWriter::commit_with_report()`](crate::IndexWriter::commit_with_report) and
+/// [`PreparedCommit::indexing_report()`](crate::indexer::PreparedCommit::indexing_report).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexingReport {
+    fields: BTreeMap<String, FieldIndexingReport>,
+    rejected_documents: Vec<TokenizationError>,
+}
+
+impl IndexingReport {
+    /// Returns true if all of the documents were tokenized entirely.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the problems of a field, if it had any.
+    pub fn field(&self, field_name: &str) -> Option<&FieldIndexingReport> {
+        self.fields.get(field_name)
+    }
+
+    /// Returns the fields with problems, ordered by name.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &FieldIndexingReport)> {
+        self.fields
+            .iter()
+            .map(|(field_name, report)| (field_name.as_str(), report))
+    }
+
+    /// Returns the errors of the documents rejected without failing the call that added them,
+    /// with [`TokenizationErrorPolicy::FailDocument`].
+    pub fn rejected_documents(&self) -> &[TokenizationError] {
+        &self.rejected_documents
+    }
+
+    /// Records that the document `opstamp` was indexed without some of the tokens of a field.
+    pub(crate) fn record_skipped(
+        &mut self,
+        field_name: &str,
+        opstamp: Opstamp,
+        tokens_dropped: u32,
+        tokenizer_errors: u32,
+    ) {
+        if tokens_dropped == 0 && tokenizer_errors == 0 {
+            return;
+        }
+        let report = self.field_mut(field_name);
+        report.docs_affected += 1;
+        report.tokens_dropped += tokens_dropped as u64;
+        report.tokenizer_errors += tokenizer_errors as u64;
+        if report.example_opstamps.len() < MAX_EXAMPLE_OPSTAMPS {
+            report.example_opstamps.push(opstamp);
+        }
+    }
+
+    /// Records that a document was rejected. `listed` documents are kept in
+    /// [`IndexingReport::rejected_documents()`], as their error is not returned to the caller.
+    pub(crate) fn record_rejected(&mut self, error: TokenizationError, listed: bool) {
+        self.field_mut(error.field()).docs_rejected += 1;
+        if listed {
+            self.rejected_documents.push(error);
+        }
+    }
+
+    /// Adds the problems of `other` to this report.
+    pub(crate) fn merge(&mut self, other: IndexingReport) {
+        for (field_name, other_report) in other.fields {
+            let report = self.field_mut(&field_name);
+            report.docs_affected += other_report.docs_affected;
+            report.tokens_dropped += other_report.tokens_dropped;
+            report.tokenizer_errors += other_report.tokenizer_errors;
+            report.docs_rejected += other_report.docs_rejected;
+            report
+                .example_opstamps
+                .extend(other_report.example_opstamps);
+            report.example_opstamps.sort_unstable();
+            report.example_opstamps.truncate(MAX_EXAMPLE_OPSTAMPS);
+        }
+        self.rejected_documents.extend(other.rejected_documents);
+    }
+
+    fn field_mut(&mut self, field_name: &str) -> &mut FieldIndexingReport {
+        if !self.fields.contains_key(field_name) {
+            self.fields
+                .insert(field_name.to_string(), FieldIndexingReport::default());
+        }
+        self.fields.get_mut(field_name).unwrap()
+    }
+}
+
+/// Tokenizes the text of the documents before they are added, to reject them according to the
+/// [`TokenizationErrorPolicy`].
+pub(crate) struct TokenizationChecker {
+    schema: Schema,
+    /// The text analyzer of each tokenized field.
+    text_analyzers: Vec<Option<TextAnalyzer>>,
+}
+
+impl TokenizationChecker {
+    pub fn new(index: &Index) -> crate::Result<Self> {
+        let schema = index.schema();
+        let text_analyzers = schema
+            .fields()
+            .map(|(_, field_entry)| {
+                let text_options = match field_entry.field_type() {
+                    FieldType::Str(text_options) => text_options.get_indexing_options(),
+                    FieldType::JsonObject(json_object_options) => {
+                        json_object_options.get_text_indexing_options()
+                    }
+                    _ => None,
+                };
+                let Some(text_options) = text_options else {
+                    return Ok(None);
+                };
+                index
+                    .tokenizers()
+                    .get(text_options.tokenizer())
+                    .map(Some)
+                    .ok_or_else(|| {
+                        TantivyError::SchemaError(format!(
+                            "Error getting tokenizer for field: {}",
+                            field_entry.name()
+                        ))
+                    })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Self {
+            schema,
+            text_analyzers,
+        })
+    }
+
+    /// Returns the first tokenization error of the document, if any.
+    pub fn check<D: Document>(&self, document: &D) -> Result<(), TokenizationError> {
+        for (field, value) in document.iter_fields_and_values() {
+            let Some(text_analyzer) = &self.text_analyzers[field.field_id() as usize] else {
+                continue;
+            };
+            let field_name = self.schema.get_field_name(field);
+            check_value(field_name, &mut text_analyzer.clone(), value)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_value<'a, V: Value<'a>>(
+    field_name: &str,
+    text_analyzer: &mut TextAnalyzer,
+    value: V,
+) -> Result<(), TokenizationError> {
+    match value.as_value() {
+        ReferenceValue::Leaf(ReferenceValueLeaf::Str(text)) => {
+            check_token_stream(field_name, &mut text_analyzer.token_stream(text))
+        }
+        ReferenceValue::Leaf(ReferenceValueLeaf::PreTokStr(pre_tokenized_text)) => {
+            check_token_stream(
+                field_name,
+                &mut PreTokenizedStream::from(*pre_tokenized_text),
+            )
+        }
+        ReferenceValue::Leaf(_) => Ok(()),
+        ReferenceValue::Array(elements) => elements
+            .into_iter()
+            .try_for_each(|element| check_value(field_name, text_analyzer, element)),
+        ReferenceValue::Object(entries) => entries
+            .into_iter()
+            .try_for_each(|(_, entry)| check_value(field_name, text_analyzer, entry)),
+    }
+}
+
+fn check_token_stream(
+    field_name: &str,
+    token_stream: &mut dyn TokenStream,
+) -> Result<(), TokenizationError> {
+    while let Some(token) = token_stream.next() {
+        if token.text.len() > MAX_TOKEN_LEN {
+            return Err(TokenizationError::TokenTooLong {
+                field: field_name.to_string(),
+                len: token.text.len(),
+            });
+        }
+    }
+    if let Some(reason) = token_stream.error() {
+        return Err(TokenizationError::TokenizerFailed {
+            field: field_name.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenizationError, TokenizationErrorPolicy};
+    use crate::indexer::{IndexWriterOptions, UserOperation};
+    use crate::schema::{Field, Schema, TextFieldIndexing, TextOptions, STRING, TEXT};
+    use crate::tokenizer::{TextAnalyzer, Token, TokenStream, Tokenizer, MAX_TOKEN_LEN};
+    use crate::{Index, IndexWriter, TantivyDocument, TantivyError};
+
+    /// Splits the text on whitespaces, and fails on the word "fail".
+    #[derive(Clone)]
+    struct FailingTokenizer;
+
+    struct FailingTokenStream<'a> {
+        words: std::str::SplitWhitespace<'a>,
+        token: Token,
+        error: Option<&'static str>,
+    }
+
+    impl Tokenizer for FailingTokenizer {
+        type TokenStream<'a> = FailingTokenStream<'a>;
+
+        fn token_stream<'a>(&'a mut self, text: &'a str) -> FailingTokenStream<'a> {
+            FailingTokenStream {
+                words: text.split_whitespace(),
+                token: Token::default(),
+                error: None,
+            }
+        }
+    }
+
+    impl TokenStream for FailingTokenStream<'_> {
+        fn advance(&mut self) -> bool {
+            match self.words.next() {
+                Some("fail") => {
+                    self.error = Some("cannot tokenize \"fail\"");
+                    false
+                }
+                Some(word) => {
+                    self.token.text = word.to_string();
+                    self.token.position = self.token.position.wrapping_add(1);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        fn token(&self) -> &Token {
+            &self.token
+        }
+
+        fn token_mut(&mut self) -> &mut Token {
+            &mut self.token
+        }
+
+        fn error(&self) -> Option<&str> {
+            self.error
+        }
+    }
+
+    struct TestIndex {
+        index: Index,
+        body: Field,
+        raw: Field,
+        failing: Field,
+    }
+
+    impl TestIndex {
+        fn new() -> Self {
+            let mut schema_builder = Schema::builder();
+            let body = schema_builder.add_text_field("body", TEXT);
+            let raw = schema_builder.add_text_field("raw", STRING);
+            let failing_options = TextOptions::default()
+                .set_indexing_options(TextFieldIndexing::default().set_tokenizer("failing"));
+            let failing = schema_builder.add_text_field("failing", failing_options);
+            let index = Index::create_in_ram(schema_builder.build());
+            index
+                .tokenizers()
+                .register("failing", TextAnalyzer::from(FailingTokenizer));
+            TestIndex {
+                index,
+                body,
+                raw,
+                failing,
+            }
+        }
+
+        fn writer(&self, policy: TokenizationErrorPolicy) -> crate::Result<IndexWriter> {
+            let options = IndexWriterOptions::builder()
+                .tokenization_error_policy(policy)
+                .build();
+            self.index.writer_with_options(options)
+        }
+
+        fn valid_doc(&self) -> TantivyDocument {
+            doc!(self.body => "hello world", self.raw => "key", self.failing => "a b")
+        }
+
+        fn oversized_doc(&self) -> TantivyDocument {
+            doc!(self.body => "hello", self.raw => "A".repeat(MAX_TOKEN_LEN + 1))
+        }
+
+        fn failing_doc(&self) -> TantivyDocument {
+            doc!(self.body => "hello", self.failing => "a fail b")
+        }
+
+        fn num_docs(&self) -> crate::Result<u64> {
+            Ok(self.index.reader()?.searcher().num_docs())
+        }
+    }
+
+    #[test]
+    fn test_skip_policy_reports_dropped_tokens() -> crate::Result<()> {
+        let test_index = TestIndex::new();
+        let mut index_writer = test_index.writer(TokenizationErrorPolicy::Skip)?;
+        index_writer.add_document(test_index.valid_doc())?;
+        let oversized_opstamp = index_writer.add_document(test_index.oversized_doc())?;
+        let failing_opstamp = index_writer.add_document(test_index.failing_doc())?;
+        index_writer.run([
+            UserOperation::Add(test_index.oversized_doc()),
+            UserOperation::Add(test_index.valid_doc()),
+        ])?;
+        let (_, report) = index_writer.commit_with_report()?;
+        assert_eq!(test_index.num_docs()?, 5);
+
+        assert!(report.field("body").is_none());
+        let raw_report = report.field("raw").unwrap();
+        assert_eq!(raw_report.docs_affected, 2);
+        assert_eq!(raw_report.tokens_dropped, 2);
+        assert_eq!(raw_report.tokenizer_errors, 0);
+        assert_eq!(raw_report.docs_rejected, 0);
+        assert_eq!(raw_report.example_opstamps.len(), 2);
+        assert_eq!(raw_report.example_opstamps[0], oversized_opstamp);
+        let failing_report = report.field("failing").unwrap();
+        assert_eq!(failing_report.docs_affected, 1);
+        assert_eq!(failing_report.tokens_dropped, 0);
+        assert_eq!(failing_report.tokenizer_errors, 1);
+        assert_eq!(failing_report.example_opstamps, vec![failing_opstamp]);
+        assert!(report.rejected_documents().is_empty());
+
+        // The report only covers the documents of the commit.
+        index_writer.add_document(test_index.valid_doc())?;
+        let (_, report) = index_writer.commit_with_report()?;
+        assert!(report.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_document_policy_rejects_documents() -> crate::Result<()> {
+        let test_index = TestIndex::new();
+        let mut index_writer = test_index.writer(TokenizationErrorPolicy::FailDocument)?;
+        index_writer.add_document(test_index.valid_doc())?;
+        let err = index_writer
+            .add_document(test_index.oversized_doc())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::Tokenization(TokenizationError::TokenTooLong { ref field, len })
+                if field == "raw" && len == MAX_TOKEN_LEN + 1
+        ));
+        assert_eq!(err.error_code(), "tokenization_error");
+        let err = index_writer
+            .add_document(test_index.failing_doc())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::Tokenization(TokenizationError::TokenizerFailed { ref field, .. })
+                if field == "failing"
+        ));
+        // Only the failing document of the batch is rejected.
+        index_writer.run([
+            UserOperation::Add(test_index.valid_doc()),
+            UserOperation::Add(test_index.failing_doc()),
+            UserOperation::Add(test_index.valid_doc()),
+        ])?;
+        let prepared_commit = index_writer.prepare_commit()?;
+        let report = prepared_commit.indexing_report().clone();
+        prepared_commit.commit()?;
+        assert_eq!(test_index.num_docs()?, 3);
+
+        assert_eq!(report.field("raw").unwrap().docs_rejected, 1);
+        assert_eq!(report.field("raw").unwrap().docs_affected, 0);
+        assert_eq!(report.field("failing").unwrap().docs_rejected, 2);
+        assert_eq!(report.field("failing").unwrap().tokenizer_errors, 0);
+        assert!(matches!(
+            report.rejected_documents(),
+            [TokenizationError::TokenizerFailed { field, .. }] if field == "failing"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_batch_policy_aborts_batches() -> crate::Result<()> {
+        let test_index = TestIndex::new();
+        let mut index_writer = test_index.writer(TokenizationErrorPolicy::FailBatch)?;
+        let err = index_writer
+            .run([
+                UserOperation::Add(test_index.valid_doc()),
+                UserOperation::Add(test_index.oversized_doc()),
+                UserOperation::Add(test_index.failing_doc()),
+            ])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::Tokenization(TokenizationError::TokenTooLong { .. })
+        ));
+        assert!(matches!(
+            index_writer.add_documents(vec![test_index.valid_doc(), test_index.failing_doc()]),
+            Err(TantivyError::Tokenization(
+                TokenizationError::TokenizerFailed { .. }
+            ))
+        ));
+        index_writer.run([
+            UserOperation::Add(test_index.valid_doc()),
+            UserOperation::Add(test_index.valid_doc()),
+        ])?;
+        let (_, report) = index_writer.commit_with_report()?;
+        assert_eq!(test_index.num_docs()?, 2);
+
+        // The first failing document of each batch is counted.
+        assert_eq!(report.field("raw").unwrap().docs_rejected, 1);
+        assert_eq!(report.field("failing").unwrap().docs_rejected, 1);
+        assert!(report.rejected_documents().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::index_writer::MARGIN_IN_BYTES;
+
+/// Number of documents of the estimated size that the adaptive flush threshold keeps room for.
+const ADAPTIVE_HEADROOM_NUM_DOCS: u64 = 1_000;
+
+/// Bounds of the adaptive flush threshold of the indexing threads.
+///
+/// By default, an indexing thread flushes its segment when its memory usage gets within
+/// `MARGIN_IN_BYTES` of the memory budget. With an adaptive flush threshold, the
+/// [`IndexWriter`](crate::IndexWriter) instead keeps a rolling estimate of the memory used per
+/// document, and flushes early enough to keep room for 1000 documents of that size. The
+/// threshold is clamped between `min_flush_threshold` and `max_flush_threshold`.
+///
+/// Small documents get a threshold close to `max_flush_threshold`, while large documents get
+/// flushed earlier, before they exceed the memory budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveFlushThreshold {
+    min_flush_threshold: usize,
+    max_flush_threshold: usize,
+}
+
+impl AdaptiveFlushThreshold {
+    /// Creates a new adaptive flush threshold, with bounds in bytes.
+    ///
+    /// The bounds are validated when the [`IndexWriter`](crate::IndexWriter) is created:
+    /// `max_flush_threshold` cannot exceed the memory budget per thread minus `MARGIN_IN_BYTES`.
+    pub fn new(min_flush_threshold: usize, max_flush_threshold: usize) -> Self {
+        AdaptiveFlushThreshold {
+            min_flush_threshold,
+            max_flush_threshold,
+        }
+    }
+
+    /// The lowest threshold the indexing threads flush at.
+    pub fn min_flush_threshold(&self) -> usize {
+        self.min_flush_threshold
+    }
+
+    /// The highest threshold the indexing threads flush at.
+    pub fn max_flush_threshold(&self) -> usize {
+        self.max_flush_threshold
+    }
+}
+
+/// Memory usage of the indexing threads of an [`IndexWriter`](crate::IndexWriter).
+///
+/// See [`IndexWriter::memory_usage_report`](crate::IndexWriter::memory_usage_report).
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryUsageReport {
+    /// One entry per running indexing thread.
+    pub threads: Vec<IndexingThreadMemoryUsage>,
+    /// Rolling estimate of the memory used per document, in bytes, if a segment has been
+    /// flushed already.
+    pub bytes_per_doc_estimate: Option<u64>,
+    /// Number of segments flushed by the indexing threads since the creation of the writer.
+    pub num_flushes: u64,
+    /// Number of documents in the segments flushed by the indexing threads since the creation
+    /// of the writer.
+    pub num_docs_flushed: u64,
+}
+
+/// Memory usage of an indexing thread.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexingThreadMemoryUsage {
+    /// Identifies the indexing thread.
+    pub worker_id: usize,
+    /// Memory used by the segment being written, in bytes.
+    pub mem_usage: usize,
+    /// Number of documents in the segment being written.
+    pub num_docs_buffered: u32,
+    /// Memory usage at which the segment being written will be flushed, in bytes.
+    pub flush_threshold: usize,
+}
+
+#[derive(Default)]
+struct ThreadStats {
+    mem_usage: AtomicUsize,
+    num_docs_buffered: AtomicU32,
+    flush_threshold: AtomicUsize,
+}
+
+/// Shared between the `IndexWriter` and its indexing threads, to track their memory usage and
+/// compute their flush threshold.
+pub(crate) struct MemoryUsageTracker {
+    memory_budget: usize,
+    adaptive_flush_threshold: Option<AdaptiveFlushThreshold>,
+    /// Rolling estimate of bytes per document, 0 if no segment was flushed yet.
+    bytes_per_doc_estimate: AtomicU64,
+    num_flushes: AtomicU64,
+    num_docs_flushed: AtomicU64,
+    threads: Mutex<Vec<(usize, Arc<ThreadStats>)>>,
+}
+
+impl MemoryUsageTracker {
+    pub fn new(
+        memory_budget: usize,
+        adaptive_flush_threshold: Option<AdaptiveFlushThreshold>,
+    ) -> Self {
+        MemoryUsageTracker {
+            memory_budget,
+            adaptive_flush_threshold,
+            bytes_per_doc_estimate: AtomicU64::new(0),
+            num_flushes: AtomicU64::new(0),
+            num_docs_flushed: AtomicU64::new(0),
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers an indexing thread. The thread is unregistered when the returned handle is
+    /// dropped.
+    pub fn register_thread(self: &Arc<Self>, worker_id: usize) -> ThreadMemoryUsage {
+        let stats = Arc::new(ThreadStats::default());
+        stats
+            .flush_threshold
+            .store(self.flush_threshold(0), Ordering::Relaxed);
+        self.threads
+            .lock()
+            .unwrap()
+            .push((worker_id, Arc::clone(&stats)));
+        ThreadMemoryUsage {
+            tracker: Arc::clone(self),
+            worker_id,
+            stats,
+            empty_mem_usage: 0,
+        }
+    }
+
+    /// Computes the flush threshold, given the number of bytes per doc observed in the segment
+    /// being written.
+    fn flush_threshold(&self, segment_bytes_per_doc: u64) -> usize {
+        let Some(adaptive_flush_threshold) = self.adaptive_flush_threshold else {
+            return self.memory_budget - MARGIN_IN_BYTES;
+        };
+        // Large documents in the current segment are taken into account right away, while the
+        // estimate is only updated when a segment is flushed.
+        let bytes_per_doc = self
+            .bytes_per_doc_estimate
+            .load(Ordering::Relaxed)
+            .max(segment_bytes_per_doc);
+        let headroom = bytes_per_doc.saturating_mul(ADAPTIVE_HEADROOM_NUM_DOCS);
+        let threshold = (self.memory_budget as u64).saturating_sub(headroom) as usize;
+        threshold.clamp(
+            adaptive_flush_threshold.min_flush_threshold,
+            adaptive_flush_threshold.max_flush_threshold,
+        )
+    }
+
+    fn record_flush(&self, num_docs: u32, bytes_per_doc: u64) {
+        self.num_flushes.fetch_add(1, Ordering::Relaxed);
+        self.num_docs_flushed
+            .fetch_add(num_docs as u64, Ordering::Relaxed);
+        // Exponential moving average, giving the same weight to the last segment and to the
+        // previous ones.
+        let _ = self.bytes_per_doc_estimate.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |estimate| {
+                if estimate == 0 {
+                    Some(bytes_per_doc.max(1))
+                } else {
+                    Some(((estimate + bytes_per_doc) / 2).max(1))
+                }
+            },
+        );
+    }
+
+    pub fn report(&self) -> MemoryUsageReport {
+        let threads = self
+            .threads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(worker_id, stats)| IndexingThreadMemoryUsage {
+                worker_id: *worker_id,
+                mem_usage: stats.mem_usage.load(Ordering::Relaxed),
+                num_docs_buffered: stats.num_docs_buffered.load(Ordering::Relaxed),
+                flush_threshold: stats.flush_threshold.load(Ordering::Relaxed),
+            })
+            .collect();
+        let bytes_per_doc_estimate = self.bytes_per_doc_estimate.load(Ordering::Relaxed);
+        MemoryUsageReport {
+            threads,
+            bytes_per_doc_estimate: (bytes_per_doc_estimate != 0).then_some(bytes_per_doc_estimate),
+            num_flushes: self.num_flushes.load(Ordering::Relaxed),
+            num_docs_flushed: self.num_docs_flushed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Handle used by an indexing thread to report its memory usage.
+pub(crate) struct ThreadMemoryUsage {
+    tracker: Arc<MemoryUsageTracker>,
+    worker_id: usize,
+    stats: Arc<ThreadStats>,
+    /// Memory usage of the segment writer before any document was added.
+    empty_mem_usage: usize,
+}
+
+impl ThreadMemoryUsage {
+    /// Called when a new segment writer is created.
+    pub fn start_segment(&mut self, empty_mem_usage: usize) {
+        self.empty_mem_usage = empty_mem_usage;
+        self.update(empty_mem_usage, 0);
+    }
+
+    /// Updates the memory usage of the segment being written, and returns its flush threshold.
+    pub fn update(&self, mem_usage: usize, num_docs: u32) -> usize {
+        let flush_threshold = self
+            .tracker
+            .flush_threshold(self.bytes_per_doc(mem_usage, num_docs));
+        self.stats.mem_usage.store(mem_usage, Ordering::Relaxed);
+        self.stats
+            .num_docs_buffered
+            .store(num_docs, Ordering::Relaxed);
+        self.stats
+            .flush_threshold
+            .store(flush_threshold, Ordering::Relaxed);
+        flush_threshold
+    }
+
+    /// Called when the segment is flushed.
+    pub fn record_flush(&self, mem_usage: usize, num_docs: u32) {
+        self.tracker
+            .record_flush(num_docs, self.bytes_per_doc(mem_usage, num_docs));
+        self.stats.mem_usage.store(0, Ordering::Relaxed);
+        self.stats.num_docs_buffered.store(0, Ordering::Relaxed);
+        self.stats
+            .flush_threshold
+            .store(self.tracker.flush_threshold(0), Ordering::Relaxed);
+    }
+
+    fn bytes_per_doc(&self, mem_usage: usize, num_docs: u32) -> u64 {
+        if num_docs == 0 {
+            return 0;
+        }
+        (mem_usage.saturating_sub(self.empty_mem_usage) / num_docs as usize) as u64
+    }
+}
+
+impl Drop for ThreadMemoryUsage {
+    fn drop(&mut self) {
+        self.tracker
+            .threads
+            .lock()
+            .unwrap()
+            .retain(|(worker_id, _)| *worker_id != self.worker_id);
+    }
+}
@@ -70,7 +70,7 @@ mod tests {
                 doc!(int_field=>1_000u64, multi_numbers => 1001_u64, multi_numbers => 1002_u64, bytes_field => vec![5, 5],text_field => "the biggest num")
             )?;
 
-            index_writer.delete_term(Term::from_field_text(text_field, "deleteme"));
+            index_writer.delete_term(Term::from_field_text(text_field, "deleteme"))?;
             index_writer.commit()?;
         }
 
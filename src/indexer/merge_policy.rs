@@ -1,12 +1,96 @@
-use std::fmt::Debug;
+use std::any::Any;
+use std::fmt::{self, Debug};
+use std::future::Future;
 use std::marker;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::index::{SegmentId, SegmentMeta};
 
+/// Default time the segment updater waits for [`MergePolicy::compute_merge_candidates_ctx()`]
+/// before giving up on the merges it suggests.
+pub const DEFAULT_MERGE_POLICY_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Set of segment suggested for a merge.
 #[derive(Debug, Clone)]
 pub struct MergeCandidate(pub Vec<SegmentId>);
 
+/// Future resolving to the merge candidates suggested by a merge policy.
+pub type MergeCandidatesFuture = Pin<Box<dyn Future<Output = Vec<MergeCandidate>> + Send>>;
+
+/// Information about the index given to [`MergePolicy::compute_merge_candidates_ctx()`].
+#[derive(Clone)]
+pub struct MergeContext {
+    segments: Vec<SegmentMeta>,
+    num_deleted_docs_with_pending: Vec<u32>,
+    total_num_bytes: u64,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+impl MergeContext {
+    pub(crate) fn new(
+        segments: Vec<SegmentMeta>,
+        num_deleted_docs_with_pending: Vec<u32>,
+        total_num_bytes: u64,
+        user_context: Option<Arc<dyn Any + Send + Sync>>,
+    ) -> MergeContext {
+        assert_eq!(segments.len(), num_deleted_docs_with_pending.len());
+        MergeContext {
+            segments,
+            num_deleted_docs_with_pending,
+            total_num_bytes,
+            user_context,
+        }
+    }
+
+    /// The metas of the segments that can be merged.
+    ///
+    /// Committed and uncommitted segments cannot be merged together, so they are given to the
+    /// merge policy in two separate contexts.
+    pub fn segments(&self) -> &[SegmentMeta] {
+        &self.segments
+    }
+
+    /// The number of deleted documents of each segment, in the order of
+    /// [`MergeContext::segments()`].
+    ///
+    /// Contrary to [`SegmentMeta::num_deleted_docs()`], this includes the deletes that have been
+    /// applied to the segment but are not committed yet.
+    pub fn num_deleted_docs_with_pending(&self) -> &[u32] {
+        &self.num_deleted_docs_with_pending
+    }
+
+    /// The number of bytes of all of the segments of the index, on disk.
+    ///
+    /// This includes the committed and uncommitted segments, as well as the segments being
+    /// merged.
+    pub fn total_num_bytes(&self) -> u64 {
+        self.total_num_bytes
+    }
+
+    /// The context set with
+    /// [`IndexWriter::set_merge_policy_context()`](crate::IndexWriter::set_merge_policy_context),
+    /// if it is of type `T`.
+    pub fn user_context<T: Any>(&self) -> Option<&T> {
+        self.user_context.as_ref()?.downcast_ref::<T>()
+    }
+}
+
+impl Debug for MergeContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MergeContext")
+            .field("segments", &self.segments)
+            .field(
+                "num_deleted_docs_with_pending",
+                &self.num_deleted_docs_with_pending,
+            )
+            .field("total_num_bytes", &self.total_num_bytes)
+            .field("has_user_context", &self.user_context.is_some())
+            .finish()
+    }
+}
+
 /// The `MergePolicy` defines which segments should be merged.
 ///
 /// Every time the list of segments changes, the segment updater
@@ -17,6 +101,23 @@ pub trait MergePolicy: marker::Send + marker::Sync + Debug {
     /// This call happens on the segment updater thread, and will block
     /// other segment updates, so all implementations should happen rapidly.
     fn compute_merge_candidates(&self, segments: &[SegmentMeta]) -> Vec<MergeCandidate>;
+
+    /// Given the context of the index, returns a future resolving to the list of merge
+    /// candidates.
+    ///
+    /// This is the method called by the segment updater. By default, it resolves immediately to
+    /// the result of [`MergePolicy::compute_merge_candidates()`].
+    ///
+    /// Policies that need to wait, e.g. for an external service, can return a pending future.
+    /// It is then driven outside of the segment updater thread, so that it does not block
+    /// commits. If it does not resolve within the merge policy timeout of the writer, no merge
+    /// is started. The candidates whose segments were merged, removed, or committed in the
+    /// meantime are discarded.
+    fn compute_merge_candidates_ctx(&self, ctx: MergeContext) -> MergeCandidatesFuture {
+        Box::pin(std::future::ready(
+            self.compute_merge_candidates(ctx.segments()),
+        ))
+    }
 }
 
 /// Never merge segments.
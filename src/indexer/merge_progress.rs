@@ -0,0 +1,152 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::index::SegmentMeta;
+use crate::{FutureResult, TantivyError};
+
+/// Callback receiving the progress of a merge.
+pub type MergeProgressCallback = Box<dyn Fn(MergeProgress) + Send + Sync>;
+
+/// Phase of a merge. The phases are run in the order of declaration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum MergePhase {
+    /// Merging the fieldnorms, field by field.
+    FieldNorms,
+    /// Merging the postings, field by field.
+    Postings,
+    /// Merging the doc store.
+    Store,
+    /// Merging the fast fields.
+    FastFields,
+}
+
+/// Progress of a merge, reported by [`IndexWriter::merge_with_progress()`].
+///
+/// The progress is reported after each field of the phases merging the fields one by one, in
+/// which case the number of processed documents is prorated on the number of processed fields,
+/// and regularly while merging the doc store. Within a phase, the number of processed documents
+/// never decreases.
+///
+/// [`IndexWriter::merge_with_progress()`]: crate::IndexWriter::merge_with_progress
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MergeProgress {
+    /// Phase the merge is in.
+    pub phase: MergePhase,
+    /// Number of documents of the merged segment processed by the phase.
+    pub num_docs_processed: u32,
+    /// Number of documents of the merged segment, i.e. the alive documents of the merged
+    /// segments.
+    pub num_docs_total: u32,
+}
+
+/// Reports the progress of a merge, and tells whether the merge was cancelled.
+#[derive(Default)]
+pub(crate) struct MergeMonitor {
+    on_progress: Option<MergeProgressCallback>,
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl MergeMonitor {
+    pub fn new(on_progress: MergeProgressCallback) -> MergeMonitor {
+        MergeMonitor {
+            on_progress: Some(on_progress),
+            is_cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the flag cancelling the merge once set.
+    pub fn cancellation_flag(&self) -> Arc<AtomicBool> {
+        self.is_cancelled.clone()
+    }
+
+    /// Returns an error if the merge was cancelled.
+    pub fn check_cancelled(&self) -> crate::Result<()> {
+        if self.is_cancelled.load(Ordering::Relaxed) {
+            return Err(TantivyError::MergeCancelled);
+        }
+        Ok(())
+    }
+
+    /// Reports the progress of the merge, or returns an error if the merge was cancelled.
+    pub fn report(
+        &self,
+        phase: MergePhase,
+        num_docs_processed: u32,
+        num_docs_total: u32,
+    ) -> crate::Result<()> {
+        self.check_cancelled()?;
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(MergeProgress {
+                phase,
+                num_docs_processed,
+                num_docs_total,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Handle on a merge started with [`IndexWriter::merge_with_progress()`].
+///
+/// It resolves to the meta of the merged segment, like the result of
+/// [`IndexWriter::merge()`], either as a future or by calling [`MergeHandle::wait()`].
+///
+/// Contrary to [`FutureResult`], dropping the handle cancels the merge. A cancelled merge stops
+/// at the next progress report, deletes the files it wrote, and leaves the merged segments
+/// untouched. A merge that is already done writing the merged segment is not cancelled.
+///
+/// [`IndexWriter::merge()`]: crate::IndexWriter::merge
+/// [`IndexWriter::merge_with_progress()`]: crate::IndexWriter::merge_with_progress
+pub struct MergeHandle {
+    result: FutureResult<Option<SegmentMeta>>,
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl MergeHandle {
+    pub(crate) fn new(
+        result: FutureResult<Option<SegmentMeta>>,
+        is_cancelled: Arc<AtomicBool>,
+    ) -> MergeHandle {
+        MergeHandle {
+            result,
+            is_cancelled,
+        }
+    }
+
+    /// Cancels the merge. Waiting for the merge then returns
+    /// [`TantivyError::MergeCancelled`], unless the merge was already done writing the merged
+    /// segment.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Blocks until the merge is done, and returns the meta of the merged segment, or `None`
+    /// if all the merged documents were deleted.
+    ///
+    /// The meta accounts for the deletes applied to the merged segment when it replaced the
+    /// merged segments.
+    pub fn wait(mut self) -> crate::Result<Option<SegmentMeta>> {
+        let result = std::mem::replace(
+            &mut self.result,
+            FutureResult::from(TantivyError::MergeCancelled),
+        );
+        result.wait()
+    }
+}
+
+impl Future for MergeHandle {
+    type Output = crate::Result<Option<SegmentMeta>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.result).poll(cx)
+    }
+}
+
+impl Drop for MergeHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -3,8 +3,7 @@ use std::sync::Arc;
 use columnar::{
     ColumnType, ColumnarReader, MergeRowOrder, RowAddr, ShuffleMergeOrder, StackMergeOrder,
 };
-use common::ReadOnlyBitSet;
-use itertools::Itertools;
+use common::{BitSet, ReadOnlyBitSet};
 use measure_time::debug_time;
 
 use crate::directory::WritePtr;
@@ -14,10 +13,10 @@ use crate::fastfield::AliveBitSet;
 use crate::fieldnorm::{FieldNormReader, FieldNormReaders, FieldNormsSerializer, FieldNormsWriter};
 use crate::index::{Segment, SegmentComponent, SegmentReader};
 use crate::indexer::doc_id_mapping::{MappingType, SegmentDocIdMapping};
-use crate::indexer::SegmentSerializer;
+use crate::indexer::{MergeMonitor, MergePhase, SegmentSerializer};
 use crate::postings::{InvertedIndexSerializer, Postings, SegmentPostings};
-use crate::schema::{value_type_to_column_type, Field, FieldType, Schema};
-use crate::store::StoreWriter;
+use crate::schema::{value_type_to_column_type, Field, FieldType, Schema, TantivyDocument};
+use crate::store::{StoreWriter, DOC_STORE_VERSION};
 use crate::termdict::{TermMerger, TermOrdinal};
 use crate::{DocAddress, DocId, InvertedIndexReader};
 
@@ -26,6 +25,9 @@ use crate::{DocAddress, DocId, InvertedIndexReader};
 /// We do not allow segments with more than
 pub const MAX_DOC_LIMIT: u32 = 1 << 31;
 
+/// Number of documents written to the doc store between two progress reports.
+const STORE_PROGRESS_NUM_DOCS: u32 = 1_024;
+
 fn estimate_total_num_tokens_in_single_segment(
     reader: &SegmentReader,
     field: Field,
@@ -39,7 +41,7 @@ fn estimate_total_num_tokens_in_single_segment(
 
     // When there are deletes, we use an approximation either
     // by using the fieldnorm.
-    if let Some(fieldnorm_reader) = reader.fieldnorms_readers().get_field(field)? {
+    if let Some(fieldnorm_reader) = reader.try_fieldnorms_readers()?.get_field(field)? {
         let mut count: [usize; 256] = [0; 256];
         for doc in reader.doc_ids_alive() {
             let fieldnorm_id = fieldnorm_reader.fieldnorm_id(doc);
@@ -80,6 +82,7 @@ pub struct IndexMerger {
     schema: Schema,
     pub(crate) readers: Vec<SegmentReader>,
     max_doc: u32,
+    monitor: MergeMonitor,
 }
 
 struct DeltaComputer {
@@ -145,11 +148,6 @@ fn extract_fast_field_required_columns(schema: &Schema) -> Vec<(String, ColumnTy
 }
 
 impl IndexMerger {
-    pub fn open(schema: Schema, segments: &[Segment]) -> crate::Result<IndexMerger> {
-        let alive_bitset = segments.iter().map(|_| None).collect_vec();
-        Self::open_with_custom_alive_set(schema, segments, alive_bitset)
-    }
-
     // Create merge with a custom delete set.
     // For every Segment, a delete bitset can be provided, which
     // will be merged with the existing bit set. Make sure the index
@@ -189,9 +187,28 @@ impl IndexMerger {
             schema,
             readers,
             max_doc,
+            monitor: MergeMonitor::default(),
         })
     }
 
+    /// Sets the monitor receiving the progress of the merge, and cancelling it.
+    pub(crate) fn set_monitor(&mut self, monitor: MergeMonitor) {
+        self.monitor = monitor;
+    }
+
+    /// Reports the progress of a phase processing `num_fields` fields one by one, once
+    /// `num_fields_processed` of them are processed.
+    fn report_fields_progress(
+        &self,
+        phase: MergePhase,
+        num_fields_processed: usize,
+        num_fields: usize,
+    ) -> crate::Result<()> {
+        let num_docs_processed =
+            (self.max_doc as u64 * num_fields_processed as u64 / num_fields.max(1) as u64) as u32;
+        self.monitor.report(phase, num_docs_processed, self.max_doc)
+    }
+
     fn write_fieldnorms(
         &self,
         mut fieldnorms_serializer: FieldNormsSerializer,
@@ -199,19 +216,28 @@ impl IndexMerger {
     ) -> crate::Result<()> {
         let fields = FieldNormsWriter::fields_with_fieldnorm(&self.schema);
         let mut fieldnorms_data = Vec::with_capacity(self.max_doc as usize);
-        for field in fields {
+        self.report_fields_progress(MergePhase::FieldNorms, 0, fields.len())?;
+        for (field_ord, &field) in fields.iter().enumerate() {
             fieldnorms_data.clear();
             let fieldnorms_readers: Vec<FieldNormReader> = self
                 .readers
                 .iter()
-                .map(|reader| reader.get_fieldnorms_reader(field))
-                .collect::<Result<_, _>>()?;
+                .map(|reader| {
+                    // The segments written before the field was added to the schema have no
+                    // fieldnorms for it.
+                    Ok(reader
+                        .try_fieldnorms_readers()?
+                        .get_field(field)?
+                        .unwrap_or_else(|| FieldNormReader::constant(reader.max_doc(), 0)))
+                })
+                .collect::<crate::Result<_>>()?;
             for old_doc_addr in doc_id_mapping.iter_old_doc_addrs() {
                 let fieldnorms_reader = &fieldnorms_readers[old_doc_addr.segment_ord as usize];
                 let fieldnorm_id = fieldnorms_reader.fieldnorm_id(old_doc_addr.doc_id);
                 fieldnorms_data.push(fieldnorm_id);
             }
             fieldnorms_serializer.serialize_field(field, &fieldnorms_data[..])?;
+            self.report_fields_progress(MergePhase::FieldNorms, field_ord + 1, fields.len())?;
         }
         fieldnorms_serializer.close()?;
         Ok(())
@@ -223,12 +249,14 @@ impl IndexMerger {
         doc_id_mapping: SegmentDocIdMapping,
     ) -> crate::Result<()> {
         debug_time!("write-fast-fields");
+        self.monitor
+            .report(MergePhase::FastFields, 0, self.max_doc)?;
         let required_columns = extract_fast_field_required_columns(&self.schema);
         let columnars: Vec<&ColumnarReader> = self
             .readers
             .iter()
-            .map(|reader| reader.fast_fields().columnar())
-            .collect();
+            .map(|reader| Ok(reader.try_fast_fields()?.columnar()))
+            .collect::<crate::Result<_>>()?;
         let merge_row_order = convert_to_merge_order(&columnars[..], doc_id_mapping);
         columnar::merge_columnar(
             &columnars[..],
@@ -236,9 +264,47 @@ impl IndexMerger {
             merge_row_order,
             fast_field_wrt,
         )?;
+        self.monitor
+            .report(MergePhase::FastFields, self.max_doc, self.max_doc)?;
         Ok(())
     }
 
+    /// Returns the documents of the merged segment that are not soft-deleted, with the tombstone
+    /// fields of the soft deletes, if some of the merged documents are soft-deleted.
+    ///
+    /// The merged segment stacks the alive documents of the segments, in order.
+    pub(crate) fn merged_soft_deleted_docs(&self) -> Option<(BitSet, Vec<String>)> {
+        if self
+            .readers
+            .iter()
+            .all(|reader| reader.num_soft_deleted_docs() == 0)
+        {
+            return None;
+        }
+        let mut not_soft_deleted_bitset = BitSet::with_max_value_and_full(self.max_doc);
+        let mut tombstone_fields: Vec<String> = Vec::new();
+        let mut new_doc_id: DocId = 0;
+        for reader in &self.readers {
+            let mut has_soft_deleted_docs = false;
+            for old_doc_id in reader.doc_ids_alive() {
+                if reader.is_soft_deleted(old_doc_id) {
+                    not_soft_deleted_bitset.remove(new_doc_id);
+                    has_soft_deleted_docs = true;
+                }
+                new_doc_id += 1;
+            }
+            if has_soft_deleted_docs {
+                tombstone_fields.extend_from_slice(reader.soft_delete_tombstone_fields());
+            }
+        }
+        if not_soft_deleted_bitset.len() == self.max_doc as usize {
+            return None;
+        }
+        tombstone_fields.sort();
+        tombstone_fields.dedup();
+        Some((not_soft_deleted_bitset, tombstone_fields))
+    }
+
     /// Creates a mapping if the segments are stacked. this is helpful to merge codelines between
     /// index sorting and the others
     pub(crate) fn get_doc_id_from_concatenated_data(&self) -> crate::Result<SegmentDocIdMapping> {
@@ -471,6 +537,13 @@ impl IndexMerger {
         fieldnorm_readers: FieldNormReaders,
         doc_id_mapping: &SegmentDocIdMapping,
     ) -> crate::Result<()> {
+        let num_indexed_fields = self
+            .schema
+            .fields()
+            .filter(|(_, field_entry)| field_entry.is_indexed())
+            .count();
+        let mut num_fields_processed = 0;
+        self.report_fields_progress(MergePhase::Postings, 0, num_indexed_fields)?;
         for (field, field_entry) in self.schema.fields() {
             let fieldnorm_reader = fieldnorm_readers.get_field(field)?;
             if field_entry.is_indexed() {
@@ -481,6 +554,12 @@ impl IndexMerger {
                     fieldnorm_reader,
                     doc_id_mapping,
                 )?;
+                num_fields_processed += 1;
+                self.report_fields_progress(
+                    MergePhase::Postings,
+                    num_fields_processed,
+                    num_indexed_fields,
+                )?;
             }
         }
         Ok(())
@@ -490,9 +569,22 @@ impl IndexMerger {
         debug_time!("write-storable-fields");
         debug!("write-storable-field");
 
+        let mut num_docs_processed = 0u32;
+        self.monitor.report(MergePhase::Store, 0, self.max_doc)?;
         for reader in &self.readers {
             let store_reader = reader.get_store_reader(1)?;
-            if reader.has_deletes()
+            if store_reader.doc_store_version() != DOC_STORE_VERSION {
+                // The documents of the stores written by an older version of tantivy are
+                // serialized again in the current format.
+                for doc_res in store_reader.iter::<TantivyDocument>(reader.alive_bitset()) {
+                    store_writer.store(&doc_res?, &self.schema)?;
+                    num_docs_processed += 1;
+                    if num_docs_processed % STORE_PROGRESS_NUM_DOCS == 0 {
+                        self.monitor
+                            .report(MergePhase::Store, num_docs_processed, self.max_doc)?;
+                    }
+                }
+            } else if reader.has_deletes()
                     // If there is not enough data in the store, we avoid stacking in order to
                     // avoid creating many small blocks in the doc store. Once we have 5 full blocks,
                     // we start stacking. In the worst case 2/7 of the blocks would be very small.
@@ -512,10 +604,18 @@ impl IndexMerger {
                 for doc_bytes_res in store_reader.iter_raw(reader.alive_bitset()) {
                     let doc_bytes = doc_bytes_res?;
                     store_writer.store_bytes(&doc_bytes)?;
+                    num_docs_processed += 1;
+                    if num_docs_processed % STORE_PROGRESS_NUM_DOCS == 0 {
+                        self.monitor
+                            .report(MergePhase::Store, num_docs_processed, self.max_doc)?;
+                    }
                 }
             } else {
                 store_writer.stack(store_reader)?;
+                num_docs_processed += reader.num_docs();
             }
+            self.monitor
+                .report(MergePhase::Store, num_docs_processed, self.max_doc)?;
         }
         Ok(())
     }
@@ -779,7 +879,7 @@ mod tests {
                 score_field => 2u64,
                 bytes_score_field => vec![0u8, 0, 0, 2],
             ))?;
-            index_writer.delete_term(Term::from_field_text(text_field, "c"));
+            index_writer.delete_term(Term::from_field_text(text_field, "c"))?;
             index_writer.add_document(doc!(
                 text_field => "c d",
                 score_field => 3u64,
@@ -820,8 +920,8 @@ mod tests {
                 score_field => 5_000u64,
                 bytes_score_field => vec![0u8, 0, 0, 5],
             ))?;
-            index_writer.delete_term(Term::from_field_text(text_field, "a"));
-            index_writer.delete_term(Term::from_field_text(text_field, "f"));
+            index_writer.delete_term(Term::from_field_text(text_field, "a"))?;
+            index_writer.delete_term(Term::from_field_text(text_field, "f"))?;
             index_writer.add_document(doc!(
                 text_field => "f g",
                 score_field => 6_000u64,
@@ -935,7 +1035,7 @@ mod tests {
         }
         {
             // test a commit with only deletes
-            index_writer.delete_term(Term::from_field_text(text_field, "c"));
+            index_writer.delete_term(Term::from_field_text(text_field, "c"))?;
             index_writer.commit()?;
 
             reader.reload()?;
@@ -1030,7 +1130,7 @@ mod tests {
 
         {
             // Test removing all docs
-            index_writer.delete_term(Term::from_field_text(text_field, "g"));
+            index_writer.delete_term(Term::from_field_text(text_field, "g"))?;
             index_writer.commit()?;
             let segment_ids = index.searchable_segment_ids()?;
             reader.reload()?;
@@ -1173,7 +1273,7 @@ mod tests {
             let mut index_writer: IndexWriter = index.writer_for_tests().unwrap();
             let facet = Facet::from_path(vec!["top", "a", "firstdoc"]);
             let facet_term = Term::from_facet(facet_field, &facet);
-            index_writer.delete_term(facet_term);
+            index_writer.delete_term(facet_term).unwrap();
             index_writer.commit().unwrap();
             reader.reload().unwrap();
             test_searcher(
@@ -1203,7 +1303,7 @@ mod tests {
         let reader = index.reader()?;
         let searcher = reader.searcher();
         assert_eq!(searcher.num_docs(), 2);
-        index_writer.delete_term(Term::from_field_u64(int_field, 1));
+        index_writer.delete_term(Term::from_field_u64(int_field, 1))?;
         let segment_ids = index
             .searchable_segment_ids()
             .expect("Searchable segments failed.");
@@ -1230,7 +1330,7 @@ mod tests {
             index_writer.commit()?;
             index_writer.add_document(doc)?;
             index_writer.commit()?;
-            index_writer.delete_term(Term::from_field_u64(int_field, 1));
+            index_writer.delete_term(Term::from_field_u64(int_field, 1))?;
             let segment_ids = index.searchable_segment_ids()?;
             index_writer.merge(&segment_ids).wait()?;
 
@@ -4,6 +4,11 @@
 //! `IndexWriter` is the main entry point for that, which created from
 //! [`Index::writer`](crate::Index::writer).
 
+mod add_batch_sequencer;
+mod commit_handle;
+pub(crate) mod commit_payload;
+pub(crate) mod commit_points;
+mod commit_validator;
 pub(crate) mod delete_queue;
 pub(crate) mod path_to_unordered_id;
 
@@ -12,13 +17,20 @@ mod doc_opstamp_mapping;
 mod flat_map_with_buffer;
 pub(crate) mod index_writer;
 pub(crate) mod index_writer_status;
+mod indexing_report;
 mod log_merge_policy;
+mod memory_usage;
 mod merge_index_test;
 mod merge_operation;
 pub(crate) mod merge_policy;
+mod merge_progress;
 pub(crate) mod merger;
 pub(crate) mod operation;
+mod operation_log;
 pub(crate) mod prepared_commit;
+mod reclaim_report;
+#[cfg(feature = "mmap")]
+mod rollover_writer;
 mod segment_entry;
 mod segment_manager;
 mod segment_register;
@@ -26,23 +38,64 @@ pub(crate) mod segment_serializer;
 pub(crate) mod segment_updater;
 pub(crate) mod segment_writer;
 pub(crate) mod single_segment_index_writer;
+mod soft_delete_retention_policy;
 mod stamper;
+mod try_add_error;
+mod upsert;
+mod versioned_update;
 
 use crossbeam_channel as channel;
 use smallvec::SmallVec;
 
-pub use self::index_writer::{IndexWriter, IndexWriterOptions};
+pub use self::commit_handle::CommitHandle;
+pub use self::commit_payload::{
+    CommitPayload, DEFAULT_MAX_COMMIT_PAYLOAD_NUM_BYTES, DEFAULT_NUM_RETAINED_COMMIT_PAYLOADS,
+};
+pub use self::commit_points::DEFAULT_NUM_RETAINED_COMMIT_POINTS;
+pub use self::commit_validator::{
+    commit_violation, CommitValidator, FieldPresentValidator, NumericRangeValidator,
+};
+pub use self::index_writer::{IndexWriter, IndexWriterOptions, DEFAULT_DELETE_MAX_EXPANDED_TERMS};
+pub use self::indexing_report::{
+    FieldIndexingReport, IndexingReport, TokenizationError, TokenizationErrorPolicy,
+    MAX_EXAMPLE_OPSTAMPS,
+};
 pub use self::log_merge_policy::LogMergePolicy;
+pub use self::memory_usage::{
+    AdaptiveFlushThreshold, IndexingThreadMemoryUsage, MemoryUsageReport,
+};
 pub use self::merge_operation::MergeOperation;
-pub use self::merge_policy::{MergeCandidate, MergePolicy, NoMergePolicy};
+pub use self::merge_policy::{
+    MergeCandidate, MergeCandidatesFuture, MergeContext, MergePolicy, NoMergePolicy,
+    DEFAULT_MERGE_POLICY_TIMEOUT,
+};
+pub(crate) use self::merge_progress::MergeMonitor;
+pub use self::merge_progress::{MergeHandle, MergePhase, MergeProgress, MergeProgressCallback};
 use self::operation::AddOperation;
 pub use self::operation::UserOperation;
+pub(crate) use self::operation_log::OperationLogBatch;
+pub use self::operation_log::{
+    OperationLog, OperationLogOptions, OperationRecord, DEFAULT_OPERATION_LOG_MAX_NUM_BYTES,
+    DEFAULT_OPERATION_LOG_SEGMENT_NUM_BYTES,
+};
 pub use self::prepared_commit::PreparedCommit;
+pub use self::reclaim_report::{
+    ReclaimReport, SegmentReclaimReport, DEFAULT_EXPUNGE_DELETES_MIN_DELETED_RATIO,
+};
+#[cfg(feature = "mmap")]
+pub use self::rollover_writer::{
+    DeleteRouting, RolloverEvent, RolloverPolicy, RolloverTrigger, RolloverWriter,
+};
 pub use self::segment_entry::SegmentEntry;
 pub(crate) use self::segment_serializer::SegmentSerializer;
 pub use self::segment_updater::{merge_filtered_segments, merge_indices};
 pub use self::segment_writer::SegmentWriter;
 pub use self::single_segment_index_writer::SingleSegmentIndexWriter;
+pub use self::soft_delete_retention_policy::{
+    ExpireSoftDeleted, KeepSoftDeleted, SoftDeleteRetentionPolicy,
+};
+pub use self::try_add_error::TryAddError;
+pub use self::upsert::{UpsertConflictPolicy, UpsertStats};
 
 /// Alias for the default merge policy, which is the `LogMergePolicy`.
 pub type DefaultMergePolicy = LogMergePolicy;
@@ -78,7 +131,7 @@ mod tests_mmap {
         let mut index_writer: IndexWriter = index.writer_for_tests()?;
         // there must be one deleted document in the segment
         index_writer.add_document(doc!(text_field=>"b"))?;
-        index_writer.delete_term(Term::from_field_text(text_field, "b"));
+        index_writer.delete_term(Term::from_field_text(text_field, "b"))?;
         // we need enough data to trigger the bug (at least 32 documents)
         for _ in 0..32 {
             index_writer.add_document(doc!(text_field=>"c"))?;
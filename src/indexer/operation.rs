@@ -1,12 +1,81 @@
-use crate::query::Weight;
+use once_cell::unsync::OnceCell;
+
+use crate::index::Segment;
+use crate::query::{EnableScoring, Query, TermQuery, Weight};
 use crate::schema::document::Document;
-use crate::schema::{TantivyDocument, Term};
-use crate::Opstamp;
+use crate::schema::{Field, IndexRecordOption, TantivyDocument, Term};
+use crate::{DocId, Opstamp, Searcher, SegmentReader};
+
+/// Segment the deletes are applied to, with a searcher over it to lower the queries of the
+/// deletes to weights. The searcher is only opened for the first delete needing it.
+pub(crate) struct DeletedSegment<'a> {
+    segment: &'a Segment,
+    reader: &'a SegmentReader,
+    searcher: OnceCell<Searcher>,
+}
+
+impl<'a> DeletedSegment<'a> {
+    pub fn new(segment: &'a Segment, reader: &'a SegmentReader) -> DeletedSegment<'a> {
+        DeletedSegment {
+            segment,
+            reader,
+            searcher: OnceCell::new(),
+        }
+    }
+
+    pub fn reader(&self) -> &'a SegmentReader {
+        self.reader
+    }
+
+    /// Lowers `query` to a weight for the segment.
+    fn weight(&self, query: &dyn Query) -> crate::Result<Box<dyn Weight>> {
+        let searcher = self
+            .searcher
+            .get_or_try_init(|| Searcher::for_segment(self.segment, self.reader.clone()))?;
+        query.weight(EnableScoring::disabled_from_searcher(searcher))
+    }
+}
+
+/// Documents targeted by a [`DeleteOperation`].
+pub enum DeleteTarget {
+    /// Weight built when the operation was pushed.
+    Weight(Box<dyn Weight>),
+    /// Query lowered to a weight for each segment the operation is applied to.
+    ///
+    /// The weight then reflects the state of the segment, including the segments created after
+    /// the operation was pushed.
+    Query(Box<dyn Query>),
+    /// Documents containing the term.
+    Term(Term),
+}
+
+impl DeleteTarget {
+    /// Iterates through all of the documents of the segment matched by the target.
+    pub(crate) fn for_each_no_score(
+        &self,
+        segment: &DeletedSegment,
+        callback: &mut dyn FnMut(&[DocId]),
+    ) -> crate::Result<()> {
+        let reader = segment.reader();
+        match self {
+            DeleteTarget::Weight(weight) => weight.for_each_no_score(reader, callback),
+            DeleteTarget::Query(query) => segment
+                .weight(query.as_ref())?
+                .for_each_no_score(reader, callback),
+            DeleteTarget::Term(term) => {
+                let query = TermQuery::new(term.clone(), IndexRecordOption::Basic);
+                segment.weight(&query)?.for_each_no_score(reader, callback)
+            }
+        }
+    }
+}
 
 /// Timestamped Delete operation.
 pub struct DeleteOperation {
     pub opstamp: Opstamp,
-    pub target: Box<dyn Weight>,
+    pub target: DeleteTarget,
+    /// Set for soft deletes, which only mark the matching documents as soft-deleted.
+    pub tombstone_field: Option<Field>,
 }
 
 /// Timestamped Add operation.
@@ -24,3 +93,13 @@ pub enum UserOperation<D: Document = TantivyDocument> {
     /// Delete operation
     Delete(Term),
 }
+
+impl<D: Document> UserOperation<D> {
+    /// Returns the document of an add operation.
+    pub(crate) fn document(&self) -> Option<&D> {
+        match self {
+            UserOperation::Add(document) => Some(document),
+            UserOperation::Delete(_) => None,
+        }
+    }
+}
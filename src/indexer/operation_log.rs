@@ -0,0 +1,959 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crc32fast::Hasher;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::directory::error::OpenReadError;
+use crate::directory::Directory;
+use crate::error::{DataCorruption, TantivyError};
+use crate::schema::document::{
+    BinaryDocumentDeserializer, BinaryDocumentSerializer, Document, DocumentDeserialize,
+};
+use crate::schema::Schema;
+use crate::store::DocStoreVersion;
+use crate::{Index, Opstamp, TantivyDocument, Term};
+
+/// Default size above which a segment of the [`OperationLog`] is sealed and a new one started.
+pub const DEFAULT_OPERATION_LOG_SEGMENT_NUM_BYTES: u64 = 16_000_000;
+
+/// Default maximum size of an [`OperationLog`].
+pub const DEFAULT_OPERATION_LOG_MAX_NUM_BYTES: u64 = 256_000_000;
+
+static MANIFEST_FILEPATH: Lazy<&'static Path> = Lazy::new(|| Path::new("oplog.json"));
+
+const ADD_CODE: u8 = 0;
+const DELETE_CODE: u8 = 1;
+const DELETE_ALL_CODE: u8 = 2;
+const UNSUPPORTED_CODE: u8 = 3;
+const COMMIT_CODE: u8 = 4;
+
+/// Sizes bounding an [`OperationLog`].
+#[derive(Clone, Debug, bon::Builder)]
+pub struct OperationLogOptions {
+    #[builder(default = DEFAULT_OPERATION_LOG_SEGMENT_NUM_BYTES)]
+    /// Size above which the current segment of the log is sealed. The next commit starts a new
+    /// segment.
+    max_segment_num_bytes: u64,
+    #[builder(default = DEFAULT_OPERATION_LOG_MAX_NUM_BYTES)]
+    /// Size above which the oldest segments are removed, even if their operations are not
+    /// safe to drop yet. The last segment is never removed.
+    max_num_bytes: u64,
+}
+
+impl Default for OperationLogOptions {
+    fn default() -> Self {
+        OperationLogOptions::builder().build()
+    }
+}
+
+/// An operation read from an [`OperationLog`].
+#[derive(Clone, Debug)]
+pub enum OperationRecord {
+    /// A document was added.
+    Add {
+        /// Opstamp of the operation.
+        opstamp: Opstamp,
+        /// The added document, with all of its fields, stored or not.
+        document: TantivyDocument,
+    },
+    /// The documents containing a term were deleted.
+    Delete {
+        /// Opstamp of the operation.
+        opstamp: Opstamp,
+        /// The deleted term.
+        term: Term,
+    },
+    /// All of the documents were deleted, see
+    /// [`IndexWriter::delete_all_documents()`](crate::IndexWriter::delete_all_documents).
+    ///
+    /// This is always the first record of the log: the commit including it restarts the log.
+    DeleteAll {
+        /// Opstamp of the operation.
+        opstamp: Opstamp,
+    },
+    /// An operation that cannot be replayed, such as a delete by query or a soft delete.
+    ///
+    /// An index replaying the log has to be seeded again from the primary index, e.g. with a
+    /// [`Snapshot`](crate::Snapshot).
+    Unsupported {
+        /// Opstamp of the operation.
+        opstamp: Opstamp,
+        /// Description of the operation, for debugging purposes.
+        description: String,
+    },
+    /// The operations preceding this record were committed.
+    Commit {
+        /// Opstamp of the commit.
+        opstamp: Opstamp,
+        /// Payload of the commit, if any.
+        payload: Option<String>,
+    },
+}
+
+impl OperationRecord {
+    /// Returns the opstamp of the operation.
+    pub fn opstamp(&self) -> Opstamp {
+        match self {
+            OperationRecord::Add { opstamp, .. }
+            | OperationRecord::Delete { opstamp, .. }
+            | OperationRecord::DeleteAll { opstamp }
+            | OperationRecord::Unsupported { opstamp, .. }
+            | OperationRecord::Commit { opstamp, .. } => *opstamp,
+        }
+    }
+}
+
+/// Records of operations that are not enqueued yet.
+///
+/// Documents are moved to the indexing pipeline, so they are serialized beforehand.
+#[derive(Default)]
+pub(crate) struct OperationLogBatch {
+    records: Vec<u8>,
+}
+
+impl OperationLogBatch {
+    pub(crate) fn add<D: Document>(
+        &mut self,
+        opstamp: Opstamp,
+        document: &D,
+        schema: &Schema,
+    ) -> io::Result<()> {
+        let mut serialized_document = Vec::new();
+        BinaryDocumentSerializer::new(&mut serialized_document, schema)
+            .serialize_doc_all_fields(document)?;
+        write_record(&mut self.records, ADD_CODE, opstamp, &serialized_document);
+        Ok(())
+    }
+
+    pub(crate) fn delete(&mut self, opstamp: Opstamp, term: &Term) {
+        write_record(
+            &mut self.records,
+            DELETE_CODE,
+            opstamp,
+            term.serialized_term(),
+        );
+    }
+}
+
+/// A segment of the log, as listed in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct LogSegmentMeta {
+    /// Increasing number naming the file of the segment. Opstamps cannot name it, since they
+    /// get reused after a delete of all the documents.
+    id: u64,
+    /// Opstamp of the commit preceding the first record of the segment.
+    start_opstamp: Opstamp,
+    /// Opstamp of the last commit of the segment.
+    last_opstamp: Opstamp,
+    num_bytes: u64,
+}
+
+impl LogSegmentMeta {
+    fn relative_path(&self) -> PathBuf {
+        segment_path(self.id)
+    }
+}
+
+fn segment_path(id: u64) -> PathBuf {
+    PathBuf::from(format!("oplog-{id:020}.log"))
+}
+
+/// Log of the operations of an [`IndexWriter`](crate::IndexWriter), e.g. to keep a hot-standby
+/// index up to date by replaying them.
+///
+/// The log is opt-in: it is given to the writer with
+/// [`IndexWriterOptions::operation_log`](crate::indexer::IndexWriterOptions). Every stamped
+/// operation of the writer is then recorded, along with a marker for each commit. Documents are
+/// recorded with all of their fields, so the standby index does not need them to be stored.
+///
+/// The log is made of segments, each made of checksummed records. A segment is sealed once it
+/// exceeds `max_segment_num_bytes`. The segments whose commits are all covered by the opstamp
+/// set with [`OperationLog::set_safe_opstamp()`] are removed on commit, and so are the oldest
+/// segments if the log exceeds `max_num_bytes`.
+///
+/// # Durability
+///
+/// The operations are buffered in memory until the writer commits. The commit then writes them
+/// to the log, along with the commit marker, and syncs the log before the index itself commits.
+/// As a result, the log only contains committed operations, and [`OperationLog::read_from()`]
+/// never returns the operations of a commit that is not durable in the log.
+///
+/// If the commit of the index fails after the log was written, the log contains a commit that
+/// the index does not. It is cut back to the last commit of the index when a writer using the
+/// log is created, e.g. on [`IndexWriter::rollback()`](crate::IndexWriter::rollback).
+///
+/// # Delete of all the documents
+///
+/// [`IndexWriter::delete_all_documents()`](crate::IndexWriter::delete_all_documents) makes the
+/// writer reuse the opstamps following its last commit. The commit including it therefore
+/// restarts the log at the last commit: the previous segments are removed, and the log starts
+/// with an [`OperationRecord::DeleteAll`]. A standby index that replayed the last commit keeps
+/// reading from it. Another standby index gets an error from [`OperationLog::read_from()`], and
+/// catches up by reading from [`OperationLog::start_opstamp()`].
+#[derive(Clone)]
+pub struct OperationLog {
+    inner: Arc<Mutex<OperationLogInner>>,
+}
+
+struct OperationLogInner {
+    directory: Box<dyn Directory>,
+    options: OperationLogOptions,
+    /// The written segments, oldest first.
+    segments: Vec<LogSegmentMeta>,
+    /// Content of the last segment, if it is not sealed.
+    current_segment: Option<Vec<u8>>,
+    /// Records of the operations since the last commit.
+    pending: Vec<u8>,
+    /// Whether the pending records start with a delete of all the documents.
+    pending_delete_all: bool,
+    last_commit_opstamp: Opstamp,
+    safe_opstamp: Option<Opstamp>,
+}
+
+impl OperationLog {
+    /// Opens the operation log stored in `directory`, or creates it.
+    pub fn open(
+        directory: Box<dyn Directory>,
+        options: OperationLogOptions,
+    ) -> crate::Result<OperationLog> {
+        let segments = read_manifest(directory.as_ref())?;
+        let mut inner = OperationLogInner {
+            directory,
+            options,
+            segments,
+            current_segment: None,
+            pending: Vec::new(),
+            pending_delete_all: false,
+            last_commit_opstamp: 0,
+            safe_opstamp: None,
+        };
+        if let Some(last_segment) = inner.segments.last_mut() {
+            let data = inner.directory.atomic_read(&last_segment.relative_path())?;
+            // The segment may have been written without the manifest being updated.
+            if let Some(last_commit) = last_commit_opstamp(&data, &last_segment.relative_path())? {
+                last_segment.last_opstamp = last_commit;
+            }
+            last_segment.num_bytes = data.len() as u64;
+            inner.last_commit_opstamp = last_segment.last_opstamp;
+            if last_segment.num_bytes < inner.options.max_segment_num_bytes {
+                inner.current_segment = Some(data);
+            }
+        }
+        Ok(OperationLog {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// Opens the operation log stored in the directory of `index`, or creates it.
+    ///
+    /// The files of the log are not managed by the index, and are therefore not garbage
+    /// collected.
+    pub fn open_in_index(
+        index: &Index,
+        options: OperationLogOptions,
+    ) -> crate::Result<OperationLog> {
+        let directory = index.directory().underlying_directory().box_clone();
+        OperationLog::open(directory, options)
+    }
+
+    /// Returns the operations committed after the commit `commit_opstamp`, in the order they
+    /// were applied, each commit being followed by its [`OperationRecord::Commit`].
+    ///
+    /// `commit_opstamp` is typically the opstamp of the last commit replayed by the standby
+    /// index, or the opstamp of the [`Snapshot`](crate::Snapshot) it was seeded from. The
+    /// records are filtered by position rather than by opstamp, since the first operations
+    /// following a commit may reuse its opstamp, e.g. after a rollback.
+    ///
+    /// The log is read from its directory, so another process can read it while the writer
+    /// appends to it. Records are read lazily, one segment at a time.
+    ///
+    /// Returns an error if the log does not go back to `commit_opstamp` anymore, in which case
+    /// the standby index has to be seeded again, or if `commit_opstamp` is more recent than the
+    /// last commit of the log.
+    pub fn read_from(
+        &self,
+        commit_opstamp: Opstamp,
+    ) -> crate::Result<impl Iterator<Item = crate::Result<OperationRecord>>> {
+        let directory = self.inner.lock().unwrap().directory.box_clone();
+        let segments = read_manifest(directory.as_ref())?;
+        if let (Some(first_segment), Some(last_segment)) = (segments.first(), segments.last()) {
+            if commit_opstamp < first_segment.start_opstamp {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "The operation log starts at commit {}, the operations following commit \
+                     {commit_opstamp} are not available anymore.",
+                    first_segment.start_opstamp
+                )));
+            }
+            if commit_opstamp > last_segment.last_opstamp {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "The last commit of the operation log is {}, it does not contain commit \
+                     {commit_opstamp}. The log restarts when all of the documents are deleted.",
+                    last_segment.last_opstamp
+                )));
+            }
+        }
+        let segments = segments
+            .into_iter()
+            .filter(|segment| segment.last_opstamp >= commit_opstamp)
+            .collect();
+        Ok(OperationLogIterator {
+            directory,
+            segments,
+            current_segment: None,
+            from_commit_opstamp: commit_opstamp,
+            started: false,
+        })
+    }
+
+    /// Declares that the operations up to the commit `opstamp` are not needed anymore, for
+    /// instance because the standby index committed them.
+    ///
+    /// The segments only containing such operations are removed on the next commit.
+    pub fn set_safe_opstamp(&self, opstamp: Opstamp) {
+        self.inner.lock().unwrap().safe_opstamp = Some(opstamp);
+    }
+
+    /// Returns the opstamp of the commit the log starts after, or `None` if the log is empty.
+    pub fn start_opstamp(&self) -> Option<Opstamp> {
+        let inner = self.inner.lock().unwrap();
+        inner.segments.first().map(|segment| segment.start_opstamp)
+    }
+
+    /// Returns the size of the segments of the log, in bytes.
+    pub fn num_bytes(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.segments.iter().map(|segment| segment.num_bytes).sum()
+    }
+
+    /// Discards the operations that were not committed by the index, whose last commit is
+    /// `committed_opstamp`.
+    pub(crate) fn attach(&self, committed_opstamp: Opstamp) -> crate::Result<()> {
+        self.inner.lock().unwrap().attach(committed_opstamp)
+    }
+
+    /// Appends the records of `batch`, once its operations are enqueued.
+    pub(crate) fn append_batch(&self, batch: OperationLogBatch) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.extend_from_slice(&batch.records);
+    }
+
+    pub(crate) fn append_delete(&self, opstamp: Opstamp, serialized_term: &[u8]) {
+        self.append(DELETE_CODE, opstamp, serialized_term);
+    }
+
+    pub(crate) fn append_unsupported(&self, opstamp: Opstamp, description: &str) {
+        self.append(UNSUPPORTED_CODE, opstamp, description.as_bytes());
+    }
+
+    /// Records a delete of all the documents, after which the writer stamps operations from
+    /// `opstamp`. The operations since the last commit are discarded.
+    pub(crate) fn append_delete_all(&self, opstamp: Opstamp) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.clear();
+        inner.pending_delete_all = true;
+        write_record(&mut inner.pending, DELETE_ALL_CODE, opstamp, &[]);
+    }
+
+    fn append(&self, code: u8, opstamp: Opstamp, body: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+        write_record(&mut inner.pending, code, opstamp, body);
+    }
+
+    /// Writes the operations since the last commit to the log, followed by the commit marker,
+    /// and syncs the log.
+    pub(crate) fn commit(&self, opstamp: Opstamp, payload: Option<&str>) -> crate::Result<()> {
+        self.inner.lock().unwrap().commit(opstamp, payload)
+    }
+}
+
+impl std::fmt::Debug for OperationLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("OperationLog")
+            .field("options", &inner.options)
+            .field("segments", &inner.segments)
+            .finish()
+    }
+}
+
+impl OperationLogInner {
+    fn attach(&mut self, committed_opstamp: Opstamp) -> crate::Result<()> {
+        self.pending.clear();
+        self.pending_delete_all = false;
+        while let Some(last_segment) = self.segments.last().cloned() {
+            if last_segment.last_opstamp <= committed_opstamp {
+                break;
+            }
+            let path = last_segment.relative_path();
+            let data = self.directory.atomic_read(&path)?;
+            let num_bytes_to_keep = num_bytes_up_to(&data, &path, committed_opstamp)?;
+            if num_bytes_to_keep == 0 && self.segments.len() > 1 {
+                self.segments.pop();
+                write_manifest(self.directory.as_ref(), &self.segments)?;
+                self.delete_segment_file(&path);
+                continue;
+            }
+            let data = &data[..num_bytes_to_keep];
+            self.directory.atomic_write(&path, data)?;
+            let last_segment = self.segments.last_mut().unwrap();
+            last_segment.last_opstamp = last_commit_opstamp(data, &path)?
+                .unwrap_or(last_segment.start_opstamp)
+                .min(committed_opstamp);
+            last_segment.num_bytes = data.len() as u64;
+            write_manifest(self.directory.as_ref(), &self.segments)?;
+            break;
+        }
+        self.current_segment = match self.segments.last() {
+            Some(last_segment) if last_segment.num_bytes < self.options.max_segment_num_bytes => {
+                Some(self.directory.atomic_read(&last_segment.relative_path())?)
+            }
+            _ => None,
+        };
+        self.last_commit_opstamp = committed_opstamp;
+        Ok(())
+    }
+
+    fn commit(&mut self, opstamp: Opstamp, payload: Option<&str>) -> crate::Result<()> {
+        let next_segment_id = self.segments.last().map_or(0, |segment| segment.id + 1);
+        let (mut segments, mut data) = match self.current_segment.clone() {
+            Some(data) if !self.pending_delete_all => (self.segments.clone(), data),
+            _ => {
+                // After a delete of all the documents, the previous segments are obsolete and
+                // the opstamps start over from the one of the delete.
+                let (mut segments, start_opstamp) = if self.pending_delete_all {
+                    let (_, delete_all_opstamp, _) =
+                        read_record(&self.pending, &mut 0, &segment_path(next_segment_id))?;
+                    (Vec::new(), delete_all_opstamp)
+                } else {
+                    (self.segments.clone(), self.last_commit_opstamp)
+                };
+                segments.push(LogSegmentMeta {
+                    id: next_segment_id,
+                    start_opstamp,
+                    last_opstamp: start_opstamp,
+                    num_bytes: 0,
+                });
+                (segments, Vec::new())
+            }
+        };
+        data.extend_from_slice(&self.pending);
+        // The body of a commit is a flag telling whether it has a payload, then the payload.
+        let mut body = vec![payload.is_some() as u8];
+        body.extend_from_slice(payload.unwrap_or_default().as_bytes());
+        write_record(&mut data, COMMIT_CODE, opstamp, &body);
+
+        let last_segment = segments.last_mut().unwrap();
+        last_segment.last_opstamp = opstamp;
+        last_segment.num_bytes = data.len() as u64;
+        // The segment is written before the manifest, so that the manifest never lists
+        // operations that are not in the segment.
+        self.directory
+            .atomic_write(&last_segment.relative_path(), &data)?;
+        write_manifest(self.directory.as_ref(), &segments)?;
+
+        let previous_segments = std::mem::replace(&mut self.segments, segments);
+        self.current_segment =
+            ((data.len() as u64) < self.options.max_segment_num_bytes).then_some(data);
+        self.pending.clear();
+        self.last_commit_opstamp = opstamp;
+        if std::mem::take(&mut self.pending_delete_all) {
+            // The safe opstamp refers to opstamps that are reused from now on.
+            self.safe_opstamp = None;
+            for segment in previous_segments {
+                self.delete_segment_file(&segment.relative_path());
+            }
+        }
+        self.truncate()
+    }
+
+    /// Removes the oldest segments that are safe to drop, then the oldest segments while the
+    /// log is too large. The last segment is always kept.
+    fn truncate(&mut self) -> crate::Result<()> {
+        let mut total_num_bytes: u64 = self.segments.iter().map(|segment| segment.num_bytes).sum();
+        let mut num_segments_to_remove = 0;
+        for segment in &self.segments[..self.segments.len().saturating_sub(1)] {
+            let is_safe = self
+                .safe_opstamp
+                .is_some_and(|safe_opstamp| segment.last_opstamp <= safe_opstamp);
+            if !is_safe && total_num_bytes <= self.options.max_num_bytes {
+                break;
+            }
+            total_num_bytes -= segment.num_bytes;
+            num_segments_to_remove += 1;
+        }
+        if num_segments_to_remove == 0 {
+            return Ok(());
+        }
+        let removed_segments: Vec<LogSegmentMeta> =
+            self.segments.drain(..num_segments_to_remove).collect();
+        write_manifest(self.directory.as_ref(), &self.segments)?;
+        for segment in removed_segments {
+            self.delete_segment_file(&segment.relative_path());
+        }
+        Ok(())
+    }
+
+    fn delete_segment_file(&self, path: &Path) {
+        if let Err(err) = self.directory.delete(path) {
+            warn!("Failed to delete operation log segment {path:?}: {err:?}");
+        }
+    }
+}
+
+fn read_manifest(directory: &dyn Directory) -> crate::Result<Vec<LogSegmentMeta>> {
+    match directory.atomic_read(&MANIFEST_FILEPATH) {
+        Ok(data) => serde_json::from_slice(&data).map_err(|err| {
+            DataCorruption::new(
+                MANIFEST_FILEPATH.to_path_buf(),
+                format!("Operation log manifest cannot be deserialized: {err:?}"),
+            )
+            .into()
+        }),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write_manifest(directory: &dyn Directory, segments: &[LogSegmentMeta]) -> io::Result<()> {
+    let data = serde_json::to_vec(segments)?;
+    directory.atomic_write(&MANIFEST_FILEPATH, &data)
+}
+
+/// Appends a record: its length, the CRC32 of its content, then its content, made of the type
+/// of the operation, its opstamp and its body.
+fn write_record(output: &mut Vec<u8>, code: u8, opstamp: Opstamp, body: &[u8]) {
+    let mut content = Vec::with_capacity(9 + body.len());
+    content.push(code);
+    content.extend_from_slice(&opstamp.to_le_bytes());
+    content.extend_from_slice(body);
+    let mut hasher = Hasher::new();
+    hasher.update(&content);
+    output.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    output.extend_from_slice(&hasher.finalize().to_le_bytes());
+    output.extend_from_slice(&content);
+}
+
+/// Reads the record starting at `data[*offset..]`, and returns its type, opstamp and body.
+fn read_record<'a>(
+    data: &'a [u8],
+    offset: &mut usize,
+    path: &Path,
+) -> crate::Result<(u8, Opstamp, &'a [u8])> {
+    let corrupted =
+        |msg: &str| TantivyError::from(DataCorruption::new(path.to_path_buf(), msg.to_string()));
+    let header = data
+        .get(*offset..*offset + 8)
+        .ok_or_else(|| corrupted("Truncated operation log record header."))?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let content = data
+        .get(*offset + 8..*offset + 8 + len)
+        .filter(|content| content.len() >= 9)
+        .ok_or_else(|| corrupted("Truncated operation log record."))?;
+    let mut hasher = Hasher::new();
+    hasher.update(content);
+    if hasher.finalize() != checksum {
+        return Err(corrupted(
+            "Operation log record does not match its checksum.",
+        ));
+    }
+    *offset += 8 + len;
+    let opstamp = Opstamp::from_le_bytes(content[1..9].try_into().unwrap());
+    Ok((content[0], opstamp, &content[9..]))
+}
+
+/// Returns the opstamp of the last commit of a segment.
+fn last_commit_opstamp(data: &[u8], path: &Path) -> crate::Result<Option<Opstamp>> {
+    let mut offset = 0;
+    let mut last_commit = None;
+    while offset < data.len() {
+        let (code, opstamp, _) = read_record(data, &mut offset, path)?;
+        if code == COMMIT_CODE {
+            last_commit = Some(opstamp);
+        }
+    }
+    Ok(last_commit)
+}
+
+/// Returns the number of bytes of a segment up to the commit `committed_opstamp`, included.
+fn num_bytes_up_to(data: &[u8], path: &Path, committed_opstamp: Opstamp) -> crate::Result<usize> {
+    let mut offset = 0;
+    let mut num_bytes = 0;
+    while offset < data.len() {
+        let (code, opstamp, _) = read_record(data, &mut offset, path)?;
+        if code == COMMIT_CODE {
+            if opstamp > committed_opstamp {
+                break;
+            }
+            num_bytes = offset;
+        }
+    }
+    Ok(num_bytes)
+}
+
+fn decode_record(
+    code: u8,
+    opstamp: Opstamp,
+    mut body: &[u8],
+    path: &Path,
+) -> crate::Result<OperationRecord> {
+    let corrupted = |msg: String| TantivyError::from(DataCorruption::new(path.to_path_buf(), msg));
+    let record = match code {
+        ADD_CODE => {
+            let deserializer =
+                BinaryDocumentDeserializer::from_reader(&mut body, DocStoreVersion::V2)
+                    .map_err(|err| corrupted(format!("Invalid document: {err:?}")))?;
+            let document = TantivyDocument::deserialize(deserializer)
+                .map_err(|err| corrupted(format!("Invalid document: {err:?}")))?;
+            OperationRecord::Add { opstamp, document }
+        }
+        DELETE_CODE => OperationRecord::Delete {
+            opstamp,
+            term: Term::wrap(body.to_vec()),
+        },
+        DELETE_ALL_CODE => OperationRecord::DeleteAll { opstamp },
+        UNSUPPORTED_CODE => OperationRecord::Unsupported {
+            opstamp,
+            description: String::from_utf8_lossy(body).into_owned(),
+        },
+        COMMIT_CODE => {
+            let (&has_payload, payload) = body
+                .split_first()
+                .ok_or_else(|| corrupted("Truncated commit record.".to_string()))?;
+            OperationRecord::Commit {
+                opstamp,
+                payload: (has_payload != 0).then(|| String::from_utf8_lossy(payload).into_owned()),
+            }
+        }
+        _ => {
+            return Err(corrupted(format!(
+                "Unknown operation log record type {code}."
+            )))
+        }
+    };
+    Ok(record)
+}
+
+struct OperationLogIterator {
+    directory: Box<dyn Directory>,
+    segments: VecDeque<LogSegmentMeta>,
+    current_segment: Option<(PathBuf, Vec<u8>, usize)>,
+    from_commit_opstamp: Opstamp,
+    /// Whether the commit `from_commit_opstamp` was passed.
+    started: bool,
+}
+
+impl OperationLogIterator {
+    fn next_record(&mut self) -> crate::Result<Option<OperationRecord>> {
+        loop {
+            let Some((path, data, offset)) = self.current_segment.as_mut() else {
+                let Some(segment) = self.segments.pop_front() else {
+                    return Ok(None);
+                };
+                // A segment starts right after the commit it is named after.
+                self.started |= segment.start_opstamp >= self.from_commit_opstamp;
+                let path = segment.relative_path();
+                let data = self.directory.atomic_read(&path)?;
+                self.current_segment = Some((path, data, 0));
+                continue;
+            };
+            if *offset >= data.len() {
+                self.current_segment = None;
+                continue;
+            }
+            let (code, opstamp, body) = read_record(data, offset, path)?;
+            if !self.started {
+                self.started = code == COMMIT_CODE && opstamp >= self.from_commit_opstamp;
+                continue;
+            }
+            return decode_record(code, opstamp, body, path).map(Some);
+        }
+    }
+}
+
+impl Iterator for OperationLogIterator {
+    type Item = crate::Result<OperationRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(err) => {
+                // A corrupted segment cannot be read further.
+                self.segments.clear();
+                self.current_segment = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::Count;
+    use crate::indexer::{IndexWriterOptions, UserOperation};
+    use crate::query::TermQuery;
+    use crate::schema::{Field, IndexRecordOption, Schema, TermBuffer, STRING};
+    use crate::IndexWriter;
+
+    fn create_index() -> (Index, Field) {
+        let mut schema_builder = Schema::builder();
+        // The field is not stored: the log records all of the fields.
+        let text = schema_builder.add_text_field("text", STRING);
+        (Index::create_in_ram(schema_builder.build()), text)
+    }
+
+    fn writer_with_log(index: &Index, operation_log: &OperationLog) -> crate::Result<IndexWriter> {
+        index.writer_with_options(
+            IndexWriterOptions::builder()
+                .operation_log(operation_log.clone())
+                .build(),
+        )
+    }
+
+    /// Replays the log into `writer`, and returns the opstamp of the last replayed commit.
+    fn replay(
+        operation_log: &OperationLog,
+        commit_opstamp: Opstamp,
+        writer: &mut IndexWriter,
+    ) -> crate::Result<Opstamp> {
+        let mut last_commit_opstamp = commit_opstamp;
+        for record in operation_log.read_from(commit_opstamp)? {
+            match record? {
+                OperationRecord::Add { document, .. } => {
+                    writer.add_document(document)?;
+                }
+                OperationRecord::Delete { term, .. } => {
+                    writer.delete_term(term)?;
+                }
+                OperationRecord::DeleteAll { .. } => {
+                    writer.delete_all_documents()?;
+                }
+                OperationRecord::Unsupported { description, .. } => {
+                    panic!("unexpected unsupported operation: {description}")
+                }
+                OperationRecord::Commit { opstamp, payload } => {
+                    let mut prepared_commit = writer.prepare_commit()?;
+                    if let Some(payload) = payload {
+                        prepared_commit.set_payload(&payload);
+                    }
+                    prepared_commit.commit()?;
+                    last_commit_opstamp = opstamp;
+                }
+            }
+        }
+        Ok(last_commit_opstamp)
+    }
+
+    fn term_counts(index: &Index, text: Field) -> crate::Result<Vec<usize>> {
+        let searcher = index.reader()?.searcher();
+        ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|term| {
+                let term = Term::from_field_text(text, term);
+                searcher.search(&TermQuery::new(term, IndexRecordOption::Basic), &Count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_operation_log_replay_into_standby() -> crate::Result<()> {
+        let (primary, text) = create_index();
+        let operation_log = OperationLog::open_in_index(&primary, OperationLogOptions::default())?;
+        let mut primary_writer = writer_with_log(&primary, &operation_log)?;
+        let (standby, _) = create_index();
+        let mut standby_writer: IndexWriter = standby.writer_for_tests()?;
+
+        primary_writer.add_document(doc!(text => "a"))?;
+        primary_writer.add_document(doc!(text => "a"))?;
+        primary_writer.add_document(doc!(text => "b"))?;
+        primary_writer.commit()?;
+
+        primary_writer.delete_term(Term::from_field_text(text, "a"))?;
+        primary_writer.add_document(doc!(text => "a"))?;
+        primary_writer.add_document(doc!(text => "c"))?;
+        primary_writer.run(vec![
+            UserOperation::Delete(Term::from_field_text(text, "b")),
+            UserOperation::Add(doc!(text => "b")),
+            UserOperation::Add(doc!(text => "d")),
+        ])?;
+        let mut prepared_commit = primary_writer.prepare_commit()?;
+        prepared_commit.set_payload("second");
+        let second_commit_opstamp = prepared_commit.commit()?;
+
+        // The second delete of the same term is elided by the writer, but still logged.
+        let mut term_buffer = TermBuffer::new(text);
+        term_buffer.set_text("c");
+        primary_writer.delete_term_ref(&term_buffer.term_ref())?;
+        primary_writer.delete_term_ref(&term_buffer.term_ref())?;
+        primary_writer.try_add_document(doc!(text => "e")).unwrap();
+        primary_writer.commit()?;
+
+        let replayed_opstamp = replay(&operation_log, 0, &mut standby_writer)?;
+        assert_eq!(replayed_opstamp, primary.load_metas()?.opstamp);
+        assert_eq!(term_counts(&standby, text)?, term_counts(&primary, text)?);
+        assert_eq!(term_counts(&standby, text)?, vec![1, 1, 0, 1, 1, 0]);
+        let payloads: Vec<Option<String>> = operation_log
+            .read_from(0)?
+            .filter_map(|record| match record.unwrap() {
+                OperationRecord::Commit { payload, .. } => Some(payload),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(payloads, vec![None, Some("second".to_string()), None]);
+        let records: Vec<OperationRecord> = operation_log
+            .read_from(second_commit_opstamp)?
+            .collect::<crate::Result<_>>()?;
+        assert_eq!(records.len(), 4);
+        assert!(matches!(records[0], OperationRecord::Delete { .. }));
+        assert_eq!(operation_log.read_from(replayed_opstamp)?.count(), 0);
+
+        // The standby catches up from its last replayed commit.
+        primary_writer.add_document(doc!(text => "b"))?;
+        primary_writer.commit()?;
+        let replayed_opstamp = replay(&operation_log, replayed_opstamp, &mut standby_writer)?;
+        assert_eq!(replayed_opstamp, primary.load_metas()?.opstamp);
+        assert_eq!(term_counts(&standby, text)?, vec![1, 2, 0, 1, 1, 0]);
+
+        // Deleting all of the documents restarts the log at the last commit.
+        primary_writer.delete_all_documents()?;
+        primary_writer.add_document(doc!(text => "f"))?;
+        primary_writer.add_document(doc!(text => "a"))?;
+        primary_writer.commit()?;
+        primary_writer.delete_term(Term::from_field_text(text, "a"))?;
+        primary_writer.commit()?;
+        assert_eq!(operation_log.start_opstamp(), Some(replayed_opstamp));
+        assert!(matches!(
+            operation_log.read_from(second_commit_opstamp).map(|_| ()),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            operation_log.read_from(replayed_opstamp)?.next(),
+            Some(Ok(OperationRecord::DeleteAll { .. }))
+        ));
+        let replayed_opstamp = replay(&operation_log, replayed_opstamp, &mut standby_writer)?;
+        assert_eq!(replayed_opstamp, primary.load_metas()?.opstamp);
+        assert_eq!(term_counts(&standby, text)?, term_counts(&primary, text)?);
+        assert_eq!(term_counts(&standby, text)?, vec![0, 0, 0, 0, 0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_log_records_unsupported_deletes() -> crate::Result<()> {
+        let (index, text) = create_index();
+        let operation_log = OperationLog::open_in_index(&index, OperationLogOptions::default())?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        index_writer.delete_term_unchecked(Term::from_field_text(text, "a"));
+        index_writer.delete_query(Box::new(crate::query::AllQuery))?;
+        index_writer.commit()?;
+        let records: Vec<OperationRecord> =
+            operation_log.read_from(0)?.collect::<crate::Result<_>>()?;
+        assert!(
+            matches!(&records[0], OperationRecord::Delete { term, .. } if term.field() == text)
+        );
+        assert!(
+            matches!(&records[1], OperationRecord::Unsupported { description, .. }
+            if description.contains("AllQuery"))
+        );
+        assert!(matches!(&records[2], OperationRecord::Commit { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_log_rollback_and_reopen() -> crate::Result<()> {
+        let (index, text) = create_index();
+        let operation_log = OperationLog::open_in_index(&index, OperationLogOptions::default())?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text => "b"))?;
+        index_writer.rollback()?;
+        index_writer.add_document(doc!(text => "c"))?;
+        index_writer.commit()?;
+        drop(index_writer);
+
+        // A new writer appends to the log written by the previous one.
+        let operation_log = OperationLog::open_in_index(&index, OperationLogOptions::default())?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        index_writer.add_document(doc!(text => "d"))?;
+        index_writer.commit()?;
+
+        let (standby, _) = create_index();
+        let mut standby_writer: IndexWriter = standby.writer_for_tests()?;
+        replay(&operation_log, 0, &mut standby_writer)?;
+        assert_eq!(term_counts(&standby, text)?, vec![1, 0, 1, 1, 0, 0]);
+        assert_eq!(operation_log.read_from(0)?.count(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_log_truncation() -> crate::Result<()> {
+        let (index, text) = create_index();
+        // Every commit seals its segment.
+        let options = OperationLogOptions::builder()
+            .max_segment_num_bytes(1)
+            .build();
+        let operation_log = OperationLog::open_in_index(&index, options)?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        let mut commit_opstamps = Vec::new();
+        for _ in 0..3 {
+            index_writer.add_document(doc!(text => "a"))?;
+            commit_opstamps.push(index_writer.commit()?);
+        }
+        assert_eq!(operation_log.read_from(0)?.count(), 6);
+
+        operation_log.set_safe_opstamp(commit_opstamps[1]);
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+        assert!(matches!(
+            operation_log.read_from(0).map(|_| ()),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert_eq!(operation_log.read_from(commit_opstamps[1])?.count(), 4);
+        let directory = index.directory().underlying_directory();
+        assert!(!directory.exists(&segment_path(0))?);
+
+        // The size bound removes segments that are not safe to drop yet, but keeps the last one.
+        let options = OperationLogOptions::builder()
+            .max_segment_num_bytes(1)
+            .max_num_bytes(1)
+            .build();
+        drop(index_writer);
+        let operation_log = OperationLog::open_in_index(&index, options)?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        index_writer.add_document(doc!(text => "a"))?;
+        let last_commit_opstamp = index_writer.commit()?;
+        assert!(operation_log.read_from(commit_opstamps[2]).is_err());
+        assert_eq!(operation_log.read_from(last_commit_opstamp)?.count(), 0);
+        let records: Vec<OperationRecord> = operation_log
+            .read_from(operation_log.start_opstamp().unwrap())?
+            .collect::<crate::Result<_>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].opstamp(), last_commit_opstamp);
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_log_detects_corruption() -> crate::Result<()> {
+        let (index, text) = create_index();
+        let operation_log = OperationLog::open_in_index(&index, OperationLogOptions::default())?;
+        let mut index_writer = writer_with_log(&index, &operation_log)?;
+        index_writer.add_document(doc!(text => "a"))?;
+        index_writer.commit()?;
+
+        let directory = index.directory().underlying_directory();
+        let mut data = directory.atomic_read(&segment_path(0))?;
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        directory.atomic_write(&segment_path(0), &data)?;
+        let records: Vec<crate::Result<OperationRecord>> = operation_log.read_from(0)?.collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_ok());
+        assert!(matches!(records[1], Err(TantivyError::DataCorruption(_))));
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
-use super::IndexWriter;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::commit_payload::{parse_commit_payload, serialize_commit_payload};
+use super::{IndexWriter, IndexingReport};
 use crate::schema::document::Document;
 use crate::{FutureResult, Opstamp, TantivyDocument};
 
@@ -7,14 +11,20 @@ pub struct PreparedCommit<'a, D: Document = TantivyDocument> {
     index_writer: &'a mut IndexWriter<D>,
     payload: Option<String>,
     opstamp: Opstamp,
+    indexing_report: IndexingReport,
 }
 
 impl<'a, D: Document> PreparedCommit<'a, D> {
-    pub(crate) fn new(index_writer: &'a mut IndexWriter<D>, opstamp: Opstamp) -> Self {
+    pub(crate) fn new(
+        index_writer: &'a mut IndexWriter<D>,
+        opstamp: Opstamp,
+        indexing_report: IndexingReport,
+    ) -> Self {
         Self {
             index_writer,
             payload: None,
             opstamp,
+            indexing_report,
         }
     }
 
@@ -23,11 +33,54 @@ impl<'a, D: Document> PreparedCommit<'a, D> {
         self.opstamp
     }
 
+    /// Returns the tokenization problems of the documents added since the last commit.
+    ///
+    /// See [`TokenizationErrorPolicy`](crate::indexer::TokenizationErrorPolicy).
+    pub fn indexing_report(&self) -> &IndexingReport {
+        &self.indexing_report
+    }
+
     /// Adds an arbitrary payload to the commit.
     pub fn set_payload(&mut self, payload: &str) {
         self.payload = Some(payload.to_string())
     }
 
+    /// Adds a structured payload to the commit, serialized as JSON.
+    ///
+    /// Returns [`TantivyError::CommitPayloadTooLarge`](crate::TantivyError::CommitPayloadTooLarge)
+    /// if the serialized payload exceeds the maximum size set in the
+    /// [`IndexWriterOptions`](crate::indexer::IndexWriterOptions).
+    pub fn set_json_payload(&mut self, payload: &impl Serialize) -> crate::Result<()> {
+        let payload =
+            serialize_commit_payload(payload, self.index_writer.max_commit_payload_num_bytes())?;
+        self.payload = Some(payload);
+        Ok(())
+    }
+
+    /// Replaces the payload of the commit by the result of `modify`.
+    ///
+    /// `modify` receives the payload already added to this commit if any, and the payload of the
+    /// last commit otherwise. Since the [`IndexWriter`] is borrowed for the lifetime of the
+    /// `PreparedCommit`, no other commit can happen in between.
+    ///
+    /// The new payload is subject to the same size limit as in
+    /// [`PreparedCommit::set_json_payload()`].
+    pub fn modify_payload(
+        &mut self,
+        modify: impl FnOnce(Option<Value>) -> Value,
+    ) -> crate::Result<()> {
+        let current_payload = match self.payload.clone() {
+            Some(payload) => Some(payload),
+            None => self
+                .index_writer
+                .segment_updater()
+                .schedule_last_commit_payload()
+                .wait()?,
+        };
+        let new_payload = modify(current_payload.as_deref().map(parse_commit_payload));
+        self.set_json_payload(&new_payload)
+    }
+
     /// Rollbacks any change.
     pub fn abort(self) -> crate::Result<Opstamp> {
         self.index_writer.rollback()
@@ -44,10 +97,24 @@ impl<'a, D: Document> PreparedCommit<'a, D> {
     /// Unfortunately, contrary to what `PrepareCommit` may suggests,
     /// this operation is not at all really light.
     /// At this point deletes have not been flushed yet.
+    ///
+    /// If the writer has an [`OperationLog`](crate::indexer::OperationLog), the operations of
+    /// the commit are written to the log and synced first. The commit fails if they cannot be.
     pub fn commit_future(self) -> FutureResult<Opstamp> {
         info!("committing {}", self.opstamp);
-        self.index_writer
-            .segment_updater()
-            .schedule_commit(self.opstamp, self.payload)
+        if let Some(operation_log) = self.index_writer.operation_log() {
+            if let Err(err) = operation_log.commit(self.opstamp, self.payload.as_deref()) {
+                return FutureResult::from(err);
+            }
+        }
+        // The opstamps up to the commit are in the delete queue and the operation log, so the
+        // writer never reverts its stamper below it, see `IndexWriter::delete_all_documents()`.
+        self.index_writer.set_committed_opstamp(self.opstamp);
+        self.index_writer.segment_updater().schedule_commit(
+            self.opstamp,
+            self.payload,
+            self.index_writer.num_retained_commit_payloads(),
+            self.index_writer.num_retained_commit_points(),
+        )
     }
 }
@@ -0,0 +1,173 @@
+use crate::directory::Directory;
+use crate::index::{SegmentComponent, SegmentId};
+use crate::indexer::SegmentEntry;
+
+/// Default value of the deleted ratio above which
+/// [`IndexWriter::expunge_deletes()`](crate::IndexWriter::expunge_deletes) merges a segment.
+pub const DEFAULT_EXPUNGE_DELETES_MIN_DELETED_RATIO: f64 = 0.1;
+
+/// Disk space that merges expunging the deleted documents could give back.
+///
+/// See [`IndexWriter::reclaimable_space_report()`](crate::IndexWriter::reclaimable_space_report).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReclaimReport {
+    /// One entry per segment, committed or not.
+    pub segments: Vec<SegmentReclaimReport>,
+    /// Sum of the on-disk size of the segments, in bytes.
+    pub total_num_bytes: u64,
+    /// Sum of the estimated reclaimable bytes of the segments.
+    pub total_estimated_reclaimable_bytes: u64,
+}
+
+/// Disk usage and deletes of a single segment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentReclaimReport {
+    /// Id of the segment.
+    pub segment_id: SegmentId,
+    /// Whether the segment is part of the last commit.
+    pub committed: bool,
+    /// Number of documents in the segment, deleted or not.
+    pub max_doc: u32,
+    /// Number of deleted documents, including the deletes that are not committed yet.
+    pub num_deleted_docs: u32,
+    /// On-disk size of each of the files of the segment, in bytes.
+    ///
+    /// Components without a file are omitted.
+    pub num_bytes_by_component: Vec<(SegmentComponent, u64)>,
+    /// On-disk size of the segment, in bytes.
+    pub num_bytes: u64,
+    /// Estimate of the bytes a merge expunging the deleted documents would give back.
+    ///
+    /// This is only an estimate: it assumes the deleted documents take their share of each
+    /// file, i.e. `deleted_ratio() * num_bytes`.
+    pub estimated_reclaimable_bytes: u64,
+}
+
+impl SegmentReclaimReport {
+    /// Ratio of deleted documents in the segment, between 0 and 1.
+    pub fn deleted_ratio(&self) -> f64 {
+        if self.max_doc == 0 {
+            return 0.0;
+        }
+        self.num_deleted_docs as f64 / self.max_doc as f64
+    }
+}
+
+/// Number of deleted documents of a segment entry, including the deletes
+/// of its alive bitset that are not written to a delete file yet.
+pub(crate) fn num_deleted_docs_with_pending(segment_entry: &SegmentEntry) -> u32 {
+    let meta = segment_entry.meta();
+    let num_pending_deleted_docs = segment_entry
+        .alive_bitset()
+        .map(|alive_bitset| meta.max_doc() - alive_bitset.len() as u32)
+        .unwrap_or(0);
+    meta.num_deleted_docs().max(num_pending_deleted_docs)
+}
+
+pub(crate) fn segment_reclaim_report(
+    directory: &dyn Directory,
+    segment_entry: &SegmentEntry,
+    committed: bool,
+) -> crate::Result<SegmentReclaimReport> {
+    let meta = segment_entry.meta();
+    let mut num_bytes_by_component = Vec::new();
+    for &component in SegmentComponent::iterator() {
+        let path = meta.relative_path(component);
+        if !directory.exists(&path)? {
+            continue;
+        }
+        let num_bytes = directory.open_read(&path)?.num_bytes().get_bytes();
+        num_bytes_by_component.push((component, num_bytes));
+    }
+    let num_bytes: u64 = num_bytes_by_component
+        .iter()
+        .map(|(_, num_bytes)| num_bytes)
+        .sum();
+    let mut segment_report = SegmentReclaimReport {
+        segment_id: meta.id(),
+        committed,
+        max_doc: meta.max_doc(),
+        num_deleted_docs: num_deleted_docs_with_pending(segment_entry),
+        num_bytes_by_component,
+        num_bytes,
+        estimated_reclaimable_bytes: 0,
+    };
+    segment_report.estimated_reclaimable_bytes =
+        (segment_report.deleted_ratio() * num_bytes as f64) as u64;
+    Ok(segment_report)
+}
+
+impl ReclaimReport {
+    pub(crate) fn new(segments: Vec<SegmentReclaimReport>) -> ReclaimReport {
+        let total_num_bytes = segments.iter().map(|segment| segment.num_bytes).sum();
+        let total_estimated_reclaimable_bytes = segments
+            .iter()
+            .map(|segment| segment.estimated_reclaimable_bytes)
+            .sum();
+        ReclaimReport {
+            segments,
+            total_num_bytes,
+            total_estimated_reclaimable_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::BitSet;
+
+    use super::*;
+    use crate::index::SegmentMetaInventory;
+    use crate::indexer::delete_queue::DeleteQueue;
+
+    #[test]
+    fn test_num_deleted_docs_with_pending() {
+        let inventory = SegmentMetaInventory::default();
+        let delete_queue = DeleteQueue::new();
+        let segment_meta = inventory.new_segment_meta(SegmentId::generate_random(), 10u32);
+
+        let segment_entry = SegmentEntry::new(segment_meta.clone(), delete_queue.cursor(), None);
+        assert_eq!(num_deleted_docs_with_pending(&segment_entry), 0);
+
+        let committed_meta = segment_meta.clone().with_delete_meta(2, 1);
+        let segment_entry = SegmentEntry::new(committed_meta.clone(), delete_queue.cursor(), None);
+        assert_eq!(num_deleted_docs_with_pending(&segment_entry), 2);
+
+        // The alive bitset includes the committed deletes, along with the pending ones.
+        let mut alive_bitset = BitSet::with_max_value_and_full(10);
+        for doc in 0..5 {
+            alive_bitset.remove(doc);
+        }
+        let segment_entry =
+            SegmentEntry::new(committed_meta, delete_queue.cursor(), Some(alive_bitset));
+        assert_eq!(num_deleted_docs_with_pending(&segment_entry), 5);
+    }
+
+    #[test]
+    fn test_reclaim_report_totals() {
+        let segment_report = |max_doc, num_deleted_docs, num_bytes| {
+            let mut segment_report = SegmentReclaimReport {
+                segment_id: SegmentId::generate_random(),
+                committed: true,
+                max_doc,
+                num_deleted_docs,
+                num_bytes_by_component: vec![(SegmentComponent::Store, num_bytes)],
+                num_bytes,
+                estimated_reclaimable_bytes: 0,
+            };
+            segment_report.estimated_reclaimable_bytes =
+                (segment_report.deleted_ratio() * num_bytes as f64) as u64;
+            segment_report
+        };
+        let report = ReclaimReport::new(vec![
+            segment_report(100, 25, 4_000),
+            segment_report(10, 0, 1_000),
+            segment_report(0, 0, 0),
+        ]);
+        assert_eq!(report.segments[0].deleted_ratio(), 0.25);
+        assert_eq!(report.segments[0].estimated_reclaimable_bytes, 1_000);
+        assert_eq!(report.segments[2].deleted_ratio(), 0.0);
+        assert_eq!(report.total_num_bytes, 5_000);
+        assert_eq!(report.total_estimated_reclaimable_bytes, 1_000);
+    }
+}
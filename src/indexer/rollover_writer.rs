@@ -0,0 +1,647 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::indexer::{IndexWriter, IndexWriterOptions};
+use crate::schema::Schema;
+use crate::{
+    Directory, Index, IndexReader, MultiSearcher, Opstamp, ReloadPolicy, TantivyDocument,
+    TantivyError, Term,
+};
+
+/// Prefix of the directories of the generations of a [`RolloverWriter`].
+const GENERATION_DIR_PREFIX: &str = "generation-";
+
+/// Thresholds at which a [`RolloverWriter`] seals its current index and starts a new one.
+///
+/// The thresholds are checked after each commit. A threshold set to `None` never triggers a
+/// rollover.
+#[derive(Clone, Debug, Default)]
+pub struct RolloverPolicy {
+    /// Number of documents of the current index, deleted documents excluded.
+    pub max_docs: Option<u64>,
+    /// Number of bytes of the files of the segments of the current index.
+    pub max_bytes: Option<u64>,
+    /// Time since the current index was created or opened by the writer.
+    pub max_age: Option<Duration>,
+    /// Merges the segments of an index into a single segment when sealing it.
+    pub force_merge_on_seal: bool,
+}
+
+/// Indexes to which a [`RolloverWriter`] applies deletes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeleteRouting {
+    /// Deletes apply to the current index and to the sealed indexes.
+    #[default]
+    AllGenerations,
+    /// Deletes only apply to the current index.
+    ActiveOnly,
+}
+
+/// Threshold of the [`RolloverPolicy`] which triggered a rollover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RolloverTrigger {
+    /// [`RolloverPolicy::max_docs`]
+    MaxDocs,
+    /// [`RolloverPolicy::max_bytes`]
+    MaxBytes,
+    /// [`RolloverPolicy::max_age`]
+    MaxAge,
+}
+
+/// Rollover reported to the callback of a [`RolloverWriter`].
+#[derive(Clone, Debug)]
+pub struct RolloverEvent {
+    /// Threshold which triggered the rollover.
+    pub trigger: RolloverTrigger,
+    /// Generation of the index which was sealed.
+    pub sealed_generation: u64,
+    /// Directory of the index which was sealed.
+    pub sealed_path: PathBuf,
+    /// Number of documents of the sealed index.
+    pub sealed_num_docs: u64,
+    /// Generation of the new current index.
+    pub new_generation: u64,
+    /// Directory of the new current index.
+    pub new_path: PathBuf,
+}
+
+type RolloverCallback = Box<dyn FnMut(&RolloverEvent) + Send>;
+
+struct Generation {
+    generation: u64,
+    path: PathBuf,
+    index: Index,
+    reader: IndexReader,
+}
+
+impl Generation {
+    fn open_or_create(base_dir: &Path, generation: u64, schema: &Schema) -> crate::Result<Self> {
+        let path = base_dir.join(format!("{GENERATION_DIR_PREFIX}{generation:06}"));
+        let index = if path.exists() {
+            let index = Index::open_in_dir(&path)?;
+            if index.schema() != *schema {
+                return Err(TantivyError::SchemaError(format!(
+                    "The schema of the index in {path:?} differs from the schema of the writer"
+                )));
+            }
+            index
+        } else {
+            std::fs::create_dir(&path)?;
+            Index::create_in_dir(&path, schema.clone())?
+        };
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(Generation {
+            generation,
+            path,
+            index,
+            reader,
+        })
+    }
+
+    fn num_docs(&self) -> crate::Result<u64> {
+        Ok(self
+            .index
+            .searchable_segment_metas()?
+            .iter()
+            .map(|segment_meta| u64::from(segment_meta.num_docs()))
+            .sum())
+    }
+
+    fn num_bytes(&self) -> crate::Result<u64> {
+        let directory = self.index.directory();
+        let mut num_bytes = 0;
+        for segment_meta in self.index.searchable_segment_metas()? {
+            for path in segment_meta.list_files() {
+                // Optional components, e.g. the positions of an index without positions, have no
+                // file.
+                if let Ok(file) = directory.open_read(&path) {
+                    num_bytes += file.num_bytes().get_bytes();
+                }
+            }
+        }
+        Ok(num_bytes)
+    }
+}
+
+/// Writes documents into a sequence of indexes, sealing the current index and starting a new
+/// one when it reaches the thresholds of a [`RolloverPolicy`].
+///
+/// Each index, or generation, lives in a sibling directory of `base_dir`, named after its
+/// generation number: `generation-000000`, `generation-000001`... Only the current generation
+/// receives new documents. The sealed generations are only modified by deletes, if the
+/// [`DeleteRouting`] routes them to all generations.
+///
+/// [`RolloverWriter::multi_searcher`] searches all of the generations as if they were a single
+/// index.
+///
+/// ```rust
+/// use tantivy::indexer::{RolloverPolicy, RolloverWriter};
+/// use tantivy::query::AllQuery;
+/// use tantivy::collector::Count;
+/// use tantivy::schema::{Schema, TEXT};
+/// use tantivy::doc;
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let body = schema_builder.add_text_field("body", TEXT);
+/// let schema = schema_builder.build();
+///
+/// let base_dir = tempfile::TempDir::new()?;
+/// let policy = RolloverPolicy {
+///     max_docs: Some(2),
+///     ..Default::default()
+/// };
+/// let mut writer = RolloverWriter::new(base_dir.path(), schema, policy)?;
+/// for text in ["a", "b", "c"] {
+///     writer.add_document(doc!(body => text))?;
+///     writer.commit()?;
+/// }
+/// assert_eq!(writer.sealed_indexes().len(), 1);
+/// assert_eq!(writer.multi_searcher()?.search(&AllQuery, &Count)?, 3);
+/// # Ok(())
+/// # }
+/// ```
+pub struct RolloverWriter {
+    base_dir: PathBuf,
+    schema: Schema,
+    policy: RolloverPolicy,
+    delete_routing: DeleteRouting,
+    writer_options: IndexWriterOptions,
+    sealed: Vec<Generation>,
+    current: Generation,
+    writer: IndexWriter,
+    opened_at: Instant,
+    /// Deletes to apply to the sealed generations on the next commit.
+    pending_sealed_deletes: Vec<Term>,
+    on_rollover: Option<RolloverCallback>,
+}
+
+impl fmt::Debug for RolloverWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RolloverWriter")
+            .field("base_dir", &self.base_dir)
+            .field("policy", &self.policy)
+            .field("delete_routing", &self.delete_routing)
+            .field("generation", &self.current.generation)
+            .field("num_sealed", &self.sealed.len())
+            .finish()
+    }
+}
+
+impl RolloverWriter {
+    /// Opens the generations found in `base_dir`, or creates the first one.
+    ///
+    /// The last generation found is the current one, the other ones are sealed. Returns an error
+    /// if the schema of one of the generations differs from `schema`.
+    pub fn new(
+        base_dir: impl AsRef<Path>,
+        schema: Schema,
+        policy: RolloverPolicy,
+    ) -> crate::Result<RolloverWriter> {
+        RolloverWriter::new_with_options(
+            base_dir,
+            schema,
+            policy,
+            IndexWriterOptions::builder().build(),
+        )
+    }
+
+    /// Same as [`RolloverWriter::new`], with the options of the index writers of the
+    /// generations.
+    pub fn new_with_options(
+        base_dir: impl AsRef<Path>,
+        schema: Schema,
+        policy: RolloverPolicy,
+        writer_options: IndexWriterOptions,
+    ) -> crate::Result<RolloverWriter> {
+        let base_dir = base_dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&base_dir)?;
+        let mut generations: Vec<u64> = Vec::new();
+        for entry in std::fs::read_dir(&base_dir)? {
+            let file_name = entry?.file_name();
+            if let Some(generation) = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(GENERATION_DIR_PREFIX))
+                .and_then(|suffix| suffix.parse().ok())
+            {
+                generations.push(generation);
+            }
+        }
+        generations.sort_unstable();
+        let current_generation = generations.pop().unwrap_or(0);
+        let sealed = generations
+            .into_iter()
+            .map(|generation| Generation::open_or_create(&base_dir, generation, &schema))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let current = Generation::open_or_create(&base_dir, current_generation, &schema)?;
+        let writer = current.index.writer_with_options(writer_options.clone())?;
+        Ok(RolloverWriter {
+            base_dir,
+            schema,
+            policy,
+            delete_routing: DeleteRouting::default(),
+            writer_options,
+            sealed,
+            current,
+            writer,
+            opened_at: Instant::now(),
+            pending_sealed_deletes: Vec::new(),
+            on_rollover: None,
+        })
+    }
+
+    /// Sets the indexes to which the deletes apply. Defaults to
+    /// [`DeleteRouting::AllGenerations`].
+    pub fn with_delete_routing(mut self, delete_routing: DeleteRouting) -> RolloverWriter {
+        self.delete_routing = delete_routing;
+        self
+    }
+
+    /// Calls `on_rollover` after each rollover.
+    pub fn with_rollover_callback(
+        mut self,
+        on_rollover: impl FnMut(&RolloverEvent) + Send + 'static,
+    ) -> RolloverWriter {
+        self.on_rollover = Some(Box::new(on_rollover));
+        self
+    }
+
+    /// Returns the index receiving the new documents.
+    pub fn current_index(&self) -> &Index {
+        &self.current.index
+    }
+
+    /// Returns the generation number of the current index.
+    pub fn current_generation(&self) -> u64 {
+        self.current.generation
+    }
+
+    /// Returns the sealed indexes, from the oldest to the most recent.
+    pub fn sealed_indexes(&self) -> Vec<&Index> {
+        self.sealed
+            .iter()
+            .map(|generation| &generation.index)
+            .collect()
+    }
+
+    /// Adds a document to the current index.
+    ///
+    /// The document becomes searchable after the next commit.
+    pub fn add_document(&self, document: TantivyDocument) -> crate::Result<Opstamp> {
+        self.writer.add_document(document)
+    }
+
+    /// Deletes the documents containing `term`, in the indexes defined by the
+    /// [`DeleteRouting`].
+    ///
+    /// The deletes are applied by the next commit.
+    pub fn delete_term(&mut self, term: Term) -> crate::Result<Opstamp> {
+        if self.delete_routing == DeleteRouting::AllGenerations && !self.sealed.is_empty() {
+            self.pending_sealed_deletes.push(term.clone());
+        }
+        self.writer.delete_term(term)
+    }
+
+    /// Commits the current index and the deletes of the sealed indexes, then rolls over if the
+    /// current index reached one of the thresholds of the policy.
+    ///
+    /// Returns the opstamp of the commit of the index which was current before the rollover.
+    pub fn commit(&mut self) -> crate::Result<Opstamp> {
+        let opstamp = self.writer.commit()?;
+        self.current.reader.reload()?;
+        if !self.pending_sealed_deletes.is_empty() {
+            let deletes = std::mem::take(&mut self.pending_sealed_deletes);
+            for generation in &self.sealed {
+                let mut writer: IndexWriter = generation
+                    .index
+                    .writer_with_options(self.writer_options.clone())?;
+                for term in &deletes {
+                    writer.delete_term(term.clone())?;
+                }
+                writer.commit()?;
+                writer.wait_merging_threads()?;
+                generation.reader.reload()?;
+            }
+        }
+        if let Some(trigger) = self.rollover_trigger()? {
+            self.rollover(trigger)?;
+        }
+        Ok(opstamp)
+    }
+
+    /// Returns a searcher over all of the generations, sealed ones first.
+    ///
+    /// The searcher sees the documents committed so far.
+    pub fn multi_searcher(&self) -> crate::Result<MultiSearcher> {
+        let searchers = self
+            .sealed
+            .iter()
+            .chain(std::iter::once(&self.current))
+            .map(|generation| generation.reader.searcher())
+            .collect();
+        MultiSearcher::new(searchers)
+    }
+
+    fn rollover_trigger(&self) -> crate::Result<Option<RolloverTrigger>> {
+        if let Some(max_docs) = self.policy.max_docs {
+            if self.current.num_docs()? >= max_docs {
+                return Ok(Some(RolloverTrigger::MaxDocs));
+            }
+        }
+        if let Some(max_bytes) = self.policy.max_bytes {
+            if self.current.num_bytes()? >= max_bytes {
+                return Ok(Some(RolloverTrigger::MaxBytes));
+            }
+        }
+        if let Some(max_age) = self.policy.max_age {
+            if self.opened_at.elapsed() >= max_age {
+                return Ok(Some(RolloverTrigger::MaxAge));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Seals the current generation, and starts writing in the next one.
+    fn rollover(&mut self, trigger: RolloverTrigger) -> crate::Result<()> {
+        let new_generation = self.current.generation + 1;
+        let next = Generation::open_or_create(&self.base_dir, new_generation, &self.schema)?;
+        let next_writer = next
+            .index
+            .writer_with_options(self.writer_options.clone())?;
+        let mut sealed_writer = std::mem::replace(&mut self.writer, next_writer);
+        if self.policy.force_merge_on_seal {
+            let segment_ids = self.current.index.searchable_segment_ids()?;
+            if segment_ids.len() > 1 {
+                sealed_writer.merge(&segment_ids).wait()?;
+            }
+        }
+        sealed_writer.wait_merging_threads()?;
+        self.current.reader.reload()?;
+        let sealed = std::mem::replace(&mut self.current, next);
+        self.opened_at = Instant::now();
+        let event = RolloverEvent {
+            trigger,
+            sealed_generation: sealed.generation,
+            sealed_path: sealed.path.clone(),
+            sealed_num_docs: sealed.num_docs()?,
+            new_generation,
+            new_path: self.current.path.clone(),
+        };
+        self.sealed.push(sealed);
+        if let Some(on_rollover) = self.on_rollover.as_mut() {
+            on_rollover(&event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{DeleteRouting, RolloverEvent, RolloverPolicy, RolloverTrigger, RolloverWriter};
+    use crate::collector::{Count, DocSetCollector};
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STORED, STRING, TEXT};
+    use crate::{Index, TantivyDocument, Term};
+
+    fn schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("id", INDEXED | FAST | STORED);
+        schema_builder.add_text_field("tag", STRING);
+        schema_builder.add_text_field("body", TEXT);
+        schema_builder.build()
+    }
+
+    fn doc(schema: &Schema, id: u64, tag: &str) -> TantivyDocument {
+        let id_field = schema.get_field("id").unwrap();
+        let tag_field = schema.get_field("tag").unwrap();
+        let body_field = schema.get_field("body").unwrap();
+        doc!(
+            id_field => id,
+            tag_field => tag,
+            body_field => "some log line with a few words in it",
+        )
+    }
+
+    fn num_docs(index: &Index) -> u64 {
+        index.reader().unwrap().searcher().num_docs()
+    }
+
+    fn generation_dirs(writer: &RolloverWriter) -> Vec<String> {
+        let mut dirs: Vec<String> = std::fs::read_dir(&writer.base_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        dirs.sort();
+        dirs
+    }
+
+    #[test]
+    fn test_rollover_max_docs() -> crate::Result<()> {
+        let base_dir = tempfile::TempDir::new()?;
+        let schema = schema();
+        let events: Arc<Mutex<Vec<RolloverEvent>>> = Default::default();
+        let events_clone = events.clone();
+        let policy = RolloverPolicy {
+            max_docs: Some(10),
+            force_merge_on_seal: true,
+            ..Default::default()
+        };
+        let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy)?
+            .with_rollover_callback(move |event| events_clone.lock().unwrap().push(event.clone()));
+
+        for id in 0..25u64 {
+            writer.add_document(doc(&schema, id, "a"))?;
+            if id % 4 == 3 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        // Commits after the 12th and 24th documents triggered the rollovers.
+        assert_eq!(
+            generation_dirs(&writer),
+            vec![
+                "generation-000000",
+                "generation-000001",
+                "generation-000002"
+            ]
+        );
+        assert_eq!(writer.current_generation(), 2);
+        let sealed = writer.sealed_indexes();
+        assert_eq!(sealed.len(), 2);
+        assert_eq!(num_docs(sealed[0]), 12);
+        assert_eq!(num_docs(sealed[1]), 12);
+        assert_eq!(num_docs(writer.current_index()), 1);
+        // Sealed indexes are force-merged.
+        assert_eq!(sealed[0].searchable_segment_ids()?.len(), 1);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].trigger, RolloverTrigger::MaxDocs);
+        assert_eq!(events[0].sealed_generation, 0);
+        assert_eq!(events[0].sealed_num_docs, 12);
+        assert_eq!(events[1].new_generation, 2);
+        assert_eq!(
+            events[1].new_path,
+            base_dir.path().join("generation-000002")
+        );
+
+        // Every document is seen exactly once.
+        let multi_searcher = writer.multi_searcher()?;
+        assert_eq!(multi_searcher.search(&AllQuery, &Count)?, 25);
+        let mut ids: Vec<u64> = multi_searcher
+            .search(&AllQuery, &DocSetCollector)?
+            .into_iter()
+            .map(|doc_address| {
+                let doc: TantivyDocument = multi_searcher.doc(doc_address).unwrap();
+                let id_field = schema.get_field("id").unwrap();
+                crate::schema::Value::as_u64(&doc.get_first(id_field).unwrap()).unwrap()
+            })
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (0..25).collect::<Vec<u64>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_max_bytes() -> crate::Result<()> {
+        let base_dir = tempfile::TempDir::new()?;
+        let schema = schema();
+        let policy = RolloverPolicy {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy)?;
+        // Empty commits do not roll over.
+        writer.commit()?;
+        assert_eq!(writer.current_generation(), 0);
+        writer.add_document(doc(&schema, 0, "a"))?;
+        writer.commit()?;
+        assert_eq!(writer.current_generation(), 1);
+        writer.add_document(doc(&schema, 1, "a"))?;
+        writer.commit()?;
+        assert_eq!(writer.current_generation(), 2);
+        assert_eq!(writer.multi_searcher()?.search(&AllQuery, &Count)?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_max_age() -> crate::Result<()> {
+        let base_dir = tempfile::TempDir::new()?;
+        let schema = schema();
+        let policy = RolloverPolicy {
+            max_age: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy)?;
+        writer.add_document(doc(&schema, 0, "a"))?;
+        writer.commit()?;
+        assert_eq!(writer.current_generation(), 0);
+        std::thread::sleep(Duration::from_millis(60));
+        writer.add_document(doc(&schema, 1, "a"))?;
+        writer.commit()?;
+        assert_eq!(writer.current_generation(), 1);
+        assert_eq!(num_docs(writer.sealed_indexes()[0]), 2);
+        assert_eq!(num_docs(writer.current_index()), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_sealed_indexes_receive_no_documents() -> crate::Result<()> {
+        let base_dir = tempfile::TempDir::new()?;
+        let schema = schema();
+        let policy = RolloverPolicy {
+            max_docs: Some(2),
+            ..Default::default()
+        };
+        let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy.clone())?;
+        writer.add_document(doc(&schema, 0, "a"))?;
+        writer.add_document(doc(&schema, 1, "a"))?;
+        writer.commit()?;
+        let sealed_opstamp = writer.sealed_indexes()[0].load_metas()?.opstamp;
+        for id in 2..5 {
+            writer.add_document(doc(&schema, id, "a"))?;
+        }
+        writer.commit()?;
+        assert_eq!(
+            writer.sealed_indexes()[0].load_metas()?.opstamp,
+            sealed_opstamp
+        );
+        assert_eq!(num_docs(writer.sealed_indexes()[0]), 2);
+        assert_eq!(num_docs(writer.sealed_indexes()[1]), 3);
+        drop(writer);
+
+        // Reopening resumes writing into the last generation.
+        let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy)?;
+        assert_eq!(writer.current_generation(), 2);
+        assert_eq!(writer.sealed_indexes().len(), 2);
+        writer.add_document(doc(&schema, 5, "a"))?;
+        writer.commit()?;
+        assert_eq!(writer.multi_searcher()?.search(&AllQuery, &Count)?, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_delete_routing() -> crate::Result<()> {
+        let schema = schema();
+        let tag_field = schema.get_field("tag").unwrap();
+        let query = TermQuery::new(
+            Term::from_field_text(tag_field, "b"),
+            IndexRecordOption::Basic,
+        );
+        for (delete_routing, expected_num_docs) in [
+            (DeleteRouting::AllGenerations, 2),
+            (DeleteRouting::ActiveOnly, 3),
+        ] {
+            let base_dir = tempfile::TempDir::new()?;
+            let policy = RolloverPolicy {
+                max_docs: Some(2),
+                ..Default::default()
+            };
+            let mut writer = RolloverWriter::new(base_dir.path(), schema.clone(), policy)?
+                .with_delete_routing(delete_routing);
+            writer.add_document(doc(&schema, 0, "a"))?;
+            writer.add_document(doc(&schema, 1, "b"))?;
+            writer.commit()?;
+            writer.add_document(doc(&schema, 2, "a"))?;
+            writer.add_document(doc(&schema, 3, "b"))?;
+            writer.delete_term(Term::from_field_text(tag_field, "b"))?;
+            writer.commit()?;
+            assert_eq!(writer.current_generation(), 1);
+            let multi_searcher = writer.multi_searcher()?;
+            assert_eq!(
+                multi_searcher.search(&AllQuery, &Count)?,
+                expected_num_docs,
+                "{delete_routing:?}"
+            );
+            assert_eq!(
+                multi_searcher.search(&query, &Count)?,
+                expected_num_docs - 2
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_schema_mismatch() -> crate::Result<()> {
+        let base_dir = tempfile::TempDir::new()?;
+        RolloverWriter::new(base_dir.path(), schema(), RolloverPolicy::default())?;
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("id", FAST);
+        let err = RolloverWriter::new(
+            base_dir.path(),
+            schema_builder.build(),
+            RolloverPolicy::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, crate::TantivyError::SchemaError(_)));
+        Ok(())
+    }
+}
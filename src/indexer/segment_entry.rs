@@ -4,6 +4,7 @@ use common::BitSet;
 
 use crate::index::{SegmentId, SegmentMeta};
 use crate::indexer::delete_queue::DeleteCursor;
+use crate::indexer::index_writer::SoftDeletedDocs;
 
 /// A segment entry describes the state of
 /// a given segment, at a given instant.
@@ -11,12 +12,14 @@ use crate::indexer::delete_queue::DeleteCursor;
 /// In addition to segment `meta`,
 /// it contains a few transient states
 /// - `alive_bitset` is a bitset describing documents that were alive during the commit itself.
+/// - `soft_deleted_docs` describes the documents that were soft-deleted during the commit itself.
 /// - `delete_cursor` is the position in the delete queue. Deletes happening before the cursor are
 ///   reflected either in the .del file or in the `alive_bitset`.
 #[derive(Clone)]
 pub struct SegmentEntry {
     meta: SegmentMeta,
     alive_bitset: Option<BitSet>,
+    soft_deleted_docs: Option<SoftDeletedDocs>,
     delete_cursor: DeleteCursor,
 }
 
@@ -30,10 +33,20 @@ impl SegmentEntry {
         SegmentEntry {
             meta: segment_meta,
             alive_bitset,
+            soft_deleted_docs: None,
             delete_cursor,
         }
     }
 
+    /// Sets the documents soft-deleted during the commit itself.
+    pub(crate) fn with_soft_deleted_docs(
+        mut self,
+        soft_deleted_docs: Option<SoftDeletedDocs>,
+    ) -> SegmentEntry {
+        self.soft_deleted_docs = soft_deleted_docs;
+        self
+    }
+
     /// Return a reference to the segment entry deleted bitset.
     ///
     /// `DocId` in this bitset are flagged as deleted.
@@ -41,6 +54,11 @@ impl SegmentEntry {
         self.alive_bitset.as_ref()
     }
 
+    /// Return a reference to the documents soft-deleted during the commit itself.
+    pub(crate) fn soft_deleted_docs(&self) -> Option<&SoftDeletedDocs> {
+        self.soft_deleted_docs.as_ref()
+    }
+
     /// Set the `SegmentMeta` for this segment.
     pub fn set_meta(&mut self, segment_meta: SegmentMeta) {
         self.meta = segment_meta;
@@ -1,4 +1,5 @@
 use std::collections::hash_set::HashSet;
+use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
@@ -12,6 +13,9 @@ use crate::indexer::SegmentEntry;
 struct SegmentRegisters {
     uncommitted: SegmentRegister,
     committed: SegmentRegister,
+    /// Segments replacing a committed or uncommitted segment at the next commit, by id of the
+    /// segment they replace.
+    replacements: HashMap<SegmentId, SegmentEntry>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -72,6 +76,7 @@ impl SegmentManager {
             registers: RwLock::new(SegmentRegisters {
                 uncommitted: SegmentRegister::default(),
                 committed: SegmentRegister::new(segment_metas, delete_cursor),
+                replacements: HashMap::new(),
             }),
         }
     }
@@ -81,23 +86,51 @@ impl SegmentManager {
         in_merge_segment_ids: &HashSet<SegmentId>,
     ) -> (Vec<SegmentMeta>, Vec<SegmentMeta>) {
         let registers_lock = self.read();
+        // The segments being replaced cannot be merged.
+        let mut unmergeable_segment_ids = in_merge_segment_ids.clone();
+        unmergeable_segment_ids.extend(registers_lock.replacements.keys().copied());
         (
             registers_lock
                 .committed
-                .get_mergeable_segments(in_merge_segment_ids),
+                .get_mergeable_segments(&unmergeable_segment_ids),
             registers_lock
                 .uncommitted
-                .get_mergeable_segments(in_merge_segment_ids),
+                .get_mergeable_segments(&unmergeable_segment_ids),
         )
     }
-    /// Returns all of the segment entries (committed or uncommitted)
+    /// Returns the committed and the uncommitted segment entries, in that order.
+    pub fn segment_entries_by_status(&self) -> (Vec<SegmentEntry>, Vec<SegmentEntry>) {
+        let registers_lock = self.read();
+        (
+            registers_lock.committed.segment_entries(),
+            registers_lock.uncommitted.segment_entries(),
+        )
+    }
+
+    /// Returns all of the segment entries (committed or uncommitted), the segments being
+    /// replaced being swapped for their replacement.
     pub fn segment_entries(&self) -> Vec<SegmentEntry> {
         let registers_lock = self.read();
         let mut segment_entries = registers_lock.uncommitted.segment_entries();
         segment_entries.extend(registers_lock.committed.segment_entries());
+        for segment_entry in &mut segment_entries {
+            if let Some(replacement) = registers_lock.replacements.get(&segment_entry.segment_id())
+            {
+                *segment_entry = replacement.clone();
+            }
+        }
         segment_entries
     }
 
+    /// Returns the entry of a committed or uncommitted segment.
+    pub(crate) fn segment_entry(&self, segment_id: &SegmentId) -> Option<SegmentEntry> {
+        let registers_lock = self.read();
+        registers_lock
+            .uncommitted
+            .get(segment_id)
+            .or_else(|| registers_lock.committed.get(segment_id))
+    }
+
     // Lock poisoning should never happen :
     // The lock is acquired and released within this class,
     // and the operations cannot panic.
@@ -132,12 +165,14 @@ impl SegmentManager {
         let mut registers_lock = self.write();
         registers_lock.committed.clear();
         registers_lock.uncommitted.clear();
+        registers_lock.replacements.clear();
     }
 
     pub fn commit(&self, segment_entries: Vec<SegmentEntry>) {
         let mut registers_lock = self.write();
         registers_lock.committed.clear();
         registers_lock.uncommitted.clear();
+        registers_lock.replacements.clear();
         for segment_entry in segment_entries {
             registers_lock.committed.add_segment_entry(segment_entry);
         }
@@ -150,6 +185,12 @@ impl SegmentManager {
     /// uncommitted.
     pub fn start_merge(&self, segment_ids: &[SegmentId]) -> crate::Result<Vec<SegmentEntry>> {
         let registers_lock = self.read();
+        if let Some(&segment_id) = segment_ids
+            .iter()
+            .find(|segment_id| registers_lock.replacements.contains_key(segment_id))
+        {
+            return Err(TantivyError::SegmentBusy { segment_id });
+        }
         let mut segment_entries = vec![];
         if registers_lock.uncommitted.contains_all(segment_ids) {
             for segment_id in segment_ids {
@@ -181,6 +222,58 @@ impl SegmentManager {
         let mut registers_lock = self.write();
         registers_lock.uncommitted.add_segment_entry(segment_entry);
     }
+
+    /// Registers `segment_meta` as the replacement of the segment `replaced_segment_id` at the
+    /// next commit.
+    ///
+    /// The replacement inherits the position of the replaced segment in the delete queue, and
+    /// its pending deletes.
+    pub(crate) fn replace_segment(
+        &self,
+        replaced_segment_id: SegmentId,
+        segment_meta: SegmentMeta,
+    ) -> crate::Result<()> {
+        let mut registers_lock = self.write();
+        if registers_lock
+            .replacements
+            .contains_key(&replaced_segment_id)
+        {
+            return Err(TantivyError::SegmentBusy {
+                segment_id: replaced_segment_id,
+            });
+        }
+        let new_segment_id = segment_meta.id();
+        if registers_lock.uncommitted.contains_all(&[new_segment_id])
+            || registers_lock.committed.contains_all(&[new_segment_id])
+            || registers_lock
+                .replacements
+                .values()
+                .any(|segment_entry| segment_entry.segment_id() == new_segment_id)
+        {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The segment {new_segment_id:?} is already in the index"
+            )));
+        }
+        let mut segment_entry = registers_lock
+            .uncommitted
+            .get(&replaced_segment_id)
+            .or_else(|| registers_lock.committed.get(&replaced_segment_id))
+            .ok_or_else(|| {
+                TantivyError::InvalidArgument(format!(
+                    "The segment {replaced_segment_id:?} to replace is not in the index"
+                ))
+            })?;
+        segment_entry.set_meta(segment_meta);
+        registers_lock
+            .replacements
+            .insert(replaced_segment_id, segment_entry);
+        Ok(())
+    }
+
+    /// Forgets the replacement of the segment `replaced_segment_id`.
+    pub(crate) fn cancel_replacement(&self, replaced_segment_id: &SegmentId) {
+        self.write().replacements.remove(replaced_segment_id);
+    }
     // Replace a list of segments for their equivalent merged segment.
     //
     // Returns true if these segments are committed, false if the merge segments are uncommitted.
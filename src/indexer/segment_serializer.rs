@@ -1,10 +1,11 @@
 use common::TerminatingWrite;
 
 use crate::directory::WritePtr;
+use crate::fastfield::SegmentColumnStats;
 use crate::fieldnorm::FieldNormsSerializer;
 use crate::index::{Segment, SegmentComponent};
 use crate::postings::InvertedIndexSerializer;
-use crate::store::StoreWriter;
+use crate::store::{Compressor, StoreWriter};
 
 /// Segment serializer is in charge of laying out on disk
 /// the data accumulated and sorted by the `SegmentWriter`.
@@ -17,14 +18,28 @@ pub struct SegmentSerializer {
 }
 
 impl SegmentSerializer {
-    /// Creates a new `SegmentSerializer`.
-    pub fn for_segment(mut segment: Segment) -> crate::Result<SegmentSerializer> {
+    /// Creates a new `SegmentSerializer` for a segment created by flushing new documents.
+    pub fn for_segment(segment: Segment) -> crate::Result<SegmentSerializer> {
+        let store_compressor = segment.index().settings().store_compressor_on_flush();
+        Self::with_store_compressor(segment, store_compressor)
+    }
+
+    /// Creates a new `SegmentSerializer` for a segment created by a merge.
+    pub fn for_merge(segment: Segment) -> crate::Result<SegmentSerializer> {
+        let store_compressor = segment.index().settings().store_compressor_on_merge();
+        Self::with_store_compressor(segment, store_compressor)
+    }
+
+    fn with_store_compressor(
+        mut segment: Segment,
+        store_compressor: Compressor,
+    ) -> crate::Result<SegmentSerializer> {
         let settings = segment.index().settings().clone();
         let store_writer = {
             let store_write = segment.open_write(SegmentComponent::Store)?;
             StoreWriter::new(
                 store_write,
-                settings.docstore_compression,
+                store_compressor,
                 settings.docstore_blocksize,
                 settings.docstore_compress_dedicated_thread,
             )?
@@ -45,6 +60,11 @@ impl SegmentSerializer {
         })
     }
 
+    /// Returns the `Compressor` of the doc store.
+    pub fn store_compressor(&self) -> Compressor {
+        self.store_writer.compressor()
+    }
+
     /// The memory used (inclusive childs)
     pub fn mem_usage(&self) -> usize {
         self.store_writer.mem_usage()
@@ -84,6 +104,11 @@ impl SegmentSerializer {
         self.fast_field_write.terminate()?;
         self.postings_serializer.close()?;
         self.store_writer.close()?;
+        let column_stats_fields = &self.segment.index().settings().column_stats_fields;
+        if !column_stats_fields.is_empty() {
+            let column_stats_fields = column_stats_fields.clone();
+            SegmentColumnStats::write(&mut self.segment, &column_stats_fields)?;
+        }
         Ok(())
     }
 }
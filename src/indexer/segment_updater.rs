@@ -1,28 +1,47 @@
 use std::any::Any;
 use std::borrow::BorrowMut;
 use std::collections::HashSet;
+use std::future::Future;
 use std::io::Write;
 use std::ops::Deref;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use common::{BitSet, ReadOnlyBitSet};
 use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use super::segment_manager::SegmentManager;
-use crate::core::META_FILEPATH;
-use crate::directory::{Directory, DirectoryClone, GarbageCollectionResult};
-use crate::fastfield::AliveBitSet;
-use crate::index::{Index, IndexMeta, IndexSettings, Segment, SegmentId, SegmentMeta};
+use crate::core::{COMMIT_PAYLOADS_FILEPATH, COMMIT_POINTS_FILEPATH, META_FILEPATH};
+use crate::directory::{
+    Directory, DirectoryClone, GarbageCollectionResult, IoBudget, TerminatingWrite,
+};
+use crate::fastfield::{write_alive_bitset, AliveBitSet};
+use crate::index::{
+    Index, IndexMeta, IndexSettings, Segment, SegmentComponent, SegmentId, SegmentMeta,
+    SegmentReader,
+};
+use crate::indexer::commit_payload::save_commit_payload;
+use crate::indexer::commit_points::{
+    list_commit_point_files, release_commit_point, save_commit_point,
+};
 use crate::indexer::delete_queue::DeleteCursor;
 use crate::indexer::index_writer::advance_deletes;
 use crate::indexer::merge_operation::MergeOperationInventory;
 use crate::indexer::merger::IndexMerger;
+use crate::indexer::reclaim_report::{
+    num_deleted_docs_with_pending, segment_reclaim_report, ReclaimReport,
+};
 use crate::indexer::segment_manager::SegmentsStatus;
 use crate::indexer::stamper::Stamper;
 use crate::indexer::{
-    DefaultMergePolicy, MergeCandidate, MergeOperation, MergePolicy, SegmentEntry,
-    SegmentSerializer,
+    CommitValidator, DefaultMergePolicy, KeepSoftDeleted, MergeCandidate, MergeCandidatesFuture,
+    MergeContext, MergeMonitor, MergeOperation, MergePolicy, SegmentEntry, SegmentSerializer,
+    SoftDeleteRetentionPolicy,
 };
 use crate::{FutureResult, Opstamp, TantivyError};
 
@@ -72,22 +91,61 @@ impl Deref for SegmentUpdater {
     }
 }
 
+/// Wakes up the thread blocked in [`block_on_with_deadline`].
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls `future` on the current thread until it resolves, or until `deadline` is reached.
+fn block_on_with_deadline<T>(
+    mut future: Pin<Box<dyn Future<Output = T> + Send>>,
+    deadline: Instant,
+) -> Option<T> {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return Some(output);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
 fn garbage_collect_files(
     segment_updater: SegmentUpdater,
 ) -> crate::Result<GarbageCollectionResult> {
     info!("Running garbage collection");
     let mut index = segment_updater.index.clone();
-    index
-        .directory_mut()
-        .garbage_collect(move || segment_updater.list_files())
+    // The files of the retained commit points are listed first, so that failing to read them
+    // does not delete them.
+    let commit_point_files = list_commit_point_files(index.directory(), index.inventory())?;
+    index.directory_mut().garbage_collect(move || {
+        let mut files = segment_updater.list_files();
+        files.extend(commit_point_files);
+        files
+    })
 }
 
 /// Merges a list of segments the list of segment givens in the `segment_entries`.
 /// This function happens in the calling thread and is computationally expensive.
+///
+/// If the merge fails, e.g. because it was cancelled, the files written for the merged segment
+/// are deleted.
 fn merge(
     index: &Index,
-    mut segment_entries: Vec<SegmentEntry>,
+    segment_entries: Vec<SegmentEntry>,
     target_opstamp: Opstamp,
+    soft_delete_retention_policy: &dyn SoftDeleteRetentionPolicy,
+    io_budget: &IoBudget,
+    monitor: MergeMonitor,
 ) -> crate::Result<Option<SegmentEntry>> {
     let num_docs = segment_entries
         .iter()
@@ -96,10 +154,43 @@ fn merge(
     if num_docs == 0 {
         return Ok(None);
     }
+    monitor.check_cancelled()?;
 
-    // first we need to apply deletes to our segment.
-    let merged_segment = index.new_segment();
+    // The files of the merged segment are throttled, to leave IO to indexing and search.
+    let segment_metas: Vec<SegmentMeta> = segment_entries
+        .iter()
+        .map(|segment_entry| segment_entry.meta().clone())
+        .collect();
+    let merged_segment = index
+        .new_segment_for_merge(&segment_metas, target_opstamp)
+        .with_io_budget(io_budget.clone());
+    let merge_res = write_merged_segment(
+        index,
+        &merged_segment,
+        segment_entries,
+        target_opstamp,
+        soft_delete_retention_policy,
+        monitor,
+    );
+    if merge_res.is_err() {
+        for component in SegmentComponent::iterator() {
+            let path = merged_segment.meta().relative_path(*component);
+            // Most components were not written if the merge stopped early.
+            let _ = index.directory().delete(&path);
+        }
+    }
+    merge_res.map(Some)
+}
 
+/// Writes the merge of the segments of `segment_entries` into `merged_segment`.
+fn write_merged_segment(
+    index: &Index,
+    merged_segment: &Segment,
+    mut segment_entries: Vec<SegmentEntry>,
+    target_opstamp: Opstamp,
+    soft_delete_retention_policy: &dyn SoftDeleteRetentionPolicy,
+    monitor: MergeMonitor,
+) -> crate::Result<SegmentEntry> {
     // First we apply all of the delete to the merged segment, up to the target opstamp.
     for segment_entry in &mut segment_entries {
         let segment = index.segment(segment_entry.meta().clone());
@@ -113,18 +204,153 @@ fn merge(
         .map(|segment_entry| index.segment(segment_entry.meta().clone()))
         .collect();
 
+    // The expired soft-deleted documents are left out of the merged segment, like deleted ones.
+    let alive_bitsets = segments
+        .iter()
+        .map(|segment| expired_soft_deletes_alive_bitset(segment, soft_delete_retention_policy))
+        .collect::<crate::Result<Vec<_>>>()?;
+
     // An IndexMerger is like a "view" of our merged segments.
-    let merger: IndexMerger = IndexMerger::open(index.schema(), &segments[..])?;
+    let mut merger: IndexMerger =
+        IndexMerger::open_with_custom_alive_set(index.schema(), &segments[..], alive_bitsets)?;
+    merger.set_monitor(monitor);
+    let merged_soft_deleted_docs = merger.merged_soft_deleted_docs();
 
     // ... we just serialize this index merger in our new segment to merge the segments.
-    let segment_serializer = SegmentSerializer::for_segment(merged_segment.clone())?;
+    let segment_serializer = SegmentSerializer::for_merge(merged_segment.clone())?;
+    let store_compressor = segment_serializer.store_compressor();
 
     let num_docs = merger.write(segment_serializer)?;
 
     let merged_segment_id = merged_segment.id();
 
-    let segment_meta = index.new_segment_meta(merged_segment_id, num_docs);
-    Ok(Some(SegmentEntry::new(segment_meta, delete_cursor, None)))
+    let mut segment_meta = index
+        .new_segment_meta(merged_segment_id, num_docs)
+        .with_store_compressor(store_compressor);
+    if let Some((not_soft_deleted_bitset, tombstone_fields)) = merged_soft_deleted_docs {
+        let num_soft_deleted_docs = num_docs - not_soft_deleted_bitset.len() as u32;
+        let mut segment = index.segment(segment_meta).with_soft_delete_meta(
+            num_soft_deleted_docs,
+            target_opstamp,
+            tombstone_fields,
+        );
+        let mut soft_delete_file = segment.open_write(SegmentComponent::SoftDelete)?;
+        write_alive_bitset(&not_soft_deleted_bitset, &mut soft_delete_file)?;
+        soft_delete_file.terminate()?;
+        segment_meta = segment.meta().clone();
+    }
+    Ok(SegmentEntry::new(segment_meta, delete_cursor, None))
+}
+
+/// Checks that the segment `segment_meta` can replace the segment `replaced_segment_meta`, and
+/// carries the deletes of the replaced segment over to it.
+///
+/// The segments must have the same documents, in the same order, written with the same schema.
+/// The replacement either has the same deleted and soft-deleted documents as the replaced
+/// segment, or none, in which case the delete files of the replaced segment are copied.
+fn prepare_replacement_segment(
+    index: &Index,
+    replaced_segment_meta: &SegmentMeta,
+    segment_meta: SegmentMeta,
+) -> crate::Result<SegmentMeta> {
+    let segment_id = segment_meta.id();
+    let invalid_replacement = |reason: &str| {
+        TantivyError::InvalidArgument(format!(
+            "The segment {segment_id:?} cannot replace the segment {:?}: {reason}",
+            replaced_segment_meta.id()
+        ))
+    };
+    if segment_id == replaced_segment_meta.id() {
+        return Err(invalid_replacement("the segments are the same"));
+    }
+    if segment_meta.max_doc() != replaced_segment_meta.max_doc() {
+        return Err(invalid_replacement("the numbers of documents differ"));
+    }
+    let replaced_segment = index.segment(replaced_segment_meta.clone());
+    let replaced_reader = SegmentReader::open(&replaced_segment)?;
+    let mut segment = index.segment(segment_meta);
+    let reader = SegmentReader::open(&segment)?;
+    // The segment files do not record their schema: the fast field columns of the segments
+    // are compared instead.
+    let fast_field_columns = |reader: &SegmentReader| -> crate::Result<Vec<_>> {
+        let mut columns: Vec<_> = reader
+            .try_fast_fields()?
+            .columnar()
+            .list_columns()?
+            .into_iter()
+            .map(|(column_name, handle)| (column_name, handle.column_type()))
+            .collect();
+        columns.sort();
+        Ok(columns)
+    };
+    if fast_field_columns(&reader)? != fast_field_columns(&replaced_reader)? {
+        return Err(invalid_replacement(
+            "the segments were not written with the same schema",
+        ));
+    }
+    let max_doc = reader.max_doc();
+    if !segment.meta().has_deletes() {
+        if let Some(delete_opstamp) = replaced_segment_meta.delete_opstamp() {
+            let delete_bytes = replaced_segment
+                .open_read(SegmentComponent::Delete)?
+                .read_bytes()?;
+            segment =
+                segment.with_delete_meta(replaced_segment_meta.num_deleted_docs(), delete_opstamp);
+            let mut delete_file = segment.open_write(SegmentComponent::Delete)?;
+            delete_file.write_all(delete_bytes.as_slice())?;
+            delete_file.terminate()?;
+        }
+    } else if (0..max_doc).any(|doc| reader.is_deleted(doc) != replaced_reader.is_deleted(doc)) {
+        return Err(invalid_replacement("the deleted documents differ"));
+    }
+    if !segment.meta().has_soft_deletes() {
+        if let Some(soft_delete_opstamp) = replaced_segment_meta.soft_delete_opstamp() {
+            let soft_delete_bytes = replaced_segment
+                .open_read(SegmentComponent::SoftDelete)?
+                .read_bytes()?;
+            segment = segment.with_soft_delete_meta(
+                replaced_segment_meta.num_soft_deleted_docs(),
+                soft_delete_opstamp,
+                replaced_segment_meta
+                    .soft_delete_tombstone_fields()
+                    .to_vec(),
+            );
+            let mut soft_delete_file = segment.open_write(SegmentComponent::SoftDelete)?;
+            soft_delete_file.write_all(soft_delete_bytes.as_slice())?;
+            soft_delete_file.terminate()?;
+        }
+    } else if (0..max_doc)
+        .any(|doc| reader.is_soft_deleted(doc) != replaced_reader.is_soft_deleted(doc))
+    {
+        return Err(invalid_replacement("the soft-deleted documents differ"));
+    }
+    Ok(segment.meta().clone())
+}
+
+/// Returns the documents of the segment that should be kept by a merge, if some soft-deleted
+/// documents expired according to the retention policy.
+fn expired_soft_deletes_alive_bitset(
+    segment: &Segment,
+    soft_delete_retention_policy: &dyn SoftDeleteRetentionPolicy,
+) -> crate::Result<Option<AliveBitSet>> {
+    if !segment.meta().has_soft_deletes() {
+        return Ok(None);
+    }
+    let segment_reader = SegmentReader::open(segment)?;
+    let mut alive_bitset = BitSet::with_max_value_and_full(segment_reader.max_doc());
+    let mut has_expired_docs = false;
+    for doc in segment_reader.doc_ids_alive() {
+        if segment_reader.is_soft_deleted(doc)
+            && soft_delete_retention_policy.is_expired(&segment_reader, doc)
+        {
+            alive_bitset.remove(doc);
+            has_expired_docs = true;
+        }
+    }
+    if !has_expired_docs {
+        return Ok(None);
+    }
+    Ok(Some(AliveBitSet::from(ReadOnlyBitSet::from(&alive_bitset))))
 }
 
 /// Advanced: Merges a list of segments from different indices in a new index.
@@ -220,10 +446,13 @@ pub fn merge_filtered_segments<T: Into<Box<dyn Directory>>>(
     let merged_segment_id = merged_segment.id();
     let merger: IndexMerger =
         IndexMerger::open_with_custom_alive_set(merged_index.schema(), segments, filter_doc_ids)?;
-    let segment_serializer = SegmentSerializer::for_segment(merged_segment)?;
+    let segment_serializer = SegmentSerializer::for_merge(merged_segment)?;
+    let store_compressor = segment_serializer.store_compressor();
     let num_docs = merger.write(segment_serializer)?;
 
-    let segment_meta = merged_index.new_segment_meta(merged_segment_id, num_docs);
+    let segment_meta = merged_index
+        .new_segment_meta(merged_segment_id, num_docs)
+        .with_store_compressor(store_compressor);
 
     let stats = format!(
         "Segments Merge: [{}]",
@@ -264,9 +493,14 @@ pub(crate) struct InnerSegmentUpdater {
     index: Index,
     segment_manager: SegmentManager,
     merge_policy: RwLock<Arc<dyn MergePolicy>>,
+    merge_policy_context: RwLock<Option<Arc<dyn Any + Send + Sync>>>,
+    merge_policy_timeout: Duration,
+    soft_delete_retention_policy: RwLock<Arc<dyn SoftDeleteRetentionPolicy>>,
+    commit_validators: RwLock<Vec<Arc<dyn CommitValidator>>>,
     killed: AtomicBool,
     stamper: Stamper,
     merge_operations: MergeOperationInventory,
+    merge_io_budget: IoBudget,
 }
 
 impl SegmentUpdater {
@@ -275,6 +509,8 @@ impl SegmentUpdater {
         stamper: Stamper,
         delete_cursor: &DeleteCursor,
         num_merge_threads: usize,
+        merge_io_budget: IoBudget,
+        merge_policy_timeout: Duration,
     ) -> crate::Result<SegmentUpdater> {
         let segments = index.searchable_segment_metas()?;
         let segment_manager = SegmentManager::from_segments(segments, delete_cursor);
@@ -313,12 +549,21 @@ impl SegmentUpdater {
             index,
             segment_manager,
             merge_policy: RwLock::new(Arc::new(DefaultMergePolicy::default())),
+            merge_policy_context: RwLock::new(None),
+            merge_policy_timeout,
+            soft_delete_retention_policy: RwLock::new(Arc::new(KeepSoftDeleted)),
+            commit_validators: Default::default(),
             killed: AtomicBool::new(false),
             stamper,
             merge_operations: Default::default(),
+            merge_io_budget,
         })))
     }
 
+    pub fn merge_io_budget(&self) -> &IoBudget {
+        &self.merge_io_budget
+    }
+
     pub fn get_merge_policy(&self) -> Arc<dyn MergePolicy> {
         self.merge_policy.read().unwrap().clone()
     }
@@ -328,6 +573,67 @@ impl SegmentUpdater {
         *self.merge_policy.write().unwrap() = arc_merge_policy;
     }
 
+    pub fn set_merge_policy_context(&self, context: Arc<dyn Any + Send + Sync>) {
+        *self.merge_policy_context.write().unwrap() = Some(context);
+    }
+
+    pub fn get_soft_delete_retention_policy(&self) -> Arc<dyn SoftDeleteRetentionPolicy> {
+        self.soft_delete_retention_policy.read().unwrap().clone()
+    }
+
+    pub fn set_soft_delete_retention_policy(
+        &self,
+        soft_delete_retention_policy: Box<dyn SoftDeleteRetentionPolicy>,
+    ) {
+        *self.soft_delete_retention_policy.write().unwrap() =
+            Arc::from(soft_delete_retention_policy);
+    }
+
+    pub fn commit_validators(&self) -> Vec<Arc<dyn CommitValidator>> {
+        self.commit_validators.read().unwrap().clone()
+    }
+
+    pub fn add_commit_validator(&self, commit_validator: Arc<dyn CommitValidator>) {
+        self.commit_validators
+            .write()
+            .unwrap()
+            .push(commit_validator);
+    }
+
+    /// Runs the commit validators on the segments of `segment_entries` which are new, or whose
+    /// deletes changed, since the last commit.
+    fn validate_commit(&self, segment_entries: &[SegmentEntry]) -> crate::Result<()> {
+        let commit_validators = self.commit_validators();
+        if commit_validators.is_empty() {
+            return Ok(());
+        }
+        let committed_meta = self.load_meta();
+        let is_unchanged = |segment_meta: &SegmentMeta| {
+            committed_meta
+                .segments
+                .iter()
+                .any(|committed_segment_meta| {
+                    committed_segment_meta.id() == segment_meta.id()
+                        && committed_segment_meta.delete_opstamp() == segment_meta.delete_opstamp()
+                })
+        };
+        let segments = segment_entries
+            .iter()
+            .map(SegmentEntry::meta)
+            .filter(|segment_meta| !is_unchanged(segment_meta))
+            .map(|segment_meta| self.index.segment(segment_meta.clone()));
+        self.index.search_executor().map(
+            |segment| {
+                let segment_reader = SegmentReader::open(&segment)?;
+                commit_validators
+                    .iter()
+                    .try_for_each(|commit_validator| commit_validator.validate(&segment_reader))
+            },
+            segments,
+        )?;
+        Ok(())
+    }
+
     fn schedule_task<T: 'static + Send, F: FnOnce() -> crate::Result<T> + 'static + Send>(
         &self,
         task: F,
@@ -354,6 +660,57 @@ impl SegmentUpdater {
         })
     }
 
+    /// Registers `segment_meta` as the replacement of the segment `replaced_segment_id` at the
+    /// next commit, see [`IndexWriter::replace_segment()`](crate::IndexWriter::replace_segment).
+    pub(crate) fn schedule_replace_segment(
+        &self,
+        replaced_segment_id: SegmentId,
+        segment_meta: SegmentMeta,
+    ) -> FutureResult<()> {
+        let segment_updater = self.clone();
+        self.schedule_task(move || {
+            let segment_busy = TantivyError::SegmentBusy {
+                segment_id: replaced_segment_id,
+            };
+            if segment_updater
+                .merge_operations
+                .segment_in_merge()
+                .contains(&replaced_segment_id)
+            {
+                return Err(segment_busy);
+            }
+            let replaced_segment_entry = segment_updater
+                .segment_manager
+                .segment_entry(&replaced_segment_id)
+                .ok_or_else(|| {
+                    TantivyError::InvalidArgument(format!(
+                        "The segment {replaced_segment_id:?} to replace is not in the index"
+                    ))
+                })?;
+            let segment_meta = prepare_replacement_segment(
+                &segment_updater.index,
+                replaced_segment_entry.meta(),
+                segment_meta,
+            )?;
+            segment_updater
+                .segment_manager
+                .replace_segment(replaced_segment_id, segment_meta)?;
+            // A merge of the replaced segment may have started before the replacement was
+            // registered. The merges starting after it fail.
+            if segment_updater
+                .merge_operations
+                .segment_in_merge()
+                .contains(&replaced_segment_id)
+            {
+                segment_updater
+                    .segment_manager
+                    .cancel_replacement(&replaced_segment_id);
+                return Err(segment_busy);
+            }
+            Ok(())
+        })
+    }
+
     /// Orders `SegmentManager` to remove all segments
     pub(crate) fn remove_all_segments(&self) {
         self.segment_manager.remove_all_segments();
@@ -418,6 +775,46 @@ impl SegmentUpdater {
         Ok(())
     }
 
+    /// Adds the payload of a commit to the retained commit payloads.
+    fn save_commit_payload(
+        &self,
+        opstamp: Opstamp,
+        payload: &str,
+        num_retained_payloads: usize,
+    ) -> crate::Result<()> {
+        if self.is_alive() {
+            save_commit_payload(
+                self.index.directory(),
+                opstamp,
+                payload,
+                num_retained_payloads,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds the last commit to the retained commit points.
+    fn save_commit_point(&self, num_retained_commit_points: usize) -> crate::Result<()> {
+        if self.is_alive() {
+            save_commit_point(
+                self.index.directory(),
+                self.index.inventory(),
+                &self.load_meta(),
+                num_retained_commit_points,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Removes a commit from the retained commit points, once the commits scheduled so far are
+    /// done.
+    pub(crate) fn schedule_release_commit_point(&self, opstamp: Opstamp) -> FutureResult<bool> {
+        let index = self.index.clone();
+        self.schedule_task(move || {
+            release_commit_point(index.directory(), index.inventory(), opstamp)
+        })
+    }
+
     pub fn schedule_garbage_collect(&self) -> FutureResult<GarbageCollectionResult> {
         let self_clone = self.clone();
         self.schedule_task(move || garbage_collect_files(self_clone))
@@ -435,6 +832,8 @@ impl SegmentUpdater {
             .flat_map(|segment_meta| segment_meta.list_files())
             .collect();
         files.insert(META_FILEPATH.to_path_buf());
+        files.insert(COMMIT_PAYLOADS_FILEPATH.to_path_buf());
+        files.insert(COMMIT_POINTS_FILEPATH.to_path_buf());
         files
     }
 
@@ -442,18 +841,31 @@ impl SegmentUpdater {
         &self,
         opstamp: Opstamp,
         payload: Option<String>,
+        num_retained_payloads: usize,
+        num_retained_commit_points: usize,
     ) -> FutureResult<Opstamp> {
         let segment_updater: SegmentUpdater = self.clone();
         self.schedule_task(move || {
             let segment_entries = segment_updater.purge_deletes(opstamp)?;
+            segment_updater.validate_commit(&segment_entries)?;
             segment_updater.segment_manager.commit(segment_entries);
-            segment_updater.save_metas(opstamp, payload)?;
+            segment_updater.save_metas(opstamp, payload.clone())?;
+            if let Some(payload) = payload.as_deref() {
+                segment_updater.save_commit_payload(opstamp, payload, num_retained_payloads)?;
+            }
+            segment_updater.save_commit_point(num_retained_commit_points)?;
             let _ = garbage_collect_files(segment_updater.clone());
             segment_updater.consider_merge_options();
             Ok(opstamp)
         })
     }
 
+    /// Returns the payload of the last commit, once the commits scheduled so far are done.
+    pub(crate) fn schedule_last_commit_payload(&self) -> FutureResult<Option<String>> {
+        let segment_updater = self.clone();
+        self.schedule_task(move || Ok(segment_updater.load_meta().payload.clone()))
+    }
+
     fn store_meta(&self, index_meta: &IndexMeta) {
         *self.active_index_meta.write().unwrap() = Arc::new(index_meta.clone());
     }
@@ -487,6 +899,16 @@ impl SegmentUpdater {
     pub fn start_merge(
         &self,
         merge_operation: MergeOperation,
+    ) -> FutureResult<Option<SegmentMeta>> {
+        self.start_merge_with_monitor(merge_operation, MergeMonitor::default())
+    }
+
+    /// Starts a merge like [`SegmentUpdater::start_merge()`], reporting its progress to
+    /// `monitor`, which can also cancel it.
+    pub(crate) fn start_merge_with_monitor(
+        &self,
+        merge_operation: MergeOperation,
+        monitor: MergeMonitor,
     ) -> FutureResult<Option<SegmentMeta>> {
         assert!(
             !merge_operation.segment_ids().is_empty(),
@@ -523,6 +945,9 @@ impl SegmentUpdater {
                     &segment_updater.index,
                     segment_entries,
                     merge_operation.target_opstamp(),
+                    segment_updater.get_soft_delete_retention_policy().as_ref(),
+                    &segment_updater.merge_io_budget,
+                    monitor,
                 )
             }));
             let merge_res = match merge_panic_res {
@@ -556,7 +981,7 @@ impl SegmentUpdater {
                         merge_operation.segment_ids().to_vec(),
                         merge_error
                     );
-                    if cfg!(test) {
+                    if cfg!(test) && !matches!(merge_error, TantivyError::MergeCancelled) {
                         panic!("{merge_error:?}");
                     }
                     let _send_result = merging_future_send.send(Err(merge_error));
@@ -573,38 +998,187 @@ impl SegmentUpdater {
             .get_mergeable_segments(&merge_segment_ids)
     }
 
-    fn consider_merge_options(&self) {
-        let (mut committed_segments, mut uncommitted_segments) = self.get_mergeable_segments();
-        if committed_segments.len() == 1 && committed_segments[0].num_deleted_docs() == 0 {
-            committed_segments.clear();
+    pub(crate) fn reclaimable_space_report(&self) -> crate::Result<ReclaimReport> {
+        let (committed_entries, uncommitted_entries) =
+            self.segment_manager.segment_entries_by_status();
+        let directory = self.index.directory();
+        let mut segments = Vec::with_capacity(committed_entries.len() + uncommitted_entries.len());
+        for segment_entry in &committed_entries {
+            segments.push(segment_reclaim_report(directory, segment_entry, true)?);
         }
-        if uncommitted_segments.len() == 1 && uncommitted_segments[0].num_deleted_docs() == 0 {
-            uncommitted_segments.clear();
+        for segment_entry in &uncommitted_entries {
+            segments.push(segment_reclaim_report(directory, segment_entry, false)?);
         }
+        Ok(ReclaimReport::new(segments))
+    }
 
-        // Committed segments cannot be merged with uncommitted_segments.
-        // We therefore consider merges using these two sets of segments independently.
-        let merge_policy = self.get_merge_policy();
+    /// Starts one merge per segment, not already being merged, whose ratio of deleted
+    /// documents is at least `min_deleted_ratio`.
+    pub(crate) fn expunge_deletes(
+        &self,
+        min_deleted_ratio: f64,
+    ) -> Vec<FutureResult<Option<SegmentMeta>>> {
+        let merge_segment_ids: HashSet<SegmentId> = self.merge_operations.segment_in_merge();
+        let (committed_entries, uncommitted_entries) =
+            self.segment_manager.segment_entries_by_status();
+        let needs_expunge = |segment_entry: &SegmentEntry| {
+            let max_doc = segment_entry.meta().max_doc();
+            let num_deleted_docs = num_deleted_docs_with_pending(segment_entry);
+            !merge_segment_ids.contains(&segment_entry.segment_id())
+                && num_deleted_docs > 0
+                && num_deleted_docs as f64 >= min_deleted_ratio * max_doc as f64
+        };
 
+        let commit_opstamp = self.load_meta().opstamp;
         let current_opstamp = self.stamper.stamp();
-        let mut merge_candidates: Vec<MergeOperation> = merge_policy
-            .compute_merge_candidates(&uncommitted_segments)
-            .into_iter()
-            .map(|merge_candidate| {
-                MergeOperation::new(&self.merge_operations, current_opstamp, merge_candidate.0)
+        let merge_operations: Vec<MergeOperation> = committed_entries
+            .iter()
+            .filter(|segment_entry| needs_expunge(segment_entry))
+            .map(|segment_entry| (commit_opstamp, segment_entry.segment_id()))
+            .chain(
+                uncommitted_entries
+                    .iter()
+                    .filter(|segment_entry| needs_expunge(segment_entry))
+                    .map(|segment_entry| (current_opstamp, segment_entry.segment_id())),
+            )
+            .map(|(opstamp, segment_id)| {
+                MergeOperation::new(&self.merge_operations, opstamp, vec![segment_id])
             })
             .collect();
-
-        let commit_opstamp = self.load_meta().opstamp;
-        let committed_merge_candidates = merge_policy
-            .compute_merge_candidates(&committed_segments)
+        merge_operations
             .into_iter()
-            .map(|merge_candidate: MergeCandidate| {
-                MergeOperation::new(&self.merge_operations, commit_opstamp, merge_candidate.0)
+            .map(|merge_operation| self.start_merge(merge_operation))
+            .collect()
+    }
+
+    fn consider_merge_options(&self) {
+        let merge_segment_ids: HashSet<SegmentId> = self.merge_operations.segment_in_merge();
+        let (committed_entries, uncommitted_entries) =
+            self.segment_manager.segment_entries_by_status();
+        let directory = self.index.directory();
+        let total_num_bytes: u64 = committed_entries
+            .iter()
+            .chain(uncommitted_entries.iter())
+            .map(|segment_entry| {
+                segment_reclaim_report(directory, segment_entry, false)
+                    .map(|report| report.num_bytes)
+                    .unwrap_or(0)
+            })
+            .sum();
+        let user_context = self.merge_policy_context.read().unwrap().clone();
+        let merge_context = |segment_entries: Vec<SegmentEntry>| {
+            let mut segment_entries: Vec<SegmentEntry> = segment_entries
+                .into_iter()
+                .filter(|segment_entry| !merge_segment_ids.contains(&segment_entry.segment_id()))
+                .collect();
+            if segment_entries.len() == 1 && segment_entries[0].meta().num_deleted_docs() == 0 {
+                segment_entries.clear();
+            }
+            let num_deleted_docs = segment_entries
+                .iter()
+                .map(num_deleted_docs_with_pending)
+                .collect();
+            let segment_metas = segment_entries
+                .iter()
+                .map(|segment_entry| segment_entry.meta().clone())
+                .collect();
+            MergeContext::new(
+                segment_metas,
+                num_deleted_docs,
+                total_num_bytes,
+                user_context.clone(),
+            )
+        };
+
+        // Committed segments cannot be merged with uncommitted_segments.
+        // We therefore consider merges using these two sets of segments independently.
+        let merge_policy = self.get_merge_policy();
+        let mut pending_futures = Vec::new();
+        for (committed, segment_entries) in
+            [(false, uncommitted_entries), (true, committed_entries)]
+        {
+            let mut merge_candidates_future =
+                merge_policy.compute_merge_candidates_ctx(merge_context(segment_entries));
+            match merge_candidates_future
+                .as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+            {
+                Poll::Ready(merge_candidates) => {
+                    self.start_merge_candidates(merge_candidates, committed)
+                }
+                Poll::Pending => pending_futures.push((committed, merge_candidates_future)),
+            }
+        }
+        if !pending_futures.is_empty() {
+            self.wait_pending_merge_candidates(pending_futures);
+        }
+    }
+
+    /// Waits for the merge policy on a dedicated thread, so that the segment updater can keep
+    /// processing commits, and then schedules the merges it suggests on the segment updater.
+    fn wait_pending_merge_candidates(&self, pending_futures: Vec<(bool, MergeCandidatesFuture)>) {
+        let segment_updater = self.clone();
+        let deadline = Instant::now() + self.merge_policy_timeout;
+        let spawn_res = thread::Builder::new()
+            .name("merge_policy".to_string())
+            .spawn(move || {
+                let mut merge_candidates_by_status = Vec::new();
+                for (committed, merge_candidates_future) in pending_futures {
+                    let Some(merge_candidates) =
+                        block_on_with_deadline(merge_candidates_future, deadline)
+                    else {
+                        warn!("The merge policy timed out. No merge will be started.");
+                        return;
+                    };
+                    merge_candidates_by_status.push((committed, merge_candidates));
+                }
+                let segment_updater_clone = segment_updater.clone();
+                drop(segment_updater.schedule_task(move || {
+                    for (committed, merge_candidates) in merge_candidates_by_status {
+                        segment_updater_clone.start_merge_candidates(merge_candidates, committed);
+                    }
+                    Ok(())
+                }));
             });
-        merge_candidates.extend(committed_merge_candidates);
+        if let Err(spawn_err) = spawn_res {
+            warn!("Failed to spawn the merge policy thread: {spawn_err:?}");
+        }
+    }
 
-        for merge_operation in merge_candidates {
+    /// Starts the merges of the candidates whose segments are all still mergeable, and
+    /// respectively committed or uncommitted.
+    fn start_merge_candidates(&self, merge_candidates: Vec<MergeCandidate>, committed: bool) {
+        let (committed_segments, uncommitted_segments) = self.get_mergeable_segments();
+        let mut mergeable_segment_ids: HashSet<SegmentId> = if committed {
+            committed_segments
+        } else {
+            uncommitted_segments
+        }
+        .iter()
+        .map(SegmentMeta::id)
+        .collect();
+        let opstamp = if committed {
+            self.load_meta().opstamp
+        } else {
+            self.stamper.stamp()
+        };
+        for merge_candidate in merge_candidates {
+            if !merge_candidate
+                .0
+                .iter()
+                .all(|segment_id| mergeable_segment_ids.contains(segment_id))
+            {
+                info!(
+                    "Discarding merge candidate {:?}, its segments are not mergeable anymore.",
+                    merge_candidate.0
+                );
+                continue;
+            }
+            for segment_id in &merge_candidate.0 {
+                mergeable_segment_ids.remove(segment_id);
+            }
+            let merge_operation =
+                MergeOperation::new(&self.merge_operations, opstamp, merge_candidate.0);
             // If a merge cannot be started this is not a fatal error.
             // We do log a warning in `start_merge`.
             drop(self.start_merge(merge_operation));
@@ -618,15 +1192,12 @@ impl SegmentUpdater {
         mut after_merge_segment_entry: Option<SegmentEntry>,
     ) -> crate::Result<Option<SegmentMeta>> {
         let segment_updater = self.clone();
-        let after_merge_segment_meta = after_merge_segment_entry
-            .as_ref()
-            .map(|after_merge_segment_entry| after_merge_segment_entry.meta().clone());
         self.schedule_task(move || {
             info!(
                 "End merge {:?}",
                 after_merge_segment_entry.as_ref().map(|entry| entry.meta())
             );
-            {
+            let after_merge_segment_meta = {
                 if let Some(after_merge_segment_entry) = after_merge_segment_entry.as_mut() {
                     // Deletes and commits could have happened as we were merging.
                     // We need to make sure we are up to date with deletes before accepting the
@@ -659,6 +1230,10 @@ impl SegmentUpdater {
                         }
                     }
                 }
+                // The meta accounts for the deletes that were just applied.
+                let after_merge_segment_meta = after_merge_segment_entry
+                    .as_ref()
+                    .map(|after_merge_segment_entry| after_merge_segment_entry.meta().clone());
                 let previous_metas = segment_updater.load_meta();
                 let segments_status = segment_updater
                     .segment_manager
@@ -670,13 +1245,13 @@ impl SegmentUpdater {
                 }
 
                 segment_updater.consider_merge_options();
-            } // we drop all possible handle to a now useless `SegmentMeta`.
+                after_merge_segment_meta
+            }; // we drop all possible handle to a now useless `SegmentMeta`.
 
             let _ = garbage_collect_files(segment_updater);
-            Ok(())
+            Ok(after_merge_segment_meta)
         })
-        .wait()?;
-        Ok(after_merge_segment_meta)
+        .wait()
     }
 
     /// Wait for current merging threads.
@@ -702,16 +1277,201 @@ impl SegmentUpdater {
 
 #[cfg(test)]
 mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
     use super::merge_indices;
     use crate::collector::TopDocs;
     use crate::directory::RamDirectory;
     use crate::fastfield::AliveBitSet;
+    use crate::index::SegmentMeta;
     use crate::indexer::merge_policy::tests::MergeWheneverPossible;
     use crate::indexer::merger::IndexMerger;
     use crate::indexer::segment_updater::merge_filtered_segments;
+    use crate::indexer::{
+        IndexWriterOptions, MergeCandidate, MergeCandidatesFuture, MergeContext, MergePolicy,
+    };
     use crate::query::QueryParser;
     use crate::schema::*;
-    use crate::{Directory, DocAddress, Index, Segment};
+    use crate::{Directory, DocAddress, Index, IndexReader, IndexWriter, Segment};
+
+    struct SearchLoad {
+        busy: AtomicBool,
+    }
+
+    /// Merges all of the segments, unless the search load injected in the context is busy.
+    #[derive(Debug, Default)]
+    struct MergeUnlessBusy {
+        total_num_bytes: Arc<AtomicU64>,
+    }
+
+    impl MergePolicy for MergeUnlessBusy {
+        fn compute_merge_candidates(&self, _segments: &[SegmentMeta]) -> Vec<MergeCandidate> {
+            unreachable!()
+        }
+
+        fn compute_merge_candidates_ctx(&self, ctx: MergeContext) -> MergeCandidatesFuture {
+            assert_eq!(
+                ctx.segments().len(),
+                ctx.num_deleted_docs_with_pending().len()
+            );
+            self.total_num_bytes
+                .store(ctx.total_num_bytes(), Ordering::SeqCst);
+            let busy = ctx
+                .user_context::<SearchLoad>()
+                .map(|search_load| search_load.busy.load(Ordering::SeqCst))
+                .unwrap_or(false);
+            let merge_candidates = MergeWheneverPossible.compute_merge_candidates(ctx.segments());
+            Box::pin(std::future::ready(if busy {
+                Vec::new()
+            } else {
+                merge_candidates
+            }))
+        }
+    }
+
+    /// Resolves to the given candidates after a delay, without blocking the thread polling it.
+    struct Delayed {
+        ready_at: Instant,
+        merge_candidates: Vec<MergeCandidate>,
+    }
+
+    impl Future for Delayed {
+        type Output = Vec<MergeCandidate>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let now = Instant::now();
+            if now >= self.ready_at {
+                return Poll::Ready(std::mem::take(&mut self.merge_candidates));
+            }
+            let waker = cx.waker().clone();
+            let delay = self.ready_at - now;
+            thread::spawn(move || {
+                thread::sleep(delay);
+                waker.wake();
+            });
+            Poll::Pending
+        }
+    }
+
+    /// Merges all of the segments, after consulting a slow external service.
+    #[derive(Debug)]
+    struct SlowMergePolicy {
+        delay: Duration,
+    }
+
+    impl MergePolicy for SlowMergePolicy {
+        fn compute_merge_candidates(&self, _segments: &[SegmentMeta]) -> Vec<MergeCandidate> {
+            unreachable!()
+        }
+
+        fn compute_merge_candidates_ctx(&self, ctx: MergeContext) -> MergeCandidatesFuture {
+            Box::pin(Delayed {
+                ready_at: Instant::now() + self.delay,
+                merge_candidates: MergeWheneverPossible.compute_merge_candidates(ctx.segments()),
+            })
+        }
+    }
+
+    fn commit_segments(
+        index_writer: &mut IndexWriter,
+        text_field: Field,
+        num_segments: usize,
+    ) -> crate::Result<()> {
+        for _ in 0..num_segments {
+            index_writer.add_document(doc!(text_field=>"a"))?;
+            index_writer.commit()?;
+        }
+        Ok(())
+    }
+
+    fn num_segments(reader: &IndexReader) -> crate::Result<usize> {
+        reader.reload()?;
+        Ok(reader.searcher().segment_readers().len())
+    }
+
+    #[test]
+    fn test_merge_policy_context() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let merge_policy = MergeUnlessBusy::default();
+        let total_num_bytes = merge_policy.total_num_bytes.clone();
+        index_writer.set_merge_policy(Box::new(merge_policy));
+        let search_load = Arc::new(SearchLoad {
+            busy: AtomicBool::new(true),
+        });
+        index_writer.set_merge_policy_context(search_load.clone());
+        let reader = index.reader()?;
+
+        commit_segments(&mut index_writer, text_field, 3)?;
+        index_writer.wait_merging_threads()?;
+        assert_eq!(num_segments(&reader)?, 3);
+        assert!(total_num_bytes.load(Ordering::SeqCst) > 0);
+
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(MergeUnlessBusy::default()));
+        search_load.busy.store(false, Ordering::SeqCst);
+        index_writer.set_merge_policy_context(search_load);
+        commit_segments(&mut index_writer, text_field, 1)?;
+        index_writer.wait_merging_threads()?;
+        assert_eq!(num_segments(&reader)?, 1);
+        assert_eq!(reader.searcher().num_docs(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_merge_policy_times_out() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let options = IndexWriterOptions::builder()
+            .merge_policy_timeout(Duration::from_millis(50))
+            .build();
+        let mut index_writer: IndexWriter = index.writer_with_options(options)?;
+        let delay = Duration::from_secs(1);
+        index_writer.set_merge_policy(Box::new(SlowMergePolicy { delay }));
+        let reader = index.reader()?;
+
+        // Commits do not wait for the merge policy.
+        let start = Instant::now();
+        commit_segments(&mut index_writer, text_field, 3)?;
+        assert!(start.elapsed() < delay);
+
+        // The merge policy resolves after the timeout, so no merge is started.
+        thread::sleep(delay + Duration::from_millis(500));
+        index_writer.wait_merging_threads()?;
+        assert_eq!(num_segments(&reader)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_merge_policy_within_timeout() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(SlowMergePolicy {
+            delay: Duration::from_millis(20),
+        }));
+        let reader = index.reader()?;
+        commit_segments(&mut index_writer, text_field, 3)?;
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while num_segments(&reader)? > 1 {
+            assert!(Instant::now() < deadline, "the merges were never started");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(reader.searcher().num_docs(), 3);
+        index_writer.wait_merging_threads()?;
+        Ok(())
+    }
 
     #[test]
     fn test_delete_during_merge() -> crate::Result<()> {
@@ -740,7 +1500,7 @@ mod tests {
         index_writer.commit()?;
 
         let term = Term::from_field_text(text_field, "a");
-        index_writer.delete_term(term);
+        index_writer.delete_term(term)?;
         index_writer.commit()?;
 
         let reader = index.reader()?;
@@ -774,11 +1534,11 @@ mod tests {
         assert!(!seg_ids.is_empty());
 
         let term = Term::from_field_text(text_field, "a");
-        index_writer.delete_term(term);
+        index_writer.delete_term(term)?;
         index_writer.commit()?;
 
         let term = Term::from_field_text(text_field, "b");
-        index_writer.delete_term(term);
+        index_writer.delete_term(term)?;
         index_writer.commit()?;
 
         index_writer.wait_merging_threads()?;
@@ -830,7 +1590,7 @@ mod tests {
         let term_vals = vec!["a", "b", "c", "d", "e", "f"];
         for term_val in term_vals {
             let term = Term::from_field_text(text_field, term_val);
-            index_writer.delete_term(term);
+            index_writer.delete_term(term)?;
             index_writer.commit()?;
         }
 
@@ -969,7 +1729,7 @@ mod tests {
             let mut index_writer = index.writer_for_tests()?;
             index_writer.add_document(doc!(text_field=>"some text 3"))?;
             index_writer.add_document(doc!(text_field=>"some text 4"))?;
-            index_writer.delete_term(Term::from_field_text(text_field, "4"));
+            index_writer.delete_term(Term::from_field_text(text_field, "4"))?;
 
             index_writer.commit()?;
             index
@@ -1016,7 +1776,7 @@ mod tests {
             index_writer.add_document(doc!(text_field=>"some text 3"))?;
             index_writer.add_document(doc!(text_field=>"some text 4"))?;
 
-            index_writer.delete_term(Term::from_field_text(text_field, "4"));
+            index_writer.delete_term(Term::from_field_text(text_field, "4"))?;
 
             index_writer.commit()?;
             index
@@ -1079,7 +1839,7 @@ mod tests {
             index_writer.add_document(doc!(text_field=>"some text 3"))?;
             index_writer.add_document(doc!(text_field=>"some text 4"))?;
 
-            index_writer.delete_term(Term::from_field_text(text_field, "4"));
+            index_writer.delete_term(Term::from_field_text(text_field, "4"))?;
 
             index_writer.commit()?;
             index
@@ -4,6 +4,7 @@ use itertools::Itertools;
 use tokenizer_api::BoxTokenStream;
 
 use super::operation::AddOperation;
+use super::IndexingReport;
 use crate::fastfield::FastFieldsWriter;
 use crate::fieldnorm::{FieldNormReaders, FieldNormsWriter};
 use crate::index::{Segment, SegmentComponent};
@@ -14,7 +15,11 @@ use crate::postings::{
     PerFieldPostingsWriter, PostingsWriter,
 };
 use crate::schema::document::{Document, Value};
-use crate::schema::{FieldEntry, FieldType, Schema, Term, DATE_TIME_PRECISION_INDEXED};
+use crate::schema::{
+    geo_cell_term_value, FieldEntry, FieldType, GeoPoint, Schema, Term,
+    DATE_TIME_PRECISION_INDEXED, GEO_TERM_LEVELS,
+};
+use crate::store::Compressor;
 use crate::tokenizer::{FacetTokenizer, PreTokenizedStream, TextAnalyzer, Tokenizer};
 use crate::{DocId, Opstamp, TantivyError};
 
@@ -57,6 +62,7 @@ pub struct SegmentWriter {
     per_field_text_analyzers: Vec<TextAnalyzer>,
     term_buffer: Term,
     schema: Schema,
+    indexing_report: IndexingReport,
 }
 
 impl SegmentWriter {
@@ -114,6 +120,7 @@ impl SegmentWriter {
             per_field_text_analyzers,
             term_buffer: Term::with_capacity(16),
             schema,
+            indexing_report: IndexingReport::default(),
         })
     }
 
@@ -134,6 +141,16 @@ impl SegmentWriter {
         Ok(self.doc_opstamps)
     }
 
+    /// Returns the tokenization problems of the documents indexed since the last call.
+    pub(crate) fn take_indexing_report(&mut self) -> IndexingReport {
+        std::mem::take(&mut self.indexing_report)
+    }
+
+    /// Returns the `Compressor` of the doc store of the segment being written.
+    pub(crate) fn store_compressor(&self) -> Compressor {
+        self.segment_serializer.store_compressor()
+    }
+
     /// Returns an estimation of the current memory usage of the segment writer.
     /// If the mem usage exceeds the `memory_budget`, the segment be serialized.
     pub fn mem_usage(&self) -> usize {
@@ -143,7 +160,7 @@ impl SegmentWriter {
             + self.segment_serializer.mem_usage()
     }
 
-    fn index_document<D: Document>(&mut self, doc: &D) -> crate::Result<()> {
+    fn index_document<D: Document>(&mut self, doc: &D, opstamp: Opstamp) -> crate::Result<()> {
         let doc_id = self.max_doc;
 
         // TODO: Can this be optimised a bit?
@@ -214,10 +231,21 @@ impl SegmentWriter {
                             &mut indexing_position,
                         );
                     }
+                    self.indexing_report.record_skipped(
+                        field_entry.name(),
+                        opstamp,
+                        indexing_position.num_dropped_tokens,
+                        indexing_position.num_tokenizer_errors,
+                    );
                     if field_entry.has_fieldnorms() {
                         self.fieldnorms_writer
                             .record(doc_id, field, indexing_position.num_tokens);
                     }
+                    self.fast_field_writers.record_token_count(
+                        doc_id,
+                        field,
+                        indexing_position.num_tokens,
+                    );
                 }
                 FieldType::U64(_) => {
                     let mut num_vals = 0;
@@ -322,6 +350,14 @@ impl SegmentWriter {
                             &mut self.json_positions_per_path,
                         );
                     }
+                    let (num_dropped_tokens, num_tokenizer_errors) =
+                        self.json_positions_per_path.num_dropped_tokens_and_errors();
+                    self.indexing_report.record_skipped(
+                        field_entry.name(),
+                        opstamp,
+                        num_dropped_tokens,
+                        num_tokenizer_errors,
+                    );
                 }
                 FieldType::IpAddr(_) => {
                     let mut num_vals = 0;
@@ -337,6 +373,17 @@ impl SegmentWriter {
                         self.fieldnorms_writer.record(doc_id, field, num_vals);
                     }
                 }
+                FieldType::GeoPoint(_) => {
+                    for value in values {
+                        let geo_point =
+                            GeoPoint::from_value(value).ok_or_else(make_schema_error)?;
+                        let code = geo_point.to_u64();
+                        for level in GEO_TERM_LEVELS {
+                            term_buffer.set_u64(geo_cell_term_value(code, level));
+                            postings_writer.subscribe(doc_id, 0u32, term_buffer, ctx);
+                        }
+                    }
+                }
             }
         }
         Ok(())
@@ -352,7 +399,7 @@ impl SegmentWriter {
         let AddOperation { document, opstamp } = add_operation;
         self.doc_opstamps.push(opstamp);
         self.fast_field_writers.add_document(&document)?;
-        self.index_document(&document)?;
+        self.index_document(&document, opstamp)?;
         let doc_writer = self.segment_serializer.get_store_writer();
         doc_writer.store(&document, &self.schema)?;
         self.max_doc += 1;
@@ -39,8 +39,12 @@ impl<D: Document> SingleSegmentIndexWriter<D> {
 
     pub fn finalize(self) -> crate::Result<Index> {
         let max_doc = self.segment_writer.max_doc();
+        let store_compressor = self.segment_writer.store_compressor();
         self.segment_writer.finalize()?;
-        let segment: Segment = self.segment.with_max_doc(max_doc);
+        let segment: Segment = self
+            .segment
+            .with_max_doc(max_doc)
+            .with_store_compressor(store_compressor);
         let index = segment.index();
         let index_meta = IndexMeta {
             index_settings: index.settings().clone(),
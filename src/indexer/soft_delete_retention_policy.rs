@@ -0,0 +1,44 @@
+use std::fmt::Debug;
+use std::marker;
+
+use crate::index::SegmentReader;
+use crate::DocId;
+
+/// The `SoftDeleteRetentionPolicy` decides when soft-deleted documents become hard-deleted.
+///
+/// Soft-deleted documents are kept in the index until they are merged. When segments are
+/// merged, the policy is asked about each of their soft-deleted documents: the expired ones
+/// are removed from the merged segment, like deleted documents, while the others are carried
+/// over, still soft-deleted.
+///
+/// See [`IndexWriter::soft_delete_term`](crate::IndexWriter::soft_delete_term).
+pub trait SoftDeleteRetentionPolicy: marker::Send + marker::Sync + Debug {
+    /// Returns true if the soft-deleted document `doc` of the segment should be hard-deleted.
+    ///
+    /// This call happens on the merge threads. The tombstone fields of the soft deletes applied
+    /// to the segment are available through
+    /// [`SegmentReader::soft_delete_tombstone_fields`].
+    fn is_expired(&self, segment_reader: &SegmentReader, doc: DocId) -> bool;
+}
+
+/// Never hard-deletes soft-deleted documents.
+///
+/// This is the default policy.
+#[derive(Debug, Clone, Default)]
+pub struct KeepSoftDeleted;
+
+impl SoftDeleteRetentionPolicy for KeepSoftDeleted {
+    fn is_expired(&self, _segment_reader: &SegmentReader, _doc: DocId) -> bool {
+        false
+    }
+}
+
+/// Hard-deletes all soft-deleted documents as soon as they are merged.
+#[derive(Debug, Clone, Default)]
+pub struct ExpireSoftDeleted;
+
+impl SoftDeleteRetentionPolicy for ExpireSoftDeleted {
+    fn is_expired(&self, _segment_reader: &SegmentReader, _doc: DocId) -> bool {
+        true
+    }
+}
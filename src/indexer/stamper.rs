@@ -20,6 +20,24 @@ impl Stamper {
         self.0.fetch_add(1u64, Ordering::SeqCst)
     }
 
+    /// Returns `opstamp` as a new stamp, if it is the next stamp.
+    ///
+    /// Returns `None` if another stamp was given in the meantime.
+    pub fn stamp_if_next(&self, opstamp: Opstamp) -> Option<Opstamp> {
+        self.0
+            .compare_exchange(opstamp, opstamp + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+    }
+
+    /// Gives back the stamps of `range`, if no stamp was given after them.
+    ///
+    /// Returns `false` if another stamp was given in the meantime.
+    pub fn unstamp(&self, range: Range<Opstamp>) -> bool {
+        self.0
+            .compare_exchange(range.end, range.start, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
     /// Given a desired count `n`, `stamps` returns an iterator that
     /// will supply `n` number of u64 stamps.
     pub fn stamps(&self, n: u64) -> Range<Opstamp> {
@@ -72,4 +90,17 @@ mod test {
         assert_eq!(stamper.stamp(), 6);
         assert_eq!(stamper_clone.stamp(), 7);
     }
+
+    #[test]
+    fn test_stamper_unstamp() {
+        let stamper = Stamper::new(7u64);
+        let stamps = stamper.stamps(3);
+        assert!(stamper.unstamp(stamps));
+        assert_eq!(stamper.stamp(), 7);
+
+        let stamps = stamper.stamps(2);
+        assert_eq!(stamper.stamp(), 10);
+        assert!(!stamper.unstamp(stamps));
+        assert_eq!(stamper.stamp(), 11);
+    }
 }
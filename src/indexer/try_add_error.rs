@@ -0,0 +1,61 @@
+use std::fmt;
+
+use crate::TantivyError;
+
+/// Error returned by the non-blocking operations of the
+/// [`IndexWriter`](crate::IndexWriter), like
+/// [`IndexWriter::try_add_document()`](crate::IndexWriter::try_add_document).
+pub enum TryAddError<T> {
+    /// The indexing queue is full.
+    ///
+    /// The rejected operation is given back unchanged, so that it can be retried.
+    Full(T),
+    /// The operation is invalid, e.g. a delete on a field that is not indexed, and was not
+    /// applied.
+    ///
+    /// The rejected operation is given back unchanged, so that it can be fixed and retried.
+    Invalid(T, TantivyError),
+    /// The operation failed, e.g. because the index writer was killed.
+    Error(TantivyError),
+}
+
+impl<T> TryAddError<T> {
+    /// Converts the operation given back by [`TryAddError::Full`] or [`TryAddError::Invalid`].
+    pub(crate) fn map_rejected<U>(self, f: impl FnOnce(T) -> U) -> TryAddError<U> {
+        match self {
+            TryAddError::Full(rejected) => TryAddError::Full(f(rejected)),
+            TryAddError::Invalid(rejected, err) => TryAddError::Invalid(f(rejected), err),
+            TryAddError::Error(err) => TryAddError::Error(err),
+        }
+    }
+}
+
+impl<T> From<TantivyError> for TryAddError<T> {
+    fn from(err: TantivyError) -> Self {
+        TryAddError::Error(err)
+    }
+}
+
+impl<T> fmt::Debug for TryAddError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryAddError::Full(_) => f.write_str("Full(..)"),
+            TryAddError::Invalid(_, err) => {
+                f.debug_tuple("Invalid").field(&"..").field(err).finish()
+            }
+            TryAddError::Error(err) => f.debug_tuple("Error").field(err).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryAddError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryAddError::Full(_) => f.write_str("The indexing queue is full."),
+            TryAddError::Invalid(_, err) => write!(f, "{err}"),
+            TryAddError::Error(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl<T> std::error::Error for TryAddError<T> {}
@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+
+use crate::index::{Index, SegmentReader};
+use crate::indexer::index_writer::validate_delete_term;
+use crate::schema::document::{Document, ReferenceValue, ReferenceValueLeaf, Value};
+use crate::schema::{Field, FieldType, IndexRecordOption, Schema, Term};
+use crate::{DocSet, Opstamp, TantivyError, TERMINATED};
+
+/// Number of keys the first layer of a [`UniqueKeyFilter`] is sized for.
+const FILTER_INITIAL_CAPACITY: usize = 1 << 14;
+/// Number of bits per key of a layer of a [`UniqueKeyFilter`], for a false positive rate of
+/// about 1%.
+const FILTER_NUM_BITS_PER_KEY: usize = 10;
+/// Number of hash functions of a [`UniqueKeyFilter`].
+const FILTER_NUM_HASHES: u64 = 7;
+
+/// What [`IndexWriter::upsert_document_with_policy()`](crate::IndexWriter::upsert_document_with_policy)
+/// does when a document with the same unique key already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpsertConflictPolicy {
+    /// The existing document is deleted, and the new one is indexed.
+    #[default]
+    Replace,
+    /// The existing document is kept, and the new one is not indexed.
+    Skip,
+    /// The existing document is kept, and [`TantivyError::UniqueKeyConflict`] is returned.
+    Error,
+}
+
+/// Counters of the upserts of an [`IndexWriter`](crate::IndexWriter), see
+/// [`IndexWriter::upsert_stats()`](crate::IndexWriter::upsert_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UpsertStats {
+    /// Number of upserts.
+    pub num_upserts: u64,
+    /// Number of upserts that enqueued a delete of the unique key before adding the document.
+    pub num_deletes_enqueued: u64,
+    /// Number of upserts whose key was known not to exist, and that skipped the delete.
+    pub num_deletes_skipped: u64,
+    /// Number of upserts whose key already existed, with the `Skip` or `Error` policies.
+    pub num_conflicts: u64,
+}
+
+/// What an upsert has to do with the new document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum UpsertAction {
+    /// The key does not exist: the document is added.
+    Add,
+    /// The key may exist: the key is deleted, then the document is added.
+    DeleteAndAdd,
+    /// The key exists, and the document is dropped.
+    Skip,
+}
+
+/// Approximate set of keys, answering whether a key was possibly inserted.
+///
+/// This is a scalable bloom filter: a new layer, twice as large as the previous one, is added
+/// whenever the last layer is full, so that the false positive rate stays bounded regardless of
+/// the number of keys. There are no false negatives.
+pub(crate) struct UniqueKeyFilter {
+    layers: Vec<FilterLayer>,
+}
+
+struct FilterLayer {
+    bits: Vec<u64>,
+    num_keys: usize,
+    capacity: usize,
+}
+
+impl FilterLayer {
+    fn with_capacity(capacity: usize) -> FilterLayer {
+        let num_bits = capacity * FILTER_NUM_BITS_PER_KEY;
+        FilterLayer {
+            bits: vec![0u64; num_bits.div_ceil(64)],
+            num_keys: 0,
+            capacity,
+        }
+    }
+
+    fn num_bits(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for pos in bit_positions(self.num_bits(), hash) {
+            self.bits[(pos / 64) as usize] |= 1u64 << (pos % 64);
+        }
+        self.num_keys += 1;
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        bit_positions(self.num_bits(), hash)
+            .all(|pos| self.bits[(pos / 64) as usize] & (1u64 << (pos % 64)) != 0)
+    }
+}
+
+/// Returns the positions of the bits of a key in a layer of `num_bits` bits.
+///
+/// This uses double hashing: the positions are derived from the two halves of a single hash.
+fn bit_positions(num_bits: u64, hash: u64) -> impl Iterator<Item = u64> {
+    let h1 = hash & 0xFFFF_FFFF;
+    let h2 = (hash >> 32) | 1;
+    (0..FILTER_NUM_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+}
+
+impl Default for UniqueKeyFilter {
+    fn default() -> UniqueKeyFilter {
+        UniqueKeyFilter {
+            layers: vec![FilterLayer::with_capacity(FILTER_INITIAL_CAPACITY)],
+        }
+    }
+}
+
+impl UniqueKeyFilter {
+    fn hash(key: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(key);
+        hasher.finish()
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let hash = Self::hash(key);
+        if self.layers.iter().any(|layer| layer.may_contain(hash)) {
+            return;
+        }
+        let last_layer = self
+            .layers
+            .last()
+            .expect("the filter has at least one layer");
+        if last_layer.num_keys >= last_layer.capacity {
+            let capacity = last_layer.capacity * 2;
+            self.layers.push(FilterLayer::with_capacity(capacity));
+        }
+        self.layers
+            .last_mut()
+            .expect("the filter has at least one layer")
+            .insert(hash);
+    }
+
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let hash = Self::hash(key);
+        self.layers.iter().any(|layer| layer.may_contain(hash))
+    }
+}
+
+/// Keys of a unique field upserted by the writer.
+#[derive(Default)]
+struct UniqueKeys {
+    /// All of the keys upserted since the writer was opened.
+    filter: UniqueKeyFilter,
+    /// The keys upserted with the opstamp of their last upsert, until a commit including them
+    /// is loaded as the committed snapshot.
+    uncommitted: HashMap<Vec<u8>, Opstamp>,
+}
+
+/// Segments of the last commit loaded by the writer.
+pub(crate) struct CommittedSnapshot {
+    opstamp: Opstamp,
+    segment_readers: Vec<SegmentReader>,
+}
+
+impl CommittedSnapshot {
+    pub fn load(index: &Index) -> crate::Result<CommittedSnapshot> {
+        let index_meta = index.load_metas()?;
+        let segment_readers = index_meta
+            .segments
+            .iter()
+            .map(|segment_meta| SegmentReader::open(&index.segment(segment_meta.clone())))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(CommittedSnapshot {
+            opstamp: index_meta.opstamp,
+            segment_readers,
+        })
+    }
+
+    pub fn opstamp(&self) -> Opstamp {
+        self.opstamp
+    }
+
+    pub fn segment_readers(&self) -> &[SegmentReader] {
+        &self.segment_readers
+    }
+
+    /// Returns true if the term is in the term dictionary of a segment, possibly only for
+    /// deleted documents.
+    fn contains_term(&self, term: &Term) -> crate::Result<bool> {
+        for segment_reader in &self.segment_readers {
+            let inverted_index = segment_reader.inverted_index(term.field())?;
+            if inverted_index.get_term_info(term)?.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns true if an alive document of a segment contains the term.
+    fn contains_alive_doc(&self, term: &Term) -> crate::Result<bool> {
+        for segment_reader in &self.segment_readers {
+            let inverted_index = segment_reader.inverted_index(term.field())?;
+            let Some(mut postings) =
+                inverted_index.read_postings(term, IndexRecordOption::Basic)?
+            else {
+                continue;
+            };
+            let mut doc = postings.doc();
+            while doc != TERMINATED {
+                if !segment_reader.is_deleted(doc) {
+                    return Ok(true);
+                }
+                doc = postings.advance();
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// State of the upserts of an [`IndexWriter`](crate::IndexWriter).
+#[derive(Default)]
+pub(crate) struct UpsertState {
+    keys: HashMap<Field, UniqueKeys>,
+    committed: Option<CommittedSnapshot>,
+    stats: UpsertStats,
+}
+
+impl UpsertState {
+    pub fn stats(&self) -> UpsertStats {
+        self.stats
+    }
+
+    /// Forgets the committed snapshot, so that the next upsert loads the new commit.
+    pub fn invalidate_committed(&mut self) {
+        self.committed = None;
+    }
+
+    fn committed(&mut self, index: &Index) -> crate::Result<&CommittedSnapshot> {
+        if self.committed.is_none() {
+            let snapshot = CommittedSnapshot::load(index)?;
+            for unique_keys in self.keys.values_mut() {
+                unique_keys
+                    .uncommitted
+                    .retain(|_, opstamp| *opstamp > snapshot.opstamp);
+            }
+            self.committed = Some(snapshot);
+        }
+        Ok(self
+            .committed
+            .as_ref()
+            .expect("the snapshot was just loaded"))
+    }
+
+    /// Decides what an upsert of a document with the unique key `term` does, under the given
+    /// conflict policy.
+    ///
+    /// A delete is only enqueued if the key may exist: if it was upserted since the writer was
+    /// opened, according to the filter, or if it is in the term dictionary of the last commit.
+    pub fn resolve(
+        &mut self,
+        index: &Index,
+        term: &Term,
+        policy: UpsertConflictPolicy,
+    ) -> crate::Result<UpsertAction> {
+        self.stats.num_upserts += 1;
+        let key = term.serialized_value_bytes();
+        let maybe_upserted = self
+            .keys
+            .get(&term.field())
+            .is_some_and(|unique_keys| unique_keys.filter.may_contain(key));
+        let action = match policy {
+            UpsertConflictPolicy::Replace => {
+                if maybe_upserted || self.committed(index)?.contains_term(term)? {
+                    UpsertAction::DeleteAndAdd
+                } else {
+                    UpsertAction::Add
+                }
+            }
+            UpsertConflictPolicy::Skip | UpsertConflictPolicy::Error => {
+                // The filter may have false positives, the existence of the key is checked
+                // exactly against the uncommitted upserts and the alive documents of the last
+                // commit.
+                let upserted_uncommitted = maybe_upserted && {
+                    self.committed(index)?;
+                    self.keys[&term.field()].uncommitted.contains_key(key)
+                };
+                if upserted_uncommitted || self.committed(index)?.contains_alive_doc(term)? {
+                    self.stats.num_conflicts += 1;
+                    if policy == UpsertConflictPolicy::Error {
+                        let field_name = index.schema().get_field_name(term.field()).to_string();
+                        return Err(TantivyError::UniqueKeyConflict { field: field_name });
+                    }
+                    return Ok(UpsertAction::Skip);
+                }
+                UpsertAction::Add
+            }
+        };
+        match action {
+            UpsertAction::DeleteAndAdd => self.stats.num_deletes_enqueued += 1,
+            UpsertAction::Add => self.stats.num_deletes_skipped += 1,
+            UpsertAction::Skip => {}
+        }
+        Ok(action)
+    }
+
+    /// Records the upsert of the unique key `term`, added at `opstamp`.
+    pub fn record(&mut self, term: &Term, opstamp: Opstamp) {
+        let key = term.serialized_value_bytes();
+        let unique_keys = self.keys.entry(term.field()).or_default();
+        unique_keys.filter.insert(key);
+        unique_keys.uncommitted.insert(key.to_vec(), opstamp);
+    }
+}
+
+/// Returns the term of the unique key of a document.
+///
+/// The unique field has to be indexed, and of type text with the `raw` tokenizer, `u64` or
+/// `i64`. The document needs to have exactly one value for it.
+pub(crate) fn unique_key_term<D: Document>(
+    schema: &Schema,
+    unique_field: Field,
+    document: &D,
+) -> crate::Result<Term> {
+    let field_entry = schema.get_field_entry(unique_field);
+    if let FieldType::Str(text_options) = field_entry.field_type() {
+        let tokenizer = text_options
+            .get_indexing_options()
+            .map(|indexing_options| indexing_options.tokenizer());
+        if tokenizer != Some("raw") {
+            return Err(TantivyError::SchemaError(format!(
+                "The unique field {:?} needs to use the raw tokenizer.",
+                field_entry.name()
+            )));
+        }
+    }
+    let mut terms = document
+        .iter_fields_and_values()
+        .filter(|(field, _)| *field == unique_field)
+        .map(|(_, value)| match value.as_value() {
+            ReferenceValue::Leaf(ReferenceValueLeaf::Str(text)) => {
+                Some(Term::from_field_text(unique_field, text))
+            }
+            ReferenceValue::Leaf(ReferenceValueLeaf::U64(val)) => {
+                Some(Term::from_field_u64(unique_field, val))
+            }
+            ReferenceValue::Leaf(ReferenceValueLeaf::I64(val)) => {
+                Some(Term::from_field_i64(unique_field, val))
+            }
+            _ => None,
+        });
+    let term = match (terms.next(), terms.next()) {
+        (Some(Some(term)), None) => term,
+        (None, _) => {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The document has no value for the unique field {:?}.",
+                field_entry.name()
+            )));
+        }
+        (Some(None), _) => {
+            return Err(TantivyError::SchemaError(format!(
+                "The unique field {:?} needs to be of type text, u64 or i64.",
+                field_entry.name()
+            )));
+        }
+        (Some(Some(_)), Some(_)) => {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The document has several values for the unique field {:?}.",
+                field_entry.name()
+            )));
+        }
+    };
+    validate_delete_term(schema, &term)?;
+    Ok(term)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UniqueKeyFilter, UpsertConflictPolicy, FILTER_INITIAL_CAPACITY};
+    use crate::collector::Count;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, Value, INDEXED, STORED, STRING, TEXT};
+    use crate::{Index, IndexWriter, TantivyDocument, TantivyError, Term};
+
+    #[test]
+    fn test_unique_key_filter_no_false_negatives() {
+        let mut filter = UniqueKeyFilter::default();
+        let num_keys = FILTER_INITIAL_CAPACITY * 3;
+        for i in 0..num_keys {
+            filter.insert(format!("key-{i}").as_bytes());
+        }
+        assert!(filter.layers.len() > 1);
+        for i in 0..num_keys {
+            assert!(filter.may_contain(format!("key-{i}").as_bytes()));
+        }
+        let num_false_positives = (0..10_000)
+            .filter(|i| filter.may_contain(format!("other-{i}").as_bytes()))
+            .count();
+        assert!(num_false_positives < 500, "{num_false_positives}");
+    }
+
+    fn count_live_docs(index: &Index, term: Term) -> crate::Result<usize> {
+        let searcher = index.reader()?.searcher();
+        searcher.search(&TermQuery::new(term, IndexRecordOption::Basic), &Count)
+    }
+
+    #[test]
+    fn test_upsert_exactly_one_live_doc_per_key() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        let body_field = schema_builder.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        // A document outside of the upserts, before the writer is opened.
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.add_document(doc!(id_field => "id-0", body_field => "initial"))?;
+            index_writer.commit()?;
+        }
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let num_ids = 100;
+        for round in 0..5 {
+            for i in 0..num_ids {
+                if (i + round) % 3 == 0 || round == 0 {
+                    let body = format!("round {round}");
+                    index_writer.upsert_document(
+                        id_field,
+                        doc!(id_field => format!("id-{i}"), body_field => body),
+                    )?;
+                }
+            }
+            if round % 2 == 1 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        for i in 0..num_ids {
+            let term = Term::from_field_text(id_field, &format!("id-{i}"));
+            assert_eq!(count_live_docs(&index, term)?, 1, "id-{i}");
+        }
+        let stats = index_writer.upsert_stats();
+        // Only the first round adds fresh keys, and all of them skip the delete except `id-0`.
+        assert_eq!(stats.num_deletes_skipped, num_ids as u64 - 1);
+        assert_eq!(
+            stats.num_deletes_enqueued + stats.num_deletes_skipped,
+            stats.num_upserts
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_fresh_keys_skip_the_delete() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..1_000u64 {
+            index_writer.upsert_document(id_field, doc!(id_field => i))?;
+            if i % 100 == 99 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        let stats = index_writer.upsert_stats();
+        assert_eq!(stats.num_upserts, 1_000);
+        // Fresh keys only enqueue a delete on a false positive of the filter.
+        assert!(stats.num_deletes_enqueued < 50, "{stats:?}");
+        assert_eq!(index.reader()?.searcher().num_docs(), 1_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_skip_policy() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        let body_field = schema_builder.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let first = index_writer.upsert_document_with_policy(
+            id_field,
+            doc!(id_field => "a", body_field => "first"),
+            UpsertConflictPolicy::Skip,
+        )?;
+        assert!(first.is_some());
+        // Conflicts with an uncommitted upsert.
+        let second = index_writer.upsert_document_with_policy(
+            id_field,
+            doc!(id_field => "a", body_field => "second"),
+            UpsertConflictPolicy::Skip,
+        )?;
+        assert_eq!(second, None);
+        index_writer.commit()?;
+        // Conflicts with a committed document.
+        let third = index_writer.upsert_document_with_policy(
+            id_field,
+            doc!(id_field => "a", body_field => "third"),
+            UpsertConflictPolicy::Skip,
+        )?;
+        assert_eq!(third, None);
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let doc: TantivyDocument = searcher.doc(crate::DocAddress::new(0, 0))?;
+        assert_eq!(
+            doc.get_first(body_field).and_then(|value| value.as_str()),
+            Some("first")
+        );
+        assert_eq!(index_writer.upsert_stats().num_conflicts, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_error_policy() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(id_field => "a"))?;
+        index_writer.commit()?;
+        let err = index_writer
+            .upsert_document_with_policy(
+                id_field,
+                doc!(id_field => "a"),
+                UpsertConflictPolicy::Error,
+            )
+            .unwrap_err();
+        assert!(matches!(err, TantivyError::UniqueKeyConflict { field } if field == "id"));
+        // A deleted document does not conflict.
+        index_writer.delete_term(Term::from_field_text(id_field, "a"))?;
+        index_writer.commit()?;
+        index_writer.upsert_document_with_policy(
+            id_field,
+            doc!(id_field => "a"),
+            UpsertConflictPolicy::Error,
+        )?;
+        index_writer.commit()?;
+        assert_eq!(
+            count_live_docs(&index, Term::from_field_text(id_field, "a"))?,
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_invalid_unique_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        assert!(matches!(
+            index_writer.upsert_document(text_field, doc!(text_field => "a")),
+            Err(TantivyError::SchemaError(_))
+        ));
+        assert!(matches!(
+            index_writer.upsert_document(id_field, doc!(text_field => "a")),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            index_writer.upsert_document(id_field, doc!(id_field => "a", id_field => "b")),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use crate::index::Index;
+use crate::indexer::upsert::CommittedSnapshot;
+use crate::schema::document::{Document, ReferenceValue, ReferenceValueLeaf, Value};
+use crate::schema::{Field, IndexRecordOption, Schema, Term, Type};
+use crate::{DocSet, Opstamp, TantivyError, TERMINATED};
+
+/// Versions of the documents updated by
+/// [`IndexWriter::update_document_versioned()`](crate::IndexWriter::update_document_versioned).
+#[derive(Default)]
+pub(crate) struct VersionState {
+    /// The version and the opstamp of the last versioned update of each key, until a commit
+    /// including it is loaded as the committed snapshot.
+    uncommitted: HashMap<Vec<u8>, (u64, Opstamp)>,
+    committed: Option<CommittedSnapshot>,
+}
+
+impl VersionState {
+    /// Forgets the committed snapshot, so that the next versioned update loads the new commit.
+    pub fn invalidate_committed(&mut self) {
+        self.committed = None;
+    }
+
+    /// Forgets the versions of the updates that were not committed.
+    pub fn clear_uncommitted(&mut self) {
+        self.uncommitted.clear();
+    }
+
+    fn committed(&mut self, index: &Index) -> crate::Result<&CommittedSnapshot> {
+        if self.committed.is_none() {
+            let snapshot = CommittedSnapshot::load(index)?;
+            self.uncommitted
+                .retain(|_, (_, opstamp)| *opstamp > snapshot.opstamp());
+            self.committed = Some(snapshot);
+        }
+        Ok(self
+            .committed
+            .as_ref()
+            .expect("the snapshot was just loaded"))
+    }
+
+    /// Returns the version of the live document with the key `term`: the version of the last
+    /// versioned update not committed yet if any, or else the highest version of the alive
+    /// documents of the last commit.
+    ///
+    /// Returns `None` if there is no such document, or if it has no version.
+    fn live_version(
+        &mut self,
+        index: &Index,
+        term: &Term,
+        version_field_name: &str,
+    ) -> crate::Result<Option<u64>> {
+        // Loading the snapshot prunes the committed updates.
+        self.committed(index)?;
+        if let Some((version, _)) = self.uncommitted.get(term.serialized_term()) {
+            return Ok(Some(*version));
+        }
+        let snapshot = self.committed(index)?;
+        let mut live_version = None;
+        for segment_reader in snapshot.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(term.field())?;
+            let Some(mut postings) =
+                inverted_index.read_postings(term, IndexRecordOption::Basic)?
+            else {
+                continue;
+            };
+            let versions = segment_reader.try_fast_fields()?.u64(version_field_name)?;
+            let mut doc = postings.doc();
+            while doc != TERMINATED {
+                if !segment_reader.is_deleted(doc) {
+                    live_version = live_version.max(versions.first(doc));
+                }
+                doc = postings.advance();
+            }
+        }
+        Ok(live_version)
+    }
+
+    /// Checks that an update of the key `term` to `version` is not stale: the live document has
+    /// to have an older version, and no newer than `expected_version` if given.
+    pub fn check(
+        &mut self,
+        index: &Index,
+        term: &Term,
+        version_field_name: &str,
+        version: u64,
+        expected_version: Option<u64>,
+    ) -> crate::Result<()> {
+        let Some(live_version) = self.live_version(index, term, version_field_name)? else {
+            return Ok(());
+        };
+        let is_stale = live_version >= version
+            || expected_version.is_some_and(|expected_version| live_version > expected_version);
+        if is_stale {
+            return Err(TantivyError::VersionConflict {
+                version,
+                expected_version,
+                live_version,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records the update of the key `term` to `version`, added at `opstamp`.
+    pub fn record(&mut self, term: &Term, version: u64, opstamp: Opstamp) {
+        self.uncommitted
+            .insert(term.serialized_term().to_vec(), (version, opstamp));
+    }
+}
+
+/// Returns the version of a document, its value for the `u64` fast field `version_field`.
+pub(crate) fn document_version<D: Document>(
+    schema: &Schema,
+    version_field: Field,
+    document: &D,
+) -> crate::Result<u64> {
+    let field_entry = schema.get_field_entry(version_field);
+    if field_entry.field_type().value_type() != Type::U64 || !field_entry.is_fast() {
+        return Err(TantivyError::SchemaError(format!(
+            "The version field {:?} needs to be a u64 fast field.",
+            field_entry.name()
+        )));
+    }
+    let mut versions = document
+        .iter_fields_and_values()
+        .filter(|(field, _)| *field == version_field)
+        .map(|(_, value)| match value.as_value() {
+            ReferenceValue::Leaf(ReferenceValueLeaf::U64(version)) => Some(version),
+            _ => None,
+        });
+    match (versions.next(), versions.next()) {
+        (Some(Some(version)), None) => Ok(version),
+        _ => Err(TantivyError::InvalidArgument(format!(
+            "The document needs exactly one u64 value for the version field {:?}.",
+            field_entry.name()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::collector::TopDocs;
+    use crate::indexer::{IndexWriterOptions, NoMergePolicy};
+    use crate::query::TermQuery;
+    use crate::schema::{Field, IndexRecordOption, Schema, Value, FAST, STORED, STRING};
+    use crate::{Index, IndexWriter, TantivyDocument, TantivyError, Term};
+
+    fn create_index() -> (Index, Field, Field) {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        let version_field = schema_builder.add_u64_field("version", FAST | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        (index, id_field, version_field)
+    }
+
+    fn create_writer(index: &Index, version_field: Field) -> crate::Result<IndexWriter> {
+        let options = IndexWriterOptions::builder()
+            .num_worker_threads(2)
+            .version_field(version_field)
+            .build();
+        let index_writer: IndexWriter = index.writer_with_options(options)?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        Ok(index_writer)
+    }
+
+    /// Returns the versions of the live documents with the key.
+    fn live_versions(index: &Index, term: Term, version_field: Field) -> crate::Result<Vec<u64>> {
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        top_docs
+            .into_iter()
+            .map(|(_, doc_address)| {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                Ok(doc.get_first(version_field).unwrap().as_u64().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_versioned_update_rejects_stale_versions() -> crate::Result<()> {
+        let (index, id_field, version_field) = create_index();
+        let mut index_writer = create_writer(&index, version_field)?;
+        let term = Term::from_field_text(id_field, "a");
+        let update = |version: u64, expected_version: Option<u64>| {
+            index_writer.update_document_versioned(
+                term.clone(),
+                doc!(id_field => "a", version_field => version),
+                expected_version,
+            )
+        };
+        update(1, None)?;
+        update(3, Some(1))?;
+        // Stale against the update that was not committed yet.
+        let err = update(2, None).unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::VersionConflict {
+                version: 2,
+                expected_version: None,
+                live_version: 3
+            }
+        ));
+        index_writer.commit()?;
+
+        let update = |version: u64, expected_version: Option<u64>| {
+            index_writer.update_document_versioned(
+                term.clone(),
+                doc!(id_field => "a", version_field => version),
+                expected_version,
+            )
+        };
+        // Stale against the committed document.
+        assert!(matches!(
+            update(3, None),
+            Err(TantivyError::VersionConflict {
+                live_version: 3,
+                ..
+            })
+        ));
+        // The version is newer, but the update expected an older live document.
+        assert!(matches!(
+            update(5, Some(2)),
+            Err(TantivyError::VersionConflict {
+                expected_version: Some(2),
+                live_version: 3,
+                ..
+            })
+        ));
+        update(5, Some(3))?;
+        index_writer.commit()?;
+        assert_eq!(live_versions(&index, term, version_field)?, vec![5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_versioned_update_interleaved_resolve_to_highest_version() -> crate::Result<()> {
+        let (index, id_field, version_field) = create_index();
+        let mut index_writer = create_writer(&index, version_field)?;
+        let num_keys = 10u64;
+        let num_threads = 4u64;
+        let num_versions = 50u64;
+        let num_conflicts: u64 = thread::scope(|scope| {
+            let handles: Vec<_> = (0..num_threads)
+                .map(|thread_id| {
+                    let index_writer = &index_writer;
+                    scope.spawn(move || {
+                        let mut num_conflicts = 0;
+                        for i in 0..num_versions {
+                            // Each thread goes through its own interleaving of versions.
+                            let version = (i * 7 + thread_id * 13) % num_versions + 1;
+                            let key = format!("key-{}", (i + thread_id) % num_keys);
+                            let res = index_writer.update_document_versioned(
+                                Term::from_field_text(id_field, &key),
+                                doc!(id_field => key, version_field => version),
+                                None,
+                            );
+                            match res {
+                                Ok(_) => {}
+                                Err(TantivyError::VersionConflict {
+                                    version: rejected,
+                                    live_version,
+                                    ..
+                                }) => {
+                                    assert!(live_version >= rejected);
+                                    num_conflicts += 1;
+                                }
+                                Err(err) => panic!("{err:?}"),
+                            }
+                        }
+                        num_conflicts
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .sum()
+        });
+        assert!(num_conflicts > 0);
+        index_writer.commit()?;
+        for key_id in 0..num_keys {
+            let key = format!("key-{key_id}");
+            let highest_version = (0..num_threads)
+                .flat_map(|thread_id| {
+                    (0..num_versions)
+                        .filter(move |i| (i + thread_id) % num_keys == key_id)
+                        .map(move |i| (i * 7 + thread_id * 13) % num_versions + 1)
+                })
+                .max()
+                .unwrap();
+            let term = Term::from_field_text(id_field, &key);
+            assert_eq!(
+                live_versions(&index, term, version_field)?,
+                vec![highest_version],
+                "{key}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unversioned_updates_ignore_versions() -> crate::Result<()> {
+        let (index, id_field, version_field) = create_index();
+        let mut index_writer = create_writer(&index, version_field)?;
+        let term = Term::from_field_text(id_field, "a");
+        index_writer.update_document_versioned(
+            term.clone(),
+            doc!(id_field => "a", version_field => 5u64),
+            None,
+        )?;
+        index_writer.commit()?;
+        // A regular update replaces the document regardless of its version.
+        index_writer.delete_term(term.clone())?;
+        index_writer.add_document(doc!(id_field => "a", version_field => 1u64))?;
+        index_writer.commit()?;
+        assert_eq!(live_versions(&index, term.clone(), version_field)?, vec![1]);
+        // The versioned updates then compare against the committed document.
+        index_writer.update_document_versioned(
+            term.clone(),
+            doc!(id_field => "a", version_field => 2u64),
+            Some(1),
+        )?;
+        index_writer.commit()?;
+        assert_eq!(live_versions(&index, term, version_field)?, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_versioned_update_requires_version_field() -> crate::Result<()> {
+        let (index, id_field, version_field) = create_index();
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        let term = Term::from_field_text(id_field, "a");
+        let err = index_writer
+            .update_document_versioned(
+                term.clone(),
+                doc!(id_field => "a", version_field => 1u64),
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, TantivyError::InvalidArgument(_)));
+        drop(index_writer);
+        let index_writer = create_writer(&index, version_field)?;
+        let err = index_writer
+            .update_document_versioned(term, doc!(id_field => "a"), None)
+            .unwrap_err();
+        assert!(matches!(err, TantivyError::InvalidArgument(_)));
+        Ok(())
+    }
+}
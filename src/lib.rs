@@ -165,8 +165,10 @@ mod macros;
 mod future_result;
 
 // Re-exports
+pub use columnar;
 pub use common::DateTime;
-pub use {columnar, query_grammar, time};
+pub use query_grammar;
+pub use time;
 
 pub use crate::error::TantivyError;
 pub use crate::future_result::FutureResult;
@@ -205,24 +207,34 @@ mod reader;
 #[cfg(test)]
 mod compat_tests;
 
-pub use self::reader::{IndexReader, IndexReaderBuilder, ReloadPolicy, Warmer};
+pub use self::reader::{
+    AccessFilter, AllowedDocs, BudgetExhaustedPolicy, GlobalSearchBudget, IndexReader,
+    IndexReaderBuilder, LeaseId, PinnedSearcher, ReloadPolicy, SearchBudgetUsage, SearcherContext,
+    Warmer,
+};
 pub mod snippet;
 
 use std::fmt;
 
 pub use census::{Inventory, TrackedObject};
-pub use common::{f64_to_u64, i64_to_u64, u64_to_f64, u64_to_i64, HasLen};
+pub use common::{f64_to_u64, i64_to_u64, u64_to_f64, u64_to_i64, BitSet, HasLen};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
 pub use self::docset::{DocSet, COLLECT_BLOCK_BUFFER_LEN, TERMINATED};
 #[doc(hidden)]
 pub use crate::core::json_utils;
-pub use crate::core::{Executor, Searcher, SearcherGeneration};
+pub use crate::core::{
+    CancellationToken, ColumnBlock, ColumnBlocks, Executor, MultiDocAddress, MultiSearcher,
+    ScratchVec, SearchProgress, SearchProgressCallback, SearchScratch, Searcher,
+    SearcherGeneration, SearcherStatistics, SegmentCandidates, SegmentDocFilter,
+};
 pub use crate::directory::Directory;
 pub use crate::index::{
-    Index, IndexBuilder, IndexMeta, IndexSettings, InvertedIndexReader, Order, Segment,
-    SegmentMeta, SegmentReader,
+    ComponentSet, Index, IndexBuilder, IndexMeta, IndexSettings, IndexVerifyReport,
+    InvertedIndexReader, MetaDiff, Order, PostingsCheck, Segment, SegmentDeletesChange,
+    SegmentMeta, SegmentReader, SegmentRepair, SegmentVerifyIssue, SegmentVerifyOptions,
+    SegmentVerifyReport, Snapshot, SnapshotFile, SnapshotListing,
 };
 pub use crate::indexer::{IndexWriter, SingleSegmentIndexWriter};
 pub use crate::schema::{Document, TantivyDocument, Term};
@@ -281,7 +293,8 @@ pub fn version_string() -> &'static str {
 /// Defines tantivy's merging strategy
 pub mod merge_policy {
     pub use crate::indexer::{
-        DefaultMergePolicy, LogMergePolicy, MergeCandidate, MergePolicy, NoMergePolicy,
+        DefaultMergePolicy, LogMergePolicy, MergeCandidate, MergeCandidatesFuture, MergeContext,
+        MergePolicy, NoMergePolicy, DEFAULT_MERGE_POLICY_TIMEOUT,
     };
 }
 
@@ -329,6 +342,9 @@ impl DocAddress {
 ///
 /// The id used for the segment is actually an ordinal
 /// in the list of `Segment`s held by a `Searcher`.
+///
+/// Merges renumber the documents, so a `DocAddress` must not be used as a persistent id. See
+/// [`Searcher::resolve_doc()`] to look documents up by an id field instead.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct DocAddress {
     /// The segment ordinal id that identifies the segment
@@ -586,8 +602,8 @@ pub mod tests {
             // 3
             index_writer.add_document(doc!(text_field=>" b d"))?;
 
-            index_writer.delete_term(Term::from_field_text(text_field, "c"));
-            index_writer.delete_term(Term::from_field_text(text_field, "a"));
+            index_writer.delete_term(Term::from_field_text(text_field, "c"))?;
+            index_writer.delete_term(Term::from_field_text(text_field, "a"))?;
             // 4
             index_writer.add_document(doc!(text_field=>" b c"))?;
             // 5
@@ -627,7 +643,7 @@ pub mod tests {
             // 0
             index_writer.add_document(doc!(text_field=>"a b"))?;
             // 1
-            index_writer.delete_term(Term::from_field_text(text_field, "c"));
+            index_writer.delete_term(Term::from_field_text(text_field, "c"))?;
             index_writer.rollback()?;
         }
         {
@@ -662,9 +678,9 @@ pub mod tests {
             // writing the segment
             let mut index_writer: IndexWriter = index.writer_for_tests()?;
             index_writer.add_document(doc!(text_field=>"a b"))?;
-            index_writer.delete_term(Term::from_field_text(text_field, "c"));
+            index_writer.delete_term(Term::from_field_text(text_field, "c"))?;
             index_writer.rollback()?;
-            index_writer.delete_term(Term::from_field_text(text_field, "a"));
+            index_writer.delete_term(Term::from_field_text(text_field, "a"))?;
             index_writer.commit()?;
         }
         {
@@ -813,8 +829,8 @@ pub mod tests {
         index_writer.add_document(doc!(text_field=>"33"))?;
         index_writer.add_document(doc!(text_field=>"40"))?;
         index_writer.add_document(doc!(text_field=>"17"))?;
-        index_writer.delete_term(Term::from_field_text(text_field, "38"));
-        index_writer.delete_term(Term::from_field_text(text_field, "34"));
+        index_writer.delete_term(Term::from_field_text(text_field, "38"))?;
+        index_writer.delete_term(Term::from_field_text(text_field, "34"))?;
         index_writer.commit()?;
         reader.reload()?;
         assert_eq!(reader.searcher().num_docs(), 6);
@@ -1140,7 +1156,7 @@ pub mod tests {
 
         // update the 10 elements by deleting and re-adding
         for doc_id in 0u64..DOC_COUNT {
-            index_writer.delete_term(Term::from_field_u64(id, doc_id));
+            index_writer.delete_term(Term::from_field_u64(id, doc_id))?;
             index_writer.commit()?;
             index_reader.reload()?;
             index_writer.add_document(doc!(id =>  doc_id))?;
@@ -1185,7 +1201,7 @@ pub mod tests {
         assert!(index.validate_checksum()?.is_empty());
 
         // delete few docs
-        writer.delete_term(Term::from_field_text(body, "foo"));
+        writer.delete_term(Term::from_field_text(body, "foo"))?;
         writer.commit()?;
         let segment_ids = index.searchable_segment_ids()?;
         writer.merge(&segment_ids).wait()?;
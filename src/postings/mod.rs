@@ -10,6 +10,7 @@ mod indexing_context;
 mod json_postings_writer;
 mod loaded_postings;
 mod per_field_postings_writer;
+mod positions_postings;
 mod postings;
 mod postings_writer;
 mod recorder;
@@ -24,6 +25,7 @@ pub(crate) use stacker::compute_table_memory_size;
 pub use self::block_segment_postings::BlockSegmentPostings;
 pub(crate) use self::indexing_context::IndexingContext;
 pub(crate) use self::per_field_postings_writer::PerFieldPostingsWriter;
+pub use self::positions_postings::{DocPositions, PositionsPostings};
 pub use self::postings::Postings;
 pub(crate) use self::postings_writer::{serialize_postings, IndexingPosition, PostingsWriter};
 pub use self::segment_postings::SegmentPostings;
@@ -435,7 +437,7 @@ pub(crate) mod tests {
         // delete some of the documents
         {
             let mut index_writer: IndexWriter = index.writer_for_tests()?;
-            index_writer.delete_term(term_0);
+            index_writer.delete_term(term_0)?;
             assert!(index_writer.commit().is_ok());
         }
         let searcher = index.reader()?.searcher();
@@ -486,7 +488,7 @@ pub(crate) mod tests {
         // delete everything else
         {
             let mut index_writer: IndexWriter = index.writer_for_tests()?;
-            index_writer.delete_term(term_1);
+            index_writer.delete_term(term_1)?;
             assert!(index_writer.commit().is_ok());
         }
         let searcher = index.reader()?.searcher();
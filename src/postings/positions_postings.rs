@@ -0,0 +1,255 @@
+use std::ops::Range;
+
+use tokenizer_api::BoxTokenStream;
+
+use super::postings_writer::POSITION_GAP;
+use crate::docset::{DocSet, TERMINATED};
+use crate::postings::{Postings, SegmentPostings};
+use crate::schema::document::Value;
+use crate::schema::{Field, FieldType, IndexRecordOption, Term};
+use crate::store::StoreReader;
+use crate::tokenizer::{PreTokenizedStream, TextAnalyzer, MAX_TOKEN_LEN};
+use crate::{DocId, SegmentReader, TantivyDocument, TantivyError};
+
+/// Postings of a term of a text field, giving access to the positions of the term in each value
+/// of the field.
+///
+/// The positions of all the values of a multi-valued field are indexed in a single sequence,
+/// separated by a gap that is too small to tell the values apart. The value boundaries are
+/// recovered by tokenizing the stored values again with the tokenizer of the field, so they are
+/// only available if the field is stored and its tokenizer did not change since the documents
+/// were indexed. Otherwise, all the positions of a document are reported as a single value.
+///
+/// Like [`SegmentPostings`], deleted documents are not filtered out.
+///
+/// Created with [`SegmentReader::positions_reader()`].
+pub struct PositionsPostings {
+    postings: SegmentPostings,
+    value_boundaries: Option<ValueBoundaries>,
+    /// Positions of the term in the current document, as indexed.
+    raw_positions: Vec<u32>,
+    /// Positions of the term in the current document, relative to the start of their value.
+    positions: Vec<u32>,
+    /// Ends of the values of the current document in `positions`.
+    value_ends: Vec<usize>,
+    value_ranges: Vec<Range<u32>>,
+}
+
+impl PositionsPostings {
+    pub(crate) fn open(
+        segment_reader: &SegmentReader,
+        field: Field,
+        term: &str,
+    ) -> crate::Result<Option<PositionsPostings>> {
+        let field_entry = segment_reader.schema().get_field_entry(field);
+        let FieldType::Str(text_options) = field_entry.field_type() else {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a text field",
+                field_entry.name()
+            )));
+        };
+        let Some(text_indexing) = text_options
+            .get_indexing_options()
+            .filter(|text_indexing| text_indexing.index_option().has_positions())
+        else {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} does not have positions indexed",
+                field_entry.name()
+            )));
+        };
+        let term = Term::from_field_text(field, term);
+        let Some(postings) = segment_reader
+            .inverted_index(field)?
+            .read_postings(&term, IndexRecordOption::WithFreqsAndPositions)?
+        else {
+            return Ok(None);
+        };
+        let value_boundaries = if text_options.is_stored() {
+            let text_analyzer = segment_reader
+                .tokenizers()
+                .get(text_indexing.tokenizer())
+                .ok_or_else(|| {
+                    TantivyError::SchemaError(format!(
+                        "Error getting tokenizer for field: {}",
+                        field_entry.name()
+                    ))
+                })?;
+            Some(ValueBoundaries {
+                field,
+                store_reader: segment_reader.open_store_reader(1)?,
+                text_analyzer,
+            })
+        } else {
+            None
+        };
+        Ok(Some(PositionsPostings {
+            postings,
+            value_boundaries,
+            raw_positions: Vec::new(),
+            positions: Vec::new(),
+            value_ends: Vec::new(),
+            value_ranges: Vec::new(),
+        }))
+    }
+
+    /// Returns the positions of the term in the current document, grouped by value.
+    ///
+    /// Reading the value boundaries requires fetching the document from the doc store.
+    pub fn doc_positions(&mut self) -> crate::Result<DocPositions<'_>> {
+        self.raw_positions.clear();
+        self.positions.clear();
+        self.value_ends.clear();
+        let doc = self.postings.doc();
+        if doc != TERMINATED {
+            self.postings.positions(&mut self.raw_positions);
+            match &mut self.value_boundaries {
+                Some(value_boundaries) => {
+                    value_boundaries.load(doc, &mut self.value_ranges)?;
+                    let mut raw_positions = self.raw_positions.iter().copied().peekable();
+                    for value_range in &self.value_ranges {
+                        while let Some(position) =
+                            raw_positions.next_if(|&position| position < value_range.end)
+                        {
+                            if position >= value_range.start {
+                                self.positions.push(position - value_range.start);
+                            }
+                        }
+                        self.value_ends.push(self.positions.len());
+                    }
+                }
+                None => {
+                    self.positions.extend_from_slice(&self.raw_positions);
+                    self.value_ends.push(self.positions.len());
+                }
+            }
+        }
+        Ok(DocPositions {
+            positions: &self.positions,
+            value_ends: &self.value_ends,
+        })
+    }
+}
+
+impl DocSet for PositionsPostings {
+    fn advance(&mut self) -> DocId {
+        self.postings.advance()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.postings.seek(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.postings.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.postings.size_hint()
+    }
+}
+
+/// Positions of a term in a document, grouped by value of the field.
+///
+/// The positions are relative to the start of their value, and sorted within a value.
+#[derive(Clone, Copy, Debug)]
+pub struct DocPositions<'a> {
+    positions: &'a [u32],
+    value_ends: &'a [usize],
+}
+
+impl<'a> DocPositions<'a> {
+    /// Returns the number of values of the field in the document, including the values that do
+    /// not contain the term.
+    pub fn num_values(&self) -> usize {
+        self.value_ends.len()
+    }
+
+    /// Returns the positions of the term in the value `value_ord`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value_ord` is not lower than [`DocPositions::num_values()`].
+    pub fn value(&self, value_ord: usize) -> &'a [u32] {
+        let start = if value_ord == 0 {
+            0
+        } else {
+            self.value_ends[value_ord - 1]
+        };
+        &self.positions[start..self.value_ends[value_ord]]
+    }
+
+    /// Returns the positions of the term in each value.
+    pub fn values(&self) -> impl Iterator<Item = &'a [u32]> + '_ {
+        (0..self.num_values()).map(|value_ord| self.value(value_ord))
+    }
+}
+
+/// Computes the range of positions of the values of a stored text field.
+struct ValueBoundaries {
+    field: Field,
+    store_reader: StoreReader,
+    text_analyzer: TextAnalyzer,
+}
+
+impl ValueBoundaries {
+    /// Fills `value_ranges` with the range of positions of each value of the field in `doc`,
+    /// assigning positions the way the indexer does.
+    fn load(&mut self, doc: DocId, value_ranges: &mut Vec<Range<u32>>) -> crate::Result<()> {
+        value_ranges.clear();
+        let document: TantivyDocument = self.store_reader.get(doc)?;
+        let mut start_position = 0u32;
+        for value in document.get_all(self.field) {
+            let value = value.as_value();
+            let mut token_stream = if let Some(text) = value.as_str() {
+                self.text_analyzer.token_stream(text)
+            } else if let Some(tok_str) = value.into_pre_tokenized_text() {
+                BoxTokenStream::new(PreTokenizedStream::from(*tok_str))
+            } else {
+                continue;
+            };
+            let mut end_position = start_position;
+            token_stream.process(&mut |token| {
+                if token.text.len() > MAX_TOKEN_LEN {
+                    return;
+                }
+                let token_start = start_position + token.position as u32;
+                end_position = end_position.max(token_start + token.position_length as u32);
+            });
+            value_ranges.push(start_position..end_position);
+            start_position = end_position + POSITION_GAP;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::docset::DocSet;
+    use crate::schema::{Schema, STORED, TEXT};
+    use crate::{Index, IndexWriter};
+
+    #[test]
+    fn test_positions_reader_multivalued() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let body = schema_builder.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(
+            body => "a b c",
+            body => "",
+            body => "c a",
+        ))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+
+        let mut postings = segment_reader.positions_reader(body, "a")?.unwrap();
+        assert_eq!(postings.doc(), 0);
+        let doc_positions = postings.doc_positions()?;
+        assert_eq!(doc_positions.num_values(), 3);
+        let values: Vec<&[u32]> = doc_positions.values().collect();
+        assert_eq!(values, vec![&[0][..], &[], &[1]]);
+        assert!(segment_reader.positions_reader(body, "z")?.is_none());
+        Ok(())
+    }
+}
@@ -14,7 +14,7 @@ use crate::schema::{Field, Schema, Term, Type};
 use crate::tokenizer::{Token, TokenStream, MAX_TOKEN_LEN};
 use crate::DocId;
 
-const POSITION_GAP: u32 = 1;
+pub(crate) const POSITION_GAP: u32 = 1;
 
 fn make_field_partition(
     term_offsets: &[(Field, OrderedPathId, &[u8], Addr)],
@@ -97,6 +97,10 @@ pub(crate) fn serialize_postings(
 pub(crate) struct IndexingPosition {
     pub num_tokens: u32,
     pub end_position: u32,
+    /// Tokens dropped for exceeding `MAX_TOKEN_LEN`.
+    pub num_dropped_tokens: u32,
+    /// Token streams ended early by a tokenizer error.
+    pub num_tokenizer_errors: u32,
 }
 
 /// The `PostingsWriter` is in charge of receiving documenting
@@ -134,6 +138,7 @@ pub(crate) trait PostingsWriter: Send + Sync {
     ) {
         let end_of_path_idx = term_buffer.len_bytes();
         let mut num_tokens = 0;
+        let mut num_dropped_tokens = 0;
         let mut end_position = indexing_position.end_position;
         token_stream.process(&mut |token: &Token| {
             // We skip all tokens with a len greater than u16.
@@ -144,6 +149,7 @@ pub(crate) trait PostingsWriter: Send + Sync {
                     token.text.len(),
                     MAX_TOKEN_LEN
                 );
+                num_dropped_tokens += 1;
                 return;
             }
             term_buffer.truncate_value_bytes(end_of_path_idx);
@@ -156,6 +162,11 @@ pub(crate) trait PostingsWriter: Send + Sync {
 
         indexing_position.end_position = end_position + POSITION_GAP;
         indexing_position.num_tokens += num_tokens;
+        indexing_position.num_dropped_tokens += num_dropped_tokens;
+        if let Some(error) = token_stream.error() {
+            warn!("A tokenizer error ended the indexing of a text: {error}");
+            indexing_position.num_tokenizer_errors += 1;
+        }
         term_buffer.truncate_value_bytes(end_of_path_idx);
     }
 
@@ -7,11 +7,32 @@ use tantivy_fst::Automaton;
 use super::phrase_prefix_query::prefix_end;
 use crate::index::SegmentReader;
 use crate::postings::TermInfo;
-use crate::query::{BitSetDocSet, ConstScorer, Explanation, Scorer, Weight};
+use crate::query::{
+    BitSetDocSet, ConstScorer, CostEstimate, Explanation, Scorer, TermExpansionCost, Weight,
+};
 use crate::schema::{Field, IndexRecordOption};
 use crate::termdict::{TermDictionary, TermStreamer};
 use crate::{DocId, Score, TantivyError};
 
+/// Returns [`TantivyError::TooManyExpandedTerms`] if a query expanded to more than
+/// `max_expanded_terms` terms of the field in the segment.
+pub(crate) fn check_num_expanded_terms(
+    reader: &SegmentReader,
+    field: Field,
+    num_expanded_terms: usize,
+    max_expanded_terms: Option<usize>,
+) -> crate::Result<()> {
+    match max_expanded_terms {
+        Some(max_expanded_terms) if num_expanded_terms > max_expanded_terms => {
+            Err(TantivyError::TooManyExpandedTerms {
+                field: reader.schema().get_field_name(field).to_string(),
+                max_expanded_terms,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
 /// A weight struct for Fuzzy Term and Regex Queries
 pub struct AutomatonWeight<A> {
     field: Field,
@@ -20,6 +41,7 @@ pub struct AutomatonWeight<A> {
     // We apply additional filtering based on the given JSON path, when searching within the term
     // dictionary. This prevents terms from unrelated paths from matching the search criteria.
     json_path_bytes: Option<Box<[u8]>>,
+    max_expanded_terms: Option<usize>,
 }
 
 impl<A> AutomatonWeight<A>
@@ -33,6 +55,7 @@ where
             field,
             automaton: automaton.into(),
             json_path_bytes: None,
+            max_expanded_terms: None,
         }
     }
 
@@ -46,9 +69,19 @@ where
             field,
             automaton: automaton.into(),
             json_path_bytes: Some(json_path_bytes.to_vec().into_boxed_slice()),
+            max_expanded_terms: None,
         }
     }
 
+    /// Caps the number of terms the automaton may match in a segment.
+    ///
+    /// Beyond it, building the scorer of the segment fails with
+    /// [`TantivyError::TooManyExpandedTerms`].
+    pub fn with_max_expanded_terms(mut self, max_expanded_terms: Option<usize>) -> Self {
+        self.max_expanded_terms = max_expanded_terms;
+        self
+    }
+
     fn automaton_stream<'a>(
         &'a self,
         term_dict: &'a TermDictionary,
@@ -74,6 +107,12 @@ where
         let mut term_infos = Vec::new();
         while term_stream.advance() {
             term_infos.push(term_stream.value().clone());
+            check_num_expanded_terms(
+                reader,
+                self.field,
+                term_infos.len(),
+                self.max_expanded_terms,
+            )?;
         }
         Ok(term_infos)
     }
@@ -90,7 +129,15 @@ where
         let inverted_index = reader.inverted_index(self.field)?;
         let term_dict = inverted_index.terms();
         let mut term_stream = self.automaton_stream(term_dict)?;
+        let mut num_expanded_terms = 0;
         while term_stream.advance() {
+            num_expanded_terms += 1;
+            check_num_expanded_terms(
+                reader,
+                self.field,
+                num_expanded_terms,
+                self.max_expanded_terms,
+            )?;
             let term_info = term_stream.value();
             let mut block_segment_postings = inverted_index
                 .read_block_postings_from_terminfo(term_info, IndexRecordOption::Basic)?;
@@ -110,6 +157,18 @@ where
         Ok(Box::new(const_scorer))
     }
 
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        let inverted_index = reader.inverted_index(self.field)?;
+        let mut term_stream = self.automaton_stream(inverted_index.terms())?;
+        let mut cost = TermExpansionCost::new(reader.max_doc());
+        while term_stream.advance() {
+            if !cost.add_term(term_stream.value().doc_freq) {
+                break;
+            }
+        }
+        Ok(cost.finish())
+    }
+
     fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
         let mut scorer = self.scorer(reader, 1.0)?;
         if scorer.seek(doc) == doc {
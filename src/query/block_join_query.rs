@@ -0,0 +1,798 @@
+use std::fmt;
+
+use common::{BitSet, TinySet};
+
+use crate::docset::{DocSet, TERMINATED};
+use crate::fastfield::AliveBitSet;
+use crate::index::SegmentReader;
+use crate::query::explanation::does_not_match;
+use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::schema::{IndexRecordOption, Type};
+use crate::{DocId, Score, TantivyError, Term};
+
+/// Identifies the parent documents of the document families of an index.
+///
+/// A family is a parent document preceded by its children, added together with
+/// [`IndexWriter::add_document_block`](crate::IndexWriter::add_document_block). The parents
+/// delimit the families: the children of a parent are the documents between the previous parent
+/// and itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParentFilter {
+    /// The parents are the documents containing the term.
+    Term(Term),
+    /// The parents are the documents having the value `true` in the bool fast field.
+    FastField(String),
+}
+
+impl ParentFilter {
+    fn validate(&self, enable_scoring: &EnableScoring<'_>) -> crate::Result<()> {
+        let schema = enable_scoring.schema();
+        match self {
+            ParentFilter::Term(term) => {
+                let field_entry = schema.get_field_entry(term.field());
+                if !field_entry.is_indexed() {
+                    return Err(TantivyError::SchemaError(format!(
+                        "Field {} is not indexed.",
+                        field_entry.name()
+                    )));
+                }
+            }
+            ParentFilter::FastField(field_name) => {
+                let field = schema.get_field(field_name)?;
+                let field_type = schema.get_field_entry(field).field_type();
+                if !field_type.is_fast() || field_type.value_type() != Type::Bool {
+                    return Err(TantivyError::SchemaError(format!(
+                        "Field {field_name} is not a bool fast field."
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the parents of the segment, including the deleted ones, as they still delimit
+    /// the families.
+    fn parents(&self, reader: &SegmentReader) -> crate::Result<BitSet> {
+        let max_doc = reader.max_doc();
+        let mut parents = BitSet::with_max_value(max_doc);
+        match self {
+            ParentFilter::Term(term) => {
+                let inverted_index = reader.inverted_index(term.field())?;
+                if let Some(mut postings) =
+                    inverted_index.read_postings(term, IndexRecordOption::Basic)?
+                {
+                    let mut doc = postings.doc();
+                    while doc != TERMINATED {
+                        parents.insert(doc);
+                        doc = postings.advance();
+                    }
+                }
+            }
+            ParentFilter::FastField(field_name) => {
+                let column = reader.try_fast_fields()?.bool(field_name)?;
+                for doc in 0..max_doc {
+                    if column.values_for_doc(doc).any(|is_parent| is_parent) {
+                        parents.insert(doc);
+                    }
+                }
+            }
+        }
+        Ok(parents)
+    }
+}
+
+/// How the scores of the matching children of a parent are combined into the score of the
+/// parent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChildScoreMode {
+    /// The parents get the score 1.0.
+    None,
+    /// The maximum score of the matching children.
+    #[default]
+    Max,
+    /// The sum of the scores of the matching children.
+    Sum,
+    /// The average score of the matching children.
+    Avg,
+    /// The number of matching children.
+    Count,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JoinDirection {
+    ParentsOf,
+    ChildrenOf,
+}
+
+/// Query joining the parents and the children of block-indexed document families.
+///
+/// The families are added with
+/// [`IndexWriter::add_document_block`](crate::IndexWriter::add_document_block), which makes sure
+/// that the children are stored right before their parent, in the same segment. The parents are
+/// identified by a [`ParentFilter`].
+///
+/// - [`BlockJoinQuery::parents_of`] matches the parents having at least one child matching the
+///   child query. The parent is scored from the scores of its matching children, according to the
+///   [`ChildScoreMode`].
+/// - [`BlockJoinQuery::children_of`] matches the children of the parents matching the parent query.
+///   The children get the score of their parent.
+///
+/// To match the parents filtered by a child condition, the parent condition and
+/// `BlockJoinQuery::parents_of` can be combined in a
+/// [`BooleanQuery`](crate::query::BooleanQuery).
+///
+/// A family has to be deleted as a whole: the children of a deleted parent would otherwise be
+/// attached to the next parent once the segment is merged.
+///
+/// ```rust
+/// use tantivy::collector::TopDocs;
+/// use tantivy::query::{BlockJoinQuery, ChildScoreMode, ParentFilter, TermQuery};
+/// use tantivy::schema::{IndexRecordOption, Schema, STRING};
+/// use tantivy::{doc, Index, IndexWriter, Term};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let kind = schema_builder.add_text_field("kind", STRING);
+/// let skill = schema_builder.add_text_field("skill", STRING);
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema);
+/// let mut index_writer: IndexWriter = index.writer(15_000_000)?;
+/// index_writer.add_document_block(vec![
+///     doc!(kind => "job", skill => "rust"),
+///     doc!(kind => "job", skill => "java"),
+///     doc!(kind => "resume"),
+/// ])?;
+/// index_writer.add_document_block(vec![
+///     doc!(kind => "job", skill => "java"),
+///     doc!(kind => "resume"),
+/// ])?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let query = BlockJoinQuery::parents_of(
+///     Box::new(TermQuery::new(
+///         Term::from_field_text(skill, "java"),
+///         IndexRecordOption::Basic,
+///     )),
+///     ParentFilter::Term(Term::from_field_text(kind, "resume")),
+/// )
+/// .with_score_mode(ChildScoreMode::Count);
+/// let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+/// assert_eq!(top_docs.len(), 2);
+/// assert!(top_docs.iter().all(|(num_children, _)| *num_children == 1.0));
+/// # Ok(())
+/// # }
+/// ```
+pub struct BlockJoinQuery {
+    query: Box<dyn Query>,
+    parent_filter: ParentFilter,
+    score_mode: ChildScoreMode,
+    direction: JoinDirection,
+}
+
+impl BlockJoinQuery {
+    /// Creates a query matching the parents of the children matching `child_query`.
+    ///
+    /// The documents matched by `child_query` which are parents themselves are ignored.
+    pub fn parents_of(child_query: Box<dyn Query>, parent_filter: ParentFilter) -> BlockJoinQuery {
+        BlockJoinQuery {
+            query: child_query,
+            parent_filter,
+            score_mode: ChildScoreMode::default(),
+            direction: JoinDirection::ParentsOf,
+        }
+    }
+
+    /// Creates a query matching the children of the parents matching `parent_query`.
+    ///
+    /// The documents matched by `parent_query` which are not parents are ignored.
+    pub fn children_of(
+        parent_query: Box<dyn Query>,
+        parent_filter: ParentFilter,
+    ) -> BlockJoinQuery {
+        BlockJoinQuery {
+            query: parent_query,
+            parent_filter,
+            score_mode: ChildScoreMode::default(),
+            direction: JoinDirection::ChildrenOf,
+        }
+    }
+
+    /// Sets how the scores of the matching children are combined into the score of their
+    /// parent. Only used by [`BlockJoinQuery::parents_of`].
+    #[must_use]
+    pub fn with_score_mode(mut self, score_mode: ChildScoreMode) -> BlockJoinQuery {
+        self.score_mode = score_mode;
+        self
+    }
+}
+
+impl Clone for BlockJoinQuery {
+    fn clone(&self) -> Self {
+        BlockJoinQuery {
+            query: self.query.box_clone(),
+            parent_filter: self.parent_filter.clone(),
+            score_mode: self.score_mode,
+            direction: self.direction,
+        }
+    }
+}
+
+impl fmt::Debug for BlockJoinQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.direction {
+            JoinDirection::ParentsOf => write!(
+                f,
+                "ParentsOf(parents={:?}, score_mode={:?}, query={:?})",
+                self.parent_filter, self.score_mode, self.query
+            ),
+            JoinDirection::ChildrenOf => write!(
+                f,
+                "ChildrenOf(parents={:?}, query={:?})",
+                self.parent_filter, self.query
+            ),
+        }
+    }
+}
+
+impl Query for BlockJoinQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        self.parent_filter.validate(&enable_scoring)?;
+        let weight = self.query.weight(enable_scoring)?;
+        Ok(Box::new(BlockJoinWeight {
+            weight,
+            parent_filter: self.parent_filter.clone(),
+            score_mode: self.score_mode,
+            direction: self.direction,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+    }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.query.set_max_expanded_terms(max_expanded_terms);
+    }
+}
+
+struct BlockJoinWeight {
+    weight: Box<dyn Weight>,
+    parent_filter: ParentFilter,
+    score_mode: ChildScoreMode,
+    direction: JoinDirection,
+}
+
+impl Weight for BlockJoinWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        let parents = self.parent_filter.parents(reader)?;
+        let scorer = self.weight.scorer(reader, boost)?;
+        Ok(match self.direction {
+            JoinDirection::ParentsOf => Box::new(ParentsOfScorer::new(
+                scorer,
+                parents,
+                reader.alive_bitset().cloned(),
+                self.score_mode,
+            )),
+            JoinDirection::ChildrenOf => Box::new(ChildrenOfScorer::new(scorer, parents)),
+        })
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(does_not_match(doc));
+        }
+        let description = match self.direction {
+            JoinDirection::ParentsOf => format!("ParentsOf, score mode {:?}", self.score_mode),
+            JoinDirection::ChildrenOf => "ChildrenOf".to_string(),
+        };
+        Ok(Explanation::new_with_string(description, scorer.score()))
+    }
+}
+
+/// Returns the first parent greater than or equal to `from`.
+fn next_parent(parents: &BitSet, from: DocId) -> Option<DocId> {
+    if from >= parents.max_value() {
+        return None;
+    }
+    let bucket = from / 64;
+    let mut tinyset = parents
+        .tinyset(bucket)
+        .intersect(TinySet::range_greater_or_equal(from % 64));
+    if let Some(lower) = tinyset.pop_lowest() {
+        return Some(bucket * 64 + lower);
+    }
+    let bucket = parents.first_non_empty_bucket(bucket + 1)?;
+    let lower = parents.tinyset(bucket).pop_lowest()?;
+    Some(bucket * 64 + lower)
+}
+
+/// Returns the first child of the parent `parent`, or `parent` itself if it has no children.
+fn first_child(parents: &BitSet, parent: DocId) -> DocId {
+    let mut doc = parent;
+    while doc > 0 && !parents.contains(doc - 1) {
+        doc -= 1;
+    }
+    doc
+}
+
+/// Scorer matching the parents of the matching children.
+struct ParentsOfScorer {
+    child_scorer: Box<dyn Scorer>,
+    parents: BitSet,
+    alive_bitset: Option<AliveBitSet>,
+    score_mode: ChildScoreMode,
+    doc: DocId,
+    score: Score,
+}
+
+impl ParentsOfScorer {
+    fn new(
+        child_scorer: Box<dyn Scorer>,
+        parents: BitSet,
+        alive_bitset: Option<AliveBitSet>,
+        score_mode: ChildScoreMode,
+    ) -> ParentsOfScorer {
+        let mut scorer = ParentsOfScorer {
+            child_scorer,
+            parents,
+            alive_bitset,
+            score_mode,
+            doc: 0,
+            score: 0.0,
+        };
+        scorer.next_match();
+        scorer
+    }
+
+    fn is_matching_child(&self, doc: DocId) -> bool {
+        !self.parents.contains(doc)
+            && self
+                .alive_bitset
+                .as_ref()
+                .map(|alive_bitset| alive_bitset.is_alive(doc))
+                .unwrap_or(true)
+    }
+
+    /// Goes to the parent of the current child, or of the next matching child.
+    fn next_match(&mut self) -> DocId {
+        let mut child = self.child_scorer.doc();
+        while child != TERMINATED && !self.is_matching_child(child) {
+            child = self.child_scorer.advance();
+        }
+        let Some(parent) = (child != TERMINATED)
+            .then(|| next_parent(&self.parents, child))
+            .flatten()
+        else {
+            // Children without a parent are not part of a family.
+            self.doc = TERMINATED;
+            return TERMINATED;
+        };
+        let mut num_children = 0u32;
+        let mut score: Score = 0.0;
+        while child < parent {
+            if self.is_matching_child(child) {
+                let child_score = self.child_scorer.score();
+                score = match self.score_mode {
+                    ChildScoreMode::Max if num_children > 0 => score.max(child_score),
+                    ChildScoreMode::None | ChildScoreMode::Count => 0.0,
+                    _ => score + child_score,
+                };
+                num_children += 1;
+            }
+            child = self.child_scorer.advance();
+        }
+        self.score = match self.score_mode {
+            ChildScoreMode::None => 1.0,
+            ChildScoreMode::Max | ChildScoreMode::Sum => score,
+            ChildScoreMode::Avg => score / num_children as Score,
+            ChildScoreMode::Count => num_children as Score,
+        };
+        self.doc = parent;
+        parent
+    }
+}
+
+impl DocSet for ParentsOfScorer {
+    fn advance(&mut self) -> DocId {
+        if self.doc == TERMINATED {
+            return TERMINATED;
+        }
+        self.next_match()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.doc >= target {
+            return self.doc;
+        }
+        // The children of the parents greater than or equal to `target` come after the parents
+        // lower than `target`.
+        let first_candidate = first_child(&self.parents, target.min(self.parents.max_value()));
+        if self.child_scorer.doc() < first_candidate {
+            self.child_scorer.seek(first_candidate);
+        }
+        self.next_match()
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.child_scorer.size_hint()
+    }
+}
+
+impl Scorer for ParentsOfScorer {
+    fn score(&mut self) -> Score {
+        self.score
+    }
+}
+
+/// Scorer matching the children of the matching parents.
+struct ChildrenOfScorer {
+    parent_scorer: Box<dyn Scorer>,
+    parents: BitSet,
+    doc: DocId,
+    /// The matching parent of the current child.
+    parent: DocId,
+    score: Score,
+}
+
+impl ChildrenOfScorer {
+    fn new(parent_scorer: Box<dyn Scorer>, parents: BitSet) -> ChildrenOfScorer {
+        let mut scorer = ChildrenOfScorer {
+            parent_scorer,
+            parents,
+            doc: 0,
+            parent: 0,
+            score: 0.0,
+        };
+        scorer.next_family();
+        scorer
+    }
+
+    /// Goes to the first child of the current parent, or of the next matching parent having
+    /// children.
+    fn next_family(&mut self) -> DocId {
+        let mut parent = self.parent_scorer.doc();
+        loop {
+            if parent == TERMINATED {
+                self.doc = TERMINATED;
+                return TERMINATED;
+            }
+            if self.parents.contains(parent) {
+                let first_child = first_child(&self.parents, parent);
+                if first_child < parent {
+                    self.parent = parent;
+                    self.score = self.parent_scorer.score();
+                    self.doc = first_child;
+                    return first_child;
+                }
+            }
+            parent = self.parent_scorer.advance();
+        }
+    }
+}
+
+impl DocSet for ChildrenOfScorer {
+    fn advance(&mut self) -> DocId {
+        if self.doc == TERMINATED {
+            return TERMINATED;
+        }
+        if self.doc + 1 < self.parent {
+            self.doc += 1;
+            return self.doc;
+        }
+        self.parent_scorer.advance();
+        self.next_family()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.doc >= target {
+            return self.doc;
+        }
+        if target < self.parent {
+            self.doc = target;
+            return target;
+        }
+        // The children of a parent equal to `target` are all lower than `target`.
+        if self.parent_scorer.seek(target) == target {
+            self.parent_scorer.advance();
+        }
+        if self.next_family() < target {
+            // The family of the parent contains `target`.
+            self.doc = target;
+        }
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.parent_scorer.size_hint()
+    }
+}
+
+impl Scorer for ChildrenOfScorer {
+    fn score(&mut self) -> Score {
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockJoinQuery, ChildScoreMode, ParentFilter};
+    use crate::collector::{Count, DocSetCollector, TopDocs};
+    use crate::query::{BooleanQuery, ConstScoreQuery, Occur, Query, TermQuery};
+    use crate::schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING};
+    use crate::{Index, IndexWriter, Searcher, TantivyDocument, Term};
+
+    struct Families {
+        index: Index,
+        kind: Field,
+        family: Field,
+        skill: Field,
+    }
+
+    /// Indexes `num_families` families of a parent and its children, in several segments. The
+    /// children of the family `i` have the skills `a`, `b`, `c` cycling from `i`.
+    fn index_families(num_families: usize, num_children: usize) -> crate::Result<Families> {
+        let mut schema_builder = Schema::builder();
+        let kind = schema_builder.add_text_field("kind", STRING | STORED);
+        let family = schema_builder.add_text_field("family", STRING | STORED);
+        let skill = schema_builder.add_text_field("skill", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_with_num_threads(4, 60_000_000)?;
+        for family_id in 0..num_families {
+            let family_name = format!("family{family_id}");
+            let mut block: Vec<TantivyDocument> = (0..num_children)
+                .map(|child_id| {
+                    let skill_name = ["a", "b", "c"][(family_id + child_id) % 3];
+                    doc!(kind => "child", family => family_name.as_str(), skill => skill_name)
+                })
+                .collect();
+            block.push(doc!(kind => "parent", family => family_name.as_str()));
+            index_writer.add_document_block(block)?;
+            if family_id % 7 == 6 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(Families {
+            index,
+            kind,
+            family,
+            skill,
+        })
+    }
+
+    fn term_query(field: Field, text: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_field_text(field, text),
+            IndexRecordOption::Basic,
+        ))
+    }
+
+    fn parent_filter(families: &Families) -> ParentFilter {
+        ParentFilter::Term(Term::from_field_text(families.kind, "parent"))
+    }
+
+    /// Returns the sorted family names and kinds of the matching docs.
+    fn matching_families(
+        searcher: &Searcher,
+        families: &Families,
+        query: &dyn Query,
+    ) -> crate::Result<Vec<(String, String)>> {
+        let mut matches = Vec::new();
+        for doc_address in searcher.search(query, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let family_name = doc.get_first(families.family).unwrap().as_str().unwrap();
+            let kind = doc.get_first(families.kind).unwrap().as_str().unwrap();
+            matches.push((family_name.to_string(), kind.to_string()));
+        }
+        matches.sort();
+        Ok(matches)
+    }
+
+    #[test]
+    fn test_parents_of_multi_family_segments() -> crate::Result<()> {
+        let families = index_families(30, 2)?;
+        let searcher = families.index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+        // Families with children `a` and `b`, or `c` and `a`.
+        let query =
+            BlockJoinQuery::parents_of(term_query(families.skill, "a"), parent_filter(&families));
+        let matches = matching_families(&searcher, &families, &query)?;
+        let expected: Vec<(String, String)> = {
+            let mut expected: Vec<(String, String)> = (0..30)
+                .filter(|family_id| family_id % 3 != 1)
+                .map(|family_id| (format!("family{family_id}"), "parent".to_string()))
+                .collect();
+            expected.sort();
+            expected
+        };
+        assert_eq!(matches, expected);
+        assert_eq!(searcher.search(&query, &Count)?, 20);
+
+        // Parents filtered by a child condition.
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, term_query(families.family, "family3")),
+            (
+                Occur::Must,
+                Box::new(BlockJoinQuery::parents_of(
+                    term_query(families.skill, "a"),
+                    parent_filter(&families),
+                )),
+            ),
+        ]);
+        assert_eq!(
+            matching_families(&searcher, &families, &query)?,
+            vec![("family3".to_string(), "parent".to_string())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_children_of() -> crate::Result<()> {
+        let families = index_families(30, 3)?;
+        let searcher = families.index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+        let query = BlockJoinQuery::children_of(
+            Box::new(BooleanQuery::union(vec![
+                term_query(families.family, "family4"),
+                term_query(families.family, "family17"),
+            ])),
+            parent_filter(&families),
+        );
+        let matches = matching_families(&searcher, &families, &query)?;
+        let mut expected = vec![("family17".to_string(), "child".to_string()); 3];
+        expected.extend(vec![("family4".to_string(), "child".to_string()); 3]);
+        assert_eq!(matches, expected);
+
+        // Children filtered by a parent condition, which requires seeking.
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, term_query(families.skill, "b")),
+            (
+                Occur::Must,
+                Box::new(BlockJoinQuery::children_of(
+                    term_query(families.family, "family5"),
+                    parent_filter(&families),
+                )),
+            ),
+        ]);
+        assert_eq!(
+            matching_families(&searcher, &families, &query)?,
+            vec![("family5".to_string(), "child".to_string())]
+        );
+
+        // The matching docs which are not parents are ignored.
+        let query =
+            BlockJoinQuery::children_of(term_query(families.skill, "a"), parent_filter(&families));
+        assert_eq!(searcher.search(&query, &Count)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_families_are_not_split_across_segments() -> crate::Result<()> {
+        let families = index_families(200, 5)?;
+        let searcher = families.index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+        for family_id in [0, 57, 123, 199] {
+            let family_name = format!("family{family_id}");
+            let query = BlockJoinQuery::children_of(
+                term_query(families.family, &family_name),
+                parent_filter(&families),
+            );
+            let matches = matching_families(&searcher, &families, &query)?;
+            assert_eq!(matches, vec![(family_name, "child".to_string()); 5]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parents_of_with_deleted_children() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let is_parent = schema_builder.add_bool_field("is_parent", FAST | INDEXED);
+        let id = schema_builder.add_text_field("id", STRING | STORED);
+        let skill = schema_builder.add_text_field("skill", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document_block(vec![
+            doc!(id => "c1", skill => "rust"),
+            doc!(id => "c2", skill => "rust"),
+            doc!(id => "p1", is_parent => true),
+        ])?;
+        index_writer.add_document_block(vec![
+            doc!(id => "c3", skill => "rust"),
+            doc!(id => "c4", skill => "java"),
+            doc!(id => "p2", is_parent => true),
+        ])?;
+        index_writer.commit()?;
+        index_writer.delete_term(Term::from_field_text(id, "c1"))?;
+        index_writer.delete_term(Term::from_field_text(id, "c3"))?;
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let query = BlockJoinQuery::parents_of(
+            term_query(skill, "rust"),
+            ParentFilter::FastField("is_parent".to_string()),
+        )
+        .with_score_mode(ChildScoreMode::Count);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        assert_eq!(top_docs.len(), 1);
+        assert_eq!(top_docs[0].0, 1.0);
+        assert_eq!(top_docs[0].1.doc_id, 2);
+
+        // The families are preserved by merges.
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        assert_eq!(top_docs.len(), 1);
+        assert_eq!(top_docs[0].0, 1.0);
+        let java_query = BlockJoinQuery::parents_of(
+            term_query(skill, "java"),
+            ParentFilter::FastField("is_parent".to_string()),
+        );
+        let parent: TantivyDocument =
+            searcher.doc(searcher.search(&java_query, &TopDocs::with_limit(1))?[0].1)?;
+        assert_eq!(parent.get_first(id).unwrap().as_str(), Some("p2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parents_of_score_modes() -> crate::Result<()> {
+        let families = index_families(1, 3)?;
+        let searcher = families.index.reader()?.searcher();
+        // The children have the skills `a`, `b` and `c`.
+        let scored_skill = |skill: &str, score: f32| -> Box<dyn Query> {
+            Box::new(ConstScoreQuery::new(
+                term_query(families.skill, skill),
+                score,
+            ))
+        };
+        let child_query = BooleanQuery::union(vec![
+            scored_skill("a", 1.0),
+            scored_skill("b", 2.0),
+            scored_skill("c", 6.0),
+        ]);
+        for (score_mode, expected_score) in [
+            (ChildScoreMode::None, 1.0),
+            (ChildScoreMode::Max, 6.0),
+            (ChildScoreMode::Sum, 9.0),
+            (ChildScoreMode::Avg, 3.0),
+            (ChildScoreMode::Count, 3.0),
+        ] {
+            let query =
+                BlockJoinQuery::parents_of(Box::new(child_query.clone()), parent_filter(&families))
+                    .with_score_mode(score_mode);
+            let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+            assert_eq!(top_docs.len(), 1, "{score_mode:?}");
+            assert_eq!(top_docs[0].0, expected_score, "{score_mode:?}");
+            let explanation = query.explain(&searcher, top_docs[0].1)?;
+            assert_eq!(explanation.value(), expected_score);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_join_invalid_parent_filter() -> crate::Result<()> {
+        let families = index_families(1, 1)?;
+        let searcher = families.index.reader()?.searcher();
+        let query = BlockJoinQuery::parents_of(
+            term_query(families.skill, "a"),
+            ParentFilter::FastField("kind".to_string()),
+        );
+        assert!(searcher.search(&query, &Count).is_err());
+        let mut index_writer: IndexWriter<TantivyDocument> = families.index.writer_for_tests()?;
+        assert!(index_writer.add_document_block(Vec::new()).is_err());
+        index_writer.rollback()?;
+        Ok(())
+    }
+}
@@ -173,6 +173,12 @@ impl Query for BooleanQuery {
             subquery.query_terms(visitor);
         }
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        for (_occur, subquery) in &mut self.subqueries {
+            subquery.set_max_expanded_terms(max_expanded_terms);
+        }
+    }
 }
 
 impl BooleanQuery {
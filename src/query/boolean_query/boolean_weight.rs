@@ -9,9 +9,10 @@ use crate::query::score_combiner::{DoNothingCombiner, ScoreCombiner};
 use crate::query::term_query::TermScorer;
 use crate::query::weight::{for_each_docset_buffered, for_each_pruning_scorer, for_each_scorer};
 use crate::query::{
-    intersect_scorers, BufferedUnionScorer, EmptyScorer, Exclude, Explanation, Occur,
-    RequiredOptionalScorer, Scorer, Weight,
+    intersect_scorers, BufferedUnionScorer, CostEstimate, EmptyScorer, Exclude, Explanation, Occur,
+    RequiredOptionalScorer, Scorer, TermMatch, Weight,
 };
+use crate::schema::Field;
 use crate::{DocId, Score};
 
 enum SpecializedScorer {
@@ -291,6 +292,45 @@ impl<TScoreCombiner: ScoreCombiner + Sync> Weight for BooleanWeight<TScoreCombin
         Ok(explanation)
     }
 
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        let mut per_occur_estimates: HashMap<Occur, Vec<CostEstimate>> = HashMap::new();
+        for (occur, subweight) in &self.weights {
+            per_occur_estimates
+                .entry(*occur)
+                .or_default()
+                .push(subweight.estimate_cost(reader)?);
+        }
+        let mut per_occur_union = |occur: Occur| {
+            let estimates = per_occur_estimates.remove(&occur).unwrap_or_default();
+            CostEstimate::union(estimates, reader.max_doc())
+        };
+        let should = per_occur_union(Occur::Should);
+        let must_not = per_occur_union(Occur::MustNot);
+        // With must clauses, the matches are within their intersection, which the should
+        // clauses can only restrict.
+        let positive = match per_occur_estimates.remove(&Occur::Must) {
+            Some(must) => CostEstimate::intersection(must).examining(should),
+            None => should,
+        };
+        Ok(positive.examining(must_not))
+    }
+
+    fn term_matches(
+        &self,
+        reader: &SegmentReader,
+        doc: DocId,
+        fields: &[Field],
+        term_matches: &mut Vec<TermMatch>,
+    ) -> crate::Result<bool> {
+        let mut supported = true;
+        for (occur, subweight) in &self.weights {
+            if is_positive_occur(*occur) {
+                supported &= subweight.term_matches(reader, doc, fields, term_matches)?;
+            }
+        }
+        Ok(supported)
+    }
+
     fn for_each(
         &self,
         reader: &SegmentReader,
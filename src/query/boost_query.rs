@@ -2,7 +2,8 @@ use std::fmt;
 
 use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
 use crate::fastfield::AliveBitSet;
-use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{CostEstimate, EnableScoring, Explanation, Query, Scorer, TermMatch, Weight};
+use crate::schema::Field;
 use crate::{DocId, DocSet, Score, SegmentReader, Term};
 
 /// `BoostQuery` is a wrapper over a query used to boost its score.
@@ -20,6 +21,16 @@ impl BoostQuery {
     pub fn new(query: Box<dyn Query>, boost: Score) -> BoostQuery {
         BoostQuery { query, boost }
     }
+
+    /// The boosted query.
+    pub fn query(&self) -> &dyn Query {
+        self.query.as_ref()
+    }
+
+    /// The factor applied to the scores of the boosted query.
+    pub fn boost(&self) -> Score {
+        self.boost
+    }
 }
 
 impl Clone for BoostQuery {
@@ -51,6 +62,10 @@ impl Query for BoostQuery {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.query.query_terms(visitor)
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.query.set_max_expanded_terms(max_expanded_terms);
+    }
 }
 
 /// Weight associated to the BoostQuery.
@@ -83,6 +98,24 @@ impl Weight for BoostWeight {
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         self.weight.count(reader)
     }
+
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        self.weight.estimate_cost(reader)
+    }
+
+    fn matches_all_docs(&self) -> bool {
+        self.weight.matches_all_docs()
+    }
+
+    fn term_matches(
+        &self,
+        reader: &SegmentReader,
+        doc: DocId,
+        fields: &[Field],
+        term_matches: &mut Vec<TermMatch>,
+    ) -> crate::Result<bool> {
+        self.weight.term_matches(reader, doc, fields, term_matches)
+    }
 }
 
 pub(crate) struct BoostScorer<S: Scorer> {
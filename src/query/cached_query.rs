@@ -0,0 +1,358 @@
+use std::fmt;
+use std::hash::Hasher;
+use std::sync::{Arc, Mutex};
+
+use common::BitSet;
+use lru::LruCache;
+
+use super::weight::for_each_docset_buffered;
+use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
+use crate::index::SegmentId;
+use crate::query::{
+    BitSetDocSet, ConstScorer, EnableScoring, Explanation, Query, Scorer, TermQuery, Weight,
+};
+use crate::store::CacheStats;
+use crate::{DocId, Score, SegmentReader, Term};
+
+/// A query whose matching documents can be cached by a [`QueryBitsetCache`].
+pub trait CacheableQuery: Query + Clone {
+    /// Returns a hash identifying the documents matched by the query.
+    ///
+    /// Two queries with the same fingerprint must match the same documents, and the fingerprint
+    /// must not change during the lifetime of the cache.
+    fn fingerprint(&self) -> u64;
+}
+
+impl CacheableQuery for TermQuery {
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(b"term");
+        hasher.write(self.term().serialized_term());
+        hasher.finish()
+    }
+}
+
+/// Cache key: searcher generation, query fingerprint and segment.
+type BitsetKey = (u64, u64, SegmentId);
+
+/// Cache of the documents matched by [`CachedQuery`]s, in each segment of a searcher generation.
+///
+/// The matching documents are stored as one bitset per segment, which takes `max_doc / 8` bytes.
+/// When the bitsets exceed the byte budget, the least recently used ones are evicted.
+///
+/// The cache holds the bitsets of a single searcher generation: searching with a searcher of
+/// another generation clears it.
+///
+/// Cloning the cache is cheap, and the clones share their bitsets.
+#[derive(Clone)]
+pub struct QueryBitsetCache {
+    inner: Arc<Mutex<QueryBitsetCacheInner>>,
+}
+
+struct QueryBitsetCacheInner {
+    generation_id: Option<u64>,
+    bitsets: LruCache<BitsetKey, Arc<BitSet>>,
+    num_bytes: usize,
+    max_num_bytes: usize,
+    cache_hits: usize,
+    cache_misses: usize,
+}
+
+impl QueryBitsetCache {
+    /// Creates a cache holding at most `max_num_bytes` of bitsets.
+    pub fn new(max_num_bytes: usize) -> QueryBitsetCache {
+        QueryBitsetCache {
+            inner: Arc::new(Mutex::new(QueryBitsetCacheInner {
+                generation_id: None,
+                bitsets: LruCache::unbounded(),
+                num_bytes: 0,
+                max_num_bytes,
+                cache_hits: 0,
+                cache_misses: 0,
+            })),
+        }
+    }
+
+    /// Returns the number of cached bitsets and the number of cache hits and misses.
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            num_entries: inner.bitsets.len(),
+            cache_hits: inner.cache_hits,
+            cache_misses: inner.cache_misses,
+        }
+    }
+
+    /// Returns the number of bytes used by the cached bitsets.
+    pub fn num_bytes(&self) -> usize {
+        self.inner.lock().unwrap().num_bytes
+    }
+
+    /// Clears the cache if it holds the bitsets of another searcher generation.
+    fn set_generation(&self, generation_id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation_id != Some(generation_id) {
+            inner.generation_id = Some(generation_id);
+            inner.bitsets.clear();
+            inner.num_bytes = 0;
+        }
+    }
+
+    fn get(&self, key: &BitsetKey) -> Option<Arc<BitSet>> {
+        let mut inner = self.inner.lock().unwrap();
+        let bitset_opt = inner.bitsets.get(key).cloned();
+        if bitset_opt.is_some() {
+            inner.cache_hits += 1;
+        } else {
+            inner.cache_misses += 1;
+        }
+        bitset_opt
+    }
+
+    fn insert(&self, key: BitsetKey, bitset: Arc<BitSet>) {
+        let mut inner = self.inner.lock().unwrap();
+        let num_bytes = bitset_num_bytes(&bitset);
+        if inner.generation_id != Some(key.0) || num_bytes > inner.max_num_bytes {
+            return;
+        }
+        if let Some((_, replaced_bitset)) = inner.bitsets.push(key, bitset) {
+            inner.num_bytes -= bitset_num_bytes(&replaced_bitset);
+        }
+        inner.num_bytes += num_bytes;
+        while inner.num_bytes > inner.max_num_bytes {
+            let Some((_, evicted_bitset)) = inner.bitsets.pop_lru() else {
+                break;
+            };
+            inner.num_bytes -= bitset_num_bytes(&evicted_bitset);
+        }
+    }
+}
+
+impl fmt::Debug for QueryBitsetCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("QueryBitsetCache")
+            .field("num_entries", &inner.bitsets.len())
+            .field("num_bytes", &inner.num_bytes)
+            .field("max_num_bytes", &inner.max_num_bytes)
+            .finish()
+    }
+}
+
+fn bitset_num_bytes(bitset: &BitSet) -> usize {
+    (bitset.max_value() as usize).div_ceil(64) * 8
+}
+
+/// Query wrapper caching the documents matched by a [`CacheableQuery`] in a [`QueryBitsetCache`].
+///
+/// When scoring is disabled, e.g. for aggregations or counting, the documents matched in a
+/// segment are read from the cache, or collected into a bitset and cached. When scoring is
+/// enabled, the wrapped query is run as is, as the bitsets do not hold the scores.
+///
+/// The bitsets hold all the documents matched in a segment: like for any other query, the
+/// deleted documents are skipped by the collectors.
+#[derive(Clone, Debug)]
+pub struct CachedQuery<Q> {
+    query: Q,
+    cache: QueryBitsetCache,
+}
+
+impl<Q: CacheableQuery> CachedQuery<Q> {
+    /// Wraps `query`, caching its matching documents in `cache`.
+    pub fn new(query: Q, cache: QueryBitsetCache) -> CachedQuery<Q> {
+        CachedQuery { query, cache }
+    }
+}
+
+impl<Q: CacheableQuery> Query for CachedQuery<Q> {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        let weight = self.query.weight(enable_scoring)?;
+        let searcher = match enable_scoring {
+            EnableScoring::Disabled {
+                searcher_opt: Some(searcher),
+                ..
+            } => searcher,
+            _ => return Ok(weight),
+        };
+        let generation_id = searcher.generation().generation_id();
+        self.cache.set_generation(generation_id);
+        Ok(Box::new(CachedWeight {
+            weight,
+            cache: self.cache.clone(),
+            generation_id,
+            fingerprint: self.query.fingerprint(),
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+    }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.query.set_max_expanded_terms(max_expanded_terms);
+    }
+}
+
+struct CachedWeight {
+    weight: Box<dyn Weight>,
+    cache: QueryBitsetCache,
+    generation_id: u64,
+    fingerprint: u64,
+}
+
+impl CachedWeight {
+    fn matching_docs(&self, reader: &SegmentReader) -> crate::Result<Arc<BitSet>> {
+        let key = (self.generation_id, self.fingerprint, reader.segment_id());
+        if let Some(bitset) = self.cache.get(&key) {
+            return Ok(bitset);
+        }
+        let mut bitset = BitSet::with_max_value(reader.max_doc());
+        let mut scorer = self.weight.scorer(reader, 1.0)?;
+        let mut buffer = [0u32; COLLECT_BLOCK_BUFFER_LEN];
+        for_each_docset_buffered(&mut scorer, &mut buffer, |docs| {
+            for &doc in docs {
+                bitset.insert(doc);
+            }
+        });
+        let bitset = Arc::new(bitset);
+        self.cache.insert(key, bitset.clone());
+        Ok(bitset)
+    }
+}
+
+impl Weight for CachedWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        let bitset = self.matching_docs(reader)?;
+        let docset = BitSetDocSet::from(bitset);
+        Ok(Box::new(ConstScorer::new(docset, boost)))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        self.weight.explain(reader, doc)
+    }
+
+    fn matches_all_docs(&self) -> bool {
+        self.weight.matches_all_docs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Count, DocSetCollector};
+    use crate::schema::{IndexRecordOption, Schema, FAST, STRING};
+    use crate::{Index, IndexWriter};
+
+    fn test_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let color = schema_builder.add_text_field("color", STRING);
+        let price = schema_builder.add_u64_field("price", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for segment in 0..2u64 {
+            for doc_id in 0..100u64 {
+                let color_value = if doc_id % 3 == 0 { "red" } else { "blue" };
+                index_writer.add_document(doc!(color => color_value, price => doc_id + segment))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn color_query(index: &Index, color: &str) -> TermQuery {
+        let field = index.schema().get_field("color").unwrap();
+        TermQuery::new(
+            Term::from_field_text(field, color),
+            IndexRecordOption::Basic,
+        )
+    }
+
+    #[test]
+    fn test_cached_query_hits_cache() -> crate::Result<()> {
+        let index = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let cache = QueryBitsetCache::new(1_000_000);
+        let query = CachedQuery::new(color_query(&index, "red"), cache.clone());
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "price_stats": { "stats": { "field": "price" } }
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+
+        let uncached = searcher.search(&color_query(&index, "red"), &collector)?;
+        let first = searcher.search(&query, &collector)?;
+        assert_eq!(cache.stats().cache_hits, 0);
+        assert_eq!(cache.stats().cache_misses, 2);
+        assert_eq!(cache.stats().num_entries, 2);
+        let second = searcher.search(&query, &collector)?;
+        assert_eq!(cache.stats().cache_hits, 2);
+        assert_eq!(cache.stats().cache_misses, 2);
+
+        let to_json = |res| serde_json::to_value(res).unwrap();
+        assert_eq!(to_json(&first), to_json(&uncached));
+        assert_eq!(to_json(&second), to_json(&uncached));
+        assert_eq!(
+            searcher.search(&query, &DocSetCollector)?,
+            searcher.search(&color_query(&index, "red"), &DocSetCollector)?
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 68);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_query_scoring_bypasses_cache() -> crate::Result<()> {
+        let index = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let cache = QueryBitsetCache::new(1_000_000);
+        let query = CachedQuery::new(color_query(&index, "red"), cache.clone());
+        let top_docs = searcher.search(&query, &crate::collector::TopDocs::with_limit(100))?;
+        assert_eq!(top_docs.len(), 68);
+        assert_eq!(cache.stats().num_entries, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_query_evicts_older_entries() -> crate::Result<()> {
+        let index = test_index()?;
+        let searcher = index.reader()?.searcher();
+        // 100 docs per segment: each bitset takes 16 bytes.
+        let cache = QueryBitsetCache::new(40);
+        let red_query = CachedQuery::new(color_query(&index, "red"), cache.clone());
+        let blue_query = CachedQuery::new(color_query(&index, "blue"), cache.clone());
+
+        assert_eq!(searcher.search(&red_query, &Count)?, 68);
+        assert_eq!(cache.num_bytes(), 32);
+        assert_eq!(searcher.search(&blue_query, &Count)?, 132);
+        assert_eq!(cache.num_bytes(), 32);
+        assert_eq!(cache.stats().num_entries, 2);
+        // The bitsets of the red query were evicted.
+        assert_eq!(searcher.search(&blue_query, &Count)?, 132);
+        assert_eq!(cache.stats().cache_hits, 2);
+        assert_eq!(searcher.search(&red_query, &Count)?, 68);
+        assert_eq!(cache.stats().cache_hits, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_query_new_generation_clears_cache() -> crate::Result<()> {
+        let index = test_index()?;
+        let reader = index.reader()?;
+        let cache = QueryBitsetCache::new(1_000_000);
+        let query = CachedQuery::new(color_query(&index, "red"), cache.clone());
+        assert_eq!(reader.searcher().search(&query, &Count)?, 68);
+
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let color = index.schema().get_field("color").unwrap();
+        index_writer.add_document(doc!(color => "red"))?;
+        index_writer.commit()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().search(&query, &Count)?, 69);
+        assert_eq!(cache.stats().cache_hits, 0);
+        assert_eq!(cache.stats().num_entries, 3);
+        Ok(())
+    }
+}
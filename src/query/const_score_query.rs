@@ -1,7 +1,7 @@
 use std::fmt;
 
 use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
-use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{CostEstimate, EnableScoring, Explanation, Query, Scorer, Weight};
 use crate::{DocId, DocSet, Score, SegmentReader, TantivyError, Term};
 
 /// `ConstScoreQuery` is a wrapper over a query to provide a constant score.
@@ -19,6 +19,16 @@ impl ConstScoreQuery {
     pub fn new(query: Box<dyn Query>, score: Score) -> ConstScoreQuery {
         ConstScoreQuery { query, score }
     }
+
+    /// The query whose matches get the constant score.
+    pub fn query(&self) -> &dyn Query {
+        self.query.as_ref()
+    }
+
+    /// The score of all of the matches.
+    pub fn score(&self) -> Score {
+        self.score
+    }
 }
 
 impl Clone for ConstScoreQuery {
@@ -49,6 +59,10 @@ impl Query for ConstScoreQuery {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.query.query_terms(visitor);
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.query.set_max_expanded_terms(max_expanded_terms);
+    }
 }
 
 struct ConstWeight {
@@ -84,6 +98,14 @@ impl Weight for ConstWeight {
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         self.weight.count(reader)
     }
+
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        self.weight.estimate_cost(reader)
+    }
+
+    fn matches_all_docs(&self) -> bool {
+        self.weight.matches_all_docs()
+    }
 }
 
 /// Wraps a `DocSet` and simply returns a constant `Scorer`.
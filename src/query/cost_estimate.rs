@@ -0,0 +1,455 @@
+use std::ops::AddAssign;
+
+use crate::DocId;
+
+/// Number of terms of the term dictionary walked to bound the cost of a query expanding to the
+/// terms matching an automaton or a range, before reporting it as unbounded.
+pub(crate) const COST_ESTIMATE_MAX_EXPANDED_TERMS: u64 = 10_000;
+
+/// Upper bounds of the cost of a query, on a segment or on all of the segments of a searcher.
+///
+/// See [`Searcher::estimate_cost()`](crate::Searcher::estimate_cost).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CostBounds {
+    /// Upper bound of the number of documents matching the query, deleted documents included.
+    pub matching_docs: u64,
+    /// Upper bound of the number of documents examined to find the matching documents: the
+    /// documents of the postings read, or the values of the fast fields scanned.
+    ///
+    /// It is only a lower bound if the bounds are `unbounded`.
+    pub docs_examined: u64,
+    /// Number of terms the query expands to, for the queries matching the terms of an automaton
+    /// or of a range.
+    pub expanded_terms: u64,
+    /// True if a part of the query could not be bounded cheaply, e.g. a regex query matching
+    /// more terms than the walk of the term dictionary goes through.
+    ///
+    /// `matching_docs` is still an upper bound, but `docs_examined` and `expanded_terms` only
+    /// account for the part of the query which was bounded.
+    pub unbounded: bool,
+}
+
+impl CostBounds {
+    /// Bounds of a query matching all of the `max_doc` documents of a segment.
+    pub(crate) fn all_docs(max_doc: DocId) -> CostBounds {
+        CostBounds {
+            matching_docs: u64::from(max_doc),
+            docs_examined: u64::from(max_doc),
+            ..CostBounds::default()
+        }
+    }
+
+    /// Bounds of a query reading the postings of a term of document frequency `doc_freq`.
+    pub(crate) fn postings(doc_freq: u32) -> CostBounds {
+        CostBounds {
+            matching_docs: u64::from(doc_freq),
+            docs_examined: u64::from(doc_freq),
+            ..CostBounds::default()
+        }
+    }
+}
+
+impl AddAssign for CostBounds {
+    fn add_assign(&mut self, other: CostBounds) {
+        self.matching_docs += other.matching_docs;
+        self.docs_examined += other.docs_examined;
+        self.expanded_terms += other.expanded_terms;
+        self.unbounded |= other.unbounded;
+    }
+}
+
+/// Estimate of the cost of a query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CostEstimate {
+    /// Upper bounds of the cost.
+    Bounded(CostBounds),
+    /// The type of query, or of one of its subqueries, does not estimate its cost.
+    Unknown,
+}
+
+impl CostEstimate {
+    /// Returns the bounds of the estimate, if the cost is known.
+    pub fn bounds(&self) -> Option<&CostBounds> {
+        match self {
+            CostEstimate::Bounded(bounds) => Some(bounds),
+            CostEstimate::Unknown => None,
+        }
+    }
+
+    /// Returns true if the cost is unknown, or if a part of the query could not be bounded.
+    pub fn is_unbounded(&self) -> bool {
+        self.bounds().is_none_or(|bounds| bounds.unbounded)
+    }
+
+    /// Combines the estimates of the subqueries of a union, on a segment of `max_doc` documents.
+    pub(crate) fn union(
+        estimates: impl IntoIterator<Item = CostEstimate>,
+        max_doc: DocId,
+    ) -> CostEstimate {
+        let mut union = CostBounds::default();
+        for estimate in estimates {
+            let CostEstimate::Bounded(bounds) = estimate else {
+                return CostEstimate::Unknown;
+            };
+            union += bounds;
+        }
+        union.matching_docs = union.matching_docs.min(u64::from(max_doc));
+        CostEstimate::Bounded(union)
+    }
+
+    /// Combines the estimates of the subqueries of an intersection: all of them are examined,
+    /// and the documents matching the intersection match each of them.
+    pub(crate) fn intersection(estimates: impl IntoIterator<Item = CostEstimate>) -> CostEstimate {
+        let mut estimates = estimates.into_iter();
+        let Some(first) = estimates.next() else {
+            return CostEstimate::Bounded(CostBounds::default());
+        };
+        estimates.fold(first, |intersection, estimate| {
+            let matching_docs = match (intersection, estimate) {
+                (CostEstimate::Bounded(left), CostEstimate::Bounded(right)) => {
+                    left.matching_docs.min(right.matching_docs)
+                }
+                _ => return CostEstimate::Unknown,
+            };
+            match intersection.examining(estimate) {
+                CostEstimate::Bounded(bounds) => CostEstimate::Bounded(CostBounds {
+                    matching_docs,
+                    ..bounds
+                }),
+                CostEstimate::Unknown => CostEstimate::Unknown,
+            }
+        })
+    }
+
+    /// Adds the cost of examining the documents of `other`, which do not change the documents
+    /// matching `self`, e.g. the documents excluded from the matches.
+    pub(crate) fn examining(self, other: CostEstimate) -> CostEstimate {
+        match (self, other) {
+            (CostEstimate::Bounded(mut bounds), CostEstimate::Bounded(other)) => {
+                let matching_docs = bounds.matching_docs;
+                bounds += other;
+                bounds.matching_docs = matching_docs;
+                CostEstimate::Bounded(bounds)
+            }
+            _ => CostEstimate::Unknown,
+        }
+    }
+}
+
+/// Bounds the cost of a query matching the terms of a walk of the term dictionary, capped to
+/// [`COST_ESTIMATE_MAX_EXPANDED_TERMS`] terms.
+pub(crate) struct TermExpansionCost {
+    max_doc: DocId,
+    bounds: CostBounds,
+}
+
+impl TermExpansionCost {
+    pub fn new(max_doc: DocId) -> TermExpansionCost {
+        TermExpansionCost {
+            max_doc,
+            bounds: CostBounds::default(),
+        }
+    }
+
+    /// Accounts for the next term of the walk. Returns false if the walk should stop, because
+    /// the cap is reached.
+    pub fn add_term(&mut self, doc_freq: u32) -> bool {
+        if self.bounds.expanded_terms == COST_ESTIMATE_MAX_EXPANDED_TERMS {
+            self.bounds.unbounded = true;
+            return false;
+        }
+        self.bounds.expanded_terms += 1;
+        self.bounds.docs_examined += u64::from(doc_freq);
+        true
+    }
+
+    pub fn finish(self) -> CostEstimate {
+        let mut bounds = self.bounds;
+        bounds.matching_docs = if bounds.unbounded {
+            u64::from(self.max_doc)
+        } else {
+            bounds.docs_examined.min(u64::from(self.max_doc))
+        };
+        CostEstimate::Bounded(bounds)
+    }
+}
+
+/// Estimate of the cost of a query on the segments of a searcher.
+///
+/// See [`Searcher::estimate_cost()`](crate::Searcher::estimate_cost).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryCostEstimate {
+    /// The estimates of the segments, in the order of the segment readers of the searcher.
+    pub segments: Vec<CostEstimate>,
+    /// The sum of the estimates of the segments, unknown if one of them is.
+    pub total: CostEstimate,
+}
+
+impl QueryCostEstimate {
+    pub(crate) fn from_segments(segments: Vec<CostEstimate>) -> QueryCostEstimate {
+        let mut total = CostBounds::default();
+        for estimate in &segments {
+            match estimate {
+                CostEstimate::Bounded(bounds) => total += *bounds,
+                CostEstimate::Unknown => {
+                    return QueryCostEstimate {
+                        segments,
+                        total: CostEstimate::Unknown,
+                    };
+                }
+            }
+        }
+        QueryCostEstimate {
+            segments,
+            total: CostEstimate::Bounded(total),
+        }
+    }
+
+    /// Returns true if the cost of the query is unknown, or if a part of the query could not be
+    /// bounded on one of the segments.
+    pub fn is_unbounded(&self) -> bool {
+        self.total.is_unbounded()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use proptest::prelude::*;
+
+    use super::CostEstimate;
+    use crate::query::{
+        AllQuery, BooleanQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery,
+        TermQuery,
+    };
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, TEXT};
+    use crate::{Index, IndexWriter, Term};
+
+    const WORDS: [&str; 6] = ["apple", "apply", "banana", "cherry", "grape", "lemon"];
+
+    fn create_index(docs: &[(Vec<usize>, u64)], deletes: &[u64]) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let num = schema_builder.add_u64_field("num", FAST | INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for (i, (words, num_val)) in docs.iter().enumerate() {
+            let text_val = words
+                .iter()
+                .map(|&word| WORDS[word])
+                .collect::<Vec<_>>()
+                .join(" ");
+            index_writer.add_document(doc!(text => text_val, num => *num_val))?;
+            if i % 7 == 6 {
+                index_writer.commit()?;
+            }
+        }
+        for delete in deletes {
+            index_writer.delete_term(Term::from_field_u64(num, *delete))?;
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn term_query(word: usize) -> Box<dyn Query> {
+        let term = Term::from_field_text(crate::schema::Field::from_field_id(0), WORDS[word]);
+        Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+    }
+
+    fn num_range(lower: u64, upper: u64) -> Box<dyn Query> {
+        let num = crate::schema::Field::from_field_id(1);
+        Box::new(RangeQuery::new(
+            Bound::Included(Term::from_field_u64(num, lower)),
+            Bound::Excluded(Term::from_field_u64(num, upper)),
+        ))
+    }
+
+    fn queries() -> Vec<Box<dyn Query>> {
+        let text = crate::schema::Field::from_field_id(0);
+        let mut queries: Vec<Box<dyn Query>> = vec![
+            Box::new(AllQuery),
+            term_query(0),
+            term_query(3),
+            num_range(2, 7),
+            num_range(0, 100),
+            num_range(50, 100),
+            Box::new(RegexQuery::from_pattern("app.*", text).unwrap()),
+            Box::new(FuzzyTermQuery::new(
+                Term::from_field_text(text, "grap"),
+                1,
+                true,
+            )),
+        ];
+        let clauses = |occurs: &[Occur]| {
+            occurs
+                .iter()
+                .enumerate()
+                .map(|(i, occur)| (*occur, term_query(i % WORDS.len())))
+                .collect::<Vec<_>>()
+        };
+        queries.push(Box::new(BooleanQuery::new(clauses(&[
+            Occur::Must,
+            Occur::Must,
+        ]))));
+        queries.push(Box::new(BooleanQuery::new(clauses(&[
+            Occur::Should,
+            Occur::Should,
+            Occur::MustNot,
+        ]))));
+        queries.push(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, num_range(3, 9)),
+            (Occur::Should, term_query(2)),
+            (Occur::MustNot, term_query(4)),
+        ])));
+        queries
+    }
+
+    fn check_upper_bounds(docs: &[(Vec<usize>, u64)], deletes: &[u64]) -> crate::Result<()> {
+        let index = create_index(docs, deletes)?;
+        let searcher = index.reader()?.searcher();
+        for query in queries() {
+            let estimate = searcher.estimate_cost(query.as_ref())?;
+            assert_eq!(estimate.segments.len(), searcher.segment_readers().len());
+            let bounds = estimate.total.bounds().copied().unwrap();
+            let count = query.count(&searcher)? as u64;
+            assert!(
+                bounds.matching_docs >= count,
+                "{query:?} {bounds:?} {count}"
+            );
+            assert!(!bounds.unbounded);
+            for (segment_reader, segment_estimate) in
+                searcher.segment_readers().iter().zip(&estimate.segments)
+            {
+                let segment_count = query
+                    .weight(crate::query::EnableScoring::disabled_from_searcher(
+                        &searcher,
+                    ))?
+                    .count(segment_reader)?;
+                let segment_bounds = segment_estimate.bounds().unwrap();
+                assert!(segment_bounds.matching_docs >= u64::from(segment_count));
+                assert!(segment_bounds.matching_docs <= u64::from(segment_reader.max_doc()));
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(20))]
+        #[test]
+        fn test_cost_estimate_upper_bounds_proptest(
+            docs in proptest::collection::vec(
+                (proptest::collection::vec(0..WORDS.len(), 0..4), 0u64..10),
+                1..40,
+            ),
+            deletes in proptest::collection::vec(0u64..10, 0..3),
+        ) {
+            check_upper_bounds(&docs, &deletes).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cost_estimate_ordering() -> crate::Result<()> {
+        let docs: Vec<(Vec<usize>, u64)> = (0..100)
+            .map(|i| {
+                let words = if i % 20 == 0 { vec![0, 2] } else { vec![2] };
+                (words, i % 10)
+            })
+            .collect();
+        let index = create_index(&docs, &[])?;
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text")?;
+        let cost = |query: &dyn Query| {
+            searcher
+                .estimate_cost(query)
+                .unwrap()
+                .total
+                .bounds()
+                .copied()
+                .unwrap()
+        };
+        let rare = cost(term_query(0).as_ref());
+        let frequent = cost(term_query(2).as_ref());
+        let missing = cost(term_query(5).as_ref());
+        assert_eq!(rare.matching_docs, 5);
+        assert_eq!(frequent.matching_docs, 100);
+        assert_eq!(missing.matching_docs, 0);
+        assert!(rare.docs_examined < frequent.docs_examined);
+
+        let regex_all = cost(&RegexQuery::from_pattern(".*", text)?);
+        // The terms are expanded on each segment.
+        assert!(regex_all.expanded_terms > searcher.segment_readers().len() as u64);
+        assert!(rare.docs_examined < regex_all.docs_examined);
+        let all = cost(&AllQuery);
+        assert!(rare.docs_examined < all.docs_examined);
+        // The range is outside of the values of the column.
+        assert_eq!(cost(num_range(20, 30).as_ref()).matching_docs, 0);
+        assert_eq!(cost(num_range(0, 30).as_ref()).matching_docs, 100);
+
+        let intersection = cost(&BooleanQuery::new(vec![
+            (Occur::Must, term_query(0)),
+            (Occur::Must, term_query(2)),
+        ]));
+        assert_eq!(intersection.matching_docs, 5);
+        assert_eq!(intersection.docs_examined, 105);
+        let exclusion = cost(&BooleanQuery::new(vec![
+            (Occur::Must, term_query(2)),
+            (Occur::MustNot, term_query(0)),
+        ]));
+        assert_eq!(exclusion.matching_docs, 100);
+        assert_eq!(exclusion.docs_examined, 105);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cost_estimate_unknown() -> crate::Result<()> {
+        let index = create_index(&[(vec![0, 1], 1)], &[])?;
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text")?;
+        let phrase = PhraseQuery::new(vec![
+            Term::from_field_text(text, "apple"),
+            Term::from_field_text(text, "apply"),
+        ]);
+        let estimate = searcher.estimate_cost(&phrase)?;
+        assert_eq!(estimate.total, CostEstimate::Unknown);
+        assert!(estimate.is_unbounded());
+        // An unknown clause makes the whole boolean query unknown.
+        let boolean = BooleanQuery::new(vec![
+            (Occur::Must, term_query(0)),
+            (Occur::Should, Box::new(phrase)),
+        ]);
+        assert_eq!(
+            searcher.estimate_cost(&boolean)?.total,
+            CostEstimate::Unknown
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cost_estimate_capped_expansion() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..(super::COST_ESTIMATE_MAX_EXPANDED_TERMS + 10) {
+            index_writer.add_document(doc!(text => format!("t{i}")))?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let estimate = searcher.estimate_cost(&RegexQuery::from_pattern("t.*", text)?)?;
+        assert!(estimate.is_unbounded());
+        let bounds = estimate.total.bounds().unwrap();
+        assert!(bounds.unbounded);
+        assert_eq!(
+            bounds.expanded_terms,
+            super::COST_ESTIMATE_MAX_EXPANDED_TERMS
+        );
+        assert_eq!(
+            bounds.matching_docs,
+            u64::from(searcher.segment_reader(0).max_doc())
+        );
+        let estimate = searcher.estimate_cost(&RegexQuery::from_pattern("t1.*", text)?)?;
+        assert!(!estimate.is_unbounded());
+        Ok(())
+    }
+}
@@ -109,6 +109,12 @@ impl Query for DisjunctionMaxQuery {
             disjunct.query_terms(visitor);
         }
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        for disjunct in &mut self.disjuncts {
+            disjunct.set_max_expanded_terms(max_expanded_terms);
+        }
+    }
 }
 
 impl DisjunctionMaxQuery {
@@ -2,7 +2,7 @@ use super::Scorer;
 use crate::docset::TERMINATED;
 use crate::index::SegmentReader;
 use crate::query::explanation::does_not_match;
-use crate::query::{EnableScoring, Explanation, Query, Weight};
+use crate::query::{CostBounds, CostEstimate, EnableScoring, Explanation, Query, Weight};
 use crate::{DocId, DocSet, Score, Searcher};
 
 /// `EmptyQuery` is a dummy `Query` in which no document matches.
@@ -33,6 +33,10 @@ impl Weight for EmptyWeight {
     fn explain(&self, _reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
         Err(does_not_match(doc))
     }
+
+    fn estimate_cost(&self, _reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        Ok(CostEstimate::Bounded(CostBounds::default()))
+    }
 }
 
 /// `EmptyScorer` is a dummy `Scorer` in which no document matches.
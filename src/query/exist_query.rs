@@ -64,6 +64,16 @@ impl ExistsQuery {
             json_subpaths,
         }
     }
+
+    /// The name of the field whose values are checked.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    /// Whether the values of the JSON subpaths of the field are checked too.
+    pub fn json_subpaths(&self) -> bool {
+        self.json_subpaths
+    }
 }
 
 impl Query for ExistsQuery {
@@ -96,7 +106,7 @@ pub struct ExistsWeight {
 
 impl Weight for ExistsWeight {
     fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
-        let fast_field_reader = reader.fast_fields();
+        let fast_field_reader = reader.try_fast_fields()?;
         let mut column_handles = fast_field_reader.dynamic_column_handles(&self.field_name)?;
         if self.field_type == Type::Json && self.json_subpaths {
             let mut sub_columns =
@@ -131,39 +141,45 @@ impl Weight for ExistsWeight {
     }
 }
 
+/// Doc set of the docs having a value in any of the columns.
+///
+/// The docs are found by going through the column indexes, skipping over the docs without values.
 pub(crate) struct ExistsDocSet {
     columns: Vec<DynamicColumn>,
+    /// For each column, the next doc having a value, greater than or equal to `doc`.
+    next_docs: Vec<DocId>,
     doc: DocId,
     max_doc: DocId,
 }
 
 impl ExistsDocSet {
     pub(crate) fn new(columns: Vec<DynamicColumn>, max_doc: DocId) -> Self {
+        let next_docs = columns
+            .iter()
+            .map(|column| next_doc_with_value(column, 0, max_doc))
+            .collect();
         let mut set = Self {
             columns,
+            next_docs,
             doc: 0u32,
             max_doc,
         };
-        set.find_next();
+        set.doc = set.min_next_doc();
         set
     }
 
-    fn find_next(&mut self) -> DocId {
-        while self.doc < self.max_doc {
-            if self
-                .columns
-                .iter()
-                .any(|col| col.column_index().has_value(self.doc))
-            {
-                return self.doc;
-            }
-            self.doc += 1;
-        }
-        self.doc = TERMINATED;
-        TERMINATED
+    fn min_next_doc(&self) -> DocId {
+        self.next_docs.iter().copied().min().unwrap_or(TERMINATED)
     }
 }
 
+fn next_doc_with_value(column: &DynamicColumn, target: DocId, max_doc: DocId) -> DocId {
+    column
+        .column_index()
+        .next_doc_with_value(target, max_doc)
+        .unwrap_or(TERMINATED)
+}
+
 impl DocSet for ExistsDocSet {
     fn advance(&mut self) -> DocId {
         self.seek(self.doc + 1)
@@ -179,8 +195,16 @@ impl DocSet for ExistsDocSet {
 
     #[inline(always)]
     fn seek(&mut self, target: DocId) -> DocId {
-        self.doc = target;
-        self.find_next()
+        if self.doc >= target {
+            return self.doc;
+        }
+        for (column, next_doc) in self.columns.iter().zip(self.next_docs.iter_mut()) {
+            if *next_doc < target {
+                *next_doc = next_doc_with_value(column, target, self.max_doc);
+            }
+        }
+        self.doc = self.min_next_doc();
+        self.doc
     }
 }
 
@@ -193,8 +217,9 @@ mod tests {
     use time::OffsetDateTime;
 
     use crate::collector::Count;
+    use crate::docset::{DocSet, TERMINATED};
     use crate::query::exist_query::ExistsQuery;
-    use crate::query::{BooleanQuery, RangeQuery};
+    use crate::query::{BooleanQuery, EnableScoring, Query, RangeQuery};
     use crate::schema::{Facet, FacetOptions, Schema, FAST, INDEXED, STRING, TEXT};
     use crate::{Index, Searcher, Term};
 
@@ -256,6 +281,58 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_exists_query_sparse_columns_and_deletes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED | FAST);
+        let sparse_field = schema_builder.add_u64_field("sparse", FAST);
+        let multi_field = schema_builder.add_text_field("multi", STRING | FAST);
+        let schema = schema_builder.build();
+
+        let index = Index::create_in_ram(schema);
+        {
+            let mut index_writer = index.writer_for_tests()?;
+            for i in 0u64..1_000u64 {
+                let mut doc = doc!(id_field => i);
+                if i % 100 == 0 {
+                    doc.add_u64(sparse_field, i);
+                }
+                if i % 250 == 0 {
+                    doc.add_text(multi_field, "a");
+                    doc.add_text(multi_field, "b");
+                }
+                index_writer.add_document(doc)?;
+            }
+            index_writer.commit()?;
+            index_writer.delete_term(Term::from_field_u64(id_field, 200))?;
+            index_writer.delete_term(Term::from_field_u64(id_field, 500))?;
+            index_writer.commit()?;
+        }
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        assert_eq!(count_existing_fields(&searcher, "sparse", false)?, 8);
+        assert_eq!(count_existing_fields(&searcher, "multi", false)?, 3);
+
+        let segment_reader = searcher.segment_reader(0);
+        let weight = ExistsQuery::new("sparse".to_string(), false)
+            .weight(EnableScoring::disabled_from_searcher(&searcher))?;
+        let mut scorer = weight.scorer(segment_reader, 1.0)?;
+        assert_eq!(scorer.doc(), 0);
+        assert_eq!(scorer.score(), 1.0);
+        assert_eq!(scorer.advance(), 100);
+        assert_eq!(scorer.seek(101), 200);
+        assert_eq!(scorer.seek(950), TERMINATED);
+
+        let query = BooleanQuery::intersection(vec![
+            Box::new(ExistsQuery::new("sparse".to_string(), false)),
+            Box::new(ExistsQuery::new("multi".to_string(), false)),
+        ]);
+        // 0, 500 (deleted) and 1000 (out of range) are multiples of both 100 and 250.
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_exists_query_json() -> crate::Result<()> {
         let mut schema_builder = Schema::builder();
@@ -31,6 +31,34 @@ impl Automaton for DfaWrapper {
     }
 }
 
+/// Returns the Levenshtein automaton builder for `distance`, built on first use.
+///
+/// Returns an `InvalidArgument` error if the distance is greater than 2.
+pub(crate) fn automaton_builder(
+    distance: u8,
+    transposition_cost_one: bool,
+) -> crate::Result<&'static LevenshteinAutomatonBuilder> {
+    static AUTOMATON_BUILDER: [[OnceCell<LevenshteinAutomatonBuilder>; 2]; 3] = [
+        [OnceCell::new(), OnceCell::new()],
+        [OnceCell::new(), OnceCell::new()],
+        [OnceCell::new(), OnceCell::new()],
+    ];
+
+    let automaton_builder = AUTOMATON_BUILDER
+        .get(distance as usize)
+        .ok_or_else(|| {
+            InvalidArgument(format!(
+                "Levenshtein distance of {} is not allowed. Choose a value less than {}",
+                distance,
+                AUTOMATON_BUILDER.len()
+            ))
+        })?
+        .get(transposition_cost_one as usize)
+        .unwrap()
+        .get_or_init(|| LevenshteinAutomatonBuilder::new(distance, transposition_cost_one));
+    Ok(automaton_builder)
+}
+
 /// A Fuzzy Query matches all of the documents
 /// containing a specific term that is within
 /// Levenshtein distance
@@ -86,6 +114,8 @@ pub struct FuzzyTermQuery {
     transposition_cost_one: bool,
     /// is a starts with query
     prefix: bool,
+    /// Maximum number of terms the query may match in a segment
+    max_expanded_terms: Option<usize>,
 }
 
 impl FuzzyTermQuery {
@@ -96,6 +126,7 @@ impl FuzzyTermQuery {
             distance,
             transposition_cost_one,
             prefix: false,
+            max_expanded_terms: None,
         }
     }
 
@@ -106,30 +137,32 @@ impl FuzzyTermQuery {
             distance,
             transposition_cost_one,
             prefix: true,
+            max_expanded_terms: None,
         }
     }
 
+    /// The term the matched terms are close to.
+    pub fn term(&self) -> &Term {
+        &self.term
+    }
+
+    /// The maximum edit distance to the term.
+    pub fn distance(&self) -> u8 {
+        self.distance
+    }
+
+    /// Whether a transposition costs 1 instead of 2.
+    pub fn transposition_cost_one(&self) -> bool {
+        self.transposition_cost_one
+    }
+
+    /// Whether the query matches the terms starting with a prefix close to the term.
+    pub fn is_prefix(&self) -> bool {
+        self.prefix
+    }
+
     fn specialized_weight(&self) -> crate::Result<AutomatonWeight<DfaWrapper>> {
-        static AUTOMATON_BUILDER: [[OnceCell<LevenshteinAutomatonBuilder>; 2]; 3] = [
-            [OnceCell::new(), OnceCell::new()],
-            [OnceCell::new(), OnceCell::new()],
-            [OnceCell::new(), OnceCell::new()],
-        ];
-
-        let automaton_builder = AUTOMATON_BUILDER
-            .get(self.distance as usize)
-            .ok_or_else(|| {
-                InvalidArgument(format!(
-                    "Levenshtein distance of {} is not allowed. Choose a value less than {}",
-                    self.distance,
-                    AUTOMATON_BUILDER.len()
-                ))
-            })?
-            .get(self.transposition_cost_one as usize)
-            .unwrap()
-            .get_or_init(|| {
-                LevenshteinAutomatonBuilder::new(self.distance, self.transposition_cost_one)
-            });
+        let automaton_builder = automaton_builder(self.distance, self.transposition_cost_one)?;
 
         let term_value = self.term.value();
 
@@ -159,18 +192,16 @@ impl FuzzyTermQuery {
             automaton_builder.build_dfa(term_text)
         };
 
-        if let Some((json_path_bytes, _)) = term_value.as_json() {
-            Ok(AutomatonWeight::new_for_json_path(
+        let weight = if let Some((json_path_bytes, _)) = term_value.as_json() {
+            AutomatonWeight::new_for_json_path(
                 self.term.field(),
                 DfaWrapper(automaton),
                 json_path_bytes,
-            ))
+            )
         } else {
-            Ok(AutomatonWeight::new(
-                self.term.field(),
-                DfaWrapper(automaton),
-            ))
-        }
+            AutomatonWeight::new(self.term.field(), DfaWrapper(automaton))
+        };
+        Ok(weight.with_max_expanded_terms(self.max_expanded_terms))
     }
 }
 
@@ -178,6 +209,10 @@ impl Query for FuzzyTermQuery {
     fn weight(&self, _enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
         Ok(Box::new(self.specialized_weight()?))
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.max_expanded_terms = Some(max_expanded_terms);
+    }
 }
 
 #[cfg(test)]
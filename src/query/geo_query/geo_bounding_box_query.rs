@@ -0,0 +1,118 @@
+use super::geo_cover::GeoBox;
+use super::{check_geo_point, check_geo_point_field, GeoShape, GeoWeight};
+use crate::query::{EnableScoring, Query, Weight};
+use crate::schema::{Field, GeoPoint};
+use crate::TantivyError;
+
+/// Query matching the documents with a point of a
+/// [geo point field](crate::schema::SchemaBuilder::add_geo_point_field) in a bounding box.
+///
+/// The box spans from the latitude of `bottom_right` to the one of `top_left`, and eastward from
+/// the longitude of `top_left` to the one of `bottom_right`. If the longitude of `top_left` is
+/// greater than the one of `bottom_right`, the box crosses the antimeridian.
+///
+/// The field has to be a fast field. If it is also indexed, the candidate documents are read
+/// from the postings of the cells covering the box instead of scanning the fast field.
+///
+/// All of the matched documents get the score 1.0.
+#[derive(Clone, Debug)]
+pub struct GeoBoundingBoxQuery {
+    field: Field,
+    top_left: GeoPoint,
+    bottom_right: GeoPoint,
+}
+
+impl GeoBoundingBoxQuery {
+    /// Creates a query matching the points of `field` in the box spanning from `top_left` to
+    /// `bottom_right`.
+    ///
+    /// This constructor never fails, but executing the search returns an error if the field is
+    /// not a fast geo point field, if a corner is not a valid point, or if the latitude of
+    /// `top_left` is lower than the one of `bottom_right`.
+    pub fn new(field: Field, top_left: GeoPoint, bottom_right: GeoPoint) -> GeoBoundingBoxQuery {
+        GeoBoundingBoxQuery {
+            field,
+            top_left,
+            bottom_right,
+        }
+    }
+
+    /// The field whose points are matched.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// The north-west corner of the box.
+    pub fn top_left(&self) -> GeoPoint {
+        self.top_left
+    }
+
+    /// The south-east corner of the box.
+    pub fn bottom_right(&self) -> GeoPoint {
+        self.bottom_right
+    }
+}
+
+impl Query for GeoBoundingBoxQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        let use_terms = check_geo_point_field(&enable_scoring, self.field)?;
+        check_geo_point(self.top_left)?;
+        check_geo_point(self.bottom_right)?;
+        if self.top_left.lat < self.bottom_right.lat {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The top left corner {:?} of the bounding box is south of its bottom right corner \
+                 {:?}",
+                self.top_left, self.bottom_right
+            )));
+        }
+        let geo_box = GeoBox::new(self.top_left, self.bottom_right);
+        Ok(Box::new(GeoWeight::new(
+            self.field,
+            GeoShape::BoundingBox(geo_box.clone()),
+            &geo_box,
+            use_terms,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeoBoundingBoxQuery;
+    use crate::query::{EnableScoring, Query};
+    use crate::schema::{GeoPoint, Schema, FAST, INDEXED, STRING};
+    use crate::{Index, TantivyError};
+
+    #[test]
+    fn test_bounding_box_query_errors() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let location = schema_builder.add_geo_point_field("location", FAST);
+        let not_fast = schema_builder.add_geo_point_field("not_fast", INDEXED);
+        let text = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let searcher = index.reader()?.searcher();
+        let weight = |field, top_left, bottom_right| {
+            GeoBoundingBoxQuery::new(field, top_left, bottom_right)
+                .weight(EnableScoring::disabled_from_searcher(&searcher))
+        };
+        let north = GeoPoint::new(10.0, 0.0);
+        let south = GeoPoint::new(-10.0, 1.0);
+        assert!(weight(location, north, south).is_ok());
+        assert!(matches!(
+            weight(location, south, north),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            weight(location, GeoPoint::new(91.0, 0.0), south),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            weight(not_fast, north, south),
+            Err(TantivyError::SchemaError(_))
+        ));
+        assert!(matches!(
+            weight(text, north, south),
+            Err(TantivyError::SchemaError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+
+use crate::schema::{
+    deinterleave, geo_cell_term_value, interleave, quantize_lat, quantize_lon, GeoPoint,
+    GEO_TERM_LEVELS,
+};
+
+/// Maximum number of cells covering a bounding box.
+///
+/// More cells fit the box more tightly, so that fewer points have to be checked, at the cost of
+/// more ranges to look the codes up in, and more terms to read when the field is indexed.
+const MAX_COVER_CELLS: usize = 64;
+
+/// Rectangle of quantized coordinates, bounds included.
+#[derive(Clone, Copy, Debug)]
+struct QuantizedBox {
+    lat_min: u32,
+    lat_max: u32,
+    lon_min: u32,
+    lon_max: u32,
+}
+
+impl QuantizedBox {
+    fn contains(&self, lat: u32, lon: u32) -> bool {
+        (self.lat_min..=self.lat_max).contains(&lat) && (self.lon_min..=self.lon_max).contains(&lon)
+    }
+}
+
+/// Bounding box of a geo query, in quantized coordinates.
+///
+/// A box crossing the antimeridian is split in two.
+#[derive(Clone, Debug)]
+pub(crate) struct GeoBox {
+    boxes: Vec<QuantizedBox>,
+}
+
+impl GeoBox {
+    /// Creates the box spanning from `top_left` to `bottom_right`.
+    ///
+    /// The box crosses the antimeridian if the longitude of `top_left` is greater than the one
+    /// of `bottom_right`.
+    pub fn new(top_left: GeoPoint, bottom_right: GeoPoint) -> GeoBox {
+        let lat_min = quantize_lat(bottom_right.lat);
+        let lat_max = quantize_lat(top_left.lat);
+        let lon_min = quantize_lon(top_left.lon);
+        let lon_max = quantize_lon(bottom_right.lon);
+        let boxes = if lon_min <= lon_max {
+            vec![QuantizedBox {
+                lat_min,
+                lat_max,
+                lon_min,
+                lon_max,
+            }]
+        } else {
+            vec![
+                QuantizedBox {
+                    lat_min,
+                    lat_max,
+                    lon_min,
+                    lon_max: u32::MAX,
+                },
+                QuantizedBox {
+                    lat_min,
+                    lat_max,
+                    lon_min: 0,
+                    lon_max,
+                },
+            ]
+        };
+        GeoBox { boxes }
+    }
+
+    /// Returns true if the point of the code is in the box.
+    pub fn contains_code(&self, code: u64) -> bool {
+        let (lat, lon) = deinterleave(code);
+        self.boxes
+            .iter()
+            .any(|quantized_box| quantized_box.contains(lat, lon))
+    }
+
+    fn classify(&self, cell: &GeoCell) -> CellRelation {
+        let mut relation = CellRelation::Disjoint;
+        for quantized_box in &self.boxes {
+            let (lat_min, lat_max) = cell.lat_range();
+            let (lon_min, lon_max) = cell.lon_range();
+            let overlaps = lat_min <= quantized_box.lat_max
+                && lat_max >= quantized_box.lat_min
+                && lon_min <= quantized_box.lon_max
+                && lon_max >= quantized_box.lon_min;
+            if !overlaps {
+                continue;
+            }
+            let is_inside = lat_min >= quantized_box.lat_min
+                && lat_max <= quantized_box.lat_max
+                && lon_min >= quantized_box.lon_min
+                && lon_max <= quantized_box.lon_max;
+            if is_inside {
+                return CellRelation::Inside;
+            }
+            relation = CellRelation::Partial;
+        }
+        relation
+    }
+
+    /// Covers the box with cells of the Z-order curve, refining the cells crossing the border of
+    /// the box as long as there are at most [`MAX_COVER_CELLS`] cells.
+    pub fn cover(&self) -> GeoCover {
+        let mut cells: Vec<(GeoCell, bool)> = Vec::new();
+        let mut frontier = vec![GeoCell::root()];
+        loop {
+            let mut partial_cells = Vec::new();
+            for cell in frontier {
+                match self.classify(&cell) {
+                    CellRelation::Inside => cells.push((cell, true)),
+                    CellRelation::Partial => partial_cells.push(cell),
+                    CellRelation::Disjoint => {}
+                }
+            }
+            let is_last_level = partial_cells
+                .first()
+                .is_none_or(|cell| cell.level == GeoCell::MAX_LEVEL);
+            if is_last_level || cells.len() + partial_cells.len() * 4 > MAX_COVER_CELLS {
+                cells.extend(partial_cells.into_iter().map(|cell| (cell, false)));
+                break;
+            }
+            frontier = partial_cells.iter().flat_map(GeoCell::children).collect();
+        }
+        GeoCover::new(cells)
+    }
+}
+
+enum CellRelation {
+    Inside,
+    Partial,
+    Disjoint,
+}
+
+/// Cell of the Z-order curve: the points whose `level` highest bits of each quantized coordinate
+/// are the ones of `lat` and `lon`.
+#[derive(Clone, Copy, Debug)]
+struct GeoCell {
+    level: u32,
+    lat: u32,
+    lon: u32,
+}
+
+impl GeoCell {
+    const MAX_LEVEL: u32 = 32;
+
+    fn root() -> GeoCell {
+        GeoCell {
+            level: 0,
+            lat: 0,
+            lon: 0,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        1u64 << (32 - self.level)
+    }
+
+    fn lat_range(&self) -> (u32, u32) {
+        (self.lat, (self.lat as u64 + self.size() - 1) as u32)
+    }
+
+    fn lon_range(&self) -> (u32, u32) {
+        (self.lon, (self.lon as u64 + self.size() - 1) as u32)
+    }
+
+    fn children(&self) -> [GeoCell; 4] {
+        let half = (self.size() / 2) as u32;
+        let child = |lat, lon| GeoCell {
+            level: self.level + 1,
+            lat,
+            lon,
+        };
+        [
+            child(self.lat, self.lon),
+            child(self.lat, self.lon + half),
+            child(self.lat + half, self.lon),
+            child(self.lat + half, self.lon + half),
+        ]
+    }
+
+    /// Returns the range of the codes of the points of the cell.
+    fn code_range(&self) -> (u64, u64) {
+        let start = interleave(self.lat, self.lon);
+        let num_low_bits = 64 - 2 * self.level;
+        let low_bits_mask = if num_low_bits == 64 {
+            u64::MAX
+        } else {
+            (1u64 << num_low_bits) - 1
+        };
+        (start, start | low_bits_mask)
+    }
+}
+
+/// Range of codes of the cells covering a bounding box.
+#[derive(Clone, Copy, Debug)]
+struct CodeRange {
+    start: u64,
+    end: u64,
+    /// True if all of the points of the range are in the box.
+    inside: bool,
+}
+
+/// Cells covering a bounding box, as sorted ranges of codes.
+#[derive(Clone, Debug)]
+pub(crate) struct GeoCover {
+    cells: Vec<(GeoCell, bool)>,
+    ranges: Vec<CodeRange>,
+}
+
+impl GeoCover {
+    fn new(cells: Vec<(GeoCell, bool)>) -> GeoCover {
+        let mut cell_ranges: Vec<CodeRange> = cells
+            .iter()
+            .map(|(cell, inside)| {
+                let (start, end) = cell.code_range();
+                CodeRange {
+                    start,
+                    end,
+                    inside: *inside,
+                }
+            })
+            .collect();
+        cell_ranges.sort_by_key(|range| range.start);
+        let mut ranges: Vec<CodeRange> = Vec::with_capacity(cell_ranges.len());
+        for range in cell_ranges {
+            match ranges.last_mut() {
+                Some(last) if last.inside == range.inside && last.end + 1 == range.start => {
+                    last.end = range.end;
+                }
+                _ => ranges.push(range),
+            }
+        }
+        GeoCover { cells, ranges }
+    }
+
+    /// Returns the smallest and largest codes of the cover, or `None` if it is empty.
+    pub fn code_bounds(&self) -> Option<(u64, u64)> {
+        Some((self.ranges.first()?.start, self.ranges.last()?.end))
+    }
+
+    /// Returns `None` if the code is not covered, and otherwise whether the point of the code is
+    /// known to be in the box without checking it.
+    pub fn find(&self, code: u64) -> Option<bool> {
+        let range_ord = self.ranges.partition_point(|range| range.end < code);
+        let range = self.ranges.get(range_ord)?;
+        (range.start <= code).then_some(range.inside)
+    }
+
+    /// Returns the values of the terms of the cells covering the box, with whether all of their
+    /// points are in the box.
+    ///
+    /// The cells are mapped to the largest of their ancestors that is indexed, or to their
+    /// descendants of the first indexed level if they are larger.
+    pub fn term_cells(&self) -> BTreeMap<u64, bool> {
+        let mut term_cells: BTreeMap<u64, bool> = BTreeMap::new();
+        let mut add_term_cell = |term_value: u64, inside: bool| {
+            term_cells
+                .entry(term_value)
+                .and_modify(|term_inside| *term_inside &= inside)
+                .or_insert(inside);
+        };
+        let first_level = GEO_TERM_LEVELS[0];
+        for &(cell, inside) in &self.cells {
+            if cell.level < first_level {
+                let mut descendants = vec![cell];
+                while descendants[0].level < first_level {
+                    descendants = descendants.iter().flat_map(GeoCell::children).collect();
+                }
+                for descendant in descendants {
+                    let (start, _) = descendant.code_range();
+                    add_term_cell(geo_cell_term_value(start, first_level), inside);
+                }
+                continue;
+            }
+            let term_level = GEO_TERM_LEVELS
+                .iter()
+                .copied()
+                .filter(|&level| level <= cell.level)
+                .max()
+                .unwrap_or(first_level);
+            let (start, _) = cell.code_range();
+            add_term_cell(
+                geo_cell_term_value(start, term_level),
+                inside && term_level == cell.level,
+            );
+        }
+        term_cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeoBox;
+    use crate::schema::GeoPoint;
+
+    #[test]
+    fn test_cover_contains_the_box() {
+        let geo_box = GeoBox::new(GeoPoint::new(10.0, 170.0), GeoPoint::new(-5.0, -175.0));
+        let cover = geo_box.cover();
+        for (lat, lon, in_box) in [
+            (0.0, 175.0, true),
+            (0.0, -179.0, true),
+            (10.0, 170.0, true),
+            (-5.0, -175.0, true),
+            (0.0, 0.0, false),
+            (11.0, 175.0, false),
+        ] {
+            let code = GeoPoint::new(lat, lon).to_u64();
+            assert_eq!(geo_box.contains_code(code), in_box);
+            match cover.find(code) {
+                Some(true) => assert!(in_box),
+                Some(false) => {}
+                None => assert!(!in_box),
+            }
+        }
+        assert!(cover.cells.len() <= super::MAX_COVER_CELLS);
+        assert!(!cover.term_cells().is_empty());
+    }
+}
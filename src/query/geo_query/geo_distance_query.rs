@@ -0,0 +1,177 @@
+use super::geo_cover::GeoBox;
+use super::{check_geo_point, check_geo_point_field, GeoShape, GeoWeight};
+use crate::query::{EnableScoring, Query, Weight};
+use crate::schema::{Field, GeoPoint};
+use crate::TantivyError;
+
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Returns the great-circle distance between two points, in meters.
+pub(crate) fn haversine_distance(left: GeoPoint, right: GeoPoint) -> f64 {
+    #[cfg(test)]
+    super::tests::NUM_DISTANCE_COMPUTATIONS.with(|count| count.set(count.get() + 1));
+    let left_lat = left.lat.to_radians();
+    let right_lat = right.lat.to_radians();
+    let half_delta_lat = (right_lat - left_lat) / 2.0;
+    let half_delta_lon = (right.lon - left.lon).to_radians() / 2.0;
+    let hav = half_delta_lat.sin().powi(2)
+        + left_lat.cos() * right_lat.cos() * half_delta_lon.sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * hav.sqrt().min(1.0).asin()
+}
+
+/// Points within `distance` meters of `center`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct GeoCircle {
+    pub center: GeoPoint,
+    pub distance: f64,
+}
+
+impl GeoCircle {
+    /// Returns the smallest bounding box of the circle, as its top left and bottom right corners.
+    ///
+    /// The box spans all of the longitudes if the circle contains a pole.
+    fn bounding_box(&self) -> (GeoPoint, GeoPoint) {
+        let angular_distance = self.distance / EARTH_RADIUS_METERS;
+        if angular_distance >= std::f64::consts::PI {
+            return (GeoPoint::new(90.0, -180.0), GeoPoint::new(-90.0, 180.0));
+        }
+        let lat = self.center.lat.to_radians();
+        let lat_min = lat - angular_distance;
+        let lat_max = lat + angular_distance;
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        if lat_min <= -half_pi || lat_max >= half_pi {
+            return (
+                GeoPoint::new(lat_max.min(half_pi).to_degrees(), -180.0),
+                GeoPoint::new(lat_min.max(-half_pi).to_degrees(), 180.0),
+            );
+        }
+        let delta_lon = (angular_distance.sin() / lat.cos())
+            .min(1.0)
+            .asin()
+            .to_degrees();
+        let mut lon_min = self.center.lon - delta_lon;
+        let mut lon_max = self.center.lon + delta_lon;
+        if lon_min < -180.0 {
+            lon_min += 360.0;
+        } else if lon_max > 180.0 {
+            lon_max -= 360.0;
+        }
+        (
+            GeoPoint::new(lat_max.to_degrees(), lon_min),
+            GeoPoint::new(lat_min.to_degrees(), lon_max),
+        )
+    }
+}
+
+/// Query matching the documents with a point of a
+/// [geo point field](crate::schema::SchemaBuilder::add_geo_point_field) within a distance of a
+/// center.
+///
+/// The distance is the great-circle distance on a sphere of the mean radius of the Earth. The
+/// candidate documents are the ones in the cells covering the bounding box of the circle, as for
+/// a [`GeoBoundingBoxQuery`](crate::query::GeoBoundingBoxQuery), and their distance to the
+/// center is then computed.
+///
+/// All of the matched documents get the score 1.0.
+#[derive(Clone, Debug)]
+pub struct GeoDistanceQuery {
+    field: Field,
+    center: GeoPoint,
+    distance: f64,
+}
+
+impl GeoDistanceQuery {
+    /// Creates a query matching the points of `field` within `distance_meters` of `center`.
+    ///
+    /// This constructor never fails, but executing the search returns an error if the field is
+    /// not a fast geo point field, if the center is not a valid point, or if the distance is
+    /// negative or not finite.
+    pub fn new(field: Field, center: GeoPoint, distance_meters: f64) -> GeoDistanceQuery {
+        GeoDistanceQuery {
+            field,
+            center,
+            distance: distance_meters,
+        }
+    }
+
+    /// The field whose points are matched.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// The center of the circle.
+    pub fn center(&self) -> GeoPoint {
+        self.center
+    }
+
+    /// The radius of the circle, in meters.
+    pub fn distance_meters(&self) -> f64 {
+        self.distance
+    }
+}
+
+impl Query for GeoDistanceQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        let use_terms = check_geo_point_field(&enable_scoring, self.field)?;
+        check_geo_point(self.center)?;
+        if !(self.distance.is_finite() && self.distance >= 0.0) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Invalid distance {}: it must be a non-negative number of meters",
+                self.distance
+            )));
+        }
+        let circle = GeoCircle {
+            center: self.center,
+            distance: self.distance,
+        };
+        let (top_left, bottom_right) = circle.bounding_box();
+        Ok(Box::new(GeoWeight::new(
+            self.field,
+            GeoShape::Circle(circle),
+            &GeoBox::new(top_left, bottom_right),
+            use_terms,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{haversine_distance, GeoDistanceQuery};
+    use crate::query::{EnableScoring, Query};
+    use crate::schema::{GeoPoint, Schema, FAST};
+    use crate::{Index, TantivyError};
+
+    #[test]
+    fn test_haversine_distance() {
+        let paris = GeoPoint::new(48.8566, 2.3522);
+        let london = GeoPoint::new(51.5074, -0.1278);
+        let distance = haversine_distance(paris, london);
+        assert!((distance - 343_500.0).abs() < 1_000.0, "{distance}");
+        assert_eq!(haversine_distance(paris, paris), 0.0);
+    }
+
+    #[test]
+    fn test_distance_query_errors() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let location = schema_builder.add_geo_point_field("location", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let searcher = index.reader()?.searcher();
+        let weight = |center, distance| {
+            GeoDistanceQuery::new(location, center, distance)
+                .weight(EnableScoring::disabled_from_searcher(&searcher))
+        };
+        assert!(weight(GeoPoint::new(0.0, 0.0), 1_000.0).is_ok());
+        for (center, distance) in [
+            (GeoPoint::new(0.0, 181.0), 1_000.0),
+            (GeoPoint::new(0.0, 0.0), -1.0),
+            (GeoPoint::new(0.0, 0.0), f64::NAN),
+        ] {
+            assert!(matches!(
+                weight(center, distance),
+                Err(TantivyError::InvalidArgument(_))
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+mod geo_bounding_box_query;
+mod geo_cover;
+mod geo_distance_query;
+
+use columnar::Column;
+use common::BitSet;
+
+pub use self::geo_bounding_box_query::GeoBoundingBoxQuery;
+use self::geo_cover::{GeoBox, GeoCover};
+pub use self::geo_distance_query::GeoDistanceQuery;
+use self::geo_distance_query::{haversine_distance, GeoCircle};
+use super::explanation::does_not_match;
+use super::{BitSetDocSet, ConstScorer, EnableScoring, Explanation, Scorer, Weight};
+use crate::docset::{DocSet, TERMINATED};
+use crate::schema::{Field, FieldType, GeoPoint, IndexRecordOption, Term};
+use crate::{DocId, Score, SegmentReader, TantivyError};
+
+/// Checks that `field` is a fast geo point field, and returns whether its cells are indexed.
+fn check_geo_point_field(enable_scoring: &EnableScoring, field: Field) -> crate::Result<bool> {
+    let field_entry = enable_scoring.schema().get_field_entry(field);
+    let FieldType::GeoPoint(geo_point_options) = field_entry.field_type() else {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {:?} is not a geo point field.",
+            field_entry.name()
+        )));
+    };
+    if !geo_point_options.is_fast() {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {:?} is not a fast field.",
+            field_entry.name()
+        )));
+    }
+    Ok(geo_point_options.is_indexed())
+}
+
+fn check_geo_point(geo_point: GeoPoint) -> crate::Result<()> {
+    if !geo_point.is_valid() {
+        return Err(TantivyError::InvalidArgument(format!(
+            "Invalid geo point {geo_point:?}: the latitude must be between -90 and 90, and the \
+             longitude between -180 and 180"
+        )));
+    }
+    Ok(())
+}
+
+/// Shape matched by a geo query.
+#[derive(Clone, Debug)]
+enum GeoShape {
+    BoundingBox(GeoBox),
+    Circle(GeoCircle),
+}
+
+impl GeoShape {
+    /// Returns true if the point of `code`, which belongs to the cover of the shape, is in the
+    /// shape. `inside` tells if the cell of the point is inside the bounding box of the shape.
+    fn contains(&self, code: u64, inside: bool) -> bool {
+        match self {
+            GeoShape::BoundingBox(geo_box) => inside || geo_box.contains_code(code),
+            GeoShape::Circle(circle) => {
+                haversine_distance(circle.center, GeoPoint::from_u64(code)) <= circle.distance
+            }
+        }
+    }
+
+    /// Returns true if the shape is its bounding box, in which case the points of the cells
+    /// inside the bounding box do not need to be checked.
+    fn is_box(&self) -> bool {
+        matches!(self, GeoShape::BoundingBox(_))
+    }
+}
+
+/// Weight of the geo queries.
+///
+/// The candidate documents are the ones whose point is in the cells covering the bounding box
+/// of the shape. They are read from the terms of the cells if the field is indexed, or from the
+/// fast field otherwise. The candidates are then checked against the shape, except for the
+/// cells inside a bounding box.
+struct GeoWeight {
+    field: Field,
+    shape: GeoShape,
+    cover: GeoCover,
+    use_terms: bool,
+}
+
+impl GeoWeight {
+    fn new(field: Field, shape: GeoShape, bounding_box: &GeoBox, use_terms: bool) -> GeoWeight {
+        GeoWeight {
+            field,
+            shape,
+            cover: bounding_box.cover(),
+            use_terms,
+        }
+    }
+
+    fn doc_matches(&self, column: &Column<u64>, doc: DocId) -> bool {
+        column.values_for_doc(doc).any(|code| {
+            self.cover
+                .find(code)
+                .is_some_and(|inside| self.shape.contains(code, inside))
+        })
+    }
+
+    fn matching_docs_from_column(&self, column: &Column<u64>, max_doc: DocId) -> BitSet {
+        let mut matching_docs = BitSet::with_max_value(max_doc);
+        let Some((min_code, max_code)) = self.cover.code_bounds() else {
+            return matching_docs;
+        };
+        let mut candidates = Vec::new();
+        column.get_docids_for_value_range(min_code..=max_code, 0..max_doc, &mut candidates);
+        for doc in candidates {
+            if !matching_docs.contains(doc) && self.doc_matches(column, doc) {
+                matching_docs.insert(doc);
+            }
+        }
+        matching_docs
+    }
+
+    fn matching_docs_from_terms(
+        &self,
+        reader: &SegmentReader,
+        column: &Column<u64>,
+    ) -> crate::Result<BitSet> {
+        let mut matching_docs = BitSet::with_max_value(reader.max_doc());
+        let inverted_index = reader.inverted_index(self.field)?;
+        for (term_value, inside) in self.cover.term_cells() {
+            let term = Term::from_field_u64(self.field, term_value);
+            let Some(mut postings) =
+                inverted_index.read_postings(&term, IndexRecordOption::Basic)?
+            else {
+                continue;
+            };
+            let accept_all = inside && self.shape.is_box();
+            let mut doc = postings.doc();
+            while doc != TERMINATED {
+                if accept_all || (!matching_docs.contains(doc) && self.doc_matches(column, doc)) {
+                    matching_docs.insert(doc);
+                }
+                doc = postings.advance();
+            }
+        }
+        Ok(matching_docs)
+    }
+}
+
+impl Weight for GeoWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        let field_name = reader.schema().get_field_name(self.field);
+        let column = reader.try_fast_fields()?.u64(field_name)?;
+        let matching_docs = if self.use_terms {
+            self.matching_docs_from_terms(reader, &column)?
+        } else {
+            self.matching_docs_from_column(&column, reader.max_doc())
+        };
+        Ok(Box::new(ConstScorer::new(
+            BitSetDocSet::from(matching_docs),
+            boost,
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(does_not_match(doc));
+        }
+        let name = if self.shape.is_box() {
+            "GeoBoundingBoxQuery"
+        } else {
+            "GeoDistanceQuery"
+        };
+        Ok(Explanation::new(name, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::haversine_distance;
+    use crate::collector::DocSetCollector;
+    use crate::query::{GeoBoundingBoxQuery, GeoDistanceQuery, Query};
+    use crate::schema::{GeoPoint, GeoPointOptions, OwnedValue, Schema, FAST, INDEXED};
+    use crate::{Index, IndexWriter, Searcher};
+
+    thread_local! {
+        pub(super) static NUM_DISTANCE_COMPUTATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    const NUM_POINTS: usize = 3_000;
+
+    fn random_points() -> Vec<GeoPoint> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let random_sign = |rng: &mut StdRng| if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+        (0..NUM_POINTS)
+            .map(|_| {
+                // Some of the points are close to the poles and to the antimeridian.
+                let lat = if rng.gen_bool(0.2) {
+                    rng.gen_range(85.0..=90.0) * random_sign(&mut rng)
+                } else {
+                    rng.gen_range(-90.0..=90.0)
+                };
+                let lon = if rng.gen_bool(0.2) {
+                    rng.gen_range(175.0..=180.0) * random_sign(&mut rng)
+                } else {
+                    rng.gen_range(-180.0..=180.0)
+                };
+                GeoPoint::new(lat, lon)
+            })
+            .collect()
+    }
+
+    fn create_index(options: GeoPointOptions, points: &[GeoPoint]) -> crate::Result<Searcher> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", FAST);
+        let location = schema_builder.add_geo_point_field("location", options);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for (point_id, point) in points.iter().enumerate() {
+            index_writer
+                .add_document(doc!(id => point_id as u64, location => OwnedValue::from(*point)))?;
+            if point_id == NUM_POINTS / 2 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index.reader()?.searcher())
+    }
+
+    /// Returns the sorted ids of the points matching the query.
+    fn search_ids(searcher: &Searcher, query: &dyn Query) -> crate::Result<Vec<usize>> {
+        let mut ids = Vec::new();
+        for doc_address in searcher.search(query, &DocSetCollector)? {
+            let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+            let id_column = segment_reader.fast_fields().u64("id")?;
+            ids.push(id_column.first(doc_address.doc_id).unwrap() as usize);
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn in_box(point: GeoPoint, top_left: GeoPoint, bottom_right: GeoPoint) -> bool {
+        let lat_in_box = (bottom_right.lat..=top_left.lat).contains(&point.lat);
+        let lon_in_box = if top_left.lon <= bottom_right.lon {
+            (top_left.lon..=bottom_right.lon).contains(&point.lon)
+        } else {
+            point.lon >= top_left.lon || point.lon <= bottom_right.lon
+        };
+        lat_in_box && lon_in_box
+    }
+
+    fn expected_ids(points: &[GeoPoint], matches: impl Fn(GeoPoint) -> bool) -> Vec<usize> {
+        (0..points.len())
+            .filter(|&point_id| matches(points[point_id]))
+            .collect()
+    }
+
+    fn test_geo_queries_aux(options: GeoPointOptions) -> crate::Result<()> {
+        let points = random_points();
+        let searcher = create_index(options, &points)?;
+        let location = searcher.schema().get_field("location")?;
+        let boxes = [
+            (GeoPoint::new(50.0, -10.0), GeoPoint::new(40.0, 10.0)),
+            // Crossing the antimeridian.
+            (GeoPoint::new(10.0, 170.0), GeoPoint::new(-10.0, -170.0)),
+            (GeoPoint::new(-80.0, 179.0), GeoPoint::new(-90.0, -179.5)),
+            // Around the north pole.
+            (GeoPoint::new(90.0, -180.0), GeoPoint::new(87.5, 180.0)),
+            // The whole world.
+            (GeoPoint::new(90.0, -180.0), GeoPoint::new(-90.0, 180.0)),
+            (GeoPoint::new(1.0, 1.0), GeoPoint::new(1.0, 1.0)),
+        ];
+        for (top_left, bottom_right) in boxes {
+            let query = GeoBoundingBoxQuery::new(location, top_left, bottom_right);
+            assert_eq!(
+                search_ids(&searcher, &query)?,
+                expected_ids(&points, |point| in_box(point, top_left, bottom_right)),
+                "{top_left:?} {bottom_right:?}"
+            );
+        }
+        let circles = [
+            (GeoPoint::new(48.8566, 2.3522), 1_500_000.0),
+            // Crossing the antimeridian.
+            (GeoPoint::new(-20.0, 179.5), 800_000.0),
+            (GeoPoint::new(60.0, -178.0), 2_000_000.0),
+            // Containing a pole.
+            (GeoPoint::new(88.0, 45.0), 500_000.0),
+            (GeoPoint::new(-90.0, 0.0), 300_000.0),
+            // Larger than the Earth.
+            (GeoPoint::new(0.0, 0.0), 30_000_000.0),
+            (GeoPoint::new(0.0, 0.0), 0.0),
+        ];
+        for (center, distance) in circles {
+            let query = GeoDistanceQuery::new(location, center, distance);
+            // The points are matched with the precision they are indexed with.
+            let expected = expected_ids(&points, |point| {
+                haversine_distance(center, GeoPoint::from_u64(point.to_u64())) <= distance
+            });
+            assert_eq!(
+                search_ids(&searcher, &query)?,
+                expected,
+                "{center:?} {distance}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_queries_fast() -> crate::Result<()> {
+        test_geo_queries_aux(FAST.into())
+    }
+
+    #[test]
+    fn test_geo_queries_indexed() -> crate::Result<()> {
+        test_geo_queries_aux((FAST | INDEXED).into())
+    }
+
+    #[test]
+    fn test_geo_distance_query_checks_only_candidates() -> crate::Result<()> {
+        let points = random_points();
+        for options in [FAST.into(), (FAST | INDEXED).into()] {
+            let searcher = create_index(options, &points)?;
+            let location = searcher.schema().get_field("location")?;
+            let center = GeoPoint::new(45.0, 5.0);
+            let query = GeoDistanceQuery::new(location, center, 2_000_000.0);
+            NUM_DISTANCE_COMPUTATIONS.with(|count| count.set(0));
+            let num_matches = search_ids(&searcher, &query)?.len();
+            let num_computations = NUM_DISTANCE_COMPUTATIONS.with(Cell::get);
+            assert!(num_matches > 0);
+            assert!(num_computations >= num_matches);
+            assert!(
+                num_computations * 10 < NUM_POINTS,
+                "{num_computations} distances computed"
+            );
+        }
+        Ok(())
+    }
+}
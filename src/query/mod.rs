@@ -1,10 +1,13 @@
 mod all_query;
 mod automaton_weight;
 mod bitset;
+mod block_join_query;
 mod bm25;
 mod boolean_query;
 mod boost_query;
+mod cached_query;
 mod const_score_query;
+mod cost_estimate;
 mod disjunction;
 mod disjunction_max_query;
 mod empty_query;
@@ -12,17 +15,23 @@ mod exclude;
 mod exist_query;
 mod explanation;
 mod fuzzy_query;
+mod geo_query;
 mod intersection;
 mod more_like_this;
 mod phrase_prefix_query;
 mod phrase_query;
+mod prefix_expansion_cache;
 mod query;
 mod query_parser;
+mod query_template;
 mod range_query;
 mod regex_query;
 mod reqopt_scorer;
 mod scorer;
+mod serializable_query;
 mod set_query;
+pub(crate) mod synonym_query;
+pub(crate) mod term_matches;
 mod term_query;
 mod union;
 mod weight;
@@ -36,31 +45,44 @@ pub use query_grammar::Occur;
 pub use self::all_query::{AllQuery, AllScorer, AllWeight};
 pub use self::automaton_weight::AutomatonWeight;
 pub use self::bitset::BitSetDocSet;
+pub use self::block_join_query::{BlockJoinQuery, ChildScoreMode, ParentFilter};
 pub use self::bm25::{Bm25StatisticsProvider, Bm25Weight};
 pub use self::boolean_query::{BooleanQuery, BooleanWeight};
 pub use self::boost_query::{BoostQuery, BoostWeight};
+pub use self::cached_query::{CacheableQuery, CachedQuery, QueryBitsetCache};
 pub use self::const_score_query::{ConstScoreQuery, ConstScorer};
+pub(crate) use self::cost_estimate::TermExpansionCost;
+pub use self::cost_estimate::{CostBounds, CostEstimate, QueryCostEstimate};
 pub use self::disjunction_max_query::DisjunctionMaxQuery;
 pub use self::empty_query::{EmptyQuery, EmptyScorer, EmptyWeight};
 pub use self::exclude::Exclude;
 pub use self::exist_query::ExistsQuery;
 pub use self::explanation::Explanation;
+pub(crate) use self::fuzzy_query::automaton_builder;
 #[cfg(test)]
 pub(crate) use self::fuzzy_query::DfaWrapper;
 pub use self::fuzzy_query::FuzzyTermQuery;
+pub use self::geo_query::{GeoBoundingBoxQuery, GeoDistanceQuery};
 pub use self::intersection::{intersect_scorers, Intersection};
 pub use self::more_like_this::{MoreLikeThisQuery, MoreLikeThisQueryBuilder};
+pub(crate) use self::phrase_prefix_query::prefix_end;
 pub use self::phrase_prefix_query::PhrasePrefixQuery;
 pub use self::phrase_query::regex_phrase_query::{wildcard_query_to_regex_str, RegexPhraseQuery};
 pub use self::phrase_query::PhraseQuery;
+pub use self::prefix_expansion_cache::PrefixExpansionBudget;
+pub(crate) use self::prefix_expansion_cache::PrefixExpansionCache;
 pub use self::query::{EnableScoring, Query, QueryClone};
 pub use self::query_parser::{QueryParser, QueryParserError};
+pub use self::query_template::{Param, QueryTemplate, TemplateQuery};
 pub use self::range_query::*;
 pub use self::regex_query::RegexQuery;
 pub use self::reqopt_scorer::RequiredOptionalScorer;
 pub use self::score_combiner::{DisjunctionMaxCombiner, ScoreCombiner, SumCombiner};
 pub use self::scorer::Scorer;
+pub use self::serializable_query::{SerializableQuery, SerializableTerm};
 pub use self::set_query::TermSetQuery;
+pub use self::synonym_query::{SynonymQueryWrapper, SynonymSet};
+pub use self::term_matches::TermMatch;
 pub use self::term_query::TermQuery;
 pub use self::union::BufferedUnionScorer;
 #[cfg(test)]
@@ -27,6 +27,7 @@ pub struct PhrasePrefixQuery {
     phrase_terms: Vec<(usize, Term)>,
     prefix: (usize, Term),
     max_expansions: u32,
+    max_expanded_terms: Option<usize>,
 }
 
 impl PhrasePrefixQuery {
@@ -60,6 +61,7 @@ impl PhrasePrefixQuery {
             prefix: terms.pop().unwrap(),
             phrase_terms: terms,
             max_expansions: DEFAULT_MAX_EXPANSIONS,
+            max_expanded_terms: None,
         }
     }
 
@@ -121,7 +123,8 @@ impl PhrasePrefixQuery {
             self.prefix.clone(),
             bm25_weight_opt,
             self.max_expansions,
-        );
+        )
+        .with_max_expanded_terms(self.max_expanded_terms);
         Ok(Some(weight))
     }
 }
@@ -148,12 +151,16 @@ impl Query for PhrasePrefixQuery {
             let lower_bound = Bound::Included(self.prefix.1.clone());
             let upper_bound = end_term;
 
-            Ok(Box::new(InvertedIndexRangeWeight::new(
-                self.field,
-                &lower_bound,
-                &upper_bound,
-                Some(self.max_expansions as u64),
-            )))
+            Ok(Box::new(
+                InvertedIndexRangeWeight::new(
+                    self.field,
+                    &lower_bound,
+                    &upper_bound,
+                    Some(self.max_expansions as u64),
+                )
+                .with_max_expanded_terms(self.max_expanded_terms)
+                .with_prefix(&self.prefix.1),
+            ))
         }
     }
 
@@ -162,4 +169,8 @@ impl Query for PhrasePrefixQuery {
             visitor(term, true);
         }
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.max_expanded_terms = Some(max_expanded_terms);
+    }
 }
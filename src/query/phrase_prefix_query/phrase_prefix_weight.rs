@@ -1,7 +1,8 @@
-use super::{prefix_end, PhrasePrefixScorer};
+use super::PhrasePrefixScorer;
 use crate::fieldnorm::FieldNormReader;
 use crate::index::SegmentReader;
 use crate::postings::SegmentPostings;
+use crate::query::automaton_weight::check_num_expanded_terms;
 use crate::query::bm25::Bm25Weight;
 use crate::query::explanation::does_not_match;
 use crate::query::{EmptyScorer, Explanation, Scorer, Weight};
@@ -13,6 +14,7 @@ pub struct PhrasePrefixWeight {
     prefix: (usize, Term),
     similarity_weight_opt: Option<Bm25Weight>,
     max_expansions: u32,
+    max_expanded_terms: Option<usize>,
 }
 
 impl PhrasePrefixWeight {
@@ -29,13 +31,25 @@ impl PhrasePrefixWeight {
             prefix,
             similarity_weight_opt,
             max_expansions,
+            max_expanded_terms: None,
         }
     }
 
+    /// Caps the number of terms the prefix may expand to in a segment.
+    ///
+    /// Unlike `max_expansions`, which silently ignores the terms beyond it, building the scorer
+    /// of a segment fails with
+    /// [`TantivyError::TooManyExpandedTerms`](crate::TantivyError::TooManyExpandedTerms) beyond
+    /// this cap.
+    pub fn with_max_expanded_terms(mut self, max_expanded_terms: Option<usize>) -> Self {
+        self.max_expanded_terms = max_expanded_terms;
+        self
+    }
+
     fn fieldnorm_reader(&self, reader: &SegmentReader) -> crate::Result<FieldNormReader> {
         let field = self.phrase_terms[0].1.field();
         if self.similarity_weight_opt.is_some() {
-            if let Some(fieldnorm_reader) = reader.fieldnorms_readers().get_field(field)? {
+            if let Some(fieldnorm_reader) = reader.try_fieldnorms_readers()?.get_field(field)? {
                 return Ok(fieldnorm_reader);
             }
         }
@@ -64,43 +78,33 @@ impl PhrasePrefixWeight {
             }
         }
 
+        // The expansion of the prefix is cached by the segment reader, for the successive
+        // prefixes of search-as-you-type queries.
         let inv_index = reader.inverted_index(self.prefix.1.field())?;
-        let mut stream = inv_index
-            .terms()
-            .range()
-            .ge(self.prefix.1.serialized_value_bytes());
-        if let Some(end) = prefix_end(self.prefix.1.serialized_value_bytes()) {
-            stream = stream.lt(&end);
-        }
-
-        #[cfg(feature = "quickwit")]
-        {
-            // We don't have this on the fst, hence  we end up needing a feature flag.
-            //
-            // This is not a problem however as we enforce the limit below too.
-            // The point of `stream.limit` is to limit the number of term dictionary
-            // blocks being downloaded.
-            stream = stream.limit(self.max_expansions as u64);
-        }
-
-        let mut stream = stream.into_stream()?;
+        let expansion = reader.prefix_expansion_cache().expand(
+            &inv_index,
+            self.prefix.1.field(),
+            self.prefix.1.serialized_value_bytes(),
+            self.max_expansions as usize,
+        )?;
 
         let mut suffixes = Vec::with_capacity(self.max_expansions as usize);
-        let mut new_term = self.prefix.1.clone();
-        while stream.advance() && (suffixes.len() as u32) < self.max_expansions {
-            new_term.clear_with_type(new_term.typ());
-            new_term.append_bytes(stream.key());
-            if reader.has_deletes() {
-                if let Some(postings) =
-                    inv_index.read_postings(&new_term, IndexRecordOption::WithFreqsAndPositions)?
-                {
-                    suffixes.push(postings);
-                }
-            } else if let Some(postings) =
-                inv_index.read_postings(&new_term, IndexRecordOption::WithFreqsAndPositions)?
-            {
-                suffixes.push(postings);
-            }
+        for (num_expanded_terms, (_, term_info)) in expansion
+            .terms()
+            .iter()
+            .take(self.max_expansions as usize)
+            .enumerate()
+        {
+            check_num_expanded_terms(
+                reader,
+                self.prefix.1.field(),
+                num_expanded_terms + 1,
+                self.max_expanded_terms,
+            )?;
+            suffixes.push(inv_index.read_postings_from_terminfo(
+                term_info,
+                IndexRecordOption::WithFreqsAndPositions,
+            )?);
         }
 
         Ok(Some(PhrasePrefixScorer::new(
@@ -81,6 +81,11 @@ impl PhraseQuery {
         self.slop = value;
     }
 
+    /// Slop allowed for the phrase, see [`PhraseQuery::set_slop()`].
+    pub fn slop(&self) -> u32 {
+        self.slop
+    }
+
     /// The [`Field`] this `PhraseQuery` is targeting.
     pub fn field(&self) -> Field {
         self.field
@@ -94,6 +99,11 @@ impl PhraseQuery {
             .collect::<Vec<Term>>()
     }
 
+    /// `Term`s in the phrase with their offsets.
+    pub fn phrase_terms_with_offsets(&self) -> &[(usize, Term)] {
+        &self.phrase_terms
+    }
+
     /// Returns the [`PhraseWeight`] for the given phrase query given a specific `searcher`.
     ///
     /// This function is the same as [`Query::weight()`] except it returns
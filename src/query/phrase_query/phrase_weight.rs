@@ -4,8 +4,9 @@ use crate::index::SegmentReader;
 use crate::postings::SegmentPostings;
 use crate::query::bm25::Bm25Weight;
 use crate::query::explanation::does_not_match;
-use crate::query::{EmptyScorer, Explanation, Scorer, Weight};
-use crate::schema::{IndexRecordOption, Term};
+use crate::query::term_matches::doc_term_positions;
+use crate::query::{EmptyScorer, Explanation, Scorer, TermMatch, Weight};
+use crate::schema::{Field, IndexRecordOption, Term};
 use crate::{DocId, DocSet, Score};
 
 pub struct PhraseWeight {
@@ -32,7 +33,7 @@ impl PhraseWeight {
     fn fieldnorm_reader(&self, reader: &SegmentReader) -> crate::Result<FieldNormReader> {
         let field = self.phrase_terms[0].1.field();
         if self.similarity_weight_opt.is_some() {
-            if let Some(fieldnorm_reader) = reader.fieldnorms_readers().get_field(field)? {
+            if let Some(fieldnorm_reader) = reader.try_fieldnorms_readers()?.get_field(field)? {
                 return Ok(fieldnorm_reader);
             }
         }
@@ -100,6 +101,53 @@ impl Weight for PhraseWeight {
         }
         Ok(explanation)
     }
+
+    fn term_matches(
+        &self,
+        reader: &SegmentReader,
+        doc: DocId,
+        fields: &[Field],
+        term_matches: &mut Vec<TermMatch>,
+    ) -> crate::Result<bool> {
+        // The terms of a sloppy phrase match can be laid out in many ways.
+        if self.slop > 0 {
+            return Ok(false);
+        }
+        if !fields.contains(&self.phrase_terms[0].1.field()) {
+            return Ok(true);
+        }
+        // Start positions of the occurrences of the phrase.
+        let mut starts: Option<Vec<u32>> = None;
+        for &(offset, ref term) in &self.phrase_terms {
+            let offset = offset as u32;
+            let positions = doc_term_positions(reader, term, doc)?;
+            starts = Some(match starts {
+                None => positions
+                    .into_iter()
+                    .filter_map(|pos| pos.checked_sub(offset))
+                    .collect(),
+                Some(mut starts) => {
+                    starts.retain(|start| positions.binary_search(&(start + offset)).is_ok());
+                    starts
+                }
+            });
+        }
+        let starts = starts.unwrap_or_default();
+        if starts.is_empty() {
+            return Ok(true);
+        }
+        for &(offset, ref term) in &self.phrase_terms {
+            let offset = offset as u32;
+            term_matches.push(TermMatch {
+                term: term.clone(),
+                positions: starts
+                    .iter()
+                    .map(|start| start + offset..start + offset + 1)
+                    .collect(),
+            });
+        }
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
@@ -47,7 +47,9 @@ impl RegexPhraseWeight {
 
     fn fieldnorm_reader(&self, reader: &SegmentReader) -> crate::Result<FieldNormReader> {
         if self.similarity_weight_opt.is_some() {
-            if let Some(fieldnorm_reader) = reader.fieldnorms_readers().get_field(self.field)? {
+            if let Some(fieldnorm_reader) =
+                reader.try_fieldnorms_readers()?.get_field(self.field)?
+            {
                 return Ok(fieldnorm_reader);
             }
         }
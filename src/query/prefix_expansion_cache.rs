@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::index::InvertedIndexReader;
+use crate::postings::TermInfo;
+use crate::query::prefix_end;
+use crate::schema::Field;
+
+/// Bounds the memory used by the prefix expansions cached for a segment.
+///
+/// See [`SearcherContext::with_prefix_expansion_budget()`](crate::SearcherContext::with_prefix_expansion_budget).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrefixExpansionBudget {
+    /// The maximum number of prefixes whose expansion is cached for a segment.
+    pub max_num_entries: usize,
+    /// The maximum number of bytes of the expansions cached for a segment.
+    pub max_num_bytes: usize,
+}
+
+impl Default for PrefixExpansionBudget {
+    fn default() -> Self {
+        PrefixExpansionBudget {
+            max_num_entries: 1_000,
+            max_num_bytes: 1_000_000,
+        }
+    }
+}
+
+/// The first terms of a segment starting with a prefix.
+#[derive(Debug)]
+pub(crate) struct PrefixExpansion {
+    /// The first terms starting with the prefix, in the order of the term dictionary.
+    terms: Vec<(Vec<u8>, TermInfo)>,
+    /// True if `terms` are all of the terms starting with the prefix.
+    complete: bool,
+}
+
+impl PrefixExpansion {
+    /// Returns the first terms starting with the prefix, in the order of the term dictionary.
+    ///
+    /// The terms include at least the number of terms the expansion was requested for, if there
+    /// are that many terms starting with the prefix.
+    pub(crate) fn terms(&self) -> &[(Vec<u8>, TermInfo)] {
+        &self.terms
+    }
+
+    /// Returns true if `self` is enough to answer an expansion of up to `limit` terms.
+    fn covers(&self, limit: usize) -> bool {
+        self.complete || self.terms.len() >= limit
+    }
+
+    fn num_bytes(&self, prefix: &[u8]) -> usize {
+        let terms_num_bytes: usize = self
+            .terms
+            .iter()
+            .map(|(term, _)| term.len() + std::mem::size_of::<(Vec<u8>, TermInfo)>())
+            .sum();
+        prefix.len() + terms_num_bytes + std::mem::size_of::<Self>()
+    }
+}
+
+struct CacheEntry {
+    expansion: Arc<PrefixExpansion>,
+    num_bytes: usize,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    budget: PrefixExpansionBudget,
+    entries: HashMap<(Field, Vec<u8>), CacheEntry>,
+    num_bytes: usize,
+    clock: u64,
+}
+
+impl CacheState {
+    fn get(&mut self, field: Field, prefix: &[u8]) -> Option<Arc<PrefixExpansion>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&(field, prefix.to_vec()))?;
+        entry.last_access = self.clock;
+        Some(entry.expansion.clone())
+    }
+
+    /// Returns the expansion of the longest cached prefix of `prefix`.
+    fn longest_cached_prefix(
+        &mut self,
+        field: Field,
+        prefix: &[u8],
+    ) -> Option<Arc<PrefixExpansion>> {
+        (0..=prefix.len())
+            .rev()
+            .find_map(|len| self.get(field, &prefix[..len]))
+    }
+
+    /// Inserts an expansion, evicting the least recently used ones beyond the budget.
+    fn insert(&mut self, field: Field, prefix: &[u8], expansion: Arc<PrefixExpansion>) {
+        let num_bytes = expansion.num_bytes(prefix);
+        if num_bytes > self.budget.max_num_bytes || self.budget.max_num_entries == 0 {
+            return;
+        }
+        self.clock += 1;
+        let entry = CacheEntry {
+            expansion,
+            num_bytes,
+            last_access: self.clock,
+        };
+        if let Some(previous_entry) = self.entries.insert((field, prefix.to_vec()), entry) {
+            self.num_bytes -= previous_entry.num_bytes;
+        }
+        self.num_bytes += num_bytes;
+        while self.entries.len() > self.budget.max_num_entries
+            || self.num_bytes > self.budget.max_num_bytes
+        {
+            let Some(evicted_key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted_entry) = self.entries.remove(&evicted_key) {
+                self.num_bytes -= evicted_entry.num_bytes;
+            }
+        }
+    }
+}
+
+/// Cache of the prefix expansions of a segment.
+///
+/// The cache is shared by the clones of a [`SegmentReader`](crate::SegmentReader), which live as
+/// long as the searcher generation. This makes the successive prefixes of a search-as-you-type
+/// session cheap: an expansion is answered from the cached expansion of a shorter prefix when
+/// possible, and the term dictionary is otherwise only walked from the last term it contains.
+#[derive(Clone, Default)]
+pub(crate) struct PrefixExpansionCache {
+    state: Arc<Mutex<CacheState>>,
+    /// Number of walks of the term dictionary, for tests.
+    num_dictionary_walks: Arc<AtomicUsize>,
+}
+
+impl PrefixExpansionCache {
+    pub(crate) fn with_budget(budget: PrefixExpansionBudget) -> PrefixExpansionCache {
+        PrefixExpansionCache {
+            state: Arc::new(Mutex::new(CacheState {
+                budget,
+                ..Default::default()
+            })),
+            num_dictionary_walks: Default::default(),
+        }
+    }
+
+    /// Returns the first terms of `inverted_index` starting with `prefix`, at least `limit` of
+    /// them if there are that many.
+    pub(crate) fn expand(
+        &self,
+        inverted_index: &InvertedIndexReader,
+        field: Field,
+        prefix: &[u8],
+        limit: usize,
+    ) -> io::Result<Arc<PrefixExpansion>> {
+        let cached_expansion = {
+            let mut state = self.state.lock().unwrap();
+            state.longest_cached_prefix(field, prefix)
+        };
+        let mut expansion = match cached_expansion {
+            Some(cached_expansion) => narrow(&cached_expansion, prefix),
+            None => PrefixExpansion {
+                terms: Vec::new(),
+                complete: false,
+            },
+        };
+        if !expansion.covers(limit) {
+            self.walk_dictionary(inverted_index, prefix, limit, &mut expansion)?;
+        }
+        let expansion = Arc::new(expansion);
+        self.state
+            .lock()
+            .unwrap()
+            .insert(field, prefix, expansion.clone());
+        Ok(expansion)
+    }
+
+    /// Completes `expansion` with the terms of the dictionary following its last term, up to
+    /// `limit` terms.
+    fn walk_dictionary(
+        &self,
+        inverted_index: &InvertedIndexReader,
+        prefix: &[u8],
+        limit: usize,
+        expansion: &mut PrefixExpansion,
+    ) -> io::Result<()> {
+        self.num_dictionary_walks.fetch_add(1, Ordering::Relaxed);
+        let mut stream_builder = inverted_index.terms().range();
+        stream_builder = match expansion.terms.last() {
+            Some((last_term, _)) => stream_builder.gt(last_term),
+            None => stream_builder.ge(prefix),
+        };
+        if let Some(end) = prefix_end(prefix) {
+            stream_builder = stream_builder.lt(end);
+        }
+        let mut stream = stream_builder.into_stream()?;
+        while expansion.terms.len() < limit {
+            if !stream.advance() {
+                expansion.complete = true;
+                return Ok(());
+            }
+            expansion
+                .terms
+                .push((stream.key().to_vec(), stream.value().clone()));
+        }
+        expansion.complete = !stream.advance();
+        Ok(())
+    }
+
+    /// Returns the number of walks of the term dictionary done by the cache.
+    #[cfg(test)]
+    pub(crate) fn num_dictionary_walks(&self) -> usize {
+        self.num_dictionary_walks.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of cached prefixes.
+    #[cfg(test)]
+    pub(crate) fn num_entries(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+}
+
+/// Returns the part of the expansion of a prefix of `prefix` which starts with `prefix`.
+///
+/// The terms starting with `prefix` are a contiguous run of the terms of the expansion. They are
+/// all of the terms starting with `prefix` if the cached expansion is complete, or if it
+/// contains a term after them.
+fn narrow(cached_expansion: &PrefixExpansion, prefix: &[u8]) -> PrefixExpansion {
+    let first_idx = cached_expansion
+        .terms
+        .partition_point(|(term, _)| term.as_slice() < prefix);
+    let terms: Vec<(Vec<u8>, TermInfo)> = cached_expansion.terms[first_idx..]
+        .iter()
+        .take_while(|(term, _)| term.starts_with(prefix))
+        .cloned()
+        .collect();
+    let complete =
+        cached_expansion.complete || first_idx + terms.len() < cached_expansion.terms.len();
+    PrefixExpansion { terms, complete }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrefixExpansionBudget, PrefixExpansionCache};
+    use crate::collector::TopDocs;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{PhrasePrefixQuery, Query};
+    use crate::reader::SearcherContext;
+    use crate::schema::{Schema, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, Searcher, Term};
+
+    const WORDS: [&str; 9] = [
+        "sable", "sage", "salt", "samba", "samovar", "sample", "samsung", "samurai", "sand",
+    ];
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for (i, word) in WORDS.iter().enumerate() {
+            index_writer.add_document(doc!(text => format!("red {word}")))?;
+            if i % 2 == 0 {
+                index_writer.add_document(doc!(text => format!("blue {word}")))?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    fn prefix_query(index: &Index, words: &[&str]) -> PhrasePrefixQuery {
+        let text = index.schema().get_field("text").unwrap();
+        let terms = words
+            .iter()
+            .map(|word| Term::from_field_text(text, word))
+            .collect();
+        PhrasePrefixQuery::new(terms)
+    }
+
+    fn search(
+        searcher: &Searcher,
+        query: &dyn Query,
+    ) -> crate::Result<Vec<(crate::Score, DocAddress)>> {
+        searcher.search(query, &TopDocs::with_limit(100))
+    }
+
+    fn cache(searcher: &Searcher) -> PrefixExpansionCache {
+        searcher.segment_reader(0).prefix_expansion_cache().clone()
+    }
+
+    #[test]
+    fn test_prefix_expansion_narrowing() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        for (i, prefix) in ["sa", "sam", "sams", "samsu"].iter().enumerate() {
+            search(&searcher, &prefix_query(&index, &["red", prefix]))?;
+            search(&searcher, &prefix_query(&index, &[prefix]))?;
+            // Only the first prefix walks the term dictionary: the expansion of "sa" contains all
+            // of its terms, from which the expansions of the longer prefixes are narrowed.
+            assert_eq!(cache(&searcher).num_dictionary_walks(), 1);
+            assert_eq!(cache(&searcher).num_entries(), i + 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_expansion_narrowing_with_max_expansions() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let mut query = prefix_query(&index, &["red", "sa"]);
+        query.set_max_expansions(2);
+        search(&searcher, &query)?;
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 1);
+        // The expansion of "sa" stops at "sage", before the terms of "sam", whose expansion walks
+        // the dictionary again.
+        let mut query = prefix_query(&index, &["red", "sam"]);
+        query.set_max_expansions(2);
+        search(&searcher, &query)?;
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 2);
+        // The expansion of "samb" is narrowed from the expansion of "sam", which stops at
+        // "samovar", after the terms of "samb".
+        let mut query = prefix_query(&index, &["red", "samb"]);
+        query.set_max_expansions(2);
+        search(&searcher, &query)?;
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 2);
+        // A larger expansion of "sam" continues the walk after "samovar".
+        let mut query = prefix_query(&index, &["red", "sam"]);
+        query.set_max_expansions(4);
+        search(&searcher, &query)?;
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_expansion_same_results_as_cold_execution() -> crate::Result<()> {
+        let index = create_index()?;
+        let warm_searcher = index.reader()?.searcher();
+        for prefix in [
+            "s", "sa", "sam", "samp", "sample", "samplex", "san", "z", "",
+        ] {
+            for max_expansions in [1, 3, 50] {
+                for words in [vec!["red", prefix], vec!["blue", prefix], vec![prefix]] {
+                    let mut query = prefix_query(&index, &words);
+                    query.set_max_expansions(max_expansions);
+                    // A new reader opens new segment readers, with an empty cache.
+                    let cold_searcher = index.reader()?.searcher();
+                    assert_eq!(
+                        search(&warm_searcher, &query)?,
+                        search(&cold_searcher, &query)?,
+                        "{words:?} {max_expansions}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_expansion_budget() -> crate::Result<()> {
+        let index = create_index()?;
+        let budget = PrefixExpansionBudget {
+            max_num_entries: 2,
+            ..Default::default()
+        };
+        let reader = index
+            .reader_builder()
+            .searcher_context(SearcherContext::default().with_prefix_expansion_budget(budget))
+            .try_into()?;
+        let searcher = reader.searcher();
+        for prefix in ["sab", "sag", "sal"] {
+            search(&searcher, &prefix_query(&index, &["red", prefix]))?;
+        }
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 3);
+        assert_eq!(cache(&searcher).num_entries(), 2);
+        // "sab" was evicted, so its expansion walks the dictionary again.
+        search(&searcher, &prefix_query(&index, &["red", "sab"]))?;
+        assert_eq!(cache(&searcher).num_dictionary_walks(), 4);
+        assert_eq!(cache(&searcher).num_entries(), 2);
+
+        let budget = PrefixExpansionBudget {
+            max_num_bytes: 0,
+            ..Default::default()
+        };
+        let reader = index
+            .reader_builder()
+            .searcher_context(SearcherContext::default().with_prefix_expansion_budget(budget))
+            .try_into()?;
+        let searcher = reader.searcher();
+        search(&searcher, &prefix_query(&index, &["red", "sa"]))?;
+        assert_eq!(cache(&searcher).num_entries(), 0);
+        Ok(())
+    }
+}
@@ -160,6 +160,17 @@ pub trait Query: QueryClone + Send + Sync + downcast_rs::Downcast + fmt::Debug {
     /// Note that there can be multiple instances of any given term
     /// in a query and deduplication must be handled by the visitor.
     fn query_terms<'a>(&'a self, _visitor: &mut dyn FnMut(&'a Term, bool)) {}
+
+    /// Caps the number of terms the query may expand to in a segment, for the queries matching
+    /// the terms of an automaton or of a range, such as regex, fuzzy or prefix queries.
+    ///
+    /// Beyond the cap, building the scorer of a segment fails with
+    /// [`TantivyError::TooManyExpandedTerms`](crate::TantivyError::TooManyExpandedTerms)
+    /// rather than going through all of the matching terms.
+    ///
+    /// Queries that do not expand terms ignore it, and queries wrapping other queries forward it
+    /// to them.
+    fn set_max_expanded_terms(&mut self, _max_expanded_terms: usize) {}
 }
 
 /// Implements `box_clone`.
@@ -188,6 +199,10 @@ impl Query for Box<dyn Query> {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.as_ref().query_terms(visitor);
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.as_mut().set_max_expanded_terms(max_expanded_terms);
+    }
 }
 
 impl QueryClone for Box<dyn Query> {
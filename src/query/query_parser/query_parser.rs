@@ -15,7 +15,7 @@ use crate::json_utils::convert_to_fast_value_and_append_to_json_term;
 use crate::query::range_query::{is_type_valid_for_fastfield_range_query, RangeQuery};
 use crate::query::{
     AllQuery, BooleanQuery, BoostQuery, EmptyQuery, FuzzyTermQuery, Occur, PhrasePrefixQuery,
-    PhraseQuery, Query, TermQuery, TermSetQuery,
+    PhraseQuery, Query, SynonymQueryWrapper, TermQuery, TermSetQuery,
 };
 use crate::schema::{
     Facet, FacetParseError, Field, FieldType, IndexRecordOption, IntoIpv6Addr, JsonObjectOptions,
@@ -206,6 +206,7 @@ pub struct QueryParser {
     tokenizer_manager: TokenizerManager,
     boost: FxHashMap<Field, Score>,
     fuzzy: FxHashMap<Field, Fuzzy>,
+    synonym_boost: Option<Score>,
 }
 
 #[derive(Clone)]
@@ -215,6 +216,13 @@ struct Fuzzy {
     transpose_cost_one: bool,
 }
 
+fn unsupported_geo_point_query(field_name: &str) -> QueryParserError {
+    QueryParserError::UnsupportedQuery(format!(
+        "The geo point field {field_name:?} can only be searched with a GeoBoundingBoxQuery or a \
+         GeoDistanceQuery"
+    ))
+}
+
 fn all_negative(ast: &LogicalAst) -> bool {
     match ast {
         LogicalAst::Leaf(_) => false,
@@ -260,6 +268,7 @@ impl QueryParser {
             conjunction_by_default: false,
             boost: Default::default(),
             fuzzy: Default::default(),
+            synonym_boost: None,
         }
     }
 
@@ -320,13 +329,31 @@ impl QueryParser {
         );
     }
 
+    /// Makes the parsed term and phrase queries match the synonyms of their terms, by wrapping
+    /// them in a [`SynonymQueryWrapper`].
+    ///
+    /// The synonyms are the ones of the reader the query is run with, see
+    /// [`IndexReader::set_query_synonyms`](crate::IndexReader::set_query_synonyms). The scores
+    /// of the synonym matches are multiplied by `synonym_boost`, which must be in `(0, 1)`.
+    pub fn set_synonym_boost(&mut self, synonym_boost: Score) {
+        assert!(
+            synonym_boost > 0.0 && synonym_boost < 1.0,
+            "The synonym boost must be in (0, 1), got {synonym_boost}"
+        );
+        self.synonym_boost = Some(synonym_boost);
+    }
+
     /// Parse a query
     ///
     /// Note that `parse_query` returns an error if the input
     /// is not a valid query.
     pub fn parse_query(&self, query: &str) -> Result<Box<dyn Query>, QueryParserError> {
         let logical_ast = self.parse_query_to_logical_ast(query)?;
-        Ok(convert_to_query(&self.fuzzy, logical_ast))
+        Ok(convert_to_query(
+            &self.fuzzy,
+            self.synonym_boost,
+            logical_ast,
+        ))
     }
 
     /// Parse a query leniently
@@ -339,7 +366,10 @@ impl QueryParser {
     /// In case it encountered such issues, they are reported as a Vec of errors.
     pub fn parse_query_lenient(&self, query: &str) -> (Box<dyn Query>, Vec<QueryParserError>) {
         let (logical_ast, errors) = self.parse_query_to_logical_ast_lenient(query);
-        (convert_to_query(&self.fuzzy, logical_ast), errors)
+        (
+            convert_to_query(&self.fuzzy, self.synonym_boost, logical_ast),
+            errors,
+        )
     }
 
     /// Build a query from an already parsed user input AST
@@ -355,7 +385,11 @@ impl QueryParser {
         if !err.is_empty() {
             return Err(err.swap_remove(0));
         }
-        Ok(convert_to_query(&self.fuzzy, logical_ast))
+        Ok(convert_to_query(
+            &self.fuzzy,
+            self.synonym_boost,
+            logical_ast,
+        ))
     }
 
     /// Build leniently a query from an already parsed user input AST.
@@ -366,7 +400,10 @@ impl QueryParser {
         user_input_ast: UserInputAst,
     ) -> (Box<dyn Query>, Vec<QueryParserError>) {
         let (logical_ast, errors) = self.compute_logical_ast_lenient(user_input_ast);
-        (convert_to_query(&self.fuzzy, logical_ast), errors)
+        (
+            convert_to_query(&self.fuzzy, self.synonym_boost, logical_ast),
+            errors,
+        )
     }
 
     /// Parse the user query into an AST.
@@ -522,6 +559,7 @@ impl QueryParser {
                 let ip_v6 = IpAddr::from_str(phrase)?.into_ipv6_addr();
                 Ok(Term::from_field_ip_addr(field, ip_v6))
             }
+            FieldType::GeoPoint(_) => Err(unsupported_geo_point_query(field_entry.name())),
         }
     }
 
@@ -622,6 +660,9 @@ impl QueryParser {
                 let term = Term::from_field_ip_addr(field, ip_v6);
                 Ok(vec![LogicalLiteral::Term(term)])
             }
+            FieldType::GeoPoint(_) => Err(unsupported_geo_point_query(
+                self.schema.get_field_name(field),
+            )),
         }
     }
 
@@ -860,8 +901,15 @@ impl QueryParser {
 
 fn convert_literal_to_query(
     fuzzy: &FxHashMap<Field, Fuzzy>,
+    synonym_boost: Option<Score>,
     logical_literal: LogicalLiteral,
 ) -> Box<dyn Query> {
+    let with_synonyms = |query: Box<dyn Query>| -> Box<dyn Query> {
+        match synonym_boost {
+            Some(synonym_boost) => Box::new(SynonymQueryWrapper::new(query, synonym_boost)),
+            None => query,
+        }
+    };
     match logical_literal {
         LogicalLiteral::Term(term) => {
             if let Some(fuzzy) = fuzzy.get(&term.field()) {
@@ -879,7 +927,7 @@ fn convert_literal_to_query(
                     ))
                 }
             } else {
-                Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+                with_synonyms(Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)))
             }
         }
         LogicalLiteral::Phrase {
@@ -890,7 +938,7 @@ fn convert_literal_to_query(
             if prefix {
                 Box::new(PhrasePrefixQuery::new_with_offset(terms))
             } else {
-                Box::new(PhraseQuery::new_with_offset_and_slop(terms, slop))
+                with_synonyms(Box::new(PhraseQuery::new_with_offset_and_slop(terms, slop)))
             }
         }
         LogicalLiteral::Range { lower, upper } => Box::new(RangeQuery::new(lower, upper)),
@@ -998,12 +1046,16 @@ fn generate_literals_for_json_object(
     Ok(logical_literals)
 }
 
-fn convert_to_query(fuzzy: &FxHashMap<Field, Fuzzy>, logical_ast: LogicalAst) -> Box<dyn Query> {
+fn convert_to_query(
+    fuzzy: &FxHashMap<Field, Fuzzy>,
+    synonym_boost: Option<Score>,
+    logical_ast: LogicalAst,
+) -> Box<dyn Query> {
     match trim_ast(logical_ast) {
         Some(LogicalAst::Clause(trimmed_clause)) => {
             let occur_subqueries = trimmed_clause
                 .into_iter()
-                .map(|(occur, subquery)| (occur, convert_to_query(fuzzy, subquery)))
+                .map(|(occur, subquery)| (occur, convert_to_query(fuzzy, synonym_boost, subquery)))
                 .collect::<Vec<_>>();
             assert!(
                 !occur_subqueries.is_empty(),
@@ -1012,10 +1064,10 @@ fn convert_to_query(fuzzy: &FxHashMap<Field, Fuzzy>, logical_ast: LogicalAst) ->
             Box::new(BooleanQuery::new(occur_subqueries))
         }
         Some(LogicalAst::Leaf(trimmed_logical_literal)) => {
-            convert_literal_to_query(fuzzy, *trimmed_logical_literal)
+            convert_literal_to_query(fuzzy, synonym_boost, *trimmed_logical_literal)
         }
         Some(LogicalAst::Boost(ast, boost)) => {
-            let query = convert_to_query(fuzzy, *ast);
+            let query = convert_to_query(fuzzy, synonym_boost, *ast);
             let boosted_query = BoostQuery::new(query, boost);
             Box::new(boosted_query)
         }
@@ -1830,11 +1882,11 @@ mod test {
             format!("{query:?}"),
             "BooleanQuery { subqueries: [(Should, PhrasePrefixQuery { field: Field(0), \
              phrase_terms: [(0, Term(field=0, type=Str, \"big\")), (1, Term(field=0, type=Str, \
-             \"bad\"))], prefix: (2, Term(field=0, type=Str, \"wo\")), max_expansions: 50 }), \
-             (Should, PhrasePrefixQuery { field: Field(1), phrase_terms: [(0, Term(field=1, \
-             type=Str, \"big\")), (1, Term(field=1, type=Str, \"bad\"))], prefix: (2, \
-             Term(field=1, type=Str, \"wo\")), max_expansions: 50 })], \
-             minimum_number_should_match: 1 }"
+             \"bad\"))], prefix: (2, Term(field=0, type=Str, \"wo\")), max_expansions: 50, \
+             max_expanded_terms: None }), (Should, PhrasePrefixQuery { field: Field(1), \
+             phrase_terms: [(0, Term(field=1, type=Str, \"big\")), (1, Term(field=1, type=Str, \
+             \"bad\"))], prefix: (2, Term(field=1, type=Str, \"wo\")), max_expansions: 50, \
+             max_expanded_terms: None })], minimum_number_should_match: 1 }"
         );
     }
 
@@ -1898,9 +1950,9 @@ mod test {
             assert_eq!(
                 format!("{query:?}"),
                 "BooleanQuery { subqueries: [(Should, FuzzyTermQuery { term: Term(field=0, \
-                 type=Str, \"abc\"), distance: 1, transposition_cost_one: true, prefix: false }), \
-                 (Should, TermQuery(Term(field=1, type=Str, \"abc\")))], \
-                 minimum_number_should_match: 1 }"
+                 type=Str, \"abc\"), distance: 1, transposition_cost_one: true, prefix: false, \
+                 max_expanded_terms: None }), (Should, TermQuery(Term(field=1, type=Str, \
+                 \"abc\")))], minimum_number_should_match: 1 }"
             );
         }
 
@@ -1917,8 +1969,8 @@ mod test {
                 format!("{query:?}"),
                 "BooleanQuery { subqueries: [(Should, TermQuery(Term(field=0, type=Str, \
                  \"abc\"))), (Should, FuzzyTermQuery { term: Term(field=1, type=Str, \"abc\"), \
-                 distance: 2, transposition_cost_one: false, prefix: true })], \
-                 minimum_number_should_match: 1 }"
+                 distance: 2, transposition_cost_one: false, prefix: true, max_expanded_terms: \
+                 None })], minimum_number_should_match: 1 }"
             );
         }
     }
@@ -0,0 +1,817 @@
+use std::fmt;
+use std::ops::Bound;
+
+use crate::query::{
+    BooleanQuery, EmptyQuery, Occur, PhraseQuery, Query, QueryClone, RangeQuery, TermQuery,
+};
+use crate::schema::{Field, FieldType, IndexRecordOption, Schema};
+use crate::tokenizer::{TextAnalyzer, TokenizerManager};
+use crate::{TantivyError, Term};
+
+/// A query with placeholders, compiled into a [`QueryTemplate`].
+///
+/// Placeholders are identified by their position in the parameters given to
+/// [`QueryTemplate::bind`].
+#[derive(Debug)]
+pub enum TemplateQuery {
+    /// A query without placeholder, shared by all of the bound queries.
+    Query(Box<dyn Query>),
+    /// The tokens of `text` in the text field `field`.
+    ///
+    /// The text is tokenized once, when the template is compiled.
+    Text {
+        /// Name of the field
+        field: String,
+        /// Text to tokenize with the analyzer of the field
+        text: String,
+    },
+    /// The term bound to `placeholder` in `field`.
+    ///
+    /// On a text field, the parameter is a [`Param::Str`] tokenized with the analyzer of the
+    /// field, as the query parser would. On a `u64`, `i64`, `f64` or `bool` field, it is the value
+    /// of the matching type.
+    Term {
+        /// Name of the field
+        field: String,
+        /// Position of the parameter
+        placeholder: usize,
+    },
+    /// The documents whose value for `field` is in the range bound to `placeholder`.
+    ///
+    /// The field is a `u64`, `i64` or `f64` field, and the parameter the range of the matching
+    /// type.
+    Range {
+        /// Name of the field
+        field: String,
+        /// Position of the parameter
+        placeholder: usize,
+    },
+    /// A boolean composition of templates.
+    Boolean(Vec<(Occur, TemplateQuery)>),
+}
+
+/// A value bound to a placeholder of a [`QueryTemplate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Param<'a> {
+    /// Text, tokenized with the analyzer of the field.
+    Str(&'a str),
+    /// `u64` value
+    U64(u64),
+    /// `i64` value
+    I64(i64),
+    /// `f64` value
+    F64(f64),
+    /// `bool` value
+    Bool(bool),
+    /// Range of `u64` values, both bounds included.
+    U64Range(u64, u64),
+    /// Range of `i64` values, both bounds included.
+    I64Range(i64, i64),
+    /// Range of `f64` values, both bounds included.
+    F64Range(f64, f64),
+}
+
+/// Type of the parameter expected by a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParamType {
+    Str,
+    U64,
+    I64,
+    F64,
+    Bool,
+    U64Range,
+    I64Range,
+    F64Range,
+}
+
+impl Param<'_> {
+    fn param_type(&self) -> ParamType {
+        match self {
+            Param::Str(_) => ParamType::Str,
+            Param::U64(_) => ParamType::U64,
+            Param::I64(_) => ParamType::I64,
+            Param::F64(_) => ParamType::F64,
+            Param::Bool(_) => ParamType::Bool,
+            Param::U64Range(..) => ParamType::U64Range,
+            Param::I64Range(..) => ParamType::I64Range,
+            Param::F64Range(..) => ParamType::F64Range,
+        }
+    }
+}
+
+/// A query compiled once, and bound to parameters to produce the queries to execute.
+///
+/// Compiling resolves the fields and analyzers of the template against the schema, and builds the
+/// parts of the query without placeholder. Binding only builds the queries of the placeholders,
+/// tokenizing the text parameters with the pre-resolved analyzers, and clones the static parts.
+///
+/// ```rust
+/// use tantivy::query::{Occur, Param, QueryTemplate, TemplateQuery};
+/// use tantivy::schema::{Schema, INDEXED, TEXT};
+/// use tantivy::tokenizer::TokenizerManager;
+///
+/// let mut schema_builder = Schema::builder();
+/// schema_builder.add_text_field("title", TEXT);
+/// schema_builder.add_u64_field("price", INDEXED);
+/// let schema = schema_builder.build();
+///
+/// let template = QueryTemplate::compile(
+///     &schema,
+///     &TokenizerManager::default(),
+///     TemplateQuery::Boolean(vec![
+///         (
+///             Occur::Must,
+///             TemplateQuery::Term {
+///                 field: "title".to_string(),
+///                 placeholder: 0,
+///             },
+///         ),
+///         (
+///             Occur::Must,
+///             TemplateQuery::Range {
+///                 field: "price".to_string(),
+///                 placeholder: 1,
+///             },
+///         ),
+///     ]),
+/// )
+/// .unwrap();
+/// let query = template
+///     .bind(&[Param::Str("Diary"), Param::U64Range(10, 20)])
+///     .unwrap();
+/// ```
+pub struct QueryTemplate {
+    root: CompiledQuery,
+    param_types: Vec<ParamType>,
+}
+
+enum CompiledQuery {
+    Static(Box<dyn Query>),
+    Text {
+        field: Field,
+        field_name: String,
+        analyzer: TextAnalyzer,
+        has_positions: bool,
+        placeholder: usize,
+    },
+    Term {
+        field: Field,
+        placeholder: usize,
+    },
+    Range {
+        field: Field,
+        placeholder: usize,
+    },
+    Boolean(Vec<(Occur, CompiledQuery)>),
+}
+
+impl fmt::Debug for QueryTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryTemplate")
+            .field("param_types", &self.param_types)
+            .finish_non_exhaustive()
+    }
+}
+
+impl QueryTemplate {
+    /// Compiles `template` against `schema`, with the analyzers of `tokenizer_manager`.
+    ///
+    /// Returns an error if a field does not exist or does not support its placeholder, or if the
+    /// placeholders are not numbered `0..n` with a single type each.
+    pub fn compile(
+        schema: &Schema,
+        tokenizer_manager: &TokenizerManager,
+        template: TemplateQuery,
+    ) -> crate::Result<QueryTemplate> {
+        let mut param_types = Vec::new();
+        let root = compile_query(schema, tokenizer_manager, template, &mut param_types)?;
+        let param_types = param_types
+            .into_iter()
+            .enumerate()
+            .map(|(placeholder, param_type)| {
+                param_type.ok_or_else(|| {
+                    TantivyError::InvalidArgument(format!(
+                        "Placeholder {placeholder} is not used by the query template"
+                    ))
+                })
+            })
+            .collect::<crate::Result<Vec<ParamType>>>()?;
+        Ok(QueryTemplate { root, param_types })
+    }
+
+    /// Returns the number of parameters expected by [`QueryTemplate::bind`].
+    pub fn num_params(&self) -> usize {
+        self.param_types.len()
+    }
+
+    /// Returns the query of the template, with the placeholder `i` bound to `params[i]`.
+    pub fn bind(&self, params: &[Param]) -> crate::Result<Box<dyn Query>> {
+        if params.len() != self.param_types.len() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "The query template expects {} parameters, got {}",
+                self.param_types.len(),
+                params.len()
+            )));
+        }
+        for (placeholder, (param, param_type)) in params.iter().zip(&self.param_types).enumerate() {
+            if param.param_type() != *param_type {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "Placeholder {placeholder} expects a {param_type:?} parameter, got {param:?}"
+                )));
+            }
+        }
+        bind_query(&self.root, params)
+    }
+}
+
+fn register_placeholder(
+    param_types: &mut Vec<Option<ParamType>>,
+    placeholder: usize,
+    param_type: ParamType,
+) -> crate::Result<()> {
+    if param_types.len() <= placeholder {
+        param_types.resize(placeholder + 1, None);
+    }
+    match param_types[placeholder] {
+        Some(registered_type) if registered_type != param_type => {
+            Err(TantivyError::InvalidArgument(format!(
+                "Placeholder {placeholder} is used both as a {registered_type:?} and a \
+                 {param_type:?} parameter"
+            )))
+        }
+        _ => {
+            param_types[placeholder] = Some(param_type);
+            Ok(())
+        }
+    }
+}
+
+fn indexed_field<'a>(
+    schema: &'a Schema,
+    field_name: &str,
+) -> crate::Result<(Field, &'a FieldType)> {
+    let field = schema.get_field(field_name)?;
+    let field_type = schema.get_field_entry(field).field_type();
+    if !field_type.is_indexed() {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {field_name:?} is not indexed"
+        )));
+    }
+    Ok((field, field_type))
+}
+
+fn field_analyzer(
+    schema: &Schema,
+    tokenizer_manager: &TokenizerManager,
+    field_name: &str,
+) -> crate::Result<(Field, TextAnalyzer, bool)> {
+    let (field, field_type) = indexed_field(schema, field_name)?;
+    let FieldType::Str(text_options) = field_type else {
+        return Err(TantivyError::SchemaError(format!(
+            "Field {field_name:?} is not a text field"
+        )));
+    };
+    let indexing_options = text_options
+        .get_indexing_options()
+        .expect("indexed text fields have indexing options");
+    let analyzer = tokenizer_manager
+        .get(indexing_options.tokenizer())
+        .ok_or_else(|| {
+            TantivyError::SchemaError(format!(
+                "Unknown tokenizer {:?} for field {field_name:?}",
+                indexing_options.tokenizer()
+            ))
+        })?;
+    let has_positions = indexing_options.index_option().has_positions();
+    Ok((field, analyzer, has_positions))
+}
+
+fn compile_query(
+    schema: &Schema,
+    tokenizer_manager: &TokenizerManager,
+    template: TemplateQuery,
+    param_types: &mut Vec<Option<ParamType>>,
+) -> crate::Result<CompiledQuery> {
+    let compiled = match template {
+        TemplateQuery::Query(query) => CompiledQuery::Static(query),
+        TemplateQuery::Text { field, text } => {
+            let (field_id, mut analyzer, has_positions) =
+                field_analyzer(schema, tokenizer_manager, &field)?;
+            CompiledQuery::Static(text_query(
+                field_id,
+                &field,
+                &mut analyzer,
+                has_positions,
+                &text,
+            )?)
+        }
+        TemplateQuery::Term { field, placeholder } => {
+            let (field_id, field_type) = indexed_field(schema, &field)?;
+            let param_type = match field_type {
+                FieldType::Str(_) => {
+                    register_placeholder(param_types, placeholder, ParamType::Str)?;
+                    let (field_id, analyzer, has_positions) =
+                        field_analyzer(schema, tokenizer_manager, &field)?;
+                    return Ok(CompiledQuery::Text {
+                        field: field_id,
+                        field_name: field,
+                        analyzer,
+                        has_positions,
+                        placeholder,
+                    });
+                }
+                FieldType::U64(_) => ParamType::U64,
+                FieldType::I64(_) => ParamType::I64,
+                FieldType::F64(_) => ParamType::F64,
+                FieldType::Bool(_) => ParamType::Bool,
+                _ => {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "Term placeholders are not supported on field {field:?} of type {:?}",
+                        field_type.value_type()
+                    )));
+                }
+            };
+            register_placeholder(param_types, placeholder, param_type)?;
+            CompiledQuery::Term {
+                field: field_id,
+                placeholder,
+            }
+        }
+        TemplateQuery::Range { field, placeholder } => {
+            let field_id = schema.get_field(&field)?;
+            let field_type = schema.get_field_entry(field_id).field_type();
+            let param_type = match field_type {
+                FieldType::U64(_) => ParamType::U64Range,
+                FieldType::I64(_) => ParamType::I64Range,
+                FieldType::F64(_) => ParamType::F64Range,
+                _ => {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "Range placeholders are not supported on field {field:?} of type {:?}",
+                        field_type.value_type()
+                    )));
+                }
+            };
+            register_placeholder(param_types, placeholder, param_type)?;
+            CompiledQuery::Range {
+                field: field_id,
+                placeholder,
+            }
+        }
+        TemplateQuery::Boolean(subqueries) => {
+            let subqueries = subqueries
+                .into_iter()
+                .map(|(occur, subquery)| {
+                    let subquery = compile_query(schema, tokenizer_manager, subquery, param_types)?;
+                    Ok((occur, subquery))
+                })
+                .collect::<crate::Result<Vec<_>>>()?;
+            if subqueries
+                .iter()
+                .all(|(_, subquery)| matches!(subquery, CompiledQuery::Static(_)))
+            {
+                // Without placeholder, the whole boolean query is built once.
+                let subqueries = subqueries
+                    .into_iter()
+                    .map(|(occur, subquery)| match subquery {
+                        CompiledQuery::Static(query) => (occur, query),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                CompiledQuery::Static(Box::new(BooleanQuery::new(subqueries)))
+            } else {
+                CompiledQuery::Boolean(subqueries)
+            }
+        }
+    };
+    Ok(compiled)
+}
+
+/// Builds the query of a text, as the query parser would for a term of a text field.
+fn text_query(
+    field: Field,
+    field_name: &str,
+    analyzer: &mut TextAnalyzer,
+    has_positions: bool,
+    text: &str,
+) -> crate::Result<Box<dyn Query>> {
+    let mut terms: Vec<(usize, Term)> = Vec::new();
+    analyzer.token_stream(text).process(&mut |token| {
+        terms.push((token.position, Term::from_field_text(field, &token.text)));
+    });
+    match terms.len() {
+        0 => Ok(Box::new(EmptyQuery)),
+        1 => {
+            let (_, term) = terms.pop().unwrap();
+            Ok(Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)))
+        }
+        _ if has_positions => Ok(Box::new(PhraseQuery::new_with_offset(terms))),
+        _ => Err(TantivyError::InvalidArgument(format!(
+            "{text:?} has several tokens, but field {field_name:?} does not have positions indexed"
+        ))),
+    }
+}
+
+fn bind_query(query: &CompiledQuery, params: &[Param]) -> crate::Result<Box<dyn Query>> {
+    let bound: Box<dyn Query> = match query {
+        CompiledQuery::Static(query) => query.box_clone(),
+        CompiledQuery::Text {
+            field,
+            field_name,
+            analyzer,
+            has_positions,
+            placeholder,
+        } => {
+            let Param::Str(text) = params[*placeholder] else {
+                unreachable!("parameter types are checked before binding")
+            };
+            let mut analyzer = analyzer.clone();
+            return text_query(*field, field_name, &mut analyzer, *has_positions, text);
+        }
+        CompiledQuery::Term { field, placeholder } => {
+            let term = match params[*placeholder] {
+                Param::U64(val) => Term::from_field_u64(*field, val),
+                Param::I64(val) => Term::from_field_i64(*field, val),
+                Param::F64(val) => Term::from_field_f64(*field, val),
+                Param::Bool(val) => Term::from_field_bool(*field, val),
+                _ => unreachable!("parameter types are checked before binding"),
+            };
+            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+        }
+        CompiledQuery::Range { field, placeholder } => {
+            let (lower, upper) = match params[*placeholder] {
+                Param::U64Range(lower, upper) => (
+                    Term::from_field_u64(*field, lower),
+                    Term::from_field_u64(*field, upper),
+                ),
+                Param::I64Range(lower, upper) => (
+                    Term::from_field_i64(*field, lower),
+                    Term::from_field_i64(*field, upper),
+                ),
+                Param::F64Range(lower, upper) => (
+                    Term::from_field_f64(*field, lower),
+                    Term::from_field_f64(*field, upper),
+                ),
+                _ => unreachable!("parameter types are checked before binding"),
+            };
+            Box::new(RangeQuery::new(
+                Bound::Included(lower),
+                Bound::Included(upper),
+            ))
+        }
+        CompiledQuery::Boolean(subqueries) => {
+            let subqueries = subqueries
+                .iter()
+                .map(|(occur, subquery)| Ok((*occur, bind_query(subquery, params)?)))
+                .collect::<crate::Result<Vec<_>>>()?;
+            Box::new(BooleanQuery::new(subqueries))
+        }
+    };
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::{Param, QueryTemplate, TemplateQuery};
+    use crate::collector::TopDocs;
+    use crate::query::{
+        BooleanQuery, Occur, PhraseQuery, Query, QueryParser, RangeQuery, TermQuery,
+    };
+    use crate::schema::{
+        IndexRecordOption, Schema, TextFieldIndexing, TextOptions, INDEXED, STRING, TEXT,
+    };
+    use crate::tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer, Tokenizer};
+    use crate::{DocAddress, Index, IndexWriter, Score, Searcher, Term};
+
+    /// Tokenizer counting the texts it tokenizes.
+    #[derive(Clone)]
+    struct CountingTokenizer {
+        inner: SimpleTokenizer,
+        num_texts: Arc<AtomicUsize>,
+    }
+
+    impl Tokenizer for CountingTokenizer {
+        type TokenStream<'a> = <SimpleTokenizer as Tokenizer>::TokenStream<'a>;
+
+        fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+            self.num_texts.fetch_add(1, Ordering::Relaxed);
+            self.inner.token_stream(text)
+        }
+    }
+
+    fn create_index() -> crate::Result<(Index, Arc<AtomicUsize>)> {
+        let mut schema_builder = Schema::builder();
+        let counting_text = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("counting")
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        let title = schema_builder.add_text_field("title", counting_text);
+        let body = schema_builder.add_text_field("body", TEXT);
+        let tag = schema_builder.add_text_field("tag", STRING);
+        let price = schema_builder.add_u64_field("price", INDEXED);
+        let score = schema_builder.add_f64_field("score", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let num_texts = Arc::new(AtomicUsize::new(0));
+        index.tokenizers().register(
+            "counting",
+            TextAnalyzer::builder(CountingTokenizer {
+                inner: SimpleTokenizer::default(),
+                num_texts: num_texts.clone(),
+            })
+            .filter(LowerCaser)
+            .build(),
+        );
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let docs = [
+            (
+                "The Old Man and the Sea",
+                "a fisherman and a fish",
+                "novel",
+                12,
+                4.5,
+            ),
+            ("Of Mice and Men", "two men and a dream", "novel", 8, 4.0),
+            ("The Sea Wolf", "a captain at sea", "novel", 15, 3.5),
+            ("Sea Shells", "a guide to shells", "guide", 25, 3.0),
+            (
+                "The Old Sea Road",
+                "old roads by the sea",
+                "travel",
+                18,
+                4.2,
+            ),
+        ];
+        for (title_text, body_text, tag_text, price_val, score_val) in docs {
+            index_writer.add_document(doc!(
+                title => title_text,
+                body => body_text,
+                tag => tag_text,
+                price => price_val as u64,
+                score => score_val,
+            ))?;
+        }
+        index_writer.commit()?;
+        Ok((index, num_texts))
+    }
+
+    fn top_docs(searcher: &Searcher, query: &dyn Query) -> Vec<(Score, DocAddress)> {
+        searcher.search(query, &TopDocs::with_limit(10)).unwrap()
+    }
+
+    fn term_template(field: &str, placeholder: usize) -> TemplateQuery {
+        TemplateQuery::Term {
+            field: field.to_string(),
+            placeholder,
+        }
+    }
+
+    fn range_template(field: &str, placeholder: usize) -> TemplateQuery {
+        TemplateQuery::Range {
+            field: field.to_string(),
+            placeholder,
+        }
+    }
+
+    #[test]
+    fn test_query_template_term() -> crate::Result<()> {
+        let (index, _) = create_index()?;
+        let schema = index.schema();
+        let title = schema.get_field("title")?;
+        let price = schema.get_field("price")?;
+        let searcher = index.reader()?.searcher();
+
+        let template =
+            QueryTemplate::compile(&schema, index.tokenizers(), term_template("title", 0))?;
+        for word in ["Sea", "old", "mice", "absent"] {
+            let expected = TermQuery::new(
+                Term::from_field_text(title, &word.to_lowercase()),
+                IndexRecordOption::WithFreqs,
+            );
+            let bound = template.bind(&[Param::Str(word)])?;
+            assert_eq!(top_docs(&searcher, &*bound), top_docs(&searcher, &expected));
+        }
+
+        let template =
+            QueryTemplate::compile(&schema, index.tokenizers(), term_template("price", 0))?;
+        let expected = TermQuery::new(Term::from_field_u64(price, 8), IndexRecordOption::WithFreqs);
+        let bound = template.bind(&[Param::U64(8)])?;
+        assert_eq!(top_docs(&searcher, &*bound).len(), 1);
+        assert_eq!(top_docs(&searcher, &*bound), top_docs(&searcher, &expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_template_range() -> crate::Result<()> {
+        let (index, _) = create_index()?;
+        let schema = index.schema();
+        let price = schema.get_field("price")?;
+        let score = schema.get_field("score")?;
+        let searcher = index.reader()?.searcher();
+
+        let template =
+            QueryTemplate::compile(&schema, index.tokenizers(), range_template("price", 0))?;
+        for (lower, upper) in [(10, 20), (0, 8), (16, 17)] {
+            let expected = RangeQuery::new(
+                Bound::Included(Term::from_field_u64(price, lower)),
+                Bound::Included(Term::from_field_u64(price, upper)),
+            );
+            let bound = template.bind(&[Param::U64Range(lower, upper)])?;
+            assert_eq!(top_docs(&searcher, &*bound), top_docs(&searcher, &expected));
+        }
+
+        let template =
+            QueryTemplate::compile(&schema, index.tokenizers(), range_template("score", 0))?;
+        let expected = RangeQuery::new(
+            Bound::Included(Term::from_field_f64(score, 4.0)),
+            Bound::Included(Term::from_field_f64(score, 4.5)),
+        );
+        let bound = template.bind(&[Param::F64Range(4.0, 4.5)])?;
+        assert_eq!(top_docs(&searcher, &*bound).len(), 3);
+        assert_eq!(top_docs(&searcher, &*bound), top_docs(&searcher, &expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_template_boolean() -> crate::Result<()> {
+        let (index, _) = create_index()?;
+        let schema = index.schema();
+        let title = schema.get_field("title")?;
+        let body = schema.get_field("body")?;
+        let tag = schema.get_field("tag")?;
+        let price = schema.get_field("price")?;
+        let searcher = index.reader()?.searcher();
+
+        let template = QueryTemplate::compile(
+            &schema,
+            index.tokenizers(),
+            TemplateQuery::Boolean(vec![
+                (Occur::Must, term_template("title", 0)),
+                (Occur::Must, range_template("price", 1)),
+                (
+                    Occur::Should,
+                    TemplateQuery::Text {
+                        field: "body".to_string(),
+                        text: "The Sea".to_string(),
+                    },
+                ),
+                (
+                    Occur::MustNot,
+                    TemplateQuery::Boolean(vec![(
+                        Occur::Should,
+                        TemplateQuery::Query(Box::new(TermQuery::new(
+                            Term::from_field_text(tag, "guide"),
+                            IndexRecordOption::Basic,
+                        ))),
+                    )]),
+                ),
+            ]),
+        )?;
+        assert_eq!(template.num_params(), 2);
+
+        for (word, lower, upper) in [("sea", 10, 30), ("Old", 0, 15), ("men", 9, 10)] {
+            let expected = BooleanQuery::new(vec![
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(title, &word.to_lowercase()),
+                        IndexRecordOption::WithFreqs,
+                    )) as Box<dyn Query>,
+                ),
+                (
+                    Occur::Must,
+                    Box::new(RangeQuery::new(
+                        Bound::Included(Term::from_field_u64(price, lower)),
+                        Bound::Included(Term::from_field_u64(price, upper)),
+                    )),
+                ),
+                (
+                    Occur::Should,
+                    Box::new(PhraseQuery::new(vec![
+                        Term::from_field_text(body, "the"),
+                        Term::from_field_text(body, "sea"),
+                    ])),
+                ),
+                (
+                    Occur::MustNot,
+                    Box::new(BooleanQuery::new(vec![(
+                        Occur::Should,
+                        Box::new(TermQuery::new(
+                            Term::from_field_text(tag, "guide"),
+                            IndexRecordOption::Basic,
+                        )) as Box<dyn Query>,
+                    )])),
+                ),
+            ]);
+            let bound = template.bind(&[Param::Str(word), Param::U64Range(lower, upper)])?;
+            assert_eq!(top_docs(&searcher, &*bound), top_docs(&searcher, &expected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_template_validation() -> crate::Result<()> {
+        let (index, _) = create_index()?;
+        let schema = index.schema();
+        let compile =
+            |template: TemplateQuery| QueryTemplate::compile(&schema, index.tokenizers(), template);
+
+        // Placeholder 1 is never used.
+        assert!(compile(TemplateQuery::Boolean(vec![
+            (Occur::Must, term_template("title", 0)),
+            (Occur::Must, range_template("price", 2)),
+        ]))
+        .is_err());
+        // Placeholder 0 is used with two types.
+        assert!(compile(TemplateQuery::Boolean(vec![
+            (Occur::Must, term_template("title", 0)),
+            (Occur::Must, range_template("price", 0)),
+        ]))
+        .is_err());
+        // The same placeholder can be used several times with the same type.
+        assert_eq!(
+            compile(TemplateQuery::Boolean(vec![
+                (Occur::Should, term_template("title", 0)),
+                (Occur::Should, term_template("body", 0)),
+            ]))?
+            .num_params(),
+            1
+        );
+        assert!(compile(range_template("title", 0)).is_err());
+        assert!(compile(term_template("absent", 0)).is_err());
+
+        let template = compile(TemplateQuery::Boolean(vec![
+            (Occur::Must, term_template("title", 0)),
+            (Occur::Must, range_template("price", 1)),
+        ]))?;
+        assert!(template.bind(&[Param::Str("sea")]).is_err());
+        assert!(template
+            .bind(&[Param::Str("sea"), Param::I64Range(0, 10)])
+            .is_err());
+        assert!(template
+            .bind(&[Param::U64(3), Param::U64Range(0, 10)])
+            .is_err());
+        assert!(template
+            .bind(&[Param::Str("sea"), Param::U64Range(0, 10)])
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_template_bind_only_tokenizes_placeholders() -> crate::Result<()> {
+        let (index, num_texts) = create_index()?;
+        let schema = index.schema();
+        let searcher = index.reader()?.searcher();
+        let query_parser = QueryParser::for_index(&index, Vec::new());
+        const NUM_QUERIES: usize = 100;
+
+        let template = QueryTemplate::compile(
+            &schema,
+            index.tokenizers(),
+            TemplateQuery::Boolean(vec![
+                (Occur::Must, term_template("title", 0)),
+                (
+                    Occur::Must,
+                    TemplateQuery::Text {
+                        field: "title".to_string(),
+                        text: "the".to_string(),
+                    },
+                ),
+                (
+                    Occur::Should,
+                    TemplateQuery::Text {
+                        field: "title".to_string(),
+                        text: "old man".to_string(),
+                    },
+                ),
+                (Occur::Must, range_template("price", 1)),
+            ]),
+        )?;
+
+        num_texts.store(0, Ordering::Relaxed);
+        let mut bound_queries = Vec::new();
+        for _ in 0..NUM_QUERIES {
+            bound_queries.push(template.bind(&[Param::Str("Sea"), Param::U64Range(10, 20)])?);
+        }
+        // Only the placeholder is tokenized.
+        assert_eq!(num_texts.swap(0, Ordering::Relaxed), NUM_QUERIES);
+
+        let mut parsed_queries = Vec::new();
+        for _ in 0..NUM_QUERIES {
+            parsed_queries.push(
+                query_parser
+                    .parse_query("+title:Sea +title:the title:\"old man\" +price:[10 TO 20]")?,
+            );
+        }
+        // Parsing tokenizes each of the three texts, every time.
+        assert_eq!(num_texts.load(Ordering::Relaxed), 3 * NUM_QUERIES);
+
+        let expected = top_docs(&searcher, &*parsed_queries[0]);
+        assert_eq!(expected.len(), 3);
+        assert_eq!(top_docs(&searcher, &*bound_queries[0]), expected);
+        Ok(())
+    }
+}
@@ -6,9 +6,14 @@ use common::BitSet;
 
 use super::range_query_fastfield::FastFieldRangeWeight;
 use crate::index::SegmentReader;
+use crate::postings::TermInfo;
+use crate::query::automaton_weight::check_num_expanded_terms;
 use crate::query::explanation::does_not_match;
 use crate::query::range_query::is_type_valid_for_fastfield_range_query;
-use crate::query::{BitSetDocSet, ConstScorer, EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{
+    BitSetDocSet, ConstScorer, CostEstimate, EnableScoring, Explanation, Query, Scorer,
+    TermExpansionCost, Weight,
+};
 use crate::schema::{Field, IndexRecordOption, Term, Type};
 use crate::termdict::{TermDictionary, TermStreamer};
 use crate::{DocId, Score};
@@ -93,6 +98,16 @@ impl RangeQuery {
         self.get_term().typ()
     }
 
+    /// The lower bound of the range.
+    pub fn lower_bound(&self) -> &Bound<Term> {
+        &self.bounds.lower_bound
+    }
+
+    /// The upper bound of the range.
+    pub fn upper_bound(&self) -> &Bound<Term> {
+        &self.bounds.upper_bound
+    }
+
     pub(crate) fn get_term(&self) -> &Term {
         self.bounds
             .get_inner()
@@ -169,6 +184,10 @@ pub struct InvertedIndexRangeWeight {
     lower_bound: Bound<Vec<u8>>,
     upper_bound: Bound<Vec<u8>>,
     limit: Option<u64>,
+    max_expanded_terms: Option<usize>,
+    /// Set if the range is the range of the terms starting with a prefix, whose expansion is
+    /// then cached by the segment readers.
+    prefix: Option<Vec<u8>>,
 }
 
 impl InvertedIndexRangeWeight {
@@ -187,9 +206,30 @@ impl InvertedIndexRangeWeight {
             lower_bound: map_bound(lower_bound, verify_and_unwrap_term),
             upper_bound: map_bound(upper_bound, verify_and_unwrap_term),
             limit,
+            max_expanded_terms: None,
+            prefix: None,
         }
     }
 
+    /// Marks the range as the range of the terms starting with `prefix`, so that its expansion
+    /// goes through the [prefix expansion
+    /// cache](crate::SearcherContext::with_prefix_expansion_budget) of the segments.
+    pub(crate) fn with_prefix(mut self, prefix: &Term) -> Self {
+        self.prefix = Some(prefix.serialized_value_bytes().to_vec());
+        self
+    }
+
+    /// Caps the number of terms of the range in a segment.
+    ///
+    /// Unlike the limit, which silently ignores the terms beyond it, building the scorer of a
+    /// segment fails with
+    /// [`TantivyError::TooManyExpandedTerms`](crate::TantivyError::TooManyExpandedTerms) beyond
+    /// this cap.
+    pub fn with_max_expanded_terms(mut self, max_expanded_terms: Option<usize>) -> Self {
+        self.max_expanded_terms = max_expanded_terms;
+        self
+    }
+
     fn term_range<'a>(&self, term_dict: &'a TermDictionary) -> io::Result<TermStreamer<'a>> {
         use std::ops::Bound::*;
         let mut term_stream_builder = term_dict.range();
@@ -217,17 +257,20 @@ impl Weight for InvertedIndexRangeWeight {
         let mut doc_bitset = BitSet::with_max_value(max_doc);
 
         let inverted_index = reader.inverted_index(self.field)?;
-        let term_dict = inverted_index.terms();
-        let mut term_range = self.term_range(term_dict)?;
         let mut processed_count = 0;
-        while term_range.advance() {
+        let mut add_term_docs = |term_info: &TermInfo| -> crate::Result<bool> {
             if let Some(limit) = self.limit {
                 if limit <= processed_count {
-                    break;
+                    return Ok(false);
                 }
             }
             processed_count += 1;
-            let term_info = term_range.value();
+            check_num_expanded_terms(
+                reader,
+                self.field,
+                processed_count as usize,
+                self.max_expanded_terms,
+            )?;
             let mut block_segment_postings = inverted_index
                 .read_block_postings_from_terminfo(term_info, IndexRecordOption::Basic)?;
             loop {
@@ -240,6 +283,28 @@ impl Weight for InvertedIndexRangeWeight {
                 }
                 block_segment_postings.advance();
             }
+            Ok(true)
+        };
+        if let Some(prefix) = self.prefix.as_deref() {
+            let limit = self.limit.map(|limit| limit as usize).unwrap_or(usize::MAX);
+            let expansion = reader.prefix_expansion_cache().expand(
+                &inverted_index,
+                self.field,
+                prefix,
+                limit,
+            )?;
+            for (_, term_info) in expansion.terms() {
+                if !add_term_docs(term_info)? {
+                    break;
+                }
+            }
+        } else {
+            let mut term_range = self.term_range(inverted_index.terms())?;
+            while term_range.advance() {
+                if !add_term_docs(term_range.value())? {
+                    break;
+                }
+            }
         }
         let doc_bitset = BitSetDocSet::from(doc_bitset);
         Ok(Box::new(ConstScorer::new(doc_bitset, boost)))
@@ -252,6 +317,23 @@ impl Weight for InvertedIndexRangeWeight {
         }
         Ok(Explanation::new("RangeQuery", 1.0))
     }
+
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        let inverted_index = reader.inverted_index(self.field)?;
+        let mut term_range = self.term_range(inverted_index.terms())?;
+        let mut cost = TermExpansionCost::new(reader.max_doc());
+        let mut num_terms = 0;
+        while term_range.advance() {
+            if self.limit.is_some_and(|limit| limit <= num_terms) {
+                break;
+            }
+            num_terms += 1;
+            if !cost.add_term(term_range.value().doc_freq) {
+                break;
+            }
+        }
+        Ok(cost.finish())
+    }
 }
 
 #[cfg(test)]
@@ -13,7 +13,8 @@ use common::bounds::{BoundsRange, TransformBound};
 
 use super::fast_field_range_doc_set::RangeDocSet;
 use crate::query::{
-    AllScorer, ConstScorer, EmptyScorer, EnableScoring, Explanation, Query, Scorer, Weight,
+    AllScorer, ConstScorer, CostBounds, CostEstimate, EmptyScorer, EnableScoring, Explanation,
+    Query, Scorer, Weight,
 };
 use crate::schema::{Type, ValueBytes};
 use crate::{DocId, DocSet, Score, SegmentReader, TantivyError, Term};
@@ -93,7 +94,7 @@ impl Weight for FastFieldRangeWeight {
             match typ {
                 Type::Str => {
                     let Some(str_dict_column): Option<StrColumn> =
-                        reader.fast_fields().str(&field_name)?
+                        reader.try_fast_fields()?.str(&field_name)?
                     else {
                         return Ok(Box::new(EmptyScorer));
                     };
@@ -103,7 +104,7 @@ impl Weight for FastFieldRangeWeight {
                     // Get term ids for terms
                     let (lower_bound, upper_bound) =
                         dict.term_bounds_to_ord(bounds.lower_bound, bounds.upper_bound)?;
-                    let fast_field_reader = reader.fast_fields();
+                    let fast_field_reader = reader.try_fast_fields()?;
                     let Some((column, _col_type)) = fast_field_reader
                         .u64_lenient_for_type(Some(&[ColumnType::Str]), &field_name)?
                     else {
@@ -115,7 +116,7 @@ impl Weight for FastFieldRangeWeight {
                     search_on_json_numerical_field(reader, &field_name, typ, bounds, boost)
                 }
                 Type::Date => {
-                    let fast_field_reader = reader.fast_fields();
+                    let fast_field_reader = reader.try_fast_fields()?;
                     let Some((column, _col_type)) = fast_field_reader
                         .u64_lenient_for_type(Some(&[ColumnType::DateTime]), &field_name)?
                     else {
@@ -144,7 +145,7 @@ impl Weight for FastFieldRangeWeight {
             let bounds: BoundsRange<Ipv6Addr> = self.bounds.map_bound_res(parse_ip_from_bytes)?;
 
             let Some(ip_addr_column): Option<Column<Ipv6Addr>> =
-                reader.fast_fields().column_opt(&field_name)?
+                reader.try_fast_fields()?.column_opt(&field_name)?
             else {
                 return Ok(Box::new(EmptyScorer));
             };
@@ -157,7 +158,8 @@ impl Weight for FastFieldRangeWeight {
             let docset = RangeDocSet::new(value_range, ip_addr_column);
             Ok(Box::new(ConstScorer::new(docset, boost)))
         } else if field_type.is_str() {
-            let Some(str_dict_column): Option<StrColumn> = reader.fast_fields().str(&field_name)?
+            let Some(str_dict_column): Option<StrColumn> =
+                reader.try_fast_fields()?.str(&field_name)?
             else {
                 return Ok(Box::new(EmptyScorer));
             };
@@ -167,7 +169,7 @@ impl Weight for FastFieldRangeWeight {
             // Get term ids for terms
             let (lower_bound, upper_bound) =
                 dict.term_bounds_to_ord(bounds.lower_bound, bounds.upper_bound)?;
-            let fast_field_reader = reader.fast_fields();
+            let fast_field_reader = reader.try_fast_fields()?;
             let Some((column, _col_type)) =
                 fast_field_reader.u64_lenient_for_type(None, &field_name)?
             else {
@@ -181,35 +183,11 @@ impl Weight for FastFieldRangeWeight {
                 field_type
             );
 
-            let bounds = self.bounds.map_bound_res(|term| {
-                let value = term.value();
-                let val = if let Some(val) = value.as_u64() {
-                    val
-                } else if let Some(val) = value.as_i64() {
-                    val.to_u64()
-                } else if let Some(val) = value.as_f64() {
-                    val.to_u64()
-                } else if let Some(val) = value.as_date() {
-                    val.to_u64()
-                } else {
-                    return Err(TantivyError::InvalidArgument(format!(
-                        "Expected term with u64, i64, f64 or date, but got {:?}",
-                        term
-                    )));
-                };
-                Ok(val)
-            })?;
-
-            let fast_field_reader = reader.fast_fields();
-            let Some((column, _col_type)) = fast_field_reader.u64_lenient_for_type(
-                Some(&[
-                    ColumnType::U64,
-                    ColumnType::I64,
-                    ColumnType::F64,
-                    ColumnType::DateTime,
-                ]),
-                &field_name,
-            )?
+            let bounds = numerical_bounds_to_u64(&self.bounds)?;
+
+            let fast_field_reader = reader.try_fast_fields()?;
+            let Some((column, _col_type)) = fast_field_reader
+                .u64_lenient_for_type(Some(&NUMERICAL_COLUMN_TYPES), &field_name)?
             else {
                 return Ok(Box::new(EmptyScorer));
             };
@@ -232,6 +210,86 @@ impl Weight for FastFieldRangeWeight {
 
         Ok(explanation)
     }
+
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        if self.bounds.is_unbounded() {
+            return Ok(CostEstimate::Bounded(CostBounds::all_docs(
+                reader.max_doc(),
+            )));
+        }
+        let term = self
+            .bounds
+            .get_inner()
+            .expect("At least one bound must be set");
+        let field_type = reader.schema().get_field_entry(term.field()).field_type();
+        let field_name = term.get_full_path(reader.schema());
+        let fast_field_reader = reader.try_fast_fields()?;
+        // On numerical fields, the range is checked against the min and max values of the
+        // column.
+        if !field_type.is_json() && maps_to_u64_fastfield(field_type.value_type()) {
+            if let (Ok(bounds), Some((column, _col_type))) = (
+                numerical_bounds_to_u64(&self.bounds),
+                fast_field_reader
+                    .u64_lenient_for_type(Some(&NUMERICAL_COLUMN_TYPES), &field_name)?,
+            ) {
+                let value_range = bound_to_value_range(
+                    &bounds.lower_bound,
+                    &bounds.upper_bound,
+                    column.min_value(),
+                    column.max_value(),
+                );
+                let is_disjoint = value_range.is_none_or(|value_range| {
+                    value_range.is_empty()
+                        || *value_range.start() > column.max_value()
+                        || *value_range.end() < column.min_value()
+                });
+                if is_disjoint {
+                    return Ok(CostEstimate::Bounded(CostBounds::default()));
+                }
+            }
+        }
+        // The values of the columns of the field are scanned.
+        let mut num_values = 0;
+        for handle in fast_field_reader.dynamic_column_handles(&field_name)? {
+            num_values += u64::from(handle.open()?.num_values());
+        }
+        Ok(CostEstimate::Bounded(CostBounds {
+            matching_docs: num_values.min(u64::from(reader.max_doc())),
+            docs_examined: num_values,
+            ..CostBounds::default()
+        }))
+    }
+}
+
+/// Column types of the fast fields of the numerical and date fields.
+const NUMERICAL_COLUMN_TYPES: [ColumnType; 4] = [
+    ColumnType::U64,
+    ColumnType::I64,
+    ColumnType::F64,
+    ColumnType::DateTime,
+];
+
+/// Maps the bounds of a range over a numerical or date field to the `u64` values of its fast
+/// field.
+fn numerical_bounds_to_u64(bounds: &BoundsRange<Term>) -> crate::Result<BoundsRange<u64>> {
+    bounds.map_bound_res(|term| {
+        let value = term.value();
+        let val = if let Some(val) = value.as_u64() {
+            val
+        } else if let Some(val) = value.as_i64() {
+            val.to_u64()
+        } else if let Some(val) = value.as_f64() {
+            val.to_u64()
+        } else if let Some(val) = value.as_date() {
+            val.to_u64()
+        } else {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Expected term with u64, i64, f64 or date, but got {:?}",
+                term
+            )));
+        };
+        Ok(val)
+    })
 }
 
 /// On numerical fields the column type may not match the user provided one.
@@ -248,7 +306,7 @@ fn search_on_json_numerical_field(
     // have to check for all numeric types (only one exists)
     let allowed_column_types: Option<&[ColumnType]> =
         Some(&[ColumnType::F64, ColumnType::I64, ColumnType::U64]);
-    let fast_field_reader = reader.fast_fields();
+    let fast_field_reader = reader.try_fast_fields()?;
     let Some((column, col_type)) =
         fast_field_reader.u64_lenient_for_type(allowed_column_types, field_name)?
     else {
@@ -56,7 +56,10 @@ use crate::schema::Field;
 #[derive(Debug, Clone)]
 pub struct RegexQuery {
     regex: Arc<Regex>,
+    /// The pattern of the regex, if the query was built from a pattern.
+    pattern: Option<String>,
     field: Field,
+    max_expanded_terms: Option<usize>,
 }
 
 impl RegexQuery {
@@ -64,19 +67,35 @@ impl RegexQuery {
     pub fn from_pattern(regex_pattern: &str, field: Field) -> crate::Result<Self> {
         let regex = Regex::new(regex_pattern)
             .map_err(|err| TantivyError::InvalidArgument(format!("RegexQueryError: {err}")))?;
-        Ok(RegexQuery::from_regex(regex, field))
+        Ok(RegexQuery {
+            pattern: Some(regex_pattern.to_string()),
+            ..RegexQuery::from_regex(regex, field)
+        })
     }
 
     /// Creates a new RegexQuery from a fully built Regex
     pub fn from_regex<T: Into<Arc<Regex>>>(regex: T, field: Field) -> Self {
         RegexQuery {
             regex: regex.into(),
+            pattern: None,
             field,
+            max_expanded_terms: None,
         }
     }
 
+    /// The field the terms are matched in.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// The pattern of the regex, if the query was built with [`RegexQuery::from_pattern()`].
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
     fn specialized_weight(&self) -> AutomatonWeight<Regex> {
         AutomatonWeight::new(self.field, self.regex.clone())
+            .with_max_expanded_terms(self.max_expanded_terms)
     }
 }
 
@@ -84,6 +103,10 @@ impl Query for RegexQuery {
     fn weight(&self, _enabled_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
         Ok(Box::new(self.specialized_weight()))
     }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.max_expanded_terms = Some(max_expanded_terms);
+    }
 }
 
 #[cfg(test)]
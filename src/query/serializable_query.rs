@@ -0,0 +1,634 @@
+use std::ops::Bound;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, EmptyQuery, ExistsQuery, FuzzyTermQuery,
+    Occur, PhraseQuery, Query, RangeQuery, RegexQuery, TermQuery,
+};
+use crate::schema::{Field, IndexRecordOption, Schema};
+use crate::{Score, TantivyError, Term};
+
+/// Version of the format written by [`SerializableQuery::to_bytes()`].
+const SERIALIZABLE_QUERY_VERSION: u8 = 1;
+
+/// A term of a [`SerializableQuery`], identified by the name of its field.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableTerm {
+    /// Name of the field of the term.
+    pub field: String,
+    /// Type and value of the term, encoded in base64.
+    value: String,
+}
+
+impl SerializableTerm {
+    /// Creates the serializable version of `term`, whose field is in `schema`.
+    pub fn from_term(term: &Term, schema: &Schema) -> SerializableTerm {
+        SerializableTerm {
+            field: schema.get_field_name(term.field()).to_string(),
+            value: BASE64.encode(term.value().as_serialized()),
+        }
+    }
+
+    /// Returns the term, after checking that its field is in `schema` and has the type of the
+    /// term.
+    pub fn to_term(&self, schema: &Schema) -> crate::Result<Term> {
+        let field = schema.get_field(&self.field)?;
+        let value = BASE64.decode(&self.value).map_err(|err| {
+            TantivyError::InvalidArgument(format!(
+                "Invalid value of a term of field {:?}: {err}",
+                self.field
+            ))
+        })?;
+        if value.is_empty() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Empty value of a term of field {:?}",
+                self.field
+            )));
+        }
+        let field_type = schema.get_field_entry(field).field_type().value_type();
+        let mut term_bytes = field.field_id().to_be_bytes().to_vec();
+        term_bytes.extend_from_slice(&value);
+        let term = Term::wrap(term_bytes);
+        if term.typ() != field_type {
+            return Err(TantivyError::SchemaError(format!(
+                "The term of field {:?} has the type {:?}, but the field has the type {:?}",
+                self.field,
+                term.typ(),
+                field_type
+            )));
+        }
+        Ok(term)
+    }
+}
+
+/// A query of one of the built-in types, which can be serialized, e.g. to be built on a node
+/// and run on another one.
+///
+/// The fields are identified by their name, and checked against the schema of the index
+/// running the query by [`SerializableQuery::into_query()`].
+///
+/// The serialized format is forward compatible: a query type unknown to the version of tantivy
+/// reading the query deserializes as [`SerializableQuery::Unknown`], and fails with
+/// [`TantivyError::UnsupportedQuery`] when converted into a query. The unknown attributes of
+/// the known query types are ignored.
+///
+/// The limits set with [`Query::set_max_expanded_terms()`] are not serialized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SerializableQuery {
+    /// See [`AllQuery`].
+    All,
+    /// See [`EmptyQuery`].
+    Empty,
+    /// See [`TermQuery`].
+    Term {
+        /// The term of the query.
+        term: SerializableTerm,
+        /// The information read from the postings of the term.
+        index_record_option: IndexRecordOption,
+    },
+    /// See [`PhraseQuery`].
+    Phrase {
+        /// The terms of the phrase, with their offsets.
+        terms: Vec<(usize, SerializableTerm)>,
+        /// Slop allowed for the phrase.
+        slop: u32,
+    },
+    /// See [`RangeQuery`].
+    Range {
+        /// The lower bound of the range.
+        lower_bound: Bound<SerializableTerm>,
+        /// The upper bound of the range.
+        upper_bound: Bound<SerializableTerm>,
+    },
+    /// See [`BooleanQuery`].
+    Boolean {
+        /// The subqueries, with their occurrence.
+        clauses: Vec<(Occur, SerializableQuery)>,
+        /// The minimum number of should clauses a document must match.
+        minimum_number_should_match: usize,
+    },
+    /// See [`BoostQuery`].
+    Boost {
+        /// The boosted query.
+        query: Box<SerializableQuery>,
+        /// The factor applied to the scores.
+        boost: Score,
+    },
+    /// See [`ConstScoreQuery`].
+    ConstScore {
+        /// The query whose matches get the score.
+        query: Box<SerializableQuery>,
+        /// The score of the matches.
+        score: Score,
+    },
+    /// See [`FuzzyTermQuery`].
+    Fuzzy {
+        /// The term the matched terms are close to.
+        term: SerializableTerm,
+        /// The maximum edit distance.
+        distance: u8,
+        /// Whether a transposition costs 1 instead of 2.
+        transposition_cost_one: bool,
+        /// Whether the terms starting with a prefix close to the term are matched.
+        prefix: bool,
+    },
+    /// See [`RegexQuery`].
+    Regex {
+        /// Name of the field.
+        field: String,
+        /// Pattern of the regex.
+        pattern: String,
+    },
+    /// See [`ExistsQuery`].
+    Exists {
+        /// Name of the field.
+        field: String,
+        /// Whether the JSON subpaths of the field are checked too.
+        json_subpaths: bool,
+    },
+    /// A query type unknown to this version of tantivy.
+    #[serde(other)]
+    Unknown,
+}
+
+impl SerializableQuery {
+    /// Creates the serializable version of `query`, whose fields are in `schema`.
+    ///
+    /// Returns [`TantivyError::UnsupportedQuery`] if the query, or one of its subqueries, is not
+    /// of one of the built-in types of [`SerializableQuery`], or is a [`RegexQuery`] built from
+    /// a compiled regex instead of a pattern.
+    pub fn from_query(query: &dyn Query, schema: &Schema) -> crate::Result<SerializableQuery> {
+        let term = |term: &Term| SerializableTerm::from_term(term, schema);
+        if query.is::<AllQuery>() {
+            return Ok(SerializableQuery::All);
+        }
+        if query.is::<EmptyQuery>() {
+            return Ok(SerializableQuery::Empty);
+        }
+        if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+            return Ok(SerializableQuery::Term {
+                term: term(term_query.term()),
+                index_record_option: term_query.index_record_option(),
+            });
+        }
+        if let Some(phrase_query) = query.downcast_ref::<PhraseQuery>() {
+            return Ok(SerializableQuery::Phrase {
+                terms: phrase_query
+                    .phrase_terms_with_offsets()
+                    .iter()
+                    .map(|(offset, phrase_term)| (*offset, term(phrase_term)))
+                    .collect(),
+                slop: phrase_query.slop(),
+            });
+        }
+        if let Some(range_query) = query.downcast_ref::<RangeQuery>() {
+            return Ok(SerializableQuery::Range {
+                lower_bound: range_query.lower_bound().as_ref().map(term),
+                upper_bound: range_query.upper_bound().as_ref().map(term),
+            });
+        }
+        if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+            return Ok(SerializableQuery::Boolean {
+                clauses: boolean_query
+                    .clauses()
+                    .iter()
+                    .map(|(occur, subquery)| {
+                        Ok((
+                            *occur,
+                            SerializableQuery::from_query(subquery.as_ref(), schema)?,
+                        ))
+                    })
+                    .collect::<crate::Result<_>>()?,
+                minimum_number_should_match: boolean_query.get_minimum_number_should_match(),
+            });
+        }
+        if let Some(boost_query) = query.downcast_ref::<BoostQuery>() {
+            return Ok(SerializableQuery::Boost {
+                query: Box::new(SerializableQuery::from_query(boost_query.query(), schema)?),
+                boost: boost_query.boost(),
+            });
+        }
+        if let Some(const_score_query) = query.downcast_ref::<ConstScoreQuery>() {
+            return Ok(SerializableQuery::ConstScore {
+                query: Box::new(SerializableQuery::from_query(
+                    const_score_query.query(),
+                    schema,
+                )?),
+                score: const_score_query.score(),
+            });
+        }
+        if let Some(fuzzy_query) = query.downcast_ref::<FuzzyTermQuery>() {
+            return Ok(SerializableQuery::Fuzzy {
+                term: term(fuzzy_query.term()),
+                distance: fuzzy_query.distance(),
+                transposition_cost_one: fuzzy_query.transposition_cost_one(),
+                prefix: fuzzy_query.is_prefix(),
+            });
+        }
+        if let Some(regex_query) = query.downcast_ref::<RegexQuery>() {
+            if let Some(pattern) = regex_query.pattern() {
+                return Ok(SerializableQuery::Regex {
+                    field: schema.get_field_name(regex_query.field()).to_string(),
+                    pattern: pattern.to_string(),
+                });
+            }
+        }
+        if let Some(exists_query) = query.downcast_ref::<ExistsQuery>() {
+            return Ok(SerializableQuery::Exists {
+                field: exists_query.field_name().to_string(),
+                json_subpaths: exists_query.json_subpaths(),
+            });
+        }
+        Err(TantivyError::UnsupportedQuery(format!(
+            "{query:?} cannot be serialized"
+        )))
+    }
+
+    /// Builds the query, after checking that its fields are in `schema` and have the types of
+    /// its terms.
+    ///
+    /// Returns [`TantivyError::UnsupportedQuery`] if the query, or one of its subqueries, is
+    /// [`SerializableQuery::Unknown`].
+    pub fn into_query(self, schema: &Schema) -> crate::Result<Box<dyn Query>> {
+        let query: Box<dyn Query> = match self {
+            SerializableQuery::All => Box::new(AllQuery),
+            SerializableQuery::Empty => Box::new(EmptyQuery),
+            SerializableQuery::Term {
+                term,
+                index_record_option,
+            } => Box::new(TermQuery::new(term.to_term(schema)?, index_record_option)),
+            SerializableQuery::Phrase { terms, slop } => {
+                let terms = terms
+                    .into_iter()
+                    .map(|(offset, term)| Ok((offset, term.to_term(schema)?)))
+                    .collect::<crate::Result<Vec<_>>>()?;
+                if terms.len() < 2 {
+                    return Err(TantivyError::InvalidArgument(
+                        "A phrase query requires at least two terms".to_string(),
+                    ));
+                }
+                check_same_field(terms.iter().map(|(_, term)| term))?;
+                Box::new(PhraseQuery::new_with_offset_and_slop(terms, slop))
+            }
+            SerializableQuery::Range {
+                lower_bound,
+                upper_bound,
+            } => {
+                let to_term = |bound: Bound<SerializableTerm>| match bound {
+                    Bound::Included(term) => term.to_term(schema).map(Bound::Included),
+                    Bound::Excluded(term) => term.to_term(schema).map(Bound::Excluded),
+                    Bound::Unbounded => Ok(Bound::Unbounded),
+                };
+                let lower_bound = to_term(lower_bound)?;
+                let upper_bound = to_term(upper_bound)?;
+                let bound_terms: Vec<&Term> = [&lower_bound, &upper_bound]
+                    .into_iter()
+                    .filter_map(|bound| match bound {
+                        Bound::Included(term) | Bound::Excluded(term) => Some(term),
+                        Bound::Unbounded => None,
+                    })
+                    .collect();
+                if bound_terms.is_empty() {
+                    return Err(TantivyError::InvalidArgument(
+                        "A range query requires at least one bound".to_string(),
+                    ));
+                }
+                check_same_field(bound_terms.into_iter())?;
+                Box::new(RangeQuery::new(lower_bound, upper_bound))
+            }
+            SerializableQuery::Boolean {
+                clauses,
+                minimum_number_should_match,
+            } => {
+                let clauses = clauses
+                    .into_iter()
+                    .map(|(occur, subquery)| Ok((occur, subquery.into_query(schema)?)))
+                    .collect::<crate::Result<Vec<_>>>()?;
+                Box::new(BooleanQuery::with_minimum_required_clauses(
+                    clauses,
+                    minimum_number_should_match,
+                ))
+            }
+            SerializableQuery::Boost { query, boost } => {
+                Box::new(BoostQuery::new(query.into_query(schema)?, boost))
+            }
+            SerializableQuery::ConstScore { query, score } => {
+                Box::new(ConstScoreQuery::new(query.into_query(schema)?, score))
+            }
+            SerializableQuery::Fuzzy {
+                term,
+                distance,
+                transposition_cost_one,
+                prefix,
+            } => {
+                let term = term.to_term(schema)?;
+                if prefix {
+                    Box::new(FuzzyTermQuery::new_prefix(
+                        term,
+                        distance,
+                        transposition_cost_one,
+                    ))
+                } else {
+                    Box::new(FuzzyTermQuery::new(term, distance, transposition_cost_one))
+                }
+            }
+            SerializableQuery::Regex { field, pattern } => Box::new(RegexQuery::from_pattern(
+                &pattern,
+                schema.get_field(&field)?,
+            )?),
+            SerializableQuery::Exists {
+                field,
+                json_subpaths,
+            } => {
+                schema.get_field(&field)?;
+                Box::new(ExistsQuery::new(field, json_subpaths))
+            }
+            SerializableQuery::Unknown => {
+                return Err(TantivyError::UnsupportedQuery(
+                    "the query type is unknown to this version of tantivy".to_string(),
+                ));
+            }
+        };
+        Ok(query)
+    }
+
+    /// Serializes the query, prefixed with the version of the format.
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = vec![SERIALIZABLE_QUERY_VERSION];
+        serde_json::to_writer(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Deserializes a query serialized by [`SerializableQuery::to_bytes()`].
+    ///
+    /// Returns an error if the query was serialized with a newer version of the format.
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<SerializableQuery> {
+        let Some((&version, json_bytes)) = bytes.split_first() else {
+            return Err(TantivyError::InvalidArgument(
+                "The serialized query is empty".to_string(),
+            ));
+        };
+        if version != SERIALIZABLE_QUERY_VERSION {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Unsupported version {version} of the serialized query, the version \
+                 {SERIALIZABLE_QUERY_VERSION} is expected"
+            )));
+        }
+        serde_json::from_slice(json_bytes).map_err(|err| {
+            TantivyError::InvalidArgument(format!("Invalid serialized query: {err}"))
+        })
+    }
+}
+
+/// Checks that the terms of a query are all in the same field.
+fn check_same_field<'a>(mut terms: impl Iterator<Item = &'a Term>) -> crate::Result<()> {
+    let Some(field): Option<Field> = terms.next().map(Term::field) else {
+        return Ok(());
+    };
+    if terms.any(|term| term.field() != field) {
+        return Err(TantivyError::InvalidArgument(
+            "The terms of a phrase or range query must be in the same field".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::SerializableQuery;
+    use crate::collector::TopDocs;
+    use crate::query::{
+        AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, EmptyQuery, ExistsQuery,
+        FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery, TermQuery, TermSetQuery,
+    };
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STRING, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, Score, TantivyError, Term};
+
+    const WORDS: [&str; 8] = [
+        "apple", "apply", "banana", "cherry", "hello", "help", "lemon", "grape",
+    ];
+
+    /// The schema of the data node, and the schema of the node building the queries, with the
+    /// same fields in another order.
+    fn schemas() -> (Schema, Schema) {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("text", TEXT);
+        schema_builder.add_u64_field("num", FAST | INDEXED);
+        schema_builder.add_text_field("tag", STRING);
+        let data_schema = schema_builder.build();
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("tag", STRING);
+        schema_builder.add_u64_field("num", FAST | INDEXED);
+        schema_builder.add_text_field("text", TEXT);
+        (data_schema, schema_builder.build())
+    }
+
+    fn create_index(schema: Schema) -> crate::Result<Index> {
+        let text = schema.get_field("text")?;
+        let num = schema.get_field("num")?;
+        let tag = schema.get_field("tag")?;
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..200usize {
+            let text_value = (0..1 + i % 5)
+                .map(|j| WORDS[(i * 7 + j * 3) % WORDS.len()])
+                .collect::<Vec<_>>()
+                .join(" ");
+            let tag_value = if i % 3 == 0 { "x" } else { "y" };
+            if i % 11 == 0 {
+                index_writer.add_document(doc!(text => text_value, tag => tag_value))?;
+            } else {
+                index_writer.add_document(
+                    doc!(text => text_value, num => (i % 10) as u64, tag => tag_value),
+                )?;
+            }
+            if i % 70 == 69 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    /// Builds a deep boolean query tree on the fields of `schema`.
+    fn query_tree(schema: &Schema) -> Box<dyn Query> {
+        let text = schema.get_field("text").unwrap();
+        let num = schema.get_field("num").unwrap();
+        let tag = schema.get_field("tag").unwrap();
+        let term_query = |term: Term| -> Box<dyn Query> {
+            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+        };
+        let words = BooleanQuery::new(vec![
+            (
+                Occur::Should,
+                term_query(Term::from_field_text(text, "apple")),
+            ),
+            (
+                Occur::Should,
+                Box::new(PhraseQuery::new(vec![
+                    Term::from_field_text(text, "lemon"),
+                    Term::from_field_text(text, "apple"),
+                ])),
+            ),
+            (
+                Occur::Should,
+                Box::new(FuzzyTermQuery::new(
+                    Term::from_field_text(text, "helo"),
+                    1,
+                    true,
+                )),
+            ),
+        ]);
+        let nested = BooleanQuery::with_minimum_required_clauses(
+            vec![
+                (Occur::Should, Box::new(words)),
+                (
+                    Occur::Should,
+                    Box::new(BoostQuery::new(
+                        Box::new(RegexQuery::from_pattern("ba.*", text).unwrap()),
+                        2.5,
+                    )),
+                ),
+                (
+                    Occur::Should,
+                    Box::new(ConstScoreQuery::new(
+                        Box::new(ExistsQuery::new("num".to_string(), false)),
+                        0.5,
+                    )),
+                ),
+                (Occur::MustNot, Box::new(EmptyQuery)),
+            ],
+            2,
+        );
+        Box::new(BooleanQuery::new(vec![
+            (Occur::Must, Box::new(nested)),
+            (
+                Occur::Must,
+                Box::new(RangeQuery::new(
+                    Bound::Included(Term::from_field_u64(num, 2)),
+                    Bound::Excluded(Term::from_field_u64(num, 9)),
+                )),
+            ),
+            (Occur::MustNot, term_query(Term::from_field_text(tag, "x"))),
+            (Occur::Should, Box::new(AllQuery)),
+        ]))
+    }
+
+    fn top_docs(index: &Index, query: &dyn Query) -> crate::Result<Vec<(Score, DocAddress)>> {
+        let searcher = index.reader()?.searcher();
+        searcher.search(query, &TopDocs::with_limit(200))
+    }
+
+    #[test]
+    fn test_serializable_query_round_trip() -> crate::Result<()> {
+        let (data_schema, api_schema) = schemas();
+        let index = create_index(data_schema.clone())?;
+
+        // The query is built and serialized with the schema of the other node.
+        let api_query = query_tree(&api_schema);
+        let bytes = SerializableQuery::from_query(api_query.as_ref(), &api_schema)?.to_bytes()?;
+
+        let serializable_query = SerializableQuery::from_bytes(&bytes)?;
+        let query = serializable_query.clone().into_query(&data_schema)?;
+        let expected_top_docs = top_docs(&index, query_tree(&data_schema).as_ref())?;
+        assert!(!expected_top_docs.is_empty());
+        assert!(expected_top_docs.len() < 200);
+        assert_eq!(top_docs(&index, query.as_ref())?, expected_top_docs);
+        assert_eq!(
+            SerializableQuery::from_query(query.as_ref(), &data_schema)?,
+            serializable_query
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_query_unknown_variant() -> crate::Result<()> {
+        let (data_schema, _) = schemas();
+        let mut bytes = vec![1u8];
+        bytes.extend_from_slice(
+            br#"{
+                "type": "boolean",
+                "clauses": [
+                    ["must", {"type": "geo_distance", "field": "location", "distance": 10.0}],
+                    ["should", {"type": "all", "added_attribute": true}]
+                ],
+                "minimum_number_should_match": 0
+            }"#,
+        );
+        let serializable_query = SerializableQuery::from_bytes(&bytes)?;
+        assert_eq!(
+            serializable_query,
+            SerializableQuery::Boolean {
+                clauses: vec![
+                    (Occur::Must, SerializableQuery::Unknown),
+                    (Occur::Should, SerializableQuery::All),
+                ],
+                minimum_number_should_match: 0,
+            }
+        );
+        let err = serializable_query.into_query(&data_schema).unwrap_err();
+        assert!(matches!(err, TantivyError::UnsupportedQuery(_)));
+        assert_eq!(err.error_code(), "unsupported_query");
+
+        // A newer version of the format is rejected.
+        bytes[0] = 2;
+        assert!(matches!(
+            SerializableQuery::from_bytes(&bytes),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_query_validation() -> crate::Result<()> {
+        let (data_schema, api_schema) = schemas();
+        let tag = api_schema.get_field("tag")?;
+        let text = api_schema.get_field("text")?;
+
+        // The queries without a serializable version are rejected.
+        let term_set = TermSetQuery::new(vec![Term::from_field_text(tag, "x")]);
+        assert!(matches!(
+            SerializableQuery::from_query(&term_set, &api_schema),
+            Err(TantivyError::UnsupportedQuery(_))
+        ));
+        let regex = RegexQuery::from_regex(tantivy_fst::Regex::new("a.*").unwrap(), text);
+        let boolean = BooleanQuery::new(vec![(Occur::Must, Box::new(regex) as Box<dyn Query>)]);
+        assert!(matches!(
+            SerializableQuery::from_query(&boolean, &api_schema),
+            Err(TantivyError::UnsupportedQuery(_))
+        ));
+
+        // The fields are checked against the schema of the data node.
+        let term_query = TermQuery::new(Term::from_field_text(tag, "x"), IndexRecordOption::Basic);
+        let serializable_query = SerializableQuery::from_query(&term_query, &api_schema)?;
+        let mut other_schema_builder = Schema::builder();
+        other_schema_builder.add_u64_field("tag", INDEXED);
+        let other_schema = other_schema_builder.build();
+        assert!(matches!(
+            serializable_query.clone().into_query(&other_schema),
+            Err(TantivyError::SchemaError(_))
+        ));
+        assert!(matches!(
+            serializable_query
+                .clone()
+                .into_query(&Schema::builder().build()),
+            Err(TantivyError::FieldNotFound(_))
+        ));
+        assert!(serializable_query.into_query(&data_schema).is_ok());
+        let range = SerializableQuery::Range {
+            lower_bound: Bound::Unbounded,
+            upper_bound: Bound::Unbounded,
+        };
+        assert!(matches!(
+            range.into_query(&data_schema),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::query::{
+    BooleanQuery, BoostQuery, EnableScoring, Occur, PhraseQuery, Query, RegexPhraseQuery,
+    TermQuery, Weight,
+};
+use crate::schema::{Field, FieldType, Schema, Term};
+use crate::tokenizer::{TextAnalyzer, TokenizerManager};
+use crate::{Score, TantivyError};
+
+/// A set of synonyms, mapping terms to the terms they should also match at query time.
+///
+/// The synonyms are attached to an [`IndexReader`](crate::IndexReader) with
+/// [`IndexReader::set_query_synonyms`](crate::IndexReader::set_query_synonyms), and applied to
+/// the term and phrase queries wrapped in a [`SynonymQueryWrapper`].
+///
+/// The entries are raw text: they are analyzed with the tokenizer of each field when the set is
+/// attached to a reader, so that `Running` matches the `running` term of a lowercased field.
+#[derive(Clone, Debug, Default)]
+pub struct SynonymSet {
+    mappings: Vec<(String, Vec<String>)>,
+}
+
+impl SynonymSet {
+    /// Creates an empty synonym set.
+    pub fn new() -> SynonymSet {
+        SynonymSet::default()
+    }
+
+    /// Parses a synonym set, one rule per line.
+    ///
+    /// - `couch, sofa, settee` makes all of the entries synonyms of each other.
+    /// - `tv => television, telly` makes `tv` also match `television` and `telly`, but not the
+    ///   other way around.
+    ///
+    /// Blank lines and lines starting with `#` are ignored. An entry can be made of several
+    /// words, e.g. `nyc => new york`.
+    pub fn parse(text: &str) -> crate::Result<SynonymSet> {
+        let mut synonym_set = SynonymSet::new();
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_line = || {
+                TantivyError::InvalidArgument(format!(
+                    "Invalid synonym rule on line {}: {line:?}",
+                    line_num + 1
+                ))
+            };
+            let split_entries = |entries: &str| -> Vec<String> {
+                entries
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|entry| !entry.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            if let Some((sources, targets)) = line.split_once("=>") {
+                let sources = split_entries(sources);
+                let targets = split_entries(targets);
+                if sources.is_empty() || targets.is_empty() {
+                    return Err(invalid_line());
+                }
+                for source in sources {
+                    synonym_set.add_mapping(&source, targets.iter().map(String::as_str));
+                }
+            } else {
+                let group = split_entries(line);
+                if group.len() < 2 {
+                    return Err(invalid_line());
+                }
+                synonym_set.add_group(group.iter().map(String::as_str));
+            }
+        }
+        Ok(synonym_set)
+    }
+
+    /// Makes `source` also match each of the `targets`.
+    pub fn add_mapping<'a>(&mut self, source: &str, targets: impl IntoIterator<Item = &'a str>) {
+        self.mappings.push((
+            source.to_string(),
+            targets.into_iter().map(str::to_string).collect(),
+        ));
+    }
+
+    /// Makes all of the entries of `group` synonyms of each other.
+    pub fn add_group<'a>(&mut self, group: impl IntoIterator<Item = &'a str>) {
+        let group: Vec<&str> = group.into_iter().collect();
+        for (pos, source) in group.iter().enumerate() {
+            let targets = group
+                .iter()
+                .enumerate()
+                .filter(|(target_pos, _)| *target_pos != pos)
+                .map(|(_, target)| *target);
+            self.add_mapping(source, targets);
+        }
+    }
+
+    /// Returns the number of rules of the set, a group of `n` entries counting as `n` rules.
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    /// Returns true if the set has no rule.
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+/// The synonyms of a field, keyed by the single token their source analyzes to.
+#[derive(Debug, Default)]
+struct FieldSynonyms {
+    synonyms: HashMap<String, Vec<Vec<String>>>,
+    has_positions: bool,
+}
+
+/// A [`SynonymSet`] analyzed with the tokenizer of each of the text fields of a schema.
+pub(crate) struct AnalyzedSynonyms {
+    synonym_set: Arc<SynonymSet>,
+    fields: HashMap<Field, FieldSynonyms>,
+}
+
+impl AnalyzedSynonyms {
+    pub(crate) fn analyze(
+        synonym_set: Arc<SynonymSet>,
+        schema: &Schema,
+        tokenizer_manager: &TokenizerManager,
+    ) -> crate::Result<AnalyzedSynonyms> {
+        let mut fields = HashMap::new();
+        for (field, field_entry) in schema.fields() {
+            let FieldType::Str(text_options) = field_entry.field_type() else {
+                continue;
+            };
+            let Some(indexing_options) = text_options.get_indexing_options() else {
+                continue;
+            };
+            let mut text_analyzer = tokenizer_manager
+                .get(indexing_options.tokenizer())
+                .ok_or_else(|| {
+                    TantivyError::InvalidArgument(format!(
+                        "No Tokenizer found for field {:?}",
+                        field_entry.name()
+                    ))
+                })?;
+            let mut field_synonyms = FieldSynonyms {
+                synonyms: HashMap::new(),
+                has_positions: indexing_options.index_option().has_positions(),
+            };
+            for (source, targets) in &synonym_set.mappings {
+                // Sources spanning several tokens cannot be matched against a single term.
+                let [source_token] = &tokens(&mut text_analyzer, source)[..] else {
+                    continue;
+                };
+                let expansions = field_synonyms
+                    .synonyms
+                    .entry(source_token.clone())
+                    .or_default();
+                for target in targets {
+                    let target_tokens = tokens(&mut text_analyzer, target);
+                    if target_tokens.is_empty()
+                        || target_tokens == [source_token.as_str()]
+                        || expansions.contains(&target_tokens)
+                    {
+                        continue;
+                    }
+                    expansions.push(target_tokens);
+                }
+            }
+            field_synonyms
+                .synonyms
+                .retain(|_, expansions| !expansions.is_empty());
+            if !field_synonyms.synonyms.is_empty() {
+                fields.insert(field, field_synonyms);
+            }
+        }
+        Ok(AnalyzedSynonyms {
+            synonym_set,
+            fields,
+        })
+    }
+
+    pub(crate) fn synonym_set(&self) -> &Arc<SynonymSet> {
+        &self.synonym_set
+    }
+
+    fn expansions(&self, term: &Term) -> Option<(&FieldSynonyms, &[Vec<String>])> {
+        let field_synonyms = self.fields.get(&term.field())?;
+        let value = term.value();
+        let expansions = field_synonyms.synonyms.get(value.as_str()?)?;
+        Some((field_synonyms, expansions))
+    }
+}
+
+fn tokens(text_analyzer: &mut TextAnalyzer, text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    text_analyzer
+        .token_stream(text)
+        .process(&mut |token| tokens.push(token.text.clone()));
+    tokens
+}
+
+/// `SynonymQueryWrapper` expands a [`TermQuery`] or a [`PhraseQuery`] with the synonyms of the
+/// searcher it runs on.
+///
+/// The synonyms are the ones attached to the reader of the searcher with
+/// [`IndexReader::set_query_synonyms`](crate::IndexReader::set_query_synonyms), and are looked
+/// up each time the query is run, so that swapping them is visible to the next search without
+/// reloading the reader.
+///
+/// - A term query also matches the synonyms of its term. Synonyms made of several tokens are
+///   matched as phrases, if the field has positions.
+/// - A phrase query also matches the phrases in which each term is replaced by one of its single
+///   token synonyms.
+///
+/// The scores of the synonym matches are multiplied by the synonym boost, so that documents
+/// matching the original terms rank first. Any other query, as well as a search without synonyms,
+/// runs as if it was not wrapped.
+pub struct SynonymQueryWrapper {
+    query: Box<dyn Query>,
+    boost: Score,
+}
+
+impl SynonymQueryWrapper {
+    /// Wraps `query`, applying `boost` to the scores of its synonym matches.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `boost` is not in `(0, 1)`.
+    pub fn new(query: Box<dyn Query>, boost: Score) -> SynonymQueryWrapper {
+        assert!(
+            boost > 0.0 && boost < 1.0,
+            "The synonym boost must be in (0, 1), got {boost}"
+        );
+        SynonymQueryWrapper { query, boost }
+    }
+
+    /// The wrapped query.
+    pub fn query(&self) -> &dyn Query {
+        self.query.as_ref()
+    }
+
+    /// The factor applied to the scores of the synonym matches.
+    pub fn boost(&self) -> Score {
+        self.boost
+    }
+
+    fn expand(&self, synonyms: &AnalyzedSynonyms) -> Option<Box<dyn Query>> {
+        let synonym_queries = if let Some(term_query) = self.query.downcast_ref::<TermQuery>() {
+            expand_term(term_query, synonyms)
+        } else if let Some(phrase_query) = self.query.downcast_ref::<PhraseQuery>() {
+            expand_phrase(phrase_query, synonyms)
+        } else {
+            Vec::new()
+        };
+        if synonym_queries.is_empty() {
+            return None;
+        }
+        let clauses = std::iter::once((Occur::Should, self.query.box_clone()))
+            .chain(synonym_queries.into_iter().map(|synonym_query| {
+                let boosted: Box<dyn Query> = Box::new(BoostQuery::new(synonym_query, self.boost));
+                (Occur::Should, boosted)
+            }))
+            .collect();
+        Some(Box::new(BooleanQuery::new(clauses)))
+    }
+}
+
+fn expand_term(term_query: &TermQuery, synonyms: &AnalyzedSynonyms) -> Vec<Box<dyn Query>> {
+    let term = term_query.term();
+    let Some((field_synonyms, expansions)) = synonyms.expansions(term) else {
+        return Vec::new();
+    };
+    expansions
+        .iter()
+        .filter_map(|tokens| -> Option<Box<dyn Query>> {
+            let terms: Vec<Term> = tokens
+                .iter()
+                .map(|token| Term::from_field_text(term.field(), token))
+                .collect();
+            if let [synonym_term] = &terms[..] {
+                Some(Box::new(TermQuery::new(
+                    synonym_term.clone(),
+                    term_query.index_record_option(),
+                )))
+            } else if field_synonyms.has_positions {
+                Some(Box::new(PhraseQuery::new(terms)))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn expand_phrase(phrase_query: &PhraseQuery, synonyms: &AnalyzedSynonyms) -> Vec<Box<dyn Query>> {
+    let mut has_synonyms = false;
+    let mut positions = Vec::new();
+    for (offset, term) in phrase_query.phrase_terms_with_offsets() {
+        let value = term.value();
+        let Some(text) = value.as_str() else {
+            return Vec::new();
+        };
+        let mut alternatives = vec![regex::escape(text)];
+        if let Some((_, expansions)) = synonyms.expansions(term) {
+            for tokens in expansions {
+                // Only the single token synonyms fit in a position of the phrase.
+                if let [token] = &tokens[..] {
+                    alternatives.push(regex::escape(token));
+                    has_synonyms = true;
+                }
+            }
+        }
+        positions.push((*offset, alternatives.join("|")));
+    }
+    if !has_synonyms {
+        return Vec::new();
+    }
+    vec![Box::new(RegexPhraseQuery::new_with_offset_and_slop(
+        phrase_query.field(),
+        positions,
+        phrase_query.slop(),
+    ))]
+}
+
+impl Clone for SynonymQueryWrapper {
+    fn clone(&self) -> Self {
+        SynonymQueryWrapper {
+            query: self.query.box_clone(),
+            boost: self.boost,
+        }
+    }
+}
+
+impl fmt::Debug for SynonymQueryWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Synonyms(query={:?}, boost={})", self.query, self.boost)
+    }
+}
+
+impl Query for SynonymQueryWrapper {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        let expanded_query = enable_scoring
+            .searcher()
+            .and_then(|searcher| searcher.analyzed_synonyms())
+            .and_then(|synonyms| self.expand(synonyms));
+        match expanded_query {
+            Some(expanded_query) => expanded_query.weight(enable_scoring),
+            None => self.query.weight(enable_scoring),
+        }
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor)
+    }
+
+    fn set_max_expanded_terms(&mut self, max_expanded_terms: usize) {
+        self.query.set_max_expanded_terms(max_expanded_terms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{SynonymQueryWrapper, SynonymSet};
+    use crate::collector::{Count, TopDocs};
+    use crate::query::{PhraseQuery, Query, QueryParser, TermQuery};
+    use crate::schema::{Field, IndexRecordOption, Schema, TEXT};
+    use crate::{DocAddress, Index, IndexReader, IndexWriter, Score, Term};
+
+    fn create_index(texts: &[&str]) -> crate::Result<(Index, Field)> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for doc_text in texts {
+            index_writer.add_document(doc!(text => *doc_text))?;
+        }
+        index_writer.commit()?;
+        Ok((index, text))
+    }
+
+    fn matching_docs(reader: &IndexReader, query: &dyn Query) -> crate::Result<Vec<u32>> {
+        let mut docs: Vec<u32> = reader
+            .searcher()
+            .search(query, &TopDocs::with_limit(10))?
+            .into_iter()
+            .map(|(_, doc_address)| doc_address.doc_id)
+            .collect();
+        docs.sort();
+        Ok(docs)
+    }
+
+    #[test]
+    fn test_synonym_set_parse() {
+        let synonym_set =
+            SynonymSet::parse("# furniture\n\ncouch, sofa, settee\ntv => television, telly\n")
+                .unwrap();
+        assert_eq!(synonym_set.len(), 4);
+        assert!(SynonymSet::parse("tv =>").is_err());
+        assert!(SynonymSet::parse("couch").is_err());
+    }
+
+    #[test]
+    fn test_synonym_term_expansion() -> crate::Result<()> {
+        let (index, text) = create_index(&[
+            "a red couch",
+            "a blue sofa",
+            "a new york loft",
+            "a green chair",
+        ])?;
+        let reader = index.reader()?;
+        // Entries are analyzed with the tokenizer of the field, hence lowercased.
+        reader.set_query_synonyms(Arc::new(SynonymSet::parse(
+            "Couch, SOFA\nloft => New York",
+        )?))?;
+        let query = |word: &str| {
+            SynonymQueryWrapper::new(
+                Box::new(TermQuery::new(
+                    Term::from_field_text(text, word),
+                    IndexRecordOption::WithFreqs,
+                )),
+                0.5,
+            )
+        };
+        assert_eq!(matching_docs(&reader, &query("couch"))?, vec![0, 1]);
+        assert_eq!(matching_docs(&reader, &query("sofa"))?, vec![0, 1]);
+        // Multi token synonyms are matched as phrases, and mappings are one way.
+        assert_eq!(matching_docs(&reader, &query("loft"))?, vec![2]);
+        assert_eq!(matching_docs(&reader, &query("york"))?, vec![2]);
+        assert_eq!(matching_docs(&reader, &query("chair"))?, vec![3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_synonym_phrase_expansion() -> crate::Result<()> {
+        let (index, text) = create_index(&[
+            "the big couch potato",
+            "the large sofa potato",
+            "the big sofa",
+            "sofa big potato",
+        ])?;
+        let reader = index.reader()?;
+        reader.set_query_synonyms(Arc::new(SynonymSet::parse("couch, sofa\nbig => large")?))?;
+        let query = SynonymQueryWrapper::new(
+            Box::new(PhraseQuery::new(vec![
+                Term::from_field_text(text, "big"),
+                Term::from_field_text(text, "couch"),
+                Term::from_field_text(text, "potato"),
+            ])),
+            0.5,
+        );
+        assert_eq!(matching_docs(&reader, &query)?, vec![0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_synonym_hot_swap() -> crate::Result<()> {
+        let (index, text) = create_index(&["a red couch", "a blue sofa", "a green settee"])?;
+        let reader = index.reader()?;
+        let mut query_parser = QueryParser::for_index(&index, vec![text]);
+        query_parser.set_synonym_boost(0.5);
+        let query = query_parser.parse_query("couch")?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        assert!(searcher.query_synonyms().is_none());
+
+        reader.set_query_synonyms(Arc::new(SynonymSet::parse("couch, sofa")?))?;
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        reader.set_query_synonyms(Arc::new(SynonymSet::parse("couch, sofa, settee")?))?;
+        assert_eq!(reader.searcher().search(&query, &Count)?, 3);
+        assert_eq!(reader.searcher().query_synonyms().unwrap().len(), 3);
+        // The searchers obtained before the swap keep their synonyms.
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+
+        reader.clear_query_synonyms();
+        assert_eq!(reader.searcher().search(&query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_synonym_boost_scoring() -> crate::Result<()> {
+        let (index, text) = create_index(&["a red couch", "a blue sofa"])?;
+        let reader = index.reader()?;
+        reader.set_query_synonyms(Arc::new(SynonymSet::parse("couch, sofa")?))?;
+        let term_query = |word: &str| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(text, word),
+                IndexRecordOption::WithFreqs,
+            ))
+        };
+        let searcher = reader.searcher();
+        let score = |query: &dyn Query, doc_id: u32| -> crate::Result<Score> {
+            Ok(query
+                .explain(&searcher, DocAddress::new(0, doc_id))?
+                .value())
+        };
+        let sofa_score = score(term_query("sofa").as_ref(), 1)?;
+        for boost in [0.2, 0.5] {
+            let query = SynonymQueryWrapper::new(term_query("couch"), boost);
+            let top_docs = searcher.search(&query, &TopDocs::with_limit(2))?;
+            assert_eq!(top_docs[0].1, DocAddress::new(0, 0));
+            assert_eq!(top_docs[1].1, DocAddress::new(0, 1));
+            assert!((score(&query, 1)? - boost * sofa_score).abs() < 1e-5);
+            assert!((top_docs[1].0 - boost * sofa_score).abs() < 1e-5);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+use crate::index::SegmentReader;
+use crate::postings::Postings;
+use crate::schema::{Field, IndexRecordOption};
+use crate::{DocId, DocSet, Term};
+
+/// Occurrences of a term of the query in a matching document.
+///
+/// Positions are token positions, as recorded in the positions of the postings. They can be
+/// mapped back to the text of the document by tokenizing its stored value with the tokenizer
+/// of the field.
+///
+/// See [`Weight::term_matches`](crate::query::Weight::term_matches).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermMatch {
+    /// The matched term.
+    pub term: Term,
+    /// The position ranges of the occurrences of the term, sorted by start position.
+    ///
+    /// For a phrase query, only the occurrences being part of a match of the whole phrase are
+    /// listed.
+    pub positions: Vec<Range<u32>>,
+}
+
+impl TermMatch {
+    /// Returns the field of the matched term.
+    pub fn field(&self) -> Field {
+        self.term.field()
+    }
+}
+
+/// Returns the positions of `term` in `doc`, or an empty `Vec` if the term does not appear in the
+/// document or if the field does not record positions.
+pub(crate) fn doc_term_positions(
+    reader: &SegmentReader,
+    term: &Term,
+    doc: DocId,
+) -> crate::Result<Vec<u32>> {
+    let mut positions = Vec::new();
+    let Some(mut postings) = reader
+        .inverted_index(term.field())?
+        .read_postings(term, IndexRecordOption::WithFreqsAndPositions)?
+    else {
+        return Ok(positions);
+    };
+    if postings.doc() <= doc && postings.seek(doc) == doc {
+        postings.positions(&mut positions);
+    }
+    Ok(positions)
+}
+
+/// Sorts the term matches by term, merging the matches of a same term.
+pub(crate) fn merge_term_matches(term_matches: &mut Vec<TermMatch>) {
+    term_matches.sort_by(|left, right| left.term.cmp(&right.term));
+    term_matches.dedup_by(|term_match, previous| {
+        if term_match.term != previous.term {
+            return false;
+        }
+        previous.positions.append(&mut term_match.positions);
+        true
+    });
+    for term_match in term_matches.iter_mut() {
+        term_match
+            .positions
+            .sort_by_key(|range| (range.start, range.end));
+        term_match.positions.dedup();
+    }
+    term_matches.retain(|term_match| !term_match.positions.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_term_matches, TermMatch};
+    use crate::schema::Field;
+    use crate::Term;
+
+    #[test]
+    fn test_merge_term_matches() {
+        let field = Field::from_field_id(0);
+        let term_match = |text: &str, positions: &[u32]| TermMatch {
+            term: Term::from_field_text(field, text),
+            positions: positions.iter().map(|&pos| pos..pos + 1).collect(),
+        };
+        let mut term_matches = vec![
+            term_match("b", &[3, 1]),
+            term_match("a", &[2]),
+            term_match("b", &[1, 5]),
+            term_match("c", &[]),
+        ];
+        merge_term_matches(&mut term_matches);
+        assert_eq!(
+            term_matches,
+            vec![term_match("a", &[2]), term_match("b", &[1, 3, 5])]
+        );
+    }
+}
@@ -7,11 +7,13 @@ pub use self::term_scorer::TermScorer;
 #[cfg(test)]
 mod tests {
 
+    use super::term_weight::CountStrategy;
     use crate::collector::TopDocs;
     use crate::docset::DocSet;
+    use crate::indexer::NoMergePolicy;
     use crate::postings::compression::COMPRESSION_BLOCK_SIZE;
-    use crate::query::{EnableScoring, Query, QueryParser, Scorer, TermQuery};
-    use crate::schema::{Field, IndexRecordOption, Schema, STRING, TEXT};
+    use crate::query::{EnableScoring, Query, QueryParser, Scorer, TermQuery, Weight};
+    use crate::schema::{Field, IndexRecordOption, Schema, INDEXED, STRING, TEXT};
     use crate::{assert_nearly_equals, DocAddress, Index, IndexWriter, Term, TERMINATED};
 
     #[test]
@@ -134,7 +136,7 @@ mod tests {
         let mut index_writer: IndexWriter = index.writer_for_tests()?;
         index_writer.add_document(doc!(text_field=>"a b"))?;
         index_writer.add_document(doc!(text_field=>"a c"))?;
-        index_writer.delete_term(Term::from_field_text(text_field, "b"));
+        index_writer.delete_term(Term::from_field_text(text_field, "b"))?;
         index_writer.commit()?;
         let term_a = Term::from_field_text(text_field, "a");
         let term_query = TermQuery::new(term_a, IndexRecordOption::Basic);
@@ -143,6 +145,97 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_term_query_count_delete_densities() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for id in 0..1_000u64 {
+            let mut doc = doc!(id_field => id, text_field => "a");
+            if id % 3 == 0 {
+                doc.add_text(text_field, "b");
+            }
+            if id % 100 == 0 {
+                doc.add_text(text_field, "c");
+            }
+            index_writer.add_document(doc)?;
+        }
+        index_writer.commit()?;
+        let reader = index.reader()?;
+
+        let has_term = |text: &str, id: u64| match text {
+            "a" => true,
+            "b" => id % 3 == 0,
+            _ => id % 100 == 0,
+        };
+        let mut deleted_ids: Vec<u64> = Vec::new();
+        let check_counts = |deleted_ids: &[u64],
+                            delete_probe_factor: u32,
+                            expected_strategies: [CountStrategy; 3]|
+         -> crate::Result<()> {
+            let searcher = reader.searcher();
+            let segment_reader = searcher.segment_reader(0);
+            assert_eq!(
+                segment_reader.num_deleted_docs() as usize,
+                deleted_ids.len()
+            );
+            for (text, expected_strategy) in ["a", "b", "c"].into_iter().zip(expected_strategies) {
+                let mut term_query = TermQuery::new(
+                    Term::from_field_text(text_field, text),
+                    IndexRecordOption::Basic,
+                );
+                term_query.set_delete_probe_factor(delete_probe_factor);
+                let term_weight = term_query
+                    .specialized_weight(EnableScoring::disabled_from_schema(searcher.schema()))?;
+                let expected_count = (0..1_000u64)
+                    .filter(|id| has_term(text, *id) && !deleted_ids.contains(id))
+                    .count();
+                assert_eq!(
+                    term_weight.count(segment_reader)? as usize,
+                    expected_count,
+                    "term {text}, {} deletes",
+                    deleted_ids.len()
+                );
+                assert_eq!(
+                    term_weight.take_count_strategies(),
+                    [expected_strategy],
+                    "term {text}, {} deletes",
+                    deleted_ids.len()
+                );
+            }
+            Ok(())
+        };
+        use CountStrategy::{DocFreq, ProbeDeletes, ScanPostings};
+        check_counts(&deleted_ids, 16, [DocFreq; 3])?;
+
+        // The doc frequencies are 1000, 334 and 10.
+        for (ids_to_delete, expected_strategies) in [
+            (vec![3], [ProbeDeletes, ProbeDeletes, ScanPostings]),
+            (
+                (0..1_000).step_by(20).collect(),
+                [ProbeDeletes, ScanPostings, ScanPostings],
+            ),
+            ((0..1_000).step_by(2).collect(), [ScanPostings; 3]),
+        ] {
+            for &id in &ids_to_delete {
+                if !deleted_ids.contains(&id) {
+                    index_writer.delete_term(Term::from_field_u64(id_field, id))?;
+                    deleted_ids.push(id);
+                }
+            }
+            index_writer.commit()?;
+            reader.reload()?;
+            check_counts(&deleted_ids, 16, expected_strategies)?;
+            // The strategies agree on the counts.
+            check_counts(&deleted_ids, 0, [ProbeDeletes; 3])?;
+            check_counts(&deleted_ids, u32::MAX, [ScanPostings; 3])?;
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_term_query_simple_seek() -> crate::Result<()> {
         let mut schema_builder = Schema::builder();
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use super::term_weight::TermWeight;
+use super::term_weight::{TermWeight, DEFAULT_DELETE_PROBE_FACTOR};
 use crate::query::bm25::Bm25Weight;
 use crate::query::{EnableScoring, Explanation, Query, Weight};
 use crate::schema::IndexRecordOption;
@@ -58,6 +58,7 @@ use crate::Term;
 pub struct TermQuery {
     term: Term,
     index_record_option: IndexRecordOption,
+    delete_probe_factor: u32,
 }
 
 impl fmt::Debug for TermQuery {
@@ -72,14 +73,33 @@ impl TermQuery {
         TermQuery {
             term,
             index_record_option: segment_postings_options,
+            delete_probe_factor: DEFAULT_DELETE_PROBE_FACTOR,
         }
     }
 
+    /// Sets how counting the matches of the query handles the deletes of a segment.
+    ///
+    /// Without deletes, the count of a segment is the doc freq of the term. With deletes, either
+    /// the deleted docs are looked up in the postings of the term and subtracted from its doc freq,
+    /// or all of the postings are scanned, skipping the deleted docs. The deleted docs are looked
+    /// up as long as they are `delete_probe_factor` times fewer than the docs of the term.
+    ///
+    /// It defaults to 16. `0` always looks up the deleted docs, and `u32::MAX` always scans the
+    /// postings of the segments with deletes.
+    pub fn set_delete_probe_factor(&mut self, delete_probe_factor: u32) {
+        self.delete_probe_factor = delete_probe_factor;
+    }
+
     /// The `Term` this query is built out of.
     pub fn term(&self) -> &Term {
         &self.term
     }
 
+    /// The information read from the postings of the term.
+    pub fn index_record_option(&self) -> IndexRecordOption {
+        self.index_record_option
+    }
+
     /// Returns a weight object.
     ///
     /// While `.weight(...)` returns a boxed trait object,
@@ -116,7 +136,8 @@ impl TermQuery {
             index_record_option,
             bm25_weight,
             scoring_enabled,
-        ))
+        )
+        .with_delete_probe_factor(self.delete_probe_factor))
     }
 }
 
@@ -1,20 +1,59 @@
 use super::term_scorer::TermScorer;
-use crate::docset::{DocSet, COLLECT_BLOCK_BUFFER_LEN};
+use crate::docset::{DocSet, COLLECT_BLOCK_BUFFER_LEN, TERMINATED};
+use crate::fastfield::AliveBitSet;
 use crate::fieldnorm::FieldNormReader;
 use crate::index::SegmentReader;
 use crate::postings::SegmentPostings;
 use crate::query::bm25::Bm25Weight;
 use crate::query::explanation::does_not_match;
+use crate::query::term_matches::doc_term_positions;
 use crate::query::weight::{for_each_docset_buffered, for_each_scorer};
-use crate::query::{Explanation, Scorer, Weight};
-use crate::schema::IndexRecordOption;
+use crate::query::{CostBounds, CostEstimate, Explanation, Scorer, TermMatch, Weight};
+use crate::schema::{Field, IndexRecordOption};
 use crate::{DocId, Score, Term};
 
+/// Default of [`TermWeight::with_delete_probe_factor`].
+pub(crate) const DEFAULT_DELETE_PROBE_FACTOR: u32 = 16;
+
 pub struct TermWeight {
     term: Term,
     index_record_option: IndexRecordOption,
     similarity_weight: Bm25Weight,
     scoring_enabled: bool,
+    delete_probe_factor: u32,
+    /// Strategies used by the calls to `count`, in order.
+    #[cfg(test)]
+    count_strategies: std::sync::Mutex<Vec<CountStrategy>>,
+}
+
+/// Strategy used by [`TermWeight::count`] on a segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CountStrategy {
+    /// The segment has no deletes: the count is the doc freq of the term.
+    DocFreq,
+    /// The deleted docs are probed in the postings of the term, and the ones found are
+    /// subtracted from its doc freq.
+    ProbeDeletes,
+    /// The postings of the term are scanned, skipping the deleted docs.
+    ScanPostings,
+}
+
+/// Counts the deleted docs of `postings`, seeking each of the deleted docs.
+fn count_deleted_matches(postings: &mut SegmentPostings, alive_bitset: &AliveBitSet) -> u32 {
+    let mut num_deleted_matches = 0;
+    for deleted_doc in alive_bitset.iter_deleted() {
+        let mut doc = postings.doc();
+        if doc == TERMINATED {
+            break;
+        }
+        if doc < deleted_doc {
+            doc = postings.seek(deleted_doc);
+        }
+        if doc == deleted_doc {
+            num_deleted_matches += 1;
+        }
+    }
+    num_deleted_matches
 }
 
 impl Weight for TermWeight {
@@ -33,17 +72,53 @@ impl Weight for TermWeight {
         Ok(explanation)
     }
 
+    fn term_matches(
+        &self,
+        reader: &SegmentReader,
+        doc: DocId,
+        fields: &[Field],
+        term_matches: &mut Vec<TermMatch>,
+    ) -> crate::Result<bool> {
+        if fields.contains(&self.term.field()) {
+            let positions = doc_term_positions(reader, &self.term, doc)?;
+            term_matches.push(TermMatch {
+                term: self.term.clone(),
+                positions: positions.into_iter().map(|pos| pos..pos + 1).collect(),
+            });
+        }
+        Ok(true)
+    }
+
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
-        if let Some(alive_bitset) = reader.alive_bitset() {
-            Ok(self.scorer(reader, 1.0)?.count(alive_bitset))
-        } else {
-            let field = self.term.field();
-            let inv_index = reader.inverted_index(field)?;
-            let term_info = inv_index.get_term_info(&self.term)?;
-            Ok(term_info.map(|term_info| term_info.doc_freq).unwrap_or(0))
+        let field = self.term.field();
+        let inv_index = reader.inverted_index(field)?;
+        let Some(term_info) = inv_index.get_term_info(&self.term)? else {
+            return Ok(0);
+        };
+        let strategy = self.count_strategy(reader, term_info.doc_freq);
+        #[cfg(test)]
+        self.count_strategies.lock().unwrap().push(strategy);
+        match (strategy, reader.alive_bitset()) {
+            (CountStrategy::ProbeDeletes, Some(alive_bitset)) => {
+                let mut postings =
+                    inv_index.read_postings_from_terminfo(&term_info, IndexRecordOption::Basic)?;
+                let num_deleted_matches = count_deleted_matches(&mut postings, alive_bitset);
+                Ok(term_info.doc_freq - num_deleted_matches)
+            }
+            (CountStrategy::ScanPostings, Some(alive_bitset)) => {
+                Ok(self.scorer(reader, 1.0)?.count(alive_bitset))
+            }
+            _ => Ok(term_info.doc_freq),
         }
     }
 
+    fn estimate_cost(&self, reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        let doc_freq = reader
+            .inverted_index(self.term.field())?
+            .doc_freq(&self.term)?;
+        Ok(CostEstimate::Bounded(CostBounds::postings(doc_freq)))
+    }
+
     /// Iterates through all of the document matched by the DocSet
     /// `DocSet` and push the scored documents to the collector.
     fn for_each(
@@ -103,13 +178,49 @@ impl TermWeight {
             index_record_option,
             similarity_weight,
             scoring_enabled,
+            delete_probe_factor: DEFAULT_DELETE_PROBE_FACTOR,
+            #[cfg(test)]
+            count_strategies: Default::default(),
         }
     }
 
+    /// Sets the factor deciding how `count` handles the deletes of a segment.
+    ///
+    /// See [`TermQuery::set_delete_probe_factor`](crate::query::TermQuery::set_delete_probe_factor).
+    pub fn with_delete_probe_factor(mut self, delete_probe_factor: u32) -> TermWeight {
+        self.delete_probe_factor = delete_probe_factor;
+        self
+    }
+
     pub fn term(&self) -> &Term {
         &self.term
     }
 
+    /// Picks the cheapest strategy to count the matches of a term of `doc_freq` in a segment.
+    ///
+    /// Probing the deleted docs costs a seek per deleted doc, while scanning the postings decodes
+    /// all of them: the deletes are probed as long as they are `delete_probe_factor` times fewer
+    /// than the docs of the term.
+    pub(crate) fn count_strategy(&self, reader: &SegmentReader, doc_freq: u32) -> CountStrategy {
+        if reader.alive_bitset().is_none() {
+            CountStrategy::DocFreq
+        } else if reader
+            .num_deleted_docs()
+            .saturating_mul(self.delete_probe_factor)
+            <= doc_freq
+        {
+            CountStrategy::ProbeDeletes
+        } else {
+            CountStrategy::ScanPostings
+        }
+    }
+
+    /// Returns the strategies used by the calls to `count`, in order, and resets them.
+    #[cfg(test)]
+    pub(crate) fn take_count_strategies(&self) -> Vec<CountStrategy> {
+        std::mem::take(&mut *self.count_strategies.lock().unwrap())
+    }
+
     pub(crate) fn specialized_scorer(
         &self,
         reader: &SegmentReader,
@@ -118,7 +229,7 @@ impl TermWeight {
         let field = self.term.field();
         let inverted_index = reader.inverted_index(field)?;
         let fieldnorm_reader_opt = if self.scoring_enabled {
-            reader.fieldnorms_readers().get_field(field)?
+            reader.try_fieldnorms_readers()?.get_field(field)?
         } else {
             None
         };
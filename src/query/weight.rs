@@ -1,7 +1,8 @@
 use super::Scorer;
 use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
 use crate::index::SegmentReader;
-use crate::query::Explanation;
+use crate::query::{CostEstimate, Explanation, TermMatch};
+use crate::schema::Field;
 use crate::{DocId, DocSet, Score, TERMINATED};
 
 /// Iterates through all of the documents and scores matched by the DocSet
@@ -74,6 +75,25 @@ pub trait Weight: Send + Sync + 'static {
     /// Returns an [`Explanation`] for the given document.
     fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation>;
 
+    /// Appends to `term_matches` the occurrences in `doc` of the terms of the query belonging to
+    /// one of the `fields`.
+    ///
+    /// `doc` is expected to match the query. The positions are read from the postings of the
+    /// terms, so this is only worth calling for a handful of documents, e.g. the top hits of a
+    /// search.
+    ///
+    /// Returns `false` if the weight does not support capturing its matches, in which case
+    /// `term_matches` is left untouched.
+    fn term_matches(
+        &self,
+        _reader: &SegmentReader,
+        _doc: DocId,
+        _fields: &[Field],
+        _term_matches: &mut Vec<TermMatch>,
+    ) -> crate::Result<bool> {
+        Ok(false)
+    }
+
     /// Returns the number documents within the given [`SegmentReader`].
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         let mut scorer = self.scorer(reader, 1.0)?;
@@ -84,6 +104,28 @@ pub trait Weight: Send + Sync + 'static {
         }
     }
 
+    /// Returns upper bounds of the cost of the query on the given [`SegmentReader`], without
+    /// building its scorer.
+    ///
+    /// The bounds are derived from cheap information of the segment, such as the document
+    /// frequencies of the term dictionary or the statistics of the fast fields. The weights
+    /// which do not know how to bound their cost return [`CostEstimate::Unknown`], which is the
+    /// default.
+    ///
+    /// See [`Searcher::estimate_cost()`](crate::Searcher::estimate_cost).
+    fn estimate_cost(&self, _reader: &SegmentReader) -> crate::Result<CostEstimate> {
+        Ok(CostEstimate::Unknown)
+    }
+
+    /// Returns true if the weight matches all of the documents of any segment, deleted documents
+    /// aside.
+    ///
+    /// Collectors can then enumerate the documents of a segment themselves, e.g. to skip ranges of
+    /// documents, instead of advancing a scorer.
+    fn matches_all_docs(&self) -> bool {
+        false
+    }
+
     /// Iterates through all of the document matched by the DocSet
     /// `DocSet` and push the scored documents to the collector.
     fn for_each(
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use common::BitSet;
+
+use crate::fastfield::AliveBitSet;
+use crate::query::PrefixExpansionBudget;
+use crate::schema::IndexRecordOption;
+use crate::{DocSet, Searcher, SegmentReader, TantivyError, Term, TERMINATED};
+
+/// Documents of a segment a principal is allowed to see, returned by an [`AccessFilter`].
+#[derive(Clone)]
+pub enum AllowedDocs {
+    /// All of the documents of the segment.
+    All,
+    /// The documents of the bitset, whose max value is the
+    /// [`max_doc`](SegmentReader::max_doc) of the segment.
+    Docs(BitSet),
+    /// The documents containing at least one of the terms, e.g. the `acl` terms of the groups of
+    /// the principal.
+    AnyTerm(Vec<Term>),
+}
+
+/// Restricts the documents seen by the searchers of an [`IndexReader`](crate::IndexReader) to the
+/// ones a principal is allowed to see.
+///
+/// See [`SearcherContext::with_access_filter()`].
+pub trait AccessFilter: Send + Sync {
+    /// Returns the documents of the segment `principal` is allowed to see.
+    ///
+    /// The result is cached by the [`SearcherContext`] for the principal and the searcher
+    /// generation, so the filter is called once per segment for the searches of a principal on
+    /// the same searcher generation.
+    fn allowed_docs(
+        &self,
+        principal: &str,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<AllowedDocs>;
+}
+
+/// Allowed documents of each segment of a searcher generation, `None` if all of them are.
+type AccessBitSets = Arc<Vec<Option<AliveBitSet>>>;
+
+/// Allowed documents per principal, with the generation of the searcher they were computed for.
+type AccessCache = HashMap<Option<String>, (u64, AccessBitSets)>;
+
+/// Context of the searchers returned by an [`IndexReader`](crate::IndexReader), set with
+/// [`IndexReaderBuilder::searcher_context()`](crate::IndexReaderBuilder::searcher_context).
+#[derive(Clone, Default)]
+pub struct SearcherContext {
+    access_filter: Option<Arc<dyn AccessFilter>>,
+    /// Allowed documents of the principals, for the last searcher generation they were
+    /// computed for. The searchers without a principal are cached under `None`.
+    access_cache: Arc<Mutex<AccessCache>>,
+    prefix_expansion_budget: PrefixExpansionBudget,
+}
+
+impl SearcherContext {
+    /// Makes the searchers of the reader only see the documents allowed by `access_filter`.
+    ///
+    /// The documents of a segment a principal is not allowed to see are handled like deleted
+    /// documents, for all of the queries and collectors: they are never pushed to the collectors,
+    /// and they are not counted in [`Searcher::num_docs`]. Fetching one of them with
+    /// [`Searcher::doc()`] returns a [`TantivyError::AccessDenied`] error. Document frequencies
+    /// and BM25 statistics are unaffected.
+    ///
+    /// The searchers of a principal are obtained with
+    /// [`IndexReader::searcher_for_principal()`](crate::IndexReader::searcher_for_principal).
+    /// The searchers obtained without a principal, e.g. with
+    /// [`IndexReader::searcher()`](crate::IndexReader::searcher), do not see any document.
+    pub fn with_access_filter(mut self, access_filter: Arc<dyn AccessFilter>) -> SearcherContext {
+        self.access_filter = Some(access_filter);
+        self.access_cache = Default::default();
+        self
+    }
+
+    /// Bounds the prefix expansions cached by each segment of a searcher generation.
+    ///
+    /// Prefix queries, e.g. [`PhrasePrefixQuery`](crate::query::PhrasePrefixQuery), reuse the
+    /// terms a segment expanded for a shorter prefix of the same field instead of walking its
+    /// term dictionary again, which speeds up search-as-you-type. The cache is dropped with
+    /// the searcher generation.
+    pub fn with_prefix_expansion_budget(
+        mut self,
+        prefix_expansion_budget: PrefixExpansionBudget,
+    ) -> SearcherContext {
+        self.prefix_expansion_budget = prefix_expansion_budget;
+        self
+    }
+
+    pub(crate) fn prefix_expansion_budget(&self) -> PrefixExpansionBudget {
+        self.prefix_expansion_budget
+    }
+
+    /// Restricts `searcher` to the documents `principal` is allowed to see.
+    pub(crate) fn restrict_to_principal(
+        &self,
+        searcher: Searcher,
+        principal: &str,
+    ) -> crate::Result<Searcher> {
+        let Some(access_filter) = self.access_filter.as_ref() else {
+            return Ok(searcher);
+        };
+        let generation_id = searcher.generation().generation_id();
+        if let Some(access_bitsets) = self.cached_access_bitsets(Some(principal), generation_id) {
+            return Ok(searcher.with_access_bitsets(access_bitsets));
+        }
+        // The lock of the cache is not held while the filter runs, so that the principals do not
+        // wait for each other. Concurrent searches of a principal may compute the same bitsets.
+        let access_bitsets: AccessBitSets = Arc::new(
+            searcher
+                .segment_readers()
+                .iter()
+                .map(|segment_reader| {
+                    let allowed_docs = access_filter.allowed_docs(principal, segment_reader)?;
+                    access_bitset(allowed_docs, segment_reader)
+                })
+                .collect::<crate::Result<_>>()?,
+        );
+        self.cache_access_bitsets(Some(principal), generation_id, access_bitsets.clone());
+        Ok(searcher.with_access_bitsets(access_bitsets))
+    }
+
+    /// Restricts `searcher` to no document, if there is an access filter.
+    pub(crate) fn restrict_to_no_principal(&self, searcher: Searcher) -> Searcher {
+        if self.access_filter.is_none() {
+            return searcher;
+        }
+        let generation_id = searcher.generation().generation_id();
+        if let Some(access_bitsets) = self.cached_access_bitsets(None, generation_id) {
+            return searcher.with_access_bitsets(access_bitsets);
+        }
+        let access_bitsets: AccessBitSets = Arc::new(
+            searcher
+                .segment_readers()
+                .iter()
+                .map(|segment_reader| {
+                    let no_docs = BitSet::with_max_value(segment_reader.max_doc());
+                    Some(AliveBitSet::from(&no_docs))
+                })
+                .collect(),
+        );
+        self.cache_access_bitsets(None, generation_id, access_bitsets.clone());
+        searcher.with_access_bitsets(access_bitsets)
+    }
+
+    fn cached_access_bitsets(
+        &self,
+        principal: Option<&str>,
+        generation_id: u64,
+    ) -> Option<AccessBitSets> {
+        let access_cache = self.access_cache.lock().unwrap();
+        let (cached_generation_id, access_bitsets) =
+            access_cache.get(&principal.map(str::to_string))?;
+        (*cached_generation_id == generation_id).then(|| access_bitsets.clone())
+    }
+
+    fn cache_access_bitsets(
+        &self,
+        principal: Option<&str>,
+        generation_id: u64,
+        access_bitsets: AccessBitSets,
+    ) {
+        self.access_cache.lock().unwrap().insert(
+            principal.map(str::to_string),
+            (generation_id, access_bitsets),
+        );
+    }
+}
+
+fn access_bitset(
+    allowed_docs: AllowedDocs,
+    segment_reader: &SegmentReader,
+) -> crate::Result<Option<AliveBitSet>> {
+    let max_doc = segment_reader.max_doc();
+    let bitset = match allowed_docs {
+        AllowedDocs::All => return Ok(None),
+        AllowedDocs::Docs(bitset) => {
+            if bitset.max_value() != max_doc {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "The allowed docs bitset of segment {} has a max value of {}, but the segment \
+                     has {} docs.",
+                    segment_reader.segment_id().short_uuid_string(),
+                    bitset.max_value(),
+                    max_doc
+                )));
+            }
+            bitset
+        }
+        AllowedDocs::AnyTerm(terms) => {
+            let mut bitset = BitSet::with_max_value(max_doc);
+            for term in &terms {
+                let inverted_index = segment_reader.inverted_index(term.field())?;
+                let Some(mut postings) =
+                    inverted_index.read_postings(term, IndexRecordOption::Basic)?
+                else {
+                    continue;
+                };
+                let mut doc = postings.doc();
+                while doc != TERMINATED {
+                    bitset.insert(doc);
+                    doc = postings.advance();
+                }
+            }
+            bitset
+        }
+    };
+    Ok(Some(AliveBitSet::from(&bitset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use common::BitSet;
+    use serde_json::json;
+
+    use super::{AccessFilter, AllowedDocs, SearcherContext};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::{AggregationCollector, AggregationSessionCache};
+    use crate::collector::{Count, TopDocs};
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{
+        Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT,
+    };
+    use crate::{
+        DocAddress, Index, IndexReader, IndexWriter, Searcher, SegmentReader, TantivyDocument,
+        TantivyError, Term,
+    };
+
+    /// Lets the principals see the documents whose `acl` field has their name or `public`.
+    struct AclFilter {
+        acl: Field,
+        num_calls: AtomicUsize,
+    }
+
+    impl AccessFilter for AclFilter {
+        fn allowed_docs(
+            &self,
+            principal: &str,
+            segment_reader: &SegmentReader,
+        ) -> crate::Result<AllowedDocs> {
+            self.num_calls.fetch_add(1, Ordering::SeqCst);
+            if principal == "admin" {
+                return Ok(AllowedDocs::All);
+            }
+            if principal == "carol" {
+                // As many documents of each segment as alice, but not the same ones.
+                let is_first_segment =
+                    segment_reader.fast_fields().u64("price")?.first(0) == Some(0);
+                let mut allowed_docs = BitSet::with_max_value(segment_reader.max_doc());
+                let docs: &[u32] = if is_first_segment { &[1, 2] } else { &[2] };
+                for &doc in docs {
+                    allowed_docs.insert(doc);
+                }
+                return Ok(AllowedDocs::Docs(allowed_docs));
+            }
+            Ok(AllowedDocs::AnyTerm(vec![
+                Term::from_field_text(self.acl, principal),
+                Term::from_field_text(self.acl, "public"),
+            ]))
+        }
+    }
+
+    fn create_reader() -> crate::Result<(IndexReader, Arc<AclFilter>)> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let acl = schema_builder.add_text_field("acl", STRING);
+        let price = schema_builder.add_u64_field("price", INDEXED | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for (i, owner) in ["alice", "bob", "alice", "public", "bob", "bob"]
+            .iter()
+            .enumerate()
+        {
+            index_writer.add_document(doc!(
+                title => format!("report {owner}"),
+                acl => *owner,
+                price => i as u64,
+            ))?;
+            if i == 2 {
+                index_writer.commit()?;
+            }
+        }
+        index_writer.commit()?;
+        let acl_filter = Arc::new(AclFilter {
+            acl,
+            num_calls: AtomicUsize::new(0),
+        });
+        let reader = index
+            .reader_builder()
+            .searcher_context(SearcherContext::default().with_access_filter(acl_filter.clone()))
+            .try_into()?;
+        Ok((reader, acl_filter))
+    }
+
+    fn titles(searcher: &Searcher) -> crate::Result<Vec<String>> {
+        let title = searcher.schema().get_field("title")?;
+        let query = TermQuery::new(
+            Term::from_field_text(title, "report"),
+            IndexRecordOption::Basic,
+        );
+        let mut titles: Vec<String> = searcher
+            .search(&query, &TopDocs::with_limit(10))?
+            .into_iter()
+            .map(|(_, doc_address)| {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                Ok(doc.get_first(title).unwrap().as_str().unwrap().to_string())
+            })
+            .collect::<crate::Result<_>>()?;
+        titles.sort();
+        Ok(titles)
+    }
+
+    fn price_sum(searcher: &Searcher) -> crate::Result<f64> {
+        let agg_req: Aggregations =
+            serde_json::from_value(json!({ "price_sum": { "sum": { "field": "price" } } }))
+                .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+        let agg_res = serde_json::to_value(searcher.search(&AllQuery, &collector)?)?;
+        Ok(agg_res["price_sum"]["value"].as_f64().unwrap())
+    }
+
+    #[test]
+    fn test_access_filter_principals() -> crate::Result<()> {
+        let (reader, _acl_filter) = create_reader()?;
+        let alice = reader.searcher_for_principal("alice")?;
+        let bob = reader.searcher_for_principal("bob")?;
+        assert_eq!(
+            titles(&alice)?,
+            vec!["report alice", "report alice", "report public"]
+        );
+        assert_eq!(
+            titles(&bob)?,
+            vec!["report bob", "report bob", "report bob", "report public"]
+        );
+        assert_eq!(alice.search(&AllQuery, &Count)?, 3);
+        assert_eq!(alice.num_docs(), 3);
+        assert_eq!(bob.search(&AllQuery, &Count)?, 4);
+        assert_eq!(price_sum(&alice)?, (2 + 3) as f64);
+        assert_eq!(price_sum(&bob)?, (1 + 3 + 4 + 5) as f64);
+        // The soft-deleted documents cannot be used to bypass the filter.
+        assert_eq!(
+            alice.include_soft_deleted(true).search(&AllQuery, &Count)?,
+            3
+        );
+
+        let admin = reader.searcher_for_principal("admin")?;
+        assert_eq!(admin.search(&AllQuery, &Count)?, 6);
+        // Without a principal, no document is visible.
+        assert_eq!(reader.searcher().search(&AllQuery, &Count)?, 0);
+        assert_eq!(reader.searcher().num_docs(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_filter_doc_fetch() -> crate::Result<()> {
+        let (reader, _acl_filter) = create_reader()?;
+        let admin = reader.searcher_for_principal("admin")?;
+        let alice = reader.searcher_for_principal("alice")?;
+        let acl = admin.schema().get_field("acl")?;
+        let bob_doc_address = admin.search(
+            &TermQuery::new(Term::from_field_text(acl, "bob"), IndexRecordOption::Basic),
+            &TopDocs::with_limit(1),
+        )?[0]
+            .1;
+        assert!(admin.doc::<TantivyDocument>(bob_doc_address).is_ok());
+        let err = alice.doc::<TantivyDocument>(bob_doc_address).unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::AccessDenied { doc_address } if doc_address == bob_doc_address
+        ));
+        assert_eq!(err.error_code(), "access_denied");
+        assert!(matches!(
+            reader
+                .searcher()
+                .doc::<TantivyDocument>(DocAddress::new(0, 0)),
+            Err(TantivyError::AccessDenied { .. })
+        ));
+        let price = admin.schema().get_field("price")?;
+        assert!(admin.doc_id_value(bob_doc_address, price)?.is_some());
+        assert!(matches!(
+            alice.doc_id_value(bob_doc_address, price),
+            Err(TantivyError::AccessDenied { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_filter_bypasses_aggregation_session_cache() -> crate::Result<()> {
+        let (reader, _acl_filter) = create_reader()?;
+        let cache = AggregationSessionCache::new();
+        let price_sum = |searcher: &Searcher| -> crate::Result<f64> {
+            let agg_req: Aggregations =
+                serde_json::from_value(json!({ "price_sum": { "sum": { "field": "price" } } }))
+                    .unwrap();
+            let title = searcher.schema().get_field("title")?;
+            let query = TermQuery::new(
+                Term::from_field_text(title, "report"),
+                IndexRecordOption::Basic,
+            );
+            let agg_res = cache.search(searcher, &query, agg_req, Default::default())?;
+            Ok(serde_json::to_value(agg_res)?["price_sum"]["value"]
+                .as_f64()
+                .unwrap())
+        };
+        // Alice and carol see as many documents of each segment, but not the same ones.
+        let alice = reader.searcher_for_principal("alice")?;
+        let carol = reader.searcher_for_principal("carol")?;
+        for (alice_segment, carol_segment) in
+            alice.segment_readers().iter().zip(carol.segment_readers())
+        {
+            assert_eq!(alice_segment.num_docs(), carol_segment.num_docs());
+        }
+        assert_eq!(price_sum(&alice)?, (2 + 3) as f64);
+        assert_eq!(price_sum(&carol)?, (1 + 2 + 5) as f64);
+        assert_eq!(cache.stats().num_entries, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_filter_cache() -> crate::Result<()> {
+        let (reader, acl_filter) = create_reader()?;
+        let num_segments = reader.searcher().segment_readers().len();
+        assert_eq!(num_segments, 2);
+        for _ in 0..3 {
+            let alice = reader.searcher_for_principal("alice")?;
+            assert_eq!(alice.search(&AllQuery, &Count)?, 3);
+        }
+        // The filter ran once per segment for alice.
+        assert_eq!(acl_filter.num_calls.load(Ordering::SeqCst), num_segments);
+        reader.searcher_for_principal("bob")?;
+        assert_eq!(
+            acl_filter.num_calls.load(Ordering::SeqCst),
+            2 * num_segments
+        );
+
+        // A new searcher generation computes the allowed documents again.
+        reader.reload()?;
+        reader.searcher_for_principal("alice")?;
+        assert_eq!(
+            acl_filter.num_calls.load(Ordering::SeqCst),
+            3 * num_segments
+        );
+        Ok(())
+    }
+}
@@ -1,16 +1,34 @@
+mod access_filter;
+mod pinned_searcher;
+mod search_budget;
 mod warming;
 
 use std::sync::atomic::AtomicU64;
-use std::sync::{atomic, Arc, Weak};
+use std::sync::{atomic, Arc, Mutex, Weak};
+use std::time::Duration;
 
-use arc_swap::ArcSwap;
+pub use access_filter::{AccessFilter, AllowedDocs, SearcherContext};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use crossbeam_channel::RecvTimeoutError;
+pub use pinned_searcher::{LeaseId, PinnedSearcher};
+pub(crate) use search_budget::{charge_current_search, SearchBudgetPool, SearchPermit};
+pub use search_budget::{BudgetExhaustedPolicy, GlobalSearchBudget, SearchBudgetUsage};
 pub use warming::Warmer;
 
+use self::pinned_searcher::SearcherLeases;
 use self::warming::WarmingState;
 use crate::core::searcher::{SearcherGeneration, SearcherInner};
 use crate::directory::{Directory, WatchCallback, WatchHandle, META_LOCK};
+use crate::index::{ComponentLoading, ComponentSet, IndexMeta};
+use crate::query::synonym_query::AnalyzedSynonyms;
+use crate::query::{PrefixExpansionBudget, SynonymSet};
 use crate::store::DOCSTORE_CACHE_CAPACITY;
-use crate::{Index, Inventory, Searcher, SegmentReader, TrackedObject};
+use crate::{
+    FutureResult, Index, Inventory, Opstamp, Searcher, SegmentReader, TantivyError, TrackedObject,
+};
+
+/// A pending [`IndexReader::wait_searchable()`] call.
+type SearchableWaiter = (Opstamp, oneshot::Sender<crate::Result<()>>);
 
 /// Defines when a new version of the index should be reloaded.
 ///
@@ -23,11 +41,18 @@ pub enum ReloadPolicy {
     /// All updates of the index should be manual.
     ///
     /// No change is reflected automatically. You are required to call [`IndexReader::reload()`]
-    /// manually.
+    /// or [`IndexReader::reload_if_newer()`] manually.
     Manual,
     /// The index is reloaded within milliseconds after a new commit is available.
     /// This is made possible by watching changes in the `meta.json` file.
     OnCommitWithDelay, // TODO add NEAR_REAL_TIME(target_ms)
+    /// The index is reloaded once no new commit has been detected for the given duration.
+    ///
+    /// Commits closer to each other than this duration are collapsed into a single reload to the
+    /// newest commit, so that indexing with frequent commits does not build and warm a searcher
+    /// for each of them. As a consequence, the searchers are not reloaded for as long as commits
+    /// keep coming at a higher rate.
+    OnCommitWithDebounce(Duration),
 }
 
 /// [`IndexReader`] builder
@@ -37,6 +62,8 @@ pub enum ReloadPolicy {
 /// - [`Warmer`] implementations
 /// - number of warming threads, for parallelizing warming work
 /// - The cache size of the underlying doc store readers.
+/// - The components of the segments loaded when they are opened.
+/// - The [`SearcherContext`] of the searchers, e.g. their access filter.
 #[derive(Clone)]
 pub struct IndexReaderBuilder {
     reload_policy: ReloadPolicy,
@@ -44,6 +71,9 @@ pub struct IndexReaderBuilder {
     warmers: Vec<Weak<dyn Warmer>>,
     num_warming_threads: usize,
     doc_store_cache_num_blocks: usize,
+    component_loading: ComponentLoading,
+    searcher_context: SearcherContext,
+    commit: Option<IndexMeta>,
 }
 
 impl IndexReaderBuilder {
@@ -55,9 +85,19 @@ impl IndexReaderBuilder {
             warmers: Vec::new(),
             num_warming_threads: 1,
             doc_store_cache_num_blocks: DOCSTORE_CACHE_CAPACITY,
+            component_loading: ComponentLoading::default(),
+            searcher_context: SearcherContext::default(),
+            commit: None,
         }
     }
 
+    /// Makes the reader open the segments of `commit` instead of the last commit.
+    #[must_use]
+    pub(crate) fn commit(mut self, commit: IndexMeta) -> IndexReaderBuilder {
+        self.commit = Some(commit);
+        self
+    }
+
     /// Builds the reader.
     ///
     /// Building the reader is a non-trivial operation that requires
@@ -72,7 +112,10 @@ impl IndexReaderBuilder {
         )?;
         let inner_reader = InnerIndexReader::new(
             self.doc_store_cache_num_blocks,
+            self.component_loading,
+            self.searcher_context,
             self.index,
+            self.commit,
             warming_state,
             searcher_generation_inventory,
         )?;
@@ -98,6 +141,33 @@ impl IndexReaderBuilder {
                     .watch(WatchCallback::new(callback))?;
                 Some(watch_handle)
             }
+            ReloadPolicy::OnCommitWithDebounce(debounce) => {
+                // A pending signal is enough to restart the debounce window.
+                let (reload_sender, reload_receiver) = crossbeam_channel::bounded(1);
+                let weak_inner_reader = Arc::downgrade(&inner_reader_arc);
+                std::thread::Builder::new()
+                    .name("tantivy-reload-debounce".to_owned())
+                    .spawn(move || {
+                        InnerIndexReader::debounced_reload_loop(
+                            weak_inner_reader,
+                            reload_receiver,
+                            debounce,
+                        )
+                    })
+                    .map_err(|_| {
+                        TantivyError::SystemError(
+                            "Failed to spawn reload debounce thread".to_owned(),
+                        )
+                    })?;
+                let callback = move || {
+                    let _ = reload_sender.try_send(());
+                };
+                let watch_handle = inner_reader_arc
+                    .index
+                    .directory()
+                    .watch(WatchCallback::new(callback))?;
+                Some(watch_handle)
+            }
         };
         Ok(IndexReader {
             inner: inner_reader_arc,
@@ -126,6 +196,41 @@ impl IndexReaderBuilder {
         self
     }
 
+    /// Sets the components loaded when the segments are opened. All of them are loaded by
+    /// default.
+    ///
+    /// A search only needing some of the components, like an aggregation only reading fast
+    /// fields, can skip loading the others. By default, the components not loaded are loaded on
+    /// first access. See [`IndexReaderBuilder::strict_component_loading`] to make accessing them
+    /// an error instead.
+    #[must_use]
+    pub fn load_components(mut self, components: ComponentSet) -> IndexReaderBuilder {
+        self.component_loading.components = components;
+        self
+    }
+
+    /// If true, accessing a component that was not loaded when the segments were opened returns a
+    /// [`TantivyError::ComponentNotLoaded`](crate::TantivyError::ComponentNotLoaded) error,
+    /// instead of loading it. Defaults to false.
+    ///
+    /// See [`IndexReaderBuilder::load_components`].
+    #[must_use]
+    pub fn strict_component_loading(mut self, strict: bool) -> IndexReaderBuilder {
+        self.component_loading.strict = strict;
+        self
+    }
+
+    /// Sets the context of the searchers returned by the reader.
+    ///
+    /// The context is applied when the searchers are handed out, so that it cannot be bypassed
+    /// by the call sites, e.g. to enforce an access filter, see
+    /// [`SearcherContext::with_access_filter()`].
+    #[must_use]
+    pub fn searcher_context(mut self, searcher_context: SearcherContext) -> IndexReaderBuilder {
+        self.searcher_context = searcher_context;
+        self
+    }
+
     /// Set the [`Warmer`]s that are invoked when reloading searchable segments.
     #[must_use]
     pub fn warmers(mut self, warmers: Vec<Weak<dyn Warmer>>) -> IndexReaderBuilder {
@@ -154,17 +259,29 @@ impl TryInto<IndexReader> for IndexReaderBuilder {
 
 struct InnerIndexReader {
     doc_store_cache_num_blocks: usize,
+    component_loading: ComponentLoading,
+    searcher_context: SearcherContext,
     index: Index,
+    // The commit the reader is pinned to. Its segment metas being alive, their files are kept by
+    // the garbage collection.
+    commit: Option<IndexMeta>,
     warming_state: WarmingState,
     searcher: arc_swap::ArcSwap<SearcherInner>,
     searcher_generation_counter: Arc<AtomicU64>,
     searcher_generation_inventory: Inventory<SearcherGeneration>,
+    searchable_waiters: Mutex<Vec<SearchableWaiter>>,
+    searcher_leases: SearcherLeases,
+    search_budget: ArcSwapOption<SearchBudgetPool>,
+    query_synonyms: ArcSwapOption<AnalyzedSynonyms>,
 }
 
 impl InnerIndexReader {
     fn new(
         doc_store_cache_num_blocks: usize,
+        component_loading: ComponentLoading,
+        searcher_context: SearcherContext,
         index: Index,
+        commit: Option<IndexMeta>,
         warming_state: WarmingState,
         // The searcher_generation_inventory is not used as source, but as target to track the
         // loaded segments.
@@ -174,33 +291,67 @@ impl InnerIndexReader {
 
         let searcher = Self::create_searcher(
             &index,
+            commit.as_ref(),
             doc_store_cache_num_blocks,
+            component_loading,
+            searcher_context.prefix_expansion_budget(),
             &warming_state,
             &searcher_generation_counter,
             &searcher_generation_inventory,
         )?;
         Ok(InnerIndexReader {
             doc_store_cache_num_blocks,
+            component_loading,
+            searcher_context,
             index,
+            commit,
             warming_state,
             searcher: ArcSwap::from(searcher),
             searcher_generation_counter,
             searcher_generation_inventory,
+            searchable_waiters: Default::default(),
+            searcher_leases: Default::default(),
+            search_budget: ArcSwapOption::empty(),
+            query_synonyms: ArcSwapOption::empty(),
         })
     }
-    /// Opens the freshest segments [`SegmentReader`].
+    /// Returns the meta of the commit the reader is pinned to, or of the last commit.
+    fn load_metas(index: &Index, commit: Option<&IndexMeta>) -> crate::Result<IndexMeta> {
+        match commit {
+            Some(commit) => Ok(commit.clone()),
+            None => index.load_metas(),
+        }
+    }
+
+    /// Opens the freshest segments [`SegmentReader`], or the ones of the commit the reader is
+    /// pinned to, and returns them with the opstamp of the commit they belong to.
     ///
     /// This function acquires a lock to prevent GC from removing files
     /// as we are opening our index.
-    fn open_segment_readers(index: &Index) -> crate::Result<Vec<SegmentReader>> {
+    fn open_segment_readers(
+        index: &Index,
+        commit: Option<&IndexMeta>,
+        component_loading: ComponentLoading,
+        prefix_expansion_budget: PrefixExpansionBudget,
+    ) -> crate::Result<(Vec<SegmentReader>, Opstamp)> {
         // Prevents segment files from getting deleted while we are in the process of opening them
         let _meta_lock = index.directory().acquire_lock(&META_LOCK)?;
-        let searchable_segments = index.searchable_segments()?;
-        let segment_readers = searchable_segments
-            .iter()
-            .map(SegmentReader::open)
+        let index_meta = Self::load_metas(index, commit)?;
+        let segment_readers = index_meta
+            .segments
+            .into_iter()
+            .map(|segment_meta| {
+                SegmentReader::open_with_component_loading(
+                    &index.segment(segment_meta),
+                    None,
+                    component_loading,
+                )
+                .map(|segment_reader| {
+                    segment_reader.with_prefix_expansion_budget(prefix_expansion_budget)
+                })
+            })
             .collect::<crate::Result<_>>()?;
-        Ok(segment_readers)
+        Ok((segment_readers, index_meta.opstamp))
     }
 
     fn track_segment_readers_in_inventory(
@@ -214,14 +365,19 @@ impl InnerIndexReader {
         searcher_generation_inventory.track(searcher_generation)
     }
 
+    #[expect(clippy::too_many_arguments)]
     fn create_searcher(
         index: &Index,
+        commit: Option<&IndexMeta>,
         doc_store_cache_num_blocks: usize,
+        component_loading: ComponentLoading,
+        prefix_expansion_budget: PrefixExpansionBudget,
         warming_state: &WarmingState,
         searcher_generation_counter: &Arc<AtomicU64>,
         searcher_generation_inventory: &Inventory<SearcherGeneration>,
     ) -> crate::Result<Arc<SearcherInner>> {
-        let segment_readers = Self::open_segment_readers(index)?;
+        let (segment_readers, commit_opstamp) =
+            Self::open_segment_readers(index, commit, component_loading, prefix_expansion_budget)?;
         let searcher_generation = Self::track_segment_readers_in_inventory(
             &segment_readers,
             searcher_generation_counter,
@@ -234,6 +390,7 @@ impl InnerIndexReader {
             index.clone(),
             segment_readers,
             searcher_generation,
+            commit_opstamp,
             doc_store_cache_num_blocks,
         )?);
 
@@ -244,19 +401,110 @@ impl InnerIndexReader {
     fn reload(&self) -> crate::Result<()> {
         let searcher = Self::create_searcher(
             &self.index,
+            self.commit.as_ref(),
             self.doc_store_cache_num_blocks,
+            self.component_loading,
+            self.searcher_context.prefix_expansion_budget(),
             &self.warming_state,
             &self.searcher_generation_counter,
             &self.searcher_generation_inventory,
         )?;
 
+        let commit_opstamp = searcher.commit_opstamp();
         self.searcher.store(searcher);
+        self.notify_searchable_waiters(commit_opstamp);
 
         Ok(())
     }
 
+    /// Reloads the searcher if the last commit or the segments of the index differ from the
+    /// ones it was loaded from. Returns true if a new searcher was loaded.
+    fn reload_if_newer(&self) -> crate::Result<bool> {
+        let index_meta = Self::load_metas(&self.index, self.commit.as_ref())?;
+        let searcher = self.unrestricted_searcher();
+        let loaded_segments = searcher.generation().segments();
+        let is_loaded = self.searcher.load().commit_opstamp() == index_meta.opstamp
+            && loaded_segments.len() == index_meta.segments.len()
+            && index_meta.segments.iter().all(|segment_meta| {
+                loaded_segments.get(&segment_meta.id()) == Some(&segment_meta.delete_opstamp())
+            });
+        if is_loaded {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Reloads the searcher once no commit has been notified for `debounce`, until the reader or
+    /// its watch callback is dropped.
+    fn debounced_reload_loop(
+        inner: Weak<InnerIndexReader>,
+        reload_receiver: crossbeam_channel::Receiver<()>,
+        debounce: Duration,
+    ) {
+        while reload_receiver.recv().is_ok() {
+            // Every commit notified during the debounce window restarts it.
+            loop {
+                match reload_receiver.recv_timeout(debounce) {
+                    Ok(()) => {}
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            if let Err(err) = inner.reload_if_newer() {
+                error!(
+                    "Error while loading searcher after commit was detected. {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn unrestricted_searcher(&self) -> Searcher {
+        Searcher::from(self.searcher.load().clone())
+            .with_search_budget(self.search_budget.load_full())
+            .with_query_synonyms(self.query_synonyms.load_full())
+    }
+
     fn searcher(&self) -> Searcher {
-        self.searcher.load().clone().into()
+        self.searcher_context
+            .restrict_to_no_principal(self.unrestricted_searcher())
+    }
+
+    fn searcher_for_principal(&self, principal: &str) -> crate::Result<Searcher> {
+        self.searcher_context
+            .restrict_to_principal(self.unrestricted_searcher(), principal)
+    }
+
+    /// Resolves the waiters whose opstamp is visible in a searcher loaded from the commit
+    /// `commit_opstamp`.
+    fn notify_searchable_waiters(&self, commit_opstamp: Opstamp) {
+        let mut waiters = self.searchable_waiters.lock().unwrap();
+        let (ready, pending) = std::mem::take(&mut *waiters)
+            .into_iter()
+            .partition(|(opstamp, _)| *opstamp <= commit_opstamp);
+        *waiters = pending;
+        for (_, sender) in ready {
+            // The receiver may have been dropped, in which case nobody is waiting anymore.
+            let _ = sender.send(Ok(()));
+        }
+    }
+
+    fn wait_searchable(&self, opstamp: Opstamp) -> FutureResult<()> {
+        let (future_result, sender) =
+            FutureResult::create("The index reader was dropped before the commit was loaded.");
+        // The lock is held while checking the current searcher, so that a concurrent reload
+        // cannot publish a new searcher between the check and the registration.
+        let mut waiters = self.searchable_waiters.lock().unwrap();
+        if self.searcher.load().commit_opstamp() >= opstamp {
+            let _ = sender.send(Ok(()));
+        } else {
+            waiters.push((opstamp, sender));
+        }
+        future_result
     }
 }
 
@@ -291,6 +539,15 @@ impl IndexReader {
         self.inner.reload()
     }
 
+    /// Reloads the searchers if a commit or a merge changed the index since they were loaded.
+    ///
+    /// Returns true if new searchers were loaded, and false if the index is unchanged, in which
+    /// case the warmers are not invoked. This is typically used with [`ReloadPolicy::Manual`],
+    /// to poll the index for changes.
+    pub fn reload_if_newer(&self) -> crate::Result<bool> {
+        self.inner.reload_if_newer()
+    }
+
     /// Returns a searcher
     ///
     /// This method should be called every single time a search
@@ -298,7 +555,158 @@ impl IndexReader {
     ///
     /// The same searcher must be used for a given query, as it ensures
     /// the use of a consistent segment set.
+    ///
+    /// If the reader has an access filter, the searcher does not see any document, see
+    /// [`IndexReader::searcher_for_principal()`].
     pub fn searcher(&self) -> Searcher {
         self.inner.searcher()
     }
+
+    /// Returns a searcher which only sees the documents `principal` is allowed to see, if the
+    /// reader has an access filter, see [`SearcherContext::with_access_filter()`].
+    ///
+    /// Without an access filter, this is the same as [`IndexReader::searcher()`].
+    pub fn searcher_for_principal(&self, principal: &str) -> crate::Result<Searcher> {
+        self.inner.searcher_for_principal(principal)
+    }
+
+    /// Returns a searcher that keeps serving the current segments across commits and merges,
+    /// e.g. to paginate through the results of a query.
+    ///
+    /// The returned [`PinnedSearcher`] holds a lease on the segments: their files are not
+    /// garbage collected until the lease is released with [`PinnedSearcher::release()`] or
+    /// [`IndexReader::release_searcher()`], or expires after being unused for `ttl`.
+    /// Expired leases are released by a background thread, which checks them every second.
+    ///
+    /// Only the garbage collection of the index writers opened on this reader's [`Index`]
+    /// (or its clones) takes leases into account. A writer running in another process may
+    /// still delete the pinned files.
+    pub fn pin_searcher(&self, ttl: Duration) -> crate::Result<PinnedSearcher> {
+        self.inner.searcher_leases.pin(self.inner.searcher(), ttl)
+    }
+
+    /// Returns the searcher pinned by the lease `lease_id`, renewing the lease for its
+    /// time-to-live.
+    ///
+    /// Returns `None` if the lease expired or was released.
+    pub fn searcher_for(&self, lease_id: LeaseId) -> Option<Searcher> {
+        self.inner.searcher_leases.get(lease_id)
+    }
+
+    /// Releases the lease `lease_id` taken by [`IndexReader::pin_searcher()`].
+    ///
+    /// Returns false if the lease had already expired or been released.
+    pub fn release_searcher(&self, lease_id: LeaseId) -> bool {
+        self.inner.searcher_leases.release(lease_id)
+    }
+
+    /// Bounds the searches running concurrently on the searchers returned by this reader, and the
+    /// memory they use.
+    ///
+    /// The budget applies to the searchers returned after this call. The searches running on
+    /// the searchers returned before keep counting against the previous budget, if any.
+    ///
+    /// ```rust
+    /// use tantivy::schema::{Schema, FAST};
+    /// use tantivy::query::AllQuery;
+    /// use tantivy::collector::Count;
+    /// use tantivy::{doc, GlobalSearchBudget, Index};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let price = schema_builder.add_u64_field("price", FAST);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(price => 10u64))?;
+    /// index_writer.commit()?;
+    ///
+    /// let reader = index.reader()?;
+    /// reader.set_search_budget(GlobalSearchBudget::new(4, 100_000_000));
+    /// let searcher = reader.searcher();
+    /// assert_eq!(searcher.search_with_estimate(&AllQuery, &Count, 1_000_000)?, 1);
+    /// // The reservation of the search is released once it completes.
+    /// assert_eq!(reader.search_budget_usage().unwrap().reserved_bytes, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_search_budget(&self, search_budget: GlobalSearchBudget) {
+        self.inner
+            .search_budget
+            .store(Some(Arc::new(SearchBudgetPool::new(search_budget))));
+    }
+
+    /// Removes the budget set with [`IndexReader::set_search_budget`].
+    pub fn clear_search_budget(&self) {
+        self.inner.search_budget.store(None);
+    }
+
+    /// Sets the synonyms the [`SynonymQueryWrapper`](crate::query::SynonymQueryWrapper)s expand
+    /// their query with, replacing the previous ones.
+    ///
+    /// The entries of `synonyms` are analyzed with the tokenizer of each of the text fields of the
+    /// schema. The next searchers returned by the reader use the new synonyms, without reloading
+    /// the reader.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    ///
+    /// use tantivy::collector::Count;
+    /// use tantivy::query::{QueryParser, SynonymSet};
+    /// use tantivy::schema::{Schema, TEXT};
+    /// use tantivy::{doc, Index};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// let mut schema_builder = Schema::builder();
+    /// let title = schema_builder.add_text_field("title", TEXT);
+    /// let index = Index::create_in_ram(schema_builder.build());
+    /// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// index_writer.add_document(doc!(title => "A comfy sofa"))?;
+    /// index_writer.commit()?;
+    ///
+    /// let reader = index.reader()?;
+    /// let mut query_parser = QueryParser::for_index(&index, vec![title]);
+    /// query_parser.set_synonym_boost(0.5);
+    /// let query = query_parser.parse_query("couch")?;
+    /// assert_eq!(reader.searcher().search(&query, &Count)?, 0);
+    ///
+    /// reader.set_query_synonyms(Arc::new(SynonymSet::parse("couch, sofa")?))?;
+    /// assert_eq!(reader.searcher().search(&query, &Count)?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_query_synonyms(&self, synonyms: Arc<SynonymSet>) -> crate::Result<()> {
+        let analyzed_synonyms = AnalyzedSynonyms::analyze(
+            synonyms,
+            &self.inner.index.schema(),
+            self.inner.index.tokenizers(),
+        )?;
+        self.inner
+            .query_synonyms
+            .store(Some(Arc::new(analyzed_synonyms)));
+        Ok(())
+    }
+
+    /// Removes the synonyms set with [`IndexReader::set_query_synonyms`].
+    pub fn clear_query_synonyms(&self) {
+        self.inner.query_synonyms.store(None);
+    }
+
+    /// Returns the current usage of the search budget, or `None` if no budget is set.
+    pub fn search_budget_usage(&self) -> Option<SearchBudgetUsage> {
+        self.inner
+            .search_budget
+            .load()
+            .as_ref()
+            .map(|search_budget| search_budget.usage())
+    }
+
+    /// Returns a future that resolves once this reader serves a searcher which includes the
+    /// commit with the given `opstamp`.
+    ///
+    /// The reader does not reload by itself, except with [`ReloadPolicy::OnCommitWithDelay`] and
+    /// [`ReloadPolicy::OnCommitWithDebounce`]. With [`ReloadPolicy::Manual`], the future resolves
+    /// on the first call to [`IndexReader::reload()`] that loads this commit or a later one.
+    pub(crate) fn wait_searchable(&self, opstamp: Opstamp) -> FutureResult<()> {
+        self.inner.wait_searchable(opstamp)
+    }
 }
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::directory::FileProtection;
+use crate::{Searcher, TantivyError};
+
+/// Interval at which expired leases are released in the background.
+pub const LEASE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Identifier of a [`PinnedSearcher`] lease.
+///
+/// It can be handed over to a client (e.g. as a pagination token) and later be used to get the
+/// pinned searcher back with [`IndexReader::searcher_for()`](super::IndexReader::searcher_for).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LeaseId(u64);
+
+impl From<u64> for LeaseId {
+    fn from(lease_id: u64) -> Self {
+        LeaseId(lease_id)
+    }
+}
+
+impl From<LeaseId> for u64 {
+    fn from(lease_id: LeaseId) -> Self {
+        lease_id.0
+    }
+}
+
+impl fmt::Display for LeaseId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A [`Searcher`] whose segments are kept searchable across commits and merges.
+///
+/// The files of the pinned segments are protected from garbage collection until the lease is
+/// released, either explicitly with [`PinnedSearcher::release()`] or
+/// [`IndexReader::release_searcher()`](super::IndexReader::release_searcher), or because it
+/// was not used for longer than its time-to-live.
+///
+/// Dropping a `PinnedSearcher` does not release its lease.
+pub struct PinnedSearcher {
+    lease_id: LeaseId,
+    searcher: Searcher,
+    leases: Weak<Mutex<SearcherLeasesInner>>,
+}
+
+impl PinnedSearcher {
+    /// Returns the id of the lease pinning this searcher.
+    pub fn lease_id(&self) -> LeaseId {
+        self.lease_id
+    }
+
+    /// Returns the pinned searcher.
+    pub fn searcher(&self) -> &Searcher {
+        &self.searcher
+    }
+
+    /// Releases the lease, allowing the next garbage collection to remove the files of the
+    /// segments that are not used anymore.
+    ///
+    /// Returns false if the lease had already expired or been released.
+    pub fn release(self) -> bool {
+        let Some(leases) = self.leases.upgrade() else {
+            return false;
+        };
+        let released_lease = leases.lock().unwrap().leases.remove(&self.lease_id);
+        released_lease.is_some()
+    }
+}
+
+impl fmt::Debug for PinnedSearcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedSearcher")
+            .field("lease_id", &self.lease_id)
+            .field("searcher", &self.searcher)
+            .finish()
+    }
+}
+
+/// Registry of the searchers pinned on an [`IndexReader`](super::IndexReader).
+#[derive(Clone, Default)]
+pub(crate) struct SearcherLeases(Arc<Mutex<SearcherLeasesInner>>);
+
+#[derive(Default)]
+struct SearcherLeasesInner {
+    next_lease_id: u64,
+    leases: HashMap<LeaseId, Lease>,
+    sweep_thread: Option<JoinHandle<()>>,
+}
+
+struct Lease {
+    searcher: Searcher,
+    ttl: Duration,
+    expires_at: Instant,
+    // Released on drop.
+    _file_protections: Vec<FileProtection>,
+}
+
+impl SearcherLeases {
+    /// Pins `searcher` for `ttl`, protecting the files of its segments from garbage collection.
+    ///
+    /// A background thread releasing expired leases is uniquely created on the first call.
+    pub fn pin(&self, searcher: Searcher, ttl: Duration) -> crate::Result<PinnedSearcher> {
+        let directory = searcher.index().directory();
+        let file_protections: Vec<FileProtection> = searcher
+            .segment_readers()
+            .iter()
+            .flat_map(|segment_reader| segment_reader.segment_files())
+            .map(|path| directory.protect_file_from_delete(path))
+            .collect();
+        let mut inner = self.0.lock().unwrap();
+        inner.start_sweep_thread_maybe(&self.0)?;
+        let lease_id = LeaseId(inner.next_lease_id);
+        inner.next_lease_id += 1;
+        inner.leases.insert(
+            lease_id,
+            Lease {
+                searcher: searcher.clone(),
+                ttl,
+                expires_at: Instant::now() + ttl,
+                _file_protections: file_protections,
+            },
+        );
+        Ok(PinnedSearcher {
+            lease_id,
+            searcher,
+            leases: Arc::downgrade(&self.0),
+        })
+    }
+
+    /// Returns the searcher pinned by `lease_id` and renews its lease, or `None` if the lease
+    /// expired or was released.
+    pub fn get(&self, lease_id: LeaseId) -> Option<Searcher> {
+        let mut inner = self.0.lock().unwrap();
+        let now = Instant::now();
+        let lease = inner.leases.get_mut(&lease_id)?;
+        if lease.expires_at <= now {
+            inner.leases.remove(&lease_id);
+            return None;
+        }
+        lease.expires_at = now + lease.ttl;
+        Some(lease.searcher.clone())
+    }
+
+    /// Releases the lease `lease_id`. Returns false if it had already expired or been released.
+    pub fn release(&self, lease_id: LeaseId) -> bool {
+        self.0.lock().unwrap().leases.remove(&lease_id).is_some()
+    }
+
+    /// Releases the expired leases and returns their number.
+    #[cfg(test)]
+    pub fn sweep_expired(&self) -> usize {
+        self.0.lock().unwrap().sweep_expired(Instant::now())
+    }
+}
+
+impl SearcherLeasesInner {
+    fn sweep_expired(&mut self, now: Instant) -> usize {
+        let num_leases = self.leases.len();
+        self.leases.retain(|_, lease| lease.expires_at > now);
+        num_leases - self.leases.len()
+    }
+
+    /// Start the sweep thread if one has not already been started.
+    fn start_sweep_thread_maybe(&mut self, this: &Arc<Mutex<Self>>) -> crate::Result<bool> {
+        if self.sweep_thread.is_some() {
+            return Ok(false);
+        }
+        let weak_inner = Arc::downgrade(this);
+        let handle = std::thread::Builder::new()
+            .name("tantivy-lease-sweep".to_owned())
+            .spawn(|| Self::sweep_loop(weak_inner))
+            .map_err(|_| {
+                TantivyError::SystemError("Failed to spawn lease sweep thread".to_owned())
+            })?;
+        self.sweep_thread = Some(handle);
+        Ok(true)
+    }
+
+    /// Every [`LEASE_SWEEP_INTERVAL`] release the expired leases, until the registry is
+    /// dropped.
+    fn sweep_loop(inner: Weak<Mutex<SearcherLeasesInner>>) {
+        for _ in crossbeam_channel::tick(LEASE_SWEEP_INTERVAL) {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            let num_expired = inner.lock().unwrap().sweep_expired(Instant::now());
+            if num_expired > 0 {
+                debug!("Released {num_expired} expired searcher leases");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use super::LEASE_SWEEP_INTERVAL;
+    use crate::collector::Count;
+    use crate::directory::Directory;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, TEXT};
+    use crate::{Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, TantivyDocument};
+
+    fn create_index() -> crate::Result<(Index, IndexWriter, IndexReader)> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for i in 0..2 {
+            let mut doc = TantivyDocument::default();
+            doc.add_text(text, format!("doc {i}"));
+            index_writer.add_document(doc)?;
+            index_writer.commit()?;
+        }
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok((index, index_writer, reader))
+    }
+
+    fn segment_files(searcher: &Searcher) -> HashSet<PathBuf> {
+        let files: HashSet<PathBuf> = searcher
+            .segment_readers()
+            .iter()
+            .flat_map(|segment_reader| segment_reader.segment_files())
+            .filter(|path| searcher.index().directory().exists(path).unwrap())
+            .cloned()
+            .collect();
+        assert!(!files.is_empty());
+        files
+    }
+
+    fn all_exist(index: &Index, files: &HashSet<PathBuf>) -> bool {
+        files
+            .iter()
+            .all(|path| index.directory().exists(path).unwrap())
+    }
+
+    fn merge_all_segments(index: &Index, index_writer: &mut IndexWriter) -> crate::Result<()> {
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.garbage_collect_files().wait()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_searcher_survives_merge_and_gc() -> crate::Result<()> {
+        let (index, mut index_writer, reader) = create_index()?;
+        let pinned = reader.pin_searcher(Duration::from_secs(60))?;
+        let pinned_files = segment_files(pinned.searcher());
+        assert_eq!(pinned.searcher().segment_readers().len(), 2);
+
+        index_writer.add_document(TantivyDocument::default())?;
+        index_writer.commit()?;
+        merge_all_segments(&index, &mut index_writer)?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().segment_readers().len(), 1);
+        assert_eq!(reader.searcher().search(&AllQuery, &Count)?, 3);
+
+        assert!(all_exist(&index, &pinned_files));
+        let searcher = reader.searcher_for(pinned.lease_id()).unwrap();
+        assert_eq!(searcher.generation(), pinned.searcher().generation());
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 2);
+
+        let lease_id = pinned.lease_id();
+        assert!(pinned.release());
+        assert!(reader.searcher_for(lease_id).is_none());
+        drop(searcher);
+        index_writer.garbage_collect_files().wait()?;
+        assert!(pinned_files
+            .iter()
+            .all(|path| !index.directory().exists(path).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_searcher_by_lease_id() -> crate::Result<()> {
+        let (_index, _index_writer, reader) = create_index()?;
+        let lease_id = reader.pin_searcher(Duration::from_secs(60))?.lease_id();
+        assert!(reader.searcher_for(lease_id).is_some());
+        assert!(reader.release_searcher(lease_id));
+        assert!(!reader.release_searcher(lease_id));
+        assert!(reader.searcher_for(lease_id).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_lease_is_released() -> crate::Result<()> {
+        let (index, mut index_writer, reader) = create_index()?;
+        let pinned = reader.pin_searcher(Duration::ZERO)?;
+        let pinned_files = segment_files(pinned.searcher());
+        let lease_id = pinned.lease_id();
+        drop(pinned);
+        assert!(reader.searcher_for(lease_id).is_none());
+
+        let pinned = reader.pin_searcher(Duration::ZERO)?;
+        assert_eq!(reader.inner.searcher_leases.sweep_expired(), 1);
+        assert!(!pinned.release());
+
+        merge_all_segments(&index, &mut index_writer)?;
+        assert!(pinned_files
+            .iter()
+            .all(|path| !index.directory().exists(path).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_lease_is_swept_in_background() -> crate::Result<()> {
+        let (index, mut index_writer, reader) = create_index()?;
+        let pinned = reader.pin_searcher(Duration::from_millis(10))?;
+        let pinned_files = segment_files(pinned.searcher());
+        drop(pinned);
+        merge_all_segments(&index, &mut index_writer)?;
+        assert!(all_exist(&index, &pinned_files));
+
+        let deadline = Instant::now() + 5 * LEASE_SWEEP_INTERVAL;
+        while !reader
+            .inner
+            .searcher_leases
+            .0
+            .lock()
+            .unwrap()
+            .leases
+            .is_empty()
+        {
+            assert!(Instant::now() < deadline, "the lease was not swept");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        index_writer.garbage_collect_files().wait()?;
+        assert!(pinned_files
+            .iter()
+            .all(|path| !index.directory().exists(path).unwrap()));
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::TantivyError;
+
+thread_local! {
+    /// Permit of the search collected by the current thread, charged by the aggregations.
+    static CURRENT_PERMIT: RefCell<Option<Arc<PermitState>>> = const { RefCell::new(None) };
+}
+
+/// Behavior of a search that can't be admitted by the [`GlobalSearchBudget`] of its reader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BudgetExhaustedPolicy {
+    /// The search waits until enough searches complete.
+    #[default]
+    Wait,
+    /// The search fails right away, with a [`TantivyError::TooManyConcurrentSearches`] or a
+    /// [`TantivyError::SearchMemoryBudgetExceeded`] error.
+    FailFast,
+}
+
+/// Bounds the searches running concurrently on an [`IndexReader`](crate::IndexReader), and the
+/// memory they use.
+///
+/// See [`IndexReader::set_search_budget`](crate::IndexReader::set_search_budget).
+///
+/// Each search reserves an estimate of its memory usage when it starts, and releases it when it
+/// completes. The memory charged by the aggregations of a search (see
+/// [`AggregationLimitsGuard`](crate::aggregation::AggregationLimitsGuard)) comes out of its
+/// reservation, and grows it once it is exceeded: a search whose actual usage exceeds the bytes
+/// left in the budget fails with a [`TantivyError::SearchMemoryBudgetExceeded`] error. The memory
+/// charged by a search is only released when it completes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlobalSearchBudget {
+    /// Maximum number of searches running concurrently.
+    pub max_concurrent: usize,
+    /// Maximum number of bytes reserved by the running searches.
+    pub max_total_bytes: u64,
+    /// Bytes reserved by the searches not declaring an estimate, see
+    /// [`Searcher::search_with_estimate`](crate::Searcher::search_with_estimate).
+    pub default_search_bytes: u64,
+    /// Behavior of the searches that can't be admitted.
+    pub when_exhausted: BudgetExhaustedPolicy,
+}
+
+impl GlobalSearchBudget {
+    /// Creates a budget of `max_concurrent` searches and `max_total_bytes` bytes.
+    ///
+    /// The searches not declaring an estimate reserve no bytes up front, and the searches that
+    /// can't be admitted wait.
+    pub fn new(max_concurrent: usize, max_total_bytes: u64) -> GlobalSearchBudget {
+        GlobalSearchBudget {
+            max_concurrent,
+            max_total_bytes,
+            default_search_bytes: 0,
+            when_exhausted: BudgetExhaustedPolicy::Wait,
+        }
+    }
+}
+
+/// Current usage of a [`GlobalSearchBudget`], e.g. to be exported as metrics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchBudgetUsage {
+    /// Number of searches running.
+    pub num_searches: usize,
+    /// Number of searches waiting to be admitted.
+    pub num_waiting: usize,
+    /// Bytes reserved by the running searches.
+    pub reserved_bytes: u64,
+}
+
+#[derive(Default)]
+struct PoolState {
+    num_searches: usize,
+    num_waiting: usize,
+    reserved_bytes: u64,
+}
+
+/// The searches admitted by a [`GlobalSearchBudget`].
+pub(crate) struct SearchBudgetPool {
+    budget: GlobalSearchBudget,
+    state: Mutex<PoolState>,
+    released: Condvar,
+}
+
+impl SearchBudgetPool {
+    pub(crate) fn new(budget: GlobalSearchBudget) -> SearchBudgetPool {
+        SearchBudgetPool {
+            budget,
+            state: Mutex::default(),
+            released: Condvar::new(),
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, PoolState> {
+        // The state is consistent between any two statements, so a poisoned lock can be reused.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn usage(&self) -> SearchBudgetUsage {
+        let state = self.lock_state();
+        SearchBudgetUsage {
+            num_searches: state.num_searches,
+            num_waiting: state.num_waiting,
+            reserved_bytes: state.reserved_bytes,
+        }
+    }
+
+    fn memory_exceeded_error(&self, state: &PoolState, requested_bytes: u64) -> TantivyError {
+        TantivyError::SearchMemoryBudgetExceeded {
+            requested_bytes,
+            available_bytes: self.budget.max_total_bytes - state.reserved_bytes,
+        }
+    }
+
+    /// Admits a search reserving `estimated_bytes`, waiting for other searches to complete
+    /// depending on the [`BudgetExhaustedPolicy`].
+    pub(crate) fn acquire(
+        self: &Arc<Self>,
+        estimated_bytes: Option<u64>,
+    ) -> crate::Result<SearchPermit> {
+        let estimated_bytes = estimated_bytes.unwrap_or(self.budget.default_search_bytes);
+        let mut state = self.lock_state();
+        if estimated_bytes > self.budget.max_total_bytes {
+            // The search would never be admitted.
+            return Err(TantivyError::SearchMemoryBudgetExceeded {
+                requested_bytes: estimated_bytes,
+                available_bytes: self.budget.max_total_bytes,
+            });
+        }
+        loop {
+            let has_search_slot = state.num_searches < self.budget.max_concurrent;
+            let has_bytes = state.reserved_bytes + estimated_bytes <= self.budget.max_total_bytes;
+            if has_search_slot && has_bytes {
+                break;
+            }
+            if self.budget.when_exhausted == BudgetExhaustedPolicy::FailFast {
+                if !has_search_slot {
+                    return Err(TantivyError::TooManyConcurrentSearches {
+                        max_concurrent: self.budget.max_concurrent,
+                    });
+                }
+                return Err(self.memory_exceeded_error(&state, estimated_bytes));
+            }
+            state.num_waiting += 1;
+            state = self
+                .released
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.num_waiting -= 1;
+        }
+        state.num_searches += 1;
+        state.reserved_bytes += estimated_bytes;
+        Ok(SearchPermit {
+            state: Arc::new(PermitState {
+                pool: self.clone(),
+                reserved_bytes: AtomicU64::new(estimated_bytes),
+                charged_bytes: AtomicU64::new(0),
+            }),
+        })
+    }
+}
+
+struct PermitState {
+    pool: Arc<SearchBudgetPool>,
+    reserved_bytes: AtomicU64,
+    charged_bytes: AtomicU64,
+}
+
+impl PermitState {
+    fn charge(&self, num_bytes: u64) -> crate::Result<()> {
+        let charged_bytes = self.charged_bytes.fetch_add(num_bytes, Ordering::Relaxed) + num_bytes;
+        if charged_bytes <= self.reserved_bytes.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut state = self.pool.lock_state();
+        // The reservation may have been grown concurrently, while waiting for the lock.
+        let reserved_bytes = self.reserved_bytes.load(Ordering::Relaxed);
+        if charged_bytes <= reserved_bytes {
+            return Ok(());
+        }
+        let missing_bytes = charged_bytes - reserved_bytes;
+        if state.reserved_bytes + missing_bytes > self.pool.budget.max_total_bytes {
+            return Err(self.pool.memory_exceeded_error(&state, missing_bytes));
+        }
+        state.reserved_bytes += missing_bytes;
+        self.reserved_bytes.store(charged_bytes, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Admission of a search by a [`GlobalSearchBudget`].
+///
+/// The search and its reserved bytes are released when the permit is dropped, including when
+/// the search panics.
+pub(crate) struct SearchPermit {
+    state: Arc<PermitState>,
+}
+
+impl SearchPermit {
+    /// Charges the memory allocated by the aggregations collected by the current thread to this
+    /// permit, until the returned scope is dropped.
+    pub(crate) fn enter(&self) -> SearchPermitScope {
+        let previous = CURRENT_PERMIT.with(|current| current.replace(Some(self.state.clone())));
+        SearchPermitScope { previous }
+    }
+}
+
+impl Drop for SearchPermit {
+    fn drop(&mut self) {
+        let pool = &self.state.pool;
+        let mut state = pool.lock_state();
+        state.num_searches -= 1;
+        state.reserved_bytes -= self.state.reserved_bytes.load(Ordering::Relaxed);
+        drop(state);
+        pool.released.notify_all();
+    }
+}
+
+/// Restores the permit charged by the current thread when dropped, see [`SearchPermit::enter`].
+pub(crate) struct SearchPermitScope {
+    previous: Option<Arc<PermitState>>,
+}
+
+impl Drop for SearchPermitScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_PERMIT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Charges `num_bytes` to the permit of the search collected by the current thread, if any.
+pub(crate) fn charge_current_search(num_bytes: u64) -> crate::Result<()> {
+    let Some(permit_state) = CURRENT_PERMIT.with(|current| current.borrow().clone()) else {
+        return Ok(());
+    };
+    permit_state.charge(num_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::time::{Duration, Instant};
+
+    use crossbeam_channel::{Receiver, Sender};
+
+    use super::{BudgetExhaustedPolicy, GlobalSearchBudget, SearchBudgetUsage};
+    use crate::aggregation::agg_req::Aggregations;
+    use crate::aggregation::AggregationCollector;
+    use crate::collector::{Collector, Count};
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, FAST};
+    use crate::{Index, IndexReader, IndexWriter, SegmentOrdinal, SegmentReader, TantivyError};
+
+    fn create_reader() -> crate::Result<IndexReader> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..1_000u64 {
+            index_writer.add_document(doc!(id => i))?;
+        }
+        index_writer.commit()?;
+        index.reader()
+    }
+
+    /// Counts the docs, but waits for `release` before merging the segment counts.
+    struct BlockingCount {
+        started: Sender<()>,
+        release: Receiver<()>,
+    }
+
+    impl Collector for BlockingCount {
+        type Fruit = usize;
+        type Child = <Count as Collector>::Child;
+
+        fn for_segment(
+            &self,
+            segment_local_id: SegmentOrdinal,
+            segment: &SegmentReader,
+        ) -> crate::Result<Self::Child> {
+            Count.for_segment(segment_local_id, segment)
+        }
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn merge_fruits(&self, segment_counts: Vec<usize>) -> crate::Result<usize> {
+            self.started.send(()).unwrap();
+            self.release.recv().unwrap();
+            Count.merge_fruits(segment_counts)
+        }
+    }
+
+    /// Runs a [`BlockingCount`] search in a thread, and returns once it is admitted.
+    fn spawn_blocking_search(
+        reader: &IndexReader,
+        estimated_bytes: u64,
+    ) -> (Sender<()>, std::thread::JoinHandle<crate::Result<usize>>) {
+        let (started_sender, started_receiver) = crossbeam_channel::unbounded();
+        let (release_sender, release_receiver) = crossbeam_channel::unbounded();
+        let searcher = reader.searcher();
+        let handle = std::thread::spawn(move || {
+            let collector = BlockingCount {
+                started: started_sender,
+                release: release_receiver,
+            };
+            searcher.search_with_estimate(&AllQuery, &collector, estimated_bytes)
+        });
+        started_receiver.recv().unwrap();
+        (release_sender, handle)
+    }
+
+    fn wait_for_usage(reader: &IndexReader, predicate: impl Fn(&SearchBudgetUsage) -> bool) {
+        let start = Instant::now();
+        while !predicate(&reader.search_budget_usage().unwrap()) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_search_budget_queues_concurrent_searches() -> crate::Result<()> {
+        let reader = create_reader()?;
+        reader.set_search_budget(GlobalSearchBudget::new(1, 1_000_000));
+        let (release_sender, blocking_search) = spawn_blocking_search(&reader, 1_000);
+        assert_eq!(
+            reader.search_budget_usage(),
+            Some(SearchBudgetUsage {
+                num_searches: 1,
+                num_waiting: 0,
+                reserved_bytes: 1_000,
+            })
+        );
+
+        let (count_sender, count_receiver) = crossbeam_channel::unbounded();
+        let searcher = reader.searcher();
+        let queued_search = std::thread::spawn(move || {
+            count_sender
+                .send(searcher.search(&AllQuery, &Count))
+                .unwrap();
+        });
+        wait_for_usage(&reader, |usage| usage.num_waiting == 1);
+        assert!(count_receiver
+            .recv_timeout(Duration::from_millis(50))
+            .is_err());
+
+        release_sender.send(()).unwrap();
+        assert_eq!(blocking_search.join().unwrap()?, 1_000);
+        assert_eq!(count_receiver.recv().unwrap()?, 1_000);
+        queued_search.join().unwrap();
+        assert_eq!(
+            reader.search_budget_usage(),
+            Some(SearchBudgetUsage::default())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_budget_fail_fast() -> crate::Result<()> {
+        let reader = create_reader()?;
+        reader.set_search_budget(GlobalSearchBudget {
+            when_exhausted: BudgetExhaustedPolicy::FailFast,
+            ..GlobalSearchBudget::new(2, 1_000_000)
+        });
+        let searcher = reader.searcher();
+        let (release_sender, blocking_search) = spawn_blocking_search(&reader, 800_000);
+        // Not enough bytes left for the estimate.
+        let err = searcher
+            .search_with_estimate(&AllQuery, &Count, 300_000)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TantivyError::SearchMemoryBudgetExceeded {
+                requested_bytes: 300_000,
+                available_bytes: 200_000,
+            }
+        ));
+        // An estimate can't exceed the whole budget.
+        assert!(matches!(
+            searcher.search_with_estimate(&AllQuery, &Count, 2_000_000),
+            Err(TantivyError::SearchMemoryBudgetExceeded { .. })
+        ));
+
+        let (release_sender_2, blocking_search_2) = spawn_blocking_search(&reader, 0);
+        assert!(matches!(
+            searcher.search(&AllQuery, &Count),
+            Err(TantivyError::TooManyConcurrentSearches { max_concurrent: 2 })
+        ));
+        release_sender.send(()).unwrap();
+        release_sender_2.send(()).unwrap();
+        assert_eq!(blocking_search.join().unwrap()?, 1_000);
+        assert_eq!(blocking_search_2.join().unwrap()?, 1_000);
+        assert_eq!(
+            searcher.search_with_estimate(&AllQuery, &Count, 300_000)?,
+            1_000
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_budget_charges_aggregation_memory() -> crate::Result<()> {
+        let reader = create_reader()?;
+        reader.set_search_budget(GlobalSearchBudget::new(4, 1_000_000));
+        let searcher = reader.searcher();
+        let agg_req: Aggregations = serde_json::from_value(json!({
+            "ids": { "terms": { "field": "id", "size": 10_000 } }
+        }))
+        .unwrap();
+        let collector = AggregationCollector::from_aggs(agg_req, Default::default());
+
+        let (release_sender, blocking_search) = spawn_blocking_search(&reader, 999_000);
+        // The aggregation is admitted without an estimate, but its actual memory usage exceeds
+        // the bytes left.
+        let err = searcher.search(&AllQuery, &collector).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                TantivyError::SearchMemoryBudgetExceeded {
+                    available_bytes: 1_000,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+        // Searches that fit in the budget are unaffected.
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 1_000);
+        release_sender.send(()).unwrap();
+        assert_eq!(blocking_search.join().unwrap()?, 1_000);
+
+        let res = searcher.search(&AllQuery, &collector)?;
+        let res = serde_json::to_value(res)?;
+        assert_eq!(res["ids"]["buckets"].as_array().unwrap().len(), 1_000);
+        assert_eq!(
+            reader.search_budget_usage(),
+            Some(SearchBudgetUsage::default())
+        );
+        Ok(())
+    }
+
+    /// Panics when collecting a segment.
+    struct PanickingCollector;
+
+    impl Collector for PanickingCollector {
+        type Fruit = usize;
+        type Child = <Count as Collector>::Child;
+
+        fn for_segment(
+            &self,
+            _segment_local_id: SegmentOrdinal,
+            _segment: &SegmentReader,
+        ) -> crate::Result<Self::Child> {
+            panic!("collector failure");
+        }
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn merge_fruits(&self, segment_counts: Vec<usize>) -> crate::Result<usize> {
+            Count.merge_fruits(segment_counts)
+        }
+    }
+
+    #[test]
+    fn test_search_budget_released_on_panic() -> crate::Result<()> {
+        let reader = create_reader()?;
+        reader.set_search_budget(GlobalSearchBudget {
+            when_exhausted: BudgetExhaustedPolicy::FailFast,
+            ..GlobalSearchBudget::new(1, 1_000)
+        });
+        let searcher = reader.searcher();
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            searcher.search_with_estimate(&AllQuery, &PanickingCollector, 1_000)
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(
+            reader.search_budget_usage(),
+            Some(SearchBudgetUsage::default())
+        );
+        assert_eq!(
+            searcher.search_with_estimate(&AllQuery, &Count, 1_000)?,
+            1_000
+        );
+        Ok(())
+    }
+}
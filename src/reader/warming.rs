@@ -175,14 +175,16 @@ mod tests {
     use std::collections::HashSet;
     use std::sync::atomic::{self, AtomicUsize};
     use std::sync::{Arc, RwLock, Weak};
+    use std::time::{Duration, Instant};
 
     use super::Warmer;
     use crate::core::searcher::SearcherGeneration;
     use crate::directory::RamDirectory;
     use crate::index::SegmentId;
     use crate::indexer::index_writer::MEMORY_BUDGET_NUM_BYTES_MIN;
+    use crate::indexer::NoMergePolicy;
     use crate::schema::{Schema, INDEXED};
-    use crate::{Index, IndexSettings, ReloadPolicy, Searcher};
+    use crate::{Index, IndexSettings, IndexWriter, ReloadPolicy, Searcher};
 
     #[derive(Default)]
     struct TestWarmer {
@@ -342,4 +344,45 @@ mod tests {
     fn warming_four_threads() -> crate::Result<()> {
         test_warming(4)
     }
+
+    #[test]
+    fn warming_debounced_reloads() -> crate::Result<()> {
+        let debounce = Duration::from_millis(100);
+        let mut schema_builder = Schema::builder();
+        let field = schema_builder.add_u64_field("pk", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut writer: IndexWriter = index.writer_for_tests()?;
+        writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        let warmer = Arc::new(TestWarmer::default());
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommitWithDebounce(debounce))
+            .warmers(vec![Arc::downgrade(&warmer) as Weak<dyn Warmer>])
+            .try_into()?;
+        assert_eq!(warmer.warm_calls(), 1);
+
+        let mut last_opstamp = 0;
+        for i in 0u64..10u64 {
+            writer.add_document(doc!(field => i))?;
+            last_opstamp = writer.commit()?;
+        }
+
+        let start = Instant::now();
+        while reader.searcher().num_docs() < 10 {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "commits were not loaded"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Leaves time for a reload that would wrongly follow the last one.
+        std::thread::sleep(debounce * 2);
+
+        let searcher = reader.searcher();
+        assert_eq!(searcher.num_docs(), 10);
+        assert_eq!(reader.inner.searcher.load().commit_opstamp(), last_opstamp);
+        assert!(warmer.warm_calls() - 1 <= 2);
+        Ok(())
+    }
 }
@@ -297,6 +297,8 @@ pub struct BinaryDocumentDeserializer<'de, R> {
     position: usize,
     doc_store_version: DocStoreVersion,
     reader: &'de mut R,
+    /// The fields to deserialize, or `None` to deserialize all of them.
+    fields: Option<&'de [Field]>,
 }
 
 impl<'de, R> BinaryDocumentDeserializer<'de, R>
@@ -314,9 +316,41 @@ where R: Read
             position: 0,
             doc_store_version,
             reader,
+            fields: None,
         })
     }
 
+    /// Only deserializes the values of `fields`, skipping the values of the other fields.
+    pub(crate) fn with_fields(mut self, fields: &'de [Field]) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+
+    fn is_requested(&self, field: Field) -> bool {
+        self.fields.is_none_or(|fields| fields.contains(&field))
+    }
+
+    /// Skips the value of a field which is not requested.
+    fn skip_value(&mut self) -> Result<(), DeserializeError> {
+        if self.doc_store_version >= DocStoreVersion::V3 {
+            let num_bytes = VInt::deserialize(self.reader)?.val();
+            let num_skipped_bytes =
+                io::copy(&mut (&mut *self.reader).take(num_bytes), &mut io::sink())?;
+            if num_skipped_bytes != num_bytes {
+                return Err(DeserializeError::from(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The document ends within a value",
+                )));
+            }
+        } else {
+            // The older formats do not record the length of the values, which have to be parsed.
+            let deserializer =
+                BinaryValueDeserializer::from_reader(self.reader, self.doc_store_version)?;
+            <OwnedValue as ValueDeserialize>::deserialize(deserializer)?;
+        }
+        Ok(())
+    }
+
     /// Returns true if the deserializer has deserialized all the entries
     /// within the document.
     fn is_complete(&self) -> bool {
@@ -333,11 +367,21 @@ where R: Read
     }
 
     fn next_field<V: ValueDeserialize>(&mut self) -> Result<Option<(Field, V)>, DeserializeError> {
-        if self.is_complete() {
-            return Ok(None);
+        let field = loop {
+            if self.is_complete() {
+                return Ok(None);
+            }
+            let field = Field::deserialize(self.reader).map_err(DeserializeError::from)?;
+            if self.is_requested(field) {
+                break field;
+            }
+            self.skip_value()?;
+            self.position += 1;
+        };
+        if self.doc_store_version >= DocStoreVersion::V3 {
+            // The length of the value is only needed to skip it.
+            VInt::deserialize(self.reader)?;
         }
-
-        let field = Field::deserialize(self.reader).map_err(DeserializeError::from)?;
         let deserializer =
             BinaryValueDeserializer::from_reader(self.reader, self.doc_store_version)?;
         let value = V::deserialize(deserializer)?;
@@ -460,7 +504,7 @@ where R: Read
                 let timestamp_micros = <i64 as BinarySerializable>::deserialize(self.reader)?;
                 Ok(DateTime::from_timestamp_micros(timestamp_micros))
             }
-            DocStoreVersion::V2 => {
+            DocStoreVersion::V2 | DocStoreVersion::V3 => {
                 let timestamp_nanos = <i64 as BinarySerializable>::deserialize(self.reader)?;
                 Ok(DateTime::from_timestamp_nanos(timestamp_nanos))
             }
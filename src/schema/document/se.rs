@@ -8,11 +8,13 @@ use common::{f64_to_u64, BinarySerializable, VInt};
 use super::{OwnedValue, ReferenceValueLeaf};
 use crate::schema::document::{type_codes, Document, ReferenceValue, Value};
 use crate::schema::Schema;
+use crate::store::{DocStoreVersion, DOC_STORE_VERSION};
 
 /// A serializer writing documents which implement [`Document`] to a provided writer.
 pub struct BinaryDocumentSerializer<'se, W> {
     writer: &'se mut W,
     schema: &'se Schema,
+    doc_store_version: DocStoreVersion,
 }
 
 impl<'se, W> BinaryDocumentSerializer<'se, W>
@@ -20,7 +22,17 @@ where W: Write
 {
     /// Creates a new serializer with a provided writer.
     pub(crate) fn new(writer: &'se mut W, schema: &'se Schema) -> Self {
-        Self { writer, schema }
+        Self {
+            writer,
+            schema,
+            doc_store_version: DOC_STORE_VERSION,
+        }
+    }
+
+    /// Serializes the documents in the format of the given version of the doc store.
+    pub(crate) fn with_doc_store_version(mut self, doc_store_version: DocStoreVersion) -> Self {
+        self.doc_store_version = doc_store_version;
+        self
     }
 
     /// Attempts to serialize a given document and write the output
@@ -34,21 +46,20 @@ where W: Write
         };
         let num_field_values = stored_field_values().count();
         let mut actual_length = 0;
+        let with_value_lengths = self.doc_store_version >= DocStoreVersion::V3;
+        let mut value_buffer = Vec::new();
 
         VInt(num_field_values as u64).serialize(self.writer)?;
         for (field, value_access) in stored_field_values() {
             field.serialize(self.writer)?;
 
-            let mut serializer = BinaryValueSerializer::new(self.writer);
-            match value_access.as_value() {
-                ReferenceValue::Leaf(ReferenceValueLeaf::PreTokStr(pre_tokenized_text)) => {
-                    serializer.serialize_value(ReferenceValue::Leaf::<&'_ OwnedValue>(
-                        ReferenceValueLeaf::Str(&pre_tokenized_text.text),
-                    ))?;
-                }
-                _ => {
-                    serializer.serialize_value(value_access.as_value())?;
-                }
+            if with_value_lengths {
+                value_buffer.clear();
+                serialize_stored_value(&mut value_buffer, value_access.as_value())?;
+                VInt(value_buffer.len() as u64).serialize(self.writer)?;
+                self.writer.write_all(&value_buffer)?;
+            } else {
+                serialize_stored_value(self.writer, value_access.as_value())?;
             }
 
             actual_length += 1;
@@ -66,6 +77,36 @@ where W: Write
 
         Ok(())
     }
+
+    /// Serializes all of the field values of a document, stored or not, and writes the output
+    /// to the writer.
+    ///
+    /// Contrary to [`Self::serialize_doc()`], pre-tokenized text is kept as is.
+    pub(crate) fn serialize_doc_all_fields<D>(&mut self, doc: &D) -> io::Result<()>
+    where D: Document {
+        let field_values: Vec<_> = doc.iter_fields_and_values().collect();
+        VInt(field_values.len() as u64).serialize(self.writer)?;
+        for (field, value_access) in field_values {
+            field.serialize(self.writer)?;
+            BinaryValueSerializer::new(self.writer).serialize_value(value_access.as_value())?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the value of a stored field, pre-tokenized text being stored as text.
+fn serialize_stored_value<'a, W: Write, V: Value<'a>>(
+    writer: &mut W,
+    value: ReferenceValue<'a, V>,
+) -> io::Result<()> {
+    let mut serializer = BinaryValueSerializer::new(writer);
+    match value {
+        ReferenceValue::Leaf(ReferenceValueLeaf::PreTokStr(pre_tokenized_text)) => serializer
+            .serialize_value(ReferenceValue::Leaf::<&'_ OwnedValue>(
+                ReferenceValueLeaf::Str(&pre_tokenized_text.text),
+            )),
+        value => serializer.serialize_value(value),
+    }
 }
 
 /// A serializer for a single value.
@@ -670,7 +711,8 @@ mod tests {
     fn serialize_doc<D: Document>(doc: &D, schema: &Schema) -> Vec<u8> {
         let mut writer = Vec::new();
 
-        let mut serializer = BinaryDocumentSerializer::new(&mut writer, schema);
+        let mut serializer = BinaryDocumentSerializer::new(&mut writer, schema)
+            .with_doc_store_version(DocStoreVersion::V2);
         serializer.serialize_doc(doc).expect("Serialize value");
 
         writer
@@ -749,4 +791,39 @@ mod tests {
             "Expected serialized document to match the binary representation"
         );
     }
+
+    #[test]
+    fn test_document_serialize_with_value_lengths() {
+        let mut builder = Schema::builder();
+        let name = builder.add_text_field("name", TEXT | STORED);
+        let age = builder.add_u64_field("age", FAST | STORED);
+        let schema = builder.build();
+
+        let mut document = BTreeMap::new();
+        document.insert(name, crate::schema::OwnedValue::Str("ChillFish8".into()));
+        document.insert(age, crate::schema::OwnedValue::U64(20));
+
+        let mut result = Vec::new();
+        BinaryDocumentSerializer::new(&mut result, &schema)
+            .with_doc_store_version(DocStoreVersion::V3)
+            .serialize_doc(&document)
+            .unwrap();
+        let mut expected = expected_doc_data!(length document.len());
+        let name_value = binary_repr!(type_codes::TEXT_CODE => String::from("ChillFish8"));
+        name.serialize(&mut expected).unwrap();
+        VInt(name_value.len() as u64)
+            .serialize(&mut expected)
+            .unwrap();
+        expected.extend_from_slice(&name_value);
+        let age_value = binary_repr!(type_codes::U64_CODE => 20u64);
+        age.serialize(&mut expected).unwrap();
+        VInt(age_value.len() as u64)
+            .serialize(&mut expected)
+            .unwrap();
+        expected.extend_from_slice(&age_value);
+        assert_eq!(
+            result, expected,
+            "Expected serialized document to match the binary representation"
+        );
+    }
 }
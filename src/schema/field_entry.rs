@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+use super::geo_point_options::GeoPointOptions;
 use super::ip_options::IpAddrOptions;
 use crate::schema::bytes_options::BytesOptions;
 use crate::schema::{
@@ -65,6 +66,11 @@ impl FieldEntry {
         Self::new(field_name, FieldType::IpAddr(ip_options))
     }
 
+    /// Creates a new geo point field entry.
+    pub fn new_geo_point(field_name: String, geo_point_options: GeoPointOptions) -> FieldEntry {
+        Self::new(field_name, FieldType::GeoPoint(geo_point_options))
+    }
+
     /// Creates a field entry for a facet.
     pub fn new_facet(field_name: String, facet_options: FacetOptions) -> FieldEntry {
         Self::new(field_name, FieldType::Facet(facet_options))
@@ -129,6 +135,7 @@ impl FieldEntry {
             FieldType::Bytes(ref options) => options.is_stored(),
             FieldType::JsonObject(ref options) => options.is_stored(),
             FieldType::IpAddr(ref options) => options.is_stored(),
+            FieldType::GeoPoint(ref options) => options.is_stored(),
         }
     }
 }
@@ -8,12 +8,13 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use thiserror::Error;
 
+use super::geo_point_options::GeoPointOptions;
 use super::ip_options::IpAddrOptions;
 use super::IntoIpv6Addr;
 use crate::schema::bytes_options::BytesOptions;
 use crate::schema::facet_options::FacetOptions;
 use crate::schema::{
-    DateOptions, Facet, IndexRecordOption, JsonObjectOptions, NumericOptions, OwnedValue,
+    DateOptions, Facet, GeoPoint, IndexRecordOption, JsonObjectOptions, NumericOptions, OwnedValue,
     TextFieldIndexing, TextOptions,
 };
 use crate::time::format_description::well_known::Rfc3339;
@@ -189,6 +190,8 @@ pub enum FieldType {
     JsonObject(JsonObjectOptions),
     /// IpAddr field
     IpAddr(IpAddrOptions),
+    /// Geo point field
+    GeoPoint(GeoPointOptions),
 }
 
 impl FieldType {
@@ -205,6 +208,8 @@ impl FieldType {
             FieldType::Bytes(_) => Type::Bytes,
             FieldType::JsonObject(_) => Type::Json,
             FieldType::IpAddr(_) => Type::IpAddr,
+            // Geo points are indexed as their code on the Z-order curve.
+            FieldType::GeoPoint(_) => Type::U64,
         }
     }
 
@@ -218,6 +223,11 @@ impl FieldType {
         matches!(self, FieldType::IpAddr(_))
     }
 
+    /// returns true if this is a geo point field
+    pub fn is_geo_point(&self) -> bool {
+        matches!(self, FieldType::GeoPoint(_))
+    }
+
     /// returns true if this is an str field
     pub fn is_str(&self) -> bool {
         matches!(self, FieldType::Str(_))
@@ -241,6 +251,7 @@ impl FieldType {
             FieldType::Bytes(ref bytes_options) => bytes_options.is_indexed(),
             FieldType::JsonObject(ref json_object_options) => json_object_options.is_indexed(),
             FieldType::IpAddr(ref ip_addr_options) => ip_addr_options.is_indexed(),
+            FieldType::GeoPoint(ref geo_point_options) => geo_point_options.is_indexed(),
         }
     }
 
@@ -276,6 +287,7 @@ impl FieldType {
             | FieldType::Bool(ref int_options) => int_options.is_fast(),
             FieldType::Date(ref date_options) => date_options.is_fast(),
             FieldType::IpAddr(ref ip_addr_options) => ip_addr_options.is_fast(),
+            FieldType::GeoPoint(ref geo_point_options) => geo_point_options.is_fast(),
             FieldType::Facet(_) => true,
             FieldType::JsonObject(ref json_object_options) => json_object_options.is_fast(),
         }
@@ -297,6 +309,7 @@ impl FieldType {
             FieldType::Bytes(ref bytes_options) => bytes_options.fieldnorms(),
             FieldType::JsonObject(ref _json_object_options) => false,
             FieldType::IpAddr(ref ip_addr_options) => ip_addr_options.fieldnorms(),
+            FieldType::GeoPoint(_) => false,
         }
     }
 
@@ -348,6 +361,13 @@ impl FieldType {
                     None
                 }
             }
+            FieldType::GeoPoint(ref geo_point_options) => {
+                if geo_point_options.is_indexed() {
+                    Some(IndexRecordOption::Basic)
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -449,6 +469,10 @@ impl FieldType {
 
                         Ok(OwnedValue::IpAddr(ip_addr.into_ipv6_addr()))
                     }
+                    FieldType::GeoPoint(_) => Err(ValueParsingError::TypeError {
+                        expected: "a geo point object",
+                        json: JsonValue::String(field_text),
+                    }),
                 }
             }
             JsonValue::Number(field_val_num) => match self {
@@ -508,6 +532,10 @@ impl FieldType {
                     expected: "a string with an ip addr",
                     json: JsonValue::Number(field_val_num),
                 }),
+                FieldType::GeoPoint(_) => Err(ValueParsingError::TypeError {
+                    expected: "a geo point object",
+                    json: JsonValue::Number(field_val_num),
+                }),
             },
             JsonValue::Object(json_map) => match self {
                 FieldType::Str(_) => {
@@ -523,6 +551,16 @@ impl FieldType {
                     }
                 }
                 FieldType::JsonObject(_) => Ok(OwnedValue::from(json_map)),
+                FieldType::GeoPoint(_) => {
+                    let geo_point_value = OwnedValue::from(json_map.clone());
+                    match GeoPoint::from_value(&geo_point_value) {
+                        Some(geo_point) => Ok(OwnedValue::from(geo_point)),
+                        None => Err(ValueParsingError::TypeError {
+                            expected: "a geo point object with a valid lat and lon",
+                            json: JsonValue::Object(json_map),
+                        }),
+                    }
+                }
                 _ => Err(ValueParsingError::TypeError {
                     expected: self.value_type().name(),
                     json: JsonValue::Object(json_map),
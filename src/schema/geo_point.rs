@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+
+use super::document::{ReferenceValue, ReferenceValueLeaf};
+use super::{OwnedValue, Value};
+
+/// Number of bits of each coordinate in the code of a point.
+const NUM_COORDINATE_BITS: u32 = 32;
+
+/// Levels of the cells indexed as terms when a geo point field is indexed, as a number of bits
+/// of each coordinate.
+///
+/// A cell of level `l` is a `180° / 2^l` by `360° / 2^l` rectangle.
+pub(crate) const GEO_TERM_LEVELS: [u32; 6] = [4, 8, 12, 16, 20, 24];
+
+/// A point on the Earth, in degrees, as indexed by a
+/// [geo point field](crate::schema::SchemaBuilder::add_geo_point_field).
+///
+/// Geo points are passed to documents as objects with a `lat` and a `lon` key, which is the
+/// value `GeoPoint` converts into.
+///
+/// The points are indexed with a precision of about a centimeter: the latitude and the longitude
+/// are quantized to 32 bits, and interleaved into a code on the Z-order curve, so that the
+/// points of a rectangular cell have contiguous codes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    /// Latitude, between -90 and 90.
+    pub lat: f64,
+    /// Longitude, between -180 and 180.
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    /// Creates a geo point from its latitude and longitude, in degrees.
+    pub fn new(lat: f64, lon: f64) -> GeoPoint {
+        GeoPoint { lat, lon }
+    }
+
+    /// Returns true if the latitude is between -90 and 90, and the longitude between -180 and
+    /// 180.
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)
+    }
+
+    /// Returns the code of the point on the Z-order curve.
+    pub fn to_u64(self) -> u64 {
+        interleave(quantize_lat(self.lat), quantize_lon(self.lon))
+    }
+
+    /// Returns the point at the center of the cell of the code.
+    pub fn from_u64(code: u64) -> GeoPoint {
+        let (lat, lon) = deinterleave(code);
+        GeoPoint {
+            lat: dequantize(lat, 180.0) - 90.0,
+            lon: dequantize(lon, 360.0) - 180.0,
+        }
+    }
+
+    /// Reads a geo point from an object with a numerical `lat` and `lon`.
+    pub(crate) fn from_value<'a, V: Value<'a>>(value: V) -> Option<GeoPoint> {
+        let mut lat = None;
+        let mut lon = None;
+        for (key, value) in value.as_object()? {
+            match key {
+                "lat" => lat = as_coordinate(value),
+                "lon" => lon = as_coordinate(value),
+                _ => {}
+            }
+        }
+        let geo_point = GeoPoint::new(lat?, lon?);
+        geo_point.is_valid().then_some(geo_point)
+    }
+}
+
+fn as_coordinate<'a, V: Value<'a>>(value: V) -> Option<f64> {
+    match value.as_value() {
+        ReferenceValue::Leaf(ReferenceValueLeaf::F64(val)) => Some(val),
+        ReferenceValue::Leaf(ReferenceValueLeaf::I64(val)) => Some(val as f64),
+        ReferenceValue::Leaf(ReferenceValueLeaf::U64(val)) => Some(val as f64),
+        _ => None,
+    }
+}
+
+impl From<GeoPoint> for OwnedValue {
+    fn from(geo_point: GeoPoint) -> OwnedValue {
+        OwnedValue::Object(vec![
+            ("lat".to_string(), OwnedValue::F64(geo_point.lat)),
+            ("lon".to_string(), OwnedValue::F64(geo_point.lon)),
+        ])
+    }
+}
+
+fn quantize(val: f64, span: f64) -> u32 {
+    let scaled = val / span * (1u64 << NUM_COORDINATE_BITS) as f64;
+    scaled.clamp(0.0, u32::MAX as f64) as u32
+}
+
+fn dequantize(quantized: u32, span: f64) -> f64 {
+    (quantized as f64 + 0.5) / (1u64 << NUM_COORDINATE_BITS) as f64 * span
+}
+
+/// Quantizes a latitude to 32 bits. The quantization is monotonic.
+pub(crate) fn quantize_lat(lat: f64) -> u32 {
+    quantize(lat + 90.0, 180.0)
+}
+
+/// Quantizes a longitude to 32 bits. The quantization is monotonic.
+pub(crate) fn quantize_lon(lon: f64) -> u32 {
+    quantize(lon + 180.0, 360.0)
+}
+
+fn spread_bits(val: u32) -> u64 {
+    let mut val = val as u64;
+    val = (val | (val << 16)) & 0x0000_FFFF_0000_FFFF;
+    val = (val | (val << 8)) & 0x00FF_00FF_00FF_00FF;
+    val = (val | (val << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    val = (val | (val << 2)) & 0x3333_3333_3333_3333;
+    (val | (val << 1)) & 0x5555_5555_5555_5555
+}
+
+fn compact_bits(val: u64) -> u32 {
+    let mut val = val & 0x5555_5555_5555_5555;
+    val = (val | (val >> 1)) & 0x3333_3333_3333_3333;
+    val = (val | (val >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    val = (val | (val >> 4)) & 0x00FF_00FF_00FF_00FF;
+    val = (val | (val >> 8)) & 0x0000_FFFF_0000_FFFF;
+    (val | (val >> 16)) as u32
+}
+
+/// Interleaves the bits of the quantized coordinates, the latitude taking the odd bits.
+///
+/// The `2 * l` highest bits of a code are the code of its cell of level `l`.
+pub(crate) fn interleave(lat: u32, lon: u32) -> u64 {
+    (spread_bits(lat) << 1) | spread_bits(lon)
+}
+
+/// Returns the quantized latitude and longitude of a code.
+pub(crate) fn deinterleave(code: u64) -> (u32, u32) {
+    (compact_bits(code >> 1), compact_bits(code))
+}
+
+/// Returns the value of the term of the cell of level `level` containing `code`.
+///
+/// The level is stored in the highest byte, so that the terms of different levels do not
+/// collide.
+pub(crate) fn geo_cell_term_value(cell_code: u64, level: u32) -> u64 {
+    (u64::from(level) << 56) | (cell_code >> (64 - 2 * level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        deinterleave, geo_cell_term_value, interleave, quantize_lat, quantize_lon, GeoPoint,
+    };
+    use crate::schema::OwnedValue;
+
+    #[test]
+    fn test_geo_point_encoding() {
+        for (lat, lon) in [
+            (0.0, 0.0),
+            (-90.0, -180.0),
+            (90.0, 180.0),
+            (48.8566, 2.3522),
+        ] {
+            let geo_point = GeoPoint::new(lat, lon);
+            let code = geo_point.to_u64();
+            let decoded = GeoPoint::from_u64(code);
+            assert!((decoded.lat - lat).abs() < 1e-7);
+            assert!((decoded.lon - lon).abs() < 1e-7);
+            assert_eq!(decoded.to_u64(), code);
+        }
+        assert_eq!(deinterleave(interleave(u32::MAX, 3)), (u32::MAX, 3));
+        // Codes are ordered by cell.
+        let code = GeoPoint::new(10.0, 20.0).to_u64();
+        assert_eq!(
+            code >> 62,
+            interleave(quantize_lat(10.0), quantize_lon(20.0)) >> 62
+        );
+        assert_eq!(geo_cell_term_value(u64::MAX, 4), (4 << 56) | 0xFF);
+    }
+
+    #[test]
+    fn test_geo_point_from_value() {
+        let value = OwnedValue::from(GeoPoint::new(1.5, -2.0));
+        assert_eq!(GeoPoint::from_value(&value), Some(GeoPoint::new(1.5, -2.0)));
+        let value = OwnedValue::Object(vec![
+            ("lon".to_string(), OwnedValue::I64(3)),
+            ("lat".to_string(), OwnedValue::U64(4)),
+        ]);
+        assert_eq!(GeoPoint::from_value(&value), Some(GeoPoint::new(4.0, 3.0)));
+        let out_of_range = OwnedValue::from(GeoPoint::new(91.0, 0.0));
+        assert_eq!(GeoPoint::from_value(&out_of_range), None);
+        assert_eq!(GeoPoint::from_value(&OwnedValue::F64(1.0)), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::ops::BitOr;
+
+use serde::{Deserialize, Serialize};
+
+use super::flags::{FastFlag, IndexedFlag, SchemaFlagList, StoredFlag};
+
+/// Define how a geo point field should be handled by tantivy.
+///
+/// Geo points are searched with [`GeoBoundingBoxQuery`](crate::query::GeoBoundingBoxQuery) and
+/// [`GeoDistanceQuery`](crate::query::GeoDistanceQuery), which require the field to be fast.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct GeoPointOptions {
+    fast: bool,
+    stored: bool,
+    indexed: bool,
+}
+
+impl GeoPointOptions {
+    /// Returns true iff the value is a fast field.
+    #[inline]
+    pub fn is_fast(&self) -> bool {
+        self.fast
+    }
+
+    /// Returns `true` if the geo point should be stored in the doc store.
+    #[inline]
+    pub fn is_stored(&self) -> bool {
+        self.stored
+    }
+
+    /// Returns true iff the cells of the geo point are indexed.
+    #[inline]
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Sets the field as stored
+    #[must_use]
+    pub fn set_stored(mut self) -> Self {
+        self.stored = true;
+        self
+    }
+
+    /// Set the field as indexed.
+    ///
+    /// Setting a geo point as indexed will generate a posting list for each of the cells
+    /// containing it, at several precision levels. The geo queries then use them to find the
+    /// candidate documents instead of scanning the fast field.
+    #[must_use]
+    pub fn set_indexed(mut self) -> Self {
+        self.indexed = true;
+        self
+    }
+
+    /// Set the field as a fast field.
+    ///
+    /// The fast field holds the code of the geo points on the Z-order curve, see
+    /// [`GeoPoint::to_u64()`](crate::schema::GeoPoint::to_u64). This is required for the field
+    /// to be searchable.
+    #[must_use]
+    pub fn set_fast(mut self) -> Self {
+        self.fast = true;
+        self
+    }
+}
+
+impl From<()> for GeoPointOptions {
+    fn from(_: ()) -> GeoPointOptions {
+        GeoPointOptions::default()
+    }
+}
+
+impl From<FastFlag> for GeoPointOptions {
+    fn from(_: FastFlag) -> Self {
+        GeoPointOptions {
+            indexed: false,
+            stored: false,
+            fast: true,
+        }
+    }
+}
+
+impl From<StoredFlag> for GeoPointOptions {
+    fn from(_: StoredFlag) -> Self {
+        GeoPointOptions {
+            indexed: false,
+            stored: true,
+            fast: false,
+        }
+    }
+}
+
+impl From<IndexedFlag> for GeoPointOptions {
+    fn from(_: IndexedFlag) -> Self {
+        GeoPointOptions {
+            indexed: true,
+            stored: false,
+            fast: false,
+        }
+    }
+}
+
+impl<T: Into<GeoPointOptions>> BitOr<T> for GeoPointOptions {
+    type Output = GeoPointOptions;
+
+    fn bitor(self, other: T) -> GeoPointOptions {
+        let other = other.into();
+        GeoPointOptions {
+            indexed: self.indexed | other.indexed,
+            stored: self.stored | other.stored,
+            fast: self.fast | other.fast,
+        }
+    }
+}
+
+impl<Head, Tail> From<SchemaFlagList<Head, Tail>> for GeoPointOptions
+where
+    Head: Clone,
+    Tail: Clone,
+    Self: BitOr<Output = Self> + From<Head> + From<Tail>,
+{
+    fn from(head_tail: SchemaFlagList<Head, Tail>) -> Self {
+        Self::from(head_tail.head) | Self::from(head_tail.tail)
+    }
+}
@@ -119,6 +119,8 @@ mod bytes_options;
 mod date_time_options;
 mod field;
 mod flags;
+mod geo_point;
+mod geo_point_options;
 mod index_record_option;
 mod ip_options;
 mod json_object_options;
@@ -138,13 +140,18 @@ pub use self::field::Field;
 pub use self::field_entry::FieldEntry;
 pub use self::field_type::{FieldType, Type};
 pub use self::flags::{COERCE, FAST, INDEXED, STORED};
+pub use self::geo_point::GeoPoint;
+pub(crate) use self::geo_point::{
+    deinterleave, geo_cell_term_value, interleave, quantize_lat, quantize_lon, GEO_TERM_LEVELS,
+};
+pub use self::geo_point_options::GeoPointOptions;
 pub use self::index_record_option::IndexRecordOption;
 pub use self::ip_options::{IntoIpv6Addr, IpAddrOptions};
 pub use self::json_object_options::JsonObjectOptions;
 pub use self::named_field_document::NamedFieldDocument;
 pub use self::numeric_options::NumericOptions;
 pub use self::schema::{Schema, SchemaBuilder};
-pub use self::term::{Term, ValueBytes};
+pub use self::term::{Term, TermBuffer, TermRef, ValueBytes};
 pub use self::text_options::{TextFieldIndexing, TextOptions, STRING, TEXT};
 
 /// Validator for a potential `field_name`.
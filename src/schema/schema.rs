@@ -139,6 +139,24 @@ impl SchemaBuilder {
         self.add_field(field_entry)
     }
 
+    /// Adds a geo point field.
+    /// Returns the associated field handle.
+    ///
+    /// The values of the field are [`GeoPoint`](crate::schema::GeoPoint)s.
+    ///
+    /// # Panics
+    ///
+    /// Panics when field already exists.
+    pub fn add_geo_point_field<T: Into<GeoPointOptions>>(
+        &mut self,
+        field_name_str: &str,
+        field_options: T,
+    ) -> Field {
+        let field_name = String::from(field_name_str);
+        let field_entry = FieldEntry::new_geo_point(field_name, field_options.into());
+        self.add_field(field_entry)
+    }
+
     /// Adds a new text field.
     /// Returns the associated field handle
     ///
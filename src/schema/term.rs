@@ -330,6 +330,119 @@ where B: AsRef<[u8]>
     }
 }
 
+/// Borrowed view of a [`Term`], e.g. the term of a [`TermBuffer`].
+pub type TermRef<'a> = Term<&'a [u8]>;
+
+/// Reusable buffer building the terms of a field.
+///
+/// Unlike the `Term::from_field_*` constructors, the setters reuse the buffer of the previous
+/// term: building terms in a loop only allocates when a term is longer than all the previous
+/// ones. The resulting [`TermRef`] can be passed to
+/// [`IndexWriter::delete_term_ref`](crate::IndexWriter::delete_term_ref).
+///
+/// ```rust
+/// use tantivy::schema::{Schema, TermBuffer, STRING};
+/// use tantivy::{doc, Index, IndexWriter};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let id = schema_builder.add_text_field("id", STRING);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer(50_000_000)?;
+/// for doc_id in ["a", "b", "c"] {
+///     index_writer.add_document(doc!(id => doc_id))?;
+/// }
+/// index_writer.commit()?;
+///
+/// let mut term_buffer = TermBuffer::new(id);
+/// for doc_id in ["a", "c"] {
+///     term_buffer.set_text(doc_id);
+///     index_writer.delete_term_ref(&term_buffer.term_ref())?;
+/// }
+/// index_writer.commit()?;
+/// assert_eq!(index.reader()?.searcher().num_docs(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TermBuffer {
+    field: Field,
+    term: Term,
+}
+
+impl TermBuffer {
+    /// Creates an empty buffer for the terms of `field`.
+    pub fn new(field: Field) -> TermBuffer {
+        TermBuffer {
+            field,
+            term: Term::with_type_and_field(Type::Str, field),
+        }
+    }
+
+    /// The field of the terms.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// The current term.
+    pub fn term_ref(&self) -> TermRef<'_> {
+        Term::wrap(self.term.serialized_term())
+    }
+
+    /// Sets a `str` term.
+    pub fn set_text(&mut self, text: &str) {
+        self.term.clear_with_field_and_type(Type::Str, self.field);
+        self.term.set_bytes(text.as_bytes());
+    }
+
+    /// Sets a `u64` term.
+    pub fn set_u64(&mut self, val: u64) {
+        self.set_fast_value(val);
+    }
+
+    /// Sets a `i64` term.
+    pub fn set_i64(&mut self, val: i64) {
+        self.set_fast_value(val);
+    }
+
+    /// Sets a `f64` term.
+    pub fn set_f64(&mut self, val: f64) {
+        self.set_fast_value(val);
+    }
+
+    /// Sets a `bool` term.
+    pub fn set_bool(&mut self, val: bool) {
+        self.set_fast_value(val);
+    }
+
+    /// Sets a `DateTime` term.
+    ///
+    /// Like [`Term::from_field_date_for_search`], the value is truncated to the precision used
+    /// in the index.
+    pub fn set_date(&mut self, val: DateTime) {
+        self.set_fast_value(val.truncate(DATE_TIME_PRECISION_INDEXED));
+    }
+
+    /// Sets a `Ipv6Addr` term.
+    pub fn set_ip_addr(&mut self, val: Ipv6Addr) {
+        self.term
+            .clear_with_field_and_type(Type::IpAddr, self.field);
+        self.term.set_ip_addr(val);
+    }
+
+    /// Sets a `Bytes` term.
+    pub fn set_bytes(&mut self, bytes: &[u8]) {
+        self.term.clear_with_field_and_type(Type::Bytes, self.field);
+        self.term.set_bytes(bytes);
+    }
+
+    fn set_fast_value<T: FastValue>(&mut self, val: T) {
+        self.term
+            .clear_with_field_and_type(T::to_type(), self.field);
+        self.term.set_fast_value(val);
+    }
+}
+
 /// ValueBytes represents a serialized value.
 ///
 /// The value can be of any type of [`Type`] (e.g. string, u64, f64, bool, date, JSON).
@@ -658,6 +771,60 @@ mod tests {
         assert_eq!(term.value().as_u64(), Some(983u64))
     }
 
+    #[test]
+    pub fn test_term_buffer() {
+        let field = Field::from_field_id(3);
+        let mut term_buffer = TermBuffer::new(field);
+        assert_eq!(term_buffer.field(), field);
+        term_buffer.set_text("hello");
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_text(field, "hello").serialized_term()
+        );
+        term_buffer.set_u64(983);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_u64(field, 983).serialized_term()
+        );
+        term_buffer.set_i64(-3);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_i64(field, -3).serialized_term()
+        );
+        term_buffer.set_text("hi");
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_text(field, "hi").serialized_term()
+        );
+        term_buffer.set_f64(1.5);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_f64(field, 1.5).serialized_term()
+        );
+        term_buffer.set_bool(true);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_bool(field, true).serialized_term()
+        );
+        let date = crate::DateTime::from_timestamp_nanos(1_234_567_890);
+        term_buffer.set_date(date);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_date_for_search(field, date).serialized_term()
+        );
+        term_buffer.set_bytes(b"abc");
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_bytes(field, b"abc").serialized_term()
+        );
+        let ip_addr = std::net::Ipv6Addr::LOCALHOST;
+        term_buffer.set_ip_addr(ip_addr);
+        assert_eq!(
+            term_buffer.term_ref().serialized_term(),
+            Term::from_field_ip_addr(field, ip_addr).serialized_term()
+        );
+    }
+
     #[test]
     pub fn test_term_bool() {
         let mut schema_builder = Schema::builder();
@@ -194,6 +194,8 @@ impl TokenizerName {
 /// - The name of the `Tokenizer` that should be used to process the field.
 /// - Flag indicating, if fieldnorms should be stored (See [fieldnorm](crate::fieldnorm)). Defaults
 ///   to `true`.
+/// - Flag indicating, if the exact number of tokens of each document should be written to a hidden
+///   fast field column, for the `token_count_field` of metric aggregations. Defaults to `false`.
 #[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
 pub struct TextFieldIndexing {
     #[serde(default)]
@@ -202,6 +204,8 @@ pub struct TextFieldIndexing {
     fieldnorms: bool,
     #[serde(default)]
     tokenizer: TokenizerName,
+    #[serde(default, skip_serializing_if = "is_false")]
+    exact_token_count: bool,
 }
 
 pub(crate) fn default_fieldnorms() -> bool {
@@ -214,6 +218,7 @@ impl Default for TextFieldIndexing {
             tokenizer: TokenizerName::default(),
             record: IndexRecordOption::default(),
             fieldnorms: default_fieldnorms(),
+            exact_token_count: false,
         }
     }
 }
@@ -243,6 +248,23 @@ impl TextFieldIndexing {
         self.fieldnorms
     }
 
+    /// Sets whether the exact number of tokens of each document is written to a fast field
+    /// column.
+    ///
+    /// Fieldnorms only store an approximation of the number of tokens of long texts. The exact
+    /// count is read by the metric aggregations over the `token_count_field` of the field.
+    #[must_use]
+    pub fn set_exact_token_count(mut self, exact_token_count: bool) -> TextFieldIndexing {
+        self.exact_token_count = exact_token_count;
+        self
+    }
+
+    /// Returns true if and only if the exact number of tokens of each document is written to a
+    /// fast field column.
+    pub fn exact_token_count(&self) -> bool {
+        self.exact_token_count
+    }
+
     /// Sets which information should be indexed with the tokens.
     ///
     /// See [`IndexRecordOption`] for more detail.
@@ -265,6 +287,7 @@ pub const STRING: TextOptions = TextOptions {
     indexing: Some(TextFieldIndexing {
         tokenizer: TokenizerName::from_static(NO_TOKENIZER_NAME),
         fieldnorms: true,
+        exact_token_count: false,
         record: IndexRecordOption::Basic,
     }),
     stored: false,
@@ -277,6 +300,7 @@ pub const TEXT: TextOptions = TextOptions {
     indexing: Some(TextFieldIndexing {
         tokenizer: TokenizerName::from_static(DEFAULT_TOKENIZER_NAME),
         fieldnorms: true,
+        exact_token_count: false,
         record: IndexRecordOption::WithFreqsAndPositions,
     }),
     stored: false,
@@ -7,13 +7,14 @@
 //! storage-level details into consideration. For example, if your file system block size is 4096
 //! bytes, we can under-count actual resultant space usage by up to 4095 bytes per file.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::AddAssign;
 
 use common::ByteCount;
 use serde::{Deserialize, Serialize};
 
 use crate::index::SegmentComponent;
-use crate::schema::Field;
+use crate::schema::{Field, Schema};
 
 /// Enum containing any of the possible space usage results for segment components.
 pub enum ComponentSpaceUsage {
@@ -25,11 +26,56 @@ pub enum ComponentSpaceUsage {
     Basic(ByteCount),
 }
 
+/// Represents space usage of an entire index: the segments of its last commit, and its meta
+/// file.
+///
+/// Created with [`Index::space_usage`](crate::Index::space_usage).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexSpaceUsage {
+    segments: SearcherSpaceUsage,
+    meta: ByteCount,
+}
+
+impl IndexSpaceUsage {
+    pub(crate) fn new(segments: SearcherSpaceUsage, meta: ByteCount) -> IndexSpaceUsage {
+        IndexSpaceUsage { segments, meta }
+    }
+
+    /// Space usage of the segments, see [`SearcherSpaceUsage`].
+    pub fn segments(&self) -> &SearcherSpaceUsage {
+        &self.segments
+    }
+
+    /// Size of the files of each component, summed over all segments.
+    pub fn components(&self) -> ComponentsSpaceUsage {
+        self.segments.components()
+    }
+
+    /// Space usage attributed to each field, by field name, summed over all segments.
+    pub fn fields(&self) -> &BTreeMap<String, FieldSpaceUsage> {
+        self.segments.fields()
+    }
+
+    /// Size of the `meta.json` file.
+    pub fn meta(&self) -> ByteCount {
+        self.meta
+    }
+
+    /// Total size of the files of the index: the files of the segments, and `meta.json`.
+    pub fn total(&self) -> ByteCount {
+        self.components().total() + self.meta
+    }
+}
+
 /// Represents combined space usage of an entire searcher and its component segments.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SearcherSpaceUsage {
     segments: Vec<SegmentSpaceUsage>,
     total: ByteCount,
+    #[serde(default)]
+    components: ComponentsSpaceUsage,
+    #[serde(default)]
+    fields: BTreeMap<String, FieldSpaceUsage>,
 }
 
 impl SearcherSpaceUsage {
@@ -37,13 +83,26 @@ impl SearcherSpaceUsage {
         SearcherSpaceUsage {
             segments: Vec::new(),
             total: Default::default(),
+            components: Default::default(),
+            fields: BTreeMap::new(),
         }
     }
 
     /// Add a segment, to `self`.
     /// Performs no deduplication or other intelligence.
-    pub(crate) fn add_segment(&mut self, segment: SegmentSpaceUsage) {
+    pub(crate) fn add_segment(&mut self, segment: SegmentSpaceUsage, schema: &Schema) {
         self.total += segment.total();
+        self.components += segment.files();
+        for (field, field_entry) in schema.fields() {
+            let field_space_usage = segment.field(field);
+            if field_space_usage.total() == 0 {
+                continue;
+            }
+            *self
+                .fields
+                .entry(field_entry.name().to_string())
+                .or_default() += field_space_usage;
+        }
         self.segments.push(segment);
     }
 
@@ -57,6 +116,104 @@ impl SearcherSpaceUsage {
     pub fn total(&self) -> ByteCount {
         self.total
     }
+
+    /// Size of the files of each component, summed over all segments.
+    ///
+    /// Unlike [`SearcherSpaceUsage::total`], this includes the headers and footers of the
+    /// files, and adds up to the size of the files of the segments.
+    pub fn components(&self) -> ComponentsSpaceUsage {
+        self.components
+    }
+
+    /// Space usage attributed to each field, by field name, summed over all segments.
+    ///
+    /// The fields without any data are omitted.
+    pub fn fields(&self) -> &BTreeMap<String, FieldSpaceUsage> {
+        &self.fields
+    }
+}
+
+/// Size of the files of each component of one or more segments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentsSpaceUsage {
+    /// Term dictionaries.
+    pub termdict: ByteCount,
+    /// Postings lists.
+    pub postings: ByteCount,
+    /// Positions.
+    pub positions: ByteCount,
+    /// Fast fields.
+    pub fast_fields: ByteCount,
+    /// Field norms.
+    pub fieldnorms: ByteCount,
+    /// Document store.
+    pub store: ByteCount,
+    /// Deletes and soft deletes.
+    pub deletes: ByteCount,
+    /// Column statistics.
+    #[serde(default)]
+    pub column_stats: ByteCount,
+}
+
+impl ComponentsSpaceUsage {
+    /// Total size of the files.
+    pub fn total(&self) -> ByteCount {
+        self.termdict
+            + self.postings
+            + self.positions
+            + self.fast_fields
+            + self.fieldnorms
+            + self.store
+            + self.deletes
+            + self.column_stats
+    }
+}
+
+impl AddAssign for ComponentsSpaceUsage {
+    fn add_assign(&mut self, other: ComponentsSpaceUsage) {
+        self.termdict += other.termdict;
+        self.postings += other.postings;
+        self.positions += other.positions;
+        self.fast_fields += other.fast_fields;
+        self.fieldnorms += other.fieldnorms;
+        self.store += other.store;
+        self.deletes += other.deletes;
+        self.column_stats += other.column_stats;
+    }
+}
+
+/// Space usage attributed to a field, in each component storing data per field.
+///
+/// The document store compresses documents as a whole, so it is not attributed to fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSpaceUsage {
+    /// Term dictionary of the field.
+    pub termdict: ByteCount,
+    /// Postings lists of the field.
+    pub postings: ByteCount,
+    /// Positions of the field.
+    pub positions: ByteCount,
+    /// Fast field columns of the field, including the columns of the paths of a JSON field.
+    pub fast_fields: ByteCount,
+    /// Field norms of the field.
+    pub fieldnorms: ByteCount,
+}
+
+impl FieldSpaceUsage {
+    /// Total space usage attributed to the field.
+    pub fn total(&self) -> ByteCount {
+        self.termdict + self.postings + self.positions + self.fast_fields + self.fieldnorms
+    }
+}
+
+impl AddAssign for FieldSpaceUsage {
+    fn add_assign(&mut self, other: FieldSpaceUsage) {
+        self.termdict += other.termdict;
+        self.postings += other.postings;
+        self.positions += other.positions;
+        self.fast_fields += other.fast_fields;
+        self.fieldnorms += other.fieldnorms;
+    }
 }
 
 /// Represents combined space usage for all of the large components comprising a segment.
@@ -75,6 +232,9 @@ pub struct SegmentSpaceUsage {
     deletes: ByteCount,
 
     total: ByteCount,
+
+    #[serde(default)]
+    files: ComponentsSpaceUsage,
 }
 
 impl SegmentSpaceUsage {
@@ -88,6 +248,7 @@ impl SegmentSpaceUsage {
         fieldnorms: PerFieldSpaceUsage,
         store: StoreSpaceUsage,
         deletes: ByteCount,
+        files: ComponentsSpaceUsage,
     ) -> SegmentSpaceUsage {
         let total = termdict.total()
             + postings.total()
@@ -106,6 +267,7 @@ impl SegmentSpaceUsage {
             store,
             deletes,
             total,
+            files,
         }
     }
 
@@ -124,7 +286,8 @@ impl SegmentSpaceUsage {
             Terms => PerField(self.termdict().clone()),
             SegmentComponent::Store => ComponentSpaceUsage::Store(self.store().clone()),
             SegmentComponent::TempStore => ComponentSpaceUsage::Store(self.store().clone()),
-            Delete => Basic(self.deletes()),
+            Delete | SoftDelete => Basic(self.deletes()),
+            ColumnStats => Basic(self.files().column_stats),
         }
     }
 
@@ -163,7 +326,7 @@ impl SegmentSpaceUsage {
         &self.store
     }
 
-    /// Space usage for document deletions
+    /// Space usage for document deletions, including soft deletes
     pub fn deletes(&self) -> ByteCount {
         self.deletes
     }
@@ -172,6 +335,24 @@ impl SegmentSpaceUsage {
     pub fn total(&self) -> ByteCount {
         self.total
     }
+
+    /// Size of the files of each component of this segment.
+    ///
+    /// Unlike the per field space usages, this includes the headers and footers of the files.
+    pub fn files(&self) -> ComponentsSpaceUsage {
+        self.files
+    }
+
+    /// Space usage attributed to `field` in this segment.
+    pub fn field(&self, field: Field) -> FieldSpaceUsage {
+        FieldSpaceUsage {
+            termdict: self.termdict.field_total(field),
+            postings: self.postings.field_total(field),
+            positions: self.positions.field_total(field),
+            fast_fields: self.fast_fields.field_total(field),
+            fieldnorms: self.fieldnorms.field_total(field),
+        }
+    }
 }
 
 /// Represents space usage for the Store for this segment.
@@ -238,6 +419,13 @@ impl PerFieldSpaceUsage {
     pub fn total(&self) -> ByteCount {
         self.total
     }
+
+    fn field_total(&self, field: Field) -> ByteCount {
+        self.fields
+            .get(&field)
+            .map(FieldUsage::total)
+            .unwrap_or_default()
+    }
 }
 
 /// Represents space usage of a given field, breaking it down into the (field, index) pairs that
@@ -290,11 +478,92 @@ impl FieldUsage {
 
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+
+    use common::{ByteCount, HasLen};
+
+    use crate::directory::Directory;
     use crate::index::Index;
-    use crate::schema::{Field, Schema, FAST, INDEXED, STORED, TEXT};
-    use crate::space_usage::PerFieldSpaceUsage;
+    use crate::schema::{Field, Schema, FAST, INDEXED, STORED, STRING, TEXT};
+    use crate::space_usage::{IndexSpaceUsage, PerFieldSpaceUsage};
     use crate::{IndexWriter, Term};
 
+    #[test]
+    fn test_index_space_usage() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let small = schema_builder.add_text_field("small", STRING | FAST);
+        let big = schema_builder.add_text_field("big", TEXT | STORED);
+        schema_builder.add_u64_field("unused", FAST | INDEXED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            for i in 0..200u64 {
+                let text: String = (0..50).map(|j| format!("word{} ", i * 50 + j)).collect();
+                let small_value = if i % 2 == 0 { "a" } else { "b" };
+                index_writer.add_document(doc!(small => small_value, big => text))?;
+                if i == 100 {
+                    index_writer.commit()?;
+                }
+            }
+            index_writer.commit()?;
+            index_writer.delete_term(Term::from_field_text(small, "b"))?;
+            index_writer.add_document(doc!(small => "c"))?;
+            index_writer.commit()?;
+        }
+
+        let space_usage = index.space_usage()?;
+        let fields = space_usage.fields();
+        assert_eq!(
+            fields.keys().collect::<Vec<_>>(),
+            vec!["big", "small", "unused"]
+        );
+        assert!(fields["big"].total() > fields["small"].total());
+        assert!(fields["small"].total() > fields["unused"].total());
+        assert!(fields["big"].termdict > fields["small"].termdict);
+        assert!(fields["big"].postings > fields["small"].postings);
+        assert!(fields["big"].positions > 0);
+        assert_eq!(fields["small"].positions, 0);
+        assert!(fields["small"].fast_fields > 0);
+        assert_eq!(fields["big"].fast_fields, 0);
+
+        // The components add up to the size of the files of the segments.
+        let directory = index.directory();
+        let mut num_bytes = 0;
+        for segment_meta in index.searchable_segment_metas()? {
+            for path in segment_meta.list_files() {
+                if let Ok(file) = directory.underlying_directory().open_read(&path) {
+                    num_bytes += file.len() as u64;
+                }
+            }
+        }
+        let components = space_usage.components();
+        assert_eq!(components.total(), num_bytes);
+        assert!(components.deletes > 0);
+        assert!(components.store > components.fast_fields);
+        let meta_num_bytes = directory.atomic_read(Path::new("meta.json"))?.len() as u64;
+        assert_eq!(space_usage.meta(), meta_num_bytes);
+        assert_eq!(space_usage.total(), num_bytes + meta_num_bytes);
+        let segment_files_total: ByteCount = space_usage
+            .segments()
+            .segments()
+            .iter()
+            .map(|segment| segment.files().total())
+            .sum();
+        assert_eq!(segment_files_total, num_bytes);
+
+        // The space usage of the searcher has the same breakdown.
+        let searcher_space_usage = index.reader()?.searcher().space_usage()?;
+        assert_eq!(searcher_space_usage.components(), components);
+        assert_eq!(searcher_space_usage.fields(), fields);
+
+        let json = serde_json::to_string(&space_usage).unwrap();
+        let deserialized: IndexSpaceUsage = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.components(), components);
+        assert_eq!(deserialized.fields(), fields);
+        Ok(())
+    }
+
     #[test]
     fn test_empty() {
         let schema = Schema::builder().build();
@@ -457,8 +726,8 @@ mod test {
 
         {
             let mut index_writer2: IndexWriter = index.writer(50_000_000)?;
-            index_writer2.delete_term(Term::from_field_u64(name, 2u64));
-            index_writer2.delete_term(Term::from_field_u64(name, 3u64));
+            index_writer2.delete_term(Term::from_field_u64(name, 2u64))?;
+            index_writer2.delete_term(Term::from_field_u64(name, 3u64))?;
             // ok, now we should have a deleted doc
             index_writer2.commit()?;
         }
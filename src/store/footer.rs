@@ -18,7 +18,7 @@ pub struct DocStoreFooter {
 /// - reserved for future use: 15 bytes
 impl BinarySerializable for DocStoreFooter {
     fn serialize<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
-        BinarySerializable::serialize(&DOC_STORE_VERSION, writer)?;
+        BinarySerializable::serialize(&self.doc_store_version, writer)?;
         BinarySerializable::serialize(&self.offset, writer)?;
         BinarySerializable::serialize(&self.decompressor.get_id(), writer)?;
         writer.write_all(&[0; 15])?;
@@ -44,7 +44,7 @@ pub use self::writer::StoreWriter;
 mod store_compressor;
 
 /// Doc store version in footer to handle format changes.
-pub(crate) const DOC_STORE_VERSION: DocStoreVersion = DocStoreVersion::V2;
+pub(crate) const DOC_STORE_VERSION: DocStoreVersion = DocStoreVersion::V3;
 
 #[cfg(feature = "lz4-compression")]
 mod compression_lz4_block;
@@ -57,13 +57,18 @@ pub(crate) mod tests {
 
     use std::path::Path;
 
+    use common::{BinarySerializable, VInt};
+
     use super::*;
     use crate::directory::{Directory, RamDirectory, WritePtr};
     use crate::fastfield::AliveBitSet;
+    use crate::index::SegmentComponent;
+    use crate::schema::document::BinaryDocumentSerializer;
     use crate::schema::{
-        self, Schema, TantivyDocument, TextFieldIndexing, TextOptions, Value, STORED, TEXT,
+        self, OwnedValue, Schema, TantivyDocument, TextFieldIndexing, TextOptions, Value, STORED,
+        TEXT,
     };
-    use crate::{Index, IndexWriter, Term};
+    use crate::{DocAddress, Index, IndexSettings, IndexWriter, Term};
 
     const LOREM: &str = "Doc Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
                          eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad \
@@ -239,7 +244,7 @@ pub(crate) mod tests {
             index_writer.add_document(doc!(text_field=> "deletemenot"))?;
             index_writer.add_document(doc!(text_field=> "deleteme"))?;
 
-            index_writer.delete_term(Term::from_field_text(text_field, "deleteme"));
+            index_writer.delete_term(Term::from_field_text(text_field, "deleteme"))?;
             index_writer.commit()?;
         }
 
@@ -318,6 +323,80 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "lz4-compression")]
+    #[test]
+    fn test_store_codec_on_flush_and_merge() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let text_field = schema_builder.add_text_field("text_field", TEXT | STORED);
+        let schema = schema_builder.build();
+        let settings = IndexSettings {
+            store_codec_on_flush: Some(Compressor::None),
+            store_codec_on_merge: Some(Compressor::Lz4),
+            ..Default::default()
+        };
+        let index = Index::builder()
+            .schema(schema)
+            .settings(settings)
+            .create_in_ram()?;
+        let store_num_bytes = |index: &Index| -> crate::Result<u64> {
+            let mut num_bytes = 0;
+            for segment_meta in index.searchable_segment_metas()? {
+                let path = segment_meta.relative_path(SegmentComponent::Store);
+                num_bytes += index.directory().open_read(&path)?.num_bytes().get_bytes();
+            }
+            Ok(num_bytes)
+        };
+        {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            for _ in 0..3 {
+                for i in 0..100 {
+                    index_writer.add_document(doc!(text_field=> format!("{i} {LOREM}")))?;
+                }
+                index_writer.commit()?;
+            }
+        }
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 3);
+        for segment_meta in &segment_metas {
+            assert_eq!(segment_meta.store_compressor(), Some(Compressor::None));
+        }
+        let flushed_num_bytes = store_num_bytes(&index)?;
+        {
+            let segment_ids = index.searchable_segment_ids()?;
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.merge(&segment_ids).wait()?;
+            index_writer.wait_merging_threads()?;
+        }
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 1);
+        assert_eq!(segment_metas[0].store_compressor(), Some(Compressor::Lz4));
+        assert!(store_num_bytes(&index)? < flushed_num_bytes);
+
+        let searcher = index.reader()?.searcher();
+        let reader = searcher.segment_reader(0);
+        let store = reader.get_store_reader(10)?;
+        assert_eq!(store.decompressor(), Decompressor::Lz4);
+        let mut texts: Vec<String> = store
+            .iter::<TantivyDocument>(reader.alive_bitset())
+            .map(|doc| {
+                let doc = doc?;
+                Ok(doc
+                    .get_first(text_field)
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string())
+            })
+            .collect::<crate::Result<_>>()?;
+        texts.sort();
+        let mut expected_texts: Vec<String> = (0..3)
+            .flat_map(|_| (0..100).map(|i| format!("{i} {LOREM}")))
+            .collect();
+        expected_texts.sort();
+        assert_eq!(texts, expected_texts);
+        Ok(())
+    }
+
     #[test]
     fn test_merge_of_small_segments() -> crate::Result<()> {
         let mut schema_builder = schema::Schema::builder();
@@ -356,6 +435,179 @@ pub(crate) mod tests {
         assert_eq!(store.block_checkpoints().count(), 1);
         Ok(())
     }
+
+    #[test]
+    fn test_store_get_fields_skips_unrequested_values() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", STORED);
+        let body = schema_builder.add_text_field("body", STORED);
+        let schema = schema_builder.build();
+        let path = Path::new("store");
+        let directory = RamDirectory::create();
+        let mut store_writer = StoreWriter::new(
+            directory.open_write(path)?,
+            Compressor::None,
+            BLOCK_SIZE,
+            false,
+        )?;
+        // A document whose body is not a valid value: it can only be read by skipping it.
+        let mut title_doc_bytes = Vec::new();
+        BinaryDocumentSerializer::new(&mut title_doc_bytes, &schema)
+            .serialize_doc(&doc!(title => "t"))?;
+        let mut doc_bytes = Vec::new();
+        VInt(2).serialize(&mut doc_bytes)?;
+        // Skips the number of values of the document, which fits in one byte.
+        doc_bytes.extend_from_slice(&title_doc_bytes[1..]);
+        body.serialize(&mut doc_bytes)?;
+        VInt(LOREM.len() as u64).serialize(&mut doc_bytes)?;
+        doc_bytes.extend_from_slice(&[u8::MAX; LOREM.len()]);
+        store_writer.store_bytes(&doc_bytes)?;
+        let mut doc = TantivyDocument::default();
+        doc.add_text(title, "u");
+        doc.add_text(body, LOREM);
+        store_writer.store(&doc, &schema)?;
+        store_writer.close()?;
+
+        let store = StoreReader::open(directory.open_read(path)?, 10)?;
+        assert!(store.get::<TantivyDocument>(0).is_err());
+        let doc: TantivyDocument = store.get_fields(0, &[title])?;
+        assert_eq!(doc.field_values().count(), 1);
+        assert_eq!(
+            doc.get_first(title).and_then(|value| value.as_str()),
+            Some("t")
+        );
+        let doc: TantivyDocument = store.get_fields(1, &[body])?;
+        assert_eq!(doc.get_first(title), None);
+        assert_eq!(
+            doc.get_first(body).and_then(|value| value.as_str()),
+            Some(LOREM)
+        );
+        let doc: TantivyDocument = store.get_fields(1, &[])?;
+        assert!(doc.field_values().next().is_none());
+        Ok(())
+    }
+
+    /// Writes the store of the segment again, in the format of an older version of the store.
+    fn rewrite_store(
+        index: &Index,
+        segment_reader: &crate::SegmentReader,
+        doc_store_version: DocStoreVersion,
+    ) -> crate::Result<()> {
+        let segment_meta = index
+            .searchable_segment_metas()?
+            .into_iter()
+            .find(|segment_meta| segment_meta.id() == segment_reader.segment_id())
+            .unwrap();
+        let mut segment = index.segment(segment_meta);
+        let docs: Vec<TantivyDocument> = segment_reader
+            .get_store_reader(0)?
+            .iter(None)
+            .collect::<crate::Result<_>>()?;
+        index
+            .directory()
+            .delete(&segment.relative_path(SegmentComponent::Store))
+            .unwrap();
+        let mut store_writer = StoreWriter::new_with_doc_store_version(
+            segment.open_write(SegmentComponent::Store)?,
+            Compressor::default(),
+            BLOCK_SIZE,
+            false,
+            doc_store_version,
+        )?;
+        for doc in &docs {
+            store_writer.store(doc, &index.schema())?;
+        }
+        store_writer.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_searcher_doc_fields_old_and_new_store_versions() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let body = schema_builder.add_text_field("body", STORED);
+        let id = schema_builder.add_u64_field("id", STORED);
+        let date = schema_builder.add_date_field("date", STORED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(crate::indexer::NoMergePolicy));
+        for segment_ord in 0..2u64 {
+            for i in 0..3u64 {
+                let doc_id = segment_ord * 3 + i;
+                index_writer.add_document(doc!(
+                    title => format!("title {doc_id}"),
+                    body => LOREM,
+                    id => doc_id,
+                    id => doc_id + 100,
+                    date => crate::DateTime::from_timestamp_nanos(doc_id as i64 + 1),
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+        let searcher = index.reader()?.searcher();
+        rewrite_store(&index, searcher.segment_reader(0), DocStoreVersion::V2)?;
+
+        let check_doc_fields = |index: &Index| -> crate::Result<()> {
+            let searcher = index.reader()?.searcher();
+            let doc_addresses: Vec<DocAddress> = searcher
+                .segment_readers()
+                .iter()
+                .enumerate()
+                .flat_map(|(segment_ord, segment_reader)| {
+                    segment_reader
+                        .doc_ids_alive()
+                        .map(move |doc_id| DocAddress::new(segment_ord as u32, doc_id))
+                })
+                .collect();
+            assert_eq!(doc_addresses.len(), 6);
+            let fields = [id, title, date];
+            let projected_docs: Vec<TantivyDocument> =
+                searcher.docs_batch_fields(&doc_addresses, &fields)?;
+            let full_docs: Vec<TantivyDocument> = searcher.docs_batch(&doc_addresses)?;
+            for ((doc_address, projected_doc), full_doc) in
+                doc_addresses.iter().zip(&projected_docs).zip(&full_docs)
+            {
+                let expected_field_values: Vec<_> = full_doc
+                    .field_values()
+                    .filter(|(field, _)| fields.contains(field))
+                    .map(|(field, value)| (field, OwnedValue::from(value)))
+                    .collect();
+                let field_values: Vec<_> = projected_doc
+                    .field_values()
+                    .map(|(field, value)| (field, OwnedValue::from(value)))
+                    .collect();
+                assert_eq!(field_values, expected_field_values);
+                assert_eq!(field_values.len(), 4);
+                let doc: TantivyDocument = searcher.doc_fields(*doc_address, &[body])?;
+                assert_eq!(
+                    doc.get_first(body).and_then(|value| value.as_str()),
+                    Some(LOREM)
+                );
+            }
+            Ok(())
+        };
+        let store_versions = |index: &Index| -> crate::Result<Vec<DocStoreVersion>> {
+            let searcher = index.reader()?.searcher();
+            searcher
+                .segment_readers()
+                .iter()
+                .map(|segment_reader| Ok(segment_reader.get_store_reader(0)?.doc_store_version()))
+                .collect()
+        };
+        let mut versions = store_versions(&index)?;
+        versions.sort_by(|left, right| left.partial_cmp(right).unwrap());
+        assert_eq!(versions, vec![DocStoreVersion::V2, DocStoreVersion::V3]);
+        check_doc_fields(&index)?;
+
+        // The documents of the old store are written in the current format by the merge.
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        assert_eq!(store_versions(&index)?, vec![DOC_STORE_VERSION]);
+        check_doc_fields(&index)?;
+        Ok(())
+    }
 }
 
 #[cfg(all(test, feature = "unstable"))]
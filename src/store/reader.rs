@@ -16,6 +16,7 @@ use crate::directory::FileSlice;
 use crate::error::DataCorruption;
 use crate::fastfield::AliveBitSet;
 use crate::schema::document::{BinaryDocumentDeserializer, DocumentDeserialize};
+use crate::schema::Field;
 use crate::space_usage::StoreSpaceUsage;
 use crate::store::index::Checkpoint;
 use crate::DocId;
@@ -30,13 +31,18 @@ type Block = OwnedBytes;
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub(crate) enum DocStoreVersion {
     V1 = 1,
+    /// Dates are stored with a nanosecond precision.
     V2 = 2,
+    /// Each stored value is prefixed by its length, so that the values of the fields which are
+    /// not requested can be skipped without being parsed.
+    V3 = 3,
 }
 impl Display for DocStoreVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DocStoreVersion::V1 => write!(f, "V1"),
             DocStoreVersion::V2 => write!(f, "V2"),
+            DocStoreVersion::V3 => write!(f, "V3"),
         }
     }
 }
@@ -49,6 +55,7 @@ impl BinarySerializable for DocStoreVersion {
         Ok(match u32::deserialize(reader)? {
             1 => DocStoreVersion::V1,
             2 => DocStoreVersion::V2,
+            3 => DocStoreVersion::V3,
             v => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -245,6 +252,30 @@ impl StoreReader {
         D::deserialize(deserializer).map_err(crate::TantivyError::from)
     }
 
+    /// Reads the values of `fields` of a given document.
+    ///
+    /// The deserialized document only contains the values of `fields`. The values of the other
+    /// fields are skipped without being parsed, unless the store was written by an older
+    /// version of tantivy.
+    pub fn get_fields<D: DocumentDeserialize>(
+        &self,
+        doc_id: DocId,
+        fields: &[Field],
+    ) -> crate::Result<D> {
+        let mut doc_bytes = self.get_document_bytes(doc_id)?;
+
+        let deserializer =
+            BinaryDocumentDeserializer::from_reader(&mut doc_bytes, self.doc_store_version)
+                .map_err(crate::TantivyError::from)?
+                .with_fields(fields);
+        D::deserialize(deserializer).map_err(crate::TantivyError::from)
+    }
+
+    /// Returns the format version of the documents of the store.
+    pub(crate) fn doc_store_version(&self) -> DocStoreVersion {
+        self.doc_store_version
+    }
+
     /// Returns raw bytes of a given document.
     ///
     /// Calling `.get(doc)` is relatively costly as it requires
@@ -456,6 +487,7 @@ mod tests {
     #[test]
     fn test_doc_store_version_ord() {
         assert!(DocStoreVersion::V1 < DocStoreVersion::V2);
+        assert!(DocStoreVersion::V2 < DocStoreVersion::V3);
     }
 
     #[test]
@@ -497,7 +529,7 @@ mod tests {
         assert_eq!(store.cache_stats().cache_hits, 1);
         assert_eq!(store.cache_stats().cache_misses, 2);
 
-        assert_eq!(store.cache.peek_lru(), Some(11207));
+        assert_eq!(store.cache.peek_lru(), Some(11337));
 
         Ok(())
     }
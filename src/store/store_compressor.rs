@@ -5,7 +5,7 @@ use std::{io, thread};
 
 use common::{BinarySerializable, CountingWriter, TerminatingWrite};
 
-use super::DOC_STORE_VERSION;
+use super::DocStoreVersion;
 use crate::directory::WritePtr;
 use crate::store::footer::DocStoreFooter;
 use crate::store::index::{Checkpoint, SkipIndexBuilder};
@@ -22,8 +22,13 @@ enum BlockCompressorVariants {
 }
 
 impl BlockCompressor {
-    pub fn new(compressor: Compressor, wrt: WritePtr, dedicated_thread: bool) -> io::Result<Self> {
-        let block_compressor_impl = BlockCompressorImpl::new(compressor, wrt);
+    pub fn new(
+        compressor: Compressor,
+        wrt: WritePtr,
+        dedicated_thread: bool,
+        doc_store_version: DocStoreVersion,
+    ) -> io::Result<Self> {
+        let block_compressor_impl = BlockCompressorImpl::new(compressor, wrt, doc_store_version);
         if dedicated_thread {
             let dedicated_thread_compressor =
                 DedicatedThreadBlockCompressorImpl::new(block_compressor_impl)?;
@@ -79,6 +84,7 @@ impl BlockCompressor {
 
 struct BlockCompressorImpl {
     compressor: Compressor,
+    doc_store_version: DocStoreVersion,
     first_doc_in_block: DocId,
     offset_index_writer: SkipIndexBuilder,
     intermediary_buffer: Vec<u8>,
@@ -86,9 +92,10 @@ struct BlockCompressorImpl {
 }
 
 impl BlockCompressorImpl {
-    fn new(compressor: Compressor, writer: WritePtr) -> Self {
+    fn new(compressor: Compressor, writer: WritePtr, doc_store_version: DocStoreVersion) -> Self {
         Self {
             compressor,
+            doc_store_version,
             first_doc_in_block: 0,
             offset_index_writer: SkipIndexBuilder::new(),
             intermediary_buffer: Vec::new(),
@@ -147,7 +154,7 @@ impl BlockCompressorImpl {
         let docstore_footer = DocStoreFooter::new(
             header_offset,
             Decompressor::from(self.compressor),
-            DOC_STORE_VERSION,
+            self.doc_store_version,
         );
         self.offset_index_writer.serialize_into(&mut self.writer)?;
         docstore_footer.serialize(&mut self.writer)?;
@@ -166,7 +173,8 @@ enum BlockCompressorMessage {
 
 struct DedicatedThreadBlockCompressorImpl {
     join_handle: Option<JoinHandle<io::Result<()>>>,
-    tx: SyncSender<BlockCompressorMessage>,
+    /// `None` once the compressor is closed.
+    tx: Option<SyncSender<BlockCompressorMessage>>,
 }
 
 impl DedicatedThreadBlockCompressorImpl {
@@ -197,7 +205,7 @@ impl DedicatedThreadBlockCompressorImpl {
             })?;
         Ok(DedicatedThreadBlockCompressorImpl {
             join_handle: Some(join_handle),
-            tx,
+            tx: Some(tx),
         })
     }
 
@@ -213,16 +221,28 @@ impl DedicatedThreadBlockCompressorImpl {
     }
 
     fn send(&mut self, msg: BlockCompressorMessage) -> io::Result<()> {
-        if self.tx.send(msg).is_err() {
+        let is_sent = self.tx.as_ref().is_some_and(|tx| tx.send(msg).is_ok());
+        if !is_sent {
             harvest_thread_result(self.join_handle.take())?;
             return Err(io::Error::new(io::ErrorKind::Other, "Unidentified error."));
         }
         Ok(())
     }
 
-    fn close(self) -> io::Result<()> {
-        drop(self.tx);
-        harvest_thread_result(self.join_handle)
+    fn close(mut self) -> io::Result<()> {
+        self.tx = None;
+        harvest_thread_result(self.join_handle.take())
+    }
+}
+
+impl Drop for DedicatedThreadBlockCompressorImpl {
+    fn drop(&mut self) {
+        // If the compressor was not closed, e.g. because the write was aborted, wait for the
+        // thread to release the file, so that it can be deleted right away.
+        self.tx = None;
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.join();
+        }
     }
 }
 
@@ -245,7 +265,7 @@ mod tests {
 
     use crate::directory::RamDirectory;
     use crate::store::store_compressor::BlockCompressor;
-    use crate::store::Compressor;
+    use crate::store::{Compressor, DOC_STORE_VERSION};
     use crate::Directory;
 
     fn populate_block_compressor(mut block_compressor: BlockCompressor) -> io::Result<()> {
@@ -262,8 +282,10 @@ mod tests {
         let path2 = Path::new("path2");
         let wrt1 = ram_directory.open_write(path1).unwrap();
         let wrt2 = ram_directory.open_write(path2).unwrap();
-        let block_compressor1 = BlockCompressor::new(Compressor::None, wrt1, true).unwrap();
-        let block_compressor2 = BlockCompressor::new(Compressor::None, wrt2, false).unwrap();
+        let block_compressor1 =
+            BlockCompressor::new(Compressor::None, wrt1, true, DOC_STORE_VERSION).unwrap();
+        let block_compressor2 =
+            BlockCompressor::new(Compressor::None, wrt2, false, DOC_STORE_VERSION).unwrap();
         populate_block_compressor(block_compressor1).unwrap();
         populate_block_compressor(block_compressor2).unwrap();
         let data1 = ram_directory.open_read(path1).unwrap();
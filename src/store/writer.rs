@@ -3,7 +3,7 @@ use std::io;
 use common::BinarySerializable;
 
 use super::compressors::Compressor;
-use super::StoreReader;
+use super::{DocStoreVersion, StoreReader, DOC_STORE_VERSION};
 use crate::directory::WritePtr;
 use crate::schema::document::{BinaryDocumentSerializer, Document};
 use crate::schema::Schema;
@@ -24,6 +24,7 @@ pub struct StoreWriter {
     current_block: Vec<u8>,
     doc_pos: Vec<u32>,
     block_compressor: BlockCompressor,
+    doc_store_version: DocStoreVersion,
 }
 
 impl StoreWriter {
@@ -37,7 +38,26 @@ impl StoreWriter {
         block_size: usize,
         dedicated_thread: bool,
     ) -> io::Result<StoreWriter> {
-        let block_compressor = BlockCompressor::new(compressor, writer, dedicated_thread)?;
+        StoreWriter::new_with_doc_store_version(
+            writer,
+            compressor,
+            block_size,
+            dedicated_thread,
+            DOC_STORE_VERSION,
+        )
+    }
+
+    /// Create a store writer, writing the documents in the format of an older version of the
+    /// doc store.
+    pub(crate) fn new_with_doc_store_version(
+        writer: WritePtr,
+        compressor: Compressor,
+        block_size: usize,
+        dedicated_thread: bool,
+        doc_store_version: DocStoreVersion,
+    ) -> io::Result<StoreWriter> {
+        let block_compressor =
+            BlockCompressor::new(compressor, writer, dedicated_thread, doc_store_version)?;
         Ok(StoreWriter {
             compressor,
             block_size,
@@ -45,6 +65,7 @@ impl StoreWriter {
             doc_pos: Vec::new(),
             current_block: Vec::new(),
             block_compressor,
+            doc_store_version,
         })
     }
 
@@ -99,7 +120,8 @@ impl StoreWriter {
     pub fn store<D: Document>(&mut self, document: &D, schema: &Schema) -> io::Result<()> {
         self.doc_pos.push(self.current_block.len() as u32);
 
-        let mut serializer = BinaryDocumentSerializer::new(&mut self.current_block, schema);
+        let mut serializer = BinaryDocumentSerializer::new(&mut self.current_block, schema)
+            .with_doc_store_version(self.doc_store_version);
         serializer.serialize_doc(document)?;
 
         self.num_docs_in_current_block += 1;
@@ -109,6 +131,8 @@ impl StoreWriter {
 
     /// Store bytes of a serialized document.
     ///
+    /// The document has to be serialized in the format of the version of the store.
+    ///
     /// The document id is implicitly the current number
     /// of documents.
     pub fn store_bytes(&mut self, serialized_document: &[u8]) -> io::Result<()> {
@@ -123,7 +147,10 @@ impl StoreWriter {
     /// This method is an optimization compared to iterating over the documents
     /// in the store and adding them one by one, as the store's data will
     /// not be decompressed and then recompressed.
+    ///
+    /// The store reader has to have the same version as the store.
     pub fn stack(&mut self, store_reader: StoreReader) -> io::Result<()> {
+        debug_assert_eq!(store_reader.doc_store_version(), self.doc_store_version);
         // We flush the current block first before stacking
         self.send_current_block_to_compressor()?;
         self.block_compressor.stack_reader(store_reader)?;
@@ -28,6 +28,7 @@ mod sstable_termdict;
 #[cfg(feature = "quickwit")]
 use sstable_termdict as termdict;
 
+mod term_stream_filter;
 #[cfg(test)]
 mod tests;
 
@@ -40,6 +41,9 @@ use common::file_slice::FileSlice;
 use common::BinarySerializable;
 use tantivy_fst::Automaton;
 
+pub use self::term_stream_filter::{
+    FilteredTermStreamer, MergedTermDictionary, MergedTermStreamer, TermStreamFilter,
+};
 use self::termdict::{
     TermDictionary as InnerTermDict, TermDictionaryBuilder as InnerTermDictBuilder,
     TermStreamerBuilder,
@@ -155,6 +159,17 @@ impl TermDictionary {
         self.0.stream()
     }
 
+    /// Streams the sorted terms matching `filter`.
+    ///
+    /// The prefix of the filter bounds the range of the dictionary that is read, while the
+    /// document frequencies and the lengths are checked on each term, before it is returned.
+    pub fn stream_filtered(
+        &self,
+        filter: &TermStreamFilter,
+    ) -> io::Result<FilteredTermStreamer<'_>> {
+        FilteredTermStreamer::new(self, filter)
+    }
+
     /// Returns a search builder, to stream all of the terms
     /// within the Automaton
     pub fn search<'a, A: Automaton + 'a>(&'a self, automaton: A) -> TermStreamerBuilder<'a, A>
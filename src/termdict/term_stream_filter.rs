@@ -0,0 +1,393 @@
+use std::io;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use super::{TermDictionary, TermOrdinal, TermStreamer};
+use crate::index::InvertedIndexReader;
+use crate::postings::TermInfo;
+use crate::query::prefix_end;
+
+/// Restricts the terms of a stream to the ones within a range of document frequencies, prefixed
+/// by a given key, and within a range of lengths.
+///
+/// See [`TermDictionary::stream_filtered()`] and
+/// [`Searcher::merged_term_dictionary()`](crate::Searcher::merged_term_dictionary).
+///
+/// The bounds are inclusive. The lengths are the number of bytes of the terms, as stored in the
+/// dictionary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TermStreamFilter<'a> {
+    /// Minimum document frequency of the terms.
+    pub min_doc_freq: u64,
+    /// Maximum document frequency of the terms.
+    pub max_doc_freq: u64,
+    /// Prefix of the terms.
+    pub prefix: Option<&'a [u8]>,
+    /// Minimum length of the terms, in bytes.
+    pub min_len: usize,
+    /// Maximum length of the terms, in bytes.
+    pub max_len: usize,
+}
+
+impl Default for TermStreamFilter<'_> {
+    fn default() -> Self {
+        TermStreamFilter {
+            min_doc_freq: 0,
+            max_doc_freq: u64::MAX,
+            prefix: None,
+            min_len: 0,
+            max_len: usize::MAX,
+        }
+    }
+}
+
+impl TermStreamFilter<'_> {
+    /// Streams the terms of `term_dict` starting with the prefix.
+    ///
+    /// The prefix bounds the range of the dictionary, so that the terms outside of it are never
+    /// read.
+    fn stream_prefix<'a>(&self, term_dict: &'a TermDictionary) -> io::Result<TermStreamer<'a>> {
+        let Some(prefix) = self.prefix else {
+            return term_dict.stream();
+        };
+        let range = term_dict.range().ge(prefix);
+        match prefix_end(prefix) {
+            Some(end) => range.lt(end).into_stream(),
+            None => range.into_stream(),
+        }
+    }
+}
+
+/// A stream of the terms of a segment matching a [`TermStreamFilter`].
+///
+/// Returned by [`TermDictionary::stream_filtered()`].
+pub struct FilteredTermStreamer<'a> {
+    streamer: TermStreamer<'a>,
+    doc_freq_range: RangeInclusive<u64>,
+    len_range: RangeInclusive<usize>,
+}
+
+impl<'a> FilteredTermStreamer<'a> {
+    pub(crate) fn new(
+        term_dict: &'a TermDictionary,
+        filter: &TermStreamFilter,
+    ) -> io::Result<FilteredTermStreamer<'a>> {
+        Ok(FilteredTermStreamer {
+            streamer: filter.stream_prefix(term_dict)?,
+            doc_freq_range: filter.min_doc_freq..=filter.max_doc_freq,
+            len_range: filter.min_len..=filter.max_len,
+        })
+    }
+
+    /// Advances the stream to the next matching term.
+    ///
+    /// Returns false once the stream is exhausted.
+    pub fn advance(&mut self) -> bool {
+        while self.streamer.advance() {
+            if self.len_range.contains(&self.streamer.key().len())
+                && self
+                    .doc_freq_range
+                    .contains(&u64::from(self.streamer.value().doc_freq))
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the `TermOrdinal` of the current term.
+    pub fn term_ord(&self) -> TermOrdinal {
+        self.streamer.term_ord()
+    }
+
+    /// Returns the current term.
+    pub fn key(&self) -> &[u8] {
+        self.streamer.key()
+    }
+
+    /// Returns the `TermInfo` of the current term.
+    pub fn value(&self) -> &TermInfo {
+        self.streamer.value()
+    }
+
+    /// Returns the next `(term, term_info)` pair.
+    #[expect(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&[u8], &TermInfo)> {
+        if self.advance() {
+            Some((self.key(), self.value()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The term dictionaries of a field over all of the segments of a
+/// [`Searcher`](crate::Searcher).
+///
+/// Returned by [`Searcher::merged_term_dictionary()`](crate::Searcher::merged_term_dictionary).
+pub struct MergedTermDictionary {
+    inverted_indexes: Vec<Arc<InvertedIndexReader>>,
+}
+
+impl MergedTermDictionary {
+    pub(crate) fn new(inverted_indexes: Vec<Arc<InvertedIndexReader>>) -> MergedTermDictionary {
+        MergedTermDictionary { inverted_indexes }
+    }
+
+    /// Streams the terms of all of the segments matching `filter`, in sorted order.
+    ///
+    /// The document frequency of a term is the sum of its document frequencies in the segments,
+    /// as returned by [`Searcher::doc_freq()`](crate::Searcher::doc_freq). The document frequency
+    /// bounds of the filter apply to that sum, while the prefix and length bounds are applied to
+    /// the stream of each segment.
+    pub fn stream_filtered(&self, filter: &TermStreamFilter) -> io::Result<MergedTermStreamer<'_>> {
+        // The document frequencies are only filtered once summed.
+        let segment_filter = TermStreamFilter {
+            min_doc_freq: 0,
+            max_doc_freq: u64::MAX,
+            ..*filter
+        };
+        let mut streamers = Vec::with_capacity(self.inverted_indexes.len());
+        for inverted_index in &self.inverted_indexes {
+            let mut streamer = FilteredTermStreamer::new(inverted_index.terms(), &segment_filter)?;
+            if streamer.advance() {
+                streamers.push(streamer);
+            }
+        }
+        Ok(MergedTermStreamer {
+            streamers,
+            doc_freq_range: filter.min_doc_freq..=filter.max_doc_freq,
+            current_key: Vec::new(),
+            current_doc_freq: 0,
+        })
+    }
+}
+
+/// A stream of the terms of several segments, with their document frequencies summed.
+///
+/// Returned by [`MergedTermDictionary::stream_filtered()`].
+pub struct MergedTermStreamer<'a> {
+    // Streams positioned on their next term. Exhausted streams are removed.
+    streamers: Vec<FilteredTermStreamer<'a>>,
+    doc_freq_range: RangeInclusive<u64>,
+    current_key: Vec<u8>,
+    current_doc_freq: u64,
+}
+
+impl MergedTermStreamer<'_> {
+    /// Advances the stream to the next matching term.
+    ///
+    /// Returns false once the stream is exhausted.
+    pub fn advance(&mut self) -> bool {
+        while let Some(min_key) = self.streamers.iter().map(FilteredTermStreamer::key).min() {
+            self.current_key.clear();
+            self.current_key.extend_from_slice(min_key);
+            let current_key = &self.current_key;
+            let mut current_doc_freq = 0;
+            self.streamers.retain_mut(|streamer| {
+                if streamer.key() != current_key.as_slice() {
+                    return true;
+                }
+                current_doc_freq += u64::from(streamer.value().doc_freq);
+                streamer.advance()
+            });
+            self.current_doc_freq = current_doc_freq;
+            if self.doc_freq_range.contains(&self.current_doc_freq) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the current term.
+    pub fn key(&self) -> &[u8] {
+        &self.current_key
+    }
+
+    /// Returns the document frequency of the current term, summed over the segments.
+    pub fn doc_freq(&self) -> u64 {
+        self.current_doc_freq
+    }
+
+    /// Returns the next `(term, doc_freq)` pair.
+    #[expect(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&[u8], u64)> {
+        if self.advance() {
+            Some((self.key(), self.doc_freq()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::TermStreamFilter;
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, STRING};
+    use crate::{Index, IndexWriter};
+
+    // Each segment is a list of documents, each document having a single term.
+    const SEGMENTS: [&[&str]; 3] = [
+        &["apple", "apple", "apply", "banana"],
+        &["apple", "applesauce", "ban", "bandana"],
+        &["apply", "banana", "banana", "cherry"],
+    ];
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for segment in SEGMENTS {
+            for term in segment {
+                index_writer.add_document(doc!(text => *term))?;
+            }
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    fn merged_terms(index: &Index, filter: &TermStreamFilter) -> crate::Result<Vec<(String, u64)>> {
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), SEGMENTS.len());
+        let text = index.schema().get_field("text")?;
+        let merged_term_dict = searcher.merged_term_dictionary(text)?;
+        let mut stream = merged_term_dict.stream_filtered(filter)?;
+        let mut terms = Vec::new();
+        while let Some((term, doc_freq)) = stream.next() {
+            terms.push((String::from_utf8(term.to_vec()).unwrap(), doc_freq));
+        }
+        Ok(terms)
+    }
+
+    /// Returns the union of the terms of the segments, each segment being filtered separately.
+    fn union_of_segment_terms(
+        index: &Index,
+        filter: &TermStreamFilter,
+    ) -> crate::Result<BTreeSet<String>> {
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text")?;
+        let mut terms = BTreeSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(text)?;
+            let mut stream = inverted_index.terms().stream_filtered(filter)?;
+            while let Some((term, term_info)) = stream.next() {
+                assert!(u64::from(term_info.doc_freq) >= filter.min_doc_freq);
+                terms.insert(String::from_utf8(term.to_vec()).unwrap());
+            }
+        }
+        Ok(terms)
+    }
+
+    fn owned(terms: &[(&str, u64)]) -> Vec<(String, u64)> {
+        terms
+            .iter()
+            .map(|(term, doc_freq)| (term.to_string(), *doc_freq))
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_filtered_doc_freq_across_segments() -> crate::Result<()> {
+        let index = create_index()?;
+        assert_eq!(
+            merged_terms(&index, &TermStreamFilter::default())?,
+            owned(&[
+                ("apple", 3),
+                ("applesauce", 1),
+                ("apply", 2),
+                ("ban", 1),
+                ("banana", 3),
+                ("bandana", 1),
+                ("cherry", 1),
+            ])
+        );
+        let filter = TermStreamFilter {
+            min_doc_freq: 2,
+            ..Default::default()
+        };
+        // Filtering the segments separately misses "apply", as it only appears once in each
+        // segment.
+        assert_eq!(
+            union_of_segment_terms(&index, &filter)?,
+            BTreeSet::from(["apple".to_string(), "banana".to_string()])
+        );
+        assert_eq!(
+            merged_terms(&index, &filter)?,
+            owned(&[("apple", 3), ("apply", 2), ("banana", 3)])
+        );
+        let filter = TermStreamFilter {
+            min_doc_freq: 2,
+            max_doc_freq: 2,
+            ..Default::default()
+        };
+        assert_eq!(merged_terms(&index, &filter)?, owned(&[("apply", 2)]));
+        let filter = TermStreamFilter {
+            max_doc_freq: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            merged_terms(&index, &filter)?,
+            owned(&[("applesauce", 1), ("ban", 1), ("bandana", 1), ("cherry", 1)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_filtered_prefix_and_len() -> crate::Result<()> {
+        let index = create_index()?;
+        let filter = TermStreamFilter {
+            prefix: Some(b"appl"),
+            ..Default::default()
+        };
+        assert_eq!(
+            merged_terms(&index, &filter)?,
+            owned(&[("apple", 3), ("applesauce", 1), ("apply", 2)])
+        );
+        let filter = TermStreamFilter {
+            prefix: Some(b"appl"),
+            max_len: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            merged_terms(&index, &filter)?,
+            owned(&[("apple", 3), ("apply", 2)])
+        );
+        let filter = TermStreamFilter {
+            prefix: Some(b"ban"),
+            min_len: 4,
+            max_len: 6,
+            ..Default::default()
+        };
+        assert_eq!(merged_terms(&index, &filter)?, owned(&[("banana", 3)]));
+        let filter = TermStreamFilter {
+            prefix: Some(b"ban"),
+            min_doc_freq: 2,
+            ..Default::default()
+        };
+        assert_eq!(merged_terms(&index, &filter)?, owned(&[("banana", 3)]));
+        let filter = TermStreamFilter {
+            prefix: Some(b"ban"),
+            max_len: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            union_of_segment_terms(&index, &filter)?,
+            BTreeSet::from(["ban".to_string()])
+        );
+        let filter = TermStreamFilter {
+            prefix: Some(b"cherry\xff"),
+            ..Default::default()
+        };
+        assert!(merged_terms(&index, &filter)?.is_empty());
+        let filter = TermStreamFilter {
+            prefix: Some(b"d"),
+            ..Default::default()
+        };
+        assert!(merged_terms(&index, &filter)?.is_empty());
+        Ok(())
+    }
+}
@@ -77,6 +77,10 @@ impl<T: TokenStream> TokenStream for AlphaNumOnlyFilterStream<T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 #[cfg(test)]
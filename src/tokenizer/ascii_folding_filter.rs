@@ -62,6 +62,10 @@ impl<T: TokenStream> TokenStream for AsciiFoldingFilterTokenStream<'_, T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 // Returns a string that represents the ascii folded version of
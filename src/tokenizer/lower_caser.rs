@@ -73,6 +73,10 @@ impl<T: TokenStream> TokenStream for LowerCaserTokenStream<'_, T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 #[cfg(test)]
@@ -87,6 +87,10 @@ impl<T: TokenStream> TokenStream for RemoveLongFilterStream<T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 #[cfg(test)]
@@ -185,6 +185,9 @@ impl<T: TokenStream> TokenStream for SplitCompoundWordsTokenStream<'_, T> {
             .last_mut()
             .unwrap_or_else(|| self.tail.token_mut())
     }
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 #[cfg(test)]
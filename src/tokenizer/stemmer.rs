@@ -141,4 +141,8 @@ impl<T: TokenStream> TokenStream for StemmerTokenStream<T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
@@ -127,6 +127,10 @@ impl<T: TokenStream> TokenStream for StopWordFilterStream<T> {
     fn token_mut(&mut self) -> &mut Token {
         self.tail.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.tail.error()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,122 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use tantivy::schema::{Schema, TermBuffer, FAST, INDEXED, STRING};
+use tantivy::{doc, Index, IndexWriter, TantivyError};
+
+/// Counts the allocations of the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        NUM_ALLOCATIONS.with(|num_allocations| num_allocations.set(num_allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        NUM_ALLOCATIONS.with(|num_allocations| num_allocations.set(num_allocations.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = NUM_ALLOCATIONS.with(Cell::get);
+    let res = f();
+    (res, NUM_ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_delete_term_ref_allocations() -> tantivy::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let id = schema_builder.add_text_field("id", STRING);
+    let num = schema_builder.add_u64_field("num", INDEXED);
+    let not_indexed = schema_builder.add_u64_field("not_indexed", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000)?;
+    for i in 0..100u64 {
+        index_writer.add_document(doc!(id => format!("doc-{i:02}"), num => i, not_indexed => i))?;
+    }
+    index_writer.commit()?;
+
+    let mut id_buffer = TermBuffer::new(id);
+    let mut num_buffer = TermBuffer::new(num);
+    let mut text = String::with_capacity(32);
+    // Warms up the buffers.
+    id_buffer.set_text("doc-000");
+    num_buffer.set_u64(0);
+
+    // Building the terms does not allocate.
+    let ((), num_allocations) = count_allocations(|| {
+        for i in 0..1_000 {
+            text.clear();
+            text.push_str(if i % 2 == 0 { "doc-" } else { "doc-0" });
+            id_buffer.set_text(&text);
+            num_buffer.set_u64(i);
+        }
+    });
+    assert_eq!(num_allocations, 0);
+
+    // Each enqueued delete copies its term. The delete queue grows by doubling its capacity.
+    let num_deletes = 50usize;
+    let (res, num_allocations) = count_allocations(|| -> tantivy::Result<()> {
+        for i in 0..num_deletes {
+            text.clear();
+            text.push_str("doc-");
+            text.push(char::from(b'0' + (i / 10) as u8));
+            text.push(char::from(b'0' + (i % 10) as u8));
+            id_buffer.set_text(&text);
+            index_writer.delete_term_ref(&id_buffer.term_ref())?;
+        }
+        Ok(())
+    });
+    res?;
+    assert!(num_allocations >= num_deletes);
+    assert!(
+        num_allocations <= num_deletes + 8,
+        "{num_allocations} allocations for {num_deletes} deletes"
+    );
+
+    // Repeating the last delete is a no-op.
+    num_buffer.set_u64(99);
+    let opstamp = index_writer.delete_term_ref(&num_buffer.term_ref())?;
+    let (res, num_allocations) = count_allocations(|| -> tantivy::Result<()> {
+        for i in 1..=100 {
+            assert_eq!(
+                index_writer.delete_term_ref(&num_buffer.term_ref())?,
+                opstamp + i
+            );
+        }
+        Ok(())
+    });
+    res?;
+    assert_eq!(num_allocations, 0);
+
+    // Invalid terms are rejected without being enqueued.
+    let mut not_indexed_buffer = TermBuffer::new(not_indexed);
+    not_indexed_buffer.set_u64(3);
+    assert!(matches!(
+        index_writer.delete_term_ref(&not_indexed_buffer.term_ref()),
+        Err(TantivyError::SchemaError(_))
+    ));
+    num_buffer.set_text("3");
+    assert!(matches!(
+        index_writer.delete_term_ref(&num_buffer.term_ref()),
+        Err(TantivyError::SchemaError(_))
+    ));
+
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    assert_eq!(searcher.num_docs(), 100 - num_deletes as u64 - 1);
+    Ok(())
+}
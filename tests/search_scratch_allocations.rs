@@ -0,0 +1,147 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use serde_json::json;
+use tantivy::aggregation::agg_req::Aggregations;
+use tantivy::aggregation::AggregationCollector;
+use tantivy::collector::{Collector, SegmentCollector, TopDocs};
+use tantivy::schema::{Schema, FAST, STRING};
+use tantivy::{doc, DocId, Index, IndexWriter, SearchScratch, SegmentReader};
+
+/// Counts the allocations of the current thread, and the bytes they hold.
+struct CountingAllocator;
+
+thread_local! {
+    static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static NUM_ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn record(num_allocations: usize, num_bytes: isize) {
+    NUM_ALLOCATIONS.with(|cell| cell.set(cell.get() + num_allocations));
+    NUM_ALLOCATED_BYTES.with(|cell| cell.set(cell.get() + num_bytes));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(1, layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record(0, -(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(1, new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by `f`, and the number of bytes it left allocated.
+fn count_allocations(f: impl FnOnce()) -> (usize, isize) {
+    let num_allocations_before = NUM_ALLOCATIONS.with(Cell::get);
+    let num_bytes_before = NUM_ALLOCATED_BYTES.with(Cell::get);
+    f();
+    (
+        NUM_ALLOCATIONS.with(Cell::get) - num_allocations_before,
+        NUM_ALLOCATED_BYTES.with(Cell::get) - num_bytes_before,
+    )
+}
+
+/// Runs a collector on all of the docs of a segment, dropping its fruit.
+fn collect_segment<C: Collector>(collector: &C, segment_reader: &SegmentReader) {
+    let mut segment_collector = collector.for_segment(0, segment_reader).unwrap();
+    let docs: Vec<DocId> = (0..segment_reader.max_doc()).collect();
+    for block in docs.chunks(64) {
+        segment_collector.collect_block(block);
+    }
+    drop(segment_collector.harvest());
+}
+
+/// Returns the allocations of a run of `collector` once the runs reached a steady state.
+fn steady_state_allocations<C: Collector>(collector: &C, segment_reader: &SegmentReader) -> usize {
+    // The pooled buffers grow to the sizes needed by the collector over the first runs.
+    for _ in 0..5 {
+        collect_segment(collector, segment_reader);
+    }
+    let (num_allocations, num_bytes) = count_allocations(|| {
+        collect_segment(collector, segment_reader);
+    });
+    assert_eq!(num_bytes, 0);
+    let pooled_bytes = SearchScratch::pooled_bytes();
+    let (num_allocations_again, num_bytes) = count_allocations(|| {
+        collect_segment(collector, segment_reader);
+    });
+    assert_eq!(num_bytes, 0);
+    assert_eq!(num_allocations_again, num_allocations);
+    assert_eq!(SearchScratch::pooled_bytes(), pooled_bytes);
+    num_allocations
+}
+
+#[test]
+fn test_search_scratch_steady_state_allocations() -> tantivy::Result<()> {
+    let mut schema_builder = Schema::builder();
+    let category = schema_builder.add_text_field("category", STRING | FAST);
+    let value = schema_builder.add_u64_field("value", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000)?;
+    for i in 0..1_000u64 {
+        if i % 10 == 0 {
+            index_writer.add_document(doc!(category => format!("category{}", i % 7)))?;
+        } else {
+            index_writer
+                .add_document(doc!(category => format!("category{}", i % 7), value => i))?;
+        }
+    }
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    let segment_reader = searcher.segment_reader(0);
+
+    let top_docs = TopDocs::with_limit(10);
+    let custom_score_top_docs =
+        TopDocs::with_limit(10).custom_score(move |segment_reader: &SegmentReader| {
+            let column = segment_reader.fast_fields().u64("value").unwrap();
+            move |doc: DocId| column.first(doc).unwrap_or(0) % 97
+        });
+    let agg_req: Aggregations = serde_json::from_value(json!({
+        "categories": {
+            "terms": { "field": "category" },
+            "aggs": { "avg_value": { "avg": { "field": "value", "missing": 0 } } }
+        },
+        "stats_value": { "stats": { "field": "value" } }
+    }))
+    .unwrap();
+    let aggregation = AggregationCollector::from_aggs(agg_req, Default::default());
+
+    // Without the pool, every run allocates the buffers of the collectors.
+    let max_pooled_bytes = SearchScratch::max_pooled_bytes();
+    SearchScratch::set_max_pooled_bytes(0);
+    SearchScratch::clear();
+    let unpooled_allocations = [
+        steady_state_allocations(&top_docs, segment_reader),
+        steady_state_allocations(&custom_score_top_docs, segment_reader),
+        steady_state_allocations(&aggregation, segment_reader),
+    ];
+    SearchScratch::set_max_pooled_bytes(max_pooled_bytes);
+
+    let pooled_allocations = [
+        steady_state_allocations(&top_docs, segment_reader),
+        steady_state_allocations(&custom_score_top_docs, segment_reader),
+        steady_state_allocations(&aggregation, segment_reader),
+    ];
+    // The top docs heap.
+    assert_eq!(pooled_allocations[0] + 1, unpooled_allocations[0]);
+    // The top docs heap and the buffer of the scores of a block.
+    assert_eq!(pooled_allocations[1] + 2, unpooled_allocations[1]);
+    // The staging buffer and the buffers of the column values of every aggregation.
+    assert!(
+        pooled_allocations[2] + 4 <= unpooled_allocations[2],
+        "{pooled_allocations:?} {unpooled_allocations:?}"
+    );
+    assert!(SearchScratch::pooled_bytes() > 0);
+    Ok(())
+}
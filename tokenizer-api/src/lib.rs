@@ -75,6 +75,10 @@ impl TokenStream for BoxTokenStream<'_> {
     fn token_mut(&mut self) -> &mut Token {
         self.0.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        self.0.error()
+    }
 }
 
 impl<'a> BoxTokenStream<'a> {
@@ -111,6 +115,11 @@ impl<'a> TokenStream for Box<dyn TokenStream + 'a> {
         let token_stream: &'b mut (dyn TokenStream + 'a) = self.borrow_mut();
         token_stream.token_mut()
     }
+
+    fn error(&self) -> Option<&str> {
+        let token_stream: &(dyn TokenStream + 'a) = self.borrow();
+        token_stream.error()
+    }
 }
 
 /// `TokenStream` is the result of the tokenization.
@@ -128,6 +137,14 @@ pub trait TokenStream {
     /// Returns a mutable reference to the current token.
     fn token_mut(&mut self) -> &mut Token;
 
+    /// Returns the error that ended the stream early, if any.
+    ///
+    /// A tokenizer that cannot tokenize its text ends its stream, and reports why here, so that
+    /// the indexer does not silently index a truncated text.
+    fn error(&self) -> Option<&str> {
+        None
+    }
+
     /// Helper to iterate over tokens. It
     /// simply combines a call to `.advance()`
     /// and `.token()`.